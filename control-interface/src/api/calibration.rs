@@ -0,0 +1,149 @@
+//! Calibration wizard endpoints.
+//!
+//! The flow wizard prints one test patch per channel; the operator weighs
+//! each patch, submits the mass, checks the proposed multipliers and applies
+//! them to the loaded material profiles.
+//!
+//! Bed levelling measures the gap deviation at each probe point, entered by
+//! hand or read from gap sensors, and saves the height map to the printer
+//! configuration.
+
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::Json;
+use serde::Deserialize;
+
+use protocol::{
+    BedLevelPointCommand, BedLevelStatus, FlowCalibrationStatus, FlowMeasurementCommand,
+    ProtocolMessage, StartFlowCalibrationCommand,
+};
+
+use super::request_firmware;
+use crate::AppState;
+
+#[derive(Debug, Deserialize)]
+pub struct MeasurementBody {
+    pub mass_g: f32,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BedPointBody {
+    pub deviation_mm: f32,
+}
+
+/// POST /calibration/flow - print the calibration patches.
+pub async fn start_flow_calibration(
+    State(state): State<AppState>,
+    Json(cmd): Json<StartFlowCalibrationCommand>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    send_command(&state, ProtocolMessage::StartFlowCalibration(cmd)).await
+}
+
+/// GET /calibration/flow - expected and measured masses per channel.
+pub async fn get_flow_calibration(
+    State(state): State<AppState>,
+) -> Result<Json<FlowCalibrationStatus>, (StatusCode, String)> {
+    let reply = request_firmware(&state, ProtocolMessage::GetFlowCalibration, |msg| {
+        matches!(msg, ProtocolMessage::FlowCalibrationStatus(_))
+    })
+    .await
+    .map_err(|e| (StatusCode::BAD_GATEWAY, e.to_string()))?;
+
+    match reply {
+        ProtocolMessage::FlowCalibrationStatus(status) => Ok(Json(status)),
+        other => Err((
+            StatusCode::BAD_GATEWAY,
+            format!("Unexpected firmware reply: {}", other.message_type()),
+        )),
+    }
+}
+
+/// POST /calibration/flow/:channel/measurement - submit a patch's mass.
+pub async fn submit_measurement(
+    State(state): State<AppState>,
+    Path(channel): Path<u8>,
+    Json(body): Json<MeasurementBody>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    let cmd = FlowMeasurementCommand { channel, mass_g: body.mass_g };
+    send_command(&state, ProtocolMessage::SubmitFlowMeasurement(cmd)).await
+}
+
+/// POST /calibration/flow/apply - store the new multipliers.
+pub async fn apply_flow_calibration(
+    State(state): State<AppState>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    send_command(&state, ProtocolMessage::ApplyFlowCalibration).await
+}
+
+/// POST /calibration/bed - start measuring the bed.
+pub async fn start_bed_level(
+    State(state): State<AppState>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    send_command(&state, ProtocolMessage::StartBedLevel).await
+}
+
+/// GET /calibration/bed - probe points and measured deviations.
+pub async fn get_bed_level(
+    State(state): State<AppState>,
+) -> Result<Json<BedLevelStatus>, (StatusCode, String)> {
+    let reply = request_firmware(&state, ProtocolMessage::GetBedLevel, |msg| {
+        matches!(msg, ProtocolMessage::BedLevelStatus(_))
+    })
+    .await
+    .map_err(|e| (StatusCode::BAD_GATEWAY, e.to_string()))?;
+
+    match reply {
+        ProtocolMessage::BedLevelStatus(status) => Ok(Json(status)),
+        other => Err((
+            StatusCode::BAD_GATEWAY,
+            format!("Unexpected firmware reply: {}", other.message_type()),
+        )),
+    }
+}
+
+/// POST /calibration/bed/points/:index - submit a manual measurement.
+pub async fn submit_bed_point(
+    State(state): State<AppState>,
+    Path(index): Path<usize>,
+    Json(body): Json<BedPointBody>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    let cmd = BedLevelPointCommand { index, deviation_mm: body.deviation_mm };
+    send_command(&state, ProtocolMessage::SubmitBedLevelPoint(cmd)).await
+}
+
+/// POST /calibration/bed/probe - read all points from the gap sensors.
+pub async fn probe_bed_level(
+    State(state): State<AppState>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    send_command(&state, ProtocolMessage::ProbeBedLevel).await
+}
+
+/// POST /calibration/bed/save - store the height map.
+pub async fn save_bed_level(
+    State(state): State<AppState>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    send_command(&state, ProtocolMessage::SaveBedLevel).await
+}
+
+async fn send_command(
+    state: &AppState,
+    request: ProtocolMessage,
+) -> Result<StatusCode, (StatusCode, String)> {
+    let reply = request_firmware(state, request, |msg| {
+        matches!(msg, ProtocolMessage::CommandResponse(_))
+    })
+    .await
+    .map_err(|e| (StatusCode::BAD_GATEWAY, e.to_string()))?;
+
+    match reply {
+        ProtocolMessage::CommandResponse(response) if response.success => Ok(StatusCode::NO_CONTENT),
+        ProtocolMessage::CommandResponse(response) => Err((
+            StatusCode::CONFLICT,
+            response.error.unwrap_or(response.message),
+        )),
+        other => Err((
+            StatusCode::BAD_GATEWAY,
+            format!("Unexpected firmware reply: {}", other.message_type()),
+        )),
+    }
+}
@@ -0,0 +1,56 @@
+//! Protocol version and features negotiated with the firmware.
+
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::Json;
+use serde::Serialize;
+
+use protocol::{Capability, CapabilityDescriptor, Hello, ProtocolMessage, PROTOCOL_VERSION};
+
+use super::request_firmware;
+use crate::AppState;
+
+/// Body of GET /capabilities.
+#[derive(Debug, Serialize)]
+pub struct CapabilitiesResponse {
+    /// Protocol version this interface speaks
+    pub protocol_version: String,
+    /// The firmware's handshake; absent for firmware that predates it
+    pub firmware: Option<Hello>,
+    /// Features both sides support; the UI hides the rest
+    pub available: Vec<Capability>,
+}
+
+/// GET /capabilities - what the connected firmware supports.
+pub async fn get_capabilities(State(state): State<AppState>) -> Json<CapabilitiesResponse> {
+    let firmware = state.firmware_hello.read().await.clone();
+    let available = firmware
+        .as_ref()
+        .map(|hello| AppState::hello().common_capabilities(hello))
+        .unwrap_or_default();
+    Json(CapabilitiesResponse {
+        protocol_version: PROTOCOL_VERSION.to_string(),
+        firmware,
+        available,
+    })
+}
+
+/// GET /capabilities/descriptor - what the printer can print, for slicers
+/// matching a print to it (`hg4d-slicer --target`).
+pub async fn get_descriptor(
+    State(state): State<AppState>,
+) -> Result<Json<CapabilityDescriptor>, (StatusCode, String)> {
+    let reply = request_firmware(&state, ProtocolMessage::GetCapabilityDescriptor, |msg| {
+        matches!(msg, ProtocolMessage::CapabilityDescriptor(_))
+    })
+    .await
+    .map_err(|e| (StatusCode::BAD_GATEWAY, e.to_string()))?;
+
+    match reply {
+        ProtocolMessage::CapabilityDescriptor(descriptor) => Ok(Json(descriptor)),
+        other => Err((
+            StatusCode::BAD_GATEWAY,
+            format!("Unexpected firmware reply: {}", other.message_type()),
+        )),
+    }
+}
@@ -0,0 +1,83 @@
+//! Firmware link state and commands awaiting replay.
+//!
+//! Commands issued while the firmware was unreachable are only sent once an
+//! operator has reviewed them here; see [`crate::connection`].
+
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::{Extension, Json};
+use serde::{Deserialize, Serialize};
+
+use crate::{AppState, ConnectionState, PendingCommand, Role, Session};
+
+/// Body of GET /connection.
+#[derive(Debug, Serialize)]
+pub struct ConnectionResponse {
+    pub firmware_url: String,
+    pub state: ConnectionState,
+    /// Commands waiting for confirmation, oldest first
+    pub pending: Vec<PendingCommand>,
+}
+
+/// Commands a replay or discard applies to.
+#[derive(Debug, Default, Deserialize)]
+pub struct PendingSelection {
+    /// Command ids; all waiting commands if absent
+    pub ids: Option<Vec<u64>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReplayResponse {
+    /// Ids sent, in the order they were issued
+    pub sent: Vec<u64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DiscardResponse {
+    pub discarded: usize,
+}
+
+/// GET /connection - link state and waiting commands.
+pub async fn get_connection(State(state): State<AppState>) -> Json<ConnectionResponse> {
+    Json(ConnectionResponse {
+        firmware_url: state.firmware.url().to_string(),
+        state: state.firmware.state(),
+        pending: state.firmware.pending().await,
+    })
+}
+
+/// POST /connection/replay - send waiting commands now that the firmware is back.
+///
+/// The confirming user needs the role each selected command requires.
+pub async fn replay_pending(
+    State(state): State<AppState>,
+    Extension(session): Extension<Session>,
+    body: Option<Json<PendingSelection>>,
+) -> Result<Json<ReplayResponse>, (StatusCode, String)> {
+    let Json(selection) = body.unwrap_or_default();
+    for cmd in state.firmware.pending().await {
+        let selected = match &selection.ids {
+            Some(ids) => ids.contains(&cmd.id),
+            None => true,
+        };
+        if selected {
+            session.require(Role::required_for_message(&cmd.message))?;
+        }
+    }
+    let sent = state
+        .firmware
+        .replay(selection.ids.as_deref())
+        .await
+        .map_err(|e| (StatusCode::CONFLICT, format!("{:#}", e)))?;
+    Ok(Json(ReplayResponse { sent }))
+}
+
+/// POST /connection/discard - drop waiting commands without sending them.
+pub async fn discard_pending(
+    State(state): State<AppState>,
+    body: Option<Json<PendingSelection>>,
+) -> Json<DiscardResponse> {
+    let Json(selection) = body.unwrap_or_default();
+    let discarded = state.firmware.discard(selection.ids.as_deref()).await;
+    Json(DiscardResponse { discarded })
+}
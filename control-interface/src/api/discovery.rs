@@ -0,0 +1,62 @@
+//! Printers announcing themselves on the LAN, for adding to the fleet.
+
+use std::time::Duration;
+
+use axum::extract::{Query, State};
+use axum::http::StatusCode;
+use axum::Json;
+use serde::{Deserialize, Serialize};
+
+use crate::discovery::{discover, DiscoveredPrinter, DEFAULT_DISCOVERY_WINDOW};
+use crate::AppState;
+
+/// Longest listening window a request may ask for.
+const MAX_DISCOVERY_WINDOW: Duration = Duration::from_secs(15);
+
+/// Query string accepted by GET /discovery.
+#[derive(Debug, Default, Deserialize)]
+pub struct DiscoveryParams {
+    /// How long to listen (ms)
+    pub window_ms: Option<u64>,
+}
+
+/// A discovered printer and whether the fleet already manages it.
+#[derive(Debug, Serialize)]
+pub struct DiscoveryEntry {
+    #[serde(flatten)]
+    pub printer: DiscoveredPrinter,
+    /// URL to add the printer with
+    pub url: String,
+    /// Fleet name of the printer, if it is already managed
+    pub managed_as: Option<String>,
+}
+
+/// GET /discovery - printers found on the LAN.
+pub async fn get_discovered(
+    State(state): State<AppState>,
+    Query(params): Query<DiscoveryParams>,
+) -> Result<Json<Vec<DiscoveryEntry>>, (StatusCode, String)> {
+    let window = params
+        .window_ms
+        .map(Duration::from_millis)
+        .unwrap_or(DEFAULT_DISCOVERY_WINDOW)
+        .min(MAX_DISCOVERY_WINDOW);
+    let printers = discover(window)
+        .await
+        .map_err(|e| (StatusCode::SERVICE_UNAVAILABLE, format!("{:#}", e)))?;
+
+    let entries = printers
+        .into_iter()
+        .map(|printer| {
+            let url = printer.websocket_url();
+            let by_name = printer.announcement.websocket_url();
+            let managed_as = state
+                .fleet
+                .printers()
+                .find(|p| p.target.url == url || p.target.url == by_name)
+                .map(|p| p.name().to_string());
+            DiscoveryEntry { printer, url, managed_as }
+        })
+        .collect();
+    Ok(Json(entries))
+}
@@ -0,0 +1,113 @@
+//! Fleet dashboard, per-printer commands and bulk uploads.
+//!
+//! The other endpoints act on the default printer; these cover every
+//! printer of the [`crate::fleet`].
+
+use axum::extract::{Multipart, Path, State};
+use axum::http::StatusCode;
+use axum::{Extension, Json};
+use serde::Serialize;
+
+use protocol::{CommandResponse, ProtocolMessage};
+
+use crate::compat::store_upload;
+use crate::{AppState, FleetPrinter, PrinterSummary, Role, Session};
+
+/// Outcome of a bulk upload for one printer.
+#[derive(Debug, Serialize)]
+pub struct FleetUploadResult {
+    pub printer: String,
+    /// Whether the printer stored the file
+    pub uploaded: bool,
+    /// Whether the print was started
+    pub started: bool,
+    pub error: Option<String>,
+}
+
+/// GET /fleet - connection and status of every printer.
+pub async fn get_fleet(State(state): State<AppState>) -> Json<Vec<PrinterSummary>> {
+    Json(state.fleet.summaries().await)
+}
+
+/// POST /fleet/printers/:name/command - send a command message to one printer.
+///
+/// The body is a protocol command such as `{"type": "PausePrint", "data":
+/// {"reason": "user"}}`; the session needs the role the command requires.
+pub async fn send_command(
+    State(state): State<AppState>,
+    Extension(session): Extension<Session>,
+    Path(name): Path<String>,
+    Json(command): Json<ProtocolMessage>,
+) -> Result<Json<CommandResponse>, (StatusCode, String)> {
+    if !command.is_command() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("{} is not a command", command.message_type()),
+        ));
+    }
+    session.require(Role::required_for_message(&command))?;
+    let printer = state
+        .fleet
+        .get(&name)
+        .ok_or_else(|| (StatusCode::NOT_FOUND, format!("No printer named '{}'", name)))?;
+    command_printer(printer, command).await.map(Json)
+}
+
+/// POST /fleet/upload - push a .hg4d to every idle printer.
+///
+/// Takes the same multipart form as the single-printer upload: the file in
+/// `file`, and `print=true` to start it on each printer right away.
+/// Printers that are busy or offline are left out of the result.
+pub async fn upload_to_idle(
+    State(state): State<AppState>,
+    multipart: Multipart,
+) -> Result<Json<Vec<FleetUploadResult>>, (StatusCode, String)> {
+    let upload = store_upload(&state, multipart).await?;
+
+    let mut results = Vec::new();
+    for printer in state.fleet.idle().await {
+        let mut result = FleetUploadResult {
+            printer: printer.name().to_string(),
+            uploaded: false,
+            started: false,
+            error: None,
+        };
+        if let Err((_, e)) = printer.files.upload(&upload.name, upload.data.clone()).await {
+            result.error = Some(e);
+            results.push(result);
+            continue;
+        }
+        result.uploaded = true;
+
+        if upload.print {
+            match printer.files.start_print(&upload.name).await {
+                Ok(()) => result.started = true,
+                Err((_, e)) => result.error = Some(e),
+            }
+        }
+        results.push(result);
+    }
+    Ok(Json(results))
+}
+
+/// Sends a command and fails unless the printer acknowledges it.
+async fn command_printer(
+    printer: &FleetPrinter,
+    command: ProtocolMessage,
+) -> Result<CommandResponse, (StatusCode, String)> {
+    let reply = printer
+        .request(command, |msg| matches!(msg, ProtocolMessage::CommandResponse(_)))
+        .await
+        .map_err(|e| (StatusCode::BAD_GATEWAY, format!("{}: {:#}", printer.name(), e)))?;
+    match reply {
+        ProtocolMessage::CommandResponse(response) if response.success => Ok(response),
+        ProtocolMessage::CommandResponse(response) => Err((
+            StatusCode::CONFLICT,
+            format!("{}: {}", printer.name(), response.error.unwrap_or(response.message)),
+        )),
+        other => Err((
+            StatusCode::BAD_GATEWAY,
+            format!("{}: unexpected reply {}", printer.name(), other.message_type()),
+        )),
+    }
+}
@@ -0,0 +1,97 @@
+//! Print history endpoints for past-job lists and success-rate dashboards.
+
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::Json;
+use serde::Deserialize;
+
+use protocol::{
+    Capability, DeleteHistoryJobCommand, HistoryQuery, HistoryResponse, HistoryStats, JobResult, ProtocolMessage,
+};
+
+use super::{request_firmware, require_capability};
+use crate::AppState;
+
+/// Query string accepted by GET /history.
+#[derive(Debug, Default, Deserialize)]
+pub struct HistoryParams {
+    /// Only jobs that ended this way (completed, cancelled, failed, emergency_stopped)
+    pub result: Option<JobResult>,
+    /// Inclusive start time (ms since epoch)
+    pub since: Option<u64>,
+    /// Inclusive end time (ms since epoch)
+    pub until: Option<u64>,
+    /// Maximum number of jobs
+    pub limit: Option<usize>,
+}
+
+impl From<HistoryParams> for HistoryQuery {
+    fn from(params: HistoryParams) -> Self {
+        HistoryQuery {
+            result: params.result,
+            since_ms: params.since,
+            until_ms: params.until,
+            limit: params.limit,
+        }
+    }
+}
+
+/// GET /history - past jobs, newest first.
+pub async fn get_history(
+    State(state): State<AppState>,
+    Query(params): Query<HistoryParams>,
+) -> Result<Json<HistoryResponse>, (StatusCode, String)> {
+    match ask(&state, ProtocolMessage::GetHistory(params.into())).await? {
+        ProtocolMessage::HistoryResponse(history) => Ok(Json(history)),
+        other => Err(unexpected(other)),
+    }
+}
+
+/// GET /history/stats - job counts, success rate and material totals.
+pub async fn get_stats(State(state): State<AppState>) -> Result<Json<HistoryStats>, (StatusCode, String)> {
+    match ask(&state, ProtocolMessage::GetHistoryStats).await? {
+        ProtocolMessage::HistoryStats(stats) => Ok(Json(stats)),
+        other => Err(unexpected(other)),
+    }
+}
+
+/// DELETE /history/jobs/:id - remove a job from the history.
+pub async fn delete_job(
+    State(state): State<AppState>,
+    Path(id): Path<u64>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    match ask(&state, ProtocolMessage::DeleteHistoryJob(DeleteHistoryJobCommand { id })).await? {
+        ProtocolMessage::CommandResponse(response) if response.success => Ok(StatusCode::NO_CONTENT),
+        other => Err(unexpected(other)),
+    }
+}
+
+/// Sends a request and maps transport failures and refused commands.
+async fn ask(state: &AppState, request: ProtocolMessage) -> Result<ProtocolMessage, (StatusCode, String)> {
+    require_capability(state, Capability::PrintHistory).await?;
+    let reply = request_firmware(state, request, |msg| {
+        matches!(
+            msg,
+            ProtocolMessage::HistoryResponse(_)
+                | ProtocolMessage::HistoryStats(_)
+                | ProtocolMessage::CommandResponse(_)
+        )
+    })
+    .await
+    .map_err(|e| (StatusCode::BAD_GATEWAY, e.to_string()))?;
+
+    match reply {
+        ProtocolMessage::CommandResponse(response) if !response.success => Err((
+            StatusCode::CONFLICT,
+            response.error.unwrap_or(response.message),
+        )),
+        reply => Ok(reply),
+    }
+}
+
+fn unexpected(reply: ProtocolMessage) -> (StatusCode, String) {
+    (
+        StatusCode::BAD_GATEWAY,
+        format!("Unexpected firmware reply: {}", reply.message_type()),
+    )
+}
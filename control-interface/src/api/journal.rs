@@ -0,0 +1,65 @@
+//! Message journal queries.
+
+use axum::extract::{Query, State};
+use axum::http::StatusCode;
+use axum::Json;
+use serde::{Deserialize, Serialize};
+
+use crate::journal::JournalEntry;
+use crate::AppState;
+
+/// Most entries one request returns.
+pub const MAX_JOURNAL_ENTRIES: usize = 10_000;
+
+/// Query string accepted by GET /journal and /ws/journal.
+#[derive(Debug, Default, Deserialize)]
+pub struct JournalParams {
+    /// Inclusive start time (ms since epoch), default the oldest entry
+    pub from: Option<u64>,
+    /// Inclusive end time (ms since epoch), default the newest entry
+    pub to: Option<u64>,
+    /// Maximum number of entries (default and cap 10000)
+    pub limit: Option<usize>,
+    /// Replay speed relative to real time (WebSocket only, default 1)
+    pub speed: Option<f32>,
+}
+
+/// Journal contents for a time range.
+#[derive(Debug, Serialize)]
+pub struct JournalResponse {
+    pub entries: Vec<JournalEntry>,
+    /// Oldest and newest entry the journal holds, if any
+    pub span_ms: Option<(u64, u64)>,
+    /// Size of the journal on disk (bytes)
+    pub bytes: u64,
+}
+
+/// GET /journal - firmware messages received in a time range, oldest first.
+pub async fn get_journal(
+    State(state): State<AppState>,
+    Query(params): Query<JournalParams>,
+) -> Result<Json<JournalResponse>, (StatusCode, String)> {
+    let entries = query(&state, &params).await?;
+    let journal = state.journal.as_ref().expect("checked by query");
+    let (span_ms, bytes) = journal.extent();
+    Ok(Json(JournalResponse { entries, span_ms, bytes }))
+}
+
+/// Reads the entries `params` select; 404 if journaling is disabled.
+pub async fn query(state: &AppState, params: &JournalParams) -> Result<Vec<JournalEntry>, (StatusCode, String)> {
+    let journal = state
+        .journal
+        .clone()
+        .ok_or((StatusCode::NOT_FOUND, "Message journal is disabled".to_string()))?;
+    let from = params.from.unwrap_or(0);
+    let to = params.to.unwrap_or(u64::MAX);
+    if from > to {
+        return Err((StatusCode::BAD_REQUEST, "'from' is after 'to'".to_string()));
+    }
+    let limit = params.limit.unwrap_or(MAX_JOURNAL_ENTRIES).min(MAX_JOURNAL_ENTRIES);
+
+    tokio::task::spawn_blocking(move || journal.query(from, to, Some(limit)))
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("{:#}", e)))
+}
@@ -0,0 +1,91 @@
+//! Log retrieval endpoints backed by the firmware's in-memory log store.
+
+use axum::extract::{Query, State};
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use serde::Deserialize;
+
+use protocol::{LogLevel, LogQuery, LogsResponse, ProtocolMessage};
+
+use super::request_firmware;
+use crate::AppState;
+
+/// Query string accepted by the log endpoints.
+#[derive(Debug, Default, Deserialize)]
+pub struct LogParams {
+    /// Minimum level (trace, debug, info, warn, error)
+    pub level: Option<LogLevel>,
+    /// Subsystem filter (hardware, core, safety, ...)
+    pub subsystem: Option<String>,
+    /// Inclusive start time (ms since epoch)
+    pub since: Option<u64>,
+    /// Inclusive end time (ms since epoch)
+    pub until: Option<u64>,
+    /// Maximum number of entries
+    pub limit: Option<usize>,
+}
+
+impl From<LogParams> for LogQuery {
+    fn from(params: LogParams) -> Self {
+        LogQuery {
+            min_level: params.level,
+            subsystem: params.subsystem,
+            since_ms: params.since,
+            until_ms: params.until,
+            limit: params.limit,
+        }
+    }
+}
+
+/// GET /logs - recent log entries as JSON.
+pub async fn get_logs(
+    State(state): State<AppState>,
+    Query(params): Query<LogParams>,
+) -> Result<Json<LogsResponse>, (StatusCode, String)> {
+    fetch_logs(&state, params.into()).await.map(Json)
+}
+
+/// GET /logs/download - matching log entries as NDJSON attachment.
+pub async fn download_logs(
+    State(state): State<AppState>,
+    Query(params): Query<LogParams>,
+) -> Result<Response, (StatusCode, String)> {
+    let logs = fetch_logs(&state, params.into()).await?;
+
+    let mut body = String::new();
+    for entry in &logs.entries {
+        let line = serde_json::to_string(entry)
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        body.push_str(&line);
+        body.push('\n');
+    }
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/x-ndjson"),
+            (
+                header::CONTENT_DISPOSITION,
+                "attachment; filename=\"hypergcode-logs.ndjson\"",
+            ),
+        ],
+        body,
+    )
+        .into_response())
+}
+
+async fn fetch_logs(state: &AppState, query: LogQuery) -> Result<LogsResponse, (StatusCode, String)> {
+    let reply = request_firmware(state, ProtocolMessage::GetLogs(query), |msg| {
+        matches!(msg, ProtocolMessage::LogsResponse(_))
+    })
+    .await
+    .map_err(|e| (StatusCode::BAD_GATEWAY, e.to_string()))?;
+
+    match reply {
+        ProtocolMessage::LogsResponse(logs) => Ok(logs),
+        other => Err((
+            StatusCode::BAD_GATEWAY,
+            format!("Unexpected firmware reply: {}", other.message_type()),
+        )),
+    }
+}
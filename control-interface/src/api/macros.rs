@@ -0,0 +1,60 @@
+//! Operator macro endpoints.
+//!
+//! Macros are defined in the printer configuration; the firmware checks a
+//! macro's arguments against its safety limits before running any step.
+
+use std::collections::BTreeMap;
+
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::Json;
+
+use protocol::{MacrosResponse, ProtocolMessage, RunMacroCommand};
+
+use super::request_firmware;
+use crate::AppState;
+
+/// GET /macros - macros defined on the printer.
+pub async fn get_macros(State(state): State<AppState>) -> Result<Json<MacrosResponse>, (StatusCode, String)> {
+    let reply = request_firmware(&state, ProtocolMessage::GetMacros, |msg| {
+        matches!(msg, ProtocolMessage::MacrosResponse(_))
+    })
+    .await
+    .map_err(|e| (StatusCode::BAD_GATEWAY, e.to_string()))?;
+
+    match reply {
+        ProtocolMessage::MacrosResponse(macros) => Ok(Json(macros)),
+        other => Err((
+            StatusCode::BAD_GATEWAY,
+            format!("Unexpected firmware reply: {}", other.message_type()),
+        )),
+    }
+}
+
+/// POST /macros/:name - start a macro; the body maps parameter names to
+/// values. Answers once the macro is checked and running.
+pub async fn run_macro(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    args: Option<Json<BTreeMap<String, f32>>>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    let Json(args) = args.unwrap_or_default();
+    let request = ProtocolMessage::RunMacro(RunMacroCommand { name, args });
+    let reply = request_firmware(&state, request, |msg| {
+        matches!(msg, ProtocolMessage::CommandResponse(_))
+    })
+    .await
+    .map_err(|e| (StatusCode::BAD_GATEWAY, e.to_string()))?;
+
+    match reply {
+        ProtocolMessage::CommandResponse(response) if response.success => Ok(StatusCode::NO_CONTENT),
+        ProtocolMessage::CommandResponse(response) => Err((
+            StatusCode::CONFLICT,
+            response.error.unwrap_or(response.message),
+        )),
+        other => Err((
+            StatusCode::BAD_GATEWAY,
+            format!("Unexpected firmware reply: {}", other.message_type()),
+        )),
+    }
+}
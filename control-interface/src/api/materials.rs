@@ -0,0 +1,102 @@
+//! Loaded material endpoints.
+//!
+//! Operators record which material is on each channel after swapping a
+//! spool; the firmware checks print files against these before starting.
+//! Spools that have been loaded too long can be dried in place.
+
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::Json;
+use serde::Deserialize;
+
+use config_types::MaterialProfile;
+use protocol::{MaterialsResponse, ProtocolMessage, SetMaterialCommand, StartDryingCommand};
+
+use super::request_firmware;
+use crate::AppState;
+
+/// Overrides for the profile's drying parameters.
+#[derive(Debug, Default, Deserialize)]
+pub struct DryingBody {
+    pub temperature: Option<f32>,
+    pub hours: Option<f32>,
+}
+
+/// GET /materials - material loaded on each channel.
+pub async fn get_materials(
+    State(state): State<AppState>,
+) -> Result<Json<MaterialsResponse>, (StatusCode, String)> {
+    let reply = request_firmware(&state, ProtocolMessage::GetMaterials, |msg| {
+        matches!(msg, ProtocolMessage::MaterialsResponse(_))
+    })
+    .await
+    .map_err(|e| (StatusCode::BAD_GATEWAY, e.to_string()))?;
+
+    match reply {
+        ProtocolMessage::MaterialsResponse(materials) => Ok(Json(materials)),
+        other => Err((
+            StatusCode::BAD_GATEWAY,
+            format!("Unexpected firmware reply: {}", other.message_type()),
+        )),
+    }
+}
+
+/// PUT /materials/:channel - record the material loaded on a channel.
+pub async fn set_material(
+    State(state): State<AppState>,
+    Path(channel): Path<u8>,
+    Json(profile): Json<MaterialProfile>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    send_command(&state, ProtocolMessage::SetMaterial(SetMaterialCommand { channel, profile: Some(profile) })).await
+}
+
+/// DELETE /materials/:channel - mark a channel empty.
+pub async fn unload_material(
+    State(state): State<AppState>,
+    Path(channel): Path<u8>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    send_command(&state, ProtocolMessage::SetMaterial(SetMaterialCommand { channel, profile: None })).await
+}
+
+/// POST /materials/:channel/dry - start drying the spool on a channel.
+pub async fn start_drying(
+    State(state): State<AppState>,
+    Path(channel): Path<u8>,
+    body: Option<Json<DryingBody>>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    let Json(body) = body.unwrap_or_default();
+    let request = ProtocolMessage::StartDrying(StartDryingCommand {
+        channel,
+        temperature: body.temperature,
+        hours: body.hours,
+    });
+    send_command(&state, request).await
+}
+
+/// DELETE /materials/:channel/dry - stop the running drying cycle.
+pub async fn cancel_drying(
+    State(state): State<AppState>,
+    Path(_channel): Path<u8>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    send_command(&state, ProtocolMessage::CancelDrying).await
+}
+
+async fn send_command(state: &AppState, request: ProtocolMessage) -> Result<StatusCode, (StatusCode, String)> {
+    let reply = request_firmware(state, request, |msg| {
+        matches!(msg, ProtocolMessage::CommandResponse(_))
+    })
+    .await
+    .map_err(|e| (StatusCode::BAD_GATEWAY, e.to_string()))?;
+
+    match reply {
+        ProtocolMessage::CommandResponse(response) if response.success => Ok(StatusCode::NO_CONTENT),
+        ProtocolMessage::CommandResponse(response) => Err((
+            StatusCode::CONFLICT,
+            response.error.unwrap_or(response.message),
+        )),
+        other => Err((
+            StatusCode::BAD_GATEWAY,
+            format!("Unexpected firmware reply: {}", other.message_type()),
+        )),
+    }
+}
@@ -7,33 +7,191 @@
 //!
 //! - **status**: System status endpoints (/api/status)
 //! - **print**: Print job management (/api/print/*)
+//! - **objects**: Cancelling single objects of a running print (/api/print/objects/*)
 //! - **files**: File upload and management (/api/files/*)
+//! - **preview**: Layer preview tiles of uploaded files (/api/files/:filename/preview/*)
 //! - **config**: Configuration endpoints (/api/config/*)
 //! - **logs**: System logs access (/api/logs/*)
+//! - **valves**: Valve array visualization (/api/valves/*)
+//! - **materials**: Materials loaded per channel (/api/materials/*)
+//! - **calibration**: Flow calibration and bed levelling (/api/calibration/*)
+//! - **macros**: Operator routines defined in the printer configuration (/api/macros/*)
+//! - **users**: Login and user management (/api/auth/*)
+//! - **history**: Past print jobs and statistics (/api/history/*)
+//! - **capabilities**: Protocol version and features negotiated with the firmware, and the
+//!   printer's capability descriptor (/api/capabilities/*)
+//! - **connection**: Firmware link state and commands awaiting replay (/api/connection/*)
+//! - **fleet**: Status of every managed printer, commands and bulk uploads (/api/fleet/*)
+//! - **discovery**: Printers announcing themselves on the LAN (/api/discovery)
+//! - **journal**: Recorded firmware messages by time range (/api/journal)
 
 pub mod status;
 pub mod print;
+pub mod objects;
 pub mod files;
 pub mod config;
 pub mod logs;
+pub mod valves;
+pub mod materials;
+pub mod calibration;
+pub mod macros;
+pub mod users;
+pub mod history;
+pub mod capabilities;
+pub mod connection;
+pub mod fleet;
+pub mod discovery;
+pub mod journal;
+pub mod preview;
 
-use axum::{Router, routing::{get, post, delete}};
-use crate::AppState;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context, Result};
+use axum::{Router, routing::{get, post, put, delete}};
+use axum::body::Body;
+use axum::http::StatusCode;
+use protocol::{Capability, MessageClient, ProtocolMessage};
+use tokio::sync::broadcast;
+
+use crate::{AppState, Delivery, FirmwareConnection};
+
+/// How long a request handler waits for the firmware to reply.
+pub const FIRMWARE_REPLY_TIMEOUT: Duration = Duration::from_secs(5);
 
 /// Creates the complete API router with all endpoints.
 pub fn create_api_router() -> Router<AppState> {
     Router::new()
+        .route("/auth/login", post(users::login))
+        .route("/auth/logout", post(users::logout::<Body>))
+        .route("/auth/me", get(users::me))
+        .route("/auth/users", get(users::list_users))
+        .route("/auth/users/:name", put(users::put_user).delete(users::delete_user))
+        .route("/capabilities", get(capabilities::get_capabilities))
+        .route("/capabilities/descriptor", get(capabilities::get_descriptor))
+        .route("/connection", get(connection::get_connection))
+        .route("/connection/replay", post(connection::replay_pending))
+        .route("/connection/discard", post(connection::discard_pending))
+        .route("/fleet", get(fleet::get_fleet))
+        .route("/fleet/printers/:name/command", post(fleet::send_command))
+        .route("/fleet/upload", post(fleet::upload_to_idle))
+        .route("/discovery", get(discovery::get_discovered))
+        .route("/journal", get(journal::get_journal))
         .route("/status", get(status::get_status))
         .route("/status/detailed", get(status::get_detailed_status))
         .route("/print/start", post(print::start_print))
         .route("/print/pause", post(print::pause_print))
         .route("/print/resume", post(print::resume_print))
         .route("/print/cancel", post(print::cancel_print))
+        .route("/print/objects/:id/cancel", post(objects::cancel_object))
         .route("/files", get(files::list_files))
         .route("/files/upload", post(files::upload_file))
         .route("/files/:filename", delete(files::delete_file))
+        .route("/files/:filename/preview", get(preview::get_preview_info))
+        .route(
+            "/files/:filename/preview/:layer/:level/:x/:y",
+            get(preview::get_preview_tile),
+        )
         .route("/config", get(config::get_config))
         .route("/config", post(config::update_config))
         .route("/logs", get(logs::get_logs))
         .route("/logs/download", get(logs::download_logs))
+        .route("/valves/heatmap", get(valves::get_heatmap))
+        .route("/history", get(history::get_history))
+        .route("/history/stats", get(history::get_stats))
+        .route("/history/jobs/:id", delete(history::delete_job))
+        .route("/materials", get(materials::get_materials))
+        .route(
+            "/materials/:channel",
+            put(materials::set_material).delete(materials::unload_material),
+        )
+        .route(
+            "/materials/:channel/dry",
+            post(materials::start_drying).delete(materials::cancel_drying),
+        )
+        .route(
+            "/calibration/flow",
+            get(calibration::get_flow_calibration).post(calibration::start_flow_calibration),
+        )
+        .route(
+            "/calibration/flow/:channel/measurement",
+            post(calibration::submit_measurement),
+        )
+        .route("/calibration/flow/apply", post(calibration::apply_flow_calibration))
+        .route(
+            "/calibration/bed",
+            get(calibration::get_bed_level).post(calibration::start_bed_level),
+        )
+        .route("/calibration/bed/points/:index", post(calibration::submit_bed_point))
+        .route("/calibration/bed/probe", post(calibration::probe_bed_level))
+        .route("/calibration/bed/save", post(calibration::save_bed_level))
+        .route("/macros", get(macros::get_macros))
+        .route("/macros/:name", post(macros::run_macro))
+}
+/// Fails with 501 Not Implemented when the connected firmware did not
+/// announce `capability` in its handshake.
+pub async fn require_capability(state: &AppState, capability: Capability) -> Result<(), (StatusCode, String)> {
+    if state.firmware_supports(capability).await {
+        Ok(())
+    } else {
+        Err((
+            StatusCode::NOT_IMPLEMENTED,
+            format!("Connected firmware does not support {:?}", capability),
+        ))
+    }
+}
+
+/// Sends a request to the firmware and waits for the first reply accepted by
+/// `is_reply` on the shared message broadcast.
+///
+/// The subscription is taken before sending so a fast reply cannot be missed.
+/// Commands issued while the firmware is disconnected are queued for
+/// confirmation (see [`crate::connection`]) and reported as an error here.
+pub async fn request_firmware<F>(
+    state: &AppState,
+    request: ProtocolMessage,
+    is_reply: F,
+) -> Result<ProtocolMessage>
+where
+    F: Fn(&ProtocolMessage) -> bool,
+{
+    request_via(&state.firmware, &state.message_tx, request, is_reply).await
+}
+
+/// [`request_firmware`] over any firmware link and the broadcast its
+/// messages are forwarded to, e.g. a printer of the fleet.
+pub async fn request_via<F>(
+    firmware: &FirmwareConnection,
+    message_tx: &broadcast::Sender<ProtocolMessage>,
+    request: ProtocolMessage,
+    is_reply: F,
+) -> Result<ProtocolMessage>
+where
+    F: Fn(&ProtocolMessage) -> bool,
+{
+    let mut rx = message_tx.subscribe();
+    let request_type = request.message_type().to_string();
+
+    let delivery = firmware
+        .send(request)
+        .await
+        .with_context(|| format!("Failed to send {} to firmware", request_type))?;
+    if let Delivery::Queued(id) = delivery {
+        bail!(
+            "Firmware is disconnected; {} was queued as command {} and is sent once replay is confirmed",
+            request_type,
+            id
+        );
+    }
+
+    tokio::time::timeout(FIRMWARE_REPLY_TIMEOUT, async {
+        loop {
+            match rx.recv().await {
+                Ok(msg) if is_reply(&msg) => return Ok(msg),
+                Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(e) => return Err(anyhow!("Firmware message channel closed: {}", e)),
+            }
+        }
+    })
+    .await
+    .map_err(|_| anyhow!("Timed out waiting for firmware reply to {}", request_type))?
 }
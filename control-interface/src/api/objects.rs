@@ -0,0 +1,40 @@
+//! Per-object print control.
+
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::Json;
+
+use protocol::{CancelObjectCommand, Capability, CommandResponse, ProtocolMessage};
+
+use super::{request_firmware, require_capability};
+use crate::AppState;
+
+/// POST /print/objects/:id/cancel - stop depositing one object.
+///
+/// The other objects keep printing. The firmware rejects the request when no
+/// print is running, and older firmware without object cancellation gets a
+/// 501.
+pub async fn cancel_object(
+    State(state): State<AppState>,
+    Path(object_id): Path<u32>,
+) -> Result<Json<CommandResponse>, (StatusCode, String)> {
+    require_capability(&state, Capability::CancelObject).await?;
+    let request = ProtocolMessage::CancelObject(CancelObjectCommand { object_id });
+    let reply = request_firmware(&state, request, |msg| {
+        matches!(msg, ProtocolMessage::CommandResponse(_))
+    })
+    .await
+    .map_err(|e| (StatusCode::BAD_GATEWAY, e.to_string()))?;
+
+    match reply {
+        ProtocolMessage::CommandResponse(response) if response.success => Ok(Json(response)),
+        ProtocolMessage::CommandResponse(response) => Err((
+            StatusCode::CONFLICT,
+            response.error.unwrap_or(response.message),
+        )),
+        other => Err((
+            StatusCode::BAD_GATEWAY,
+            format!("Unexpected firmware reply: {}", other.message_type()),
+        )),
+    }
+}
@@ -0,0 +1,78 @@
+//! Print-preview endpoints for uploaded files.
+
+use axum::extract::{Path, State};
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use protocol::ProtocolMessage;
+
+use crate::api::request_firmware;
+use crate::compat::resolve_upload;
+use crate::preview::{render_tile, PreviewFile, PreviewInfo, TileAddress};
+use crate::AppState;
+
+/// GET /files/:filename/preview - layers, grid and zoom levels of a file's
+/// preview.
+pub async fn get_preview_info(
+    State(state): State<AppState>,
+    Path(filename): Path<String>,
+) -> Result<Json<PreviewInfo>, (StatusCode, String)> {
+    let path = upload_path(&state, &filename)?;
+    let grid = printer_grid(&state).await?;
+
+    tokio::task::spawn_blocking(move || {
+        PreviewFile::open(&path).map(|file| PreviewInfo::new(&filename, file.index(), grid))
+    })
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .map(Json)
+    .map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, format!("{:#}", e)))
+}
+
+/// GET /files/:filename/preview/:layer/:level/:x/:y - one PNG tile of a
+/// layer.
+pub async fn get_preview_tile(
+    State(state): State<AppState>,
+    Path((filename, layer, level, x, y)): Path<(String, u32, u32, u32, u32)>,
+) -> Result<Response, (StatusCode, String)> {
+    let path = upload_path(&state, &filename)?;
+    let grid = printer_grid(&state).await?;
+    let tile = TileAddress { level, x, y };
+    tile.validate(grid).map_err(|e| (StatusCode::NOT_FOUND, e.to_string()))?;
+
+    let previews = state.previews.clone();
+    let png = tokio::task::spawn_blocking(move || {
+        previews.layer(&path, layer).and_then(|frame| render_tile(&frame, grid, tile))
+    })
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, format!("{:#}", e)))?;
+
+    Ok(([(header::CONTENT_TYPE, "image/png")], png).into_response())
+}
+
+/// Path of an existing upload; 404 for unknown or unsafe names.
+fn upload_path(state: &AppState, filename: &str) -> Result<std::path::PathBuf, (StatusCode, String)> {
+    resolve_upload(state, filename)
+        .filter(|path| path.is_file())
+        .ok_or((StatusCode::NOT_FOUND, format!("No uploaded file '{}'", filename)))
+}
+
+/// Valve grid of the connected printer, asked from the firmware once.
+async fn printer_grid(state: &AppState) -> Result<(u32, u32), (StatusCode, String)> {
+    if let Some(grid) = state.previews.grid().await {
+        return Ok(grid);
+    }
+    let reply = request_firmware(state, ProtocolMessage::GetConfig, |msg| {
+        matches!(msg, ProtocolMessage::ConfigResponse(_))
+    })
+    .await
+    .map_err(|e| (StatusCode::SERVICE_UNAVAILABLE, format!("Printer grid size unknown: {:#}", e)))?;
+    let ProtocolMessage::ConfigResponse(config) = reply else {
+        return Err((StatusCode::BAD_GATEWAY, format!("Unexpected firmware reply: {}", reply.message_type())));
+    };
+
+    let grid = (config.printer_config.grid_x_count(), config.printer_config.grid_y_count());
+    state.previews.set_grid(grid).await;
+    Ok(grid)
+}
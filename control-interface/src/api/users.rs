@@ -0,0 +1,109 @@
+//! Login and user management endpoints.
+//!
+//! Login returns a session token for the `Authorization: Bearer` header;
+//! see [`crate::auth`] for which role each route needs.
+
+use axum::extract::{Path, State};
+use axum::http::{Request, StatusCode};
+use axum::{Extension, Json};
+use serde::{Deserialize, Serialize};
+
+use crate::auth::{request_token, Role, Session, SESSION_TTL};
+use crate::AppState;
+
+#[derive(Debug, Deserialize)]
+pub struct LoginBody {
+    pub username: String,
+    pub password: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LoginResponse {
+    pub token: String,
+    pub role: Role,
+    pub expires_in_s: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UserInfo {
+    pub username: String,
+    pub role: Role,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UserBody {
+    pub role: Role,
+    /// Required for new users; omitted to keep the current password
+    pub password: Option<String>,
+}
+
+/// POST /auth/login - open a session.
+pub async fn login(
+    State(state): State<AppState>,
+    Json(body): Json<LoginBody>,
+) -> Result<Json<LoginResponse>, (StatusCode, String)> {
+    let (token, session) = state
+        .auth
+        .login(&body.username, &body.password)
+        .await
+        .ok_or((StatusCode::UNAUTHORIZED, "Invalid user name or password".to_string()))?;
+    Ok(Json(LoginResponse {
+        token,
+        role: session.role,
+        expires_in_s: SESSION_TTL.as_secs(),
+    }))
+}
+
+/// POST /auth/logout - end the current session.
+pub async fn logout<B>(State(state): State<AppState>, req: Request<B>) -> StatusCode {
+    if let Some(token) = request_token(&req) {
+        state.auth.logout(&token).await;
+    }
+    StatusCode::NO_CONTENT
+}
+
+/// GET /auth/me - the logged-in user.
+pub async fn me(Extension(session): Extension<Session>) -> Json<UserInfo> {
+    Json(UserInfo {
+        username: session.username,
+        role: session.role,
+    })
+}
+
+/// GET /auth/users - all users and their roles.
+pub async fn list_users(State(state): State<AppState>) -> Json<Vec<UserInfo>> {
+    let users = state
+        .auth
+        .users()
+        .await
+        .into_iter()
+        .map(|(username, role)| UserInfo { username, role })
+        .collect();
+    Json(users)
+}
+
+/// PUT /auth/users/:name - create a user or change role and password.
+pub async fn put_user(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    Json(body): Json<UserBody>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    state
+        .auth
+        .set_user(&name, body.password.as_deref(), body.role)
+        .await
+        .map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, format!("{:#}", e)))?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// DELETE /auth/users/:name
+pub async fn delete_user(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    match state.auth.remove_user(&name).await {
+        Ok(true) => Ok(StatusCode::NO_CONTENT),
+        Ok(false) => Err((StatusCode::NOT_FOUND, format!("No user {}", name))),
+        Err(e) => Err((StatusCode::CONFLICT, format!("{:#}", e))),
+    }
+}
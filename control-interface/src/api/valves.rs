@@ -0,0 +1,35 @@
+//! Valve array visualization endpoint.
+
+use axum::extract::{Query, State};
+use axum::http::StatusCode;
+use axum::Json;
+use serde::Deserialize;
+
+use crate::visualization::{heatmap_from_update, Heatmap, DEFAULT_HEATMAP_SIZE};
+use crate::AppState;
+
+/// Query string accepted by the heatmap endpoints.
+#[derive(Debug, Default, Deserialize)]
+pub struct HeatmapParams {
+    /// Heatmap edge length in cells (default 128, max 512)
+    pub size: Option<u32>,
+}
+
+/// GET /valves/heatmap - latest valve frame binned to a heatmap.
+pub async fn get_heatmap(
+    State(state): State<AppState>,
+    Query(params): Query<HeatmapParams>,
+) -> Result<Json<Heatmap>, (StatusCode, String)> {
+    let update = state
+        .valve_frames
+        .latest()
+        .await
+        .ok_or((StatusCode::NOT_FOUND, "No valve frame received yet".to_string()))?;
+
+    heatmap_from_update(&update, params.size.unwrap_or(DEFAULT_HEATMAP_SIZE))
+        .map(Json)
+        .ok_or((
+            StatusCode::NOT_FOUND,
+            "Firmware valve updates do not include grid size".to_string(),
+        ))
+}
@@ -0,0 +1,408 @@
+//! # Users, Sessions and Authorization
+//!
+//! Every request except the index page, static files and `/auth/login`
+//! needs a session token, sent as `Authorization: Bearer <token>`,
+//! `X-Api-Key: <token>` (OctoPrint clients) or a `token` query parameter
+//! (browsers cannot set headers on WebSocket upgrades).
+//!
+//! Roles are ordered, each including the rights of the ones below:
+//!
+//! - **viewer**: read-only status, files and logs
+//! - **operator**: print control, materials, file uploads
+//! - **admin**: configuration, calibration, print history deletion and
+//!   user management
+//!
+//! Users are stored in a JSON file with Argon2 password hashes. Sessions
+//! live in memory only, so a restart logs everybody out.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Context, Result};
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use axum::extract::State;
+use axum::http::{header, Method, Request, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
+use tracing::{info, warn};
+
+use protocol::ProtocolMessage;
+
+use crate::AppState;
+
+/// Default location of the user database.
+pub const DEFAULT_USERS_FILE: &str = "./users.json";
+
+/// How long a login stays valid.
+pub const SESSION_TTL: Duration = Duration::from_secs(12 * 3600);
+
+/// Access level of a user.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Viewer,
+    Operator,
+    Admin,
+}
+
+impl Role {
+    /// Role needed for an HTTP request, or `None` for public routes.
+    pub fn required_for(method: &Method, path: &str) -> Option<Role> {
+        if path == "/" || path == "/auth/login" || path.starts_with("/static/") {
+            return None;
+        }
+        if path.starts_with("/auth/users") {
+            return Some(Role::Admin);
+        }
+        // Reads need no more than the matching WebSocket request, e.g.
+        // GET /history and GetHistory both need Viewer
+        if path.starts_with("/auth/") || method == Method::GET || method == Method::HEAD {
+            return Some(Role::Viewer);
+        }
+        let admin_writes = ["/config", "/calibration", "/history/jobs"];
+        let under = |prefix: &str| {
+            path.strip_prefix(prefix).is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+        };
+        if admin_writes.iter().any(|prefix| under(prefix)) {
+            return Some(Role::Admin);
+        }
+        Some(Role::Operator)
+    }
+
+    /// Role needed to send a message over the WebSocket.
+    pub fn required_for_message(msg: &ProtocolMessage) -> Role {
+        match msg {
+            ProtocolMessage::StartFlowCalibration(_)
+            | ProtocolMessage::SubmitFlowMeasurement(_)
+            | ProtocolMessage::ApplyFlowCalibration
+            | ProtocolMessage::StartBedLevel
+            | ProtocolMessage::SubmitBedLevelPoint(_)
+            | ProtocolMessage::ProbeBedLevel
+            | ProtocolMessage::SaveBedLevel
+            | ProtocolMessage::DeleteHistoryJob(_) => Role::Admin,
+            _ if msg.is_command() => Role::Operator,
+            _ => Role::Viewer,
+        }
+    }
+}
+
+/// A user record as stored on disk.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoredUser {
+    pub username: String,
+    pub role: Role,
+    /// Argon2 PHC string
+    pub password_hash: String,
+}
+
+/// A logged-in user, attached to authorized requests as an extension.
+#[derive(Debug, Clone)]
+pub struct Session {
+    pub username: String,
+    pub role: Role,
+    expires: Instant,
+}
+
+impl Session {
+    /// Fails with 403 unless the session has at least `role`.
+    pub fn require(&self, role: Role) -> Result<(), (StatusCode, String)> {
+        if self.role >= role {
+            Ok(())
+        } else {
+            Err((
+                StatusCode::FORBIDDEN,
+                format!("{} needs the {:?} role", self.username, role).to_lowercase(),
+            ))
+        }
+    }
+}
+
+/// User database and active sessions.
+pub struct AuthService {
+    /// File users are persisted to; `None` keeps them in memory
+    path: Option<PathBuf>,
+    users: RwLock<Vec<StoredUser>>,
+    sessions: RwLock<HashMap<String, Session>>,
+}
+
+impl AuthService {
+    /// An empty, unpersisted user database.
+    pub fn in_memory() -> Self {
+        Self {
+            path: None,
+            users: RwLock::new(Vec::new()),
+            sessions: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Loads the user database, starting empty if the file does not exist.
+    pub fn load(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let users = if path.exists() {
+            let content = std::fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read {}", path.display()))?;
+            serde_json::from_str(&content)
+                .with_context(|| format!("Failed to parse {}", path.display()))?
+        } else {
+            Vec::new()
+        };
+        Ok(Self {
+            path: Some(path),
+            users: RwLock::new(users),
+            sessions: RwLock::new(HashMap::new()),
+        })
+    }
+
+    /// Creates an `admin` account with a random password if there are no
+    /// users yet, returning the password so it can be shown once.
+    pub async fn bootstrap_admin(&self) -> Result<Option<String>> {
+        if !self.users.read().await.is_empty() {
+            return Ok(None);
+        }
+        let password = random_token(12);
+        self.set_user("admin", Some(&password), Role::Admin).await?;
+        Ok(Some(password))
+    }
+
+    /// Users without their password hashes.
+    pub async fn users(&self) -> Vec<(String, Role)> {
+        self.users
+            .read()
+            .await
+            .iter()
+            .map(|u| (u.username.clone(), u.role))
+            .collect()
+    }
+
+    /// Creates or updates a user. A new user needs a password.
+    pub async fn set_user(&self, username: &str, password: Option<&str>, role: Role) -> Result<()> {
+        if username.is_empty() || !username.chars().all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c)) {
+            bail!("Invalid user name '{}'", username);
+        }
+        let password_hash = password.map(hash_password).transpose()?;
+
+        let mut users = self.users.write().await;
+        match users.iter().position(|u| u.username == username) {
+            Some(index) => {
+                if users[index].role == Role::Admin && role != Role::Admin && admin_count(&users) == 1 {
+                    bail!("Cannot demote the last admin");
+                }
+                let user = &mut users[index];
+                user.role = role;
+                if let Some(hash) = password_hash {
+                    user.password_hash = hash;
+                }
+            }
+            None => {
+                let Some(password_hash) = password_hash else {
+                    bail!("New user {} needs a password", username);
+                };
+                users.push(StoredUser { username: username.to_string(), role, password_hash });
+            }
+        }
+        self.save(&users)?;
+        drop(users);
+
+        // Role or password changes apply from the next login
+        self.sessions.write().await.retain(|_, s| s.username != username);
+        Ok(())
+    }
+
+    /// Deletes a user and ends their sessions.
+    pub async fn remove_user(&self, username: &str) -> Result<bool> {
+        let mut users = self.users.write().await;
+        let Some(index) = users.iter().position(|u| u.username == username) else {
+            return Ok(false);
+        };
+        if users[index].role == Role::Admin && admin_count(&users) == 1 {
+            bail!("Cannot remove the last admin");
+        }
+        users.remove(index);
+        self.save(&users)?;
+        drop(users);
+
+        self.sessions.write().await.retain(|_, s| s.username != username);
+        Ok(true)
+    }
+
+    /// Checks credentials and opens a session, returning its token.
+    pub async fn login(&self, username: &str, password: &str) -> Option<(String, Session)> {
+        let role = {
+            let users = self.users.read().await;
+            let user = users.iter().find(|u| u.username == username)?;
+            let hash = PasswordHash::new(&user.password_hash).ok()?;
+            Argon2::default().verify_password(password.as_bytes(), &hash).ok()?;
+            user.role
+        };
+
+        let token = random_token(32);
+        let session = Session {
+            username: username.to_string(),
+            role,
+            expires: Instant::now() + SESSION_TTL,
+        };
+        let mut sessions = self.sessions.write().await;
+        let now = Instant::now();
+        sessions.retain(|_, s| s.expires > now);
+        sessions.insert(token.clone(), session.clone());
+        info!("{} logged in as {:?}", username, role);
+        Some((token, session))
+    }
+
+    pub async fn logout(&self, token: &str) {
+        self.sessions.write().await.remove(token);
+    }
+
+    /// Session for a token, if it exists and has not expired.
+    pub async fn session(&self, token: &str) -> Option<Session> {
+        self.sessions
+            .read()
+            .await
+            .get(token)
+            .filter(|s| s.expires > Instant::now())
+            .cloned()
+    }
+
+    fn save(&self, users: &[StoredUser]) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let tmp = path.with_extension("json.tmp");
+        std::fs::write(&tmp, serde_json::to_string_pretty(users)?)
+            .with_context(|| format!("Failed to write {}", tmp.display()))?;
+        restrict_permissions(&tmp);
+        std::fs::rename(&tmp, path)
+            .with_context(|| format!("Failed to replace {}", path.display()))
+    }
+}
+
+fn admin_count(users: &[StoredUser]) -> usize {
+    users.iter().filter(|u| u.role == Role::Admin).count()
+}
+
+fn hash_password(password: &str) -> Result<String> {
+    if password.len() < 8 {
+        bail!("Password must have at least 8 characters");
+    }
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map(|h| h.to_string())
+        .map_err(|e| anyhow::anyhow!("Failed to hash password: {}", e))
+}
+
+fn random_token(bytes: usize) -> String {
+    let mut buf = vec![0u8; bytes];
+    OsRng.fill_bytes(&mut buf);
+    buf.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(unix)]
+fn restrict_permissions(path: &Path) {
+    use std::os::unix::fs::PermissionsExt;
+    if let Err(e) = std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600)) {
+        warn!("Could not restrict permissions of {}: {}", path.display(), e);
+    }
+}
+
+#[cfg(not(unix))]
+fn restrict_permissions(_path: &Path) {}
+
+/// Session token of a request, from the headers or the query string.
+pub fn request_token<B>(req: &Request<B>) -> Option<String> {
+    let headers = req.headers();
+    if let Some(bearer) = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+    {
+        return Some(bearer.trim().to_string());
+    }
+    if let Some(key) = headers.get("x-api-key").and_then(|v| v.to_str().ok()) {
+        return Some(key.trim().to_string());
+    }
+    req.uri()
+        .query()?
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("token="))
+        .map(str::to_string)
+}
+
+/// Middleware rejecting requests whose session lacks the route's role.
+///
+/// Authorized requests carry their [`Session`] as an extension.
+pub async fn authorize<B>(State(state): State<AppState>, mut req: Request<B>, next: Next<B>) -> Response {
+    let Some(required) = Role::required_for(req.method(), req.uri().path()) else {
+        return next.run(req).await;
+    };
+
+    let session = match request_token(&req) {
+        Some(token) => state.auth.session(&token).await,
+        None => None,
+    };
+    let Some(session) = session else {
+        return (StatusCode::UNAUTHORIZED, "Login required").into_response();
+    };
+    if let Err(rejection) = session.require(required) {
+        warn!("{} denied {} {}", session.username, req.method(), req.uri().path());
+        return rejection.into_response();
+    }
+
+    req.extensions_mut().insert(session);
+    next.run(req).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_users_roles_and_sessions() {
+        let path = std::env::temp_dir().join(format!("hg4d-users-{}.json", std::process::id()));
+        let auth = AuthService::load(&path).unwrap();
+        let password = auth.bootstrap_admin().await.unwrap().unwrap();
+        auth.set_user("bench", Some("operator-pw"), Role::Operator).await.unwrap();
+        assert!(auth.remove_user("admin").await.is_err());
+
+        // Persisted with hashes only
+        let reloaded = AuthService::load(&path).unwrap();
+        assert!(!std::fs::read_to_string(&path).unwrap().contains("operator-pw"));
+        assert!(reloaded.login("bench", "wrong-password").await.is_none());
+        let (token, session) = reloaded.login("bench", "operator-pw").await.unwrap();
+        assert_eq!(session.role, Role::Operator);
+        assert!(reloaded.session(&token).await.is_some());
+        assert!(reloaded.login("admin", &password).await.is_some());
+
+        // Demotion ends the session
+        reloaded.set_user("bench", None, Role::Viewer).await.unwrap();
+        assert!(reloaded.session(&token).await.is_none());
+        std::fs::remove_file(&path).ok();
+
+        assert_eq!(Role::required_for(&Method::GET, "/status"), Some(Role::Viewer));
+        assert_eq!(Role::required_for(&Method::POST, "/print/start"), Some(Role::Operator));
+        assert_eq!(Role::required_for(&Method::POST, "/config"), Some(Role::Admin));
+        assert_eq!(Role::required_for(&Method::POST, "/auth/login"), None);
+        assert_eq!(Role::required_for_message(&ProtocolMessage::CancelPrint), Role::Operator);
+    }
+
+    #[test]
+    fn test_history_roles_match_websocket() {
+        use protocol::{DeleteHistoryJobCommand, HistoryQuery};
+
+        let get_history = ProtocolMessage::GetHistory(HistoryQuery::default());
+        assert_eq!(Role::required_for_message(&get_history), Role::Viewer);
+        assert_eq!(Role::required_for(&Method::GET, "/history"), Some(Role::Viewer));
+        assert_eq!(Role::required_for_message(&ProtocolMessage::GetHistoryStats), Role::Viewer);
+        assert_eq!(Role::required_for(&Method::GET, "/history/stats"), Some(Role::Viewer));
+
+        let delete = ProtocolMessage::DeleteHistoryJob(DeleteHistoryJobCommand { id: 3 });
+        assert_eq!(Role::required_for_message(&delete), Role::Admin);
+        assert_eq!(Role::required_for(&Method::DELETE, "/history/jobs/3"), Some(Role::Admin));
+        assert_eq!(Role::required_for(&Method::POST, "/configure"), Some(Role::Operator));
+    }
+}
@@ -0,0 +1,254 @@
+//! # Third-Party API Compatibility
+//!
+//! Translates a subset of the OctoPrint and Moonraker REST APIs onto the
+//! HyperGCode-4D protocol so existing dashboards and mobile apps can monitor
+//! and control the printer without modification.
+//!
+//! ## Module Organization
+//!
+//! - **octoprint**: OctoPrint endpoints (/api/version, /api/job, /api/printer, ...)
+//! - **moonraker**: Moonraker endpoints (/server/*, /printer/*)
+//!
+//! Only job status, start/pause/resume/cancel, file upload and temperatures
+//! are supported. Everything else returns 404 as the router does not match.
+
+pub mod octoprint;
+pub mod moonraker;
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use axum::body::Bytes;
+use axum::extract::Multipart;
+use axum::http::StatusCode;
+use axum::{Extension, Router};
+use tokio::sync::RwLock;
+
+use protocol::{
+    AdjustParameterCommand, AdjustTiming, AdjustableParameter, CommandResponse, GetStatusRequest,
+    PausePrintCommand, ProtocolMessage, StatusResponse,
+};
+
+use crate::api::request_firmware;
+use crate::AppState;
+
+/// Error type returned by compatibility handlers.
+pub type CompatError = (StatusCode, String);
+
+/// State shared by the compatibility handlers.
+#[derive(Clone, Default)]
+pub struct CompatState {
+    /// Name of the file selected for printing (OctoPrint "select" / last
+    /// upload)
+    pub selected_file: Arc<RwLock<Option<String>>>,
+}
+
+/// Creates the router serving both OctoPrint and Moonraker endpoints.
+pub fn create_compat_router() -> Router<AppState> {
+    Router::new()
+        .nest("/api", octoprint::router())
+        .merge(moonraker::router())
+        .layer(Extension(CompatState::default()))
+}
+
+/// Simplified printer state shared by both API flavours.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PrinterActivity {
+    Idle,
+    Printing,
+    Paused,
+    Error,
+    Offline,
+}
+
+impl PrinterActivity {
+    /// Maps the firmware state name onto a coarse activity.
+    pub fn from_state(state: &str) -> Self {
+        let state = state.to_ascii_lowercase();
+        if state.contains("error") || state.contains("emergency") {
+            PrinterActivity::Error
+        } else if state.contains("paus") {
+            PrinterActivity::Paused
+        } else if state.contains("print") {
+            PrinterActivity::Printing
+        } else if state.contains("offline") || state.contains("disconnect") {
+            PrinterActivity::Offline
+        } else {
+            PrinterActivity::Idle
+        }
+    }
+}
+
+/// Requests a full status snapshot from the firmware.
+pub async fn fetch_status(state: &AppState) -> Result<StatusResponse, CompatError> {
+    let request = ProtocolMessage::GetStatus(GetStatusRequest { status_type: None });
+    let reply = request_firmware(state, request, |msg| {
+        matches!(msg, ProtocolMessage::StatusResponse(_))
+    })
+    .await
+    .map_err(|e| (StatusCode::BAD_GATEWAY, e.to_string()))?;
+
+    match reply {
+        ProtocolMessage::StatusResponse(status) => Ok(status),
+        other => Err(unexpected_reply(&other)),
+    }
+}
+
+/// Sends a command and fails unless the firmware acknowledges it.
+pub async fn send_command(state: &AppState, command: ProtocolMessage) -> Result<(), CompatError> {
+    let reply = request_firmware(state, command, |msg| {
+        matches!(msg, ProtocolMessage::CommandResponse(_))
+    })
+    .await
+    .map_err(|e| (StatusCode::BAD_GATEWAY, e.to_string()))?;
+
+    match reply {
+        ProtocolMessage::CommandResponse(CommandResponse { success: true, .. }) => Ok(()),
+        ProtocolMessage::CommandResponse(resp) => Err((
+            StatusCode::CONFLICT,
+            resp.error.unwrap_or(resp.message),
+        )),
+        other => Err(unexpected_reply(&other)),
+    }
+}
+
+/// Sets the target temperature of a heating zone.
+pub async fn set_temperature(state: &AppState, zone: u8, target: f32) -> Result<(), CompatError> {
+    send_command(
+        state,
+        ProtocolMessage::AdjustParameter(AdjustParameterCommand {
+            parameter: AdjustableParameter::Temperature,
+            channel_or_zone: Some(zone),
+            value: target,
+            unit: "C".to_string(),
+            apply: AdjustTiming::Immediate,
+        }),
+    )
+    .await
+}
+
+/// Starts printing a file stored on the default printer.
+pub async fn start_file(state: &AppState, name: &str) -> Result<(), CompatError> {
+    state.default_printer().files.start_print(name).await
+}
+
+/// Pushes an upload to the default printer, then starts it if the client
+/// asked to.
+pub async fn send_upload(state: &AppState, upload: &StoredUpload) -> Result<(), CompatError> {
+    let files = &state.default_printer().files;
+    files.upload(&upload.name, upload.data.clone()).await?;
+    if upload.print {
+        files.start_print(&upload.name).await?;
+    }
+    Ok(())
+}
+
+/// Builds a user-initiated PausePrint message.
+pub fn pause_message() -> ProtocolMessage {
+    ProtocolMessage::PausePrint(PausePrintCommand {
+        reason: "user".to_string(),
+    })
+}
+
+/// Uploaded file and whether the client asked to print it immediately.
+pub struct StoredUpload {
+    pub name: String,
+    /// The interface's copy
+    pub path: PathBuf,
+    /// Contents, for pushing to printers
+    pub data: Bytes,
+    pub print: bool,
+}
+
+/// Stores the `file` part of a multipart upload in the upload directory;
+/// printers get it through [`send_upload`].
+///
+/// Both APIs use a `print` form field set to "true" to start the job right
+/// after uploading.
+pub async fn store_upload(state: &AppState, mut multipart: Multipart) -> Result<StoredUpload, CompatError> {
+    let mut stored: Option<(String, PathBuf, Bytes)> = None;
+    let mut print = false;
+
+    while let Some(field) = multipart
+        .next_field()
+        .await
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?
+    {
+        match field.name() {
+            Some("file") => {
+                let name = sanitize_filename(field.file_name().unwrap_or("upload.hg4d"))
+                    .ok_or_else(|| (StatusCode::BAD_REQUEST, "Invalid file name".to_string()))?;
+                let data = field
+                    .bytes()
+                    .await
+                    .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+
+                tokio::fs::create_dir_all(&state.upload_dir)
+                    .await
+                    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+                let path = state.upload_dir.join(&name);
+                tokio::fs::write(&path, &data)
+                    .await
+                    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+                stored = Some((name, path, data));
+            }
+            Some("print") => {
+                let value = field.text().await.unwrap_or_default();
+                print = value.eq_ignore_ascii_case("true");
+            }
+            _ => {}
+        }
+    }
+
+    let (name, path, data) =
+        stored.ok_or_else(|| (StatusCode::BAD_REQUEST, "Missing 'file' field".to_string()))?;
+    Ok(StoredUpload { name, path, data, print })
+}
+
+/// Checks a client-supplied name of a file to print.
+pub fn print_file_name(name: &str) -> Result<String, CompatError> {
+    sanitize_filename(name).ok_or_else(|| (StatusCode::BAD_REQUEST, format!("Invalid file name: {}", name)))
+}
+
+/// Resolves a client-supplied file name inside the upload directory.
+pub fn resolve_upload(state: &AppState, name: &str) -> Option<PathBuf> {
+    sanitize_filename(name).map(|n| state.upload_dir.join(n))
+}
+
+/// Reduces a client-supplied name to a bare file name, rejecting anything
+/// that could escape the upload directory.
+fn sanitize_filename(name: &str) -> Option<String> {
+    let base = Path::new(name).file_name()?.to_str()?;
+    if base.is_empty() || base.starts_with('.') {
+        return None;
+    }
+    Some(base.to_string())
+}
+
+fn unexpected_reply(msg: &ProtocolMessage) -> CompatError {
+    (
+        StatusCode::BAD_GATEWAY,
+        format!("Unexpected firmware reply: {}", msg.message_type()),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_activity_from_state() {
+        assert_eq!(PrinterActivity::from_state("Printing"), PrinterActivity::Printing);
+        assert_eq!(PrinterActivity::from_state("Paused"), PrinterActivity::Paused);
+        assert_eq!(PrinterActivity::from_state("EmergencyStop"), PrinterActivity::Error);
+        assert_eq!(PrinterActivity::from_state("Idle"), PrinterActivity::Idle);
+    }
+
+    #[test]
+    fn test_sanitize_filename() {
+        assert_eq!(sanitize_filename("part.hg4d").as_deref(), Some("part.hg4d"));
+        assert_eq!(sanitize_filename("../../etc/passwd").as_deref(), Some("passwd"));
+        assert_eq!(sanitize_filename(".hidden"), None);
+        assert_eq!(sanitize_filename(".."), None);
+    }
+}
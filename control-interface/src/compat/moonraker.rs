@@ -0,0 +1,255 @@
+//! Moonraker REST API subset.
+//!
+//! Responses use Moonraker's `{"result": ...}` envelope. Heating zone 0 is
+//! exposed as `extruder`, further zones as `extruder1`, `extruder2`, ...
+//! G-code scripts only understand M104/M109 (zone temperature).
+
+use std::collections::HashMap;
+
+use axum::extract::{Multipart, Query, State};
+use axum::http::StatusCode;
+use axum::routing::{get, post};
+use axum::{Extension, Json, Router};
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+
+use protocol::{ProtocolMessage, StatusResponse};
+
+use super::{
+    fetch_status, pause_message, print_file_name, send_command, send_upload, set_temperature, start_file,
+    store_upload, CompatError, CompatState, PrinterActivity,
+};
+use crate::AppState;
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/server/info", get(server_info))
+        .route("/printer/info", get(printer_info))
+        .route("/printer/objects/query", get(query_objects).post(query_objects))
+        .route("/printer/print/start", post(print_start))
+        .route("/printer/print/pause", post(print_pause))
+        .route("/printer/print/resume", post(print_resume))
+        .route("/printer/print/cancel", post(print_cancel))
+        .route("/printer/gcode/script", post(gcode_script))
+        .route("/server/files/upload", post(upload_file))
+}
+
+fn ok() -> Json<Value> {
+    Json(json!({ "result": "ok" }))
+}
+
+/// GET /server/info
+async fn server_info(State(state): State<AppState>) -> Json<Value> {
+    let connected = fetch_status(&state).await.is_ok();
+    Json(json!({
+        "result": {
+            "klippy_connected": connected,
+            "klippy_state": if connected { "ready" } else { "disconnected" },
+            "components": ["file_manager"],
+            "moonraker_version": format!("hypergcode-{}", env!("CARGO_PKG_VERSION")),
+            "api_version": [1, 0, 0],
+        }
+    }))
+}
+
+/// GET /printer/info
+async fn printer_info(State(state): State<AppState>) -> Result<Json<Value>, CompatError> {
+    let status = fetch_status(&state).await?;
+    let activity = PrinterActivity::from_state(&status.state);
+    Ok(Json(json!({
+        "result": {
+            "state": if activity == PrinterActivity::Error { "error" } else { "ready" },
+            "state_message": status.state,
+            "hostname": "hypergcode-4d",
+            "software_version": env!("CARGO_PKG_VERSION"),
+        }
+    })))
+}
+
+/// GET|POST /printer/objects/query?print_stats&extruder&heater_bed&virtual_sdcard
+///
+/// Object attribute filters (`extruder=temperature,target`) are accepted but
+/// the full object is always returned.
+async fn query_objects(
+    State(state): State<AppState>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<Json<Value>, CompatError> {
+    let status = fetch_status(&state).await?;
+    let all = status_objects(&status);
+
+    let status_map: Map<String, Value> = if params.is_empty() {
+        all
+    } else {
+        all.into_iter()
+            .filter(|(name, _)| params.contains_key(name))
+            .collect()
+    };
+
+    Ok(Json(json!({
+        "result": {
+            "eventtime": 0.0,
+            "status": status_map,
+        }
+    })))
+}
+
+#[derive(Debug, Deserialize)]
+struct StartParams {
+    filename: String,
+}
+
+/// POST /printer/print/start?filename=...
+async fn print_start(
+    State(state): State<AppState>,
+    Extension(compat): Extension<CompatState>,
+    Query(params): Query<StartParams>,
+) -> Result<Json<Value>, CompatError> {
+    let name = print_file_name(&params.filename)?;
+    start_file(&state, &name).await?;
+    *compat.selected_file.write().await = Some(name);
+    Ok(ok())
+}
+
+/// POST /printer/print/pause
+async fn print_pause(State(state): State<AppState>) -> Result<Json<Value>, CompatError> {
+    send_command(&state, pause_message()).await?;
+    Ok(ok())
+}
+
+/// POST /printer/print/resume
+async fn print_resume(State(state): State<AppState>) -> Result<Json<Value>, CompatError> {
+    send_command(&state, ProtocolMessage::ResumePrint).await?;
+    Ok(ok())
+}
+
+/// POST /printer/print/cancel
+async fn print_cancel(State(state): State<AppState>) -> Result<Json<Value>, CompatError> {
+    send_command(&state, ProtocolMessage::CancelPrint).await?;
+    Ok(ok())
+}
+
+#[derive(Debug, Deserialize)]
+struct ScriptParams {
+    script: String,
+}
+
+/// POST /printer/gcode/script?script=...
+async fn gcode_script(
+    State(state): State<AppState>,
+    Query(params): Query<ScriptParams>,
+) -> Result<Json<Value>, CompatError> {
+    for line in params.script.lines().map(str::trim).filter(|l| !l.is_empty()) {
+        let (zone, target) = parse_temperature_gcode(line).ok_or_else(|| {
+            (StatusCode::BAD_REQUEST, format!("Unsupported G-code: {}", line))
+        })?;
+        set_temperature(&state, zone, target).await?;
+    }
+    Ok(ok())
+}
+
+/// POST /server/files/upload
+async fn upload_file(
+    State(state): State<AppState>,
+    Extension(compat): Extension<CompatState>,
+    multipart: Multipart,
+) -> Result<(StatusCode, Json<Value>), CompatError> {
+    let upload = store_upload(&state, multipart).await?;
+    send_upload(&state, &upload).await?;
+    *compat.selected_file.write().await = Some(upload.name.clone());
+
+    Ok((
+        StatusCode::CREATED,
+        Json(json!({
+            "result": {
+                "item": { "path": upload.name, "root": "gcodes" },
+                "print_started": upload.print,
+                "action": "create_file",
+            }
+        })),
+    ))
+}
+
+/// Builds Moonraker printer objects from a firmware status snapshot.
+fn status_objects(status: &StatusResponse) -> Map<String, Value> {
+    let mut objects = Map::new();
+
+    let print_state = match PrinterActivity::from_state(&status.state) {
+        PrinterActivity::Printing => "printing",
+        PrinterActivity::Paused => "paused",
+        PrinterActivity::Error => "error",
+        PrinterActivity::Idle | PrinterActivity::Offline => "standby",
+    };
+    let (filename, progress, layer, total_layers) = match &status.print_status {
+        Some(ps) => (
+            ps.file_path.clone(),
+            ps.progress_percent / 100.0,
+            Some(ps.current_layer),
+            Some(ps.total_layers),
+        ),
+        None => (String::new(), 0.0, None, None),
+    };
+
+    objects.insert(
+        "print_stats".to_string(),
+        json!({
+            "state": print_state,
+            "filename": filename,
+            "info": { "current_layer": layer, "total_layer": total_layers },
+        }),
+    );
+    objects.insert(
+        "virtual_sdcard".to_string(),
+        json!({ "progress": progress, "is_active": print_state == "printing" }),
+    );
+
+    for zone in &status.thermal.zones {
+        let name = if zone.id == 0 {
+            "extruder".to_string()
+        } else {
+            format!("extruder{}", zone.id)
+        };
+        objects.insert(name, json!({ "temperature": zone.current, "target": zone.target }));
+    }
+    if let Some(bed) = &status.thermal.bed {
+        objects.insert(
+            "heater_bed".to_string(),
+            json!({ "temperature": bed.current, "target": bed.target }),
+        );
+    }
+
+    objects
+}
+
+/// Parses `M104 S<temp> [T<zone>]` / `M109 ...` into (zone, target).
+fn parse_temperature_gcode(line: &str) -> Option<(u8, f32)> {
+    let mut words = line.split_whitespace();
+    let code = words.next()?.to_ascii_uppercase();
+    if code != "M104" && code != "M109" {
+        return None;
+    }
+
+    let mut zone = 0u8;
+    let mut target = None;
+    for word in words {
+        let (letter, value) = word.split_at(1);
+        match letter.to_ascii_uppercase().as_str() {
+            "S" => target = value.parse::<f32>().ok(),
+            "T" => zone = value.parse::<u8>().ok()?,
+            _ => {}
+        }
+    }
+    target.map(|t| (zone, t))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_temperature_gcode() {
+        assert_eq!(parse_temperature_gcode("M104 S210"), Some((0, 210.0)));
+        assert_eq!(parse_temperature_gcode("m109 s200 t2"), Some((2, 200.0)));
+        assert_eq!(parse_temperature_gcode("G28"), None);
+        assert_eq!(parse_temperature_gcode("M104 T1"), None);
+    }
+}
@@ -0,0 +1,257 @@
+//! OctoPrint REST API subset.
+//!
+//! Mounted under `/api`. Heating zone 0 is reported as `tool0`; further zones
+//! appear as `tool1`, `tool2`, ... The manifold is not exposed.
+
+use axum::extract::{Multipart, Path, State};
+use axum::http::StatusCode;
+use axum::routing::{get, post};
+use axum::{Extension, Json, Router};
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+
+use protocol::{ProtocolMessage, StatusResponse};
+
+use super::{
+    fetch_status, pause_message, print_file_name, send_command, send_upload, set_temperature, start_file,
+    store_upload, CompatError, CompatState, PrinterActivity,
+};
+use crate::AppState;
+
+/// OctoPrint API version reported to clients.
+const API_VERSION: &str = "0.1";
+
+/// OctoPrint server version clients should assume.
+const SERVER_VERSION: &str = "1.9.0";
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/version", get(get_version))
+        .route("/job", get(get_job).post(post_job))
+        .route("/printer", get(get_printer))
+        .route("/printer/tool", post(post_tool))
+        .route("/files/local", post(upload_file))
+        .route("/files/local/:filename", post(file_command))
+}
+
+/// GET /api/version
+async fn get_version() -> Json<Value> {
+    Json(json!({
+        "api": API_VERSION,
+        "server": SERVER_VERSION,
+        "text": format!("OctoPrint {} (HyperGCode-4D compatibility)", SERVER_VERSION),
+    }))
+}
+
+/// GET /api/job
+async fn get_job(
+    State(state): State<AppState>,
+    Extension(compat): Extension<CompatState>,
+) -> Result<Json<Value>, CompatError> {
+    let status = fetch_status(&state).await?;
+    let selected = compat.selected_file.read().await.clone();
+
+    let (name, path, completion) = match &status.print_status {
+        Some(ps) => {
+            let name = std::path::Path::new(&ps.file_path)
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned());
+            (name, Some(ps.file_path.clone()), Some(ps.progress_percent))
+        }
+        None => (selected.clone(), selected, None),
+    };
+
+    Ok(Json(json!({
+        "job": {
+            "file": { "name": name, "path": path, "origin": "local" },
+            "estimatedPrintTime": null,
+        },
+        "progress": {
+            "completion": completion,
+            "printTime": null,
+            "printTimeLeft": null,
+        },
+        "state": state_text(&status),
+    })))
+}
+
+#[derive(Debug, Deserialize)]
+struct JobCommand {
+    command: String,
+    action: Option<String>,
+}
+
+/// POST /api/job
+async fn post_job(
+    State(state): State<AppState>,
+    Extension(compat): Extension<CompatState>,
+    Json(cmd): Json<JobCommand>,
+) -> Result<StatusCode, CompatError> {
+    let message = match cmd.command.as_str() {
+        "start" => {
+            let file = compat
+                .selected_file
+                .read()
+                .await
+                .clone()
+                .ok_or_else(|| (StatusCode::CONFLICT, "No file selected".to_string()))?;
+            start_file(&state, &file).await?;
+            return Ok(StatusCode::NO_CONTENT);
+        }
+        "cancel" => ProtocolMessage::CancelPrint,
+        "pause" => match cmd.action.as_deref().unwrap_or("toggle") {
+            "pause" => pause_message(),
+            "resume" => ProtocolMessage::ResumePrint,
+            "toggle" => {
+                let status = fetch_status(&state).await?;
+                match PrinterActivity::from_state(&status.state) {
+                    PrinterActivity::Paused => ProtocolMessage::ResumePrint,
+                    _ => pause_message(),
+                }
+            }
+            other => {
+                return Err((StatusCode::BAD_REQUEST, format!("Unknown action: {}", other)));
+            }
+        },
+        other => {
+            return Err((StatusCode::BAD_REQUEST, format!("Unknown command: {}", other)));
+        }
+    };
+
+    send_command(&state, message).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// GET /api/printer
+async fn get_printer(State(state): State<AppState>) -> Result<Json<Value>, CompatError> {
+    let status = fetch_status(&state).await?;
+    let activity = PrinterActivity::from_state(&status.state);
+
+    let mut temperature = Map::new();
+    for zone in &status.thermal.zones {
+        temperature.insert(
+            format!("tool{}", zone.id),
+            json!({ "actual": zone.current, "target": zone.target, "offset": 0 }),
+        );
+    }
+    if let Some(bed) = &status.thermal.bed {
+        temperature.insert(
+            "bed".to_string(),
+            json!({ "actual": bed.current, "target": bed.target, "offset": 0 }),
+        );
+    }
+    if let Some(chamber) = &status.thermal.chamber {
+        temperature.insert(
+            "chamber".to_string(),
+            json!({ "actual": chamber.current, "target": chamber.target, "offset": 0 }),
+        );
+    }
+
+    Ok(Json(json!({
+        "temperature": temperature,
+        "state": {
+            "text": state_text(&status),
+            "flags": {
+                "operational": activity != PrinterActivity::Offline,
+                "printing": activity == PrinterActivity::Printing,
+                "paused": activity == PrinterActivity::Paused,
+                "pausing": false,
+                "cancelling": false,
+                "error": activity == PrinterActivity::Error,
+                "ready": activity == PrinterActivity::Idle,
+                "closedOrError": matches!(activity, PrinterActivity::Error | PrinterActivity::Offline),
+                "sdReady": true,
+            },
+        },
+    })))
+}
+
+#[derive(Debug, Deserialize)]
+struct ToolCommand {
+    command: String,
+    #[serde(default)]
+    targets: std::collections::HashMap<String, f32>,
+}
+
+/// POST /api/printer/tool
+async fn post_tool(
+    State(state): State<AppState>,
+    Json(cmd): Json<ToolCommand>,
+) -> Result<StatusCode, CompatError> {
+    if cmd.command != "target" {
+        return Err((StatusCode::BAD_REQUEST, format!("Unsupported tool command: {}", cmd.command)));
+    }
+
+    for (tool, target) in &cmd.targets {
+        let zone = tool
+            .strip_prefix("tool")
+            .and_then(|n| n.parse::<u8>().ok())
+            .ok_or_else(|| (StatusCode::BAD_REQUEST, format!("Unknown tool: {}", tool)))?;
+        set_temperature(&state, zone, *target).await?;
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// POST /api/files/local
+async fn upload_file(
+    State(state): State<AppState>,
+    Extension(compat): Extension<CompatState>,
+    multipart: Multipart,
+) -> Result<(StatusCode, Json<Value>), CompatError> {
+    let upload = store_upload(&state, multipart).await?;
+    send_upload(&state, &upload).await?;
+    *compat.selected_file.write().await = Some(upload.name.clone());
+
+    Ok((
+        StatusCode::CREATED,
+        Json(json!({
+            "done": true,
+            "files": {
+                "local": {
+                    "name": upload.name,
+                    "path": upload.name,
+                    "origin": "local",
+                },
+            },
+        })),
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+struct FileCommand {
+    command: String,
+    #[serde(default)]
+    print: bool,
+}
+
+/// POST /api/files/local/:filename (select command)
+async fn file_command(
+    State(state): State<AppState>,
+    Extension(compat): Extension<CompatState>,
+    Path(filename): Path<String>,
+    Json(cmd): Json<FileCommand>,
+) -> Result<StatusCode, CompatError> {
+    if cmd.command != "select" {
+        return Err((StatusCode::BAD_REQUEST, format!("Unsupported file command: {}", cmd.command)));
+    }
+
+    let name = print_file_name(&filename)?;
+    if cmd.print {
+        start_file(&state, &name).await?;
+    }
+    *compat.selected_file.write().await = Some(name);
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// OctoPrint's human-readable state names.
+fn state_text(status: &StatusResponse) -> &'static str {
+    match PrinterActivity::from_state(&status.state) {
+        PrinterActivity::Idle => "Operational",
+        PrinterActivity::Printing => "Printing",
+        PrinterActivity::Paused => "Paused",
+        PrinterActivity::Error => "Error",
+        PrinterActivity::Offline => "Offline",
+    }
+}
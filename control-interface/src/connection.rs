@@ -0,0 +1,419 @@
+//! Firmware connection with automatic reconnection.
+//!
+//! The interface starts whether or not the firmware is up.
+//! [`FirmwareConnection::run`] connects in the background and reconnects
+//! with exponential [`Backoff`] whenever the link drops, publishing each
+//! change as a [`ConnectionState`] that the browser reads from `/connection`.
+//!
+//! Once the handshake shows both sides support heartbeats, the client
+//! pings the firmware whenever the link goes quiet, so a connection that
+//! half-died (Wi-Fi gone, firmware hung) is dropped and reconnected within
+//! a few intervals instead of after a TCP timeout. The interface does not
+//! announce `Capability::Heartbeat` until its WebSocket client polls the
+//! monitor. Every lost link is published as a [`DisconnectedEvent`], on
+//! [`FirmwareConnection::disconnects`] and in the `Reconnecting` state.
+//! When both sides announce `Capability::MessagePack`, messages after the
+//! handshake go out as MessagePack binary frames.
+//!
+//! Commands issued while disconnected are kept in a [`CommandBuffer`] rather
+//! than failed, but never replayed on their own: a start or pause that
+//! reaches the printer minutes late can ruin a print. They wait until a user
+//! confirms or discards them. Requests that only read state fail right away,
+//! and so do emergency stops and cancels: replayed later they would stop a
+//! print the user has since seen continue, and the user must know at once
+//! that the printer was not stopped.
+
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, Context, Result};
+use serde::Serialize;
+use tokio::sync::{broadcast, watch, Mutex, RwLock};
+use tracing::{info, warn};
+
+use protocol::{
+    DisconnectedEvent, HeartbeatConfig, HeartbeatMonitor, Hello, MessageClient, ProtocolError, ProtocolMessage,
+    WebSocketClient, WireFormat,
+};
+
+/// Commands kept while disconnected before further ones are refused.
+pub const DEFAULT_BUFFER_CAPACITY: usize = 32;
+
+/// How often the receive loop polls the firmware for messages. The client
+/// lock is released between polls so handlers can send.
+const RECV_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// State of the link to the firmware, as shown to the browser.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum ConnectionState {
+    /// First connection attempt in progress
+    Connecting,
+    Connected,
+    /// Waiting `retry_in_ms` before attempt number `attempt`
+    Reconnecting {
+        attempt: u32,
+        retry_in_ms: u64,
+        last_error: String,
+        /// How the last connection ended; None if it was never up
+        #[serde(skip_serializing_if = "Option::is_none")]
+        disconnect: Option<DisconnectedEvent>,
+    },
+}
+
+/// Exponential reconnection delay: `initial`, doubling per failed attempt
+/// up to `max`.
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    initial: Duration,
+    max: Duration,
+    attempt: u32,
+}
+
+impl Backoff {
+    pub fn new(initial: Duration, max: Duration) -> Self {
+        Self { initial, max, attempt: 0 }
+    }
+
+    /// Delay before the next attempt.
+    pub fn next_delay(&mut self) -> Duration {
+        let factor = 1u32 << self.attempt.min(16);
+        self.attempt = self.attempt.saturating_add(1);
+        self.initial.saturating_mul(factor).min(self.max)
+    }
+
+    /// Failed attempts since the last successful connection.
+    pub fn attempt(&self) -> u32 {
+        self.attempt
+    }
+
+    pub fn reset(&mut self) {
+        self.attempt = 0;
+    }
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self::new(Duration::from_millis(500), Duration::from_secs(30))
+    }
+}
+
+/// A command issued while the firmware was unreachable.
+#[derive(Debug, Clone, Serialize)]
+pub struct PendingCommand {
+    pub id: u64,
+    pub message_type: String,
+    pub message: ProtocolMessage,
+    /// Unix time the command was issued (ms)
+    pub queued_at_ms: u64,
+}
+
+/// Commands waiting for confirmation, oldest first.
+#[derive(Debug, Clone)]
+pub struct CommandBuffer {
+    capacity: usize,
+    next_id: u64,
+    commands: VecDeque<PendingCommand>,
+}
+
+impl CommandBuffer {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            next_id: 1,
+            commands: VecDeque::new(),
+        }
+    }
+
+    /// Queues a command and returns its id. Refuses rather than dropping
+    /// older commands once full.
+    pub fn push(&mut self, message: ProtocolMessage) -> Result<u64> {
+        if self.commands.len() >= self.capacity {
+            bail!(
+                "{} commands are already waiting for the firmware; replay or discard them first",
+                self.commands.len()
+            );
+        }
+        let id = self.next_id;
+        self.next_id += 1;
+        let queued_at_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0);
+        self.commands.push_back(PendingCommand {
+            id,
+            message_type: message.message_type().to_string(),
+            message,
+            queued_at_ms,
+        });
+        Ok(id)
+    }
+
+    pub fn commands(&self) -> impl Iterator<Item = &PendingCommand> {
+        self.commands.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.commands.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+
+    /// Removes the selected commands (all if `ids` is None), in queue order.
+    pub fn take(&mut self, ids: Option<&[u64]>) -> Vec<PendingCommand> {
+        let (taken, kept): (VecDeque<_>, VecDeque<_>) = self.commands.drain(..).partition(|cmd| match ids {
+            Some(ids) => ids.contains(&cmd.id),
+            None => true,
+        });
+        self.commands = kept;
+        taken.into()
+    }
+
+    /// Puts commands back after a failed replay, keeping queue order.
+    pub fn restore(&mut self, commands: impl IntoIterator<Item = PendingCommand>) {
+        for cmd in commands {
+            let index = self.commands.partition_point(|c| c.id < cmd.id);
+            self.commands.insert(index, cmd);
+        }
+    }
+}
+
+/// Outcome of [`FirmwareConnection::send`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Delivery {
+    Sent,
+    /// Firmware unreachable; the command waits under this id
+    Queued(u64),
+}
+
+/// Link to the firmware, shared by all handlers.
+pub struct FirmwareConnection {
+    url: String,
+    /// None while disconnected
+    client: RwLock<Option<WebSocketClient>>,
+    state: watch::Sender<ConnectionState>,
+    pending: Mutex<CommandBuffer>,
+    heartbeat: HeartbeatConfig,
+    disconnects: broadcast::Sender<DisconnectedEvent>,
+}
+
+impl FirmwareConnection {
+    /// Creates a disconnected link; [`run`](Self::run) connects it.
+    pub fn new(url: impl Into<String>, buffer_capacity: usize) -> Self {
+        let (state, _) = watch::channel(ConnectionState::Connecting);
+        Self {
+            url: url.into(),
+            client: RwLock::new(None),
+            state,
+            pending: Mutex::new(CommandBuffer::new(buffer_capacity)),
+            heartbeat: HeartbeatConfig::default(),
+            disconnects: broadcast::channel(16).0,
+        }
+    }
+
+    /// Uses `config` for heartbeats instead of the default.
+    pub fn with_heartbeat(mut self, config: HeartbeatConfig) -> Self {
+        self.heartbeat = config;
+        self
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    pub fn state(&self) -> ConnectionState {
+        self.state.borrow().clone()
+    }
+
+    pub fn is_connected(&self) -> bool {
+        *self.state.borrow() == ConnectionState::Connected
+    }
+
+    /// Receiver notified on every state change.
+    pub fn subscribe(&self) -> watch::Receiver<ConnectionState> {
+        self.state.subscribe()
+    }
+
+    /// Receiver notified each time an established link is lost.
+    pub fn disconnects(&self) -> broadcast::Receiver<DisconnectedEvent> {
+        self.disconnects.subscribe()
+    }
+
+    /// Applies what both `Hello`s agree on to the current link: heartbeats
+    /// and the wire encoding. Called after each handshake.
+    pub async fn apply_handshake(&self, local: &Hello, firmware: &Hello) {
+        let mut client = self.client.write().await;
+        let Some(client) = client.as_mut() else {
+            return;
+        };
+        client.set_wire_format(WireFormat::negotiate(local, firmware));
+        if let Some(monitor) = HeartbeatMonitor::negotiate(self.heartbeat, local, firmware, Instant::now()) {
+            client.enable_heartbeat(monitor);
+        }
+    }
+
+    /// Connects, forwards firmware messages to `message_tx` and reconnects
+    /// after failures, forever.
+    pub async fn run(self: Arc<Self>, message_tx: broadcast::Sender<ProtocolMessage>, mut backoff: Backoff) {
+        loop {
+            let (error, disconnect) = match WebSocketClient::connect(&self.url).await {
+                Ok(client) => {
+                    info!("Connected to firmware at {}", self.url);
+                    backoff.reset();
+                    *self.client.write().await = Some(client);
+                    self.state.send_replace(ConnectionState::Connected);
+                    let error = self.forward(&message_tx).await;
+                    *self.client.write().await = None;
+                    let disconnect = disconnect_event(&error);
+                    // No subscribers is not an error
+                    self.disconnects.send(disconnect.clone()).ok();
+                    (error, Some(disconnect))
+                }
+                Err(e) => (e, None),
+            };
+
+            let delay = backoff.next_delay();
+            warn!(
+                "Firmware connection lost ({}); retrying in {:.1}s",
+                error,
+                delay.as_secs_f32()
+            );
+            self.state.send_replace(ConnectionState::Reconnecting {
+                attempt: backoff.attempt(),
+                retry_in_ms: delay.as_millis() as u64,
+                last_error: error.to_string(),
+                disconnect,
+            });
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    /// Forwards received messages until the link fails.
+    async fn forward(&self, message_tx: &broadcast::Sender<ProtocolMessage>) -> ProtocolError {
+        loop {
+            let received = match self.client.write().await.as_mut() {
+                Some(client) => client.try_recv().await,
+                // A failed send dropped the client
+                None => return ProtocolError::ConnectionError("Send to firmware failed".to_string()),
+            };
+            match received {
+                Ok(Some(msg)) => {
+                    // No subscribers is not an error
+                    message_tx.send(msg).ok();
+                }
+                Ok(None) => tokio::time::sleep(RECV_POLL_INTERVAL).await,
+                Err(e) => return e,
+            }
+        }
+    }
+
+    /// Sends a message, or queues it for confirmation if it is a command
+    /// and the firmware is unreachable. Safety commands are never queued.
+    pub async fn send(&self, msg: ProtocolMessage) -> Result<Delivery> {
+        let queueable = msg.is_command() && !is_safety_command(&msg);
+        match self.send_now(msg.clone()).await {
+            Ok(()) => Ok(Delivery::Sent),
+            Err(_) if queueable => Ok(Delivery::Queued(self.pending.lock().await.push(msg)?)),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Sends a message if connected. A failed send drops the client so the
+    /// run loop reconnects.
+    async fn send_now(&self, msg: ProtocolMessage) -> Result<()> {
+        let mut client = self.client.write().await;
+        let Some(connected) = client.as_mut() else {
+            bail!("Firmware at {} is not connected", self.url);
+        };
+        if let Err(e) = connected.send(msg).await {
+            *client = None;
+            return Err(e).context("Firmware connection failed");
+        }
+        Ok(())
+    }
+
+    /// Commands waiting for confirmation.
+    pub async fn pending(&self) -> Vec<PendingCommand> {
+        self.pending.lock().await.commands().cloned().collect()
+    }
+
+    /// Sends the selected waiting commands (all if `ids` is None) in the
+    /// order they were issued. Returns the ids sent; on failure the unsent
+    /// commands stay queued.
+    pub async fn replay(&self, ids: Option<&[u64]>) -> Result<Vec<u64>> {
+        if !self.is_connected() {
+            bail!("Firmware at {} is not connected", self.url);
+        }
+        let mut commands = self.pending.lock().await.take(ids).into_iter();
+        let mut sent = Vec::new();
+        while let Some(cmd) = commands.next() {
+            if let Err(e) = self.send_now(cmd.message.clone()).await {
+                let id = cmd.id;
+                self.pending.lock().await.restore(std::iter::once(cmd).chain(commands));
+                return Err(e).with_context(|| format!("Replay stopped at command {}", id));
+            }
+            sent.push(cmd.id);
+        }
+        Ok(sent)
+    }
+
+    /// Drops the selected waiting commands (all if `ids` is None). Returns
+    /// how many were dropped.
+    pub async fn discard(&self, ids: Option<&[u64]>) -> usize {
+        self.pending.lock().await.take(ids).len()
+    }
+}
+
+/// How a link that failed with `error` ended.
+/// Commands that must reach the printer now or fail.
+fn is_safety_command(msg: &ProtocolMessage) -> bool {
+    matches!(msg, ProtocolMessage::EmergencyStop | ProtocolMessage::CancelPrint)
+}
+
+fn disconnect_event(error: &ProtocolError) -> DisconnectedEvent {
+    match error {
+        ProtocolError::Disconnected(event) => event.clone(),
+        error => DisconnectedEvent::failed(error.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_and_command_buffer() {
+        let mut backoff = Backoff::new(Duration::from_millis(100), Duration::from_millis(500));
+        let delays: Vec<u128> = (0..5).map(|_| backoff.next_delay().as_millis()).collect();
+        assert_eq!(delays, vec![100, 200, 400, 500, 500]);
+        backoff.reset();
+        assert_eq!(backoff.next_delay(), Duration::from_millis(100));
+
+        let mut buffer = CommandBuffer::new(3);
+        for _ in 0..3 {
+            buffer.push(ProtocolMessage::ResumePrint).unwrap();
+        }
+        assert!(buffer.push(ProtocolMessage::CancelPrint).is_err());
+
+        let taken = buffer.take(Some(&[1, 3]));
+        assert_eq!(taken.iter().map(|c| c.id).collect::<Vec<_>>(), vec![1, 3]);
+        assert_eq!(buffer.len(), 1);
+        buffer.restore(taken);
+        assert_eq!(buffer.commands().map(|c| c.id).collect::<Vec<_>>(), vec![1, 2, 3]);
+        assert_eq!(buffer.take(None).len(), 3);
+        assert!(buffer.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_safety_commands_not_queued() {
+        let connection = FirmwareConnection::new("ws://127.0.0.1:9", DEFAULT_BUFFER_CAPACITY);
+        for msg in [ProtocolMessage::EmergencyStop, ProtocolMessage::CancelPrint] {
+            assert!(connection.send(msg).await.is_err());
+        }
+        assert!(connection.pending().await.is_empty());
+        assert_eq!(connection.send(ProtocolMessage::ResumePrint).await.unwrap(), Delivery::Queued(1));
+    }
+}
@@ -0,0 +1,78 @@
+//! Finding printers on the LAN.
+//!
+//! Firmware announces itself over mDNS (see [`protocol::discovery`]).
+//! [`discover`] browses for those announcements for a short window and
+//! returns every printer that resolved, with the addresses it answered
+//! from. Browsing needs multicast on the local network; routed or
+//! containerized setups may see nothing and should add printers by URL.
+
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use mdns_sd::{ServiceDaemon, ServiceEvent};
+use serde::Serialize;
+use tokio::time::Instant;
+use tracing::debug;
+
+use protocol::discovery::SERVICE_TYPE;
+use protocol::ServiceAnnouncement;
+
+/// How long [`discover`] listens by default.
+pub const DEFAULT_DISCOVERY_WINDOW: Duration = Duration::from_secs(3);
+
+/// A printer that answered on the LAN.
+#[derive(Debug, Clone, Serialize)]
+pub struct DiscoveredPrinter {
+    #[serde(flatten)]
+    pub announcement: ServiceAnnouncement,
+    /// Addresses the service resolved to, IPv4 first
+    pub addresses: Vec<IpAddr>,
+}
+
+impl DiscoveredPrinter {
+    /// WebSocket URL by address, for networks where `.local` names do not
+    /// resolve; by host name if no address is known.
+    pub fn websocket_url(&self) -> String {
+        match self.addresses.first() {
+            Some(IpAddr::V4(ip)) => format!("ws://{}:{}", ip, self.announcement.websocket_port),
+            Some(IpAddr::V6(ip)) => format!("ws://[{}]:{}", ip, self.announcement.websocket_port),
+            None => self.announcement.websocket_url(),
+        }
+    }
+}
+
+/// Browses for printers for `window`. Printers are ordered by instance
+/// name; one announcing several times is listed once.
+pub async fn discover(window: Duration) -> Result<Vec<DiscoveredPrinter>> {
+    let daemon = ServiceDaemon::new().context("Failed to start mDNS daemon")?;
+    let events = daemon.browse(SERVICE_TYPE).context("Failed to browse for printers")?;
+
+    let mut found: BTreeMap<String, DiscoveredPrinter> = BTreeMap::new();
+    let deadline = Instant::now() + window;
+    while let Ok(Ok(event)) = tokio::time::timeout_at(deadline, events.recv_async()).await {
+        let ServiceEvent::ServiceResolved(info) = event else {
+            continue;
+        };
+        let instance = info
+            .get_fullname()
+            .strip_suffix(&format!(".{}", SERVICE_TYPE))
+            .unwrap_or(info.get_fullname());
+        let txt = info.get_properties().iter().map(|p| (p.key(), p.val_str()));
+        let Some(announcement) = ServiceAnnouncement::from_txt(instance, info.get_hostname(), info.get_port(), txt) else {
+            debug!("Ignoring {}: incomplete announcement", info.get_fullname());
+            continue;
+        };
+        let mut addresses: Vec<IpAddr> = info.get_addresses().iter().copied().collect();
+        addresses.sort_by_key(|ip| (ip.is_ipv6(), *ip));
+        found.insert(
+            announcement.instance.clone(),
+            DiscoveredPrinter { announcement, addresses },
+        );
+    }
+
+    daemon.stop_browse(SERVICE_TYPE).ok();
+    daemon.shutdown().ok();
+    Ok(found.into_values().collect())
+}
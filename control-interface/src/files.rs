@@ -0,0 +1,117 @@
+//! Print files on the firmware.
+//!
+//! The firmware prints from its own print directory, which this host
+//! cannot see. [`FirmwareFiles`] pushes uploads with the firmware's
+//! `PUT /files/:name` and starts them by name with `POST /print/start`, so
+//! the firmware resolves the name inside its directory. The interface keeps
+//! its own copy in the upload directory for previews and file listings.
+
+use std::time::Duration;
+
+use axum::body::Bytes;
+use axum::http::StatusCode;
+use serde::Serialize;
+
+/// Port of the firmware's REST API when only its WebSocket URL is known.
+pub const DEFAULT_FIRMWARE_API_PORT: u16 = 8081;
+
+/// Upload and start requests give up after this long.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Body of the firmware's `POST /print/start`.
+#[derive(Debug, Serialize)]
+struct StartPrintBody<'a> {
+    file: &'a str,
+}
+
+/// One printer's file endpoints.
+#[derive(Debug, Clone)]
+pub struct FirmwareFiles {
+    client: reqwest::Client,
+    api_url: String,
+}
+
+impl FirmwareFiles {
+    pub fn new(api_url: impl Into<String>) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .unwrap_or_default();
+        Self {
+            client,
+            api_url: api_url.into().trim_end_matches('/').to_string(),
+        }
+    }
+
+    /// For the firmware at `websocket_url`, with its REST API on the same
+    /// host at [`DEFAULT_FIRMWARE_API_PORT`].
+    pub fn for_websocket(websocket_url: &str) -> Self {
+        Self::new(api_url_for(websocket_url))
+    }
+
+    pub fn api_url(&self) -> &str {
+        &self.api_url
+    }
+
+    /// Stores `data` as `name` in the firmware's print directory, replacing
+    /// a file of that name.
+    pub async fn upload(&self, name: &str, data: Bytes) -> Result<(), (StatusCode, String)> {
+        let url = format!("{}/files/{}", self.api_url, name);
+        let response = self.client.put(&url).body(data).send().await;
+        check(response, &format!("Upload of {}", name)).await
+    }
+
+    /// Starts printing the stored file `name`.
+    pub async fn start_print(&self, name: &str) -> Result<(), (StatusCode, String)> {
+        let url = format!("{}/print/start", self.api_url);
+        let response = self.client.post(&url).json(&StartPrintBody { file: name }).send().await;
+        check(response, &format!("Starting {}", name)).await
+    }
+}
+
+/// REST API URL of the firmware serving WebSockets at `websocket_url`.
+pub fn api_url_for(websocket_url: &str) -> String {
+    let (scheme, rest) = match websocket_url.split_once("://") {
+        Some(("wss", rest)) => ("https", rest),
+        Some((_, rest)) => ("http", rest),
+        None => ("http", websocket_url),
+    };
+    let authority = rest.split('/').next().unwrap_or(rest);
+    // Bracketed IPv6 addresses contain colons of their own
+    let host = match authority.rfind(':') {
+        Some(colon) if !authority[colon..].contains(']') => &authority[..colon],
+        _ => authority,
+    };
+    format!("{}://{}:{}", scheme, host, DEFAULT_FIRMWARE_API_PORT)
+}
+
+/// Maps the firmware's answer onto this interface's error type: its
+/// refusals (missing file, printer busy) keep their status, anything else
+/// is a bad gateway.
+async fn check(response: reqwest::Result<reqwest::Response>, what: &str) -> Result<(), (StatusCode, String)> {
+    let response = response.map_err(|e| (StatusCode::BAD_GATEWAY, format!("{} failed: {}", what, e)))?;
+    let status = response.status();
+    if status.is_success() {
+        return Ok(());
+    }
+    let message = response.text().await.unwrap_or_default();
+    let status = match StatusCode::from_u16(status.as_u16()) {
+        Ok(status) if status.is_client_error() => status,
+        _ => StatusCode::BAD_GATEWAY,
+    };
+    Err((status, format!("{} refused by the firmware: {}", what, message)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_api_url_from_websocket_url() {
+        assert_eq!(api_url_for("ws://localhost:8080"), "http://localhost:8081");
+        assert_eq!(api_url_for("wss://bay3.local:8080/ws"), "https://bay3.local:8081");
+        assert_eq!(api_url_for("ws://10.0.0.12"), "http://10.0.0.12:8081");
+        assert_eq!(api_url_for("ws://[fe80::1]:8080"), "http://[fe80::1]:8081");
+        assert_eq!(FirmwareFiles::new("http://printer:9000/").api_url(), "http://printer:9000");
+    }
+}
@@ -0,0 +1,287 @@
+//! Several printers managed from one interface.
+//!
+//! The firmware given on the command line is the [`DEFAULT_PRINTER`]; more
+//! are added as named [`PrinterTarget`]s (`--printer NAME=URL`). Each gets
+//! its own [`FirmwareConnection`], reconnected in the background like the
+//! default one, and its own message broadcast.
+//!
+//! Print files are pushed to each printer's REST API, which a target may
+//! name; otherwise it is taken to run on the WebSocket URL's host at the
+//! default port.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+use tracing::warn;
+
+use protocol::{GetStatusRequest, PrintStatus, ProtocolMessage};
+
+use crate::api::request_via;
+use crate::connection::{self, Backoff, ConnectionState, FirmwareConnection};
+use crate::files::FirmwareFiles;
+
+/// Name of the printer given with `--firmware-url`.
+pub const DEFAULT_PRINTER: &str = "default";
+
+/// A printer to manage.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PrinterTarget {
+    pub name: String,
+    /// Firmware WebSocket URL
+    pub url: String,
+    /// Firmware REST API URL; derived from `url` if absent
+    #[serde(default)]
+    pub api_url: Option<String>,
+}
+
+impl PrinterTarget {
+    /// File endpoints of this printer's firmware.
+    pub fn files(&self) -> FirmwareFiles {
+        match &self.api_url {
+            Some(api_url) => FirmwareFiles::new(api_url.clone()),
+            None => FirmwareFiles::for_websocket(&self.url),
+        }
+    }
+}
+
+impl FromStr for PrinterTarget {
+    type Err = String;
+
+    /// Parses `NAME=URL` or `NAME=URL,API_URL`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, rest) = s
+            .split_once('=')
+            .ok_or_else(|| format!("Expected NAME=URL[,API_URL], got '{}'", s))?;
+        let (url, api_url) = match rest.split_once(',') {
+            Some((url, api_url)) => (url, Some(api_url.to_string())),
+            None => (rest, None),
+        };
+        let name = name.trim();
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+            return Err(format!("Invalid printer name '{}': use letters, digits, '-' and '_'", name));
+        }
+        if url.is_empty() {
+            return Err(format!("Missing firmware URL for printer '{}'", name));
+        }
+        Ok(Self {
+            name: name.to_string(),
+            url: url.to_string(),
+            api_url,
+        })
+    }
+}
+
+/// One printer of the fleet and its link.
+#[derive(Clone)]
+pub struct FleetPrinter {
+    pub target: PrinterTarget,
+    pub firmware: Arc<FirmwareConnection>,
+    /// Messages received from this printer
+    pub message_tx: broadcast::Sender<ProtocolMessage>,
+    pub files: FirmwareFiles,
+}
+
+impl FleetPrinter {
+    /// Wraps an existing link, e.g. the default printer's.
+    pub fn from_connection(
+        target: PrinterTarget,
+        firmware: Arc<FirmwareConnection>,
+        message_tx: broadcast::Sender<ProtocolMessage>,
+    ) -> Self {
+        let files = target.files();
+        Self { target, firmware, message_tx, files }
+    }
+
+    /// Starts connecting to the target in the background.
+    pub fn connect(target: PrinterTarget) -> Self {
+        let firmware = Arc::new(FirmwareConnection::new(target.url.clone(), connection::DEFAULT_BUFFER_CAPACITY));
+        let (message_tx, _) = broadcast::channel(100);
+        tokio::spawn(firmware.clone().run(message_tx.clone(), Backoff::default()));
+        let files = target.files();
+        Self { target, firmware, message_tx, files }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.target.name
+    }
+
+    /// Sends a request to this printer and waits for the reply accepted by
+    /// `is_reply`; see [`crate::api::request_firmware`].
+    pub async fn request<F>(&self, request: ProtocolMessage, is_reply: F) -> Result<ProtocolMessage>
+    where
+        F: Fn(&ProtocolMessage) -> bool,
+    {
+        request_via(&self.firmware, &self.message_tx, request, is_reply).await
+    }
+
+    /// Connection state plus, if connected, a fresh status from the firmware.
+    pub async fn summary(&self) -> PrinterSummary {
+        let mut summary = PrinterSummary {
+            name: self.target.name.clone(),
+            url: self.target.url.clone(),
+            connection: self.firmware.state(),
+            state: None,
+            print_status: None,
+            error: None,
+        };
+        if !self.firmware.is_connected() {
+            return summary;
+        }
+        let request = ProtocolMessage::GetStatus(GetStatusRequest { status_type: None });
+        match self.request(request, |msg| matches!(msg, ProtocolMessage::StatusResponse(_))).await {
+            Ok(ProtocolMessage::StatusResponse(status)) => {
+                summary.state = Some(status.state);
+                summary.print_status = status.print_status;
+            }
+            Ok(other) => summary.error = Some(format!("Unexpected reply: {}", other.message_type())),
+            Err(e) => summary.error = Some(format!("{:#}", e)),
+        }
+        summary
+    }
+}
+
+impl fmt::Debug for FleetPrinter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FleetPrinter").field("target", &self.target).finish()
+    }
+}
+
+/// One printer as shown on the fleet dashboard.
+#[derive(Debug, Clone, Serialize)]
+pub struct PrinterSummary {
+    pub name: String,
+    pub url: String,
+    pub connection: ConnectionState,
+    /// Firmware state; absent while disconnected or if the status request failed
+    pub state: Option<String>,
+    pub print_status: Option<PrintStatus>,
+    /// Why the status request failed
+    pub error: Option<String>,
+}
+
+impl PrinterSummary {
+    /// Connected and ready to accept a job.
+    pub fn is_idle(&self) -> bool {
+        self.connection == ConnectionState::Connected && self.state.as_deref() == Some("Idle")
+    }
+}
+
+/// All managed printers by name.
+#[derive(Debug, Clone, Default)]
+pub struct Fleet {
+    printers: BTreeMap<String, FleetPrinter>,
+}
+
+impl Fleet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a printer; names must be unique.
+    pub fn insert(&mut self, printer: FleetPrinter) -> Result<()> {
+        if self.printers.contains_key(printer.name()) {
+            bail!("Printer '{}' is configured twice", printer.name());
+        }
+        self.printers.insert(printer.name().to_string(), printer);
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&FleetPrinter> {
+        self.printers.get(name)
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut FleetPrinter> {
+        self.printers.get_mut(name)
+    }
+
+    /// Printers ordered by name.
+    pub fn printers(&self) -> impl Iterator<Item = &FleetPrinter> {
+        self.printers.values()
+    }
+
+    pub fn len(&self) -> usize {
+        self.printers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.printers.is_empty()
+    }
+
+    /// Summaries of every printer, queried concurrently, ordered by name.
+    pub async fn summaries(&self) -> Vec<PrinterSummary> {
+        let tasks: Vec<_> = self
+            .printers()
+            .cloned()
+            .map(|printer| (printer.target.clone(), tokio::spawn(async move { printer.summary().await })))
+            .collect();
+
+        let mut summaries = Vec::with_capacity(tasks.len());
+        for (target, task) in tasks {
+            match task.await {
+                Ok(summary) => summaries.push(summary),
+                Err(e) => {
+                    warn!("Status of printer {} failed: {}", target.name, e);
+                    summaries.push(PrinterSummary {
+                        name: target.name,
+                        url: target.url,
+                        connection: ConnectionState::Connecting,
+                        state: None,
+                        print_status: None,
+                        error: Some(e.to_string()),
+                    });
+                }
+            }
+        }
+        summaries
+    }
+
+    /// Printers that are connected and idle right now.
+    pub async fn idle(&self) -> Vec<&FleetPrinter> {
+        let idle: Vec<String> = self
+            .summaries()
+            .await
+            .into_iter()
+            .filter(PrinterSummary::is_idle)
+            .map(|summary| summary.name)
+            .collect();
+        idle.iter().filter_map(|name| self.get(name)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_printer_target_parsing() {
+        let target: PrinterTarget = "bay-2=ws://10.0.0.12:8080".parse().unwrap();
+        assert_eq!(target.name, "bay-2");
+        assert_eq!(target.url, "ws://10.0.0.12:8080");
+        assert_eq!(target.api_url, None);
+        assert_eq!(target.files().api_url(), "http://10.0.0.12:8081");
+
+        let target: PrinterTarget = "bay3=ws://bay3.local:8080,http://bay3.local:9000".parse().unwrap();
+        assert_eq!(target.files().api_url(), "http://bay3.local:9000");
+
+        assert!("ws://no-name:8080".parse::<PrinterTarget>().is_err());
+        assert!("bad name=ws://host".parse::<PrinterTarget>().is_err());
+        assert!("empty=".parse::<PrinterTarget>().is_err());
+
+        let summary = |connection, state: Option<&str>| PrinterSummary {
+            name: "a".to_string(),
+            url: String::new(),
+            connection,
+            state: state.map(String::from),
+            print_status: None,
+            error: None,
+        };
+        assert!(summary(ConnectionState::Connected, Some("Idle")).is_idle());
+        assert!(!summary(ConnectionState::Connected, Some("Printing")).is_idle());
+        assert!(!summary(ConnectionState::Connecting, Some("Idle")).is_idle());
+    }
+}
@@ -0,0 +1,323 @@
+//! Durable journal of firmware messages.
+//!
+//! Every message the firmware sends is appended, with the time it arrived,
+//! to JSON-lines segment files named after their first entry's timestamp.
+//! A segment is closed once it reaches an eighth of the size budget or
+//! spans an eighth of the retention period, and whole segments are
+//! deleted, oldest first, when the journal exceeds `max_bytes` or a
+//! segment's newest entry is older than the retention period.
+//!
+//! [`MessageJournal::query`] reads back a time range for `GET /journal`,
+//! and `/ws/journal` replays one into a WebSocket at a chosen speed (see
+//! [`replay_delay`]) for debugging the UI or reviewing a finished print.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{debug, warn};
+
+use protocol::ProtocolMessage;
+
+/// Default directory for journal segments.
+pub const DEFAULT_JOURNAL_DIR: &str = "./journal";
+
+/// Longest pause a replay makes between two messages, however slow the
+/// speed, so idle stretches of a print do not stall it.
+pub const MAX_REPLAY_GAP: Duration = Duration::from_secs(5);
+
+const SEGMENT_PREFIX: &str = "journal-";
+const SEGMENT_EXTENSION: &str = "jsonl";
+
+/// Where the journal lives and how much it keeps.
+#[derive(Debug, Clone)]
+pub struct JournalConfig {
+    pub dir: PathBuf,
+    /// Total size of all segments (bytes)
+    pub max_bytes: u64,
+    /// Age after which segments are deleted
+    pub retention: Duration,
+}
+
+impl Default for JournalConfig {
+    fn default() -> Self {
+        Self {
+            dir: PathBuf::from(DEFAULT_JOURNAL_DIR),
+            max_bytes: 256 * 1024 * 1024,
+            retention: Duration::from_secs(7 * 24 * 3600),
+        }
+    }
+}
+
+/// A firmware message and when the control interface received it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JournalEntry {
+    /// Milliseconds since the Unix epoch
+    pub timestamp_ms: u64,
+    pub message: ProtocolMessage,
+}
+
+/// One segment file.
+#[derive(Debug, Clone)]
+struct Segment {
+    path: PathBuf,
+    /// First entry's timestamp
+    start_ms: u64,
+    /// Last entry's timestamp
+    end_ms: u64,
+    bytes: u64,
+}
+
+struct JournalState {
+    segments: Vec<Segment>,
+    writer: Option<BufWriter<File>>,
+}
+
+/// Append-only message journal.
+pub struct MessageJournal {
+    config: JournalConfig,
+    state: Mutex<JournalState>,
+}
+
+impl MessageJournal {
+    /// Opens the journal, creating its directory, and indexes existing
+    /// segments.
+    pub fn open(config: JournalConfig) -> Result<Self> {
+        fs::create_dir_all(&config.dir)
+            .with_context(|| format!("Failed to create journal directory {}", config.dir.display()))?;
+
+        let mut segments = Vec::new();
+        for entry in fs::read_dir(&config.dir)? {
+            let path = entry?.path();
+            let Some(start_ms) = segment_start(&path) else { continue };
+            let bytes = fs::metadata(&path)?.len();
+            let end_ms = read_segment(&path)?.last().map_or(start_ms, |e| e.timestamp_ms);
+            segments.push(Segment { path, start_ms, end_ms, bytes });
+        }
+        segments.sort_by_key(|s| s.start_ms);
+        debug!("Journal at {} has {} segments", config.dir.display(), segments.len());
+
+        Ok(Self {
+            config,
+            state: Mutex::new(JournalState { segments, writer: None }),
+        })
+    }
+
+    pub fn config(&self) -> &JournalConfig {
+        &self.config
+    }
+
+    /// Appends an entry, rotating and pruning segments as needed.
+    pub fn append(&self, entry: &JournalEntry) -> Result<()> {
+        let mut line = serde_json::to_vec(entry)?;
+        line.push(b'\n');
+
+        let mut state = self.state.lock().unwrap();
+        let byte_limit = (self.config.max_bytes / 8).max(1);
+        let span_limit = self.config.retention.as_millis() as u64 / 8;
+        let full = !state.segments.last().is_some_and(|s| {
+            s.bytes < byte_limit && entry.timestamp_ms.saturating_sub(s.start_ms) <= span_limit
+        });
+        if full || state.writer.is_none() {
+            if let Some(mut writer) = state.writer.take() {
+                writer.flush()?;
+            }
+            if full {
+                let path = self
+                    .config
+                    .dir
+                    .join(format!("{}{}.{}", SEGMENT_PREFIX, entry.timestamp_ms, SEGMENT_EXTENSION));
+                state.segments.push(Segment {
+                    path,
+                    start_ms: entry.timestamp_ms,
+                    end_ms: entry.timestamp_ms,
+                    bytes: 0,
+                });
+            }
+            let path = &state.segments.last().expect("segment just ensured").path;
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .with_context(|| format!("Failed to open journal segment {}", path.display()))?;
+            state.writer = Some(BufWriter::new(file));
+        }
+
+        state.writer.as_mut().expect("writer just opened").write_all(&line)?;
+        let segment = state.segments.last_mut().expect("segment just ensured");
+        segment.bytes += line.len() as u64;
+        segment.end_ms = entry.timestamp_ms;
+        self.prune(&mut state, entry.timestamp_ms)
+    }
+
+    /// Entries received between `from_ms` and `to_ms` inclusive, oldest
+    /// first, at most `limit`.
+    pub fn query(&self, from_ms: u64, to_ms: u64, limit: Option<usize>) -> Result<Vec<JournalEntry>> {
+        let segments: Vec<Segment> = {
+            let mut state = self.state.lock().unwrap();
+            if let Some(writer) = state.writer.as_mut() {
+                writer.flush()?;
+            }
+            state
+                .segments
+                .iter()
+                .filter(|s| s.start_ms <= to_ms && s.end_ms >= from_ms)
+                .cloned()
+                .collect()
+        };
+
+        let limit = limit.unwrap_or(usize::MAX);
+        let mut entries = Vec::new();
+        for segment in segments {
+            for entry in read_segment(&segment.path)? {
+                if entry.timestamp_ms < from_ms || entry.timestamp_ms > to_ms {
+                    continue;
+                }
+                if entries.len() >= limit {
+                    return Ok(entries);
+                }
+                entries.push(entry);
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Time span covered and total size on disk.
+    pub fn extent(&self) -> (Option<(u64, u64)>, u64) {
+        let state = self.state.lock().unwrap();
+        let span = state
+            .segments
+            .first()
+            .zip(state.segments.last())
+            .map(|(first, last)| (first.start_ms, last.end_ms));
+        (span, state.segments.iter().map(|s| s.bytes).sum())
+    }
+
+    /// Journals messages from `rx` until the channel closes.
+    pub async fn record(self: Arc<Self>, mut rx: broadcast::Receiver<ProtocolMessage>) {
+        loop {
+            let message = match rx.recv().await {
+                Ok(message) => message,
+                Err(RecvError::Lagged(missed)) => {
+                    warn!("Journal fell behind; {} firmware messages not recorded", missed);
+                    continue;
+                }
+                Err(RecvError::Closed) => return,
+            };
+            let entry = JournalEntry {
+                timestamp_ms: now_ms(),
+                message,
+            };
+            if let Err(e) = self.append(&entry) {
+                warn!("Failed to journal {}: {:#}", entry.message.message_type(), e);
+            }
+        }
+    }
+
+    fn prune(&self, state: &mut JournalState, now_ms: u64) -> Result<()> {
+        let cutoff = now_ms.saturating_sub(self.config.retention.as_millis() as u64);
+        // The open segment is never removed
+        while state.segments.len() > 1 {
+            let total: u64 = state.segments.iter().map(|s| s.bytes).sum();
+            let oldest = &state.segments[0];
+            if total <= self.config.max_bytes && oldest.end_ms >= cutoff {
+                break;
+            }
+            fs::remove_file(&oldest.path)
+                .with_context(|| format!("Failed to remove journal segment {}", oldest.path.display()))?;
+            debug!("Removed journal segment {}", oldest.path.display());
+            state.segments.remove(0);
+        }
+        Ok(())
+    }
+}
+
+/// How long a replay at `speed` times real time waits between entries
+/// received at `previous_ms` and `next_ms`, capped at [`MAX_REPLAY_GAP`].
+pub fn replay_delay(previous_ms: u64, next_ms: u64, speed: f32) -> Duration {
+    if speed <= 0.0 || !speed.is_finite() {
+        return Duration::ZERO;
+    }
+    let gap = next_ms.saturating_sub(previous_ms) as f32 / 1000.0 / speed;
+    Duration::from_secs_f32(gap).min(MAX_REPLAY_GAP)
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+fn segment_start(path: &Path) -> Option<u64> {
+    if path.extension()? != SEGMENT_EXTENSION {
+        return None;
+    }
+    path.file_stem()?.to_str()?.strip_prefix(SEGMENT_PREFIX)?.parse().ok()
+}
+
+/// Reads a segment, skipping a torn final line left by a crash.
+fn read_segment(path: &Path) -> Result<Vec<JournalEntry>> {
+    let file = File::open(path).with_context(|| format!("Failed to open journal segment {}", path.display()))?;
+    let mut entries = Vec::new();
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        match serde_json::from_str(&line) {
+            Ok(entry) => entries.push(entry),
+            Err(e) => debug!("Skipping unreadable journal line in {}: {}", path.display(), e),
+        }
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_append_query_and_prune() {
+        let dir = std::env::temp_dir().join(format!("hg4d-journal-{}", std::process::id()));
+        fs::remove_dir_all(&dir).ok();
+        let config = JournalConfig {
+            dir: dir.clone(),
+            max_bytes: 4096,
+            retention: Duration::from_secs(3600),
+        };
+        let journal = MessageJournal::open(config.clone()).unwrap();
+        let entry = |t| JournalEntry {
+            timestamp_ms: t,
+            message: ProtocolMessage::GetMacros,
+        };
+        for t in (0..200).map(|i| 1_000_000 + i * 100) {
+            journal.append(&entry(t)).unwrap();
+        }
+
+        // Old segments were pruned to the size budget
+        let (span, bytes) = journal.extent();
+        let (start, end) = span.unwrap();
+        assert!(bytes <= 4096);
+        assert!(start > 1_000_000 && end == 1_019_900);
+
+        let entries = journal.query(1_019_000, 1_019_500, None).unwrap();
+        assert_eq!(entries.len(), 6);
+        assert_eq!(journal.query(1_019_000, u64::MAX, Some(3)).unwrap().len(), 3);
+
+        // A segment older than the retention period goes on the next append
+        journal.append(&entry(1_019_900 + 7_200_000)).unwrap();
+        assert!(journal.query(0, 1_019_900, None).unwrap().is_empty());
+
+        drop(journal);
+        let reopened = MessageJournal::open(config).unwrap();
+        assert_eq!(reopened.query(0, u64::MAX, None).unwrap().len(), 1);
+        fs::remove_dir_all(&dir).ok();
+
+        assert_eq!(replay_delay(0, 1000, 2.0), Duration::from_millis(500));
+        assert_eq!(replay_delay(0, 60_000, 1.0), MAX_REPLAY_GAP);
+    }
+}
@@ -3,6 +3,7 @@
 //! This library provides the web server and control logic for monitoring and
 //! controlling HyperGCode-4D printers through a browser interface.
 
+use std::path::PathBuf;
 use std::sync::Arc;
 use tokio::sync::{RwLock, broadcast};
 use axum::Router;
@@ -10,35 +11,210 @@ use tower_http::services::ServeDir;
 use tower_http::trace::TraceLayer;
 
 // Internal ecosystem imports
-use protocol::{ProtocolMessage, WebSocketClient};
+use protocol::{Capability, Hello, ProtocolMessage};
+use tracing::{info, warn};
 
 // Public module declarations
 pub mod api;
+pub mod auth;
+pub mod compat;
+pub mod connection;
+pub mod discovery;
+pub mod files;
+pub mod fleet;
+pub mod journal;
+pub mod preview;
+pub mod visualization;
 pub mod websocket;
 
 // Re-exports
 pub use api::create_api_router;
-pub use websocket::{handle_websocket_connection, ClientSession};
+pub use auth::{AuthService, Role, Session};
+pub use compat::create_compat_router;
+pub use connection::{Backoff, ConnectionState, Delivery, FirmwareConnection, PendingCommand};
+pub use discovery::DiscoveredPrinter;
+pub use files::FirmwareFiles;
+pub use fleet::{Fleet, FleetPrinter, PrinterSummary, PrinterTarget};
+pub use journal::{JournalConfig, JournalEntry, MessageJournal};
+pub use preview::{PreviewCache, PreviewInfo};
+pub use visualization::{Heatmap, ValveFrameCache};
+pub use websocket::{handle_websocket_connection, journal_ws_handler, valves_ws_handler, ClientSession};
+
+/// Default directory for uploaded print files.
+pub const DEFAULT_UPLOAD_DIR: &str = "./uploads";
 
 /// Application state shared across all handlers.
 #[derive(Clone)]
 pub struct AppState {
-    /// Client connection to firmware
-    pub firmware_client: Arc<RwLock<WebSocketClient>>,
+    /// Connection to firmware, reconnected in the background
+    pub firmware: Arc<FirmwareConnection>,
     /// Broadcast channel for firmware messages
     pub message_tx: broadcast::Sender<ProtocolMessage>,
+    /// Copies of uploaded print files, for previews and listings; the
+    /// firmware gets its own through [`FirmwareFiles`]
+    pub upload_dir: PathBuf,
+    /// Latest valve frame, for heatmap requests
+    pub valve_frames: ValveFrameCache,
+    /// Printer grid and decoded layers for file previews
+    pub previews: PreviewCache,
+    /// Users and login sessions
+    pub auth: Arc<AuthService>,
+    /// The firmware's handshake; None until it answers, or if it predates
+    /// the handshake
+    pub firmware_hello: Arc<RwLock<Option<Hello>>>,
+    /// Every managed printer, including the default one above
+    pub fleet: Arc<Fleet>,
+    /// Record of the default printer's messages; None if disabled
+    pub journal: Option<Arc<MessageJournal>>,
 }
 
 impl AppState {
-    /// Creates new application state with firmware connection.
-    pub async fn new(firmware_url: &str) -> anyhow::Result<Self> {
-        let firmware_client = WebSocketClient::connect(firmware_url).await?;
+    /// Creates application state and starts connecting to the firmware in
+    /// the background; an unreachable firmware is retried, not an error.
+    pub fn new(firmware_url: &str) -> Self {
+        let firmware = Arc::new(FirmwareConnection::new(firmware_url, connection::DEFAULT_BUFFER_CAPACITY));
         let (message_tx, _) = broadcast::channel(100);
 
-        Ok(Self {
-            firmware_client: Arc::new(RwLock::new(firmware_client)),
+        let valve_frames = ValveFrameCache::new();
+        tokio::spawn(valve_frames.clone().track(message_tx.subscribe()));
+        tokio::spawn(firmware.clone().run(message_tx.clone(), Backoff::default()));
+
+        let mut fleet = Fleet::new();
+        let default = PrinterTarget {
+            name: fleet::DEFAULT_PRINTER.to_string(),
+            url: firmware_url.to_string(),
+            api_url: None,
+        };
+        fleet
+            .insert(FleetPrinter::from_connection(default, firmware.clone(), message_tx.clone()))
+            .expect("fleet starts empty");
+
+        let state = Self {
+            firmware,
             message_tx,
+            upload_dir: PathBuf::from(DEFAULT_UPLOAD_DIR),
+            valve_frames,
+            previews: PreviewCache::new(),
+            auth: Arc::new(AuthService::in_memory()),
+            firmware_hello: Arc::new(RwLock::new(None)),
+            fleet: Arc::new(fleet),
+            journal: None,
+        };
+        tokio::spawn(state.clone().handshake_on_connect());
+        state
+    }
+
+    /// This build's handshake.
+    pub fn hello() -> Hello {
+        Hello::new(
+            "control-interface",
+            env!("CARGO_PKG_VERSION"),
+            vec![
+                Capability::CancelObject,
+                Capability::Subscriptions,
+                Capability::PrintHistory,
+                Capability::MessagePack,
+            ],
+        )
+    }
+
+    /// Exchanges `Hello` with the firmware and records its capabilities.
+    /// On failure the firmware is treated as having none.
+    pub async fn handshake(&self) -> anyhow::Result<Hello> {
+        let reply = api::request_firmware(self, ProtocolMessage::Hello(Self::hello()), |msg| {
+            matches!(msg, ProtocolMessage::Hello(_))
         })
+        .await?;
+        let ProtocolMessage::Hello(firmware) = reply else {
+            anyhow::bail!("Unexpected handshake reply: {}", reply.message_type());
+        };
+        firmware.check_compatible()?;
+        self.firmware.apply_handshake(&Self::hello(), &firmware).await;
+        *self.firmware_hello.write().await = Some(firmware.clone());
+        Ok(firmware)
+    }
+
+    /// Repeats the handshake after every (re)connection, since the firmware
+    /// may have been updated meanwhile, and forgets it while disconnected.
+    async fn handshake_on_connect(self) {
+        let mut states = self.firmware.subscribe();
+        loop {
+            let connected = *states.borrow_and_update() == ConnectionState::Connected;
+            if connected {
+                match self.handshake().await {
+                    Ok(firmware) => info!(
+                        "Firmware {} (protocol {}), capabilities {:?}",
+                        firmware.software_version, firmware.protocol_version, firmware.capabilities
+                    ),
+                    Err(e) => warn!("Firmware handshake failed, optional features disabled: {:#}", e),
+                }
+            } else {
+                *self.firmware_hello.write().await = None;
+            }
+            if states.changed().await.is_err() {
+                return;
+            }
+        }
+    }
+
+    /// Whether the firmware announced a capability in its handshake.
+    pub async fn firmware_supports(&self, capability: Capability) -> bool {
+        self.firmware_hello
+            .read()
+            .await
+            .as_ref()
+            .is_some_and(|hello| hello.supports(capability))
+    }
+
+    /// The printer given with `--firmware-url`.
+    pub fn default_printer(&self) -> &FleetPrinter {
+        self.fleet.get(fleet::DEFAULT_PRINTER).expect("the default printer is always in the fleet")
+    }
+
+    /// Uses `url` as the default printer's REST API instead of deriving it
+    /// from the WebSocket URL.
+    pub fn with_firmware_api_url(mut self, url: impl Into<String>) -> Self {
+        let fleet = Arc::make_mut(&mut self.fleet);
+        if let Some(printer) = fleet.get_mut(fleet::DEFAULT_PRINTER) {
+            printer.target.api_url = Some(url.into());
+            printer.files = printer.target.files();
+        }
+        self
+    }
+
+    /// Sets the directory used for uploaded print files.
+    pub fn with_upload_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.upload_dir = dir.into();
+        self
+    }
+
+    /// Adds printers to the fleet and starts connecting to them. Fails if
+    /// a name is used twice.
+    pub fn with_printers(mut self, targets: impl IntoIterator<Item = PrinterTarget>) -> anyhow::Result<Self> {
+        let fleet = Arc::make_mut(&mut self.fleet);
+        for target in targets {
+            if fleet.get(&target.name).is_some() {
+                anyhow::bail!("Printer '{}' is configured twice", target.name);
+            }
+            info!("Managing printer {} at {}", target.name, target.url);
+            fleet.insert(FleetPrinter::connect(target))?;
+        }
+        Ok(self)
+    }
+
+    /// Opens the message journal and starts recording the default
+    /// printer's messages into it.
+    pub fn with_journal(mut self, config: JournalConfig) -> anyhow::Result<Self> {
+        let journal = Arc::new(MessageJournal::open(config)?);
+        tokio::spawn(journal.clone().record(self.message_tx.subscribe()));
+        self.journal = Some(journal);
+        Ok(self)
+    }
+
+    /// Sets the user database used for login and authorization.
+    pub fn with_auth(mut self, auth: AuthService) -> Self {
+        self.auth = Arc::new(auth);
+        self
     }
 }
 
@@ -47,8 +223,12 @@ pub fn create_app_router(state: AppState, static_dir: std::path::PathBuf) -> Rou
     Router::new()
         .route("/", axum::routing::get(index_handler))
         .route("/ws", axum::routing::get(ws_upgrade_handler))
+        .route("/ws/valves", axum::routing::get(valves_ws_handler))
+        .route("/ws/journal", axum::routing::get(journal_ws_handler))
         .merge(create_api_router())
+        .merge(create_compat_router())
         .nest_service("/static", ServeDir::new(static_dir))
+        .layer(axum::middleware::from_fn_with_state(state.clone(), auth::authorize))
         .layer(TraceLayer::new_for_http())
         .with_state(state)
 }
@@ -60,22 +240,40 @@ async fn index_handler() -> axum::response::Html<&'static str> {
 <head><title>HyperGCode-4D Control</title></head>
 <body>
     <h1>HyperGCode-4D Control Interface</h1>
+    <div id="connection"></div>
     <div id="status">Connecting...</div>
     <script>
-        const ws = new WebSocket('ws://' + location.host + '/ws');
+        const token = sessionStorage.getItem('hg4d_token') || '';
+        const ws = new WebSocket('ws://' + location.host + '/ws?token=' + encodeURIComponent(token));
         ws.onmessage = (e) => {
             const msg = JSON.parse(e.data);
             document.getElementById('status').innerText = JSON.stringify(msg, null, 2);
         };
+        const showConnection = async () => {
+            const res = await fetch('/connection?token=' + encodeURIComponent(token));
+            if (!res.ok) return;
+            const conn = await res.json();
+            let text = 'Firmware: ' + conn.state.state;
+            if (conn.pending.length > 0) {
+                text += ' (' + conn.pending.length + ' commands waiting for confirmation)';
+            }
+            document.getElementById('connection').innerText = text;
+        };
+        showConnection();
+        setInterval(showConnection, 2000);
     </script>
 </body>
 </html>"#)
 }
 
 /// WebSocket upgrade handler.
+///
+/// The session is checked once here; the connection handler checks each
+/// incoming message against it with [`Role::required_for_message`].
 async fn ws_upgrade_handler(
     ws: axum::extract::WebSocketUpgrade,
     axum::extract::State(state): axum::extract::State<AppState>,
+    axum::Extension(session): axum::Extension<Session>,
 ) -> axum::response::Response {
-    ws.on_upgrade(|socket| handle_websocket_connection(socket, state))
+    ws.on_upgrade(|socket| handle_websocket_connection(socket, state, session))
 }
@@ -2,11 +2,14 @@
 
 use std::net::SocketAddr;
 use std::path::PathBuf;
+use std::time::Duration;
 use clap::Parser;
-use tracing::info;
+use tracing::{info, warn};
 
 // Import from our library
-use hypergcode_control_interface::{AppState, create_app_router};
+use hypergcode_control_interface::{
+    AppState, AuthService, JournalConfig, PrinterTarget, create_app_router, discovery,
+};
 
 #[derive(Parser)]
 #[command(name = "hg4d-control")]
@@ -20,9 +23,46 @@ struct Cli {
     #[arg(short, long, default_value = "ws://localhost:8080")]
     firmware_url: String,
 
+    /// Firmware REST API URL; the WebSocket URL's host at port 8081 if not given
+    #[arg(long)]
+    firmware_api_url: Option<String>,
+
     /// Static files directory
     #[arg(long, default_value = "./static")]
     static_dir: PathBuf,
+
+    /// Directory for uploaded print files
+    #[arg(long, default_value = hypergcode_control_interface::DEFAULT_UPLOAD_DIR)]
+    upload_dir: PathBuf,
+
+    /// User database (created with a random admin password if missing)
+    #[arg(long, default_value = hypergcode_control_interface::auth::DEFAULT_USERS_FILE)]
+    users_file: PathBuf,
+
+    /// Further printer to manage, as NAME=URL or NAME=URL,API_URL
+    /// (repeatable); the firmware URL above is the "default" printer
+    #[arg(long = "printer", value_name = "NAME=URL")]
+    printers: Vec<PrinterTarget>,
+
+    /// Directory for the firmware message journal
+    #[arg(long, default_value = hypergcode_control_interface::journal::DEFAULT_JOURNAL_DIR)]
+    journal_dir: PathBuf,
+
+    /// Journal size limit in MiB; oldest messages are dropped beyond it
+    #[arg(long, default_value = "256")]
+    journal_max_mb: u64,
+
+    /// Days journaled messages are kept
+    #[arg(long, default_value = "7")]
+    journal_retention_days: u64,
+
+    /// Do not record firmware messages
+    #[arg(long)]
+    no_journal: bool,
+
+    /// List printers announcing themselves on the LAN, then exit
+    #[arg(long)]
+    discover: bool,
 }
 
 #[tokio::main]
@@ -32,11 +72,53 @@ async fn main() -> anyhow::Result<()> {
     
     let cli = Cli::parse();
 
+    if cli.discover {
+        let printers = discovery::discover(discovery::DEFAULT_DISCOVERY_WINDOW).await?;
+        if printers.is_empty() {
+            println!("No printers found");
+        }
+        for printer in printers {
+            let announcement = &printer.announcement;
+            println!(
+                "{}  {}  {} (firmware {}, protocol {})",
+                announcement.instance,
+                printer.websocket_url(),
+                announcement.model,
+                announcement.firmware_version,
+                announcement.protocol_version
+            );
+        }
+        return Ok(());
+    }
+
     info!("HyperGCode-4D Control Interface v{}", env!("CARGO_PKG_VERSION"));
     info!("Connecting to firmware at {}", cli.firmware_url);
 
+    let auth = AuthService::load(&cli.users_file)?;
+    if let Some(password) = auth.bootstrap_admin().await? {
+        warn!(
+            "No users configured; created 'admin' with password {} in {}. Change it after logging in.",
+            password,
+            cli.users_file.display()
+        );
+    }
+
     // Create application state
-    let state = AppState::new(&cli.firmware_url).await?;
+    // Connects in the background; the handshake follows each connection
+    let mut state = AppState::new(&cli.firmware_url)
+        .with_upload_dir(cli.upload_dir)
+        .with_auth(auth)
+        .with_printers(cli.printers)?;
+    if let Some(api_url) = cli.firmware_api_url {
+        state = state.with_firmware_api_url(api_url);
+    }
+    if !cli.no_journal {
+        state = state.with_journal(JournalConfig {
+            dir: cli.journal_dir,
+            max_bytes: cli.journal_max_mb * 1024 * 1024,
+            retention: Duration::from_secs(cli.journal_retention_days * 24 * 3600),
+        })?;
+    }
 
     // Build application router
     let app = create_app_router(state, cli.static_dir);
@@ -0,0 +1,460 @@
+//! Layer previews of uploaded print files.
+//!
+//! Before starting a print, the web UI lets the user scrub through the
+//! layers of an uploaded .hg4d file. The slicer does not embed preview
+//! images in .hg4d files, so previews are rendered on demand from the layer
+//! blocks themselves: [`PreviewFile`] reads the layer index and decodes
+//! single layers, and [`render_tile`] draws a layer as PNG tiles.
+//!
+//! Tiles are addressed by layer, zoom level and tile position. Level 0 fits
+//! the printer's whole valve grid into one [`TILE_SIZE`] tile; every level
+//! above halves the nodes per pixel, up to [`max_level`] where one pixel is
+//! one node. A pixel takes the colour of the channel depositing there, with
+//! an opacity following the fraction of its nodes that are open, binned
+//! from runs as for live heatmaps (see [`crate::visualization`]).
+//!
+//! Scrubbing requests the same few layers at several levels, so
+//! [`PreviewCache`] keeps the most recently decoded layers.
+
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
+
+use gcode_types::codec::unpack_block;
+use gcode_types::{BlockCodec, IndexTrailer, LayerBlock, LayerFrame, LayerIndexEntry};
+
+/// Tile edge length (pixels).
+pub const TILE_SIZE: u32 = 256;
+
+/// Decoded layers kept by [`PreviewCache`].
+pub const LAYER_CACHE_SIZE: usize = 8;
+
+/// .hg4d magic number, as written by the slicer.
+const HG4D_MAGIC: u32 = 0x48473444;
+
+/// Largest metadata section accepted.
+const MAX_METADATA_SIZE: u32 = 16 * 1024 * 1024;
+
+/// Largest layer block accepted; anything larger indicates a corrupt index.
+const MAX_LAYER_BLOCK_SIZE: u32 = 256 * 1024 * 1024;
+
+/// Colour per material channel; channels past the end reuse the palette.
+const CHANNEL_COLORS: [[u8; 3]; 8] = [
+    [0x3b, 0x82, 0xf6],
+    [0xef, 0x44, 0x44],
+    [0x22, 0xc5, 0x5e],
+    [0xea, 0xb3, 0x08],
+    [0xa8, 0x55, 0xf7],
+    [0x06, 0xb6, 0xd4],
+    [0xf9, 0x73, 0x16],
+    [0xec, 0x48, 0x99],
+];
+
+/// Header metadata fields previews need; everything else is skipped.
+#[derive(Debug, Deserialize)]
+struct PreviewMetadata {
+    #[serde(default)]
+    codec: Option<BlockCodec>,
+}
+
+/// One layer of a file, as listed in its preview.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct PreviewLayer {
+    pub layer: u32,
+    pub z_height: f32,
+}
+
+/// What a client needs to request a file's preview tiles.
+#[derive(Debug, Clone, Serialize)]
+pub struct PreviewInfo {
+    pub file: String,
+    pub layers: Vec<PreviewLayer>,
+    /// Valve grid size in nodes (x, y)
+    pub grid: (u32, u32),
+    pub tile_size: u32,
+    /// Highest zoom level, at one node per pixel
+    pub max_level: u32,
+    /// Tiles along x and y, per level from 0
+    pub tiles: Vec<(u32, u32)>,
+}
+
+impl PreviewInfo {
+    pub fn new(file: &str, index: &[LayerIndexEntry], grid: (u32, u32)) -> Self {
+        let max_level = max_level(grid);
+        Self {
+            file: file.to_string(),
+            layers: index
+                .iter()
+                .map(|entry| PreviewLayer { layer: entry.layer_number, z_height: entry.z_height })
+                .collect(),
+            grid,
+            tile_size: TILE_SIZE,
+            max_level,
+            tiles: (0..=max_level).map(|level| tiles_at(grid, level)).collect(),
+        }
+    }
+}
+
+/// Highest zoom level of a grid: the level at which one pixel is one node.
+pub fn max_level(grid: (u32, u32)) -> u32 {
+    let tiles = grid.0.max(grid.1).div_ceil(TILE_SIZE).max(1);
+    tiles.next_power_of_two().trailing_zeros()
+}
+
+/// Nodes along one pixel edge at `level`.
+fn nodes_per_pixel(grid: (u32, u32), level: u32) -> u32 {
+    1 << max_level(grid).saturating_sub(level)
+}
+
+/// Number of tiles along x and y at `level`.
+pub fn tiles_at(grid: (u32, u32), level: u32) -> (u32, u32) {
+    let span = TILE_SIZE * nodes_per_pixel(grid, level);
+    (grid.0.div_ceil(span).max(1), grid.1.div_ceil(span).max(1))
+}
+
+/// Position of one tile.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TileAddress {
+    pub level: u32,
+    pub x: u32,
+    pub y: u32,
+}
+
+impl TileAddress {
+    /// Fails if the tile lies outside the grid at its level.
+    pub fn validate(&self, grid: (u32, u32)) -> Result<()> {
+        let max = max_level(grid);
+        if self.level > max {
+            bail!("Zoom level {} is above the highest level {}", self.level, max);
+        }
+        let (columns, rows) = tiles_at(grid, self.level);
+        if self.x >= columns || self.y >= rows {
+            bail!(
+                "Tile ({}, {}) is outside the {}x{} tiles of level {}",
+                self.x,
+                self.y,
+                columns,
+                rows,
+                self.level
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Row-major RGBA pixels of one tile of a frame placed on a `grid`.
+pub fn tile_pixels(frame: &LayerFrame, grid: (u32, u32), tile: TileAddress) -> Vec<u8> {
+    let npp = nodes_per_pixel(grid, tile.level);
+    let span = TILE_SIZE * npp;
+    let (x0, y0) = (tile.x * span, tile.y * span);
+
+    let pixels = (TILE_SIZE * TILE_SIZE) as usize;
+    let mut open = vec![0u32; pixels];
+    let mut channels = vec![0u8; pixels];
+    for plane in &frame.planes {
+        let channel = plane.channel.or(frame.primary_material).unwrap_or(0);
+        for row in &plane.rows {
+            let y = frame.origin.y + row.y;
+            if y < y0 || y >= y0 + span {
+                continue;
+            }
+            let py = (y - y0) / npp;
+            for run in row.runs.iter().filter(|r| r.mask != 0) {
+                // Clip the run to the tile, then split it at pixel boundaries
+                let start = frame.origin.x + run.x;
+                let mut x = start.max(x0);
+                let end = (start + run.len).min(x0 + span);
+                while x < end {
+                    let px = (x - x0) / npp;
+                    let pixel_end = (x0 + (px + 1) * npp).min(end);
+                    let i = (py * TILE_SIZE + px) as usize;
+                    open[i] += pixel_end - x;
+                    channels[i] = channel;
+                    x = pixel_end;
+                }
+            }
+        }
+    }
+
+    let nodes_per_pixel = (npp * npp) as f32;
+    let mut rgba = Vec::with_capacity(pixels * 4);
+    for (n, channel) in open.into_iter().zip(channels) {
+        let [r, g, b] = CHANNEL_COLORS[channel as usize % CHANNEL_COLORS.len()];
+        let alpha = ((n as f32 / nodes_per_pixel).min(1.0) * 255.0).round() as u8;
+        rgba.extend_from_slice(&[r, g, b, alpha]);
+    }
+    rgba
+}
+
+/// One tile of a frame, PNG encoded.
+pub fn render_tile(frame: &LayerFrame, grid: (u32, u32), tile: TileAddress) -> Result<Vec<u8>> {
+    tile.validate(grid)?;
+    let rgba = tile_pixels(frame, grid, tile);
+
+    let mut png = Vec::new();
+    let mut encoder = png::Encoder::new(&mut png, TILE_SIZE, TILE_SIZE);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().context("Failed to start PNG tile")?;
+    writer.write_image_data(&rgba).context("Failed to encode PNG tile")?;
+    writer.finish().context("Failed to finish PNG tile")?;
+    Ok(png)
+}
+
+/// An .hg4d file opened for previewing: header codec and layer index only.
+pub struct PreviewFile {
+    path: PathBuf,
+    codec: Option<BlockCodec>,
+    index: Vec<LayerIndexEntry>,
+}
+
+impl PreviewFile {
+    pub fn open(path: &Path) -> Result<Self> {
+        let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+        let mut reader = BufReader::new(file);
+        let codec = read_codec(&mut reader)?;
+        let index = read_index(&mut reader)?;
+        Ok(Self { path: path.to_path_buf(), codec, index })
+    }
+
+    pub fn index(&self) -> &[LayerIndexEntry] {
+        &self.index
+    }
+
+    /// Reads, verifies and decodes one layer.
+    pub fn read_layer(&self, layer: u32) -> Result<LayerFrame> {
+        let entry = self
+            .index
+            .iter()
+            .find(|entry| entry.layer_number == layer)
+            .with_context(|| format!("{} has no layer {}", self.path.display(), layer))?;
+
+        let mut reader =
+            File::open(&self.path).with_context(|| format!("Failed to open {}", self.path.display()))?;
+        let mut data = vec![0u8; entry.data_size as usize];
+        reader.seek(SeekFrom::Start(entry.file_offset))?;
+        reader
+            .read_exact(&mut data)
+            .with_context(|| format!("Layer {} block is truncated", layer))?;
+
+        let actual = crc32fast::hash(&data);
+        if actual != entry.checksum {
+            bail!(
+                "Layer {} checksum mismatch: expected {:08x}, got {:08x}",
+                layer,
+                entry.checksum,
+                actual
+            );
+        }
+        let block = match self.codec {
+            Some(_) => {
+                let data = unpack_block(&data, MAX_LAYER_BLOCK_SIZE as usize)
+                    .with_context(|| format!("Failed to decompress layer {}", layer))?;
+                LayerBlock::from_bytes(&data)
+            }
+            None => LayerBlock::from_bytes(&data),
+        }
+        .with_context(|| format!("Failed to decode layer {}", layer))?;
+
+        match block {
+            LayerBlock::Frame(frame) => Ok(frame),
+            LayerBlock::Nodes(layer) => {
+                LayerFrame::from_layer(&layer).context("Failed to encode layer as frame")
+            }
+        }
+    }
+}
+
+/// Block codec named in the header metadata.
+fn read_codec<R: Read + Seek>(reader: &mut R) -> Result<Option<BlockCodec>> {
+    let mut header = [0u8; 12];
+    reader.seek(SeekFrom::Start(0))?;
+    reader.read_exact(&mut header).context("File too short for an .hg4d header")?;
+
+    let word = |i: usize| u32::from_le_bytes([header[i], header[i + 1], header[i + 2], header[i + 3]]);
+    if word(0) != HG4D_MAGIC {
+        bail!("Not an .hg4d file (magic {:08x})", word(0));
+    }
+    let size = word(8);
+    if size > MAX_METADATA_SIZE {
+        bail!("Metadata section of {} bytes is too large", size);
+    }
+
+    let mut document = vec![0u8; size as usize];
+    reader.read_exact(&mut document).context("Metadata section is truncated")?;
+    let document = String::from_utf8(document).context("Metadata is not UTF-8")?;
+    let metadata: PreviewMetadata = toml::from_str(&document).context("Invalid .hg4d metadata")?;
+    Ok(metadata.codec)
+}
+
+/// Layer index from the trailer at the end of the file.
+fn read_index<R: Read + Seek>(reader: &mut R) -> Result<Vec<LayerIndexEntry>> {
+    let file_len = reader.seek(SeekFrom::End(0))?;
+    if file_len < IndexTrailer::ENCODED_SIZE as u64 {
+        bail!("File too short for an index trailer ({} bytes)", file_len);
+    }
+
+    let mut trailer = [0u8; IndexTrailer::ENCODED_SIZE];
+    reader.seek(SeekFrom::End(-(IndexTrailer::ENCODED_SIZE as i64)))?;
+    reader.read_exact(&mut trailer)?;
+    let trailer = IndexTrailer::from_bytes(&trailer).context("Invalid index trailer")?;
+    if trailer.index_offset + trailer.index_size() + IndexTrailer::ENCODED_SIZE as u64 != file_len {
+        bail!("Index of {} layers does not fit a {} byte file", trailer.layer_count, file_len);
+    }
+
+    let mut raw = vec![0u8; trailer.index_size() as usize];
+    reader.seek(SeekFrom::Start(trailer.index_offset))?;
+    reader.read_exact(&mut raw)?;
+    let index = raw
+        .chunks_exact(LayerIndexEntry::ENCODED_SIZE)
+        .map(LayerIndexEntry::from_bytes)
+        .collect::<Result<Vec<_>, _>>()
+        .context("Invalid index entry")?;
+
+    let outside = |entry: &&LayerIndexEntry| {
+        entry.data_size > MAX_LAYER_BLOCK_SIZE
+            || entry.file_offset + entry.data_size as u64 > trailer.index_offset
+    };
+    if let Some(entry) = index.iter().find(outside) {
+        bail!("Layer {} block lies outside the layer data", entry.layer_number);
+    }
+    Ok(index)
+}
+
+/// Decoded layer, identified by file, modification time and layer number
+/// so a replaced upload is not served from stale entries.
+type CacheKey = (PathBuf, Option<SystemTime>, u32);
+
+/// Printer grid size and recently decoded preview layers.
+#[derive(Clone, Default)]
+pub struct PreviewCache {
+    /// Valve grid of the connected printer, once fetched from the firmware
+    grid: Arc<RwLock<Option<(u32, u32)>>>,
+    /// Most recently used last
+    layers: Arc<Mutex<VecDeque<(CacheKey, Arc<LayerFrame>)>>>,
+}
+
+impl PreviewCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub async fn grid(&self) -> Option<(u32, u32)> {
+        *self.grid.read().await
+    }
+
+    pub async fn set_grid(&self, grid: (u32, u32)) {
+        *self.grid.write().await = Some(grid);
+    }
+
+    /// A layer of a file, decoded on a miss. Blocking.
+    pub fn layer(&self, path: &Path, layer: u32) -> Result<Arc<LayerFrame>> {
+        let modified = std::fs::metadata(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?
+            .modified()
+            .ok();
+        let key = (path.to_path_buf(), modified, layer);
+
+        {
+            let mut layers = self.layers.lock().expect("preview cache lock poisoned");
+            if let Some(i) = layers.iter().position(|(k, _)| *k == key) {
+                let hit = layers.remove(i).expect("position is in range");
+                let frame = hit.1.clone();
+                layers.push_back(hit);
+                return Ok(frame);
+            }
+        }
+
+        // Decoded outside the lock; concurrent misses on one layer decode it twice
+        let frame = Arc::new(PreviewFile::open(path)?.read_layer(layer)?);
+        let mut layers = self.layers.lock().expect("preview cache lock poisoned");
+        if layers.len() >= LAYER_CACHE_SIZE {
+            layers.pop_front();
+        }
+        layers.push_back((key, frame.clone()));
+        Ok(frame)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gcode_types::{GridCoordinate, Layer, NodeValveState, ValveState};
+
+    fn hg4d_file(layers: &[Layer]) -> Vec<u8> {
+        let metadata = b"model_name = \"cube\"\n";
+        let mut file = HG4D_MAGIC.to_le_bytes().to_vec();
+        file.extend_from_slice(&1u32.to_le_bytes());
+        file.extend_from_slice(&(metadata.len() as u32).to_le_bytes());
+        file.extend_from_slice(metadata);
+
+        let mut index = Vec::new();
+        for layer in layers {
+            let data = LayerBlock::encode_compact(layer).unwrap();
+            index.push(LayerIndexEntry {
+                layer_number: layer.layer_number,
+                z_height: layer.z_height,
+                file_offset: file.len() as u64,
+                data_size: data.len() as u32,
+                checksum: crc32fast::hash(&data),
+            });
+            file.extend_from_slice(&data);
+        }
+        let trailer = IndexTrailer { index_offset: file.len() as u64, layer_count: layers.len() as u32 };
+        for entry in &index {
+            file.extend_from_slice(&entry.to_bytes());
+        }
+        file.extend_from_slice(&trailer.to_bytes());
+        file
+    }
+
+    #[test]
+    fn test_renders_tiles_from_file() {
+        // Layer 1: channel 1 fills the first 4x4 nodes, and node (600, 10)
+        let mut layers = vec![Layer::new(0.2, 0), Layer::new(0.4, 1)];
+        for y in 0..4 {
+            for x in 0..4 {
+                let node = NodeValveState::new(GridCoordinate::new(x, y), vec![ValveState::open(0)]);
+                layers[1].add_node(node.with_material(1));
+            }
+        }
+        layers[1].add_node(NodeValveState::new(GridCoordinate::new(600, 10), vec![ValveState::open(0)]));
+        let path = std::env::temp_dir().join(format!("hg4d-preview-{}.hg4d", std::process::id()));
+        std::fs::write(&path, hg4d_file(&layers)).unwrap();
+
+        let cache = PreviewCache::new();
+        let frame = cache.layer(&path, 1).unwrap();
+        assert!(Arc::ptr_eq(&frame, &cache.layer(&path, 1).unwrap()), "second read is cached");
+        let info = PreviewInfo::new("cube.hg4d", PreviewFile::open(&path).unwrap().index(), (1000, 500));
+        assert!(cache.layer(&path, 7).is_err());
+        std::fs::remove_file(&path).ok();
+
+        // 1000 nodes need 4 tiles of 256 at full resolution
+        assert_eq!(info.layers.len(), 2);
+        assert_eq!(info.max_level, 2);
+        assert_eq!(info.tiles, vec![(1, 1), (2, 1), (4, 2)]);
+
+        // Full resolution: one node per pixel
+        let full = tile_pixels(&frame, (1000, 500), TileAddress { level: 2, x: 0, y: 0 });
+        assert_eq!(&full[..4], &[0xef, 0x44, 0x44, 255]);
+        assert_eq!(full[4 * 4 + 3], 0, "x = 4 is empty");
+        let far = tile_pixels(&frame, (1000, 500), TileAddress { level: 2, x: 2, y: 0 });
+        assert_eq!(far[((10 * TILE_SIZE + 600 - 512) * 4 + 3) as usize], 255);
+
+        // Level 0: 4x4 nodes per pixel, the filled block is one opaque pixel
+        let overview = tile_pixels(&frame, (1000, 500), TileAddress { level: 0, x: 0, y: 0 });
+        assert_eq!(overview[3], 255);
+        assert_eq!(overview[((2 * TILE_SIZE + 150) * 4 + 3) as usize], 16, "1 of 16 nodes open");
+
+        let png = render_tile(&frame, (1000, 500), TileAddress { level: 1, x: 1, y: 0 }).unwrap();
+        assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+        assert!(render_tile(&frame, (1000, 500), TileAddress { level: 1, x: 2, y: 0 }).is_err());
+    }
+}
@@ -0,0 +1,176 @@
+//! Live valve array heatmaps.
+//!
+//! The firmware streams the active valve pattern as run-length encoded
+//! frames inside `ValveStateUpdate` messages. A full-resolution grid can be
+//! hundreds of thousands of nodes, so browsers receive a [`Heatmap`]
+//! instead: the grid binned down to at most `size × size` cells, each cell
+//! holding the fraction of its nodes with an open valve.
+//!
+//! Binning works on runs rather than individual nodes, so the cost follows
+//! the encoded frame size, not the grid size.
+
+use std::sync::Arc;
+
+use serde::Serialize;
+use tokio::sync::{broadcast, RwLock};
+use tracing::debug;
+
+use gcode_types::LayerFrame;
+use protocol::{ProtocolMessage, ValveStateUpdate};
+
+/// Default heatmap edge length (cells).
+pub const DEFAULT_HEATMAP_SIZE: u32 = 128;
+
+/// Largest heatmap edge length clients may request.
+pub const MAX_HEATMAP_SIZE: u32 = 512;
+
+/// Downsampled valve activity for one layer.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Heatmap {
+    pub layer: u32,
+    /// Heatmap size in cells
+    pub width: u32,
+    pub height: u32,
+    /// Grid nodes covered by one cell along each axis
+    pub bin_size: u32,
+    /// Row-major activity per cell, 0 (idle) to 255 (every node open)
+    pub cells: Vec<u8>,
+}
+
+impl Heatmap {
+    /// Bins a frame placed on a `grid` of (x, y) nodes.
+    pub fn from_frame(frame: &LayerFrame, grid: (u32, u32), size: u32) -> Self {
+        let size = size.clamp(1, MAX_HEATMAP_SIZE);
+        let bin_size = grid.0.max(grid.1).div_ceil(size).max(1);
+        let width = grid.0.div_ceil(bin_size).max(1);
+        let height = grid.1.div_ceil(bin_size).max(1);
+
+        let mut open = vec![0u32; (width * height) as usize];
+        for plane in &frame.planes {
+            for row in &plane.rows {
+                let cy = (frame.origin.y + row.y) / bin_size;
+                if cy >= height {
+                    continue;
+                }
+                for run in row.runs.iter().filter(|r| r.mask != 0) {
+                    // Split the run at bin boundaries
+                    let mut x = frame.origin.x + run.x;
+                    let end = x + run.len;
+                    while x < end {
+                        let cx = x / bin_size;
+                        if cx >= width {
+                            break;
+                        }
+                        let bin_end = ((cx + 1) * bin_size).min(end);
+                        open[(cy * width + cx) as usize] += bin_end - x;
+                        x = bin_end;
+                    }
+                }
+            }
+        }
+
+        let nodes_per_cell = (bin_size * bin_size) as f32;
+        let cells = open
+            .into_iter()
+            .map(|n| ((n as f32 / nodes_per_cell).min(1.0) * 255.0).round() as u8)
+            .collect();
+
+        Self {
+            layer: frame.layer_number,
+            width,
+            height,
+            bin_size,
+            cells,
+        }
+    }
+
+    /// Binary WebSocket encoding: little-endian `layer: u32`, `width: u16`,
+    /// `height: u16`, `bin_size: u16`, followed by the row-major cells.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(10 + self.cells.len());
+        out.extend_from_slice(&self.layer.to_le_bytes());
+        out.extend_from_slice(&(self.width as u16).to_le_bytes());
+        out.extend_from_slice(&(self.height as u16).to_le_bytes());
+        out.extend_from_slice(&(self.bin_size.min(u16::MAX as u32) as u16).to_le_bytes());
+        out.extend_from_slice(&self.cells);
+        out
+    }
+}
+
+/// Builds a heatmap from an update, if it carries a frame and grid size.
+pub fn heatmap_from_update(update: &ValveStateUpdate, size: u32) -> Option<Heatmap> {
+    let frame = update.frame.as_ref()?;
+    let grid = update.grid_size?;
+    Some(Heatmap::from_frame(frame, grid, size))
+}
+
+/// Latest valve frame received from the firmware.
+#[derive(Clone, Default)]
+pub struct ValveFrameCache {
+    latest: Arc<RwLock<Option<ValveStateUpdate>>>,
+}
+
+impl ValveFrameCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub async fn latest(&self) -> Option<ValveStateUpdate> {
+        self.latest.read().await.clone()
+    }
+
+    /// Records frame-carrying valve updates from the firmware broadcast
+    /// until the channel closes.
+    pub async fn track(self, mut rx: broadcast::Receiver<ProtocolMessage>) {
+        loop {
+            match rx.recv().await {
+                Ok(ProtocolMessage::ValveStateUpdate(update)) if update.frame.is_some() => {
+                    *self.latest.write().await = Some(update);
+                }
+                Ok(_) => {}
+                Err(broadcast::error::RecvError::Lagged(n)) => {
+                    debug!("Valve frame tracker skipped {} messages", n);
+                }
+                Err(broadcast::error::RecvError::Closed) => return,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gcode_types::{GridCoordinate, Layer, NodeValveState, ValveState};
+
+    #[test]
+    fn test_bins_runs_into_cells() {
+        // 400x400 grid, bins of 4 nodes; rows 0..4 fully open from x=0..8
+        let mut layer = Layer::new(0.2, 9);
+        for y in 0..4 {
+            for x in 0..8 {
+                layer.add_node(NodeValveState::new(GridCoordinate::new(x, y), vec![ValveState::open(0)]));
+            }
+            // Closed node does not count
+            layer.add_node(NodeValveState::new(GridCoordinate::new(8, y), vec![ValveState::closed(0)]));
+        }
+        // Half of the cell at (2, 2)
+        for x in 8..10 {
+            for y in 8..12 {
+                layer.add_node(NodeValveState::new(GridCoordinate::new(x, y), vec![ValveState::open(0)]));
+            }
+        }
+        let frame = LayerFrame::from_layer(&layer).unwrap();
+
+        let map = Heatmap::from_frame(&frame, (400, 400), 100);
+        assert_eq!((map.width, map.height, map.bin_size), (100, 100, 4));
+        assert_eq!(map.cells[0], 255);
+        assert_eq!(map.cells[1], 255);
+        assert_eq!(map.cells[2], 0);
+        assert_eq!(map.cells[2 * 100 + 2], 128);
+        assert_eq!(map.cells.iter().filter(|c| **c > 0).count(), 3);
+
+        let bytes = map.to_bytes();
+        assert_eq!(bytes.len(), 10 + 100 * 100);
+        assert_eq!(&bytes[0..4], &9u32.to_le_bytes());
+    }
+}
@@ -0,0 +1,108 @@
+//! Browser sessions on `/ws`.
+//!
+//! Every firmware message is forwarded to the browser as JSON text. Each
+//! message the browser sends is checked against the session's role with
+//! [`Role::required_for_message`] before it reaches the firmware; one the
+//! role may not send is dropped and answered with a failed
+//! `CommandResponse`, and the connection stays open.
+
+use axum::extract::ws::{Message, WebSocket};
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{debug, warn};
+
+use protocol::{CommandResponse, ProtocolMessage};
+
+use crate::auth::{Role, Session};
+use crate::connection::Delivery;
+use crate::AppState;
+
+/// Runs one browser connection until either side closes it.
+pub async fn handle_websocket_connection(mut socket: WebSocket, state: AppState, session: Session) {
+    let mut firmware_rx = state.message_tx.subscribe();
+    loop {
+        let outgoing = tokio::select! {
+            frame = socket.recv() => {
+                let text = match frame {
+                    None | Some(Ok(Message::Close(_))) | Some(Err(_)) => break,
+                    Some(Ok(Message::Text(text))) => text,
+                    Some(Ok(_)) => continue,
+                };
+                match admit(session.role, &text) {
+                    Ok(Some(msg)) => forward(&state, msg).await,
+                    Ok(None) => None,
+                    Err(refusal) => {
+                        warn!("{}: {}", session.username, refusal);
+                        Some(ProtocolMessage::CommandResponse(CommandResponse::error(refusal)))
+                    }
+                }
+            }
+            msg = firmware_rx.recv() => match msg {
+                Ok(msg) => Some(msg),
+                Err(RecvError::Lagged(skipped)) => {
+                    debug!("WebSocket client {} skipped {} messages", session.username, skipped);
+                    None
+                }
+                Err(RecvError::Closed) => break,
+            },
+        };
+
+        let Some(msg) = outgoing else { continue };
+        let text = match serde_json::to_string(&msg) {
+            Ok(text) => text,
+            Err(e) => {
+                debug!("Skipping unserializable message: {}", e);
+                continue;
+            }
+        };
+        if socket.send(Message::Text(text)).await.is_err() {
+            break;
+        }
+    }
+    debug!("WebSocket client {} disconnected", session.username);
+}
+
+/// Parses a browser message and checks `role` may send it. Messages of a
+/// type this build does not know are skipped.
+fn admit(role: Role, text: &str) -> Result<Option<ProtocolMessage>, String> {
+    let Some(msg) = protocol::decode_message(text.as_bytes()).map_err(|e| e.to_string())? else {
+        return Ok(None);
+    };
+    let required = Role::required_for_message(&msg);
+    if role < required {
+        return Err(format!("{} needs the {:?} role", msg.message_type(), required).to_lowercase());
+    }
+    Ok(Some(msg))
+}
+
+/// Sends an admitted message to the firmware. Its reply reaches the
+/// browser through the broadcast; only failures are answered here.
+async fn forward(state: &AppState, msg: ProtocolMessage) -> Option<ProtocolMessage> {
+    let refusal = match state.firmware.send(msg).await {
+        Ok(Delivery::Sent) => return None,
+        Ok(Delivery::Queued(id)) => format!("Firmware is disconnected; queued as command {}", id),
+        Err(e) => format!("{:#}", e),
+    };
+    Some(ProtocolMessage::CommandResponse(CommandResponse::error(refusal)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_messages_checked_against_role() {
+        let start = r#"{"type":"StartPrint","data":{"file_path":"part.hg4d"}}"#;
+        let status = r#"{"type":"GetStatus","data":{}}"#;
+
+        let refused = admit(Role::Viewer, start).unwrap_err();
+        assert!(refused.contains("operator"), "{}", refused);
+        assert!(admit(Role::Viewer, r#"{"type":"EmergencyStop"}"#).is_err());
+        assert!(matches!(admit(Role::Viewer, status), Ok(Some(ProtocolMessage::GetStatus(_)))));
+        assert!(matches!(admit(Role::Operator, start), Ok(Some(ProtocolMessage::StartPrint(_)))));
+        assert!(admit(Role::Operator, r#"{"type":"ApplyFlowCalibration"}"#).is_err());
+
+        // Unknown types are skipped, malformed known ones refused
+        assert!(matches!(admit(Role::Viewer, r#"{"type":"FromTheFuture"}"#), Ok(None)));
+        assert!(admit(Role::Admin, r#"{"type":"StartPrint","data":{}}"#).is_err());
+    }
+}
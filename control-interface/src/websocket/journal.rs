@@ -0,0 +1,60 @@
+//! Journal replay stream.
+//!
+//! Clients connect to `/ws/journal?from=..&to=..&speed=..` and receive the
+//! journaled firmware messages of that range as JSON text messages, in the
+//! same form as the live `/ws` stream, paced at `speed` times the original
+//! timing (see [`replay_delay`]). The connection closes after the last
+//! entry.
+
+use axum::extract::ws::{CloseFrame, Message, WebSocket};
+use axum::extract::{Query, State, WebSocketUpgrade};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use tracing::debug;
+
+use crate::api::journal::{query, JournalParams};
+use crate::journal::{replay_delay, JournalEntry};
+use crate::AppState;
+
+/// GET /ws/journal - upgrade to a replay of a journal range.
+pub async fn journal_ws_handler(
+    ws: WebSocketUpgrade,
+    State(state): State<AppState>,
+    Query(params): Query<JournalParams>,
+) -> Response {
+    let speed = params.speed.unwrap_or(1.0);
+    if !(speed > 0.0 && speed.is_finite()) {
+        return (StatusCode::BAD_REQUEST, "'speed' must be positive").into_response();
+    }
+    // Read before upgrading so a disabled journal or bad range is an HTTP error
+    let entries = match query(&state, &params).await {
+        Ok(entries) => entries,
+        Err(rejection) => return rejection.into_response(),
+    };
+    ws.on_upgrade(move |socket| replay(socket, entries, speed))
+}
+
+async fn replay(mut socket: WebSocket, entries: Vec<JournalEntry>, speed: f32) {
+    let mut previous_ms = entries.first().map_or(0, |e| e.timestamp_ms);
+    for entry in &entries {
+        tokio::time::sleep(replay_delay(previous_ms, entry.timestamp_ms, speed)).await;
+        previous_ms = entry.timestamp_ms;
+
+        let text = match serde_json::to_string(&entry.message) {
+            Ok(text) => text,
+            Err(e) => {
+                debug!("Skipping unserializable journal entry: {}", e);
+                continue;
+            }
+        };
+        if socket.send(Message::Text(text)).await.is_err() {
+            debug!("Journal replay client disconnected");
+            return;
+        }
+    }
+    let close = CloseFrame {
+        code: axum::extract::ws::close_code::NORMAL,
+        reason: "replay complete".into(),
+    };
+    socket.send(Message::Close(Some(close))).await.ok();
+}
@@ -5,13 +5,18 @@
 //!
 //! ## Module Organization
 //!
-//! - **handler**: WebSocket connection handler
+//! - **handler**: WebSocket connection handler; drops commands the
+//!   session's role may not send (see `auth::Role::required_for_message`)
 //! - **messages**: Message routing and transformation
 //! - **broadcast**: Broadcasting to multiple clients
+//! - **valves**: Live valve heatmap stream
+//! - **journal**: Replay of journaled firmware messages
 
 pub mod handler;
 pub mod messages;
 pub mod broadcast;
+pub mod valves;
+pub mod journal;
 
 use axum::extract::ws::WebSocket;
 use tokio::sync::broadcast;
@@ -20,6 +25,8 @@ use protocol::ProtocolMessage;
 pub use handler::handle_websocket_connection;
 pub use messages::MessageRouter;
 pub use broadcast::BroadcastManager;
+pub use valves::valves_ws_handler;
+pub use journal::journal_ws_handler;
 
 /// WebSocket client session state.
 pub struct ClientSession {
@@ -0,0 +1,63 @@
+//! Live valve heatmap stream.
+//!
+//! Clients connect to `/ws/valves?size=N` and receive one binary message per
+//! firmware valve frame (see [`Heatmap::to_bytes`]). Frames are dropped
+//! rather than queued when a client falls behind, so a slow browser always
+//! sees the most recent pattern.
+
+use axum::extract::ws::{Message, WebSocket};
+use axum::extract::{Query, State, WebSocketUpgrade};
+use axum::response::Response;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::debug;
+
+use protocol::ProtocolMessage;
+
+use crate::api::valves::HeatmapParams;
+use crate::visualization::{heatmap_from_update, Heatmap, DEFAULT_HEATMAP_SIZE};
+use crate::AppState;
+
+/// GET /ws/valves - upgrade to a heatmap stream.
+pub async fn valves_ws_handler(
+    ws: WebSocketUpgrade,
+    State(state): State<AppState>,
+    Query(params): Query<HeatmapParams>,
+) -> Response {
+    let size = params.size.unwrap_or(DEFAULT_HEATMAP_SIZE);
+    ws.on_upgrade(move |socket| stream_heatmaps(socket, state, size))
+}
+
+async fn stream_heatmaps(mut socket: WebSocket, state: AppState, size: u32) {
+    let mut rx = state.message_tx.subscribe();
+
+    // Start with the current pattern so the view is not blank until the next change
+    if let Some(map) = state
+        .valve_frames
+        .latest()
+        .await
+        .and_then(|u| heatmap_from_update(&u, size))
+    {
+        if send_heatmap(&mut socket, &map).await.is_err() {
+            return;
+        }
+    }
+
+    loop {
+        match rx.recv().await {
+            Ok(ProtocolMessage::ValveStateUpdate(update)) => {
+                if let Some(map) = heatmap_from_update(&update, size) {
+                    if send_heatmap(&mut socket, &map).await.is_err() {
+                        debug!("Valve heatmap client disconnected");
+                        return;
+                    }
+                }
+            }
+            Ok(_) | Err(RecvError::Lagged(_)) => continue,
+            Err(RecvError::Closed) => return,
+        }
+    }
+}
+
+async fn send_heatmap(socket: &mut WebSocket, map: &Heatmap) -> Result<(), axum::Error> {
+    socket.send(Message::Binary(map.to_bytes())).await
+}
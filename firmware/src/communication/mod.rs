@@ -6,14 +6,17 @@
 //! ## Module Organization
 //!
 //! - **serial**: Serial port communication
-//! - **network**: Network interface and REST API
+//! - **network**: Network presence and mDNS discovery
 //! - **websocket**: WebSocket server for real-time updates
+//! - **subscription**: Per-client topic subscriptions and rate limiting
 
 pub mod serial;
 pub mod network;
 pub mod websocket;
+pub mod subscription;
 
 pub use serial::SerialInterface;
 pub use network::NetworkInterface;
 pub use websocket::WebSocketServer;
+pub use subscription::ClientSubscription;
 
@@ -0,0 +1,132 @@
+//! Network presence of the firmware.
+//!
+//! [`NetworkInterface`] owns the mDNS responder that makes the printer
+//! discoverable as `<hostname>.local` (`hypergcode-4d.local` by default).
+//! It registers one [`protocol::discovery::SERVICE_TYPE`] service whose
+//! port is the WebSocket port and whose TXT record carries the REST API
+//! port and the printer identity (see [`ServiceAnnouncement`]). Addresses
+//! follow the host's interfaces as they come and go. The record is
+//! withdrawn on shutdown so clients drop the printer immediately instead
+//! of waiting for its TTL.
+
+use anyhow::{Context, Result};
+use mdns_sd::{ServiceDaemon, ServiceInfo};
+use tracing::{info, warn};
+
+use config_types::PrinterConfig;
+use protocol::discovery::{DEFAULT_HOSTNAME, SERVICE_TYPE};
+use protocol::{ServiceAnnouncement, PROTOCOL_VERSION};
+
+use crate::FIRMWARE_VERSION;
+
+/// Builds the announcement for this printer.
+///
+/// The instance name is the model name plus the serial number, if any, so
+/// several printers of one model stay distinguishable.
+pub fn announcement(
+    config: &PrinterConfig,
+    hostname: Option<&str>,
+    websocket_port: u16,
+    api_port: u16,
+) -> ServiceAnnouncement {
+    let serial_number = config.metadata.serial_number.clone();
+    let instance = match &serial_number {
+        Some(serial) => format!("{} #{}", config.model.name(), serial),
+        None => config.model.name().to_string(),
+    };
+    ServiceAnnouncement {
+        instance,
+        hostname: hostname.unwrap_or(DEFAULT_HOSTNAME).to_string(),
+        websocket_port,
+        api_port,
+        model: format!("{:?}", config.model),
+        serial_number,
+        firmware_version: FIRMWARE_VERSION.to_string(),
+        protocol_version: PROTOCOL_VERSION.to_string(),
+    }
+}
+
+/// Registered mDNS service.
+pub struct MdnsResponder {
+    daemon: ServiceDaemon,
+    fullname: String,
+}
+
+impl MdnsResponder {
+    /// Starts answering queries for the announcement.
+    pub fn start(announcement: &ServiceAnnouncement) -> Result<Self> {
+        let daemon = ServiceDaemon::new().context("Failed to start mDNS daemon")?;
+        let records = announcement.txt_records();
+        let properties: Vec<(&str, &str)> = records.iter().map(|(key, value)| (*key, value.as_str())).collect();
+        let service = ServiceInfo::new(
+            SERVICE_TYPE,
+            &announcement.instance,
+            &announcement.host_fqdn(),
+            "",
+            announcement.websocket_port,
+            &properties[..],
+        )
+        .context("Invalid mDNS service record")?
+        .enable_addr_auto();
+        let fullname = service.get_fullname().to_string();
+        daemon.register(service).context("Failed to register mDNS service")?;
+        info!("Announcing {} as {}", fullname, announcement.host_fqdn());
+        Ok(Self { daemon, fullname })
+    }
+
+    /// Withdraws the service and stops the daemon.
+    pub fn shutdown(self) {
+        if let Err(e) = self.daemon.unregister(&self.fullname) {
+            warn!("Failed to withdraw mDNS service {}: {}", self.fullname, e);
+        }
+        if let Err(e) = self.daemon.shutdown() {
+            warn!("Failed to stop mDNS daemon: {}", e);
+        }
+    }
+}
+
+/// Network services beyond the WebSocket and REST servers.
+pub struct NetworkInterface {
+    announcement: ServiceAnnouncement,
+    responder: Option<MdnsResponder>,
+}
+
+impl NetworkInterface {
+    pub fn new(announcement: ServiceAnnouncement) -> Self {
+        Self {
+            announcement,
+            responder: None,
+        }
+    }
+
+    pub fn announcement(&self) -> &ServiceAnnouncement {
+        &self.announcement
+    }
+
+    /// Starts the mDNS responder. A failure leaves the printer reachable by
+    /// address only and is returned for logging, not treated as fatal.
+    pub fn start_discovery(&mut self) -> Result<()> {
+        if self.responder.is_none() {
+            self.responder = Some(MdnsResponder::start(&self.announcement)?);
+        }
+        Ok(())
+    }
+
+    pub fn is_discoverable(&self) -> bool {
+        self.responder.is_some()
+    }
+
+    /// Withdraws the announcement.
+    pub fn shutdown(&mut self) {
+        if let Some(responder) = self.responder.take() {
+            responder.shutdown();
+        }
+    }
+}
+
+impl Drop for NetworkInterface {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
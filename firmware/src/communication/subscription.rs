@@ -0,0 +1,250 @@
+//! Per-client topic subscriptions and rate limiting.
+//!
+//! Every connected client gets a [`ClientSubscription`] between the firmware
+//! broadcast channel and its socket. The subscription decides, per message,
+//! whether the client wants the topic at all and whether its rate allows a
+//! send now. A message arriving too early is held as the topic's pending
+//! message and replaced by anything newer, then sent when the interval
+//! elapses, so a throttled client always sees the latest state.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use tokio::sync::{broadcast, mpsc};
+use tokio::time::Instant;
+use tracing::debug;
+
+use protocol::{ProtocolMessage, SubscribeRequest, SubscriptionAck, Topic, TopicSubscription};
+
+/// Highest per-topic rate a client may request (Hz).
+pub const MAX_TOPIC_RATE_HZ: f32 = 50.0;
+
+#[derive(Debug)]
+struct TopicState {
+    /// Minimum spacing between sends, `None` when unthrottled
+    interval: Option<Duration>,
+    last_sent: Option<Instant>,
+    pending: Option<ProtocolMessage>,
+}
+
+impl TopicState {
+    fn new(interval: Option<Duration>) -> Self {
+        Self {
+            interval,
+            last_sent: None,
+            pending: None,
+        }
+    }
+
+    fn due_at(&self) -> Option<Instant> {
+        match (self.interval, self.last_sent) {
+            (Some(interval), Some(last)) => Some(last + interval),
+            _ => None,
+        }
+    }
+}
+
+/// Topics and rates one client receives.
+#[derive(Debug)]
+pub struct ClientSubscription {
+    topics: HashMap<Topic, TopicState>,
+}
+
+impl ClientSubscription {
+    /// Every topic at full rate, the behaviour before a client subscribes.
+    pub fn all() -> Self {
+        Self {
+            topics: Topic::ALL.iter().map(|t| (*t, TopicState::new(None))).collect(),
+        }
+    }
+
+    /// Replaces the subscription and returns what was granted.
+    pub fn apply(&mut self, request: &SubscribeRequest) -> SubscriptionAck {
+        self.topics.clear();
+        let mut granted = Vec::with_capacity(request.topics.len());
+
+        for sub in &request.topics {
+            // Errors always go out as they happen
+            let rate_hz = match sub.topic {
+                Topic::Errors => None,
+                _ => sub
+                    .rate_hz
+                    .filter(|r| r.is_finite() && *r > 0.0)
+                    .map(|r| r.min(MAX_TOPIC_RATE_HZ)),
+            };
+            let interval = rate_hz.map(|r| Duration::from_secs_f32(1.0 / r));
+            self.topics.insert(sub.topic, TopicState::new(interval));
+            granted.push(TopicSubscription { topic: sub.topic, rate_hz });
+        }
+
+        debug!("Client subscribed to {:?}", granted);
+        SubscriptionAck { topics: granted }
+    }
+
+    pub fn is_subscribed(&self, topic: Topic) -> bool {
+        self.topics.contains_key(&topic)
+    }
+
+    /// Returns the message if it should be sent now. Messages of an
+    /// unsubscribed topic are dropped; early ones are held until due.
+    pub fn offer(&mut self, msg: ProtocolMessage, now: Instant) -> Option<ProtocolMessage> {
+        let Some(topic) = Topic::of(&msg) else {
+            return Some(msg);
+        };
+        let state = self.topics.get_mut(&topic)?;
+
+        match state.due_at() {
+            Some(due) if now < due => {
+                state.pending = Some(msg);
+                None
+            }
+            _ => {
+                state.last_sent = Some(now);
+                state.pending = None;
+                Some(msg)
+            }
+        }
+    }
+
+    /// Earliest time a held message becomes due.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.topics
+            .values()
+            .filter(|s| s.pending.is_some())
+            .filter_map(TopicState::due_at)
+            .min()
+    }
+
+    /// Takes every held message that is due at `now`.
+    pub fn take_due(&mut self, now: Instant) -> Vec<ProtocolMessage> {
+        let mut due = Vec::new();
+        for state in self.topics.values_mut() {
+            if state.pending.is_some() && !matches!(state.due_at(), Some(at) if at > now) {
+                state.last_sent = Some(now);
+                due.extend(state.pending.take());
+            }
+        }
+        due
+    }
+
+    /// Forwards broadcasts to one client until either side closes.
+    ///
+    /// `requests` carries the client's `Subscribe` messages; each one is
+    /// answered with a `SubscriptionAck` on `out`.
+    pub async fn forward(
+        mut self,
+        mut broadcasts: broadcast::Receiver<ProtocolMessage>,
+        mut requests: mpsc::Receiver<SubscribeRequest>,
+        out: mpsc::Sender<ProtocolMessage>,
+    ) {
+        loop {
+            let deadline = self.next_deadline();
+            let mut outgoing = Vec::new();
+
+            tokio::select! {
+                msg = broadcasts.recv() => match msg {
+                    Ok(msg) => outgoing.extend(self.offer(msg, Instant::now())),
+                    Err(broadcast::error::RecvError::Lagged(n)) => {
+                        debug!("Client subscription skipped {} messages", n);
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return,
+                },
+                request = requests.recv() => match request {
+                    Some(request) => {
+                        outgoing.push(ProtocolMessage::SubscriptionAck(self.apply(&request)));
+                    }
+                    None => return,
+                },
+                _ = sleep_until(deadline), if deadline.is_some() => {
+                    outgoing = self.take_due(Instant::now());
+                }
+            }
+
+            for msg in outgoing {
+                if out.send(msg).await.is_err() {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+impl Default for ClientSubscription {
+    fn default() -> Self {
+        Self::all()
+    }
+}
+
+async fn sleep_until(deadline: Option<Instant>) {
+    if let Some(deadline) = deadline {
+        tokio::time::sleep_until(deadline).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use protocol::{create_error_event, create_status_update, create_thermal_update, ErrorSeverity};
+
+    fn status(layer: u32) -> ProtocolMessage {
+        create_status_update("Printing", layer, 100, 0.2 * layer as f32, 0, 0)
+    }
+
+    fn layer_of(msg: &ProtocolMessage) -> u32 {
+        match msg {
+            ProtocolMessage::StatusUpdate(s) => s.current_layer,
+            other => panic!("unexpected {}", other.message_type()),
+        }
+    }
+
+    #[test]
+    fn test_rate_limit_delivers_latest() {
+        let mut sub = ClientSubscription::all();
+        let ack = sub.apply(&SubscribeRequest {
+            topics: vec![
+                TopicSubscription { topic: Topic::Status, rate_hz: Some(1.0) },
+                TopicSubscription { topic: Topic::Errors, rate_hz: Some(1.0) },
+            ],
+        });
+        assert_eq!(ack.topics[1].rate_hz, None);
+
+        let t0 = Instant::now();
+        assert_eq!(layer_of(&sub.offer(status(1), t0).unwrap()), 1);
+        assert!(sub.offer(status(2), t0 + Duration::from_millis(300)).is_none());
+        assert!(sub.offer(status(3), t0 + Duration::from_millis(600)).is_none());
+        assert_eq!(sub.next_deadline(), Some(t0 + Duration::from_secs(1)));
+
+        assert!(sub.take_due(t0 + Duration::from_millis(900)).is_empty());
+        let due = sub.take_due(t0 + Duration::from_secs(1));
+        assert_eq!(due.len(), 1);
+        assert_eq!(layer_of(&due[0]), 3);
+
+        // Unsubscribed topics are dropped, errors pass immediately
+        assert!(sub.offer(create_thermal_update(vec![(0, 200.0, 210.0)]), t0).is_none());
+        let error = create_error_event(ErrorSeverity::Warning, "W1", "warn");
+        assert!(sub.offer(error.clone(), t0).is_some());
+        assert!(sub.offer(error, t0).is_some());
+    }
+
+    #[tokio::test]
+    async fn test_forward_applies_subscription() {
+        let (tx, rx) = broadcast::channel(16);
+        let (req_tx, req_rx) = mpsc::channel(4);
+        let (out_tx, mut out_rx) = mpsc::channel(16);
+        tokio::spawn(ClientSubscription::all().forward(rx, req_rx, out_tx));
+
+        req_tx
+            .send(SubscribeRequest {
+                topics: vec![TopicSubscription { topic: Topic::Status, rate_hz: Some(20.0) }],
+            })
+            .await
+            .unwrap();
+        assert!(matches!(out_rx.recv().await, Some(ProtocolMessage::SubscriptionAck(_))));
+
+        tx.send(status(1)).unwrap();
+        tx.send(status(2)).unwrap();
+        assert_eq!(layer_of(&out_rx.recv().await.unwrap()), 1);
+        // Held message follows after the 50ms interval
+        assert_eq!(layer_of(&out_rx.recv().await.unwrap()), 2);
+    }
+}
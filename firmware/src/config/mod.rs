@@ -6,10 +6,13 @@
 //!
 //! - **machine**: Machine configuration loading
 //! - **validation**: Configuration validation
+//! - **watcher**: Configuration hot-reload
 
 pub mod machine;
 pub mod validation;
+pub mod watcher;
 
 pub use machine::MachineConfig;
 pub use validation::ConfigValidator;
+pub use watcher::ConfigWatcher;
 
@@ -0,0 +1,487 @@
+//! Printer configuration hot-reload.
+//!
+//! The watcher follows the printer TOML file through inotify, re-validates it
+//! on every change and applies the parameters that are safe to change while
+//! the machine is running (PID gains and safety thresholds that stay within
+//! hardware limits). Anything that would require re-initializing hardware is
+//! rejected and reported to clients with a `ConfigChanged` notification.
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use serde::Serialize;
+use tokio::sync::{broadcast, mpsc, RwLock};
+use tracing::{debug, info, warn};
+
+use config_types::{Celsius, PrinterConfig, Psi, SafetyLimits};
+use protocol::{ConfigChangeNotification, ProtocolMessage};
+
+/// Quiet period used to coalesce editor save bursts into one reload.
+pub const RELOAD_DEBOUNCE: Duration = Duration::from_millis(250);
+
+/// Result of comparing a reloaded configuration with the active one.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ConfigDiff {
+    /// Parameters applied without restart
+    pub applied: Vec<String>,
+
+    /// Parameters that changed but need a firmware restart
+    pub requires_restart: Vec<String>,
+
+    /// Live parameters rejected because they violate limits
+    pub rejected: Vec<String>,
+}
+
+impl ConfigDiff {
+    pub fn is_empty(&self) -> bool {
+        self.applied.is_empty() && self.requires_restart.is_empty() && self.rejected.is_empty()
+    }
+}
+
+/// Watches the printer configuration file and applies live-safe changes.
+pub struct ConfigWatcher {
+    path: PathBuf,
+    active: Arc<RwLock<PrinterConfig>>,
+    status_tx: broadcast::Sender<ProtocolMessage>,
+}
+
+impl ConfigWatcher {
+    pub fn new(
+        path: impl AsRef<Path>,
+        active: Arc<RwLock<PrinterConfig>>,
+        status_tx: broadcast::Sender<ProtocolMessage>,
+    ) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+            active,
+            status_tx,
+        }
+    }
+
+    /// Runs the watch loop until a shutdown signal is received.
+    pub async fn run(self, mut shutdown: broadcast::Receiver<()>) -> Result<()> {
+        let (event_tx, mut event_rx) = mpsc::unbounded_channel();
+
+        let mut watcher: RecommendedWatcher = notify::recommended_watcher(
+            move |res: notify::Result<notify::Event>| {
+                if let Ok(event) = res {
+                    if matches!(event.kind, EventKind::Modify(_) | EventKind::Create(_)) {
+                        event_tx.send(()).ok();
+                    }
+                }
+            },
+        )
+        .context("Failed to create config watcher")?;
+
+        // Watch the directory so editors that replace the file via rename
+        // keep triggering events.
+        let watch_dir = self.path.parent().unwrap_or_else(|| Path::new("."));
+        watcher
+            .watch(watch_dir, RecursiveMode::NonRecursive)
+            .with_context(|| format!("Failed to watch {}", watch_dir.display()))?;
+
+        info!("Watching {} for configuration changes", self.path.display());
+
+        loop {
+            tokio::select! {
+                Some(()) = event_rx.recv() => {
+                    // Debounce: drain events until the file has been quiet
+                    while tokio::time::timeout(RELOAD_DEBOUNCE, event_rx.recv()).await.is_ok() {}
+
+                    if let Err(e) = self.reload().await {
+                        warn!("Configuration reload failed: {:#}", e);
+                        self.notify(ConfigChangeNotification {
+                            accepted: false,
+                            applied: Vec::new(),
+                            requires_restart: Vec::new(),
+                            message: format!("Reload failed: {:#}", e),
+                        });
+                    }
+                }
+                _ = shutdown.recv() => break,
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Reloads the file, applies live-safe changes and notifies clients.
+    pub async fn reload(&self) -> Result<ConfigDiff> {
+        let candidate = PrinterConfig::from_file(&self.path)
+            .context("Failed to parse printer configuration")?;
+        candidate
+            .validate()
+            .context("Reloaded configuration failed validation")?;
+
+        let mut active = self.active.write().await;
+        let (merged, diff) = apply_live_changes(&active, &candidate);
+
+        if diff.is_empty() {
+            debug!("Configuration file changed but no parameters differ");
+            return Ok(diff);
+        }
+
+        *active = merged;
+        drop(active);
+
+        for param in &diff.applied {
+            info!("Applied live configuration change: {}", param);
+        }
+        for param in diff.requires_restart.iter().chain(&diff.rejected) {
+            warn!("Configuration change not applied: {}", param);
+        }
+
+        let accepted = diff.requires_restart.is_empty() && diff.rejected.is_empty();
+        let message = if accepted {
+            format!("Applied {} configuration change(s)", diff.applied.len())
+        } else {
+            format!(
+                "Applied {} change(s); {} require a firmware restart, {} rejected",
+                diff.applied.len(),
+                diff.requires_restart.len(),
+                diff.rejected.len()
+            )
+        };
+
+        self.notify(ConfigChangeNotification {
+            accepted,
+            applied: diff.applied.clone(),
+            requires_restart: diff
+                .requires_restart
+                .iter()
+                .chain(&diff.rejected)
+                .cloned()
+                .collect(),
+            message,
+        });
+
+        Ok(diff)
+    }
+
+    fn notify(&self, notification: ConfigChangeNotification) {
+        // No subscribers is not an error
+        self.status_tx
+            .send(ProtocolMessage::ConfigChanged(notification))
+            .ok();
+    }
+}
+
+/// Merges live-safe parameters from `candidate` into a copy of `active`.
+///
+/// Returns the merged configuration alongside a description of what was
+/// applied, what needs a restart, and what was rejected for violating limits.
+pub fn apply_live_changes(
+    active: &PrinterConfig,
+    candidate: &PrinterConfig,
+) -> (PrinterConfig, ConfigDiff) {
+    let mut merged = active.clone();
+    let mut diff = ConfigDiff::default();
+
+    // Sections that are baked into hardware initialization
+    for (name, changed) in [
+        ("model", section_changed(&active.model, &candidate.model)),
+        ("build_volume", section_changed(&active.build_volume, &candidate.build_volume)),
+        ("valve_array", section_changed(&active.valve_array, &candidate.valve_array)),
+        ("materials", section_changed(&active.materials, &candidate.materials)),
+        ("motion", section_changed(&active.motion, &candidate.motion)),
+        ("sensors", section_changed(&active.sensors, &candidate.sensors)),
+    ] {
+        if changed {
+            diff.requires_restart.push(name.to_string());
+        }
+    }
+
+    // Thermal: PID gains are live, everything else needs a restart
+    if active.thermal.zones.len() != candidate.thermal.zones.len() {
+        diff.requires_restart.push("thermal.zones".to_string());
+    } else {
+        for (i, (old, new)) in active
+            .thermal
+            .zones
+            .iter()
+            .zip(&candidate.thermal.zones)
+            .enumerate()
+        {
+            let mut without_pid = new.clone();
+            without_pid.pid = old.pid;
+            if section_changed(old, &without_pid) {
+                diff.requires_restart.push(format!("thermal.zones[{}]", old.id));
+                continue;
+            }
+            if section_changed(&old.pid, &new.pid) {
+                merged.thermal.zones[i].pid = new.pid;
+                diff.applied.push(format!("thermal.zones[{}].pid", old.id));
+            }
+        }
+    }
+
+    match (&active.thermal.manifold, &candidate.thermal.manifold) {
+        (Some(old), Some(new)) => {
+            let mut without_pid = new.clone();
+            without_pid.pid = old.pid;
+            if section_changed(old, &without_pid) {
+                diff.requires_restart.push("thermal.manifold".to_string());
+            } else if section_changed(&old.pid, &new.pid) {
+                if let Some(m) = merged.thermal.manifold.as_mut() {
+                    m.pid = new.pid;
+                }
+                diff.applied.push("thermal.manifold.pid".to_string());
+            }
+        }
+        (None, None) => {}
+        _ => diff.requires_restart.push("thermal.manifold".to_string()),
+    }
+
+    if section_changed(&active.thermal.chamber, &candidate.thermal.chamber) {
+        diff.requires_restart.push("thermal.chamber".to_string());
+    }
+
+    // Interlock inputs are opened at startup
+    if section_changed(&active.safety.interlocks, &candidate.safety.interlocks) {
+        diff.requires_restart.push("safety.interlocks".to_string());
+    }
+    let mut safety = candidate.safety.clone();
+    safety.interlocks = active.safety.interlocks.clone();
+
+    // Safety thresholds are live as long as they stay within hardware limits
+    if section_changed(&active.safety, &safety) {
+        match check_safety_limits(&safety, active) {
+            Ok(()) => {
+                merged.safety = safety;
+                diff.applied.push("safety".to_string());
+            }
+            Err(reason) => diff.rejected.push(format!("safety ({})", reason)),
+        }
+    }
+
+    // Metadata is informational only
+    if section_changed(&active.metadata, &candidate.metadata) {
+        merged.metadata = candidate.metadata.clone();
+        diff.applied.push("metadata".to_string());
+    }
+
+    (merged, diff)
+}
+
+/// Checks that new safety limits do not exceed what the hardware supports.
+fn check_safety_limits(limits: &SafetyLimits, hardware: &PrinterConfig) -> Result<(), String> {
+    let values = [
+        ("max_temperature", limits.max_temperature.get()),
+        ("max_pressure", limits.max_pressure.get()),
+        ("max_valve_rate", limits.max_valve_rate),
+        ("max_z_speed", limits.max_z_speed),
+        ("thermal_runaway_rate", limits.thermal_runaway_rate),
+        ("pressure_fault_threshold", limits.pressure_fault_threshold),
+    ];
+    for (name, value) in values {
+        if !value.is_finite() || value <= 0.0 {
+            return Err(format!("{} must be positive", name));
+        }
+    }
+
+    let hardware_max_temp = hardware
+        .thermal
+        .zones
+        .iter()
+        .map(|z| z.max_temp)
+        .fold(f32::MIN, f32::max);
+    if !hardware.thermal.zones.is_empty() && limits.max_temperature.get() > hardware_max_temp {
+        return Err(format!(
+            "max_temperature {} exceeds hardware maximum {}",
+            limits.max_temperature,
+            Celsius::new(hardware_max_temp)
+        ));
+    }
+
+    if limits.max_pressure.get() > hardware.materials.pressure.max_pressure {
+        return Err(format!(
+            "max_pressure {} exceeds pressure system maximum {}",
+            limits.max_pressure,
+            Psi::new(hardware.materials.pressure.max_pressure)
+        ));
+    }
+
+    if limits.max_valve_rate > hardware.valve_array.max_switching_freq {
+        return Err(format!(
+            "max_valve_rate {} exceeds valve switching limit {}",
+            limits.max_valve_rate, hardware.valve_array.max_switching_freq
+        ));
+    }
+
+    if limits.max_z_speed > hardware.motion.z_axis.max_speed {
+        return Err(format!(
+            "max_z_speed {} exceeds Z-axis maximum {}",
+            limits.max_z_speed, hardware.motion.z_axis.max_speed
+        ));
+    }
+
+    Ok(())
+}
+
+/// Compares two config sections through their serialized form.
+fn section_changed<T: Serialize>(a: &T, b: &T) -> bool {
+    serde_json::to_value(a).ok() != serde_json::to_value(b).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use config_types::*;
+
+    fn printer() -> PrinterConfig {
+        PrinterConfig {
+            model: PrinterModel::HyperCubeMini,
+            build_volume: BuildVolume::new(100.0, 100.0, 150.0),
+            valve_array: ValveArrayConfig {
+                grid_spacing: 0.5,
+                total_nodes: 40000,
+                valves_per_node: 4,
+                valve_type: ValveType::PneumaticSolenoid,
+                response_time_ms: 10.0,
+                dead_volume: CubicMm::new(0.5),
+                max_switching_freq: 30.0,
+                injection_points: vec![],
+                verification: ValveVerificationConfig::default(),
+                drivers: ValveDriverConfig::default(),
+                sub_frames: SubFrameConfig::default(),
+                frame_merge_window_ms: 0.0,
+            },
+            thermal: ThermalConfig {
+                zones: vec![ThermalZone {
+                    id: 0,
+                    name: "manifold".to_string(),
+                    min_temp: 20.0,
+                    max_temp: 280.0,
+                    power_watts: 40.0,
+                    pid: PidParameters::default(),
+                    heater_pin: None,
+                    regions: vec![],
+                    thermal_mass: None,
+                }],
+                manifold: None,
+                chamber: None,
+                bed: None,
+                channel_zones: vec![],
+                supply_watts: None,
+            },
+            materials: MaterialSystemConfig {
+                channel_count: 1,
+                isolated_channels: false,
+                extruders: vec![],
+                pressure: PressureConfig {
+                    min_pressure: 20.0,
+                    max_pressure: 100.0,
+                    regulation_type: PressureRegulationType::Pneumatic,
+                    sensors: vec![],
+                    channels: vec![],
+                },
+                runout_sensors: vec![],
+                compatibility: CompatibilityMatrix::default(),
+            },
+            motion: MotionConfig {
+                z_axis: ZAxisConfig {
+                    lead_screw_pitch: 2.0,
+                    screw_count: 1,
+                    steps_per_mm: 400.0,
+                    max_speed: 15.0,
+                    max_acceleration: 100.0,
+                    driver: StepperDriverConfig::default(),
+                },
+                homing: HomingConfig {
+                    homing_speed: 5.0,
+                    home_to_max: false,
+                    home_at_startup: true,
+                },
+            },
+            safety: SafetyLimits {
+                max_temperature: Celsius::new(260.0),
+                max_pressure: Psi::new(90.0),
+                max_valve_rate: 20.0,
+                max_z_speed: 10.0,
+                thermal_runaway_rate: 10.0,
+                pressure_fault_threshold: 10.0,
+                barrier_timeout_ms: 60_000,
+                barrier_timeout_action: BarrierTimeoutAction::Abort,
+                interlocks: Vec::new(),
+                heartbeat: HeartbeatConfig::default(),
+                disconnect_action: DisconnectAction::Continue,
+            },
+            metadata: PrinterMetadata {
+                serial_number: None,
+                firmware_version: None,
+                last_calibration: None,
+                notes: None,
+            },
+            sensors: Vec::new(),
+            power_loss: None,
+            bed_level: None,
+            camera: None,
+            host_telemetry: HostTelemetryConfig::default(),
+            macros: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_live_changes_applied_and_hardware_changes_held() {
+        let active = printer();
+        let mut candidate = printer();
+        candidate.thermal.zones[0].pid.kp = 35.0;
+        candidate.metadata.notes = Some("Retuned after nozzle swap".to_string());
+        candidate.motion.z_axis.max_speed = 20.0;
+        candidate.valve_array.response_time_ms = 5.0;
+
+        let (merged, diff) = apply_live_changes(&active, &candidate);
+        assert_eq!(diff.applied, vec!["thermal.zones[0].pid", "metadata"]);
+        assert_eq!(diff.requires_restart, vec!["valve_array", "motion"]);
+        assert!(diff.rejected.is_empty());
+        assert_eq!(merged.thermal.zones[0].pid.kp, 35.0);
+        assert_eq!(merged.metadata.notes, candidate.metadata.notes);
+        // Restart-only sections keep their running values
+        assert_eq!(merged.motion.z_axis.max_speed, 15.0);
+        assert_eq!(merged.valve_array.response_time_ms, 10.0);
+
+        // A zone whose heater changed holds its new gains back as well
+        let mut candidate = printer();
+        candidate.thermal.zones[0].pid.kp = 35.0;
+        candidate.thermal.zones[0].power_watts = 60.0;
+        let (merged, diff) = apply_live_changes(&active, &candidate);
+        assert_eq!(diff.requires_restart, vec!["thermal.zones[0]"]);
+        assert!(diff.applied.is_empty());
+        assert_eq!(merged.thermal.zones[0].pid.kp, PidParameters::default().kp);
+
+        assert!(apply_live_changes(&active, &printer()).1.is_empty());
+    }
+
+    #[test]
+    fn test_safety_limits_checked_against_hardware() {
+        let active = printer();
+        let mut candidate = printer();
+        candidate.safety.max_z_speed = 12.0;
+        let (merged, diff) = apply_live_changes(&active, &candidate);
+        assert_eq!(diff.applied, vec!["safety"]);
+        assert_eq!(merged.safety.max_z_speed, 12.0);
+
+        let cases: [(&str, fn(&mut SafetyLimits)); 6] = [
+            ("max_temperature", |s| s.max_temperature = Celsius::new(300.0)),
+            ("max_pressure", |s| s.max_pressure = Psi::new(150.0)),
+            ("max_valve_rate", |s| s.max_valve_rate = 40.0),
+            ("max_z_speed", |s| s.max_z_speed = 25.0),
+            ("thermal_runaway_rate", |s| s.thermal_runaway_rate = -1.0),
+            ("pressure_fault_threshold", |s| s.pressure_fault_threshold = f32::NAN),
+        ];
+        for (name, change) in cases {
+            let mut candidate = printer();
+            change(&mut candidate.safety);
+            let reason = check_safety_limits(&candidate.safety, &active).unwrap_err();
+            assert!(reason.starts_with(name), "{}: {}", name, reason);
+
+            let (merged, diff) = apply_live_changes(&active, &candidate);
+            assert!(diff.applied.is_empty(), "{}", name);
+            assert_eq!(diff.rejected, vec![format!("safety ({})", reason)]);
+            assert_eq!(merged.safety.max_z_speed, 10.0);
+        }
+    }
+}
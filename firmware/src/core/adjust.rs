@@ -0,0 +1,321 @@
+//! Live parameter adjustment during a print.
+//!
+//! `AdjustParameter` commands are resolved against the printer
+//! configuration and loaded materials into an [`Adjustment`]: units are
+//! converted and values clamped to what the hardware and material allow.
+//! The executor holds a [`LiveAdjuster`] with the active values:
+//!
+//! - **flow** scales the `extrusion` of every G4D before it is compiled
+//! - **speed** stretches the scheduler tick, slowing frames and waits alike
+//! - **temperature** and **pressure** set controller targets and take
+//!   precedence over targets later in the file
+//!
+//! Adjustments marked [`AdjustTiming::NextLayer`] queue until the executor
+//! reaches a layer boundary and then apply together, so a layer never runs
+//! with half of a set of changes.
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use anyhow::Result;
+
+use config_types::PrinterConfig;
+use gcode_types::Command;
+use protocol::{AdjustParameterCommand, AdjustTiming, AdjustableParameter};
+
+use super::MaterialRegistry;
+use crate::FirmwareError;
+
+/// Flow multipliers accepted mid-print.
+pub const FLOW_FACTOR_RANGE: (f32, f32) = (0.5, 1.5);
+
+/// Speed multipliers accepted mid-print. Layers are compiled as tight as
+/// valve response allows, so speed can only slow execution down.
+pub const SPEED_FACTOR_RANGE: (f32, f32) = (0.25, 1.0);
+
+const PSI_PER_BAR: f32 = 14.5038;
+
+/// A validated change to one parameter.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Adjustment {
+    Flow(f32),
+    Speed(f32),
+    Temperature { zone: u8, target: f32 },
+    Pressure { channel: u8, target: f32 },
+}
+
+impl Adjustment {
+    /// Converts and clamps a command. Returns the adjustment and, if the
+    /// value had to be clamped, a note saying so.
+    pub fn resolve(
+        cmd: &AdjustParameterCommand,
+        config: &PrinterConfig,
+        materials: &MaterialRegistry,
+    ) -> Result<(Self, Option<String>)> {
+        let unit = cmd.unit.trim().to_ascii_lowercase();
+        match cmd.parameter {
+            AdjustableParameter::FlowRate | AdjustableParameter::Speed => Self::resolve_factor(cmd),
+            AdjustableParameter::Temperature => {
+                let zone = cmd
+                    .channel_or_zone
+                    .ok_or_else(|| invalid("Temperature adjustment needs a zone".to_string()))?;
+                let limits = config
+                    .thermal
+                    .zone(zone)
+                    .ok_or_else(|| invalid(format!("No thermal zone {}", zone)))?;
+                check_finite(cmd)?;
+                let celsius = match unit.as_str() {
+                    "" | "c" | "°c" | "celsius" => cmd.value,
+                    "f" | "°f" | "fahrenheit" => (cmd.value - 32.0) / 1.8,
+                    other => return Err(invalid(format!("Unknown temperature unit '{}'", other))),
+                };
+
+                // Narrow the zone's limits to every material flowing through it
+                let (mut low, mut high) = (limits.min_temp, limits.max_temp);
+                for mapping in config.thermal.channel_zones.iter().filter(|m| m.zones.contains(&zone)) {
+                    if let Some(profile) = materials.get(mapping.channel) {
+                        low = low.max(profile.temp_range.0);
+                        high = high.min(profile.temp_range.1);
+                    }
+                }
+                if low > high {
+                    return Err(invalid(format!(
+                        "Materials through zone {} have no common temperature range",
+                        zone
+                    )));
+                }
+                let (target, note) = clamp("temperature", celsius, low, high);
+                Ok((Adjustment::Temperature { zone, target }, note))
+            }
+            AdjustableParameter::Pressure => {
+                let channel = cmd
+                    .channel_or_zone
+                    .ok_or_else(|| invalid("Pressure adjustment needs a channel".to_string()))?;
+                if channel >= config.materials.channel_count {
+                    return Err(invalid(format!("No material channel {}", channel)));
+                }
+                check_finite(cmd)?;
+                let psi = match unit.as_str() {
+                    "" | "psi" => cmd.value,
+                    "bar" => cmd.value * PSI_PER_BAR,
+                    "kpa" => cmd.value * PSI_PER_BAR / 100.0,
+                    other => return Err(invalid(format!("Unknown pressure unit '{}'", other))),
+                };
+                let limits = &config.materials.pressure;
+                let (target, note) = clamp("pressure", psi, limits.min_pressure, limits.max_pressure);
+                Ok((Adjustment::Pressure { channel, target }, note))
+            }
+        }
+    }
+
+    /// Flow and speed: printer-wide multipliers given as "%" or a factor.
+    fn resolve_factor(cmd: &AdjustParameterCommand) -> Result<(Self, Option<String>)> {
+        check_finite(cmd)?;
+        if cmd.channel_or_zone.is_some() {
+            return Err(invalid(format!("{:?} applies to all channels", cmd.parameter)));
+        }
+        let factor = match cmd.unit.trim().to_ascii_lowercase().as_str() {
+            "%" | "percent" => cmd.value / 100.0,
+            "" | "x" | "factor" => cmd.value,
+            other => return Err(invalid(format!("Unknown {:?} unit '{}'", cmd.parameter, other))),
+        };
+        Ok(match cmd.parameter {
+            AdjustableParameter::FlowRate => {
+                let (value, note) = clamp("flow", factor, FLOW_FACTOR_RANGE.0, FLOW_FACTOR_RANGE.1);
+                (Adjustment::Flow(value), note)
+            }
+            _ => {
+                let (value, note) = clamp("speed", factor, SPEED_FACTOR_RANGE.0, SPEED_FACTOR_RANGE.1);
+                (Adjustment::Speed(value), note)
+            }
+        })
+    }
+}
+
+fn invalid(message: String) -> anyhow::Error {
+    FirmwareError::InvalidCommand(message).into()
+}
+
+fn check_finite(cmd: &AdjustParameterCommand) -> Result<()> {
+    if cmd.value.is_finite() {
+        Ok(())
+    } else {
+        Err(invalid(format!("{:?} value {} is not a number", cmd.parameter, cmd.value)))
+    }
+}
+
+fn clamp(what: &str, value: f32, low: f32, high: f32) -> (f32, Option<String>) {
+    let clamped = value.clamp(low, high);
+    let note = (clamped != value)
+        .then(|| format!("{} {:.2} clamped to {:.2} (range {:.2}-{:.2})", what, value, clamped, low, high));
+    (clamped, note)
+}
+
+/// Values currently in force.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ActiveAdjustments {
+    pub flow: f32,
+    pub speed: f32,
+    /// Zone targets overriding the file's
+    pub temperatures: BTreeMap<u8, f32>,
+    /// Channel targets overriding the file's
+    pub pressures: BTreeMap<u8, f32>,
+}
+
+impl Default for ActiveAdjustments {
+    fn default() -> Self {
+        Self {
+            flow: 1.0,
+            speed: 1.0,
+            temperatures: BTreeMap::new(),
+            pressures: BTreeMap::new(),
+        }
+    }
+}
+
+/// Active and queued adjustments of the running job.
+#[derive(Debug, Clone, Default)]
+pub struct LiveAdjuster {
+    active: ActiveAdjustments,
+    pending: Vec<Adjustment>,
+}
+
+impl LiveAdjuster {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn active(&self) -> &ActiveAdjustments {
+        &self.active
+    }
+
+    pub fn pending(&self) -> &[Adjustment] {
+        &self.pending
+    }
+
+    /// Queues an adjustment or applies it at once. Returns the adjustments
+    /// that took effect and need pushing to the controllers.
+    pub fn submit(&mut self, adjustment: Adjustment, timing: AdjustTiming) -> Vec<Adjustment> {
+        match timing {
+            AdjustTiming::Immediate => {
+                self.apply(adjustment);
+                vec![adjustment]
+            }
+            AdjustTiming::NextLayer => {
+                self.pending.push(adjustment);
+                Vec::new()
+            }
+        }
+    }
+
+    /// Applies every queued adjustment, in the order received, and returns
+    /// them. Called by the executor between layers.
+    pub fn at_layer_boundary(&mut self) -> Vec<Adjustment> {
+        let applied = std::mem::take(&mut self.pending);
+        for &adjustment in &applied {
+            self.apply(adjustment);
+        }
+        applied
+    }
+
+    /// Drops everything; a new job starts from the file's values.
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Target for a zone: the live override if there is one.
+    pub fn temperature_for(&self, zone: u8, file_target: f32) -> f32 {
+        self.active.temperatures.get(&zone).copied().unwrap_or(file_target)
+    }
+
+    /// Target for a channel: the live override if there is one.
+    pub fn pressure_for(&self, channel: u8, file_target: f32) -> f32 {
+        self.active.pressures.get(&channel).copied().unwrap_or(file_target)
+    }
+
+    /// Scales the deposited volume of every G4D by the flow factor.
+    pub fn scale_extrusion(&self, commands: &mut [Command]) {
+        if self.active.flow == 1.0 {
+            return;
+        }
+        for cmd in commands {
+            if let Command::G4D(g4d) = cmd {
+                if let Some(extrusion) = g4d.extrusion.as_mut() {
+                    *extrusion *= self.active.flow;
+                }
+            }
+        }
+    }
+
+    /// Scheduler tick stretched by the speed factor.
+    pub fn tick_interval(&self, base: Duration) -> Duration {
+        base.div_f32(self.active.speed)
+    }
+
+    fn apply(&mut self, adjustment: Adjustment) {
+        match adjustment {
+            Adjustment::Flow(factor) => self.active.flow = factor,
+            Adjustment::Speed(factor) => self.active.speed = factor,
+            Adjustment::Temperature { zone, target } => {
+                self.active.temperatures.insert(zone, target);
+            }
+            Adjustment::Pressure { channel, target } => {
+                self.active.pressures.insert(channel, target);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gcode_types::{Coordinate, G4DCommand, ValveState};
+
+    fn command(parameter: AdjustableParameter, value: f32, unit: &str) -> AdjustParameterCommand {
+        AdjustParameterCommand {
+            parameter,
+            channel_or_zone: None,
+            value,
+            unit: unit.to_string(),
+            apply: AdjustTiming::NextLayer,
+        }
+    }
+
+    #[test]
+    fn test_factors_apply_at_layer_boundary() {
+        let (flow, note) = Adjustment::resolve_factor(&command(AdjustableParameter::FlowRate, 110.0, "%")).unwrap();
+        assert!(matches!(flow, Adjustment::Flow(f) if (f - 1.1).abs() < 1e-6));
+        assert!(note.is_none());
+        let (slow, note) = Adjustment::resolve_factor(&command(AdjustableParameter::Speed, 2.0, "")).unwrap();
+        assert_eq!(slow, Adjustment::Speed(1.0));
+        assert!(note.unwrap().contains("clamped"));
+        assert!(Adjustment::resolve_factor(&command(AdjustableParameter::Speed, 50.0, "mm/s")).is_err());
+
+        let mut adjuster = LiveAdjuster::new();
+        let hot = Adjustment::Temperature { zone: 2, target: 215.0 };
+        assert!(adjuster.submit(flow, AdjustTiming::NextLayer).is_empty());
+        assert_eq!(adjuster.submit(hot, AdjustTiming::Immediate), vec![hot]);
+        assert_eq!(adjuster.active().flow, 1.0);
+        assert_eq!(adjuster.temperature_for(2, 200.0), 215.0);
+        assert_eq!(adjuster.temperature_for(3, 200.0), 200.0);
+        let pressure = Adjustment::Pressure { channel: 1, target: 45.0 };
+        adjuster.submit(pressure, AdjustTiming::Immediate);
+        assert_eq!(adjuster.pressure_for(1, 40.0), 45.0);
+        assert_eq!(adjuster.pressure_for(0, 40.0), 40.0);
+
+        assert_eq!(adjuster.at_layer_boundary(), vec![flow]);
+        assert!(adjuster.pending().is_empty());
+        let mut commands = vec![Command::G4D(G4DCommand {
+            position: Coordinate::new(1.0, 1.0, 0.2),
+            valves: vec![ValveState::open(0)],
+            extrusion: Some(2.0),
+            close_early_ms: None,
+        })];
+        adjuster.scale_extrusion(&mut commands);
+        let Command::G4D(g4d) = &commands[0] else { unreachable!() };
+        assert!((g4d.extrusion.unwrap() - 2.2).abs() < 1e-5);
+
+        adjuster.submit(Adjustment::Speed(0.5), AdjustTiming::Immediate);
+        assert_eq!(adjuster.tick_interval(Duration::from_millis(1)), Duration::from_millis(2));
+    }
+}
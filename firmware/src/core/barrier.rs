@@ -0,0 +1,277 @@
+//! G4W barrier release against live subsystem state.
+//!
+//! [`SubsystemBarrier`] is the executor's [`BarrierHandler`]. Each barrier
+//! polls the subsystem it names until the condition holds:
+//!
+//! - **Valves**: no valve update within the valve response time
+//! - **Pressure**: every channel within tolerance of its target
+//! - **Temperature**: every heater within tolerance of its target
+//! - **Duration**: a plain dwell, never timed out
+//! - **Inspection**: an operator hold ([`InspectionPause`]), never timed out
+//!
+//! A barrier gives up after its `timeout_ms`, or the configured default if
+//! the command sets none. Depending on
+//! [`SafetyLimits::barrier_timeout_action`](config_types::SafetyLimits) a
+//! timeout then either logs a warning and lets the layer continue, or fails
+//! with [`FirmwareError::Timeout`]. Every wait is recorded; the totals of a
+//! layer are taken with [`SubsystemBarrier::take_layer_waits`] and sent in
+//! its [`protocol::LayerTimingReport`] so the slicer can tune its estimates.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use tokio::sync::RwLock;
+use tokio::time::Instant;
+use tracing::warn;
+
+use config_types::{BarrierTimeoutAction, PrinterConfig};
+use gcode_types::{G4WCommand, WaitType};
+use protocol::BarrierWaitTiming;
+
+use super::inspection::InspectionPause;
+use super::scheduler::BarrierHandler;
+use super::state_machine::{PRESSURE_TOLERANCE, THERMAL_TOLERANCE};
+use crate::{FirmwareError, SystemState};
+
+/// Default interval between two checks of a barrier condition.
+pub const DEFAULT_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Barrier polling and timeout parameters.
+#[derive(Debug, Clone, Copy)]
+pub struct BarrierConfig {
+    /// Interval between two checks of the condition
+    pub poll_interval: Duration,
+
+    /// Timeout of barriers without their own
+    pub default_timeout: Duration,
+
+    /// Reaction to a timeout
+    pub on_timeout: BarrierTimeoutAction,
+
+    /// Time without valve updates after which valves count as settled
+    pub valve_settle_time: Duration,
+
+    /// Allowed temperature deviation (°C)
+    pub thermal_tolerance: f32,
+
+    /// Allowed pressure deviation (PSI)
+    pub pressure_tolerance: f32,
+}
+
+impl BarrierConfig {
+    pub fn from_config(config: &PrinterConfig) -> Self {
+        Self {
+            poll_interval: DEFAULT_POLL_INTERVAL,
+            default_timeout: Duration::from_millis(config.safety.barrier_timeout_ms as u64),
+            on_timeout: config.safety.barrier_timeout_action,
+            valve_settle_time: Duration::from_secs_f32(
+                config.valve_array.response_time_ms.max(0.0) / 1000.0,
+            ),
+            thermal_tolerance: THERMAL_TOLERANCE,
+            pressure_tolerance: PRESSURE_TOLERANCE,
+        }
+    }
+}
+
+/// Label of a wait type in [`BarrierWaitTiming::wait_type`].
+pub fn wait_label(wait_type: &WaitType) -> &'static str {
+    match wait_type {
+        WaitType::Valves => "valves",
+        WaitType::Pressure => "pressure",
+        WaitType::Temperature => "temperature",
+        WaitType::Duration(_) => "duration",
+        WaitType::Inspection => "inspection",
+    }
+}
+
+/// Releases barriers once the named subsystem reaches its condition.
+pub struct SubsystemBarrier {
+    state: Arc<RwLock<SystemState>>,
+    config: BarrierConfig,
+    inspection: Option<InspectionPause>,
+    layer_waits: BTreeMap<&'static str, BarrierWaitTiming>,
+}
+
+impl SubsystemBarrier {
+    pub fn new(state: Arc<RwLock<SystemState>>, config: BarrierConfig) -> Self {
+        Self {
+            state,
+            config,
+            inspection: None,
+            layer_waits: BTreeMap::new(),
+        }
+    }
+
+    /// Handles inspection barriers; without it they are skipped with a
+    /// warning.
+    pub fn with_inspection(mut self, inspection: InspectionPause) -> Self {
+        self.inspection = Some(inspection);
+        self
+    }
+
+    pub fn config(&self) -> &BarrierConfig {
+        &self.config
+    }
+
+    /// Wait totals since the last call, ordered by wait type. Called once
+    /// per layer.
+    pub fn take_layer_waits(&mut self) -> Vec<BarrierWaitTiming> {
+        std::mem::take(&mut self.layer_waits).into_values().collect()
+    }
+
+    fn timeout_for(&self, barrier: &G4WCommand) -> Duration {
+        barrier
+            .timeout_ms
+            .map(|ms| Duration::from_millis(ms as u64))
+            .unwrap_or(self.config.default_timeout)
+    }
+
+    async fn is_released(&self, wait_type: &WaitType) -> bool {
+        let mut state = self.state.write().await;
+        match wait_type {
+            WaitType::Valves => state.valves.last_update.elapsed() >= self.config.valve_settle_time,
+            WaitType::Pressure => state.pressure.check_stable(self.config.pressure_tolerance),
+            WaitType::Temperature => state.thermal.check_at_target(self.config.thermal_tolerance),
+            WaitType::Duration(_) | WaitType::Inspection => true,
+        }
+    }
+
+    fn record(&mut self, wait_type: &WaitType, waited: Duration, timed_out: bool) {
+        let label = wait_label(wait_type);
+        let entry = self.layer_waits.entry(label).or_insert_with(|| BarrierWaitTiming {
+            wait_type: label.to_string(),
+            count: 0,
+            total_ms: 0,
+            max_ms: 0,
+            timeouts: 0,
+        });
+        let ms = waited.as_millis() as u64;
+        entry.count += 1;
+        entry.total_ms += ms;
+        entry.max_ms = entry.max_ms.max(ms);
+        if timed_out {
+            entry.timeouts += 1;
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl BarrierHandler for SubsystemBarrier {
+    async fn wait(&mut self, barrier: &G4WCommand) -> Result<()> {
+        let start = Instant::now();
+
+        if let WaitType::Duration(ms) = barrier.wait_type {
+            tokio::time::sleep(Duration::from_millis(ms as u64)).await;
+            self.record(&barrier.wait_type, start.elapsed(), false);
+            return Ok(());
+        }
+        if barrier.wait_type == WaitType::Inspection {
+            match &self.inspection {
+                Some(inspection) => {
+                    let (layer, z) = {
+                        let state = self.state.read().await;
+                        (state.valves.current_layer, state.motion.z_position)
+                    };
+                    inspection.hold(layer, z).await;
+                }
+                None => warn!("No inspection handler; skipping inspection pause"),
+            }
+            self.record(&barrier.wait_type, start.elapsed(), false);
+            return Ok(());
+        }
+
+        let deadline = start + self.timeout_for(barrier);
+        loop {
+            if self.is_released(&barrier.wait_type).await {
+                self.record(&barrier.wait_type, start.elapsed(), false);
+                return Ok(());
+            }
+            if Instant::now() >= deadline {
+                break;
+            }
+            tokio::time::sleep_until((Instant::now() + self.config.poll_interval).min(deadline)).await;
+        }
+
+        let waited = start.elapsed();
+        self.record(&barrier.wait_type, waited, true);
+        let message = format!(
+            "G4W {} barrier not released after {} ms",
+            wait_label(&barrier.wait_type),
+            waited.as_millis()
+        );
+        match self.config.on_timeout {
+            BarrierTimeoutAction::Warn => {
+                warn!("{}; continuing", message);
+                self.state.write().await.warnings.push(message);
+                Ok(())
+            }
+            BarrierTimeoutAction::Abort => Err(FirmwareError::Timeout(message).into()),
+        }
+    }
+
+    async fn latched(&mut self) {
+        self.state.write().await.valves.last_update = std::time::Instant::now();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn barrier(on_timeout: BarrierTimeoutAction) -> (SubsystemBarrier, Arc<RwLock<SystemState>>) {
+        let mut state = SystemState::new();
+        state.pressure.channels.insert(0, (40.0, 50.0));
+        let state = Arc::new(RwLock::new(state));
+        let config = BarrierConfig {
+            poll_interval: Duration::from_millis(5),
+            default_timeout: Duration::from_millis(50),
+            on_timeout,
+            valve_settle_time: Duration::ZERO,
+            thermal_tolerance: THERMAL_TOLERANCE,
+            pressure_tolerance: PRESSURE_TOLERANCE,
+        };
+        (SubsystemBarrier::new(state.clone(), config), state)
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_pressure_barrier_release_and_timeout() {
+        let pressure = G4WCommand {
+            wait_type: WaitType::Pressure,
+            timeout_ms: None,
+        };
+
+        let (mut handler, state) = barrier(BarrierTimeoutAction::Abort);
+        let err = handler.wait(&pressure).await.unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(FirmwareError::Timeout(_))));
+
+        let (mut handler, state_warn) = barrier(BarrierTimeoutAction::Warn);
+        handler.wait(&pressure).await.unwrap();
+        assert_eq!(state_warn.read().await.warnings.len(), 1);
+
+        let setter = state.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            setter.write().await.pressure.channels.insert(0, (50.0, 50.0));
+        });
+        let mut handler = SubsystemBarrier::new(state, *handler.config());
+        handler.wait(&pressure).await.unwrap();
+        handler
+            .wait(&G4WCommand {
+                wait_type: WaitType::Duration(100),
+                timeout_ms: Some(1),
+            })
+            .await
+            .unwrap();
+
+        let waits = handler.take_layer_waits();
+        assert_eq!(waits.len(), 2);
+        assert_eq!(waits[0].wait_type, "duration");
+        assert_eq!(waits[0].total_ms, 100);
+        assert_eq!(waits[1].wait_type, "pressure");
+        assert_eq!(waits[1].timeouts, 0);
+        assert!((20..50).contains(&waits[1].total_ms));
+        assert!(handler.take_layer_waits().is_empty());
+    }
+}
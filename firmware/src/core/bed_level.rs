@@ -0,0 +1,251 @@
+//! Bed level measurement and first-layer compensation.
+//!
+//! A measurement session walks the probe points of `BedLevelConfig`, either
+//! taking operator-entered deviations or reading one gap sensor per point.
+//! The finished height map is stored in `PrinterConfig::bed_level`.
+//!
+//! Layers carry only valve states, so compensation acts on valve open time:
+//! on the first layers every open interval of a node is stretched or
+//! shortened by the mesh's extrusion factor at that node,
+//!
+//! ```text
+//! close' = open + round(factor · (close - open))
+//! ```
+//!
+//! never past the next opening of the same valve.
+
+use std::collections::{BTreeMap, HashMap};
+
+use anyhow::Result;
+
+use config_types::{BedLevelConfig, BedMesh, BedProbeMethod, PrinterConfig};
+use gcode_types::{GridCoordinate, ValveState};
+use protocol::BedLevelPoint;
+
+use super::scheduler::{CompiledLayer, ScheduleSegment, ValveFrame};
+use crate::{FirmwareError, SensorInterface};
+
+/// One bed level measurement run.
+#[derive(Debug, Clone)]
+pub struct BedLevelSession {
+    config: BedLevelConfig,
+    points: Vec<(f32, f32)>,
+    deviations: Vec<Option<f32>>,
+}
+
+impl BedLevelSession {
+    /// Starts a run over the configured probe points.
+    pub fn plan(config: &PrinterConfig) -> Result<Self> {
+        let bed_level = config.bed_level.clone().ok_or_else(|| {
+            FirmwareError::InvalidCommand("No bed level probing configured".to_string())
+        })?;
+        let points = bed_level.probe_points(&config.build_volume);
+        Ok(Self {
+            deviations: vec![None; points.len()],
+            config: bed_level,
+            points,
+        })
+    }
+
+    /// Records the deviation measured at one probe point.
+    pub fn record(&mut self, index: usize, deviation_mm: f32) -> Result<()> {
+        if !deviation_mm.is_finite() {
+            return Err(FirmwareError::InvalidCommand("Deviation must be finite".to_string()).into());
+        }
+        let slot = self.deviations.get_mut(index).ok_or_else(|| {
+            FirmwareError::InvalidCommand(format!("No probe point {}", index))
+        })?;
+        *slot = Some(deviation_mm);
+        Ok(())
+    }
+
+    /// Reads every probe point from its gap sensor.
+    pub async fn probe(&mut self, sensors: &dyn SensorInterface) -> Result<()> {
+        let sensor_ids = match &self.config.probe {
+            BedProbeMethod::Sensors { sensor_ids } => sensor_ids.clone(),
+            BedProbeMethod::Manual => {
+                return Err(FirmwareError::InvalidCommand(
+                    "Bed is probed manually; submit each point".to_string(),
+                )
+                .into())
+            }
+        };
+        for (index, sensor_id) in sensor_ids.iter().enumerate() {
+            let deviation = sensors.read_sensor(sensor_id).await.map_err(|e| {
+                FirmwareError::HardwareOperation(format!("Gap sensor {}: {:#}", sensor_id, e))
+            })?;
+            self.record(index, deviation)?;
+        }
+        Ok(())
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.deviations.iter().all(Option::is_some)
+    }
+
+    pub fn status(&self) -> Vec<BedLevelPoint> {
+        self.points
+            .iter()
+            .zip(&self.deviations)
+            .enumerate()
+            .map(|(index, (&(x, y), &deviation_mm))| BedLevelPoint { index, x, y, deviation_mm })
+            .collect()
+    }
+
+    /// Height map from a complete run.
+    pub fn finish(&self, config: &PrinterConfig) -> Result<BedMesh> {
+        let deviations = self
+            .deviations
+            .iter()
+            .copied()
+            .collect::<Option<Vec<f32>>>()
+            .ok_or_else(|| FirmwareError::InvalidCommand("Not every probe point is measured".to_string()))?;
+        self.config
+            .mesh_from(&config.build_volume, deviations)
+            .map_err(|e| FirmwareError::InvalidCommand(e).into())
+    }
+}
+
+/// Applies a height map to compiled first layers.
+#[derive(Debug, Clone)]
+pub struct BedCompensation {
+    pub mesh: BedMesh,
+    pub fade_layers: u32,
+    pub grid_spacing: f32,
+    pub layer_height: f32,
+}
+
+impl BedCompensation {
+    /// Compensation for the active config, if a mesh has been measured.
+    pub fn from_config(config: &PrinterConfig, layer_height: f32) -> Option<Self> {
+        let bed_level = config.bed_level.as_ref()?;
+        Some(Self {
+            mesh: bed_level.mesh.clone()?,
+            fade_layers: bed_level.fade_layers,
+            grid_spacing: config.valve_array.grid_spacing,
+            layer_height,
+        })
+    }
+
+    /// Open time multiplier for a node on a layer.
+    pub fn factor(&self, node: GridCoordinate, layer_number: u32) -> f32 {
+        self.mesh.extrusion_factor(
+            node.x as f32 * self.grid_spacing,
+            node.y as f32 * self.grid_spacing,
+            self.layer_height,
+            layer_number,
+            self.fade_layers,
+        )
+    }
+
+    /// Rescales valve open intervals of a compiled layer. Layers past the
+    /// fade are left untouched.
+    pub fn apply(&self, layer: &mut CompiledLayer, layer_number: u32) {
+        if layer_number >= self.fade_layers.max(1) {
+            return;
+        }
+        for segment in &mut layer.segments {
+            self.apply_segment(segment, layer_number);
+        }
+    }
+
+    fn apply_segment(&self, segment: &mut ScheduleSegment, layer_number: u32) {
+        let Some(last_tick) = segment.frames.last().map(|f| f.tick) else {
+            return;
+        };
+        let settle = segment.end_tick.saturating_sub(last_tick);
+
+        // Change ticks per valve, to find each close and the following open
+        let mut changes: HashMap<(GridCoordinate, u8), Vec<(u64, bool)>> = HashMap::new();
+        for frame in &segment.frames {
+            for (node, valves) in &frame.updates {
+                for valve in valves {
+                    changes.entry((*node, valve.index)).or_default().push((frame.tick, valve.open));
+                }
+            }
+        }
+
+        let mut retimed: BTreeMap<u64, Vec<(GridCoordinate, ValveState)>> = BTreeMap::new();
+        let mut seen: HashMap<(GridCoordinate, u8), usize> = HashMap::new();
+        for frame in &segment.frames {
+            for (node, valves) in &frame.updates {
+                for valve in valves {
+                    let key = (*node, valve.index);
+                    let position = seen.entry(key).or_insert(0);
+                    let history = &changes[&key];
+                    let mut tick = frame.tick;
+                    if !valve.open && *position > 0 && history[*position - 1].1 {
+                        let opened = history[*position - 1].0;
+                        let scaled = (self.factor(*node, layer_number) * (frame.tick - opened) as f32).round() as u64;
+                        let limit = history.get(*position + 1).map(|&(next, _)| next - 1);
+                        tick = (opened + scaled.max(1)).min(limit.unwrap_or(u64::MAX));
+                    }
+                    *position += 1;
+                    retimed.entry(tick).or_default().push((*node, *valve));
+                }
+            }
+        }
+
+        segment.frames = retimed
+            .into_iter()
+            .map(|(tick, states)| {
+                let mut updates: Vec<(GridCoordinate, Vec<ValveState>)> = Vec::new();
+                for (node, valve) in states {
+                    match updates.iter_mut().find(|(n, _)| *n == node) {
+                        Some((_, valves)) => valves.push(valve),
+                        None => updates.push((node, vec![valve])),
+                    }
+                }
+                ValveFrame { tick, updates }
+            })
+            .collect();
+        let last_tick = segment.frames.last().map(|f| f.tick).unwrap_or(0);
+        segment.end_tick = last_tick + settle;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(tick: u64, open: bool) -> ValveFrame {
+        ValveFrame {
+            tick,
+            updates: vec![(GridCoordinate::new(0, 0), vec![ValveState { index: 0, open }])],
+        }
+    }
+
+    #[test]
+    fn test_first_layer_open_time_scaled() {
+        let compensation = BedCompensation {
+            mesh: BedMesh {
+                origin: (0.0, 0.0),
+                spacing: (10.0, 10.0),
+                points_x: 2,
+                points_y: 2,
+                deviations: vec![0.1; 4],
+            },
+            fade_layers: 2,
+            grid_spacing: 0.5,
+            layer_height: 0.2,
+        };
+        let mut layer = CompiledLayer {
+            segments: vec![ScheduleSegment {
+                frames: vec![frame(0, true), frame(100, false), frame(120, true), frame(200, false)],
+                barrier: None,
+                end_tick: 205,
+            }],
+        };
+
+        // Gap 0.3mm instead of 0.2mm: open time x1.5, capped at the next open
+        compensation.apply(&mut layer, 0);
+        let ticks: Vec<u64> = layer.segments[0].frames.iter().map(|f| f.tick).collect();
+        assert_eq!(ticks, vec![0, 119, 120, 240]);
+        assert_eq!(layer.segments[0].end_tick, 245);
+
+        // Past the fade nothing changes
+        let before = layer.segments[0].frames.clone();
+        compensation.apply(&mut layer, 2);
+        assert_eq!(layer.segments[0].frames, before);
+    }
+}
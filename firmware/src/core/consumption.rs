@@ -0,0 +1,245 @@
+//! Live material consumption tracking.
+//!
+//! Every executed layer adds its deposit to a per-channel total: each open
+//! node fills one grid cell to the layer's height, scaled by the live flow
+//! factor, the same model the slicer and `hg4d-slicer info` use. Flow sensor
+//! readings are integrated alongside. [`ConsumptionTracker::check`] compares
+//! the two and the expected total from the file header
+//! (`SliceResult::material_usage`):
+//!
+//! - measured flow well below the commanded deposit points at a clog
+//! - measured flow well above it points at a leak
+//! - a commanded total past the expected one means the file and the print
+//!   disagree, e.g. a flow factor left raised
+//!
+//! Each deviation is reported once per channel and kind. Channels below
+//! [`MIN_COMPARED_GRAMS`] are not compared, so the first layers' noise does
+//! not trigger warnings.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::time::Duration;
+
+use config_types::PrinterConfig;
+use gcode_types::LayerFrame;
+use protocol::ErrorCode;
+
+use crate::gcode::stream::FileMetadata;
+
+/// Relative deviation tolerated before warning.
+pub const DEFAULT_CONSUMPTION_TOLERANCE: f32 = 0.15;
+
+/// Amount per channel below which nothing is compared (g).
+pub const MIN_COMPARED_GRAMS: f32 = 1.0;
+
+/// Used when the file carries no material for a channel (g/cm³).
+const FALLBACK_DENSITY: f32 = 1.24;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum DeviationKind {
+    /// Measured flow below the commanded deposit
+    UnderFlow,
+    /// Measured flow above the commanded deposit
+    OverFlow,
+    /// Commanded deposit above the file's expected total
+    OverPlan,
+}
+
+impl DeviationKind {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            DeviationKind::UnderFlow => ErrorCode::MaterialUnderflow,
+            DeviationKind::OverFlow => ErrorCode::MaterialOverflow,
+            DeviationKind::OverPlan => ErrorCode::MaterialOverPlan,
+        }
+    }
+}
+
+/// A channel whose consumption is outside the tolerance.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ConsumptionDeviation {
+    pub channel: u8,
+    pub kind: DeviationKind,
+    /// Reference amount (g)
+    pub expected: f32,
+    /// Amount found (g)
+    pub actual: f32,
+}
+
+impl fmt::Display for ConsumptionDeviation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let reference = match self.kind {
+            DeviationKind::UnderFlow | DeviationKind::OverFlow => "measured vs commanded",
+            DeviationKind::OverPlan => "commanded vs expected",
+        };
+        write!(
+            f,
+            "Channel {} {}: {:.1}g vs {:.1}g ({:+.0}%)",
+            self.channel,
+            reference,
+            self.actual,
+            self.expected,
+            (self.actual / self.expected - 1.0) * 100.0
+        )
+    }
+}
+
+/// Integrates deposited and measured material per channel over a print.
+#[derive(Debug, Clone)]
+pub struct ConsumptionTracker {
+    cell_area: f64,
+    /// g/cm³, index = channel
+    densities: Vec<f32>,
+    /// Expected total per channel (g), index = channel
+    expected: Vec<f32>,
+    tolerance: f32,
+    previous_z: f32,
+    commanded_mm3: BTreeMap<u8, f64>,
+    measured_mm3: BTreeMap<u8, f64>,
+    reported: BTreeSet<(u8, DeviationKind)>,
+}
+
+impl ConsumptionTracker {
+    pub fn new(printer: &PrinterConfig, metadata: &FileMetadata, tolerance: f32) -> Self {
+        let spacing = printer.valve_array.grid_spacing as f64;
+        Self {
+            cell_area: spacing * spacing,
+            densities: metadata.materials.iter().map(|m| m.properties.density).collect(),
+            expected: metadata.material_usage.clone(),
+            tolerance,
+            previous_z: 0.0,
+            commanded_mm3: BTreeMap::new(),
+            measured_mm3: BTreeMap::new(),
+            reported: BTreeSet::new(),
+        }
+    }
+
+    /// Adds an executed layer. `flow` is the live flow factor it ran with;
+    /// nodes of cancelled objects must already be removed from the frame.
+    pub fn record_layer(&mut self, frame: &LayerFrame, flow: f32) {
+        let height = (frame.z_height - self.previous_z).max(0.0) as f64;
+        self.previous_z = frame.z_height;
+        for plane in &frame.planes {
+            let channel = plane.channel.or(frame.primary_material).unwrap_or(0);
+            let open: u64 = plane
+                .rows
+                .iter()
+                .flat_map(|row| &row.runs)
+                .filter(|run| run.mask != 0)
+                .map(|run| run.len as u64)
+                .sum();
+            if open > 0 {
+                *self.commanded_mm3.entry(channel).or_default() += open as f64 * self.cell_area * height * flow as f64;
+            }
+        }
+    }
+
+    /// Adds a flow sensor reading held for `elapsed`.
+    pub fn record_flow(&mut self, channel: u8, flow_mm3_per_s: f32, elapsed: Duration) {
+        *self.measured_mm3.entry(channel).or_default() += flow_mm3_per_s.max(0.0) as f64 * elapsed.as_secs_f64();
+    }
+
+    /// Deposited material per channel from executed layers (g).
+    pub fn consumed_grams(&self) -> BTreeMap<u8, f32> {
+        self.to_grams(&self.commanded_mm3)
+    }
+
+    /// Material per channel from the flow sensors (g).
+    pub fn measured_grams(&self) -> BTreeMap<u8, f32> {
+        self.to_grams(&self.measured_mm3)
+    }
+
+    /// Deviations not reported before.
+    pub fn check(&mut self) -> Vec<ConsumptionDeviation> {
+        let measured = self.measured_grams();
+        let mut found = Vec::new();
+        for (channel, commanded) in self.consumed_grams() {
+            if commanded < MIN_COMPARED_GRAMS {
+                continue;
+            }
+            if let Some(&actual) = measured.get(&channel) {
+                if actual < commanded * (1.0 - self.tolerance) {
+                    found.push(ConsumptionDeviation { channel, kind: DeviationKind::UnderFlow, expected: commanded, actual });
+                } else if actual > commanded * (1.0 + self.tolerance) {
+                    found.push(ConsumptionDeviation { channel, kind: DeviationKind::OverFlow, expected: commanded, actual });
+                }
+            }
+            if let Some(&expected) = self.expected.get(channel as usize) {
+                if expected > 0.0 && commanded > expected * (1.0 + self.tolerance) {
+                    found.push(ConsumptionDeviation {
+                        channel,
+                        kind: DeviationKind::OverPlan,
+                        expected,
+                        actual: commanded,
+                    });
+                }
+            }
+        }
+        found.retain(|d| self.reported.insert((d.channel, d.kind)));
+        found
+    }
+
+    fn to_grams(&self, volumes: &BTreeMap<u8, f64>) -> BTreeMap<u8, f32> {
+        volumes
+            .iter()
+            .map(|(&channel, &mm3)| {
+                let density = self.densities.get(channel as usize).copied().unwrap_or(FALLBACK_DENSITY);
+                // g/cm³ × mm³ / 1000
+                (channel, (mm3 * density as f64 / 1000.0) as f32)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gcode_types::{ChannelPlane, FrameRow, Layer, ValveRun};
+
+    fn tracker(expected: Vec<f32>) -> ConsumptionTracker {
+        ConsumptionTracker {
+            cell_area: 1.0,
+            densities: vec![1.0],
+            expected,
+            tolerance: 0.1,
+            previous_z: 0.0,
+            commanded_mm3: BTreeMap::new(),
+            measured_mm3: BTreeMap::new(),
+            reported: BTreeSet::new(),
+        }
+    }
+
+    fn frame(layer_number: u32, z_height: f32, open: u32) -> LayerFrame {
+        let mut frame = LayerFrame::from_layer(&Layer::new(z_height, layer_number)).unwrap();
+        frame.planes = vec![ChannelPlane {
+            channel: Some(0),
+            rows: vec![FrameRow { y: 0, runs: vec![ValveRun { x: 0, len: open, mask: 1 }] }],
+        }];
+        frame
+    }
+
+    #[test]
+    fn test_consumption_and_deviations() {
+        let mut tracker = tracker(vec![2.0]);
+        // 1000 cells × 1mm² × 1mm at 1 g/cm³ = 1g per layer
+        tracker.record_layer(&frame(0, 1.0, 1000), 1.0);
+        tracker.record_flow(0, 100.0, Duration::from_secs(10));
+        assert!((tracker.consumed_grams()[&0] - 1.0).abs() < 1e-6);
+        assert!(tracker.check().is_empty());
+
+        // A clog: the sensor sees half the commanded flow
+        tracker.record_layer(&frame(1, 2.0, 1000), 1.0);
+        tracker.record_flow(0, 0.0, Duration::from_secs(10));
+        let deviations = tracker.check();
+        assert_eq!(deviations.len(), 1);
+        assert_eq!(deviations[0].kind, DeviationKind::UnderFlow);
+        assert!(tracker.check().is_empty(), "reported once");
+
+        // Raised flow pushes the total past the file's expectation
+        tracker.record_layer(&frame(2, 3.0, 1000), 1.5);
+        let deviations = tracker.check();
+        assert_eq!(deviations.len(), 1);
+        assert_eq!(deviations[0].kind, DeviationKind::OverPlan);
+        assert!((deviations[0].actual - 3.5).abs() < 1e-5);
+    }
+}
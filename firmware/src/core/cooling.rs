@@ -0,0 +1,141 @@
+//! Minimum layer time for cooling.
+//!
+//! Small layers deposit faster than the material below them can cool, so
+//! every material profile sets a minimum layer time. The floor for a layer
+//! is the largest of the file's `Layer::min_layer_time` and the
+//! `CoolingParameters::min_layer_time` of the loaded materials on the
+//! channels it deposits; a layer that finishes sooner dwells for the rest
+//! (see `CommandScheduler::hold_for_cooling`) before Z moves up.
+//!
+//! With fan linkage enabled the plan also sets the part-cooling fan: the
+//! materials' initial speed on the first layer, the regular speed after,
+//! raised toward 100% the shorter the layer's estimated time is than the
+//! floor, so the dwell it needs is shorter too.
+
+use std::collections::BTreeSet;
+use std::time::Duration;
+
+use config_types::CoolingParameters;
+use gcode_types::Layer;
+
+use super::materials::MaterialRegistry;
+
+/// Cooling for one layer.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CoolingPlan {
+    pub min_layer_time: Duration,
+    /// Part-cooling fan duty (0-100), with fan linkage enabled
+    pub fan_speed: Option<f32>,
+}
+
+impl CoolingPlan {
+    /// Dwell needed after a layer that took `elapsed`.
+    pub fn dwell(&self, elapsed: Duration) -> Duration {
+        self.min_layer_time.saturating_sub(elapsed)
+    }
+}
+
+/// Works out each layer's cooling floor and fan speed.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CoolingPolicy {
+    fan_linkage: bool,
+}
+
+impl CoolingPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Also plan the fan speed for each layer.
+    pub fn with_fan_linkage(mut self, enabled: bool) -> Self {
+        self.fan_linkage = enabled;
+        self
+    }
+
+    /// Plan for `layer` with the materials loaded now; None if nothing
+    /// needs cooling.
+    pub fn plan(&self, layer: &Layer, materials: &MaterialRegistry) -> Option<CoolingPlan> {
+        self.plan_with(layer, |channel| materials.get(channel).map(|p| &p.cooling))
+    }
+
+    fn plan_with<'a>(
+        &self,
+        layer: &Layer,
+        cooling: impl Fn(u8) -> Option<&'a CoolingParameters>,
+    ) -> Option<CoolingPlan> {
+        let channels: BTreeSet<u8> = layer
+            .nodes
+            .iter()
+            .filter(|n| n.has_open_valve())
+            .map(|n| n.material_channel.or(layer.primary_material).unwrap_or(0))
+            .collect();
+        let profiles: Vec<&CoolingParameters> = channels.into_iter().filter_map(cooling).collect();
+
+        let floor = profiles
+            .iter()
+            .map(|c| c.min_layer_time)
+            .chain(layer.min_layer_time)
+            .filter(|t| t.is_finite())
+            .fold(0.0f32, f32::max);
+
+        let fan_speed = (self.fan_linkage && profiles.iter().any(|c| c.requires_cooling)).then(|| {
+            let base = profiles
+                .iter()
+                .map(|c| if layer.layer_number == 0 { c.initial_fan_speed } else { c.regular_fan_speed })
+                .fold(0.0f32, f32::max);
+            let shortfall = match layer.estimated_time {
+                Some(time) if floor > 0.0 && time < floor => 1.0 - time.max(0.0) / floor,
+                _ => 0.0,
+            };
+            (base + (100.0 - base) * shortfall).clamp(0.0, 100.0)
+        });
+
+        if floor <= 0.0 && fan_speed.is_none() {
+            return None;
+        }
+        Some(CoolingPlan {
+            min_layer_time: Duration::from_secs_f32(floor),
+            fan_speed,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gcode_types::{GridCoordinate, NodeValveState, ValveState};
+
+    #[test]
+    fn test_plan_floor_and_fan() {
+        let cooling = |min_layer_time, regular_fan_speed| CoolingParameters {
+            min_layer_time,
+            requires_cooling: true,
+            initial_fan_speed: 0.0,
+            regular_fan_speed,
+        };
+        let profiles = [cooling(8.0, 50.0), cooling(12.0, 40.0)];
+        let lookup = |channel: u8| profiles.get(channel as usize);
+
+        let mut layer = Layer::new(1.0, 4);
+        let node = |x, valve| NodeValveState::new(GridCoordinate::new(x, 0), vec![valve]);
+        layer.add_node(node(0, ValveState::open(0)).with_material(0));
+        layer.estimated_time = Some(2.0);
+
+        let plan = CoolingPolicy::new().plan_with(&layer, lookup).unwrap();
+        assert_eq!(plan.min_layer_time, Duration::from_secs(8));
+        assert_eq!(plan.fan_speed, None);
+        assert_eq!(plan.dwell(Duration::from_secs(3)), Duration::from_secs(5));
+        assert_eq!(plan.dwell(Duration::from_secs(9)), Duration::ZERO);
+
+        // Channel 1 is closed on this layer; the file's floor still counts
+        layer.add_node(node(1, ValveState::new(1, false)).with_material(1));
+        layer.min_layer_time = Some(10.0);
+        let linked = CoolingPolicy::new().with_fan_linkage(true);
+        let plan = linked.plan_with(&layer, lookup).unwrap();
+        assert_eq!(plan.min_layer_time, Duration::from_secs(10));
+        // 2s of a 10s floor: 50% raised 80% of the way to full
+        assert!((plan.fan_speed.unwrap() - 90.0).abs() < 1e-3);
+
+        assert!(CoolingPolicy::new().plan_with(&Layer::new(0.2, 0), lookup).is_none());
+    }
+}
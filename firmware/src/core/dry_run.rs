@@ -0,0 +1,187 @@
+//! Dry-run execution.
+//!
+//! A dry run executes a complete print (Z moves, valve actuation timing,
+//! waits) with heaters and pressure inhibited, so the valve array and motion
+//! system can be checked without material. For the duration of the run the
+//! real heater and pressure controllers are swapped out of their shared
+//! handles for inhibited stand-ins:
+//!
+//! - Set-points are recorded but never applied to hardware
+//! - Readbacks report the recorded set-point, so G4W temperature and
+//!   pressure waits complete immediately instead of stalling the print
+//!
+//! With no pressure behind the valves nothing is extruded. The real
+//! controllers are switched off before the swap and put back by
+//! [`DryRunSwap::restore`].
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+use tracing::{info, warn};
+
+use crate::{HeaterController, PressureController};
+
+/// How a print job drives the hardware.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ExecutionMode {
+    /// Full print
+    #[default]
+    Normal,
+    /// Motion and valves only; heaters, pressure and extrusion inhibited
+    DryRun,
+}
+
+/// Heater stand-in that records targets without energizing anything.
+#[derive(Debug, Default)]
+pub struct InhibitedHeaters {
+    targets: HashMap<u8, f32>,
+}
+
+#[async_trait::async_trait]
+impl HeaterController for InhibitedHeaters {
+    async fn set_temperature(&mut self, zone_id: u8, target: f32) -> Result<()> {
+        self.targets.insert(zone_id, target);
+        Ok(())
+    }
+
+    async fn get_temperature(&self, zone_id: u8) -> Result<f32> {
+        Ok(self.targets.get(&zone_id).copied().unwrap_or(0.0))
+    }
+
+    async fn update_control(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    async fn emergency_off(&mut self) -> Result<()> {
+        self.targets.clear();
+        Ok(())
+    }
+}
+
+/// Pressure stand-in that records targets without pressurizing anything.
+#[derive(Debug, Default)]
+pub struct InhibitedPressure {
+    targets: HashMap<u8, f32>,
+}
+
+#[async_trait::async_trait]
+impl PressureController for InhibitedPressure {
+    async fn set_pressure(&mut self, channel_id: u8, target: f32) -> Result<()> {
+        self.targets.insert(channel_id, target);
+        Ok(())
+    }
+
+    async fn get_pressure(&self, channel_id: u8) -> Result<f32> {
+        Ok(self.targets.get(&channel_id).copied().unwrap_or(0.0))
+    }
+
+    async fn get_flow_rate(&self, _channel_id: u8) -> Result<f32> {
+        Ok(0.0)
+    }
+
+    async fn update_control(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    async fn emergency_vent(&mut self) -> Result<()> {
+        self.targets.clear();
+        Ok(())
+    }
+}
+
+/// Real controllers set aside while a dry run is active.
+pub struct DryRunSwap {
+    heaters: Box<dyn HeaterController>,
+    pressure: Box<dyn PressureController>,
+}
+
+impl DryRunSwap {
+    /// Switches the real heaters off, vents pressure, then installs the
+    /// inhibited stand-ins in the shared handles.
+    pub async fn enter(
+        heaters: &Arc<Mutex<Box<dyn HeaterController>>>,
+        pressure: &Arc<Mutex<Box<dyn PressureController>>>,
+    ) -> Result<Self> {
+        let mut heaters = heaters.lock().await;
+        let mut pressure = pressure.lock().await;
+
+        heaters.emergency_off().await?;
+        pressure.emergency_vent().await?;
+
+        let swap = Self {
+            heaters: std::mem::replace(&mut *heaters, Box::new(InhibitedHeaters::default())),
+            pressure: std::mem::replace(&mut *pressure, Box::new(InhibitedPressure::default())),
+        };
+        warn!("Dry run: heaters and pressure inhibited");
+        Ok(swap)
+    }
+
+    /// Puts the real controllers back. They stay off; the next job sets its
+    /// own targets.
+    pub async fn restore(
+        self,
+        heaters: &Arc<Mutex<Box<dyn HeaterController>>>,
+        pressure: &Arc<Mutex<Box<dyn PressureController>>>,
+    ) {
+        *heaters.lock().await = self.heaters;
+        *pressure.lock().await = self.pressure;
+        info!("Dry run finished: heater and pressure control restored");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[derive(Default)]
+    struct Calls {
+        set: AtomicUsize,
+        off: AtomicUsize,
+    }
+
+    struct RealHeaters(Arc<Calls>);
+
+    #[async_trait::async_trait]
+    impl HeaterController for RealHeaters {
+        async fn set_temperature(&mut self, _zone_id: u8, _target: f32) -> Result<()> {
+            self.0.set.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+        async fn get_temperature(&self, _zone_id: u8) -> Result<f32> {
+            Ok(21.0)
+        }
+        async fn update_control(&mut self) -> Result<()> {
+            Ok(())
+        }
+        async fn emergency_off(&mut self) -> Result<()> {
+            self.0.off.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_dry_run_inhibits_and_restores() {
+        let calls = Arc::new(Calls::default());
+        let heaters: Arc<Mutex<Box<dyn HeaterController>>> =
+            Arc::new(Mutex::new(Box::new(RealHeaters(calls.clone()))));
+        let pressure: Arc<Mutex<Box<dyn PressureController>>> =
+            Arc::new(Mutex::new(Box::new(InhibitedPressure::default())));
+
+        let swap = DryRunSwap::enter(&heaters, &pressure).await.unwrap();
+        assert_eq!(calls.off.load(Ordering::SeqCst), 1);
+
+        // Targets are accepted and reported as reached without touching hardware
+        heaters.lock().await.set_temperature(0, 210.0).await.unwrap();
+        assert_eq!(heaters.lock().await.get_temperature(0).await.unwrap(), 210.0);
+        pressure.lock().await.set_pressure(0, 50.0).await.unwrap();
+        assert_eq!(pressure.lock().await.get_flow_rate(0).await.unwrap(), 0.0);
+        assert_eq!(calls.set.load(Ordering::SeqCst), 0);
+
+        swap.restore(&heaters, &pressure).await;
+        assert_eq!(heaters.lock().await.get_temperature(0).await.unwrap(), 21.0);
+    }
+}
@@ -0,0 +1,95 @@
+//! Spool drying cycles.
+//!
+//! A spool is dried in place by holding the chamber (or, on printers
+//! without a chamber heater, the bed) at the material's drying temperature
+//! for the profile's drying time. The printer must be idle; the heater is
+//! switched off and the spool's exposure reset when the cycle completes.
+
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+
+use config_types::{MaterialProfile, PrinterConfig};
+
+use crate::FirmwareError;
+
+/// Heater used to dry a spool.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DryingHeater {
+    Chamber,
+    Bed,
+}
+
+/// A running drying cycle.
+#[derive(Debug, Clone)]
+pub struct DryingCycle {
+    pub channel: u8,
+    pub heater: DryingHeater,
+    pub temperature: f32,
+    pub duration: Duration,
+    started: Instant,
+}
+
+impl DryingCycle {
+    /// Plans a cycle from the loaded profile, with optional overrides.
+    ///
+    /// The temperature must stay below the material's glass transition and
+    /// within the chamber's limit when drying in the chamber.
+    pub fn plan(
+        config: &PrinterConfig,
+        channel: u8,
+        profile: &MaterialProfile,
+        temperature: Option<f32>,
+        hours: Option<f32>,
+    ) -> Result<Self> {
+        let defaults = profile.drying.as_ref();
+        let (Some(temperature), Some(hours)) = (
+            temperature.or(defaults.map(|d| d.temperature)),
+            hours.or(defaults.map(|d| d.hours)),
+        ) else {
+            return Err(FirmwareError::InvalidCommand(format!(
+                "{} has no drying parameters; give a temperature and time",
+                profile.name
+            ))
+            .into());
+        };
+        if !(hours > 0.0 && hours.is_finite()) {
+            return Err(FirmwareError::InvalidCommand("Drying time must be positive".to_string()).into());
+        }
+        if temperature >= profile.properties.glass_transition_temp {
+            return Err(FirmwareError::SafetyViolation(format!(
+                "Drying at {:.0}°C would soften {} (glass transition {:.0}°C)",
+                temperature, profile.name, profile.properties.glass_transition_temp
+            ))
+            .into());
+        }
+
+        let heater = match &config.thermal.chamber {
+            Some(chamber) if temperature > chamber.max_temp => {
+                return Err(FirmwareError::SafetyViolation(format!(
+                    "Drying at {:.0}°C exceeds the chamber limit {:.0}°C",
+                    temperature, chamber.max_temp
+                ))
+                .into())
+            }
+            Some(_) => DryingHeater::Chamber,
+            None => DryingHeater::Bed,
+        };
+
+        Ok(Self {
+            channel,
+            heater,
+            temperature,
+            duration: Duration::from_secs_f32(hours * 3600.0),
+            started: Instant::now(),
+        })
+    }
+
+    pub fn remaining(&self) -> Duration {
+        self.duration.saturating_sub(self.started.elapsed())
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.started.elapsed() >= self.duration
+    }
+}
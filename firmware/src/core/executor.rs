@@ -0,0 +1,1128 @@
+//! Print job execution.
+//!
+//! The [`Executor`] holds shared handles to the firmware's controllers and
+//! state rather than the firmware itself, so a print runs as a task of its
+//! own ([`Executor::run_job`]) while the firmware keeps answering commands.
+//! The firmware steers the running job through a [`JobControl`] channel;
+//! the job acts on it between layers, so a pause or cancel lets the current
+//! layer finish.
+//!
+//! A job heats the zones its materials need, pressurizes their channels and
+//! homes Z if needed, then streams the file layer by layer. Heaters are
+//! switched on as a [`PreheatPlan`] schedules them, so their ramps overlap
+//! the homing and the file's first reads and end together. Runs of empty
+//! layers are crossed in one Z move ([`ZFastForward`]), and spatial zones
+//! idle over the coming layers are parked ([`ZonePowerManager`]). Each
+//! depositing layer:
+//!
+//! 1. Applies the adjustments queued for the layer boundary
+//! 2. Closes the valves of cancelled objects
+//! 3. Moves Z to the layer
+//! 4. Latches the compiled frame, rescaled by the bed mesh over the first
+//!    layers and checked against valve feedback when verification is
+//!    enabled. A ramp layer (vase mode) is instead latched node by node
+//!    while Z rises to it at the ramp's speed
+//! 5. Dwells for the layer's cooling floor
+//! 6. Reports its measured duration as a `LayerTiming` message
+//! 7. Holds, Paused, for inspection if the slicer ended it with
+//!    `G4W INSPECT`, until resume_print releases the [`InspectionGate`]
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, VecDeque};
+use std::hash::{Hash, Hasher};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use tokio::sync::{broadcast, watch, Mutex, RwLock};
+use tracing::{debug, error, info, warn};
+
+use config_types::{MaterialProfile, PrinterConfig};
+use gcode_types::{
+    Command, Coordinate, G4DCommand, G4LCommand, G4WCommand, GridCoordinate, Layer, LayerFrame, NodeValveState,
+    ValveState, WaitType,
+};
+use protocol::{ErrorCode, JobResult, ProtocolMessage};
+
+use super::adjust::{ActiveAdjustments, Adjustment, LiveAdjuster};
+use super::bed_level::BedCompensation;
+use super::barrier::{BarrierConfig, SubsystemBarrier};
+use super::consumption::{ConsumptionTracker, DEFAULT_CONSUMPTION_TOLERANCE};
+use super::cooling::CoolingPolicy;
+use super::dry_run::DryRunSwap;
+use super::fast_forward::{ZAdvance, ZFastForward};
+use super::history::{JobRecorder, PrintHistory};
+use super::inspection::{CameraWebhook, InspectionGate, InspectionPause};
+use super::macros::MacroAction;
+use super::materials::MaterialRegistry;
+use super::preheat::{homing_time, HeaterTarget, PreheatHeater, PreheatPlanner, PreheatStep};
+use super::scheduler::{BarrierHandler, CommandScheduler};
+use super::state_machine::StateMachine;
+use super::verification::FeedbackVerifier;
+use super::zone_power::{ZonePowerManager, ZoneTargetChange, DEFAULT_ZONE_LOOKAHEAD_LAYERS};
+use crate::gcode::stream::{FileMetadata, LayerStream, DEFAULT_LOOKAHEAD_LAYERS};
+use crate::safety::StuckValveDetector;
+use crate::gcode::GCodeParser;
+use crate::{
+    validate_material_zones, FirmwareError, FirmwareState, HeaterController, PressureController, PrintStatus,
+    SensorInterface, SystemError, SystemState, ValveController, ZAxisController,
+};
+
+/// Longest a job waits for its heaters and pressures before the first
+/// layer.
+pub const PRINT_START_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+
+/// What the firmware asks of a running job.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobControl {
+    Run,
+    /// Hold before the next layer until told to run again
+    Pause,
+    /// Stop before the next layer
+    Cancel,
+}
+
+/// A print job as handed to the executor.
+#[derive(Debug, Clone)]
+pub struct PrintJob {
+    pub path: PathBuf,
+    pub metadata: FileMetadata,
+    pub total_layers: u32,
+    /// Layers before this one are skipped (resume)
+    pub start_layer: u32,
+    /// Objects cancelled before the job was interrupted (resume)
+    pub cancelled_objects: BTreeMap<u32, u32>,
+}
+
+/// Shared handles layers are executed through.
+#[derive(Clone)]
+pub struct Executor {
+    pub config: Arc<RwLock<PrinterConfig>>,
+    pub state: Arc<RwLock<SystemState>>,
+    pub valves: Arc<Mutex<Box<dyn ValveController>>>,
+    pub z_axis: Arc<Mutex<Box<dyn ZAxisController>>>,
+    pub heaters: Arc<Mutex<Box<dyn HeaterController>>>,
+    pub pressure: Arc<Mutex<Box<dyn PressureController>>>,
+    pub sensors: Arc<Box<dyn SensorInterface>>,
+    pub status_tx: broadcast::Sender<ProtocolMessage>,
+    pub state_machine: Arc<StateMachine>,
+    pub materials: Arc<RwLock<MaterialRegistry>>,
+    pub adjustments: Arc<Mutex<LiveAdjuster>>,
+    /// Kept across jobs so latched valve states and statistics carry over
+    pub scheduler: Arc<Mutex<CommandScheduler>>,
+    /// Real heater/pressure controllers while a dry run is active
+    pub dry_run: Arc<Mutex<Option<DryRunSwap>>>,
+    /// Record of the running job
+    pub job: Arc<Mutex<Option<JobRecorder>>>,
+    /// Finished jobs, if a history database is configured
+    pub history: Option<Arc<PrintHistory>>,
+    /// Material deposited and measured by the running job; the pressure
+    /// task adds the flow sensor readings
+    pub consumption: Arc<Mutex<Option<ConsumptionTracker>>>,
+    /// Released by resume_print while the job holds at an inspection layer
+    pub inspection: InspectionGate,
+    /// Told every latched valve state, to spot flow with the valves closed
+    pub stuck_valves: Arc<Mutex<StuckValveDetector>>,
+}
+
+impl Executor {
+    /// Moves the firmware to `to` through the state machine.
+    pub async fn set_state(&self, to: FirmwareState, reason: &str) -> Result<()> {
+        let mut state = self.state.write().await;
+        self.state_machine.transition(&mut state, to, reason).map_err(FirmwareError::from)?;
+        Ok(())
+    }
+
+    /// Runs a print job to its end and returns how it ended. The valves are
+    /// then closed and the heaters and pressures turned off, and the
+    /// firmware returns to Idle, or to Error if the job failed.
+    pub async fn run_job(&self, job: PrintJob, control: watch::Receiver<JobControl>) -> Result<JobResult> {
+        let result = self.print(&job, control).await;
+        self.end_dry_run().await;
+        self.end_job(&result).await;
+        self.finish_job(match &result {
+            Ok(ended) => *ended,
+            Err(_) => JobResult::Failed,
+        })
+        .await;
+        result
+    }
+
+    async fn print(&self, job: &PrintJob, mut control: watch::Receiver<JobControl>) -> Result<JobResult> {
+        // Prefetching starts now, alongside homing and heating
+        let mut stream = LayerStream::open(&job.path, GCodeParser::new(), DEFAULT_LOOKAHEAD_LAYERS)?;
+        let zones = self.prepare(job).await?;
+        *self.consumption.lock().await = Some(ConsumptionTracker::new(
+            &*self.config.read().await,
+            &job.metadata,
+            DEFAULT_CONSUMPTION_TOLERANCE,
+        ));
+
+        let mut status = PrintStatus::new(job.path.clone(), job.total_layers);
+        status.cancelled_objects = job.cancelled_objects.clone();
+        {
+            let mut state = self.state.write().await;
+            status.update_progress(job.start_layer, state.motion.z_position);
+            state.print_status = Some(status);
+        }
+        self.set_state(FirmwareState::Printing, "heaters and pressures at target").await?;
+
+        let (mut zone_power, mut fast_forward) = {
+            let config = self.config.read().await;
+            (
+                ZonePowerManager::new(&config, &zones, DEFAULT_ZONE_LOOKAHEAD_LAYERS),
+                ZFastForward::new(&config.motion.z_axis),
+            )
+        };
+        let mut barriers = self.barriers().await;
+        let mut verifier = self.verifier().await;
+        // The next layer to run first, then the zone power lookahead
+        let mut upcoming = VecDeque::with_capacity(DEFAULT_ZONE_LOOKAHEAD_LAYERS);
+        loop {
+            while upcoming.len() < DEFAULT_ZONE_LOOKAHEAD_LAYERS {
+                let Some(frame) = stream.next_layer().await else {
+                    break;
+                };
+                let frame = frame?;
+                if frame.layer_number >= job.start_layer {
+                    upcoming.push_back(frame);
+                }
+            }
+            let Some(frame) = upcoming.front() else {
+                break;
+            };
+
+            if *control.borrow() == JobControl::Pause {
+                // A resumed print should not wait on cold zones
+                self.apply_zone_power(&zone_power.restore_all()).await?;
+            }
+            if !wait_for_run(&mut control).await {
+                info!("Print cancelled before layer {}", frame.layer_number);
+                return Ok(JobResult::Cancelled);
+            }
+            if fast_forward.absorb(frame) {
+                upcoming.pop_front();
+                continue;
+            }
+            if let Some(advance) = fast_forward.take_advance() {
+                self.fast_forward_z(&advance).await?;
+            }
+            let changes = zone_power.update(upcoming.make_contiguous());
+            self.apply_zone_power(&changes).await?;
+
+            let Some(frame) = upcoming.pop_front() else {
+                break;
+            };
+            self.execute_frame(frame, &mut barriers, &mut verifier).await?;
+        }
+        if let Some(advance) = fast_forward.discard() {
+            debug!("Skipping {} empty layers at the end of the file", advance.layer_count());
+        }
+
+        info!("Print of {} complete", job.path.display());
+        Ok(JobResult::Completed)
+    }
+
+    /// Restores the real heater and pressure controllers after a dry run.
+    pub async fn end_dry_run(&self) {
+        if let Some(swap) = self.dry_run.lock().await.take() {
+            swap.restore(&self.heaters, &self.pressure).await;
+        }
+    }
+
+    /// Ends the running job's record, stores it and announces it.
+    pub async fn finish_job(&self, result: JobResult) {
+        let Some(job) = self.job.lock().await.take() else {
+            return;
+        };
+        let record = job.finish(result);
+        info!(
+            "Job {} ended {:?} after {:.0}s ({} of {} layers)",
+            record.file_name, record.result, record.duration_s, record.layers_printed, record.total_layers
+        );
+        let Some(history) = &self.history else {
+            return;
+        };
+        match history.record(record) {
+            Ok(record) => {
+                // No subscribers is not an error
+                self.status_tx.send(ProtocolMessage::JobFinished(record)).ok();
+            }
+            Err(e) => error!("Failed to record print history: {:#}", e),
+        }
+    }
+
+    /// Makes the printer safe, clears the print status and leaves the
+    /// printing states. However the job ended, its valves are closed, its
+    /// channels vented and every heater and pressure target zeroed.
+    async fn end_job(&self, result: &Result<JobResult>) {
+        for failure in shut_down(&self.valves, &self.heaters, &self.pressure, &self.state).await {
+            error!("Failed to make the printer safe after the job: {}", failure);
+        }
+        // The valves were closed around the scheduler
+        self.scheduler.lock().await.forget_latched();
+        self.consumption.lock().await.take();
+        let mut state = self.state.write().await;
+        state.print_status = None;
+        let (to, reason) = match result {
+            Ok(JobResult::Completed) => (FirmwareState::Idle, "print complete".to_string()),
+            Ok(other) => (FirmwareState::Idle, format!("print ended {:?}", other)),
+            Err(e) => {
+                let message = format!("Print failed: {:#}", e);
+                error!("{}", message);
+                let error = SystemError::new(ErrorCode::PrintFailed, message, vec!["print".to_string()]);
+                // No subscribers is not an error
+                self.status_tx.send(ProtocolMessage::ErrorEvent(error.to_event())).ok();
+                state.errors.push(error);
+                (FirmwareState::Error, "print failed".to_string())
+            }
+        };
+        // An emergency stop or fault already took the firmware out of the job
+        if state.firmware_state.is_error() {
+            return;
+        }
+        if let Err(e) = self.state_machine.transition(&mut state, to, reason) {
+            warn!("Job ended in {:?}: {}", state.firmware_state, e);
+        }
+    }
+
+    /// Runs layers that do not come from a file, e.g. calibration patches,
+    /// as a job of their own: Printing while they run and steered by
+    /// `control` between layers like a print. The firmware returns to Idle
+    /// afterwards, or to Error if a layer failed.
+    pub async fn run_layers(
+        &self,
+        name: &str,
+        layers: Vec<Layer>,
+        control: watch::Receiver<JobControl>,
+    ) -> Result<JobResult> {
+        let result = self.deposit_layers(name, &layers, control).await;
+        self.end_job(&result).await;
+        result
+    }
+
+    async fn deposit_layers(
+        &self,
+        name: &str,
+        layers: &[Layer],
+        mut control: watch::Receiver<JobControl>,
+    ) -> Result<JobResult> {
+        self.set_state(FirmwareState::Printing, name).await?;
+        let mut barriers = self.barriers().await;
+        let mut verifier = self.verifier().await;
+        for layer in layers {
+            if !wait_for_run(&mut control).await {
+                info!("{} cancelled before layer {}", name, layer.layer_number);
+                return Ok(JobResult::Cancelled);
+            }
+            let frame = LayerFrame::from_layer(layer).map_err(|e| FirmwareError::PrintExecution(e.to_string()))?;
+            self.execute_frame(frame, &mut barriers, &mut verifier).await?;
+        }
+        info!("{} complete", name);
+        Ok(JobResult::Completed)
+    }
+
+    /// Runs a macro resolved by [`super::macros::resolve`] as a job of its
+    /// own. Steps run in order and the first failure ends it; the firmware
+    /// state is left alone, and a cancel stops it between steps.
+    pub async fn run_macro(
+        &self,
+        name: &str,
+        actions: &[MacroAction],
+        control: watch::Receiver<JobControl>,
+    ) -> Result<JobResult> {
+        info!("Running macro '{}' ({} steps)", name, actions.len());
+        for (i, action) in actions.iter().enumerate() {
+            if *control.borrow() == JobControl::Cancel {
+                info!("Macro '{}' cancelled before step {}", name, i + 1);
+                return Ok(JobResult::Cancelled);
+            }
+            debug!("Macro '{}' step {}: {:?}", name, i + 1, action);
+            if let Err(e) = self.macro_step(action).await {
+                error!("Macro '{}' failed at step {}: {:#}", name, i + 1, e);
+                return Err(e);
+            }
+        }
+        info!("Macro '{}' done", name);
+        Ok(JobResult::Completed)
+    }
+
+    async fn macro_step(&self, action: &MacroAction) -> Result<()> {
+        // Waits for targets use the G4W barrier conditions and timeouts
+        let wait_for = |wait_type| async move {
+            let config = BarrierConfig::from_config(&*self.config.read().await);
+            SubsystemBarrier::new(self.state.clone(), config)
+                .wait(&G4WCommand { wait_type, timeout_ms: None })
+                .await
+        };
+        match *action {
+            MacroAction::SetTemperature { zone, target, wait } => {
+                self.heaters.lock().await.set_temperature(zone, target).await?;
+                if wait {
+                    wait_for(WaitType::Temperature).await?;
+                }
+            }
+            MacroAction::SetPressure { channel, target, wait } => {
+                self.pressure.lock().await.set_pressure(channel, target).await?;
+                if wait {
+                    wait_for(WaitType::Pressure).await?;
+                }
+            }
+            MacroAction::Dwell(duration) => tokio::time::sleep(duration).await,
+            MacroAction::MoveZ { z, speed } => {
+                self.z_axis.lock().await.move_to(z, speed).await?;
+            }
+            MacroAction::Purge(ref strip) => {
+                let z = self.z_axis.lock().await.get_position().await?;
+                self.execute_layer(&strip.layer(z)).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Executes a single layer outside a print job, e.g. a calibration
+    /// patch or a purge strip.
+    pub async fn execute_layer(&self, layer: &Layer) -> Result<()> {
+        let frame = LayerFrame::from_layer(layer).map_err(|e| FirmwareError::PrintExecution(e.to_string()))?;
+        let mut barriers = self.barriers().await;
+        let mut verifier = self.verifier().await;
+        self.execute_frame(frame, &mut barriers, &mut verifier).await
+    }
+
+    /// Homes Z and records the axis as homed at 0.
+    pub async fn home_z(&self) -> Result<()> {
+        info!("Homing Z");
+        self.z_axis.lock().await.home().await?;
+        let mut state = self.state.write().await;
+        state.motion.z_homed = true;
+        state.motion.z_position = 0.0;
+        state.motion.z_target = 0.0;
+        Ok(())
+    }
+
+    /// Heats, pressurizes and homes for the job, then waits until every
+    /// heater and channel is at target. Returns the zone targets.
+    async fn prepare(&self, job: &PrintJob) -> Result<BTreeMap<u8, f32>> {
+        let (zones, bed, mut pressures) = self.print_targets(&job.metadata).await?;
+        {
+            // A live adjustment outranks the material profile
+            let adjustments = self.adjustments.lock().await;
+            let mut pressure = self.pressure.lock().await;
+            for (&channel, target) in pressures.iter_mut() {
+                *target = adjustments.pressure_for(channel, *target);
+                pressure.set_pressure(channel, *target).await?;
+            }
+        }
+        {
+            let mut state = self.state.write().await;
+            for (&channel, &target) in &pressures {
+                state.pressure.channels.entry(channel).or_insert((0.0, 0.0)).1 = target;
+            }
+        }
+
+        let homed = self.state.read().await.motion.z_homed;
+        let mut plan = {
+            let config = self.config.read().await;
+            let state = self.state.read().await;
+            let current = |reading: Option<&(f32, f32)>| reading.map_or(0.0, |&(current, _)| current);
+            let targets: Vec<HeaterTarget> = zones
+                .iter()
+                .map(|(&zone, &target)| HeaterTarget {
+                    heater: PreheatHeater::Zone(zone),
+                    current: current(state.thermal.zones.get(&zone)),
+                    target,
+                })
+                .chain(bed.map(|target| HeaterTarget {
+                    heater: PreheatHeater::Bed,
+                    current: current(state.thermal.bed.as_ref()),
+                    target,
+                }))
+                .collect();
+            let preparation = if homed { Duration::ZERO } else { homing_time(&config) };
+            PreheatPlanner::new(&config).plan(&targets, preparation)
+        };
+        if plan.time_saved() > Duration::ZERO {
+            info!("Preheat overlaps preparation, saving about {:.0}s", plan.time_saved().as_secs_f32());
+        }
+
+        let started = tokio::time::Instant::now();
+        self.apply_preheat(plan.take_due(Duration::ZERO)).await?;
+        if !homed {
+            self.set_state(FirmwareState::Homing, "print started").await?;
+            let homing = self.home_z();
+            tokio::pin!(homing);
+            loop {
+                let next = plan.next_start();
+                tokio::select! {
+                    homed = &mut homing => {
+                        homed?;
+                        break;
+                    }
+                    _ = tokio::time::sleep_until(started + next.unwrap_or_default()), if next.is_some() => {
+                        self.apply_preheat(plan.take_due(started.elapsed())).await?;
+                    }
+                }
+            }
+        }
+        self.set_state(FirmwareState::Heating, "print started").await?;
+        while let Some(next) = plan.next_start() {
+            tokio::time::sleep_until(started + next).await;
+            self.apply_preheat(plan.take_due(started.elapsed())).await?;
+        }
+
+        let mut barriers = self.barriers().await;
+        let timeout_ms = Some(PRINT_START_TIMEOUT.as_millis() as u32);
+        for wait_type in [WaitType::Temperature, WaitType::Pressure] {
+            barriers.wait(&G4WCommand { wait_type, timeout_ms }).await?;
+        }
+        Ok(zones)
+    }
+
+    /// Zone, bed and channel pressure targets for the materials loaded on
+    /// the channels the file uses.
+    async fn print_targets(
+        &self,
+        metadata: &FileMetadata,
+    ) -> Result<(BTreeMap<u8, f32>, Option<f32>, BTreeMap<u8, f32>)> {
+        let config = self.config.read().await;
+        let materials = self.materials.read().await;
+        let channels: Vec<(u8, &MaterialProfile)> = (0..metadata.materials.len().max(1) as u8)
+            .filter_map(|channel| materials.get(channel).map(|profile| (channel, profile)))
+            .collect();
+
+        let zones = validate_material_zones(&config, &channels)?;
+        let bed = config
+            .thermal
+            .bed
+            .as_ref()
+            .map(|_| channels.iter().map(|(_, p)| p.bed_temp).fold(0.0, f32::max));
+        let pressures = channels
+            .iter()
+            .map(|&(channel, profile)| (channel, profile.extrusion.pressure_psi))
+            .collect();
+        Ok((zones, bed, pressures))
+    }
+
+    /// Runs one layer: boundary adjustments, cancelled objects, Z move,
+    /// valve frames, cooling dwell and timing report, then the inspection
+    /// hold if the layer asks for one. The job is Paused during the hold
+    /// and resume_print moves it back to Printing before releasing it.
+    async fn execute_frame(
+        &self,
+        mut frame: LayerFrame,
+        barriers: &mut SubsystemBarrier,
+        verifier: &mut FeedbackVerifier,
+    ) -> Result<()> {
+        let started = tokio::time::Instant::now();
+        let previous_z = self.state.read().await.motion.z_position;
+
+        let active = self.apply_layer_adjustments().await?;
+        self.suppress_cancelled(&mut frame).await?;
+        // A ramp layer deposits while Z rises; others move first
+        let ramp = frame.ramp_ms.map(|ramp_ms| G4LCommand {
+            z_height: frame.z_height,
+            feed_rate: None,
+            ramp_ms: Some(ramp_ms),
+        });
+        if ramp.is_none() {
+            self.move_z(frame.z_height).await?;
+        }
+        {
+            let mut state = self.state.write().await;
+            state.valves.current_layer = frame.layer_number;
+            state.valves.active_nodes = frame.node_count();
+        }
+
+        let layer = frame.to_layer();
+        let cooling = CoolingPolicy::new().plan(&layer, &*self.materials.read().await);
+        let mut scheduler = self.scheduler.lock().await;
+        scheduler.set_speed_factor(active.speed);
+        let mut compiled = match &ramp {
+            Some(ramp) => {
+                let spacing = self.config.read().await.valve_array.grid_spacing;
+                scheduler.compile_ramp(ramp, previous_z, &ramp_deposits(&layer, previous_z, spacing))?
+            }
+            None => scheduler.compile_frame(&frame),
+        };
+        let height = frame.z_height - previous_z;
+        if let Some(bed) = BedCompensation::from_config(&*self.config.read().await, height) {
+            bed.apply(&mut compiled, frame.layer_number);
+        }
+        self.record_valve_commands(&layer, false).await;
+        let deposit = async {
+            let mut valves = self.valves.lock().await;
+            if verifier.is_enabled() {
+                let (_, verification) =
+                    scheduler.execute_layer_verified(&compiled, &mut **valves, barriers, verifier).await?;
+                if verification.failed > 0 {
+                    warn!(
+                        "Layer {}: {} of {} valves failed verification",
+                        frame.layer_number, verification.failed, verification.commanded
+                    );
+                }
+            } else {
+                scheduler.execute_layer(&compiled, &mut **valves, barriers).await?;
+            }
+            Ok::<_, anyhow::Error>(())
+        };
+        match &ramp {
+            Some(ramp) => {
+                let max_speed = self.config.read().await.motion.z_axis.max_speed;
+                let speed = ramp.ramp_speed(previous_z).unwrap_or(max_speed).min(max_speed);
+                let (moved, deposited) = tokio::join!(self.move_z_at(frame.z_height, speed), deposit);
+                moved?;
+                deposited?;
+            }
+            None => deposit.await?,
+        }
+        self.record_valve_commands(&layer, true).await;
+        debug!(
+            "Layer {} jitter: {:?}, merged: {:?}",
+            frame.layer_number,
+            scheduler.last_layer_jitter(),
+            scheduler.last_layer_merges()
+        );
+        if let Some(plan) = &cooling {
+            scheduler.hold_for_cooling(started, plan).await;
+        }
+        drop(scheduler);
+
+        {
+            let mut state = self.state.write().await;
+            state.valves.open_valves = layer.nodes.iter().map(|n| n.open_count()).sum();
+            state.valves.pattern_hash = pattern_hash(&layer);
+            state.valves.last_update = std::time::Instant::now();
+            if let Some(status) = state.print_status.as_mut() {
+                status.update_progress(frame.layer_number + 1, frame.z_height);
+            }
+        }
+        self.report_valve_frame(&frame).await;
+        let cell_area = self.config.read().await.valve_array.grid_spacing.powi(2);
+        self.report_layer_timing(previous_z, &frame, cell_area, started.elapsed(), barriers.take_layer_waits());
+        if let Some(job) = self.job.lock().await.as_mut() {
+            job.record_layer(&frame);
+        }
+        let mut consumption = self.consumption.lock().await;
+        if let Some(tracker) = consumption.as_mut() {
+            tracker.record_layer(&frame, active.flow);
+            self.report_consumption(tracker).await;
+        }
+        drop(consumption);
+
+        if frame.inspect {
+            self.set_state(FirmwareState::Paused, "inspection pause").await?;
+            let wait = G4WCommand {
+                wait_type: WaitType::Inspection,
+                timeout_ms: None,
+            };
+            barriers.wait(&wait).await?;
+        }
+        Ok(())
+    }
+
+    /// Applies adjustments queued for the layer boundary and returns the
+    /// values the layer runs with.
+    async fn apply_layer_adjustments(&self) -> Result<ActiveAdjustments> {
+        let (applied, active) = {
+            let mut adjustments = self.adjustments.lock().await;
+            let applied = adjustments.at_layer_boundary();
+            (applied, adjustments.active().clone())
+        };
+        self.apply_adjustments(&applied).await?;
+        Ok(active)
+    }
+
+    /// Pushes temperature and pressure adjustments to the controllers.
+    /// Flow and speed are read when a layer is compiled.
+    pub async fn apply_adjustments(&self, applied: &[Adjustment]) -> Result<()> {
+        for adjustment in applied {
+            info!("Applying {:?}", adjustment);
+            match *adjustment {
+                Adjustment::Temperature { zone, target } => {
+                    self.heaters.lock().await.set_temperature(zone, target).await?;
+                    self.state.write().await.thermal.zones.entry(zone).or_insert((0.0, 0.0)).1 = target;
+                }
+                Adjustment::Pressure { channel, target } => {
+                    self.pressure.lock().await.set_pressure(channel, target).await?;
+                    self.state.write().await.pressure.channels.entry(channel).or_insert((0.0, 0.0)).1 = target;
+                }
+                Adjustment::Flow(_) | Adjustment::Speed(_) => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Sets every heater and pressure target to zero.
+    pub async fn cool_down(&self) -> Result<()> {
+        cool_down(&self.heaters, &self.pressure, &self.state).await
+    }
+
+    /// Removes the nodes of objects cancelled by this layer.
+    async fn suppress_cancelled(&self, frame: &mut LayerFrame) -> Result<()> {
+        let cancelled = match &self.state.read().await.print_status {
+            Some(status) => status.cancelled_in(frame.layer_number),
+            None => return Ok(()),
+        };
+        if cancelled.is_empty() {
+            return Ok(());
+        }
+        let mut layer = frame.to_layer();
+        let closed = layer.suppress_objects(&cancelled);
+        if closed > 0 {
+            debug!("Layer {}: {} nodes of cancelled objects closed", frame.layer_number, closed);
+            *frame = LayerFrame::from_layer(&layer).map_err(|e| FirmwareError::PrintExecution(e.to_string()))?;
+        }
+        Ok(())
+    }
+
+    /// Moves Z through a run of empty layers in one move and reports the
+    /// run's last layer as reached.
+    async fn fast_forward_z(&self, advance: &ZAdvance) -> Result<()> {
+        debug!(
+            "Fast-forwarding {} empty layers ({}-{}) to Z {:.3}",
+            advance.layer_count(),
+            advance.first_layer,
+            advance.last_layer,
+            advance.z
+        );
+        self.state.write().await.motion.z_target = advance.z;
+        self.z_axis.lock().await.move_to(advance.z, advance.speed).await?;
+        let mut state = self.state.write().await;
+        state.motion.z_position = advance.z;
+        if let Some(status) = state.print_status.as_mut() {
+            status.update_progress(advance.last_layer + 1, advance.z);
+        }
+        Ok(())
+    }
+
+    /// Sets the heater targets of preheat steps that have come due.
+    async fn apply_preheat(&self, steps: &[PreheatStep]) -> Result<()> {
+        let mut heaters = self.heaters.lock().await;
+        for step in steps {
+            info!(
+                "Preheating {} to {:.0}°C, at target in about {:.0}s",
+                step.heater,
+                step.target,
+                step.ramp.as_secs_f32()
+            );
+            match step.heater {
+                PreheatHeater::Zone(zone) => heaters.set_temperature(zone, step.target).await?,
+                PreheatHeater::Bed => heaters.set_bed_temperature(step.target).await?,
+                PreheatHeater::Chamber => heaters.set_chamber_temperature(step.target).await?,
+            }
+            let mut state = self.state.write().await;
+            let reading = match step.heater {
+                PreheatHeater::Zone(zone) => state.thermal.zones.entry(zone).or_insert((0.0, 0.0)),
+                PreheatHeater::Bed => state.thermal.bed.get_or_insert((0.0, 0.0)),
+                PreheatHeater::Chamber => state.thermal.chamber.get_or_insert((0.0, 0.0)),
+            };
+            reading.1 = step.target;
+        }
+        Ok(())
+    }
+
+    /// Sets the heater targets chosen by the zone power manager, parking
+    /// idle zones and reheating ones needed again.
+    async fn apply_zone_power(&self, changes: &[ZoneTargetChange]) -> Result<()> {
+        let mut heaters = self.heaters.lock().await;
+        for change in changes {
+            if change.target == 0.0 {
+                info!("Parking idle thermal zone {}", change.zone);
+            } else {
+                info!("Reheating thermal zone {} to {:.0}°C", change.zone, change.target);
+            }
+            heaters.set_temperature(change.zone, change.target).await?;
+        }
+        Ok(())
+    }
+
+    /// Tells the stuck-valve detector, by channel, the valve states a layer
+    /// latches as it starts, or with `finished` the closes a ramp layer
+    /// ends with. A ramp's valves all count as open until it is done.
+    async fn record_valve_commands(&self, layer: &Layer, finished: bool) {
+        let ramp = layer.ramp_ms.is_some();
+        if finished && !ramp {
+            return;
+        }
+        let mut updates: BTreeMap<u8, Vec<(GridCoordinate, Vec<ValveState>)>> = BTreeMap::new();
+        for node in &layer.nodes {
+            let Some(channel) = node.material_channel.or(layer.primary_material) else {
+                continue;
+            };
+            let valves: Vec<ValveState> = node
+                .valves
+                .iter()
+                .filter(|v| !ramp || v.open)
+                .map(|v| ValveState::new(v.index, v.open && !finished))
+                .collect();
+            if !valves.is_empty() {
+                updates.entry(channel).or_default().push((node.position, valves));
+            }
+        }
+
+        let now = std::time::Instant::now();
+        let mut detector = self.stuck_valves.lock().await;
+        for (channel, updates) in updates {
+            detector.record_commands(channel, &updates, now);
+        }
+    }
+
+    async fn move_z(&self, z: f32) -> Result<()> {
+        let speed = self.config.read().await.motion.z_axis.max_speed;
+        self.move_z_at(z, speed).await
+    }
+
+    async fn move_z_at(&self, z: f32, speed: f32) -> Result<()> {
+        self.state.write().await.motion.z_target = z;
+        self.z_axis.lock().await.move_to(z, speed).await?;
+        self.state.write().await.motion.z_position = z;
+        Ok(())
+    }
+
+    async fn barriers(&self) -> SubsystemBarrier {
+        let config = self.config.read().await;
+        let mut inspection = InspectionPause::new(self.inspection.clone(), self.status_tx.clone());
+        if let Some(camera) = &config.camera {
+            match CameraWebhook::new(camera) {
+                Ok(webhook) => inspection = inspection.with_camera(webhook),
+                Err(e) => warn!("Inspection camera unavailable: {:#}", e),
+            }
+        }
+        SubsystemBarrier::new(self.state.clone(), BarrierConfig::from_config(&config)).with_inspection(inspection)
+    }
+
+    async fn verifier(&self) -> FeedbackVerifier {
+        let config = self.config.read().await;
+        let settle = Duration::from_secs_f32(config.valve_array.response_time_ms.max(0.0) / 1000.0);
+        FeedbackVerifier::new(config.valve_array.verification.clone(), self.sensors.clone(), settle)
+    }
+
+    /// Records newly faulted thermal zones as errors and broadcasts them.
+    /// The heater controller has already disabled the zones; called by the
+    /// thermal task after each control step.
+    pub async fn report_thermal_faults(&self) {
+        let faults = self.heaters.lock().await.faults();
+        let mut messages = Vec::new();
+        let mut state = self.state.write().await;
+        for (zone, fault) in faults {
+            let system = format!("thermal_zone_{}", zone);
+            let known = state
+                .errors
+                .iter()
+                .any(|e| e.code == fault.code() && e.affected_systems.contains(&system));
+            if known {
+                continue;
+            }
+
+            let message = format!("Zone {} disabled: {}", zone, fault);
+            let error = SystemError::new(fault.code(), message.clone(), vec![system]);
+            let event = error.to_event();
+            state.add_error(error, &self.state_machine);
+            messages.push(message);
+
+            // No subscribers is not an error
+            self.status_tx.send(ProtocolMessage::ErrorEvent(event)).ok();
+        }
+        drop(state);
+        if let Some(job) = self.job.lock().await.as_mut() {
+            for message in messages {
+                job.record_error(message);
+            }
+        }
+    }
+
+    /// Publishes the tracked material consumption in the print status and
+    /// warns once about each channel deviating from its reference.
+    async fn report_consumption(&self, tracker: &mut ConsumptionTracker) {
+        let deviations = tracker.check();
+        let mut state = self.state.write().await;
+        if let Some(status) = state.print_status.as_mut() {
+            status.material_consumed = tracker.consumed_grams();
+        }
+        for deviation in deviations {
+            let message = deviation.to_string();
+            warn!("{}", message);
+            state.warnings.push(message.clone());
+
+            let affected = vec![format!("material_channel_{}", deviation.channel)];
+            // No subscribers is not an error
+            self.status_tx
+                .send(ProtocolMessage::ErrorEvent(protocol::ErrorEvent::new(
+                    deviation.kind.code(),
+                    message,
+                    affected,
+                )))
+                .ok();
+        }
+    }
+
+    /// Publishes the layer's valve pattern with the grid it sits on, for
+    /// heatmaps and the live valve view.
+    async fn report_valve_frame(&self, frame: &LayerFrame) {
+        let grid_size = {
+            let config = self.config.read().await;
+            (config.grid_x_count(), config.grid_y_count())
+        };
+        let update = {
+            let state = self.state.read().await;
+            protocol::ValveStateUpdate {
+                layer: frame.layer_number,
+                active_nodes: state.valves.active_nodes,
+                open_valves: state.valves.open_valves,
+                pattern_hash: format!("{:016x}", state.valves.pattern_hash),
+                grid_size: Some(grid_size),
+                frame: Some(frame.clone()),
+            }
+        };
+        // No subscribers is not an error
+        self.status_tx.send(ProtocolMessage::ValveStateUpdate(update)).ok();
+    }
+
+    /// Publishes the measured duration of a finished layer so the slicer can
+    /// recalibrate its time model.
+    fn report_layer_timing(
+        &self,
+        previous_z: f32,
+        frame: &LayerFrame,
+        cell_area: f32,
+        elapsed: Duration,
+        barrier_waits: Vec<protocol::BarrierWaitTiming>,
+    ) {
+        let report = protocol::LayerTimingReport::from_frame(previous_z, frame, cell_area, elapsed)
+            .with_barrier_waits(barrier_waits);
+        debug!(
+            "Layer {} took {} ms ({} valve switches, {} ms at pressure barriers)",
+            report.layer_number,
+            report.duration_ms,
+            report.valve_switches,
+            report.barrier_wait_ms("pressure")
+        );
+        // No subscribers is not an error
+        self.status_tx.send(ProtocolMessage::LayerTiming(report)).ok();
+    }
+}
+
+/// Closes every valve, vents the channels and zeroes every heater and
+/// pressure target. Each step is tried even if an earlier one failed;
+/// returns the failures.
+async fn shut_down(
+    valves: &Mutex<Box<dyn ValveController>>,
+    heaters: &Mutex<Box<dyn HeaterController>>,
+    pressure: &Mutex<Box<dyn PressureController>>,
+    state: &RwLock<SystemState>,
+) -> Vec<String> {
+    let mut failures = Vec::new();
+    if let Err(e) = valves.lock().await.emergency_close_all().await {
+        failures.push(format!("valves: {:#}", e));
+    }
+    if let Err(e) = pressure.lock().await.emergency_vent().await {
+        failures.push(format!("pressure: {:#}", e));
+    }
+    if let Err(e) = cool_down(heaters, pressure, state).await {
+        failures.push(format!("targets: {:#}", e));
+    }
+    failures
+}
+
+/// Sets every heater and pressure target in `state` to zero, on the
+/// controllers first.
+async fn cool_down(
+    heaters: &Mutex<Box<dyn HeaterController>>,
+    pressure: &Mutex<Box<dyn PressureController>>,
+    state: &RwLock<SystemState>,
+) -> Result<()> {
+    let (zones, bed, channels) = {
+        let state = state.read().await;
+        let zones: Vec<u8> = state.thermal.zones.keys().copied().collect();
+        let channels: Vec<u8> = state.pressure.channels.keys().copied().collect();
+        (zones, state.thermal.bed.is_some(), channels)
+    };
+    {
+        let mut heaters = heaters.lock().await;
+        for &zone in &zones {
+            heaters.set_temperature(zone, 0.0).await?;
+        }
+        if bed {
+            heaters.set_bed_temperature(0.0).await?;
+        }
+    }
+    {
+        let mut pressure = pressure.lock().await;
+        for &channel in &channels {
+            pressure.set_pressure(channel, 0.0).await?;
+        }
+    }
+    let mut state = state.write().await;
+    for target in state.thermal.zones.values_mut().map(|(_, target)| target) {
+        *target = 0.0;
+    }
+    if let Some((_, target)) = state.thermal.bed.as_mut() {
+        *target = 0.0;
+    }
+    for target in state.pressure.channels.values_mut().map(|(_, target)| target) {
+        *target = 0.0;
+    }
+    Ok(())
+}
+
+/// Hash of the open valves of a layer, for spotting pattern changes.
+fn pattern_hash(layer: &Layer) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    for node in &layer.nodes {
+        node.position.hash(&mut hasher);
+        for valve in node.valves.iter().filter(|v| v.open) {
+            valve.index.hash(&mut hasher);
+        }
+    }
+    hasher.finish()
+}
+
+/// Waits while the job is paused. Returns false once it is cancelled or
+/// the firmware has dropped the control channel.
+async fn wait_for_run(control: &mut watch::Receiver<JobControl>) -> bool {
+    loop {
+        match *control.borrow_and_update() {
+            JobControl::Run => return true,
+            JobControl::Cancel => return false,
+            JobControl::Pause => {}
+        }
+        if control.changed().await.is_err() {
+            return false;
+        }
+    }
+}
+
+/// G4D commands depositing a ramp layer as the slicer's helix laid it out:
+/// its nodes open in order as Z rises evenly from `start_z`, each closing
+/// as the next opens.
+fn ramp_deposits(layer: &Layer, start_z: f32, grid_spacing: f32) -> Vec<Command> {
+    let rise = layer.z_height - start_z;
+    let count = layer.nodes.len().max(1) as f32;
+    let deposit = |node: &NodeValveState, z: f32, open: bool| {
+        let position = node.position.to_physical(grid_spacing);
+        Command::G4D(G4DCommand {
+            position: Coordinate::new(position.x, position.y, z),
+            valves: node.valves.iter().filter(|v| v.open).map(|v| ValveState::new(v.index, open)).collect(),
+            extrusion: None,
+            close_early_ms: None,
+        })
+    };
+
+    let mut commands = Vec::with_capacity(layer.nodes.len() * 2);
+    for (k, node) in layer.nodes.iter().enumerate() {
+        let z = start_z + rise * k as f32 / count;
+        commands.push(deposit(node, z, true));
+        if k > 0 {
+            commands.push(deposit(&layer.nodes[k - 1], z, false));
+        }
+    }
+    if let Some(last) = layer.nodes.last() {
+        commands.push(deposit(last, layer.z_height, false));
+    }
+    commands
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ValveHealth;
+    use std::sync::Mutex as StdMutex;
+
+    /// Every call the job end makes, in order.
+    #[derive(Clone, Default)]
+    struct Calls(Arc<StdMutex<Vec<String>>>);
+
+    impl Calls {
+        fn push(&self, call: String) -> Result<()> {
+            self.0.lock().unwrap().push(call);
+            Ok(())
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl ValveController for Calls {
+        async fn set_valve_states(&mut self, _states: &[(GridCoordinate, Vec<ValveState>)]) -> Result<()> {
+            Ok(())
+        }
+        async fn get_valve_states(&self, _position: GridCoordinate) -> Result<Vec<ValveState>> {
+            Ok(Vec::new())
+        }
+        async fn health_check(&mut self) -> Result<Vec<ValveHealth>> {
+            Ok(Vec::new())
+        }
+        async fn emergency_close_all(&mut self) -> Result<()> {
+            self.push("close valves".to_string())
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl HeaterController for Calls {
+        async fn set_temperature(&mut self, zone_id: u8, target: f32) -> Result<()> {
+            self.push(format!("zone {} to {}", zone_id, target))
+        }
+        async fn get_temperature(&self, _zone_id: u8) -> Result<f32> {
+            Ok(0.0)
+        }
+        async fn update_control(&mut self) -> Result<()> {
+            Ok(())
+        }
+        async fn emergency_off(&mut self) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl PressureController for Calls {
+        async fn set_pressure(&mut self, channel_id: u8, target: f32) -> Result<()> {
+            self.push(format!("channel {} to {}", channel_id, target))
+        }
+        async fn get_pressure(&self, _channel_id: u8) -> Result<f32> {
+            Ok(0.0)
+        }
+        async fn get_flow_rate(&self, _channel_id: u8) -> Result<f32> {
+            Ok(0.0)
+        }
+        async fn update_control(&mut self) -> Result<()> {
+            Ok(())
+        }
+        async fn emergency_vent(&mut self) -> Result<()> {
+            self.push("vent".to_string())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_job_end_closes_vents_and_cools() {
+        let calls = Calls::default();
+        let valves: Mutex<Box<dyn ValveController>> = Mutex::new(Box::new(calls.clone()));
+        let heaters: Mutex<Box<dyn HeaterController>> = Mutex::new(Box::new(calls.clone()));
+        let pressure: Mutex<Box<dyn PressureController>> = Mutex::new(Box::new(calls.clone()));
+        let mut state = SystemState::new();
+        state.thermal.zones.insert(0, (231.0, 235.0));
+        state.pressure.channels.insert(1, (29.5, 30.0));
+        let state = RwLock::new(state);
+
+        assert!(shut_down(&valves, &heaters, &pressure, &state).await.is_empty());
+        assert_eq!(
+            *calls.0.lock().unwrap(),
+            vec!["close valves", "vent", "zone 0 to 0", "channel 1 to 0"]
+        );
+        let state = state.read().await;
+        assert_eq!(state.thermal.zones[&0], (231.0, 0.0));
+        assert_eq!(state.pressure.channels[&1], (29.5, 0.0));
+    }
+
+    #[tokio::test]
+    async fn test_job_holds_while_paused() {
+        let (tx, mut rx) = watch::channel(JobControl::Pause);
+        let waiter = tokio::spawn(async move { wait_for_run(&mut rx).await });
+        tokio::task::yield_now().await;
+        assert!(!waiter.is_finished());
+        tx.send(JobControl::Run).unwrap();
+        assert!(waiter.await.unwrap());
+
+        let (tx, mut rx) = watch::channel(JobControl::Pause);
+        tx.send(JobControl::Cancel).unwrap();
+        assert!(!wait_for_run(&mut rx).await);
+    }
+}
@@ -0,0 +1,137 @@
+//! Z fast-forward through empty layers.
+//!
+//! A layer in which no valve opens only moves Z. The print loop offers each
+//! decoded frame to [`ZFastForward::absorb`]; empty frames are collected
+//! instead of executed. Before the next layer that deposits, the collected
+//! run is released by [`ZFastForward::take_advance`] as one move to the
+//! run's last Z at the axis' maximum speed, replacing a layer cycle per
+//! empty layer. A run still pending when the file ends is dropped: nothing
+//! follows that needs the nozzle-free head to be there.
+
+use std::time::Duration;
+
+use config_types::ZAxisConfig;
+use gcode_types::LayerFrame;
+
+/// One combined Z move through a run of empty layers.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ZAdvance {
+    pub first_layer: u32,
+    pub last_layer: u32,
+    /// Target Z: the run's last layer
+    pub z: f32,
+    /// Move speed (mm/s)
+    pub speed: f32,
+}
+
+impl ZAdvance {
+    pub fn layer_count(&self) -> u32 {
+        self.last_layer - self.first_layer + 1
+    }
+}
+
+/// Collects empty layers into combined Z moves.
+#[derive(Debug, Clone)]
+pub struct ZFastForward {
+    max_speed: f32,
+    max_acceleration: f32,
+    pending: Option<ZAdvance>,
+}
+
+impl ZFastForward {
+    pub fn new(z_axis: &ZAxisConfig) -> Self {
+        Self {
+            max_speed: z_axis.max_speed,
+            max_acceleration: z_axis.max_acceleration,
+            pending: None,
+        }
+    }
+
+    /// Takes an empty frame into the pending run. Returns false, leaving
+    /// the run untouched, for frames that deposit.
+    pub fn absorb(&mut self, frame: &LayerFrame) -> bool {
+        if !frame.is_empty() {
+            return false;
+        }
+        let speed = self.max_speed;
+        let run = self.pending.get_or_insert(ZAdvance {
+            first_layer: frame.layer_number,
+            last_layer: frame.layer_number,
+            z: frame.z_height,
+            speed,
+        });
+        run.last_layer = frame.layer_number;
+        run.z = frame.z_height;
+        true
+    }
+
+    /// Layers absorbed since the last advance.
+    pub fn pending_layers(&self) -> u32 {
+        self.pending.map_or(0, |run| run.layer_count())
+    }
+
+    /// Releases the pending run as one move.
+    pub fn take_advance(&mut self) -> Option<ZAdvance> {
+        self.pending.take()
+    }
+
+    /// Drops the pending run, e.g. at the end of the file or on cancel.
+    pub fn discard(&mut self) -> Option<ZAdvance> {
+        self.pending.take()
+    }
+
+    /// Time the move from `from_z` takes with a trapezoidal velocity
+    /// profile at the axis limits.
+    pub fn move_time(&self, from_z: f32, advance: &ZAdvance) -> Duration {
+        let distance = (advance.z - from_z).abs();
+        if distance <= 0.0 || self.max_speed <= 0.0 {
+            return Duration::ZERO;
+        }
+        let seconds = if self.max_acceleration <= 0.0 {
+            distance / self.max_speed
+        } else if distance < self.max_speed * self.max_speed / self.max_acceleration {
+            // Never reaches max speed
+            2.0 * (distance / self.max_acceleration).sqrt()
+        } else {
+            distance / self.max_speed + self.max_speed / self.max_acceleration
+        };
+        Duration::from_secs_f32(seconds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gcode_types::{GridCoordinate, Layer, NodeValveState, ValveState};
+
+    fn frame(n: u32, open: bool) -> LayerFrame {
+        let mut layer = Layer::new(0.2 * (n + 1) as f32, n);
+        layer.add_node(NodeValveState::new(GridCoordinate::new(2, 3), vec![ValveState::new(0, open)]));
+        LayerFrame::from_layer(&layer).unwrap()
+    }
+
+    #[test]
+    fn test_empty_layers_collapse_into_one_move() {
+        let mut ff = ZFastForward {
+            max_speed: 10.0,
+            max_acceleration: 100.0,
+            pending: None,
+        };
+        assert!(!ff.absorb(&frame(0, true)));
+        assert!(ff.take_advance().is_none());
+
+        for n in 1..=4 {
+            assert!(ff.absorb(&frame(n, false)));
+        }
+        assert_eq!(ff.pending_layers(), 4);
+        assert!(!ff.absorb(&frame(5, true)));
+
+        let advance = ff.take_advance().unwrap();
+        assert_eq!((advance.first_layer, advance.last_layer, advance.speed), (1, 4, 10.0));
+        assert!((advance.z - 1.0).abs() < 1e-6);
+        assert_eq!(ff.pending_layers(), 0);
+        // 0.8mm is below the 1mm needed to reach full speed
+        let expected = 2.0 * (0.8f32 / 100.0).sqrt();
+        assert!((ff.move_time(0.2, &advance).as_secs_f32() - expected).abs() < 1e-4);
+    }
+}
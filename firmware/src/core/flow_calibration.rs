@@ -0,0 +1,268 @@
+//! Per-channel flow calibration.
+//!
+//! The wizard prints one square test patch per channel, a few layers high,
+//! so each patch holds a known nominal volume. The operator weighs each
+//! patch and submits the mass; the expected mass follows from the volume
+//! and the loaded material's density. The corrected multiplier is
+//!
+//! ```text
+//! new = current · expected_mass / measured_mass
+//! ```
+//!
+//! since the patch was deposited with the current multiplier. Results are
+//! written to `ExtrusionParameters::flow_multiplier` of the loaded material
+//! profiles once the operator applies them.
+
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+use tracing::warn;
+
+use config_types::PrinterConfig;
+use gcode_types::{GridCoordinate, Layer, NodeValveState, ValveState};
+use protocol::FlowCalibrationChannel;
+
+use super::MaterialRegistry;
+use crate::FirmwareError;
+
+/// Multipliers outside this range mean a mis-weighed or failed patch rather
+/// than a miscalibrated channel.
+pub const FLOW_MULTIPLIER_RANGE: (f32, f32) = (0.5, 2.0);
+
+/// Geometry of the test patches.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CalibrationPattern {
+    /// Patch side length (mm)
+    pub patch_mm: f32,
+    pub layers: u32,
+    pub layer_height: f32,
+}
+
+impl Default for CalibrationPattern {
+    fn default() -> Self {
+        Self {
+            patch_mm: 20.0,
+            layers: 10,
+            layer_height: 0.2,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct ChannelPatch {
+    origin: GridCoordinate,
+    side_nodes: u32,
+    material: String,
+    expected_mass_g: f32,
+    /// Multiplier the patch was printed with
+    multiplier: f32,
+    measured_mass_g: Option<f32>,
+}
+
+impl ChannelPatch {
+    fn new_multiplier(&self) -> Option<f32> {
+        self.measured_mass_g
+            .map(|measured| self.multiplier * self.expected_mass_g / measured)
+    }
+}
+
+/// One calibration run over a set of channels.
+#[derive(Debug, Clone)]
+pub struct FlowCalibration {
+    pattern: CalibrationPattern,
+    patches: BTreeMap<u8, ChannelPatch>,
+}
+
+impl FlowCalibration {
+    /// Lays out one patch per channel in a row along X, a patch width apart.
+    ///
+    /// Every channel needs a loaded material, whose density gives the
+    /// expected mass.
+    pub fn plan(
+        config: &PrinterConfig,
+        materials: &MaterialRegistry,
+        channels: &[u8],
+        pattern: CalibrationPattern,
+    ) -> Result<Self> {
+        if channels.is_empty() {
+            return Err(FirmwareError::InvalidCommand("No channels to calibrate".to_string()).into());
+        }
+        if !(pattern.patch_mm > 0.0 && pattern.layer_height > 0.0 && pattern.layers > 0) {
+            return Err(FirmwareError::InvalidCommand(format!(
+                "Invalid calibration pattern {:?}",
+                pattern
+            ))
+            .into());
+        }
+
+        let spacing = config.valve_array.grid_spacing;
+        let side_nodes = ((pattern.patch_mm / spacing).round() as u32).max(1);
+        let side_mm = side_nodes as f32 * spacing;
+        let volume_mm3 = side_mm * side_mm * pattern.layer_height * pattern.layers as f32;
+
+        let needed = side_nodes * (2 * channels.len() as u32 + 1);
+        if needed > config.grid_x_count() || side_nodes * 3 > config.grid_y_count() {
+            return Err(FirmwareError::InvalidCommand(format!(
+                "{} patches of {:.1}mm do not fit the build plate",
+                channels.len(),
+                side_mm
+            ))
+            .into());
+        }
+        let y = (config.grid_y_count() - side_nodes) / 2;
+
+        let mut patches = BTreeMap::new();
+        for (i, &channel) in channels.iter().enumerate() {
+            let profile = materials.get(channel).ok_or_else(|| {
+                FirmwareError::InvalidCommand(format!("No material loaded on channel {}", channel))
+            })?;
+            let origin = GridCoordinate::new(side_nodes * (2 * i as u32 + 1), y);
+            patches.insert(
+                channel,
+                ChannelPatch {
+                    origin,
+                    side_nodes,
+                    material: profile.name.clone(),
+                    // g/cm³ → g/mm³
+                    expected_mass_g: volume_mm3 * profile.properties.density / 1000.0,
+                    multiplier: profile.extrusion.flow_multiplier,
+                    measured_mass_g: None,
+                },
+            );
+        }
+
+        Ok(Self { pattern, patches })
+    }
+
+    /// Layers that print the test patches.
+    pub fn layers(&self) -> Vec<Layer> {
+        (0..self.pattern.layers)
+            .map(|n| {
+                let mut layer = Layer::new((n + 1) as f32 * self.pattern.layer_height, n);
+                for (&channel, patch) in &self.patches {
+                    for dy in 0..patch.side_nodes {
+                        for dx in 0..patch.side_nodes {
+                            let position = GridCoordinate::new(patch.origin.x + dx, patch.origin.y + dy);
+                            layer.add_node(
+                                NodeValveState::new(position, vec![ValveState::open(0)]).with_material(channel),
+                            );
+                        }
+                    }
+                }
+                layer
+            })
+            .collect()
+    }
+
+    /// Records the weighed mass of a channel's patch.
+    pub fn record(&mut self, channel: u8, mass_g: f32) -> Result<()> {
+        let patch = self.patches.get_mut(&channel).ok_or_else(|| {
+            FirmwareError::InvalidCommand(format!("Channel {} is not being calibrated", channel))
+        })?;
+        if !(mass_g.is_finite() && mass_g > 0.0) {
+            return Err(FirmwareError::InvalidCommand(format!("Invalid mass {} g", mass_g)).into());
+        }
+
+        let multiplier = patch.multiplier * patch.expected_mass_g / mass_g;
+        let (min, max) = FLOW_MULTIPLIER_RANGE;
+        if !(min..=max).contains(&multiplier) {
+            return Err(FirmwareError::InvalidCommand(format!(
+                "Channel {}: {:.2} g against {:.2} g expected gives multiplier {:.2}; \
+                 check the scale and the printed patch",
+                channel, mass_g, patch.expected_mass_g, multiplier
+            ))
+            .into());
+        }
+        patch.measured_mass_g = Some(mass_g);
+        Ok(())
+    }
+
+    /// True once every patch has been weighed.
+    pub fn is_complete(&self) -> bool {
+        self.patches.values().all(|p| p.measured_mass_g.is_some())
+    }
+
+    /// Calibration state per channel.
+    pub fn status(&self) -> Vec<FlowCalibrationChannel> {
+        self.patches
+            .iter()
+            .map(|(&channel, patch)| FlowCalibrationChannel {
+                channel,
+                material: patch.material.clone(),
+                expected_mass_g: patch.expected_mass_g,
+                measured_mass_g: patch.measured_mass_g,
+                previous_multiplier: patch.multiplier,
+                new_multiplier: patch.new_multiplier(),
+            })
+            .collect()
+    }
+
+    /// Writes the new multipliers of weighed channels into the loaded
+    /// material profiles. Returns the channels updated.
+    ///
+    /// A channel whose material was swapped since the patch was printed is
+    /// skipped; its measurement describes the old material.
+    pub fn apply(&self, materials: &mut MaterialRegistry) -> Vec<u8> {
+        let mut updated = Vec::new();
+        for (&channel, patch) in &self.patches {
+            let Some(multiplier) = patch.new_multiplier() else {
+                continue;
+            };
+            match materials.get_mut(channel) {
+                Some(profile) if profile.name == patch.material => {
+                    profile.extrusion.flow_multiplier = multiplier;
+                    updated.push(channel);
+                }
+                _ => warn!(
+                    "Channel {} no longer holds {}; flow calibration not applied",
+                    channel,
+                    patch.material
+                ),
+            }
+        }
+        updated
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn patch(expected_mass_g: f32, multiplier: f32) -> FlowCalibration {
+        let mut patches = BTreeMap::new();
+        patches.insert(
+            0,
+            ChannelPatch {
+                origin: GridCoordinate::new(40, 40),
+                side_nodes: 40,
+                material: "PLA".to_string(),
+                expected_mass_g,
+                multiplier,
+                measured_mass_g: None,
+            },
+        );
+        FlowCalibration {
+            pattern: CalibrationPattern::default(),
+            patches,
+        }
+    }
+
+    #[test]
+    fn test_multiplier_from_measured_mass() {
+        let mut calibration = patch(1.0, 1.1);
+        assert!(!calibration.is_complete());
+        assert!(calibration.record(1, 1.0).is_err());
+        // A tenth of the expected mass is a failed patch, not a calibration
+        assert!(calibration.record(0, 0.1).is_err());
+
+        calibration.record(0, 1.25).unwrap();
+        assert!(calibration.is_complete());
+        let status = calibration.status();
+        assert!((status[0].new_multiplier.unwrap() - 0.88).abs() < 1e-4);
+
+        let layers = calibration.layers();
+        assert_eq!(layers.len(), 10);
+        assert_eq!(layers[0].nodes.len(), 1600);
+        assert_eq!(layers[0].nodes[0].material_channel, Some(0));
+    }
+}
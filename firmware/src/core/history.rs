@@ -0,0 +1,265 @@
+//! Persistent print history.
+//!
+//! Every print that ends, whichever way, becomes a [`PrintJobRecord`] in a
+//! sled database kept next to the print directory. Records are keyed by a
+//! big-endian job id from the database's id generator, so iteration in key
+//! order is start order and newest-first queries walk the tree backwards.
+//!
+//! While a job runs, a [`JobRecorder`] accumulates what the record needs:
+//! layers reached, material deposited per channel, errors raised and
+//! samples of how far the heated zones sat from their targets.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+
+use gcode_types::LayerFrame;
+use protocol::{HistoryQuery, HistoryStats, JobResult, PrintJobRecord};
+
+use crate::ThermalState;
+
+/// Default location of the history database.
+pub const DEFAULT_HISTORY_PATH: &str = "/var/hypergcode/history";
+
+/// Maximum jobs returned by a single query.
+pub const MAX_QUERY_RESULTS: usize = 1_000;
+
+/// Print history database.
+pub struct PrintHistory {
+    db: sled::Db,
+    jobs: sled::Tree,
+}
+
+impl PrintHistory {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let db = sled::open(path).with_context(|| format!("Failed to open print history {}", path.display()))?;
+        let jobs = db.open_tree("jobs")?;
+        Ok(Self { db, jobs })
+    }
+
+    /// Stores a finished job, assigning its id.
+    pub fn record(&self, mut job: PrintJobRecord) -> Result<PrintJobRecord> {
+        job.id = self.db.generate_id()?;
+        let value = serde_json::to_vec(&job).context("Failed to serialize job record")?;
+        self.jobs.insert(job.id.to_be_bytes(), value)?;
+        self.jobs.flush()?;
+        Ok(job)
+    }
+
+    pub fn get(&self, id: u64) -> Result<Option<PrintJobRecord>> {
+        self.jobs
+            .get(id.to_be_bytes())?
+            .map(|value| decode(&value))
+            .transpose()
+    }
+
+    /// Jobs matching the query, newest first.
+    pub fn query(&self, query: &HistoryQuery) -> Result<Vec<PrintJobRecord>> {
+        let limit = query.limit.unwrap_or(MAX_QUERY_RESULTS).min(MAX_QUERY_RESULTS);
+        let mut jobs = Vec::new();
+        for entry in self.jobs.iter().rev() {
+            if jobs.len() >= limit {
+                break;
+            }
+            let (_, value) = entry?;
+            let job = decode(&value)?;
+            if query.matches(&job) {
+                jobs.push(job);
+            }
+        }
+        Ok(jobs)
+    }
+
+    /// Totals over every job except dry runs.
+    pub fn stats(&self) -> Result<HistoryStats> {
+        let mut stats = HistoryStats::default();
+        let mut deviations = Vec::new();
+        for entry in self.jobs.iter() {
+            let (_, value) = entry?;
+            let job = decode(&value)?;
+            if job.dry_run {
+                continue;
+            }
+
+            stats.total_jobs += 1;
+            match job.result {
+                JobResult::Completed => stats.completed += 1,
+                JobResult::Cancelled => stats.cancelled += 1,
+                JobResult::Failed | JobResult::EmergencyStopped => stats.failed += 1,
+            }
+            stats.total_print_time_s += job.duration_s;
+            for (channel, volume) in &job.filament_mm3 {
+                *stats.filament_mm3.entry(*channel).or_default() += volume;
+            }
+            deviations.extend(job.thermal_deviation);
+        }
+
+        if stats.total_jobs > 0 {
+            stats.success_rate = stats.completed as f32 / stats.total_jobs as f32;
+        }
+        if !deviations.is_empty() {
+            stats.mean_thermal_deviation = Some(deviations.iter().sum::<f32>() / deviations.len() as f32);
+        }
+        Ok(stats)
+    }
+
+    /// Removes a job; false if there was none with that id.
+    pub fn remove(&self, id: u64) -> Result<bool> {
+        let removed = self.jobs.remove(id.to_be_bytes())?.is_some();
+        self.jobs.flush()?;
+        Ok(removed)
+    }
+}
+
+fn decode(value: &[u8]) -> Result<PrintJobRecord> {
+    serde_json::from_slice(value).context("Corrupt print history record")
+}
+
+/// Accumulates a running job's record.
+#[derive(Debug, Clone)]
+pub struct JobRecorder {
+    file_name: String,
+    started_ms: u64,
+    clock: Instant,
+    total_layers: u32,
+    layers_printed: u32,
+    dry_run: bool,
+    /// Deposited volume per open node per mm of layer height
+    cell_area: f32,
+    previous_z: f32,
+    filament: BTreeMap<u8, f64>,
+    errors: Vec<String>,
+    deviation_sum: f64,
+    deviation_samples: u64,
+}
+
+impl JobRecorder {
+    pub fn new(file: &Path, total_layers: u32, dry_run: bool, grid_spacing: f32) -> Self {
+        let started_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0);
+        Self {
+            file_name: file
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+                .unwrap_or_else(|| file.display().to_string()),
+            started_ms,
+            clock: Instant::now(),
+            total_layers,
+            layers_printed: 0,
+            dry_run,
+            cell_area: grid_spacing * grid_spacing,
+            previous_z: 0.0,
+            filament: BTreeMap::new(),
+            errors: Vec::new(),
+            deviation_sum: 0.0,
+            deviation_samples: 0,
+        }
+    }
+
+    /// Counts a deposited layer: one cell of material per open node, on
+    /// the node's channel (or the layer's primary channel). Dry runs
+    /// deposit nothing.
+    pub fn record_layer(&mut self, frame: &LayerFrame) {
+        let height = (frame.z_height - self.previous_z).max(0.0);
+        self.previous_z = frame.z_height;
+        self.layers_printed = self.layers_printed.max(frame.layer_number + 1);
+        if self.dry_run {
+            return;
+        }
+
+        let volume = (self.cell_area * height) as f64;
+        for node in frame.nodes().filter(|n| n.open_count() > 0) {
+            let channel = node.material_channel.or(frame.primary_material).unwrap_or(0);
+            *self.filament.entry(channel).or_default() += volume;
+        }
+    }
+
+    pub fn record_error(&mut self, message: impl Into<String>) {
+        self.errors.push(message.into());
+    }
+
+    /// Adds the deviation of every heated zone from its target.
+    pub fn sample_thermal(&mut self, thermal: &ThermalState) {
+        let readings = thermal
+            .zones
+            .values()
+            .copied()
+            .chain(thermal.manifold)
+            .chain(thermal.bed)
+            .chain(thermal.chamber)
+            .filter(|&(_, target)| target > 0.0);
+        for (current, target) in readings {
+            self.deviation_sum += (current - target).abs() as f64;
+            self.deviation_samples += 1;
+        }
+    }
+
+    /// The record for the ended job; its id is assigned when stored.
+    pub fn finish(self, result: JobResult) -> PrintJobRecord {
+        PrintJobRecord {
+            id: 0,
+            file_name: self.file_name,
+            started_ms: self.started_ms,
+            duration_s: self.clock.elapsed().as_secs_f64(),
+            result,
+            layers_printed: self.layers_printed,
+            total_layers: self.total_layers,
+            dry_run: self.dry_run,
+            filament_mm3: self.filament.into_iter().map(|(c, v)| (c, v as f32)).collect(),
+            errors: self.errors,
+            thermal_deviation: (self.deviation_samples > 0)
+                .then(|| (self.deviation_sum / self.deviation_samples as f64) as f32),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gcode_types::{GridCoordinate, Layer, NodeValveState, ValveState};
+
+    #[test]
+    fn test_history_round_trip_and_stats() {
+        let dir = std::env::temp_dir().join(format!("hg4d-history-{}", std::process::id()));
+        let history = PrintHistory::open(&dir).unwrap();
+
+        let mut job = JobRecorder::new(Path::new("/prints/bracket.hg4d"), 2, false, 0.5);
+        let mut layer = Layer::new(0.2, 0);
+        for x in 0..10 {
+            layer.add_node(NodeValveState::new(GridCoordinate::new(x, 0), vec![ValveState::open(0)]).with_material(1));
+        }
+        job.record_layer(&LayerFrame::from_layer(&layer).unwrap());
+        let mut thermal = ThermalState::new();
+        thermal.zones.insert(0, (208.0, 210.0));
+        job.sample_thermal(&thermal);
+        let first = history.record(job.finish(JobResult::Completed)).unwrap();
+        assert_eq!(first.file_name, "bracket.hg4d");
+        assert!((first.filament_mm3[&1] - 10.0 * 0.25 * 0.2).abs() < 1e-5);
+
+        let mut failed = JobRecorder::new(Path::new("gear.hg4d"), 5, false, 0.5);
+        failed.record_error("Pressure fault on channel 0");
+        let second = history.record(failed.finish(JobResult::Failed)).unwrap();
+        history.record(JobRecorder::new(Path::new("gear.hg4d"), 5, true, 0.5).finish(JobResult::Completed)).unwrap();
+
+        let newest = history.query(&HistoryQuery { limit: Some(2), ..Default::default() }).unwrap();
+        assert_eq!(newest[1].id, second.id);
+        let failures = history.query(&HistoryQuery { result: Some(JobResult::Failed), ..Default::default() }).unwrap();
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].errors, vec!["Pressure fault on channel 0".to_string()]);
+
+        let stats = history.stats().unwrap();
+        assert_eq!((stats.total_jobs, stats.completed, stats.failed), (2, 1, 1));
+        assert_eq!(stats.success_rate, 0.5);
+        assert_eq!(stats.mean_thermal_deviation, Some(2.0));
+
+        assert!(history.remove(first.id).unwrap());
+        assert!(history.get(first.id).unwrap().is_none());
+        drop(history);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,176 @@
+//! Inspection pauses.
+//!
+//! The slicer ends a layer with `G4W INSPECT` where the operator asked to
+//! look at the part. When the [`SubsystemBarrier`](super::SubsystemBarrier)
+//! reaches one, it hands it to [`InspectionPause::hold`], which:
+//!
+//! 1. Posts the pause to the camera webhook, if a camera is configured
+//! 2. Broadcasts `ProtocolMessage::InspectionPause`
+//! 3. Waits, without timeout, until the [`InspectionGate`] is released by
+//!    `ResumePrint`
+//!
+//! Heaters and pressure stay at their targets throughout; the caller moves
+//! the firmware to `Paused` and back around the hold so the usual resume
+//! guards apply.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use tokio::sync::{broadcast, Notify};
+use tracing::{info, warn};
+
+use config_types::CameraConfig;
+use protocol::{InspectionPauseEvent, ProtocolMessage};
+
+/// Resume signal shared between the print loop and the command handler.
+#[derive(Debug, Clone, Default)]
+pub struct InspectionGate {
+    resume: Arc<Notify>,
+    holding: Arc<AtomicBool>,
+}
+
+impl InspectionGate {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A print is waiting at an inspection pause.
+    pub fn is_holding(&self) -> bool {
+        self.holding.load(Ordering::Acquire)
+    }
+
+    /// Ends the current hold. Returns false, and does nothing, if no print
+    /// is holding, so a stray resume cannot skip the next pause.
+    pub fn release(&self) -> bool {
+        if !self.holding.load(Ordering::Acquire) {
+            return false;
+        }
+        self.resume.notify_one();
+        true
+    }
+
+    async fn wait(&self) {
+        self.holding.store(true, Ordering::Release);
+        self.resume.notified().await;
+        self.holding.store(false, Ordering::Release);
+    }
+}
+
+/// Body posted to the camera webhook.
+#[derive(Debug, Serialize)]
+struct CameraTriggerRequest<'a> {
+    event: &'static str,
+    #[serde(flatten)]
+    pause: &'a InspectionPauseEvent,
+}
+
+/// Triggers an external camera over HTTP.
+pub struct CameraWebhook {
+    client: reqwest::Client,
+    url: String,
+}
+
+impl CameraWebhook {
+    pub fn new(config: &CameraConfig) -> Result<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_millis(config.timeout_ms as u64))
+            .build()
+            .context("Failed to create camera webhook client")?;
+        Ok(Self {
+            client,
+            url: config.webhook_url.clone(),
+        })
+    }
+
+    /// Posts the pause; fails on a transport error or a non-success status.
+    pub async fn trigger(&self, pause: &InspectionPauseEvent) -> Result<()> {
+        let body = CameraTriggerRequest {
+            event: "inspection_pause",
+            pause,
+        };
+        self.client
+            .post(&self.url)
+            .json(&body)
+            .send()
+            .await
+            .with_context(|| format!("Camera webhook {} unreachable", self.url))?
+            .error_for_status()
+            .with_context(|| format!("Camera webhook {} rejected the trigger", self.url))?;
+        Ok(())
+    }
+}
+
+/// Carries out inspection pauses for the print loop.
+pub struct InspectionPause {
+    gate: InspectionGate,
+    camera: Option<CameraWebhook>,
+    status_tx: broadcast::Sender<ProtocolMessage>,
+}
+
+impl InspectionPause {
+    pub fn new(gate: InspectionGate, status_tx: broadcast::Sender<ProtocolMessage>) -> Self {
+        Self {
+            gate,
+            camera: None,
+            status_tx,
+        }
+    }
+
+    pub fn with_camera(mut self, camera: CameraWebhook) -> Self {
+        self.camera = Some(camera);
+        self
+    }
+
+    /// Announces the pause and waits for the operator.
+    pub async fn hold(&self, layer_number: u32, z_height: f32) {
+        let mut event = InspectionPauseEvent {
+            layer_number,
+            z_height,
+            camera_triggered: None,
+        };
+        if let Some(camera) = &self.camera {
+            let triggered = camera.trigger(&event).await;
+            if let Err(e) = &triggered {
+                warn!("Inspection camera not triggered: {:#}", e);
+            }
+            event.camera_triggered = Some(triggered.is_ok());
+        }
+
+        info!("Holding for inspection after layer {} (Z {:.2}mm)", layer_number, z_height);
+        // No subscribers is not an error
+        self.status_tx.send(ProtocolMessage::InspectionPause(event)).ok();
+        self.gate.wait().await;
+        info!("Inspection after layer {} done; resuming", layer_number);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_hold_until_released() {
+        let gate = InspectionGate::new();
+        assert!(!gate.release());
+
+        let (tx, mut rx) = broadcast::channel(4);
+        let pause = InspectionPause::new(gate.clone(), tx);
+        let holding = tokio::spawn(async move { pause.hold(12, 2.5).await });
+
+        let ProtocolMessage::InspectionPause(event) = rx.recv().await.unwrap() else {
+            panic!("expected an inspection pause");
+        };
+        assert_eq!(event.layer_number, 12);
+        assert_eq!(event.camera_triggered, None);
+
+        while !gate.is_holding() {
+            tokio::task::yield_now().await;
+        }
+        assert!(gate.release());
+        holding.await.unwrap();
+        assert!(!gate.is_holding());
+    }
+}
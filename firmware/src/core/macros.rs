@@ -0,0 +1,286 @@
+//! Operator macros.
+//!
+//! Macros are named step sequences in the printer configuration (see
+//! [`MacroDefinition`]) for routines such as loading material or priming a
+//! channel. [`resolve`] substitutes the caller's arguments, falling back to
+//! declared defaults, and checks every resulting value against
+//! [`MacroLimits`] (the configured
+//! [`SafetyLimits`](config_types::SafetyLimits) and hardware) before
+//! anything moves, so a macro is either rejected whole or runs.
+//! `Firmware::run_macro` then executes the [`MacroAction`]s in order.
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use config_types::{MacroDefinition, MacroStep, MacroValue, PrinterConfig};
+use gcode_types::{GridCoordinate, Layer, NodeValveState, ValveState};
+
+use crate::FirmwareError;
+
+/// Longest dwell a macro step may request.
+pub const MAX_DWELL: Duration = Duration::from_secs(600);
+
+/// Purge strip width when the step sets none (mm).
+pub const DEFAULT_PURGE_WIDTH_MM: f32 = 2.0;
+
+/// Bounds macro values are checked against.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MacroLimits {
+    /// Settable range per thermal zone, capped at the safety maximum (°C)
+    pub zones: BTreeMap<u8, (f32, f32)>,
+    pub max_pressure: f32,
+    pub channel_count: u8,
+    /// Build height (mm)
+    pub max_z: f32,
+    /// Fastest Z move allowed (mm/s)
+    pub max_z_speed: f32,
+    /// Valve grid spacing (mm)
+    pub grid_spacing: f32,
+    /// Valve grid size in nodes (X, Y)
+    pub grid_size: (u32, u32),
+}
+
+impl MacroLimits {
+    pub fn from_config(config: &PrinterConfig) -> Self {
+        let max_temperature = config.safety.max_temperature.get();
+        Self {
+            zones: config
+                .thermal
+                .zones
+                .iter()
+                .map(|z| (z.id, (z.min_temp, z.max_temp.min(max_temperature))))
+                .collect(),
+            max_pressure: config.safety.max_pressure.get(),
+            channel_count: config.materials.channel_count,
+            max_z: config.build_volume.z,
+            max_z_speed: config.safety.max_z_speed.min(config.motion.z_axis.max_speed),
+            grid_spacing: config.valve_array.grid_spacing,
+            grid_size: (config.grid_x_count(), config.grid_y_count()),
+        }
+    }
+}
+
+/// Strip along the front edge of the plate that primes a channel.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PurgeStrip {
+    pub channel: u8,
+    /// Nodes along X, from the plate origin
+    pub x_nodes: u32,
+    /// Nodes along Y, from the plate origin
+    pub y_nodes: u32,
+}
+
+impl PurgeStrip {
+    /// Layer opening the strip's nodes at height `z`.
+    pub fn layer(&self, z: f32) -> Layer {
+        let mut layer = Layer::new(z, 0);
+        for y in 0..self.y_nodes {
+            for x in 0..self.x_nodes {
+                layer.add_node(
+                    NodeValveState::new(GridCoordinate::new(x, y), vec![ValveState::open(0)])
+                        .with_material(self.channel),
+                );
+            }
+        }
+        layer
+    }
+}
+
+/// Macro step with its arguments substituted and checked.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MacroAction {
+    SetTemperature { zone: u8, target: f32, wait: bool },
+    SetPressure { channel: u8, target: f32, wait: bool },
+    Dwell(Duration),
+    /// Absolute move at `speed` mm/s
+    MoveZ { z: f32, speed: f32 },
+    Purge(PurgeStrip),
+}
+
+/// Substitutes `args` into a macro's steps and validates the result.
+pub fn resolve(
+    definition: &MacroDefinition,
+    args: &BTreeMap<String, f32>,
+    limits: &MacroLimits,
+) -> Result<Vec<MacroAction>, FirmwareError> {
+    if let Some(unknown) = args.keys().find(|name| definition.parameter(name).is_none()) {
+        return Err(FirmwareError::InvalidCommand(format!(
+            "Macro '{}' has no parameter '{}'",
+            definition.name, unknown
+        )));
+    }
+
+    let value = |value: &MacroValue| -> Result<f32, FirmwareError> {
+        let number = match value {
+            MacroValue::Number(number) => *number,
+            MacroValue::Parameter(reference) => {
+                let name = MacroValue::parameter_name(reference).unwrap_or(reference);
+                args.get(name)
+                    .copied()
+                    .or_else(|| definition.parameter(name).and_then(|p| p.default))
+                    .ok_or_else(|| {
+                        FirmwareError::InvalidCommand(format!(
+                            "Macro '{}' needs a value for '{}'",
+                            definition.name, name
+                        ))
+                    })?
+            }
+        };
+        if !number.is_finite() {
+            return Err(FirmwareError::InvalidCommand(format!(
+                "Macro '{}' got non-finite value {}",
+                definition.name, number
+            )));
+        }
+        Ok(number)
+    };
+    let unsafe_step = |step: usize, message: String| {
+        FirmwareError::SafetyViolation(format!("Macro '{}' step {}: {}", definition.name, step + 1, message))
+    };
+    let check_channel = |step: usize, channel: u8| {
+        if channel >= limits.channel_count {
+            return Err(unsafe_step(
+                step,
+                format!("channel {} does not exist ({} channels)", channel, limits.channel_count),
+            ));
+        }
+        Ok(())
+    };
+
+    let mut actions = Vec::with_capacity(definition.steps.len());
+    for (i, step) in definition.steps.iter().enumerate() {
+        let action = match step {
+            MacroStep::SetTemperature { zone, target, wait } => {
+                let target = value(target)?;
+                let (min, max) = *limits
+                    .zones
+                    .get(zone)
+                    .ok_or_else(|| unsafe_step(i, format!("thermal zone {} does not exist", zone)))?;
+                // 0 switches the heater off
+                if target != 0.0 && !(min..=max).contains(&target) {
+                    return Err(unsafe_step(
+                        i,
+                        format!("{:.0}°C is outside zone {}'s range {:.0}-{:.0}°C", target, zone, min, max),
+                    ));
+                }
+                MacroAction::SetTemperature { zone: *zone, target, wait: *wait }
+            }
+            MacroStep::SetPressure { channel, target, wait } => {
+                check_channel(i, *channel)?;
+                let target = value(target)?;
+                if !(0.0..=limits.max_pressure).contains(&target) {
+                    return Err(unsafe_step(
+                        i,
+                        format!("{:.1} PSI is outside 0-{:.1} PSI", target, limits.max_pressure),
+                    ));
+                }
+                MacroAction::SetPressure { channel: *channel, target, wait: *wait }
+            }
+            MacroStep::Dwell { ms } => {
+                let ms = value(ms)?;
+                if !(0.0..=MAX_DWELL.as_millis() as f32).contains(&ms) {
+                    return Err(unsafe_step(
+                        i,
+                        format!("dwell of {:.0} ms is outside 0-{} ms", ms, MAX_DWELL.as_millis()),
+                    ));
+                }
+                MacroAction::Dwell(Duration::from_millis(ms as u64))
+            }
+            MacroStep::MoveZ { z, feed_rate } => {
+                let z = value(z)?;
+                if !(0.0..=limits.max_z).contains(&z) {
+                    return Err(unsafe_step(i, format!("Z {:.2}mm is outside 0-{:.2}mm", z, limits.max_z)));
+                }
+                let max_speed = limits.max_z_speed;
+                let speed = feed_rate.as_ref().map(&value).transpose()?.unwrap_or(max_speed);
+                if !(speed > 0.0 && speed <= max_speed) {
+                    return Err(unsafe_step(
+                        i,
+                        format!("Z speed {:.1} mm/s is outside 0-{:.1} mm/s", speed, max_speed),
+                    ));
+                }
+                MacroAction::MoveZ { z, speed }
+            }
+            MacroStep::Purge { channel, length_mm, width_mm } => {
+                check_channel(i, *channel)?;
+                let length = value(length_mm)?;
+                let width = width_mm.as_ref().map(&value).transpose()?.unwrap_or(DEFAULT_PURGE_WIDTH_MM);
+                let spacing = limits.grid_spacing;
+                let strip = PurgeStrip {
+                    channel: *channel,
+                    x_nodes: (length / spacing).round().max(0.0) as u32,
+                    y_nodes: (width / spacing).round().max(0.0) as u32,
+                };
+                if strip.x_nodes == 0
+                    || strip.y_nodes == 0
+                    || strip.x_nodes > limits.grid_size.0
+                    || strip.y_nodes > limits.grid_size.1
+                {
+                    return Err(unsafe_step(
+                        i,
+                        format!("purge strip of {:.1}x{:.1}mm does not fit the valve grid", length, width),
+                    ));
+                }
+                MacroAction::Purge(strip)
+            }
+        };
+        actions.push(action);
+    }
+    Ok(actions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use config_types::MacroParameter;
+
+    #[test]
+    fn test_resolve_substitutes_and_checks_limits() {
+        let limits = MacroLimits {
+            zones: BTreeMap::from([(0, (20.0, 280.0))]),
+            max_pressure: 50.0,
+            channel_count: 2,
+            max_z: 200.0,
+            max_z_speed: 10.0,
+            grid_spacing: 0.5,
+            grid_size: (400, 400),
+        };
+        let definition = MacroDefinition {
+            name: "load".to_string(),
+            description: String::new(),
+            parameters: vec![MacroParameter {
+                name: "temp".to_string(),
+                default: Some(220.0),
+                description: String::new(),
+            }],
+            steps: vec![
+                MacroStep::SetTemperature {
+                    zone: 0,
+                    target: MacroValue::Parameter("{temp}".to_string()),
+                    wait: true,
+                },
+                MacroStep::MoveZ { z: MacroValue::Number(10.0), feed_rate: None },
+                MacroStep::Purge { channel: 0, length_mm: MacroValue::Number(20.0), width_mm: None },
+            ],
+        };
+        definition.validate().unwrap();
+
+        let actions = resolve(&definition, &BTreeMap::new(), &limits).unwrap();
+        assert_eq!(actions[0], MacroAction::SetTemperature { zone: 0, target: 220.0, wait: true });
+        assert_eq!(actions[1], MacroAction::MoveZ { z: 10.0, speed: 10.0 });
+        let MacroAction::Purge(strip) = actions[2] else {
+            panic!("expected a purge");
+        };
+        assert_eq!((strip.x_nodes, strip.y_nodes), (40, 4));
+        assert_eq!(strip.layer(0.2).nodes.len(), 160);
+
+        let args = BTreeMap::from([("temp".to_string(), 240.0)]);
+        let actions = resolve(&definition, &args, &limits).unwrap();
+        assert_eq!(actions[0], MacroAction::SetTemperature { zone: 0, target: 240.0, wait: true });
+
+        let args = BTreeMap::from([("temp".to_string(), 300.0)]);
+        assert!(matches!(resolve(&definition, &args, &limits), Err(FirmwareError::SafetyViolation(_))));
+        let args = BTreeMap::from([("speed".to_string(), 1.0)]);
+        assert!(matches!(resolve(&definition, &args, &limits), Err(FirmwareError::InvalidCommand(_))));
+    }
+}
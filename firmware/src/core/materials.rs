@@ -0,0 +1,360 @@
+//! Materials loaded on each channel.
+//!
+//! The operator records which material profile is loaded on each channel
+//! (protocol `SetMaterial` or the REST API) when swapping spools. Before a
+//! print starts, the materials the file was sliced for are compared
+//! against the loaded ones:
+//!
+//! - **Errors** refuse the print: a channel the printer does not have, a
+//!   channel with nothing loaded, a different material type, a file
+//!   temperature outside the loaded material's range, or zones that cannot
+//!   heat the loaded materials (see [`validate_material_zones`])
+//! - **Warnings** are logged and the print continues: same type but a
+//!   different profile name, differing temperature ranges, or a spool past
+//!   its moisture exposure limit
+//!
+//! Each loaded spool accumulates exposure while the firmware runs (uptime,
+//! not wall time, since the printer enclosure is assumed open to ambient
+//! air only while powered). Exposure resets when a drying cycle completes
+//! (see [`super::drying`]).
+
+use std::collections::BTreeMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::Result;
+use tracing::warn;
+
+use config_types::{MaterialProfile, PrinterConfig};
+use protocol::{LoadedMaterial, MaterialExposureWarning, SpoolStatus};
+
+use crate::{validate_material_zones, FirmwareError};
+
+/// Temperature range difference (°C) below which profiles count as equal.
+const TEMP_RANGE_TOLERANCE: f32 = 5.0;
+
+/// Result of comparing a file's materials with the loaded ones.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MaterialCheck {
+    pub errors: Vec<String>,
+    pub warnings: Vec<String>,
+}
+
+impl MaterialCheck {
+    pub fn is_ok(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    /// Logs warnings and fails with `PrintRejected` if there are errors.
+    pub fn enforce(self) -> Result<()> {
+        for warning in &self.warnings {
+            warn!("Material mismatch: {}", warning);
+        }
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(FirmwareError::PrintRejected(self.errors.join("; ")).into())
+        }
+    }
+}
+
+/// Exposure bookkeeping for a loaded spool.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpoolState {
+    /// Seconds since the Unix epoch when the spool was loaded
+    pub loaded_at: u64,
+    /// Firmware uptime since loading or the last drying
+    pub exposure: Duration,
+    /// Exposure warning already sent for the current exposure
+    warned: bool,
+}
+
+impl SpoolState {
+    fn new() -> Self {
+        Self {
+            loaded_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+            exposure: Duration::ZERO,
+            warned: false,
+        }
+    }
+
+    pub fn exposure_hours(&self) -> f32 {
+        self.exposure.as_secs_f32() / 3600.0
+    }
+}
+
+/// Material profile loaded on each channel.
+#[derive(Debug, Clone, Default)]
+pub struct MaterialRegistry {
+    channels: BTreeMap<u8, MaterialProfile>,
+    spools: BTreeMap<u8, SpoolState>,
+}
+
+impl MaterialRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records a material as loaded on a channel, replacing any previous one.
+    ///
+    /// Fails if the printer has no such channel or the channel's zones
+    /// cannot reach the material's temperature range.
+    pub fn load(&mut self, config: &PrinterConfig, channel: u8, profile: MaterialProfile) -> Result<()> {
+        if channel >= config.materials.channel_count {
+            return Err(FirmwareError::InvalidCommand(format!(
+                "Channel {} does not exist (printer has {})",
+                channel, config.materials.channel_count
+            ))
+            .into());
+        }
+        validate_material_zones(config, &[(channel, &profile)])?;
+        self.channels.insert(channel, profile);
+        self.spools.insert(channel, SpoolState::new());
+        Ok(())
+    }
+
+    /// Marks a channel empty, returning the material that was loaded.
+    pub fn unload(&mut self, channel: u8) -> Option<MaterialProfile> {
+        self.spools.remove(&channel);
+        self.channels.remove(&channel)
+    }
+
+    pub fn spool(&self, channel: u8) -> Option<&SpoolState> {
+        self.spools.get(&channel)
+    }
+
+    /// Adds uptime to every loaded spool except the one being dried.
+    pub fn add_exposure(&mut self, elapsed: Duration, drying: Option<u8>) {
+        for (&channel, spool) in &mut self.spools {
+            if Some(channel) != drying {
+                spool.exposure += elapsed;
+            }
+        }
+    }
+
+    /// Resets a spool's exposure after drying.
+    pub fn mark_dried(&mut self, channel: u8) {
+        if let Some(spool) = self.spools.get_mut(&channel) {
+            spool.exposure = Duration::ZERO;
+            spool.warned = false;
+        }
+    }
+
+    /// Spools that went past their profile's exposure limit since the last
+    /// call. Each spool is reported once per exposure.
+    pub fn exposure_warnings(&mut self) -> Vec<MaterialExposureWarning> {
+        let mut warnings = Vec::new();
+        for (&channel, spool) in &mut self.spools {
+            let Some(profile) = self.channels.get(&channel) else {
+                continue;
+            };
+            let Some(limit) = profile.drying.as_ref().map(|d| d.max_exposure_hours) else {
+                continue;
+            };
+            if !spool.warned && spool.exposure_hours() > limit {
+                spool.warned = true;
+                warnings.push(MaterialExposureWarning {
+                    channel,
+                    material: profile.name.clone(),
+                    exposure_hours: spool.exposure_hours(),
+                    limit_hours: limit,
+                });
+            }
+        }
+        warnings
+    }
+
+    pub fn get(&self, channel: u8) -> Option<&MaterialProfile> {
+        self.channels.get(&channel)
+    }
+
+    pub fn get_mut(&mut self, channel: u8) -> Option<&mut MaterialProfile> {
+        self.channels.get_mut(&channel)
+    }
+
+    /// Loaded materials in channel order.
+    pub fn loaded(&self) -> Vec<LoadedMaterial> {
+        self.channels
+            .iter()
+            .map(|(&channel, profile)| LoadedMaterial {
+                channel,
+                profile: profile.clone(),
+                spool: self.spools.get(&channel).map(|spool| SpoolStatus {
+                    loaded_at: spool.loaded_at,
+                    exposure_hours: spool.exposure_hours(),
+                    limit_hours: profile.drying.as_ref().map(|d| d.max_exposure_hours),
+                    drying_remaining_s: None,
+                }),
+            })
+            .collect()
+    }
+
+    /// Compares the materials a file was sliced for (index = channel) with
+    /// the loaded ones.
+    pub fn check_print(&self, config: &PrinterConfig, file_materials: &[MaterialProfile]) -> MaterialCheck {
+        let mut check = MaterialCheck::default();
+
+        if file_materials.len() > config.materials.channel_count as usize {
+            check.errors.push(format!(
+                "File uses {} material channels but the printer has {}",
+                file_materials.len(),
+                config.materials.channel_count
+            ));
+            return check;
+        }
+
+        let mut channels = Vec::with_capacity(file_materials.len());
+        for (channel, expected) in file_materials.iter().enumerate() {
+            let channel = channel as u8;
+            let Some(loaded) = self.get(channel) else {
+                check.errors.push(format!(
+                    "Channel {} needs {} but no material is loaded",
+                    channel, expected.name
+                ));
+                continue;
+            };
+            compare(channel, expected, loaded, &mut check);
+            if let (Some(spool), Some(drying)) = (self.spools.get(&channel), &loaded.drying) {
+                if spool.exposure_hours() > drying.max_exposure_hours {
+                    check.warnings.push(format!(
+                        "Channel {}: {} has been loaded {:.0}h (limit {:.0}h) and should be dried",
+                        channel, loaded.name, spool.exposure_hours(), drying.max_exposure_hours
+                    ));
+                }
+            }
+            channels.push((channel, loaded));
+        }
+
+        if check.is_ok() {
+            if let Err(e) = validate_material_zones(config, &channels) {
+                check.errors.push(e.to_string());
+            }
+        }
+        check
+    }
+}
+
+fn compare(channel: u8, expected: &MaterialProfile, loaded: &MaterialProfile, check: &mut MaterialCheck) {
+    if expected.material_type != loaded.material_type {
+        check.errors.push(format!(
+            "Channel {} was sliced for {:?} ({}) but {:?} ({}) is loaded",
+            channel, expected.material_type, expected.name, loaded.material_type, loaded.name
+        ));
+        return;
+    }
+
+    let (min, max) = loaded.temp_range;
+    if expected.optimal_temp < min || expected.optimal_temp > max {
+        check.errors.push(format!(
+            "Channel {}: file prints {} at {:.0}°C, outside the loaded {} range {:.0}-{:.0}°C",
+            channel, expected.name, expected.optimal_temp, loaded.name, min, max
+        ));
+        return;
+    }
+
+    if expected.name != loaded.name {
+        check.warnings.push(format!(
+            "Channel {} was sliced for {} but {} is loaded",
+            channel, expected.name, loaded.name
+        ));
+    }
+    let (file_min, file_max) = expected.temp_range;
+    if (file_min - min).abs() > TEMP_RANGE_TOLERANCE || (file_max - max).abs() > TEMP_RANGE_TOLERANCE {
+        check.warnings.push(format!(
+            "Channel {}: file range {:.0}-{:.0}°C differs from loaded {:.0}-{:.0}°C",
+            channel, file_min, file_max, min, max
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use config_types::MaterialType;
+
+    fn profile(name: &str, material_type: MaterialType, range: (f32, f32)) -> MaterialProfile {
+        let mut profile: MaterialProfile = toml::from_str(
+            r#"
+            name = ""
+            material_type = "PLA"
+            temp_range = [0.0, 0.0]
+            optimal_temp = 0.0
+            bed_temp = 60.0
+            [properties]
+            density = 1.24
+            viscosity = 300.0
+            glass_transition_temp = 60.0
+            thermal_conductivity = 0.13
+            shrinkage = 0.3
+            [extrusion]
+            pressure_psi = 40.0
+            flow_multiplier = 1.0
+            retraction_distance = 0.5
+            retraction_speed = 25.0
+            [purge]
+            purge_volume_incoming = 15.0
+            purge_volume_outgoing = 15.0
+            [cooling]
+            min_layer_time = 8.0
+            requires_cooling = true
+            initial_fan_speed = 0.0
+            regular_fan_speed = 100.0
+            "#,
+        )
+        .unwrap();
+        profile.name = name.to_string();
+        profile.material_type = material_type;
+        profile.temp_range = range;
+        profile.optimal_temp = (range.0 + range.1) / 2.0;
+        profile
+    }
+
+    #[test]
+    fn test_compare_loaded_materials() {
+        let pla = profile("Generic PLA", MaterialType::PLA, (190.0, 220.0));
+        let mut check = MaterialCheck::default();
+
+        compare(0, &pla, &profile("Brand PLA", MaterialType::PLA, (185.0, 215.0)), &mut check);
+        assert!(check.is_ok());
+        assert_eq!(check.warnings.len(), 1);
+
+        compare(1, &pla, &profile("Generic PETG", MaterialType::PETG, (220.0, 250.0)), &mut check);
+        compare(2, &pla, &profile("Hot PLA", MaterialType::PLA, (210.0, 240.0)), &mut check);
+        assert_eq!(check.errors.len(), 2);
+        assert!(check.errors[0].contains("PETG"));
+        assert!(check.errors[1].contains("205°C"));
+
+        let err = check.enforce().unwrap_err();
+        assert!(err.to_string().starts_with("Print rejected"));
+    }
+
+    #[test]
+    fn test_spool_exposure_warning() {
+        let mut nylon = profile("Generic PA", MaterialType::PLA, (240.0, 270.0));
+        nylon.drying = Some(config_types::DryingParameters {
+            max_exposure_hours: 12.0,
+            temperature: 70.0,
+            hours: 8.0,
+        });
+        let mut registry = MaterialRegistry::new();
+        registry.channels.insert(0, nylon);
+        registry.spools.insert(0, SpoolState::new());
+        registry.channels.insert(1, profile("Generic PLA", MaterialType::PLA, (190.0, 220.0)));
+        registry.spools.insert(1, SpoolState::new());
+
+        registry.add_exposure(Duration::from_secs(10 * 3600), None);
+        assert!(registry.exposure_warnings().is_empty());
+        registry.add_exposure(Duration::from_secs(3 * 3600), Some(1));
+        let warnings = registry.exposure_warnings();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].channel, 0);
+        // Reported once, until dried
+        assert!(registry.exposure_warnings().is_empty());
+        assert_eq!(registry.spool(1).unwrap().exposure, Duration::from_secs(10 * 3600));
+
+        registry.mark_dried(0);
+        assert_eq!(registry.loaded()[0].spool.as_ref().unwrap().exposure_hours, 0.0);
+    }
+}
@@ -6,15 +6,69 @@
 //! ## Module Organization
 //!
 //! - **executor**: Main G-code execution engine
-//! - **state_machine**: Firmware state management
+//! - **state_machine**: Firmware state management with guarded transitions
 //! - **scheduler**: Command scheduling and timing
+//! - **barrier**: G4W barrier release against live subsystem state
+//! - **inspection**: Operator inspection holds with camera trigger
+//! - **dry_run**: Dry-run execution with heaters and pressure inhibited
+//! - **verification**: Valve feedback verification of deposited layers
+//! - **materials**: Materials loaded per channel and pre-print checks
+//! - **flow_calibration**: Per-channel flow multiplier calibration from weighed patches
+//! - **bed_level**: Bed flatness measurement and first-layer compensation
+//! - **drying**: Spool drying cycles on the chamber or bed heater
+//! - **history**: Persistent record of finished print jobs
+//! - **adjust**: Live flow, speed, temperature and pressure changes mid-print
+//! - **fast_forward**: One Z move through runs of empty layers
+//! - **consumption**: Per-channel material consumption and clog/leak warnings
+//! - **zone_power**: Parking heaters of zones with no upcoming deposition
+//! - **macros**: Operator routines from the configuration, checked against safety limits
+//! - **stagger**: Splitting dense layers into staggered sub-frames
+//! - **cooling**: Minimum layer time dwells and fan speed for cooling
+//! - **runout**: Material runout switches and the channel-aware pause and resume
+//! - **preheat**: Staggered heater starts timed to the first layer
 
 pub mod executor;
 pub mod state_machine;
 pub mod scheduler;
+pub mod barrier;
+pub mod inspection;
+pub mod dry_run;
+pub mod verification;
+pub mod materials;
+pub mod flow_calibration;
+pub mod bed_level;
+pub mod drying;
+pub mod history;
+pub mod adjust;
+pub mod fast_forward;
+pub mod consumption;
+pub mod zone_power;
+pub mod macros;
+pub mod stagger;
+pub mod cooling;
+pub mod runout;
+pub mod preheat;
 
 pub use executor::Executor;
-pub use state_machine::StateMachine;
-pub use scheduler::CommandScheduler;
+pub use state_machine::{StateChange, StateMachine, TransitionError};
+pub use scheduler::{CommandScheduler, LatchReset, MergeStats};
+pub use barrier::{BarrierConfig, SubsystemBarrier};
+pub use inspection::{CameraWebhook, InspectionGate, InspectionPause};
+pub use dry_run::ExecutionMode;
+pub use verification::{FeedbackVerifier, LayerVerification};
+pub use materials::{MaterialCheck, MaterialRegistry};
+pub use flow_calibration::{CalibrationPattern, FlowCalibration};
 
 
+pub use bed_level::{BedCompensation, BedLevelSession};
+pub use drying::DryingCycle;
+pub use history::{JobRecorder, PrintHistory};
+pub use adjust::{Adjustment, LiveAdjuster};
+pub use fast_forward::{ZAdvance, ZFastForward};
+pub use consumption::{ConsumptionDeviation, ConsumptionTracker, DeviationKind};
+pub use zone_power::{ZonePowerManager, ZoneTargetChange};
+pub use macros::{MacroAction, MacroLimits};
+pub use stagger::{StaggerSource, SubFramePlan, SubFramePlanner};
+pub use cooling::{CoolingPlan, CoolingPolicy};
+pub use runout::{RunoutChange, RunoutMonitor, RunoutPause, RunoutStatus};
+pub use preheat::{HeaterTarget, PreheatHeater, PreheatPlan, PreheatPlanner, PreheatStep};
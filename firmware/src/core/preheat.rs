@@ -0,0 +1,337 @@
+//! Staggered preheating ahead of the first layer.
+//!
+//! Heating every heater and only then loading the file and homing Z leaves
+//! the printer idle through work that could overlap the warm-up, and fast
+//! zones sit at temperature waiting for the bed or chamber. A
+//! [`PreheatPlan`] instead times each heater's ramp to end when the first
+//! layer is ready: once the preparation (file load and, see
+//! [`homing_time`], Z homing) is done, or later if a heater cannot make it
+//! by then.
+//!
+//! Each heater is a lumped thermal mass `C` (J/K) driven at full power `P`,
+//! derated by [`HEATING_EFFICIENCY`] for losses:
+//!
+//! ```text
+//! t = C·(T_target − T_now) / (η·P)
+//! ```
+//!
+//! `C` is the heater's `thermal_mass` from the configuration, or a default
+//! for its kind. With `ThermalConfig::supply_watts` set, ramps are placed
+//! longest first and a ramp that would draw more than the supply alongside
+//! those already placed is moved earlier, ending as the one it clashes with
+//! starts. Heaters already at or above their target get it at once.
+//!
+//! The plan is an estimate: the print still starts only once every heater
+//! reports its target.
+
+use std::fmt;
+use std::time::Duration;
+
+use tracing::warn;
+
+use config_types::{PrinterConfig, ThermalConfig};
+
+/// Share of heater power that goes into the heated mass.
+pub const HEATING_EFFICIENCY: f32 = 0.7;
+
+/// Heat capacity of a zone without `thermal_mass` (J/K): an aluminium
+/// heater block of about 250 g.
+pub const DEFAULT_ZONE_THERMAL_MASS: f32 = 250.0;
+
+/// Heat capacity of a bed without `thermal_mass` (J/K): a 6 mm aluminium
+/// plate of about 1.7 kg.
+pub const DEFAULT_BED_THERMAL_MASS: f32 = 1_500.0;
+
+/// Heat capacity of a chamber without `thermal_mass` (J/K), walls and
+/// panels included.
+pub const DEFAULT_CHAMBER_THERMAL_MASS: f32 = 12_000.0;
+
+/// A heater the planner schedules.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum PreheatHeater {
+    Zone(u8),
+    Bed,
+    Chamber,
+}
+
+impl fmt::Display for PreheatHeater {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PreheatHeater::Zone(id) => write!(f, "zone {}", id),
+            PreheatHeater::Bed => f.write_str("bed"),
+            PreheatHeater::Chamber => f.write_str("chamber"),
+        }
+    }
+}
+
+/// A heater's temperature now and for the print (°C).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HeaterTarget {
+    pub heater: PreheatHeater,
+    pub current: f32,
+    pub target: f32,
+}
+
+/// When to set one heater's target.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PreheatStep {
+    pub heater: PreheatHeater,
+    pub target: f32,
+    /// Since the plan began
+    pub start_at: Duration,
+    /// Estimated time to reach the target
+    pub ramp: Duration,
+}
+
+impl PreheatStep {
+    /// Since the plan began.
+    pub fn at_target(&self) -> Duration {
+        self.start_at + self.ramp
+    }
+}
+
+/// Heater targets to set as preparation runs; see the module documentation.
+#[derive(Debug, Clone)]
+pub struct PreheatPlan {
+    /// In start order
+    steps: Vec<PreheatStep>,
+    ready_at: Duration,
+    /// Heating everything first, then preparing
+    sequential: Duration,
+    next: usize,
+}
+
+impl PreheatPlan {
+    /// When every heater is expected at target, since the plan began.
+    pub fn ready_at(&self) -> Duration {
+        self.ready_at
+    }
+
+    pub fn steps(&self) -> &[PreheatStep] {
+        &self.steps
+    }
+
+    /// Time to the first layer saved over heating before preparing.
+    pub fn time_saved(&self) -> Duration {
+        self.sequential.saturating_sub(self.ready_at)
+    }
+
+    /// Steps due by `elapsed` since the plan began that have not been
+    /// taken yet.
+    pub fn take_due(&mut self, elapsed: Duration) -> &[PreheatStep] {
+        let first = self.next;
+        while self.steps.get(self.next).is_some_and(|s| s.start_at <= elapsed) {
+            self.next += 1;
+        }
+        &self.steps[first..self.next]
+    }
+
+    /// When the next step is due, or None once all have been taken.
+    pub fn next_start(&self) -> Option<Duration> {
+        self.steps.get(self.next).map(|s| s.start_at)
+    }
+}
+
+/// Plans preheat schedules from the printer's heaters.
+#[derive(Debug, Clone)]
+pub struct PreheatPlanner {
+    thermal: ThermalConfig,
+}
+
+impl PreheatPlanner {
+    pub fn new(printer: &PrinterConfig) -> Self {
+        Self::from_thermal(&printer.thermal)
+    }
+
+    pub fn from_thermal(thermal: &ThermalConfig) -> Self {
+        Self { thermal: thermal.clone() }
+    }
+
+    /// Power (W) and thermal mass (J/K) of a heater, or None if it is not
+    /// fitted.
+    fn heater(&self, heater: PreheatHeater) -> Option<(f32, f32)> {
+        match heater {
+            PreheatHeater::Zone(id) => self
+                .thermal
+                .zone(id)
+                .map(|z| (z.power_watts, z.thermal_mass.unwrap_or(DEFAULT_ZONE_THERMAL_MASS))),
+            PreheatHeater::Bed => self
+                .thermal
+                .bed
+                .as_ref()
+                .map(|b| (b.power_watts, b.thermal_mass.unwrap_or(DEFAULT_BED_THERMAL_MASS))),
+            PreheatHeater::Chamber => self
+                .thermal
+                .chamber
+                .as_ref()
+                .map(|c| (c.power_watts, c.thermal_mass.unwrap_or(DEFAULT_CHAMBER_THERMAL_MASS))),
+        }
+    }
+
+    /// Estimated time for a heater to go from `from` to `to` at full power;
+    /// zero if it is already there. None if the heater is not fitted.
+    pub fn ramp_time(&self, heater: PreheatHeater, from: f32, to: f32) -> Option<Duration> {
+        let (power, mass) = self.heater(heater)?;
+        let rise = (to - from).max(0.0);
+        Some(Duration::from_secs_f32(mass * rise / (HEATING_EFFICIENCY * power.max(1.0))))
+    }
+
+    /// Schedules the targets so every heater arrives together, as soon as
+    /// possible after `preparation`. Heaters that are not fitted are left
+    /// out.
+    pub fn plan(&self, targets: &[HeaterTarget], preparation: Duration) -> PreheatPlan {
+        let mut ramps: Vec<(HeaterTarget, f32, f32)> = targets
+            .iter()
+            .filter_map(|t| {
+                let Some((power, _)) = self.heater(t.heater) else {
+                    warn!("No {} heater to preheat", t.heater);
+                    return None;
+                };
+                let ramp = self.ramp_time(t.heater, t.current, t.target)?;
+                Some((*t, power, ramp.as_secs_f32()))
+            })
+            .collect();
+        // Longest first: they constrain the schedule most
+        ramps.sort_by(|a, b| b.2.total_cmp(&a.2));
+
+        // Ramps laid out backwards from a common end at 0 (s)
+        let supply = self.thermal.supply_watts.unwrap_or(f32::INFINITY);
+        let mut placed: Vec<(f32, f32, f32)> = Vec::new();
+        let mut windows = Vec::with_capacity(ramps.len());
+        for &(target, power, ramp) in &ramps {
+            if ramp <= 0.0 {
+                windows.push((target, None));
+                continue;
+            }
+            let mut end = 0.0f32;
+            loop {
+                let start = end - ramp;
+                let overlapping: Vec<&(f32, f32, f32)> =
+                    placed.iter().filter(|(s, e, _)| *s < end && *e > start).collect();
+                // Draw only steps up where a placed ramp starts
+                let load_at = |t: f32| -> f32 {
+                    overlapping.iter().filter(|(s, e, _)| *s <= t && t < *e).map(|(_, _, p)| p).sum()
+                };
+                let peak = overlapping
+                    .iter()
+                    .map(|(s, _, _)| s.max(start))
+                    .chain([start])
+                    .map(load_at)
+                    .fold(0.0, f32::max);
+                if overlapping.is_empty() || peak + power <= supply {
+                    break;
+                }
+                // End as the latest clashing ramp starts
+                end = overlapping.iter().map(|(s, _, _)| *s).fold(f32::NEG_INFINITY, f32::max);
+            }
+            placed.push((end - ramp, end, power));
+            windows.push((target, Some((end - ramp, ramp))));
+        }
+
+        let lead = placed.iter().map(|(s, _, _)| -s).fold(0.0, f32::max);
+        let ready_at = preparation.as_secs_f32().max(lead);
+        let mut steps: Vec<PreheatStep> = windows
+            .into_iter()
+            .map(|(target, window)| {
+                let (start_at, ramp) = match window {
+                    Some((start, ramp)) => ((ready_at + start).max(0.0), ramp),
+                    None => (0.0, 0.0),
+                };
+                PreheatStep {
+                    heater: target.heater,
+                    target: target.target,
+                    start_at: Duration::from_secs_f32(start_at),
+                    ramp: Duration::from_secs_f32(ramp),
+                }
+            })
+            .collect();
+        steps.sort_by_key(|s| (s.start_at, s.heater));
+
+        PreheatPlan {
+            steps,
+            ready_at: Duration::from_secs_f32(ready_at),
+            sequential: preparation + Duration::from_secs_f32(lead),
+            next: 0,
+        }
+    }
+}
+
+/// Worst-case time to home Z: the full build height at the homing speed.
+pub fn homing_time(printer: &PrinterConfig) -> Duration {
+    let speed = printer.motion.homing.homing_speed;
+    if speed > 0.0 {
+        Duration::from_secs_f32(printer.build_volume.z / speed)
+    } else {
+        Duration::ZERO
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use config_types::{BedHeating, PidParameters, ThermalZone};
+
+    fn zone(id: u8) -> ThermalZone {
+        ThermalZone {
+            id,
+            name: format!("zone{}", id),
+            min_temp: 20.0,
+            max_temp: 260.0,
+            power_watts: 40.0,
+            pid: PidParameters::default(),
+            heater_pin: None,
+            regions: vec![],
+            thermal_mass: Some(100.0),
+        }
+    }
+
+    fn secs(d: Duration) -> f32 {
+        d.as_secs_f32()
+    }
+
+    #[test]
+    fn test_ramps_end_together_within_supply() {
+        let mut thermal = ThermalConfig {
+            zones: vec![zone(0), zone(1)],
+            manifold: None,
+            chamber: None,
+            bed: Some(BedHeating {
+                power_watts: 300.0,
+                min_temp: 20.0,
+                max_temp: 110.0,
+                pid: PidParameters::default(),
+                heater_pin: None,
+                sensor_zone: 10,
+                thermal_mass: None,
+            }),
+            channel_zones: vec![],
+            supply_watts: None,
+        };
+        let targets = [
+            HeaterTarget { heater: PreheatHeater::Zone(0), current: 25.0, target: 205.0 },
+            HeaterTarget { heater: PreheatHeater::Zone(1), current: 25.0, target: 205.0 },
+            HeaterTarget { heater: PreheatHeater::Bed, current: 25.0, target: 60.0 },
+        ];
+        let zone_ramp = 100.0 * 180.0 / (0.7 * 40.0);
+        let bed_ramp = 1_500.0 * 35.0 / (0.7 * 300.0);
+
+        // Unlimited supply: the bed starts late enough to finish with the zones
+        let mut plan = PreheatPlanner::from_thermal(&thermal).plan(&targets, Duration::from_secs(60));
+        assert!((secs(plan.ready_at()) - zone_ramp).abs() < 0.01);
+        assert!((secs(plan.time_saved()) - 60.0).abs() < 0.01);
+        assert_eq!(plan.take_due(Duration::ZERO).len(), 2);
+        assert!(plan.take_due(Duration::from_secs(300)).is_empty());
+        let bed = plan.take_due(Duration::from_secs(400));
+        assert_eq!(bed[0].heater, PreheatHeater::Bed);
+        assert!((secs(bed[0].at_target()) - zone_ramp).abs() < 0.01);
+        assert_eq!(plan.next_start(), None);
+
+        // 350 W cannot run the bed beside both zones: it heats first
+        thermal.supply_watts = Some(350.0);
+        let plan = PreheatPlanner::from_thermal(&thermal).plan(&targets, Duration::from_secs(60));
+        assert!((secs(plan.ready_at()) - (zone_ramp + bed_ramp)).abs() < 0.01);
+        let order: Vec<PreheatHeater> = plan.steps().iter().map(|s| s.heater).collect();
+        assert_eq!(order, vec![PreheatHeater::Bed, PreheatHeater::Zone(0), PreheatHeater::Zone(1)]);
+        assert!((secs(plan.steps()[0].at_target()) - secs(plan.steps()[1].start_at)).abs() < 0.01);
+    }
+}
@@ -0,0 +1,214 @@
+//! Material runout.
+//!
+//! Channels with a runout switch (`MaterialSystemConfig::runout_sensors`)
+//! are polled by the [`RunoutMonitor`]. A switch that reads empty for its
+//! debounce time marks the channel in the shared [`RunoutStatus`] and hands
+//! a [`RunoutChange`] to the firmware (see `Firmware::handle_runout`),
+//! which during a print:
+//!
+//! 1. Pauses, then releases the pressure of that channel only, keeping its
+//!    target in a [`RunoutPause`]; the other channels stay pressurized
+//! 2. Broadcasts an `E-MAT-RUNOUT-04` warning and `MaterialRunout` at stage
+//!    `Paused`
+//! 3. Broadcasts stage `Loaded` once the switch reads material again
+//!
+//! `ResumePrint` is refused while the channel still reads empty. Otherwise
+//! `Firmware::resume_after_runout` restores the channel's pressure, waits
+//! for it and runs the sensor's prime macro (stage `Priming`) before the
+//! interrupted layer continues (stage `Resumed`).
+
+use std::collections::BTreeSet;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use tokio::sync::{broadcast, mpsc};
+use tokio::time::Instant;
+use tracing::{info, warn};
+
+use config_types::{MaterialSystemConfig, RunoutSensorConfig};
+use protocol::{MaterialRunoutEvent, RunoutStage};
+
+use crate::hardware::bus::{GpioProvider, InputPin};
+use crate::safety::interlocks::Debounce;
+
+/// Interval between switch reads.
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Channels whose runout switch reads empty, shared between the monitor
+/// and the firmware.
+#[derive(Debug, Clone, Default)]
+pub struct RunoutStatus(Arc<RwLock<BTreeSet<u8>>>);
+
+impl RunoutStatus {
+    pub fn empty_channels(&self) -> Vec<u8> {
+        self.0.read().unwrap().iter().copied().collect()
+    }
+
+    pub fn is_empty(&self, channel: u8) -> bool {
+        self.0.read().unwrap().contains(&channel)
+    }
+
+    fn set(&self, channel: u8, empty: bool) {
+        let mut set = self.0.write().unwrap();
+        if empty {
+            set.insert(channel);
+        } else {
+            set.remove(&channel);
+        }
+    }
+}
+
+/// A runout switch settled on empty or loaded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RunoutChange {
+    pub channel: u8,
+    pub empty: bool,
+}
+
+/// A print paused because a channel ran out.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RunoutPause {
+    pub channel: u8,
+    /// Material that was loaded on the channel
+    pub material: Option<String>,
+    /// Layer the print paused in
+    pub layer_number: u32,
+    /// Channel pressure to restore before priming (PSI)
+    pub pressure_target: f32,
+    /// Macro that re-primes the channel
+    pub prime_macro: Option<String>,
+}
+
+impl RunoutPause {
+    pub fn event(&self, stage: RunoutStage) -> MaterialRunoutEvent {
+        MaterialRunoutEvent {
+            channel: self.channel,
+            material: self.material.clone(),
+            layer_number: self.layer_number,
+            stage,
+        }
+    }
+}
+
+/// Polls the configured runout switches.
+pub struct RunoutMonitor {
+    sensors: Vec<RunoutSensorConfig>,
+    status: RunoutStatus,
+}
+
+impl RunoutMonitor {
+    /// Returns `None` if no runout switches are configured.
+    pub fn new(materials: &MaterialSystemConfig, status: RunoutStatus) -> Option<Self> {
+        if materials.runout_sensors.is_empty() {
+            return None;
+        }
+        Some(Self {
+            sensors: materials.runout_sensors.clone(),
+            status,
+        })
+    }
+
+    /// Opens every switch input, in configuration order.
+    pub fn open_pins(&self, gpio: &dyn GpioProvider) -> Result<Vec<Box<dyn InputPin>>> {
+        self.sensors
+            .iter()
+            .map(|s| {
+                gpio.input(s.pin).with_context(|| {
+                    format!("Channel {} runout switch on GPIO {} unavailable", s.channel, s.pin)
+                })
+            })
+            .collect()
+    }
+
+    /// Polls `pins` (from [`open_pins`](Self::open_pins)) until shutdown,
+    /// sending every settled change to `changes`.
+    pub async fn watch(
+        &self,
+        pins: Vec<Box<dyn InputPin>>,
+        changes: mpsc::Sender<RunoutChange>,
+        mut shutdown: broadcast::Receiver<()>,
+    ) -> Result<()> {
+        let mut inputs = vec![Debounce::default(); self.sensors.len()];
+        let mut interval = tokio::time::interval(POLL_INTERVAL);
+        for sensor in &self.sensors {
+            info!("Watching channel {} runout switch on GPIO {}", sensor.channel, sensor.pin);
+        }
+
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {
+                    let readings = pins.iter().map(|p| p.is_high()).collect::<Result<Vec<bool>>>()?;
+                    for change in self.update(&mut inputs, &readings, Instant::now()) {
+                        if changes.send(change).await.is_err() {
+                            return Ok(());
+                        }
+                    }
+                }
+                _ = shutdown.recv() => return Ok(()),
+            }
+        }
+    }
+
+    /// Debounces one reading of every switch and records settled changes.
+    fn update(&self, inputs: &mut [Debounce], readings: &[bool], now: Instant) -> Vec<RunoutChange> {
+        let mut changes = Vec::new();
+        for ((sensor, input), &high) in self.sensors.iter().zip(inputs.iter_mut()).zip(readings) {
+            let debounce = Duration::from_millis(sensor.debounce_ms as u64);
+            if let Some(empty) = input.update(high != sensor.active_low, now, debounce) {
+                self.status.set(sensor.channel, empty);
+                if empty {
+                    warn!("Channel {} runout switch reads empty", sensor.channel);
+                }
+                changes.push(RunoutChange { channel: sensor.channel, empty });
+            }
+        }
+        changes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_runout_changes() {
+        let sensor = |channel, active_low| RunoutSensorConfig {
+            channel,
+            pin: 20 + channel,
+            active_low,
+            debounce_ms: 100,
+            prime_macro: None,
+        };
+        let status = RunoutStatus::default();
+        let monitor = RunoutMonitor {
+            sensors: vec![sensor(0, false), sensor(2, true)],
+            status: status.clone(),
+        };
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let mut inputs = vec![Debounce::default(); 2];
+
+        // Channel 2 reads high (loaded, active low); channel 0 runs out
+        assert!(monitor.update(&mut inputs, &[true, true], at(0)).is_empty());
+        let changes = monitor.update(&mut inputs, &[true, true], at(100));
+        assert_eq!(changes, vec![RunoutChange { channel: 0, empty: true }]);
+        assert_eq!(status.empty_channels(), vec![0]);
+
+        // Reloaded
+        monitor.update(&mut inputs, &[false, true], at(200));
+        let changes = monitor.update(&mut inputs, &[false, true], at(300));
+        assert_eq!(changes, vec![RunoutChange { channel: 0, empty: false }]);
+        assert!(!status.is_empty(0));
+
+        let pause = RunoutPause {
+            channel: 0,
+            material: Some("PLA".to_string()),
+            layer_number: 42,
+            pressure_target: 60.0,
+            prime_macro: None,
+        };
+        let event = pause.event(RunoutStage::Loaded);
+        assert_eq!((event.channel, event.layer_number, event.stage), (0, 42, RunoutStage::Loaded));
+    }
+}
@@ -0,0 +1,1132 @@
+//! Tick-based command scheduling for deterministic valve execution.
+//!
+//! The scheduler compiles a layer's command stream into a timeline of valve
+//! frames aligned to a fixed tick. Frame timing honors the valve response time
+//! and the maximum switching frequency of the array, while G4W barriers split
+//! the timeline into segments that only resume once the barrier is released.
+//! Dense frames are latched as staggered sub-frames (see [`super::stagger`]),
+//! and a layer that finishes under its cooling floor is held for the rest
+//! (see [`super::cooling`]).
+//! Execution records how far each tick deviates from its scheduled instant so
+//! timing problems can be diagnosed on real hardware.
+//!
+//! Thick regions often repeat the same valve pattern over consecutive frames
+//! and layers. The executor diffs each frame against the states last written
+//! to the drivers and drops assignments that would not change a valve; a
+//! frame left empty is not written at all. A written state is only trusted
+//! for [`SchedulerConfig::merge_window`], after which it is written again, so
+//! a driver output that was disturbed is corrected. Saved writes are counted
+//! in [`MergeStats`]. Anything that drives valves around the scheduler (an
+//! emergency close, a stuck-valve shutoff, a power loss) triggers the
+//! scheduler's [`LatchReset`], and the next frame is written in full.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use tracing::{debug, trace, warn};
+
+use config_types::ValveArrayConfig;
+use gcode_types::{Command, G4LCommand, G4WCommand, GridCoordinate, LayerFrame, ValveState};
+
+use super::cooling::CoolingPlan;
+use super::stagger::SubFramePlanner;
+use super::verification::{FeedbackVerifier, LayerVerification};
+use crate::{FirmwareError, ValveController};
+
+/// Default scheduler tick (1ms, matching the ±1ms valve accuracy target).
+pub const DEFAULT_TICK_INTERVAL: Duration = Duration::from_millis(1);
+
+/// Timing parameters used when compiling and executing layers.
+#[derive(Debug, Clone, Copy)]
+pub struct SchedulerConfig {
+    /// Grid spacing used to convert G4D positions to valve nodes (mm)
+    pub grid_spacing: f32,
+
+    /// Fixed tick interval of the executor
+    pub tick_interval: Duration,
+
+    /// Time a valve needs to settle after a state change
+    pub response_time: Duration,
+
+    /// Minimum interval between two state changes of the same valve
+    pub min_switch_interval: Duration,
+
+    /// How long a written valve state is trusted when skipping repeated
+    /// writes; zero writes every frame in full
+    pub merge_window: Duration,
+}
+
+impl SchedulerConfig {
+    /// Derives scheduler timing from the valve array configuration.
+    pub fn from_valve_array(config: &ValveArrayConfig) -> Self {
+        let min_switch_interval = if config.max_switching_freq > 0.0 {
+            Duration::from_secs_f32(1.0 / config.max_switching_freq)
+        } else {
+            Duration::ZERO
+        };
+
+        Self {
+            grid_spacing: config.grid_spacing,
+            tick_interval: DEFAULT_TICK_INTERVAL,
+            response_time: Duration::from_secs_f32(config.response_time_ms.max(0.0) / 1000.0),
+            min_switch_interval,
+            merge_window: Duration::from_secs_f32(config.frame_merge_window_ms.max(0.0) / 1000.0),
+        }
+    }
+
+    /// Rounds a duration up to a whole number of ticks.
+    pub fn ticks_for(&self, duration: Duration) -> u64 {
+        let tick = self.tick_interval.as_nanos().max(1);
+        ((duration.as_nanos() + tick - 1) / tick) as u64
+    }
+}
+
+/// Valve updates latched together on a single tick.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValveFrame {
+    /// Tick (relative to segment start) at which the frame is latched
+    pub tick: u64,
+
+    /// Node updates applied by this frame
+    pub updates: Vec<(GridCoordinate, Vec<ValveState>)>,
+}
+
+impl ValveFrame {
+    /// Number of individual valve state assignments in this frame.
+    pub fn valve_count(&self) -> usize {
+        self.updates.iter().map(|(_, v)| v.len()).sum()
+    }
+}
+
+/// A run of frames that executes without interruption.
+#[derive(Debug, Clone, Default)]
+pub struct ScheduleSegment {
+    /// Frames ordered by tick
+    pub frames: Vec<ValveFrame>,
+
+    /// Barrier that must be released after the last frame settles
+    pub barrier: Option<G4WCommand>,
+
+    /// Tick at which the segment is complete (last frame plus settling)
+    pub end_tick: u64,
+}
+
+impl ScheduleSegment {
+    /// Adds a node update to the frame at `tick`, keeping frames ordered.
+    fn add_update(&mut self, tick: u64, node: GridCoordinate, valves: Vec<ValveState>) {
+        match self.frames.binary_search_by_key(&tick, |f| f.tick) {
+            Ok(i) => self.frames[i].updates.push((node, valves)),
+            Err(i) => self.frames.insert(i, ValveFrame { tick, updates: vec![(node, valves)] }),
+        }
+    }
+
+    /// Last commanded state of every valve the segment touches.
+    pub fn final_states(&self) -> Vec<(GridCoordinate, Vec<ValveState>)> {
+        let mut order = Vec::new();
+        let mut states: HashMap<GridCoordinate, Vec<ValveState>> = HashMap::new();
+        for (node, valves) in self.frames.iter().flat_map(|f| &f.updates) {
+            let entry = states.entry(*node).or_insert_with(|| {
+                order.push(*node);
+                Vec::new()
+            });
+            for valve in valves {
+                match entry.iter_mut().find(|v| v.index == valve.index) {
+                    Some(existing) => *existing = *valve,
+                    None => entry.push(*valve),
+                }
+            }
+        }
+        order
+            .into_iter()
+            .map(|node| {
+                let valves = states.remove(&node).unwrap_or_default();
+                (node, valves)
+            })
+            .collect()
+    }
+}
+
+/// A layer compiled into barrier-separated frame timelines.
+#[derive(Debug, Clone, Default)]
+pub struct CompiledLayer {
+    pub segments: Vec<ScheduleSegment>,
+}
+
+impl CompiledLayer {
+    /// Total number of frames across all segments.
+    pub fn frame_count(&self) -> usize {
+        self.segments.iter().map(|s| s.frames.len()).sum()
+    }
+
+    /// Scheduled duration excluding time spent waiting at barriers.
+    pub fn scheduled_duration(&self, tick_interval: Duration) -> Duration {
+        let ticks: u64 = self.segments.iter().map(|s| s.end_tick).sum();
+        tick_interval * ticks as u32
+    }
+}
+
+/// Releases G4W barriers during layer execution.
+///
+/// The executor implements this against live subsystem state; the scheduler
+/// only guarantees that all preceding frames have settled before calling it.
+#[async_trait::async_trait]
+pub trait BarrierHandler: Send {
+    async fn wait(&mut self, barrier: &G4WCommand) -> Result<()>;
+
+    /// Called after each frame is latched, so valve barriers can time
+    /// their settling from the last write.
+    async fn latched(&mut self) {}
+}
+
+/// Per-tick timing deviation statistics.
+#[derive(Debug, Clone, Default)]
+pub struct JitterStats {
+    /// Number of ticks measured
+    pub samples: u64,
+
+    /// Mean absolute deviation from the scheduled instant
+    pub mean: Duration,
+
+    /// Largest observed deviation
+    pub max: Duration,
+
+    /// Ticks that landed later than one full tick interval
+    pub overruns: u64,
+
+    total_nanos: u128,
+}
+
+impl JitterStats {
+    /// Records the deviation of a single tick.
+    pub fn record(&mut self, deviation: Duration, tick_interval: Duration) {
+        self.samples += 1;
+        self.total_nanos += deviation.as_nanos();
+        self.mean = Duration::from_nanos((self.total_nanos / self.samples as u128) as u64);
+        self.max = self.max.max(deviation);
+        if deviation > tick_interval {
+            self.overruns += 1;
+        }
+    }
+
+    /// Merges statistics from another run.
+    pub fn merge(&mut self, other: &JitterStats) {
+        self.samples += other.samples;
+        self.total_nanos += other.total_nanos;
+        self.max = self.max.max(other.max);
+        self.overruns += other.overruns;
+        if self.samples > 0 {
+            self.mean = Duration::from_nanos((self.total_nanos / self.samples as u128) as u64);
+        }
+    }
+}
+
+/// Valve writes saved by skipping repeated states.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MergeStats {
+    /// Frames not written because every valve already held its state
+    pub frames_skipped: u64,
+
+    /// Valve assignments dropped because they repeated the written state
+    pub transitions_saved: u64,
+}
+
+impl MergeStats {
+    /// Merges statistics from another run.
+    pub fn merge(&mut self, other: &MergeStats) {
+        self.frames_skipped += other.frames_skipped;
+        self.transitions_saved += other.transitions_saved;
+    }
+}
+
+/// Valve states last written to the drivers.
+#[derive(Debug, Default)]
+struct LatchedStates {
+    /// (node, valve index) -> (state, when it was written)
+    states: HashMap<(GridCoordinate, u8), (bool, tokio::time::Instant)>,
+}
+
+impl LatchedStates {
+    /// The updates of a frame that change a valve or repeat a state written
+    /// more than `window` ago; None if the frame is to be written as is.
+    fn changes(
+        &self,
+        updates: &[(GridCoordinate, Vec<ValveState>)],
+        now: tokio::time::Instant,
+        window: Duration,
+    ) -> Option<Vec<(GridCoordinate, Vec<ValveState>)>> {
+        let repeated = |node: GridCoordinate, valve: &ValveState| {
+            self.states
+                .get(&(node, valve.index))
+                .is_some_and(|&(open, written)| open == valve.open && now.duration_since(written) < window)
+        };
+        if window.is_zero() {
+            return None;
+        }
+        if !updates.iter().any(|(node, valves)| valves.iter().any(|v| repeated(*node, v))) {
+            return None;
+        }
+        Some(
+            updates
+                .iter()
+                .filter_map(|(node, valves)| {
+                    let changed: Vec<ValveState> =
+                        valves.iter().copied().filter(|v| !repeated(*node, v)).collect();
+                    (!changed.is_empty()).then_some((*node, changed))
+                })
+                .collect(),
+        )
+    }
+
+    fn record(&mut self, updates: &[(GridCoordinate, Vec<ValveState>)], now: tokio::time::Instant) {
+        for (node, valves) in updates {
+            for valve in valves {
+                self.states.insert((*node, valve.index), (valve.open, now));
+            }
+        }
+    }
+}
+
+/// Handle telling a scheduler that the valves were written behind its back.
+///
+/// Cloned into whatever writes valves outside a job; the scheduler forgets
+/// its latched states before the next frame after a trigger.
+#[derive(Debug, Clone, Default)]
+pub struct LatchReset(Arc<AtomicBool>);
+
+impl LatchReset {
+    pub fn trigger(&self) {
+        self.0.store(true, Ordering::Release);
+    }
+
+    fn take(&self) -> bool {
+        self.0.swap(false, Ordering::AcqRel)
+    }
+}
+
+/// Deterministic tick-based scheduler for valve frames.
+pub struct CommandScheduler {
+    config: SchedulerConfig,
+    jitter: JitterStats,
+    last_layer_jitter: JitterStats,
+    /// Live speed adjustment; 1.0 runs at the configured tick
+    speed: f32,
+    /// Splits dense frames into staggered sub-frames
+    sub_frames: SubFramePlanner,
+    latched: LatchedStates,
+    reset: LatchReset,
+    merges: MergeStats,
+    last_layer_merges: MergeStats,
+}
+
+impl CommandScheduler {
+    pub fn new(config: SchedulerConfig) -> Self {
+        Self {
+            config,
+            jitter: JitterStats::default(),
+            last_layer_jitter: JitterStats::default(),
+            speed: 1.0,
+            sub_frames: SubFramePlanner::default(),
+            latched: LatchedStates::default(),
+            reset: LatchReset::default(),
+            merges: MergeStats::default(),
+            last_layer_merges: MergeStats::default(),
+        }
+    }
+
+    /// Scheduler timed and staggering dense frames as the valve array is
+    /// configured; the firmware runs every job on one.
+    pub fn from_valve_array(config: &ValveArrayConfig) -> Self {
+        Self::new(SchedulerConfig::from_valve_array(config)).with_sub_frames(SubFramePlanner::from_valve_array(config))
+    }
+
+    /// Replaces the default planner, which only honors activation groups
+    /// embedded in the file.
+    pub fn with_sub_frames(mut self, planner: SubFramePlanner) -> Self {
+        self.sub_frames = planner;
+        self
+    }
+
+    /// Stretches the tick by `1 / factor` from the next layer on. Frames
+    /// keep their tick numbers, so relative timing is preserved.
+    pub fn set_speed_factor(&mut self, factor: f32) {
+        if factor > 0.0 && factor.is_finite() {
+            self.speed = factor;
+        }
+    }
+
+    pub fn config(&self) -> &SchedulerConfig {
+        &self.config
+    }
+
+    /// Jitter accumulated since the scheduler was created.
+    pub fn jitter_stats(&self) -> &JitterStats {
+        &self.jitter
+    }
+
+    /// Jitter of the most recently executed layer.
+    pub fn last_layer_jitter(&self) -> &JitterStats {
+        &self.last_layer_jitter
+    }
+
+    /// Writes skipped since the scheduler was created.
+    pub fn merge_stats(&self) -> &MergeStats {
+        &self.merges
+    }
+
+    /// Writes skipped in the most recently executed layer.
+    pub fn last_layer_merges(&self) -> &MergeStats {
+        &self.last_layer_merges
+    }
+
+    /// Forgets the written valve states, so the next frame is written in
+    /// full. Needed whenever valves are driven around the scheduler, e.g.
+    /// by an emergency close.
+    pub fn forget_latched(&mut self) {
+        self.latched.states.clear();
+    }
+
+    /// Handle for code outside the job to make the scheduler forget its
+    /// written states.
+    pub fn latch_reset(&self) -> LatchReset {
+        self.reset.clone()
+    }
+
+    /// Compiles a layer's commands into timed frames.
+    ///
+    /// Consecutive G4D commands are packed into the earliest tick at which
+    /// every touched valve may switch again. A G4D with `close_early_ms`
+    /// latches its closing valves that much earlier (dead volume
+    /// compensation), never before they may switch; its opening valves keep
+    /// their tick. Non-valve commands are ignored here; the executor applies
+    /// them before handing the layer over.
+    pub fn compile_layer(&self, commands: &[Command]) -> Result<CompiledLayer> {
+        let response_ticks = self.config.ticks_for(self.config.response_time).max(1);
+        let switch_ticks = self.config.ticks_for(self.config.min_switch_interval);
+
+        let mut layer = CompiledLayer::default();
+        let mut segment = ScheduleSegment::default();
+        // (node, valve index) -> (tick of last change, last commanded state).
+        // Ticks are signed so changes from earlier segments can be expressed
+        // relative to the current segment origin.
+        let mut last_change: HashMap<(GridCoordinate, u8), (i64, bool)> = HashMap::new();
+        let mut cursor = 0u64;
+
+        for cmd in commands {
+            match cmd {
+                Command::G4D(g4d) => {
+                    let node = self.node_for(g4d.position.x, g4d.position.y)?;
+
+                    // Earliest tick at which every changing valve is allowed to switch
+                    let mut tick = cursor;
+                    for valve in &g4d.valves {
+                        if let Some(&(prev_tick, prev_open)) = last_change.get(&(node, valve.index)) {
+                            if prev_open != valve.open {
+                                tick = tick.max((prev_tick + switch_ticks as i64).max(0) as u64);
+                            }
+                        }
+                    }
+
+                    // Closing valves may latch ahead of the command, back
+                    // to the earliest tick they are allowed to switch
+                    let lead = g4d
+                        .close_early_ms
+                        .filter(|ms| *ms > 0.0 && ms.is_finite())
+                        .map(|ms| self.config.ticks_for(Duration::from_secs_f32(ms / 1000.0)))
+                        .unwrap_or(0);
+                    let mut close_tick = tick;
+                    if lead > 0 {
+                        close_tick = tick.saturating_sub(lead);
+                        for valve in g4d.valves.iter().filter(|v| !v.open) {
+                            if let Some(&(prev_tick, true)) = last_change.get(&(node, valve.index)) {
+                                let earliest = prev_tick + switch_ticks.max(1) as i64;
+                                close_tick = close_tick.max(earliest.max(0) as u64);
+                            }
+                        }
+                    }
+
+                    for valve in &g4d.valves {
+                        let at = if valve.open { tick } else { close_tick };
+                        let entry = last_change
+                            .entry((node, valve.index))
+                            .or_insert((at as i64, !valve.open));
+                        if entry.1 != valve.open {
+                            *entry = (at as i64, valve.open);
+                        }
+                    }
+
+                    if close_tick < tick {
+                        let (opens, closes): (Vec<ValveState>, Vec<ValveState>) =
+                            g4d.valves.iter().copied().partition(|v| v.open);
+                        segment.add_update(close_tick, node, closes);
+                        if !opens.is_empty() {
+                            segment.add_update(tick, node, opens);
+                        }
+                    } else {
+                        segment.add_update(tick, node, g4d.valves.clone());
+                    }
+
+                    cursor = tick;
+                    segment.end_tick = segment.end_tick.max(tick + response_ticks);
+                }
+                Command::G4W(wait) => {
+                    segment.barrier = Some(*wait);
+                    let elapsed = segment.end_tick as i64;
+                    layer.segments.push(std::mem::take(&mut segment));
+                    // Timing restarts after a barrier; rebase earlier switch
+                    // times onto the new segment origin.
+                    for (tick, _) in last_change.values_mut() {
+                        *tick -= elapsed;
+                    }
+                    cursor = 0;
+                }
+                _ => {}
+            }
+        }
+
+        if !segment.frames.is_empty() || layer.segments.is_empty() {
+            layer.segments.push(segment);
+        }
+
+        debug!(
+            "Compiled layer: {} segments, {} frames",
+            layer.segments.len(),
+            layer.frame_count()
+        );
+
+        Ok(layer)
+    }
+
+    /// Compiles the deposition of a continuous Z ramp (vase mode).
+    ///
+    /// Each G4D fires when Z, rising at constant speed from `start_z`,
+    /// passes the Z of its position; Z values outside the ramp clamp to its
+    /// ends. The result is one segment without barriers, ending no earlier
+    /// than the ramp itself.
+    pub fn compile_ramp(&self, ramp: &G4LCommand, start_z: f32, commands: &[Command]) -> Result<CompiledLayer> {
+        let Some(ramp_ms) = ramp.ramp_ms else {
+            return Err(FirmwareError::InvalidCommand("G4L without a ramp duration".to_string()).into());
+        };
+        let response_ticks = self.config.ticks_for(self.config.response_time).max(1);
+        let ramp_ticks = self.config.ticks_for(Duration::from_millis(ramp_ms as u64));
+        let rise = ramp.z_height - start_z;
+
+        let mut segment = ScheduleSegment::default();
+        let mut cursor = 0u64;
+        for cmd in commands {
+            let Command::G4D(g4d) = cmd else {
+                continue;
+            };
+            let node = self.node_for(g4d.position.x, g4d.position.y)?;
+            let fraction = if rise.abs() > f32::EPSILON {
+                ((g4d.position.z - start_z) / rise).clamp(0.0, 1.0)
+            } else {
+                0.0
+            };
+            // Commands are in deposition order; never latch one before its predecessor
+            let tick = ((fraction as f64 * ramp_ticks as f64).round() as u64).max(cursor);
+
+            match segment.frames.last_mut() {
+                Some(frame) if frame.tick == tick => frame.updates.push((node, g4d.valves.clone())),
+                _ => segment.frames.push(ValveFrame {
+                    tick,
+                    updates: vec![(node, g4d.valves.clone())],
+                }),
+            }
+            cursor = tick;
+        }
+        segment.end_tick = (cursor + response_ticks).max(ramp_ticks);
+
+        debug!(
+            "Compiled Z ramp to {:.3}mm over {} ms: {} frames",
+            ramp.z_height,
+            ramp_ms,
+            segment.frames.len()
+        );
+        Ok(CompiledLayer {
+            segments: vec![segment],
+        })
+    }
+
+    /// Compiles a run-length encoded frame into latched updates.
+    ///
+    /// A frame is a full valve-plane snapshot, applied in one segment with
+    /// no barriers: on tick 0, or as sub-frames one stagger interval apart
+    /// when the planner splits it.
+    pub fn compile_frame(&self, frame: &LayerFrame) -> CompiledLayer {
+        let response_ticks = self.config.ticks_for(self.config.response_time).max(1);
+        let updates: Vec<(GridCoordinate, Vec<ValveState>)> =
+            frame.nodes().map(|n| (n.position, n.valves)).collect();
+
+        let mut segment = ScheduleSegment::default();
+        if !updates.is_empty() {
+            match self.sub_frames.plan(frame) {
+                Some(plan) => {
+                    let interval_ticks = self.config.ticks_for(plan.interval).max(1);
+                    for (i, updates) in plan.split(updates).into_iter().enumerate() {
+                        if !updates.is_empty() {
+                            segment.frames.push(ValveFrame { tick: i as u64 * interval_ticks, updates });
+                        }
+                    }
+                    debug!(
+                        "Layer {} staggered into {} sub-frames ({:?})",
+                        frame.layer_number,
+                        plan.groups.count,
+                        plan.source
+                    );
+                }
+                None => segment.frames.push(ValveFrame { tick: 0, updates }),
+            }
+            segment.end_tick = segment.frames.last().map_or(0, |f| f.tick) + response_ticks;
+        }
+
+        debug!(
+            "Compiled frame for layer {}: {} nodes in {} runs",
+            frame.layer_number,
+            frame.node_count(),
+            frame.run_count()
+        );
+
+        CompiledLayer {
+            segments: vec![segment],
+        }
+    }
+
+    /// Executes a compiled layer against the valve controller.
+    ///
+    /// Frames are latched at `segment_start + tick * tick_interval`; after
+    /// each segment the scheduler waits for valves to settle and then hands
+    /// the barrier (if any) to `barriers`.
+    pub async fn execute_layer(
+        &mut self,
+        layer: &CompiledLayer,
+        valves: &mut dyn ValveController,
+        barriers: &mut dyn BarrierHandler,
+    ) -> Result<JitterStats> {
+        self.run_layer(layer, valves, barriers, None).await
+    }
+
+    /// Executes a layer like [`execute_layer`](Self::execute_layer) and
+    /// checks valve feedback once each frame has settled.
+    ///
+    /// Checks of settled frames run before the next frame is latched. A
+    /// valve switched again before its previous state settled is only
+    /// checked in its new state.
+    pub async fn execute_layer_verified(
+        &mut self,
+        layer: &CompiledLayer,
+        valves: &mut dyn ValveController,
+        barriers: &mut dyn BarrierHandler,
+        verifier: &mut FeedbackVerifier,
+    ) -> Result<(JitterStats, LayerVerification)> {
+        let commanded = layer
+            .segments
+            .iter()
+            .flat_map(|s| &s.frames)
+            .map(|f| f.valve_count() as u64)
+            .sum();
+        verifier.begin_layer(commanded);
+
+        let jitter = self.run_layer(layer, valves, barriers, Some(verifier)).await?;
+        Ok((jitter, verifier.layer().clone()))
+    }
+
+    async fn run_layer(
+        &mut self,
+        layer: &CompiledLayer,
+        valves: &mut dyn ValveController,
+        barriers: &mut dyn BarrierHandler,
+        mut verifier: Option<&mut FeedbackVerifier>,
+    ) -> Result<JitterStats> {
+        let tick_interval = self.config.tick_interval.div_f32(self.speed);
+        let mut layer_jitter = JitterStats::default();
+        let mut layer_merges = MergeStats::default();
+        // Frames latched but not yet checked, by the instant they settle
+        let mut unverified = VecDeque::new();
+
+        for segment in &layer.segments {
+            let start = tokio::time::Instant::now();
+
+            for frame in &segment.frames {
+                let target = start + tick_interval * frame.tick as u32;
+                if let Some(verifier) = verifier.as_deref_mut() {
+                    if verify_settled(&mut unverified, Some(target), valves, verifier).await? {
+                        // Mismatched valves are not in their latched state
+                        self.forget_latched();
+                    }
+                }
+                if self.reset.take() {
+                    self.forget_latched();
+                }
+                let changes = self.latched.changes(&frame.updates, target, self.config.merge_window);
+                let updates = changes.as_deref().unwrap_or(&frame.updates);
+                let written: usize = updates.iter().map(|(_, v)| v.len()).sum();
+                layer_merges.transitions_saved += (frame.valve_count() - written) as u64;
+                if updates.is_empty() {
+                    layer_merges.frames_skipped += 1;
+                    trace!("Frame at tick {} repeats the latched states; skipped", frame.tick);
+                    continue;
+                }
+
+                tokio::time::sleep_until(target).await;
+
+                let deviation = tokio::time::Instant::now().saturating_duration_since(target);
+                layer_jitter.record(deviation, tick_interval);
+                if deviation > tick_interval {
+                    warn!("Tick {} overran by {:?}", frame.tick, deviation);
+                }
+
+                trace!("Latching frame at tick {} ({} of {} valves)", frame.tick, written, frame.valve_count());
+                if let Err(e) = valves.set_valve_states(updates).await {
+                    // A failed write leaves the driver outputs unknown
+                    self.forget_latched();
+                    return Err(FirmwareError::HardwareOperation(format!(
+                        "Valve frame at tick {} failed: {}",
+                        frame.tick, e
+                    ))
+                    .into());
+                }
+                self.latched.record(updates, target);
+                barriers.latched().await;
+                if verifier.is_some() {
+                    supersede(&mut unverified, &frame.updates);
+                    unverified.push_back((target + self.config.response_time, frame.updates.clone()));
+                }
+            }
+
+            // Let the last frame settle before releasing the barrier
+            tokio::time::sleep_until(start + tick_interval * segment.end_tick as u32).await;
+
+            if let Some(verifier) = verifier.as_deref_mut() {
+                if verify_settled(&mut unverified, None, valves, verifier).await? {
+                    self.forget_latched();
+                }
+            }
+
+            if let Some(barrier) = &segment.barrier {
+                let waited = Instant::now();
+                barriers.wait(barrier).await?;
+                debug!("Barrier {:?} released after {:?}", barrier.wait_type, waited.elapsed());
+            }
+        }
+
+        self.jitter.merge(&layer_jitter);
+        self.last_layer_jitter = layer_jitter.clone();
+        if layer_merges.transitions_saved > 0 {
+            debug!(
+                "Skipped {} repeated valve states ({} whole frames)",
+                layer_merges.transitions_saved, layer_merges.frames_skipped
+            );
+        }
+        self.merges.merge(&layer_merges);
+        self.last_layer_merges = layer_merges;
+        Ok(layer_jitter)
+    }
+
+    /// Dwells until a layer started at `layer_started` has lasted its
+    /// cooling floor, returning how long it waited. The floor is physical,
+    /// so the speed factor does not shorten it.
+    pub async fn hold_for_cooling(&self, layer_started: tokio::time::Instant, plan: &CoolingPlan) -> Duration {
+        let dwell = plan.dwell(layer_started.elapsed());
+        if !dwell.is_zero() {
+            debug!("Layer finished early; dwelling {:?} to cool", dwell);
+            tokio::time::sleep(dwell).await;
+        }
+        dwell
+    }
+
+    /// Converts a physical G4D position to its valve node.
+    fn node_for(&self, x: f32, y: f32) -> Result<GridCoordinate> {
+        if !x.is_finite() || !y.is_finite() || x < 0.0 || y < 0.0 {
+            return Err(FirmwareError::InvalidCommand(format!(
+                "G4D position X{} Y{} is not on the valve grid",
+                x, y
+            ))
+            .into());
+        }
+
+        let spacing = self.config.grid_spacing;
+        Ok(GridCoordinate::new(
+            (x / spacing).round() as u32,
+            (y / spacing).round() as u32,
+        ))
+    }
+}
+
+/// A latched frame awaiting its feedback check: when it settles and the
+/// states it commanded.
+type UnverifiedFrame = (tokio::time::Instant, Vec<(GridCoordinate, Vec<ValveState>)>);
+
+/// Checks the frames that have settled by `until` (all of them if `None`),
+/// oldest first. Returns whether any valve had to be re-commanded.
+async fn verify_settled(
+    unverified: &mut VecDeque<UnverifiedFrame>,
+    until: Option<tokio::time::Instant>,
+    valves: &mut dyn ValveController,
+    verifier: &mut FeedbackVerifier,
+) -> Result<bool> {
+    let retried = verifier.layer().retried;
+    while let Some((settled, _)) = unverified.front() {
+        if until.is_some_and(|until| *settled > until) {
+            break;
+        }
+        let Some((settled, states)) = unverified.pop_front() else {
+            break;
+        };
+        tokio::time::sleep_until(settled).await;
+        verifier.verify(&states, valves).await?;
+    }
+    Ok(verifier.layer().retried > retried)
+}
+
+/// Drops the valves `updates` switches from frames still awaiting their
+/// check; they are checked in their new state instead.
+fn supersede(unverified: &mut VecDeque<UnverifiedFrame>, updates: &[(GridCoordinate, Vec<ValveState>)]) {
+    let switched: HashMap<GridCoordinate, Vec<u8>> = updates
+        .iter()
+        .map(|(node, valves)| (*node, valves.iter().map(|v| v.index).collect()))
+        .collect();
+    for (_, states) in unverified.iter_mut() {
+        for (node, valves) in states.iter_mut() {
+            if let Some(indices) = switched.get(node) {
+                valves.retain(|v| !indices.contains(&v.index));
+            }
+        }
+        states.retain(|(_, valves)| !valves.is_empty());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gcode_types::{Coordinate, G4DCommand, WaitType};
+
+    fn config() -> SchedulerConfig {
+        SchedulerConfig {
+            grid_spacing: 0.5,
+            tick_interval: Duration::from_millis(1),
+            response_time: Duration::from_millis(10),
+            min_switch_interval: Duration::from_millis(100),
+            merge_window: Duration::from_secs(1),
+        }
+    }
+
+    fn deposit(x: f32, y: f32, open: bool) -> Command {
+        Command::G4D(G4DCommand {
+            position: Coordinate::new(x, y, 0.2),
+            valves: vec![ValveState::new(0, open)],
+            extrusion: None,
+            close_early_ms: None,
+        })
+    }
+
+    #[test]
+    fn test_ramp_frames_follow_z() {
+        let scheduler = CommandScheduler::new(config());
+        let ramp = G4LCommand { z_height: 1.2, feed_rate: None, ramp_ms: Some(1000) };
+        let at = |x: f32, z: f32, open: bool| {
+            Command::G4D(G4DCommand {
+                position: Coordinate::new(x, 0.0, z),
+                valves: vec![ValveState::new(0, open)],
+                extrusion: None,
+                close_early_ms: None,
+            })
+        };
+        let layer = scheduler
+            .compile_ramp(&ramp, 1.0, &[at(0.0, 1.0, true), at(0.5, 1.1, true), at(0.0, 1.1, false), at(0.5, 1.2, false)])
+            .unwrap();
+
+        let ticks: Vec<u64> = layer.segments[0].frames.iter().map(|f| f.tick).collect();
+        assert_eq!(ticks, vec![0, 500, 1000]);
+        assert_eq!(layer.segments[0].frames[1].updates.len(), 2);
+        assert_eq!(layer.segments[0].end_tick, 1010);
+    }
+
+    #[test]
+    fn test_independent_nodes_share_frame() {
+        let scheduler = CommandScheduler::new(config());
+        let layer = scheduler
+            .compile_layer(&[deposit(0.0, 0.0, true), deposit(0.5, 0.0, true)])
+            .unwrap();
+
+        assert_eq!(layer.segments.len(), 1);
+        assert_eq!(layer.frame_count(), 1);
+        assert_eq!(layer.segments[0].frames[0].updates.len(), 2);
+        assert_eq!(layer.segments[0].end_tick, 10);
+    }
+
+    #[test]
+    fn test_switching_frequency_delays_toggle() {
+        let scheduler = CommandScheduler::new(config());
+        let layer = scheduler
+            .compile_layer(&[deposit(1.0, 1.0, true), deposit(1.0, 1.0, false)])
+            .unwrap();
+
+        let frames = &layer.segments[0].frames;
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].tick, 0);
+        assert_eq!(frames[1].tick, 100);
+    }
+
+    #[test]
+    fn test_close_early_latches_ahead() {
+        let scheduler = CommandScheduler::new(SchedulerConfig {
+            min_switch_interval: Duration::from_millis(20),
+            ..config()
+        });
+        let early = |x: f32, valves: Vec<ValveState>, ms: f32| {
+            Command::G4D(G4DCommand {
+                position: Coordinate::new(x, 0.0, 0.2),
+                valves,
+                extrusion: None,
+                close_early_ms: Some(ms),
+            })
+        };
+        let layer = scheduler
+            .compile_layer(&[
+                deposit(0.0, 0.0, true),
+                deposit(1.0, 0.0, true),
+                deposit(1.0, 0.0, false),
+                deposit(1.0, 0.0, true),
+                // Wants tick 10 but cannot switch before tick 20
+                early(0.0, vec![ValveState::new(0, false)], 30.0),
+                // Only the close moves; the open keeps its tick
+                early(0.5, vec![ValveState::new(0, true), ValveState::new(1, false)], 50.0),
+            ])
+            .unwrap();
+
+        let frames = &layer.segments[0].frames;
+        let ticks: Vec<u64> = frames.iter().map(|f| f.tick).collect();
+        assert_eq!(ticks, vec![0, 20, 40]);
+        assert_eq!(frames[0].updates[2], (GridCoordinate::new(1, 0), vec![ValveState::new(1, false)]));
+        assert_eq!(frames[1].updates[1], (GridCoordinate::new(0, 0), vec![ValveState::new(0, false)]));
+        assert_eq!(frames[2].updates[1], (GridCoordinate::new(1, 0), vec![ValveState::new(0, true)]));
+    }
+
+    #[test]
+    fn test_barrier_splits_segments() {
+        let scheduler = CommandScheduler::new(config());
+        let wait = Command::G4W(G4WCommand {
+            wait_type: WaitType::Valves,
+            timeout_ms: Some(1000),
+        });
+        let layer = scheduler
+            .compile_layer(&[deposit(0.0, 0.0, true), wait, deposit(0.0, 0.0, false)])
+            .unwrap();
+
+        assert_eq!(layer.segments.len(), 2);
+        assert!(layer.segments[0].barrier.is_some());
+        assert!(layer.segments[1].barrier.is_none());
+    }
+
+    #[test]
+    fn test_segment_final_states() {
+        let scheduler = CommandScheduler::new(config());
+        let layer = scheduler
+            .compile_layer(&[deposit(0.0, 0.0, true), deposit(0.5, 0.0, true), deposit(0.0, 0.0, false)])
+            .unwrap();
+
+        let states = layer.segments[0].final_states();
+        assert_eq!(states.len(), 2);
+        assert_eq!(states[0], (GridCoordinate::new(0, 0), vec![ValveState::new(0, false)]));
+        assert_eq!(states[1], (GridCoordinate::new(1, 0), vec![ValveState::new(0, true)]));
+    }
+
+    #[test]
+    fn test_jitter_stats_record() {
+        let mut stats = JitterStats::default();
+        let tick = Duration::from_millis(1);
+        stats.record(Duration::from_micros(200), tick);
+        stats.record(Duration::from_micros(1800), tick);
+
+        assert_eq!(stats.samples, 2);
+        assert_eq!(stats.mean, Duration::from_micros(1000));
+        assert_eq!(stats.max, Duration::from_micros(1800));
+        assert_eq!(stats.overruns, 1);
+    }
+
+    /// Records how many valve assignments each write carries.
+    #[derive(Default)]
+    struct Writes(Vec<usize>);
+
+    #[async_trait::async_trait]
+    impl ValveController for Writes {
+        async fn set_valve_states(&mut self, states: &[(GridCoordinate, Vec<ValveState>)]) -> Result<()> {
+            self.0.push(states.iter().map(|(_, v)| v.len()).sum());
+            Ok(())
+        }
+        async fn get_valve_states(&self, _position: GridCoordinate) -> Result<Vec<ValveState>> {
+            Ok(vec![])
+        }
+        async fn health_check(&mut self) -> Result<Vec<crate::ValveHealth>> {
+            Ok(vec![])
+        }
+        async fn emergency_close_all(&mut self) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    struct NoBarriers;
+
+    #[async_trait::async_trait]
+    impl BarrierHandler for NoBarriers {
+        async fn wait(&mut self, _barrier: &G4WCommand) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_repeated_frames_are_not_rewritten() {
+        let layer = |closed: &[u32]| {
+            let mut layer = gcode_types::Layer::new(0.2, 0);
+            for x in 0..10 {
+                let valve = ValveState::new(0, !closed.contains(&x));
+                layer.add_node(gcode_types::NodeValveState::new(GridCoordinate::new(x, 2), vec![valve]));
+            }
+            LayerFrame::from_layer(&layer).unwrap()
+        };
+        let mut scheduler = CommandScheduler::new(config());
+        let mut valves = Writes::default();
+        let open = scheduler.compile_frame(&layer(&[]));
+        let one_closed = scheduler.compile_frame(&layer(&[3]));
+
+        for compiled in [&open, &open, &one_closed] {
+            scheduler.execute_layer(compiled, &mut valves, &mut NoBarriers).await.unwrap();
+        }
+        assert_eq!(valves.0, vec![10, 1], "second layer skipped, third only writes the change");
+        assert_eq!(scheduler.merge_stats(), &MergeStats { frames_skipped: 1, transitions_saved: 19 });
+        assert_eq!(scheduler.last_layer_merges().transitions_saved, 9);
+
+        scheduler.forget_latched();
+        scheduler.execute_layer(&one_closed, &mut valves, &mut NoBarriers).await.unwrap();
+        assert_eq!(valves.0.last(), Some(&10));
+
+        // A close-all between layers leaves every valve shut
+        scheduler.execute_layer(&one_closed, &mut valves, &mut NoBarriers).await.unwrap();
+        assert_eq!(valves.0.len(), 3, "unchanged layer skipped");
+        valves.emergency_close_all().await.unwrap();
+        scheduler.latch_reset().trigger();
+        scheduler.execute_layer(&one_closed, &mut valves, &mut NoBarriers).await.unwrap();
+        assert_eq!(valves.0.last(), Some(&10));
+        assert_eq!(valves.0.len(), 4);
+
+        let unmerged_config = SchedulerConfig { merge_window: Duration::ZERO, ..config() };
+        let mut unmerged = CommandScheduler::new(unmerged_config);
+        unmerged.execute_layer(&open, &mut valves, &mut NoBarriers).await.unwrap();
+        unmerged.execute_layer(&open, &mut valves, &mut NoBarriers).await.unwrap();
+        assert_eq!(&valves.0[4..], &[10, 10]);
+        assert_eq!(unmerged.merge_stats(), &MergeStats::default());
+    }
+
+    /// Valve array whose node (0, 0) never opens; feedback reports the
+    /// actual positions.
+    #[derive(Clone, Default)]
+    struct StuckOpen(std::sync::Arc<std::sync::Mutex<HashMap<GridCoordinate, Vec<bool>>>>);
+
+    #[async_trait::async_trait]
+    impl ValveController for StuckOpen {
+        async fn set_valve_states(&mut self, states: &[(GridCoordinate, Vec<ValveState>)]) -> Result<()> {
+            let mut actual = self.0.lock().unwrap();
+            for (node, valves) in states {
+                let slot = actual.entry(*node).or_insert_with(|| vec![false]);
+                for v in valves.iter().filter(|v| *node != GridCoordinate::new(0, 0) || !v.open) {
+                    slot[v.index as usize] = v.open;
+                }
+            }
+            Ok(())
+        }
+        async fn get_valve_states(&self, _position: GridCoordinate) -> Result<Vec<ValveState>> {
+            Ok(vec![])
+        }
+        async fn health_check(&mut self) -> Result<Vec<crate::ValveHealth>> {
+            Ok(vec![])
+        }
+        async fn emergency_close_all(&mut self) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl crate::SensorInterface for StuckOpen {
+        async fn read_all(&self) -> Result<crate::SensorReadings> {
+            Ok(crate::SensorReadings {
+                valve_feedbacks: self.0.lock().unwrap().clone(),
+                ..crate::SensorReadings::default()
+            })
+        }
+        async fn read_sensor(&self, _sensor_id: &str) -> Result<f32> {
+            Ok(0.0)
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_every_frame_is_verified() {
+        // The stuck valve is closed again within the segment, so only a
+        // check of the opening frame catches it
+        let mut scheduler = CommandScheduler::new(config());
+        let layer = scheduler
+            .compile_layer(&[deposit(0.0, 0.0, true), deposit(0.5, 0.0, true), deposit(0.0, 0.0, false)])
+            .unwrap();
+        assert_eq!(layer.segments.len(), 1);
+        assert_eq!(layer.frame_count(), 2);
+
+        let array = StuckOpen::default();
+        let verification = config_types::ValveVerificationConfig {
+            max_retries: 1,
+            mismatch_threshold: 0.5,
+            on_threshold: config_types::MismatchAction::Flag,
+            ..config_types::ValveVerificationConfig::default()
+        };
+        let sensors: std::sync::Arc<Box<dyn crate::SensorInterface>> = std::sync::Arc::new(Box::new(array.clone()));
+        let mut verifier = FeedbackVerifier::new(verification, sensors, Duration::ZERO);
+        let (_, result) = scheduler
+            .execute_layer_verified(&layer, &mut array.clone(), &mut NoBarriers, &mut verifier)
+            .await
+            .unwrap();
+        assert_eq!(result.commanded, 3);
+        assert_eq!(result.checked, 3);
+        assert_eq!((result.retried, result.failed), (1, 1));
+        assert!(!result.flagged);
+        assert_eq!(verifier.node_failures()[&GridCoordinate::new(0, 0)], 1);
+    }
+
+    #[test]
+    fn test_compile_frame() {
+        let mut layer = gcode_types::Layer::new(0.2, 0);
+        for x in 0..10 {
+            layer.add_node(gcode_types::NodeValveState::new(
+                GridCoordinate::new(x, 2),
+                vec![ValveState::open(0)],
+            ));
+        }
+        let frame = LayerFrame::from_layer(&layer).unwrap();
+
+        let compiled = CommandScheduler::new(config()).compile_frame(&frame);
+        assert_eq!(compiled.segments.len(), 1);
+        assert_eq!(compiled.frame_count(), 1);
+        assert_eq!(compiled.segments[0].frames[0].updates.len(), 10);
+        assert!(compiled.segments[0].barrier.is_none());
+    }
+
+    #[test]
+    fn test_compile_frame_staggered() {
+        let mut layer = gcode_types::Layer::new(0.2, 0);
+        for x in 0..10 {
+            layer.add_node(gcode_types::NodeValveState::new(
+                GridCoordinate::new(x, 2),
+                vec![ValveState::open(0)],
+            ));
+        }
+        layer.activation_groups = Some(gcode_types::ActivationGroups::new(2));
+        let frame = LayerFrame::from_layer(&layer).unwrap();
+
+        let compiled = CommandScheduler::new(config()).compile_frame(&frame);
+        let frames = &compiled.segments[0].frames;
+        let ticks: Vec<u64> = frames.iter().map(|f| f.tick).collect();
+        assert_eq!(ticks, vec![0, 5]);
+        assert!(frames.iter().all(|f| f.updates.len() == 5));
+        assert_eq!(compiled.segments[0].end_tick, 15);
+    }
+}
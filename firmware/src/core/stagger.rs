@@ -0,0 +1,137 @@
+//! Sub-frame staggering of dense layers.
+//!
+//! Latching thousands of valve openings on one tick sags the supply
+//! pressure. [`SubFramePlanner`] splits a layer's frame into K sub-frames
+//! along [`ActivationGroups`], so neighbouring nodes open in different
+//! sub-frames and each spreads over the whole layer, and the scheduler
+//! latches them `interval_ms` apart.
+//!
+//! K comes from the layer itself when the slicer's pressure check embedded
+//! activation groups in the .hg4d; those were simulated and are used as
+//! sliced. A layer without them is split at runtime only if its openings
+//! exceed `max_simultaneous_openings`, into just enough sub-frames to stay
+//! under it, at most `max_sub_frames`.
+//!
+//! Only openings are staggered: a node that opens nothing closes on the
+//! first sub-frame.
+
+use std::time::Duration;
+
+use config_types::{SubFrameConfig, ValveArrayConfig};
+use gcode_types::{ActivationGroups, GridCoordinate, LayerFrame, ValveState};
+
+/// Where a layer's sub-frame count came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StaggerSource {
+    /// Activation groups embedded by the slicer
+    Embedded,
+    /// Computed from the layer's opening count
+    Heuristic,
+}
+
+/// How one layer is split.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SubFramePlan {
+    pub groups: ActivationGroups,
+    pub source: StaggerSource,
+    /// Delay between consecutive sub-frames
+    pub interval: Duration,
+}
+
+impl SubFramePlan {
+    /// Splits node updates into one list per sub-frame, in latch order.
+    pub fn split(
+        &self,
+        updates: Vec<(GridCoordinate, Vec<ValveState>)>,
+    ) -> Vec<Vec<(GridCoordinate, Vec<ValveState>)>> {
+        let mut sub_frames = vec![Vec::new(); self.groups.count.max(1) as usize];
+        for (node, valves) in updates {
+            let group = if valves.iter().any(|v| v.open) {
+                self.groups.group_of(node) as usize
+            } else {
+                0
+            };
+            sub_frames[group].push((node, valves));
+        }
+        sub_frames
+    }
+}
+
+/// Chooses how layers are split into sub-frames. The default honors
+/// embedded groups only.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SubFramePlanner {
+    config: SubFrameConfig,
+}
+
+impl SubFramePlanner {
+    pub fn new(config: SubFrameConfig) -> Self {
+        Self { config }
+    }
+
+    pub fn from_valve_array(config: &ValveArrayConfig) -> Self {
+        Self::new(config.sub_frames)
+    }
+
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs_f32(self.config.interval_ms.max(0.0) / 1000.0)
+    }
+
+    /// Plan for `frame`, or None to latch it on one tick.
+    pub fn plan(&self, frame: &LayerFrame) -> Option<SubFramePlan> {
+        if let Some(groups) = frame.activation_groups {
+            return Some(SubFramePlan {
+                groups,
+                source: StaggerSource::Embedded,
+                interval: self.interval(),
+            });
+        }
+
+        let limit = self.config.max_simultaneous_openings.filter(|&n| n > 0)?;
+        let openings: u64 = frame.nodes().map(|n| n.open_count() as u64).sum();
+        let count = openings.div_ceil(limit as u64).min(self.config.max_sub_frames as u64);
+        (count >= 2).then(|| SubFramePlan {
+            groups: ActivationGroups::new(count as u8),
+            source: StaggerSource::Heuristic,
+            interval: self.interval(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gcode_types::{Layer, NodeValveState};
+
+    #[test]
+    fn test_plan_and_split() {
+        let mut layer = Layer::new(0.2, 0);
+        for x in 0..10 {
+            layer.add_node(NodeValveState::new(GridCoordinate::new(x, 0), vec![ValveState::open(0)]));
+        }
+        layer.add_node(NodeValveState::new(GridCoordinate::new(3, 1), vec![ValveState::new(0, false)]));
+        let mut frame = LayerFrame::from_layer(&layer).unwrap();
+
+        let planner = SubFramePlanner::new(SubFrameConfig {
+            max_simultaneous_openings: Some(4),
+            interval_ms: 2.0,
+            max_sub_frames: 8,
+        });
+        let plan = planner.plan(&frame).unwrap();
+        assert_eq!(plan.source, StaggerSource::Heuristic);
+        assert_eq!(plan.groups, ActivationGroups::new(3));
+        assert_eq!(plan.interval, Duration::from_millis(2));
+
+        let sub_frames = plan.split(frame.nodes().map(|n| (n.position, n.valves)).collect());
+        let sizes: Vec<usize> = sub_frames.iter().map(Vec::len).collect();
+        // The closing node goes first
+        assert_eq!(sizes, vec![5, 3, 3]);
+        assert_eq!(sub_frames.iter().flatten().filter(|(_, v)| v[0].open).count(), 10);
+
+        // Embedded groups win, and nothing splits without a limit
+        frame.activation_groups = Some(ActivationGroups::new(2));
+        assert_eq!(planner.plan(&frame).unwrap().source, StaggerSource::Embedded);
+        frame.activation_groups = None;
+        assert!(SubFramePlanner::default().plan(&frame).is_none());
+    }
+}
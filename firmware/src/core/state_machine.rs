@@ -0,0 +1,250 @@
+//! Firmware state transitions.
+//!
+//! Every change of [`FirmwareState`] goes through [`StateMachine::transition`],
+//! which checks it against the transition table ([`is_allowed`]) and the
+//! guards of the target state before writing it to the [`SystemState`]:
+//!
+//! - `Printing` needs every heater at target, every pressure channel
+//!   stable and a homed Z axis ([`Guard`])
+//! - `Idle` is only reached from `Error` once the errors are cleared
+//!
+//! `EmergencyStopped` is reachable from every state and never guarded.
+//! Rejected transitions leave the state untouched and return a
+//! [`TransitionError`]. Accepted ones run the registered hooks and are
+//! broadcast as `ProtocolMessage::StateChanged`.
+
+use std::fmt;
+
+use tokio::sync::broadcast;
+use tracing::info;
+
+use protocol::{ProtocolMessage, StateChangeEvent};
+
+use crate::{FirmwareState, SystemState};
+
+/// Temperature deviation treated as at target (°C).
+pub const THERMAL_TOLERANCE: f32 = 2.0;
+
+/// Pressure deviation treated as stable (PSI).
+pub const PRESSURE_TOLERANCE: f32 = 0.5;
+
+/// Whether the table permits `from → to`, before guards.
+pub fn is_allowed(from: FirmwareState, to: FirmwareState) -> bool {
+    use FirmwareState::*;
+
+    if from == to {
+        return false;
+    }
+    match (from, to) {
+        // Safety stops are always reachable
+        (_, EmergencyStopped) => true,
+        (ShuttingDown, _) => false,
+        (EmergencyStopped, Initializing | ShuttingDown) => true,
+        (EmergencyStopped, _) => false,
+        (_, Error) => true,
+
+        (Initializing, Idle | ShuttingDown) => true,
+        (Idle, Homing | Heating | Printing | ShuttingDown) => true,
+        (Homing, Idle | Heating) => true,
+        (Heating, Idle | Printing) => true,
+        (Printing, Paused | Idle | ShuttingDown) => true,
+        (Paused, Printing | Heating | Idle | ShuttingDown) => true,
+        (Error, Idle | Initializing | ShuttingDown) => true,
+        _ => false,
+    }
+}
+
+/// Condition a target state requires.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Guard {
+    ThermalAtTarget,
+    PressureStable,
+    ZHomed,
+    NoActiveErrors,
+}
+
+impl fmt::Display for Guard {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = match self {
+            Guard::ThermalAtTarget => "heaters are not at target temperature",
+            Guard::PressureStable => "pressure is not stable at target",
+            Guard::ZHomed => "Z axis is not homed",
+            Guard::NoActiveErrors => "errors are still active",
+        };
+        f.write_str(text)
+    }
+}
+
+/// Guards checked before entering `to`.
+pub fn guards_for(from: FirmwareState, to: FirmwareState) -> &'static [Guard] {
+    match (from, to) {
+        (_, FirmwareState::Printing) => &[Guard::ThermalAtTarget, Guard::PressureStable, Guard::ZHomed],
+        (FirmwareState::Error, FirmwareState::Idle) => &[Guard::NoActiveErrors],
+        _ => &[],
+    }
+}
+
+/// A rejected transition.
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum TransitionError {
+    #[error("Illegal state transition {from:?} -> {to:?}")]
+    Illegal { from: FirmwareState, to: FirmwareState },
+
+    #[error("Cannot enter {to:?} from {from:?}: {guard}")]
+    GuardFailed {
+        from: FirmwareState,
+        to: FirmwareState,
+        guard: Guard,
+    },
+}
+
+/// An accepted transition, as passed to hooks.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StateChange {
+    pub from: FirmwareState,
+    pub to: FirmwareState,
+    pub reason: String,
+}
+
+impl StateChange {
+    pub fn event(&self) -> StateChangeEvent {
+        StateChangeEvent {
+            from: format!("{:?}", self.from),
+            to: format!("{:?}", self.to),
+            reason: self.reason.clone(),
+        }
+    }
+}
+
+type TransitionHook = Box<dyn Fn(&StateChange) + Send + Sync>;
+
+/// Enforces the transition table; see the module documentation.
+pub struct StateMachine {
+    status_tx: Option<broadcast::Sender<ProtocolMessage>>,
+    hooks: Vec<TransitionHook>,
+}
+
+impl StateMachine {
+    /// A machine broadcasting accepted transitions on `status_tx`.
+    pub fn new(status_tx: broadcast::Sender<ProtocolMessage>) -> Self {
+        Self {
+            status_tx: Some(status_tx),
+            hooks: Vec::new(),
+        }
+    }
+
+    /// A machine without a broadcast channel, e.g. for tools and tests.
+    pub fn detached() -> Self {
+        Self {
+            status_tx: None,
+            hooks: Vec::new(),
+        }
+    }
+
+    /// Runs `hook` after every accepted transition, in registration order.
+    pub fn on_transition(&mut self, hook: impl Fn(&StateChange) + Send + Sync + 'static) {
+        self.hooks.push(Box::new(hook));
+    }
+
+    /// First guard of `from → to` that `state` does not meet.
+    pub fn check(&self, state: &SystemState, to: FirmwareState) -> Result<(), TransitionError> {
+        let from = state.firmware_state;
+        if !is_allowed(from, to) {
+            return Err(TransitionError::Illegal { from, to });
+        }
+        let failed = guards_for(from, to).iter().copied().find(|guard| !guard_met(state, *guard));
+        match failed {
+            Some(guard) => Err(TransitionError::GuardFailed { from, to, guard }),
+            None => Ok(()),
+        }
+    }
+
+    /// Moves `state` to `to` if the table and guards allow it.
+    pub fn transition(
+        &self,
+        state: &mut SystemState,
+        to: FirmwareState,
+        reason: impl Into<String>,
+    ) -> Result<StateChange, TransitionError> {
+        self.check(state, to)?;
+        let change = StateChange {
+            from: state.firmware_state,
+            to,
+            reason: reason.into(),
+        };
+        state.firmware_state = to;
+        info!("State {:?} -> {:?}: {}", change.from, change.to, change.reason);
+
+        for hook in &self.hooks {
+            hook(&change);
+        }
+        if let Some(tx) = &self.status_tx {
+            // No subscribers is not an error
+            tx.send(ProtocolMessage::StateChanged(change.event())).ok();
+        }
+        Ok(change)
+    }
+}
+
+fn guard_met(state: &SystemState, guard: Guard) -> bool {
+    match guard {
+        Guard::ThermalAtTarget => state.thermal.clone().check_at_target(THERMAL_TOLERANCE),
+        Guard::PressureStable => state.pressure.clone().check_stable(PRESSURE_TOLERANCE),
+        Guard::ZHomed => state.motion.z_homed,
+        Guard::NoActiveErrors => state.errors.is_empty(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn test_transitions_are_enforced() {
+        let (tx, mut rx) = broadcast::channel(8);
+        let mut machine = StateMachine::new(tx);
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let hook_seen = seen.clone();
+        machine.on_transition(move |change| hook_seen.lock().unwrap().push(change.to));
+
+        let mut state = SystemState::new();
+        machine.transition(&mut state, FirmwareState::Idle, "hardware ready").unwrap();
+        assert_eq!(
+            machine.transition(&mut state, FirmwareState::Paused, "pause"),
+            Err(TransitionError::Illegal { from: FirmwareState::Idle, to: FirmwareState::Paused })
+        );
+
+        // Cold heater blocks printing
+        state.thermal.zones.insert(0, (25.0, 210.0));
+        state.motion.z_homed = true;
+        let err = machine.transition(&mut state, FirmwareState::Printing, "start").unwrap_err();
+        assert!(matches!(err, TransitionError::GuardFailed { guard: Guard::ThermalAtTarget, .. }));
+        assert_eq!(state.firmware_state, FirmwareState::Idle);
+
+        state.thermal.zones.insert(0, (209.0, 210.0));
+        machine.transition(&mut state, FirmwareState::Printing, "start").unwrap();
+        machine.transition(&mut state, FirmwareState::EmergencyStopped, "button").unwrap();
+        assert!(!is_allowed(FirmwareState::EmergencyStopped, FirmwareState::Printing));
+
+        assert_eq!(
+            *seen.lock().unwrap(),
+            vec![FirmwareState::Idle, FirmwareState::Printing, FirmwareState::EmergencyStopped]
+        );
+        let ProtocolMessage::StateChanged(event) = rx.try_recv().unwrap() else {
+            panic!("expected a state change event");
+        };
+        assert_eq!((event.from.as_str(), event.to.as_str()), ("Initializing", "Idle"));
+
+        // Errors raised after an emergency stop keep the stop
+        let error = || crate::SystemError::new(protocol::ErrorCode::PrintFailed, "late", Vec::new());
+        state.add_error(error(), &machine);
+        assert_eq!(state.firmware_state, FirmwareState::EmergencyStopped);
+
+        let mut state = SystemState::new();
+        state.add_error(error(), &machine);
+        assert_eq!(state.firmware_state, FirmwareState::Error);
+        state.clear_errors(&machine);
+        assert_eq!(state.firmware_state, FirmwareState::Idle);
+    }
+}
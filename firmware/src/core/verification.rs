@@ -0,0 +1,322 @@
+//! Layer deposition verification from valve feedback.
+//!
+//! Valve arrays with position feedback report the actual state of every
+//! valve in `SensorReadings::valve_feedbacks`. After a frame has settled the
+//! verifier compares the commanded states against that feedback:
+//!
+//! 1. Valves whose feedback disagrees are re-commanded and re-read, up to
+//!    `max_retries` times, to ride out transient sticking
+//! 2. Valves still wrong after the retries count as failed, and the failure
+//!    is added to a per-node tally kept across the whole print
+//! 3. Once the failures in a layer exceed `mismatch_threshold` of the valves
+//!    the layer commands, the layer is aborted or flagged according to
+//!    `on_threshold`
+//!
+//! Nodes (or valve indices) without feedback are not checked.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use tracing::{debug, warn};
+
+use config_types::{MismatchAction, ValveVerificationConfig};
+use gcode_types::{GridCoordinate, ValveState};
+
+use crate::{FirmwareError, SensorInterface, ValveController};
+
+/// Verification outcome for one layer.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LayerVerification {
+    /// Valve assignments the layer commands
+    pub commanded: u64,
+    /// Valve assignments compared against feedback
+    pub checked: u64,
+    /// Valves that needed at least one retry
+    pub retried: u64,
+    /// Valves still mismatched after all retries
+    pub failed: u64,
+    /// Layer exceeded the mismatch threshold but was allowed to finish
+    pub flagged: bool,
+}
+
+impl LayerVerification {
+    /// Failed valves as a fraction of the valves the layer commands.
+    pub fn failure_ratio(&self) -> f32 {
+        if self.commanded == 0 {
+            0.0
+        } else {
+            self.failed as f32 / self.commanded as f32
+        }
+    }
+}
+
+/// Compares commanded valve states against feedback after each frame.
+pub struct FeedbackVerifier {
+    config: ValveVerificationConfig,
+    sensors: Arc<Box<dyn SensorInterface>>,
+    /// Wait after re-commanding before feedback is read again
+    settle: Duration,
+    node_failures: HashMap<GridCoordinate, u32>,
+    layer: LayerVerification,
+}
+
+impl FeedbackVerifier {
+    pub fn new(
+        config: ValveVerificationConfig,
+        sensors: Arc<Box<dyn SensorInterface>>,
+        settle: Duration,
+    ) -> Self {
+        Self {
+            config,
+            sensors,
+            settle,
+            node_failures: HashMap::new(),
+            layer: LayerVerification::default(),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    /// Failed valve count per node since the verifier was created.
+    pub fn node_failures(&self) -> &HashMap<GridCoordinate, u32> {
+        &self.node_failures
+    }
+
+    /// Starts a layer that commands `commanded` valve assignments in total.
+    pub fn begin_layer(&mut self, commanded: u64) {
+        self.layer = LayerVerification {
+            commanded,
+            ..LayerVerification::default()
+        };
+    }
+
+    /// Result of the current layer so far.
+    pub fn layer(&self) -> &LayerVerification {
+        &self.layer
+    }
+
+    /// Verifies settled valves, retrying mismatches.
+    ///
+    /// Fails with a print execution error if the layer crosses the mismatch
+    /// threshold and the configured action is [`MismatchAction::Abort`].
+    pub async fn verify(
+        &mut self,
+        commanded: &[(GridCoordinate, Vec<ValveState>)],
+        valves: &mut dyn ValveController,
+    ) -> Result<()> {
+        if !self.config.enabled || commanded.is_empty() {
+            return Ok(());
+        }
+
+        let feedback = self.sensors.read_all().await?.valve_feedbacks;
+        let (checked, mut pending) = mismatches(commanded, &feedback);
+        self.layer.checked += checked;
+        self.layer.retried += count(&pending);
+
+        for attempt in 1..=self.config.max_retries {
+            if pending.is_empty() {
+                break;
+            }
+            debug!("Re-commanding {} mismatched valves (attempt {})", count(&pending), attempt);
+            valves.set_valve_states(&pending).await?;
+            tokio::time::sleep(self.settle).await;
+
+            let feedback = self.sensors.read_all().await?.valve_feedbacks;
+            pending = mismatches(&pending, &feedback).1;
+        }
+
+        for (node, states) in &pending {
+            warn!("Valve(s) {:?} at {} do not follow commands", indices(states), node);
+            *self.node_failures.entry(*node).or_insert(0) += states.len() as u32;
+        }
+        self.layer.failed += count(&pending);
+
+        self.check_threshold()
+    }
+
+    fn check_threshold(&mut self) -> Result<()> {
+        let ratio = self.layer.failure_ratio();
+        if ratio <= self.config.mismatch_threshold {
+            return Ok(());
+        }
+
+        match self.config.on_threshold {
+            MismatchAction::Abort => Err(FirmwareError::PrintExecution(format!(
+                "{} of {} valves failed verification ({:.1}% > {:.1}%)",
+                self.layer.failed,
+                self.layer.commanded,
+                ratio * 100.0,
+                self.config.mismatch_threshold * 100.0
+            ))
+            .into()),
+            MismatchAction::Flag => {
+                if !self.layer.flagged {
+                    warn!(
+                        "Layer flagged: {:.1}% of valves failed verification",
+                        ratio * 100.0
+                    );
+                    self.layer.flagged = true;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Returns the number of valves that had feedback and the commanded states
+/// of those that disagree with it, grouped by node.
+fn mismatches(
+    commanded: &[(GridCoordinate, Vec<ValveState>)],
+    feedback: &HashMap<GridCoordinate, Vec<bool>>,
+) -> (u64, Vec<(GridCoordinate, Vec<ValveState>)>) {
+    let mut checked = 0;
+    let mut wrong = Vec::new();
+
+    for (node, states) in commanded {
+        let Some(actual) = feedback.get(node) else {
+            continue;
+        };
+        let mut node_wrong = Vec::new();
+        for state in states {
+            if let Some(&open) = actual.get(state.index as usize) {
+                checked += 1;
+                if open != state.open {
+                    node_wrong.push(*state);
+                }
+            }
+        }
+        if !node_wrong.is_empty() {
+            wrong.push((*node, node_wrong));
+        }
+    }
+
+    (checked, wrong)
+}
+
+fn count(updates: &[(GridCoordinate, Vec<ValveState>)]) -> u64 {
+    updates.iter().map(|(_, v)| v.len() as u64).sum()
+}
+
+fn indices(states: &[ValveState]) -> Vec<u8> {
+    states.iter().map(|s| s.index).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{SensorReadings, ValveHealth};
+    use std::sync::Mutex as StdMutex;
+
+    /// Valve array where some valves ignore commands; feedback reports the
+    /// actual positions.
+    #[derive(Default)]
+    struct Array {
+        actual: HashMap<GridCoordinate, Vec<bool>>,
+        /// (node, index) -> commands ignored before the valve moves
+        sticky: HashMap<(GridCoordinate, u8), u32>,
+    }
+
+    #[derive(Clone, Default)]
+    struct Shared(Arc<StdMutex<Array>>);
+
+    #[async_trait::async_trait]
+    impl ValveController for Shared {
+        async fn set_valve_states(&mut self, states: &[(GridCoordinate, Vec<ValveState>)]) -> Result<()> {
+            let mut array = self.0.lock().unwrap();
+            for (node, valves) in states {
+                for v in valves {
+                    if let Some(left) = array.sticky.get_mut(&(*node, v.index)) {
+                        if *left > 0 {
+                            *left -= 1;
+                            continue;
+                        }
+                    }
+                    let slot = array.actual.entry(*node).or_insert_with(|| vec![false; 2]);
+                    slot[v.index as usize] = v.open;
+                }
+            }
+            Ok(())
+        }
+        async fn get_valve_states(&self, _position: GridCoordinate) -> Result<Vec<ValveState>> {
+            Ok(vec![])
+        }
+        async fn health_check(&mut self) -> Result<Vec<ValveHealth>> {
+            Ok(vec![])
+        }
+        async fn emergency_close_all(&mut self) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl SensorInterface for Shared {
+        async fn read_all(&self) -> Result<SensorReadings> {
+            Ok(SensorReadings {
+                valve_feedbacks: self.0.lock().unwrap().actual.clone(),
+                ..SensorReadings::default()
+            })
+        }
+        async fn read_sensor(&self, _sensor_id: &str) -> Result<f32> {
+            Ok(0.0)
+        }
+    }
+
+    fn frame(nodes: u32) -> Vec<(GridCoordinate, Vec<ValveState>)> {
+        (0..nodes)
+            .map(|x| (GridCoordinate::new(x, 0), vec![ValveState::open(0)]))
+            .collect()
+    }
+
+    async fn run(config: ValveVerificationConfig, sticky: &[(u32, u32)]) -> (Result<()>, FeedbackVerifier) {
+        let shared = Shared::default();
+        for &(x, ignored) in sticky {
+            shared.0.lock().unwrap().sticky.insert((GridCoordinate::new(x, 0), 0), ignored);
+        }
+        let mut valves = shared.clone();
+        let mut verifier = FeedbackVerifier::new(config, Arc::new(Box::new(shared)), Duration::ZERO);
+
+        let commanded = frame(10);
+        verifier.begin_layer(count(&commanded));
+        valves.set_valve_states(&commanded).await.unwrap();
+        let result = verifier.verify(&commanded, &mut valves).await;
+        (result, verifier)
+    }
+
+    #[tokio::test]
+    async fn test_transient_mismatch_is_retried() {
+        let (result, verifier) = run(ValveVerificationConfig::default(), &[(3, 2)]).await;
+        result.unwrap();
+        assert_eq!(verifier.layer().checked, 10);
+        assert_eq!(verifier.layer().retried, 1);
+        assert_eq!(verifier.layer().failed, 0);
+        assert!(verifier.node_failures().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_threshold_aborts_or_flags() {
+        let config = ValveVerificationConfig {
+            mismatch_threshold: 0.15,
+            ..ValveVerificationConfig::default()
+        };
+        // One stuck valve in ten stays under the threshold
+        let (result, verifier) = run(config, &[(0, u32::MAX)]).await;
+        result.unwrap();
+        assert_eq!(verifier.node_failures()[&GridCoordinate::new(0, 0)], 1);
+
+        let (result, _) = run(config, &[(0, u32::MAX), (1, u32::MAX)]).await;
+        assert!(result.unwrap_err().to_string().contains("2 of 10"));
+
+        let flag = ValveVerificationConfig {
+            on_threshold: MismatchAction::Flag,
+            ..config
+        };
+        let (result, verifier) = run(flag, &[(0, u32::MAX), (1, u32::MAX)]).await;
+        result.unwrap();
+        assert!(verifier.layer().flagged);
+        assert_eq!(verifier.layer().failed, 2);
+    }
+}
@@ -0,0 +1,185 @@
+//! Parking heaters of idle thermal zones.
+//!
+//! On printers whose zones heat areas of the valve grid
+//! (`ThermalZone::regions`), a zone only needs heat while layers deposit
+//! inside it. The print loop hands [`ZonePowerManager::update`] the frames
+//! coming up next; a zone backing none of their open nodes is parked at 0°C,
+//! and brought back to its planned target as soon as work for it appears
+//! within the window. The window, [`DEFAULT_ZONE_LOOKAHEAD_LAYERS`] by
+//! default, must span at least a zone's reheat time.
+//!
+//! Zones without regions heat a channel's whole flow path and are never
+//! parked.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use config_types::{PrinterConfig, ThermalConfig};
+use gcode_types::LayerFrame;
+
+/// Upcoming layers a zone must be idle for before it is parked.
+pub const DEFAULT_ZONE_LOOKAHEAD_LAYERS: usize = 10;
+
+/// A heater target to apply; 0 parks the zone.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ZoneTargetChange {
+    pub zone: u8,
+    pub target: f32,
+}
+
+/// Tracks which spatial zones are parked during a print.
+#[derive(Debug, Clone)]
+pub struct ZonePowerManager {
+    thermal: ThermalConfig,
+    grid_spacing: f32,
+    /// Planned target per spatial zone
+    targets: BTreeMap<u8, f32>,
+    parked: BTreeSet<u8>,
+    lookahead: usize,
+}
+
+impl ZonePowerManager {
+    /// `targets` are the print's planned zone temperatures; zones without
+    /// regions are left out.
+    pub fn new(printer: &PrinterConfig, targets: &BTreeMap<u8, f32>, lookahead: usize) -> Self {
+        let thermal = printer.thermal.clone();
+        let targets = targets
+            .iter()
+            .filter(|(id, _)| thermal.zone(**id).is_some_and(|z| z.is_spatial()))
+            .map(|(&id, &target)| (id, target))
+            .collect();
+        Self {
+            thermal,
+            grid_spacing: printer.valve_array.grid_spacing,
+            targets,
+            parked: BTreeSet::new(),
+            lookahead,
+        }
+    }
+
+    /// Spatial zones heating at least one open node of the frame.
+    pub fn active_zones(&self, frame: &LayerFrame) -> BTreeSet<u8> {
+        let mut active = BTreeSet::new();
+        for plane in &frame.planes {
+            let channel = plane.channel.or(frame.primary_material).unwrap_or(0);
+            for row in &plane.rows {
+                let y = frame.origin.y + row.y;
+                for run in row.runs.iter().filter(|run| run.mask != 0) {
+                    let x0 = frame.origin.x + run.x;
+                    for x in x0..x0 + run.len {
+                        active.extend(
+                            self.thermal
+                                .zones_at(channel, x, y, self.grid_spacing)
+                                .filter(|zone| zone.is_spatial())
+                                .map(|zone| zone.id),
+                        );
+                    }
+                }
+            }
+        }
+        active
+    }
+
+    /// Parks zones idle over the next frames and restores parked zones
+    /// that are needed again. `upcoming` starts with the next layer to run;
+    /// frames past the lookahead window are ignored.
+    pub fn update(&mut self, upcoming: &[LayerFrame]) -> Vec<ZoneTargetChange> {
+        let needed: BTreeSet<u8> = upcoming
+            .iter()
+            .take(self.lookahead)
+            .flat_map(|frame| self.active_zones(frame))
+            .collect();
+
+        let mut changes = Vec::new();
+        for (&zone, &target) in &self.targets {
+            if needed.contains(&zone) {
+                if self.parked.remove(&zone) {
+                    changes.push(ZoneTargetChange { zone, target });
+                }
+            } else if self.parked.insert(zone) {
+                changes.push(ZoneTargetChange { zone, target: 0.0 });
+            }
+        }
+        changes
+    }
+
+    /// Zones currently parked.
+    pub fn parked(&self) -> &BTreeSet<u8> {
+        &self.parked
+    }
+
+    /// Targets restoring every parked zone, e.g. before a pause so the
+    /// resumed print does not wait on cold zones.
+    pub fn restore_all(&mut self) -> Vec<ZoneTargetChange> {
+        let targets = &self.targets;
+        std::mem::take(&mut self.parked)
+            .into_iter()
+            .map(|zone| ZoneTargetChange { zone, target: targets[&zone] })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use config_types::{ChannelZoneMapping, PidParameters, ThermalZone, ZoneRegion};
+    use gcode_types::{GridCoordinate, Layer, NodeValveState, ValveState};
+
+    fn zone(id: u8, regions: Vec<ZoneRegion>) -> ThermalZone {
+        ThermalZone {
+            id,
+            name: format!("zone{}", id),
+            min_temp: 20.0,
+            max_temp: 260.0,
+            power_watts: 40.0,
+            pid: PidParameters::default(),
+            heater_pin: None,
+            regions,
+            thermal_mass: None,
+        }
+    }
+
+    fn frame(n: u32, xs: &[u32]) -> LayerFrame {
+        let mut layer = Layer::new(0.2 * (n + 1) as f32, n);
+        for &x in xs {
+            layer.add_node(NodeValveState::new(GridCoordinate::new(x, 5), vec![ValveState::new(0, true)]));
+        }
+        LayerFrame::from_layer(&layer).unwrap()
+    }
+
+    #[test]
+    fn test_idle_zones_are_parked_and_restored() {
+        let thermal = ThermalConfig {
+            zones: vec![
+                zone(0, vec![ZoneRegion::Nodes { x: (0, 49), y: (0, 99) }]),
+                zone(1, vec![ZoneRegion::Nodes { x: (50, 99), y: (0, 99) }]),
+                zone(2, vec![]),
+            ],
+            manifold: None,
+            chamber: None,
+            bed: None,
+            channel_zones: vec![ChannelZoneMapping { channel: 0, zones: vec![0, 1, 2] }],
+            supply_watts: None,
+        };
+        let mut manager = ZonePowerManager {
+            thermal,
+            grid_spacing: 1.0,
+            targets: BTreeMap::from([(0, 210.0), (1, 210.0)]),
+            parked: BTreeSet::new(),
+            lookahead: 2,
+        };
+        assert_eq!(manager.active_zones(&frame(0, &[10, 60])), BTreeSet::from([0, 1]));
+
+        // Zone 1 has nothing to do in the next two layers
+        let frames = [frame(0, &[10]), frame(1, &[20]), frame(2, &[70])];
+        assert_eq!(manager.update(&frames), vec![ZoneTargetChange { zone: 1, target: 0.0 }]);
+        assert!(manager.update(&frames).is_empty(), "already parked");
+
+        // Layer 2 enters the window
+        assert_eq!(manager.update(&frames[1..]), vec![ZoneTargetChange { zone: 1, target: 210.0 }]);
+        assert!(manager.parked().is_empty());
+
+        manager.update(&[frame(3, &[])]);
+        assert_eq!(manager.parked(), &BTreeSet::from([0, 1]));
+        assert_eq!(manager.restore_all().len(), 2);
+    }
+}
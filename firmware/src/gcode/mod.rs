@@ -6,14 +6,17 @@
 //! ## Module Organization
 //!
 //! - **parser**: .hg4d file parsing
+//! - **stream**: Streaming layer reader with background prefetch
 //! - **interpreter**: Command interpretation
 //! - **validator**: Command validation
 
 pub mod parser;
+pub mod stream;
 pub mod interpreter;
 pub mod validator;
 
 pub use parser::GCodeParser;
+pub use stream::LayerStream;
 pub use interpreter::CommandInterpreter;
 pub use validator::CommandValidator;
 
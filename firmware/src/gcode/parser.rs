@@ -0,0 +1,128 @@
+//! .hg4d layer block parsing.
+//!
+//! Layer blocks are stored either as explicit node lists or as run-length
+//! encoded [`LayerFrame`]s. Frames are handed to the executor as-is so dense
+//! layers never have to be expanded into per-node commands.
+//!
+//! Files whose header names a codec store each block compressed (see
+//! [`gcode_types::codec`]); the parser decompresses before decoding.
+
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use tracing::trace;
+
+use gcode_types::codec::{self, BlockCodec};
+use gcode_types::{LayerBlock, LayerFrame};
+
+use super::stream::{LayerStream, MAX_LAYER_BLOCK_SIZE};
+
+/// Parses layer blocks read from an .hg4d file.
+#[derive(Debug, Clone, Default)]
+pub struct GCodeParser {
+    verify_checksums: bool,
+    /// Codec from the file header; `None` for bare blocks
+    codec: Option<BlockCodec>,
+}
+
+impl GCodeParser {
+    pub fn new() -> Self {
+        Self {
+            verify_checksums: true,
+            codec: None,
+        }
+    }
+
+    /// Sets the block codec recorded in the file header.
+    pub fn with_codec(mut self, codec: Option<BlockCodec>) -> Self {
+        self.codec = codec;
+        self
+    }
+
+    /// Disables CRC verification (for trusted, already-verified files).
+    pub fn without_checksums(mut self) -> Self {
+        self.verify_checksums = false;
+        self
+    }
+
+    /// Streams the layers of an .hg4d file, keeping `lookahead` decoded
+    /// layers buffered instead of loading the whole file.
+    pub fn stream<P: AsRef<Path>>(self, path: P, lookahead: usize) -> Result<LayerStream> {
+        LayerStream::open(path, self, lookahead)
+    }
+
+    /// Decodes one layer block, verifying it against the index checksum.
+    pub fn parse_layer_block(&self, data: &[u8], checksum: u32) -> Result<LayerBlock> {
+        if self.verify_checksums {
+            let actual = crc32fast::hash(data);
+            if actual != checksum {
+                bail!(
+                    "Layer block checksum mismatch: expected {:08x}, got {:08x}",
+                    checksum,
+                    actual
+                );
+            }
+        }
+
+        let block = match self.codec {
+            Some(_) => {
+                let data = codec::unpack_block(data, MAX_LAYER_BLOCK_SIZE as usize)
+                    .context("Failed to decompress layer block")?;
+                LayerBlock::from_bytes(&data)
+            }
+            None => LayerBlock::from_bytes(data),
+        }
+        .context("Failed to decode layer block")?;
+        trace!(
+            "Parsed layer {} ({})",
+            block.layer_number(),
+            match &block {
+                LayerBlock::Nodes(_) => "node list",
+                LayerBlock::Frame(_) => "frame",
+            }
+        );
+        Ok(block)
+    }
+
+    /// Decodes a layer block into a frame, encoding node lists on the fly so
+    /// the executor only has to handle one representation.
+    pub fn parse_layer_frame(&self, data: &[u8], checksum: u32) -> Result<LayerFrame> {
+        match self.parse_layer_block(data, checksum)? {
+            LayerBlock::Frame(frame) => Ok(frame),
+            LayerBlock::Nodes(layer) => {
+                LayerFrame::from_layer(&layer).context("Failed to convert layer to frame")
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gcode_types::{GridCoordinate, Layer, NodeValveState, ValveState};
+
+    #[test]
+    fn test_parse_compact_block() {
+        let mut layer = Layer::new(0.2, 3);
+        for x in 0..50 {
+            layer.add_node(NodeValveState::new(
+                GridCoordinate::new(x, 0),
+                vec![ValveState::open(0), ValveState::closed(1)],
+            ));
+        }
+        let data = LayerBlock::encode_compact(&layer).unwrap();
+        let checksum = crc32fast::hash(&data);
+
+        let parser = GCodeParser::new();
+        let frame = parser.parse_layer_frame(&data, checksum).unwrap();
+        assert_eq!(frame.layer_number, 3);
+        assert_eq!(frame.node_count(), 50);
+
+        assert!(parser.parse_layer_block(&data, checksum ^ 1).is_err());
+
+        let packed = codec::pack_block(BlockCodec::Lz4, 0, &data).unwrap();
+        let parser = GCodeParser::new().with_codec(Some(BlockCodec::Lz4));
+        let frame = parser.parse_layer_frame(&packed, crc32fast::hash(&packed)).unwrap();
+        assert_eq!(frame.node_count(), 50);
+    }
+}
@@ -0,0 +1,349 @@
+//! Streaming .hg4d layer reader.
+//!
+//! Print files can be several gigabytes, far more than a Pi 4 should hold in
+//! RAM. [`LayerStream`] loads only the layer index up front; layer blocks
+//! are read one at a time by a background prefetch task and decoded into
+//! [`LayerFrame`]s. Decoded frames wait in a bounded channel of
+//! `lookahead` layers, so the executor normally finds the next layer already
+//! decoded when it finishes the current one, while memory use stays bounded
+//! by the lookahead regardless of file size.
+//!
+//! A read or decode failure is delivered in place of the failing layer and
+//! ends the stream.
+
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use tokio::sync::mpsc;
+use tracing::{debug, info};
+
+use config_types::MaterialProfile;
+use gcode_types::{BlockCodec, IndexTrailer, LayerFrame, LayerIndexEntry};
+use serde::Deserialize;
+
+use super::GCodeParser;
+
+/// Default number of decoded layers buffered ahead of the executor.
+pub const DEFAULT_LOOKAHEAD_LAYERS: usize = 4;
+
+/// Largest layer block the reader accepts; anything larger indicates a
+/// corrupt index rather than a real layer.
+pub const MAX_LAYER_BLOCK_SIZE: u32 = 256 * 1024 * 1024;
+
+/// .hg4d magic number, as written by the slicer.
+pub const HG4D_MAGIC: u32 = 0x48473444;
+
+/// .hg4d format versions this reader understands, ascending. Version 1
+/// layer blocks lack fields the current layer types decode positionally.
+pub const SUPPORTED_FORMAT_VERSIONS: &[u32] = &[2];
+
+/// Largest metadata section accepted.
+const MAX_METADATA_SIZE: u32 = 16 * 1024 * 1024;
+
+/// Header metadata written by the slicer after the magic and format version.
+///
+/// Only the fields the firmware acts on are decoded; the resolved print
+/// settings are skipped.
+#[derive(Debug, Clone, Deserialize)]
+pub struct FileMetadata {
+    pub model_name: String,
+    pub slicer_version: String,
+    /// Hex-encoded SHA-256 of the printer configuration sliced for
+    pub printer_config_hash: String,
+    /// Material each channel was sliced for (index = channel)
+    #[serde(default)]
+    pub materials: Vec<MaterialProfile>,
+    /// Layer block codec; absent for files with bare blocks
+    #[serde(default)]
+    pub codec: Option<BlockCodec>,
+    /// Expected grams per channel (index = channel); empty for older files
+    #[serde(default)]
+    pub material_usage: Vec<f32>,
+}
+
+/// Layers of an .hg4d file, decoded ahead of consumption.
+pub struct LayerStream {
+    index: Vec<LayerIndexEntry>,
+    rx: mpsc::Receiver<Result<LayerFrame>>,
+    delivered: usize,
+}
+
+impl LayerStream {
+    /// Opens an .hg4d file and starts prefetching from its first layer.
+    /// Blocks are decompressed with the codec named in the file header.
+    ///
+    /// Must be called from within a Tokio runtime.
+    pub fn open<P: AsRef<Path>>(path: P, parser: GCodeParser, lookahead: usize) -> Result<Self> {
+        let path = path.as_ref();
+        let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+        let mut reader = BufReader::new(file);
+        let metadata = read_file_metadata(&mut reader)?;
+        let parser = parser.with_codec(metadata.codec);
+        let stream = Self::from_reader(reader, parser, lookahead)?;
+        info!(
+            "Streaming {} ({} layers, lookahead {}, codec {})",
+            path.display(),
+            stream.layer_count(),
+            lookahead,
+            metadata.codec.unwrap_or(BlockCodec::None)
+        );
+        Ok(stream)
+    }
+
+    /// Starts streaming from any seekable source.
+    pub fn from_reader<R>(mut reader: R, parser: GCodeParser, lookahead: usize) -> Result<Self>
+    where
+        R: Read + Seek + Send + 'static,
+    {
+        let index = read_layer_index(&mut reader)?;
+        let (tx, rx) = mpsc::channel(lookahead.max(1));
+
+        let entries = index.clone();
+        tokio::task::spawn_blocking(move || prefetch_layers(reader, entries, parser, tx));
+
+        Ok(Self {
+            index,
+            rx,
+            delivered: 0,
+        })
+    }
+
+    pub fn layer_count(&self) -> usize {
+        self.index.len()
+    }
+
+    /// Layer index as read from the file.
+    pub fn index(&self) -> &[LayerIndexEntry] {
+        &self.index
+    }
+
+    /// Layers not yet returned by [`next_layer`](Self::next_layer).
+    pub fn remaining(&self) -> usize {
+        self.index.len() - self.delivered
+    }
+
+    /// Returns the next layer, waiting only if the prefetcher has fallen
+    /// behind. `None` once every layer has been delivered or after an error.
+    pub async fn next_layer(&mut self) -> Option<Result<LayerFrame>> {
+        let item = self.rx.recv().await?;
+        self.delivered += 1;
+        Some(item)
+    }
+}
+
+/// Reads the header metadata from the start of the file.
+pub fn read_file_metadata<R: Read + Seek>(reader: &mut R) -> Result<FileMetadata> {
+    let mut header = [0u8; 12];
+    reader.seek(SeekFrom::Start(0))?;
+    reader.read_exact(&mut header).context("File too short for an .hg4d header")?;
+
+    let word = |i: usize| u32::from_le_bytes([header[i], header[i + 1], header[i + 2], header[i + 3]]);
+    if word(0) != HG4D_MAGIC {
+        bail!("Not an .hg4d file (magic {:08x})", word(0));
+    }
+    if !SUPPORTED_FORMAT_VERSIONS.contains(&word(4)) {
+        bail!(
+            "Unsupported .hg4d format version {} (this firmware reads {:?}); re-slice the model",
+            word(4),
+            SUPPORTED_FORMAT_VERSIONS
+        );
+    }
+    let size = word(8);
+    if size > MAX_METADATA_SIZE {
+        bail!("Metadata section of {} bytes is too large", size);
+    }
+
+    let mut document = vec![0u8; size as usize];
+    reader.read_exact(&mut document).context("Metadata section is truncated")?;
+    let document = String::from_utf8(document).context("Metadata is not UTF-8")?;
+    toml::from_str(&document).context("Invalid .hg4d metadata")
+}
+
+/// Reads the trailer and index from the end of the file.
+pub fn read_layer_index<R: Read + Seek>(reader: &mut R) -> Result<Vec<LayerIndexEntry>> {
+    let file_len = reader.seek(SeekFrom::End(0))?;
+    if file_len < IndexTrailer::ENCODED_SIZE as u64 {
+        bail!("File too short for an index trailer ({} bytes)", file_len);
+    }
+
+    let mut trailer = [0u8; IndexTrailer::ENCODED_SIZE];
+    reader.seek(SeekFrom::End(-(IndexTrailer::ENCODED_SIZE as i64)))?;
+    reader.read_exact(&mut trailer)?;
+    let trailer = IndexTrailer::from_bytes(&trailer).context("Invalid index trailer")?;
+
+    let index_end = trailer.index_offset + trailer.index_size();
+    if index_end + IndexTrailer::ENCODED_SIZE as u64 != file_len {
+        bail!(
+            "Index of {} layers at offset {} does not fit a {} byte file",
+            trailer.layer_count,
+            trailer.index_offset,
+            file_len
+        );
+    }
+
+    let mut raw = vec![0u8; trailer.index_size() as usize];
+    reader.seek(SeekFrom::Start(trailer.index_offset))?;
+    reader.read_exact(&mut raw)?;
+
+    let index = raw
+        .chunks_exact(LayerIndexEntry::ENCODED_SIZE)
+        .map(LayerIndexEntry::from_bytes)
+        .collect::<Result<Vec<_>, _>>()
+        .context("Invalid index entry")?;
+
+    for entry in &index {
+        if entry.data_size > MAX_LAYER_BLOCK_SIZE
+            || entry.file_offset + entry.data_size as u64 > trailer.index_offset
+        {
+            bail!(
+                "Layer {} block ({} bytes at {}) lies outside the layer data",
+                entry.layer_number,
+                entry.data_size,
+                entry.file_offset
+            );
+        }
+    }
+
+    Ok(index)
+}
+
+/// Background task: reads and decodes layers in order until the consumer
+/// is gone, an error occurs, or the file ends.
+fn prefetch_layers<R: Read + Seek>(
+    mut reader: R,
+    index: Vec<LayerIndexEntry>,
+    parser: GCodeParser,
+    tx: mpsc::Sender<Result<LayerFrame>>,
+) {
+    let mut buf = Vec::new();
+
+    for entry in index {
+        let result = read_block(&mut reader, &entry, &mut buf)
+            .and_then(|_| parser.parse_layer_frame(&buf, entry.checksum))
+            .and_then(|frame| {
+                if frame.layer_number != entry.layer_number {
+                    bail!(
+                        "Index entry for layer {} points at layer {}",
+                        entry.layer_number,
+                        frame.layer_number
+                    );
+                }
+                Ok(frame)
+            })
+            .with_context(|| format!("Failed to load layer {}", entry.layer_number));
+
+        let failed = result.is_err();
+        if tx.blocking_send(result).is_err() {
+            debug!("Layer stream dropped; prefetch stopped");
+            return;
+        }
+        if failed {
+            return;
+        }
+    }
+
+    debug!("Layer prefetch complete");
+}
+
+fn read_block<R: Read + Seek>(reader: &mut R, entry: &LayerIndexEntry, buf: &mut Vec<u8>) -> Result<()> {
+    buf.resize(entry.data_size as usize, 0);
+    reader.seek(SeekFrom::Start(entry.file_offset))?;
+    reader.read_exact(buf)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gcode_types::{GridCoordinate, Layer, LayerBlock, NodeValveState, ValveState};
+    use std::io::Cursor;
+
+    fn hg4d_file(layers: u32) -> (Vec<u8>, Vec<LayerIndexEntry>) {
+        let mut file = b"HG4D\x02\x00\x00\x00".to_vec();
+        let mut index = Vec::new();
+
+        for n in 0..layers {
+            let mut layer = Layer::new(0.2 * (n + 1) as f32, n);
+            for x in 0..20 {
+                layer.add_node(NodeValveState::new(
+                    GridCoordinate::new(x, n),
+                    vec![ValveState::open(0)],
+                ));
+            }
+            let data = LayerBlock::encode_compact(&layer).unwrap();
+            index.push(LayerIndexEntry {
+                layer_number: n,
+                z_height: layer.z_height,
+                file_offset: file.len() as u64,
+                data_size: data.len() as u32,
+                checksum: crc32fast::hash(&data),
+            });
+            file.extend_from_slice(&data);
+        }
+
+        let trailer = IndexTrailer {
+            index_offset: file.len() as u64,
+            layer_count: layers,
+        };
+        for entry in &index {
+            file.extend_from_slice(&entry.to_bytes());
+        }
+        file.extend_from_slice(&trailer.to_bytes());
+        (file, index)
+    }
+
+    #[tokio::test]
+    async fn test_streams_layers_in_order() {
+        let (file, _) = hg4d_file(12);
+        let mut stream = LayerStream::from_reader(Cursor::new(file), GCodeParser::new(), 2).unwrap();
+        assert_eq!(stream.layer_count(), 12);
+
+        let mut seen = Vec::new();
+        while let Some(frame) = stream.next_layer().await {
+            let frame = frame.unwrap();
+            assert_eq!(frame.node_count(), 20);
+            seen.push(frame.layer_number);
+        }
+        assert_eq!(seen, (0..12).collect::<Vec<_>>());
+        assert_eq!(stream.remaining(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_corrupt_layer_ends_stream() {
+        let (mut file, index) = hg4d_file(5);
+        file[index[2].file_offset as usize] ^= 0xff;
+
+        let mut stream = LayerStream::from_reader(Cursor::new(file), GCodeParser::new(), 4).unwrap();
+        assert!(stream.next_layer().await.unwrap().is_ok());
+        assert!(stream.next_layer().await.unwrap().is_ok());
+        let err = stream.next_layer().await.unwrap().unwrap_err();
+        assert!(format!("{:#}", err).contains("layer 2"));
+        assert!(stream.next_layer().await.is_none());
+    }
+
+    #[test]
+    fn test_rejects_other_format_versions() {
+        let header = |version: u32| {
+            let mut bytes = HG4D_MAGIC.to_le_bytes().to_vec();
+            bytes.extend_from_slice(&version.to_le_bytes());
+            bytes.extend_from_slice(&0u32.to_le_bytes());
+            bytes
+        };
+        for version in [1, 3] {
+            let err = read_file_metadata(&mut Cursor::new(header(version))).unwrap_err();
+            assert!(err.to_string().contains("re-slice"), "{}", err);
+        }
+        // A current header gets past the version check to its metadata
+        let err = read_file_metadata(&mut Cursor::new(header(2))).unwrap_err();
+        assert!(!err.to_string().contains("format version"), "{}", err);
+    }
+
+    #[test]
+    fn test_rejects_truncated_index() {
+        let (file, _) = hg4d_file(3);
+        let truncated = [&file[..20], &file[file.len() - IndexTrailer::ENCODED_SIZE..]].concat();
+        assert!(read_layer_index(&mut Cursor::new(truncated)).is_err());
+    }
+}
@@ -0,0 +1,180 @@
+//! Low-level bus access shared by hardware drivers.
+//!
+//! Drivers talk to SPI and I2C peripherals through the [`SpiBus`] and
+//! [`I2cBus`] traits, and to discrete signals through [`OutputPin`] and
+//! [`InputPin`], so they can run against the Linux kernel interfaces on the
+//! controller board or against in-memory fakes in tests. A [`BusProvider`]
+//! opens buses by number; the Linux provider maps them to `/dev/spidevB.C`
+//! and `/dev/i2c-B`. A [`GpioProvider`] opens pins by BCM number.
+
+use anyhow::{Context, Result};
+
+/// Full-duplex SPI transfers on one chip select.
+pub trait SpiBus: Send {
+    /// Clocks out `tx` while filling `rx` (both the same length).
+    fn transfer(&mut self, tx: &[u8], rx: &mut [u8]) -> Result<()>;
+}
+
+/// Combined write/read transactions on an I2C bus.
+pub trait I2cBus: Send {
+    /// Writes `write` (if non-empty) then reads `read.len()` bytes from the
+    /// device at `address` with a repeated start.
+    fn write_read(&mut self, address: u8, write: &[u8], read: &mut [u8]) -> Result<()>;
+}
+
+/// Opens buses by number.
+pub trait BusProvider {
+    fn open_spi(&self, bus: u8, chip_select: u8) -> Result<Box<dyn SpiBus>>;
+    fn open_i2c(&self, bus: u8) -> Result<Box<dyn I2cBus>>;
+}
+
+/// Push-pull digital output.
+pub trait OutputPin: Send {
+    fn set(&mut self, high: bool) -> Result<()>;
+}
+
+/// Digital input.
+pub trait InputPin: Send {
+    fn is_high(&self) -> Result<bool>;
+}
+
+/// Opens GPIO pins by number.
+pub trait GpioProvider {
+    fn output(&self, pin: u8) -> Result<Box<dyn OutputPin>>;
+    /// Opens an input with the internal pull-up enabled.
+    fn input(&self, pin: u8) -> Result<Box<dyn InputPin>>;
+}
+
+/// Default SPI clock for sensor peripherals (Hz).
+pub const DEFAULT_SPI_SPEED_HZ: u32 = 1_000_000;
+
+/// Opens buses through the Linux spidev and i2c-dev interfaces.
+#[derive(Debug, Clone)]
+pub struct LinuxBusProvider {
+    spi_speed_hz: u32,
+}
+
+impl LinuxBusProvider {
+    pub fn new() -> Self {
+        Self {
+            spi_speed_hz: DEFAULT_SPI_SPEED_HZ,
+        }
+    }
+
+    pub fn with_spi_speed(mut self, hz: u32) -> Self {
+        self.spi_speed_hz = hz;
+        self
+    }
+}
+
+impl Default for LinuxBusProvider {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BusProvider for LinuxBusProvider {
+    fn open_spi(&self, bus: u8, chip_select: u8) -> Result<Box<dyn SpiBus>> {
+        use spidev::{SpiModeFlags, Spidev, SpidevOptions};
+
+        let path = format!("/dev/spidev{}.{}", bus, chip_select);
+        let mut dev = Spidev::open(&path).with_context(|| format!("Failed to open {}", path))?;
+        let options = SpidevOptions::new()
+            .bits_per_word(8)
+            .max_speed_hz(self.spi_speed_hz)
+            .mode(SpiModeFlags::SPI_MODE_0)
+            .build();
+        dev.configure(&options)
+            .with_context(|| format!("Failed to configure {}", path))?;
+
+        Ok(Box::new(LinuxSpiBus { dev, path }))
+    }
+
+    fn open_i2c(&self, bus: u8) -> Result<Box<dyn I2cBus>> {
+        let path = format!("/dev/i2c-{}", bus);
+        let dev = i2cdev::linux::LinuxI2CBus::new(&path)
+            .with_context(|| format!("Failed to open {}", path))?;
+        Ok(Box::new(LinuxI2cBus { dev, path }))
+    }
+}
+
+impl GpioProvider for LinuxBusProvider {
+    fn output(&self, pin: u8) -> Result<Box<dyn OutputPin>> {
+        let pin = rppal::gpio::Gpio::new()
+            .context("Failed to open GPIO")?
+            .get(pin)
+            .with_context(|| format!("GPIO {} unavailable", pin))?
+            .into_output_low();
+        Ok(Box::new(LinuxOutputPin(pin)))
+    }
+
+    fn input(&self, pin: u8) -> Result<Box<dyn InputPin>> {
+        let pin = rppal::gpio::Gpio::new()
+            .context("Failed to open GPIO")?
+            .get(pin)
+            .with_context(|| format!("GPIO {} unavailable", pin))?
+            .into_input_pullup();
+        Ok(Box::new(LinuxInputPin(pin)))
+    }
+}
+
+struct LinuxOutputPin(rppal::gpio::OutputPin);
+
+impl OutputPin for LinuxOutputPin {
+    fn set(&mut self, high: bool) -> Result<()> {
+        if high {
+            self.0.set_high();
+        } else {
+            self.0.set_low();
+        }
+        Ok(())
+    }
+}
+
+struct LinuxInputPin(rppal::gpio::InputPin);
+
+impl InputPin for LinuxInputPin {
+    fn is_high(&self) -> Result<bool> {
+        Ok(self.0.is_high())
+    }
+}
+
+struct LinuxSpiBus {
+    dev: spidev::Spidev,
+    path: String,
+}
+
+impl SpiBus for LinuxSpiBus {
+    fn transfer(&mut self, tx: &[u8], rx: &mut [u8]) -> Result<()> {
+        let mut transfer = spidev::SpidevTransfer::read_write(tx, rx);
+        self.dev
+            .transfer(&mut transfer)
+            .with_context(|| format!("SPI transfer on {} failed", self.path))
+    }
+}
+
+struct LinuxI2cBus {
+    dev: i2cdev::linux::LinuxI2CBus,
+    path: String,
+}
+
+impl I2cBus for LinuxI2cBus {
+    fn write_read(&mut self, address: u8, write: &[u8], read: &mut [u8]) -> Result<()> {
+        use i2cdev::core::{I2CMessage, I2CTransfer};
+        use i2cdev::linux::LinuxI2CMessage;
+
+        let address = address as u16;
+        let result = if write.is_empty() {
+            self.dev
+                .transfer(&mut [LinuxI2CMessage::read(read).with_address(address)])
+        } else {
+            self.dev.transfer(&mut [
+                LinuxI2CMessage::write(write).with_address(address),
+                LinuxI2CMessage::read(read).with_address(address),
+            ])
+        };
+        result
+            .map(|_| ())
+            .with_context(|| format!("I2C transfer to 0x{:02x} on {} failed", address, self.path))
+    }
+}
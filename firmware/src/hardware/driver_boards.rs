@@ -0,0 +1,427 @@
+//! Valve driver board discovery and topology self-test.
+//!
+//! Driver boards are daisy-chained on one SPI chip select. Each board holds
+//! a [`FRAME_BYTES`]-byte shift register: bytes clocked in on MOSI push the
+//! register out to the next board, and the last board's output returns on
+//! MISO. The first frame of a transfer therefore lands in the board
+//! farthest from the controller, and the first frame read back came from
+//! it. When chip select is released every board acts on the frame in its
+//! register:
+//!
+//! | Frame                            | Board loads for the next transfer    |
+//! |----------------------------------|--------------------------------------|
+//! | `NOP`                            | zeros                                |
+//! | `READ_ID`                        | `REPLY_ID, id_hi, id_lo, check`      |
+//! | `LATCH_TEST, p, !p`              | `LATCH_TEST, latch, !latch, check`   |
+//!
+//! The last byte of every frame is the XOR of the first three; frames that
+//! fail it are treated as `NOP`. `LATCH_TEST` loads the output latch with
+//! the drivers disabled, so no valve moves; the next valve frame re-enables
+//! them.
+//!
+//! [`DriverChain::self_test`] counts the boards by timing a marker through
+//! the chain, reads every board's ID, latches two patterns through each
+//! board and compares the result with the configuration. Faults carry the
+//! position in the chain so the message says which cable or board to check.
+
+use std::fmt;
+
+use anyhow::Result;
+use tracing::debug;
+
+use config_types::{ValveArrayConfig, ValveDriverConfig};
+
+use super::bus::{BusProvider, SpiBus};
+use crate::FirmwareError;
+
+/// Bytes in each board's shift register.
+pub const FRAME_BYTES: usize = 4;
+
+/// Boards beyond the configured count probed during discovery, so a chain
+/// with extra boards is reported as such rather than as broken.
+pub const DISCOVERY_MARGIN: usize = 8;
+
+const CMD_NOP: u8 = 0x00;
+const CMD_READ_ID: u8 = 0xA1;
+const CMD_LATCH_TEST: u8 = 0xA2;
+const REPLY_ID: u8 = 0xB1;
+
+/// Shifted through the chain to count boards. Its check byte is wrong, so
+/// a board holding it at chip select release ignores it.
+const MARKER: [u8; FRAME_BYTES] = [0x5A, 0xC3, 0x96, 0x3C];
+
+/// Latch patterns; each board rotates them by its position so crossed data
+/// lines between neighbours show up.
+const LATCH_PATTERNS: [u8; 2] = [0x55, 0xAA];
+
+/// A board found in the chain.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DriverBoard {
+    /// Position in the chain, 0 nearest the controller
+    pub position: usize,
+    /// None if the board's ID reply was corrupt
+    pub id: Option<u16>,
+    pub latch_ok: bool,
+}
+
+/// A mismatch between the chain and the configuration.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TopologyFault {
+    /// The marker never came back
+    NoResponse { probed: usize },
+    Missing { found: usize, expected: usize },
+    Unexpected { found: usize, expected: usize },
+    UnreadableId { position: usize },
+    /// A board whose ID is not configured anywhere
+    WrongBoard { position: usize, expected: u16, found: u16 },
+    /// A configured board at the wrong position
+    Misordered { position: usize, id: u16, configured_position: usize },
+    LatchFailed { position: usize, wrote: u8, read: u8 },
+}
+
+impl fmt::Display for TopologyFault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            TopologyFault::NoResponse { probed } => write!(
+                f,
+                "No reply from the valve driver chain within {} boards: check the SPI cable to board 0, \
+                 board power and the return line from the last board",
+                probed
+            ),
+            TopologyFault::Missing { found: 0, expected } => write!(
+                f,
+                "No valve driver boards answered ({} expected): check the link from the controller to board 0",
+                expected
+            ),
+            TopologyFault::Missing { found, expected } => write!(
+                f,
+                "Found {} of {} valve driver boards: check the link from board {} to board {} and board {}'s power",
+                found,
+                expected,
+                found - 1,
+                found,
+                found
+            ),
+            TopologyFault::Unexpected { found, expected } => write!(
+                f,
+                "Found {} valve driver boards but {} are configured: remove the extra boards or update \
+                 valve_array.drivers",
+                found, expected
+            ),
+            TopologyFault::UnreadableId { position } => write!(
+                f,
+                "Board {} returned a corrupt ID: check the data lines into and out of it",
+                position
+            ),
+            TopologyFault::WrongBoard { position, expected, found } => write!(
+                f,
+                "Board {} has ID 0x{:04x} where 0x{:04x} is configured, and 0x{:04x} is not in \
+                 valve_array.drivers.board_ids",
+                position, found, expected, found
+            ),
+            TopologyFault::Misordered { position, id, configured_position } => write!(
+                f,
+                "Board 0x{:04x} is at position {} but configured at position {}: swap the chain cables \
+                 or reorder valve_array.drivers.board_ids",
+                id, position, configured_position
+            ),
+            TopologyFault::LatchFailed { position, wrote, read } => write!(
+                f,
+                "Board {} latched 0x{:02x} as 0x{:02x}: its shift register or output latch is faulty",
+                position, wrote, read
+            ),
+        }
+    }
+}
+
+/// Outcome of a driver chain self-test.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TopologyReport {
+    pub expected: usize,
+    pub boards: Vec<DriverBoard>,
+    pub faults: Vec<TopologyFault>,
+}
+
+impl TopologyReport {
+    /// The configured chain as if every board had answered, for running
+    /// without hardware.
+    pub fn simulated(config: &ValveArrayConfig) -> Self {
+        let expected = config.expected_driver_boards();
+        let boards = (0..expected)
+            .map(|position| DriverBoard {
+                position,
+                id: config.drivers.board_ids.get(position).copied(),
+                latch_ok: true,
+            })
+            .collect();
+        Self {
+            expected,
+            boards,
+            faults: Vec::new(),
+        }
+    }
+
+    pub fn is_ok(&self) -> bool {
+        self.faults.is_empty()
+    }
+
+    /// Fails with every fault listed if the chain does not match.
+    pub fn ensure_ok(&self) -> std::result::Result<(), FirmwareError> {
+        if self.is_ok() {
+            return Ok(());
+        }
+        let faults: Vec<String> = self.faults.iter().map(|f| f.to_string()).collect();
+        Err(FirmwareError::HardwareInit(format!(
+            "Valve driver self-test failed: {}",
+            faults.join("; ")
+        )))
+    }
+}
+
+/// The daisy-chained driver boards on one chip select.
+pub struct DriverChain {
+    spi: Box<dyn SpiBus>,
+}
+
+impl DriverChain {
+    pub fn new(spi: Box<dyn SpiBus>) -> Self {
+        Self { spi }
+    }
+
+    pub fn open(provider: &dyn BusProvider, config: &ValveDriverConfig) -> Result<Self> {
+        Ok(Self::new(provider.open_spi(config.spi_bus, config.chip_select)?))
+    }
+
+    /// Counts the boards, reading at most `probe` boards deep. None if the
+    /// marker never returns.
+    pub fn count_boards(&mut self, probe: usize) -> Result<Option<usize>> {
+        // Clear every register so stale data cannot look like the marker
+        let mut zeros = vec![CMD_NOP; (probe + 1) * FRAME_BYTES];
+        let mut rx = vec![0; zeros.len()];
+        self.spi.transfer(&zeros, &mut rx)?;
+
+        zeros[..FRAME_BYTES].copy_from_slice(&MARKER);
+        self.spi.transfer(&zeros, &mut rx)?;
+        Ok(rx
+            .chunks_exact(FRAME_BYTES)
+            .position(|frame| frame == MARKER))
+    }
+
+    /// Reads the ID of each of `count` boards, nearest first.
+    pub fn read_ids(&mut self, count: usize) -> Result<Vec<Option<u16>>> {
+        let replies = self.exchange(&vec![command(CMD_READ_ID, 0, 0); count])?;
+        Ok(replies
+            .iter()
+            .map(|reply| {
+                let valid = reply[0] == REPLY_ID && check_byte(reply) == reply[3];
+                valid.then(|| u16::from_be_bytes([reply[1], reply[2]]))
+            })
+            .collect())
+    }
+
+    /// Latches the test patterns through each of `count` boards and returns
+    /// the first mismatch per board as (wrote, read).
+    pub fn latch_test(&mut self, count: usize) -> Result<Vec<Option<(u8, u8)>>> {
+        let mut failures = vec![None; count];
+        for base in LATCH_PATTERNS {
+            let frames: Vec<_> = (0..count)
+                .map(|position| {
+                    let pattern = latch_pattern(base, position);
+                    command(CMD_LATCH_TEST, pattern, !pattern)
+                })
+                .collect();
+            let replies = self.exchange(&frames)?;
+            for (position, reply) in replies.iter().enumerate() {
+                let wrote = latch_pattern(base, position);
+                let valid = reply[0] == CMD_LATCH_TEST && reply[2] == !reply[1] && check_byte(reply) == reply[3];
+                let read = if valid { reply[1] } else { !wrote };
+                if read != wrote && failures[position].is_none() {
+                    failures[position] = Some((wrote, read));
+                }
+            }
+        }
+        // Leave every latch cleared
+        self.exchange(&vec![command(CMD_LATCH_TEST, 0, 0xFF); count])?;
+        Ok(failures)
+    }
+
+    /// Discovers the chain and checks it against the configuration.
+    pub fn self_test(&mut self, config: &ValveArrayConfig) -> Result<TopologyReport> {
+        let expected = config.expected_driver_boards();
+        let probe = expected + DISCOVERY_MARGIN;
+        let mut report = TopologyReport {
+            expected,
+            boards: Vec::new(),
+            faults: Vec::new(),
+        };
+
+        let Some(found) = self.count_boards(probe)? else {
+            report.faults.push(TopologyFault::NoResponse { probed: probe });
+            return Ok(report);
+        };
+        debug!("Valve driver chain: {} boards ({} expected)", found, expected);
+        if found < expected {
+            report.faults.push(TopologyFault::Missing { found, expected });
+        } else if found > expected {
+            report.faults.push(TopologyFault::Unexpected { found, expected });
+        }
+
+        let ids = self.read_ids(found)?;
+        let latches = self.latch_test(found)?;
+        let configured = &config.drivers.board_ids;
+        for (position, (id, latch)) in ids.into_iter().zip(latches).enumerate() {
+            match (id, configured.get(position)) {
+                (None, _) => report.faults.push(TopologyFault::UnreadableId { position }),
+                (Some(id), Some(&expected_id)) if id != expected_id => {
+                    report.faults.push(match configured.iter().position(|&c| c == id) {
+                        Some(configured_position) => TopologyFault::Misordered { position, id, configured_position },
+                        None => TopologyFault::WrongBoard { position, expected: expected_id, found: id },
+                    });
+                }
+                _ => {}
+            }
+            if let Some((wrote, read)) = latch {
+                report.faults.push(TopologyFault::LatchFailed { position, wrote, read });
+            }
+            report.boards.push(DriverBoard {
+                position,
+                id,
+                latch_ok: latch.is_none(),
+            });
+        }
+        Ok(report)
+    }
+
+    /// Sends one frame per board (index = position) and returns each
+    /// board's reply, nearest first.
+    fn exchange(&mut self, frames: &[[u8; FRAME_BYTES]]) -> Result<Vec<[u8; FRAME_BYTES]>> {
+        // The farthest board takes the first frame
+        let tx: Vec<u8> = frames.iter().rev().flatten().copied().collect();
+        let mut rx = vec![0; tx.len()];
+        self.spi.transfer(&tx, &mut rx)?;
+
+        let nops = vec![CMD_NOP; tx.len()];
+        self.spi.transfer(&nops, &mut rx)?;
+        Ok(rx
+            .chunks_exact(FRAME_BYTES)
+            .rev()
+            .map(|chunk| [chunk[0], chunk[1], chunk[2], chunk[3]])
+            .collect())
+    }
+}
+
+fn command(cmd: u8, a: u8, b: u8) -> [u8; FRAME_BYTES] {
+    [cmd, a, b, cmd ^ a ^ b]
+}
+
+fn check_byte(frame: &[u8]) -> u8 {
+    frame[0] ^ frame[1] ^ frame[2]
+}
+
+fn latch_pattern(base: u8, position: usize) -> u8 {
+    base.rotate_left((position % 8) as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+    use std::sync::{Arc, Mutex as StdMutex};
+
+    /// A simulated board: its ID and output bits stuck high.
+    #[derive(Clone, Copy)]
+    struct Board {
+        id: u16,
+        stuck_high: u8,
+    }
+
+    /// Shift-register chain of simulated boards, board 0 nearest MOSI.
+    struct FakeChain {
+        boards: Arc<StdMutex<Vec<Board>>>,
+        /// Farthest board's register first
+        registers: VecDeque<u8>,
+    }
+
+    impl SpiBus for FakeChain {
+        fn transfer(&mut self, tx: &[u8], rx: &mut [u8]) -> Result<()> {
+            let boards = self.boards.lock().unwrap();
+            self.registers.resize(boards.len() * FRAME_BYTES, 0);
+            for (out, &byte) in rx.iter_mut().zip(tx) {
+                self.registers.push_back(byte);
+                *out = self.registers.pop_front().unwrap();
+            }
+            // Chip select released: every board acts on its frame
+            let count = boards.len();
+            for (position, board) in boards.iter().enumerate() {
+                let start = (count - 1 - position) * FRAME_BYTES;
+                let frame: Vec<u8> = self.registers.range(start..start + FRAME_BYTES).copied().collect();
+                let reply = if check_byte(&frame) != frame[3] {
+                    [0; FRAME_BYTES]
+                } else if frame[0] == CMD_READ_ID {
+                    let [hi, lo] = board.id.to_be_bytes();
+                    command(REPLY_ID, hi, lo)
+                } else if frame[0] == CMD_LATCH_TEST {
+                    let latch = frame[1] | board.stuck_high;
+                    command(CMD_LATCH_TEST, latch, !latch)
+                } else {
+                    [0; FRAME_BYTES]
+                };
+                for (i, byte) in reply.into_iter().enumerate() {
+                    self.registers[start + i] = byte;
+                }
+            }
+            Ok(())
+        }
+    }
+
+    fn config(board_ids: Vec<u16>) -> ValveArrayConfig {
+        ValveArrayConfig {
+            grid_spacing: 1.0,
+            total_nodes: 48,
+            valves_per_node: 4,
+            valve_type: config_types::ValveType::PneumaticSolenoid,
+            response_time_ms: 10.0,
+            dead_volume: config_types::CubicMm::new(0.5),
+            max_switching_freq: 10.0,
+            injection_points: vec![],
+            verification: Default::default(),
+            drivers: ValveDriverConfig { board_ids, ..Default::default() },
+            sub_frames: Default::default(),
+            frame_merge_window_ms: 0.0,
+        }
+    }
+
+    #[test]
+    fn test_self_test_reports_chain_faults() {
+        let board = |id| Board { id, stuck_high: 0 };
+        let boards = Arc::new(StdMutex::new(vec![board(0x10), board(0x11), board(0x12)]));
+        let mut chain = DriverChain::new(Box::new(FakeChain { boards: boards.clone(), registers: VecDeque::new() }));
+
+        // 48 nodes x 4 valves on 64-output boards
+        let report = chain.self_test(&config(vec![])).unwrap();
+        assert!(report.is_ok(), "{:?}", report.faults);
+        let ids: Vec<_> = report.boards.iter().map(|b| b.id).collect();
+        assert_eq!(ids, vec![Some(0x10), Some(0x11), Some(0x12)]);
+        let simulated = TopologyReport::simulated(&config(vec![0x10, 0x11, 0x12]));
+        assert_eq!(simulated, report);
+
+        // Cables swapped between the last two boards, one output stuck
+        *boards.lock().unwrap() = vec![board(0x10), Board { id: 0x12, stuck_high: 0x01 }, board(0x11)];
+        let report = chain.self_test(&config(vec![0x10, 0x11, 0x12])).unwrap();
+        assert_eq!(
+            report.faults,
+            vec![
+                TopologyFault::Misordered { position: 1, id: 0x12, configured_position: 2 },
+                TopologyFault::LatchFailed { position: 1, wrote: 0xAA, read: 0xAB },
+                TopologyFault::Misordered { position: 2, id: 0x11, configured_position: 1 },
+            ]
+        );
+        assert!(report.ensure_ok().unwrap_err().to_string().contains("swap the chain cables"));
+
+        // Chain broken after the first board
+        boards.lock().unwrap().truncate(1);
+        let report = chain.self_test(&config(vec![])).unwrap();
+        assert_eq!(report.faults, vec![TopologyFault::Missing { found: 1, expected: 3 }]);
+        assert!(report.faults[0].to_string().contains("from board 0 to board 1"));
+    }
+}
@@ -0,0 +1,668 @@
+//! Zone heater control.
+//!
+//! Each thermal zone's heater is switched by an SSR on a GPIO using
+//! time-proportioned PWM: a [`PWM_PERIOD`] cycle is divided into
+//! [`PWM_SLOTS`] slots and the heater is on for a number of slots set by
+//! its duty cycle. Once per cycle [`PidHeaterController::update_control`]
+//! reads the zone temperatures and runs one PID step per zone:
+//!
+//! ```text
+//! duty = (kp·e + ki·∫e + kd·de/dt) / 255        (e = target − measured)
+//! ```
+//!
+//! Gains use the 0-255 output scale common to printer firmware; the duty is
+//! clamped to 0..1 and the integral only accumulates while it is not
+//! saturated.
+//!
+//! ## Power budget
+//!
+//! With `ThermalConfig::supply_watts` set, heaters share a limited supply.
+//! [`schedule_slots`] first scales every duty down proportionally if their
+//! average draw exceeds the supply, then hands out slots round-robin, each
+//! into the least-loaded slot that still has room. The heaters' on-windows
+//! are staggered and their instantaneous draw never exceeds the supply. The
+//! duty actually granted is reported by [`HeaterController::duty_cycles`].
+//!
+//! The heated bed and chamber, when configured with a heater pin and a
+//! sensor zone, run as further zones under their sensor zone ids and share
+//! the power budget.
+//!
+//! `update_control` must be called at least once per slot
+//! (`PWM_PERIOD / PWM_SLOTS`) to switch the outputs on time.
+//!
+//! ## Zone faults
+//!
+//! A zone is disabled, its heater held off and new targets refused, when
+//! its thermistor reads open or shorted or when its heater is decoupled:
+//! granted at least [`DECOUPLE_MIN_DUTY`] while more than
+//! [`DECOUPLE_HOLD_BAND`] below target, the zone must gain
+//! [`DECOUPLE_MIN_RISE`] within every [`DECOUPLE_WINDOW`]. Other zones keep
+//! running; the faults are reported by [`HeaterController::faults`] and stay
+//! until [`HeaterController::clear_fault`].
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Context, Result};
+use tracing::{debug, error, info, warn};
+
+use config_types::{PrinterConfig, ThermalConfig, ThermalZone};
+
+use super::bus::{GpioProvider, OutputPin};
+use super::pressure::{pid_step, PidState};
+use crate::{FirmwareError, HeaterController, SensorInterface, ThermalFault};
+
+/// Length of one PWM cycle.
+pub const PWM_PERIOD: Duration = Duration::from_secs(1);
+
+/// Slots per PWM cycle; duty cycles are granted in steps of one slot.
+pub const PWM_SLOTS: usize = 20;
+
+/// Full-scale PID output the configured gains are tuned for.
+const PID_OUTPUT_SCALE: f32 = 255.0;
+
+/// Granted duty above which a heater is expected to raise its zone.
+pub const DECOUPLE_MIN_DUTY: f32 = 0.5;
+
+/// Rise (°C) a hard-driven zone must make within [`DECOUPLE_WINDOW`].
+pub const DECOUPLE_MIN_RISE: f32 = 2.0;
+
+pub const DECOUPLE_WINDOW: Duration = Duration::from_secs(20);
+
+/// Distance below target (°C) within which the zone is holding, not
+/// heating, and high duty only means losses are high.
+pub const DECOUPLE_HOLD_BAND: f32 = 5.0;
+
+/// A zone's request to the power scheduler.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HeaterDemand {
+    pub power_watts: f32,
+    /// Requested duty cycle (0..1)
+    pub duty: f32,
+}
+
+/// Assigns PWM slots to heaters without exceeding the supply in any slot.
+///
+/// Returns one bitmask per demand, bit `n` set if the heater is on in slot
+/// `n`. With no supply limit every heater gets the slots it asked for.
+pub fn schedule_slots(demands: &[HeaterDemand], supply_watts: Option<f32>) -> Vec<u32> {
+    let supply = supply_watts.unwrap_or(f32::INFINITY);
+    let average: f32 = demands.iter().map(|d| d.power_watts * d.duty.clamp(0.0, 1.0)).sum();
+    let scale = if average > supply { supply / average } else { 1.0 };
+
+    let mut load = [0.0f32; PWM_SLOTS];
+    let mut masks = vec![0u32; demands.len()];
+    let mut wanted: Vec<usize> = demands
+        .iter()
+        .map(|d| ((d.duty.clamp(0.0, 1.0) * scale) * PWM_SLOTS as f32).floor() as usize)
+        .collect();
+
+    // Slots are handed out one per heater per round, so when heaters cannot
+    // all fit each gives up a share rather than the last one starving. The
+    // cursor makes each heater start where the previous one ended, so
+    // windows interleave.
+    let mut cursor = 0;
+    let mut progress = true;
+    while progress {
+        progress = false;
+        for (i, demand) in demands.iter().enumerate() {
+            if wanted[i] == 0 {
+                continue;
+            }
+            let slot = (0..PWM_SLOTS)
+                .map(|k| (cursor + k) % PWM_SLOTS)
+                .filter(|&s| masks[i] & (1 << s) == 0 && load[s] + demand.power_watts <= supply)
+                .min_by(|&a, &b| load[a].total_cmp(&load[b]));
+            match slot {
+                Some(slot) => {
+                    masks[i] |= 1 << slot;
+                    load[slot] += demand.power_watts;
+                    cursor = (slot + 1) % PWM_SLOTS;
+                    wanted[i] -= 1;
+                    progress = true;
+                }
+                // Loads only grow, so no slot will open up later
+                None => wanted[i] = 0,
+            }
+        }
+    }
+    masks
+}
+
+fn duty_of(mask: u32) -> f32 {
+    mask.count_ones() as f32 / PWM_SLOTS as f32
+}
+
+struct ZoneLoop {
+    config: ThermalZone,
+    pin: Box<dyn OutputPin>,
+    target: f32,
+    /// PID output before budgeting (0..1)
+    requested: f32,
+    /// Slots the heater is on in this cycle
+    mask: u32,
+    on: bool,
+    pid: PidState,
+    /// Start of the current hard-heating interval and the temperature then
+    watch: Option<(Instant, f32)>,
+    fault: Option<ThermalFault>,
+}
+
+impl ZoneLoop {
+    /// Takes the zone out of service with its heater off.
+    fn disable(&mut self, fault: ThermalFault) -> Result<()> {
+        error!("Zone {} ({}) disabled: {}", self.config.id, self.config.name, fault);
+        self.fault = Some(fault);
+        self.target = 0.0;
+        self.requested = 0.0;
+        self.mask = 0;
+        self.pid = PidState::new();
+        self.watch = None;
+        self.on = false;
+        self.pin.set(false)
+    }
+
+    /// Tracks a zone heated hard towards its target; true if it has not
+    /// risen enough within the window.
+    fn is_decoupled(&mut self, measured: f32, now: Instant) -> bool {
+        let heating = duty_of(self.mask) >= DECOUPLE_MIN_DUTY
+            && self.target - measured > DECOUPLE_HOLD_BAND;
+        match self.watch {
+            _ if !heating => self.watch = None,
+            Some((_, from)) if measured - from >= DECOUPLE_MIN_RISE => self.watch = Some((now, measured)),
+            Some((since, _)) => return now.saturating_duration_since(since) >= DECOUPLE_WINDOW,
+            None => self.watch = Some((now, measured)),
+        }
+        false
+    }
+}
+
+/// Heater controller running one PID loop per thermal zone.
+pub struct PidHeaterController {
+    zones: Vec<ZoneLoop>,
+    /// Zone ids of the bed and chamber loops, if they can be heated
+    bed: Option<u8>,
+    chamber: Option<u8>,
+    sensors: Arc<Box<dyn SensorInterface>>,
+    supply_watts: Option<f32>,
+    /// Start of the current PWM cycle
+    cycle_start: Option<Instant>,
+}
+
+impl PidHeaterController {
+    /// Opens the heater output of every zone, all switched off.
+    pub fn new(
+        config: &PrinterConfig,
+        sensors: Arc<Box<dyn SensorInterface>>,
+        gpio: &dyn GpioProvider,
+    ) -> Result<Self> {
+        Self::from_thermal(&config.thermal, sensors, gpio)
+    }
+
+    /// Builds the controller from the thermal section alone.
+    pub fn from_thermal(
+        thermal: &ThermalConfig,
+        sensors: Arc<Box<dyn SensorInterface>>,
+        gpio: &dyn GpioProvider,
+    ) -> Result<Self> {
+        // The bed and chamber run as zones of their own, identified by
+        // their sensor zones
+        let bed = thermal.bed.as_ref().map(|bed| ThermalZone {
+            id: bed.sensor_zone,
+            name: "bed".to_string(),
+            min_temp: bed.min_temp,
+            max_temp: bed.max_temp,
+            power_watts: bed.power_watts,
+            pid: bed.pid,
+            heater_pin: bed.heater_pin,
+            regions: vec![],
+            thermal_mass: bed.thermal_mass,
+        });
+        let chamber = match &thermal.chamber {
+            Some(chamber) => match chamber.sensor_zone {
+                Some(id) => Some(ThermalZone {
+                    id,
+                    name: "chamber".to_string(),
+                    min_temp: 0.0,
+                    max_temp: chamber.max_temp,
+                    power_watts: chamber.power_watts,
+                    pid: chamber.pid,
+                    heater_pin: chamber.heater_pin,
+                    regions: vec![],
+                    thermal_mass: chamber.thermal_mass,
+                }),
+                None => {
+                    warn!("Chamber has no sensor zone; it cannot be heated");
+                    None
+                }
+            },
+            None => None,
+        };
+
+        let mut zones = Vec::with_capacity(thermal.zones.len() + 2);
+        for zone in thermal.zones.iter().chain(&bed).chain(&chamber) {
+            let Some(pin) = zone.heater_pin else {
+                warn!("Zone {} ({}) has no heater pin; it cannot be heated", zone.id, zone.name);
+                continue;
+            };
+            let mut pin = gpio
+                .output(pin)
+                .with_context(|| format!("Heater pin for zone {}", zone.id))?;
+            pin.set(false)?;
+            zones.push(ZoneLoop {
+                config: zone.clone(),
+                pin,
+                target: 0.0,
+                requested: 0.0,
+                mask: 0,
+                on: false,
+                pid: PidState::new(),
+                watch: None,
+                fault: None,
+            });
+        }
+
+        info!(
+            "Heater control on {} zone(s), supply {}",
+            zones.len(),
+            thermal
+                .supply_watts
+                .map(|w| format!("{:.0} W", w))
+                .unwrap_or_else(|| "unlimited".to_string())
+        );
+        let controlled = |zone: Option<ThermalZone>| {
+            zone.map(|z| z.id).filter(|id| zones.iter().any(|z| z.config.id == *id))
+        };
+        Ok(Self {
+            bed: controlled(bed),
+            chamber: controlled(chamber),
+            zones,
+            sensors,
+            supply_watts: thermal.supply_watts,
+            cycle_start: None,
+        })
+    }
+
+    fn zone(&self, zone_id: u8) -> Result<&ZoneLoop> {
+        self.zones
+            .iter()
+            .find(|z| z.config.id == zone_id)
+            .ok_or_else(|| anyhow!("No heater for zone {}", zone_id))
+    }
+
+    fn zone_mut(&mut self, zone_id: u8) -> Result<&mut ZoneLoop> {
+        self.zones
+            .iter_mut()
+            .find(|z| z.config.id == zone_id)
+            .ok_or_else(|| anyhow!("No heater for zone {}", zone_id))
+    }
+
+    /// Set-point of a zone (°C).
+    pub fn target(&self, zone_id: u8) -> Result<f32> {
+        Ok(self.zone(zone_id)?.target)
+    }
+
+    /// Duty cycle the PID loop asked for before budgeting (0..1).
+    pub fn requested_duty(&self, zone_id: u8) -> Result<f32> {
+        Ok(self.zone(zone_id)?.requested)
+    }
+
+    /// Switches every heater off and clears all targets, collecting
+    /// failures rather than stopping at the first.
+    fn all_off(&mut self) -> Vec<anyhow::Error> {
+        let mut failures = Vec::new();
+        for zone in &mut self.zones {
+            zone.target = 0.0;
+            zone.requested = 0.0;
+            zone.mask = 0;
+            zone.pid = PidState::new();
+            zone.watch = None;
+            zone.on = false;
+            if let Err(e) = zone.pin.set(false) {
+                failures.push(e.context(format!("zone {} heater", zone.config.id)));
+            }
+        }
+        self.cycle_start = None;
+        failures
+    }
+
+    /// Reads temperatures, runs the PID loops and plans the next cycle.
+    async fn start_cycle(&mut self, now: Instant) -> Result<()> {
+        let (readings, faults) = match self.sensors.read_all().await {
+            Ok(readings) => (readings.temperatures, readings.thermal_faults),
+            Err(e) => {
+                // Never heat blind
+                self.all_off();
+                return Err(e.context("Heater temperature read failed"));
+            }
+        };
+
+        for i in 0..self.zones.len() {
+            let zone = &mut self.zones[i];
+            let id = zone.config.id;
+            if zone.fault.is_none() {
+                if let Some(&fault) = faults.get(&id) {
+                    if let Err(e) = zone.disable(fault) {
+                        self.all_off();
+                        return Err(e.context(format!("Failed to disable zone {} heater", id)));
+                    }
+                }
+            }
+
+            let zone = &self.zones[i];
+            if zone.target <= 0.0 {
+                let zone = &mut self.zones[i];
+                zone.requested = 0.0;
+                zone.pid = PidState::new();
+                continue;
+            }
+
+            let Some(&measured) = readings.get(&id) else {
+                self.all_off();
+                return Err(FirmwareError::HardwareOperation(format!(
+                    "No temperature reading for heated zone {}",
+                    id
+                ))
+                .into());
+            };
+            if measured > zone.config.max_temp {
+                error!(
+                    "Zone {} at {:.1}°C exceeds its {:.1}°C limit; heaters off",
+                    id, measured, zone.config.max_temp
+                );
+                for failure in self.all_off() {
+                    error!("Heater shutdown failed: {:#}", failure);
+                }
+                return Err(FirmwareError::SafetyViolation(format!(
+                    "Zone {} overtemperature: {:.1}°C (limit {:.1})",
+                    id, measured, zone.config.max_temp
+                ))
+                .into());
+            }
+
+            let zone = &mut self.zones[i];
+            if zone.is_decoupled(measured, now) {
+                if let Err(e) = zone.disable(ThermalFault::HeaterDecoupled) {
+                    self.all_off();
+                    return Err(e.context(format!("Failed to disable zone {} heater", id)));
+                }
+                continue;
+            }
+            let error = (zone.target - measured) / PID_OUTPUT_SCALE;
+            zone.requested = pid_step(&mut zone.pid, &zone.config.pid, 0.0, error, now);
+        }
+
+        let demands: Vec<HeaterDemand> = self
+            .zones
+            .iter()
+            .map(|z| HeaterDemand { power_watts: z.config.power_watts, duty: z.requested })
+            .collect();
+        let masks = schedule_slots(&demands, self.supply_watts);
+        for (zone, mask) in self.zones.iter_mut().zip(masks) {
+            if zone.requested > 0.0 && duty_of(mask) + 1.0 / PWM_SLOTS as f32 <= zone.requested {
+                debug!(
+                    "Zone {} limited by power budget: {:.2} of {:.2} requested",
+                    zone.config.id,
+                    duty_of(mask),
+                    zone.requested
+                );
+            }
+            zone.mask = mask;
+        }
+        self.cycle_start = Some(now);
+        Ok(())
+    }
+
+    /// Drives each heater output for the slot `now` falls in.
+    fn apply_slot(&mut self, now: Instant) -> Result<()> {
+        let Some(start) = self.cycle_start else {
+            return Ok(());
+        };
+        let slot_len = PWM_PERIOD / PWM_SLOTS as u32;
+        let slot = ((now.saturating_duration_since(start).as_nanos() / slot_len.as_nanos()) as usize)
+            .min(PWM_SLOTS - 1);
+
+        for zone in &mut self.zones {
+            let on = zone.mask & (1 << slot) != 0;
+            if on != zone.on {
+                zone.pin.set(on)?;
+                zone.on = on;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl HeaterController for PidHeaterController {
+    async fn set_temperature(&mut self, zone_id: u8, target: f32) -> Result<()> {
+        let index = self
+            .zones
+            .iter()
+            .position(|z| z.config.id == zone_id)
+            .ok_or_else(|| anyhow!("No heater for zone {}", zone_id))?;
+        let zone = &mut self.zones[index];
+        if let Some(fault) = zone.fault.filter(|_| target != 0.0) {
+            return Err(FirmwareError::SafetyViolation(format!(
+                "Zone {} is disabled ({}); clear the fault before heating",
+                zone_id, fault
+            ))
+            .into());
+        }
+        if target != 0.0 && !(zone.config.min_temp..=zone.config.max_temp).contains(&target) {
+            return Err(FirmwareError::SafetyViolation(format!(
+                "Temperature target {:.1}°C for zone {} outside {:.1}-{:.1}°C",
+                target, zone_id, zone.config.min_temp, zone.config.max_temp
+            ))
+            .into());
+        }
+        zone.target = target;
+        debug!("Zone {} temperature target {:.1}°C", zone_id, target);
+        Ok(())
+    }
+
+    async fn set_bed_temperature(&mut self, target: f32) -> Result<()> {
+        let Some(id) = self.bed else {
+            return Err(FirmwareError::HardwareOperation("No bed heater fitted".to_string()).into());
+        };
+        self.set_temperature(id, target).await
+    }
+
+    async fn set_chamber_temperature(&mut self, target: f32) -> Result<()> {
+        let Some(id) = self.chamber else {
+            return Err(FirmwareError::HardwareOperation("No chamber heater fitted".to_string()).into());
+        };
+        self.set_temperature(id, target).await
+    }
+
+    async fn get_temperature(&self, zone_id: u8) -> Result<f32> {
+        self.sensors
+            .read_all()
+            .await?
+            .temperatures
+            .get(&zone_id)
+            .copied()
+            .ok_or_else(|| anyhow!("No temperature reading for zone {}", zone_id))
+    }
+
+    async fn update_control(&mut self) -> Result<()> {
+        let now = Instant::now();
+        let in_cycle = self
+            .cycle_start
+            .is_some_and(|start| now.saturating_duration_since(start) < PWM_PERIOD);
+        if !in_cycle {
+            self.start_cycle(now).await?;
+        }
+        self.apply_slot(now)
+    }
+
+    async fn emergency_off(&mut self) -> Result<()> {
+        warn!("Emergency shutdown of all heaters");
+        match self.all_off().into_iter().next() {
+            None => Ok(()),
+            Some(first) => Err(first.context("Heater shutdown incomplete")),
+        }
+    }
+
+    fn duty_cycles(&self) -> BTreeMap<u8, f32> {
+        self.zones.iter().map(|z| (z.config.id, duty_of(z.mask))).collect()
+    }
+
+    fn faults(&self) -> BTreeMap<u8, ThermalFault> {
+        self.zones
+            .iter()
+            .filter_map(|z| z.fault.map(|f| (z.config.id, f)))
+            .collect()
+    }
+
+    async fn clear_fault(&mut self, zone_id: u8) -> Result<()> {
+        let zone = self.zone_mut(zone_id)?;
+        if let Some(fault) = zone.fault.take() {
+            info!("Zone {} fault cleared ({})", zone_id, fault);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex as StdMutex;
+
+    use config_types::{BedHeating, PidParameters};
+
+    use crate::SensorReadings;
+    use super::super::bus::InputPin;
+
+    struct NullPin;
+
+    impl OutputPin for NullPin {
+        fn set(&mut self, _high: bool) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    struct NullGpio;
+
+    impl GpioProvider for NullGpio {
+        fn output(&self, _pin: u8) -> Result<Box<dyn OutputPin>> {
+            Ok(Box::new(NullPin))
+        }
+        fn input(&self, _pin: u8) -> Result<Box<dyn InputPin>> {
+            Err(anyhow!("no inputs"))
+        }
+    }
+
+    struct FakeSensors(Arc<StdMutex<SensorReadings>>);
+
+    #[async_trait::async_trait]
+    impl SensorInterface for FakeSensors {
+        async fn read_all(&self) -> Result<SensorReadings> {
+            Ok(self.0.lock().unwrap().clone())
+        }
+        async fn read_sensor(&self, _sensor_id: &str) -> Result<f32> {
+            Ok(0.0)
+        }
+    }
+
+    fn demand(power_watts: f32, duty: f32) -> HeaterDemand {
+        HeaterDemand { power_watts, duty }
+    }
+
+    #[test]
+    fn test_schedule_respects_supply_in_every_slot() {
+        let demands = [demand(100.0, 0.8), demand(100.0, 0.6), demand(60.0, 0.5)];
+
+        // Unlimited: everyone gets what they asked for
+        let masks = schedule_slots(&demands, None);
+        assert_eq!(masks.iter().map(|&m| duty_of(m)).collect::<Vec<_>>(), vec![0.8, 0.6, 0.5]);
+
+        // 150 W supply: at most one 100 W heater on at a time
+        let masks = schedule_slots(&demands, Some(150.0));
+        for slot in 0..PWM_SLOTS {
+            let draw: f32 = demands
+                .iter()
+                .zip(&masks)
+                .filter(|(_, &m)| m & (1 << slot) != 0)
+                .map(|(d, _)| d.power_watts)
+                .sum();
+            assert!(draw <= 150.0, "slot {} draws {} W", slot, draw);
+        }
+        assert_eq!(masks[0] & masks[1], 0);
+
+        // No heater can share a slot here, so the cycle is split between them
+        let granted: Vec<f32> = masks.iter().map(|&m| duty_of(m)).collect();
+        assert!(granted.iter().sum::<f32>() <= 1.0 + 1e-6);
+        assert!(granted.iter().all(|&g| g >= 0.25), "{:?}", granted);
+    }
+
+    #[tokio::test]
+    async fn test_faulted_zones_are_disabled_and_bed_is_a_zone() {
+        let zone = |id| ThermalZone {
+            id,
+            name: format!("zone{}", id),
+            min_temp: 0.0,
+            max_temp: 300.0,
+            power_watts: 40.0,
+            pid: PidParameters { kp: 50.0, ki: 0.0, kd: 0.0 },
+            heater_pin: Some(id),
+            regions: vec![],
+            thermal_mass: None,
+        };
+        let thermal = ThermalConfig {
+            zones: vec![zone(0), zone(1)],
+            manifold: None,
+            chamber: None,
+            bed: Some(BedHeating {
+                power_watts: 200.0,
+                min_temp: 20.0,
+                max_temp: 110.0,
+                pid: PidParameters::default(),
+                heater_pin: Some(2),
+                sensor_zone: 2,
+                thermal_mass: None,
+            }),
+            channel_zones: vec![],
+            supply_watts: None,
+        };
+        let readings = Arc::new(StdMutex::new(SensorReadings::default()));
+        readings.lock().unwrap().temperatures.extend([(0, 25.0), (1, 25.0)]);
+        let sensors: Arc<Box<dyn SensorInterface>> = Arc::new(Box::new(FakeSensors(readings.clone())));
+        let mut heaters = PidHeaterController::from_thermal(&thermal, sensors, &NullGpio).unwrap();
+        heaters.set_temperature(0, 200.0).await.unwrap();
+        heaters.set_temperature(1, 200.0).await.unwrap();
+
+        // Zone 1 is driven flat out but never warms up
+        let start = Instant::now();
+        heaters.start_cycle(start).await.unwrap();
+        assert_eq!(heaters.duty_cycles()[&1], 1.0);
+        readings.lock().unwrap().temperatures.insert(0, 40.0);
+        heaters.start_cycle(start + Duration::from_secs(10)).await.unwrap();
+        assert!(heaters.faults().is_empty());
+        readings.lock().unwrap().temperatures.insert(0, 60.0);
+        heaters.start_cycle(start + Duration::from_secs(11) + DECOUPLE_WINDOW).await.unwrap();
+        assert_eq!(heaters.faults(), BTreeMap::from([(1, ThermalFault::HeaterDecoupled)]));
+        assert_eq!(heaters.duty_cycles()[&1], 0.0);
+        assert!(heaters.set_temperature(1, 200.0).await.is_err());
+
+        // Zone 0's thermistor comes unplugged mid-heat
+        {
+            let mut r = readings.lock().unwrap();
+            r.temperatures.remove(&0);
+            r.thermal_faults.insert(0, ThermalFault::ThermistorOpen);
+        }
+        heaters.start_cycle(start + Duration::from_secs(40)).await.unwrap();
+        assert_eq!(heaters.faults()[&0], ThermalFault::ThermistorOpen);
+        assert_eq!(heaters.target(0).unwrap(), 0.0);
+
+        heaters.clear_fault(1).await.unwrap();
+        heaters.set_temperature(1, 200.0).await.unwrap();
+
+        // The bed runs as its sensor zone; there is no chamber heater
+        heaters.set_bed_temperature(60.0).await.unwrap();
+        assert_eq!(heaters.target(2).unwrap(), 60.0);
+        assert!(heaters.set_bed_temperature(150.0).await.is_err());
+        assert!(heaters.set_chamber_temperature(40.0).await.is_err());
+    }
+}
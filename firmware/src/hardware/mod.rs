@@ -13,16 +13,25 @@
 //! - **heaters**: Thermal management and PID control
 //! - **pressure**: Pressure regulation and monitoring
 //! - **sensors**: Sensor reading and processing
+//! - **bus**: SPI/I2C bus access shared by drivers
+//! - **driver_boards**: Valve driver board discovery and self-test
+//! - **valve_buses**: Parallel flush of arrays split across SPI buses
 
+pub mod bus;
+pub mod driver_boards;
+pub mod valve_buses;
 pub mod valve_controller;
 pub mod z_axis;
 pub mod heaters;
 pub mod pressure;
 pub mod sensors;
 
-pub use valve_controller::SpiValveController;
+pub use valve_controller::{SpiValveController, ValveCutoff};
 pub use z_axis::StepperZAxis;
 pub use heaters::PidHeaterController;
 pub use pressure::PneumaticPressureController;
 pub use sensors::MultiplexedSensorInterface;
+pub use bus::{BusProvider, GpioProvider, LinuxBusProvider};
+pub use driver_boards::{DriverChain, TopologyFault, TopologyReport};
+pub use valve_buses::{BusFlusher, FlushTiming};
 
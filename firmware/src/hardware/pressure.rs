@@ -0,0 +1,466 @@
+//! Closed-loop pneumatic pressure control.
+//!
+//! Each material channel listed in `PressureConfig::channels` has a pressure
+//! sensor at its regulator outlet and a proportional regulator driven by a
+//! DAC. [`PneumaticPressureController::update_control`] runs one PID step
+//! per channel:
+//!
+//! ```text
+//! output = target / max_pressure            (feed-forward)
+//!        + kp·e + ki·∫e + kd·de/dt          (correction, e = target − measured)
+//! ```
+//!
+//! The output is clamped to 0..1 of DAC full scale and the integral only
+//! accumulates while the output is not saturated.
+//!
+//! A reading above `SafetyLimits::max_pressure` vents every channel and
+//! returns a safety violation. Flow is estimated from the pressure
+//! differential across the channel's restriction as `k·√ΔP`.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex as StdMutex};
+use std::time::Instant;
+
+use anyhow::{anyhow, Context, Result};
+use tracing::{debug, error, info, warn};
+
+use config_types::{PidParameters, PressureChannelConfig, PressureConfig, PrinterConfig, RegulatorOutput};
+
+use super::bus::{BusProvider, GpioProvider, I2cBus, OutputPin};
+use crate::{FirmwareError, PressureController, SensorInterface};
+
+/// Full-scale code of a 12-bit DAC.
+const DAC_MAX: u16 = 4095;
+
+/// MCP4728 multi-write command; DAC channel goes in bits 2..1.
+const MCP4728_MULTI_WRITE: u8 = 0x40;
+
+type SharedI2c = Arc<StdMutex<Box<dyn I2cBus>>>;
+
+/// Drives a proportional pressure regulator.
+pub trait RegulatorDrive: Send {
+    /// Sets the regulator command as a fraction of full scale (0..1).
+    fn set_output(&mut self, fraction: f32) -> Result<()>;
+}
+
+/// One channel of an MCP4728-style quad 12-bit I2C DAC (VDD reference).
+pub struct I2cDacChannel {
+    i2c: SharedI2c,
+    address: u8,
+    channel: u8,
+}
+
+impl RegulatorDrive for I2cDacChannel {
+    fn set_output(&mut self, fraction: f32) -> Result<()> {
+        let code = (fraction.clamp(0.0, 1.0) * DAC_MAX as f32).round() as u16;
+        let command = [
+            MCP4728_MULTI_WRITE | ((self.channel & 0x03) << 1),
+            (code >> 8) as u8 & 0x0F,
+            code as u8,
+        ];
+        self.i2c
+            .lock()
+            .map_err(|_| anyhow!("I2C bus lock poisoned"))?
+            .write_read(self.address, &command, &mut [])
+    }
+}
+
+pub(super) struct PidState {
+    integral: f32,
+    last_error: Option<f32>,
+    last_update: Option<Instant>,
+}
+
+impl PidState {
+    pub(super) fn new() -> Self {
+        Self {
+            integral: 0.0,
+            last_error: None,
+            last_update: None,
+        }
+    }
+}
+
+struct ChannelLoop {
+    config: PressureChannelConfig,
+    drive: Box<dyn RegulatorDrive>,
+    vent: Option<Box<dyn OutputPin>>,
+    target: f32,
+    output: f32,
+    pid: PidState,
+}
+
+/// Pressure controller running one PID loop per material channel.
+pub struct PneumaticPressureController {
+    channels: Vec<ChannelLoop>,
+    sensors: Arc<Box<dyn SensorInterface>>,
+    /// Highest accepted set-point (PSI)
+    max_target: f32,
+    /// Reading that triggers an emergency vent (PSI)
+    max_pressure: f32,
+    /// Regulator pressure at full-scale output, for feed-forward (PSI)
+    full_scale: f32,
+}
+
+impl PneumaticPressureController {
+    /// Opens the regulator outputs and vent pins for every configured channel.
+    pub fn new(
+        config: &PrinterConfig,
+        sensors: Arc<Box<dyn SensorInterface>>,
+        buses: &dyn BusProvider,
+        gpio: &dyn GpioProvider,
+    ) -> Result<Self> {
+        Self::with_limit(&config.materials.pressure, config.safety.max_pressure.get(), sensors, buses, gpio)
+    }
+
+    /// Builds the controller from the pressure section alone, venting at
+    /// `max_pressure` (PSI).
+    pub fn with_limit(
+        pressure: &PressureConfig,
+        max_pressure: f32,
+        sensors: Arc<Box<dyn SensorInterface>>,
+        buses: &dyn BusProvider,
+        gpio: &dyn GpioProvider,
+    ) -> Result<Self> {
+        let mut i2c_buses: HashMap<u8, SharedI2c> = HashMap::new();
+        let mut channels = Vec::with_capacity(pressure.channels.len());
+
+        for channel in &pressure.channels {
+            let drive: Box<dyn RegulatorDrive> = match channel.output {
+                RegulatorOutput::I2cDac { bus, address, dac_channel } => {
+                    let i2c = match i2c_buses.get(&bus) {
+                        Some(i2c) => i2c.clone(),
+                        None => {
+                            let i2c = Arc::new(StdMutex::new(buses.open_i2c(bus)?));
+                            i2c_buses.insert(bus, i2c.clone());
+                            i2c
+                        }
+                    };
+                    Box::new(I2cDacChannel { i2c, address, channel: dac_channel })
+                }
+            };
+            let vent = channel
+                .vent_pin
+                .map(|pin| gpio.output(pin))
+                .transpose()
+                .with_context(|| format!("Vent pin for channel {}", channel.channel))?;
+
+            channels.push(ChannelLoop {
+                config: channel.clone(),
+                drive,
+                vent,
+                target: 0.0,
+                output: 0.0,
+                pid: PidState::new(),
+            });
+        }
+
+        let mut controller = Self {
+            channels,
+            sensors,
+            max_target: pressure.max_pressure.min(max_pressure),
+            max_pressure,
+            full_scale: pressure.max_pressure,
+        };
+        // Start unpressurized with vents closed
+        for ch in &mut controller.channels {
+            ch.drive.set_output(0.0)?;
+            if let Some(vent) = ch.vent.as_mut() {
+                vent.set(false)?;
+            }
+        }
+        info!("Pressure control on {} channel(s)", controller.channels.len());
+        Ok(controller)
+    }
+
+    fn channel(&self, channel_id: u8) -> Result<&ChannelLoop> {
+        self.channels
+            .iter()
+            .find(|c| c.config.channel == channel_id)
+            .ok_or_else(|| anyhow!("No pressure control for channel {}", channel_id))
+    }
+
+    /// Current regulator command for a channel (0..1 of full scale).
+    pub fn output(&self, channel_id: u8) -> Result<f32> {
+        Ok(self.channel(channel_id)?.output)
+    }
+
+    /// Set-point for a channel (PSI).
+    pub fn target(&self, channel_id: u8) -> Result<f32> {
+        Ok(self.channel(channel_id)?.target)
+    }
+
+    fn vent_all(&mut self) -> Vec<anyhow::Error> {
+        let mut failures = Vec::new();
+        for ch in &mut self.channels {
+            ch.target = 0.0;
+            ch.output = 0.0;
+            ch.pid = PidState::new();
+            if let Err(e) = ch.drive.set_output(0.0) {
+                failures.push(e.context(format!("channel {} regulator", ch.config.channel)));
+            }
+            if let Some(vent) = ch.vent.as_mut() {
+                if let Err(e) = vent.set(true) {
+                    failures.push(e.context(format!("channel {} vent", ch.config.channel)));
+                }
+            }
+        }
+        failures
+    }
+}
+
+/// One PID step; returns the clamped output and updates the state.
+pub(super) fn pid_step(pid: &mut PidState, gains: &PidParameters, feed_forward: f32, error: f32, now: Instant) -> f32 {
+    let dt = pid
+        .last_update
+        .map(|t| now.saturating_duration_since(t).as_secs_f32())
+        .unwrap_or(0.0);
+    let derivative = match (pid.last_error, dt > 0.0) {
+        (Some(last), true) => (error - last) / dt,
+        _ => 0.0,
+    };
+
+    let unclamped = feed_forward + gains.kp * error + gains.ki * (pid.integral + error * dt) + gains.kd * derivative;
+    let output = unclamped.clamp(0.0, 1.0);
+    // Anti-windup: only integrate while the output can still respond
+    if output == unclamped {
+        pid.integral += error * dt;
+    }
+
+    pid.last_error = Some(error);
+    pid.last_update = Some(now);
+    output
+}
+
+fn flow_from_differential(coefficient: f32, upstream: f32, downstream: f32) -> f32 {
+    coefficient * (upstream - downstream).max(0.0).sqrt()
+}
+
+#[async_trait::async_trait]
+impl PressureController for PneumaticPressureController {
+    async fn set_pressure(&mut self, channel_id: u8, target: f32) -> Result<()> {
+        if !(0.0..=self.max_target).contains(&target) {
+            return Err(FirmwareError::SafetyViolation(format!(
+                "Pressure target {:.1} PSI for channel {} outside 0-{:.1} PSI",
+                target, channel_id, self.max_target
+            ))
+            .into());
+        }
+
+        let index = self
+            .channels
+            .iter()
+            .position(|c| c.config.channel == channel_id)
+            .ok_or_else(|| anyhow!("No pressure control for channel {}", channel_id))?;
+        let ch = &mut self.channels[index];
+        ch.target = target;
+        if target > 0.0 {
+            if let Some(vent) = ch.vent.as_mut() {
+                vent.set(false)?;
+            }
+        }
+        debug!("Channel {} pressure target {:.1} PSI", channel_id, target);
+        Ok(())
+    }
+
+    async fn get_pressure(&self, channel_id: u8) -> Result<f32> {
+        let ch = self.channel(channel_id)?;
+        self.sensors.read_sensor(&ch.config.sensor_id).await
+    }
+
+    async fn get_flow_rate(&self, channel_id: u8) -> Result<f32> {
+        let ch = self.channel(channel_id)?;
+        let upstream = self.sensors.read_sensor(&ch.config.sensor_id).await?;
+        let downstream = match &ch.config.downstream_sensor_id {
+            Some(id) => self.sensors.read_sensor(id).await?,
+            None => 0.0,
+        };
+        Ok(flow_from_differential(ch.config.flow_coefficient, upstream, downstream))
+    }
+
+    async fn update_control(&mut self) -> Result<()> {
+        let now = Instant::now();
+
+        for i in 0..self.channels.len() {
+            let sensor_id = self.channels[i].config.sensor_id.clone();
+            let channel_id = self.channels[i].config.channel;
+
+            let measured = match self.sensors.read_sensor(&sensor_id).await {
+                Ok(p) => p,
+                Err(e) => {
+                    // Never regulate blind
+                    let ch = &mut self.channels[i];
+                    ch.output = 0.0;
+                    ch.drive.set_output(0.0).ok();
+                    return Err(e.context(format!("Channel {} pressure sensor failed", channel_id)));
+                }
+            };
+
+            if measured > self.max_pressure {
+                error!(
+                    "Channel {} at {:.1} PSI exceeds limit {:.1} PSI; venting",
+                    channel_id, measured, self.max_pressure
+                );
+                for failure in self.vent_all() {
+                    error!("Vent failed: {:#}", failure);
+                }
+                return Err(FirmwareError::SafetyViolation(format!(
+                    "Channel {} overpressure: {:.1} PSI (limit {:.1})",
+                    channel_id, measured, self.max_pressure
+                ))
+                .into());
+            }
+
+            let full_scale = self.full_scale;
+            let ch = &mut self.channels[i];
+            let output = if ch.target > 0.0 {
+                let feed_forward = if full_scale > 0.0 { ch.target / full_scale } else { 0.0 };
+                pid_step(&mut ch.pid, &ch.config.pid, feed_forward, ch.target - measured, now)
+            } else {
+                ch.pid = PidState::new();
+                0.0
+            };
+            ch.drive.set_output(output)?;
+            ch.output = output;
+        }
+
+        Ok(())
+    }
+
+    async fn emergency_vent(&mut self) -> Result<()> {
+        warn!("Emergency vent on all pressure channels");
+        let failures = self.vent_all();
+        match failures.into_iter().next() {
+            None => Ok(()),
+            Some(first) => Err(first.context("Emergency vent incomplete")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SensorReadings;
+    use config_types::PressureRegulationType;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    struct FakeSensors(Arc<StdMutex<HashMap<String, f32>>>);
+
+    #[async_trait::async_trait]
+    impl SensorInterface for FakeSensors {
+        async fn read_all(&self) -> Result<SensorReadings> {
+            Ok(SensorReadings::default())
+        }
+        async fn read_sensor(&self, sensor_id: &str) -> Result<f32> {
+            self.0.lock().unwrap().get(sensor_id).copied().ok_or_else(|| anyhow!("no sensor"))
+        }
+    }
+
+    /// Records the last DAC code written per address/channel.
+    struct FakeDac(Arc<StdMutex<HashMap<u8, u16>>>);
+
+    impl I2cBus for FakeDac {
+        fn write_read(&mut self, _address: u8, write: &[u8], _read: &mut [u8]) -> Result<()> {
+            let channel = (write[0] >> 1) & 0x03;
+            let code = ((write[1] as u16 & 0x0F) << 8) | write[2] as u16;
+            self.0.lock().unwrap().insert(channel, code);
+            Ok(())
+        }
+    }
+
+    struct FakeBuses(Arc<StdMutex<HashMap<u8, u16>>>);
+
+    impl BusProvider for FakeBuses {
+        fn open_spi(&self, _bus: u8, _cs: u8) -> Result<Box<dyn super::super::bus::SpiBus>> {
+            Err(anyhow!("no SPI"))
+        }
+        fn open_i2c(&self, _bus: u8) -> Result<Box<dyn I2cBus>> {
+            Ok(Box::new(FakeDac(self.0.clone())))
+        }
+    }
+
+    struct FakePin(Arc<AtomicBool>);
+
+    impl OutputPin for FakePin {
+        fn set(&mut self, high: bool) -> Result<()> {
+            self.0.store(high, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    struct FakeGpio(Arc<AtomicBool>);
+
+    impl GpioProvider for FakeGpio {
+        fn output(&self, _pin: u8) -> Result<Box<dyn OutputPin>> {
+            Ok(Box::new(FakePin(self.0.clone())))
+        }
+        fn input(&self, _pin: u8) -> Result<Box<dyn super::super::bus::InputPin>> {
+            Err(anyhow!("no inputs"))
+        }
+    }
+
+    struct Rig {
+        controller: PneumaticPressureController,
+        readings: Arc<StdMutex<HashMap<String, f32>>>,
+        dac: Arc<StdMutex<HashMap<u8, u16>>>,
+        vent: Arc<AtomicBool>,
+    }
+
+    fn rig() -> Rig {
+        let pressure = PressureConfig {
+            min_pressure: 0.0,
+            max_pressure: 100.0,
+            regulation_type: PressureRegulationType::Pneumatic,
+            sensors: vec![],
+            channels: vec![PressureChannelConfig {
+                channel: 0,
+                sensor_id: "p0".to_string(),
+                downstream_sensor_id: Some("p0_out".to_string()),
+                output: RegulatorOutput::I2cDac { bus: 1, address: 0x60, dac_channel: 2 },
+                vent_pin: Some(5),
+                pid: PidParameters { kp: 0.01, ki: 0.0, kd: 0.0 },
+                flow_coefficient: 2.0,
+            }],
+        };
+
+        let readings = Arc::new(StdMutex::new(HashMap::new()));
+        let dac = Arc::new(StdMutex::new(HashMap::new()));
+        let vent = Arc::new(AtomicBool::new(true));
+        let sensors: Arc<Box<dyn SensorInterface>> = Arc::new(Box::new(FakeSensors(readings.clone())));
+        let controller =
+            PneumaticPressureController::with_limit(&pressure, 120.0, sensors, &FakeBuses(dac.clone()), &FakeGpio(vent.clone()))
+                .unwrap();
+        Rig { controller, readings, dac, vent }
+    }
+
+    #[tokio::test]
+    async fn test_pid_drives_regulator_and_estimates_flow() {
+        let mut rig = rig();
+        assert!(!rig.vent.load(Ordering::SeqCst));
+
+        rig.readings.lock().unwrap().insert("p0".to_string(), 40.0);
+        rig.readings.lock().unwrap().insert("p0_out".to_string(), 31.0);
+        rig.controller.set_pressure(0, 50.0).await.unwrap();
+        rig.controller.update_control().await.unwrap();
+
+        // Feed-forward 0.5 plus 0.01 * 10 PSI error
+        assert!((rig.controller.output(0).unwrap() - 0.6).abs() < 1e-6);
+        assert_eq!(rig.dac.lock().unwrap()[&2], 2457);
+        assert!((rig.controller.get_flow_rate(0).await.unwrap() - 6.0).abs() < 1e-6);
+
+        assert!(rig.controller.set_pressure(0, 101.0).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_overpressure_vents() {
+        let mut rig = rig();
+        rig.controller.set_pressure(0, 80.0).await.unwrap();
+        rig.readings.lock().unwrap().insert("p0".to_string(), 125.0);
+
+        let err = rig.controller.update_control().await.unwrap_err();
+        assert!(err.to_string().contains("overpressure"));
+        assert!(rig.vent.load(Ordering::SeqCst));
+        assert_eq!(rig.dac.lock().unwrap()[&2], 0);
+        assert_eq!(rig.controller.target(0).unwrap(), 0.0);
+    }
+}
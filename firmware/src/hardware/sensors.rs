@@ -0,0 +1,527 @@
+//! Sensor acquisition.
+//!
+//! Sensors are declared in `PrinterConfig::sensors` with their type, bus and
+//! calibration. [`MultiplexedSensorInterface`] builds one driver per entry and
+//! shares bus handles between sensors on the same SPI chip select or I2C bus.
+//!
+//! Supported backends:
+//! - Thermistors on MCP3008-style 10-bit SPI ADCs
+//! - 14-bit I2C pressure sensors (Honeywell ABP-style transfer function)
+//! - Valve position switches on MCP23017-style 16-bit I2C GPIO expanders
+//!
+//! A thermistor reading within a few counts of either rail is a broken
+//! divider, not a temperature: near full scale the thermistor is open, near
+//! zero it is shorted. [`SensorInterface::read_all`] reports these as
+//! [`ThermalFault`]s for the zone and carries on with the other sensors.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use anyhow::{anyhow, bail, Context, Result};
+use tracing::{debug, warn};
+
+use config_types::{SensorBus, SensorCalibration, SensorDefinition, SensorType};
+use gcode_types::GridCoordinate;
+
+use super::bus::{BusProvider, I2cBus, SpiBus};
+use crate::{SensorInterface, SensorReadings, ThermalFault};
+
+/// Full-scale count of a 10-bit ADC.
+const ADC_MAX: f32 = 1023.0;
+
+/// Counts from either rail within which a thermistor divider is open or
+/// shorted. A 100k NTC on a 4.7k pull-up stays clear of this from below
+/// 0°C to above 300°C.
+const THERMISTOR_RAIL_COUNTS: f32 = 3.0;
+
+/// Output counts at 10% and 90% of a 14-bit digital pressure sensor.
+const PRESSURE_COUNTS_MIN: f32 = 1638.0;
+const PRESSURE_COUNTS_MAX: f32 = 14745.0;
+
+/// MCP23017 input port register (GPIOA, followed by GPIOB).
+const EXPANDER_GPIO_REGISTER: u8 = 0x12;
+
+const KELVIN_OFFSET: f32 = 273.15;
+
+type SharedSpi = Arc<Mutex<Box<dyn SpiBus>>>;
+type SharedI2c = Arc<Mutex<Box<dyn I2cBus>>>;
+
+/// Value produced by a single sensor read.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SensorValue {
+    Temperature { zone: u8, celsius: f32 },
+    ThermistorFault { zone: u8, fault: ThermalFault },
+    Pressure { channel: u8, psi: f32 },
+    ValveFeedback(Vec<(GridCoordinate, Vec<bool>)>),
+}
+
+enum SensorDriver {
+    Thermistor {
+        spi: SharedSpi,
+        channel: u8,
+        zone: u8,
+        model: ThermistorModel,
+    },
+    Pressure {
+        i2c: SharedI2c,
+        address: u8,
+        channel: u8,
+        range_psi: (f32, f32),
+    },
+    ValveFeedback {
+        i2c: SharedI2c,
+        address: u8,
+        nodes: Vec<GridCoordinate>,
+        valves_per_node: u8,
+    },
+}
+
+struct RegisteredSensor {
+    id: String,
+    driver: SensorDriver,
+    calibration: SensorCalibration,
+}
+
+/// Beta-equation thermistor on the low side of a pull-up divider.
+#[derive(Debug, Clone, Copy)]
+struct ThermistorModel {
+    beta: f32,
+    nominal_resistance: f32,
+    nominal_temp: f32,
+    pullup_resistance: f32,
+}
+
+impl ThermistorModel {
+    /// Converts an ADC ratio (0.0-1.0) to °C.
+    fn temperature(&self, ratio: f32) -> Result<f32, ThermalFault> {
+        let margin = THERMISTOR_RAIL_COUNTS / ADC_MAX;
+        if ratio >= 1.0 - margin {
+            return Err(ThermalFault::ThermistorOpen);
+        }
+        if ratio <= margin {
+            return Err(ThermalFault::ThermistorShort);
+        }
+        let resistance = self.pullup_resistance * ratio / (1.0 - ratio);
+        let t0 = self.nominal_temp + KELVIN_OFFSET;
+        let inv_t = 1.0 / t0 + (resistance / self.nominal_resistance).ln() / self.beta;
+        Ok(1.0 / inv_t - KELVIN_OFFSET)
+    }
+}
+
+/// Sensor interface reading all configured sensors over shared buses.
+pub struct MultiplexedSensorInterface {
+    sensors: Vec<RegisteredSensor>,
+}
+
+impl MultiplexedSensorInterface {
+    /// Builds drivers for every sensor definition.
+    pub fn from_config(definitions: &[SensorDefinition], buses: &dyn BusProvider) -> Result<Self> {
+        let mut spi_buses: HashMap<(u8, u8), SharedSpi> = HashMap::new();
+        let mut i2c_buses: HashMap<u8, SharedI2c> = HashMap::new();
+        let mut sensors = Vec::with_capacity(definitions.len());
+
+        for def in definitions {
+            let driver = match (&def.sensor_type, def.bus) {
+                (
+                    SensorType::Thermistor {
+                        zone,
+                        beta,
+                        nominal_resistance,
+                        nominal_temp,
+                        pullup_resistance,
+                    },
+                    SensorBus::Spi { bus, chip_select },
+                ) => {
+                    let spi = match spi_buses.get(&(bus, chip_select)) {
+                        Some(spi) => spi.clone(),
+                        None => {
+                            let spi = Arc::new(Mutex::new(buses.open_spi(bus, chip_select)?));
+                            spi_buses.insert((bus, chip_select), spi.clone());
+                            spi
+                        }
+                    };
+                    SensorDriver::Thermistor {
+                        spi,
+                        channel: def.channel,
+                        zone: *zone,
+                        model: ThermistorModel {
+                            beta: *beta,
+                            nominal_resistance: *nominal_resistance,
+                            nominal_temp: *nominal_temp,
+                            pullup_resistance: *pullup_resistance,
+                        },
+                    }
+                }
+                (SensorType::Pressure { channel, range_psi }, SensorBus::I2c { bus, address }) => {
+                    SensorDriver::Pressure {
+                        i2c: shared_i2c(&mut i2c_buses, buses, bus)?,
+                        address,
+                        channel: *channel,
+                        range_psi: *range_psi,
+                    }
+                }
+                (
+                    SensorType::ValveFeedback {
+                        nodes,
+                        valves_per_node,
+                    },
+                    SensorBus::I2c { bus, address },
+                ) => {
+                    if nodes.len() * *valves_per_node as usize > 16 {
+                        bail!(
+                            "Sensor '{}': {} nodes x {} valves exceeds 16 expander inputs",
+                            def.id,
+                            nodes.len(),
+                            valves_per_node
+                        );
+                    }
+                    SensorDriver::ValveFeedback {
+                        i2c: shared_i2c(&mut i2c_buses, buses, bus)?,
+                        address,
+                        nodes: nodes.iter().map(|&(x, y)| GridCoordinate::new(x, y)).collect(),
+                        valves_per_node: *valves_per_node,
+                    }
+                }
+                (sensor_type, bus) => bail!(
+                    "Sensor '{}': {:?} is not supported on {:?}",
+                    def.id,
+                    sensor_type,
+                    bus
+                ),
+            };
+
+            debug!("Registered sensor '{}'", def.id);
+            sensors.push(RegisteredSensor {
+                id: def.id.clone(),
+                driver,
+                calibration: def.calibration.clone(),
+            });
+        }
+
+        Ok(Self { sensors })
+    }
+
+    /// Number of registered sensors.
+    pub fn len(&self) -> usize {
+        self.sensors.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sensors.is_empty()
+    }
+
+    /// Reads one sensor by id, returning its calibrated value.
+    pub fn read_value(&self, sensor_id: &str) -> Result<SensorValue> {
+        let sensor = self
+            .sensors
+            .iter()
+            .find(|s| s.id == sensor_id)
+            .ok_or_else(|| anyhow!("Unknown sensor '{}'", sensor_id))?;
+        read_sensor(sensor).with_context(|| format!("Failed to read sensor '{}'", sensor_id))
+    }
+}
+
+#[async_trait::async_trait]
+impl SensorInterface for MultiplexedSensorInterface {
+    async fn read_all(&self) -> Result<SensorReadings> {
+        let mut readings = SensorReadings::default();
+
+        for sensor in &self.sensors {
+            let value = read_sensor(sensor)
+                .with_context(|| format!("Failed to read sensor '{}'", sensor.id))?;
+            match value {
+                SensorValue::Temperature { zone, celsius } => {
+                    readings.temperatures.insert(zone, celsius);
+                }
+                SensorValue::ThermistorFault { zone, fault } => {
+                    warn!("Sensor '{}': zone {} {}", sensor.id, zone, fault);
+                    readings.thermal_faults.insert(zone, fault);
+                }
+                SensorValue::Pressure { channel, psi } => {
+                    readings.pressures.insert(channel, psi);
+                }
+                SensorValue::ValveFeedback(nodes) => {
+                    readings.valve_feedbacks.extend(nodes);
+                }
+            }
+        }
+
+        Ok(readings)
+    }
+
+    async fn read_sensor(&self, sensor_id: &str) -> Result<f32> {
+        match self.read_value(sensor_id)? {
+            SensorValue::Temperature { celsius, .. } => Ok(celsius),
+            SensorValue::Pressure { psi, .. } => Ok(psi),
+            SensorValue::ThermistorFault { zone, fault } => {
+                bail!("Sensor '{}': zone {} {}", sensor_id, zone, fault)
+            }
+            SensorValue::ValveFeedback(_) => {
+                bail!("Sensor '{}' reports valve states, not a scalar value", sensor_id)
+            }
+        }
+    }
+}
+
+fn shared_i2c(
+    cache: &mut HashMap<u8, SharedI2c>,
+    buses: &dyn BusProvider,
+    bus: u8,
+) -> Result<SharedI2c> {
+    if let Some(i2c) = cache.get(&bus) {
+        return Ok(i2c.clone());
+    }
+    let i2c = Arc::new(Mutex::new(buses.open_i2c(bus)?));
+    cache.insert(bus, i2c.clone());
+    Ok(i2c)
+}
+
+fn read_sensor(sensor: &RegisteredSensor) -> Result<SensorValue> {
+    match &sensor.driver {
+        SensorDriver::Thermistor {
+            spi,
+            channel,
+            zone,
+            model,
+        } => {
+            let counts = read_mcp3008(spi, *channel)?;
+            match model.temperature(counts as f32 / ADC_MAX) {
+                Ok(celsius) => Ok(SensorValue::Temperature {
+                    zone: *zone,
+                    celsius: sensor.calibration.apply(celsius),
+                }),
+                Err(fault) => Ok(SensorValue::ThermistorFault { zone: *zone, fault }),
+            }
+        }
+        SensorDriver::Pressure {
+            i2c,
+            address,
+            channel,
+            range_psi,
+        } => {
+            let psi = read_digital_pressure(i2c, *address, *range_psi)?;
+            Ok(SensorValue::Pressure {
+                channel: *channel,
+                psi: sensor.calibration.apply(psi),
+            })
+        }
+        SensorDriver::ValveFeedback {
+            i2c,
+            address,
+            nodes,
+            valves_per_node,
+        } => {
+            let inputs = read_expander(i2c, *address)?;
+            let per_node = *valves_per_node as usize;
+            let states = nodes
+                .iter()
+                .enumerate()
+                .map(|(n, node)| {
+                    let bits = (0..per_node)
+                        .map(|v| inputs & (1 << (n * per_node + v)) != 0)
+                        .collect();
+                    (*node, bits)
+                })
+                .collect();
+            Ok(SensorValue::ValveFeedback(states))
+        }
+    }
+}
+
+/// Single-ended conversion on an MCP3008: start bit, SGL/DIFF + channel,
+/// then 10 result bits across the last two bytes.
+fn read_mcp3008(spi: &SharedSpi, channel: u8) -> Result<u16> {
+    if channel > 7 {
+        bail!("MCP3008 channel {} out of range", channel);
+    }
+    let tx = [0x01, (0x08 | channel) << 4, 0x00];
+    let mut rx = [0u8; 3];
+    spi.lock()
+        .map_err(|_| anyhow!("SPI bus lock poisoned"))?
+        .transfer(&tx, &mut rx)?;
+    Ok((((rx[1] & 0x03) as u16) << 8) | rx[2] as u16)
+}
+
+/// Reads a 14-bit digital pressure sensor. The top two bits of the first
+/// byte carry status: 0 = valid, 2 = stale data, 3 = diagnostic fault.
+fn read_digital_pressure(i2c: &SharedI2c, address: u8, range: (f32, f32)) -> Result<f32> {
+    let mut data = [0u8; 2];
+    i2c.lock()
+        .map_err(|_| anyhow!("I2C bus lock poisoned"))?
+        .write_read(address, &[], &mut data)?;
+
+    match data[0] >> 6 {
+        0 => {}
+        2 => warn!("Pressure sensor 0x{:02x} returned stale data", address),
+        status => bail!("Pressure sensor 0x{:02x} reported status {}", address, status),
+    }
+
+    let counts = ((((data[0] & 0x3F) as u16) << 8) | data[1] as u16) as f32;
+    let (min, max) = range;
+    Ok((counts - PRESSURE_COUNTS_MIN) * (max - min) / (PRESSURE_COUNTS_MAX - PRESSURE_COUNTS_MIN) + min)
+}
+
+/// Reads both input ports of a 16-bit GPIO expander (GPIOA = low byte).
+fn read_expander(i2c: &SharedI2c, address: u8) -> Result<u16> {
+    let mut data = [0u8; 2];
+    i2c.lock()
+        .map_err(|_| anyhow!("I2C bus lock poisoned"))?
+        .write_read(address, &[EXPANDER_GPIO_REGISTER], &mut data)?;
+    Ok(u16::from_le_bytes(data))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// SPI fake returning a fixed 10-bit ADC value.
+    struct FakeAdc(u16);
+
+    impl SpiBus for FakeAdc {
+        fn transfer(&mut self, _tx: &[u8], rx: &mut [u8]) -> Result<()> {
+            rx[1] = (self.0 >> 8) as u8 & 0x03;
+            rx[2] = self.0 as u8;
+            Ok(())
+        }
+    }
+
+    /// I2C fake answering by device address.
+    struct FakeI2c(HashMap<u8, [u8; 2]>);
+
+    impl I2cBus for FakeI2c {
+        fn write_read(&mut self, address: u8, _write: &[u8], read: &mut [u8]) -> Result<()> {
+            let data = self.0.get(&address).ok_or_else(|| anyhow!("NACK"))?;
+            read.copy_from_slice(data);
+            Ok(())
+        }
+    }
+
+    struct FakeProvider {
+        adc: u16,
+        i2c: HashMap<u8, [u8; 2]>,
+    }
+
+    impl BusProvider for FakeProvider {
+        fn open_spi(&self, _bus: u8, _cs: u8) -> Result<Box<dyn SpiBus>> {
+            Ok(Box::new(FakeAdc(self.adc)))
+        }
+
+        fn open_i2c(&self, _bus: u8) -> Result<Box<dyn I2cBus>> {
+            Ok(Box::new(FakeI2c(self.i2c.clone())))
+        }
+    }
+
+    fn thermistor(calibration: SensorCalibration) -> SensorDefinition {
+        SensorDefinition {
+            id: "zone0".to_string(),
+            sensor_type: SensorType::Thermistor {
+                zone: 0,
+                beta: 3950.0,
+                nominal_resistance: 100_000.0,
+                nominal_temp: 25.0,
+                pullup_resistance: 100_000.0,
+            },
+            bus: SensorBus::Spi { bus: 0, chip_select: 0 },
+            channel: 0,
+            calibration,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_thermistor_at_nominal() {
+        // Mid-scale with equal pull-up means R = R25
+        let provider = FakeProvider { adc: 512, i2c: HashMap::new() };
+        let sensors =
+            MultiplexedSensorInterface::from_config(&[thermistor(SensorCalibration::None)], &provider)
+                .unwrap();
+        let t = sensors.read_sensor("zone0").await.unwrap();
+        assert!((t - 25.0).abs() < 0.2, "got {}", t);
+
+        let calibrated = MultiplexedSensorInterface::from_config(
+            &[thermistor(SensorCalibration::Linear { offset: 1.0, coefficient: 1.0 })],
+            &provider,
+        )
+        .unwrap();
+        let t2 = calibrated.read_sensor("zone0").await.unwrap();
+        assert!((t2 - t - 1.0).abs() < 1e-3);
+    }
+
+    #[tokio::test]
+    async fn test_pressure_and_feedback() {
+        // 50% of span: counts = 8191.5 ≈ 0x1FFF
+        let mut i2c = HashMap::new();
+        i2c.insert(0x28, [0x1F, 0xFF]);
+        i2c.insert(0x20, [0b0000_0101, 0x00]);
+        let provider = FakeProvider { adc: 0, i2c };
+
+        let defs = vec![
+            SensorDefinition {
+                id: "pressure0".to_string(),
+                sensor_type: SensorType::Pressure { channel: 0, range_psi: (0.0, 100.0) },
+                bus: SensorBus::I2c { bus: 1, address: 0x28 },
+                channel: 0,
+                calibration: SensorCalibration::None,
+            },
+            SensorDefinition {
+                id: "valves".to_string(),
+                sensor_type: SensorType::ValveFeedback {
+                    nodes: vec![(0, 0), (1, 0)],
+                    valves_per_node: 2,
+                },
+                bus: SensorBus::I2c { bus: 1, address: 0x20 },
+                channel: 0,
+                calibration: SensorCalibration::None,
+            },
+        ];
+        let sensors = MultiplexedSensorInterface::from_config(&defs, &provider).unwrap();
+        let readings = sensors.read_all().await.unwrap();
+
+        assert!((readings.pressures[&0] - 50.0).abs() < 0.1);
+        assert_eq!(readings.valve_feedbacks[&GridCoordinate::new(0, 0)], vec![true, false]);
+        assert_eq!(readings.valve_feedbacks[&GridCoordinate::new(1, 0)], vec![true, false]);
+    }
+
+    #[tokio::test]
+    async fn test_thermistor_rail_is_fault() {
+        let model = ThermistorModel {
+            beta: 3950.0,
+            nominal_resistance: 100_000.0,
+            nominal_temp: 25.0,
+            pullup_resistance: 4_700.0,
+        };
+        assert_eq!(model.temperature(0.0), Err(ThermalFault::ThermistorShort));
+        assert_eq!(model.temperature(1.0), Err(ThermalFault::ThermistorOpen));
+        assert!(model.temperature(1018.0 / ADC_MAX).is_ok());
+
+        // An unplugged thermistor reads full scale; the zone is faulted but
+        // the other sensors are still read
+        let mut i2c = HashMap::new();
+        i2c.insert(0x28, [0x1F, 0xFF]);
+        let provider = FakeProvider { adc: 1023, i2c };
+        let pressure = SensorDefinition {
+            id: "pressure0".to_string(),
+            sensor_type: SensorType::Pressure { channel: 0, range_psi: (0.0, 100.0) },
+            bus: SensorBus::I2c { bus: 1, address: 0x28 },
+            channel: 0,
+            calibration: SensorCalibration::None,
+        };
+        let sensors = MultiplexedSensorInterface::from_config(
+            &[thermistor(SensorCalibration::None), pressure],
+            &provider,
+        )
+        .unwrap();
+        let readings = sensors.read_all().await.unwrap();
+        assert_eq!(readings.thermal_faults[&0], ThermalFault::ThermistorOpen);
+        assert!(!readings.temperatures.contains_key(&0));
+        assert!(readings.pressures.contains_key(&0));
+        assert!(sensors.read_sensor("zone0").await.is_err());
+    }
+
+    #[test]
+    fn test_mismatched_bus_rejected() {
+        let provider = FakeProvider { adc: 0, i2c: HashMap::new() };
+        let mut def = thermistor(SensorCalibration::None);
+        def.bus = SensorBus::I2c { bus: 1, address: 0x48 };
+        assert!(MultiplexedSensorInterface::from_config(&[def], &provider).is_err());
+    }
+}
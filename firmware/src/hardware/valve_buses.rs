@@ -0,0 +1,321 @@
+//! Parallel flushing of a valve array split across SPI buses.
+//!
+//! One chain cannot shift a 100k-valve frame within the array's ±1 ms
+//! switching budget, so large arrays divide their driver boards between
+//! several buses (`ValveDriverConfig::buses`). [`BusFlusher`] keeps one
+//! writer thread per bus, fed through an SPSC ring
+//! ([`spsc_channel`](crate::utils::spsc_channel)) so handing over a frame
+//! never locks or blocks. A flush gives every thread its boards' slice of
+//! the frame, the threads shift them out concurrently and each
+//! acknowledges on its own oneshot channel, and only once every bus has
+//! acknowledged is the shared latch line pulsed, so every board switches
+//! on the same edge however long its own chain took. The flush awaits the
+//! acknowledgements rather than blocking its worker, and gives up after
+//! [`FLUSH_TIMEOUT`] or as soon as a bus thread dies, without latching.
+//!
+//! Frames are array-wide output bitmaps, [`bytes_per_board`] bytes per
+//! board in board order. Within a chain the farthest board's bytes go
+//! first (see [`driver_boards`](super::driver_boards)).
+
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Context, Result};
+use tokio::sync::oneshot;
+use tracing::debug;
+
+use config_types::{ValveArrayConfig, ValveBusPartition};
+
+use super::bus::{BusProvider, GpioProvider, OutputPin, SpiBus};
+use crate::utils::{spsc_channel, Consumer, Producer};
+
+/// Width of the latch pulse.
+const LATCH_PULSE: Duration = Duration::from_micros(2);
+
+/// Frames a bus thread may have waiting.
+const FRAME_QUEUE_LEN: usize = 4;
+
+/// Longest a flush waits for every bus to finish shifting.
+pub const FLUSH_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Frame bytes for one board.
+pub fn bytes_per_board(outputs_per_board: u16) -> usize {
+    (outputs_per_board as usize).div_ceil(8)
+}
+
+/// Shift time of the slowest bus, and when the latch fired.
+#[derive(Debug, Clone, Copy)]
+pub struct FlushTiming {
+    pub shift: Duration,
+    pub latched_at: Instant,
+}
+
+/// One bus's share of a flush, acknowledged with the shift time.
+struct Job {
+    data: Vec<u8>,
+    done: oneshot::Sender<Result<Duration>>,
+}
+
+struct Worker {
+    partition: ValveBusPartition,
+    jobs: Option<Producer<Job>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Worker {
+    fn is_stopped(&self) -> bool {
+        self.thread.as_ref().is_none_or(|t| t.is_finished())
+    }
+
+    fn wake(&self) {
+        if let Some(thread) = &self.thread {
+            thread.thread().unpark();
+        }
+    }
+}
+
+/// Shifts frames out on every bus at once and latches them together.
+pub struct BusFlusher {
+    workers: Vec<Worker>,
+    latch: Option<Box<dyn OutputPin>>,
+    board_bytes: usize,
+}
+
+impl BusFlusher {
+    /// Opens every bus of `config` and the shared latch line, if any.
+    pub fn open(
+        provider: &dyn BusProvider,
+        gpio: &dyn GpioProvider,
+        config: &ValveArrayConfig,
+    ) -> Result<Self> {
+        let mut buses = Vec::new();
+        for partition in config.bus_partitions() {
+            let spi = provider
+                .open_spi(partition.spi_bus, partition.chip_select)
+                .with_context(|| bus_name(&partition))?;
+            buses.push((partition, spi));
+        }
+        let latch = config
+            .drivers
+            .latch_pin
+            .map(|pin| gpio.output(pin).with_context(|| format!("Valve latch on GPIO {}", pin)))
+            .transpose()?;
+        Ok(Self::new(buses, latch, bytes_per_board(config.drivers.outputs_per_board)))
+    }
+
+    /// Starts one thread per bus. Without a latch line the boards act at
+    /// chip select release, which only keeps a single bus in step.
+    pub fn new(
+        buses: Vec<(ValveBusPartition, Box<dyn SpiBus>)>,
+        latch: Option<Box<dyn OutputPin>>,
+        board_bytes: usize,
+    ) -> Self {
+        let workers = buses
+            .into_iter()
+            .map(|(partition, spi)| {
+                let (jobs, rx) = spsc_channel(FRAME_QUEUE_LEN);
+                let name = format!("valve-bus-{}.{}", partition.spi_bus, partition.chip_select);
+                let thread = std::thread::Builder::new()
+                    .name(name)
+                    .spawn(move || run_bus(spi, rx))
+                    .expect("failed to spawn valve bus thread");
+                Worker {
+                    partition,
+                    jobs: Some(jobs),
+                    thread: Some(thread),
+                }
+            })
+            .collect();
+        Self {
+            workers,
+            latch,
+            board_bytes,
+        }
+    }
+
+    pub fn partitions(&self) -> impl Iterator<Item = &ValveBusPartition> {
+        self.workers.iter().map(|w| &w.partition)
+    }
+
+    /// Shifts `frame` out on every bus, then latches it. Nothing is
+    /// latched if any bus fails, stops or misses [`FLUSH_TIMEOUT`].
+    pub async fn flush(&mut self, frame: &[u8]) -> Result<FlushTiming> {
+        if let Some(worker) = self.workers.iter().find(|w| w.is_stopped()) {
+            return Err(anyhow!("{} thread has stopped", bus_name(&worker.partition)));
+        }
+        let mut acks = Vec::with_capacity(self.workers.len());
+        for worker in &mut self.workers {
+            let data = chain_order(frame, &worker.partition, self.board_bytes);
+            let (done, ack) = oneshot::channel();
+            let jobs = worker.jobs.as_mut().expect("producer kept until drop");
+            // Buses already given the frame shift it, but nothing latches it
+            if jobs.push(Job { data, done }).is_err() {
+                return Err(anyhow!("{} is {} frames behind", bus_name(&worker.partition), FRAME_QUEUE_LEN));
+            }
+            worker.wake();
+            acks.push(ack);
+        }
+
+        let deadline = tokio::time::Instant::now() + FLUSH_TIMEOUT;
+        let mut shift = Duration::ZERO;
+        for (worker, ack) in self.workers.iter().zip(acks) {
+            let name = bus_name(&worker.partition);
+            let result = match tokio::time::timeout_at(deadline, ack).await {
+                Ok(Ok(result)) => result,
+                // The thread dropped the job unanswered: it panicked
+                Ok(Err(_)) => return Err(anyhow!("{} thread has stopped", name)),
+                Err(_) => return Err(anyhow!("{} did not shift within {:?}", name, FLUSH_TIMEOUT)),
+            };
+            shift = shift.max(result.context(name)?);
+        }
+
+        if let Some(latch) = self.latch.as_mut() {
+            latch.set(true)?;
+            spin(LATCH_PULSE);
+            latch.set(false)?;
+        }
+        let latched_at = Instant::now();
+        debug!("Valve frame shifted on {} buses in {:?}", self.workers.len(), shift);
+        Ok(FlushTiming { shift, latched_at })
+    }
+}
+
+impl Drop for BusFlusher {
+    fn drop(&mut self) {
+        for worker in &mut self.workers {
+            // The thread exits once it finds the ring empty and abandoned
+            worker.jobs.take();
+            worker.wake();
+            if let Some(thread) = worker.thread.take() {
+                thread.join().ok();
+            }
+        }
+    }
+}
+
+/// Shifts out each frame handed over, parking while the ring is empty.
+fn run_bus(mut spi: Box<dyn SpiBus>, mut jobs: Consumer<Job>) {
+    let mut rx = Vec::new();
+    loop {
+        let Some(job) = jobs.pop() else {
+            if jobs.is_abandoned() {
+                return;
+            }
+            // A wake-up sent before parking is not lost
+            std::thread::park();
+            continue;
+        };
+        let start = Instant::now();
+        rx.resize(job.data.len(), 0);
+        let result = spi.transfer(&job.data, &mut rx).map(|()| start.elapsed());
+        // A flush that gave up no longer listens
+        job.done.send(result).ok();
+    }
+}
+
+/// The partition's boards' bytes, farthest board first.
+fn chain_order(frame: &[u8], partition: &ValveBusPartition, board_bytes: usize) -> Vec<u8> {
+    let mut data = Vec::with_capacity(partition.boards.len() * board_bytes);
+    for board in partition.boards.clone().rev() {
+        let start = board as usize * board_bytes;
+        let end = start + board_bytes;
+        match frame.get(start..end) {
+            Some(bytes) => data.extend_from_slice(bytes),
+            // Outputs beyond the frame stay closed
+            None => data.resize(data.len() + board_bytes, 0),
+        }
+    }
+    data
+}
+
+fn bus_name(partition: &ValveBusPartition) -> String {
+    format!("Valve driver bus {}.{}", partition.spi_bus, partition.chip_select)
+}
+
+/// Busy-waits; the latch pulse is far shorter than a scheduler tick.
+fn spin(duration: Duration) {
+    let start = Instant::now();
+    while start.elapsed() < duration {
+        std::hint::spin_loop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    type Log = Arc<Mutex<Vec<String>>>;
+
+    struct FakeSpi(u8, Log);
+
+    impl SpiBus for FakeSpi {
+        fn transfer(&mut self, tx: &[u8], _rx: &mut [u8]) -> Result<()> {
+            self.1.lock().unwrap().push(format!("bus{} {:?}", self.0, tx));
+            Ok(())
+        }
+    }
+
+    struct FakeLatch(Log);
+
+    impl OutputPin for FakeLatch {
+        fn set(&mut self, high: bool) -> Result<()> {
+            self.0.lock().unwrap().push(format!("latch {}", high));
+            Ok(())
+        }
+    }
+
+    fn partition(spi_bus: u8, boards: std::ops::Range<u32>) -> ValveBusPartition {
+        ValveBusPartition {
+            spi_bus,
+            chip_select: 0,
+            nodes: boards.start * 2..boards.end * 2,
+            boards,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_parallel_flush_latches_after_every_bus() {
+        let log = Log::default();
+        let buses: Vec<(ValveBusPartition, Box<dyn SpiBus>)> = vec![
+            (partition(0, 0..2), Box::new(FakeSpi(0, log.clone()))),
+            (partition(1, 2..3), Box::new(FakeSpi(1, log.clone()))),
+        ];
+        let mut flusher = BusFlusher::new(buses, Some(Box::new(FakeLatch(log.clone()))), 1);
+
+        flusher.flush(&[0x01, 0x02, 0x03]).await.unwrap();
+        let mut entries = log.lock().unwrap().clone();
+        // The buses run in either order, but both before the latch
+        assert_eq!(entries.split_off(2), vec!["latch true", "latch false"]);
+        entries.sort();
+        assert_eq!(entries, vec!["bus0 [2, 1]", "bus1 [3]"]);
+
+        // Missing bytes are sent as closed outputs
+        log.lock().unwrap().clear();
+        flusher.flush(&[0xFF]).await.unwrap();
+        assert!(log.lock().unwrap().contains(&"bus1 [0]".to_string()));
+    }
+
+    struct PanickingSpi;
+
+    impl SpiBus for PanickingSpi {
+        fn transfer(&mut self, _tx: &[u8], _rx: &mut [u8]) -> Result<()> {
+            panic!("driver fault");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_dead_bus_fails_flush_without_latching() {
+        let log = Log::default();
+        let buses: Vec<(ValveBusPartition, Box<dyn SpiBus>)> = vec![
+            (partition(0, 0..1), Box::new(FakeSpi(0, log.clone()))),
+            (partition(1, 1..2), Box::new(PanickingSpi)),
+        ];
+        let mut flusher = BusFlusher::new(buses, Some(Box::new(FakeLatch(log.clone()))), 1);
+
+        let error = flusher.flush(&[0x01, 0x02]).await.unwrap_err();
+        assert!(error.to_string().contains("bus 1.0 thread has stopped"), "{}", error);
+        assert!(flusher.flush(&[0x01, 0x02]).await.is_err());
+        assert!(!log.lock().unwrap().iter().any(|entry| entry.starts_with("latch")));
+    }
+}
@@ -0,0 +1,267 @@
+//! Valve array control over the daisy-chained driver boards.
+//!
+//! [`SpiValveController`] keeps the whole array's outputs as one bitmap in
+//! the layout [`BusFlusher`] shifts out: nodes are numbered row-major
+//! across the valve grid, node `n`'s valve `v` is output
+//! `n · valves_per_node + v`, and each board holds `outputs_per_board`
+//! consecutive outputs, lowest in bit 0 of its first byte. Every write
+//! updates the bitmap and flushes all of it, so the boards always hold the
+//! complete commanded state and switch together on the latch.
+//!
+//! [`ValveCutoff`] drives the boards' output enable line directly, closing
+//! every valve without the controller or a bus.
+
+use std::sync::{Arc, Mutex as StdMutex};
+
+use anyhow::{anyhow, Result};
+use tracing::{debug, info, warn};
+
+use config_types::{PrinterConfig, ValveArrayConfig, ValveDriverConfig};
+use gcode_types::{GridCoordinate, ValveState};
+
+use super::bus::{GpioProvider, OutputPin};
+use super::valve_buses::{bytes_per_board, BusFlusher};
+use crate::{FirmwareError, ValveController, ValveHealth};
+
+/// Valve array driven through shift-register driver boards.
+pub struct SpiValveController {
+    /// Not shared between threads; the lock only makes it `Sync`
+    flusher: StdMutex<BusFlusher>,
+    grid_width: u32,
+    total_nodes: u32,
+    valves_per_node: u8,
+    outputs_per_board: u16,
+    board_bytes: usize,
+    /// Commanded outputs, in board order
+    frame: Vec<u8>,
+    /// Switches per output since start-up
+    cycles: Vec<u64>,
+    response_time_ms: f32,
+}
+
+impl SpiValveController {
+    /// Drives the array through its opened buses ([`BusFlusher::open`]),
+    /// closing every valve.
+    pub async fn start(flusher: BusFlusher, config: &PrinterConfig) -> Result<Self> {
+        let mut controller = Self::with_flusher(flusher, &config.valve_array, config.grid_x_count());
+        controller.flush().await?;
+        info!(
+            "Valve array: {} nodes on {} driver boards",
+            controller.total_nodes,
+            config.valve_array.expected_driver_boards()
+        );
+        Ok(controller)
+    }
+
+    /// Drives the array through an opened flusher; nothing is written until
+    /// the first update. `grid_width` is the number of nodes per row.
+    pub fn with_flusher(flusher: BusFlusher, array: &ValveArrayConfig, grid_width: u32) -> Self {
+        let board_bytes = bytes_per_board(array.drivers.outputs_per_board);
+        let outputs = array.total_nodes as usize * array.valves_per_node as usize;
+        Self {
+            flusher: StdMutex::new(flusher),
+            grid_width: grid_width.max(1),
+            total_nodes: array.total_nodes,
+            valves_per_node: array.valves_per_node,
+            outputs_per_board: array.drivers.outputs_per_board.max(1),
+            board_bytes,
+            frame: vec![0; array.expected_driver_boards() * board_bytes],
+            cycles: vec![0; outputs],
+            response_time_ms: array.response_time_ms,
+        }
+    }
+
+    /// Output number of a node's valve.
+    fn output(&self, node: GridCoordinate, valve: u8) -> Result<usize, FirmwareError> {
+        let index = node.y as u64 * self.grid_width as u64 + node.x as u64;
+        if node.x >= self.grid_width || index >= self.total_nodes as u64 {
+            return Err(FirmwareError::InvalidCommand(format!("Node {:?} is not on the valve grid", node)));
+        }
+        if valve >= self.valves_per_node {
+            return Err(FirmwareError::InvalidCommand(format!(
+                "Node {:?} has no valve {} ({} per node)",
+                node, valve, self.valves_per_node
+            )));
+        }
+        Ok(index as usize * self.valves_per_node as usize + valve as usize)
+    }
+
+    /// Byte and mask of an output in the frame.
+    fn bit(&self, output: usize) -> (usize, u8) {
+        let per_board = self.outputs_per_board as usize;
+        let (board, bit) = (output / per_board, output % per_board);
+        (board * self.board_bytes + bit / 8, 1 << (bit % 8))
+    }
+
+    fn is_open(&self, output: usize) -> bool {
+        let (byte, mask) = self.bit(output);
+        self.frame.get(byte).is_some_and(|b| b & mask != 0)
+    }
+
+    async fn flush(&mut self) -> Result<()> {
+        let flusher = self.flusher.get_mut().map_err(|_| anyhow!("Valve bus flusher poisoned"))?;
+        let timing = flusher.flush(&self.frame).await?;
+        debug!("Valve frame latched after {:?} shifting", timing.shift);
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl ValveController for SpiValveController {
+    async fn set_valve_states(&mut self, states: &[(GridCoordinate, Vec<ValveState>)]) -> Result<()> {
+        // Check the whole frame before touching the bitmap
+        let mut changes = Vec::with_capacity(states.iter().map(|(_, v)| v.len()).sum());
+        for (node, valves) in states {
+            for valve in valves {
+                changes.push((self.output(*node, valve.index)?, valve.open));
+            }
+        }
+        for (output, open) in changes {
+            if self.is_open(output) == open {
+                continue;
+            }
+            let (byte, mask) = self.bit(output);
+            self.frame[byte] ^= mask;
+            self.cycles[output] += 1;
+        }
+        self.flush().await
+    }
+
+    async fn get_valve_states(&self, position: GridCoordinate) -> Result<Vec<ValveState>> {
+        (0..self.valves_per_node)
+            .map(|valve| Ok(ValveState::new(valve, self.is_open(self.output(position, valve)?))))
+            .collect()
+    }
+
+    /// Reports the valves that have switched. The drivers give no feedback,
+    /// so response times are nominal; the feedback verifier finds valves
+    /// that do not follow.
+    async fn health_check(&mut self) -> Result<Vec<ValveHealth>> {
+        let per_node = self.valves_per_node.max(1) as usize;
+        Ok(self
+            .cycles
+            .iter()
+            .enumerate()
+            .filter(|(_, &cycles)| cycles > 0)
+            .map(|(output, &cycle_count)| {
+                let node = (output / per_node) as u32;
+                ValveHealth {
+                    position: GridCoordinate::new(node % self.grid_width, node / self.grid_width),
+                    valve_id: (output % per_node) as u8,
+                    cycle_count,
+                    avg_response_time_ms: self.response_time_ms,
+                    health_score: 1.0,
+                }
+            })
+            .collect())
+    }
+
+    async fn emergency_close_all(&mut self) -> Result<()> {
+        warn!("Closing every valve");
+        self.frame.fill(0);
+        self.flush().await
+    }
+}
+
+/// The driver boards' output enable line. Cutting it closes every valve
+/// at once, whoever holds the valve controller; the outputs stay off until
+/// the next start-up.
+#[derive(Clone, Default)]
+pub struct ValveCutoff(Option<Arc<StdMutex<Box<dyn OutputPin>>>>);
+
+impl ValveCutoff {
+    /// Opens the configured line and enables the outputs. Without one,
+    /// [`Self::cut`] always fails.
+    pub fn open(drivers: &ValveDriverConfig, gpio: &dyn GpioProvider) -> Result<Self> {
+        let Some(pin) = drivers.output_enable_pin else {
+            return Ok(Self::default());
+        };
+        let mut line = gpio.output(pin).map_err(|e| anyhow!("Valve output enable on GPIO {}: {:#}", pin, e))?;
+        line.set(false)?;
+        Ok(Self::with_line(line))
+    }
+
+    pub fn with_line(line: Box<dyn OutputPin>) -> Self {
+        Self(Some(Arc::new(StdMutex::new(line))))
+    }
+
+    pub fn is_fitted(&self) -> bool {
+        self.0.is_some()
+    }
+
+    /// Switches every output off.
+    pub fn cut(&self) -> Result<()> {
+        let line = self.0.as_ref().ok_or_else(|| anyhow!("No valve output enable line is configured"))?;
+        warn!("Cutting the valve output enable line");
+        line.lock().unwrap_or_else(|e| e.into_inner()).set(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hardware::bus::SpiBus;
+    use config_types::{ValveBusPartition, ValveDriverConfig};
+    use std::sync::{Arc, Mutex};
+
+    /// Records every transfer.
+    struct FakeSpi(Arc<Mutex<Vec<Vec<u8>>>>);
+
+    impl SpiBus for FakeSpi {
+        fn transfer(&mut self, tx: &[u8], _rx: &mut [u8]) -> Result<()> {
+            self.0.lock().unwrap().push(tx.to_vec());
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_outputs_follow_grid_layout() {
+        // 4x3 nodes of 4 valves on two 32-output boards
+        let array = ValveArrayConfig {
+            grid_spacing: 1.0,
+            total_nodes: 12,
+            valves_per_node: 4,
+            valve_type: config_types::ValveType::PneumaticSolenoid,
+            response_time_ms: 10.0,
+            dead_volume: config_types::CubicMm::new(0.5),
+            max_switching_freq: 10.0,
+            injection_points: vec![],
+            verification: Default::default(),
+            drivers: ValveDriverConfig { outputs_per_board: 32, ..Default::default() },
+            sub_frames: Default::default(),
+            frame_merge_window_ms: 0.0,
+        };
+        let sent = Arc::new(Mutex::new(Vec::new()));
+        let partition = ValveBusPartition { spi_bus: 0, chip_select: 0, boards: 0..2, nodes: 0..12 };
+        let bus: Box<dyn SpiBus> = Box::new(FakeSpi(sent.clone()));
+        let flusher = BusFlusher::new(vec![(partition, bus)], None, 4);
+        let mut valves = SpiValveController::with_flusher(flusher, &array, 4);
+
+        // Node (1, 0) is outputs 4-7 on the first board, (0, 2) is node 8,
+        // outputs 32-35 on the second
+        let updates = vec![
+            (GridCoordinate::new(1, 0), vec![ValveState::new(1, true)]),
+            (GridCoordinate::new(0, 2), vec![ValveState::new(0, true), ValveState::new(3, true)]),
+        ];
+        valves.set_valve_states(&updates).await.unwrap();
+        // The farthest board goes first
+        assert_eq!(sent.lock().unwrap().last().unwrap(), &vec![0x09, 0, 0, 0, 0x20, 0, 0, 0]);
+        let states = valves.get_valve_states(GridCoordinate::new(0, 2)).await.unwrap();
+        assert_eq!(states.iter().filter(|v| v.open).count(), 2);
+
+        // Off-grid nodes and valves are refused without writing
+        let writes = sent.lock().unwrap().len();
+        for (node, valve) in [(GridCoordinate::new(4, 0), 0), (GridCoordinate::new(0, 3), 0), (GridCoordinate::new(0, 0), 4)] {
+            assert!(valves.set_valve_states(&[(node, vec![ValveState::new(valve, true)])]).await.is_err());
+        }
+        assert_eq!(sent.lock().unwrap().len(), writes);
+
+        valves.set_valve_states(&[(GridCoordinate::new(1, 0), vec![ValveState::new(1, false)])]).await.unwrap();
+        let health = valves.health_check().await.unwrap();
+        let switched = health.iter().find(|h| h.position == GridCoordinate::new(1, 0)).unwrap();
+        assert_eq!((switched.valve_id, switched.cycle_count), (1, 2));
+
+        valves.emergency_close_all().await.unwrap();
+        assert_eq!(sent.lock().unwrap().last().unwrap(), &vec![0; 8]);
+    }
+}
@@ -0,0 +1,535 @@
+//! Z-axis stepper control.
+//!
+//! Moves are executed as step/dir pulse trains on GPIO. Each move follows a
+//! trapezoidal velocity profile limited by `ZAxisConfig::max_speed` and
+//! `max_acceleration`; pulses are timed on a dedicated blocking thread that
+//! sleeps for coarse waits and spins for the final stretch, keeping step
+//! timing within a few microseconds.
+//!
+//! Homing follows `HomingConfig`: a fast approach until the endstop
+//! triggers, a short back-off, and a slow second approach for repeatability.
+//!
+//! Position is tracked by counting steps, so anything that could make the
+//! carriage lose steps is treated as a motion error rather than ignored:
+//!
+//! - the driver's stall output asserting during a move
+//! - the endstop triggering during a normal move
+//!
+//! Either error clears the homed flag; the axis must be re-homed before it
+//! accepts further moves.
+
+use std::sync::atomic::{AtomicBool, AtomicI64, Ordering};
+use std::sync::{Arc, Mutex as StdMutex};
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use tracing::{debug, error, info, warn};
+
+use config_types::{HomingConfig, PrinterConfig, StepperDriverConfig, ZAxisConfig};
+
+use super::bus::{GpioProvider, InputPin, OutputPin};
+use crate::utils::timing::precise_sleep_until;
+use crate::{FirmwareError, ZAxisController};
+
+/// Distance to back off from the endstop before the slow approach (mm).
+pub const HOMING_BACKOFF_MM: f32 = 2.0;
+
+/// Slow approach speed as a fraction of the homing speed.
+const HOMING_SLOW_FACTOR: f32 = 0.25;
+
+/// Extra travel allowed when searching for the endstop, as a fraction of
+/// the axis length.
+const HOMING_SEARCH_MARGIN: f32 = 0.25;
+
+/// Direction signal setup time before the first step.
+const DIR_SETUP_TIME: Duration = Duration::from_micros(5);
+
+/// Trapezoidal velocity profile over a fixed number of steps.
+///
+/// The speed at step `i` is the lowest of the cruise speed, the speed
+/// reachable accelerating from the start and the speed from which the move
+/// can still decelerate to rest at the end, each evaluated at the step's
+/// midpoint.
+#[derive(Debug, Clone, Copy)]
+pub struct TrapezoidalProfile {
+    steps: u64,
+    /// Cruise speed (steps/s)
+    max_speed: f64,
+    /// Acceleration (steps/s²)
+    acceleration: f64,
+}
+
+impl TrapezoidalProfile {
+    pub fn new(steps: u64, max_speed: f64, acceleration: f64) -> Self {
+        Self {
+            steps,
+            max_speed: max_speed.max(1.0),
+            acceleration,
+        }
+    }
+
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    /// Time between step `i` and step `i + 1`.
+    pub fn interval(&self, i: u64) -> Duration {
+        let mut speed = self.max_speed;
+        if self.acceleration > 0.0 {
+            let from_start = (2.0 * self.acceleration * (i as f64 + 0.5)).sqrt();
+            let to_end = (2.0 * self.acceleration * (self.steps as f64 - i as f64 - 0.5).max(0.5)).sqrt();
+            speed = speed.min(from_start).min(to_end);
+        }
+        Duration::from_secs_f64(1.0 / speed)
+    }
+
+    /// Ideal duration of the move.
+    pub fn duration(&self) -> Duration {
+        let steps = self.steps as f64;
+        if self.acceleration <= 0.0 {
+            return Duration::from_secs_f64(steps / self.max_speed);
+        }
+        let ramp_steps = self.max_speed * self.max_speed / (2.0 * self.acceleration);
+        let seconds = if steps >= 2.0 * ramp_steps {
+            2.0 * self.max_speed / self.acceleration + (steps - 2.0 * ramp_steps) / self.max_speed
+        } else {
+            2.0 * (steps / self.acceleration).sqrt()
+        };
+        Duration::from_secs_f64(seconds)
+    }
+}
+
+/// GPIO lines of one stepper driver.
+pub struct StepperPins {
+    pub step: Box<dyn OutputPin>,
+    pub dir: Box<dyn OutputPin>,
+    pub enable: Option<Box<dyn OutputPin>>,
+    pub endstop: Box<dyn InputPin>,
+    pub stall: Option<Box<dyn InputPin>>,
+}
+
+impl StepperPins {
+    /// Opens the pins named in the driver configuration.
+    pub fn open(driver: &StepperDriverConfig, gpio: &dyn GpioProvider) -> Result<Self> {
+        Ok(Self {
+            step: gpio.output(driver.step_pin).context("Z step pin")?,
+            dir: gpio.output(driver.dir_pin).context("Z dir pin")?,
+            enable: driver
+                .enable_pin
+                .map(|pin| gpio.output(pin).context("Z enable pin"))
+                .transpose()?,
+            endstop: gpio.input(driver.endstop_pin).context("Z endstop pin")?,
+            stall: driver
+                .stall_pin
+                .map(|pin| gpio.input(pin).context("Z stall pin"))
+                .transpose()?,
+        })
+    }
+}
+
+/// How an endstop trigger is handled during a move.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EndstopMode {
+    /// Homing: stop cleanly when triggered
+    Stop,
+    /// Normal move: a trigger means the position is wrong
+    Fault,
+    /// Moving away from the endstop
+    Ignore,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct StepOutcome {
+    steps: u64,
+    endstop_hit: bool,
+}
+
+/// Z axis driven by a step/dir stepper driver.
+pub struct StepperZAxis {
+    axis: ZAxisConfig,
+    homing: HomingConfig,
+    /// Axis length (mm)
+    travel: f32,
+    pins: Arc<StdMutex<StepperPins>>,
+    /// Position in steps from Z = 0
+    position: Arc<AtomicI64>,
+    moving: Arc<AtomicBool>,
+    abort: Arc<AtomicBool>,
+    homed: bool,
+}
+
+impl StepperZAxis {
+    /// Opens the driver pins from the printer configuration.
+    pub fn new(config: &PrinterConfig, gpio: &dyn GpioProvider) -> Result<Self> {
+        let pins = StepperPins::open(&config.motion.z_axis.driver, gpio)?;
+        Ok(Self::with_pins(
+            config.motion.z_axis.clone(),
+            config.motion.homing.clone(),
+            config.build_volume.z,
+            pins,
+        ))
+    }
+
+    pub fn with_pins(axis: ZAxisConfig, homing: HomingConfig, travel: f32, pins: StepperPins) -> Self {
+        Self {
+            axis,
+            homing,
+            travel,
+            pins: Arc::new(StdMutex::new(pins)),
+            position: Arc::new(AtomicI64::new(0)),
+            moving: Arc::new(AtomicBool::new(false)),
+            abort: Arc::new(AtomicBool::new(false)),
+            homed: false,
+        }
+    }
+
+    /// Flag that stops the current move at the next step when set.
+    ///
+    /// Safety code holds this handle so it can stop motion without waiting
+    /// for the controller lock held by the moving task.
+    pub fn abort_handle(&self) -> Arc<AtomicBool> {
+        self.abort.clone()
+    }
+
+    pub fn is_homed(&self) -> bool {
+        self.homed
+    }
+
+    fn steps_for(&self, mm: f32) -> i64 {
+        (mm * self.axis.steps_per_mm).round() as i64
+    }
+
+    /// Runs a relative move of `steps` (positive = +Z) on the step thread.
+    async fn run(&mut self, steps: i64, speed: f32, mode: EndstopMode) -> Result<StepOutcome> {
+        if steps == 0 {
+            return Ok(StepOutcome { steps: 0, endstop_hit: false });
+        }
+
+        let spm = self.axis.steps_per_mm as f64;
+        let profile = TrapezoidalProfile::new(
+            steps.unsigned_abs(),
+            speed.min(self.axis.max_speed) as f64 * spm,
+            self.axis.max_acceleration as f64 * spm,
+        );
+        debug!("Z move: {} steps over ~{:?}", steps, profile.duration());
+
+        let job = StepJob {
+            forward: steps > 0,
+            profile,
+            mode,
+            steps_per_mm: self.axis.steps_per_mm,
+            driver: self.axis.driver.clone(),
+            pins: self.pins.clone(),
+            position: self.position.clone(),
+            abort: self.abort.clone(),
+        };
+
+        self.moving.store(true, Ordering::SeqCst);
+        let result = tokio::task::spawn_blocking(move || job.run())
+            .await
+            .map_err(|e| FirmwareError::Motion(format!("Step thread failed: {}", e)))
+            .and_then(|r| r);
+        self.moving.store(false, Ordering::SeqCst);
+
+        result.map_err(|e| {
+            self.homed = false;
+            error!("Z motion error: {}; axis must be re-homed", e);
+            e.into()
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl ZAxisController for StepperZAxis {
+    async fn home(&mut self) -> Result<()> {
+        info!("Homing Z towards {}", if self.homing.home_to_max { "max" } else { "min" });
+        self.abort.store(false, Ordering::SeqCst);
+        self.homed = false;
+
+        let toward: i64 = if self.homing.home_to_max { 1 } else { -1 };
+        let speed = self.homing.homing_speed;
+
+        // Fast approach
+        let search = self.steps_for(self.travel * (1.0 + HOMING_SEARCH_MARGIN));
+        let fast = self.run(toward * search, speed, EndstopMode::Stop).await?;
+        if !fast.endstop_hit {
+            return Err(FirmwareError::Motion(format!(
+                "Endstop not triggered after {:.1} mm",
+                fast.steps as f32 / self.axis.steps_per_mm
+            ))
+            .into());
+        }
+
+        // Back off and make sure the switch released
+        let backoff = self.steps_for(HOMING_BACKOFF_MM);
+        self.run(-toward * backoff, speed, EndstopMode::Ignore).await?;
+        if endstop_triggered(&self.pins, &self.axis.driver)? {
+            return Err(FirmwareError::Motion(
+                "Endstop still triggered after back-off".to_string(),
+            )
+            .into());
+        }
+
+        // Slow approach for repeatability
+        let slow = self
+            .run(toward * backoff * 2, speed * HOMING_SLOW_FACTOR, EndstopMode::Stop)
+            .await?;
+        if !slow.endstop_hit {
+            return Err(FirmwareError::Motion(
+                "Endstop not triggered on slow approach".to_string(),
+            )
+            .into());
+        }
+
+        let home = if self.homing.home_to_max { self.steps_for(self.travel) } else { 0 };
+        self.position.store(home, Ordering::SeqCst);
+        self.homed = true;
+        info!("Z homed at {:.3} mm", home as f32 / self.axis.steps_per_mm);
+        Ok(())
+    }
+
+    async fn move_to(&mut self, z: f32, speed: f32) -> Result<()> {
+        if !self.homed {
+            return Err(FirmwareError::Motion("Z axis is not homed".to_string()).into());
+        }
+        if !(0.0..=self.travel).contains(&z) {
+            return Err(FirmwareError::Motion(format!(
+                "Z target {:.3} mm outside travel 0-{:.1} mm",
+                z, self.travel
+            ))
+            .into());
+        }
+        if speed <= 0.0 {
+            return Err(FirmwareError::Motion(format!("Invalid Z speed {}", speed)).into());
+        }
+        if speed > self.axis.max_speed {
+            warn!("Z speed {} limited to {}", speed, self.axis.max_speed);
+        }
+
+        let delta = self.steps_for(z) - self.position.load(Ordering::SeqCst);
+        let toward_endstop = (delta > 0) == self.homing.home_to_max;
+        let mode = if toward_endstop { EndstopMode::Fault } else { EndstopMode::Ignore };
+        self.run(delta, speed, mode).await.map(|_| ())
+    }
+
+    async fn get_position(&self) -> Result<f32> {
+        Ok(self.position.load(Ordering::SeqCst) as f32 / self.axis.steps_per_mm)
+    }
+
+    async fn is_motion_complete(&self) -> Result<bool> {
+        Ok(!self.moving.load(Ordering::SeqCst))
+    }
+
+    async fn emergency_stop(&mut self) -> Result<()> {
+        self.abort.store(true, Ordering::SeqCst);
+        self.homed = false;
+        // The step thread releases the pins at its next step
+        if let Ok(mut pins) = self.pins.lock() {
+            if let Some(enable) = pins.enable.as_mut() {
+                enable.set(true)?;
+            }
+        }
+        warn!("Z emergency stop; axis must be re-homed");
+        Ok(())
+    }
+}
+
+/// A move handed to the blocking step thread.
+struct StepJob {
+    forward: bool,
+    profile: TrapezoidalProfile,
+    mode: EndstopMode,
+    steps_per_mm: f32,
+    driver: StepperDriverConfig,
+    pins: Arc<StdMutex<StepperPins>>,
+    position: Arc<AtomicI64>,
+    abort: Arc<AtomicBool>,
+}
+
+impl StepJob {
+    fn run(self) -> std::result::Result<StepOutcome, FirmwareError> {
+        let mut pins = self
+            .pins
+            .lock()
+            .map_err(|_| FirmwareError::Motion("Stepper pins lock poisoned".to_string()))?;
+        let io = |e: anyhow::Error| FirmwareError::HardwareOperation(format!("Z GPIO: {}", e));
+
+        pins.dir.set(self.forward != self.driver.invert_direction).map_err(io)?;
+        if let Some(enable) = pins.enable.as_mut() {
+            enable.set(false).map_err(io)?;
+        }
+        precise_sleep_until(Instant::now() + DIR_SETUP_TIME);
+
+        let pulse = Duration::from_micros(self.driver.min_pulse_us as u64);
+        let delta = if self.forward { 1 } else { -1 };
+        let mut next = Instant::now();
+
+        for i in 0..self.profile.steps() {
+            if self.abort.load(Ordering::SeqCst) {
+                return Err(FirmwareError::Motion(format!("Move aborted after {} steps", i)));
+            }
+            if let Some(stall) = pins.stall.as_ref() {
+                if stall.is_high().map_err(io)? {
+                    return Err(FirmwareError::Motion(format!(
+                        "Stall detected at {:.3} mm",
+                        self.position_mm()
+                    )));
+                }
+            }
+            if self.mode != EndstopMode::Ignore {
+                let triggered = pins.endstop.is_high().map_err(io)? != self.driver.endstop_active_low;
+                if triggered {
+                    return match self.mode {
+                        EndstopMode::Stop => Ok(StepOutcome { steps: i, endstop_hit: true }),
+                        _ => Err(FirmwareError::Motion(format!(
+                            "Endstop triggered unexpectedly at {:.3} mm; position lost",
+                            self.position_mm()
+                        ))),
+                    };
+                }
+            }
+
+            precise_sleep_until(next);
+            pins.step.set(true).map_err(io)?;
+            precise_sleep_until(Instant::now() + pulse);
+            pins.step.set(false).map_err(io)?;
+            self.position.fetch_add(delta, Ordering::SeqCst);
+
+            next += self.profile.interval(i);
+        }
+
+        Ok(StepOutcome {
+            steps: self.profile.steps(),
+            endstop_hit: false,
+        })
+    }
+
+    fn position_mm(&self) -> f32 {
+        self.position.load(Ordering::SeqCst) as f32 / self.steps_per_mm
+    }
+}
+
+fn endstop_triggered(pins: &Arc<StdMutex<StepperPins>>, driver: &StepperDriverConfig) -> Result<bool> {
+    let pins = pins
+        .lock()
+        .map_err(|_| FirmwareError::Motion("Stepper pins lock poisoned".to_string()))?;
+    Ok(pins.endstop.is_high()? != driver.endstop_active_low)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Simulated carriage: step pulses move it, endstop closes at or below 0.
+    #[derive(Default)]
+    struct Carriage {
+        position: AtomicI64,
+        forward: AtomicBool,
+        stalled: AtomicBool,
+    }
+
+    struct StepOut(Arc<Carriage>, bool);
+
+    impl OutputPin for StepOut {
+        fn set(&mut self, high: bool) -> Result<()> {
+            if high && !self.1 {
+                let delta = if self.0.forward.load(Ordering::SeqCst) { 1 } else { -1 };
+                self.0.position.fetch_add(delta, Ordering::SeqCst);
+            }
+            self.1 = high;
+            Ok(())
+        }
+    }
+
+    struct DirOut(Arc<Carriage>);
+
+    impl OutputPin for DirOut {
+        fn set(&mut self, high: bool) -> Result<()> {
+            self.0.forward.store(high, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    struct Endstop(Arc<Carriage>);
+
+    impl InputPin for Endstop {
+        fn is_high(&self) -> Result<bool> {
+            Ok(self.0.position.load(Ordering::SeqCst) <= 0)
+        }
+    }
+
+    struct Stall(Arc<Carriage>);
+
+    impl InputPin for Stall {
+        fn is_high(&self) -> Result<bool> {
+            Ok(self.0.stalled.load(Ordering::SeqCst))
+        }
+    }
+
+    fn axis(carriage: &Arc<Carriage>) -> StepperZAxis {
+        let pins = StepperPins {
+            step: Box::new(StepOut(carriage.clone(), false)),
+            dir: Box::new(DirOut(carriage.clone())),
+            enable: None,
+            endstop: Box::new(Endstop(carriage.clone())),
+            stall: Some(Box::new(Stall(carriage.clone()))),
+        };
+        let z = ZAxisConfig {
+            lead_screw_pitch: 2.0,
+            screw_count: 1,
+            steps_per_mm: 100.0,
+            max_speed: 50.0,
+            max_acceleration: 2000.0,
+            driver: StepperDriverConfig::default(),
+        };
+        let homing = HomingConfig {
+            homing_speed: 40.0,
+            home_to_max: false,
+            home_at_startup: true,
+        };
+        StepperZAxis::with_pins(z, homing, 20.0, pins)
+    }
+
+    #[test]
+    fn test_profile_duration() {
+        // 1000 steps, 2000 steps/s cruise, 20000 steps/s² -> 100 step ramps
+        let profile = TrapezoidalProfile::new(1000, 2000.0, 20000.0);
+        let summed: Duration = (0..1000).map(|i| profile.interval(i)).sum();
+        let ideal = profile.duration();
+        assert!((ideal.as_secs_f64() - 0.6).abs() < 1e-9);
+        assert!((summed.as_secs_f64() - ideal.as_secs_f64()).abs() / ideal.as_secs_f64() < 0.03);
+
+        // Too short to reach cruise speed: triangular
+        let short = TrapezoidalProfile::new(50, 2000.0, 20000.0);
+        assert!(short.interval(25) > Duration::from_secs_f64(1.0 / 2000.0));
+    }
+
+    #[tokio::test]
+    async fn test_home_and_move() {
+        let carriage = Arc::new(Carriage::default());
+        carriage.position.store(300, Ordering::SeqCst);
+        let mut z = axis(&carriage);
+
+        assert!(z.move_to(1.0, 10.0).await.is_err());
+        z.home().await.unwrap();
+        assert!(z.is_homed());
+        assert_eq!(z.get_position().await.unwrap(), 0.0);
+
+        z.move_to(1.5, 50.0).await.unwrap();
+        assert_eq!(z.get_position().await.unwrap(), 1.5);
+        assert!(z.is_motion_complete().await.unwrap());
+        assert!(z.move_to(25.0, 10.0).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_stall_clears_homed() {
+        let carriage = Arc::new(Carriage::default());
+        let mut z = axis(&carriage);
+        z.home().await.unwrap();
+
+        carriage.stalled.store(true, Ordering::SeqCst);
+        let err = z.move_to(1.0, 10.0).await.unwrap_err();
+        assert!(err.to_string().contains("Stall"));
+        assert!(!z.is_homed());
+    }
+}
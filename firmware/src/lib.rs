@@ -57,13 +57,13 @@
 //! ```
 
 // External crate imports - Standard library
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 
 // External crate imports - Async runtime
-use tokio::sync::{mpsc, RwLock, Mutex, broadcast};
+use tokio::sync::{mpsc, watch, RwLock, Mutex, broadcast};
 use tokio::time::interval;
 
 // External crate imports - Third party
@@ -72,7 +72,7 @@ use serde::{Deserialize, Serialize};
 use tracing::{debug, error, info, warn, trace};
 
 // Internal ecosystem imports
-use gcode_types::{Command, Coordinate, GridCoordinate, Layer, ValveState};
+use gcode_types::{Command, Coordinate, G4WCommand, GridCoordinate, ValveState, WaitType};
 use config_types::{PrinterConfig, MaterialProfile, SafetyLimits};
 use protocol::{ProtocolMessage, StatusUpdate, ThermalUpdate, PressureUpdate};
 
@@ -85,6 +85,8 @@ pub mod safety;
 pub mod config;
 pub mod utils;
 
+use crate::core::scheduler::BarrierHandler;
+
 // Shared Type Definitions - Fully Implemented
 
 /// Firmware operational state.
@@ -150,6 +152,14 @@ pub struct PrintStatus {
     
     /// Path to current .hg4d file
     pub file_path: PathBuf,
+    
+    /// Cancelled objects (object id -> first layer suppressed)
+    #[serde(default)]
+    pub cancelled_objects: BTreeMap<u32, u32>,
+    
+    /// Material deposited so far per channel (g)
+    #[serde(default)]
+    pub material_consumed: BTreeMap<u8, f32>,
 }
 
 impl PrintStatus {
@@ -162,6 +172,22 @@ impl PrintStatus {
             elapsed_time: Duration::ZERO,
             estimated_remaining: Duration::ZERO,
             file_path,
+            cancelled_objects: BTreeMap::new(),
+            material_consumed: BTreeMap::new(),
+        }
+    }
+
+    /// The periodic status message for this job.
+    pub fn status_update(&self, state: impl Into<String>) -> StatusUpdate {
+        StatusUpdate {
+            state: state.into(),
+            current_layer: self.current_layer,
+            total_layers: self.total_layers,
+            z_position: self.z_position,
+            progress_percent: self.progress_percent,
+            elapsed_time: self.elapsed_time.as_secs(),
+            estimated_remaining: self.estimated_remaining.as_secs(),
+            material_consumed: self.material_consumed.clone(),
         }
     }
 
@@ -174,6 +200,25 @@ impl PrintStatus {
             0.0
         };
     }
+
+    /// Cancels an object from the current layer onward. Returns false if it
+    /// was already cancelled.
+    pub fn cancel_object(&mut self, object_id: u32) -> bool {
+        if self.cancelled_objects.contains_key(&object_id) {
+            return false;
+        }
+        self.cancelled_objects.insert(object_id, self.current_layer);
+        true
+    }
+
+    /// Objects whose valves must stay closed in the given layer.
+    pub fn cancelled_in(&self, layer_number: u32) -> Vec<u32> {
+        self.cancelled_objects
+            .iter()
+            .filter(|(_, &from)| layer_number >= from)
+            .map(|(&id, _)| id)
+            .collect()
+    }
 }
 
 /// Thermal system state tracking all temperature zones.
@@ -384,17 +429,36 @@ impl SystemState {
         }
     }
 
-    /// Adds an error to the system state.
-    pub fn add_error(&mut self, error: SystemError) {
+    /// Adds an error to the system state and moves the firmware to `Error`
+    /// where the transition table allows it; an emergency stop or shutdown
+    /// under way is kept.
+    pub fn add_error(&mut self, error: SystemError, machine: &core::StateMachine) {
+        let reason = error.message.clone();
         self.errors.push(error);
-        self.firmware_state = FirmwareState::Error;
+        // Refused from EmergencyStopped, ShuttingDown and Error itself
+        machine.transition(self, FirmwareState::Error, reason).ok();
     }
 
-    /// Clears all errors if they've been resolved.
-    pub fn clear_errors(&mut self) {
+    /// Removes the thermal fault errors recorded for one zone; other
+    /// zones' faults and other errors are kept.
+    pub fn clear_thermal_fault(&mut self, zone: u8) {
+        let system = format!("thermal_zone_{}", zone);
+        let thermal_codes = [
+            ThermalFault::ThermistorOpen.code(),
+            ThermalFault::ThermistorShort.code(),
+            ThermalFault::HeaterDecoupled.code(),
+        ];
+        self.errors.retain(|e| {
+            !(thermal_codes.iter().any(|c| e.code == *c) && e.affected_systems.contains(&system))
+        });
+    }
+
+    /// Clears all errors if they've been resolved, returning from `Error`
+    /// to `Idle`.
+    pub fn clear_errors(&mut self, machine: &core::StateMachine) {
         self.errors.clear();
-        if self.firmware_state == FirmwareState::Error && self.errors.is_empty() {
-            self.firmware_state = FirmwareState::Idle;
+        if self.firmware_state == FirmwareState::Error {
+            machine.transition(self, FirmwareState::Idle, "errors cleared").ok();
         }
     }
 }
@@ -411,7 +475,7 @@ pub struct SystemError {
     /// Error severity
     pub severity: ErrorSeverity,
     
-    /// Error code for programmatic handling
+    /// Error code for programmatic handling, from `protocol::ErrorCode`
     pub code: String,
     
     /// Human-readable error message
@@ -439,6 +503,54 @@ pub enum ErrorSeverity {
     Critical,
 }
 
+impl SystemError {
+    /// Error for a registered code at its default severity, with its
+    /// recovery hint.
+    pub fn new(code: protocol::ErrorCode, message: impl Into<String>, affected_systems: Vec<String>) -> Self {
+        Self {
+            severity: code.default_severity().into(),
+            code: code.into(),
+            message: message.into(),
+            affected_systems,
+            recovery_action: Some(code.recovery_hint().to_string()),
+            timestamp: std::time::SystemTime::now(),
+        }
+    }
+
+    /// The same error as broadcast to clients.
+    pub fn to_event(&self) -> protocol::ErrorEvent {
+        protocol::ErrorEvent {
+            severity: self.severity.into(),
+            code: self.code.clone(),
+            message: self.message.clone(),
+            affected_systems: self.affected_systems.clone(),
+            recommended_action: self.recovery_action.clone(),
+        }
+    }
+}
+
+impl From<protocol::ErrorSeverity> for ErrorSeverity {
+    fn from(severity: protocol::ErrorSeverity) -> Self {
+        match severity {
+            protocol::ErrorSeverity::Info => ErrorSeverity::Info,
+            protocol::ErrorSeverity::Warning => ErrorSeverity::Warning,
+            protocol::ErrorSeverity::Error => ErrorSeverity::Error,
+            protocol::ErrorSeverity::Critical => ErrorSeverity::Critical,
+        }
+    }
+}
+
+impl From<ErrorSeverity> for protocol::ErrorSeverity {
+    fn from(severity: ErrorSeverity) -> Self {
+        match severity {
+            ErrorSeverity::Info => protocol::ErrorSeverity::Info,
+            ErrorSeverity::Warning => protocol::ErrorSeverity::Warning,
+            ErrorSeverity::Error => protocol::ErrorSeverity::Error,
+            ErrorSeverity::Critical => protocol::ErrorSeverity::Critical,
+        }
+    }
+}
+
 // Core Trait Definitions
 
 /// Trait for controlling valve arrays.
@@ -503,6 +615,33 @@ pub trait HeaterController: Send + Sync {
     
     /// Emergency: turns off all heating.
     async fn emergency_off(&mut self) -> Result<()>;
+    
+    /// Heater PWM duty cycle per zone (0-1) from the last control step, for
+    /// diagnostics. Empty if the controller does not use PWM.
+    fn duty_cycles(&self) -> BTreeMap<u8, f32> {
+        BTreeMap::new()
+    }
+    
+    /// Zones disabled by a fault. A faulted zone's heater stays off and
+    /// refuses targets until the fault is cleared.
+    fn faults(&self) -> BTreeMap<u8, ThermalFault> {
+        BTreeMap::new()
+    }
+    
+    /// Returns a faulted zone to service once the cause has been fixed.
+    async fn clear_fault(&mut self, _zone_id: u8) -> Result<()> {
+        Ok(())
+    }
+    
+    /// Sets the build chamber target (0 turns it off).
+    async fn set_chamber_temperature(&mut self, _target: f32) -> Result<()> {
+        Err(FirmwareError::HardwareOperation("No chamber heater fitted".to_string()).into())
+    }
+    
+    /// Sets the build plate target (0 turns it off).
+    async fn set_bed_temperature(&mut self, _target: f32) -> Result<()> {
+        Err(FirmwareError::HardwareOperation("No bed heater fitted".to_string()).into())
+    }
 }
 
 /// Trait for pressure management.
@@ -517,6 +656,9 @@ pub trait PressureController: Send + Sync {
     /// Gets current flow rate for a channel.
     async fn get_flow_rate(&self, channel_id: u8) -> Result<f32>;
     
+    /// Updates pressure control loops (call periodically).
+    async fn update_control(&mut self) -> Result<()>;
+    
     /// Emergency: vents all pressure.
     async fn emergency_vent(&mut self) -> Result<()>;
 }
@@ -538,13 +680,48 @@ pub struct SensorReadings {
     pub pressures: HashMap<u8, f32>,
     pub flow_rates: HashMap<u8, f32>,
     pub valve_feedbacks: HashMap<GridCoordinate, Vec<bool>>,
+    /// Zones whose thermistor reads at a rail; they have no temperature
+    pub thermal_faults: HashMap<u8, ThermalFault>,
+}
+
+/// Fault that takes a thermal zone out of service.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ThermalFault {
+    /// Thermistor disconnected: the divider reads at the supply rail
+    ThermistorOpen,
+    /// Thermistor or its wiring shorted: the divider reads at ground
+    ThermistorShort,
+    /// Heater driven hard without the temperature rising, e.g. a
+    /// thermistor that has fallen out of its block
+    HeaterDecoupled,
+}
+
+impl ThermalFault {
+    /// Error code reported in [`SystemError::code`].
+    pub fn code(&self) -> protocol::ErrorCode {
+        match self {
+            ThermalFault::ThermistorOpen => protocol::ErrorCode::ThermistorOpen,
+            ThermalFault::ThermistorShort => protocol::ErrorCode::ThermistorShort,
+            ThermalFault::HeaterDecoupled => protocol::ErrorCode::HeaterDecoupled,
+        }
+    }
+}
+
+impl std::fmt::Display for ThermalFault {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ThermalFault::ThermistorOpen => write!(f, "thermistor open circuit"),
+            ThermalFault::ThermistorShort => write!(f, "thermistor short circuit"),
+            ThermalFault::HeaterDecoupled => write!(f, "heater decoupled from thermistor"),
+        }
+    }
 }
 
 // Implementation Skeletons
 
 /// Main firmware struct coordinating all subsystems.
 pub struct Firmware {
-    config: PrinterConfig,
+    config: Arc<RwLock<PrinterConfig>>,
     state: Arc<RwLock<SystemState>>,
     valve_controller: Arc<Mutex<Box<dyn ValveController>>>,
     z_axis: Arc<Mutex<Box<dyn ZAxisController>>>,
@@ -554,52 +731,1237 @@ pub struct Firmware {
     command_tx: mpsc::Sender<FirmwareCommand>,
     command_rx: Option<mpsc::Receiver<FirmwareCommand>>,
     status_tx: broadcast::Sender<ProtocolMessage>,
+    /// Checks and broadcasts every firmware state change
+    state_machine: Arc<core::StateMachine>,
+    supervisor: Arc<safety::TaskSupervisor>,
+    /// Real heater/pressure controllers while a dry run is active
+    dry_run: Arc<Mutex<Option<core::dry_run::DryRunSwap>>>,
+    /// Material loaded on each channel
+    materials: Arc<RwLock<core::MaterialRegistry>>,
+    /// Protocol trace being recorded, if any
+    trace: Option<Arc<utils::TraceRecorder>>,
+    /// Flow calibration awaiting measurements
+    flow_calibration: Option<core::FlowCalibration>,
+    /// Bed level measurement in progress
+    bed_level: Option<core::BedLevelSession>,
+    /// Spool drying cycle in progress
+    drying: Option<core::DryingCycle>,
+    /// File the active configuration was loaded from, for saving calibration
+    config_path: Option<PathBuf>,
+    /// Finished jobs, if a history database is configured
+    history: Option<Arc<core::PrintHistory>>,
+    /// Record of the running job
+    job: Arc<Mutex<Option<core::JobRecorder>>>,
+    /// Material deposited and measured by the running job
+    consumption: Arc<Mutex<Option<core::ConsumptionTracker>>>,
+    /// Live adjustments in force and queued for the next layer
+    adjustments: Arc<Mutex<core::LiveAdjuster>>,
+    /// Compiles and latches layers for every job
+    scheduler: Arc<Mutex<core::CommandScheduler>>,
+    /// The scheduler's latch reset, triggered by valve writes around it
+    latch_reset: core::LatchReset,
+    /// Steers the running job between layers
+    job_control: Arc<watch::Sender<core::executor::JobControl>>,
+    /// Task running the current print job
+    print_task: Option<tokio::task::JoinHandle<Result<protocol::JobResult>>>,
+    /// Re-prime after a runout, resuming the print when done
+    priming: Option<tokio::task::JoinHandle<()>>,
+    /// The print and priming tasks, for emergency stops made without the
+    /// firmware
+    job_tasks: safety::emergency::JobTasks,
+    /// The driver boards' output enable, cut by power-loss shutdown
+    valve_cutoff: hardware::ValveCutoff,
+    /// Last valve driver self-test; prints are refused until one passes
+    driver_topology: Option<hardware::TopologyReport>,
+    /// Released by resume_print while a print holds at an inspection pause
+    inspection: core::InspectionGate,
+    /// Interlocks currently tripped; prints neither start nor resume meanwhile
+    interlocks: safety::InterlockStatus,
+    /// Channels whose runout switch reads empty
+    runout: core::RunoutStatus,
+    /// Print paused by a channel running out, until its re-prime is done
+    runout_pause: Arc<Mutex<Option<core::RunoutPause>>>,
+    /// Fed the latched valve states by jobs and the channel readings by
+    /// the pressure task
+    stuck_valves: Arc<Mutex<safety::StuckValveDetector>>,
+}
+
+/// Options for starting a print job.
+#[derive(Debug, Clone, Default)]
+pub struct PrintOptions {
+    /// Layer to start from (for resume)
+    pub start_layer: Option<u32>,
+    pub mode: ExecutionMode,
+    /// Objects cancelled before the job was interrupted (resume only)
+    pub cancelled_objects: BTreeMap<u32, u32>,
+}
+
+impl PrintOptions {
+    pub fn dry_run() -> Self {
+        Self {
+            mode: ExecutionMode::DryRun,
+            ..Self::default()
+        }
+    }
+}
+
+impl From<&protocol::StartPrintCommand> for PrintOptions {
+    fn from(cmd: &protocol::StartPrintCommand) -> Self {
+        Self {
+            start_layer: cmd.start_layer,
+            mode: if cmd.dry_run { ExecutionMode::DryRun } else { ExecutionMode::Normal },
+            ..Self::default()
+        }
+    }
 }
 
 impl Firmware {
     /// Creates and initializes firmware with given printer configuration.
     pub async fn new(config: PrinterConfig) -> Result<Self> {
-        todo!("Implementation needed: Initialize all hardware controllers and subsystems")
+        let (status_tx, _) = broadcast::channel(256);
+        let (command_tx, command_rx) = mpsc::channel(32);
+        let state = Arc::new(RwLock::new(SystemState::new()));
+        let state_machine = Arc::new(core::StateMachine::new(status_tx.clone()));
+
+        let buses = hardware::LinuxBusProvider::new();
+        let sensors: Arc<Box<dyn SensorInterface>> = Arc::new(Box::new(
+            hardware::MultiplexedSensorInterface::from_config(&config.sensors, &buses).context("Sensors")?,
+        ));
+        let valve_buses = hardware::BusFlusher::open(&buses, &buses, &config.valve_array).context("Valve array")?;
+        let valves: Box<dyn ValveController> =
+            Box::new(hardware::SpiValveController::start(valve_buses, &config).await.context("Valve array")?);
+        // Enabled once the controller has shifted out an all-closed frame
+        let valve_cutoff = hardware::ValveCutoff::open(&config.valve_array.drivers, &buses)?;
+        let z_axis: Box<dyn ZAxisController> =
+            Box::new(hardware::StepperZAxis::new(&config, &buses).context("Z axis")?);
+        let heaters: Box<dyn HeaterController> =
+            Box::new(hardware::PidHeaterController::new(&config, sensors.clone(), &buses).context("Heaters")?);
+        let pressure: Box<dyn PressureController> = Box::new(
+            hardware::PneumaticPressureController::new(&config, sensors.clone(), &buses, &buses)
+                .context("Pressure regulators")?,
+        );
+        let valve_controller = Arc::new(Mutex::new(valves));
+        let heater_controller = Arc::new(Mutex::new(heaters));
+
+        let scheduler = core::CommandScheduler::from_valve_array(&config.valve_array);
+        let latch_reset = scheduler.latch_reset();
+        let job_tasks = safety::emergency::JobTasks::default();
+        let supervisor = Arc::new(safety::TaskSupervisor::new(
+            safety::watchdog::SafeStateTargets {
+                heaters: heater_controller.clone(),
+                valves: valve_controller.clone(),
+                state: state.clone(),
+                state_machine: state_machine.clone(),
+                latch_reset: latch_reset.clone(),
+                job_tasks: job_tasks.clone(),
+            },
+            status_tx.clone(),
+        ));
+
+        let firmware = Self {
+            config: Arc::new(RwLock::new(config)),
+            state,
+            valve_controller,
+            z_axis: Arc::new(Mutex::new(z_axis)),
+            heater_controller,
+            pressure_controller: Arc::new(Mutex::new(pressure)),
+            sensors,
+            command_tx,
+            command_rx: Some(command_rx),
+            status_tx,
+            state_machine,
+            supervisor,
+            dry_run: Arc::new(Mutex::new(None)),
+            materials: Arc::new(RwLock::new(core::MaterialRegistry::new())),
+            trace: None,
+            flow_calibration: None,
+            bed_level: None,
+            drying: None,
+            config_path: None,
+            history: None,
+            job: Arc::new(Mutex::new(None)),
+            consumption: Arc::new(Mutex::new(None)),
+            adjustments: Arc::new(Mutex::new(core::LiveAdjuster::new())),
+            scheduler: Arc::new(Mutex::new(scheduler)),
+            latch_reset,
+            job_control: Arc::new(watch::channel(core::executor::JobControl::Run).0),
+            print_task: None,
+            priming: None,
+            job_tasks,
+            valve_cutoff,
+            driver_topology: None,
+            inspection: core::InspectionGate::new(),
+            interlocks: safety::InterlockStatus::default(),
+            runout: core::RunoutStatus::default(),
+            runout_pause: Arc::new(Mutex::new(None)),
+            stuck_valves: Arc::new(Mutex::new(safety::StuckValveDetector::new(
+                safety::stuck_valve::StuckValveConfig::default(),
+            ))),
+        };
+        firmware.set_state(FirmwareState::Idle, "hardware initialized").await?;
+        Ok(firmware)
     }
 
     /// Starts a print job from .hg4d file.
     pub async fn start_print<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
-        todo!("Implementation needed: Load .hg4d file and begin print execution")
+        self.start_print_with(path, PrintOptions::default()).await
+    }
+
+    /// Starts a print job with explicit options.
+    ///
+    /// In [`ExecutionMode::DryRun`] the heater and pressure controllers are
+    /// inhibited until the job ends (see [`core::dry_run`]); Z moves, valve
+    /// timing and waits run as in a normal print.
+    pub async fn start_print_with<P: AsRef<Path>>(&mut self, path: P, options: PrintOptions) -> Result<()> {
+        if self.job_running() {
+            return Err(FirmwareError::InvalidCommand("A print is already running".to_string()).into());
+        }
+        self.check_not_drying()?;
+
+        // Every valve must be reachable before anything heats
+        self.check_driver_boards()?;
+        self.check_interlocks()?;
+
+        // Refuse files sliced for other materials before heating anything
+        let path_ref = path.as_ref();
+        let mut file = std::fs::File::open(path_ref)
+            .map_err(|e| FirmwareError::File(format!("{}: {}", path_ref.display(), e)))?;
+        let metadata = gcode::stream::read_file_metadata(&mut file)?;
+        self.materials
+            .read()
+            .await
+            .check_print(&*self.config.read().await, &metadata.materials)
+            .enforce()?;
+        let total_layers = gcode::stream::read_layer_index(&mut file)?.len() as u32;
+        let grid_spacing = self.config.read().await.valve_array.grid_spacing;
+        *self.job.lock().await = Some(core::JobRecorder::new(
+            path_ref,
+            total_layers,
+            options.mode == ExecutionMode::DryRun,
+            grid_spacing,
+        ));
+        let mut dry_run = self.dry_run.lock().await;
+        if options.mode == ExecutionMode::DryRun && dry_run.is_none() {
+            info!("Starting dry run of {}", path.as_ref().display());
+            *dry_run = Some(
+                core::dry_run::DryRunSwap::enter(&self.heater_controller, &self.pressure_controller)
+                    .await?,
+            );
+        }
+        drop(dry_run);
+
+        self.adjustments.lock().await.reset();
+        let job = core::executor::PrintJob {
+            path: path_ref.to_path_buf(),
+            metadata,
+            total_layers,
+            start_layer: options.start_layer.unwrap_or(0),
+            cancelled_objects: options.cancelled_objects,
+        };
+        self.spawn_job(|executor, control| async move { executor.run_job(job, control).await });
+        Ok(())
+    }
+
+    /// Whether a print job's task is still running.
+    fn job_running(&self) -> bool {
+        self.print_task.as_ref().is_some_and(|task| !task.is_finished())
+    }
+
+    /// Runs a job as the print task, steered by the job control from now.
+    ///
+    /// The task beats the supervisor whenever it is polled, so a job that
+    /// panics or blocks its worker thread puts the printer in a safe state.
+    fn spawn_job<F>(&mut self, job: impl FnOnce(core::Executor, watch::Receiver<core::executor::JobControl>) -> F)
+    where
+        F: std::future::Future<Output = Result<protocol::JobResult>> + Send + 'static,
+    {
+        self.job_control.send_replace(core::executor::JobControl::Run);
+        let run = job(self.executor(), self.job_control.subscribe());
+        let period = Duration::from_millis(PRINT_JOB_HEARTBEAT_MS);
+        let heartbeat = self.supervisor.register("print_job", period * HEARTBEAT_DEADLINE_FACTOR);
+        let release = heartbeat.release_handle();
+        let task = tokio::spawn(async move {
+            tokio::pin!(run);
+            let mut beat = interval(period);
+            let result = loop {
+                tokio::select! {
+                    result = &mut run => break result,
+                    _ = beat.tick() => heartbeat.beat(),
+                }
+            };
+            heartbeat.finish();
+            result
+        });
+        self.job_tasks.track_supervised(task.abort_handle(), release);
+        self.print_task = Some(task);
+    }
+
+    /// Handles for running layers without the firmware itself.
+    fn executor(&self) -> core::Executor {
+        core::Executor {
+            config: self.config.clone(),
+            state: self.state.clone(),
+            valves: self.valve_controller.clone(),
+            z_axis: self.z_axis.clone(),
+            heaters: self.heater_controller.clone(),
+            pressure: self.pressure_controller.clone(),
+            sensors: self.sensors.clone(),
+            status_tx: self.status_tx.clone(),
+            state_machine: self.state_machine.clone(),
+            materials: self.materials.clone(),
+            adjustments: self.adjustments.clone(),
+            scheduler: self.scheduler.clone(),
+            dry_run: self.dry_run.clone(),
+            job: self.job.clone(),
+            history: self.history.clone(),
+            consumption: self.consumption.clone(),
+            inspection: self.inspection.clone(),
+            stuck_valves: self.stuck_valves.clone(),
+        }
+    }
+
+    /// Discovers the valve driver boards and checks them against the
+    /// configuration. The report is kept; prints start only after a
+    /// passing one.
+    pub async fn run_driver_self_test(&mut self, provider: &dyn hardware::BusProvider) -> Result<hardware::TopologyReport> {
+        let valve_array = self.config.read().await.valve_array.clone();
+        let mut chain = hardware::DriverChain::open(provider, &valve_array.drivers)?;
+        let report = chain.self_test(&valve_array)?;
+        for fault in &report.faults {
+            error!("{}", fault);
+        }
+        self.driver_topology = Some(report.clone());
+        Ok(report)
+    }
+
+    /// Takes the configured driver chain as tested, in simulation mode where
+    /// there is no chain to talk to.
+    pub async fn assume_driver_chain(&mut self) -> hardware::TopologyReport {
+        let report = hardware::TopologyReport::simulated(&self.config.read().await.valve_array);
+        self.driver_topology = Some(report.clone());
+        report
+    }
+
+    /// Result of the last driver self-test.
+    pub fn driver_topology(&self) -> Option<&hardware::TopologyReport> {
+        self.driver_topology.as_ref()
+    }
+
+    /// Refuses jobs while a drying cycle holds the bed or chamber heater.
+    fn check_not_drying(&self) -> std::result::Result<(), FirmwareError> {
+        match &self.drying {
+            Some(cycle) => Err(FirmwareError::PrintRejected(format!(
+                "Channel {} is drying; cancel the cycle or wait for it to finish",
+                cycle.channel
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    fn check_driver_boards(&self) -> std::result::Result<(), FirmwareError> {
+        match &self.driver_topology {
+            Some(report) => report
+                .ensure_ok()
+                .map_err(|e| FirmwareError::PrintRejected(e.to_string())),
+            None => Err(FirmwareError::PrintRejected(
+                "Valve driver boards have not been self-tested".to_string(),
+            )),
+        }
+    }
+
+    /// Execution mode of the current job.
+    pub async fn execution_mode(&self) -> ExecutionMode {
+        if self.dry_run.lock().await.is_some() {
+            ExecutionMode::DryRun
+        } else {
+            ExecutionMode::Normal
+        }
     }
 
     /// Pauses current print job.
+    ///
+    /// The layer being deposited finishes; the job then holds with
+    /// temperatures and pressures maintained.
     pub async fn pause_print(&mut self) -> Result<()> {
-        todo!("Implementation needed: Pause printing, maintain temperatures and pressures")
+        self.set_state(FirmwareState::Paused, "pause requested").await?;
+        self.job_control.send_replace(core::executor::JobControl::Pause);
+        info!("Print paused after the current layer");
+        Ok(())
     }
 
     /// Resumes paused print job.
+    ///
+    /// Refused while an interlock is tripped; the Printing guards re-check
+    /// thermal and pressure targets first. A print paused by a runout is
+    /// re-primed first and resumes once that is done (see
+    /// resume_after_runout). Also ends an inspection hold.
     pub async fn resume_print(&mut self) -> Result<()> {
-        todo!("Implementation needed: Resume printing from pause point")
+        self.check_interlocks()?;
+        if self.resume_after_runout().await? {
+            return Ok(());
+        }
+        self.set_state(FirmwareState::Printing, "resume requested").await?;
+        self.job_control.send_replace(core::executor::JobControl::Run);
+        self.inspection.release();
+        info!("Print resumed");
+        Ok(())
     }
 
     /// Cancels current print job.
+    ///
+    /// A job still homing or heating stops at once; a printing one after
+    /// its current layer, closing its valves and turning heaters and
+    /// pressures off as it ends. Returns without waiting for that layer.
     pub async fn cancel_print(&mut self) -> Result<()> {
-        todo!("Implementation needed: Cancel print, cool down, return to idle")
+        if !self.job_running() {
+            return Err(FirmwareError::InvalidCommand("No print is running".to_string()).into());
+        }
+        self.job_control.send_replace(core::executor::JobControl::Cancel);
+        // A job held for inspection ends at the hold
+        self.inspection.release();
+        if let Some(priming) = self.priming.take() {
+            priming.abort();
+        }
+        self.runout_pause.lock().await.take();
+        let state = self.state.read().await.firmware_state;
+        if matches!(state, FirmwareState::Homing | FirmwareState::Heating) {
+            // Releases the job's heartbeat before aborting it
+            self.job_tasks.abort_all();
+            self.print_task = None;
+            let executor = self.executor();
+            executor.end_dry_run().await;
+            executor.finish_job(protocol::JobResult::Cancelled).await;
+            self.set_state(FirmwareState::Idle, "print cancelled").await?;
+            executor.cool_down().await?;
+            info!("Print cancelled");
+        } else {
+            info!("Print cancelled; stopping after the current layer");
+        }
+        Ok(())
+    }
+
+    /// Cancels one tagged object of the running print.
+    ///
+    /// The cancellation is recorded against the current layer. Every layer
+    /// compiled from then on keeps the object's valves closed while the
+    /// other objects continue; frames already compiled run unchanged.
+    pub async fn cancel_object(&mut self, object_id: u32) -> Result<()> {
+        let mut state = self.state.write().await;
+        if !state.firmware_state.is_printing() {
+            return Err(FirmwareError::InvalidCommand("No print is running".to_string()).into());
+        }
+        let Some(status) = state.print_status.as_mut() else {
+            return Err(FirmwareError::InvalidCommand("No print is running".to_string()).into());
+        };
+
+        if status.cancel_object(object_id) {
+            info!("Cancelled object {} from layer {}", object_id, status.current_layer);
+        } else {
+            debug!("Object {} is already cancelled", object_id);
+        }
+        Ok(())
+    }
+
+    /// Records the material loaded on a channel, or marks it empty.
+    ///
+    /// Refused while printing, since the running job was checked against
+    /// the materials loaded when it started.
+    pub async fn set_material(&mut self, channel: u8, profile: Option<MaterialProfile>) -> Result<()> {
+        if self.state.read().await.firmware_state.is_printing() {
+            return Err(FirmwareError::InvalidCommand(
+                "Materials cannot be changed while printing".to_string(),
+            )
+            .into());
+        }
+
+        let mut materials = self.materials.write().await;
+        match profile {
+            Some(profile) => {
+                let name = profile.name.clone();
+                materials.load(&*self.config.read().await, channel, profile)?;
+                info!("Channel {} loaded with {}", channel, name);
+            }
+            None => {
+                if let Some(previous) = materials.unload(channel) {
+                    info!("Channel {} unloaded ({})", channel, previous.name);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Starts drying the spool loaded on a channel.
+    pub async fn start_drying(&mut self, cmd: protocol::StartDryingCommand) -> Result<()> {
+        if self.job_running() || !self.state.read().await.firmware_state.is_ready() {
+            return Err(FirmwareError::InvalidCommand("Drying needs an idle printer".to_string()).into());
+        }
+        if let Some(running) = &self.drying {
+            return Err(FirmwareError::InvalidCommand(format!(
+                "Channel {} is already drying",
+                running.channel
+            ))
+            .into());
+        }
+
+        let cycle = {
+            let materials = self.materials.read().await;
+            let profile = materials.get(cmd.channel).ok_or_else(|| {
+                FirmwareError::InvalidCommand(format!("No material loaded on channel {}", cmd.channel))
+            })?;
+            core::DryingCycle::plan(&*self.config.read().await, cmd.channel, profile, cmd.temperature, cmd.hours)?
+        };
+        self.set_drying_heater(cycle.heater, cycle.temperature).await?;
+        info!(
+            "Drying channel {} at {:.0}°C ({:?}) for {:.1}h",
+            cycle.channel,
+            cycle.temperature,
+            cycle.heater,
+            cycle.duration.as_secs_f32() / 3600.0
+        );
+        self.drying = Some(cycle);
+        Ok(())
+    }
+
+    /// Stops a drying cycle early; the spool's exposure is kept.
+    pub async fn cancel_drying(&mut self) -> Result<()> {
+        let cycle = self.drying.take().ok_or_else(|| {
+            FirmwareError::InvalidCommand("No drying cycle running".to_string())
+        })?;
+        self.set_drying_heater(cycle.heater, 0.0).await?;
+        info!("Drying of channel {} cancelled", cycle.channel);
+        Ok(())
+    }
+
+    /// Accounts spool exposure for `elapsed` uptime, finishes a completed
+    /// drying cycle and broadcasts newly exceeded exposure limits. Called
+    /// periodically.
+    pub async fn update_material_conditioning(&mut self, elapsed: Duration) -> Result<()> {
+        let drying_channel = self.drying.as_ref().map(|c| c.channel);
+        if let Some(cycle) = self.drying.as_ref().filter(|c| c.is_complete()).cloned() {
+            self.drying = None;
+            self.set_drying_heater(cycle.heater, 0.0).await?;
+            self.materials.write().await.mark_dried(cycle.channel);
+            info!("Channel {} dried", cycle.channel);
+        }
+
+        let warnings = {
+            let mut materials = self.materials.write().await;
+            materials.add_exposure(elapsed, drying_channel);
+            materials.exposure_warnings()
+        };
+        for warning in warnings {
+            warn!(
+                "Channel {} ({}) loaded {:.0}h, over its {:.0}h limit; dry it before printing",
+                warning.channel, warning.material, warning.exposure_hours, warning.limit_hours
+            );
+            // No subscribers is not an error
+            self.status_tx.send(ProtocolMessage::MaterialExposure(warning)).ok();
+        }
+        Ok(())
+    }
+
+    /// Broadcasts a host telemetry sample and raises the warnings it
+    /// crossed. Called periodically.
+    pub async fn report_host_telemetry(
+        &mut self,
+        telemetry: protocol::HostTelemetry,
+        warnings: Vec<utils::HostWarning>,
+    ) {
+        for warning in warnings {
+            let mut job = self.job.lock().await;
+            let message = match &*job {
+                Some(_) => format!("{} during a print", warning.message),
+                None => warning.message,
+            };
+            warn!("{}", message);
+            self.state.write().await.warnings.push(message.clone());
+            if let Some(job) = job.as_mut() {
+                job.record_error(message.clone());
+            }
+            let event = protocol::ErrorEvent::new(warning.code, message, vec!["host".to_string()]);
+            // No subscribers is not an error
+            self.status_tx.send(ProtocolMessage::ErrorEvent(event)).ok();
+        }
+        self.status_tx.send(ProtocolMessage::HostTelemetry(telemetry)).ok();
+    }
+
+    /// Loaded materials with spool exposure and drying progress.
+    pub async fn loaded_materials(&self) -> Vec<protocol::LoadedMaterial> {
+        let mut loaded = self.materials.read().await.loaded();
+        if let Some(cycle) = &self.drying {
+            if let Some(spool) = loaded
+                .iter_mut()
+                .find(|m| m.channel == cycle.channel)
+                .and_then(|m| m.spool.as_mut())
+            {
+                spool.drying_remaining_s = Some(cycle.remaining().as_secs());
+            }
+        }
+        loaded
+    }
+
+    async fn set_drying_heater(&self, heater: core::drying::DryingHeater, target: f32) -> Result<()> {
+        let mut heaters = self.heater_controller.lock().await;
+        match heater {
+            core::drying::DryingHeater::Chamber => heaters.set_chamber_temperature(target).await,
+            core::drying::DryingHeater::Bed => heaters.set_bed_temperature(target).await,
+        }
+    }
+
+    /// Sets the heater a G4H addresses. A zone-less zone target applies to
+    /// every configured zone. Waiting for the target is left to the
+    /// temperature barrier that follows in the program.
+    pub async fn apply_heating(&self, cmd: &gcode_types::G4HCommand) -> Result<()> {
+        let zones: Vec<u8> = match (cmd.heater, cmd.zone) {
+            (gcode_types::Heater::Zone, Some(zone)) => vec![zone],
+            (gcode_types::Heater::Zone, None) => {
+                self.config.read().await.thermal.zones.iter().map(|z| z.id).collect()
+            }
+            _ => Vec::new(),
+        };
+
+        let adjustments = self.adjustments.lock().await;
+        let mut heaters = self.heater_controller.lock().await;
+        match cmd.heater {
+            gcode_types::Heater::Zone => {
+                for &zone in &zones {
+                    // A live adjustment outranks the file
+                    let target = adjustments.temperature_for(zone, cmd.temperature.get());
+                    heaters.set_temperature(zone, target).await?;
+                }
+            }
+            gcode_types::Heater::Bed => heaters.set_bed_temperature(cmd.temperature.get()).await?,
+            gcode_types::Heater::Chamber => heaters.set_chamber_temperature(cmd.temperature.get()).await?,
+        }
+        drop(heaters);
+
+        let mut state = self.state.write().await;
+        let thermal = &mut state.thermal;
+        let target = cmd.temperature.get();
+        match cmd.heater {
+            gcode_types::Heater::Zone => {
+                for zone in zones {
+                    thermal.zones.entry(zone).or_insert((0.0, 0.0)).1 =
+                        adjustments.temperature_for(zone, target);
+                }
+            }
+            gcode_types::Heater::Bed => thermal.bed.get_or_insert((0.0, 0.0)).1 = target,
+            gcode_types::Heater::Chamber => thermal.chamber.get_or_insert((0.0, 0.0)).1 = target,
+        }
+        Ok(())
+    }
+
+    /// Sets the channel pressure a G4P addresses. A channel-less target
+    /// applies to every channel. Waiting for the target is left to the
+    /// pressure barrier that follows in the program.
+    pub async fn apply_pressure(&self, cmd: &gcode_types::G4PCommand) -> Result<()> {
+        let channels: Vec<u8> = match cmd.material_channel {
+            Some(channel) => vec![channel],
+            None => (0..self.config.read().await.materials.channel_count).collect(),
+        };
+
+        let adjustments = self.adjustments.lock().await;
+        let targets: Vec<(u8, f32)> = channels
+            .into_iter()
+            // A live adjustment outranks the file
+            .map(|channel| (channel, adjustments.pressure_for(channel, cmd.pressure.get())))
+            .collect();
+        drop(adjustments);
+
+        let mut pressure = self.pressure_controller.lock().await;
+        for &(channel, target) in &targets {
+            pressure.set_pressure(channel, target).await?;
+        }
+        drop(pressure);
+
+        let mut state = self.state.write().await;
+        for (channel, target) in targets {
+            state.pressure.channels.entry(channel).or_insert((0.0, 0.0)).1 = target;
+        }
+        Ok(())
+    }
+
+    /// Validates an `AdjustParameter` command and applies it now or queues
+    /// it for the next layer. Outside a print there is no next layer, so
+    /// changes apply at once. Returns a note when the value was clamped.
+    pub async fn adjust_parameter(&mut self, cmd: &protocol::AdjustParameterCommand) -> Result<Option<String>> {
+        let (adjustment, note) = {
+            let config = self.config.read().await;
+            let materials = self.materials.read().await;
+            core::Adjustment::resolve(cmd, &config, &materials)?
+        };
+        if let Some(note) = &note {
+            warn!("Adjustment {:?}: {}", cmd.parameter, note);
+        }
+
+        let state = self.state.read().await.firmware_state;
+        let timing = match state {
+            FirmwareState::Printing | FirmwareState::Paused => cmd.apply,
+            _ => protocol::AdjustTiming::Immediate,
+        };
+        let applied = self.adjustments.lock().await.submit(adjustment, timing);
+        if applied.is_empty() {
+            info!("Queued {:?} for the next layer", adjustment);
+        }
+        self.executor().apply_adjustments(&applied).await?;
+        Ok(note)
+    }
+
+    /// Starts printing the flow calibration patches for the given channels
+    /// as a job; it can be paused and cancelled like a print.
+    ///
+    /// The run stays open for measurements until it is applied or a new
+    /// one starts.
+    pub async fn start_flow_calibration(&mut self, cmd: protocol::StartFlowCalibrationCommand) -> Result<()> {
+        if self.job_running() {
+            return Err(FirmwareError::InvalidCommand("A print is already running".to_string()).into());
+        }
+        if !self.state.read().await.firmware_state.is_ready() {
+            return Err(FirmwareError::InvalidCommand(
+                "Flow calibration needs an idle printer".to_string(),
+            )
+            .into());
+        }
+        self.check_not_drying()?;
+        self.check_driver_boards()?;
+        self.check_interlocks()?;
+        // The patches are deposited hot, pressurized and homed, as a print is
+        self.state_machine
+            .check(&*self.state.read().await, FirmwareState::Printing)
+            .map_err(FirmwareError::from)?;
+
+        let defaults = core::CalibrationPattern::default();
+        let pattern = core::CalibrationPattern {
+            patch_mm: cmd.patch_mm.unwrap_or(defaults.patch_mm),
+            layers: cmd.layers.unwrap_or(defaults.layers),
+            ..defaults
+        };
+        let calibration = core::FlowCalibration::plan(
+            &*self.config.read().await,
+            &*self.materials.read().await,
+            &cmd.channels,
+            pattern,
+        )?;
+        info!("Printing flow calibration patches for channels {:?}", cmd.channels);
+
+        let layers = calibration.layers();
+        self.flow_calibration = Some(calibration);
+        self.spawn_job(|executor, control| async move {
+            let result = executor.run_layers("Flow calibration", layers, control).await;
+            if let Ok(protocol::JobResult::Completed) = result {
+                info!("Weigh each flow calibration patch and submit its mass");
+            }
+            result
+        });
+        Ok(())
+    }
+
+    /// Records the weighed mass of one calibration patch.
+    pub fn submit_flow_measurement(&mut self, channel: u8, mass_g: f32) -> Result<()> {
+        let calibration = self.flow_calibration.as_mut().ok_or_else(|| {
+            FirmwareError::InvalidCommand("No flow calibration in progress".to_string())
+        })?;
+        calibration.record(channel, mass_g)
+    }
+
+    /// Stores the calibrated multipliers in the loaded material profiles
+    /// and closes the calibration run.
+    pub async fn apply_flow_calibration(&mut self) -> Result<()> {
+        let calibration = self.flow_calibration.as_ref().ok_or_else(|| {
+            FirmwareError::InvalidCommand("No flow calibration in progress".to_string())
+        })?;
+        if !calibration.is_complete() {
+            warn!("Applying flow calibration with unweighed patches; those channels keep their multiplier");
+        }
+
+        let updated = calibration.apply(&mut *self.materials.write().await);
+        info!("Flow multipliers updated on channels {:?}", updated);
+        self.flow_calibration = None;
+        Ok(())
+    }
+
+    /// Starts measuring the bed at the configured probe points.
+    pub async fn start_bed_level(&mut self) -> Result<()> {
+        if !self.state.read().await.firmware_state.is_ready() {
+            return Err(FirmwareError::InvalidCommand(
+                "Bed levelling needs an idle printer".to_string(),
+            )
+            .into());
+        }
+        let session = core::BedLevelSession::plan(&*self.config.read().await)?;
+        info!("Bed level measurement started ({} points)", session.status().len());
+        self.bed_level = Some(session);
+        Ok(())
+    }
+
+    /// Records a manually measured deviation.
+    pub fn submit_bed_level_point(&mut self, index: usize, deviation_mm: f32) -> Result<()> {
+        let session = self.bed_level.as_mut().ok_or_else(|| {
+            FirmwareError::InvalidCommand("No bed level measurement in progress".to_string())
+        })?;
+        session.record(index, deviation_mm)
+    }
+
+    /// Reads all probe points from the configured gap sensors.
+    pub async fn probe_bed_level(&mut self) -> Result<()> {
+        let session = self.bed_level.as_mut().ok_or_else(|| {
+            FirmwareError::InvalidCommand("No bed level measurement in progress".to_string())
+        })?;
+        session.probe(&**self.sensors).await
+    }
+
+    /// Stores the measured height map in the active configuration and, when
+    /// the config file is known, on disk.
+    pub async fn save_bed_level(&mut self) -> Result<()> {
+        let session = self.bed_level.as_ref().ok_or_else(|| {
+            FirmwareError::InvalidCommand("No bed level measurement in progress".to_string())
+        })?;
+        let config = {
+            let mut config = self.config.write().await;
+            let mesh = session.finish(&config)?;
+            if let Some(bed_level) = config.bed_level.as_mut() {
+                bed_level.mesh = Some(mesh);
+            }
+            config.clone()
+        };
+
+        if let Some(path) = &self.config_path {
+            // Replaced atomically, keeping the previous file as a backup
+            config
+                .to_file(path)
+                .map_err(|e| FirmwareError::File(format!("{}: {}", path.display(), e)))?;
+        }
+        info!("Bed height map saved");
+        self.bed_level = None;
+        Ok(())
+    }
+
+    /// Starts a macro from the configuration as a job in its own task.
+    ///
+    /// The whole macro is resolved and checked against the safety limits
+    /// before its first step runs; steps then run in order and the first
+    /// failure ends it.
+    pub async fn run_macro(&mut self, cmd: &protocol::RunMacroCommand) -> Result<()> {
+        if self.job_running() {
+            return Err(FirmwareError::InvalidCommand("A print is already running".to_string()).into());
+        }
+        if !self.state.read().await.firmware_state.is_ready() {
+            return Err(FirmwareError::InvalidCommand("Macros need an idle printer".to_string()).into());
+        }
+        self.check_not_drying()?;
+        let actions = self.resolve_macro(&cmd.name, &cmd.args).await?;
+        let name = cmd.name.clone();
+        self.spawn_job(|executor, control| async move { executor.run_macro(&name, &actions, control).await });
+        Ok(())
+    }
+
+    /// Resolves a configured macro, whatever the firmware state.
+    async fn resolve_macro(&self, name: &str, args: &BTreeMap<String, f32>) -> Result<Vec<core::MacroAction>> {
+        let config = self.config.read().await;
+        let definition = config
+            .macros
+            .iter()
+            .find(|m| m.name == name)
+            .ok_or_else(|| FirmwareError::InvalidCommand(format!("No macro '{}'", name)))?;
+        Ok(core::macros::resolve(definition, args, &core::MacroLimits::from_config(&config))?)
+    }
+
+    /// Recovery journal left by a power loss, if any.
+    pub async fn recovery_journal(&self) -> Result<Option<safety::RecoveryJournal>> {
+        match &self.config.read().await.power_loss {
+            Some(power_loss) => safety::RecoveryJournal::load(&power_loss.journal_path),
+            None => Ok(None),
+        }
+    }
+
+    /// Resumes the print interrupted by a power loss.
+    ///
+    /// Temperature and pressure targets are restored and printing restarts
+    /// at the journaled layer, which is reprinted in full. Z must have been
+    /// re-established without lowering onto the part (boot skips homing
+    /// while a journal exists). The journal is removed once the print starts.
+    pub async fn resume_from_journal(&mut self) -> Result<()> {
+        let journal = self.recovery_journal().await?.ok_or_else(|| {
+            FirmwareError::InvalidCommand("No interrupted print to resume".to_string())
+        })?;
+        info!(
+            "Resuming {} from layer {} (Z {:.2}mm)",
+            journal.file_path.display(),
+            journal.layer,
+            journal.z_position
+        );
+
+        for (&zone_id, &target) in &journal.zone_targets {
+            self.set_temperature(zone_id, target).await?;
+        }
+        for (&channel_id, &target) in &journal.pressure_targets {
+            self.set_pressure(channel_id, target).await?;
+        }
+        let options = PrintOptions {
+            start_layer: Some(journal.layer),
+            cancelled_objects: journal.cancelled_objects.clone(),
+            ..PrintOptions::default()
+        };
+        self.start_print_with(&journal.file_path, options).await?;
+        self.discard_journal().await
+    }
+
+    /// Declines resuming the interrupted print.
+    pub async fn discard_journal(&mut self) -> Result<()> {
+        if let Some(power_loss) = &self.config.read().await.power_loss {
+            safety::RecoveryJournal::discard(&power_loss.journal_path)?;
+            info!("Recovery journal discarded");
+        }
+        Ok(())
+    }
+
+    /// Builds the power-loss handler over this firmware's controllers, or
+    /// `None` if no power-loss signal is configured.
+    pub async fn power_loss_handler(&self) -> Option<safety::PowerLossHandler> {
+        let targets = safety::PowerLossTargets {
+            valves: self.valve_controller.clone(),
+            heaters: self.heater_controller.clone(),
+            pressure: self.pressure_controller.clone(),
+            z_axis: self.z_axis.clone(),
+            state: self.state.clone(),
+            state_machine: self.state_machine.clone(),
+            latch_reset: self.latch_reset.clone(),
+            valve_cutoff: self.valve_cutoff.clone(),
+            job_control: self.job_control.clone(),
+            job_tasks: self.job_tasks.clone(),
+        };
+        safety::PowerLossHandler::new(&*self.config.read().await, targets, self.status_tx.clone())
+    }
+
+    /// Builds the monitor for the configured interlocks, or `None` if there
+    /// are none.
+    pub async fn interlock_monitor(&self) -> Option<safety::InterlockMonitor> {
+        safety::InterlockMonitor::new(
+            &self.config.read().await.safety,
+            self.interlocks.clone(),
+            self.state.clone(),
+            self.state_machine.clone(),
+            self.status_tx.clone(),
+        )
+    }
+
+    /// Responds to a tripped interlock. Pause and abort only act on a
+    /// print; an emergency stop is always carried out.
+    pub async fn handle_interlock(&mut self, trip: &safety::InterlockTrip) -> Result<()> {
+        // The state the trip interrupted; an emergency stop trip has left it
+        let state = trip.state;
+        match trip.action {
+            config_types::InterlockAction::EmergencyStop => self.emergency_stop().await,
+            config_types::InterlockAction::Pause if state == FirmwareState::Printing => self.pause_print().await,
+            config_types::InterlockAction::Abort
+                if matches!(state, FirmwareState::Heating | FirmwareState::Printing | FirmwareState::Paused) =>
+            {
+                self.cancel_print().await
+            }
+            _ => {
+                debug!("Interlock '{}' tripped with no print running", trip.name);
+                Ok(())
+            }
+        }
+    }
+
+    /// Responds to a client connection that died, per
+    /// `SafetyLimits::disconnect_action`. `remaining_clients` is the number
+    /// of clients still connected; a running print is only paused once the
+    /// last one is gone, since any of them can still watch and stop it.
+    pub async fn handle_client_disconnected(
+        &mut self,
+        event: &protocol::DisconnectedEvent,
+        remaining_clients: usize,
+    ) -> Result<()> {
+        let action = self.config.read().await.safety.disconnect_action;
+        let state = self.state.read().await.firmware_state;
+        if remaining_clients > 0 || state != FirmwareState::Printing {
+            debug!("Client disconnected ({}), {} still connected", event, remaining_clients);
+            return Ok(());
+        }
+        match action {
+            config_types::DisconnectAction::Continue => {
+                warn!("Last client disconnected ({}); printing on unattended", event);
+                Ok(())
+            }
+            config_types::DisconnectAction::Pause => {
+                warn!("Last client disconnected ({}); pausing the print", event);
+                self.pause_print().await
+            }
+        }
+    }
+
+    /// Fails while any interlock is tripped.
+    fn check_interlocks(&self) -> std::result::Result<(), FirmwareError> {
+        let tripped = self.interlocks.tripped();
+        if tripped.is_empty() {
+            return Ok(());
+        }
+        Err(FirmwareError::SafetyViolation(format!("Interlock tripped: {}", tripped.join(", "))))
+    }
+
+    /// Builds the monitor for the configured runout switches, or `None` if
+    /// there are none.
+    pub async fn runout_monitor(&self) -> Option<core::RunoutMonitor> {
+        core::RunoutMonitor::new(&self.config.read().await.materials, self.runout.clone())
+    }
+
+    /// Responds to a runout switch settling. A channel running empty
+    /// pauses a running print and releases that channel's pressure only;
+    /// reloading it is announced so the operator can resume.
+    pub async fn handle_runout(&mut self, change: core::RunoutChange) -> Result<()> {
+        let channel = change.channel;
+        if !change.empty {
+            info!("Channel {} runout switch reads material", channel);
+            if let Some(pause) = self.runout_pause.lock().await.as_ref().filter(|p| p.channel == channel) {
+                // No subscribers is not an error
+                self.status_tx
+                    .send(ProtocolMessage::MaterialRunout(pause.event(protocol::RunoutStage::Loaded)))
+                    .ok();
+            }
+            return Ok(());
+        }
+        if self.state.read().await.firmware_state != FirmwareState::Printing {
+            debug!("Channel {} ran out with no print running", channel);
+            return Ok(());
+        }
+
+        self.pause_print().await?;
+        let (layer_number, pressure_target) = {
+            let state = self.state.read().await;
+            (
+                state.print_status.as_ref().map_or(0, |s| s.current_layer),
+                state.pressure.channels.get(&channel).map_or(0.0, |&(_, target)| target),
+            )
+        };
+        self.pressure_controller.lock().await.set_pressure(channel, 0.0).await?;
+
+        let prime_macro = self
+            .config
+            .read()
+            .await
+            .materials
+            .runout_sensors
+            .iter()
+            .find(|s| s.channel == channel)
+            .and_then(|s| s.prime_macro.clone());
+        let pause = core::RunoutPause {
+            channel,
+            material: self.materials.read().await.get(channel).map(|p| p.name.clone()),
+            layer_number,
+            pressure_target,
+            prime_macro,
+        };
+
+        let message = format!("Channel {} ran out in layer {}; print paused", channel, layer_number);
+        warn!("{}", message);
+        self.state.write().await.warnings.push(message.clone());
+        if let Some(job) = self.job.lock().await.as_mut() {
+            job.record_error(message.clone());
+        }
+        let affected = vec![format!("material_channel_{}", channel)];
+        // No subscribers is not an error
+        self.status_tx
+            .send(ProtocolMessage::ErrorEvent(protocol::ErrorEvent::new(
+                protocol::ErrorCode::MaterialRunout,
+                message,
+                affected,
+            )))
+            .ok();
+        self.status_tx
+            .send(ProtocolMessage::MaterialRunout(pause.event(protocol::RunoutStage::Paused)))
+            .ok();
+        *self.runout_pause.lock().await = Some(pause);
+        Ok(())
+    }
+
+    /// Guided resume after a runout: restores the channel's pressure,
+    /// waits for it and runs the channel's prime macro, then resumes the
+    /// print. The re-prime runs as a task of its own and this returns once
+    /// it has started; returns false, doing nothing, unless the print was
+    /// paused by a runout. Refused while the channel still reads empty or
+    /// a re-prime is already running.
+    ///
+    /// A failed re-prime leaves the print paused, to be resumed again.
+    pub async fn resume_after_runout(&mut self) -> Result<bool> {
+        let Some(pause) = self.runout_pause.lock().await.clone() else {
+            return Ok(false);
+        };
+        if self.priming.as_ref().is_some_and(|task| !task.is_finished()) {
+            return Err(FirmwareError::InvalidCommand(format!(
+                "Channel {} is already being re-primed",
+                pause.channel
+            ))
+            .into());
+        }
+        if self.runout.is_empty(pause.channel) {
+            return Err(FirmwareError::InvalidCommand(format!(
+                "Channel {} still reads empty; load material before resuming",
+                pause.channel
+            ))
+            .into());
+        }
+        let actions = match &pause.prime_macro {
+            Some(name) => Some((name.clone(), self.resolve_macro(name, &BTreeMap::new()).await?)),
+            None => None,
+        };
+
+        info!("Re-priming channel {} at {:.1} PSI", pause.channel, pause.pressure_target);
+        // No subscribers is not an error
+        self.status_tx
+            .send(ProtocolMessage::MaterialRunout(pause.event(protocol::RunoutStage::Priming)))
+            .ok();
+        let executor = self.executor();
+        let job_control = self.job_control.clone();
+        let runout_pause = self.runout_pause.clone();
+        let priming = tokio::spawn(async move {
+            let primed = async {
+                executor.pressure.lock().await.set_pressure(pause.channel, pause.pressure_target).await?;
+                let barrier = core::BarrierConfig::from_config(&*executor.config.read().await);
+                core::SubsystemBarrier::new(executor.state.clone(), barrier)
+                    .wait(&G4WCommand { wait_type: WaitType::Pressure, timeout_ms: None })
+                    .await?;
+                if let Some((name, actions)) = &actions {
+                    executor.run_macro(name, actions, job_control.subscribe()).await?;
+                }
+                executor.set_state(FirmwareState::Printing, "resume requested").await
+            };
+            if let Err(e) = primed.await {
+                error!("Re-priming channel {} failed; print stays paused: {:#}", pause.channel, e);
+                return;
+            }
+            runout_pause.lock().await.take();
+            job_control.send_replace(core::executor::JobControl::Run);
+            executor.inspection.release();
+            info!("Channel {} primed; continuing layer {}", pause.channel, pause.layer_number);
+            executor
+                .status_tx
+                .send(ProtocolMessage::MaterialRunout(pause.event(protocol::RunoutStage::Resumed)))
+                .ok();
+        });
+        self.job_tasks.track(priming.abort_handle());
+        self.priming = Some(priming);
+        Ok(true)
     }
 
     /// Triggers emergency stop.
+    ///
+    /// The running job is aborted and every subsystem is made safe; a
+    /// failure of one does not stop the others from being tried. Callers
+    /// that cannot wait for the firmware lock use [`Self::emergency_handle`].
     pub async fn emergency_stop(&mut self) -> Result<()> {
-        todo!("Implementation needed: Immediately stop all operations, make system safe")
+        self.print_task = None;
+        self.priming = None;
+        self.emergency_handle().trigger().await
+    }
+
+    /// Emergency stop usable without the firmware, taken once at start-up.
+    pub fn emergency_handle(&self) -> safety::EmergencyStopHandler {
+        safety::EmergencyStopHandler::new(
+            self.executor(),
+            self.job_control.clone(),
+            self.job_tasks.clone(),
+            self.latch_reset.clone(),
+            self.runout_pause.clone(),
+        )
+    }
+
+    /// Returns a zone disabled by a thermal fault to service and clears
+    /// its fault errors. The cause must have been fixed first.
+    pub async fn clear_thermal_fault(&mut self, zone: u8) -> Result<()> {
+        self.heater_controller.lock().await.clear_fault(zone).await?;
+        self.state.write().await.clear_thermal_fault(zone);
+        info!("Thermal zone {} fault cleared", zone);
+        Ok(())
+    }
+
+    /// Current temperatures with each zone's heater duty cycle.
+    pub async fn thermal_update(&self) -> ProtocolMessage {
+        let duty = self.heater_controller.lock().await.duty_cycles();
+        let thermal = self.state.read().await.thermal.clone();
+        let reading = |(current, target): (f32, f32)| protocol::ThermalReading { current, target };
+
+        let mut zones: Vec<protocol::ThermalZone> = thermal
+            .zones
+            .iter()
+            .map(|(&id, &(current, target))| protocol::ThermalZone {
+                id,
+                current,
+                target,
+                duty: duty.get(&id).copied(),
+            })
+            .collect();
+        zones.sort_by_key(|z| z.id);
+
+        ProtocolMessage::ThermalUpdate(ThermalUpdate {
+            zones,
+            manifold: thermal.manifold.map(reading),
+            bed: thermal.bed.map(reading),
+            chamber: thermal.chamber.map(reading),
+        })
     }
 
     /// Gets current system state.
     pub async fn get_state(&self) -> SystemState {
-        todo!("Implementation needed: Return current system state snapshot")
+        self.state.read().await.clone()
     }
 
     /// Subscribes to status updates.
     pub fn subscribe_status(&self) -> broadcast::Receiver<ProtocolMessage> {
-        todo!("Implementation needed: Return receiver for status broadcasts")
+        self.status_tx.subscribe()
+    }
+
+    /// Moves the firmware to `to` through the state machine.
+    pub async fn set_state(&self, to: FirmwareState, reason: &str) -> Result<()> {
+        let mut state = self.state.write().await;
+        self.state_machine.transition(&mut state, to, reason).map_err(FirmwareError::from)?;
+        Ok(())
+    }
+
+    /// Returns the sender used for status broadcasts.
+    pub fn status_sender(&self) -> broadcast::Sender<ProtocolMessage> {
+        self.status_tx.clone()
+    }
+
+    /// Returns a shared handle to the active printer configuration.
+    ///
+    /// The configuration watcher updates live-safe parameters through this
+    /// handle; subsystems read it at the start of each control cycle.
+    pub fn config_handle(&self) -> Arc<RwLock<PrinterConfig>> {
+        self.config.clone()
+    }
+
+    /// Records client requests and their responses into a trace. Broadcasts
+    /// are recorded by running the recorder on a status subscription.
+    pub fn set_trace_recorder(&mut self, recorder: Arc<utils::TraceRecorder>) {
+        self.trace = Some(recorder);
+    }
+
+    /// Sets the file calibration results are saved to.
+    pub fn set_config_path(&mut self, path: PathBuf) {
+        self.config_path = Some(path);
+    }
+
+    /// Stores finished jobs in the given history database.
+    pub fn set_history(&mut self, history: Arc<core::PrintHistory>) {
+        self.history = Some(history);
+    }
+
+    /// Returns the supervisor background tasks heartbeat into.
+    pub fn supervisor(&self) -> Arc<safety::TaskSupervisor> {
+        self.supervisor.clone()
     }
 
     /// Waits for print to complete.
     pub async fn wait_for_completion(&mut self) -> Result<()> {
-        todo!("Implementation needed: Block until print finishes or errors")
+        let Some(task) = self.print_task.take() else {
+            return Ok(());
+        };
+        match task.await {
+            Ok(result) => result.map(|_| ()),
+            Err(e) => Err(FirmwareError::PrintExecution(format!("Print task ended abnormally: {}", e)).into()),
+        }
     }
 
     /// Runs firmware main loop.
@@ -607,37 +1969,317 @@ impl Firmware {
         todo!("Implementation needed: Main firmware loop coordinating all subsystems")
     }
 
+    /// Handles a protocol message received from a client.
+    ///
+    /// Commands are answered with a `CommandResponse`; requests are answered
+    /// with their matching response message. Status messages are ignored.
+    pub async fn handle_request(&mut self, msg: ProtocolMessage) -> Result<Option<ProtocolMessage>> {
+        let Some(trace) = self.trace.clone() else {
+            return self.dispatch_request(msg).await;
+        };
+        trace.record(protocol::TraceDirection::Inbound, &msg);
+        let response = self.dispatch_request(msg).await?;
+        if let Some(response) = &response {
+            trace.record(protocol::TraceDirection::Outbound, response);
+        }
+        Ok(response)
+    }
+
+    async fn dispatch_request(&mut self, msg: ProtocolMessage) -> Result<Option<ProtocolMessage>> {
+        let result = match msg {
+            ProtocolMessage::StartPrint(cmd) => {
+                self.start_print_with(&cmd.file_path, PrintOptions::from(&cmd)).await
+            }
+            ProtocolMessage::PausePrint(_) => self.pause_print().await,
+            ProtocolMessage::ResumePrint => self.resume_print().await,
+            ProtocolMessage::CancelPrint => self.cancel_print().await,
+            ProtocolMessage::EmergencyStop => self.emergency_stop().await,
+            ProtocolMessage::CancelObject(cmd) => self.cancel_object(cmd.object_id).await,
+            ProtocolMessage::AdjustParameter(cmd) => match self.adjust_parameter(&cmd).await {
+                Ok(note) => {
+                    let response = protocol::CommandResponse::success(note.unwrap_or_else(|| "OK".to_string()));
+                    return Ok(Some(ProtocolMessage::CommandResponse(response)));
+                }
+                Err(e) => Err(e),
+            },
+            ProtocolMessage::SetMaterial(cmd) => self.set_material(cmd.channel, cmd.profile).await,
+            ProtocolMessage::ResumeFromJournal => self.resume_from_journal().await,
+            ProtocolMessage::DiscardJournal => self.discard_journal().await,
+            ProtocolMessage::GetMaterials => {
+                let channels = self.loaded_materials().await;
+                return Ok(Some(ProtocolMessage::MaterialsResponse(protocol::MaterialsResponse {
+                    channels,
+                })));
+            }
+            ProtocolMessage::StartFlowCalibration(cmd) => self.start_flow_calibration(cmd).await,
+            ProtocolMessage::SubmitFlowMeasurement(cmd) => {
+                self.submit_flow_measurement(cmd.channel, cmd.mass_g)
+            }
+            ProtocolMessage::ApplyFlowCalibration => self.apply_flow_calibration().await,
+            ProtocolMessage::GetFlowCalibration => {
+                let channels = self
+                    .flow_calibration
+                    .as_ref()
+                    .map(core::FlowCalibration::status)
+                    .unwrap_or_default();
+                return Ok(Some(ProtocolMessage::FlowCalibrationStatus(
+                    protocol::FlowCalibrationStatus { channels },
+                )));
+            }
+            ProtocolMessage::StartDrying(cmd) => self.start_drying(cmd).await,
+            ProtocolMessage::CancelDrying => self.cancel_drying().await,
+            ProtocolMessage::ClearThermalFault(cmd) => self.clear_thermal_fault(cmd.zone).await,
+            ProtocolMessage::StartBedLevel => self.start_bed_level().await,
+            ProtocolMessage::SubmitBedLevelPoint(cmd) => {
+                self.submit_bed_level_point(cmd.index, cmd.deviation_mm)
+            }
+            ProtocolMessage::ProbeBedLevel => self.probe_bed_level().await,
+            ProtocolMessage::SaveBedLevel => self.save_bed_level().await,
+            ProtocolMessage::GetBedLevel => {
+                let points = self
+                    .bed_level
+                    .as_ref()
+                    .map(core::BedLevelSession::status)
+                    .unwrap_or_default();
+                return Ok(Some(ProtocolMessage::BedLevelStatus(protocol::BedLevelStatus { points })));
+            }
+            ProtocolMessage::GetHistory(query) => match self.history().and_then(|h| h.query(&query)) {
+                Ok(jobs) => {
+                    return Ok(Some(ProtocolMessage::HistoryResponse(protocol::HistoryResponse { jobs })));
+                }
+                Err(e) => Err(e),
+            },
+            ProtocolMessage::GetHistoryStats => match self.history().and_then(|h| h.stats()) {
+                Ok(stats) => return Ok(Some(ProtocolMessage::HistoryStats(stats))),
+                Err(e) => Err(e),
+            },
+            ProtocolMessage::DeleteHistoryJob(cmd) => {
+                self.history().and_then(|h| h.remove(cmd.id)).and_then(|removed| {
+                    if removed {
+                        Ok(())
+                    } else {
+                        Err(FirmwareError::InvalidCommand(format!("No job {} in the history", cmd.id)).into())
+                    }
+                })
+            }
+            ProtocolMessage::RunMacro(cmd) => self.run_macro(&cmd).await,
+            ProtocolMessage::GetMacros => {
+                let macros = self.config.read().await.macros.clone();
+                return Ok(Some(ProtocolMessage::MacrosResponse(protocol::MacrosResponse { macros })));
+            }
+            ProtocolMessage::GetCapabilityDescriptor => {
+                let descriptor = self.capability_descriptor().await;
+                return Ok(Some(ProtocolMessage::CapabilityDescriptor(descriptor)));
+            }
+            ProtocolMessage::GetLogs(query) => {
+                let logs = utils::LogStore::global().query(&query);
+                return Ok(Some(ProtocolMessage::LogsResponse(logs)));
+            }
+            ProtocolMessage::Hello(peer) => {
+                match peer.check_compatible() {
+                    Ok(()) => info!(
+                        "{} {} connected (protocol {})",
+                        peer.software, peer.software_version, peer.protocol_version
+                    ),
+                    // Answered anyway so the peer can report the mismatch
+                    Err(e) => warn!("Incompatible client: {}", e),
+                }
+                return Ok(Some(ProtocolMessage::Hello(self.hello())));
+            }
+            // WebSocket connections answer pings themselves
+            ProtocolMessage::Ping(ping) => return Ok(Some(ProtocolMessage::Pong(ping))),
+            _ => return Ok(None),
+        };
+
+        let response = match result {
+            Ok(()) => protocol::CommandResponse::success("OK"),
+            Err(e) => protocol::CommandResponse::error(format!("{:#}", e)),
+        };
+        Ok(Some(ProtocolMessage::CommandResponse(response)))
+    }
+
+    /// This build's handshake: protocol and firmware version with the
+    /// optional features it implements.
+    pub fn hello(&self) -> protocol::Hello {
+        let mut capabilities = vec![
+            protocol::Capability::CancelObject,
+            protocol::Capability::Subscriptions,
+            protocol::Capability::Heartbeat,
+            protocol::Capability::MessagePack,
+        ];
+        if self.history.is_some() {
+            capabilities.push(protocol::Capability::PrintHistory);
+        }
+        protocol::Hello::new("firmware", FIRMWARE_VERSION, capabilities)
+    }
+
+    /// What this printer can print, from the live configuration, for
+    /// slicers to check their printer file against.
+    pub async fn capability_descriptor(&self) -> protocol::CapabilityDescriptor {
+        let config = self.config.read().await;
+        protocol::CapabilityDescriptor::from_printer_config(
+            &config,
+            FIRMWARE_VERSION,
+            gcode::stream::SUPPORTED_FORMAT_VERSIONS,
+        )
+    }
+
     /// Homes all axes.
     pub async fn home_axes(&mut self) -> Result<()> {
-        todo!("Implementation needed: Home Z-axis")
+        self.set_state(FirmwareState::Homing, "homing requested").await?;
+        let homed = self.executor().home_z().await;
+        self.set_state(FirmwareState::Idle, "homing done").await?;
+        homed
     }
 
     /// Sets target temperature for a zone.
     pub async fn set_temperature(&mut self, zone_id: u8, target: f32) -> Result<()> {
-        todo!("Implementation needed: Set zone temperature target")
+        self.heater_controller.lock().await.set_temperature(zone_id, target).await?;
+        self.state.write().await.thermal.zones.entry(zone_id).or_insert((0.0, 0.0)).1 = target;
+        Ok(())
     }
 
     /// Sets target pressure for a channel.
     pub async fn set_pressure(&mut self, channel_id: u8, target: f32) -> Result<()> {
-        todo!("Implementation needed: Set channel pressure target")
+        self.pressure_controller.lock().await.set_pressure(channel_id, target).await?;
+        self.state.write().await.pressure.channels.entry(channel_id).or_insert((0.0, 0.0)).1 = target;
+        Ok(())
     }
 
     // Private helper methods
 
+    fn history(&self) -> Result<&core::PrintHistory> {
+        self.history
+            .as_deref()
+            .ok_or_else(|| FirmwareError::InvalidCommand("Print history is not enabled".to_string()).into())
+    }
+
     async fn initialize_hardware(&mut self) -> Result<()> {
         todo!("Implementation needed: Initialize all hardware controllers")
     }
 
+    /// Spawns the thermal and pressure control loops. Each beats the task
+    /// supervisor once per cycle.
     async fn start_background_tasks(&mut self) -> Result<()> {
-        todo!("Implementation needed: Spawn thermal control, pressure control, monitoring tasks")
-    }
+        let period = Duration::from_millis(THERMAL_CONTROL_INTERVAL_MS);
+        let heartbeat = self.supervisor.register("thermal_control", period * HEARTBEAT_DEADLINE_FACTOR);
+        let executor = self.executor();
+        let (heaters, state, job) = (executor.heaters.clone(), executor.state.clone(), executor.job.clone());
+        tokio::spawn(async move {
+            let mut interval = interval(period);
+            // Logged once rather than every cycle
+            let mut failing = false;
+            loop {
+                interval.tick().await;
+                let mut heaters = heaters.lock().await;
+                match heaters.update_control().await {
+                    Ok(()) => failing = false,
+                    Err(e) if !failing => {
+                        failing = true;
+                        error!("Thermal control step failed: {:#}", e);
+                    }
+                    Err(_) => {}
+                }
+                let mut state = state.write().await;
+                let zones: Vec<u8> = state.thermal.zones.keys().copied().collect();
+                for zone in zones {
+                    if let Ok(current) = heaters.get_temperature(zone).await {
+                        state.thermal.zones.entry(zone).or_insert((0.0, 0.0)).0 = current;
+                    }
+                }
+                drop(heaters);
+                state.thermal.check_at_target(TEMP_TOLERANCE);
+                let thermal = state.thermal.clone();
+                drop(state);
+                if let Some(job) = job.lock().await.as_mut() {
+                    job.sample_thermal(&thermal);
+                }
+                executor.report_thermal_faults().await;
+                heartbeat.beat();
+            }
+        });
+
+        let period = Duration::from_millis(PRESSURE_CONTROL_INTERVAL_MS);
+        let heartbeat = self.supervisor.register("pressure_control", period * HEARTBEAT_DEADLINE_FACTOR);
+        let (pressure, state, consumption, stuck_valves) = (
+            self.pressure_controller.clone(),
+            self.state.clone(),
+            self.consumption.clone(),
+            self.stuck_valves.clone(),
+        );
+        let response = safety::StuckValveResponse {
+            pressure: self.pressure_controller.clone(),
+            valves: self.valve_controller.clone(),
+            state: self.state.clone(),
+            state_machine: self.state_machine.clone(),
+            latch_reset: self.latch_reset.clone(),
+            status_tx: self.status_tx.clone(),
+        };
+        tokio::spawn(async move {
+            let mut interval = interval(period);
+            let mut failing = false;
+            loop {
+                interval.tick().await;
+                let mut pressure = pressure.lock().await;
+                match pressure.update_control().await {
+                    Ok(()) => failing = false,
+                    Err(e) if !failing => {
+                        failing = true;
+                        error!("Pressure control step failed: {:#}", e);
+                    }
+                    Err(_) => {}
+                }
+                let targets: Vec<(u8, f32)> =
+                    state.read().await.pressure.channels.iter().map(|(&c, &(_, target))| (c, target)).collect();
+                let mut readings = Vec::with_capacity(targets.len());
+                for &(channel, _) in &targets {
+                    if let (Ok(current), Ok(flow)) =
+                        (pressure.get_pressure(channel).await, pressure.get_flow_rate(channel).await)
+                    {
+                        readings.push((channel, current, flow));
+                    }
+                }
+                drop(pressure);
 
-    async fn execute_layer(&mut self, layer: &Layer) -> Result<()> {
-        todo!("Implementation needed: Execute single layer deposition")
+                if let Some(tracker) = consumption.lock().await.as_mut() {
+                    for &(channel, _, flow) in &readings {
+                        tracker.record_flow(channel, flow, period);
+                    }
+                }
+                {
+                    let mut state = state.write().await;
+                    for &(channel, current, flow) in &readings {
+                        state.pressure.channels.entry(channel).or_insert((0.0, 0.0)).0 = current;
+                        state.pressure.flow_rates.insert(channel, flow);
+                    }
+                    state.pressure.check_stable(PRESSURE_TOLERANCE);
+                }
+
+                let faults = {
+                    let mut detector = stuck_valves.lock().await;
+                    for &(channel, target) in &targets {
+                        detector.record_target(channel, target);
+                    }
+                    let sensed = SensorReadings {
+                        pressures: readings.iter().map(|&(channel, current, _)| (channel, current)).collect(),
+                        flow_rates: readings.iter().map(|&(channel, _, flow)| (channel, flow)).collect(),
+                        ..SensorReadings::default()
+                    };
+                    detector.observe(&sensed, Instant::now())
+                };
+                for fault in &faults {
+                    response.respond(fault).await;
+                }
+                heartbeat.beat();
+            }
+        });
+        Ok(())
     }
 
     async fn broadcast_status(&self, status: ProtocolMessage) -> Result<()> {
-        todo!("Implementation needed: Broadcast status update to all subscribers")
+        // No subscribers is not an error
+        self.status_tx.send(status).ok();
+        Ok(())
     }
 }
 
@@ -687,6 +2329,9 @@ pub fn validate_command_safety(cmd: &Command, limits: &SafetyLimits) -> Result<(
             }
         }
         Command::G4L(l) => {
+            if l.ramp_ms == Some(0) {
+                anyhow::bail!("Z ramp to {} has no duration", l.z_height);
+            }
             if let Some(f) = l.feed_rate {
                 if f > limits.max_z_speed {
                     anyhow::bail!(
@@ -703,6 +2348,23 @@ pub fn validate_command_safety(cmd: &Command, limits: &SafetyLimits) -> Result<(
     Ok(())
 }
 
+/// Checks that every material channel a print uses can be heated into its
+/// material's temperature range, returning the target for each zone
+/// involved.
+///
+/// Prints are rejected before any heater is switched on if a channel has no
+/// zone mapping, a zone cannot reach a material's range, or two materials
+/// sharing a zone have no common temperature.
+pub fn validate_material_zones(
+    config: &PrinterConfig,
+    channels: &[(u8, &MaterialProfile)],
+) -> std::result::Result<std::collections::BTreeMap<u8, f32>, FirmwareError> {
+    config
+        .thermal
+        .plan_zone_temperatures(channels)
+        .map_err(|e| FirmwareError::PrintRejected(e.to_string()))
+}
+
 // Module-level Constants
 
 /// Firmware version.
@@ -726,6 +2388,13 @@ pub const PRESSURE_CONTROL_INTERVAL_MS: u64 = 10;
 /// Safety monitoring interval (ms).
 pub const SAFETY_MONITOR_INTERVAL_MS: u64 = 1;
 
+/// Print job heartbeat interval (ms).
+pub const PRINT_JOB_HEARTBEAT_MS: u64 = 100;
+
+/// Missed-heartbeat deadline for control loops, as a multiple of their
+/// interval. Loose enough to tolerate scheduling jitter on a loaded SBC.
+pub const HEARTBEAT_DEADLINE_FACTOR: u32 = 10;
+
 // Error Type Definitions
 
 /// Firmware-specific errors.
@@ -743,9 +2412,15 @@ pub enum FirmwareError {
     #[error("Invalid command: {0}")]
     InvalidCommand(String),
 
+    #[error("Motion error: {0}")]
+    Motion(String),
+
     #[error("Print execution error: {0}")]
     PrintExecution(String),
 
+    #[error("Print rejected: {0}")]
+    PrintRejected(String),
+
     #[error("File error: {0}")]
     File(String),
 
@@ -755,6 +2430,9 @@ pub enum FirmwareError {
     #[error("Timeout: {0}")]
     Timeout(String),
 
+    #[error(transparent)]
+    Transition(#[from] core::state_machine::TransitionError),
+
     #[error("I/O error: {0}")]
     Io(#[from] std::io::Error),
 
@@ -776,6 +2454,7 @@ pub use self::core::{
     executor::Executor,
     state_machine::StateMachine,
     scheduler::CommandScheduler,
+    dry_run::ExecutionMode,
 };
 
 pub use self::gcode::{
@@ -811,6 +2490,38 @@ mod tests {
         assert!(!FirmwareState::Idle.is_printing());
     }
 
+    #[test]
+    fn test_cancelled_objects_apply_from_current_layer() {
+        let mut status = PrintStatus::new(PathBuf::from("job.hg4d"), 100);
+        status.update_progress(12, 2.4);
+        assert!(status.cancel_object(3));
+        status.update_progress(20, 4.0);
+        assert!(!status.cancel_object(3));
+        assert!(status.cancel_object(5));
+
+        assert!(status.cancelled_in(11).is_empty());
+        assert_eq!(status.cancelled_in(12), vec![3]);
+        assert_eq!(status.cancelled_in(20), vec![3, 5]);
+    }
+
+    #[test]
+    fn test_clear_thermal_fault_keeps_other_zones() {
+        let mut state = SystemState::new();
+        for zone in [0, 1] {
+            let system = format!("thermal_zone_{}", zone);
+            state.errors.push(SystemError::new(ThermalFault::HeaterDecoupled.code(), "decoupled", vec![system]));
+        }
+        state.errors.push(SystemError::new(
+            protocol::ErrorCode::ThermistorOpen,
+            "open",
+            vec!["thermal_zone_1".to_string()],
+        ));
+
+        state.clear_thermal_fault(1);
+        assert_eq!(state.errors.len(), 1);
+        assert_eq!(state.errors[0].affected_systems, vec!["thermal_zone_0".to_string()]);
+    }
+
     #[test]
     fn test_calculate_valve_update_rate() {
         let rate = calculate_valve_update_rate(Duration::from_secs(1), 1000);
@@ -12,6 +12,8 @@
 //! - **Simulation Mode**: Runs without real hardware for testing (--simulate flag)
 //! - **Safe Mode**: Limited functionality after error recovery
 //! - **Calibration Mode**: Special mode for hardware calibration procedures
+//! - **Dry Run**: Runs a print file with heaters, pressure and extrusion
+//!   inhibited to check motion and valve behavior (--dry-run FILE)
 //!
 //! ## Hardware Requirements
 //!
@@ -39,8 +41,9 @@
 
 use std::path::PathBuf;
 use std::process::ExitCode;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use tokio::runtime::Runtime;
 use tokio::signal;
@@ -50,14 +53,31 @@ use tracing_subscriber::{EnvFilter, fmt, prelude::*};
 
 use clap::Parser;
 use anyhow::{Result, Context};
+use axum::body::Bytes;
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::{DefaultBodyLimit, Path as AxumPath, State};
+use axum::http::StatusCode;
+use axum::response::Response;
+use axum::routing::{get, post, put};
+use axum::{Json, Router};
 
 // Internal ecosystem imports
 use hypergcode_firmware::{
-    Firmware, FirmwareState, SystemState, FirmwareError,
+    Firmware, FirmwareState, SystemState, FirmwareError, PrintOptions,
     FIRMWARE_VERSION,
 };
-use config_types::PrinterConfig;
-use protocol::{ProtocolMessage, MessageBroker};
+use hypergcode_firmware::communication::{network, ClientSubscription, NetworkInterface};
+use hypergcode_firmware::config::ConfigWatcher;
+use hypergcode_firmware::core::PrintHistory;
+use hypergcode_firmware::hardware::{GpioProvider, LinuxBusProvider};
+use hypergcode_firmware::safety::EmergencyStopHandler;
+use hypergcode_firmware::utils::{HostMonitor, HostSampler, LogStore, TraceRecorder};
+use config_types::{HeartbeatConfig, InterlockAction, PrinterConfig};
+use gcode_types::{LayerPatch, PatchError};
+use protocol::{
+    decode_frame, DisconnectedEvent, HeartbeatMonitor, Hello, ProtocolMessage, MessageBroker, SubscribeRequest,
+    WireFormat, WireFrame,
+};
 
 // Command-Line Interface Definition
 
@@ -88,6 +108,10 @@ struct Cli {
     #[arg(long)]
     no_network: bool,
 
+    /// Host name announced over mDNS (as NAME.local)
+    #[arg(long, default_value = protocol::discovery::DEFAULT_HOSTNAME)]
+    hostname: String,
+
     /// Log level (error, warn, info, debug, trace)
     #[arg(short, long, default_value = "info")]
     log_level: String,
@@ -111,6 +135,18 @@ struct Cli {
     /// Print directory for .hg4d files
     #[arg(long, default_value = "/var/hypergcode/prints")]
     print_dir: PathBuf,
+
+    /// Run a .hg4d file without heat, pressure or extrusion, then exit
+    #[arg(long, value_name = "FILE")]
+    dry_run: Option<PathBuf>,
+
+    /// Record all protocol messages to a trace file for replay
+    #[arg(long, value_name = "FILE")]
+    record_trace: Option<PathBuf>,
+
+    /// Print history database directory
+    #[arg(long, value_name = "DIR", default_value = hypergcode_firmware::core::history::DEFAULT_HISTORY_PATH)]
+    history: PathBuf,
 }
 
 // Configuration Management Types
@@ -118,6 +154,7 @@ struct Cli {
 /// Complete runtime configuration.
 struct RuntimeConfig {
     printer_config: PrinterConfig,
+    config_path: PathBuf,
     websocket_port: u16,
     api_port: u16,
     network_enabled: bool,
@@ -138,6 +175,7 @@ impl RuntimeConfig {
 
         Ok(Self {
             printer_config,
+            config_path: cli.config.clone(),
             websocket_port: cli.websocket_port,
             api_port: cli.api_port,
             network_enabled: !cli.no_network,
@@ -168,9 +206,13 @@ impl RuntimeConfig {
 /// Application-level state managing firmware and services.
 struct ApplicationState {
     firmware: Arc<RwLock<Firmware>>,
+    /// Emergency stops bypass the firmware lock
+    emergency: EmergencyStopHandler,
     message_broker: Arc<MessageBroker>,
     shutdown_tx: broadcast::Sender<()>,
     config: RuntimeConfig,
+    /// WebSocket clients connected
+    clients: AtomicUsize,
 }
 
 impl ApplicationState {
@@ -182,14 +224,18 @@ impl ApplicationState {
         let (shutdown_tx, _) = broadcast::channel(1);
 
         // Initialize firmware
-        let firmware = Firmware::new(config.printer_config.clone()).await
+        let mut firmware = Firmware::new(config.printer_config.clone()).await
             .context("Failed to initialize firmware")?;
+        firmware.set_config_path(config.config_path.clone());
+        let emergency = firmware.emergency_handle();
 
         Ok(Self {
             firmware: Arc::new(RwLock::new(firmware)),
+            emergency,
             message_broker,
             shutdown_tx,
             config,
+            clients: AtomicUsize::new(0),
         })
     }
 
@@ -208,8 +254,10 @@ fn init_logging(log_level: &str, log_file: Option<PathBuf>) -> Result<()> {
     let filter = EnvFilter::try_new(log_level)
         .context("Invalid log level")?;
 
+    // Recent events are also kept in memory for retrieval over the protocol
     let subscriber = tracing_subscriber::registry()
-        .with(filter);
+        .with(filter)
+        .with(LogStore::global().layer());
 
     if let Some(file_path) = log_file {
         let file = std::fs::OpenOptions::new()
@@ -234,8 +282,23 @@ fn init_logging(log_level: &str, log_file: Option<PathBuf>) -> Result<()> {
 }
 
 /// Performs hardware self-test.
-async fn run_self_test(firmware: &mut Firmware) -> Result<()> {
-    todo!("Implementation needed: Run comprehensive hardware self-test")
+///
+/// Lists every valve driver board found and fails with the chain's faults
+/// if it does not match the configuration.
+async fn run_self_test(firmware: &mut Firmware, simulate: bool) -> Result<()> {
+    let report = if simulate {
+        firmware.assume_driver_chain().await
+    } else {
+        firmware.run_driver_self_test(&LinuxBusProvider::new()).await?
+    };
+    info!("Valve driver chain: {} boards found, {} expected", report.boards.len(), report.expected);
+    for board in &report.boards {
+        let id = board.id.map(|id| format!("0x{:04x}", id)).unwrap_or_else(|| "unreadable".to_string());
+        let latch = if board.latch_ok { "ok" } else { "FAILED" };
+        info!("  board {}: ID {}, latch {}", board.position, id, latch);
+    }
+    report.ensure_ok()?;
+    Ok(())
 }
 
 /// Performs hardware calibration.
@@ -258,7 +321,218 @@ async fn start_websocket_server(
     state: Arc<ApplicationState>,
     mut shutdown_rx: broadcast::Receiver<()>,
 ) -> Result<()> {
-    todo!("Implementation needed: Start WebSocket server")
+    let app = Router::new().route("/", get(websocket_upgrade)).with_state(state);
+    let addr = std::net::SocketAddr::from(([0, 0, 0, 0], port));
+
+    axum::Server::try_bind(&addr)
+        .with_context(|| format!("Failed to bind WebSocket server to {}", addr))?
+        .serve(app.into_make_service())
+        .with_graceful_shutdown(async move {
+            shutdown_rx.recv().await.ok();
+        })
+        .await
+        .context("WebSocket server failed")?;
+
+    debug!("WebSocket server stopped");
+    Ok(())
+}
+
+async fn websocket_upgrade(ws: WebSocketUpgrade, State(state): State<Arc<ApplicationState>>) -> Response {
+    ws.on_upgrade(move |socket| client_connection(socket, state))
+}
+
+/// Serves one client, then lets the firmware react to it leaving.
+async fn client_connection(mut socket: WebSocket, state: Arc<ApplicationState>) {
+    state.clients.fetch_add(1, Ordering::SeqCst);
+    let status_rx = state.firmware.read().await.subscribe_status();
+    let event = serve_client(&mut socket, &state, status_rx).await;
+    socket.close().await.ok();
+
+    let remaining = state.clients.fetch_sub(1, Ordering::SeqCst) - 1;
+    info!("WebSocket client gone: {}", event);
+    if let Err(e) = state.firmware.write().await.handle_client_disconnected(&event, remaining).await {
+        error!("Handling the client disconnect failed: {:#}", e);
+    }
+}
+
+/// Runs a client's connection until it ends: broadcasts out through the
+/// client's subscription, requests in. Requests wait for the firmware lock
+/// in their own task, so Pings are answered and the heartbeat kept here
+/// however long a request takes. Emergency stops skip the queue and the
+/// lock entirely.
+async fn serve_client(
+    socket: &mut WebSocket,
+    state: &ApplicationState,
+    status_rx: broadcast::Receiver<ProtocolMessage>,
+) -> DisconnectedEvent {
+    let (request_tx, mut request_rx) = mpsc::unbounded_channel();
+    let (reply_tx, replies) = mpsc::unbounded_channel();
+    let firmware = state.firmware.clone();
+    // Requests already queued still run after the client leaves
+    tokio::spawn(async move {
+        while let Some(request) = request_rx.recv().await {
+            match firmware.write().await.handle_request(request).await {
+                Ok(Some(reply)) => {
+                    if reply_tx.send(reply).is_err() {
+                        break;
+                    }
+                }
+                Ok(None) => {}
+                Err(e) => warn!("Client request failed: {:#}", e),
+            }
+        }
+    });
+    let (subscribe, subscribe_rx) = mpsc::channel(CLIENT_QUEUE_LEN);
+    let (broadcast_tx, broadcasts) = mpsc::channel(CLIENT_QUEUE_LEN);
+    tokio::spawn(ClientSubscription::all().forward(status_rx, subscribe_rx, broadcast_tx));
+
+    let link = ClientLink {
+        requests: request_tx,
+        replies,
+        subscribe,
+        broadcasts,
+    };
+    let heartbeat_config = state.config.printer_config.safety.heartbeat;
+    run_client(socket, link, heartbeat_config, || emergency_stop(state)).await
+}
+
+/// Messages a client's subscription holds before the socket takes them.
+const CLIENT_QUEUE_LEN: usize = 64;
+
+/// The socket side of one client, as [`run_client`] needs it.
+#[async_trait::async_trait]
+trait ClientSocket: Send {
+    async fn recv(&mut self) -> Option<Result<Message, String>>;
+    async fn send(&mut self, msg: Message) -> Result<(), String>;
+}
+
+#[async_trait::async_trait]
+impl ClientSocket for WebSocket {
+    async fn recv(&mut self) -> Option<Result<Message, String>> {
+        WebSocket::recv(self).await.map(|frame| frame.map_err(|e| e.to_string()))
+    }
+
+    async fn send(&mut self, msg: Message) -> Result<(), String> {
+        WebSocket::send(self, msg).await.map_err(|e| e.to_string())
+    }
+}
+
+/// The firmware side of one client.
+struct ClientLink {
+    /// Requests for the firmware, answered on `replies`
+    requests: mpsc::UnboundedSender<ProtocolMessage>,
+    replies: mpsc::UnboundedReceiver<ProtocolMessage>,
+    /// `Subscribe` requests for the client's subscription
+    subscribe: mpsc::Sender<SubscribeRequest>,
+    /// Broadcasts the subscription lets through, and its acks
+    broadcasts: mpsc::Receiver<ProtocolMessage>,
+}
+
+/// Moves messages between a client's socket and the firmware until either
+/// side closes or the heartbeat lapses.
+async fn run_client<F, Fut>(
+    socket: &mut impl ClientSocket,
+    mut link: ClientLink,
+    heartbeat_config: HeartbeatConfig,
+    emergency_stop: F,
+) -> DisconnectedEvent
+where
+    F: Fn() -> Fut,
+    Fut: std::future::Future<Output = ProtocolMessage>,
+{
+    let mut peer: Option<Hello> = None;
+    let mut heartbeat: Option<HeartbeatMonitor> = None;
+    let mut wire = WireFormat::default();
+    // Encoding switched to once the reply carrying our Hello is sent
+    let mut agreed_wire: Option<WireFormat> = None;
+    loop {
+        let deadline = heartbeat.as_ref().map(|h| tokio::time::Instant::from_std(h.next_deadline()));
+        let outgoing = tokio::select! {
+            frame = socket.recv() => {
+                let frame = match frame {
+                    None | Some(Ok(Message::Close(_))) => break DisconnectedEvent::closed(),
+                    Some(Err(e)) => break DisconnectedEvent::failed(e),
+                    Some(Ok(Message::Text(text))) => WireFrame::Text(text),
+                    Some(Ok(Message::Binary(data))) => WireFrame::Binary(data),
+                    Some(Ok(_)) => continue,
+                };
+                let msg = match decode_frame(&frame) {
+                    Ok(Some(msg)) => msg,
+                    Ok(None) => {
+                        debug!("Skipping a client message of unknown type");
+                        continue;
+                    }
+                    Err(e) => {
+                        warn!("Malformed client message: {}", e);
+                        continue;
+                    }
+                };
+                if let Some(monitor) = heartbeat.as_mut() {
+                    monitor.received(&msg, Instant::now());
+                }
+                match msg {
+                    ProtocolMessage::Ping(ping) => Some(ProtocolMessage::Pong(ping)),
+                    ProtocolMessage::Pong(_) => None,
+                    ProtocolMessage::EmergencyStop => Some(emergency_stop().await),
+                    // Answered by the subscription with a SubscriptionAck
+                    ProtocolMessage::Subscribe(request) => {
+                        if link.subscribe.send(request).await.is_err() {
+                            break DisconnectedEvent::failed("subscription stopped");
+                        }
+                        None
+                    }
+                    msg => {
+                        if let ProtocolMessage::Hello(hello) = &msg {
+                            peer = Some(hello.clone());
+                        }
+                        if link.requests.send(msg).is_err() {
+                            break DisconnectedEvent::failed("request task stopped");
+                        }
+                        None
+                    }
+                }
+            }
+            Some(reply) = link.replies.recv() => {
+                // Heartbeats and the encoding are agreed once both Hellos
+                // are known; the Hello itself still goes out as JSON
+                if let (ProtocolMessage::Hello(local), Some(peer)) = (&reply, &peer) {
+                    heartbeat = HeartbeatMonitor::negotiate(heartbeat_config, local, peer, Instant::now());
+                    agreed_wire = Some(WireFormat::negotiate(local, peer));
+                }
+                Some(reply)
+            }
+            msg = link.broadcasts.recv() => match msg {
+                Some(msg) => Some(msg),
+                None => break DisconnectedEvent::closed(),
+            },
+            _ = async {
+                match deadline {
+                    Some(at) => tokio::time::sleep_until(at).await,
+                    None => std::future::pending().await,
+                }
+            } => match heartbeat.as_mut().map(|monitor| monitor.poll(Instant::now())) {
+                Some(Ok(ping)) => ping,
+                Some(Err(event)) => break event,
+                None => None,
+            },
+        };
+
+        let Some(msg) = outgoing else { continue };
+        let frame = match wire.encode(&msg) {
+            Ok(WireFrame::Text(text)) => Message::Text(text),
+            Ok(WireFrame::Binary(data)) => Message::Binary(data),
+            Err(e) => {
+                warn!("Dropping an unencodable message: {}", e);
+                continue;
+            }
+        };
+        if let Err(e) = socket.send(frame).await {
+            break DisconnectedEvent::failed(e);
+        }
+        if let Some(agreed) = agreed_wire.take() {
+            wire = agreed;
+        }
+    }
 }
 
 /// Starts REST API server for configuration and file management.
@@ -267,7 +541,20 @@ async fn start_api_server(
     state: Arc<ApplicationState>,
     mut shutdown_rx: broadcast::Receiver<()>,
 ) -> Result<()> {
-    todo!("Implementation needed: Start REST API server")
+    let app = api_router(state);
+    let addr = std::net::SocketAddr::from(([0, 0, 0, 0], port));
+
+    axum::Server::try_bind(&addr)
+        .with_context(|| format!("Failed to bind REST API to {}", addr))?
+        .serve(app.into_make_service())
+        .with_graceful_shutdown(async move {
+            shutdown_rx.recv().await.ok();
+        })
+        .await
+        .context("REST API server failed")?;
+
+    debug!("REST API server stopped");
+    Ok(())
 }
 
 /// Starts background monitoring tasks.
@@ -350,14 +637,47 @@ async fn run_firmware(cli: Cli) -> Result<()> {
     // Create application state
     let state = Arc::new(ApplicationState::new(config).await?);
 
+    // Start recording before anything is broadcast
+    if let Some(path) = &cli.record_trace {
+        let recorder = Arc::new(TraceRecorder::create(path)?);
+        let broadcasts = {
+            let mut fw = state.firmware.write().await;
+            fw.set_trace_recorder(recorder.clone());
+            fw.status_sender().subscribe()
+        };
+        let trace_shutdown = state.shutdown_tx.subscribe();
+        tokio::spawn(async move {
+            if let Err(e) = recorder.run(broadcasts, trace_shutdown).await {
+                error!("Trace recorder error: {:#}", e);
+            }
+        });
+    }
+
+    // Finished jobs go to the history; printing works without it
+    match PrintHistory::open(&cli.history) {
+        Ok(history) => state.firmware.write().await.set_history(Arc::new(history)),
+        Err(e) => warn!("Print history disabled: {:#}", e),
+    }
+
     // Setup signal handling
     let signal_handler = tokio::spawn(handle_signals(state.clone()));
 
     // Perform self-test if requested
     if cli.self_test {
         info!("Running hardware self-test");
-        run_self_test(&mut state.firmware.write().await).await?;
+        run_self_test(&mut state.firmware.write().await, cli.simulate).await?;
         info!("Self-test passed");
+    } else if cli.simulate {
+        let report = state.firmware.write().await.assume_driver_chain().await;
+        info!("Simulation mode: valve driver chain of {} boards assumed", report.boards.len());
+    } else {
+        // Always check the driver chain; a mismatch blocks prints but
+        // leaves the interfaces up to report it
+        match state.firmware.write().await.run_driver_self_test(&LinuxBusProvider::new()).await {
+            Ok(report) if report.is_ok() => info!("Valve driver chain OK: {} boards", report.boards.len()),
+            Ok(_) => warn!("Prints disabled until the valve driver chain matches the configuration"),
+            Err(e) => error!("Valve driver self-test could not run: {:#}", e),
+        }
     }
 
     // Perform calibration if requested
@@ -368,12 +688,40 @@ async fn run_firmware(cli: Cli) -> Result<()> {
         return Ok(()); // Exit after calibration
     }
 
+    // A recovery journal means power failed mid-print. Homing would drive
+    // the plate down into the part, so leave Z alone and offer to resume.
+    let recovery = state.firmware.read().await.recovery_journal().await
+        .unwrap_or_else(|e| {
+            warn!("Ignoring unreadable recovery journal: {:#}", e);
+            None
+        });
+    if let Some(journal) = &recovery {
+        warn!(
+            "Print of {} was interrupted by power loss at layer {} (Z {:.2}mm)",
+            journal.file_path.display(),
+            journal.layer,
+            journal.z_position
+        );
+        warn!("Send ResumeFromJournal to resume or DiscardJournal to discard; skipping homing");
+    }
+
     // Home axes unless skipped
-    if !cli.no_home {
+    if !cli.no_home && recovery.is_none() {
         home_axes(&mut state.firmware.write().await).await?;
     }
 
+    // Dry run requested from the command line: execute and exit
+    if let Some(file) = &cli.dry_run {
+        let mut fw = state.firmware.write().await;
+        fw.start_print_with(file, PrintOptions::dry_run()).await
+            .context("Failed to start dry run")?;
+        fw.wait_for_completion().await?;
+        info!("Dry run of {} complete", file.display());
+        return Ok(());
+    }
+
     // Start network services if enabled
+    let mut _network_interface = None;
     if state.config.network_enabled {
         info!("Starting network services");
         
@@ -401,11 +749,189 @@ async fn run_firmware(cli: Cli) -> Result<()> {
             }
         });
 
+        let mut interface = NetworkInterface::new(network::announcement(
+            &state.config.printer_config,
+            Some(&cli.hostname),
+            state.config.websocket_port,
+            state.config.api_port,
+        ));
+        if let Err(e) = interface.start_discovery() {
+            warn!("mDNS discovery disabled: {:#}", e);
+        }
+
         info!("Network services started");
         info!("  WebSocket: ws://0.0.0.0:{}", state.config.websocket_port);
         info!("  REST API: http://0.0.0.0:{}", state.config.api_port);
+        if interface.is_discoverable() {
+            info!("  Discovery: {}", interface.announcement().websocket_url());
+        }
+        // Withdrawn when main returns
+        _network_interface = Some(interface);
+    }
+
+    // Watch printer configuration for live-safe changes
+    let (config_handle, status_tx) = {
+        let fw = state.firmware.read().await;
+        (fw.config_handle(), fw.status_sender())
+    };
+    let watcher = ConfigWatcher::new(&state.config.config_path, config_handle, status_tx);
+    let watcher_shutdown = state.shutdown_tx.subscribe();
+    tokio::spawn(async move {
+        if let Err(e) = watcher.run(watcher_shutdown).await {
+            error!("Configuration watcher error: {}", e);
+        }
+    });
+
+    // Shut down within the hold-up time when the UPS signals power loss
+    if let Some(handler) = state.firmware.read().await.power_loss_handler().await {
+        let power_shutdown = state.shutdown_tx.subscribe();
+        let pin = if state.config.simulation_mode {
+            None
+        } else {
+            let pin_number = state.config.printer_config.power_loss.as_ref().map(|p| p.signal_pin);
+            pin_number.and_then(|n| match LinuxBusProvider::new().input(n) {
+                Ok(pin) => Some(pin),
+                Err(e) => {
+                    error!("Power-loss signal unavailable: {:#}", e);
+                    None
+                }
+            })
+        };
+        if let Some(pin) = pin {
+            tokio::spawn(async move {
+                if let Err(e) = handler.watch(pin, power_shutdown).await {
+                    error!("Power-loss watcher error: {:#}", e);
+                }
+            });
+        }
+    }
+
+    // Pause, cancel or stop when a door, thermostat or smoke interlock trips
+    if let Some(monitor) = state.firmware.read().await.interlock_monitor().await {
+        if state.config.simulation_mode {
+            info!("Simulation mode: interlock inputs not monitored");
+        } else {
+            match monitor.open_pins(&LinuxBusProvider::new()) {
+                Ok(pins) => {
+                    let (trip_tx, mut trip_rx) = mpsc::channel(8);
+                    let interlock_shutdown = state.shutdown_tx.subscribe();
+                    tokio::spawn(async move {
+                        if let Err(e) = monitor.watch(pins, trip_tx, interlock_shutdown).await {
+                            error!("Interlock monitor error: {:#}", e);
+                        }
+                    });
+                    let interlock_firmware = state.firmware.clone();
+                    let emergency = state.emergency.clone();
+                    tokio::spawn(async move {
+                        while let Some(trip) = trip_rx.recv().await {
+                            let response = if trip.action == InterlockAction::EmergencyStop {
+                                emergency.trigger().await
+                            } else {
+                                interlock_firmware.write().await.handle_interlock(&trip).await
+                            };
+                            if let Err(e) = response {
+                                error!("Interlock '{}' response failed: {:#}", trip.name, e);
+                            }
+                        }
+                    });
+                }
+                Err(e) => error!("Interlock inputs unavailable: {:#}", e),
+            }
+        }
     }
 
+    // Pause the print when a channel's runout switch reads empty
+    if let Some(monitor) = state.firmware.read().await.runout_monitor().await {
+        if state.config.simulation_mode {
+            info!("Simulation mode: runout switches not monitored");
+        } else {
+            match monitor.open_pins(&LinuxBusProvider::new()) {
+                Ok(pins) => {
+                    let (change_tx, mut change_rx) = mpsc::channel(8);
+                    let runout_shutdown = state.shutdown_tx.subscribe();
+                    tokio::spawn(async move {
+                        if let Err(e) = monitor.watch(pins, change_tx, runout_shutdown).await {
+                            error!("Runout monitor error: {:#}", e);
+                        }
+                    });
+                    let runout_firmware = state.firmware.clone();
+                    tokio::spawn(async move {
+                        while let Some(change) = change_rx.recv().await {
+                            if let Err(e) = runout_firmware.write().await.handle_runout(change).await {
+                                error!("Channel {} runout response failed: {:#}", change.channel, e);
+                            }
+                        }
+                    });
+                }
+                Err(e) => error!("Runout switches unavailable: {:#}", e),
+            }
+        }
+    }
+
+    // Announce the interrupted print to connected clients
+    if let Some(journal) = &recovery {
+        let status_tx = state.firmware.read().await.status_sender();
+        status_tx.send(ProtocolMessage::RecoveryAvailable(journal.offer())).ok();
+    }
+
+    // Track spool exposure and finish drying cycles
+    let conditioning_firmware = state.firmware.clone();
+    let mut conditioning_shutdown = state.shutdown_tx.subscribe();
+    tokio::spawn(async move {
+        let period = Duration::from_secs(60);
+        let mut interval = tokio::time::interval(period);
+        interval.tick().await;
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {
+                    let mut fw = conditioning_firmware.write().await;
+                    if let Err(e) = fw.update_material_conditioning(period).await {
+                        error!("Material conditioning error: {:#}", e);
+                    }
+                }
+                _ = conditioning_shutdown.recv() => break,
+            }
+        }
+    });
+
+    // Sample the controller's own load, memory, temperature and disk space
+    let telemetry_config = state.config.printer_config.host_telemetry.clone();
+    let mut sampler = HostSampler::new(&state.config.print_directory, &telemetry_config);
+    let mut host_monitor = HostMonitor::new(&telemetry_config);
+    let telemetry_firmware = state.firmware.clone();
+    let mut telemetry_shutdown = state.shutdown_tx.subscribe();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_millis(telemetry_config.interval_ms as u64));
+        // Logged once rather than every sample
+        let mut failing = false;
+        loop {
+            tokio::select! {
+                _ = interval.tick() => match sampler.sample() {
+                    Ok(telemetry) => {
+                        failing = false;
+                        let warnings = host_monitor.check(&telemetry);
+                        telemetry_firmware.write().await.report_host_telemetry(telemetry, warnings).await;
+                    }
+                    Err(e) if !failing => {
+                        failing = true;
+                        warn!("Host telemetry unavailable: {:#}", e);
+                    }
+                    Err(_) => {}
+                },
+                _ = telemetry_shutdown.recv() => break,
+            }
+        }
+    });
+
+    // Supervise background task heartbeats; a stalled task forces safe state
+    let supervisor = state.firmware.read().await.supervisor();
+    let supervisor_shutdown = state.shutdown_tx.subscribe();
+    tokio::spawn(async move {
+        if let Err(e) = supervisor.run(supervisor_shutdown).await {
+            error!("Task supervisor error: {}", e);
+        }
+    });
+
     // Start background monitoring
     let monitor_shutdown = state.shutdown_tx.subscribe();
     let monitor_firmware = state.firmware.clone();
@@ -501,6 +1027,8 @@ async fn shutdown_firmware(state: &ApplicationState) -> Result<()> {
     if sys_state.firmware_state.is_printing() {
         warn!("Cancelling active print for shutdown");
         firmware.cancel_print().await?;
+        // The job closes its valves and cools down as it ends
+        firmware.wait_for_completion().await.ok();
     }
 
     // Cool down heaters
@@ -540,13 +1068,8 @@ async fn publish_status_updates(
 
                 // Create and publish status message
                 if let Some(print_status) = &state.print_status {
-                    let msg = protocol::create_status_update(
-                        format!("{:?}", state.firmware_state),
-                        print_status.current_layer,
-                        print_status.total_layers,
-                        print_status.z_position,
-                        print_status.elapsed_time.as_secs(),
-                        print_status.estimated_remaining.as_secs(),
+                    let msg = ProtocolMessage::StatusUpdate(
+                        print_status.status_update(format!("{:?}", state.firmware_state)),
                     );
 
                     broker.publish(msg).await.ok();
@@ -569,6 +1092,322 @@ async fn monitor_system_health(
     todo!("Implementation needed: Monitor temperatures, pressures, valve health")
 }
 
+// REST API
+
+/// Largest print file accepted by upload.
+const MAX_UPLOAD_BYTES: usize = 1 << 30;
+
+type ApiResult<T> = std::result::Result<T, (StatusCode, String)>;
+
+fn api_router(state: Arc<ApplicationState>) -> Router {
+    Router::new()
+        .route("/health", get(api_health))
+        .route("/files", get(api_list_files))
+        .route("/files/:name", put(api_upload_file).delete(api_delete_file))
+        .route("/files/:name/patch", post(api_patch_file))
+        .route("/config", get(api_get_config).put(api_put_config))
+        .route("/print/start", post(api_start_print))
+        .route("/print/pause", post(api_pause_print))
+        .route("/print/resume", post(api_resume_print))
+        .route("/print/cancel", post(api_cancel_print))
+        .route("/print/objects/:id/cancel", post(api_cancel_object))
+        .route("/emergency-stop", post(api_emergency_stop))
+        .route("/thermal/zones/:zone/clear-fault", post(api_clear_thermal_fault))
+        .layer(DefaultBodyLimit::max(MAX_UPLOAD_BYTES))
+        .with_state(state)
+}
+
+/// A print file in the print directory.
+#[derive(Debug, serde::Serialize)]
+struct PrintFileInfo {
+    name: String,
+    size_bytes: u64,
+    /// Seconds since the Unix epoch
+    modified: u64,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct StartPrintBody {
+    /// File name within the print directory
+    file: String,
+    #[serde(default)]
+    start_layer: Option<u32>,
+    #[serde(default)]
+    dry_run: bool,
+}
+
+#[derive(Debug, Default, serde::Deserialize)]
+struct PauseBody {
+    #[serde(default)]
+    reason: Option<String>,
+}
+
+/// Resolves a client-supplied file name inside the print directory.
+///
+/// Only bare `.hg4d` file names are accepted, so a request can never reach
+/// outside the directory.
+fn print_file_path(dir: &std::path::Path, name: &str) -> ApiResult<PathBuf> {
+    let bare = std::path::Path::new(name)
+        .file_name()
+        .is_some_and(|f| f == std::ffi::OsStr::new(name));
+    if !bare || name.starts_with('.') || !name.ends_with(".hg4d") {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("Invalid print file name '{}': expected NAME.hg4d", name),
+        ));
+    }
+    Ok(dir.join(name))
+}
+
+/// Lists the `.hg4d` files in a directory, newest first.
+fn list_print_files(dir: &std::path::Path) -> std::io::Result<Vec<PrintFileInfo>> {
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let metadata = entry.metadata()?;
+        if !metadata.is_file() || !name.ends_with(".hg4d") {
+            continue;
+        }
+        let modified = metadata
+            .modified()
+            .ok()
+            .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        files.push(PrintFileInfo { name, size_bytes: metadata.len(), modified });
+    }
+    files.sort_by(|a, b| b.modified.cmp(&a.modified).then_with(|| a.name.cmp(&b.name)));
+    Ok(files)
+}
+
+fn internal_error(e: impl std::fmt::Display) -> (StatusCode, String) {
+    (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+}
+
+/// GET /health
+async fn api_health(State(state): State<Arc<ApplicationState>>) -> Json<HealthStatus> {
+    let system = state.firmware.read().await.get_state().await;
+    Json(get_health_status(&system))
+}
+
+/// GET /files
+async fn api_list_files(
+    State(state): State<Arc<ApplicationState>>,
+) -> ApiResult<Json<Vec<PrintFileInfo>>> {
+    list_print_files(&state.config.print_directory)
+        .map(Json)
+        .map_err(internal_error)
+}
+
+/// PUT /files/:name - upload a print file, replacing any existing one.
+async fn api_upload_file(
+    State(state): State<Arc<ApplicationState>>,
+    AxumPath(name): AxumPath<String>,
+    body: Bytes,
+) -> ApiResult<StatusCode> {
+    let path = print_file_path(&state.config.print_directory, &name)?;
+    // Replaced atomically so a print never reads a partial upload
+    let size = body.len();
+    tokio::task::spawn_blocking(move || config_types::persist::write_atomic(&path, &body))
+        .await
+        .map_err(internal_error)?
+        .map_err(internal_error)?;
+    info!("Received print file {} ({} bytes)", name, size);
+    Ok(StatusCode::CREATED)
+}
+
+/// POST /files/:name/patch - apply a layer patch to a stored print file.
+///
+/// The patch must have been made against exactly the stored file, and the
+/// result must match the checksum of the file it was made from; otherwise
+/// the stored file is left untouched.
+async fn api_patch_file(
+    State(state): State<Arc<ApplicationState>>,
+    AxumPath(name): AxumPath<String>,
+    body: Bytes,
+) -> ApiResult<StatusCode> {
+    let path = print_file_path(&state.config.print_directory, &name)?;
+    let printing = state.firmware.read().await.get_state().await.print_status;
+    if printing.is_some_and(|p| p.file_path == path) {
+        return Err((StatusCode::CONFLICT, format!("{} is printing", name)));
+    }
+    let patch = LayerPatch::from_bytes(&body).map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+    let base = match tokio::fs::read(&path).await {
+        Ok(base) => base,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Err((StatusCode::NOT_FOUND, format!("No print file {}", name)));
+        }
+        Err(e) => return Err(internal_error(e)),
+    };
+
+    let patched = tokio::task::spawn_blocking(move || patch.apply(&base))
+        .await
+        .map_err(internal_error)?
+        .map_err(|e| match e {
+            PatchError::BaseMismatch { .. } => (StatusCode::PRECONDITION_FAILED, e.to_string()),
+            PatchError::InvalidPatch(_) => (StatusCode::BAD_REQUEST, e.to_string()),
+            PatchError::InvalidFile(_) | PatchError::TargetMismatch { .. } => {
+                (StatusCode::UNPROCESSABLE_ENTITY, e.to_string())
+            }
+        })?;
+
+    let size = patched.len();
+    tokio::task::spawn_blocking(move || config_types::persist::write_atomic(&path, &patched))
+        .await
+        .map_err(internal_error)?
+        .map_err(internal_error)?;
+    info!("Patched print file {} ({} byte patch, {} bytes)", name, body.len(), size);
+    Ok(StatusCode::OK)
+}
+
+/// DELETE /files/:name
+async fn api_delete_file(
+    State(state): State<Arc<ApplicationState>>,
+    AxumPath(name): AxumPath<String>,
+) -> ApiResult<StatusCode> {
+    let path = print_file_path(&state.config.print_directory, &name)?;
+    let printing = state.firmware.read().await.get_state().await.print_status;
+    if printing.is_some_and(|p| p.file_path == path) {
+        return Err((StatusCode::CONFLICT, format!("{} is printing", name)));
+    }
+    match tokio::fs::remove_file(&path).await {
+        Ok(()) => Ok(StatusCode::NO_CONTENT),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            Err((StatusCode::NOT_FOUND, format!("No print file {}", name)))
+        }
+        Err(e) => Err(internal_error(e)),
+    }
+}
+
+/// GET /config - the active printer configuration.
+async fn api_get_config(State(state): State<Arc<ApplicationState>>) -> Json<PrinterConfig> {
+    let config = state.firmware.read().await.config_handle();
+    let config = config.read().await.clone();
+    Json(config)
+}
+
+/// PUT /config - validate and save the printer configuration.
+///
+/// The file is replaced on disk; the configuration watcher applies the
+/// live-safe changes and reports those needing a restart.
+async fn api_put_config(
+    State(state): State<Arc<ApplicationState>>,
+    Json(config): Json<PrinterConfig>,
+) -> ApiResult<StatusCode> {
+    config
+        .validate()
+        .map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e.to_string()))?;
+
+    let path = state.config.config_path.clone();
+    // Replaced atomically; the previous file is kept as a backup
+    tokio::task::spawn_blocking(move || config.to_file(&path).map_err(internal_error))
+        .await
+        .map_err(internal_error)??;
+
+    info!("Printer configuration updated over REST API");
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// POST /print/start
+async fn api_start_print(
+    State(state): State<Arc<ApplicationState>>,
+    Json(body): Json<StartPrintBody>,
+) -> ApiResult<StatusCode> {
+    let path = print_file_path(&state.config.print_directory, &body.file)?;
+    if !path.is_file() {
+        return Err((StatusCode::NOT_FOUND, format!("No print file {}", body.file)));
+    }
+    let cmd = protocol::StartPrintCommand {
+        file_path: path.to_string_lossy().into_owned(),
+        start_layer: body.start_layer,
+        dry_run: body.dry_run,
+    };
+    send_command(&state, ProtocolMessage::StartPrint(cmd)).await
+}
+
+/// POST /print/pause
+async fn api_pause_print(
+    State(state): State<Arc<ApplicationState>>,
+    body: Option<Json<PauseBody>>,
+) -> ApiResult<StatusCode> {
+    let reason = body
+        .and_then(|Json(b)| b.reason)
+        .unwrap_or_else(|| "user".to_string());
+    let cmd = protocol::PausePrintCommand { reason };
+    send_command(&state, ProtocolMessage::PausePrint(cmd)).await
+}
+
+/// POST /print/resume
+async fn api_resume_print(State(state): State<Arc<ApplicationState>>) -> ApiResult<StatusCode> {
+    send_command(&state, ProtocolMessage::ResumePrint).await
+}
+
+/// POST /print/cancel
+async fn api_cancel_print(State(state): State<Arc<ApplicationState>>) -> ApiResult<StatusCode> {
+    send_command(&state, ProtocolMessage::CancelPrint).await
+}
+
+/// POST /print/objects/:id/cancel
+async fn api_cancel_object(
+    State(state): State<Arc<ApplicationState>>,
+    AxumPath(object_id): AxumPath<u32>,
+) -> ApiResult<StatusCode> {
+    let cmd = protocol::CancelObjectCommand { object_id };
+    send_command(&state, ProtocolMessage::CancelObject(cmd)).await
+}
+
+/// POST /emergency-stop
+async fn api_emergency_stop(State(state): State<Arc<ApplicationState>>) -> ApiResult<StatusCode> {
+    send_command(&state, ProtocolMessage::EmergencyStop).await
+}
+
+/// POST /thermal/zones/:zone/clear-fault
+async fn api_clear_thermal_fault(
+    State(state): State<Arc<ApplicationState>>,
+    AxumPath(zone): AxumPath<u8>,
+) -> ApiResult<StatusCode> {
+    let cmd = protocol::ClearThermalFaultCommand { zone };
+    send_command(&state, ProtocolMessage::ClearThermalFault(cmd)).await
+}
+
+/// Runs a protocol command through the same path as WebSocket clients.
+async fn send_command(state: &ApplicationState, request: ProtocolMessage) -> ApiResult<StatusCode> {
+    let reply = match request {
+        ProtocolMessage::EmergencyStop => Some(emergency_stop(state).await),
+        request => state
+            .firmware
+            .write()
+            .await
+            .handle_request(request)
+            .await
+            .map_err(internal_error)?,
+    };
+
+    match reply {
+        Some(ProtocolMessage::CommandResponse(response)) if response.success => {
+            Ok(StatusCode::NO_CONTENT)
+        }
+        Some(ProtocolMessage::CommandResponse(response)) => {
+            Err((StatusCode::CONFLICT, response.error.unwrap_or(response.message)))
+        }
+        other => Err(internal_error(format!(
+            "Unexpected firmware reply: {}",
+            other.map(|m| m.message_type()).unwrap_or("none")
+        ))),
+    }
+}
+
+/// Stops the printer through the emergency handle, never waiting for the
+/// firmware lock, and answers like any other command.
+async fn emergency_stop(state: &ApplicationState) -> ProtocolMessage {
+    let response = match state.emergency.trigger().await {
+        Ok(()) => protocol::CommandResponse::success("OK"),
+        Err(e) => protocol::CommandResponse::error(format!("{:#}", e)),
+    };
+    ProtocolMessage::CommandResponse(response)
+}
+
 // Health Check Endpoints
 
 /// Provides health status for monitoring systems.
@@ -610,6 +1449,143 @@ mod tests {
 
         assert!(cli.simulate);
         assert_eq!(cli.config, PathBuf::from("test.toml"));
+        assert!(cli.dry_run.is_none());
+        assert!(cli.record_trace.is_none());
+
+        let cli = Cli::parse_from(vec!["hg4d-firmware", "--dry-run", "part.hg4d"]);
+        assert_eq!(cli.dry_run, Some(PathBuf::from("part.hg4d")));
+    }
+
+    #[test]
+    fn test_print_file_names() {
+        let dir = std::env::temp_dir().join(format!("hg4d-api-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("part.hg4d"), b"data").unwrap();
+        std::fs::write(dir.join("notes.txt"), b"data").unwrap();
+
+        assert_eq!(print_file_path(&dir, "part.hg4d").unwrap(), dir.join("part.hg4d"));
+        for name in ["../part.hg4d", "sub/part.hg4d", ".hg4d", "part.gcode", ""] {
+            assert!(print_file_path(&dir, name).is_err(), "{}", name);
+        }
+
+        let files = list_print_files(&dir).unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].name, "part.hg4d");
+        assert_eq!(files[0].size_bytes, 4);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    struct FakeSocket {
+        incoming: mpsc::UnboundedReceiver<Message>,
+        sent: mpsc::UnboundedSender<Message>,
+    }
+
+    #[async_trait::async_trait]
+    impl ClientSocket for FakeSocket {
+        async fn recv(&mut self) -> Option<Result<Message, String>> {
+            self.incoming.recv().await.map(Ok)
+        }
+
+        async fn send(&mut self, msg: Message) -> Result<(), String> {
+            self.sent.send(msg).map_err(|e| e.to_string())
+        }
+    }
+
+    async fn next_message(sent: &mut mpsc::UnboundedReceiver<Message>) -> ProtocolMessage {
+        let frame = match sent.recv().await {
+            Some(Message::Text(text)) => WireFrame::Text(text),
+            Some(Message::Binary(data)) => WireFrame::Binary(data),
+            other => panic!("unexpected frame {:?}", other),
+        };
+        decode_frame(&frame).unwrap().unwrap()
+    }
+
+    /// A client served by [`run_client`] with a subscription but no
+    /// firmware; replies are sent by the test.
+    struct TestClient {
+        frames: mpsc::UnboundedSender<Message>,
+        sent: mpsc::UnboundedReceiver<Message>,
+        status: broadcast::Sender<ProtocolMessage>,
+        replies: mpsc::UnboundedSender<ProtocolMessage>,
+        requests: mpsc::UnboundedReceiver<ProtocolMessage>,
+        task: tokio::task::JoinHandle<DisconnectedEvent>,
+    }
+
+    fn test_client() -> TestClient {
+        let (frames, incoming) = mpsc::unbounded_channel();
+        let (sent_tx, sent) = mpsc::unbounded_channel();
+        let mut socket = FakeSocket { incoming, sent: sent_tx };
+
+        let (status, status_rx) = broadcast::channel(16);
+        let (request_tx, requests) = mpsc::unbounded_channel();
+        let (replies, reply_rx) = mpsc::unbounded_channel();
+        let (subscribe, subscribe_rx) = mpsc::channel(CLIENT_QUEUE_LEN);
+        let (broadcast_tx, broadcasts) = mpsc::channel(CLIENT_QUEUE_LEN);
+        tokio::spawn(ClientSubscription::all().forward(status_rx, subscribe_rx, broadcast_tx));
+        let link = ClientLink {
+            requests: request_tx,
+            replies: reply_rx,
+            subscribe,
+            broadcasts,
+        };
+        let task = tokio::spawn(async move {
+            let emergency_stop = || async { ProtocolMessage::CommandResponse(protocol::CommandResponse::success("OK")) };
+            run_client(&mut socket, link, HeartbeatConfig::default(), emergency_stop).await
+        });
+        TestClient { frames, sent, status, replies, requests, task }
+    }
+
+    fn json_frame(msg: &ProtocolMessage) -> Message {
+        Message::Text(serde_json::to_string(msg).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_client_subscription() {
+        let mut client = test_client();
+        let request = ProtocolMessage::Subscribe(SubscribeRequest {
+            topics: vec![protocol::TopicSubscription { topic: protocol::Topic::Status, rate_hz: None }],
+        });
+        client.frames.send(json_frame(&request)).unwrap();
+        match next_message(&mut client.sent).await {
+            ProtocolMessage::SubscriptionAck(ack) => assert_eq!(ack.topics.len(), 1),
+            other => panic!("unexpected {}", other.message_type()),
+        }
+
+        // Thermal updates are no longer subscribed
+        client.status.send(protocol::create_thermal_update(vec![(0, 200.0, 210.0)])).unwrap();
+        client.status.send(protocol::create_status_update("Printing", 3, 100, 0.6, 0, 0)).unwrap();
+        assert!(matches!(next_message(&mut client.sent).await, ProtocolMessage::StatusUpdate(_)));
+
+        drop(client.frames);
+        assert_eq!(client.task.await.unwrap(), DisconnectedEvent::closed());
+    }
+
+    #[tokio::test]
+    async fn test_message_pack_after_hello() {
+        let mut client = test_client();
+        let hello = Hello::new("test", "1.0.0", vec![protocol::Capability::MessagePack]);
+        client.frames.send(json_frame(&ProtocolMessage::Hello(hello.clone()))).unwrap();
+        assert!(matches!(client.requests.recv().await, Some(ProtocolMessage::Hello(_))));
+        client.replies.send(ProtocolMessage::Hello(hello)).unwrap();
+
+        // The Hello reply is JSON, everything after it MessagePack
+        assert!(matches!(client.sent.recv().await, Some(Message::Text(_))));
+        client.status.send(protocol::create_status_update("Printing", 3, 100, 0.6, 0, 0)).unwrap();
+        match client.sent.recv().await {
+            Some(Message::Binary(data)) => {
+                let msg = decode_frame(&WireFrame::Binary(data)).unwrap();
+                assert!(matches!(msg, Some(ProtocolMessage::StatusUpdate(_))));
+            }
+            other => panic!("unexpected frame {:?}", other),
+        }
+
+        // Binary frames from the client are decoded too
+        let ping = WireFormat { encoding: protocol::Encoding::MessagePack }
+            .encode(&ProtocolMessage::Ping(protocol::Heartbeat { seq: 7 }))
+            .unwrap();
+        let WireFrame::Binary(data) = ping else { panic!("expected a binary frame") };
+        client.frames.send(Message::Binary(data)).unwrap();
+        assert!(matches!(next_message(&mut client.sent).await, ProtocolMessage::Pong(_)));
     }
 
     #[test]
@@ -0,0 +1,122 @@
+//! Emergency stop.
+//!
+//! [`EmergencyStopHandler`] holds its own handles to the controllers, the
+//! job control channel and the job's tasks, so an emergency stop never
+//! waits for the firmware lock behind a command already running on it.
+//! Whatever can stop the printer (WebSocket and REST clients, interlocks)
+//! takes one with [`crate::Firmware::emergency_handle`] at start-up.
+//!
+//! The job's tasks are aborted before anything else: a running layer holds
+//! the valve controller until it ends, and an aborted task gives it up.
+
+use std::sync::{Arc, Mutex as StdMutex};
+
+use anyhow::Result;
+use tokio::sync::{watch, Mutex};
+use tokio::task::AbortHandle;
+use tracing::error;
+
+use protocol::JobResult;
+
+use crate::core::executor::JobControl;
+use crate::core::{Executor, LatchReset, RunoutPause};
+use crate::safety::watchdog::HeartbeatRelease;
+use crate::{FirmwareError, FirmwareState};
+
+/// Tasks acting for the current job: the print itself and a runout
+/// re-prime.
+#[derive(Clone, Default)]
+pub struct JobTasks(Arc<StdMutex<Vec<(AbortHandle, Option<HeartbeatRelease>)>>>);
+
+impl JobTasks {
+    /// Adds a task, forgetting those already finished.
+    pub fn track(&self, task: AbortHandle) {
+        self.push(task, None);
+    }
+
+    /// Adds a task registered with the supervisor; aborting it on purpose
+    /// finishes its heartbeat first, so the abort is not reported as a
+    /// task failure.
+    pub fn track_supervised(&self, task: AbortHandle, heartbeat: HeartbeatRelease) {
+        self.push(task, Some(heartbeat));
+    }
+
+    fn push(&self, task: AbortHandle, heartbeat: Option<HeartbeatRelease>) {
+        let mut tasks = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        tasks.retain(|(t, _)| !t.is_finished());
+        tasks.push((task, heartbeat));
+    }
+
+    pub fn abort_all(&self) {
+        for (task, heartbeat) in self.0.lock().unwrap_or_else(|e| e.into_inner()).drain(..) {
+            if let Some(heartbeat) = heartbeat {
+                heartbeat.release();
+            }
+            task.abort();
+        }
+    }
+}
+
+/// Stops the printer without going through the firmware.
+#[derive(Clone)]
+pub struct EmergencyStopHandler {
+    executor: Executor,
+    job_control: Arc<watch::Sender<JobControl>>,
+    job_tasks: JobTasks,
+    latch_reset: LatchReset,
+    runout_pause: Arc<Mutex<Option<RunoutPause>>>,
+}
+
+impl EmergencyStopHandler {
+    pub fn new(
+        executor: Executor,
+        job_control: Arc<watch::Sender<JobControl>>,
+        job_tasks: JobTasks,
+        latch_reset: LatchReset,
+        runout_pause: Arc<Mutex<Option<RunoutPause>>>,
+    ) -> Self {
+        Self {
+            executor,
+            job_control,
+            job_tasks,
+            latch_reset,
+            runout_pause,
+        }
+    }
+
+    /// Aborts the running job and makes every subsystem safe; a failure
+    /// of one does not stop the others from being tried.
+    pub async fn trigger(&self) -> Result<()> {
+        error!("Emergency stop");
+        self.job_tasks.abort_all();
+        self.job_control.send_replace(JobControl::Cancel);
+        self.runout_pause.lock().await.take();
+
+        let executor = &self.executor;
+        let mut failures = Vec::new();
+        if let Err(e) = executor.valves.lock().await.emergency_close_all().await {
+            failures.push(format!("valves: {:#}", e));
+        }
+        self.latch_reset.trigger();
+        if let Err(e) = executor.heaters.lock().await.emergency_off().await {
+            failures.push(format!("heaters: {:#}", e));
+        }
+        if let Err(e) = executor.pressure.lock().await.emergency_vent().await {
+            failures.push(format!("pressure: {:#}", e));
+        }
+        if let Err(e) = executor.z_axis.lock().await.emergency_stop().await {
+            failures.push(format!("Z axis: {:#}", e));
+        }
+        // The aborted job cannot put the real controllers back or file its
+        // record itself
+        executor.end_dry_run().await;
+        executor.finish_job(JobResult::EmergencyStopped).await;
+        executor.set_state(FirmwareState::EmergencyStopped, "emergency stop").await?;
+
+        if failures.is_empty() {
+            Ok(())
+        } else {
+            Err(FirmwareError::HardwareOperation(format!("Emergency stop incomplete: {}", failures.join("; "))).into())
+        }
+    }
+}
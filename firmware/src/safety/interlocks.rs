@@ -0,0 +1,300 @@
+//! Configurable safety interlocks.
+//!
+//! Builders wire enclosure door switches, chamber over-temperature
+//! thermostats and smoke detectors to GPIO inputs and declare them in
+//! `SafetyLimits::interlocks`. The [`InterlockMonitor`] polls every input;
+//! one that stays tripped for its debounce time records an error and hands
+//! an [`InterlockTrip`] to the firmware, which pauses, cancels or
+//! emergency-stops as configured (see `Firmware::handle_interlock`). Only
+//! an emergency stop interlock moves the firmware to `Error` itself; a
+//! pause or cancel is left to the response, so the print it acts on is
+//! still running when the firmware gets the trip.
+//!
+//! An interlock also counts as tripped until it has read clear for its
+//! debounce time again. [`InterlockStatus`] shares the tripped set with the
+//! firmware, which refuses to start or resume a print while any is tripped.
+
+use std::collections::BTreeSet;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use tokio::sync::{broadcast, mpsc};
+use tokio::time::Instant;
+use tracing::{error, info};
+
+use config_types::{InterlockAction, InterlockConfig, SafetyLimits};
+use protocol::{ErrorCode, ProtocolMessage};
+
+use crate::hardware::bus::{GpioProvider, InputPin};
+use crate::core::StateMachine;
+use crate::{ErrorSeverity, FirmwareState, SystemError, SystemState};
+
+/// Error code reported when an interlock trips.
+pub const INTERLOCK_CODE: ErrorCode = ErrorCode::InterlockTripped;
+
+/// Interval between input reads.
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+/// A tripped interlock and the response it calls for.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InterlockTrip {
+    pub name: String,
+    pub action: InterlockAction,
+    /// Firmware state when the interlock tripped
+    pub state: FirmwareState,
+}
+
+/// Names of the interlocks currently tripped, shared between the monitor
+/// and the firmware.
+#[derive(Debug, Clone, Default)]
+pub struct InterlockStatus(Arc<RwLock<BTreeSet<String>>>);
+
+impl InterlockStatus {
+    pub fn tripped(&self) -> Vec<String> {
+        self.0.read().unwrap().iter().cloned().collect()
+    }
+
+    pub fn is_clear(&self) -> bool {
+        self.0.read().unwrap().is_empty()
+    }
+
+    fn set(&self, name: &str, tripped: bool) {
+        let mut set = self.0.write().unwrap();
+        if tripped {
+            set.insert(name.to_string());
+        } else {
+            set.remove(name);
+        }
+    }
+}
+
+/// Debounced state of one input.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct Debounce {
+    /// Since when the raw input has disagreed with `tripped`
+    changing_since: Option<Instant>,
+    tripped: bool,
+}
+
+impl Debounce {
+    /// Feeds one reading; returns the new state when it changes.
+    pub(crate) fn update(&mut self, active: bool, now: Instant, debounce: Duration) -> Option<bool> {
+        if active == self.tripped {
+            self.changing_since = None;
+            return None;
+        }
+        let since = *self.changing_since.get_or_insert(now);
+        if now.duration_since(since) < debounce {
+            return None;
+        }
+        self.tripped = active;
+        self.changing_since = None;
+        Some(active)
+    }
+}
+
+/// Polls the configured interlock inputs.
+pub struct InterlockMonitor {
+    interlocks: Vec<InterlockConfig>,
+    status: InterlockStatus,
+    state: Arc<tokio::sync::RwLock<SystemState>>,
+    state_machine: Arc<StateMachine>,
+    status_tx: broadcast::Sender<ProtocolMessage>,
+}
+
+impl InterlockMonitor {
+    /// Returns `None` if no interlocks are configured.
+    pub fn new(
+        limits: &SafetyLimits,
+        status: InterlockStatus,
+        state: Arc<tokio::sync::RwLock<SystemState>>,
+        state_machine: Arc<StateMachine>,
+        status_tx: broadcast::Sender<ProtocolMessage>,
+    ) -> Option<Self> {
+        if limits.interlocks.is_empty() {
+            return None;
+        }
+        Some(Self {
+            interlocks: limits.interlocks.clone(),
+            status,
+            state,
+            state_machine,
+            status_tx,
+        })
+    }
+
+    /// Opens every input, in configuration order.
+    pub fn open_pins(&self, gpio: &dyn GpioProvider) -> Result<Vec<Box<dyn InputPin>>> {
+        self.interlocks
+            .iter()
+            .map(|i| {
+                gpio.input(i.pin)
+                    .with_context(|| format!("Interlock '{}' on GPIO {} unavailable", i.name, i.pin))
+            })
+            .collect()
+    }
+
+    /// Polls `pins` (from [`open_pins`](Self::open_pins)) until shutdown,
+    /// sending a trip to `trips` each time an interlock trips.
+    pub async fn watch(
+        &self,
+        pins: Vec<Box<dyn InputPin>>,
+        trips: mpsc::Sender<InterlockTrip>,
+        mut shutdown: broadcast::Receiver<()>,
+    ) -> Result<()> {
+        let mut inputs: Vec<Debounce> = vec![Debounce::default(); self.interlocks.len()];
+        let mut interval = tokio::time::interval(POLL_INTERVAL);
+        for interlock in &self.interlocks {
+            info!("Watching interlock '{}' on GPIO {}", interlock.name, interlock.pin);
+        }
+
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {
+                    // Read every input before acting, so no pin is held across an await
+                    let now = Instant::now();
+                    let mut changes = Vec::new();
+                    for (i, (interlock, pin)) in self.interlocks.iter().zip(&pins).enumerate() {
+                        let active = pin.is_high()? != interlock.active_low;
+                        let debounce = Duration::from_millis(interlock.debounce_ms as u64);
+                        if let Some(tripped) = inputs[i].update(active, now, debounce) {
+                            changes.push((i, tripped));
+                        }
+                    }
+
+                    for (i, tripped) in changes {
+                        let interlock = &self.interlocks[i];
+                        if !tripped {
+                            self.status.set(&interlock.name, false);
+                            info!("Interlock '{}' cleared", interlock.name);
+                            continue;
+                        }
+                        let state = self.trip(interlock).await;
+                        let trip = InterlockTrip { name: interlock.name.clone(), action: interlock.action, state };
+                        if trips.send(trip).await.is_err() {
+                            return Ok(());
+                        }
+                    }
+                }
+                _ = shutdown.recv() => return Ok(()),
+            }
+        }
+    }
+
+    /// Records the trip and returns the firmware state it interrupted.
+    async fn trip(&self, interlock: &InterlockConfig) -> FirmwareState {
+        self.status.set(&interlock.name, true);
+        let (severity, response) = match interlock.action {
+            InterlockAction::Pause => (ErrorSeverity::Error, "pausing the print"),
+            InterlockAction::Abort => (ErrorSeverity::Critical, "cancelling the print"),
+            InterlockAction::EmergencyStop => (ErrorSeverity::Critical, "emergency stop"),
+        };
+        let message = format!("Interlock '{}' tripped; {}", interlock.name, response);
+        error!("{}", message);
+
+        let error = SystemError {
+            severity,
+            recovery_action: Some(format!("Clear the '{}' condition before resuming", interlock.name)),
+            ..SystemError::new(INTERLOCK_CODE, message, vec![interlock.name.clone()])
+        };
+        let event = error.to_event();
+        let previous = {
+            let mut state = self.state.write().await;
+            let previous = state.firmware_state;
+            match interlock.action {
+                // The print must still be Printing for the firmware to pause or cancel it
+                InterlockAction::Pause | InterlockAction::Abort => state.errors.push(error),
+                InterlockAction::EmergencyStop => state.add_error(error, &self.state_machine),
+            }
+            previous
+        };
+
+        // No subscribers is not an error
+        self.status_tx.send(ProtocolMessage::ErrorEvent(event)).ok();
+        previous
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_debounce() {
+        let debounce = Duration::from_millis(50);
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let mut input = Debounce::default();
+
+        // A 30ms glitch is ignored
+        assert_eq!(input.update(true, at(0), debounce), None);
+        assert_eq!(input.update(false, at(30), debounce), None);
+        assert_eq!(input.update(true, at(40), debounce), None);
+        assert_eq!(input.update(true, at(89), debounce), None);
+        assert_eq!(input.update(true, at(90), debounce), Some(true));
+        assert_eq!(input.update(true, at(500), debounce), None);
+
+        // Clearing is debounced too
+        assert_eq!(input.update(false, at(510), debounce), None);
+        assert_eq!(input.update(false, at(560), debounce), Some(false));
+
+        let status = InterlockStatus::default();
+        status.set("door", true);
+        assert_eq!(status.tripped(), vec!["door".to_string()]);
+        status.set("door", false);
+        assert!(status.is_clear());
+    }
+
+    struct Tripped;
+
+    impl InputPin for Tripped {
+        fn is_high(&self) -> Result<bool> {
+            Ok(true)
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_trip_leaves_the_print_to_the_response() {
+        let interlock = |name: &str, action| InterlockConfig {
+            name: name.to_string(),
+            pin: 5,
+            active_low: false,
+            action,
+            debounce_ms: 10,
+        };
+        let mut printing = SystemState::new();
+        printing.firmware_state = FirmwareState::Printing;
+        let state = Arc::new(tokio::sync::RwLock::new(printing));
+        let (status_tx, _) = broadcast::channel(8);
+        let monitor = InterlockMonitor {
+            interlocks: vec![interlock("door", InterlockAction::Pause), interlock("smoke", InterlockAction::EmergencyStop)],
+            status: InterlockStatus::default(),
+            state: state.clone(),
+            state_machine: Arc::new(StateMachine::detached()),
+            status_tx,
+        };
+
+        // The pause is recorded without leaving Printing, so the firmware can pause
+        let door = monitor.trip(&monitor.interlocks[0]).await;
+        assert_eq!(door, FirmwareState::Printing);
+        assert_eq!(state.read().await.firmware_state, FirmwareState::Printing);
+        assert_eq!(state.read().await.errors.len(), 1);
+        assert_eq!(monitor.status.tripped(), vec!["door".to_string()]);
+
+        let (trip_tx, mut trip_rx) = mpsc::channel(4);
+        let (shutdown_tx, shutdown_rx) = broadcast::channel(1);
+        let watch = monitor.watch(vec![Box::new(Tripped), Box::new(Tripped)], trip_tx, shutdown_rx);
+        let trips = async {
+            let door = trip_rx.recv().await.unwrap();
+            let smoke = trip_rx.recv().await.unwrap();
+            shutdown_tx.send(()).unwrap();
+            (door, smoke)
+        };
+        let (watched, (door, smoke)) = tokio::join!(watch, trips);
+        watched.unwrap();
+        assert_eq!((door.action, door.state), (InterlockAction::Pause, FirmwareState::Printing));
+        assert_eq!((smoke.action, smoke.state), (InterlockAction::EmergencyStop, FirmwareState::Printing));
+        assert_eq!(state.read().await.firmware_state, FirmwareState::Error);
+    }
+}
@@ -7,12 +7,24 @@
 //! - **monitors**: Continuous safety monitoring
 //! - **emergency**: Emergency stop handling
 //! - **limits**: Safety limit enforcement
+//! - **watchdog**: Heartbeat supervision of background tasks
+//! - **power_loss**: Shutdown on supply failure and the recovery journal
+//! - **stuck_valve**: Flow with every valve closed, localized to suspect valves
+//! - **interlocks**: Door, thermostat and smoke inputs declared in the configuration
 
 pub mod monitors;
 pub mod emergency;
 pub mod limits;
+pub mod watchdog;
+pub mod power_loss;
+pub mod stuck_valve;
+pub mod interlocks;
 
 pub use monitors::SafetyMonitor;
 pub use emergency::EmergencyStopHandler;
 pub use limits::LimitEnforcer;
+pub use watchdog::{Heartbeat, TaskSupervisor};
+pub use power_loss::{PowerLossHandler, PowerLossTargets, RecoveryJournal};
+pub use stuck_valve::{StuckValveDetector, StuckValveFault, StuckValveResponse};
+pub use interlocks::{InterlockMonitor, InterlockStatus, InterlockTrip};
 
@@ -0,0 +1,590 @@
+//! Power-loss shutdown and recovery journal.
+//!
+//! A UPS or undervoltage detector drives a GPIO when supply power fails.
+//! Once the signal has persisted for the debounce time, the
+//! [`PowerLossHandler`] runs the shutdown sequence against the configured
+//! hold-up deadline:
+//!
+//! 1. Snapshot the print state in memory and abort the print
+//! 2. Close all valves, through the output enable line if the valve
+//!    controller is busy
+//! 3. Cut heater PWM
+//! 4. Vent pressure
+//! 5. Write the [`RecoveryJournal`]
+//! 6. Lift Z away from the part, only if the move fits in the time left
+//!
+//! The fast hardware steps run before the journal write so material stops
+//! flowing even if the storage write cannot finish. Every step is bounded by
+//! the deadline and attempted even if an earlier one failed.
+//!
+//! At the next boot a journal on disk means the last print was interrupted;
+//! the firmware offers to resume it from the journaled layer or discard it.
+
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use tokio::sync::{broadcast, watch, Mutex, RwLock};
+use tokio::time::Instant;
+use tracing::{error, info, warn};
+
+use config_types::{PowerLossConfig, PrinterConfig};
+use protocol::{ErrorCode, ProtocolMessage, RecoveryOffer};
+
+use crate::core::executor::JobControl;
+use crate::core::{LatchReset, StateMachine};
+use crate::hardware::bus::InputPin;
+use crate::hardware::ValveCutoff;
+use crate::safety::emergency::JobTasks;
+use crate::{
+    FirmwareState, HeaterController, PressureController, SystemError, SystemState, ValveController,
+    ZAxisController,
+};
+
+/// Error code reported when power loss is detected.
+pub const POWER_LOSS_CODE: ErrorCode = ErrorCode::PowerLoss;
+
+/// Interval between signal pin reads.
+const POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+
+/// Print state needed to resume after power returns.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecoveryJournal {
+    /// File being printed
+    pub file_path: PathBuf,
+    /// Layer that was in progress; resuming reprints it
+    pub layer: u32,
+    /// Z position when power failed (mm)
+    pub z_position: f32,
+    /// Zone temperature targets (zone_id -> °C)
+    pub zone_targets: BTreeMap<u8, f32>,
+    /// Channel pressure targets (channel_id -> PSI)
+    pub pressure_targets: BTreeMap<u8, f32>,
+    /// Cancelled objects (object id -> first layer suppressed)
+    #[serde(default)]
+    pub cancelled_objects: BTreeMap<u32, u32>,
+    /// Unix time the journal was written (ms)
+    pub written_at_ms: u64,
+}
+
+impl RecoveryJournal {
+    /// Captures the running or paused print, if any.
+    pub fn capture(state: &SystemState) -> Option<Self> {
+        if !matches!(state.firmware_state, FirmwareState::Printing | FirmwareState::Paused) {
+            return None;
+        }
+        let status = state.print_status.as_ref()?;
+
+        Some(Self {
+            file_path: status.file_path.clone(),
+            layer: status.current_layer,
+            z_position: status.z_position,
+            zone_targets: state.thermal.zones.iter().map(|(&id, &(_, t))| (id, t)).collect(),
+            pressure_targets: state.pressure.channels.iter().map(|(&id, &(_, t))| (id, t)).collect(),
+            cancelled_objects: status.cancelled_objects.clone(),
+            written_at_ms: unix_ms(),
+        })
+    }
+
+    /// Writes the journal atomically: a temporary file is synced to disk and
+    /// then renamed over the journal, so a half-written journal never exists.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create {}", dir.display()))?;
+        }
+        let json = serde_json::to_vec(self).context("Failed to serialize recovery journal")?;
+        config_types::persist::write_atomic(path, &json)?;
+        Ok(())
+    }
+
+    /// Reads the journal, returning `None` if there is none.
+    pub fn load(path: &Path) -> Result<Option<Self>> {
+        let json = match std::fs::read(path) {
+            Ok(json) => json,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        };
+        let journal = serde_json::from_slice(&json)
+            .with_context(|| format!("Corrupt recovery journal {}", path.display()))?;
+        Ok(Some(journal))
+    }
+
+    /// Removes the journal once it has been resumed or declined.
+    pub fn discard(path: &Path) -> Result<()> {
+        match std::fs::remove_file(path) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e).with_context(|| format!("Failed to remove {}", path.display())),
+        }
+    }
+
+    /// Offer presented to clients at boot.
+    pub fn offer(&self) -> RecoveryOffer {
+        RecoveryOffer {
+            file_path: self.file_path.to_string_lossy().into_owned(),
+            layer: self.layer,
+            z_position: self.z_position,
+            written_at_ms: self.written_at_ms,
+        }
+    }
+}
+
+/// Subsystems driven during the shutdown sequence.
+#[derive(Clone)]
+pub struct PowerLossTargets {
+    pub valves: Arc<Mutex<Box<dyn ValveController>>>,
+    pub heaters: Arc<Mutex<Box<dyn HeaterController>>>,
+    pub pressure: Arc<Mutex<Box<dyn PressureController>>>,
+    pub z_axis: Arc<Mutex<Box<dyn ZAxisController>>>,
+    pub state: Arc<RwLock<SystemState>>,
+    pub state_machine: Arc<StateMachine>,
+    pub latch_reset: LatchReset,
+    pub valve_cutoff: ValveCutoff,
+    /// The print is cancelled and its tasks aborted before anything else
+    pub job_control: Arc<watch::Sender<JobControl>>,
+    pub job_tasks: JobTasks,
+}
+
+/// Outcome of one shutdown sequence.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ShutdownReport {
+    pub valves_closed: bool,
+    pub heaters_off: bool,
+    pub pressure_vented: bool,
+    /// A journal was written (false if nothing was printing)
+    pub journaled: bool,
+    pub parked: bool,
+    pub elapsed: Duration,
+    /// Every attempted step finished before the deadline
+    pub deadline_met: bool,
+}
+
+/// Watches the power-loss signal and runs the shutdown sequence.
+pub struct PowerLossHandler {
+    config: PowerLossConfig,
+    max_z: f32,
+    z_speed: f32,
+    targets: PowerLossTargets,
+    status_tx: broadcast::Sender<ProtocolMessage>,
+}
+
+impl PowerLossHandler {
+    /// Returns `None` if the printer has no power-loss signal configured.
+    pub fn new(
+        config: &PrinterConfig,
+        targets: PowerLossTargets,
+        status_tx: broadcast::Sender<ProtocolMessage>,
+    ) -> Option<Self> {
+        Some(Self {
+            config: config.power_loss.clone()?,
+            max_z: config.build_volume.z,
+            z_speed: config.motion.z_axis.max_speed,
+            targets,
+            status_tx,
+        })
+    }
+
+    /// Polls the signal pin until shutdown, running the shutdown sequence
+    /// once the signal has been asserted for the debounce time.
+    pub async fn watch(&self, pin: Box<dyn InputPin>, mut shutdown: broadcast::Receiver<()>) -> Result<()> {
+        let debounce = Duration::from_millis(self.config.debounce_ms as u64);
+        let mut interval = tokio::time::interval(POLL_INTERVAL);
+        let mut asserted_since: Option<Instant> = None;
+        info!("Watching power-loss signal on GPIO {}", self.config.signal_pin);
+
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {
+                    if pin.is_high()? == self.config.active_low {
+                        asserted_since = None;
+                        continue;
+                    }
+                    let since = *asserted_since.get_or_insert_with(Instant::now);
+                    if since.elapsed() >= debounce {
+                        self.execute().await;
+                        return Ok(());
+                    }
+                }
+                _ = shutdown.recv() => return Ok(()),
+            }
+        }
+    }
+
+    /// Runs the shutdown sequence within the configured deadline.
+    pub async fn execute(&self) -> ShutdownReport {
+        let start = Instant::now();
+        let deadline = start + Duration::from_millis(self.config.deadline_ms as u64);
+        error!("Power loss detected; {} ms to shut down", self.config.deadline_ms);
+
+        let journal = {
+            let mut state = self.targets.state.write().await;
+            let journal = RecoveryJournal::capture(&state);
+            let machine = &self.targets.state_machine;
+            if machine.transition(&mut state, FirmwareState::ShuttingDown, "power loss").is_err() {
+                // Homing and heating only leave through a safety stop
+                machine.transition(&mut state, FirmwareState::EmergencyStopped, "power loss").ok();
+                machine.transition(&mut state, FirmwareState::ShuttingDown, "power loss").ok();
+            }
+            journal
+        };
+        // A running layer holds the valve controller until it ends and
+        // would latch its next frame after the valves were closed
+        self.targets.job_tasks.abort_all();
+        self.targets.job_control.send_replace(JobControl::Cancel);
+
+        let mut report = ShutdownReport {
+            valves_closed: self.close_valves(deadline).await,
+            heaters_off: step(deadline, "turn off heaters", async {
+                self.targets.heaters.lock().await.emergency_off().await
+            })
+            .await,
+            pressure_vented: step(deadline, "vent pressure", async {
+                self.targets.pressure.lock().await.emergency_vent().await
+            })
+            .await,
+            ..ShutdownReport::default()
+        };
+
+        if let Some(journal) = &journal {
+            let path = self.config.journal_path.clone();
+            let journal = journal.clone();
+            report.journaled = step(deadline, "write recovery journal", async move {
+                tokio::task::spawn_blocking(move || journal.save(&path)).await?
+            })
+            .await;
+            report.parked = self.park(deadline).await;
+        }
+
+        report.elapsed = start.elapsed();
+        report.deadline_met = Instant::now() <= deadline;
+        self.report(&report).await;
+        report
+    }
+
+    /// Closes the valves through the controller if it is free, or else by
+    /// cutting the output enable line. Without a line, waits for the
+    /// controller until the deadline.
+    async fn close_valves(&self, deadline: Instant) -> bool {
+        let cutoff = &self.targets.valve_cutoff;
+        let closed = match self.targets.valves.try_lock() {
+            Ok(mut valves) => step(deadline, "close valves", valves.emergency_close_all()).await,
+            Err(_) if cutoff.is_fitted() => false,
+            Err(_) => {
+                step(deadline, "close valves", async {
+                    self.targets.valves.lock().await.emergency_close_all().await
+                })
+                .await
+            }
+        };
+        self.targets.latch_reset.trigger();
+        if closed || !cutoff.is_fitted() {
+            return closed;
+        }
+        match cutoff.cut() {
+            Ok(()) => true,
+            Err(e) => {
+                error!("Power loss: failed to cut the valve outputs: {:#}", e);
+                false
+            }
+        }
+    }
+
+    /// Lifts Z by the configured distance if the move fits before the
+    /// deadline. A move cut short by the supply collapsing is harmless, but
+    /// one that never starts leaves the valve plate resting on the part.
+    async fn park(&self, deadline: Instant) -> bool {
+        if self.config.park_lift_mm <= 0.0 || self.z_speed <= 0.0 {
+            return false;
+        }
+        let mut z_axis = self.targets.z_axis.lock().await;
+        let Ok(position) = z_axis.get_position().await else {
+            return false;
+        };
+        let target = (position + self.config.park_lift_mm).min(self.max_z);
+        if target <= position {
+            return false;
+        }
+
+        let needed = Duration::from_secs_f32((target - position) / self.z_speed);
+        if Instant::now() + needed > deadline {
+            warn!("Power loss: no time to park Z ({} ms needed)", needed.as_millis());
+            return false;
+        }
+        step(deadline, "park Z", z_axis.move_to(target, self.z_speed)).await
+    }
+
+    async fn report(&self, report: &ShutdownReport) {
+        let message = format!(
+            "Power lost; shutdown {} in {} ms (journal {})",
+            if report.deadline_met { "completed" } else { "overran deadline" },
+            report.elapsed.as_millis(),
+            if report.journaled { "written" } else { "not written" },
+        );
+        error!("{}", message);
+
+        let error = SystemError::new(POWER_LOSS_CODE, message, vec!["power".to_string()]);
+        let event = error.to_event();
+        self.targets.state.write().await.add_error(error, &self.targets.state_machine);
+
+        // No subscribers is not an error
+        self.status_tx.send(ProtocolMessage::ErrorEvent(event)).ok();
+    }
+}
+
+/// Runs one shutdown step, logging failure or a missed deadline.
+async fn step<F>(deadline: Instant, name: &str, fut: F) -> bool
+where
+    F: Future<Output = Result<()>>,
+{
+    // An expired deadline still polls the step once, so fast steps run
+    match tokio::time::timeout_at(deadline, fut).await {
+        Ok(Ok(())) => true,
+        Ok(Err(e)) => {
+            error!("Power loss: failed to {}: {:#}", name, e);
+            false
+        }
+        Err(_) => {
+            error!("Power loss: deadline passed before {} finished", name);
+            false
+        }
+    }
+}
+
+fn unix_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hardware::bus::OutputPin;
+    use crate::{PrintStatus, ValveHealth};
+    use gcode_types::{GridCoordinate, ValveState};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Mutex as StdMutex;
+
+    #[derive(Default)]
+    struct Calls {
+        valves_closed: AtomicUsize,
+        heaters_off: AtomicUsize,
+        vented: AtomicUsize,
+        z_moves: StdMutex<Vec<f32>>,
+        outputs_cut: AtomicUsize,
+    }
+
+    struct FakeLine(Arc<Calls>);
+
+    impl OutputPin for FakeLine {
+        fn set(&mut self, high: bool) -> Result<()> {
+            if high {
+                self.0.outputs_cut.fetch_add(1, Ordering::SeqCst);
+            }
+            Ok(())
+        }
+    }
+
+    struct FakeHardware(Arc<Calls>);
+
+    #[async_trait::async_trait]
+    impl ValveController for FakeHardware {
+        async fn set_valve_states(&mut self, _states: &[(GridCoordinate, Vec<ValveState>)]) -> Result<()> {
+            Ok(())
+        }
+        async fn get_valve_states(&self, _position: GridCoordinate) -> Result<Vec<ValveState>> {
+            Ok(Vec::new())
+        }
+        async fn health_check(&mut self) -> Result<Vec<ValveHealth>> {
+            Ok(Vec::new())
+        }
+        async fn emergency_close_all(&mut self) -> Result<()> {
+            self.0.valves_closed.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl HeaterController for FakeHardware {
+        async fn set_temperature(&mut self, _zone_id: u8, _target: f32) -> Result<()> {
+            Ok(())
+        }
+        async fn get_temperature(&self, _zone_id: u8) -> Result<f32> {
+            Ok(0.0)
+        }
+        async fn update_control(&mut self) -> Result<()> {
+            Ok(())
+        }
+        async fn emergency_off(&mut self) -> Result<()> {
+            self.0.heaters_off.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl PressureController for FakeHardware {
+        async fn set_pressure(&mut self, _channel_id: u8, _target: f32) -> Result<()> {
+            Ok(())
+        }
+        async fn get_pressure(&self, _channel_id: u8) -> Result<f32> {
+            Ok(0.0)
+        }
+        async fn get_flow_rate(&self, _channel_id: u8) -> Result<f32> {
+            Ok(0.0)
+        }
+        async fn update_control(&mut self) -> Result<()> {
+            Ok(())
+        }
+        async fn emergency_vent(&mut self) -> Result<()> {
+            self.0.vented.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl ZAxisController for FakeHardware {
+        async fn home(&mut self) -> Result<()> {
+            Ok(())
+        }
+        async fn move_to(&mut self, z: f32, _speed: f32) -> Result<()> {
+            self.0.z_moves.lock().unwrap().push(z);
+            Ok(())
+        }
+        async fn get_position(&self) -> Result<f32> {
+            Ok(42.0)
+        }
+        async fn is_motion_complete(&self) -> Result<bool> {
+            Ok(true)
+        }
+        async fn emergency_stop(&mut self) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    fn handler(config: PowerLossConfig, state: SystemState) -> (PowerLossHandler, Arc<Calls>) {
+        let calls = Arc::new(Calls::default());
+        let targets = PowerLossTargets {
+            valves: Arc::new(Mutex::new(Box::new(FakeHardware(calls.clone())))),
+            heaters: Arc::new(Mutex::new(Box::new(FakeHardware(calls.clone())))),
+            pressure: Arc::new(Mutex::new(Box::new(FakeHardware(calls.clone())))),
+            z_axis: Arc::new(Mutex::new(Box::new(FakeHardware(calls.clone())))),
+            state: Arc::new(RwLock::new(state)),
+            state_machine: Arc::new(StateMachine::detached()),
+            latch_reset: LatchReset::default(),
+            valve_cutoff: ValveCutoff::with_line(Box::new(FakeLine(calls.clone()))),
+            job_control: Arc::new(watch::channel(JobControl::Run).0),
+            job_tasks: JobTasks::default(),
+        };
+        let (tx, _) = broadcast::channel(4);
+        let handler = PowerLossHandler {
+            config,
+            max_z: 150.0,
+            z_speed: 10.0,
+            targets,
+            status_tx: tx,
+        };
+        (handler, calls)
+    }
+
+    fn printing_state() -> SystemState {
+        let mut state = SystemState::new();
+        state.firmware_state = FirmwareState::Printing;
+        let mut status = PrintStatus::new(PathBuf::from("/prints/part.hg4d"), 200);
+        status.update_progress(120, 42.0);
+        status.cancel_object(3);
+        state.print_status = Some(status);
+        state.thermal.zones.insert(0, (208.0, 210.0));
+        state.pressure.channels.insert(0, (39.0, 40.0));
+        state
+    }
+
+    fn config(journal_path: PathBuf, deadline_ms: u32, park_lift_mm: f32) -> PowerLossConfig {
+        PowerLossConfig {
+            signal_pin: 26,
+            active_low: true,
+            deadline_ms,
+            debounce_ms: 5,
+            park_lift_mm,
+            journal_path,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_journals_and_parks() {
+        let dir = std::env::temp_dir().join(format!("hg4d-power-{}", std::process::id()));
+        let path = dir.join("recovery.json");
+        let (handler, calls) = handler(config(path.clone(), 1000, 5.0), printing_state());
+
+        let report = handler.execute().await;
+        assert!(report.valves_closed && report.heaters_off && report.pressure_vented);
+        assert!(report.journaled && report.parked && report.deadline_met);
+        assert_eq!(calls.valves_closed.load(Ordering::SeqCst), 1);
+        assert_eq!(*calls.z_moves.lock().unwrap(), vec![47.0]);
+
+        let state = handler.targets.state.read().await;
+        assert_eq!(state.errors[0].code, POWER_LOSS_CODE);
+
+        let journal = RecoveryJournal::load(&path).unwrap().unwrap();
+        assert_eq!(journal.layer, 120);
+        assert_eq!(journal.zone_targets[&0], 210.0);
+        assert_eq!(journal.cancelled_objects[&3], 120);
+
+        RecoveryJournal::discard(&path).unwrap();
+        assert!(RecoveryJournal::load(&path).unwrap().is_none());
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[tokio::test]
+    async fn test_no_park_without_time() {
+        let dir = std::env::temp_dir().join(format!("hg4d-power-short-{}", std::process::id()));
+        // 5mm at 10mm/s needs 500ms
+        let (handler, calls) = handler(config(dir.join("recovery.json"), 100, 5.0), printing_state());
+
+        let report = handler.execute().await;
+        assert!(report.valves_closed && report.heaters_off);
+        assert!(!report.parked);
+        assert!(calls.z_moves.lock().unwrap().is_empty());
+        std::fs::remove_dir_all(&dir).ok();
+
+        // Nothing printing: hardware is still made safe, no journal
+        let (handler, calls) = handler_idle();
+        let report = handler.execute().await;
+        assert!(!report.journaled);
+        assert_eq!(calls.heaters_off.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_aborts_job_and_cuts_busy_valves() {
+        let dir = std::env::temp_dir().join(format!("hg4d-power-busy-{}", std::process::id()));
+        let (handler, calls) = handler(config(dir.join("recovery.json"), 100, 0.0), printing_state());
+        let mut control = handler.targets.job_control.subscribe();
+        // The job holds the valve controller for its whole layer
+        let valves = handler.targets.valves.clone();
+        let job = tokio::spawn(async move {
+            let _layer = valves.lock().await;
+            std::future::pending::<()>().await;
+        });
+        handler.targets.job_tasks.track(job.abort_handle());
+        tokio::task::yield_now().await;
+
+        let report = handler.execute().await;
+        assert!(report.valves_closed && report.deadline_met);
+        assert_eq!(calls.outputs_cut.load(Ordering::SeqCst), 1);
+        assert_eq!(calls.valves_closed.load(Ordering::SeqCst), 0);
+        assert_eq!(*control.borrow_and_update(), JobControl::Cancel);
+        assert!(job.await.unwrap_err().is_cancelled());
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    fn handler_idle() -> (PowerLossHandler, Arc<Calls>) {
+        let path = std::env::temp_dir().join("hg4d-power-idle").join("recovery.json");
+        handler(config(path, 100, 5.0), SystemState::new())
+    }
+}
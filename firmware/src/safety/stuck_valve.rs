@@ -0,0 +1,448 @@
+//! Stuck-open valve detection.
+//!
+//! A valve that fails to close keeps feeding material onto the plate. The
+//! [`StuckValveDetector`] follows every commanded valve state per channel
+//! and compares it with the channel sensors: flow above
+//! `max_idle_flow`, or pressure falling faster than `max_idle_pressure_drop`,
+//! while no valve of the channel is commanded open means material is
+//! escaping. The condition has to hold for `confirm_samples` consecutive
+//! readings, and is not evaluated within `close_settle` of the channel's
+//! last close so draining dead volume does not trigger it. Pressure falling
+//! toward a lowered target ([`StuckValveDetector::record_target`]) is
+//! commanded, so the drop rule is skipped while the pressure is above it.
+//!
+//! Suspects are localized in two ways:
+//!
+//! 1. Valves whose feedback reads open although they are commanded closed
+//! 2. Without such feedback, the channel's valves closed most recently,
+//!    newest first, since a valve that sticks does so on the close that
+//!    should have stopped the flow
+//!
+//! [`StuckValveResponse::respond`] then drops the channel's pressure,
+//! re-commands the suspects closed and raises a Critical error naming their
+//! coordinates. Each channel trips once until [`StuckValveDetector::reset`].
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use tokio::sync::{broadcast, Mutex, RwLock};
+use tracing::{error, warn};
+
+use gcode_types::{GridCoordinate, ValveState};
+use protocol::{ErrorCode, ProtocolMessage};
+
+use crate::core::{LatchReset, StateMachine};
+use crate::utils::RingBuffer;
+use crate::{PressureController, SensorReadings, SystemError, SystemState, ValveController};
+
+/// Error code of a detected stuck-open valve.
+pub const VALVE_STUCK_OPEN_CODE: ErrorCode = ErrorCode::ValveStuckOpen;
+
+/// Most suspects named in one fault.
+pub const MAX_SUSPECTS: usize = 4;
+
+/// Closes remembered for localization.
+const CLOSE_HISTORY: usize = 256;
+
+/// Pressure above target treated as still falling to it (PSI).
+const TARGET_TOLERANCE: f32 = 0.5;
+
+
+/// Detection thresholds.
+#[derive(Debug, Clone, Copy)]
+pub struct StuckValveConfig {
+    /// Flow tolerated with every valve of a channel closed (mm³/s)
+    pub max_idle_flow: f32,
+
+    /// Pressure loss tolerated with every valve closed (PSI/s)
+    pub max_idle_pressure_drop: f32,
+
+    /// Consecutive anomalous readings before the channel trips
+    pub confirm_samples: u32,
+
+    /// Time after a close before the channel is evaluated
+    pub close_settle: Duration,
+
+    /// How far back recent closes count as suspects
+    pub suspect_window: Duration,
+}
+
+impl Default for StuckValveConfig {
+    fn default() -> Self {
+        Self {
+            max_idle_flow: 0.5,
+            max_idle_pressure_drop: 2.0,
+            confirm_samples: 3,
+            close_settle: Duration::from_millis(250),
+            suspect_window: Duration::from_secs(5),
+        }
+    }
+}
+
+/// Why a valve is suspected.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Evidence {
+    /// Its feedback reads open
+    Feedback,
+    /// It was closed this long before the fault
+    RecentClose { ago: Duration },
+}
+
+/// A valve that may be stuck open.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SuspectValve {
+    pub node: GridCoordinate,
+    pub index: u8,
+    pub evidence: Evidence,
+}
+
+impl fmt::Display for SuspectValve {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "({}, {}) V{}", self.node.x, self.node.y, self.index)?;
+        match self.evidence {
+            Evidence::Feedback => write!(f, " [feedback open]"),
+            Evidence::RecentClose { ago } => write!(f, " [closed {} ms before]", ago.as_millis()),
+        }
+    }
+}
+
+/// Material escaping from a channel with no valve commanded open.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StuckValveFault {
+    pub channel: u8,
+    /// Flow at detection (mm³/s), if measured
+    pub flow: Option<f32>,
+    /// Pressure loss rate at detection (PSI/s), if measured
+    pub pressure_drop: Option<f32>,
+    /// Likely culprits, most likely first; empty if none could be named
+    pub suspects: Vec<SuspectValve>,
+}
+
+impl StuckValveFault {
+    pub fn message(&self) -> String {
+        let mut evidence = Vec::new();
+        if let Some(flow) = self.flow {
+            evidence.push(format!("flow {:.2} mm³/s", flow));
+        }
+        if let Some(drop) = self.pressure_drop {
+            evidence.push(format!("pressure falling {:.1} PSI/s", drop));
+        }
+        let suspects = if self.suspects.is_empty() {
+            "no suspect could be localized".to_string()
+        } else {
+            let named: Vec<String> = self.suspects.iter().map(ToString::to_string).collect();
+            format!("suspected valves: {}", named.join(", "))
+        };
+        format!(
+            "Channel {} leaking with all valves closed ({}); {}",
+            self.channel,
+            evidence.join(", "),
+            suspects
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct ClosedValve {
+    channel: u8,
+    node: GridCoordinate,
+    index: u8,
+    at: Instant,
+}
+
+/// Compares commanded valve states with channel flow and pressure.
+pub struct StuckValveDetector {
+    config: StuckValveConfig,
+    /// Valves commanded open, per channel
+    open: HashMap<u8, HashSet<(GridCoordinate, u8)>>,
+    /// Channel each valve was last commanded on
+    valve_channels: HashMap<(GridCoordinate, u8), u8>,
+    recent_closes: RingBuffer<ClosedValve>,
+    last_close: HashMap<u8, Instant>,
+    last_pressure: HashMap<u8, (f32, Instant)>,
+    /// Pressure target of each channel
+    targets: HashMap<u8, f32>,
+    strikes: HashMap<u8, u32>,
+    tripped: HashSet<u8>,
+}
+
+impl StuckValveDetector {
+    pub fn new(config: StuckValveConfig) -> Self {
+        Self {
+            config,
+            open: HashMap::new(),
+            valve_channels: HashMap::new(),
+            recent_closes: RingBuffer::new(CLOSE_HISTORY),
+            last_close: HashMap::new(),
+            last_pressure: HashMap::new(),
+            targets: HashMap::new(),
+            strikes: HashMap::new(),
+            tripped: HashSet::new(),
+        }
+    }
+
+    /// Records valve states latched for `channel` at `at`.
+    pub fn record_commands(&mut self, channel: u8, updates: &[(GridCoordinate, Vec<ValveState>)], at: Instant) {
+        let open = self.open.entry(channel).or_default();
+        for (node, valves) in updates {
+            for valve in valves {
+                let key = (*node, valve.index);
+                self.valve_channels.insert(key, channel);
+                if valve.open {
+                    open.insert(key);
+                } else if open.remove(&key) {
+                    self.recent_closes.push(ClosedValve {
+                        channel,
+                        node: *node,
+                        index: valve.index,
+                        at,
+                    });
+                    self.last_close.insert(channel, at);
+                }
+            }
+        }
+    }
+
+    /// Records a channel's pressure target.
+    pub fn record_target(&mut self, channel: u8, target: f32) {
+        self.targets.insert(channel, target);
+    }
+
+    /// Evaluates one sensor reading; returns the channels that tripped.
+    pub fn observe(&mut self, readings: &SensorReadings, at: Instant) -> Vec<StuckValveFault> {
+        let mut channels: Vec<u8> = readings.flow_rates.keys().chain(readings.pressures.keys()).copied().collect();
+        channels.sort_unstable();
+        channels.dedup();
+
+        let mut faults = Vec::new();
+        for channel in channels {
+            let pressure_drop = readings.pressures.get(&channel).and_then(|&pressure| {
+                let previous = self.last_pressure.insert(channel, (pressure, at));
+                previous.and_then(|(before, then)| {
+                    let elapsed = at.saturating_duration_since(then).as_secs_f32();
+                    (elapsed > 0.0).then(|| (before - pressure) / elapsed)
+                })
+            });
+            let flow = readings.flow_rates.get(&channel).copied();
+
+            if self.tripped.contains(&channel) || !self.is_idle(channel, at) {
+                self.strikes.remove(&channel);
+                continue;
+            }
+            let venting = readings
+                .pressures
+                .get(&channel)
+                .zip(self.targets.get(&channel))
+                .is_some_and(|(pressure, target)| *pressure > target + TARGET_TOLERANCE);
+            let leaking = flow.is_some_and(|f| f > self.config.max_idle_flow)
+                || (!venting && pressure_drop.is_some_and(|d| d > self.config.max_idle_pressure_drop));
+            if !leaking {
+                self.strikes.remove(&channel);
+                continue;
+            }
+
+            let strikes = self.strikes.entry(channel).or_default();
+            *strikes += 1;
+            if *strikes < self.config.confirm_samples {
+                continue;
+            }
+            self.strikes.remove(&channel);
+            self.tripped.insert(channel);
+            faults.push(StuckValveFault {
+                channel,
+                flow,
+                pressure_drop,
+                suspects: self.suspects(channel, readings, at),
+            });
+        }
+        faults
+    }
+
+    /// Re-arms a channel after the fault was handled.
+    pub fn reset(&mut self, channel: u8) {
+        self.tripped.remove(&channel);
+        self.strikes.remove(&channel);
+    }
+
+    /// No valve open and the last close has settled.
+    fn is_idle(&self, channel: u8, at: Instant) -> bool {
+        let any_open = self.open.get(&channel).is_some_and(|open| !open.is_empty());
+        let settling = self
+            .last_close
+            .get(&channel)
+            .is_some_and(|closed| at.saturating_duration_since(*closed) < self.config.close_settle);
+        !any_open && !settling
+    }
+
+    fn suspects(&self, channel: u8, readings: &SensorReadings, at: Instant) -> Vec<SuspectValve> {
+        let mut suspects: Vec<SuspectValve> = Vec::new();
+        for (node, feedback) in &readings.valve_feedbacks {
+            for (index, open) in feedback.iter().enumerate() {
+                let key = (*node, index as u8);
+                if *open && self.valve_channels.get(&key) == Some(&channel) {
+                    suspects.push(SuspectValve {
+                        node: *node,
+                        index: index as u8,
+                        evidence: Evidence::Feedback,
+                    });
+                }
+            }
+        }
+        if !suspects.is_empty() {
+            suspects.sort_by_key(|s| (s.node.y, s.node.x, s.index));
+            suspects.truncate(MAX_SUSPECTS);
+            return suspects;
+        }
+
+        for closed in self.recent_closes.iter().rev() {
+            let ago = at.saturating_duration_since(closed.at);
+            if ago > self.config.suspect_window || suspects.len() == MAX_SUSPECTS {
+                break;
+            }
+            // Valves without feedback only; one that reads closed is cleared
+            let reads_closed = readings
+                .valve_feedbacks
+                .get(&closed.node)
+                .and_then(|f| f.get(closed.index as usize))
+                .is_some_and(|open| !open);
+            let duplicate = suspects.iter().any(|s| s.node == closed.node && s.index == closed.index);
+            if closed.channel == channel && !reads_closed && !duplicate {
+                suspects.push(SuspectValve {
+                    node: closed.node,
+                    index: closed.index,
+                    evidence: Evidence::RecentClose { ago },
+                });
+            }
+        }
+        suspects
+    }
+}
+
+/// What the response acts on.
+pub struct StuckValveResponse {
+    pub pressure: Arc<Mutex<Box<dyn PressureController>>>,
+    pub valves: Arc<Mutex<Box<dyn ValveController>>>,
+    pub state: Arc<RwLock<SystemState>>,
+    pub state_machine: Arc<StateMachine>,
+    pub latch_reset: LatchReset,
+    pub status_tx: broadcast::Sender<ProtocolMessage>,
+}
+
+impl StuckValveResponse {
+    /// Drops the channel pressure, re-closes the suspects and raises a
+    /// Critical error. Every step is attempted even if an earlier one fails.
+    pub async fn respond(&self, fault: &StuckValveFault) {
+        let message = fault.message();
+        error!("{}", message);
+
+        if let Err(e) = self.pressure.lock().await.set_pressure(fault.channel, 0.0).await {
+            error!("Failed to drop channel {} pressure: {:#}", fault.channel, e);
+        }
+
+        let close: Vec<(GridCoordinate, Vec<ValveState>)> = fault
+            .suspects
+            .iter()
+            .map(|s| (s.node, vec![ValveState::closed(s.index)]))
+            .collect();
+        if !close.is_empty() {
+            if let Err(e) = self.valves.lock().await.set_valve_states(&close).await {
+                warn!("Failed to re-close suspected valves: {:#}", e);
+            }
+            self.latch_reset.trigger();
+        }
+
+        let affected = vec!["valves".to_string(), format!("channel {}", fault.channel)];
+        let error = SystemError::new(VALVE_STUCK_OPEN_CODE, message, affected);
+        let event = error.to_event();
+        self.state.write().await.add_error(error, &self.state_machine);
+
+        // No subscribers is not an error
+        self.status_tx.send(ProtocolMessage::ErrorEvent(event)).ok();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn readings(flow: f32) -> SensorReadings {
+        SensorReadings {
+            flow_rates: HashMap::from([(0, flow)]),
+            ..SensorReadings::default()
+        }
+    }
+
+    #[test]
+    fn test_flow_with_closed_valves_trips_once() {
+        let mut detector = StuckValveDetector::new(StuckValveConfig::default());
+        let start = Instant::now();
+        let a = GridCoordinate::new(4, 7);
+        let b = GridCoordinate::new(5, 7);
+        detector.record_commands(0, &[(a, vec![ValveState::open(0)]), (b, vec![ValveState::open(1)])], start);
+        detector.record_commands(0, &[(a, vec![ValveState::closed(0)])], start + Duration::from_millis(10));
+
+        // Flow while a valve is open is normal
+        let at = start + Duration::from_millis(15);
+        for i in 0..5 {
+            assert!(detector.observe(&readings(8.0), at + Duration::from_millis(i)).is_empty());
+        }
+
+        let closed = start + Duration::from_millis(20);
+        detector.record_commands(0, &[(b, vec![ValveState::closed(1)])], closed);
+        // Within the settle time the draining dead volume is ignored
+        assert!(detector.observe(&readings(8.0), closed + Duration::from_millis(50)).is_empty());
+
+        let at = closed + Duration::from_millis(500);
+        assert!(detector.observe(&readings(3.0), at).is_empty());
+        assert!(detector.observe(&readings(3.0), at + Duration::from_millis(10)).is_empty());
+        let faults = detector.observe(&readings(3.0), at + Duration::from_millis(20));
+        assert_eq!(faults.len(), 1);
+        let suspects: Vec<_> = faults[0].suspects.iter().map(|s| (s.node, s.index)).collect();
+        assert_eq!(suspects, vec![(b, 1), (a, 0)]);
+        assert!(faults[0].message().contains("(5, 7) V1"));
+
+        // Tripped channels stay quiet until reset
+        assert!(detector.observe(&readings(3.0), at + Duration::from_millis(30)).is_empty());
+
+        detector.reset(0);
+        let mut with_feedback = readings(3.0);
+        with_feedback.valve_feedbacks.insert(a, vec![true]);
+        with_feedback.valve_feedbacks.insert(b, vec![false, false]);
+        let later = at + Duration::from_millis(100);
+        let mut faults = Vec::new();
+        for i in 0..3 {
+            faults = detector.observe(&with_feedback, later + Duration::from_millis(i));
+        }
+        assert_eq!(faults[0].suspects.len(), 1);
+        assert_eq!(faults[0].suspects[0].node, a);
+        assert_eq!(faults[0].suspects[0].evidence, Evidence::Feedback);
+    }
+
+    #[test]
+    fn test_commanded_drop_is_not_a_leak() {
+        let mut detector = StuckValveDetector::new(StuckValveConfig::default());
+        let start = Instant::now();
+        let pressure = |psi: f32| SensorReadings {
+            pressures: HashMap::from([(0, psi)]),
+            ..SensorReadings::default()
+        };
+
+        // Venting from 40 to a 10 PSI target at 20 PSI/s
+        detector.record_target(0, 10.0);
+        for i in 0..10 {
+            let at = start + Duration::from_millis(100 * i);
+            assert!(detector.observe(&pressure(40.0 - 2.0 * i as f32), at).is_empty());
+        }
+
+        // Falling below the target is a leak
+        let mut faults = Vec::new();
+        for i in 0..3 {
+            let at = start + Duration::from_millis(2000 + 100 * i);
+            faults = detector.observe(&pressure(10.0 - 1.0 * i as f32), at);
+        }
+        assert_eq!(faults.len(), 1);
+        assert!(faults[0].pressure_drop.is_some_and(|d| d > 2.0));
+    }
+}
@@ -0,0 +1,410 @@
+//! Task health supervision.
+//!
+//! Every long-running firmware task (thermal control, pressure control, the
+//! print job driving the valve scheduler, ...) registers with the
+//! [`TaskSupervisor`] and receives a [`Heartbeat`] handle which it beats
+//! once per cycle. The supervisor checks all tasks periodically; a task that
+//! misses its deadline, or whose handle is dropped without
+//! [`Heartbeat::finish`] (task returned early or panicked), puts the printer
+//! into a safe state:
+//!
+//! 1. The print job aborted and all valves closed
+//! 2. All heaters off
+//! 3. System state set to `Error`
+//! 4. A Critical `ErrorEvent` naming the failed task is broadcast
+//!
+//! The failed task may be holding the controller a step needs, so each step
+//! gives up after [`SAFE_STATE_STEP_TIMEOUT`] and the next one still runs.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex as StdMutex};
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use tokio::sync::{broadcast, Mutex, RwLock};
+use tracing::{debug, error, info};
+
+use protocol::{ErrorCode, ProtocolMessage};
+
+use crate::core::{LatchReset, StateMachine};
+use crate::safety::emergency::JobTasks;
+use crate::{HeaterController, SystemError, SystemState, ValveController};
+
+/// Error code reported when a task stops heartbeating.
+pub const TASK_STALLED_CODE: ErrorCode = ErrorCode::TaskStalled;
+
+/// Error code reported when a task exits without finishing cleanly.
+pub const TASK_EXITED_CODE: ErrorCode = ErrorCode::TaskExited;
+
+/// Default interval between supervisor checks.
+pub const DEFAULT_CHECK_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Longest one safe state step waits for its controller.
+pub const SAFE_STATE_STEP_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Why a task was declared failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TaskFailure {
+    /// No heartbeat within the deadline
+    Stalled { since: Duration },
+    /// Heartbeat handle dropped without `finish()`
+    Exited,
+}
+
+struct TaskSlot {
+    name: String,
+    deadline: Duration,
+    last_beat: StdMutex<Instant>,
+    finished: AtomicBool,
+    dropped: AtomicBool,
+}
+
+/// Handle a supervised task uses to report liveness.
+pub struct Heartbeat {
+    slot: Arc<TaskSlot>,
+}
+
+impl Heartbeat {
+    /// Records that the task completed another cycle.
+    pub fn beat(&self) {
+        if let Ok(mut last) = self.slot.last_beat.lock() {
+            *last = Instant::now();
+        }
+    }
+
+    /// Task name as registered.
+    pub fn name(&self) -> &str {
+        &self.slot.name
+    }
+
+    /// Marks the task as finished normally; the supervisor stops tracking it.
+    pub fn finish(self) {
+        self.slot.finished.store(true, Ordering::SeqCst);
+    }
+
+    /// Handle that finishes the task from outside, for a task about to be
+    /// aborted on purpose.
+    pub fn release_handle(&self) -> HeartbeatRelease {
+        HeartbeatRelease { slot: self.slot.clone() }
+    }
+}
+
+/// Finishes a supervised task without its [`Heartbeat`].
+#[derive(Clone)]
+pub struct HeartbeatRelease {
+    slot: Arc<TaskSlot>,
+}
+
+impl HeartbeatRelease {
+    pub fn release(&self) {
+        self.slot.finished.store(true, Ordering::SeqCst);
+    }
+}
+
+impl Drop for Heartbeat {
+    fn drop(&mut self) {
+        self.slot.dropped.store(true, Ordering::SeqCst);
+    }
+}
+
+/// Subsystems the supervisor makes safe when a task fails.
+#[derive(Clone)]
+pub struct SafeStateTargets {
+    pub heaters: Arc<Mutex<Box<dyn HeaterController>>>,
+    pub valves: Arc<Mutex<Box<dyn ValveController>>>,
+    pub state: Arc<RwLock<SystemState>>,
+    pub state_machine: Arc<StateMachine>,
+    pub latch_reset: LatchReset,
+    /// Aborted first, so a stalled job gives up the valve controller
+    pub job_tasks: JobTasks,
+}
+
+/// Watches task heartbeats and enforces safe state on failure.
+pub struct TaskSupervisor {
+    tasks: StdMutex<HashMap<String, Arc<TaskSlot>>>,
+    check_interval: Duration,
+    targets: SafeStateTargets,
+    status_tx: broadcast::Sender<ProtocolMessage>,
+}
+
+impl TaskSupervisor {
+    pub fn new(targets: SafeStateTargets, status_tx: broadcast::Sender<ProtocolMessage>) -> Self {
+        Self {
+            tasks: StdMutex::new(HashMap::new()),
+            check_interval: DEFAULT_CHECK_INTERVAL,
+            targets,
+            status_tx,
+        }
+    }
+
+    pub fn with_check_interval(mut self, interval: Duration) -> Self {
+        self.check_interval = interval;
+        self
+    }
+
+    /// Registers a task that must beat at least once per `deadline`.
+    ///
+    /// Registering a name again replaces the previous slot, so a restarted
+    /// task can re-register under the same name.
+    pub fn register(&self, name: impl Into<String>, deadline: Duration) -> Heartbeat {
+        let name = name.into();
+        let slot = Arc::new(TaskSlot {
+            name: name.clone(),
+            deadline,
+            last_beat: StdMutex::new(Instant::now()),
+            finished: AtomicBool::new(false),
+            dropped: AtomicBool::new(false),
+        });
+        debug!("Supervising task '{}' (deadline {:?})", name, deadline);
+        self.tasks
+            .lock()
+            .expect("supervisor task table poisoned")
+            .insert(name, slot.clone());
+        Heartbeat { slot }
+    }
+
+    /// Names of tasks currently supervised.
+    pub fn task_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self
+            .tasks
+            .lock()
+            .expect("supervisor task table poisoned")
+            .keys()
+            .cloned()
+            .collect();
+        names.sort();
+        names
+    }
+
+    /// Returns tasks that have failed since registration, removing them (and
+    /// cleanly finished tasks) from supervision.
+    pub fn check(&self, now: Instant) -> Vec<(String, TaskFailure)> {
+        let mut failed = Vec::new();
+        let mut tasks = self.tasks.lock().expect("supervisor task table poisoned");
+
+        tasks.retain(|name, slot| {
+            if slot.finished.load(Ordering::SeqCst) {
+                debug!("Task '{}' finished", name);
+                return false;
+            }
+            if slot.dropped.load(Ordering::SeqCst) {
+                failed.push((name.clone(), TaskFailure::Exited));
+                return false;
+            }
+            let since = slot
+                .last_beat
+                .lock()
+                .map(|last| now.saturating_duration_since(*last))
+                .unwrap_or(Duration::MAX);
+            if since > slot.deadline {
+                failed.push((name.clone(), TaskFailure::Stalled { since }));
+                return false;
+            }
+            true
+        });
+
+        failed.sort_by(|a, b| a.0.cmp(&b.0));
+        failed
+    }
+
+    /// Runs the supervision loop until shutdown.
+    pub async fn run(&self, mut shutdown: broadcast::Receiver<()>) -> Result<()> {
+        let mut interval = tokio::time::interval(self.check_interval);
+        info!("Task supervisor started");
+
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {
+                    let failed = self.check(Instant::now());
+                    if !failed.is_empty() {
+                        self.enter_safe_state(&failed).await;
+                    }
+                }
+                _ = shutdown.recv() => {
+                    info!("Task supervisor stopped");
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    /// Aborts the print job, closes valves, turns off heaters and reports
+    /// the failed tasks.
+    ///
+    /// Every step is attempted even if an earlier one fails or times out,
+    /// so a stalled thermal task holding the heaters cannot prevent the
+    /// valves from closing.
+    pub async fn enter_safe_state(&self, failed: &[(String, TaskFailure)]) {
+        let names: Vec<&str> = failed.iter().map(|(n, _)| n.as_str()).collect();
+        error!("Supervised task failure: {}; entering safe state", names.join(", "));
+
+        self.targets.job_tasks.abort_all();
+        safe_state_step("close valves", async {
+            self.targets.valves.lock().await.emergency_close_all().await
+        })
+        .await;
+        self.targets.latch_reset.trigger();
+        safe_state_step("turn off heaters", async {
+            self.targets.heaters.lock().await.emergency_off().await
+        })
+        .await;
+
+        let mut state = self.targets.state.write().await;
+        for (name, failure) in failed {
+            let (code, message) = describe(name, *failure);
+            let error = SystemError::new(code, message, vec![name.clone()]);
+            let event = error.to_event();
+            state.add_error(error, &self.targets.state_machine);
+
+            // No subscribers is not an error
+            self.status_tx.send(ProtocolMessage::ErrorEvent(event)).ok();
+        }
+    }
+}
+
+/// Runs one safe state step, giving up after [`SAFE_STATE_STEP_TIMEOUT`].
+async fn safe_state_step(name: &str, step: impl std::future::Future<Output = Result<()>>) {
+    match tokio::time::timeout(SAFE_STATE_STEP_TIMEOUT, step).await {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => error!("Failed to {}: {:#}", name, e),
+        Err(_) => error!("Failed to {}: controller busy for {:?}", name, SAFE_STATE_STEP_TIMEOUT),
+    }
+}
+
+fn describe(name: &str, failure: TaskFailure) -> (ErrorCode, String) {
+    match failure {
+        TaskFailure::Stalled { since } => (
+            TASK_STALLED_CODE,
+            format!("Task '{}' missed heartbeat ({} ms since last)", name, since.as_millis()),
+        ),
+        TaskFailure::Exited => (TASK_EXITED_CODE, format!("Task '{}' exited unexpectedly", name)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ValveHealth;
+    use gcode_types::{GridCoordinate, ValveState};
+    use std::sync::atomic::AtomicUsize;
+
+    #[derive(Default)]
+    struct Calls {
+        heaters_off: AtomicUsize,
+        valves_closed: AtomicUsize,
+    }
+
+    struct FakeHeaters(Arc<Calls>);
+
+    #[async_trait::async_trait]
+    impl HeaterController for FakeHeaters {
+        async fn set_temperature(&mut self, _zone_id: u8, _target: f32) -> Result<()> {
+            Ok(())
+        }
+        async fn get_temperature(&self, _zone_id: u8) -> Result<f32> {
+            Ok(0.0)
+        }
+        async fn update_control(&mut self) -> Result<()> {
+            Ok(())
+        }
+        async fn emergency_off(&mut self) -> Result<()> {
+            self.0.heaters_off.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    struct FakeValves(Arc<Calls>);
+
+    #[async_trait::async_trait]
+    impl ValveController for FakeValves {
+        async fn set_valve_states(&mut self, _states: &[(GridCoordinate, Vec<ValveState>)]) -> Result<()> {
+            Ok(())
+        }
+        async fn get_valve_states(&self, _position: GridCoordinate) -> Result<Vec<ValveState>> {
+            Ok(Vec::new())
+        }
+        async fn health_check(&mut self) -> Result<Vec<ValveHealth>> {
+            Ok(Vec::new())
+        }
+        async fn emergency_close_all(&mut self) -> Result<()> {
+            self.0.valves_closed.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    fn supervisor() -> (TaskSupervisor, Arc<Calls>, broadcast::Receiver<ProtocolMessage>) {
+        let calls = Arc::new(Calls::default());
+        let targets = SafeStateTargets {
+            heaters: Arc::new(Mutex::new(Box::new(FakeHeaters(calls.clone())))),
+            valves: Arc::new(Mutex::new(Box::new(FakeValves(calls.clone())))),
+            state: Arc::new(RwLock::new(SystemState::new())),
+            state_machine: Arc::new(StateMachine::detached()),
+            latch_reset: LatchReset::default(),
+            job_tasks: JobTasks::default(),
+        };
+        let (tx, rx) = broadcast::channel(16);
+        (TaskSupervisor::new(targets, tx), calls, rx)
+    }
+
+    #[test]
+    fn test_missed_and_dropped_heartbeats() {
+        let (sup, _, _) = supervisor();
+        let thermal = sup.register("thermal", Duration::from_millis(100));
+        let pressure = sup.register("pressure", Duration::from_millis(100));
+        let scheduler = sup.register("scheduler", Duration::from_millis(100));
+        scheduler.finish();
+        // A deliberately aborted job is released, not reported
+        let job = sup.register("print_job", Duration::from_millis(100));
+        job.release_handle().release();
+        drop(job);
+
+        let start = Instant::now();
+        assert!(sup.check(start).is_empty());
+
+        thermal.beat();
+        drop(pressure);
+        let failed = sup.check(Instant::now() + Duration::from_millis(50));
+        assert_eq!(failed, vec![("pressure".to_string(), TaskFailure::Exited)]);
+
+        let failed = sup.check(Instant::now() + Duration::from_millis(500));
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0].0, "thermal");
+        assert!(matches!(failed[0].1, TaskFailure::Stalled { .. }));
+        assert!(sup.task_names().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_safe_state_on_failure() {
+        let (sup, calls, mut rx) = supervisor();
+        let state = sup.targets.state.clone();
+
+        sup.enter_safe_state(&[("thermal".to_string(), TaskFailure::Exited)]).await;
+
+        assert_eq!(calls.heaters_off.load(Ordering::SeqCst), 1);
+        assert_eq!(calls.valves_closed.load(Ordering::SeqCst), 1);
+        assert!(state.read().await.firmware_state.is_error());
+
+        match rx.recv().await.unwrap() {
+            ProtocolMessage::ErrorEvent(event) => {
+                assert_eq!(event.code, TASK_EXITED_CODE);
+                assert_eq!(event.affected_systems, vec!["thermal".to_string()]);
+                assert!(matches!(event.severity, protocol::ErrorSeverity::Critical));
+            }
+            other => panic!("unexpected message {:?}", other.message_type()),
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_stalled_heaters_do_not_keep_valves_open() {
+        let (sup, calls, _rx) = supervisor();
+        // The stalled thermal task still holds the heaters
+        let _heaters = sup.targets.heaters.clone().lock_owned().await;
+
+        sup.enter_safe_state(&[("thermal".to_string(), TaskFailure::Stalled { since: Duration::from_secs(1) })])
+            .await;
+        assert_eq!(calls.valves_closed.load(Ordering::SeqCst), 1);
+        assert_eq!(calls.heaters_off.load(Ordering::SeqCst), 0);
+        assert!(sup.targets.state.read().await.firmware_state.is_error());
+    }
+}
@@ -0,0 +1,290 @@
+//! Fixed-capacity ring buffers.
+//!
+//! [`RingBuffer`] is a bounded buffer for use from one thread that drops its
+//! oldest element when full, for rolling windows such as sensor histories.
+//!
+//! [`spsc_channel`] creates a lock-free single-producer single-consumer ring
+//! split into a [`Producer`] and a [`Consumer`], for handing valve frames
+//! from the scheduler to the SPI writer thread. Slots are allocated once;
+//! push and pop never allocate, lock or block, so neither side can stall
+//! the other. A full ring hands the pushed element back and the producer
+//! decides whether to retry or drop it. Elements that are never popped are
+//! dropped with the ring.
+
+use std::cell::UnsafeCell;
+use std::collections::VecDeque;
+use std::mem::MaybeUninit;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// Bounded buffer that evicts its oldest element when full.
+#[derive(Debug, Clone)]
+pub struct RingBuffer<T> {
+    items: VecDeque<T>,
+    capacity: usize,
+}
+
+impl<T> RingBuffer<T> {
+    /// Creates an empty buffer holding at most `capacity` elements (at
+    /// least one).
+    pub fn new(capacity: usize) -> Self {
+        let capacity = capacity.max(1);
+        Self {
+            items: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    /// Appends an element, returning the evicted one if the buffer was full.
+    pub fn push(&mut self, item: T) -> Option<T> {
+        let evicted = if self.is_full() {
+            self.items.pop_front()
+        } else {
+            None
+        };
+        self.items.push_back(item);
+        evicted
+    }
+
+    /// Removes the oldest element.
+    pub fn pop(&mut self) -> Option<T> {
+        self.items.pop_front()
+    }
+
+    /// Most recently pushed element.
+    pub fn latest(&self) -> Option<&T> {
+        self.items.back()
+    }
+
+    /// Elements from oldest to newest.
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &T> + ExactSizeIterator {
+        self.items.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.items.len() == self.capacity
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn clear(&mut self) {
+        self.items.clear();
+    }
+}
+
+/// Keeps the producer and consumer indices on separate cache lines.
+#[repr(align(64))]
+struct CachePadded<T>(T);
+
+struct Shared<T> {
+    /// Next position to read; written by the consumer only
+    head: CachePadded<AtomicUsize>,
+    /// Next position to write; written by the producer only
+    tail: CachePadded<AtomicUsize>,
+    slots: Box<[UnsafeCell<MaybeUninit<T>>]>,
+    /// `slots.len() - 1`; the length is a power of two
+    mask: usize,
+}
+
+// SAFETY: slots between head and tail are only read by the consumer, slots
+// outside that range only written by the producer, and each index is
+// published with release/acquire ordering before the other side touches the
+// slot.
+unsafe impl<T: Send> Sync for Shared<T> {}
+
+impl<T> Shared<T> {
+    fn capacity(&self) -> usize {
+        self.slots.len()
+    }
+
+    fn len(&self) -> usize {
+        let tail = self.tail.0.load(Ordering::Acquire);
+        let head = self.head.0.load(Ordering::Acquire);
+        tail.wrapping_sub(head)
+    }
+}
+
+impl<T> Drop for Shared<T> {
+    fn drop(&mut self) {
+        let head = *self.head.0.get_mut();
+        let tail = *self.tail.0.get_mut();
+        let mut index = head;
+        while index != tail {
+            // SAFETY: slots in head..tail hold initialized elements that
+            // were never popped, and both halves are gone.
+            unsafe { self.slots[index & self.mask].get_mut().assume_init_drop() };
+            index = index.wrapping_add(1);
+        }
+    }
+}
+
+/// Creates an SPSC ring with room for at least `capacity` elements; the
+/// capacity is rounded up to a power of two.
+pub fn spsc_channel<T: Send>(capacity: usize) -> (Producer<T>, Consumer<T>) {
+    let capacity = capacity.max(1).next_power_of_two();
+    let slots = (0..capacity).map(|_| UnsafeCell::new(MaybeUninit::uninit())).collect();
+    let shared = Arc::new(Shared {
+        head: CachePadded(AtomicUsize::new(0)),
+        tail: CachePadded(AtomicUsize::new(0)),
+        slots,
+        mask: capacity - 1,
+    });
+    (
+        Producer {
+            shared: shared.clone(),
+            tail: 0,
+            cached_head: 0,
+        },
+        Consumer {
+            shared,
+            head: 0,
+            cached_tail: 0,
+        },
+    )
+}
+
+/// Writing half of an SPSC ring.
+pub struct Producer<T> {
+    shared: Arc<Shared<T>>,
+    tail: usize,
+    /// Last head seen; refreshed only when the ring looks full
+    cached_head: usize,
+}
+
+impl<T> Producer<T> {
+    /// Appends an element, or hands it back if the ring is full.
+    pub fn push(&mut self, value: T) -> Result<(), T> {
+        let capacity = self.shared.capacity();
+        if self.tail.wrapping_sub(self.cached_head) == capacity {
+            self.cached_head = self.shared.head.0.load(Ordering::Acquire);
+            if self.tail.wrapping_sub(self.cached_head) == capacity {
+                return Err(value);
+            }
+        }
+        // SAFETY: the slot is outside head..tail, so the consumer does not
+        // access it until the new tail is published below.
+        unsafe { (*self.shared.slots[self.tail & self.shared.mask].get()).write(value) };
+        self.tail = self.tail.wrapping_add(1);
+        self.shared.tail.0.store(self.tail, Ordering::Release);
+        Ok(())
+    }
+
+    /// Elements waiting to be popped.
+    pub fn len(&self) -> usize {
+        self.shared.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.shared.capacity()
+    }
+
+    /// The consumer has been dropped; nothing pushed will be read.
+    pub fn is_abandoned(&self) -> bool {
+        Arc::strong_count(&self.shared) == 1
+    }
+}
+
+/// Reading half of an SPSC ring.
+pub struct Consumer<T> {
+    shared: Arc<Shared<T>>,
+    head: usize,
+    /// Last tail seen; refreshed only when the ring looks empty
+    cached_tail: usize,
+}
+
+impl<T> Consumer<T> {
+    /// Removes the oldest element, if any.
+    pub fn pop(&mut self) -> Option<T> {
+        if self.head == self.cached_tail {
+            self.cached_tail = self.shared.tail.0.load(Ordering::Acquire);
+            if self.head == self.cached_tail {
+                return None;
+            }
+        }
+        // SAFETY: the slot is inside head..tail, initialized by the producer
+        // before it published the tail, and not written again until the new
+        // head is published below.
+        let value = unsafe { (*self.shared.slots[self.head & self.shared.mask].get()).assume_init_read() };
+        self.head = self.head.wrapping_add(1);
+        self.shared.head.0.store(self.head, Ordering::Release);
+        Some(value)
+    }
+
+    /// Elements waiting to be popped.
+    pub fn len(&self) -> usize {
+        self.shared.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.shared.capacity()
+    }
+
+    /// The producer has been dropped; once empty, nothing more will arrive.
+    pub fn is_abandoned(&self) -> bool {
+        Arc::strong_count(&self.shared) == 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ring_buffers() {
+        let mut ring = RingBuffer::new(3);
+        for i in 0..3 {
+            assert_eq!(ring.push(i), None);
+        }
+        assert_eq!(ring.push(3), Some(0));
+        assert_eq!(ring.iter().copied().collect::<Vec<_>>(), vec![1, 2, 3]);
+        assert_eq!(ring.latest(), Some(&3));
+
+        let (mut tx, mut rx) = spsc_channel::<Vec<u8>>(3);
+        assert_eq!(tx.capacity(), 4);
+        for i in 0..4 {
+            tx.push(vec![i]).unwrap();
+        }
+        assert_eq!(tx.push(vec![9]), Err(vec![9]));
+        assert_eq!(rx.pop(), Some(vec![0]));
+        tx.push(vec![4]).unwrap();
+        drop(rx);
+        assert!(tx.is_abandoned());
+
+        let (mut tx, mut rx) = spsc_channel::<u32>(64);
+        let writer = std::thread::spawn(move || {
+            let mut next = 0;
+            while next < 100_000 {
+                if tx.push(next).is_ok() {
+                    next += 1;
+                }
+            }
+        });
+        let mut expected = 0;
+        while expected < 100_000 {
+            if let Some(value) = rx.pop() {
+                assert_eq!(value, expected);
+                expected += 1;
+            }
+        }
+        writer.join().unwrap();
+        assert!(rx.is_empty() && rx.is_abandoned());
+    }
+}
@@ -0,0 +1,237 @@
+//! Host self-telemetry.
+//!
+//! The firmware shares its single-board computer with the REST API and
+//! whatever else the operator runs on it, and a Pi 4 in a warm enclosure
+//! throttles its SoC at 80 °C, which stretches valve frame timing in the
+//! middle of a print. [`HostSampler`] reads CPU load and memory from
+//! procfs, the SoC temperature from sysfs and the free space in the print
+//! directory; [`HostMonitor`] checks each sample against the thresholds in
+//! `HostTelemetryConfig`.
+//!
+//! A warning is raised when a reading crosses its threshold and re-armed
+//! only once the reading is back below it by a margin, so a value hovering
+//! at the limit does not repeat the warning every sample.
+
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+
+use config_types::HostTelemetryConfig;
+use protocol::{ErrorCode, HostTelemetry};
+
+/// Hysteresis before a percentage or temperature warning re-arms.
+const REARM_MARGIN: f32 = 5.0;
+
+const KB_PER_MB: u64 = 1024;
+const BYTES_PER_MB: u64 = 1024 * 1024;
+
+/// Cumulative CPU time from `/proc/stat`, in clock ticks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct CpuTimes {
+    busy: u64,
+    total: u64,
+}
+
+impl CpuTimes {
+    fn parse(stat: &str) -> Option<Self> {
+        let fields: Vec<u64> = stat
+            .lines()
+            .find(|l| l.starts_with("cpu "))?
+            .split_whitespace()
+            .skip(1)
+            .map(|f| f.parse().ok())
+            .collect::<Option<_>>()?;
+        // user nice system idle iowait irq softirq steal ...
+        let idle = fields.get(3)? + fields.get(4).copied().unwrap_or(0);
+        let total: u64 = fields.iter().take(8).sum();
+        Some(Self { busy: total - idle, total })
+    }
+
+    /// Busy percentage of all cores between `earlier` and this reading.
+    fn load_since(&self, earlier: &CpuTimes) -> f32 {
+        let total = self.total.saturating_sub(earlier.total);
+        if total == 0 {
+            return 0.0;
+        }
+        self.busy.saturating_sub(earlier.busy) as f32 / total as f32 * 100.0
+    }
+}
+
+/// (total, available) from `/proc/meminfo`, in kB.
+fn parse_meminfo(meminfo: &str) -> Option<(u64, u64)> {
+    let field = |name: &str| {
+        meminfo
+            .lines()
+            .find_map(|l| l.strip_prefix(name)?.trim().trim_end_matches("kB").trim().parse::<u64>().ok())
+    };
+    Some((field("MemTotal:")?, field("MemAvailable:")?))
+}
+
+/// Reads the host's resource use.
+pub struct HostSampler {
+    print_dir: PathBuf,
+    thermal_zone: PathBuf,
+    previous: Option<CpuTimes>,
+}
+
+impl HostSampler {
+    pub fn new(print_dir: &Path, config: &HostTelemetryConfig) -> Self {
+        Self {
+            print_dir: print_dir.to_path_buf(),
+            thermal_zone: config.thermal_zone.clone(),
+            previous: None,
+        }
+    }
+
+    /// Takes a sample. CPU load is measured since the previous sample, so
+    /// the first reads 0.
+    pub fn sample(&mut self) -> Result<HostTelemetry> {
+        let stat = fs::read_to_string("/proc/stat").context("Failed to read /proc/stat")?;
+        let cpu = CpuTimes::parse(&stat).context("No CPU totals in /proc/stat")?;
+        let cpu_load_percent = self.previous.replace(cpu).map_or(0.0, |earlier| cpu.load_since(&earlier));
+        let load_average = fs::read_to_string("/proc/loadavg")
+            .ok()
+            .and_then(|t| t.split_whitespace().next()?.parse().ok())
+            .unwrap_or(0.0);
+
+        let meminfo = fs::read_to_string("/proc/meminfo").context("Failed to read /proc/meminfo")?;
+        let (total_kb, available_kb) = parse_meminfo(&meminfo).context("No memory totals in /proc/meminfo")?;
+
+        // Boards without a thermal zone report no temperature
+        let soc_temperature = fs::read_to_string(&self.thermal_zone)
+            .ok()
+            .and_then(|t| t.trim().parse::<f32>().ok())
+            .map(|millidegrees| millidegrees / 1000.0);
+
+        let disk = nix::sys::statvfs::statvfs(&self.print_dir)
+            .with_context(|| format!("Failed to stat {}", self.print_dir.display()))?;
+        let fragment = disk.fragment_size() as u64;
+
+        Ok(HostTelemetry {
+            cpu_load_percent,
+            load_average,
+            memory_used_mb: total_kb.saturating_sub(available_kb) / KB_PER_MB,
+            memory_total_mb: total_kb / KB_PER_MB,
+            soc_temperature,
+            disk_free_mb: disk.blocks_available() as u64 * fragment / BYTES_PER_MB,
+            disk_total_mb: disk.blocks() as u64 * fragment / BYTES_PER_MB,
+        })
+    }
+}
+
+/// A threshold a sample newly crossed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HostWarning {
+    pub code: ErrorCode,
+    pub message: String,
+}
+
+/// Checks samples against the warning thresholds.
+pub struct HostMonitor {
+    config: HostTelemetryConfig,
+    raised: BTreeSet<ErrorCode>,
+}
+
+impl HostMonitor {
+    pub fn new(config: &HostTelemetryConfig) -> Self {
+        Self {
+            config: config.clone(),
+            raised: BTreeSet::new(),
+        }
+    }
+
+    /// Warnings for thresholds `sample` crosses that were not already
+    /// raised; re-arms those it has fallen back from.
+    pub fn check(&mut self, sample: &HostTelemetry) -> Vec<HostWarning> {
+        let c = &self.config;
+        let memory_percent = if sample.memory_total_mb > 0 {
+            sample.memory_used_mb as f32 / sample.memory_total_mb as f32 * 100.0
+        } else {
+            0.0
+        };
+        let disk_rearm = c.disk_free_warning_mb + c.disk_free_warning_mb / 10;
+        let soc = sample.soc_temperature;
+
+        // (code, crossed, back under the re-arm level, message)
+        let readings = [
+            (
+                ErrorCode::HostCpuLoad,
+                sample.cpu_load_percent >= c.cpu_load_warning,
+                sample.cpu_load_percent < c.cpu_load_warning - REARM_MARGIN,
+                format!("Controller CPU load {:.0}%", sample.cpu_load_percent),
+            ),
+            (
+                ErrorCode::HostMemory,
+                memory_percent >= c.memory_warning,
+                memory_percent < c.memory_warning - REARM_MARGIN,
+                format!(
+                    "Controller memory {:.0}% used ({} of {} MB)",
+                    memory_percent, sample.memory_used_mb, sample.memory_total_mb
+                ),
+            ),
+            (
+                ErrorCode::HostThermal,
+                soc.is_some_and(|t| t >= c.soc_temperature_warning),
+                soc.is_none_or(|t| t < c.soc_temperature_warning - REARM_MARGIN),
+                format!("Controller SoC at {:.1}°C", soc.unwrap_or(0.0)),
+            ),
+            (
+                ErrorCode::HostDiskSpace,
+                sample.disk_free_mb < c.disk_free_warning_mb,
+                sample.disk_free_mb >= disk_rearm,
+                format!("{} MB free in the print directory", sample.disk_free_mb),
+            ),
+        ];
+
+        let mut warnings = Vec::new();
+        for (code, crossed, rearmed, message) in readings {
+            if crossed && self.raised.insert(code) {
+                warnings.push(HostWarning { code, message });
+            } else if rearmed {
+                self.raised.remove(&code);
+            }
+        }
+        warnings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_thresholds() {
+        let earlier = CpuTimes::parse("cpu  100 0 100 700 100 0 0 0 0 0\ncpu0 1 2 3 4\n").unwrap();
+        let later = CpuTimes::parse("cpu  250 0 200 750 100 0 0 0 0 0\n").unwrap();
+        assert_eq!(earlier, CpuTimes { busy: 200, total: 1000 });
+        assert!((later.load_since(&earlier) - 83.33).abs() < 0.01);
+        let meminfo = "MemTotal:        3884360 kB\nMemFree:  100 kB\nMemAvailable:    1942180 kB\n";
+        assert_eq!(parse_meminfo(meminfo), Some((3884360, 1942180)));
+
+        let mut monitor = HostMonitor::new(&HostTelemetryConfig::default());
+        let sample = |cpu_load_percent, soc: f32| HostTelemetry {
+            cpu_load_percent,
+            load_average: 1.0,
+            memory_used_mb: 1024,
+            memory_total_mb: 4096,
+            soc_temperature: Some(soc),
+            disk_free_mb: 200,
+            disk_total_mb: 30_000,
+        };
+        let codes = |warnings: Vec<HostWarning>| warnings.into_iter().map(|w| w.code).collect::<Vec<_>>();
+
+        // Low disk space and a hot SoC, raised once
+        assert_eq!(
+            codes(monitor.check(&sample(50.0, 78.0))),
+            vec![ErrorCode::HostThermal, ErrorCode::HostDiskSpace]
+        );
+        assert_eq!(codes(monitor.check(&sample(95.0, 78.0))), vec![ErrorCode::HostCpuLoad]);
+        // Hovering just under the threshold does not re-arm
+        assert!(monitor.check(&sample(50.0, 73.0)).is_empty());
+        assert!(monitor.check(&sample(50.0, 76.0)).is_empty());
+        monitor.check(&sample(50.0, 60.0));
+        assert_eq!(codes(monitor.check(&sample(50.0, 76.0))), vec![ErrorCode::HostThermal]);
+    }
+}
@@ -0,0 +1,246 @@
+//! In-memory structured log store.
+//!
+//! A tracing layer copies every log event into a bounded ring buffer so the
+//! most recent history can be retrieved over the protocol without touching
+//! the filesystem. Oldest entries are evicted once the buffer is full.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt::Write as _;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use tracing::field::{Field, Visit};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::layer::{Context, Layer};
+
+use protocol::{LogEntry, LogLevel, LogQuery, LogsResponse};
+
+/// Default number of entries retained in memory.
+pub const DEFAULT_LOG_CAPACITY: usize = 10_000;
+
+/// Maximum entries returned by a single query.
+pub const MAX_QUERY_RESULTS: usize = 5_000;
+
+/// Prefix stripped from tracing targets to derive the subsystem name.
+const CRATE_PREFIX: &str = "hypergcode_firmware::";
+
+/// Bounded ring buffer of recent log entries.
+#[derive(Clone)]
+pub struct LogStore {
+    inner: Arc<Mutex<LogBuffer>>,
+}
+
+struct LogBuffer {
+    entries: VecDeque<LogEntry>,
+    capacity: usize,
+    dropped: u64,
+}
+
+impl LogStore {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(LogBuffer {
+                entries: VecDeque::with_capacity(capacity.min(DEFAULT_LOG_CAPACITY)),
+                capacity: capacity.max(1),
+                dropped: 0,
+            })),
+        }
+    }
+
+    /// Process-wide store fed by the layer installed at startup.
+    pub fn global() -> &'static LogStore {
+        static STORE: OnceLock<LogStore> = OnceLock::new();
+        STORE.get_or_init(|| LogStore::new(DEFAULT_LOG_CAPACITY))
+    }
+
+    /// Creates a tracing layer that records into this store.
+    pub fn layer(&self) -> LogCaptureLayer {
+        LogCaptureLayer { store: self.clone() }
+    }
+
+    /// Appends an entry, evicting the oldest one when full.
+    pub fn push(&self, entry: LogEntry) {
+        let mut buffer = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        if buffer.entries.len() >= buffer.capacity {
+            buffer.entries.pop_front();
+            buffer.dropped += 1;
+        }
+        buffer.entries.push_back(entry);
+    }
+
+    /// Number of entries currently retained.
+    pub fn len(&self) -> usize {
+        self.inner.lock().map(|b| b.entries.len()).unwrap_or(0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns entries matching the query, oldest first.
+    ///
+    /// When more entries match than the limit allows, the most recent ones
+    /// are returned and `truncated` is set.
+    pub fn query(&self, query: &LogQuery) -> LogsResponse {
+        let buffer = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        let limit = query
+            .limit
+            .map(|l| l.min(MAX_QUERY_RESULTS))
+            .unwrap_or(MAX_QUERY_RESULTS);
+
+        let matching: Vec<&LogEntry> = buffer
+            .entries
+            .iter()
+            .filter(|e| query.matches(e))
+            .collect();
+
+        let truncated = matching.len() > limit;
+        let entries = matching
+            .into_iter()
+            .rev()
+            .take(limit)
+            .rev()
+            .cloned()
+            .collect();
+
+        LogsResponse {
+            entries,
+            truncated,
+            dropped: buffer.dropped,
+        }
+    }
+}
+
+/// Tracing layer that copies events into a [`LogStore`].
+pub struct LogCaptureLayer {
+    store: LogStore,
+}
+
+impl<S: Subscriber> Layer<S> for LogCaptureLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let metadata = event.metadata();
+        let mut visitor = FieldVisitor::default();
+        event.record(&mut visitor);
+
+        let timestamp_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0);
+
+        self.store.push(LogEntry {
+            timestamp_ms,
+            level: level_from_tracing(metadata.level()),
+            subsystem: subsystem_from_target(metadata.target()),
+            target: metadata.target().to_string(),
+            message: visitor.message,
+            fields: visitor.fields,
+        });
+    }
+}
+
+#[derive(Default)]
+struct FieldVisitor {
+    message: String,
+    fields: BTreeMap<String, String>,
+}
+
+impl Visit for FieldVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "message" {
+            write!(self.message, "{:?}", value).ok();
+        } else {
+            self.fields
+                .insert(field.name().to_string(), format!("{:?}", value));
+        }
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message.push_str(value);
+        } else {
+            self.fields.insert(field.name().to_string(), value.to_string());
+        }
+    }
+}
+
+fn level_from_tracing(level: &Level) -> LogLevel {
+    match *level {
+        Level::TRACE => LogLevel::Trace,
+        Level::DEBUG => LogLevel::Debug,
+        Level::INFO => LogLevel::Info,
+        Level::WARN => LogLevel::Warn,
+        Level::ERROR => LogLevel::Error,
+    }
+}
+
+/// Maps a tracing target to the firmware subsystem that emitted it.
+///
+/// `hypergcode_firmware::hardware::heaters` becomes `hardware`; targets from
+/// other crates keep their crate name.
+fn subsystem_from_target(target: &str) -> String {
+    let trimmed = target.strip_prefix(CRATE_PREFIX).unwrap_or(target);
+    trimmed.split("::").next().unwrap_or(trimmed).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(ts: u64, level: LogLevel, subsystem: &str) -> LogEntry {
+        LogEntry {
+            timestamp_ms: ts,
+            level,
+            subsystem: subsystem.to_string(),
+            target: format!("{}{}", CRATE_PREFIX, subsystem),
+            message: format!("event {}", ts),
+            fields: BTreeMap::new(),
+        }
+    }
+
+    #[test]
+    fn test_ring_buffer_evicts_oldest() {
+        let store = LogStore::new(3);
+        for ts in 0..5 {
+            store.push(entry(ts, LogLevel::Info, "core"));
+        }
+
+        let response = store.query(&LogQuery::default());
+        assert_eq!(response.entries.len(), 3);
+        assert_eq!(response.entries[0].timestamp_ms, 2);
+        assert_eq!(response.dropped, 2);
+    }
+
+    #[test]
+    fn test_query_filters() {
+        let store = LogStore::new(100);
+        store.push(entry(10, LogLevel::Debug, "hardware"));
+        store.push(entry(20, LogLevel::Warn, "hardware"));
+        store.push(entry(30, LogLevel::Error, "safety"));
+
+        let query = LogQuery {
+            min_level: Some(LogLevel::Warn),
+            subsystem: Some("hardware".to_string()),
+            ..Default::default()
+        };
+        let response = store.query(&query);
+        assert_eq!(response.entries.len(), 1);
+        assert_eq!(response.entries[0].timestamp_ms, 20);
+
+        let query = LogQuery {
+            since_ms: Some(15),
+            until_ms: Some(30),
+            limit: Some(1),
+            ..Default::default()
+        };
+        let response = store.query(&query);
+        assert_eq!(response.entries.len(), 1);
+        assert_eq!(response.entries[0].timestamp_ms, 30);
+        assert!(response.truncated);
+    }
+
+    #[test]
+    fn test_subsystem_from_target() {
+        assert_eq!(subsystem_from_target("hypergcode_firmware::hardware::heaters"), "hardware");
+        assert_eq!(subsystem_from_target("protocol"), "protocol");
+    }
+}
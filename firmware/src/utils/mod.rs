@@ -7,12 +7,21 @@
 //!
 //! - **timing**: Precise timing utilities
 //! - **math**: Math operations optimized for embedded
-//! - **buffer**: Ring buffers and data structures
+//! - **buffer**: Ring buffers, including a lock-free SPSC ring for valve frames
+//! - **log_store**: In-memory structured log capture
+//! - **trace_recorder**: Protocol trace files for replay in the simulator
+//! - **host_telemetry**: CPU, memory, SoC temperature and disk use of the host
 
 pub mod timing;
 pub mod math;
 pub mod buffer;
+pub mod log_store;
+pub mod trace_recorder;
+pub mod host_telemetry;
 
-pub use timing::{precise_sleep, timestamp};
+pub use timing::{precise_sleep, precise_sleep_until, timestamp};
 pub use math::{pid_control, interpolate_linear};
-pub use buffer::RingBuffer;
+pub use buffer::{spsc_channel, Consumer, Producer, RingBuffer};
+pub use log_store::LogStore;
+pub use trace_recorder::TraceRecorder;
+pub use host_telemetry::{HostMonitor, HostSampler, HostWarning};
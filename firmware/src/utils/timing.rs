@@ -0,0 +1,63 @@
+//! Precise timing for threads outside the async runtime.
+//!
+//! `std::thread::sleep` on Linux wakes late by the thread's timer slack
+//! (50 µs by default) plus scheduling latency, which can reach several
+//! hundred microseconds under load. [`precise_sleep_until`] therefore
+//! sleeps only until [`SPIN_THRESHOLD`] before the deadline and spins the
+//! rest, which keeps wake-ups within a few microseconds on an otherwise
+//! idle core. The price is up to one threshold of busy CPU per call, so
+//! these functions belong on dedicated blocking threads (SPI writer, step
+//! pulses), never on the tokio workers.
+
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+/// Remaining wait below which the caller spins instead of sleeping.
+pub const SPIN_THRESHOLD: Duration = Duration::from_micros(200);
+
+/// Blocks the calling thread for `duration`.
+pub fn precise_sleep(duration: Duration) {
+    precise_sleep_until(Instant::now() + duration);
+}
+
+/// Blocks the calling thread until `deadline`, sleeping while far away and
+/// spinning close to it. Returns immediately if the deadline has passed.
+pub fn precise_sleep_until(deadline: Instant) {
+    loop {
+        let now = Instant::now();
+        if now >= deadline {
+            return;
+        }
+        let remaining = deadline - now;
+        if remaining > SPIN_THRESHOLD {
+            std::thread::sleep(remaining - SPIN_THRESHOLD);
+        } else {
+            std::hint::spin_loop();
+        }
+    }
+}
+
+/// Monotonic microseconds since the first call in this process.
+pub fn timestamp() -> u64 {
+    static EPOCH: OnceLock<Instant> = OnceLock::new();
+    EPOCH.get_or_init(Instant::now).elapsed().as_micros() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_precise_sleep_never_wakes_early() {
+        let first = timestamp();
+        for micros in [0, 50, 300, 1500] {
+            let duration = Duration::from_micros(micros);
+            let start = Instant::now();
+            precise_sleep(duration);
+            assert!(start.elapsed() >= duration);
+        }
+        assert!(timestamp() >= first + 1850);
+
+        precise_sleep_until(Instant::now() - Duration::from_millis(1));
+    }
+}
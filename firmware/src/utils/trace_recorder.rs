@@ -0,0 +1,114 @@
+//! Protocol trace recording.
+//!
+//! With `--record-trace FILE` every broadcast, every client request and its
+//! response are written to a [`protocol::trace`] file with their offsets
+//! from the start of recording, for replay in the simulator. Entries are
+//! flushed as they are written so a trace survives a crash up to its last
+//! complete line.
+
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use tokio::sync::broadcast;
+use tracing::{debug, info, warn};
+
+use protocol::{ProtocolMessage, TraceDirection, TraceEntry, TraceHeader, TraceWriter};
+
+use crate::FIRMWARE_VERSION;
+
+/// Writes protocol messages to a trace file.
+pub struct TraceRecorder {
+    started: Instant,
+    writer: Mutex<TraceWriter<BufWriter<File>>>,
+}
+
+impl TraceRecorder {
+    /// Creates the trace file, replacing any existing one.
+    pub fn create(path: &Path) -> Result<Self> {
+        let file = File::create(path)
+            .with_context(|| format!("Failed to create trace {}", path.display()))?;
+        let header = TraceHeader {
+            version: protocol::trace::TRACE_VERSION,
+            started_at_ms: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_millis() as u64)
+                .unwrap_or(0),
+            source: format!("hg4d-firmware {}", FIRMWARE_VERSION),
+        };
+        let writer = TraceWriter::new(BufWriter::new(file), &header)?;
+        info!("Recording protocol trace to {}", path.display());
+
+        Ok(Self {
+            started: Instant::now(),
+            writer: Mutex::new(writer),
+        })
+    }
+
+    /// Appends one message. Write failures are logged, not returned, so a
+    /// full disk never interrupts a print.
+    pub fn record(&self, direction: TraceDirection, message: &ProtocolMessage) {
+        let entry = TraceEntry {
+            offset_ms: self.started.elapsed().as_millis() as u64,
+            direction,
+            message: message.clone(),
+        };
+        let Ok(mut writer) = self.writer.lock() else {
+            return;
+        };
+        if let Err(e) = writer.record(&entry).and_then(|_| writer.flush()) {
+            warn!("Failed to write trace entry: {}", e);
+        }
+    }
+
+    /// Records every broadcast until shutdown.
+    pub async fn run(
+        &self,
+        mut broadcasts: broadcast::Receiver<ProtocolMessage>,
+        mut shutdown: broadcast::Receiver<()>,
+    ) -> Result<()> {
+        loop {
+            tokio::select! {
+                msg = broadcasts.recv() => match msg {
+                    Ok(msg) => self.record(TraceDirection::Outbound, &msg),
+                    Err(broadcast::error::RecvError::Lagged(n)) => {
+                        warn!("Trace recorder skipped {} messages", n);
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                },
+                _ = shutdown.recv() => break,
+            }
+        }
+        debug!("Trace recording stopped");
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use protocol::{create_status_update, Trace};
+
+    #[tokio::test]
+    async fn test_records_broadcasts() {
+        let path = std::env::temp_dir().join(format!("hg4d-trace-{}.jsonl", std::process::id()));
+        let recorder = TraceRecorder::create(&path).unwrap();
+        recorder.record(TraceDirection::Inbound, &ProtocolMessage::GetMaterials);
+
+        let (tx, rx) = broadcast::channel(8);
+        let (shutdown_tx, shutdown_rx) = broadcast::channel(1);
+        tx.send(create_status_update("Printing", 1, 10, 0.2, 0, 0)).unwrap();
+        drop(tx);
+        recorder.run(rx, shutdown_rx).await.unwrap();
+        drop(shutdown_tx);
+
+        let trace = Trace::from_file(&path).unwrap();
+        assert_eq!(trace.entries.len(), 2);
+        assert_eq!(trace.entries[0].direction, TraceDirection::Inbound);
+        assert_eq!(trace.entries[1].message.message_type(), "StatusUpdate");
+        std::fs::remove_file(&path).ok();
+    }
+}
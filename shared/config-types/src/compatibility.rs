@@ -0,0 +1,223 @@
+//! Which materials can share a print.
+//!
+//! Some material pairs cannot be co-printed: their bed temperatures do not
+//! overlap, one degrades at the other's extrusion temperature, or they do
+//! not bond. A [`CompatibilityMatrix`] rates pairs of [`MaterialType`]s as
+//! [`Compatibility::Allowed`], [`Compatibility::Warned`] or
+//! [`Compatibility::Forbidden`], with notes explaining why.
+//!
+//! The printer's own rules (`materials.compatibility` in the printer
+//! configuration) are consulted first, then a built-in table of well-known
+//! pairs. Pairs in neither, including two channels of the same type, are
+//! allowed:
+//!
+//! ```toml
+//! [[materials.compatibility.rules]]
+//! materials = ["PLA", "ABS"]
+//! level = "allowed"
+//! notes = "Enclosed chamber keeps PLA below its glass transition"
+//! ```
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::{MaterialProfile, MaterialType};
+
+/// How well two materials co-print, from best to worst.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Compatibility {
+    Allowed,
+    /// Prints, but the result is likely to suffer
+    Warned,
+    /// Must not be printed together without an explicit override
+    Forbidden,
+}
+
+/// Rating of one material pair, in either order.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CompatibilityRule {
+    pub materials: [MaterialType; 2],
+    pub level: Compatibility,
+    #[serde(default)]
+    pub notes: Option<String>,
+}
+
+impl CompatibilityRule {
+    pub fn matches(&self, a: MaterialType, b: MaterialType) -> bool {
+        self.materials == [a, b] || self.materials == [b, a]
+    }
+}
+
+/// Pairs known not to co-print well, rated when the printer has no rule.
+const BUILTIN_RULES: &[(MaterialType, MaterialType, Compatibility, &str)] = {
+    use Compatibility::*;
+    use MaterialType::*;
+    &[
+        (PLA, ABS, Warned, "Bed temperatures differ by about 40°C; PLA softens on an ABS bed"),
+        (PLA, ASA, Warned, "Bed temperatures differ by about 40°C; PLA softens on an ASA bed"),
+        (PLA, PC, Forbidden, "PLA degrades at polycarbonate extrusion and bed temperatures"),
+        (PLA, Nylon, Warned, "Poor adhesion between PLA and nylon"),
+        (PLA, HIPS, Warned, "HIPS bonds poorly to PLA"),
+        (PVA, ABS, Forbidden, "PVA degrades at ABS extrusion temperatures"),
+        (PVA, ASA, Forbidden, "PVA degrades at ASA extrusion temperatures"),
+        (PVA, PC, Forbidden, "PVA degrades at polycarbonate extrusion temperatures"),
+        (PVA, Nylon, Warned, "PVA is near its degradation limit at nylon temperatures"),
+        (TPU, PC, Warned, "TPU degrades on a polycarbonate bed"),
+        (PETG, ABS, Warned, "PETG and ABS bond poorly"),
+    ]
+};
+
+/// Material pair ratings; see the module documentation.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CompatibilityMatrix {
+    /// Printer-specific ratings, taking precedence over the built-in table;
+    /// the last rule for a pair wins
+    #[serde(default)]
+    pub rules: Vec<CompatibilityRule>,
+}
+
+impl CompatibilityMatrix {
+    /// Rating of a pair and the reason given for it, if any.
+    pub fn lookup(&self, a: MaterialType, b: MaterialType) -> (Compatibility, Option<&str>) {
+        if let Some(rule) = self.rules.iter().rev().find(|rule| rule.matches(a, b)) {
+            return (rule.level, rule.notes.as_deref());
+        }
+        BUILTIN_RULES
+            .iter()
+            .find(|(x, y, _, _)| (*x, *y) == (a, b) || (*x, *y) == (b, a))
+            .map(|&(_, _, level, notes)| (level, Some(notes)))
+            .unwrap_or((Compatibility::Allowed, None))
+    }
+
+    /// Every pair of `(channel, material)` that is not allowed, each pair
+    /// once, lower channel first.
+    pub fn check(&self, materials: &[(u8, &MaterialProfile)]) -> Vec<MaterialConflict> {
+        let mut conflicts = Vec::new();
+        for (i, &(channel_a, a)) in materials.iter().enumerate() {
+            for &(channel_b, b) in &materials[i + 1..] {
+                let (level, notes) = self.lookup(a.material_type, b.material_type);
+                if level == Compatibility::Allowed {
+                    continue;
+                }
+                let ((channel_a, a), (channel_b, b)) = if channel_a <= channel_b {
+                    ((channel_a, a), (channel_b, b))
+                } else {
+                    ((channel_b, b), (channel_a, a))
+                };
+                conflicts.push(MaterialConflict {
+                    channels: (channel_a, channel_b),
+                    materials: (a.name.clone(), b.name.clone()),
+                    level,
+                    notes: notes.map(str::to_string),
+                });
+            }
+        }
+        conflicts
+    }
+}
+
+/// Two loaded materials that should not share a print.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MaterialConflict {
+    pub channels: (u8, u8),
+    /// Profile names, in channel order
+    pub materials: (String, String),
+    pub level: Compatibility,
+    pub notes: Option<String>,
+}
+
+impl fmt::Display for MaterialConflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let verdict = match self.level {
+            Compatibility::Allowed => "can be printed with",
+            Compatibility::Warned => "prints poorly with",
+            Compatibility::Forbidden => "must not be printed with",
+        };
+        write!(
+            f,
+            "{} (channel {}) {} {} (channel {})",
+            self.materials.0, self.channels.0, verdict, self.materials.1, self.channels.1
+        )?;
+        if let Some(notes) = &self.notes {
+            write!(f, ": {}", notes)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CoolingParameters, ExtrusionParameters, MaterialProperties, PurgeParameters};
+
+    fn material(name: &str, material_type: MaterialType) -> MaterialProfile {
+        MaterialProfile {
+            name: name.to_string(),
+            material_type,
+            temp_range: (190.0, 230.0),
+            optimal_temp: 210.0,
+            bed_temp: 60.0,
+            properties: MaterialProperties {
+                density: 1.24,
+                viscosity: 100.0,
+                glass_transition_temp: 60.0,
+                thermal_conductivity: 0.13,
+                shrinkage: 0.3,
+            },
+            extrusion: ExtrusionParameters {
+                pressure_psi: 50.0,
+                flow_multiplier: 1.0,
+                retraction_distance: 0.0,
+                retraction_speed: 0.0,
+            },
+            purge: PurgeParameters {
+                purge_volume_incoming: 10.0,
+                purge_volume_outgoing: 10.0,
+                purge_temp: None,
+            },
+            cooling: CoolingParameters {
+                min_layer_time: 5.0,
+                requires_cooling: true,
+                initial_fan_speed: 0.0,
+                regular_fan_speed: 100.0,
+            },
+            color: None,
+            drying: None,
+            chamber_temp: None,
+        }
+    }
+
+    #[test]
+    fn test_printer_rules_override_builtin_table() {
+        let pla = material("PLA", MaterialType::PLA);
+        let abs = material("ABS", MaterialType::ABS);
+        let pva = material("PVA", MaterialType::PVA);
+        let matrix = CompatibilityMatrix::default();
+        assert_eq!(matrix.lookup(MaterialType::ABS, MaterialType::PLA).0, Compatibility::Warned);
+        assert_eq!(matrix.lookup(MaterialType::PLA, MaterialType::PLA), (Compatibility::Allowed, None));
+
+        // Listed out of channel order; reported lower channel first
+        let conflicts = matrix.check(&[(2, &pva), (0, &pla), (1, &abs)]);
+        assert_eq!(conflicts.len(), 2);
+        assert_eq!(conflicts[0].channels, (1, 2));
+        assert_eq!(conflicts[0].level, Compatibility::Forbidden);
+        assert_eq!(conflicts[0].materials, ("ABS".to_string(), "PVA".to_string()));
+        let message = conflicts[0].to_string();
+        assert!(message.starts_with("ABS (channel 1) must not be printed with PVA (channel 2)"));
+        assert_eq!(conflicts[1].channels, (0, 1));
+
+        let matrix: CompatibilityMatrix = toml::from_str(
+            r#"
+            [[rules]]
+            materials = ["ABS", "PLA"]
+            level = "allowed"
+            notes = "Heated chamber"
+            "#,
+        )
+        .unwrap();
+        let rating = matrix.lookup(MaterialType::PLA, MaterialType::ABS);
+        assert_eq!(rating, (Compatibility::Allowed, Some("Heated chamber")));
+        assert_eq!(matrix.check(&[(0, &pla), (1, &abs)]), vec![]);
+    }
+}
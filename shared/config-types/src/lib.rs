@@ -11,14 +11,39 @@
 //! - **Material Profiles**: Material-specific parameters for extrusion and deposition
 //! - **Print Settings**: User-adjustable parameters for specific print jobs
 //! 
+//! Print settings can be assembled from presets and layered overrides; see
+//! [`presets`].
+//! 
+//! Safety limits and valve dead volume use the unit types from
+//! [`gcode_types::units`], re-exported here; they serialize as bare numbers.
+//! 
 //! ## File Format
 //! 
 //! Configurations are stored as TOML files for human readability and easy editing.
 //! The slicer and firmware can load these files at startup or runtime.
+//! 
+//! Printer configurations and material profiles are saved atomically with
+//! timestamped backups beside them; see [`persist`].
+//! 
+//! Which material types may share a multi-material print is rated by a
+//! [`CompatibilityMatrix`] in the printer's material system; see
+//! [`compatibility`].
 
 use serde::{Deserialize, Serialize};
 use std::path::{Path, PathBuf};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+
+pub use gcode_types::units::{Celsius, CubicMm, Millimeters, Psi};
+
+pub mod compatibility;
+pub mod persist;
+pub mod presets;
+
+pub use compatibility::{Compatibility, CompatibilityMatrix, CompatibilityRule, MaterialConflict};
+
+pub use presets::{
+    AppliedOverride, PresetLibrary, ResolvedSettings, SettingsLayer, SettingsPreset, SettingsStack,
+};
 
 /// Complete printer configuration describing hardware capabilities.
 /// 
@@ -50,6 +75,32 @@ pub struct PrinterConfig {
     
     /// Optional metadata
     pub metadata: PrinterMetadata,
+    
+    /// Sensor hardware wiring and calibration
+    #[serde(default)]
+    pub sensors: Vec<SensorDefinition>,
+    
+    /// Power-loss detection and shutdown, if a UPS or undervoltage signal
+    /// is wired
+    #[serde(default)]
+    pub power_loss: Option<PowerLossConfig>,
+    
+    /// Bed flatness measurement and first-layer compensation
+    #[serde(default)]
+    pub bed_level: Option<BedLevelConfig>,
+    
+    /// External camera triggered at inspection pauses
+    #[serde(default)]
+    pub camera: Option<CameraConfig>,
+    
+    /// Sampling and warning thresholds for the controller board's own load,
+    /// memory, temperature and disk space
+    #[serde(default)]
+    pub host_telemetry: HostTelemetryConfig,
+    
+    /// Operator routines run on demand outside a print
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub macros: Vec<MacroDefinition>,
 }
 
 impl PrinterConfig {
@@ -63,12 +114,25 @@ impl PrinterConfig {
     }
 
     /// Saves printer configuration to a TOML file.
+    ///
+    /// The file is replaced atomically and the previous version kept as a
+    /// backup (the newest [`persist::DEFAULT_BACKUP_COUNT`] are kept).
     pub fn to_file<P: AsRef<Path>>(&self, path: P) -> Result<(), ConfigError> {
         let contents = toml::to_string_pretty(self)
             .map_err(|e| ConfigError::SerializationError(e.to_string()))?;
         
-        std::fs::write(path.as_ref(), contents)
-            .map_err(|e| ConfigError::IoError(e.to_string()))
+        persist::write_with_backups(path.as_ref(), contents.as_bytes(), persist::DEFAULT_BACKUP_COUNT)
+    }
+
+    /// Restores the newest backup of the file at `path` that loads and
+    /// validates, and returns it.
+    pub fn restore_backup<P: AsRef<Path>>(path: P) -> Result<Self, ConfigError> {
+        persist::restore_latest(path.as_ref(), |contents| {
+            let config: Self = toml::from_str(contents)
+                .map_err(|e| ConfigError::ParseError(e.to_string()))?;
+            config.validate()?;
+            Ok(config)
+        })
     }
 
     /// Validates that configuration values are physically reasonable.
@@ -98,6 +162,17 @@ impl PrinterConfig {
             ));
         }
 
+        // Driver boards are told apart by ID
+        let mut board_ids = self.valve_array.drivers.board_ids.clone();
+        board_ids.sort_unstable();
+        if let Some(pair) = board_ids.windows(2).find(|pair| pair[0] == pair[1]) {
+            return Err(ConfigError::InvalidConfiguration(
+                format!("Valve driver board ID 0x{:04x} is listed twice", pair[0])
+            ));
+        }
+
+        self.valve_array.validate_buses().map_err(ConfigError::InvalidConfiguration)?;
+
         // Validate temperature ranges
         for zone in &self.thermal.zones {
             if zone.min_temp >= zone.max_temp {
@@ -108,6 +183,155 @@ impl PrinterConfig {
             }
         }
 
+        // Zone regions must lie on the valve grid
+        let (grid_x, grid_y) = (self.grid_x_count(), self.grid_y_count());
+        for zone in &self.thermal.zones {
+            for region in &zone.regions {
+                let valid = match *region {
+                    ZoneRegion::Rect { x_min, y_min, x_max, y_max } => {
+                        x_min < x_max && y_min < y_max
+                            && x_min >= 0.0 && y_min >= 0.0
+                            && x_max <= self.build_volume.x && y_max <= self.build_volume.y
+                    }
+                    ZoneRegion::Nodes { x: (x0, x1), y: (y0, y1) } => {
+                        x0 <= x1 && y0 <= y1 && x1 < grid_x && y1 < grid_y
+                    }
+                };
+                if !valid {
+                    return Err(ConfigError::InvalidConfiguration(
+                        format!("Zone {} region {:?} is empty or outside the {}x{} valve grid",
+                            zone.id, region, grid_x, grid_y)
+                    ));
+                }
+            }
+        }
+
+        // Bed and chamber are read through sensor zones of their own
+        let mut sensor_zones: Vec<(u8, &str)> = Vec::new();
+        if let Some(bed) = &self.thermal.bed {
+            if bed.min_temp >= bed.max_temp {
+                return Err(ConfigError::InvalidConfiguration(
+                    format!("Invalid bed temperature range: min {} >= max {}",
+                        bed.min_temp, bed.max_temp)
+                ));
+            }
+            sensor_zones.push((bed.sensor_zone, "bed"));
+        }
+        if let Some(zone) = self.thermal.chamber.as_ref().and_then(|c| c.sensor_zone) {
+            sensor_zones.push((zone, "chamber"));
+        }
+        for (i, &(zone, name)) in sensor_zones.iter().enumerate() {
+            if self.thermal.zone(zone).is_some() || sensor_zones[..i].iter().any(|(z, _)| *z == zone) {
+                return Err(ConfigError::InvalidConfiguration(
+                    format!("The {} sensor zone {} is already in use", name, zone)
+                ));
+            }
+        }
+
+        // Every zone heater must fit the supply on its own
+        if let Some(supply) = self.thermal.supply_watts {
+            if supply <= 0.0 {
+                return Err(ConfigError::InvalidConfiguration(
+                    "Heater supply capacity must be positive".to_string()
+                ));
+            }
+            if let Some(zone) = self.thermal.zones.iter().find(|z| z.power_watts > supply) {
+                return Err(ConfigError::InvalidConfiguration(
+                    format!("Zone {} heater ({} W) exceeds the {} W heater supply",
+                        zone.id, zone.power_watts, supply)
+                ));
+            }
+            if let Some(bed) = self.thermal.bed.as_ref().filter(|b| b.power_watts > supply) {
+                return Err(ConfigError::InvalidConfiguration(
+                    format!("Bed heater ({} W) exceeds the {} W heater supply",
+                        bed.power_watts, supply)
+                ));
+            }
+        }
+
+        // Validate channel-to-zone mapping
+        let mut mapped_channels = std::collections::HashSet::new();
+        for mapping in &self.thermal.channel_zones {
+            if mapping.channel >= self.materials.channel_count {
+                return Err(ConfigError::InvalidConfiguration(
+                    format!("Zone mapping refers to channel {} but printer has {} channels",
+                        mapping.channel, self.materials.channel_count)
+                ));
+            }
+            if !mapped_channels.insert(mapping.channel) {
+                return Err(ConfigError::InvalidConfiguration(
+                    format!("Channel {} is mapped to zones more than once", mapping.channel)
+                ));
+            }
+            if let Some(zone) = mapping.zones.iter().find(|z| self.thermal.zone(**z).is_none()) {
+                return Err(ConfigError::InvalidConfiguration(
+                    format!("Channel {} is mapped to undefined zone {}", mapping.channel, zone)
+                ));
+            }
+        }
+
+        if let Some(bed_level) = &self.bed_level {
+            bed_level.validate().map_err(|e| ConfigError::InvalidConfiguration(
+                format!("Bed level: {}", e)
+            ))?;
+        }
+
+        // Macros are invoked by name
+        let mut macro_names = std::collections::HashSet::new();
+        for definition in &self.macros {
+            if !macro_names.insert(definition.name.as_str()) {
+                return Err(ConfigError::InvalidConfiguration(
+                    format!("Duplicate macro '{}'", definition.name)
+                ));
+            }
+            definition.validate().map_err(|e| ConfigError::InvalidConfiguration(
+                format!("Macro '{}': {}", definition.name, e)
+            ))?;
+        }
+
+        // One runout switch per channel, priming through a known macro
+        let mut runout_channels = std::collections::HashSet::new();
+        for sensor in &self.materials.runout_sensors {
+            if sensor.channel >= self.materials.channel_count {
+                return Err(ConfigError::InvalidConfiguration(
+                    format!("Runout sensor refers to channel {} but printer has {} channels",
+                        sensor.channel, self.materials.channel_count)
+                ));
+            }
+            if !runout_channels.insert(sensor.channel) {
+                return Err(ConfigError::InvalidConfiguration(
+                    format!("Channel {} has more than one runout sensor", sensor.channel)
+                ));
+            }
+            if let Some(name) = sensor.prime_macro.as_ref().filter(|n| !macro_names.contains(n.as_str())) {
+                return Err(ConfigError::InvalidConfiguration(
+                    format!("Channel {} runout sensor primes with undefined macro '{}'",
+                        sensor.channel, name)
+                ));
+            }
+        }
+
+        // Validate sensor definitions
+        let mut sensor_ids = std::collections::HashSet::new();
+        for sensor in &self.sensors {
+            if !sensor_ids.insert(sensor.id.as_str()) {
+                return Err(ConfigError::InvalidConfiguration(
+                    format!("Duplicate sensor id '{}'", sensor.id)
+                ));
+            }
+            sensor.calibration.validate().map_err(|e| ConfigError::InvalidConfiguration(
+                format!("Sensor '{}': {}", sensor.id, e)
+            ))?;
+        }
+
+        self.host_telemetry.validate().map_err(|e| ConfigError::InvalidConfiguration(
+            format!("Host telemetry: {}", e)
+        ))?;
+
+        self.safety.heartbeat.validate().map_err(|e| ConfigError::InvalidConfiguration(
+            format!("Heartbeat: {}", e)
+        ))?;
+
         Ok(())
     }
 
@@ -198,14 +422,121 @@ pub struct ValveArrayConfig {
     /// Valve response time (ms)
     pub response_time_ms: f32,
     
-    /// Dead volume per valve (mm³)
-    pub dead_volume: f32,
+    /// Dead volume per valve
+    pub dead_volume: CubicMm,
     
     /// Maximum valve switching frequency (Hz)
     pub max_switching_freq: f32,
     
     /// Material injection points
     pub injection_points: Vec<InjectionPoint>,
+    
+    /// Commanded-vs-feedback verification during printing
+    #[serde(default)]
+    pub verification: ValveVerificationConfig,
+
+    /// Driver boards switching the valves
+    #[serde(default)]
+    pub drivers: ValveDriverConfig,
+
+    /// Staggered opening of dense layers
+    #[serde(default)]
+    pub sub_frames: SubFrameConfig,
+
+    /// How long a valve state written to the drivers is trusted (ms):
+    /// repeating it within the window skips the write, later it is written
+    /// again. 0, the default, writes every frame in full
+    #[serde(default)]
+    pub frame_merge_window_ms: f32,
+}
+
+impl ValveArrayConfig {
+    /// Driver boards needed for every valve: the configured board IDs if
+    /// listed, otherwise enough boards for all outputs.
+    pub fn expected_driver_boards(&self) -> usize {
+        if !self.drivers.board_ids.is_empty() {
+            return self.drivers.board_ids.len();
+        }
+        let valves = self.total_nodes as u64 * self.valves_per_node as u64;
+        valves.div_ceil(self.drivers.outputs_per_board.max(1) as u64) as usize
+    }
+
+    /// How the array is split across SPI chains, in board order. A single
+    /// chain covers every board and node.
+    pub fn bus_partitions(&self) -> Vec<ValveBusPartition> {
+        let outputs = self.drivers.outputs_per_board as u64;
+        let valves_per_node = self.valves_per_node.max(1) as u64;
+        let node_at = |board: u32| {
+            ((board as u64 * outputs).div_ceil(valves_per_node)).min(self.total_nodes as u64) as u32
+        };
+
+        if self.drivers.buses.is_empty() {
+            let boards = self.expected_driver_boards() as u32;
+            return vec![ValveBusPartition {
+                spi_bus: self.drivers.spi_bus,
+                chip_select: self.drivers.chip_select,
+                boards: 0..boards,
+                nodes: 0..self.total_nodes,
+            }];
+        }
+        let mut partitions: Vec<ValveBusPartition> = self
+            .drivers
+            .buses
+            .iter()
+            .map(|bus| {
+                let end = bus.first_board + bus.board_count;
+                ValveBusPartition {
+                    spi_bus: bus.spi_bus,
+                    chip_select: bus.chip_select,
+                    boards: bus.first_board..end,
+                    nodes: node_at(bus.first_board)..node_at(end),
+                }
+            })
+            .collect();
+        partitions.sort_by_key(|p| p.boards.start);
+        partitions
+    }
+
+    /// Checks that the chains cover every board once, that no node's
+    /// valves span two chains and that a shared latch line is wired.
+    fn validate_buses(&self) -> Result<(), String> {
+        let buses = &self.drivers.buses;
+        if buses.is_empty() {
+            return Ok(());
+        }
+        if buses.len() > 1 && self.drivers.latch_pin.is_none() {
+            return Err("several driver buses need a shared latch_pin".to_string());
+        }
+        for (i, bus) in buses.iter().enumerate() {
+            if buses[..i].iter().any(|b| (b.spi_bus, b.chip_select) == (bus.spi_bus, bus.chip_select)) {
+                return Err(format!("driver bus {}.{} is listed twice", bus.spi_bus, bus.chip_select));
+            }
+        }
+
+        let mut next_board = 0;
+        for partition in self.bus_partitions() {
+            let name = format!("driver bus {}.{}", partition.spi_bus, partition.chip_select);
+            if partition.boards.is_empty() {
+                return Err(format!("{} has no boards", name));
+            }
+            if partition.boards.start != next_board {
+                return Err(format!(
+                    "{} starts at board {}, expected {}",
+                    name, partition.boards.start, next_board
+                ));
+            }
+            let first_output = partition.boards.start as u64 * self.drivers.outputs_per_board as u64;
+            if first_output % self.valves_per_node.max(1) as u64 != 0 {
+                return Err(format!("{} starts partway through a node's valves", name));
+            }
+            next_board = partition.boards.end;
+        }
+        let expected = self.expected_driver_boards() as u32;
+        if next_board != expected {
+            return Err(format!("driver buses cover {} boards; the array needs {}", next_board, expected));
+        }
+        Ok(())
+    }
 }
 
 /// Types of valve technology.
@@ -217,6 +548,146 @@ pub enum ValveType {
     Microfluidic,
 }
 
+/// Valve feedback verification settings.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ValveVerificationConfig {
+    /// Compare valve feedback against commanded states
+    pub enabled: bool,
+    
+    /// Re-commands of a mismatched valve before it counts as failed
+    pub max_retries: u32,
+    
+    /// Fraction of the valve assignments a layer commands allowed to fail
+    /// verification (0-1)
+    pub mismatch_threshold: f32,
+    
+    /// What to do when a layer exceeds the threshold
+    pub on_threshold: MismatchAction,
+}
+
+impl Default for ValveVerificationConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_retries: 2,
+            mismatch_threshold: 0.01,
+            on_threshold: MismatchAction::Abort,
+        }
+    }
+}
+
+/// Splitting of a layer's valve openings into sub-frames.
+///
+/// Layers carrying the slicer's activation groups are always split as
+/// sliced; `max_simultaneous_openings` lets the firmware split layers that
+/// carry none.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SubFrameConfig {
+    /// Valve openings one sub-frame may latch; None never splits a layer
+    /// without activation groups
+    pub max_simultaneous_openings: Option<u32>,
+
+    /// Delay between consecutive sub-frames (ms)
+    pub interval_ms: f32,
+
+    /// Most sub-frames a layer is split into at runtime
+    pub max_sub_frames: u8,
+}
+
+impl Default for SubFrameConfig {
+    fn default() -> Self {
+        Self {
+            max_simultaneous_openings: None,
+            interval_ms: 5.0,
+            max_sub_frames: 8,
+        }
+    }
+}
+
+/// Response to a layer exceeding the valve mismatch threshold.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MismatchAction {
+    /// Stop the print
+    Abort,
+    /// Finish the layer and report it as suspect
+    Flag,
+}
+
+/// Valve driver boards daisy-chained on one SPI chip select, or split
+/// across several chains when one cannot refresh the whole array in time.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ValveDriverConfig {
+    pub spi_bus: u8,
+    pub chip_select: u8,
+
+    /// Valve outputs per board
+    pub outputs_per_board: u16,
+
+    /// Board IDs in chain order, nearest the controller first. Empty
+    /// checks only the board count. With `buses`, the array-wide board
+    /// order.
+    #[serde(default)]
+    pub board_ids: Vec<u16>,
+
+    /// Chains sharing the array, each driving a consecutive range of
+    /// boards; empty for a single chain on `spi_bus`/`chip_select`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub buses: Vec<ValveBusConfig>,
+
+    /// GPIO of the latch line shared by every board. Needed with `buses`:
+    /// the boards then load their outputs on its pulse rather than at chip
+    /// select release, so all chains switch together.
+    #[serde(default)]
+    pub latch_pin: Option<u8>,
+
+    /// GPIO of the output enable line shared by every board, active low.
+    /// Releasing it switches every output off at once without shifting a
+    /// frame, which power-loss shutdown falls back to when the valve
+    /// controller is busy.
+    #[serde(default)]
+    pub output_enable_pin: Option<u8>,
+}
+
+impl Default for ValveDriverConfig {
+    fn default() -> Self {
+        Self {
+            spi_bus: 0,
+            chip_select: 0,
+            outputs_per_board: 64,
+            board_ids: Vec::new(),
+            buses: Vec::new(),
+            latch_pin: None,
+            output_enable_pin: None,
+        }
+    }
+}
+
+/// One chain of driver boards when the array spans several SPI buses.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ValveBusConfig {
+    pub spi_bus: u8,
+    pub chip_select: u8,
+
+    /// Position of this chain's first board in the array-wide board order
+    pub first_board: u32,
+
+    /// Boards on this chain
+    pub board_count: u32,
+}
+
+/// The boards and valve nodes one chain drives, from
+/// [`ValveArrayConfig::bus_partitions`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValveBusPartition {
+    pub spi_bus: u8,
+    pub chip_select: u8,
+    /// Array-wide board positions
+    pub boards: std::ops::Range<u32>,
+    /// Node indices (row-major) whose valves those boards switch
+    pub nodes: std::ops::Range<u32>,
+}
+
 /// Material injection point on the valve plane.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct InjectionPoint {
@@ -241,6 +712,153 @@ pub struct ThermalConfig {
     
     /// Build chamber heating (if available)
     pub chamber: Option<ChamberHeating>,
+    
+    /// Heated build plate (if available)
+    #[serde(default)]
+    pub bed: Option<BedHeating>,
+    
+    /// Zones heating each material channel's flow path
+    #[serde(default)]
+    pub channel_zones: Vec<ChannelZoneMapping>,
+    
+    /// Capacity of the heater supply (watts); zone heaters are scheduled so
+    /// their combined draw never exceeds it. Unlimited if absent.
+    #[serde(default)]
+    pub supply_watts: Option<f32>,
+}
+
+/// Thermal zones backing one material channel.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChannelZoneMapping {
+    /// Material channel
+    pub channel: u8,
+    
+    /// Zones the channel's material passes through
+    pub zones: Vec<u8>,
+}
+
+impl ThermalConfig {
+    /// Looks up a zone by identifier.
+    pub fn zone(&self, id: u8) -> Option<&ThermalZone> {
+        self.zones.iter().find(|z| z.id == id)
+    }
+
+    /// Zones backing a material channel (empty if unmapped).
+    pub fn zones_for_channel(&self, channel: u8) -> &[u8] {
+        self.channel_zones
+            .iter()
+            .find(|m| m.channel == channel)
+            .map(|m| m.zones.as_slice())
+            .unwrap_or(&[])
+    }
+
+    /// Whether any zone is tied to an area of the valve grid.
+    pub fn is_spatially_mapped(&self) -> bool {
+        self.zones.iter().any(ThermalZone::is_spatial)
+    }
+
+    /// Zones backing `channel` that heat the node at (`x`, `y`), including
+    /// zones without regions.
+    pub fn zones_at<'a>(
+        &'a self,
+        channel: u8,
+        x: u32,
+        y: u32,
+        grid_spacing: f32,
+    ) -> impl Iterator<Item = &'a ThermalZone> + 'a {
+        self.zones_for_channel(channel)
+            .iter()
+            .filter_map(|&id| self.zone(id))
+            .filter(move |zone| zone.covers_node(x, y, grid_spacing))
+    }
+
+    /// Chooses a target temperature for every zone backing the given
+    /// channels.
+    ///
+    /// Each zone's target must lie within its own limits and within the
+    /// `temp_range` of every material flowing through it. The target is the
+    /// mean of those materials' optimal temperatures, clamped into that
+    /// range. Zones not backing any listed channel are left out.
+    pub fn plan_zone_temperatures(
+        &self,
+        channels: &[(u8, &MaterialProfile)],
+    ) -> Result<BTreeMap<u8, f32>, ConfigError> {
+        // zone -> materials flowing through it
+        let mut zone_materials: BTreeMap<u8, Vec<(u8, &MaterialProfile)>> = BTreeMap::new();
+        for &(channel, material) in channels {
+            let zones = self.zones_for_channel(channel);
+            if zones.is_empty() {
+                return Err(ConfigError::ThermalConflict(format!(
+                    "Channel {} has no thermal zone mapping", channel
+                )));
+            }
+            for &zone in zones {
+                zone_materials.entry(zone).or_default().push((channel, material));
+            }
+        }
+
+        let mut targets = BTreeMap::new();
+        for (zone_id, materials) in zone_materials {
+            let zone = self.zone(zone_id).ok_or_else(|| ConfigError::ThermalConflict(
+                format!("Zone {} is mapped but not defined", zone_id)
+            ))?;
+
+            let (mut low, mut high) = (zone.min_temp, zone.max_temp);
+            for (channel, material) in &materials {
+                let (min, max) = material.temp_range;
+                if min > zone.max_temp || max < zone.min_temp {
+                    return Err(ConfigError::ThermalConflict(format!(
+                        "Zone {} ({}) range {:.0}-{:.0}°C cannot reach {} range {:.0}-{:.0}°C for channel {}",
+                        zone.id, zone.name, zone.min_temp, zone.max_temp,
+                        material.name, min, max, channel
+                    )));
+                }
+                low = low.max(min);
+                high = high.min(max);
+            }
+            if low > high {
+                let names: Vec<String> = materials
+                    .iter()
+                    .map(|(c, m)| format!("{} (channel {})", m.name, c))
+                    .collect();
+                return Err(ConfigError::ThermalConflict(format!(
+                    "Zone {} ({}) is shared by {} with no common temperature",
+                    zone.id, zone.name, names.join(", ")
+                )));
+            }
+
+            let optimal = materials.iter().map(|(_, m)| m.optimal_temp).sum::<f32>()
+                / materials.len() as f32;
+            targets.insert(zone_id, optimal.clamp(low, high));
+        }
+
+        Ok(targets)
+    }
+
+    /// Bed target for the given channels: the hottest `bed_temp` among
+    /// their materials, clamped to the bed's range. None without a heated
+    /// bed or if no material wants one.
+    pub fn plan_bed_temperature(&self, channels: &[(u8, &MaterialProfile)]) -> Option<f32> {
+        let bed = self.bed.as_ref()?;
+        channels
+            .iter()
+            .map(|(_, m)| m.bed_temp)
+            .filter(|&t| t > 0.0)
+            .reduce(f32::max)
+            .map(|t| t.clamp(bed.min_temp, bed.max_temp))
+    }
+
+    /// Chamber target for the given channels: the hottest `chamber_temp`
+    /// among their materials, capped at the chamber's maximum. None without
+    /// a heated chamber or if no material asks for one.
+    pub fn plan_chamber_temperature(&self, channels: &[(u8, &MaterialProfile)]) -> Option<f32> {
+        let chamber = self.chamber.as_ref()?;
+        channels
+            .iter()
+            .filter_map(|(_, m)| m.chamber_temp)
+            .reduce(f32::max)
+            .map(|t| t.min(chamber.max_temp))
+    }
 }
 
 /// Single thermal zone configuration.
@@ -263,6 +881,64 @@ pub struct ThermalZone {
     
     /// PID tuning parameters
     pub pid: PidParameters,
+    
+    /// GPIO (BCM) switching the zone's heater SSR
+    #[serde(default)]
+    pub heater_pin: Option<u8>,
+    
+    /// Valve grid areas the zone heats. Empty for zones that heat a
+    /// channel's whole flow path, e.g. a feed line, wherever it deposits.
+    #[serde(default)]
+    pub regions: Vec<ZoneRegion>,
+    
+    /// Heat capacity of the heated parts (J/K), for preheat planning;
+    /// estimated if absent
+    #[serde(default)]
+    pub thermal_mass: Option<f32>,
+}
+
+impl ThermalZone {
+    /// Whether the zone heats only part of the valve grid.
+    pub fn is_spatial(&self) -> bool {
+        !self.regions.is_empty()
+    }
+
+    /// Whether the zone heats the node at grid position (`x`, `y`). Zones
+    /// without regions heat every node.
+    pub fn covers_node(&self, x: u32, y: u32, grid_spacing: f32) -> bool {
+        !self.is_spatial() || self.regions.iter().any(|r| r.contains_node(x, y, grid_spacing))
+    }
+
+    /// Whether the zone can hold a temperature inside the material's range.
+    pub fn can_hold(&self, material: &MaterialProfile) -> bool {
+        let (min, max) = material.temp_range;
+        min <= self.max_temp && max >= self.min_temp
+    }
+}
+
+/// Area of the valve grid heated by one zone.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ZoneRegion {
+    /// Rectangle on the valve plane (mm); a node belongs to it if its
+    /// center lies inside, edges included
+    Rect { x_min: f32, y_min: f32, x_max: f32, y_max: f32 },
+    /// Node ranges, both ends inclusive
+    Nodes { x: (u32, u32), y: (u32, u32) },
+}
+
+impl ZoneRegion {
+    pub fn contains_node(&self, x: u32, y: u32, grid_spacing: f32) -> bool {
+        match *self {
+            ZoneRegion::Rect { x_min, y_min, x_max, y_max } => {
+                let (px, py) = (x as f32 * grid_spacing, y as f32 * grid_spacing);
+                (x_min..=x_max).contains(&px) && (y_min..=y_max).contains(&py)
+            }
+            ZoneRegion::Nodes { x: (x0, x1), y: (y0, y1) } => {
+                (x0..=x1).contains(&x) && (y0..=y1).contains(&y)
+            }
+        }
+    }
 }
 
 /// PID control parameters for temperature regulation.
@@ -308,6 +984,51 @@ pub struct ChamberHeating {
     
     /// Whether chamber heating is required for operation
     pub required: bool,
+    
+    /// GPIO (BCM) switching the chamber heater SSR
+    #[serde(default)]
+    pub heater_pin: Option<u8>,
+    
+    /// Sensor zone whose thermistor measures the chamber air; must not
+    /// clash with a thermal zone id
+    #[serde(default)]
+    pub sensor_zone: Option<u8>,
+    
+    /// PID tuning parameters
+    #[serde(default)]
+    pub pid: PidParameters,
+    
+    /// Heat capacity of the chamber air and walls (J/K), for preheat
+    /// planning; estimated if absent
+    #[serde(default)]
+    pub thermal_mass: Option<f32>,
+}
+
+/// Heated build plate configuration.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BedHeating {
+    /// Bed heater power (watts)
+    pub power_watts: f32,
+    
+    /// Temperature range
+    pub min_temp: f32,
+    pub max_temp: f32,
+    
+    /// PID tuning parameters
+    pub pid: PidParameters,
+    
+    /// GPIO (BCM) switching the bed heater SSR
+    #[serde(default)]
+    pub heater_pin: Option<u8>,
+    
+    /// Sensor zone whose thermistor measures the plate; must not clash
+    /// with a thermal zone id
+    pub sensor_zone: u8,
+    
+    /// Heat capacity of the plate (J/K), for preheat planning; estimated
+    /// if absent
+    #[serde(default)]
+    pub thermal_mass: Option<f32>,
 }
 
 /// Material system configuration.
@@ -324,6 +1045,41 @@ pub struct MaterialSystemConfig {
     
     /// Pressure system configuration
     pub pressure: PressureConfig,
+    
+    /// Material runout switches, at most one per channel
+    #[serde(default)]
+    pub runout_sensors: Vec<RunoutSensorConfig>,
+    
+    /// Which loaded materials may be printed together
+    #[serde(default)]
+    pub compatibility: CompatibilityMatrix,
+}
+
+/// A switch that detects a channel running out of material.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RunoutSensorConfig {
+    /// Material channel the switch watches
+    pub channel: u8,
+    
+    /// GPIO (BCM) the switch drives
+    pub pin: u8,
+    
+    /// Input reads low when the channel is empty
+    #[serde(default)]
+    pub active_low: bool,
+    
+    /// Input must read empty this long before the print pauses (ms)
+    #[serde(default = "default_runout_debounce_ms")]
+    pub debounce_ms: u32,
+    
+    /// Macro (from `PrinterConfig::macros`) that re-primes the channel
+    /// before the print resumes; without one only pressure is restored
+    #[serde(default)]
+    pub prime_macro: Option<String>,
+}
+
+fn default_runout_debounce_ms() -> u32 {
+    500
 }
 
 /// Single extruder configuration.
@@ -369,6 +1125,51 @@ pub struct PressureConfig {
     
     /// Pressure sensor locations and specifications
     pub sensors: Vec<PressureSensor>,
+    
+    /// Closed-loop control wiring per material channel
+    #[serde(default)]
+    pub channels: Vec<PressureChannelConfig>,
+}
+
+/// Closed-loop pressure control for one material channel.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PressureChannelConfig {
+    /// Material channel
+    pub channel: u8,
+    
+    /// Sensor (from `PrinterConfig::sensors`) at the regulator outlet
+    pub sensor_id: String,
+    
+    /// Sensor past the channel's flow restriction; flow is estimated from
+    /// the differential, or from gauge pressure if absent
+    #[serde(default)]
+    pub downstream_sensor_id: Option<String>,
+    
+    /// Regulator drive output
+    pub output: RegulatorOutput,
+    
+    /// Vent solenoid GPIO (high = vent open), if fitted
+    #[serde(default)]
+    pub vent_pin: Option<u8>,
+    
+    /// PID gains on pressure error (output fraction per PSI)
+    #[serde(default = "default_pressure_pid")]
+    pub pid: PidParameters,
+    
+    /// Flow per square root of pressure differential (mm³/s per √PSI)
+    pub flow_coefficient: f32,
+}
+
+/// How a proportional regulator is driven.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum RegulatorOutput {
+    /// One channel of an MCP4728-style 12-bit quad I2C DAC
+    I2cDac { bus: u8, address: u8, dac_channel: u8 },
+}
+
+fn default_pressure_pid() -> PidParameters {
+    PidParameters { kp: 0.01, ki: 0.02, kd: 0.0 }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
@@ -386,18 +1187,147 @@ pub struct PressureSensor {
     pub accuracy_percent: f32,
 }
 
-/// Motion system configuration.
+/// A physical sensor and how it is wired to the controller.
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct MotionConfig {
-    /// Z-axis configuration
-    pub z_axis: ZAxisConfig,
+pub struct SensorDefinition {
+    /// Unique sensor identifier (used by `read_sensor`)
+    pub id: String,
     
-    /// Homing configuration
-    pub homing: HomingConfig,
+    /// What the sensor measures and where readings are reported
+    pub sensor_type: SensorType,
+    
+    /// Bus the sensor is attached to
+    pub bus: SensorBus,
+    
+    /// ADC input channel (SPI ADCs) or expander bank (GPIO expanders)
+    #[serde(default)]
+    pub channel: u8,
+    
+    /// Conversion applied to the measured value
+    #[serde(default)]
+    pub calibration: SensorCalibration,
 }
 
-/// Z-axis motion configuration.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Sensor kinds supported by the firmware.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SensorType {
+    /// NTC thermistor read through an SPI ADC (MCP3008-style) with a pull-up
+    Thermistor {
+        /// Thermal zone the reading belongs to
+        zone: u8,
+        /// Beta coefficient (K)
+        beta: f32,
+        /// Resistance at the nominal temperature (Ω)
+        nominal_resistance: f32,
+        /// Nominal temperature (°C), usually 25
+        nominal_temp: f32,
+        /// Pull-up resistor value (Ω)
+        pullup_resistance: f32,
+    },
+    /// Digital I2C pressure sensor (14-bit, 10%-90% transfer function)
+    Pressure {
+        /// Material channel the reading belongs to
+        channel: u8,
+        /// Calibrated range (PSI)
+        range_psi: (f32, f32),
+    },
+    /// Valve position switches read through a 16-bit I2C GPIO expander
+    ValveFeedback {
+        /// Grid nodes monitored, in input bit order
+        nodes: Vec<(u32, u32)>,
+        /// Feedback inputs per node
+        valves_per_node: u8,
+    },
+}
+
+/// Bus connection for a sensor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "bus", rename_all = "snake_case")]
+pub enum SensorBus {
+    Spi { bus: u8, chip_select: u8 },
+    I2c { bus: u8, address: u8 },
+}
+
+/// Per-sensor calibration applied after unit conversion.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(tag = "method", rename_all = "snake_case")]
+pub enum SensorCalibration {
+    /// Use the converted value unchanged
+    #[default]
+    None,
+    /// `value * coefficient + offset`
+    Linear { offset: f32, coefficient: f32 },
+    /// Piecewise-linear interpolation over (measured, actual) points sorted
+    /// by measured value; clamped outside the table
+    Lookup { points: Vec<(f32, f32)> },
+}
+
+impl SensorCalibration {
+    /// Applies the calibration to a converted reading.
+    pub fn apply(&self, value: f32) -> f32 {
+        match self {
+            SensorCalibration::None => value,
+            SensorCalibration::Linear { offset, coefficient } => value * coefficient + offset,
+            SensorCalibration::Lookup { points } => {
+                let (first, last) = match (points.first(), points.last()) {
+                    (Some(first), Some(last)) => (first, last),
+                    _ => return value,
+                };
+                if value <= first.0 {
+                    return first.1;
+                }
+                if value >= last.0 {
+                    return last.1;
+                }
+                for pair in points.windows(2) {
+                    let (x0, y0) = pair[0];
+                    let (x1, y1) = pair[1];
+                    if value <= x1 {
+                        let t = (value - x0) / (x1 - x0);
+                        return y0 + t * (y1 - y0);
+                    }
+                }
+                last.1
+            }
+        }
+    }
+
+    /// Checks that the calibration is usable.
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            SensorCalibration::None => Ok(()),
+            SensorCalibration::Linear { offset, coefficient } => {
+                if !offset.is_finite() || !coefficient.is_finite() || *coefficient == 0.0 {
+                    return Err("linear calibration needs a finite, non-zero coefficient".to_string());
+                }
+                Ok(())
+            }
+            SensorCalibration::Lookup { points } => {
+                if points.len() < 2 {
+                    return Err("lookup table needs at least two points".to_string());
+                }
+                if points.windows(2).any(|w| w[1].0 <= w[0].0) {
+                    return Err("lookup table must be strictly increasing".to_string());
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Motion system configuration.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MotionConfig {
+    /// Z-axis configuration
+    pub z_axis: ZAxisConfig,
+    
+    /// Homing configuration
+    pub homing: HomingConfig,
+}
+
+/// Z-axis motion configuration.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ZAxisConfig {
     /// Lead screw pitch (mm)
     pub lead_screw_pitch: f32,
@@ -413,6 +1343,55 @@ pub struct ZAxisConfig {
     
     /// Maximum acceleration (mm/s²)
     pub max_acceleration: f32,
+    
+    /// Stepper driver wiring
+    #[serde(default)]
+    pub driver: StepperDriverConfig,
+}
+
+/// GPIO wiring of a step/dir stepper driver (BCM pin numbers).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StepperDriverConfig {
+    /// Step pulse output
+    pub step_pin: u8,
+    
+    /// Direction output
+    pub dir_pin: u8,
+    
+    /// Driver enable output (active low), if wired
+    pub enable_pin: Option<u8>,
+    
+    /// Homing endstop input
+    pub endstop_pin: u8,
+    
+    /// Endstop reads low when triggered (normally-closed switch to ground)
+    #[serde(default)]
+    pub endstop_active_low: bool,
+    
+    /// Driver stall/diagnostic output (e.g. TMC DIAG), if wired
+    pub stall_pin: Option<u8>,
+    
+    /// Reverse the direction signal
+    #[serde(default)]
+    pub invert_direction: bool,
+    
+    /// Minimum step pulse width (µs)
+    pub min_pulse_us: u32,
+}
+
+impl Default for StepperDriverConfig {
+    fn default() -> Self {
+        Self {
+            step_pin: 17,
+            dir_pin: 27,
+            enable_pin: Some(22),
+            endstop_pin: 23,
+            endstop_active_low: false,
+            stall_pin: None,
+            invert_direction: false,
+            min_pulse_us: 2,
+        }
+    }
 }
 
 /// Homing configuration.
@@ -431,11 +1410,11 @@ pub struct HomingConfig {
 /// Safety limits for all monitored parameters.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SafetyLimits {
-    /// Maximum allowed temperature anywhere (°C)
-    pub max_temperature: f32,
+    /// Maximum allowed temperature anywhere
+    pub max_temperature: Celsius,
     
-    /// Maximum allowed pressure (PSI)
-    pub max_pressure: f32,
+    /// Maximum allowed pressure
+    pub max_pressure: Psi,
     
     /// Maximum valve switching rate (Hz)
     pub max_valve_rate: f32,
@@ -448,6 +1427,550 @@ pub struct SafetyLimits {
     
     /// Pressure fault threshold (PSI deviation)
     pub pressure_fault_threshold: f32,
+    
+    /// Timeout of G4W barriers that do not set their own (ms)
+    #[serde(default = "default_barrier_timeout_ms")]
+    pub barrier_timeout_ms: u32,
+    
+    /// What the executor does when a G4W barrier times out
+    #[serde(default)]
+    pub barrier_timeout_action: BarrierTimeoutAction,
+    
+    /// External safety inputs: door switches, chamber thermostats, smoke
+    /// detectors
+    #[serde(default)]
+    pub interlocks: Vec<InterlockConfig>,
+    
+    /// Ping interval and miss threshold for detecting dead client
+    /// connections
+    #[serde(default)]
+    pub heartbeat: HeartbeatConfig,
+    
+    /// What the firmware does when its last client connection dies
+    #[serde(default)]
+    pub disconnect_action: DisconnectAction,
+}
+
+fn default_barrier_timeout_ms() -> u32 {
+    60_000
+}
+
+/// Heartbeats on a protocol connection.
+///
+/// A side that has received nothing for `interval_ms` sends a ping; each
+/// further interval without an answer counts as a miss, and the peer is
+/// declared dead after `miss_threshold` misses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HeartbeatConfig {
+    /// Silence before a ping is sent (ms)
+    #[serde(default = "default_heartbeat_interval_ms")]
+    pub interval_ms: u32,
+    
+    /// Unanswered pings before the peer is declared dead
+    #[serde(default = "default_heartbeat_miss_threshold")]
+    pub miss_threshold: u32,
+}
+
+impl HeartbeatConfig {
+    pub fn interval(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.interval_ms as u64)
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.interval_ms == 0 {
+            return Err("interval_ms must be positive".to_string());
+        }
+        if self.miss_threshold == 0 {
+            return Err("miss_threshold must be at least 1".to_string());
+        }
+        Ok(())
+    }
+}
+
+impl Default for HeartbeatConfig {
+    fn default() -> Self {
+        Self {
+            interval_ms: default_heartbeat_interval_ms(),
+            miss_threshold: default_heartbeat_miss_threshold(),
+        }
+    }
+}
+
+fn default_heartbeat_interval_ms() -> u32 {
+    2_000
+}
+
+fn default_heartbeat_miss_threshold() -> u32 {
+    3
+}
+
+/// Reaction to the last client connection dying during a print.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DisconnectAction {
+    /// Keep printing unattended
+    #[default]
+    Continue,
+    /// Pause the print until an operator reconnects and resumes it
+    Pause,
+}
+
+/// A safety input wired to a GPIO.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InterlockConfig {
+    /// Name used in logs and error reports, e.g. "door"
+    pub name: String,
+    
+    /// GPIO (BCM) the switch or sensor drives
+    pub pin: u8,
+    
+    /// Input reads low when tripped
+    #[serde(default)]
+    pub active_low: bool,
+    
+    /// Response when the input trips
+    pub action: InterlockAction,
+    
+    /// Input must stay tripped this long before acting (ms)
+    #[serde(default = "default_interlock_debounce_ms")]
+    pub debounce_ms: u32,
+}
+
+fn default_interlock_debounce_ms() -> u32 {
+    50
+}
+
+/// Response to a tripped interlock.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InterlockAction {
+    /// Pause a running print; resuming is refused while tripped
+    Pause,
+    /// Cancel a running print
+    Abort,
+    /// Emergency stop, printing or not
+    EmergencyStop,
+}
+
+/// Reaction to a G4W barrier that is not released in time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BarrierTimeoutAction {
+    /// Log a warning and continue the layer
+    Warn,
+    /// Fail the print
+    #[default]
+    Abort,
+}
+
+/// Shutdown sequencing when supply power fails.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PowerLossConfig {
+    /// GPIO (BCM) driven by the UPS or undervoltage detector
+    pub signal_pin: u8,
+    
+    /// Signal reads low on power loss
+    #[serde(default)]
+    pub active_low: bool,
+    
+    /// Hold-up time from detection until the supply collapses (ms)
+    pub deadline_ms: u32,
+    
+    /// Signal must persist this long before shutdown starts (ms)
+    #[serde(default = "default_power_loss_debounce_ms")]
+    pub debounce_ms: u32,
+    
+    /// Distance to lift Z away from the part if time allows (mm, 0 = never)
+    #[serde(default)]
+    pub park_lift_mm: f32,
+    
+    /// Recovery journal location
+    #[serde(default = "default_recovery_journal")]
+    pub journal_path: PathBuf,
+}
+
+fn default_power_loss_debounce_ms() -> u32 {
+    5
+}
+
+fn default_recovery_journal() -> PathBuf {
+    PathBuf::from("/var/lib/hypergcode/recovery.json")
+}
+
+/// Camera notified when the print holds for inspection.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CameraConfig {
+    /// URL that receives a JSON POST describing the pause
+    pub webhook_url: String,
+    
+    /// Request timeout (ms)
+    #[serde(default = "default_camera_timeout_ms")]
+    pub timeout_ms: u32,
+}
+
+fn default_camera_timeout_ms() -> u32 {
+    5_000
+}
+
+/// Self-telemetry of the computer running the firmware.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HostTelemetryConfig {
+    /// Sampling interval (ms)
+    pub interval_ms: u32,
+    
+    /// CPU load that raises a warning (percentage of all cores)
+    pub cpu_load_warning: f32,
+    
+    /// Memory use that raises a warning (percentage)
+    pub memory_warning: f32,
+    
+    /// SoC temperature that raises a warning (°C); below the 80 °C at
+    /// which a Raspberry Pi 4 starts throttling
+    pub soc_temperature_warning: f32,
+    
+    /// Free space in the print directory below which a warning is raised (MB)
+    pub disk_free_warning_mb: u64,
+    
+    /// sysfs file with the SoC temperature in millidegrees
+    pub thermal_zone: PathBuf,
+}
+
+impl Default for HostTelemetryConfig {
+    fn default() -> Self {
+        Self {
+            interval_ms: 5_000,
+            cpu_load_warning: 90.0,
+            memory_warning: 90.0,
+            soc_temperature_warning: 75.0,
+            disk_free_warning_mb: 500,
+            thermal_zone: PathBuf::from("/sys/class/thermal/thermal_zone0/temp"),
+        }
+    }
+}
+
+impl HostTelemetryConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.interval_ms == 0 {
+            return Err("sampling interval must be positive".to_string());
+        }
+        for (name, value) in [("CPU load", self.cpu_load_warning), ("memory", self.memory_warning)] {
+            if !(0.0..=100.0).contains(&value) {
+                return Err(format!("{} warning {} is not a percentage", name, value));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Named sequence of steps the operator runs outside a print, such as
+/// loading material or purging a channel.
+///
+/// ```toml
+/// [[macros]]
+/// name = "load_material"
+/// parameters = [{ name = "temp", default = 220.0 }]
+/// steps = [
+///     { action = "set_temperature", zone = 0, target = "{temp}", wait = true },
+///     { action = "set_pressure", channel = 0, target = 15.0, wait = true },
+///     { action = "purge", channel = 0, length_mm = 60.0 },
+///     { action = "set_pressure", channel = 0, target = 0.0 },
+/// ]
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MacroDefinition {
+    pub name: String,
+    
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub description: String,
+    
+    /// Values substituted for `"{name}"` in step fields
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub parameters: Vec<MacroParameter>,
+    
+    pub steps: Vec<MacroStep>,
+}
+
+impl MacroDefinition {
+    pub fn parameter(&self, name: &str) -> Option<&MacroParameter> {
+        self.parameters.iter().find(|p| p.name == name)
+    }
+
+    /// Checks the macro is runnable: a name, at least one step, and every
+    /// parameter a step refers to declared once.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.name.trim().is_empty() {
+            return Err("name is empty".to_string());
+        }
+        if self.steps.is_empty() {
+            return Err("no steps".to_string());
+        }
+        for (i, parameter) in self.parameters.iter().enumerate() {
+            if self.parameters[..i].iter().any(|p| p.name == parameter.name) {
+                return Err(format!("parameter '{}' is declared twice", parameter.name));
+            }
+        }
+        for (i, step) in self.steps.iter().enumerate() {
+            for value in step.values() {
+                if let MacroValue::Parameter(reference) = value {
+                    let name = MacroValue::parameter_name(reference)
+                        .ok_or_else(|| format!("step {}: '{}' is neither a number nor {{parameter}}", i + 1, reference))?;
+                    if self.parameter(name).is_none() {
+                        return Err(format!("step {} uses undeclared parameter '{}'", i + 1, name));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Macro argument.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MacroParameter {
+    pub name: String,
+    
+    /// Value used when the caller gives none; required if absent
+    #[serde(default)]
+    pub default: Option<f32>,
+    
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub description: String,
+}
+
+/// Number in a macro step, given literally or as `"{parameter}"`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum MacroValue {
+    Number(f32),
+    Parameter(String),
+}
+
+impl MacroValue {
+    /// Name inside a `"{name}"` reference.
+    pub fn parameter_name(reference: &str) -> Option<&str> {
+        reference
+            .strip_prefix('{')
+            .and_then(|r| r.strip_suffix('}'))
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+    }
+}
+
+/// One step of a macro. Temperatures in °C, pressures in PSI, Z in mm.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum MacroStep {
+    /// Set a thermal zone's target, optionally waiting until it is reached
+    SetTemperature {
+        zone: u8,
+        target: MacroValue,
+        #[serde(default)]
+        wait: bool,
+    },
+    /// Set a channel's pressure target, optionally waiting until stable
+    SetPressure {
+        channel: u8,
+        target: MacroValue,
+        #[serde(default)]
+        wait: bool,
+    },
+    /// Pause for a fixed time
+    Dwell { ms: MacroValue },
+    /// Move Z to an absolute height
+    MoveZ {
+        z: MacroValue,
+        /// mm/s; the configured Z speed if absent
+        #[serde(default)]
+        feed_rate: Option<MacroValue>,
+    },
+    /// Deposit a strip along the front edge of the plate to prime a channel
+    Purge {
+        channel: u8,
+        length_mm: MacroValue,
+        #[serde(default)]
+        width_mm: Option<MacroValue>,
+    },
+}
+
+impl MacroStep {
+    /// The step's numeric fields.
+    pub fn values(&self) -> Vec<&MacroValue> {
+        match self {
+            MacroStep::SetTemperature { target, .. } | MacroStep::SetPressure { target, .. } => vec![target],
+            MacroStep::Dwell { ms } => vec![ms],
+            MacroStep::MoveZ { z, feed_rate } => std::iter::once(z).chain(feed_rate.as_ref()).collect(),
+            MacroStep::Purge { length_mm, width_mm, .. } => {
+                std::iter::once(length_mm).chain(width_mm.as_ref()).collect()
+            }
+        }
+    }
+}
+
+/// How bed gap deviations are measured.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BedProbeMethod {
+    /// Operator measures each point (feeler gauge, dial indicator) and
+    /// enters the deviation
+    Manual,
+    /// One gap sensor per probe point, row-major, reporting the deviation
+    /// from the nominal gap in mm
+    Sensors { sensor_ids: Vec<String> },
+}
+
+/// Bed level measurement setup and the resulting height map.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BedLevelConfig {
+    pub probe: BedProbeMethod,
+    
+    /// Probe points along X and Y (at least 2 each)
+    pub points_x: u32,
+    pub points_y: u32,
+    
+    /// Distance of the outer probe points from the build area edge (mm)
+    #[serde(default = "default_bed_probe_margin")]
+    pub margin_mm: f32,
+    
+    /// Layers over which compensation fades out (first layer fully
+    /// compensated)
+    #[serde(default = "default_bed_fade_layers")]
+    pub fade_layers: u32,
+    
+    /// Last measured height map
+    #[serde(default)]
+    pub mesh: Option<BedMesh>,
+}
+
+fn default_bed_probe_margin() -> f32 {
+    10.0
+}
+
+fn default_bed_fade_layers() -> u32 {
+    1
+}
+
+impl BedLevelConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.points_x < 2 || self.points_y < 2 {
+            return Err(format!("Need at least 2x2 probe points, got {}x{}", self.points_x, self.points_y));
+        }
+        if self.margin_mm < 0.0 {
+            return Err("Probe margin must not be negative".to_string());
+        }
+        if let BedProbeMethod::Sensors { sensor_ids } = &self.probe {
+            let points = (self.points_x * self.points_y) as usize;
+            if sensor_ids.len() != points {
+                return Err(format!("{} gap sensors for {} probe points", sensor_ids.len(), points));
+            }
+        }
+        if let Some(mesh) = &self.mesh {
+            mesh.validate()?;
+        }
+        Ok(())
+    }
+
+    /// Probe point positions (mm), row-major from the front-left corner.
+    pub fn probe_points(&self, build_volume: &BuildVolume) -> Vec<(f32, f32)> {
+        let xs = self.axis(self.points_x, build_volume.x);
+        let ys = self.axis(self.points_y, build_volume.y);
+        ys.iter().flat_map(|&y| xs.iter().map(move |&x| (x, y))).collect()
+    }
+
+    /// Mesh over the probe points with the given deviations.
+    pub fn mesh_from(&self, build_volume: &BuildVolume, deviations: Vec<f32>) -> Result<BedMesh, String> {
+        let xs = self.axis(self.points_x, build_volume.x);
+        let ys = self.axis(self.points_y, build_volume.y);
+        let mesh = BedMesh {
+            origin: (xs[0], ys[0]),
+            spacing: (xs[1] - xs[0], ys[1] - ys[0]),
+            points_x: self.points_x,
+            points_y: self.points_y,
+            deviations,
+        };
+        mesh.validate()?;
+        Ok(mesh)
+    }
+
+    fn axis(&self, count: u32, length: f32) -> Vec<f32> {
+        let span = (length - 2.0 * self.margin_mm).max(0.0);
+        (0..count)
+            .map(|i| self.margin_mm + span * i as f32 / (count - 1).max(1) as f32)
+            .collect()
+    }
+}
+
+/// Measured deviation of the bed from the nominal valve-plate gap on a
+/// regular grid.
+///
+/// A positive deviation means the bed is farther from the valve plate than
+/// nominal, so the first layer needs more material there.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BedMesh {
+    /// Position of the first probe point (mm)
+    pub origin: (f32, f32),
+    
+    /// Distance between probe points along X and Y (mm)
+    pub spacing: (f32, f32),
+    
+    pub points_x: u32,
+    pub points_y: u32,
+    
+    /// Deviation per point (mm), row-major
+    pub deviations: Vec<f32>,
+}
+
+impl BedMesh {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.points_x < 2 || self.points_y < 2 {
+            return Err("Bed mesh needs at least 2x2 points".to_string());
+        }
+        if !(self.spacing.0 > 0.0 && self.spacing.1 > 0.0) {
+            return Err("Bed mesh spacing must be positive".to_string());
+        }
+        if self.deviations.len() != (self.points_x * self.points_y) as usize {
+            return Err(format!(
+                "Bed mesh has {} deviations for {}x{} points",
+                self.deviations.len(), self.points_x, self.points_y
+            ));
+        }
+        if self.deviations.iter().any(|d| !d.is_finite()) {
+            return Err("Bed mesh deviations must be finite".to_string());
+        }
+        Ok(())
+    }
+
+    /// Deviation at a position (mm), bilinearly interpolated and clamped to
+    /// the measured area.
+    pub fn deviation_at(&self, x: f32, y: f32) -> f32 {
+        let cell = |pos: f32, origin: f32, spacing: f32, count: u32| -> (usize, f32) {
+            let t = ((pos - origin) / spacing).clamp(0.0, (count - 1) as f32);
+            let i = (t.floor() as usize).min(count as usize - 2);
+            (i, t - i as f32)
+        };
+        let (i, fx) = cell(x, self.origin.0, self.spacing.0, self.points_x);
+        let (j, fy) = cell(y, self.origin.1, self.spacing.1, self.points_y);
+        let at = |i: usize, j: usize| self.deviations[j * self.points_x as usize + i];
+
+        let front = at(i, j) * (1.0 - fx) + at(i + 1, j) * fx;
+        let back = at(i, j + 1) * (1.0 - fx) + at(i + 1, j + 1) * fx;
+        front * (1.0 - fy) + back * fy
+    }
+
+    /// Extrusion multiplier compensating the local gap on a layer.
+    ///
+    /// The layer is locally `layer_height + deviation` thick, so material
+    /// scales by that ratio on the first layer, easing linearly to 1 by
+    /// `fade_layers`. Clamped to 0.5-2.0.
+    pub fn extrusion_factor(&self, x: f32, y: f32, layer_height: f32, layer_number: u32, fade_layers: u32) -> f32 {
+        let fade_layers = fade_layers.max(1);
+        if layer_number >= fade_layers || layer_height <= 0.0 {
+            return 1.0;
+        }
+        let weight = 1.0 - layer_number as f32 / fade_layers as f32;
+        let deviation = self.deviation_at(x, y) * weight;
+        ((layer_height + deviation) / layer_height).clamp(0.5, 2.0)
+    }
 }
 
 /// Printer metadata.
@@ -495,6 +2018,19 @@ pub struct MaterialProfile {
     
     /// Cooling requirements
     pub cooling: CoolingParameters,
+    
+    /// Base color as RGB, used for color mixing
+    #[serde(default)]
+    pub color: Option<[u8; 3]>,
+    
+    /// Moisture sensitivity; None for materials that need no drying
+    #[serde(default)]
+    pub drying: Option<DryingParameters>,
+    
+    /// Build chamber temperature (°C); None if the material does not need
+    /// a heated chamber
+    #[serde(default)]
+    pub chamber_temp: Option<f32>,
 }
 
 impl MaterialProfile {
@@ -506,15 +2042,36 @@ impl MaterialProfile {
             .map_err(|e| ConfigError::ParseError(e.to_string()))
     }
 
+    /// Saves the profile atomically, keeping the previous version as a
+    /// backup.
     pub fn to_file<P: AsRef<Path>>(&self, path: P) -> Result<(), ConfigError> {
         let contents = toml::to_string_pretty(self)
             .map_err(|e| ConfigError::SerializationError(e.to_string()))?;
         
-        std::fs::write(path.as_ref(), contents)
-            .map_err(|e| ConfigError::IoError(e.to_string()))
+        persist::write_with_backups(path.as_ref(), contents.as_bytes(), persist::DEFAULT_BACKUP_COUNT)
+    }
+
+    /// Restores the newest backup of the profile at `path` that loads.
+    pub fn restore_backup<P: AsRef<Path>>(path: P) -> Result<Self, ConfigError> {
+        persist::restore_latest(path.as_ref(), |contents| {
+            toml::from_str(contents).map_err(|e| ConfigError::ParseError(e.to_string()))
+        })
     }
 }
 
+/// Drying needs of a hygroscopic material.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DryingParameters {
+    /// Hours a spool may stay loaded before it should be dried again
+    pub max_exposure_hours: f32,
+    
+    /// Drying temperature (°C), below the glass transition
+    pub temperature: f32,
+    
+    /// Drying time at temperature (hours)
+    pub hours: f32,
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum MaterialType {
     PLA,
@@ -608,6 +2165,10 @@ pub struct PrintSettings {
     /// Print speed settings
     pub speeds: SpeedSettings,
     
+    /// Perimeter (shell) settings
+    #[serde(default)]
+    pub shells: ShellSettings,
+    
     /// Infill settings
     pub infill: InfillSettings,
     
@@ -616,6 +2177,28 @@ pub struct PrintSettings {
     
     /// Multi-material settings (if applicable)
     pub multi_material: Option<MultiMaterialSettings>,
+    
+    /// Spiral (vase) mode: a single wall deposited along a continuous
+    /// Z ramp instead of discrete layers
+    #[serde(default)]
+    pub vase: Option<VaseSettings>,
+    
+    /// Ooze compensation for material left in valve dead volume
+    #[serde(default)]
+    pub dead_volume: DeadVolumeSettings,
+    
+    /// Layers after which the print holds for operator inspection
+    #[serde(default)]
+    pub inspection: InspectionSettings,
+    
+    /// Skirt, brim and raft around the first layer
+    #[serde(default)]
+    pub adhesion: AdhesionSettings,
+    
+    /// Volumes overriding flow, temperature or channel where they
+    /// intersect the model; later modifiers win where they overlap
+    #[serde(default)]
+    pub modifiers: Vec<ModifierMesh>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -631,6 +2214,29 @@ pub struct SpeedSettings {
     pub small_perimeter_factor: f32,
 }
 
+/// Perimeter rings deposited along region boundaries before infill.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShellSettings {
+    /// Rings of valve nodes inside each boundary
+    pub perimeter_count: u32,
+    
+    /// Extrusion multiplier for perimeter nodes
+    pub flow_factor: f32,
+    
+    /// Channel for perimeters; None uses the region's channel
+    pub material_channel: Option<u8>,
+}
+
+impl Default for ShellSettings {
+    fn default() -> Self {
+        Self {
+            perimeter_count: 2,
+            flow_factor: 1.0,
+            material_channel: None,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct InfillSettings {
     /// Infill density (percentage)
@@ -638,6 +2244,40 @@ pub struct InfillSettings {
     
     /// Infill pattern
     pub pattern: InfillPattern,
+    
+    /// Density graded by distance to walls and top/bottom surfaces, and
+    /// raised in painted regions; None fills uniformly at `density`
+    #[serde(default)]
+    pub gradient: Option<InfillGradient>,
+}
+
+/// Variable infill density.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InfillGradient {
+    /// Density far from walls and surfaces (percentage)
+    pub min_density: f32,
+    
+    /// Density next to walls and surfaces and in painted regions (percentage)
+    pub max_density: f32,
+    
+    /// Distance over which the density falls from max to min (mm)
+    pub transition_distance: f32,
+    
+    /// Grade toward the perimeters
+    #[serde(default = "default_gradient_enabled")]
+    pub near_walls: bool,
+    
+    /// Grade toward top and bottom surfaces
+    #[serde(default = "default_gradient_enabled")]
+    pub near_surfaces: bool,
+    
+    /// Region spec file of painted boxes filled at a fixed density
+    #[serde(default)]
+    pub regions_file: Option<PathBuf>,
+}
+
+fn default_gradient_enabled() -> bool {
+    true
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
@@ -662,6 +2302,198 @@ pub struct SupportSettings {
     pub density: f32,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VaseSettings {
+    /// Solid layers printed normally before the spiral starts
+    #[serde(default = "default_vase_bottom_layers")]
+    pub bottom_layers: u32,
+}
+
+fn default_vase_bottom_layers() -> u32 {
+    3
+}
+
+/// A modifier mesh: a closed mesh marking a volume whose overrides apply
+/// to the model regions inside it. Unset overrides leave the model's own.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ModifierMesh {
+    /// Mesh file (STL, OBJ or 3MF), in the coordinates of the placed model
+    pub path: PathBuf,
+    
+    /// Extrusion multiplier for nodes inside the volume
+    #[serde(default)]
+    pub flow_multiplier: Option<f32>,
+    
+    /// Temperature of the zones feeding the volume's channels on layers
+    /// that cross it (°C)
+    #[serde(default)]
+    pub temperature: Option<f32>,
+    
+    /// Channel the volume prints with
+    #[serde(default)]
+    pub material_channel: Option<u8>,
+}
+
+/// Bed adhesion aids generated from the first layer's footprint.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AdhesionSettings {
+    /// Detached priming ring that stabilizes flow before the part starts
+    #[serde(default)]
+    pub skirt: Option<SkirtSettings>,
+    
+    /// Rings attached to the first layer's outline
+    #[serde(default)]
+    pub brim: Option<BrimSettings>,
+    
+    /// Sacrificial layers the part is printed on
+    #[serde(default)]
+    pub raft: Option<RaftSettings>,
+    
+    /// Channel for skirt, brim and raft; None uses the first layer's channel
+    #[serde(default)]
+    pub material_channel: Option<u8>,
+}
+
+impl AdhesionSettings {
+    pub fn is_empty(&self) -> bool {
+        self.skirt.is_none() && self.brim.is_none() && self.raft.is_none()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SkirtSettings {
+    /// Gap between the part and the skirt (mm)
+    #[serde(default = "default_skirt_distance_mm")]
+    pub distance_mm: f32,
+    
+    /// Rings of valve nodes
+    #[serde(default = "default_skirt_loops")]
+    pub loops: u32,
+    
+    /// Layers the skirt is repeated on
+    #[serde(default = "default_skirt_layers")]
+    pub layers: u32,
+}
+
+impl Default for SkirtSettings {
+    fn default() -> Self {
+        Self {
+            distance_mm: default_skirt_distance_mm(),
+            loops: default_skirt_loops(),
+            layers: default_skirt_layers(),
+        }
+    }
+}
+
+fn default_skirt_distance_mm() -> f32 {
+    3.0
+}
+
+fn default_skirt_loops() -> u32 {
+    2
+}
+
+fn default_skirt_layers() -> u32 {
+    1
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BrimSettings {
+    /// Rings of valve nodes outside the first layer's outline
+    pub loops: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RaftSettings {
+    /// Solid raft layers, the first at the first layer height
+    #[serde(default = "default_raft_layers")]
+    pub layers: u32,
+    
+    /// Raft extent beyond the part's footprint (mm)
+    #[serde(default = "default_raft_margin_mm")]
+    pub margin_mm: f32,
+    
+    /// Gap between the raft top and the part, for separation (mm)
+    #[serde(default = "default_raft_air_gap_mm")]
+    pub air_gap_mm: f32,
+}
+
+impl Default for RaftSettings {
+    fn default() -> Self {
+        Self {
+            layers: default_raft_layers(),
+            margin_mm: default_raft_margin_mm(),
+            air_gap_mm: default_raft_air_gap_mm(),
+        }
+    }
+}
+
+fn default_raft_layers() -> u32 {
+    3
+}
+
+fn default_raft_margin_mm() -> f32 {
+    3.0
+}
+
+fn default_raft_air_gap_mm() -> f32 {
+    0.15
+}
+
+/// Inspection pause points. The print holds at temperature after each
+/// listed layer until the operator resumes it.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct InspectionSettings {
+    /// Layer numbers (0-based) to pause after
+    #[serde(default)]
+    pub layers: Vec<u32>,
+    
+    /// Z heights (mm); the pause follows the first layer reaching each
+    #[serde(default)]
+    pub heights: Vec<f32>,
+}
+
+impl InspectionSettings {
+    pub fn is_empty(&self) -> bool {
+        self.layers.is_empty() && self.heights.is_empty()
+    }
+}
+
+/// Compensation for material in a valve's dead volume, which keeps flowing
+/// after the valve is commanded shut.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DeadVolumeSettings {
+    /// Close valves ahead of schedule by the time the dead volume takes to
+    /// drain at the valve's share of the channel flow
+    pub early_close: bool,
+    
+    /// Longest early-close lead (ms)
+    pub max_lead_ms: f32,
+    
+    /// Pressure pulse once a channel's last valve in a run closes
+    pub suck_back: Option<SuckBackPulse>,
+}
+
+impl Default for DeadVolumeSettings {
+    fn default() -> Self {
+        Self {
+            early_close: true,
+            max_lead_ms: 20.0,
+            suck_back: None,
+        }
+    }
+}
+
+/// Brief pressure drop that draws material back out of the valve seats.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SuckBackPulse {
+    /// Pulse pressure (PSI); below zero needs a vacuum-capable regulator
+    pub pressure: f32,
+    
+    /// Pulse length (ms)
+    pub duration_ms: u32,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MultiMaterialSettings {
     /// Material assignments by region or object
@@ -672,6 +2504,11 @@ pub struct MultiMaterialSettings {
     
     /// Purge tower settings (if using purge tower)
     pub purge_tower: Option<PurgeTowerSettings>,
+    
+    /// Slice even with materials the printer's compatibility matrix
+    /// forbids together; the conflicts become warnings
+    #[serde(default)]
+    pub allow_incompatible: bool,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
@@ -712,12 +2549,207 @@ pub enum ConfigError {
 
     #[error("Missing required field: {0}")]
     MissingField(String),
+
+    #[error("Thermal conflict: {0}")]
+    ThermalConflict(String),
+
+    #[error("No usable backup of {0}")]
+    NoBackup(String),
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_sensor_calibration() {
+        let linear = SensorCalibration::Linear { offset: -1.5, coefficient: 2.0 };
+        assert_eq!(linear.apply(10.0), 18.5);
+
+        let lookup = SensorCalibration::Lookup {
+            points: vec![(0.0, 0.0), (10.0, 20.0), (20.0, 25.0)],
+        };
+        assert!(lookup.validate().is_ok());
+        assert_eq!(lookup.apply(5.0), 10.0);
+        assert_eq!(lookup.apply(15.0), 22.5);
+        assert_eq!(lookup.apply(-3.0), 0.0);
+        assert_eq!(lookup.apply(99.0), 25.0);
+
+        let unsorted = SensorCalibration::Lookup { points: vec![(1.0, 0.0), (0.0, 1.0)] };
+        assert!(unsorted.validate().is_err());
+    }
+
+    fn zone(id: u8, min_temp: f32, max_temp: f32) -> ThermalZone {
+        ThermalZone {
+            id,
+            name: format!("zone{}", id),
+            min_temp,
+            max_temp,
+            power_watts: 40.0,
+            pid: PidParameters { kp: 1.0, ki: 0.1, kd: 0.5 },
+            heater_pin: None,
+            regions: vec![],
+            thermal_mass: None,
+        }
+    }
+
+    fn material(name: &str, temp_range: (f32, f32), optimal_temp: f32) -> MaterialProfile {
+        MaterialProfile {
+            name: name.to_string(),
+            material_type: MaterialType::PLA,
+            temp_range,
+            optimal_temp,
+            bed_temp: 60.0,
+            properties: MaterialProperties {
+                density: 1.24,
+                viscosity: 100.0,
+                glass_transition_temp: 60.0,
+                thermal_conductivity: 0.13,
+                shrinkage: 0.3,
+            },
+            extrusion: ExtrusionParameters {
+                pressure_psi: 50.0,
+                flow_multiplier: 1.0,
+                retraction_distance: 0.0,
+                retraction_speed: 0.0,
+            },
+            purge: PurgeParameters {
+                purge_volume_incoming: 10.0,
+                purge_volume_outgoing: 10.0,
+                purge_temp: None,
+            },
+            cooling: CoolingParameters {
+                min_layer_time: 5.0,
+                requires_cooling: true,
+                initial_fan_speed: 0.0,
+                regular_fan_speed: 100.0,
+            },
+            color: None,
+            drying: None,
+            chamber_temp: None,
+        }
+    }
+
+    #[test]
+    fn test_bed_mesh_interpolation() {
+        let mesh = BedMesh {
+            origin: (10.0, 10.0),
+            spacing: (100.0, 100.0),
+            points_x: 2,
+            points_y: 2,
+            deviations: vec![0.0, 0.1, 0.0, 0.1],
+        };
+        assert!(mesh.validate().is_ok());
+        assert!((mesh.deviation_at(60.0, 50.0) - 0.05).abs() < 1e-6);
+        // Outside the probed area the edge value holds
+        assert!((mesh.deviation_at(500.0, 0.0) - 0.1).abs() < 1e-6);
+
+        // 0.2 mm layer over a 0.1 mm deeper gap needs 1.5x material
+        assert!((mesh.extrusion_factor(110.0, 10.0, 0.2, 0, 1) - 1.5).abs() < 1e-5);
+        assert!((mesh.extrusion_factor(110.0, 10.0, 0.2, 1, 2) - 1.25).abs() < 1e-5);
+        assert_eq!(mesh.extrusion_factor(110.0, 10.0, 0.2, 1, 1), 1.0);
+    }
+
+    #[test]
+    fn test_plan_zone_temperatures() {
+        let thermal = ThermalConfig {
+            zones: vec![zone(0, 20.0, 300.0), zone(1, 20.0, 230.0), zone(2, 20.0, 300.0)],
+            manifold: None,
+            chamber: None,
+            bed: None,
+            channel_zones: vec![
+                ChannelZoneMapping { channel: 0, zones: vec![0, 2] },
+                ChannelZoneMapping { channel: 1, zones: vec![1, 2] },
+            ],
+            supply_watts: None,
+        };
+        let pla = material("PLA", (190.0, 220.0), 210.0);
+        let petg = material("PETG", (215.0, 250.0), 235.0);
+        let pc = material("PC", (260.0, 300.0), 280.0);
+
+        // Only zones backing channel 0 are planned
+        let plan = thermal.plan_zone_temperatures(&[(0, &pla)]).unwrap();
+        assert_eq!(plan, BTreeMap::from([(0, 210.0), (2, 210.0)]));
+
+        // Shared zone 2 settles inside both ranges
+        let plan = thermal.plan_zone_temperatures(&[(0, &pla), (1, &petg)]).unwrap();
+        assert_eq!(plan[&2], 220.0);
+
+        // Zone 1 tops out below PC; PC and PLA cannot share zone 2
+        assert!(thermal.plan_zone_temperatures(&[(1, &pc)]).is_err());
+        assert!(thermal.plan_zone_temperatures(&[(0, &pc), (1, &pla)]).is_err());
+        // Unmapped channel
+        assert!(thermal.plan_zone_temperatures(&[(3, &pla)]).is_err());
+    }
+
+    #[test]
+    fn test_zone_regions() {
+        let mut left = zone(0, 20.0, 230.0);
+        left.regions = vec![ZoneRegion::Rect { x_min: 0.0, y_min: 0.0, x_max: 50.0, y_max: 100.0 }];
+        let mut right = zone(1, 20.0, 300.0);
+        right.regions = vec![ZoneRegion::Nodes { x: (101, 199), y: (0, 199) }];
+        let thermal = ThermalConfig {
+            zones: vec![left, right, zone(2, 20.0, 300.0)],
+            manifold: None,
+            chamber: None,
+            bed: None,
+            channel_zones: vec![ChannelZoneMapping { channel: 0, zones: vec![0, 1, 2] }],
+            supply_watts: None,
+        };
+        assert!(thermal.is_spatially_mapped());
+
+        // 0.5mm grid: node 100 sits on the rectangle's edge at 50mm
+        let ids = |x, y| thermal.zones_at(0, x, y, 0.5).map(|z| z.id).collect::<Vec<_>>();
+        assert_eq!(ids(100, 10), vec![0, 2]);
+        assert_eq!(ids(150, 10), vec![1, 2]);
+        assert_eq!(ids(150, 250), vec![2]);
+        assert!(thermal.zones_at(1, 10, 10, 0.5).next().is_none());
+
+        assert!(thermal.zones[0].can_hold(&material("PLA", (190.0, 220.0), 210.0)));
+        assert!(!thermal.zones[0].can_hold(&material("PC", (260.0, 300.0), 280.0)));
+    }
+
+    #[test]
+    fn test_plan_bed_and_chamber() {
+        let mut thermal = ThermalConfig {
+            zones: vec![zone(0, 20.0, 300.0)],
+            manifold: None,
+            chamber: None,
+            bed: None,
+            channel_zones: vec![],
+            supply_watts: None,
+        };
+        let pla = material("PLA", (190.0, 220.0), 210.0);
+        let mut abs = material("ABS", (230.0, 260.0), 245.0);
+        abs.bed_temp = 110.0;
+        abs.chamber_temp = Some(60.0);
+        assert_eq!(thermal.plan_bed_temperature(&[(0, &pla)]), None);
+
+        thermal.bed = Some(BedHeating {
+            power_watts: 300.0,
+            min_temp: 20.0,
+            max_temp: 100.0,
+            pid: PidParameters::default(),
+            heater_pin: None,
+            sensor_zone: 10,
+            thermal_mass: None,
+        });
+        thermal.chamber = Some(ChamberHeating {
+            power_watts: 400.0,
+            max_temp: 50.0,
+            required: false,
+            heater_pin: None,
+            sensor_zone: Some(11),
+            pid: PidParameters::default(),
+            thermal_mass: None,
+        });
+        assert_eq!(thermal.plan_bed_temperature(&[(0, &pla)]), Some(60.0));
+        // Hottest material wins, within the bed's limit
+        assert_eq!(thermal.plan_bed_temperature(&[(0, &pla), (1, &abs)]), Some(100.0));
+        assert_eq!(thermal.plan_chamber_temperature(&[(0, &pla)]), None);
+        assert_eq!(thermal.plan_chamber_temperature(&[(0, &pla), (1, &abs)]), Some(50.0));
+    }
+
     #[test]
     fn test_build_volume_contains_point() {
         let volume = BuildVolume::new(200.0, 200.0, 150.0);
@@ -736,14 +2768,21 @@ mod tests {
                 valves_per_node: 4,
                 valve_type: ValveType::PneumaticSolenoid,
                 response_time_ms: 10.0,
-                dead_volume: 0.5,
+                dead_volume: CubicMm::new(0.5),
                 max_switching_freq: 10.0,
                 injection_points: vec![],
+                verification: ValveVerificationConfig::default(),
+                drivers: ValveDriverConfig::default(),
+                sub_frames: SubFrameConfig::default(),
+                frame_merge_window_ms: 0.0,
             },
             thermal: ThermalConfig {
                 zones: vec![],
                 manifold: None,
                 chamber: None,
+                bed: None,
+                channel_zones: vec![],
+                supply_watts: None,
             },
             materials: MaterialSystemConfig {
                 channel_count: 1,
@@ -754,7 +2793,10 @@ mod tests {
                     max_pressure: 100.0,
                     regulation_type: PressureRegulationType::Pneumatic,
                     sensors: vec![],
+                    channels: vec![],
                 },
+                runout_sensors: vec![],
+                compatibility: CompatibilityMatrix::default(),
             },
             motion: MotionConfig {
                 z_axis: ZAxisConfig {
@@ -763,6 +2805,7 @@ mod tests {
                     steps_per_mm: 400.0,
                     max_speed: 10.0,
                     max_acceleration: 100.0,
+                    driver: StepperDriverConfig::default(),
                 },
                 homing: HomingConfig {
                     homing_speed: 5.0,
@@ -771,12 +2814,17 @@ mod tests {
                 },
             },
             safety: SafetyLimits {
-                max_temperature: 300.0,
-                max_pressure: 120.0,
+                max_temperature: Celsius::new(300.0),
+                max_pressure: Psi::new(120.0),
                 max_valve_rate: 20.0,
                 max_z_speed: 15.0,
                 thermal_runaway_rate: 10.0,
                 pressure_fault_threshold: 10.0,
+                barrier_timeout_ms: default_barrier_timeout_ms(),
+                barrier_timeout_action: BarrierTimeoutAction::Abort,
+                interlocks: Vec::new(),
+                heartbeat: HeartbeatConfig::default(),
+                disconnect_action: DisconnectAction::Continue,
             },
             metadata: PrinterMetadata {
                 serial_number: None,
@@ -784,9 +2832,58 @@ mod tests {
                 last_calibration: None,
                 notes: None,
             },
+            sensors: Vec::new(),
+            power_loss: None,
+            bed_level: None,
+            camera: None,
+            host_telemetry: HostTelemetryConfig::default(),
+            macros: Vec::new(),
         };
 
         assert_eq!(config.grid_x_count(), 200);
         assert_eq!(config.grid_y_count(), 200);
     }
+
+    #[test]
+    fn test_bus_partitions() {
+        let bus = |spi_bus, first_board| ValveBusConfig {
+            spi_bus,
+            chip_select: 0,
+            first_board,
+            board_count: 1250,
+        };
+        let mut array = ValveArrayConfig {
+            grid_spacing: 0.5,
+            total_nodes: 40000,
+            valves_per_node: 4,
+            valve_type: ValveType::PneumaticSolenoid,
+            response_time_ms: 10.0,
+            dead_volume: CubicMm::new(0.5),
+            max_switching_freq: 10.0,
+            injection_points: vec![],
+            verification: ValveVerificationConfig::default(),
+            drivers: ValveDriverConfig {
+                buses: vec![bus(1, 1250), bus(0, 0)],
+                ..Default::default()
+            },
+            sub_frames: SubFrameConfig::default(),
+            frame_merge_window_ms: 0.0,
+        };
+
+        // 160k valves on 2500 boards of 64 outputs, half per bus
+        let partitions = array.bus_partitions();
+        assert_eq!(partitions[0].boards, 0..1250);
+        assert_eq!(partitions[0].nodes, 0..20000);
+        assert_eq!((partitions[1].spi_bus, partitions[1].nodes.clone()), (1, 20000..40000));
+
+        assert!(array.validate_buses().unwrap_err().contains("latch_pin"));
+        array.drivers.latch_pin = Some(17);
+        assert!(array.validate_buses().is_ok());
+
+        array.drivers.buses[0].first_board = 1300;
+        assert!(array.validate_buses().unwrap_err().contains("expected 1250"));
+        array.drivers.buses[0].first_board = 1250;
+        array.valves_per_node = 3;
+        assert!(array.validate_buses().unwrap_err().contains("partway"));
+    }
 }
@@ -0,0 +1,199 @@
+//! Crash-safe configuration files.
+//!
+//! A configuration is never truncated in place: [`write_atomic`] writes a
+//! temporary file beside the target, syncs it and renames it over the
+//! target, so after a crash or power cut the file holds either the old or
+//! the new contents. [`write_with_backups`] first copies the file being
+//! replaced to `<name>.<unix ms>.bak` in the same directory and keeps only
+//! the newest `keep` of those.
+//!
+//! [`restore_latest`] puts back the newest backup that still parses, for a
+//! configuration that was edited into an unusable state by hand.
+
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::ConfigError;
+
+/// Backups kept by `to_file` for printer configurations and material
+/// profiles.
+pub const DEFAULT_BACKUP_COUNT: usize = 5;
+
+const BACKUP_EXTENSION: &str = "bak";
+
+/// A backup of a configuration file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Backup {
+    pub path: PathBuf,
+    /// When the replaced file was backed up (ms since the Unix epoch)
+    pub timestamp_ms: u64,
+}
+
+/// Replaces `path` with `contents` so that a crash never leaves it partly
+/// written.
+pub fn write_atomic(path: &Path, contents: &[u8]) -> Result<(), ConfigError> {
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| ConfigError::IoError(format!("{} is not a file path", path.display())))?;
+    let mut tmp_name = file_name.to_os_string();
+    tmp_name.push(".tmp");
+    let tmp = path.with_file_name(tmp_name);
+
+    let result = (|| {
+        let mut file = File::create(&tmp)?;
+        file.write_all(contents)?;
+        file.sync_all()?;
+        fs::rename(&tmp, path)?;
+        sync_parent(path)
+    })();
+    result.map_err(|e| {
+        fs::remove_file(&tmp).ok();
+        ConfigError::IoError(format!("{}: {}", path.display(), e))
+    })
+}
+
+/// Backs up the current `path`, if any, then replaces it atomically,
+/// keeping the newest `keep` backups.
+pub fn write_with_backups(path: &Path, contents: &[u8], keep: usize) -> Result<(), ConfigError> {
+    if keep > 0 {
+        match fs::read(path) {
+            // Identical contents need no second backup
+            Ok(current) if current != contents => {
+                let mut timestamp_ms = now_ms();
+                let mut backup = backup_path(path, timestamp_ms);
+                while backup.exists() {
+                    timestamp_ms += 1;
+                    backup = backup_path(path, timestamp_ms);
+                }
+                write_atomic(&backup, &current)?;
+            }
+            Ok(_) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(ConfigError::IoError(format!("{}: {}", path.display(), e))),
+        }
+    }
+    write_atomic(path, contents)?;
+
+    for old in list_backups(path)?.into_iter().skip(keep) {
+        fs::remove_file(&old.path).map_err(|e| ConfigError::IoError(format!("{}: {}", old.path.display(), e)))?;
+    }
+    Ok(())
+}
+
+/// Backups of `path`, newest first.
+pub fn list_backups(path: &Path) -> Result<Vec<Backup>, ConfigError> {
+    let Some(file_name) = path.file_name().and_then(|n| n.to_str()) else {
+        return Ok(Vec::new());
+    };
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(ConfigError::IoError(format!("{}: {}", dir.display(), e))),
+    };
+
+    let mut backups: Vec<Backup> = entries
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            let timestamp_ms = path
+                .file_name()?
+                .to_str()?
+                .strip_prefix(file_name)?
+                .strip_prefix('.')?
+                .strip_suffix(BACKUP_EXTENSION)?
+                .strip_suffix('.')?
+                .parse()
+                .ok()?;
+            Some(Backup { path, timestamp_ms })
+        })
+        .collect();
+    backups.sort_by(|a, b| b.timestamp_ms.cmp(&a.timestamp_ms));
+    Ok(backups)
+}
+
+/// Restores the newest backup of `path` that `parse` accepts and returns
+/// what it parsed to. Backups that fail to parse are skipped and left in
+/// place.
+pub fn restore_latest<T>(
+    path: &Path,
+    parse: impl Fn(&str) -> Result<T, ConfigError>,
+) -> Result<T, ConfigError> {
+    for backup in list_backups(path)? {
+        let Ok(contents) = fs::read_to_string(&backup.path) else { continue };
+        if let Ok(value) = parse(&contents) {
+            write_atomic(path, contents.as_bytes())?;
+            return Ok(value);
+        }
+    }
+    Err(ConfigError::NoBackup(path.display().to_string()))
+}
+
+fn backup_path(path: &Path, timestamp_ms: u64) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".{}.{}", timestamp_ms, BACKUP_EXTENSION));
+    path.with_file_name(name)
+}
+
+/// Makes the rename itself durable. Directories cannot be opened for
+/// syncing on every platform, so failure to open is ignored.
+fn sync_parent(path: &Path) -> std::io::Result<()> {
+    match path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        Some(dir) => match File::open(dir) {
+            Ok(dir) => dir.sync_all().or(Ok(())),
+            Err(_) => Ok(()),
+        },
+        None => Ok(()),
+    }
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backups_and_restore() {
+        let dir = std::env::temp_dir().join(format!("hg4d-persist-{}", std::process::id()));
+        fs::remove_dir_all(&dir).ok();
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("printer.toml");
+
+        for i in 0..5 {
+            write_with_backups(&path, format!("value = {}\n", i).as_bytes(), 3).unwrap();
+        }
+        // Rewriting the same contents adds no backup
+        write_with_backups(&path, b"value = 4\n", 3).unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "value = 4\n");
+        let backups = list_backups(&path).unwrap();
+        assert_eq!(backups.len(), 3);
+        assert_eq!(fs::read_to_string(&backups[0].path).unwrap(), "value = 3\n");
+        assert!(!dir.join("printer.toml.tmp").exists());
+
+        // A hand edit breaks the file; the newest parseable backup comes back
+        fs::write(&path, "value = ").unwrap();
+        fs::write(&backups[0].path, "garbage").unwrap();
+        let parse = |s: &str| {
+            s.trim()
+                .strip_prefix("value = ")
+                .and_then(|v| v.parse::<u32>().ok())
+                .ok_or_else(|| ConfigError::ParseError(s.to_string()))
+        };
+        assert_eq!(restore_latest(&path, parse).unwrap(), 2);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "value = 2\n");
+
+        fs::remove_dir_all(&dir).ok();
+        assert!(matches!(restore_latest(&path, parse), Err(ConfigError::NoBackup(_))));
+    }
+}
@@ -0,0 +1,441 @@
+//! Print settings presets and layered overrides.
+//!
+//! A full [`PrintSettings`] is rarely written by hand. Instead a job starts
+//! from the printer's defaults and applies partial overrides on top:
+//!
+//! ```text
+//! printer defaults → material overrides → job overrides → resolved settings
+//! ```
+//!
+//! Each override is a [`SettingsPreset`]: a named, partial settings table
+//! tagged with the [`SettingsLayer`] it belongs to. Presets may inherit from
+//! another preset of the same layer, so a `petg-fast` bundle can extend
+//! `petg` and change only what differs.
+//!
+//! ## Merge Semantics
+//!
+//! Merging is deterministic and independent of file or hash-map order:
+//!
+//! - Layers apply in [`SettingsLayer`] order; presets within one layer apply
+//!   in the order they were pushed
+//! - Tables merge key by key, recursively
+//! - Any other value (numbers, strings, arrays) replaces the previous value
+//!   wholesale
+//! - Paths listed in `unset` are removed before the preset's own values are
+//!   applied, which is how an optional setting is cleared (TOML has no null)
+//!
+//! The result is deserialized back into [`PrintSettings`], so an override
+//! that gives a setting the wrong type is rejected when resolving.
+//!
+//! ## Example
+//!
+//! ```toml
+//! name = "petg"
+//! layer = "material"
+//! unset = ["supports.material_channel"]
+//!
+//! [settings.speeds]
+//! normal_speed = 35.0
+//! ```
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{persist, ConfigError, PrintSettings};
+
+/// Maximum preset inheritance depth, guarding against runaway chains.
+const MAX_INHERITANCE_DEPTH: usize = 16;
+
+/// Source of an override, in application order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SettingsLayer {
+    /// Printer-wide defaults
+    Printer,
+    /// Material-specific overrides
+    Material,
+    /// Overrides for a single job
+    Job,
+}
+
+/// Named bundle of partial settings.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SettingsPreset {
+    /// Preset name, unique within a library
+    pub name: String,
+
+    /// Layer the preset applies at
+    pub layer: SettingsLayer,
+
+    /// Preset (same layer) whose values this one extends
+    #[serde(default)]
+    pub inherits: Option<String>,
+
+    /// Dotted setting paths to remove before applying `settings`
+    #[serde(default)]
+    pub unset: Vec<String>,
+
+    /// Partial `PrintSettings` table
+    #[serde(default)]
+    pub settings: toml::Table,
+}
+
+impl SettingsPreset {
+    /// Creates an empty preset.
+    pub fn new(name: impl Into<String>, layer: SettingsLayer) -> Self {
+        Self {
+            name: name.into(),
+            layer,
+            inherits: None,
+            unset: Vec::new(),
+            settings: toml::Table::new(),
+        }
+    }
+
+    /// Sets a single value by dotted path (e.g. `"infill.density"`).
+    pub fn set(mut self, path: &str, value: impl Into<toml::Value>) -> Self {
+        let mut table = &mut self.settings;
+        let mut keys = path.split('.').peekable();
+        while let Some(key) = keys.next() {
+            if keys.peek().is_none() {
+                table.insert(key.to_string(), value.into());
+                break;
+            }
+            let entry = table
+                .entry(key.to_string())
+                .or_insert_with(|| toml::Value::Table(toml::Table::new()));
+            if !entry.is_table() {
+                *entry = toml::Value::Table(toml::Table::new());
+            }
+            table = entry.as_table_mut().expect("entry is a table");
+        }
+        self
+    }
+
+    /// Adds a dotted path to clear.
+    pub fn unset(mut self, path: &str) -> Self {
+        self.unset.push(path.to_string());
+        self
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, ConfigError> {
+        let contents = std::fs::read_to_string(path.as_ref())
+            .map_err(|e| ConfigError::IoError(e.to_string()))?;
+
+        toml::from_str(&contents)
+            .map_err(|e| ConfigError::ParseError(e.to_string()))
+    }
+
+    pub fn to_file<P: AsRef<Path>>(&self, path: P) -> Result<(), ConfigError> {
+        let contents = toml::to_string_pretty(self)
+            .map_err(|e| ConfigError::SerializationError(e.to_string()))?;
+
+        persist::write_atomic(path.as_ref(), contents.as_bytes())
+    }
+
+    /// Applies this preset's removals and values to `target`.
+    fn apply_to(&self, target: &mut toml::Table) {
+        for path in &self.unset {
+            remove_path(target, path);
+        }
+        merge_tables(target, &self.settings);
+    }
+}
+
+/// Collection of named presets.
+#[derive(Debug, Clone, Default)]
+pub struct PresetLibrary {
+    presets: BTreeMap<String, SettingsPreset>,
+}
+
+impl PresetLibrary {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Loads every `*.toml` preset in a directory.
+    pub fn load_dir<P: AsRef<Path>>(dir: P) -> Result<Self, ConfigError> {
+        let mut library = Self::new();
+        let entries = std::fs::read_dir(dir.as_ref())
+            .map_err(|e| ConfigError::IoError(e.to_string()))?;
+
+        let mut paths: Vec<_> = entries
+            .filter_map(|e| e.ok().map(|e| e.path()))
+            .filter(|p| p.extension().is_some_and(|ext| ext == "toml"))
+            .collect();
+        paths.sort();
+
+        for path in paths {
+            library.insert(SettingsPreset::from_file(&path)?)?;
+        }
+        Ok(library)
+    }
+
+    /// Adds a preset; names must be unique.
+    pub fn insert(&mut self, preset: SettingsPreset) -> Result<(), ConfigError> {
+        if self.presets.contains_key(&preset.name) {
+            return Err(ConfigError::InvalidConfiguration(format!(
+                "Duplicate preset '{}'",
+                preset.name
+            )));
+        }
+        self.presets.insert(preset.name.clone(), preset);
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&SettingsPreset> {
+        self.presets.get(name)
+    }
+
+    /// Preset names, sorted.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.presets.keys().map(String::as_str)
+    }
+
+    /// Resolves a preset's inheritance chain into one self-contained preset.
+    pub fn flatten(&self, name: &str) -> Result<SettingsPreset, ConfigError> {
+        let mut chain = Vec::new();
+        let mut current = Some(name);
+        while let Some(next) = current {
+            if chain.len() >= MAX_INHERITANCE_DEPTH
+                || chain.iter().any(|p: &&SettingsPreset| p.name == next)
+            {
+                return Err(ConfigError::InvalidConfiguration(format!(
+                    "Preset '{}' has a cyclic or too deep inheritance chain",
+                    name
+                )));
+            }
+            let preset = self.presets.get(next).ok_or_else(|| {
+                ConfigError::MissingField(format!("preset '{}'", next))
+            })?;
+            if let Some(child) = chain.last() {
+                if preset.layer != child.layer {
+                    return Err(ConfigError::InvalidConfiguration(format!(
+                        "Preset '{}' ({:?}) cannot inherit from '{}' ({:?})",
+                        child.name, child.layer, preset.name, preset.layer
+                    )));
+                }
+            }
+            chain.push(preset);
+            current = preset.inherits.as_deref();
+        }
+
+        // Apply from the root ancestor down to the requested preset
+        let requested = chain[0];
+        let mut flat = SettingsPreset::new(requested.name.clone(), requested.layer);
+        for preset in chain.iter().rev() {
+            for path in &preset.unset {
+                remove_path(&mut flat.settings, path);
+                flat.unset.retain(|p| p != path);
+                flat.unset.push(path.clone());
+            }
+            merge_tables(&mut flat.settings, &preset.settings);
+        }
+        Ok(flat)
+    }
+}
+
+/// Override applied during resolution, recorded for provenance.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AppliedOverride {
+    pub layer: SettingsLayer,
+    /// Preset name or other description of the override's origin
+    pub source: String,
+}
+
+/// Base settings plus the overrides to apply on top of them.
+#[derive(Debug, Clone)]
+pub struct SettingsStack {
+    base: PrintSettings,
+    overrides: Vec<SettingsPreset>,
+}
+
+impl SettingsStack {
+    /// Starts from complete printer default settings.
+    pub fn new(base: PrintSettings) -> Self {
+        Self {
+            base,
+            overrides: Vec::new(),
+        }
+    }
+
+    /// Adds an override; application order is decided by its layer.
+    pub fn push(&mut self, preset: SettingsPreset) -> &mut Self {
+        self.overrides.push(preset);
+        self
+    }
+
+    /// Adds a preset from `library`, with its inheritance flattened.
+    pub fn push_named(&mut self, library: &PresetLibrary, name: &str) -> Result<&mut Self, ConfigError> {
+        let preset = library.flatten(name)?;
+        Ok(self.push(preset))
+    }
+
+    /// Merges all overrides into the base settings.
+    pub fn resolve(&self) -> Result<ResolvedSettings, ConfigError> {
+        let mut table = match toml::Value::try_from(&self.base) {
+            Ok(toml::Value::Table(table)) => table,
+            Ok(_) => unreachable!("PrintSettings serializes to a table"),
+            Err(e) => return Err(ConfigError::SerializationError(e.to_string())),
+        };
+
+        // Stable sort keeps push order within a layer
+        let mut ordered: Vec<&SettingsPreset> = self.overrides.iter().collect();
+        ordered.sort_by_key(|p| p.layer);
+
+        let mut applied = Vec::with_capacity(ordered.len());
+        for preset in ordered {
+            preset.apply_to(&mut table);
+            applied.push(AppliedOverride {
+                layer: preset.layer,
+                source: preset.name.clone(),
+            });
+        }
+
+        let settings = toml::Value::Table(table).try_into().map_err(|e: toml::de::Error| {
+            ConfigError::InvalidConfiguration(format!("Resolved settings are invalid: {}", e))
+        })?;
+
+        Ok(ResolvedSettings { settings, applied })
+    }
+}
+
+/// Final settings for a job together with the overrides that produced them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResolvedSettings {
+    pub settings: PrintSettings,
+
+    /// Overrides in the order they were applied
+    pub applied: Vec<AppliedOverride>,
+}
+
+impl ResolvedSettings {
+    /// TOML document embedded in `.hg4d` metadata.
+    pub fn to_metadata(&self) -> Result<String, ConfigError> {
+        toml::to_string(self).map_err(|e| ConfigError::SerializationError(e.to_string()))
+    }
+
+    pub fn from_metadata(metadata: &str) -> Result<Self, ConfigError> {
+        toml::from_str(metadata).map_err(|e| ConfigError::ParseError(e.to_string()))
+    }
+}
+
+/// Recursively merges `overlay` into `base`: tables merge, everything else
+/// replaces.
+fn merge_tables(base: &mut toml::Table, overlay: &toml::Table) {
+    for (key, value) in overlay {
+        match (base.get_mut(key), value) {
+            (Some(toml::Value::Table(existing)), toml::Value::Table(incoming)) => {
+                merge_tables(existing, incoming);
+            }
+            _ => {
+                base.insert(key.clone(), value.clone());
+            }
+        }
+    }
+}
+
+/// Removes a dotted path; missing paths are ignored.
+fn remove_path(table: &mut toml::Table, path: &str) {
+    match path.split_once('.') {
+        None => {
+            table.remove(path);
+        }
+        Some((head, rest)) => {
+            if let Some(toml::Value::Table(child)) = table.get_mut(head) {
+                remove_path(child, rest);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        AdhesionSettings, DeadVolumeSettings, InfillPattern, InfillSettings, InspectionSettings, ShellSettings, SpeedSettings,
+        SupportSettings,
+    };
+
+    fn base() -> PrintSettings {
+        PrintSettings {
+            layer_height: 0.2,
+            first_layer_height: 0.3,
+            speeds: SpeedSettings {
+                normal_speed: 50.0,
+                first_layer_factor: 0.5,
+                small_perimeter_factor: 0.5,
+            },
+            shells: ShellSettings::default(),
+            infill: InfillSettings {
+                density: 20.0,
+                pattern: InfillPattern::Grid,
+                gradient: None,
+            },
+            supports: SupportSettings {
+                enabled: false,
+                material_channel: Some(1),
+                density: 15.0,
+            },
+            multi_material: None,
+            vase: None,
+            dead_volume: DeadVolumeSettings::default(),
+            inspection: InspectionSettings::default(),
+            adhesion: AdhesionSettings::default(),
+            modifiers: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_layers_apply_in_order() {
+        let mut library = PresetLibrary::new();
+        library
+            .insert(
+                SettingsPreset::new("petg", SettingsLayer::Material)
+                    .set("speeds.normal_speed", 35.0)
+                    .set("infill.density", 30.0)
+                    .unset("supports.material_channel"),
+            )
+            .unwrap();
+        let mut fast = SettingsPreset::new("petg-fast", SettingsLayer::Material).set("speeds.normal_speed", 45.0);
+        fast.inherits = Some("petg".to_string());
+        library.insert(fast).unwrap();
+
+        let mut stack = SettingsStack::new(base());
+        // Job pushed first still applies after the material layer
+        stack.push(SettingsPreset::new("job", SettingsLayer::Job).set("infill.density", 50.0));
+        stack.push_named(&library, "petg-fast").unwrap();
+
+        let resolved = stack.resolve().unwrap();
+        assert_eq!(resolved.settings.speeds.normal_speed, 45.0);
+        assert_eq!(resolved.settings.speeds.first_layer_factor, 0.5);
+        assert_eq!(resolved.settings.infill.density, 50.0);
+        assert_eq!(resolved.settings.supports.material_channel, None);
+        assert_eq!(
+            resolved.applied.iter().map(|a| a.source.as_str()).collect::<Vec<_>>(),
+            ["petg-fast", "job"]
+        );
+
+        let round_trip = ResolvedSettings::from_metadata(&resolved.to_metadata().unwrap()).unwrap();
+        assert_eq!(round_trip.applied, resolved.applied);
+        assert_eq!(round_trip.settings.infill.density, 50.0);
+    }
+
+    #[test]
+    fn test_invalid_presets_rejected() {
+        let mut library = PresetLibrary::new();
+        let mut a = SettingsPreset::new("a", SettingsLayer::Job);
+        a.inherits = Some("b".to_string());
+        let mut b = SettingsPreset::new("b", SettingsLayer::Job);
+        b.inherits = Some("a".to_string());
+        library.insert(a).unwrap();
+        library.insert(b).unwrap();
+        assert!(library.flatten("a").is_err());
+
+        let mut stack = SettingsStack::new(base());
+        stack.push(SettingsPreset::new("bad-type", SettingsLayer::Job).set("infill.density", "dense"));
+        assert!(stack.resolve().is_err());
+    }
+}
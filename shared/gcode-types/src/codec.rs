@@ -0,0 +1,227 @@
+//! Layer block compression.
+//!
+//! Valve patterns are highly repetitive, so encoded layer blocks compress
+//! well. The codec of a file is recorded in its header metadata; in a file
+//! with a codec, every layer block is stored as
+//!
+//! ```text
+//! [codec id: u8][payload]
+//! ```
+//!
+//! so the writer can store a block uncompressed (id 0) when compression does
+//! not shrink it. Files without a codec entry hold bare blocks, as written
+//! before codecs existed. Block checksums cover the stored bytes.
+//!
+//! zstd gives the best ratio; lz4 decodes several times faster, which
+//! matters on controllers where layer decoding competes with valve timing.
+//! [`benchmark`] measures both on real layer data.
+
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::CommandError;
+
+/// Compression applied to layer blocks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BlockCodec {
+    None,
+    Zstd,
+    Lz4,
+}
+
+impl BlockCodec {
+    pub const ALL: [BlockCodec; 3] = [BlockCodec::None, BlockCodec::Zstd, BlockCodec::Lz4];
+
+    /// Identifier stored in front of each block.
+    pub fn id(&self) -> u8 {
+        match self {
+            BlockCodec::None => 0,
+            BlockCodec::Zstd => 1,
+            BlockCodec::Lz4 => 2,
+        }
+    }
+
+    pub fn from_id(id: u8) -> Result<Self, CommandError> {
+        match id {
+            0 => Ok(BlockCodec::None),
+            1 => Ok(BlockCodec::Zstd),
+            2 => Ok(BlockCodec::Lz4),
+            _ => Err(CommandError::DeserializationError(format!("Unknown block codec id {}", id))),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            BlockCodec::None => "none",
+            BlockCodec::Zstd => "zstd",
+            BlockCodec::Lz4 => "lz4",
+        }
+    }
+
+    /// Compresses a payload. `level` (0-9) is used by zstd only; 0 selects
+    /// the zstd default.
+    pub fn compress(&self, data: &[u8], level: u32) -> Result<Vec<u8>, CommandError> {
+        match self {
+            BlockCodec::None => Ok(data.to_vec()),
+            BlockCodec::Zstd => zstd::bulk::compress(data, level.min(9) as i32)
+                .map_err(|e| CommandError::SerializationError(format!("zstd: {}", e))),
+            BlockCodec::Lz4 => Ok(lz4_flex::compress_prepend_size(data)),
+        }
+    }
+
+    /// Decompresses a payload, refusing output larger than `max_size`.
+    pub fn decompress(&self, data: &[u8], max_size: usize) -> Result<Vec<u8>, CommandError> {
+        let out = match self {
+            BlockCodec::None => data.to_vec(),
+            BlockCodec::Zstd => zstd::bulk::decompress(data, max_size)
+                .map_err(|e| CommandError::DeserializationError(format!("zstd: {}", e)))?,
+            BlockCodec::Lz4 => {
+                let declared = data
+                    .get(..4)
+                    .map(|b| u32::from_le_bytes(b.try_into().unwrap()) as usize)
+                    .ok_or_else(|| CommandError::DeserializationError("lz4 block truncated".to_string()))?;
+                if declared > max_size {
+                    return Err(CommandError::DeserializationError(format!(
+                        "lz4 block declares {} bytes, limit is {}",
+                        declared, max_size
+                    )));
+                }
+                lz4_flex::decompress_size_prepended(data)
+                    .map_err(|e| CommandError::DeserializationError(format!("lz4: {}", e)))?
+            }
+        };
+        if out.len() > max_size {
+            return Err(CommandError::DeserializationError(format!(
+                "Block decompresses to {} bytes, limit is {}",
+                out.len(),
+                max_size
+            )));
+        }
+        Ok(out)
+    }
+}
+
+impl fmt::Display for BlockCodec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for BlockCodec {
+    type Err = CommandError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        BlockCodec::ALL
+            .into_iter()
+            .find(|c| c.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| CommandError::InvalidParameter(format!("Unknown codec '{}'", s)))
+    }
+}
+
+/// Wraps an encoded block for storage, falling back to an uncompressed
+/// block when compression does not shrink it.
+pub fn pack_block(codec: BlockCodec, level: u32, block: &[u8]) -> Result<Vec<u8>, CommandError> {
+    let compressed = codec.compress(block, level)?;
+    let (codec, payload) = if codec != BlockCodec::None && compressed.len() < block.len() {
+        (codec, compressed)
+    } else {
+        (BlockCodec::None, block.to_vec())
+    };
+
+    let mut stored = Vec::with_capacity(payload.len() + 1);
+    stored.push(codec.id());
+    stored.extend_from_slice(&payload);
+    Ok(stored)
+}
+
+/// Recovers an encoded block stored by [`pack_block`].
+pub fn unpack_block(stored: &[u8], max_size: usize) -> Result<Vec<u8>, CommandError> {
+    let (&id, payload) = stored
+        .split_first()
+        .ok_or_else(|| CommandError::DeserializationError("Empty layer block".to_string()))?;
+    BlockCodec::from_id(id)?.decompress(payload, max_size)
+}
+
+/// Size and speed of one codec over a set of blocks.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CodecBenchmark {
+    pub codec: BlockCodec,
+    pub level: u32,
+    pub raw_bytes: u64,
+    pub stored_bytes: u64,
+    pub encode_time: Duration,
+    pub decode_time: Duration,
+}
+
+impl CodecBenchmark {
+    /// Stored size relative to raw size (lower is better).
+    pub fn ratio(&self) -> f64 {
+        if self.raw_bytes == 0 {
+            return 1.0;
+        }
+        self.stored_bytes as f64 / self.raw_bytes as f64
+    }
+
+    /// Decode throughput in MB/s of raw output.
+    pub fn decode_mb_per_s(&self) -> f64 {
+        let secs = self.decode_time.as_secs_f64();
+        if secs == 0.0 {
+            return f64::INFINITY;
+        }
+        self.raw_bytes as f64 / 1e6 / secs
+    }
+}
+
+/// Packs and unpacks every block with a codec, checking the round trip.
+pub fn benchmark(codec: BlockCodec, level: u32, blocks: &[Vec<u8>]) -> Result<CodecBenchmark, CommandError> {
+    let max_size = blocks.iter().map(Vec::len).max().unwrap_or(0);
+
+    let start = Instant::now();
+    let stored = blocks
+        .iter()
+        .map(|block| pack_block(codec, level, block))
+        .collect::<Result<Vec<_>, _>>()?;
+    let encode_time = start.elapsed();
+
+    let start = Instant::now();
+    for (block, stored) in blocks.iter().zip(&stored) {
+        if unpack_block(stored, max_size)? != *block {
+            return Err(CommandError::DeserializationError(format!("{} round trip mismatch", codec)));
+        }
+    }
+    let decode_time = start.elapsed();
+
+    Ok(CodecBenchmark {
+        codec,
+        level,
+        raw_bytes: blocks.iter().map(|b| b.len() as u64).sum(),
+        stored_bytes: stored.iter().map(|b| b.len() as u64).sum(),
+        encode_time,
+        decode_time,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pack_round_trip_and_fallback() {
+        let repetitive: Vec<u8> = (0..4096u32).map(|i| (i / 64) as u8).collect();
+        for codec in BlockCodec::ALL {
+            let stored = pack_block(codec, 6, &repetitive).unwrap();
+            assert_eq!(stored[0], codec.id());
+            assert_eq!(unpack_block(&stored, repetitive.len()).unwrap(), repetitive);
+        }
+        assert!(unpack_block(&pack_block(BlockCodec::Zstd, 6, &repetitive).unwrap(), 100).is_err());
+
+        // Too short to compress: stored raw
+        let stored = pack_block(BlockCodec::Zstd, 6, &[1, 2, 3]).unwrap();
+        assert_eq!(stored, vec![0, 1, 2, 3]);
+        assert_eq!("LZ4".parse::<BlockCodec>().unwrap(), BlockCodec::Lz4);
+    }
+}
@@ -0,0 +1,462 @@
+//! Compact layer encoding.
+//!
+//! Dense layers repeat the same valve pattern across long stretches of the
+//! grid. A [`LayerFrame`] stores a layer as one plane per material channel,
+//! each holding run-length encoded rows inside the layer's region of
+//! interest. A run covers consecutive nodes on a row that share the same
+//! valve bitmask, so a solid 200-node row costs one run instead of 200
+//! coordinate/valve-list pairs.
+//!
+//! Frames always expand to explicit states for every valve at a node
+//! (`0..valves_per_node`), so valves a [`NodeValveState`] left unspecified
+//! decode as closed.
+
+use std::collections::{BTreeMap, HashMap};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{ActivationGroups, CommandError, GridCoordinate, Layer, NodeValveState, ObjectTag, ValveState};
+
+/// Maximum number of valves per node a frame can encode (bits in the mask).
+pub const MAX_FRAME_VALVES: u8 = 16;
+
+/// Consecutive nodes on a row sharing the same valve pattern.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ValveRun {
+    /// X offset of the first node from the frame origin
+    pub x: u32,
+    /// Number of nodes in the run
+    pub len: u32,
+    /// Open valves as a bitmask (bit N = valve N)
+    pub mask: u16,
+}
+
+/// Runs on a single row of a channel plane.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FrameRow {
+    /// Y offset from the frame origin
+    pub y: u32,
+    /// Runs ordered by X
+    pub runs: Vec<ValveRun>,
+}
+
+/// All nodes assigned to one material channel.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChannelPlane {
+    /// Material channel, or None for nodes without an assignment
+    pub channel: Option<u8>,
+    /// Non-empty rows ordered by Y
+    pub rows: Vec<FrameRow>,
+}
+
+/// Run-length encoded representation of a [`Layer`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LayerFrame {
+    pub z_height: f32,
+    pub layer_number: u32,
+    pub primary_material: Option<u8>,
+    pub estimated_time: Option<f32>,
+    /// Lowest grid coordinate of the region of interest
+    pub origin: GridCoordinate,
+    /// Region of interest size in nodes
+    pub width: u32,
+    pub height: u32,
+    /// Valves decoded per node
+    pub valves_per_node: u8,
+    /// One plane per material channel, ordered by channel
+    pub planes: Vec<ChannelPlane>,
+    /// Object tags carried over from the layer unchanged
+    pub objects: Vec<ObjectTag>,
+    /// Staggered opening carried over from the layer unchanged
+    #[serde(default)]
+    pub activation_groups: Option<ActivationGroups>,
+    /// Cooling floor carried over from the layer unchanged
+    #[serde(default)]
+    pub min_layer_time: Option<f32>,
+    /// Close-early leads (ms) of the nodes that have one, ordered by row
+    /// then column
+    #[serde(default)]
+    pub close_early: Vec<(GridCoordinate, f32)>,
+    /// Inspection hold carried over from the layer unchanged
+    #[serde(default)]
+    pub inspect: bool,
+    /// Z ramp carried over from the layer unchanged
+    #[serde(default)]
+    pub ramp_ms: Option<u32>,
+    /// Firing order of a ramp layer's nodes, which the row-ordered planes
+    /// lose; empty for other layers
+    #[serde(default)]
+    pub ramp_path: Vec<GridCoordinate>,
+}
+
+impl LayerFrame {
+    /// Encodes a layer.
+    ///
+    /// If a position appears more than once within a channel the last entry
+    /// wins, matching the order in which an executor would apply them.
+    pub fn from_layer(layer: &Layer) -> Result<Self, CommandError> {
+        let valves_per_node = layer
+            .nodes
+            .iter()
+            .flat_map(|n| n.valves.iter().map(|v| v.index as u16 + 1))
+            .max()
+            .unwrap_or(0);
+        if valves_per_node > MAX_FRAME_VALVES as u16 {
+            return Err(CommandError::InvalidValveState(format!(
+                "Frame encoding supports at most {} valves per node, layer uses {}",
+                MAX_FRAME_VALVES, valves_per_node
+            )));
+        }
+
+        let (min_x, min_y, max_x, max_y) = layer.nodes.iter().fold(
+            (u32::MAX, u32::MAX, 0u32, 0u32),
+            |(ax, ay, bx, by), n| {
+                (
+                    ax.min(n.position.x),
+                    ay.min(n.position.y),
+                    bx.max(n.position.x),
+                    by.max(n.position.y),
+                )
+            },
+        );
+        let origin = if layer.nodes.is_empty() {
+            GridCoordinate::new(0, 0)
+        } else {
+            GridCoordinate::new(min_x, min_y)
+        };
+        let (width, height) = if layer.nodes.is_empty() {
+            (0, 0)
+        } else {
+            (max_x - min_x + 1, max_y - min_y + 1)
+        };
+
+        // channel -> (y, x) -> mask, ordered for deterministic output
+        let mut channels: BTreeMap<Option<u8>, BTreeMap<(u32, u32), u16>> = BTreeMap::new();
+        for node in &layer.nodes {
+            let mask = node
+                .valves
+                .iter()
+                .filter(|v| v.open)
+                .fold(0u16, |m, v| m | (1 << v.index));
+            channels.entry(node.material_channel).or_default().insert(
+                (node.position.y - origin.y, node.position.x - origin.x),
+                mask,
+            );
+        }
+
+        let leads: BTreeMap<(u32, u32), f32> = layer
+            .nodes
+            .iter()
+            .filter_map(|n| n.close_early_ms.map(|ms| ((n.position.y, n.position.x), ms)))
+            .collect();
+        let close_early = leads.into_iter().map(|((y, x), ms)| (GridCoordinate::new(x, y), ms)).collect();
+
+        let planes = channels
+            .into_iter()
+            .map(|(channel, nodes)| ChannelPlane {
+                channel,
+                rows: encode_rows(nodes),
+            })
+            .collect();
+
+        Ok(Self {
+            z_height: layer.z_height,
+            layer_number: layer.layer_number,
+            primary_material: layer.primary_material,
+            estimated_time: layer.estimated_time,
+            origin,
+            width,
+            height,
+            valves_per_node: valves_per_node as u8,
+            planes,
+            objects: layer.objects.clone(),
+            activation_groups: layer.activation_groups,
+            min_layer_time: layer.min_layer_time,
+            close_early,
+            inspect: layer.inspect,
+            ramp_ms: layer.ramp_ms,
+            ramp_path: match layer.ramp_ms {
+                Some(_) => layer.nodes.iter().map(|n| n.position).collect(),
+                None => Vec::new(),
+            },
+        })
+    }
+
+    /// Decodes nodes in channel, row, column order.
+    pub fn nodes(&self) -> impl Iterator<Item = NodeValveState> + '_ {
+        self.planes.iter().flat_map(move |plane| {
+            plane.rows.iter().flat_map(move |row| {
+                row.runs.iter().flat_map(move |run| {
+                    (0..run.len).map(move |i| {
+                        let position =
+                            GridCoordinate::new(self.origin.x + run.x + i, self.origin.y + row.y);
+                        let mut node = NodeValveState::new(position, self.decode_mask(run.mask));
+                        node.material_channel = plane.channel;
+                        node.close_early_ms = self.close_early_at(position);
+                        node
+                    })
+                })
+            })
+        })
+    }
+
+    /// Expands the frame back into a layer.
+    pub fn to_layer(&self) -> Layer {
+        let mut nodes: Vec<NodeValveState> = self.nodes().collect();
+        if !self.ramp_path.is_empty() {
+            let order: HashMap<GridCoordinate, usize> =
+                self.ramp_path.iter().enumerate().map(|(i, &p)| (p, i)).collect();
+            nodes.sort_by_key(|n| order.get(&n.position).copied().unwrap_or(usize::MAX));
+        }
+        Layer {
+            z_height: self.z_height,
+            layer_number: self.layer_number,
+            nodes,
+            primary_material: self.primary_material,
+            estimated_time: self.estimated_time,
+            objects: self.objects.clone(),
+            activation_groups: self.activation_groups,
+            min_layer_time: self.min_layer_time,
+            inspect: self.inspect,
+            ramp_ms: self.ramp_ms,
+        }
+    }
+
+    /// Number of nodes encoded in the frame.
+    pub fn node_count(&self) -> usize {
+        self.runs().map(|r| r.len as usize).sum()
+    }
+
+    /// Number of runs across all planes.
+    pub fn run_count(&self) -> usize {
+        self.runs().count()
+    }
+
+    /// True if no valve opens: the layer deposits nothing and only moves Z.
+    pub fn is_empty(&self) -> bool {
+        self.runs().all(|r| r.mask == 0)
+    }
+
+    fn close_early_at(&self, position: GridCoordinate) -> Option<f32> {
+        self.close_early
+            .binary_search_by_key(&(position.y, position.x), |(p, _)| (p.y, p.x))
+            .ok()
+            .map(|i| self.close_early[i].1)
+    }
+
+    fn runs(&self) -> impl Iterator<Item = &ValveRun> {
+        self.planes
+            .iter()
+            .flat_map(|p| p.rows.iter().flat_map(|r| r.runs.iter()))
+    }
+
+    fn decode_mask(&self, mask: u16) -> Vec<ValveState> {
+        (0..self.valves_per_node)
+            .map(|i| ValveState::new(i, mask & (1 << i) != 0))
+            .collect()
+    }
+}
+
+/// Groups (y, x) -> mask entries into per-row runs.
+fn encode_rows(nodes: BTreeMap<(u32, u32), u16>) -> Vec<FrameRow> {
+    let mut rows: Vec<FrameRow> = Vec::new();
+
+    for ((y, x), mask) in nodes {
+        let row = match rows.last_mut() {
+            Some(row) if row.y == y => row,
+            _ => {
+                rows.push(FrameRow { y, runs: Vec::new() });
+                rows.last_mut().expect("row just pushed")
+            }
+        };
+
+        match row.runs.last_mut() {
+            Some(run) if run.mask == mask && run.x + run.len == x => run.len += 1,
+            _ => row.runs.push(ValveRun { x, len: 1, mask }),
+        }
+    }
+
+    rows
+}
+
+/// Encoded layer data as stored in a .hg4d layer block.
+///
+/// Blocks are bincode, so fields decode by position: changing [`Layer`],
+/// [`LayerFrame`] or [`NodeValveState`] needs a new .hg4d format version.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum LayerBlock {
+    /// Explicit node list (smaller for very sparse, irregular layers)
+    Nodes(Layer),
+    /// Run-length encoded frame
+    Frame(LayerFrame),
+}
+
+impl LayerBlock {
+    /// Encodes a layer using whichever representation is smaller. Ramp
+    /// layers always use the node list, which keeps their firing order.
+    pub fn encode_compact(layer: &Layer) -> Result<Vec<u8>, CommandError> {
+        let nodes = LayerBlock::Nodes(layer.clone()).to_bytes()?;
+        if layer.ramp_ms.is_some() {
+            return Ok(nodes);
+        }
+        match LayerFrame::from_layer(layer) {
+            Ok(frame) => {
+                let framed = LayerBlock::Frame(frame).to_bytes()?;
+                Ok(if framed.len() < nodes.len() { framed } else { nodes })
+            }
+            // Layers with too many valves per node can only use the node list
+            Err(_) => Ok(nodes),
+        }
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, CommandError> {
+        bincode::serialize(self).map_err(|e| CommandError::SerializationError(e.to_string()))
+    }
+
+    /// Decodes a block, rejecting activation groups a file cannot carry
+    /// (fewer than two).
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, CommandError> {
+        let block: Self =
+            bincode::deserialize(bytes).map_err(|e| CommandError::DeserializationError(e.to_string()))?;
+        let groups = match &block {
+            LayerBlock::Nodes(layer) => layer.activation_groups,
+            LayerBlock::Frame(frame) => frame.activation_groups,
+        };
+        if let Some(groups) = groups.filter(|g| g.count < 2) {
+            return Err(CommandError::InvalidParameter(format!(
+                "Layer {} has {} activation groups; at least 2 are needed",
+                block.layer_number(),
+                groups.count
+            )));
+        }
+        Ok(block)
+    }
+
+    pub fn layer_number(&self) -> u32 {
+        match self {
+            LayerBlock::Nodes(layer) => layer.layer_number,
+            LayerBlock::Frame(frame) => frame.layer_number,
+        }
+    }
+
+    pub fn z_height(&self) -> f32 {
+        match self {
+            LayerBlock::Nodes(layer) => layer.z_height,
+            LayerBlock::Frame(frame) => frame.z_height,
+        }
+    }
+
+    /// Expands the block into a plain layer.
+    pub fn into_layer(self) -> Layer {
+        match self {
+            LayerBlock::Nodes(layer) => layer,
+            LayerBlock::Frame(frame) => frame.to_layer(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(x: u32, y: u32, open: &[u8], channel: Option<u8>) -> NodeValveState {
+        let valves = (0..4).map(|i| ValveState::new(i, open.contains(&i))).collect();
+        NodeValveState {
+            position: GridCoordinate::new(x, y),
+            valves,
+            material_channel: channel,
+            close_early_ms: None,
+        }
+    }
+
+    fn dense_layer() -> Layer {
+        let mut layer = Layer::new(0.4, 2);
+        for y in 10..30 {
+            for x in 5..105 {
+                layer.add_node(node(x, y, &[0, 2], Some(0)));
+            }
+        }
+        layer
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut layer = Layer::new(0.2, 1);
+        layer.add_node(node(3, 1, &[0], Some(1)));
+        layer.add_node(node(4, 1, &[0], Some(1)));
+        layer.add_node(node(6, 1, &[1, 3], Some(1)));
+        layer.add_node(node(2, 2, &[], None));
+        layer.nodes[2].close_early_ms = Some(4.5);
+        layer.tag_object(7, [GridCoordinate::new(3, 1), GridCoordinate::new(4, 1)]);
+        layer.inspect = true;
+
+        let frame = LayerFrame::from_layer(&layer).unwrap();
+        assert_eq!(frame.origin, GridCoordinate::new(2, 1));
+        assert_eq!((frame.width, frame.height), (5, 2));
+        assert_eq!(frame.node_count(), 4);
+        assert_eq!(frame.run_count(), 3);
+        assert_eq!(frame.close_early, vec![(GridCoordinate::new(6, 1), 4.5)]);
+
+        let decoded = frame.to_layer();
+        for original in &layer.nodes {
+            assert!(decoded.nodes.contains(original));
+        }
+        assert_eq!(decoded.nodes.len(), layer.nodes.len());
+        assert_eq!(decoded.object_at(GridCoordinate::new(4, 1)), Some(7));
+        assert!(decoded.inspect);
+    }
+
+    #[test]
+    fn test_dense_layer_compresses() {
+        let layer = dense_layer();
+        let frame = LayerFrame::from_layer(&layer).unwrap();
+        assert_eq!(frame.run_count(), 20);
+
+        let nodes = LayerBlock::Nodes(layer.clone()).to_bytes().unwrap();
+        let compact = LayerBlock::encode_compact(&layer).unwrap();
+        assert!(compact.len() * 20 < nodes.len());
+
+        let decoded = LayerBlock::from_bytes(&compact).unwrap();
+        assert!(matches!(decoded, LayerBlock::Frame(_)));
+        assert_eq!(decoded.into_layer().nodes.len(), 2000);
+    }
+
+    #[test]
+    fn test_ramp_keeps_firing_order() {
+        let mut layer = Layer::new(1.2, 5);
+        for (x, y) in [(2, 2), (1, 1), (2, 1), (1, 2)] {
+            layer.add_node(node(x, y, &[0], Some(0)));
+        }
+        layer.ramp_ms = Some(400);
+
+        let compact = LayerBlock::encode_compact(&layer).unwrap();
+        let LayerBlock::Nodes(stored) = LayerBlock::from_bytes(&compact).unwrap() else {
+            panic!("ramp layer stored as a frame");
+        };
+        assert_eq!(stored.ramp_ms, Some(400));
+
+        let decoded = LayerFrame::from_layer(&stored).unwrap().to_layer();
+        assert_eq!(decoded.ramp_ms, Some(400));
+        let order: Vec<GridCoordinate> = decoded.nodes.iter().map(|n| n.position).collect();
+        let expected: Vec<GridCoordinate> = layer.nodes.iter().map(|n| n.position).collect();
+        assert_eq!(order, expected);
+    }
+
+    #[test]
+    fn test_too_many_valves_falls_back_to_nodes() {
+        let mut layer = Layer::new(0.2, 0);
+        layer.add_node(NodeValveState::new(
+            GridCoordinate::new(0, 0),
+            vec![ValveState::open(MAX_FRAME_VALVES)],
+        ));
+
+        assert!(LayerFrame::from_layer(&layer).is_err());
+        let compact = LayerBlock::encode_compact(&layer).unwrap();
+        assert!(matches!(LayerBlock::from_bytes(&compact).unwrap(), LayerBlock::Nodes(_)));
+
+        // Zero groups cannot be split into
+        layer.activation_groups = Some(ActivationGroups { count: 0 });
+        let bytes = LayerBlock::Nodes(layer).to_bytes().unwrap();
+        assert!(matches!(LayerBlock::from_bytes(&bytes), Err(CommandError::InvalidParameter(_))));
+    }
+}
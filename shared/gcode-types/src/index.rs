@@ -0,0 +1,131 @@
+//! .hg4d layer index.
+//!
+//! The index is written after the last layer block so files can be produced
+//! in a single pass, and is located through a fixed-size trailer at the very
+//! end of the file:
+//!
+//! ```text
+//! [header][layer block]...[layer block][index entry]...[index entry][trailer]
+//! ```
+//!
+//! All integers are little-endian. Readers seek to the trailer, load the
+//! index, and can then fetch any layer block without scanning the file.
+
+use crate::CommandError;
+
+/// Trailer magic (ASCII "HGIX").
+pub const INDEX_MAGIC: u32 = 0x48474958;
+
+/// Location and checksum of one layer block.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LayerIndexEntry {
+    pub layer_number: u32,
+    /// Layer Z height (mm)
+    pub z_height: f32,
+    /// Byte offset of the layer block from the start of the file
+    pub file_offset: u64,
+    /// Encoded block size (bytes)
+    pub data_size: u32,
+    /// CRC32 of the encoded block
+    pub checksum: u32,
+}
+
+impl LayerIndexEntry {
+    pub const ENCODED_SIZE: usize = 24;
+
+    pub fn to_bytes(&self) -> [u8; Self::ENCODED_SIZE] {
+        let mut out = [0u8; Self::ENCODED_SIZE];
+        out[0..4].copy_from_slice(&self.layer_number.to_le_bytes());
+        out[4..8].copy_from_slice(&self.z_height.to_le_bytes());
+        out[8..16].copy_from_slice(&self.file_offset.to_le_bytes());
+        out[16..20].copy_from_slice(&self.data_size.to_le_bytes());
+        out[20..24].copy_from_slice(&self.checksum.to_le_bytes());
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, CommandError> {
+        if bytes.len() != Self::ENCODED_SIZE {
+            return Err(CommandError::DeserializationError(format!(
+                "Index entry must be {} bytes, got {}",
+                Self::ENCODED_SIZE,
+                bytes.len()
+            )));
+        }
+        Ok(Self {
+            layer_number: u32::from_le_bytes(bytes[0..4].try_into().unwrap()),
+            z_height: f32::from_le_bytes(bytes[4..8].try_into().unwrap()),
+            file_offset: u64::from_le_bytes(bytes[8..16].try_into().unwrap()),
+            data_size: u32::from_le_bytes(bytes[16..20].try_into().unwrap()),
+            checksum: u32::from_le_bytes(bytes[20..24].try_into().unwrap()),
+        })
+    }
+}
+
+/// Fixed-size record at the end of the file pointing at the index.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IndexTrailer {
+    /// Byte offset of the first index entry
+    pub index_offset: u64,
+    pub layer_count: u32,
+}
+
+impl IndexTrailer {
+    pub const ENCODED_SIZE: usize = 16;
+
+    pub fn to_bytes(&self) -> [u8; Self::ENCODED_SIZE] {
+        let mut out = [0u8; Self::ENCODED_SIZE];
+        out[0..8].copy_from_slice(&self.index_offset.to_le_bytes());
+        out[8..12].copy_from_slice(&self.layer_count.to_le_bytes());
+        out[12..16].copy_from_slice(&INDEX_MAGIC.to_le_bytes());
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, CommandError> {
+        if bytes.len() != Self::ENCODED_SIZE {
+            return Err(CommandError::DeserializationError(format!(
+                "Index trailer must be {} bytes, got {}",
+                Self::ENCODED_SIZE,
+                bytes.len()
+            )));
+        }
+        let magic = u32::from_le_bytes(bytes[12..16].try_into().unwrap());
+        if magic != INDEX_MAGIC {
+            return Err(CommandError::DeserializationError(format!(
+                "Bad index magic {:08x}",
+                magic
+            )));
+        }
+        Ok(Self {
+            index_offset: u64::from_le_bytes(bytes[0..8].try_into().unwrap()),
+            layer_count: u32::from_le_bytes(bytes[8..12].try_into().unwrap()),
+        })
+    }
+
+    /// Total size of the index entries this trailer describes.
+    pub fn index_size(&self) -> u64 {
+        self.layer_count as u64 * LayerIndexEntry::ENCODED_SIZE as u64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_index_round_trip() {
+        let entry = LayerIndexEntry {
+            layer_number: 7,
+            z_height: 1.4,
+            file_offset: 1 << 33,
+            data_size: 4096,
+            checksum: 0xdeadbeef,
+        };
+        assert_eq!(LayerIndexEntry::from_bytes(&entry.to_bytes()).unwrap(), entry);
+
+        let trailer = IndexTrailer { index_offset: 1 << 33, layer_count: 12 };
+        let mut bytes = trailer.to_bytes();
+        assert_eq!(IndexTrailer::from_bytes(&bytes).unwrap(), trailer);
+        bytes[15] ^= 0xff;
+        assert!(IndexTrailer::from_bytes(&bytes).is_err());
+    }
+}
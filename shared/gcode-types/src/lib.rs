@@ -28,6 +28,32 @@
 //! Multi-material systems have separate valve sets per material. Valve states
 //! specify which material's valves are active at each position.
 //! 
+//! ### Compact Layers
+//! Dense layers can be stored as a [`LayerFrame`]: run-length encoded rows
+//! per material channel within the layer's region of interest. See [`frame`].
+//! 
+//! ### Layer Index
+//! .hg4d files end with an index of layer block offsets so readers can fetch
+//! layers without scanning the file. See [`index`].
+//! 
+//! ### Block Compression
+//! Layer blocks may be compressed with zstd or lz4; the codec is recorded in
+//! the file header and in front of each block. See [`codec`].
+//! 
+//! ### Units
+//! Temperatures, pressures, lengths and volumes use the newtypes in
+//! [`units`] ([`Celsius`], [`Psi`], [`Millimeters`], [`CubicMm`]), which
+//! serialize as bare numbers.
+//! 
+//! ### Layer Patches
+//! A re-sliced file can be sent as a patch holding only the layers that
+//! changed, applied against the stored file with checksum verification.
+//! See [`patch`].
+//! 
+//! ### Command Streams
+//! Filtering, per-layer splitting and aggregate statistics of command lists
+//! are shared through [`stream`] rather than rewritten by each consumer.
+//! 
 //! ## Usage Example
 //! 
 //! ```rust
@@ -49,6 +75,20 @@
 use serde::{Deserialize, Serialize};
 use std::fmt;
 
+pub mod codec;
+pub mod frame;
+pub mod index;
+pub mod patch;
+pub mod stream;
+pub mod units;
+
+pub use codec::{BlockCodec, CodecBenchmark};
+pub use frame::{ChannelPlane, FrameRow, LayerBlock, LayerFrame, ValveRun};
+pub use index::{IndexTrailer, LayerIndexEntry};
+pub use patch::{LayerPatch, PatchError};
+pub use stream::{split_layers, CommandKind, CommandStats, CommandStreamExt, LayerCommands, ValveToggles};
+pub use units::{Celsius, CubicMm, Millimeters, Psi, UnitError};
+
 /// A three-dimensional coordinate in the build volume.
 /// 
 /// Coordinates use millimeters as the unit for all axes. The origin (0,0,0)
@@ -175,6 +215,10 @@ pub struct NodeValveState {
     pub valves: Vec<ValveState>,
     /// Optional material channel assignment (for multi-material)
     pub material_channel: Option<u8>,
+    /// Lead (ms) the node's valves close ahead of the end of the layer, so
+    /// the melt left in their dead volume drains into the layer
+    #[serde(default)]
+    pub close_early_ms: Option<f32>,
 }
 
 impl NodeValveState {
@@ -183,6 +227,7 @@ impl NodeValveState {
             position,
             valves,
             material_channel: None,
+            close_early_ms: None,
         }
     }
 
@@ -251,18 +296,44 @@ pub struct G4DCommand {
     pub valves: Vec<ValveState>,
     /// Optional extrusion amount (mm³ of material)
     pub extrusion: Option<f32>,
+    /// Latch this command's closing valves this many ms ahead of their
+    /// scheduled tick, so material left in the valve dead volume does not
+    /// ooze after the deposit ends
+    #[serde(default)]
+    pub close_early_ms: Option<f32>,
 }
 
 /// G4L command: Layer Advance - moves Z-axis to next layer.
 /// 
 /// This command increments the Z position without any X,Y motion occurring.
 /// All valve plane moves upward by the specified amount.
+///
+/// With `ramp_ms` set (vase mode), Z instead rises at constant speed to
+/// `z_height` over that time while the G4D commands up to the next G4L are
+/// deposited. Each of those G4D commands fires when Z passes its position's
+/// Z, so a helical wall is laid down without a layer seam.
 #[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct G4LCommand {
     /// New Z height in millimeters
     pub z_height: f32,
     /// Optional feed rate for Z movement (mm/s)
     pub feed_rate: Option<f32>,
+    /// Duration of a continuous Z ramp (ms)
+    #[serde(default)]
+    pub ramp_ms: Option<u32>,
+}
+
+impl G4LCommand {
+    /// True for a continuous ramp with deposition during the move.
+    pub fn is_ramp(&self) -> bool {
+        self.ramp_ms.is_some()
+    }
+
+    /// Z speed of a ramp starting at `from_z` (mm/s).
+    pub fn ramp_speed(&self, from_z: f32) -> Option<f32> {
+        let ms = self.ramp_ms?;
+        Some((self.z_height - from_z).abs() / (ms.max(1) as f32 / 1000.0))
+    }
 }
 
 /// G4C command: Color/Material Configuration - sets material mixing parameters.
@@ -288,12 +359,28 @@ pub struct G4SCommand {
 /// G4H command: Heating Control - manages temperature.
 #[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct G4HCommand {
-    /// Target temperature in Celsius
-    pub temperature: f32,
-    /// Heating zone index (for multi-zone systems)
+    /// Target temperature
+    pub temperature: Celsius,
+    /// Heating zone index (for multi-zone systems); only meaningful for
+    /// [`Heater::Zone`]
     pub zone: Option<u8>,
     /// Whether to wait for temperature to stabilize
     pub wait: bool,
+    /// Which heater the target is for
+    #[serde(default)]
+    pub heater: Heater,
+}
+
+/// Heater addressed by a G4H command.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Heater {
+    /// Material path zones: `zone`, or every zone if absent
+    #[default]
+    Zone,
+    /// Build plate
+    Bed,
+    /// Build chamber air
+    Chamber,
 }
 
 /// G4W command: Wait - synchronization barrier.
@@ -315,13 +402,15 @@ pub enum WaitType {
     Temperature,
     /// Wait for specified duration in milliseconds
     Duration(u32),
+    /// Hold at temperature until the operator resumes
+    Inspection,
 }
 
 /// G4P command: Pressure Control - adjusts pressure setpoints.
 #[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct G4PCommand {
-    /// Target pressure in PSI
-    pub pressure: f32,
+    /// Target pressure
+    pub pressure: Psi,
     /// Material channel (None = all channels)
     pub material_channel: Option<u8>,
 }
@@ -384,14 +473,21 @@ impl Command {
                     .iter()
                     .map(|v| format!("V{}:{}", v.index, if v.open { "O" } else { "C" }))
                     .collect();
-                format!("G4D {} {}", cmd.position, valves_str.join(" "))
+                let mut text = format!("G4D {} {}", cmd.position, valves_str.join(" "));
+                if let Some(ms) = cmd.close_early_ms {
+                    text.push_str(&format!(" K{:.1}", ms));
+                }
+                text
             }
             Command::G4L(cmd) => {
+                let mut text = format!("G4L Z{:.3}", cmd.z_height);
                 if let Some(f) = cmd.feed_rate {
-                    format!("G4L Z{:.3} F{:.1}", cmd.z_height, f)
-                } else {
-                    format!("G4L Z{:.3}", cmd.z_height)
+                    text.push_str(&format!(" F{:.1}", f));
+                }
+                if let Some(ms) = cmd.ramp_ms {
+                    text.push_str(&format!(" R{}", ms));
                 }
+                text
             }
             Command::G4C(cmd) => {
                 let mut parts = vec!["G4C".to_string()];
@@ -404,14 +500,22 @@ impl Command {
                 parts.join(" ")
             }
             Command::G4S(cmd) => format!("G4S SPEED {:.1}", cmd.speed_percentage),
-            Command::G4H(cmd) => format!("G4H TEMP {:.1}", cmd.temperature),
+            Command::G4H(cmd) => {
+                let prefix = match cmd.heater {
+                    Heater::Zone => "G4H",
+                    Heater::Bed => "G4H BED",
+                    Heater::Chamber => "G4H CHAMBER",
+                };
+                format!("{} TEMP {:.1}", prefix, cmd.temperature.get())
+            }
             Command::G4W(cmd) => match cmd.wait_type {
                 WaitType::Valves => "G4W VALVES".to_string(),
                 WaitType::Pressure => "G4W PRESSURE".to_string(),
                 WaitType::Temperature => "G4W TEMPERATURE".to_string(),
                 WaitType::Duration(ms) => format!("G4W P{}", ms),
+                WaitType::Inspection => "G4W INSPECT".to_string(),
             },
-            Command::G4P(cmd) => format!("G4P PRESSURE {:.1}", cmd.pressure),
+            Command::G4P(cmd) => format!("G4P PRESSURE {:.1}", cmd.pressure.get()),
             Command::Comment(text) => format!("; {}", text),
         }
     }
@@ -439,6 +543,26 @@ pub struct Layer {
     pub primary_material: Option<u8>,
     /// Estimated print time for this layer in seconds
     pub estimated_time: Option<f32>,
+    /// Nodes belonging to each printed object, for cancel-object
+    #[serde(default)]
+    pub objects: Vec<ObjectTag>,
+    /// Staggered valve opening chosen by the slicer's pressure check; None
+    /// opens every valve at once
+    #[serde(default)]
+    pub activation_groups: Option<ActivationGroups>,
+    /// Minimum time the layer must take to cool (s), from the materials it
+    /// deposits. `estimated_time` then excludes the cooling dwell, which
+    /// the firmware adds when the layer finishes early.
+    #[serde(default)]
+    pub min_layer_time: Option<f32>,
+    /// Hold for operator inspection once the layer is deposited (`G4W INSPECT`)
+    #[serde(default)]
+    pub inspect: bool,
+    /// Duration of the continuous Z ramp the layer is deposited on (ms,
+    /// vase mode). Its nodes are then in firing order, spread evenly over
+    /// the rise from the previous layer.
+    #[serde(default)]
+    pub ramp_ms: Option<u32>,
 }
 
 impl Layer {
@@ -449,9 +573,20 @@ impl Layer {
             nodes: Vec::new(),
             primary_material: None,
             estimated_time: None,
+            objects: Vec::new(),
+            activation_groups: None,
+            min_layer_time: None,
+            inspect: false,
+            ramp_ms: None,
         }
     }
 
+    /// Expected duration in seconds, including any cooling dwell.
+    pub fn expected_duration(&self) -> Option<f32> {
+        let time = self.estimated_time?;
+        Some(self.min_layer_time.map_or(time, |min| time.max(min)))
+    }
+
     /// Adds a valve node to this layer.
     pub fn add_node(&mut self, node: NodeValveState) {
         self.nodes.push(node);
@@ -467,6 +602,11 @@ impl Layer {
         self.nodes.iter().map(|n| n.open_count()).sum()
     }
 
+    /// True if no valve opens: the layer deposits nothing and only moves Z.
+    pub fn is_empty(&self) -> bool {
+        self.nodes.iter().all(|n| n.open_count() == 0)
+    }
+
     /// Checks if this layer uses multiple materials.
     pub fn is_multi_material(&self) -> bool {
         if self.nodes.is_empty() {
@@ -475,6 +615,141 @@ impl Layer {
         let first_material = self.nodes[0].material_channel;
         self.nodes.iter().any(|n| n.material_channel != first_material)
     }
+
+    /// Tags nodes as belonging to an object, merging with an existing tag.
+    pub fn tag_object(&mut self, object_id: u32, positions: impl IntoIterator<Item = GridCoordinate>) {
+        let mut tag = ObjectTag::from_positions(object_id, positions);
+        if let Some(i) = self.objects.iter().position(|t| t.object_id == object_id) {
+            let existing = self.objects.remove(i);
+            tag = ObjectTag::from_positions(
+                object_id,
+                existing.positions().chain(tag.positions()),
+            );
+        }
+        self.objects.push(tag);
+        self.objects.sort_by_key(|t| t.object_id);
+    }
+
+    /// Returns the object a node belongs to, if it is tagged.
+    pub fn object_at(&self, position: GridCoordinate) -> Option<u32> {
+        self.objects
+            .iter()
+            .find(|t| t.contains(position))
+            .map(|t| t.object_id)
+    }
+
+    /// Closes every valve of nodes belonging to the given objects.
+    ///
+    /// Nodes keep explicit closed states rather than being removed so a
+    /// valve left open by the previous layer is still closed. Returns the
+    /// number of nodes suppressed.
+    pub fn suppress_objects(&mut self, object_ids: &[u32]) -> usize {
+        let tags: Vec<&ObjectTag> = self
+            .objects
+            .iter()
+            .filter(|t| object_ids.contains(&t.object_id))
+            .collect();
+        if tags.is_empty() {
+            return 0;
+        }
+
+        let mut suppressed = 0;
+        for node in &mut self.nodes {
+            if tags.iter().any(|t| t.contains(node.position)) {
+                for valve in &mut node.valves {
+                    valve.open = false;
+                }
+                suppressed += 1;
+            }
+        }
+        suppressed
+    }
+}
+
+/// Split of a layer's valve openings into spatially interleaved groups.
+///
+/// Opening every valve of a dense layer at once sags the supply pressure.
+/// With `count` groups, each node belongs to group
+/// `(x + y * ceil(count / 2)) % count`, so neighbouring nodes fall into
+/// different groups and each group spreads evenly over the layer. Groups
+/// open one after another, in group order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ActivationGroups {
+    /// Number of groups, at least 2
+    pub count: u8,
+}
+
+impl ActivationGroups {
+    pub fn new(count: u8) -> Self {
+        Self { count: count.max(2) }
+    }
+
+    /// Group the node at `position` opens with.
+    pub fn group_of(&self, position: GridCoordinate) -> u8 {
+        let count = self.count.max(1) as u64;
+        let stride = count.div_ceil(2);
+        ((position.x as u64 + position.y as u64 * stride) % count) as u8
+    }
+}
+
+/// Consecutive nodes on one grid row.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NodeSpan {
+    pub y: u32,
+    /// X of the first node
+    pub x: u32,
+    /// Number of nodes
+    pub len: u32,
+}
+
+/// Nodes of one printed object within a layer.
+///
+/// The slicer knows which object each region came from; tagging the nodes
+/// lets the firmware cancel a failed object without stopping the print.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ObjectTag {
+    /// Object id, stable across all layers of a print
+    pub object_id: u32,
+    /// Row spans ordered by Y then X
+    pub spans: Vec<NodeSpan>,
+}
+
+impl ObjectTag {
+    /// Builds a tag from node positions, merging neighbours into spans.
+    pub fn from_positions(object_id: u32, positions: impl IntoIterator<Item = GridCoordinate>) -> Self {
+        let mut sorted: Vec<(u32, u32)> = positions.into_iter().map(|p| (p.y, p.x)).collect();
+        sorted.sort_unstable();
+        sorted.dedup();
+
+        let mut spans: Vec<NodeSpan> = Vec::new();
+        for (y, x) in sorted {
+            match spans.last_mut() {
+                Some(span) if span.y == y && span.x + span.len == x => span.len += 1,
+                _ => spans.push(NodeSpan { y, x, len: 1 }),
+            }
+        }
+        Self { object_id, spans }
+    }
+
+    pub fn contains(&self, position: GridCoordinate) -> bool {
+        let row_start = self.spans.partition_point(|s| s.y < position.y);
+        self.spans[row_start..]
+            .iter()
+            .take_while(|s| s.y == position.y)
+            .any(|s| position.x >= s.x && position.x < s.x + s.len)
+    }
+
+    /// Number of nodes tagged.
+    pub fn node_count(&self) -> usize {
+        self.spans.iter().map(|s| s.len as usize).sum()
+    }
+
+    /// Expands the spans into node positions.
+    pub fn positions(&self) -> impl Iterator<Item = GridCoordinate> + '_ {
+        self.spans
+            .iter()
+            .flat_map(|s| (s.x..s.x + s.len).map(move |x| GridCoordinate::new(x, s.y)))
+    }
 }
 
 /// Error types for command operations.
@@ -567,6 +842,7 @@ mod tests {
         let cmd = Command::G4L(G4LCommand {
             z_height: 1.5,
             feed_rate: Some(10.0),
+            ramp_ms: Some(2500),
         });
         let bytes = cmd.to_bytes().unwrap();
         let deserialized = Command::from_bytes(&bytes).unwrap();
@@ -580,4 +856,40 @@ mod tests {
         assert_eq!(physical.x, 5.0);
         assert_eq!(physical.y, 10.0);
     }
+
+    #[test]
+    fn test_suppress_tagged_object() {
+        let mut layer = Layer::new(0.2, 4);
+        for x in 0..6 {
+            layer.add_node(NodeValveState::new(GridCoordinate::new(x, 1), vec![ValveState::open(0)]));
+        }
+        layer.tag_object(1, (0..3).map(|x| GridCoordinate::new(x, 1)));
+        layer.tag_object(2, [GridCoordinate::new(4, 1), GridCoordinate::new(5, 1)]);
+        layer.tag_object(2, [GridCoordinate::new(3, 1)]);
+
+        assert_eq!(layer.objects[0].spans.len(), 1);
+        assert_eq!(layer.objects[1].spans, vec![NodeSpan { y: 1, x: 3, len: 3 }]);
+        assert_eq!(layer.object_at(GridCoordinate::new(3, 1)), Some(2));
+        assert_eq!(layer.object_at(GridCoordinate::new(3, 0)), None);
+
+        assert_eq!(layer.suppress_objects(&[2]), 3);
+        assert_eq!(layer.open_valve_count(), 3);
+        assert_eq!(layer.node_count(), 6);
+    }
+
+    #[test]
+    fn test_activation_groups_interleave() {
+        let groups = ActivationGroups::new(2);
+        assert_ne!(groups.group_of(GridCoordinate::new(4, 4)), groups.group_of(GridCoordinate::new(5, 4)));
+        assert_ne!(groups.group_of(GridCoordinate::new(4, 4)), groups.group_of(GridCoordinate::new(4, 5)));
+
+        let groups = ActivationGroups::new(4);
+        let mut sizes = [0; 4];
+        for y in 0..8 {
+            for x in 0..8 {
+                sizes[groups.group_of(GridCoordinate::new(x, y)) as usize] += 1;
+            }
+        }
+        assert_eq!(sizes, [16; 4]);
+    }
 }
@@ -0,0 +1,432 @@
+//! Layer-level .hg4d patches.
+//!
+//! Re-slicing after a small design change usually leaves most layer blocks
+//! byte-identical. A [`LayerPatch`] carries the new file's header and, for
+//! each of its layers, either a reference to an identical block of the old
+//! file or the new block itself, so only changed layers cross the network.
+//!
+//! ```text
+//! [magic: u32][version: u32]
+//! [base CRC32: u32][base size: u64][target CRC32: u32][target size: u64]
+//! [header size: u32][header bytes]
+//! [layer count: u32]
+//! per layer: [layer_number: u32][z_height: f32][source: u8]
+//!            source 0: [base layer position: u32]
+//!            source 1: [block size: u32][block bytes]
+//! ```
+//!
+//! All integers are little-endian. [`LayerPatch::apply`] refuses a base
+//! whose CRC32 and size differ from those the patch was made against, and
+//! verifies the rebuilt file against the target's CRC32 and size, so a
+//! patch either reproduces the sliced file exactly or fails.
+
+use crate::index::{IndexTrailer, LayerIndexEntry};
+
+/// Patch magic (ASCII "HGDP").
+pub const PATCH_MAGIC: u32 = 0x48474450;
+
+/// Current patch format version.
+pub const PATCH_FORMAT_VERSION: u32 = 1;
+
+/// Errors from making or applying a patch.
+#[derive(Debug, thiserror::Error)]
+pub enum PatchError {
+    #[error("Invalid .hg4d file: {0}")]
+    InvalidFile(String),
+
+    #[error("Invalid patch: {0}")]
+    InvalidPatch(String),
+
+    #[error("Patch was made for another file (expected CRC32 {expected:08x}, {expected_size} bytes; got {actual:08x}, {actual_size} bytes)")]
+    BaseMismatch {
+        expected: u32,
+        expected_size: u64,
+        actual: u32,
+        actual_size: u64,
+    },
+
+    #[error("Patched file does not match the target (expected CRC32 {expected:08x}, got {actual:08x})")]
+    TargetMismatch { expected: u32, actual: u32 },
+}
+
+/// Where a patched layer's block comes from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BlockSource {
+    /// Copy of the block at this position in the base file's index
+    Base(u32),
+    /// New block, stored in the patch
+    Data(Vec<u8>),
+}
+
+/// One layer of the patched file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PatchLayer {
+    pub layer_number: u32,
+    /// Layer Z height (mm)
+    pub z_height: f32,
+    pub source: BlockSource,
+}
+
+/// Difference between two .hg4d files at layer granularity.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LayerPatch {
+    pub base_checksum: u32,
+    pub base_size: u64,
+    pub target_checksum: u32,
+    pub target_size: u64,
+    /// Target bytes before the first layer block (magic, version, metadata)
+    pub header: Vec<u8>,
+    pub layers: Vec<PatchLayer>,
+}
+
+/// An .hg4d file split at its index.
+struct SplitFile<'a> {
+    header: &'a [u8],
+    index: Vec<LayerIndexEntry>,
+    bytes: &'a [u8],
+}
+
+impl<'a> SplitFile<'a> {
+    fn parse(bytes: &'a [u8]) -> Result<Self, PatchError> {
+        let invalid = |msg: &str| PatchError::InvalidFile(msg.to_string());
+        let end = bytes
+            .len()
+            .checked_sub(IndexTrailer::ENCODED_SIZE)
+            .ok_or_else(|| invalid("file is too short for an index trailer"))?;
+        let trailer = IndexTrailer::from_bytes(&bytes[end..])
+            .map_err(|e| PatchError::InvalidFile(e.to_string()))?;
+        if trailer.index_offset.checked_add(trailer.index_size()) != Some(end as u64) {
+            return Err(invalid("layer index does not end at the trailer"));
+        }
+        let index = bytes[trailer.index_offset as usize..end]
+            .chunks_exact(LayerIndexEntry::ENCODED_SIZE)
+            .map(LayerIndexEntry::from_bytes)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| PatchError::InvalidFile(e.to_string()))?;
+
+        // Blocks must follow the header back to back, as the slicer writes
+        // them, so the patched file can be laid out the same way
+        let header_end = index.first().map_or(trailer.index_offset, |e| e.file_offset);
+        let mut offset = header_end;
+        for entry in &index {
+            if entry.file_offset != offset {
+                return Err(invalid("layer blocks are not contiguous"));
+            }
+            offset += entry.data_size as u64;
+        }
+        if offset != trailer.index_offset {
+            return Err(invalid("layer blocks do not end at the index"));
+        }
+
+        Ok(Self {
+            header: &bytes[..header_end as usize],
+            index,
+            bytes,
+        })
+    }
+
+    fn block(&self, entry: &LayerIndexEntry) -> &'a [u8] {
+        let start = entry.file_offset as usize;
+        &self.bytes[start..start + entry.data_size as usize]
+    }
+}
+
+impl LayerPatch {
+    /// Computes the patch turning `base` into `target`.
+    ///
+    /// A target block is taken from the base when the base holds an
+    /// identical block, preferably at the same layer number, so layers that
+    /// merely shifted are reused as well.
+    pub fn diff(base: &[u8], target: &[u8]) -> Result<Self, PatchError> {
+        let old = SplitFile::parse(base)?;
+        let new = SplitFile::parse(target)?;
+
+        let layers = new
+            .index
+            .iter()
+            .map(|entry| {
+                let block = new.block(entry);
+                let same = |candidate: &LayerIndexEntry| {
+                    candidate.checksum == entry.checksum
+                        && candidate.data_size == entry.data_size
+                        && old.block(candidate) == block
+                };
+                let reused = old
+                    .index
+                    .iter()
+                    .position(|c| c.layer_number == entry.layer_number && same(c))
+                    .or_else(|| old.index.iter().position(same));
+                PatchLayer {
+                    layer_number: entry.layer_number,
+                    z_height: entry.z_height,
+                    source: match reused {
+                        Some(position) => BlockSource::Base(position as u32),
+                        None => BlockSource::Data(block.to_vec()),
+                    },
+                }
+            })
+            .collect();
+
+        Ok(Self {
+            base_checksum: crc32fast::hash(base),
+            base_size: base.len() as u64,
+            target_checksum: crc32fast::hash(target),
+            target_size: target.len() as u64,
+            header: new.header.to_vec(),
+            layers,
+        })
+    }
+
+    /// Rebuilds the target file from `base`.
+    pub fn apply(&self, base: &[u8]) -> Result<Vec<u8>, PatchError> {
+        let actual = crc32fast::hash(base);
+        if actual != self.base_checksum || base.len() as u64 != self.base_size {
+            return Err(PatchError::BaseMismatch {
+                expected: self.base_checksum,
+                expected_size: self.base_size,
+                actual,
+                actual_size: base.len() as u64,
+            });
+        }
+        let old = SplitFile::parse(base)?;
+
+        // target_size is read from the patch; reserve no more than the base
+        // and the patch's own blocks can supply
+        let carried: usize = self
+            .layers
+            .iter()
+            .map(|layer| match &layer.source {
+                BlockSource::Data(data) => data.len(),
+                BlockSource::Base(_) => 0,
+            })
+            .sum();
+        let bound = base.len() + self.header.len() + carried;
+        let mut out = Vec::with_capacity((self.target_size as usize).min(bound));
+        out.extend_from_slice(&self.header);
+        let mut index = Vec::with_capacity(self.layers.len());
+        for layer in &self.layers {
+            let block = match &layer.source {
+                BlockSource::Base(position) => {
+                    let entry = old.index.get(*position as usize).ok_or_else(|| {
+                        PatchError::InvalidPatch(format!(
+                            "layer {} refers to base layer position {} of {}",
+                            layer.layer_number,
+                            position,
+                            old.index.len()
+                        ))
+                    })?;
+                    old.block(entry)
+                }
+                BlockSource::Data(data) => data.as_slice(),
+            };
+            index.push(LayerIndexEntry {
+                layer_number: layer.layer_number,
+                z_height: layer.z_height,
+                file_offset: out.len() as u64,
+                data_size: block.len() as u32,
+                checksum: crc32fast::hash(block),
+            });
+            out.extend_from_slice(block);
+        }
+
+        let trailer = IndexTrailer {
+            index_offset: out.len() as u64,
+            layer_count: index.len() as u32,
+        };
+        for entry in &index {
+            out.extend_from_slice(&entry.to_bytes());
+        }
+        out.extend_from_slice(&trailer.to_bytes());
+
+        let actual = crc32fast::hash(&out);
+        if actual != self.target_checksum || out.len() as u64 != self.target_size {
+            return Err(PatchError::TargetMismatch {
+                expected: self.target_checksum,
+                actual,
+            });
+        }
+        Ok(out)
+    }
+
+    /// Layers whose blocks are carried in the patch.
+    pub fn changed_layers(&self) -> usize {
+        self.layers
+            .iter()
+            .filter(|l| matches!(l.source, BlockSource::Data(_)))
+            .count()
+    }
+
+    /// Layers copied from the base file.
+    pub fn reused_layers(&self) -> usize {
+        self.layers.len() - self.changed_layers()
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(&PATCH_MAGIC.to_le_bytes());
+        out.extend_from_slice(&PATCH_FORMAT_VERSION.to_le_bytes());
+        out.extend_from_slice(&self.base_checksum.to_le_bytes());
+        out.extend_from_slice(&self.base_size.to_le_bytes());
+        out.extend_from_slice(&self.target_checksum.to_le_bytes());
+        out.extend_from_slice(&self.target_size.to_le_bytes());
+        out.extend_from_slice(&(self.header.len() as u32).to_le_bytes());
+        out.extend_from_slice(&self.header);
+        out.extend_from_slice(&(self.layers.len() as u32).to_le_bytes());
+        for layer in &self.layers {
+            out.extend_from_slice(&layer.layer_number.to_le_bytes());
+            out.extend_from_slice(&layer.z_height.to_le_bytes());
+            match &layer.source {
+                BlockSource::Base(position) => {
+                    out.push(0);
+                    out.extend_from_slice(&position.to_le_bytes());
+                }
+                BlockSource::Data(data) => {
+                    out.push(1);
+                    out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+                    out.extend_from_slice(data);
+                }
+            }
+        }
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, PatchError> {
+        let mut cursor = Cursor { bytes, position: 0 };
+        let magic = cursor.u32()?;
+        if magic != PATCH_MAGIC {
+            return Err(PatchError::InvalidPatch(format!("bad magic {:08x}", magic)));
+        }
+        let version = cursor.u32()?;
+        if version > PATCH_FORMAT_VERSION {
+            return Err(PatchError::InvalidPatch(format!(
+                "unsupported format version {}",
+                version
+            )));
+        }
+        let base_checksum = cursor.u32()?;
+        let base_size = cursor.u64()?;
+        let target_checksum = cursor.u32()?;
+        let target_size = cursor.u64()?;
+        let header_size = cursor.u32()? as usize;
+        let header = cursor.take(header_size)?.to_vec();
+
+        let count = cursor.u32()?;
+        let mut layers = Vec::new();
+        for _ in 0..count {
+            let layer_number = cursor.u32()?;
+            let z_height = f32::from_le_bytes(cursor.take(4)?.try_into().unwrap());
+            let source = match cursor.take(1)?[0] {
+                0 => BlockSource::Base(cursor.u32()?),
+                1 => {
+                    let size = cursor.u32()? as usize;
+                    BlockSource::Data(cursor.take(size)?.to_vec())
+                }
+                other => {
+                    return Err(PatchError::InvalidPatch(format!(
+                        "unknown block source {} for layer {}",
+                        other, layer_number
+                    )))
+                }
+            };
+            layers.push(PatchLayer { layer_number, z_height, source });
+        }
+        if cursor.position != bytes.len() {
+            return Err(PatchError::InvalidPatch(format!(
+                "{} trailing bytes",
+                bytes.len() - cursor.position
+            )));
+        }
+
+        Ok(Self {
+            base_checksum,
+            base_size,
+            target_checksum,
+            target_size,
+            header,
+            layers,
+        })
+    }
+}
+
+/// Bounds-checked little-endian reads over a patch.
+struct Cursor<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], PatchError> {
+        let end = self
+            .position
+            .checked_add(len)
+            .filter(|end| *end <= self.bytes.len())
+            .ok_or_else(|| PatchError::InvalidPatch("truncated".to_string()))?;
+        let slice = &self.bytes[self.position..end];
+        self.position = end;
+        Ok(slice)
+    }
+
+    fn u32(&mut self) -> Result<u32, PatchError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, PatchError> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A file with a fixed header and one block per entry.
+    fn hg4d_file(blocks: &[&[u8]]) -> Vec<u8> {
+        let mut out = b"HG4D header".to_vec();
+        let mut index = Vec::new();
+        for (n, block) in blocks.iter().enumerate() {
+            index.push(LayerIndexEntry {
+                layer_number: n as u32,
+                z_height: 0.2 * (n + 1) as f32,
+                file_offset: out.len() as u64,
+                data_size: block.len() as u32,
+                checksum: crc32fast::hash(block),
+            });
+            out.extend_from_slice(block);
+        }
+        let trailer = IndexTrailer {
+            index_offset: out.len() as u64,
+            layer_count: index.len() as u32,
+        };
+        for entry in &index {
+            out.extend_from_slice(&entry.to_bytes());
+        }
+        out.extend_from_slice(&trailer.to_bytes());
+        out
+    }
+
+    #[test]
+    fn test_patch_round_trip() {
+        let base = hg4d_file(&[b"layer zero", b"layer one", b"layer two", b"layer three"]);
+        // Layer 1 changed, layer 3 removed, a new layer appended
+        let target = hg4d_file(&[b"layer zero", b"layer one, thicker", b"layer two", b"layer zero"]);
+
+        let patch = LayerPatch::diff(&base, &target).unwrap();
+        assert_eq!(patch.changed_layers(), 1);
+        assert_eq!(patch.reused_layers(), 3);
+        assert_eq!(patch.layers[3].source, BlockSource::Base(0));
+
+        let patch = LayerPatch::from_bytes(&patch.to_bytes()).unwrap();
+        assert_eq!(patch.apply(&base).unwrap(), target);
+
+        assert!(matches!(patch.apply(&target), Err(PatchError::BaseMismatch { .. })));
+
+        // A forged target size is caught by the checks, not allocated
+        let mut forged = patch.clone();
+        forged.target_size = u64::MAX;
+        assert!(matches!(forged.apply(&base), Err(PatchError::TargetMismatch { .. })));
+
+        let mut bytes = patch.to_bytes();
+        bytes.truncate(bytes.len() - 1);
+        assert!(matches!(LayerPatch::from_bytes(&bytes), Err(PatchError::InvalidPatch(_))));
+    }
+}
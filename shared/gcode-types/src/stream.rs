@@ -0,0 +1,446 @@
+//! Traversal helpers over command streams.
+//!
+//! The slicer's validator, the firmware's layer reports and the simulator's
+//! analyses all walk command lists the same ways, so the walks live here:
+//!
+//! - [`CommandStreamExt`] adapts any iterator of `&Command`: filter by
+//!   [`CommandKind`], pick out the G4D deposits or G4H setpoints, or fold
+//!   into [`CommandStats`]
+//! - [`split_layers`] cuts a command slice at its G4L boundaries
+//! - [`ValveToggles`] counts valve state changes per node and valve
+//!
+//! Valves are taken to start closed. G4D positions are mapped to nodes by
+//! rounding to the grid spacing, or to [`POSITION_RESOLUTION`] when the
+//! spacing is not known.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::iter::FilterMap;
+
+use crate::{Command, Coordinate, G4DCommand, G4HCommand, Heater, WaitType};
+
+/// Node resolution (mm) used when the grid spacing is not known.
+pub const POSITION_RESOLUTION: f32 = 0.001;
+
+/// Command type, without its parameters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum CommandKind {
+    Deposit,
+    LayerAdvance,
+    Material,
+    Speed,
+    Heating,
+    Wait,
+    Pressure,
+    Comment,
+}
+
+impl Command {
+    pub fn kind(&self) -> CommandKind {
+        match self {
+            Command::G4D(_) => CommandKind::Deposit,
+            Command::G4L(_) => CommandKind::LayerAdvance,
+            Command::G4C(_) => CommandKind::Material,
+            Command::G4S(_) => CommandKind::Speed,
+            Command::G4H(_) => CommandKind::Heating,
+            Command::G4W(_) => CommandKind::Wait,
+            Command::G4P(_) => CommandKind::Pressure,
+            Command::Comment(_) => CommandKind::Comment,
+        }
+    }
+}
+
+impl fmt::Display for CommandKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            CommandKind::Deposit => "G4D",
+            CommandKind::LayerAdvance => "G4L",
+            CommandKind::Material => "G4C",
+            CommandKind::Speed => "G4S",
+            CommandKind::Heating => "G4H",
+            CommandKind::Wait => "G4W",
+            CommandKind::Pressure => "G4P",
+            CommandKind::Comment => "comment",
+        })
+    }
+}
+
+/// Iterator over the commands of one kind; see [`CommandStreamExt::of_kind`].
+#[derive(Debug, Clone)]
+pub struct OfKind<I> {
+    inner: I,
+    kind: CommandKind,
+}
+
+impl<'a, I: Iterator<Item = &'a Command>> Iterator for OfKind<I> {
+    type Item = &'a Command;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let kind = self.kind;
+        self.inner.find(|cmd| cmd.kind() == kind)
+    }
+}
+
+fn as_deposit(cmd: &Command) -> Option<&G4DCommand> {
+    match cmd {
+        Command::G4D(d) => Some(d),
+        _ => None,
+    }
+}
+
+fn as_setpoint(cmd: &Command) -> Option<&G4HCommand> {
+    match cmd {
+        Command::G4H(h) => Some(h),
+        _ => None,
+    }
+}
+
+/// Adapters for iterators over commands.
+pub trait CommandStreamExt<'a>: Iterator<Item = &'a Command> + Sized {
+    /// Only the commands of `kind`.
+    fn of_kind(self, kind: CommandKind) -> OfKind<Self> {
+        OfKind { inner: self, kind }
+    }
+
+    /// The G4D deposit commands.
+    #[allow(clippy::type_complexity)]
+    fn deposits(self) -> FilterMap<Self, fn(&'a Command) -> Option<&'a G4DCommand>> {
+        self.filter_map(as_deposit)
+    }
+
+    /// The G4H heater setpoints.
+    #[allow(clippy::type_complexity)]
+    fn setpoints(self) -> FilterMap<Self, fn(&'a Command) -> Option<&'a G4HCommand>> {
+        self.filter_map(as_setpoint)
+    }
+
+    /// Statistics of the commands, nodes keyed at [`POSITION_RESOLUTION`].
+    fn stats(self) -> CommandStats {
+        let mut stats = CommandStats::new();
+        stats.extend(self);
+        stats
+    }
+}
+
+impl<'a, I: Iterator<Item = &'a Command>> CommandStreamExt<'a> for I {}
+
+/// The commands of one layer: from a G4L up to the next.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LayerCommands<'a> {
+    /// Z of the opening G4L; None for commands before the first G4L
+    pub z_height: Option<f32>,
+    /// The commands, opening G4L included
+    pub commands: &'a [Command],
+}
+
+/// Iterator over the layers of a command slice; see [`split_layers`].
+#[derive(Debug, Clone)]
+pub struct SplitLayers<'a> {
+    rest: &'a [Command],
+}
+
+impl<'a> Iterator for SplitLayers<'a> {
+    type Item = LayerCommands<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.rest.is_empty() {
+            return None;
+        }
+        let z_height = match &self.rest[0] {
+            Command::G4L(l) => Some(l.z_height),
+            _ => None,
+        };
+        let end = self.rest[1..]
+            .iter()
+            .position(Command::is_motion_command)
+            .map_or(self.rest.len(), |i| i + 1);
+        let (commands, rest) = self.rest.split_at(end);
+        self.rest = rest;
+        Some(LayerCommands { z_height, commands })
+    }
+}
+
+/// Splits commands at each G4L. Commands before the first G4L, if any,
+/// come first with no Z.
+pub fn split_layers(commands: &[Command]) -> SplitLayers<'_> {
+    SplitLayers { rest: commands }
+}
+
+/// A valve at a node: ((node x, node y), valve index).
+pub type ValveKey = ((i64, i64), u8);
+
+/// Counts valve state changes per node and valve.
+#[derive(Debug, Clone)]
+pub struct ValveToggles {
+    grid_spacing: f32,
+    /// Last commanded state
+    last: HashMap<ValveKey, bool>,
+    /// Changes since the counts were last reset
+    counts: HashMap<ValveKey, u32>,
+    total: u64,
+}
+
+impl ValveToggles {
+    pub fn new(grid_spacing: f32) -> Self {
+        Self {
+            grid_spacing: if grid_spacing > 0.0 { grid_spacing } else { POSITION_RESOLUTION },
+            last: HashMap::new(),
+            counts: HashMap::new(),
+            total: 0,
+        }
+    }
+
+    /// Grid node of a G4D position.
+    pub fn node_of(&self, position: &Coordinate) -> (i64, i64) {
+        (
+            (position.x / self.grid_spacing).round() as i64,
+            (position.y / self.grid_spacing).round() as i64,
+        )
+    }
+
+    /// Records a deposit and returns the valves it switched.
+    pub fn record(&mut self, deposit: &G4DCommand) -> u32 {
+        let node = self.node_of(&deposit.position);
+        let mut switched = 0;
+        for valve in &deposit.valves {
+            let last = self.last.entry((node, valve.index)).or_insert(false);
+            if *last != valve.open {
+                *last = valve.open;
+                *self.counts.entry((node, valve.index)).or_insert(0) += 1;
+                switched += 1;
+            }
+        }
+        self.total += switched as u64;
+        switched
+    }
+
+    /// Forgets valve states and counts, e.g. at a layer boundary; the
+    /// total is kept.
+    pub fn reset(&mut self) {
+        self.last.clear();
+        self.counts.clear();
+    }
+
+    /// Changes since the last [`reset`](Self::reset) of one valve.
+    pub fn count(&self, valve: ValveKey) -> u32 {
+        self.counts.get(&valve).copied().unwrap_or(0)
+    }
+
+    /// The valve switched most since the last reset.
+    pub fn busiest(&self) -> Option<(ValveKey, u32)> {
+        self.counts.iter().map(|(&k, &n)| (k, n)).max_by_key(|&(k, n)| (n, std::cmp::Reverse(k)))
+    }
+
+    /// Changes since the counter was made.
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+}
+
+/// Aggregate statistics of a command stream.
+#[derive(Debug, Clone)]
+pub struct CommandStats {
+    pub commands: u32,
+    /// G4L commands
+    pub layers: u32,
+    /// Valve state changes
+    pub valve_toggles: u64,
+    /// Material deposited by G4D commands (mm³)
+    pub extrusion_mm3: f32,
+    /// G4H commands changing a heater's target
+    pub thermal_setpoint_changes: u32,
+    /// G4P commands changing a channel's target
+    pub pressure_setpoint_changes: u32,
+    /// G4W pressure stabilization waits
+    pub pressure_waits: u32,
+    /// Fixed G4W dwells (ms)
+    pub dwell_ms: u64,
+    /// Z moved by G4L commands (mm); the first move counts only with a
+    /// known starting Z
+    pub z_travel_mm: f32,
+    by_kind: BTreeMap<CommandKind, u32>,
+    z: Option<f32>,
+    toggles: ValveToggles,
+    heater_targets: Vec<((Heater, Option<u8>), f32)>,
+    pressure_targets: Vec<(Option<u8>, f32)>,
+}
+
+impl Default for CommandStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CommandStats {
+    pub fn new() -> Self {
+        Self::with_grid_spacing(POSITION_RESOLUTION)
+    }
+
+    /// Statistics keying valve toggles to grid nodes.
+    pub fn with_grid_spacing(grid_spacing: f32) -> Self {
+        Self {
+            commands: 0,
+            layers: 0,
+            valve_toggles: 0,
+            extrusion_mm3: 0.0,
+            thermal_setpoint_changes: 0,
+            pressure_setpoint_changes: 0,
+            pressure_waits: 0,
+            dwell_ms: 0,
+            z_travel_mm: 0.0,
+            by_kind: BTreeMap::new(),
+            z: None,
+            toggles: ValveToggles::new(grid_spacing),
+            heater_targets: Vec::new(),
+            pressure_targets: Vec::new(),
+        }
+    }
+
+    /// Counts travel from `z` to the first G4L.
+    pub fn starting_at(mut self, z: f32) -> Self {
+        self.z = Some(z);
+        self
+    }
+
+    pub fn record(&mut self, cmd: &Command) {
+        self.commands += 1;
+        *self.by_kind.entry(cmd.kind()).or_insert(0) += 1;
+        match cmd {
+            Command::G4D(d) => {
+                self.valve_toggles += self.toggles.record(d) as u64;
+                self.extrusion_mm3 += d.extrusion.unwrap_or(0.0);
+            }
+            Command::G4L(l) => {
+                self.layers += 1;
+                if let Some(z) = self.z {
+                    self.z_travel_mm += (l.z_height - z).abs();
+                }
+                self.z = Some(l.z_height);
+            }
+            Command::G4H(h) => {
+                let heater = (h.heater, h.zone);
+                if set_target(&mut self.heater_targets, heater, h.temperature.get()) {
+                    self.thermal_setpoint_changes += 1;
+                }
+            }
+            Command::G4P(p) => {
+                if set_target(&mut self.pressure_targets, p.material_channel, p.pressure.get()) {
+                    self.pressure_setpoint_changes += 1;
+                }
+            }
+            Command::G4W(w) => match w.wait_type {
+                WaitType::Pressure => self.pressure_waits += 1,
+                WaitType::Duration(ms) => self.dwell_ms += ms as u64,
+                _ => {}
+            },
+            Command::G4C(_) | Command::G4S(_) | Command::Comment(_) => {}
+        }
+    }
+
+    /// Commands of one kind.
+    pub fn count(&self, kind: CommandKind) -> u32 {
+        self.by_kind.get(&kind).copied().unwrap_or(0)
+    }
+
+    /// Z after the last G4L, or the starting Z.
+    pub fn z(&self) -> Option<f32> {
+        self.z
+    }
+
+    /// Last target per heater, as (heater, zone).
+    pub fn heater_targets(&self) -> &[((Heater, Option<u8>), f32)] {
+        &self.heater_targets
+    }
+}
+
+impl<'a> Extend<&'a Command> for CommandStats {
+    fn extend<T: IntoIterator<Item = &'a Command>>(&mut self, commands: T) {
+        for cmd in commands {
+            self.record(cmd);
+        }
+    }
+}
+
+/// Sets `key`'s target, returning whether it changed.
+fn set_target<K: PartialEq>(targets: &mut Vec<(K, f32)>, key: K, target: f32) -> bool {
+    match targets.iter_mut().find(|(k, _)| *k == key) {
+        Some((_, current)) if *current == target => false,
+        Some((_, current)) => {
+            *current = target;
+            true
+        }
+        None => {
+            targets.push((key, target));
+            true
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Celsius, G4LCommand, G4WCommand, ValveState};
+
+    fn layer(z: f32) -> Command {
+        Command::G4L(G4LCommand { z_height: z, feed_rate: None, ramp_ms: None })
+    }
+
+    fn deposit(x: f32, open: bool) -> Command {
+        Command::G4D(G4DCommand {
+            position: Coordinate::new(x, 0.0, 0.2),
+            valves: vec![ValveState::new(0, open)],
+            extrusion: open.then_some(0.5),
+            close_early_ms: None,
+        })
+    }
+
+    fn heat(temperature: f32) -> Command {
+        Command::G4H(G4HCommand {
+            temperature: Celsius::new(temperature),
+            zone: None,
+            wait: false,
+            heater: Heater::Zone,
+        })
+    }
+
+    #[test]
+    fn test_split_and_stats() {
+        let commands = vec![
+            heat(200.0),
+            heat(200.0),
+            layer(0.2),
+            deposit(1.0, true),
+            deposit(1.0, false),
+            Command::G4W(G4WCommand { wait_type: WaitType::Pressure, timeout_ms: None }),
+            layer(0.4),
+            deposit(1.0, true),
+            deposit(1.5, true),
+            heat(210.0),
+            Command::G4W(G4WCommand { wait_type: WaitType::Duration(250), timeout_ms: None }),
+        ];
+
+        let layers: Vec<LayerCommands> = split_layers(&commands).collect();
+        let shape: Vec<(Option<f32>, usize)> =
+            layers.iter().map(|l| (l.z_height, l.commands.len())).collect();
+        assert_eq!(shape, vec![(None, 2), (Some(0.2), 4), (Some(0.4), 5)]);
+        assert!(split_layers(&[]).next().is_none());
+
+        assert_eq!(commands.iter().of_kind(CommandKind::Heating).count(), 3);
+        assert_eq!(commands.iter().deposits().filter(|d| d.extrusion.is_some()).count(), 3);
+        assert_eq!(commands.iter().setpoints().last().unwrap().temperature.get(), 210.0);
+
+        let stats = commands.iter().stats();
+        assert_eq!((stats.commands, stats.layers), (11, 2));
+        assert_eq!(stats.count(CommandKind::Deposit), 4);
+        // Open, close, then two opens on different nodes
+        assert_eq!(stats.valve_toggles, 4);
+        assert!((stats.extrusion_mm3 - 1.5).abs() < 1e-6);
+        assert_eq!(stats.thermal_setpoint_changes, 2);
+        assert_eq!((stats.pressure_waits, stats.dwell_ms), (1, 250));
+        assert!((stats.z_travel_mm - 0.2).abs() < 1e-6);
+
+        let mut from_ground = CommandStats::with_grid_spacing(0.5).starting_at(0.0);
+        from_ground.extend(layers[1].commands);
+        assert!((from_ground.z_travel_mm - 0.2).abs() < 1e-6);
+    }
+}
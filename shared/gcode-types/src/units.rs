@@ -0,0 +1,297 @@
+//! Physical units.
+//!
+//! Temperatures, pressures, lengths and volumes are carried as newtypes
+//! over `f32` so a PSI value cannot be passed where bar is meant, or a
+//! length where a temperature is. Each serializes as a bare number
+//! (`serde(transparent)`), so configuration files, .hg4d files and protocol
+//! messages are unchanged.
+//!
+//! Values come from numbers in the unit's own scale with `new`, or from
+//! another scale with the `from_*` conversions; the `checked` constructors
+//! and `try_from_*` conversions also reject values that cannot be physical
+//! (non-finite, negative, below absolute zero). `Display` appends the
+//! unit's symbol and honors a requested precision: `format!("{:.1}",
+//! Psi::new(40.0))` is `"40.0 PSI"`.
+
+use std::fmt;
+use std::ops::{Add, AddAssign, Div, Mul, Sub, SubAssign};
+
+use serde::{Deserialize, Serialize};
+
+/// Kelvin at 0 °C.
+const KELVIN_OFFSET: f32 = 273.15;
+/// kPa per PSI.
+const KPA_PER_PSI: f32 = 6.894_757;
+/// Millimeters per inch.
+const MM_PER_INCH: f32 = 25.4;
+/// Cubic millimeters per milliliter.
+const MM3_PER_ML: f32 = 1000.0;
+
+/// A value rejected by a checked constructor or conversion.
+#[derive(Debug, Clone, Copy, PartialEq, thiserror::Error)]
+pub enum UnitError {
+    #[error("{0} is not a finite number")]
+    NotFinite(f32),
+    #[error("{value} {unit} is negative")]
+    Negative { value: f32, unit: &'static str },
+    #[error("{0} °C is below absolute zero")]
+    BelowAbsoluteZero(f32),
+}
+
+macro_rules! unit {
+    ($(#[$meta:meta])* $name:ident, $symbol:expr) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, Copy, Default, PartialEq, PartialOrd, Serialize, Deserialize)]
+        #[serde(transparent)]
+        pub struct $name(f32);
+
+        impl $name {
+            pub const ZERO: Self = Self(0.0);
+            pub const SYMBOL: &'static str = $symbol;
+
+            pub const fn new(value: f32) -> Self {
+                Self(value)
+            }
+
+            /// The value in this unit's scale.
+            pub const fn get(self) -> f32 {
+                self.0
+            }
+
+            pub fn is_finite(self) -> bool {
+                self.0.is_finite()
+            }
+
+            pub fn min(self, other: Self) -> Self {
+                Self(self.0.min(other.0))
+            }
+
+            pub fn max(self, other: Self) -> Self {
+                Self(self.0.max(other.0))
+            }
+
+            pub fn clamp(self, min: Self, max: Self) -> Self {
+                Self(self.0.clamp(min.0, max.0))
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                match f.precision() {
+                    Some(precision) => write!(f, "{:.*} {}", precision, self.0, Self::SYMBOL),
+                    None => write!(f, "{} {}", self.0, Self::SYMBOL),
+                }
+            }
+        }
+
+        impl From<$name> for f32 {
+            fn from(value: $name) -> f32 {
+                value.0
+            }
+        }
+
+        impl Add for $name {
+            type Output = Self;
+            fn add(self, rhs: Self) -> Self {
+                Self(self.0 + rhs.0)
+            }
+        }
+
+        impl AddAssign for $name {
+            fn add_assign(&mut self, rhs: Self) {
+                self.0 += rhs.0;
+            }
+        }
+
+        impl Sub for $name {
+            type Output = Self;
+            fn sub(self, rhs: Self) -> Self {
+                Self(self.0 - rhs.0)
+            }
+        }
+
+        impl SubAssign for $name {
+            fn sub_assign(&mut self, rhs: Self) {
+                self.0 -= rhs.0;
+            }
+        }
+
+        impl Mul<f32> for $name {
+            type Output = Self;
+            fn mul(self, rhs: f32) -> Self {
+                Self(self.0 * rhs)
+            }
+        }
+
+        impl Div<f32> for $name {
+            type Output = Self;
+            fn div(self, rhs: f32) -> Self {
+                Self(self.0 / rhs)
+            }
+        }
+
+        /// Ratio of two quantities of the same unit.
+        impl Div for $name {
+            type Output = f32;
+            fn div(self, rhs: Self) -> f32 {
+                self.0 / rhs.0
+            }
+        }
+    };
+}
+
+unit!(
+    /// Temperature in degrees Celsius.
+    Celsius,
+    "°C"
+);
+
+unit!(
+    /// Gauge pressure in pounds per square inch.
+    Psi,
+    "PSI"
+);
+
+unit!(
+    /// Length in millimeters.
+    Millimeters,
+    "mm"
+);
+
+unit!(
+    /// Volume in cubic millimeters.
+    CubicMm,
+    "mm³"
+);
+
+impl Celsius {
+    /// Rejects non-finite values and values below absolute zero.
+    pub fn checked(value: f32) -> Result<Self, UnitError> {
+        let value = finite(value)?;
+        if value < -KELVIN_OFFSET {
+            return Err(UnitError::BelowAbsoluteZero(value));
+        }
+        Ok(Self(value))
+    }
+
+    pub fn from_fahrenheit(fahrenheit: f32) -> Self {
+        Self((fahrenheit - 32.0) * 5.0 / 9.0)
+    }
+
+    pub fn to_fahrenheit(self) -> f32 {
+        self.0 * 9.0 / 5.0 + 32.0
+    }
+
+    pub fn try_from_kelvin(kelvin: f32) -> Result<Self, UnitError> {
+        Self::checked(finite(kelvin)? - KELVIN_OFFSET)
+    }
+
+    pub fn to_kelvin(self) -> f32 {
+        self.0 + KELVIN_OFFSET
+    }
+}
+
+impl Psi {
+    /// Rejects non-finite and negative pressures.
+    pub fn checked(value: f32) -> Result<Self, UnitError> {
+        non_negative(value, Self::SYMBOL).map(Self)
+    }
+
+    pub fn from_bar(bar: f32) -> Self {
+        Self(bar * 100.0 / KPA_PER_PSI)
+    }
+
+    pub fn try_from_bar(bar: f32) -> Result<Self, UnitError> {
+        non_negative(bar, "bar").map(Self::from_bar)
+    }
+
+    pub fn to_bar(self) -> f32 {
+        self.0 * KPA_PER_PSI / 100.0
+    }
+
+    pub fn from_kpa(kpa: f32) -> Self {
+        Self(kpa / KPA_PER_PSI)
+    }
+
+    pub fn to_kpa(self) -> f32 {
+        self.0 * KPA_PER_PSI
+    }
+}
+
+impl Millimeters {
+    /// Rejects non-finite and negative lengths.
+    pub fn checked(value: f32) -> Result<Self, UnitError> {
+        non_negative(value, Self::SYMBOL).map(Self)
+    }
+
+    pub fn from_inches(inches: f32) -> Self {
+        Self(inches * MM_PER_INCH)
+    }
+
+    pub fn to_inches(self) -> f32 {
+        self.0 / MM_PER_INCH
+    }
+}
+
+impl CubicMm {
+    /// Rejects non-finite and negative volumes.
+    pub fn checked(value: f32) -> Result<Self, UnitError> {
+        non_negative(value, Self::SYMBOL).map(Self)
+    }
+
+    pub fn from_ml(ml: f32) -> Self {
+        Self(ml * MM3_PER_ML)
+    }
+
+    pub fn to_ml(self) -> f32 {
+        self.0 / MM3_PER_ML
+    }
+
+    /// Volume of a box.
+    pub fn from_box(x: Millimeters, y: Millimeters, z: Millimeters) -> Self {
+        Self(x.0 * y.0 * z.0)
+    }
+}
+
+fn finite(value: f32) -> Result<f32, UnitError> {
+    if value.is_finite() {
+        Ok(value)
+    } else {
+        Err(UnitError::NotFinite(value))
+    }
+}
+
+fn non_negative(value: f32, unit: &'static str) -> Result<f32, UnitError> {
+    let value = finite(value)?;
+    if value < 0.0 {
+        return Err(UnitError::Negative { value, unit });
+    }
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_conversions_and_serialization() {
+        assert!((Psi::from_bar(1.0).get() - 14.503_774).abs() < 1e-3);
+        assert!((Psi::new(30.0).to_bar() - 2.068_427).abs() < 1e-4);
+        assert_eq!(Millimeters::from_inches(2.0), Millimeters::new(50.8));
+        assert_eq!(Celsius::from_fahrenheit(212.0), Celsius::new(100.0));
+        assert_eq!(CubicMm::from_ml(1.5), CubicMm::new(1500.0));
+
+        assert!(matches!(Celsius::try_from_kelvin(-1.0), Err(UnitError::BelowAbsoluteZero(_))));
+        assert!(matches!(Psi::checked(-2.0), Err(UnitError::Negative { unit: "PSI", .. })));
+        assert!(matches!(Psi::try_from_bar(f32::NAN), Err(UnitError::NotFinite(_))));
+        assert!(Celsius::checked(-40.0).is_ok());
+
+        assert_eq!(format!("{:.1}", Psi::new(40.0)), "40.0 PSI");
+        assert_eq!(Psi::new(60.0) / Psi::new(120.0), 0.5);
+
+        // Same representation as a bare number
+        let bytes = bincode::serialize(&215.5f32).unwrap();
+        assert_eq!(bincode::serialize(&Celsius::new(215.5)).unwrap(), bytes);
+        assert_eq!(bincode::deserialize::<Celsius>(&bytes).unwrap(), Celsius::new(215.5));
+    }
+}
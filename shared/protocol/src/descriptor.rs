@@ -0,0 +1,296 @@
+//! Normalized printer capabilities for matching slices to printers.
+//!
+//! A printer configuration file carries everything from PID gains to pin
+//! numbers, and the copy on a workstation drifts from the one on the
+//! printer. The slicer only needs the parts that decide whether a sliced
+//! file will print: the valve grid, the material channels, the temperature
+//! and pressure ranges the firmware accepts and the `.hg4d` format versions
+//! it reads. The firmware answers `GetCapabilityDescriptor` with a
+//! [`CapabilityDescriptor`] built from its live configuration; the control
+//! interface serves it at `/api/capabilities/descriptor`.
+//!
+//! Descriptors are normalized so two built from equivalent configurations
+//! compare equal: values are rounded to [`DESCRIPTOR_DECIMALS`] places,
+//! ranges are clamped to the safety limits and lists are sorted. Comparing
+//! a descriptor built from a local file with the printer's
+//! ([`CapabilityDescriptor::diff`]) names every field the file gets wrong.
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use config_types::{PrinterConfig, PrinterModel};
+
+/// Layout version of [`CapabilityDescriptor`].
+pub const DESCRIPTOR_VERSION: u32 = 1;
+
+/// Decimal places descriptor values are rounded to (mm, °C, PSI, Hz).
+pub const DESCRIPTOR_DECIMALS: i32 = 3;
+
+/// What a printer can print, independent of how it is wired.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CapabilityDescriptor {
+    pub descriptor_version: u32,
+    pub firmware_version: String,
+    pub model: PrinterModel,
+    /// Build volume (mm)
+    pub build_volume: [f32; 3],
+    pub grid: GridDescriptor,
+    /// One entry per material channel, by channel number
+    pub channels: Vec<ChannelDescriptor>,
+    pub temperatures: TemperatureRanges,
+    /// Operating pressure range (PSI), within the safety limit
+    pub pressure_psi: (f32, f32),
+    /// `.hg4d` format versions the firmware reads, ascending
+    pub hg4d_versions: Vec<u32>,
+}
+
+/// The valve grid.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GridDescriptor {
+    /// Distance between nodes (mm)
+    pub spacing_mm: f32,
+    pub nodes_x: u32,
+    pub nodes_y: u32,
+    pub valves_per_node: u8,
+    /// Fastest a valve may switch (Hz), within the safety limit
+    pub max_switching_hz: f32,
+}
+
+impl GridDescriptor {
+    pub fn total_valves(&self) -> u64 {
+        self.nodes_x as u64 * self.nodes_y as u64 * self.valves_per_node as u64
+    }
+}
+
+/// One material channel.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChannelDescriptor {
+    pub channel: u8,
+    /// Filament diameter of the extruder feeding the channel (mm)
+    pub filament_diameter: Option<f32>,
+    /// Combined maximum flow of the channel's extruders (mm³/s)
+    pub max_flow_rate: Option<f32>,
+}
+
+/// Temperatures the firmware accepts, each within the safety limit (°C).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TemperatureRanges {
+    /// Hottest temperature allowed anywhere
+    pub limit: f32,
+    /// Heated zones by id, as `(id, min, max)`
+    pub zones: Vec<(u8, f32, f32)>,
+    pub bed: Option<(f32, f32)>,
+    pub chamber_max: Option<f32>,
+}
+
+/// A field whose local value differs from the printer's.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DescriptorMismatch {
+    /// Dotted path of the field, such as `grid.spacing_mm`
+    pub field: String,
+    pub local: String,
+    pub printer: String,
+}
+
+impl fmt::Display for DescriptorMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {} locally, {} on the printer", self.field, self.local, self.printer)
+    }
+}
+
+impl CapabilityDescriptor {
+    /// Descriptor of a printer configuration, for firmware reading the
+    /// given `.hg4d` versions.
+    pub fn from_printer_config(
+        config: &PrinterConfig,
+        firmware_version: &str,
+        hg4d_versions: &[u32],
+    ) -> Self {
+        let limit = config.safety.max_temperature.get();
+        let range = |min: f32, max: f32| (round(min.min(limit)), round(max.min(limit)));
+
+        let mut zones: Vec<_> = config
+            .thermal
+            .zones
+            .iter()
+            .map(|zone| {
+                let (min, max) = range(zone.min_temp, zone.max_temp);
+                (zone.id, min, max)
+            })
+            .collect();
+        zones.sort_by_key(|&(id, _, _)| id);
+
+        let channels = (0..config.materials.channel_count)
+            .map(|channel| {
+                let extruders: Vec<_> = config
+                    .materials
+                    .extruders
+                    .iter()
+                    .filter(|extruder| extruder.material_channel == channel)
+                    .collect();
+                ChannelDescriptor {
+                    channel,
+                    filament_diameter: extruders.first().map(|extruder| round(extruder.filament_diameter)),
+                    max_flow_rate: (!extruders.is_empty())
+                        .then(|| round(extruders.iter().map(|extruder| extruder.max_flow_rate).sum())),
+                }
+            })
+            .collect();
+
+        let pressure = &config.materials.pressure;
+        let max_pressure = pressure.max_pressure.min(config.safety.max_pressure.get());
+        let max_switching_hz = config.valve_array.max_switching_freq.min(config.safety.max_valve_rate);
+        let mut hg4d_versions = hg4d_versions.to_vec();
+        hg4d_versions.sort_unstable();
+        hg4d_versions.dedup();
+
+        Self {
+            descriptor_version: DESCRIPTOR_VERSION,
+            firmware_version: firmware_version.to_string(),
+            model: config.model,
+            build_volume: [config.build_volume.x, config.build_volume.y, config.build_volume.z].map(round),
+            grid: GridDescriptor {
+                spacing_mm: round(config.valve_array.grid_spacing),
+                nodes_x: config.grid_x_count(),
+                nodes_y: config.grid_y_count(),
+                valves_per_node: config.valve_array.valves_per_node,
+                max_switching_hz: round(max_switching_hz),
+            },
+            channels,
+            temperatures: TemperatureRanges {
+                limit: round(limit),
+                zones,
+                bed: config.thermal.bed.as_ref().map(|bed| range(bed.min_temp, bed.max_temp)),
+                chamber_max: config.thermal.chamber.as_ref().map(|chamber| range(0.0, chamber.max_temp).1),
+            },
+            pressure_psi: (round(pressure.min_pressure.min(max_pressure)), round(max_pressure)),
+            hg4d_versions,
+        }
+    }
+
+    /// Whether the firmware reads files of the given `.hg4d` format version.
+    pub fn reads_format(&self, version: u32) -> bool {
+        self.hg4d_versions.contains(&version)
+    }
+
+    /// Fields where this descriptor, built from a local configuration,
+    /// differs from the printer's. Firmware version and format versions
+    /// are not compared; check the latter with [`Self::reads_format`].
+    pub fn diff(&self, printer: &CapabilityDescriptor) -> Vec<DescriptorMismatch> {
+        let mut mismatches = Vec::new();
+        let mut compare = |field: &str, local: String, remote: String| {
+            if local != remote {
+                mismatches.push(DescriptorMismatch { field: field.to_string(), local, printer: remote });
+            }
+        };
+
+        compare("model", format!("{:?}", self.model), format!("{:?}", printer.model));
+        compare("build_volume", format!("{:?}", self.build_volume), format!("{:?}", printer.build_volume));
+        compare("grid.spacing_mm", self.grid.spacing_mm.to_string(), printer.grid.spacing_mm.to_string());
+        compare(
+            "grid.nodes",
+            format!("{}x{}", self.grid.nodes_x, self.grid.nodes_y),
+            format!("{}x{}", printer.grid.nodes_x, printer.grid.nodes_y),
+        );
+        compare(
+            "grid.valves_per_node",
+            self.grid.valves_per_node.to_string(),
+            printer.grid.valves_per_node.to_string(),
+        );
+        compare(
+            "grid.max_switching_hz",
+            self.grid.max_switching_hz.to_string(),
+            printer.grid.max_switching_hz.to_string(),
+        );
+        compare("channels", self.channels.len().to_string(), printer.channels.len().to_string());
+        for (local, remote) in self.channels.iter().zip(&printer.channels) {
+            compare(
+                &format!("channels[{}].filament_diameter", local.channel),
+                format!("{:?}", local.filament_diameter),
+                format!("{:?}", remote.filament_diameter),
+            );
+            compare(
+                &format!("channels[{}].max_flow_rate", local.channel),
+                format!("{:?}", local.max_flow_rate),
+                format!("{:?}", remote.max_flow_rate),
+            );
+        }
+
+        let (local, remote) = (&self.temperatures, &printer.temperatures);
+        compare("temperatures.limit", local.limit.to_string(), remote.limit.to_string());
+        compare("temperatures.zones", format!("{:?}", local.zones), format!("{:?}", remote.zones));
+        compare("temperatures.bed", format!("{:?}", local.bed), format!("{:?}", remote.bed));
+        compare(
+            "temperatures.chamber_max",
+            format!("{:?}", local.chamber_max),
+            format!("{:?}", remote.chamber_max),
+        );
+        compare("pressure_psi", format!("{:?}", self.pressure_psi), format!("{:?}", printer.pressure_psi));
+        mismatches
+    }
+}
+
+fn round(value: f32) -> f32 {
+    let scale = 10f32.powi(DESCRIPTOR_DECIMALS);
+    (value * scale).round() / scale
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn descriptor() -> CapabilityDescriptor {
+        CapabilityDescriptor {
+            descriptor_version: DESCRIPTOR_VERSION,
+            firmware_version: "0.4.0".to_string(),
+            model: PrinterModel::HyperCubeStandard,
+            build_volume: [200.0, 200.0, 200.0],
+            grid: GridDescriptor {
+                spacing_mm: 0.5,
+                nodes_x: 400,
+                nodes_y: 400,
+                valves_per_node: 4,
+                max_switching_hz: 100.0,
+            },
+            channels: vec![
+                ChannelDescriptor { channel: 0, filament_diameter: Some(1.75), max_flow_rate: Some(15.0) },
+                ChannelDescriptor { channel: 1, filament_diameter: Some(1.75), max_flow_rate: Some(15.0) },
+            ],
+            temperatures: TemperatureRanges {
+                limit: 300.0,
+                zones: vec![(0, 20.0, 280.0), (1, 20.0, 280.0)],
+                bed: Some((20.0, 110.0)),
+                chamber_max: None,
+            },
+            pressure_psi: (5.0, 100.0),
+            hg4d_versions: vec![2],
+        }
+    }
+
+    #[test]
+    fn test_diff_names_stale_fields() {
+        let printer = descriptor();
+        let mut local = descriptor();
+        local.firmware_version = "0.3.9".to_string();
+        assert_eq!(local.diff(&printer), vec![]);
+
+        local.grid.spacing_mm = 0.25;
+        local.grid.nodes_x = 800;
+        local.channels.pop();
+        local.temperatures.bed = None;
+        let fields: Vec<_> = local.diff(&printer).into_iter().map(|m| m.field).collect();
+        assert_eq!(fields, vec!["grid.spacing_mm", "grid.nodes", "channels", "temperatures.bed"]);
+        assert_eq!(
+            local.diff(&printer)[1].to_string(),
+            "grid.nodes: 800x400 locally, 400x400 on the printer"
+        );
+
+        assert!(printer.reads_format(2) && !printer.reads_format(1));
+        assert_eq!(printer.grid.total_valves(), 640_000);
+        assert_eq!(round(0.1 + 0.2), 0.3);
+
+        let json = serde_json::to_string(&printer).unwrap();
+        assert_eq!(serde_json::from_str::<CapabilityDescriptor>(&json).unwrap(), printer);
+    }
+}
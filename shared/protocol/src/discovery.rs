@@ -0,0 +1,132 @@
+//! Network discovery records.
+//!
+//! Firmware announces itself over mDNS/DNS-SD as a [`SERVICE_TYPE`]
+//! service on `<hostname>.local`, by default `hypergcode-4d.local`. The
+//! service port is the WebSocket port; the TXT record carries the REST API
+//! port and the printer's identity so clients can list printers without
+//! connecting to each. Both the firmware's responder and the control
+//! interface's browser use [`ServiceAnnouncement`] for the record, and
+//! unknown TXT keys are ignored so either side can add more.
+
+use serde::{Deserialize, Serialize};
+
+/// DNS-SD service type of the firmware.
+pub const SERVICE_TYPE: &str = "_hypergcode._tcp.local.";
+
+/// Host name announced unless configured otherwise.
+pub const DEFAULT_HOSTNAME: &str = "hypergcode-4d";
+
+const TXT_API_PORT: &str = "api";
+const TXT_MODEL: &str = "model";
+const TXT_SERIAL: &str = "serial";
+const TXT_FIRMWARE: &str = "fw";
+const TXT_PROTOCOL: &str = "proto";
+
+/// What a printer announces about itself.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ServiceAnnouncement {
+    /// Service instance name shown to users
+    pub instance: String,
+    /// Host name without the `.local` suffix
+    pub hostname: String,
+    pub websocket_port: u16,
+    pub api_port: u16,
+    /// Printer model, as in the printer configuration
+    pub model: String,
+    pub serial_number: Option<String>,
+    pub firmware_version: String,
+    pub protocol_version: String,
+}
+
+impl ServiceAnnouncement {
+    /// Fully qualified host name, e.g. `hypergcode-4d.local.`.
+    pub fn host_fqdn(&self) -> String {
+        format!("{}.local.", self.hostname)
+    }
+
+    /// TXT record entries.
+    pub fn txt_records(&self) -> Vec<(&'static str, String)> {
+        let mut records = vec![
+            (TXT_API_PORT, self.api_port.to_string()),
+            (TXT_MODEL, self.model.clone()),
+            (TXT_FIRMWARE, self.firmware_version.clone()),
+            (TXT_PROTOCOL, self.protocol_version.clone()),
+        ];
+        if let Some(serial) = &self.serial_number {
+            records.push((TXT_SERIAL, serial.clone()));
+        }
+        records
+    }
+
+    /// Rebuilds an announcement from a resolved service. `hostname` may
+    /// carry the `.local.` suffix. None if a required TXT entry is missing
+    /// or malformed.
+    pub fn from_txt<'a>(
+        instance: &str,
+        hostname: &str,
+        websocket_port: u16,
+        txt: impl IntoIterator<Item = (&'a str, &'a str)>,
+    ) -> Option<Self> {
+        let mut api_port = None;
+        let mut model = None;
+        let mut serial_number = None;
+        let mut firmware_version = None;
+        let mut protocol_version = None;
+        for (key, value) in txt {
+            match key {
+                TXT_API_PORT => api_port = value.parse().ok(),
+                TXT_MODEL => model = Some(value.to_string()),
+                TXT_SERIAL => serial_number = Some(value.to_string()),
+                TXT_FIRMWARE => firmware_version = Some(value.to_string()),
+                TXT_PROTOCOL => protocol_version = Some(value.to_string()),
+                _ => {}
+            }
+        }
+        let hostname = hostname.trim_end_matches('.').trim_end_matches(".local");
+        Some(Self {
+            instance: instance.to_string(),
+            hostname: hostname.to_string(),
+            websocket_port,
+            api_port: api_port?,
+            model: model?,
+            serial_number,
+            firmware_version: firmware_version?,
+            protocol_version: protocol_version?,
+        })
+    }
+
+    /// WebSocket URL of the printer by host name.
+    pub fn websocket_url(&self) -> String {
+        format!("ws://{}.local:{}", self.hostname, self.websocket_port)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_announcement_txt_round_trip() {
+        let announcement = ServiceAnnouncement {
+            instance: "HyperCube Mini #0042".to_string(),
+            hostname: DEFAULT_HOSTNAME.to_string(),
+            websocket_port: 8080,
+            api_port: 8081,
+            model: "HyperCubeMini".to_string(),
+            serial_number: Some("0042".to_string()),
+            firmware_version: "0.3.0".to_string(),
+            protocol_version: "1.1".to_string(),
+        };
+        assert_eq!(announcement.host_fqdn(), "hypergcode-4d.local.");
+        assert_eq!(announcement.websocket_url(), "ws://hypergcode-4d.local:8080");
+
+        let records = announcement.txt_records();
+        let mut txt: Vec<(&str, &str)> = records.iter().map(|(k, v)| (*k, v.as_str())).collect();
+        txt.push(("future", "ignored"));
+        let parsed = ServiceAnnouncement::from_txt(&announcement.instance, "hypergcode-4d.local.", 8080, txt.clone());
+        assert_eq!(parsed, Some(announcement));
+
+        txt.retain(|(key, _)| *key != TXT_API_PORT);
+        assert_eq!(ServiceAnnouncement::from_txt("x", "h.local.", 8080, txt), None);
+    }
+}
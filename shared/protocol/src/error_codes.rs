@@ -0,0 +1,212 @@
+//! Registry of error codes shared by firmware and user interfaces.
+//!
+//! Every fault the firmware reports carries one of the codes below in
+//! `SystemError::code` and [`ErrorEvent::code`](crate::ErrorEvent). Codes
+//! read `E-<AREA>-<NAME>-<NN>` and never change meaning once released, so a
+//! UI can key translated text and documentation pages on them; the
+//! description and recovery hint here are the English defaults. A code a UI
+//! does not know (from newer firmware) still arrives as its string, with the
+//! firmware's own message. The table is also in `docs/api-reference.md`.
+//!
+//! New faults get a new code at the end of their area; retired codes are
+//! kept so old logs still resolve.
+
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{ErrorSeverity, ProtocolError};
+
+macro_rules! error_codes {
+    ($(
+        $(#[$meta:meta])*
+        $variant:ident = $code:literal, $severity:ident, $description:literal, $hint:literal;
+    )*) => {
+        /// A registered error code.
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+        pub enum ErrorCode {
+            $(
+                $(#[$meta])*
+                #[serde(rename = $code)]
+                $variant,
+            )*
+        }
+
+        impl ErrorCode {
+            /// Every registered code.
+            pub const ALL: &'static [ErrorCode] = &[$(ErrorCode::$variant),*];
+
+            /// The code as reported, e.g. `E-THERM-OPEN-01`.
+            pub fn as_str(&self) -> &'static str {
+                match self {
+                    $(ErrorCode::$variant => $code,)*
+                }
+            }
+
+            /// Severity the firmware reports this fault with unless the
+            /// situation calls for another.
+            pub fn default_severity(&self) -> ErrorSeverity {
+                match self {
+                    $(ErrorCode::$variant => ErrorSeverity::$severity,)*
+                }
+            }
+
+            /// What the fault means.
+            pub fn description(&self) -> &'static str {
+                match self {
+                    $(ErrorCode::$variant => $description,)*
+                }
+            }
+
+            /// What the operator should do about it.
+            pub fn recovery_hint(&self) -> &'static str {
+                match self {
+                    $(ErrorCode::$variant => $hint,)*
+                }
+            }
+        }
+    };
+}
+
+error_codes! {
+    /// Thermistor disconnected: the divider reads at the supply rail
+    ThermistorOpen = "E-THERM-OPEN-01", Critical,
+        "Thermistor open circuit; the zone's heater is disabled",
+        "Check the thermistor connector and wiring, then clear the fault";
+    /// Thermistor or its wiring shorted: the divider reads at ground
+    ThermistorShort = "E-THERM-SHORT-02", Critical,
+        "Thermistor short circuit; the zone's heater is disabled",
+        "Check the thermistor leads for a short, then clear the fault";
+    /// Heater driven hard without the temperature rising
+    HeaterDecoupled = "E-THERM-DECOUPLED-03", Critical,
+        "Heater running without the temperature rising; the zone's heater is disabled",
+        "Check the thermistor is seated in the heater block and the heater is powered, then clear the fault";
+    /// Channel pressure fell while the valves feeding it were closed
+    ValveStuckOpen = "E-VALVE-STUCK-01", Critical,
+        "A valve is stuck open; the channel's pressure is dropped",
+        "Channel pressure dropped; clear the plate, inspect the named valves and clear the fault";
+    /// Measured flow below the commanded deposit
+    MaterialUnderflow = "E-MAT-UNDERFLOW-01", Warning,
+        "Less material is flowing than the valves deposit",
+        "Check the channel's valves and feed for a clog";
+    /// Measured flow above the commanded deposit
+    MaterialOverflow = "E-MAT-OVERFLOW-02", Warning,
+        "More material is flowing than the valves deposit",
+        "Check the channel's manifold and valve seats for leaks";
+    /// Commanded deposit above the file's expected total
+    MaterialOverPlan = "E-MAT-OVERPLAN-03", Warning,
+        "More material deposited than the sliced file expects",
+        "Check the live flow adjustment against the sliced file";
+    /// A channel's runout switch reads empty during a print
+    MaterialRunout = "E-MAT-RUNOUT-04", Warning,
+        "A material channel ran out; the print is paused",
+        "Load material on the channel, then resume to re-prime it and continue the layer";
+    /// Mains power failed during operation
+    PowerLoss = "E-POWER-LOSS-01", Critical,
+        "Power lost; the printer shut down on its hold-up supply",
+        "Restore power; the print can be resumed from the recovery journal";
+    /// A door switch, thermostat or smoke detector tripped
+    InterlockTripped = "E-SAFETY-INTERLOCK-01", Critical,
+        "A safety interlock tripped",
+        "Clear the interlock's condition before resuming";
+    /// A supervised task stopped heartbeating
+    TaskStalled = "E-TASK-STALLED-01", Critical,
+        "A firmware task stopped responding",
+        "Heaters and valves disabled; check logs and restart firmware";
+    /// A supervised task exited without finishing cleanly
+    TaskExited = "E-TASK-EXITED-02", Critical,
+        "A firmware task exited unexpectedly",
+        "Heaters and valves disabled; check logs and restart firmware";
+    /// A print job stopped on an error
+    PrintFailed = "E-PRINT-FAILED-01", Error,
+        "The print stopped on an error",
+        "Check the error message, fix its cause and start the print again";
+    /// Controller CPU load above the configured threshold
+    HostCpuLoad = "E-HOST-CPU-01", Warning,
+        "The controller's CPU is saturated; valve frame timing may slip",
+        "Stop other services on the controller or lower client telemetry rates";
+    /// Controller memory use above the configured threshold
+    HostMemory = "E-HOST-MEMORY-02", Warning,
+        "The controller is running out of memory",
+        "Stop other services on the controller; restart the firmware between prints if it persists";
+    /// SoC temperature above the configured threshold
+    HostThermal = "E-HOST-THERMAL-03", Warning,
+        "The controller's SoC is close to thermal throttling, which slows valve frame timing",
+        "Improve the controller's cooling with a heatsink or fan, away from the heated chamber";
+    /// Free space in the print directory below the configured threshold
+    HostDiskSpace = "E-HOST-DISK-04", Warning,
+        "Little free space is left in the print directory",
+        "Delete old print files or logs to make room";
+}
+
+impl ErrorCode {
+    /// Name of this code's documentation page or anchor, e.g.
+    /// `e-therm-open-01`; UIs join it to their documentation base.
+    pub fn doc_slug(&self) -> String {
+        self.as_str().to_ascii_lowercase()
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for ErrorCode {
+    type Err = ProtocolError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ErrorCode::ALL
+            .iter()
+            .copied()
+            .find(|c| c.as_str().eq_ignore_ascii_case(s))
+            .ok_or_else(|| ProtocolError::ValidationError(format!("Unknown error code '{}'", s)))
+    }
+}
+
+impl From<ErrorCode> for String {
+    fn from(code: ErrorCode) -> Self {
+        code.as_str().to_string()
+    }
+}
+
+impl PartialEq<ErrorCode> for String {
+    fn eq(&self, other: &ErrorCode) -> bool {
+        self == other.as_str()
+    }
+}
+
+impl PartialEq<ErrorCode> for str {
+    fn eq(&self, other: &ErrorCode) -> bool {
+        self == other.as_str()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_registry_codes() {
+        let mut seen = HashSet::new();
+        for &code in ErrorCode::ALL {
+            let s = code.as_str();
+            assert!(seen.insert(s), "{} registered twice", s);
+            let parts: Vec<&str> = s.split('-').collect();
+            assert!(parts.len() >= 4 && parts[0] == "E", "{}", s);
+            assert_eq!(parts.last().unwrap().len(), 2, "{}", s);
+            assert!(!code.description().is_empty() && !code.recovery_hint().is_empty());
+
+            assert_eq!(s.parse::<ErrorCode>().unwrap(), code);
+            assert_eq!(serde_json::to_string(&code).unwrap(), format!("\"{}\"", s));
+            assert_eq!(serde_json::from_str::<ErrorCode>(&format!("\"{}\"", s)).unwrap(), code);
+        }
+        assert!("THERMISTOR_OPEN".parse::<ErrorCode>().is_err());
+        assert_eq!(ErrorCode::PowerLoss.to_string(), "E-POWER-LOSS-01");
+        assert_eq!(ErrorCode::PowerLoss.doc_slug(), "e-power-loss-01");
+        assert!(String::from("E-VALVE-STUCK-01") == ErrorCode::ValveStuckOpen);
+    }
+}
@@ -0,0 +1,249 @@
+//! Heartbeats and dead-peer detection.
+//!
+//! A connection can half-die without either side seeing an error: a
+//! WebSocket whose Wi-Fi dropped, or a serial cable pulled mid-print, stays
+//! "open" until a write finally times out minutes later. When both `Hello`s
+//! announce [`Capability::Heartbeat`], each side runs a [`HeartbeatMonitor`]
+//! over its connection:
+//!
+//! - every message received proves the peer alive
+//! - after [`HeartbeatConfig::interval_ms`] of silence the monitor sends a
+//!   `Ping`, which the peer answers with a `Pong` carrying the same sequence
+//!   number
+//! - each further interval without a message is a miss, and after
+//!   `miss_threshold` misses the peer is declared dead with a
+//!   [`DisconnectedEvent`]
+//!
+//! A busy connection streaming status never pings. `Ping` and `Pong` are
+//! answered and consumed by the client; they are not returned from
+//! `MessageClient::recv`. The firmware reacts to a dead client per
+//! `SafetyLimits::disconnect_action`; the control interface reconnects and
+//! shows the event to the browser.
+//!
+//! Peers that predate heartbeats never answer a `Ping`, so the monitor is
+//! only started once the handshake shows the peer announces the capability.
+
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+pub use config_types::HeartbeatConfig;
+
+use crate::{Capability, Hello, ProtocolMessage};
+
+/// Payload of `Ping` and `Pong`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Heartbeat {
+    /// Sequence number of the ping; a pong echoes it
+    pub seq: u64,
+}
+
+/// Why a connection was given up.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum DisconnectReason {
+    /// No message within the heartbeat miss threshold
+    HeartbeatTimeout,
+    /// The peer closed the connection
+    Closed,
+    /// The transport failed
+    Failed { error: String },
+}
+
+/// A connection that died, for the UI and firmware to react to.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DisconnectedEvent {
+    #[serde(flatten)]
+    pub reason: DisconnectReason,
+    /// Time since the last message from the peer (ms)
+    pub silent_ms: u64,
+    /// Pings left unanswered
+    pub missed_pings: u32,
+}
+
+impl DisconnectedEvent {
+    pub fn closed() -> Self {
+        Self { reason: DisconnectReason::Closed, silent_ms: 0, missed_pings: 0 }
+    }
+
+    pub fn failed(error: impl Into<String>) -> Self {
+        Self {
+            reason: DisconnectReason::Failed { error: error.into() },
+            silent_ms: 0,
+            missed_pings: 0,
+        }
+    }
+
+    pub fn is_timeout(&self) -> bool {
+        self.reason == DisconnectReason::HeartbeatTimeout
+    }
+}
+
+impl fmt::Display for DisconnectedEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.reason {
+            DisconnectReason::HeartbeatTimeout => write!(
+                f,
+                "peer silent for {} ms, {} pings unanswered",
+                self.silent_ms, self.missed_pings
+            ),
+            DisconnectReason::Closed => f.write_str("peer closed the connection"),
+            DisconnectReason::Failed { error } => write!(f, "connection failed: {}", error),
+        }
+    }
+}
+
+/// Heartbeat state of one connection; see the module documentation.
+///
+/// The monitor does no I/O. The connection passes it every received
+/// message and calls [`poll`](Self::poll) at least once per
+/// [`next_deadline`](Self::next_deadline), sending whatever it returns.
+#[derive(Debug, Clone)]
+pub struct HeartbeatMonitor {
+    config: HeartbeatConfig,
+    next_seq: u64,
+    /// Sequence and send time of the latest unanswered ping
+    outstanding: Option<(u64, Instant)>,
+    /// A ping went out and nothing has arrived since
+    awaiting: bool,
+    missed: u32,
+    last_received: Instant,
+    next_ping: Instant,
+    round_trip: Option<Duration>,
+}
+
+impl HeartbeatMonitor {
+    pub fn new(config: HeartbeatConfig, now: Instant) -> Self {
+        Self {
+            config,
+            next_seq: 1,
+            outstanding: None,
+            awaiting: false,
+            missed: 0,
+            last_received: now,
+            next_ping: now + config.interval(),
+            round_trip: None,
+        }
+    }
+
+    /// Monitor for a connection whose handshake is done, or None if either
+    /// side lacks [`Capability::Heartbeat`].
+    pub fn negotiate(config: HeartbeatConfig, local: &Hello, peer: &Hello, now: Instant) -> Option<Self> {
+        (local.supports(Capability::Heartbeat) && peer.supports(Capability::Heartbeat))
+            .then(|| Self::new(config, now))
+    }
+
+    pub fn config(&self) -> &HeartbeatConfig {
+        &self.config
+    }
+
+    /// Notes a message from the peer. Returns the `Pong` to send for a
+    /// `Ping`.
+    pub fn received(&mut self, msg: &ProtocolMessage, now: Instant) -> Option<ProtocolMessage> {
+        self.last_received = now;
+        self.awaiting = false;
+        self.missed = 0;
+        self.next_ping = now + self.config.interval();
+        match msg {
+            ProtocolMessage::Ping(ping) => Some(ProtocolMessage::Pong(*ping)),
+            ProtocolMessage::Pong(pong) => {
+                if let Some((_, sent)) = self.outstanding.filter(|(seq, _)| *seq == pong.seq) {
+                    self.round_trip = Some(now.duration_since(sent));
+                    self.outstanding = None;
+                }
+                None
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns a `Ping` to send if the peer has been silent for an
+    /// interval, or the disconnect once it has missed too many.
+    pub fn poll(&mut self, now: Instant) -> Result<Option<ProtocolMessage>, DisconnectedEvent> {
+        if now < self.next_ping {
+            return Ok(None);
+        }
+        if self.awaiting {
+            self.missed += 1;
+        }
+        if self.missed >= self.config.miss_threshold {
+            return Err(DisconnectedEvent {
+                reason: DisconnectReason::HeartbeatTimeout,
+                silent_ms: self.silent_for(now).as_millis() as u64,
+                missed_pings: self.missed,
+            });
+        }
+        let ping = Heartbeat { seq: self.next_seq };
+        self.next_seq += 1;
+        self.outstanding = Some((ping.seq, now));
+        self.awaiting = true;
+        self.next_ping = now + self.config.interval();
+        Ok(Some(ProtocolMessage::Ping(ping)))
+    }
+
+    /// When [`poll`](Self::poll) next has something to do.
+    pub fn next_deadline(&self) -> Instant {
+        self.next_ping
+    }
+
+    pub fn silent_for(&self, now: Instant) -> Duration {
+        now.saturating_duration_since(self.last_received)
+    }
+
+    /// Round trip of the latest answered ping.
+    pub fn round_trip(&self) -> Option<Duration> {
+        self.round_trip
+    }
+
+    /// Pings unanswered since the peer was last heard.
+    pub fn missed(&self) -> u32 {
+        self.missed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pings_and_dead_peer() {
+        let config = HeartbeatConfig { interval_ms: 1_000, miss_threshold: 3 };
+        let start = Instant::now();
+        let at = |ms: u64| start + Duration::from_millis(ms);
+        let hello = |capabilities| Hello::new("test", "1.0.0", capabilities);
+        let heartbeat = hello(vec![Capability::Heartbeat]);
+        assert!(HeartbeatMonitor::negotiate(config, &heartbeat, &hello(vec![]), start).is_none());
+        let mut monitor = HeartbeatMonitor::negotiate(config, &heartbeat, &heartbeat, start).unwrap();
+
+        // Traffic keeps the link alive without pings
+        assert!(matches!(monitor.poll(at(500)), Ok(None)));
+        assert!(monitor.received(&ProtocolMessage::ResumePrint, at(900)).is_none());
+        assert!(matches!(monitor.poll(at(1_500)), Ok(None)));
+        assert_eq!(monitor.next_deadline(), at(1_900));
+
+        // Silence: ping, answered with a pong
+        let Ok(Some(ProtocolMessage::Ping(ping))) = monitor.poll(at(1_900)) else {
+            panic!("expected a ping");
+        };
+        let pong = monitor.received(&ProtocolMessage::Ping(Heartbeat { seq: 7 }), at(1_950));
+        assert!(matches!(pong, Some(ProtocolMessage::Pong(Heartbeat { seq: 7 }))));
+        let mut unanswered = monitor.clone();
+        monitor.received(&ProtocolMessage::Pong(ping), at(1_960));
+        assert_eq!(monitor.round_trip(), Some(Duration::from_millis(60)));
+
+        // A peer that stops answering is dead after three missed pings
+        for ms in [2_950, 3_950, 4_950] {
+            assert!(matches!(unanswered.poll(at(ms)), Ok(Some(ProtocolMessage::Ping(_)))));
+        }
+        assert_eq!(unanswered.missed(), 2);
+        let dead = unanswered.poll(at(5_950)).unwrap_err();
+        assert!(dead.is_timeout());
+        assert_eq!((dead.silent_ms, dead.missed_pings), (4_000, 3));
+        assert_eq!(dead.to_string(), "peer silent for 4000 ms, 3 pings unanswered");
+
+        let json = serde_json::to_value(&DisconnectedEvent::failed("reset")).unwrap();
+        assert_eq!(json["reason"], "failed");
+        assert_eq!(json["error"], "reset");
+    }
+}
@@ -12,7 +12,7 @@
 //! - **Serial**: Development and debugging interface
 //!
 //! All messages use JSON serialization for human readability and debugging, with
-//! optional binary encoding for performance-critical paths.
+//! optional binary encoding for performance-critical paths (see Wire Formats).
 //!
 //! ## Message Flow
 //!
@@ -23,14 +23,104 @@
 //!   - PressureUpdate (when pressures change)
 //!   - ValveStateUpdate (when valve patterns change)
 //!   - ErrorEvent (when errors occur)
+//!   - ConfigChanged (when the configuration file is reloaded)
+//!   - LayerTiming (after each layer, for print time calibration)
+//!   - RecoveryAvailable (at boot, when a print was interrupted by power loss)
+//!   - MaterialExposure (a loaded spool exceeded its moisture exposure limit)
+//!   - JobFinished (a print ended and was added to the history)
+//!   - StateChanged (the firmware moved to another operational state)
+//!   - InspectionPause (the print holds for inspection until ResumePrint)
+//!   - MaterialRunout (a channel ran out and the print paused; repeated as
+//!     the channel is reloaded, re-primed and the print resumes)
+//!   - HostTelemetry (periodic CPU, memory, SoC temperature and disk use of
+//!     the controller board)
+//!
+//! Either direction:
+//!   - Ping, answered with Pong (heartbeats on an idle connection)
 //!
 //! Control Interface → Firmware:
+//!   - Hello (handshake on connect; answered with the firmware's Hello)
 //!   - StartPrint, PausePrint, ResumePrint, CancelPrint
 //!   - EmergencyStop
 //!   - AdjustParameter (temperature, pressure, flow during print)
+//!   - CancelObject (stop depositing one tagged object, keep printing the rest)
+//!   - SetMaterial (record the material loaded on a channel after a spool swap)
+//!   - ResumeFromJournal, DiscardJournal (answer a RecoveryAvailable offer)
+//!   - StartFlowCalibration, SubmitFlowMeasurement, ApplyFlowCalibration
+//!     (flow calibration wizard; progress via GetFlowCalibration)
+//!   - StartBedLevel, SubmitBedLevelPoint, ProbeBedLevel, SaveBedLevel
+//!     (bed flatness measurement; progress via GetBedLevel)
+//!   - StartDrying, CancelDrying (dry a loaded spool with the chamber or
+//!     bed heater; exposure shown in MaterialsResponse)
+//!   - ClearThermalFault (return a zone disabled by a thermistor or heater
+//!     fault to service once the cause is fixed)
+//!   - DeleteHistoryJob (remove a job from the print history; jobs via
+//!     GetHistory, totals via GetHistoryStats)
+//!   - RunMacro (start an operator routine from the configuration as a
+//!     job, stopped with CancelPrint; the available macros via GetMacros)
 //!   - ConfigUpdate
+//!   - GetCapabilityDescriptor (what the printer can print, for matching
+//!     slices to it; see Capability Descriptors)
+//!   - Subscribe (topics and per-topic rates; answered with SubscriptionAck)
 //! ```
 //!
+//! ## Handshake
+//!
+//! Each side sends `Hello` on connect with its protocol version, software
+//! version and the optional [`Capability`]s it implements; the firmware
+//! answers a client's `Hello` with its own. Peers agree when their
+//! protocol major versions match, and use only capabilities both announce.
+//! Capabilities and message types a peer does not know are skipped rather
+//! than failing the connection: [`decode_message`] returns `None` for a
+//! message of an unknown type, and unknown capability names are dropped
+//! when a `Hello` is parsed. A peer that never answers `Hello` predates the
+//! handshake and is treated as having no optional capabilities.
+//!
+//! ## Wire Formats
+//!
+//! Messages start as JSON text frames. Peers that both announce
+//! `Capability::MessagePack` switch to MessagePack binary frames after the
+//! handshake; see the [`wire`] module.
+//!
+//! ## Heartbeats
+//!
+//! Peers announcing `Capability::Heartbeat` ping each other after a
+//! configurable interval of silence and declare the connection dead after
+//! a number of unanswered pings, surfacing a [`DisconnectedEvent`]; see the
+//! [`heartbeat`] module.
+//!
+//! ## Subscriptions
+//!
+//! Until a client sends `Subscribe` it receives every broadcast message. A
+//! subscription replaces that with an explicit [`Topic`] list, each with an
+//! optional maximum rate, so a phone UI can take 1 Hz status while a
+//! diagnostics dashboard takes 10 Hz thermal and pressure plus valve frames.
+//! Rate-limited topics deliver the newest message once per interval rather
+//! than a backlog. Responses to a client's own requests are never filtered.
+//!
+//! ## Traces
+//!
+//! The [`trace`] module records message streams with their timing to a file
+//! for replay in the simulator.
+//!
+//! ## Error Codes
+//!
+//! `ErrorEvent::code` is one of the stable codes in the [`error_codes`]
+//! registry, which also carries each code's default severity, description
+//! and recovery hint for UIs to localize and link to documentation.
+//!
+//! ## Discovery
+//!
+//! Firmware announces itself on the LAN over mDNS; the [`discovery`]
+//! module defines the service type and the record both sides read.
+//!
+//! ## Capability Descriptors
+//!
+//! The [`descriptor`] module normalizes a printer configuration into the
+//! grid, channels, ranges and `.hg4d` versions a slicer has to agree with,
+//! so a slice can be checked against the live printer rather than a local
+//! copy of its configuration.
+//!
 //! ## Usage Example
 //!
 //! ```rust
@@ -45,19 +135,35 @@
 //!         progress_percent: 28.4,
 //!         elapsed_time: 1234,
 //!         estimated_remaining: 3122,
+//!         material_consumed: Default::default(),
 //!     };
 //!
 //!     client.send(ProtocolMessage::StatusUpdate(status)).await.unwrap();
 //! }
 //! ```
 
+use std::collections::BTreeMap;
 use std::time::{Duration, SystemTime};
 use serde::{Deserialize, Serialize};
 use async_trait::async_trait;
 
 // Internal ecosystem imports
-use gcode_types::{Coordinate, GridCoordinate, Color};
-use config_types::PrinterConfig;
+use gcode_types::{Command, CommandStats, Coordinate, GridCoordinate, Color, LayerFrame};
+use config_types::{MacroDefinition, MaterialProfile, PrinterConfig};
+
+pub mod trace;
+pub mod discovery;
+pub mod error_codes;
+pub mod wire;
+pub mod heartbeat;
+pub mod descriptor;
+
+pub use trace::{Trace, TraceDirection, TraceEntry, TraceHeader, TraceWriter};
+pub use discovery::ServiceAnnouncement;
+pub use error_codes::ErrorCode;
+pub use wire::{decode_frame, Encoding, WireFormat, WireFrame};
+pub use heartbeat::{DisconnectReason, DisconnectedEvent, Heartbeat, HeartbeatConfig, HeartbeatMonitor};
+pub use descriptor::{CapabilityDescriptor, DescriptorMismatch};
 
 // Shared Type Definitions - Fully Implemented
 
@@ -74,6 +180,15 @@ pub enum ProtocolMessage {
     PressureUpdate(PressureUpdate),
     ValveStateUpdate(ValveStateUpdate),
     ErrorEvent(ErrorEvent),
+    ConfigChanged(ConfigChangeNotification),
+    LayerTiming(LayerTimingReport),
+    RecoveryAvailable(RecoveryOffer),
+    MaterialExposure(MaterialExposureWarning),
+    JobFinished(PrintJobRecord),
+    StateChanged(StateChangeEvent),
+    InspectionPause(InspectionPauseEvent),
+    MaterialRunout(MaterialRunoutEvent),
+    HostTelemetry(HostTelemetry),
     
     // Control Interface → Firmware (commands)
     StartPrint(StartPrintCommand),
@@ -82,12 +197,50 @@ pub enum ProtocolMessage {
     CancelPrint,
     EmergencyStop,
     AdjustParameter(AdjustParameterCommand),
+    CancelObject(CancelObjectCommand),
+    SetMaterial(SetMaterialCommand),
+    ResumeFromJournal,
+    DiscardJournal,
+    StartFlowCalibration(StartFlowCalibrationCommand),
+    SubmitFlowMeasurement(FlowMeasurementCommand),
+    ApplyFlowCalibration,
+    StartBedLevel,
+    SubmitBedLevelPoint(BedLevelPointCommand),
+    ProbeBedLevel,
+    SaveBedLevel,
+    StartDrying(StartDryingCommand),
+    CancelDrying,
+    ClearThermalFault(ClearThermalFaultCommand),
+    DeleteHistoryJob(DeleteHistoryJobCommand),
+    RunMacro(RunMacroCommand),
+    Subscribe(SubscribeRequest),
     
     // Bidirectional (request/response)
+    Hello(Hello),
+    Ping(Heartbeat),
+    Pong(Heartbeat),
     GetStatus(GetStatusRequest),
     StatusResponse(StatusResponse),
     GetConfig,
     ConfigResponse(ConfigResponse),
+    GetLogs(LogQuery),
+    LogsResponse(LogsResponse),
+    GetMaterials,
+    MaterialsResponse(MaterialsResponse),
+    GetFlowCalibration,
+    FlowCalibrationStatus(FlowCalibrationStatus),
+    GetBedLevel,
+    BedLevelStatus(BedLevelStatus),
+    GetHistory(HistoryQuery),
+    HistoryResponse(HistoryResponse),
+    GetHistoryStats,
+    HistoryStats(HistoryStats),
+    GetMacros,
+    MacrosResponse(MacrosResponse),
+    GetCapabilityDescriptor,
+    CapabilityDescriptor(CapabilityDescriptor),
+    
+    SubscriptionAck(SubscriptionAck),
     
     // Generic response
     CommandResponse(CommandResponse),
@@ -110,16 +263,62 @@ impl ProtocolMessage {
             ProtocolMessage::PressureUpdate(_) => "PressureUpdate",
             ProtocolMessage::ValveStateUpdate(_) => "ValveStateUpdate",
             ProtocolMessage::ErrorEvent(_) => "ErrorEvent",
+            ProtocolMessage::ConfigChanged(_) => "ConfigChanged",
+            ProtocolMessage::LayerTiming(_) => "LayerTiming",
+            ProtocolMessage::RecoveryAvailable(_) => "RecoveryAvailable",
+            ProtocolMessage::MaterialExposure(_) => "MaterialExposure",
+            ProtocolMessage::JobFinished(_) => "JobFinished",
+            ProtocolMessage::StateChanged(_) => "StateChanged",
+            ProtocolMessage::InspectionPause(_) => "InspectionPause",
+            ProtocolMessage::MaterialRunout(_) => "MaterialRunout",
+            ProtocolMessage::HostTelemetry(_) => "HostTelemetry",
             ProtocolMessage::StartPrint(_) => "StartPrint",
             ProtocolMessage::PausePrint(_) => "PausePrint",
             ProtocolMessage::ResumePrint => "ResumePrint",
             ProtocolMessage::CancelPrint => "CancelPrint",
             ProtocolMessage::EmergencyStop => "EmergencyStop",
             ProtocolMessage::AdjustParameter(_) => "AdjustParameter",
+            ProtocolMessage::CancelObject(_) => "CancelObject",
+            ProtocolMessage::SetMaterial(_) => "SetMaterial",
+            ProtocolMessage::ResumeFromJournal => "ResumeFromJournal",
+            ProtocolMessage::DiscardJournal => "DiscardJournal",
+            ProtocolMessage::StartFlowCalibration(_) => "StartFlowCalibration",
+            ProtocolMessage::SubmitFlowMeasurement(_) => "SubmitFlowMeasurement",
+            ProtocolMessage::ApplyFlowCalibration => "ApplyFlowCalibration",
+            ProtocolMessage::StartBedLevel => "StartBedLevel",
+            ProtocolMessage::SubmitBedLevelPoint(_) => "SubmitBedLevelPoint",
+            ProtocolMessage::ProbeBedLevel => "ProbeBedLevel",
+            ProtocolMessage::SaveBedLevel => "SaveBedLevel",
+            ProtocolMessage::StartDrying(_) => "StartDrying",
+            ProtocolMessage::CancelDrying => "CancelDrying",
+            ProtocolMessage::ClearThermalFault(_) => "ClearThermalFault",
+            ProtocolMessage::DeleteHistoryJob(_) => "DeleteHistoryJob",
+            ProtocolMessage::RunMacro(_) => "RunMacro",
+            ProtocolMessage::Subscribe(_) => "Subscribe",
+            ProtocolMessage::SubscriptionAck(_) => "SubscriptionAck",
+            ProtocolMessage::Hello(_) => "Hello",
+            ProtocolMessage::Ping(_) => "Ping",
+            ProtocolMessage::Pong(_) => "Pong",
             ProtocolMessage::GetStatus(_) => "GetStatus",
             ProtocolMessage::StatusResponse(_) => "StatusResponse",
             ProtocolMessage::GetConfig => "GetConfig",
             ProtocolMessage::ConfigResponse(_) => "ConfigResponse",
+            ProtocolMessage::GetLogs(_) => "GetLogs",
+            ProtocolMessage::LogsResponse(_) => "LogsResponse",
+            ProtocolMessage::GetMaterials => "GetMaterials",
+            ProtocolMessage::MaterialsResponse(_) => "MaterialsResponse",
+            ProtocolMessage::GetFlowCalibration => "GetFlowCalibration",
+            ProtocolMessage::FlowCalibrationStatus(_) => "FlowCalibrationStatus",
+            ProtocolMessage::GetBedLevel => "GetBedLevel",
+            ProtocolMessage::BedLevelStatus(_) => "BedLevelStatus",
+            ProtocolMessage::GetHistory(_) => "GetHistory",
+            ProtocolMessage::HistoryResponse(_) => "HistoryResponse",
+            ProtocolMessage::GetHistoryStats => "GetHistoryStats",
+            ProtocolMessage::HistoryStats(_) => "HistoryStats",
+            ProtocolMessage::GetMacros => "GetMacros",
+            ProtocolMessage::MacrosResponse(_) => "MacrosResponse",
+            ProtocolMessage::GetCapabilityDescriptor => "GetCapabilityDescriptor",
+            ProtocolMessage::CapabilityDescriptor(_) => "CapabilityDescriptor",
             ProtocolMessage::CommandResponse(_) => "CommandResponse",
         }
     }
@@ -134,6 +333,22 @@ impl ProtocolMessage {
                 | ProtocolMessage::CancelPrint
                 | ProtocolMessage::EmergencyStop
                 | ProtocolMessage::AdjustParameter(_)
+                | ProtocolMessage::CancelObject(_)
+                | ProtocolMessage::SetMaterial(_)
+                | ProtocolMessage::ResumeFromJournal
+                | ProtocolMessage::DiscardJournal
+                | ProtocolMessage::StartFlowCalibration(_)
+                | ProtocolMessage::SubmitFlowMeasurement(_)
+                | ProtocolMessage::ApplyFlowCalibration
+                | ProtocolMessage::StartBedLevel
+                | ProtocolMessage::SubmitBedLevelPoint(_)
+                | ProtocolMessage::ProbeBedLevel
+                | ProtocolMessage::SaveBedLevel
+                | ProtocolMessage::StartDrying(_)
+                | ProtocolMessage::CancelDrying
+                | ProtocolMessage::ClearThermalFault(_)
+                | ProtocolMessage::DeleteHistoryJob(_)
+                | ProtocolMessage::RunMacro(_)
         )
     }
 
@@ -145,6 +360,10 @@ impl ProtocolMessage {
                 | ProtocolMessage::ThermalUpdate(_)
                 | ProtocolMessage::PressureUpdate(_)
                 | ProtocolMessage::ValveStateUpdate(_)
+                | ProtocolMessage::StateChanged(_)
+                | ProtocolMessage::InspectionPause(_)
+                | ProtocolMessage::MaterialRunout(_)
+                | ProtocolMessage::HostTelemetry(_)
         )
     }
 }
@@ -205,6 +424,10 @@ pub struct StatusUpdate {
     
     /// Estimated seconds remaining
     pub estimated_remaining: u64,
+
+    /// Material deposited so far per channel (g)
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub material_consumed: BTreeMap<u8, f32>,
 }
 
 /// Thermal system update.
@@ -228,6 +451,9 @@ pub struct ThermalZone {
     pub id: u8,
     pub current: f32,
     pub target: f32,
+    /// Heater PWM duty cycle (0-1) after power budgeting
+    #[serde(default)]
+    pub duty: Option<f32>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -265,6 +491,14 @@ pub struct ValveStateUpdate {
     
     /// Hash of current pattern (for change detection)
     pub pattern_hash: String,
+    
+    /// Full valve grid size in nodes (x, y)
+    #[serde(default)]
+    pub grid_size: Option<(u32, u32)>,
+    
+    /// Current pattern as a run-length encoded frame, when streamed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub frame: Option<LayerFrame>,
 }
 
 /// Error event notification.
@@ -273,7 +507,7 @@ pub struct ErrorEvent {
     /// Error severity level
     pub severity: ErrorSeverity,
     
-    /// Machine-readable error code
+    /// Machine-readable error code, from the [`ErrorCode`] registry
     pub code: String,
     
     /// Human-readable message
@@ -286,6 +520,152 @@ pub struct ErrorEvent {
     pub recommended_action: Option<String>,
 }
 
+impl ErrorEvent {
+    /// Event for a registered code at its default severity, recommending
+    /// its recovery hint.
+    pub fn new(code: ErrorCode, message: impl Into<String>, affected_systems: Vec<String>) -> Self {
+        Self {
+            severity: code.default_severity(),
+            code: code.into(),
+            message: message.into(),
+            affected_systems,
+            recommended_action: Some(code.recovery_hint().to_string()),
+        }
+    }
+
+    /// The registered code, or None for one this build does not know.
+    pub fn error_code(&self) -> Option<ErrorCode> {
+        self.code.parse().ok()
+    }
+}
+
+/// Notification sent after the firmware reloads its configuration file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConfigChangeNotification {
+    /// True if every changed parameter was applied
+    pub accepted: bool,
+    
+    /// Parameters applied live
+    pub applied: Vec<String>,
+    
+    /// Parameters left unchanged (restart required or limits violated)
+    pub requires_restart: Vec<String>,
+    
+    /// Human-readable summary
+    pub message: String,
+}
+
+/// Measured duration of a completed layer together with the workload that
+/// produced it. The slicer fits its time model against these reports.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LayerTimingReport {
+    pub layer_number: u32,
+    pub z_height: f32,
+    
+    /// Wall-clock time from layer start to the next layer advance
+    pub duration_ms: u64,
+    
+    /// Number of valve state changes (opening or closing one valve),
+    /// counted from all valves closed at layer start
+    pub valve_switches: u32,
+    
+    /// Number of pressure stabilization waits (G4W pressure)
+    pub pressure_waits: u32,
+    
+    /// Total Z travel in this layer (mm)
+    pub z_travel_mm: f32,
+    
+    /// Material deposited (mm³)
+    pub deposited_volume_mm3: f32,
+    
+    /// Time spent at G4W barriers, one entry per wait type that occurred
+    #[serde(default)]
+    pub barrier_waits: Vec<BarrierWaitTiming>,
+}
+
+/// Time a layer spent waiting at G4W barriers of one type.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BarrierWaitTiming {
+    /// `valves`, `pressure`, `temperature` or `duration`
+    pub wait_type: String,
+    
+    /// Number of barriers of this type
+    pub count: u32,
+    
+    /// Total time until release or timeout (ms)
+    pub total_ms: u64,
+    
+    /// Longest single wait (ms)
+    pub max_ms: u64,
+    
+    /// Barriers that hit their timeout
+    pub timeouts: u32,
+}
+
+impl LayerTimingReport {
+    /// Derives the workload counters from a layer's command list.
+    ///
+    /// `previous_z` is the Z height before the layer started and is used to
+    /// compute travel for the first G4L command.
+    pub fn from_commands(
+        layer_number: u32,
+        previous_z: f32,
+        commands: &[Command],
+        duration: Duration,
+    ) -> Self {
+        let mut stats = CommandStats::new().starting_at(previous_z);
+        stats.extend(commands);
+        Self {
+            layer_number,
+            z_height: stats.z().unwrap_or(previous_z),
+            duration_ms: duration.as_millis() as u64,
+            valve_switches: stats.valve_toggles as u32,
+            pressure_waits: stats.pressure_waits,
+            z_travel_mm: stats.z_travel_mm,
+            deposited_volume_mm3: stats.extrusion_mm3,
+            barrier_waits: Vec::new(),
+        }
+    }
+
+    /// Derives the workload counters from an executed frame: a frame starts
+    /// with every valve closed, so each open valve is one switch, and each
+    /// depositing node deposits one grid cell (`cell_area` mm²) as tall as
+    /// the layer.
+    pub fn from_frame(previous_z: f32, frame: &LayerFrame, cell_area: f32, duration: Duration) -> Self {
+        let (depositing, switches) = frame
+            .nodes()
+            .map(|node| node.open_count() as u32)
+            .filter(|&open| open > 0)
+            .fold((0u32, 0u32), |(nodes, valves), open| (nodes + 1, valves + open));
+        let height = (frame.z_height - previous_z).max(0.0);
+        Self {
+            layer_number: frame.layer_number,
+            z_height: frame.z_height,
+            duration_ms: duration.as_millis() as u64,
+            valve_switches: switches,
+            pressure_waits: 0,
+            z_travel_mm: (frame.z_height - previous_z).abs(),
+            deposited_volume_mm3: depositing as f32 * cell_area * height,
+            barrier_waits: Vec::new(),
+        }
+    }
+
+    /// Attaches the barrier wait times measured during the layer.
+    pub fn with_barrier_waits(mut self, waits: Vec<BarrierWaitTiming>) -> Self {
+        self.barrier_waits = waits;
+        self
+    }
+
+    /// Total time spent at barriers of `wait_type` (ms).
+    pub fn barrier_wait_ms(&self, wait_type: &str) -> u64 {
+        self.barrier_waits
+            .iter()
+            .filter(|w| w.wait_type == wait_type)
+            .map(|w| w.total_ms)
+            .sum()
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum ErrorSeverity {
     #[serde(rename = "Info")]
@@ -308,6 +688,10 @@ pub struct StartPrintCommand {
     
     /// Optional: start from specific layer (for resume)
     pub start_layer: Option<u32>,
+    
+    /// Run without heat, pressure or extrusion to check motion and valves
+    #[serde(default)]
+    pub dry_run: bool,
 }
 
 /// Pause print command.
@@ -317,6 +701,44 @@ pub struct PausePrintCommand {
     pub reason: String,
 }
 
+/// Cancel one object of a multi-object print.
+///
+/// Valves of nodes tagged with the object stay closed from the current
+/// layer onward; other objects keep printing.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CancelObjectCommand {
+    /// Object id as tagged by the slicer
+    pub object_id: u32,
+}
+
+/// Return a thermal zone disabled by a fault to service.
+///
+/// The zone's heater stays off until this is sent; its fault errors are
+/// cleared and it accepts targets again.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClearThermalFaultCommand {
+    pub zone: u8,
+}
+
+/// Print interrupted by power loss that can be resumed.
+///
+/// Sent at boot while a recovery journal exists; answered with
+/// `ResumeFromJournal` or `DiscardJournal`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecoveryOffer {
+    /// File that was printing
+    pub file_path: String,
+    
+    /// Layer printing resumes from
+    pub layer: u32,
+    
+    /// Z position when power failed (mm)
+    pub z_position: f32,
+    
+    /// Unix time the journal was written (ms)
+    pub written_at_ms: u64,
+}
+
 /// Adjust parameter during printing.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AdjustParameterCommand {
@@ -331,19 +753,196 @@ pub struct AdjustParameterCommand {
     
     /// Unit of value
     pub unit: String,
+
+    /// When the firmware applies the change during a print
+    #[serde(default)]
+    pub apply: AdjustTiming,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum AdjustableParameter {
+    /// Multiplier on deposited volume ("%" or a plain factor)
     FlowRate,
+    /// Zone target ("C" or "F"), clamped to the zone and loaded material
     Temperature,
+    /// Channel target ("psi", "bar" or "kPa")
     Pressure,
+    /// Multiplier on execution rate ("%" or a plain factor)
     Speed,
 }
 
+/// When an adjustment takes effect.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AdjustTiming {
+    /// With the other pending adjustments, before the next layer starts
+    #[default]
+    NextLayer,
+    /// As soon as the command is received
+    Immediate,
+}
+
+/// Broadcast stream a client can subscribe to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Topic {
+    Status,
+    Thermal,
+    Pressure,
+    ValveFrames,
+    Errors,
+    Config,
+    LayerTiming,
+    Host,
+}
+
+impl Topic {
+    pub const ALL: [Topic; 8] = [
+        Topic::Status,
+        Topic::Thermal,
+        Topic::Pressure,
+        Topic::ValveFrames,
+        Topic::Errors,
+        Topic::Config,
+        Topic::LayerTiming,
+        Topic::Host,
+    ];
+
+    /// Topic a broadcast message belongs to; `None` for commands and
+    /// request/response messages, which subscriptions do not filter.
+    pub fn of(msg: &ProtocolMessage) -> Option<Topic> {
+        match msg {
+            ProtocolMessage::StatusUpdate(_) => Some(Topic::Status),
+            ProtocolMessage::ThermalUpdate(_) => Some(Topic::Thermal),
+            ProtocolMessage::PressureUpdate(_) => Some(Topic::Pressure),
+            ProtocolMessage::ValveStateUpdate(_) => Some(Topic::ValveFrames),
+            ProtocolMessage::ErrorEvent(_) => Some(Topic::Errors),
+            ProtocolMessage::ConfigChanged(_) => Some(Topic::Config),
+            ProtocolMessage::LayerTiming(_) => Some(Topic::LayerTiming),
+            ProtocolMessage::RecoveryAvailable(_) => Some(Topic::Status),
+            ProtocolMessage::MaterialExposure(_) => Some(Topic::Status),
+            ProtocolMessage::JobFinished(_) => Some(Topic::Status),
+            ProtocolMessage::StateChanged(_) => Some(Topic::Status),
+            ProtocolMessage::InspectionPause(_) => Some(Topic::Status),
+            ProtocolMessage::MaterialRunout(_) => Some(Topic::Status),
+            ProtocolMessage::HostTelemetry(_) => Some(Topic::Host),
+            _ => None,
+        }
+    }
+}
+
+/// One topic of a subscription.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct TopicSubscription {
+    pub topic: Topic,
+    
+    /// Maximum messages per second; every message if absent. Error events
+    /// are never rate limited.
+    #[serde(default)]
+    pub rate_hz: Option<f32>,
+}
+
+/// Replaces the client's subscription.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SubscribeRequest {
+    pub topics: Vec<TopicSubscription>,
+}
+
+/// Subscription as granted, with rates clamped to what the firmware allows.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SubscriptionAck {
+    pub topics: Vec<TopicSubscription>,
+}
+
 // Request/Response Messages
 
+/// Optional protocol features a peer may implement.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Capability {
+    /// Valve frames as binary WebSocket frames instead of JSON
+    BinaryFrames,
+    /// Queued print jobs started one after another
+    JobQueue,
+    /// CancelObject during a print
+    CancelObject,
+    /// Topic subscriptions with rate limits
+    Subscriptions,
+    /// Print history queries and statistics
+    PrintHistory,
+    /// Messages after the handshake as MessagePack in binary frames
+    MessagePack,
+    /// Ping/Pong heartbeats and dead-peer detection
+    Heartbeat,
+}
+
+/// Handshake sent by each side on connect.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Hello {
+    /// Sender's [`PROTOCOL_VERSION`]
+    pub protocol_version: String,
+    /// Sending component, e.g. "firmware" or "control-interface"
+    pub software: String,
+    pub software_version: String,
+    /// Capabilities known to this build; others are dropped when parsing
+    #[serde(deserialize_with = "known_capabilities")]
+    pub capabilities: Vec<Capability>,
+}
+
+impl Hello {
+    /// Hello for this build's protocol version.
+    pub fn new(
+        software: impl Into<String>,
+        software_version: impl Into<String>,
+        capabilities: Vec<Capability>,
+    ) -> Self {
+        Self {
+            protocol_version: PROTOCOL_VERSION.to_string(),
+            software: software.into(),
+            software_version: software_version.into(),
+            capabilities,
+        }
+    }
+
+    pub fn supports(&self, capability: Capability) -> bool {
+        self.capabilities.contains(&capability)
+    }
+
+    /// Checks the peer speaks a protocol this build understands: the same
+    /// major version. Minor versions only add messages and capabilities.
+    pub fn check_compatible(&self) -> Result<(), ProtocolError> {
+        let major = |version: &str| version.split('.').next().unwrap_or("").to_string();
+        if major(&self.protocol_version) != major(PROTOCOL_VERSION) {
+            return Err(ProtocolError::ValidationError(format!(
+                "{} {} speaks protocol {}, this build speaks {}",
+                self.software, self.software_version, self.protocol_version, PROTOCOL_VERSION
+            )));
+        }
+        Ok(())
+    }
+
+    /// Capabilities both peers announce.
+    pub fn common_capabilities(&self, peer: &Hello) -> Vec<Capability> {
+        self.capabilities
+            .iter()
+            .copied()
+            .filter(|c| peer.supports(*c))
+            .collect()
+    }
+}
+
+fn known_capabilities<'de, D>(deserializer: D) -> Result<Vec<Capability>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let names = Vec::<String>::deserialize(deserializer)?;
+    Ok(names
+        .into_iter()
+        .filter_map(|name| serde_json::from_value(serde_json::Value::String(name)).ok())
+        .collect())
+}
+
 /// Request current status.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GetStatusRequest {
@@ -376,6 +975,396 @@ pub struct ConfigResponse {
     pub firmware_version: String,
 }
 
+/// Log severity levels, ordered from most to least verbose.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogLevel {
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+/// A single structured log record captured by the firmware.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogEntry {
+    /// Milliseconds since the Unix epoch
+    pub timestamp_ms: u64,
+    pub level: LogLevel,
+    /// Firmware subsystem (hardware, core, safety, ...)
+    pub subsystem: String,
+    /// Full tracing target
+    pub target: String,
+    pub message: String,
+    /// Structured fields attached to the event
+    #[serde(default)]
+    pub fields: std::collections::BTreeMap<String, String>,
+}
+
+/// Request for recent firmware log entries.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LogQuery {
+    /// Minimum level to include
+    pub min_level: Option<LogLevel>,
+    /// Only include this subsystem
+    pub subsystem: Option<String>,
+    /// Inclusive lower bound (ms since epoch)
+    pub since_ms: Option<u64>,
+    /// Inclusive upper bound (ms since epoch)
+    pub until_ms: Option<u64>,
+    /// Maximum number of entries (most recent kept)
+    pub limit: Option<usize>,
+}
+
+impl LogQuery {
+    /// Returns true if the entry satisfies every filter in the query.
+    pub fn matches(&self, entry: &LogEntry) -> bool {
+        self.min_level.is_none_or(|l| entry.level >= l)
+            && self
+                .subsystem
+                .as_ref()
+                .is_none_or(|s| entry.subsystem.eq_ignore_ascii_case(s))
+            && self.since_ms.is_none_or(|t| entry.timestamp_ms >= t)
+            && self.until_ms.is_none_or(|t| entry.timestamp_ms <= t)
+    }
+}
+
+/// Log entries returned for a [`LogQuery`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogsResponse {
+    /// Matching entries, oldest first
+    pub entries: Vec<LogEntry>,
+    /// More entries matched than the limit allowed
+    pub truncated: bool,
+    /// Entries evicted from the firmware buffer since startup
+    pub dropped: u64,
+}
+
+/// Material profile loaded on a channel.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LoadedMaterial {
+    pub channel: u8,
+    pub profile: MaterialProfile,
+    
+    #[serde(default)]
+    pub spool: Option<SpoolStatus>,
+}
+
+/// Moisture exposure of a loaded spool.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpoolStatus {
+    /// Seconds since the Unix epoch when the spool was loaded
+    pub loaded_at: u64,
+    
+    /// Time loaded (firmware uptime) since loading or the last drying
+    pub exposure_hours: f32,
+    
+    /// Exposure limit from the material profile
+    pub limit_hours: Option<f32>,
+    
+    /// Remaining time of a running drying cycle
+    pub drying_remaining_s: Option<u64>,
+}
+
+/// A loaded spool exceeded its exposure limit and should be dried.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MaterialExposureWarning {
+    pub channel: u8,
+    pub material: String,
+    pub exposure_hours: f32,
+    pub limit_hours: f32,
+}
+
+/// Dries the spool loaded on a channel.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StartDryingCommand {
+    pub channel: u8,
+    
+    /// Overrides the profile's drying temperature (°C)
+    #[serde(default)]
+    pub temperature: Option<f32>,
+    
+    /// Overrides the profile's drying time (hours)
+    #[serde(default)]
+    pub hours: Option<f32>,
+}
+
+/// Records the material loaded on a channel.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SetMaterialCommand {
+    pub channel: u8,
+    
+    /// Loaded material; None marks the channel empty
+    pub profile: Option<MaterialProfile>,
+}
+
+/// Materials currently loaded, in channel order.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MaterialsResponse {
+    pub channels: Vec<LoadedMaterial>,
+}
+
+/// Start the flow calibration wizard on the given channels.
+///
+/// Prints one test patch per channel; pattern fields fall back to the
+/// firmware defaults when absent.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StartFlowCalibrationCommand {
+    pub channels: Vec<u8>,
+    
+    /// Patch side length (mm)
+    #[serde(default)]
+    pub patch_mm: Option<f32>,
+    
+    /// Patch height in layers
+    #[serde(default)]
+    pub layers: Option<u32>,
+}
+
+/// Weighed mass of one channel's calibration patch.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FlowMeasurementCommand {
+    pub channel: u8,
+    pub mass_g: f32,
+}
+
+/// Flow calibration wizard progress.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FlowCalibrationStatus {
+    pub channels: Vec<FlowCalibrationChannel>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FlowCalibrationChannel {
+    pub channel: u8,
+    
+    /// Material profile the patch was printed with
+    pub material: String,
+    
+    pub expected_mass_g: f32,
+    pub measured_mass_g: Option<f32>,
+    
+    /// Flow multiplier the patch was printed with
+    pub previous_multiplier: f32,
+    
+    /// Corrected multiplier, once the patch has been weighed
+    pub new_multiplier: Option<f32>,
+}
+
+/// Measured deviation at one bed level probe point.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BedLevelPointCommand {
+    /// Probe point index (row-major)
+    pub index: usize,
+    
+    /// Gap minus nominal gap (mm); positive where the bed is farther away
+    pub deviation_mm: f32,
+}
+
+/// Bed level measurement progress.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BedLevelStatus {
+    pub points: Vec<BedLevelPoint>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BedLevelPoint {
+    pub index: usize,
+    
+    /// Probe position (mm)
+    pub x: f32,
+    pub y: f32,
+    
+    pub deviation_mm: Option<f32>,
+}
+
+/// How a print job ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobResult {
+    Completed,
+    Cancelled,
+    Failed,
+    EmergencyStopped,
+}
+
+/// The firmware changed operational state.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StateChangeEvent {
+    /// State left, as in `StatusUpdate::state`
+    pub from: String,
+    
+    /// State entered
+    pub to: String,
+    
+    /// What caused the change
+    pub reason: String,
+}
+
+/// The print reached an inspection pause and holds at temperature until
+/// `ResumePrint`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InspectionPauseEvent {
+    /// Layer just deposited
+    pub layer_number: u32,
+    
+    pub z_height: f32,
+    
+    /// Whether the camera webhook accepted the trigger; None without a camera
+    pub camera_triggered: Option<bool>,
+}
+
+/// A material channel ran out during a print. Sent at each stage of the
+/// guided resume: the operator reloads the channel, then sends
+/// `ResumePrint`, which re-primes it before the interrupted layer continues.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MaterialRunoutEvent {
+    pub channel: u8,
+    
+    /// Material that was loaded on the channel, if known
+    pub material: Option<String>,
+    
+    /// Layer the print paused in
+    pub layer_number: u32,
+    
+    pub stage: RunoutStage,
+}
+
+/// Where a runout pause stands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RunoutStage {
+    /// Paused with the channel's pressure released; load material
+    Paused,
+    /// The runout switch reads material again; send `ResumePrint`
+    Loaded,
+    /// Pressure restored and the channel being primed
+    Priming,
+    /// The interrupted layer continues
+    Resumed,
+}
+
+/// Resource use of the computer running the firmware, sampled at the
+/// configured interval. Crossing a warning threshold also raises an
+/// `E-HOST-*` warning event.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HostTelemetry {
+    /// CPU busy time since the previous sample (percentage of all cores)
+    pub cpu_load_percent: f32,
+    
+    /// One-minute load average
+    pub load_average: f32,
+    
+    pub memory_used_mb: u64,
+    pub memory_total_mb: u64,
+    
+    /// SoC temperature (°C), if the board reports one
+    pub soc_temperature: Option<f32>,
+    
+    /// Space in the print directory
+    pub disk_free_mb: u64,
+    pub disk_total_mb: u64,
+}
+
+/// A finished print job in the history.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PrintJobRecord {
+    /// Assigned by the history store; increases with each job
+    pub id: u64,
+    
+    pub file_name: String,
+    
+    /// Start time (ms since epoch)
+    pub started_ms: u64,
+    
+    pub duration_s: f64,
+    pub result: JobResult,
+    pub layers_printed: u32,
+    pub total_layers: u32,
+    
+    /// Dry runs are kept in the history but left out of statistics
+    #[serde(default)]
+    pub dry_run: bool,
+    
+    /// Material deposited per channel (mm³)
+    #[serde(default)]
+    pub filament_mm3: BTreeMap<u8, f32>,
+    
+    /// Errors raised during the job, in order
+    #[serde(default)]
+    pub errors: Vec<String>,
+    
+    /// Mean absolute deviation of heated zones from target (°C)
+    pub thermal_deviation: Option<f32>,
+}
+
+/// Filter for print history requests; newest jobs first.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct HistoryQuery {
+    pub result: Option<JobResult>,
+    /// Inclusive lower bound on start time (ms since epoch)
+    pub since_ms: Option<u64>,
+    /// Inclusive upper bound on start time (ms since epoch)
+    pub until_ms: Option<u64>,
+    pub limit: Option<usize>,
+}
+
+impl HistoryQuery {
+    /// Returns true if the job satisfies every filter in the query.
+    pub fn matches(&self, job: &PrintJobRecord) -> bool {
+        self.result.is_none_or(|r| job.result == r)
+            && self.since_ms.is_none_or(|t| job.started_ms >= t)
+            && self.until_ms.is_none_or(|t| job.started_ms <= t)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryResponse {
+    pub jobs: Vec<PrintJobRecord>,
+}
+
+/// Totals over the print history, excluding dry runs.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct HistoryStats {
+    pub total_jobs: usize,
+    pub completed: usize,
+    pub cancelled: usize,
+    pub failed: usize,
+    
+    /// Completed jobs as a fraction of all jobs (0-1)
+    pub success_rate: f32,
+    
+    pub total_print_time_s: f64,
+    
+    /// Material deposited per channel (mm³)
+    pub filament_mm3: BTreeMap<u8, f32>,
+    
+    /// Mean of the jobs' thermal deviations (°C)
+    pub mean_thermal_deviation: Option<f32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeleteHistoryJobCommand {
+    pub id: u64,
+}
+
+/// Runs a macro from the printer configuration.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RunMacroCommand {
+    pub name: String,
+    
+    /// Parameter values; declared defaults fill the rest
+    #[serde(default)]
+    pub args: BTreeMap<String, f32>,
+}
+
+/// Macros defined in the printer configuration.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MacrosResponse {
+    pub macros: Vec<MacroDefinition>,
+}
+
 /// Generic command response.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CommandResponse {
@@ -418,6 +1407,17 @@ pub trait MessageClient: Send + Sync {
     
     /// Closes the connection.
     async fn close(&mut self) -> Result<(), ProtocolError>;
+
+    /// Starts heartbeats on the connection; call once the handshake shows
+    /// the peer announces `Capability::Heartbeat`. After that `Ping`s are
+    /// answered rather than returned, and receiving fails with
+    /// [`ProtocolError::Disconnected`] once the peer is declared dead.
+    fn enable_heartbeat(&mut self, monitor: HeartbeatMonitor);
+
+    /// Encodes sent messages with `format` from now on; call once the
+    /// handshake is done. Received messages are decoded by frame kind
+    /// whatever the format.
+    fn set_wire_format(&mut self, format: WireFormat);
 }
 
 /// Trait for handling received messages.
@@ -449,6 +1449,8 @@ pub trait MessageTransport: Send + Sync {
 pub struct WebSocketClient {
     // WebSocket connection would be stored here
     connected: bool,
+    heartbeat: Option<HeartbeatMonitor>,
+    wire: WireFormat,
 }
 
 impl WebSocketClient {
@@ -460,11 +1462,11 @@ impl WebSocketClient {
 #[async_trait]
 impl MessageClient for WebSocketClient {
     async fn send(&mut self, msg: ProtocolMessage) -> Result<(), ProtocolError> {
-        todo!("Implementation needed: Serialize and send message over WebSocket")
+        todo!("Implementation needed: Encode message with self.wire and send it as a text or binary frame")
     }
 
     async fn recv(&mut self) -> Result<ProtocolMessage, ProtocolError> {
-        todo!("Implementation needed: Receive and deserialize message from WebSocket")
+        todo!("Implementation needed: Receive a frame from WebSocket and decode it with wire::decode_frame")
     }
 
     async fn try_recv(&mut self) -> Result<Option<ProtocolMessage>, ProtocolError> {
@@ -474,11 +1476,21 @@ impl MessageClient for WebSocketClient {
     async fn close(&mut self) -> Result<(), ProtocolError> {
         todo!("Implementation needed: Close WebSocket connection gracefully")
     }
+
+    fn enable_heartbeat(&mut self, monitor: HeartbeatMonitor) {
+        self.heartbeat = Some(monitor);
+    }
+
+    fn set_wire_format(&mut self, format: WireFormat) {
+        self.wire = format;
+    }
 }
 
 /// Serial port message client implementation.
 pub struct SerialClient {
     connected: bool,
+    heartbeat: Option<HeartbeatMonitor>,
+    wire: WireFormat,
 }
 
 impl SerialClient {
@@ -504,6 +1516,14 @@ impl MessageClient for SerialClient {
     async fn close(&mut self) -> Result<(), ProtocolError> {
         todo!("Implementation needed: Close serial port")
     }
+
+    fn enable_heartbeat(&mut self, monitor: HeartbeatMonitor) {
+        self.heartbeat = Some(monitor);
+    }
+
+    fn set_wire_format(&mut self, format: WireFormat) {
+        self.wire = format;
+    }
 }
 
 /// Message broker for pub/sub pattern.
@@ -541,6 +1561,28 @@ pub fn deserialize_message(data: &[u8]) -> Result<ProtocolMessage, ProtocolError
     Ok(timestamped.message)
 }
 
+/// Deserializes a message from JSON bytes, returning `None` for a message
+/// type this build does not know (sent by a newer peer) so the receiver can
+/// skip it. Malformed messages of a known type are still errors.
+pub fn decode_message(data: &[u8]) -> Result<Option<ProtocolMessage>, ProtocolError> {
+    let value: serde_json::Value = serde_json::from_slice(data)
+        .map_err(|e| ProtocolError::DeserializationError(e.to_string()))?;
+    decode_value(value)
+}
+
+/// [`decode_message`] for a message already parsed into a JSON value.
+pub(crate) fn decode_value(mut value: serde_json::Value) -> Result<Option<ProtocolMessage>, ProtocolError> {
+    if let Some(fields) = value.as_object_mut() {
+        fields.remove("timestamp");
+    }
+    match serde_json::from_value::<ProtocolMessage>(value) {
+        Ok(message) => Ok(Some(message)),
+        // serde reports an unrecognized tag as an unknown variant
+        Err(e) if e.to_string().starts_with("unknown variant") => Ok(None),
+        Err(e) => Err(ProtocolError::DeserializationError(e.to_string())),
+    }
+}
+
 /// Validates message structure and content.
 pub fn validate_message(msg: &ProtocolMessage) -> Result<(), ProtocolError> {
     match msg {
@@ -558,6 +1600,13 @@ pub fn validate_message(msg: &ProtocolMessage) -> Result<(), ProtocolError> {
                 ));
             }
         }
+        ProtocolMessage::Subscribe(req) => {
+            if req.topics.iter().any(|t| t.rate_hz.is_some_and(|r| !(r.is_finite() && r > 0.0))) {
+                return Err(ProtocolError::ValidationError(
+                    "subscription rates must be positive".to_string(),
+                ));
+            }
+        }
         _ => {}
     }
     Ok(())
@@ -584,6 +1633,7 @@ pub fn create_status_update(
         },
         elapsed_time: elapsed_secs,
         estimated_remaining: remaining_secs,
+        material_consumed: BTreeMap::new(),
     })
 }
 
@@ -592,7 +1642,7 @@ pub fn create_thermal_update(zones: Vec<(u8, f32, f32)>) -> ProtocolMessage {
     ProtocolMessage::ThermalUpdate(ThermalUpdate {
         zones: zones
             .into_iter()
-            .map(|(id, current, target)| ThermalZone { id, current, target })
+            .map(|(id, current, target)| ThermalZone { id, current, target, duty: None })
             .collect(),
         manifold: None,
         bed: None,
@@ -617,8 +1667,9 @@ pub fn create_error_event(
 
 // Module-level Constants
 
-/// Protocol version identifier.
-pub const PROTOCOL_VERSION: &str = "1.0";
+/// Protocol version identifier, `major.minor`. Peers with different major
+/// versions cannot talk; a minor bump only adds messages or capabilities.
+pub const PROTOCOL_VERSION: &str = "1.1";
 
 /// Default WebSocket port.
 pub const DEFAULT_WEBSOCKET_PORT: u16 = 8080;
@@ -652,6 +1703,9 @@ pub enum ProtocolError {
     #[error("Timeout: {0}")]
     Timeout(String),
 
+    #[error("Disconnected: {0}")]
+    Disconnected(DisconnectedEvent),
+
     #[error("I/O error: {0}")]
     Io(#[from] std::io::Error),
 
@@ -673,6 +1727,7 @@ mod tests {
             progress_percent: 10.0,
             elapsed_time: 100,
             estimated_remaining: 900,
+            material_consumed: BTreeMap::new(),
         });
 
         assert!(status.is_status());
@@ -682,10 +1737,16 @@ mod tests {
         let start = ProtocolMessage::StartPrint(StartPrintCommand {
             file_path: "/path/to/file.hg4d".to_string(),
             start_layer: None,
+            dry_run: false,
         });
 
         assert!(start.is_command());
         assert!(!start.is_status());
+
+        let cancel = ProtocolMessage::CancelObject(CancelObjectCommand { object_id: 3 });
+        assert!(cancel.is_command());
+        assert_eq!(cancel.message_type(), "CancelObject");
+        assert!(ProtocolMessage::ResumeFromJournal.is_command());
     }
 
     #[test]
@@ -720,16 +1781,64 @@ mod tests {
         let valid = ProtocolMessage::StartPrint(StartPrintCommand {
             file_path: "/path/to/file.hg4d".to_string(),
             start_layer: None,
+            dry_run: false,
         });
         assert!(validate_message(&valid).is_ok());
 
         let invalid = ProtocolMessage::StartPrint(StartPrintCommand {
             file_path: String::new(),
             start_layer: None,
+            dry_run: false,
         });
         assert!(validate_message(&invalid).is_err());
     }
 
+    #[test]
+    fn test_subscribe_round_trip() {
+        let msg = ProtocolMessage::Subscribe(SubscribeRequest {
+            topics: vec![
+                TopicSubscription { topic: Topic::Status, rate_hz: Some(1.0) },
+                TopicSubscription { topic: Topic::ValveFrames, rate_hz: None },
+            ],
+        });
+        assert!(validate_message(&msg).is_ok());
+
+        let json = String::from_utf8(serialize_message(&msg).unwrap()).unwrap();
+        assert!(json.contains("\"valve_frames\""));
+        match deserialize_message(json.as_bytes()).unwrap() {
+            ProtocolMessage::Subscribe(req) => assert_eq!(req.topics[0].rate_hz, Some(1.0)),
+            other => panic!("unexpected {}", other.message_type()),
+        }
+
+        let zero = ProtocolMessage::Subscribe(SubscribeRequest {
+            topics: vec![TopicSubscription { topic: Topic::Thermal, rate_hz: Some(0.0) }],
+        });
+        assert!(validate_message(&zero).is_err());
+        assert_eq!(Topic::of(&create_status_update("Idle", 0, 0, 0.0, 0, 0)), Some(Topic::Status));
+    }
+
+    #[test]
+    fn test_hello_negotiation_tolerates_newer_peers() {
+        let ours = Hello::new("control-interface", "0.1.0", vec![Capability::CancelObject, Capability::JobQueue]);
+        let json = br#"{"timestamp": 0, "type": "Hello", "data": {
+            "protocol_version": "1.7", "software": "firmware", "software_version": "0.9.0",
+            "capabilities": ["cancel_object", "subscriptions", "teleport"]}}"#;
+        let peer = match decode_message(json).unwrap() {
+            Some(ProtocolMessage::Hello(hello)) => hello,
+            other => panic!("unexpected {:?}", other),
+        };
+        assert!(peer.check_compatible().is_ok());
+        assert_eq!(peer.capabilities, vec![Capability::CancelObject, Capability::Subscriptions]);
+        assert_eq!(ours.common_capabilities(&peer), vec![Capability::CancelObject]);
+
+        let old = Hello { protocol_version: "0.9".to_string(), ..peer };
+        assert!(old.check_compatible().is_err());
+
+        // A message type from the future is skipped; a broken known one is not
+        assert!(decode_message(br#"{"timestamp": 0, "type": "Teleport", "data": {}}"#).unwrap().is_none());
+        assert!(decode_message(br#"{"timestamp": 0, "type": "Hello", "data": {}}"#).is_err());
+    }
+
     #[test]
     fn test_error_severity_levels() {
         use ErrorSeverity::*;
@@ -0,0 +1,176 @@
+//! Recorded protocol traces.
+//!
+//! A trace is a JSON-lines file: a [`TraceHeader`] on the first line, then
+//! one [`TraceEntry`] per message in the order it was recorded. Offsets are
+//! milliseconds since recording started, so a player can reproduce the
+//! original timing. The firmware writes traces; the simulator replays them.
+
+use std::io::{BufRead, Write};
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{ProtocolError, ProtocolMessage};
+
+/// Trace format version written by [`TraceWriter`].
+pub const TRACE_VERSION: u32 = 1;
+
+/// First line of a trace file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TraceHeader {
+    pub version: u32,
+
+    /// Unix time recording started (ms)
+    pub started_at_ms: u64,
+
+    /// Firmware version that recorded the trace
+    pub source: String,
+}
+
+/// Direction of a recorded message relative to the firmware.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TraceDirection {
+    /// Received from a client
+    Inbound,
+    /// Broadcast or sent by the firmware
+    Outbound,
+}
+
+/// One recorded message.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TraceEntry {
+    /// Milliseconds since recording started
+    pub offset_ms: u64,
+    pub direction: TraceDirection,
+    pub message: ProtocolMessage,
+}
+
+/// Appends entries to a trace.
+pub struct TraceWriter<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> TraceWriter<W> {
+    /// Writes the header and returns a writer for the entries.
+    pub fn new(mut writer: W, header: &TraceHeader) -> Result<Self, ProtocolError> {
+        write_line(&mut writer, header)?;
+        Ok(Self { writer })
+    }
+
+    pub fn record(&mut self, entry: &TraceEntry) -> Result<(), ProtocolError> {
+        write_line(&mut self.writer, entry)
+    }
+
+    pub fn flush(&mut self) -> Result<(), ProtocolError> {
+        Ok(self.writer.flush()?)
+    }
+}
+
+fn write_line<W: Write, T: Serialize>(writer: &mut W, value: &T) -> Result<(), ProtocolError> {
+    serde_json::to_writer(&mut *writer, value)
+        .map_err(|e| ProtocolError::SerializationError(e.to_string()))?;
+    writer.write_all(b"\n")?;
+    Ok(())
+}
+
+/// A complete trace loaded into memory.
+#[derive(Debug, Clone)]
+pub struct Trace {
+    pub header: TraceHeader,
+    /// Entries in offset order
+    pub entries: Vec<TraceEntry>,
+}
+
+impl Trace {
+    /// Reads a trace. A truncated final line (recording cut off by a crash
+    /// or power loss) is ignored; any other malformed line is an error.
+    pub fn read<R: BufRead>(reader: R) -> Result<Self, ProtocolError> {
+        let mut lines = reader.lines();
+        let header_line = lines
+            .next()
+            .ok_or_else(|| ProtocolError::DeserializationError("Empty trace".to_string()))??;
+        let header: TraceHeader = serde_json::from_str(&header_line)
+            .map_err(|e| ProtocolError::DeserializationError(format!("Trace header: {}", e)))?;
+        if header.version > TRACE_VERSION {
+            return Err(ProtocolError::DeserializationError(format!(
+                "Trace version {} is newer than supported version {}",
+                header.version, TRACE_VERSION
+            )));
+        }
+
+        let lines: Vec<String> = lines.collect::<Result<_, _>>()?;
+        let mut entries = Vec::with_capacity(lines.len());
+        for (i, line) in lines.iter().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str::<TraceEntry>(line) {
+                Ok(entry) => entries.push(entry),
+                Err(_) if i + 1 == lines.len() => break,
+                Err(e) => {
+                    return Err(ProtocolError::DeserializationError(format!(
+                        "Trace line {}: {}",
+                        i + 2,
+                        e
+                    )))
+                }
+            }
+        }
+        // Entries from concurrent writers may interleave slightly out of order
+        entries.sort_by_key(|e| e.offset_ms);
+
+        Ok(Self { header, entries })
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, ProtocolError> {
+        let file = std::fs::File::open(path)?;
+        Self::read(std::io::BufReader::new(file))
+    }
+
+    /// Offset of the last entry (ms).
+    pub fn duration_ms(&self) -> u64 {
+        self.entries.last().map(|e| e.offset_ms).unwrap_or(0)
+    }
+
+    /// Index of the first entry at or after `offset_ms`.
+    pub fn index_at(&self, offset_ms: u64) -> usize {
+        self.entries.partition_point(|e| e.offset_ms < offset_ms)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::create_thermal_update;
+
+    #[test]
+    fn test_trace_round_trip() {
+        let header = TraceHeader {
+            version: TRACE_VERSION,
+            started_at_ms: 1_700_000_000_000,
+            source: "test".to_string(),
+        };
+        let mut buf = Vec::new();
+        let mut writer = TraceWriter::new(&mut buf, &header).unwrap();
+        for offset_ms in [0, 100, 250] {
+            writer
+                .record(&TraceEntry {
+                    offset_ms,
+                    direction: TraceDirection::Outbound,
+                    message: create_thermal_update(vec![(0, 200.0, 210.0)]),
+                })
+                .unwrap();
+        }
+        writer.flush().unwrap();
+        // Recording cut off mid-line
+        buf.extend_from_slice(b"{\"offset_ms\":300,\"direc");
+
+        let trace = Trace::read(buf.as_slice()).unwrap();
+        assert_eq!(trace.header, header);
+        assert_eq!(trace.entries.len(), 3);
+        assert_eq!(trace.duration_ms(), 250);
+        assert_eq!(trace.index_at(100), 1);
+        assert_eq!(trace.index_at(101), 2);
+    }
+}
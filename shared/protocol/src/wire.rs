@@ -0,0 +1,142 @@
+//! Wire encodings.
+//!
+//! A connection starts with JSON text frames. When both `Hello`s announce
+//! [`Capability::MessagePack`], [`WireFormat::negotiate`] switches the
+//! sending side to MessagePack in binary frames for every later message,
+//! roughly halving the size of high-rate telemetry. Each side switches
+//! after sending its own `Hello`, which always goes out as JSON. Receivers
+//! decode by frame kind ([`decode_frame`]), so messages already in flight
+//! when the other side switches still parse, and unknown message types are
+//! skipped as with [`decode_message`](crate::decode_message).
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Capability, Hello, ProtocolError, ProtocolMessage, MAX_MESSAGE_SIZE};
+
+/// How messages are encoded on a connection.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Encoding {
+    /// JSON in text frames
+    #[default]
+    Json,
+    /// MessagePack (maps with field names) in binary frames
+    MessagePack,
+}
+
+/// An encoded message and the frame kind that carries it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WireFrame {
+    Text(String),
+    Binary(Vec<u8>),
+}
+
+impl WireFrame {
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
+            WireFrame::Text(text) => text.as_bytes(),
+            WireFrame::Binary(data) => data,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.as_bytes().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// A connection's outgoing encoding.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WireFormat {
+    pub encoding: Encoding,
+}
+
+impl WireFormat {
+    /// Encoding to send with after exchanging `Hello`s: MessagePack when
+    /// both announce it, JSON otherwise.
+    pub fn negotiate(local: &Hello, peer: &Hello) -> Self {
+        let encoding = if local.common_capabilities(peer).contains(&Capability::MessagePack) {
+            Encoding::MessagePack
+        } else {
+            Encoding::Json
+        };
+        Self { encoding }
+    }
+
+    /// Encodes `msg` with a timestamp, like
+    /// [`serialize_message`](crate::serialize_message).
+    pub fn encode(&self, msg: &ProtocolMessage) -> Result<WireFrame, ProtocolError> {
+        let timestamped = msg.clone().with_timestamp();
+        let frame = match self.encoding {
+            Encoding::Json => serde_json::to_string(&timestamped)
+                .map(WireFrame::Text)
+                .map_err(|e| ProtocolError::SerializationError(e.to_string()))?,
+            Encoding::MessagePack => rmp_serde::to_vec_named(&timestamped)
+                .map(WireFrame::Binary)
+                .map_err(|e| ProtocolError::SerializationError(e.to_string()))?,
+        };
+        if frame.len() > MAX_MESSAGE_SIZE {
+            return Err(ProtocolError::MessageTooLarge(frame.len(), MAX_MESSAGE_SIZE));
+        }
+        Ok(frame)
+    }
+}
+
+/// Decodes a received frame: text frames as JSON, binary frames as
+/// MessagePack. Returns `None` for a message type this build does not know.
+pub fn decode_frame(frame: &WireFrame) -> Result<Option<ProtocolMessage>, ProtocolError> {
+    match frame {
+        WireFrame::Text(text) => crate::decode_message(text.as_bytes()),
+        WireFrame::Binary(data) => {
+            // Through a JSON value so unknown types and the timestamp are
+            // handled as for JSON; messages carry no raw byte fields
+            let value: serde_json::Value = rmp_serde::from_slice(data)
+                .map_err(|e| ProtocolError::DeserializationError(e.to_string()))?;
+            crate::decode_value(value)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::StatusUpdate;
+
+    #[test]
+    fn test_negotiated_encoding() {
+        let hello = |capabilities| Hello::new("test", "1.0.0", capabilities);
+        let binary = hello(vec![Capability::MessagePack]);
+        assert_eq!(WireFormat::negotiate(&binary, &hello(vec![])).encoding, Encoding::Json);
+        let format = WireFormat::negotiate(&binary, &binary);
+        assert_eq!(format.encoding, Encoding::MessagePack);
+
+        let status = ProtocolMessage::StatusUpdate(StatusUpdate {
+            state: "Printing".to_string(),
+            current_layer: 142,
+            total_layers: 500,
+            z_position: 28.4,
+            progress_percent: 28.4,
+            elapsed_time: 1234,
+            estimated_remaining: 3122,
+            material_consumed: Default::default(),
+        });
+        let packed = format.encode(&status).unwrap();
+        let json = WireFormat::default().encode(&status).unwrap();
+        assert!(matches!(packed, WireFrame::Binary(_)) && matches!(json, WireFrame::Text(_)));
+        assert!(packed.len() < json.len());
+        for frame in [&packed, &json] {
+            match decode_frame(frame).unwrap() {
+                Some(ProtocolMessage::StatusUpdate(s)) => assert_eq!(s.current_layer, 142),
+                other => panic!("unexpected {:?}", other),
+            }
+        }
+
+        // A newer peer's message type is skipped in either encoding
+        let unknown = serde_json::json!({"type": "FutureMessage", "data": {}});
+        let frame = WireFrame::Binary(rmp_serde::to_vec_named(&unknown).unwrap());
+        assert!(decode_frame(&frame).unwrap().is_none());
+    }
+}
@@ -0,0 +1,458 @@
+//! End-to-end acceptance runs: slice, parse, simulate, check.
+//!
+//! An [`AcceptanceCase`] pairs a generated sample model with one of the
+//! stock printer configurations. [`AcceptanceHarness::run`] slices it with
+//! the slicer library, streams the resulting .hg4d back through the
+//! firmware's parser exactly as a printer would, and feeds the decoded
+//! frames to the performance analysis and the thermal model. The run fails
+//! on any broken invariant:
+//!
+//! - **Bounds**: every node lies on the printer's valve grid, uses a valve
+//!   the nodes have and a channel the printer has
+//! - **Pressure**: no channel needs more than the maximum pressure
+//! - **Z**: layer heights strictly rise, layer numbers run without gaps
+//! - **Layer count**: the file holds as many layers as the slicer reported,
+//!   and within one of what the model height and layer heights imply
+//!
+//! Layers the thermal model leaves above the glass transition are reported
+//! but not checked: whether that is a defect depends on the part.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use tracing::{info, warn};
+
+use config_types::{MaterialProfile, PrintSettings, PrinterConfig, PrinterModel};
+use gcode_types::LayerFrame;
+use hypergcode_firmware::gcode::stream::DEFAULT_LOOKAHEAD_LAYERS;
+use hypergcode_firmware::gcode::{GCodeParser, LayerStream};
+use hypergcode_slicer::config::ExampleConfigs;
+use hypergcode_slicer::core::arrange::DEFAULT_OBJECT_SPACING;
+use hypergcode_slicer::core::Plate;
+use hypergcode_slicer::{Mesh, MeshUnits, Slicer};
+
+use crate::analysis::{AnalysisReport, AnomalyKind, PerformanceAnalyzer};
+use crate::physics::{PhysicsEngine, ThermalModel, ThermalProperties};
+
+/// Longest thermal integration step; the model shortens it further where
+/// stability requires.
+const THERMAL_STEP_S: f32 = 0.05;
+
+/// Model generated for a case, so runs need no files on disk.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SampleModel {
+    /// Axis-aligned box (mm)
+    Cuboid { x: f32, y: f32, z: f32 },
+    /// Two boxes sharing a corner, giving an L-shaped footprint (mm)
+    LShape { arm: f32, width: f32, z: f32 },
+}
+
+impl SampleModel {
+    pub fn height(&self) -> f32 {
+        match *self {
+            SampleModel::Cuboid { z, .. } | SampleModel::LShape { z, .. } => z,
+        }
+    }
+
+    pub fn mesh(&self) -> Mesh {
+        match *self {
+            SampleModel::Cuboid { x, y, z } => cuboid((0.0, 0.0), (x, y), z),
+            SampleModel::LShape { arm, width, z } => {
+                let mut mesh = cuboid((0.0, 0.0), (arm, width), z);
+                let upright = cuboid((0.0, width), (width, arm), z);
+                let offset = (mesh.vertices.len() / 3) as u32;
+                mesh.vertices.extend(upright.vertices);
+                mesh.indices.extend(upright.indices.iter().map(|i| i + offset));
+                mesh
+            }
+        }
+    }
+}
+
+/// Closed box spanning `min` to `max` in XY and 0 to `z`.
+fn cuboid(min: (f32, f32), max: (f32, f32), z: f32) -> Mesh {
+    let mut vertices = Vec::with_capacity(24);
+    for i in 0..8 {
+        vertices.extend([
+            if i & 1 != 0 { max.0 } else { min.0 },
+            if i & 2 != 0 { max.1 } else { min.1 },
+            if i & 4 != 0 { z } else { 0.0 },
+        ]);
+    }
+    Mesh {
+        vertices,
+        indices: vec![
+            0, 2, 1, 1, 2, 3, 4, 5, 6, 5, 7, 6, 0, 1, 4, 1, 5, 4,
+            2, 6, 3, 3, 6, 7, 0, 4, 2, 2, 4, 6, 1, 3, 5, 3, 7, 5,
+        ],
+        normals: None,
+        face_channels: None,
+        face_objects: None,
+        units: MeshUnits::Millimeters,
+    }
+}
+
+/// One model on one printer.
+#[derive(Debug, Clone)]
+pub struct AcceptanceCase {
+    pub name: String,
+    pub model: SampleModel,
+    pub printer: PrinterModel,
+}
+
+impl AcceptanceCase {
+    pub fn new(name: impl Into<String>, model: SampleModel, printer: PrinterModel) -> Self {
+        Self { name: name.into(), model, printer }
+    }
+}
+
+/// The regression set: a calibration cube, a thin tower, a wide flat
+/// plate that opens most of the grid at once, and an L-shape, across the
+/// stock printers.
+pub fn standard_cases() -> Vec<AcceptanceCase> {
+    vec![
+        AcceptanceCase::new("cube-20", SampleModel::Cuboid { x: 20.0, y: 20.0, z: 20.0 }, PrinterModel::HyperCubeMini),
+        AcceptanceCase::new("tower-5x40", SampleModel::Cuboid { x: 5.0, y: 5.0, z: 40.0 }, PrinterModel::HyperCubeStandard),
+        AcceptanceCase::new("plate-80", SampleModel::Cuboid { x: 80.0, y: 80.0, z: 1.0 }, PrinterModel::HyperCubeStandard),
+        AcceptanceCase::new("l-shape", SampleModel::LShape { arm: 30.0, width: 8.0, z: 10.0 }, PrinterModel::HyperCubePro),
+        AcceptanceCase::new("cube-40", SampleModel::Cuboid { x: 40.0, y: 40.0, z: 10.0 }, PrinterModel::HyperCubeIndustrial),
+    ]
+}
+
+/// A broken invariant.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Violation {
+    /// Node outside the valve grid
+    OffGrid { layer: u32, x: u32, y: u32 },
+    /// Frame decodes more valves per node than the printer has
+    ValveIndex { layer: u32, valves_per_node: u8 },
+    /// Plane for a channel the printer does not have
+    Channel { layer: u32, channel: u8 },
+    /// A channel needs more than the maximum pressure
+    Pressure { layer: u32, message: String },
+    ZNotIncreasing { layer: u32, message: String },
+    /// Layer numbers skip or repeat
+    LayerNumber { expected: u32, found: u32 },
+    LayerCount { expected: u32, found: u32, source: &'static str },
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Violation::OffGrid { layer, x, y } => write!(f, "layer {}: node ({}, {}) is off the valve grid", layer, x, y),
+            Violation::ValveIndex { layer, valves_per_node } => {
+                write!(f, "layer {}: frame uses {} valves per node", layer, valves_per_node)
+            }
+            Violation::Channel { layer, channel } => write!(f, "layer {}: no material channel {}", layer, channel),
+            Violation::Pressure { layer, message } | Violation::ZNotIncreasing { layer, message } => {
+                write!(f, "layer {}: {}", layer, message)
+            }
+            Violation::LayerNumber { expected, found } => write!(f, "layer {} follows where {} was expected", found, expected),
+            Violation::LayerCount { expected, found, source } => {
+                write!(f, "{} layers in the file, {} expected from {}", found, expected, source)
+            }
+        }
+    }
+}
+
+/// What the file is expected to hold.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Expectation {
+    /// Exact layer count, as reported by the slicer
+    pub layers: Option<u32>,
+    /// Model height; the layer count must be within one of what it implies
+    pub model_height: Option<f32>,
+}
+
+/// Result of checking one file.
+#[derive(Debug, Clone)]
+pub struct FileCheck {
+    pub layers: u32,
+    pub analysis: AnalysisReport,
+    /// Layers ending above the glass transition somewhere
+    pub hot_layers: Vec<u32>,
+    pub violations: Vec<Violation>,
+}
+
+impl FileCheck {
+    pub fn passed(&self) -> bool {
+        self.violations.is_empty()
+    }
+}
+
+/// Result of one case.
+#[derive(Debug, Clone)]
+pub struct CaseReport {
+    pub name: String,
+    pub output: PathBuf,
+    pub check: FileCheck,
+}
+
+impl CaseReport {
+    pub fn passed(&self) -> bool {
+        self.check.passed()
+    }
+}
+
+impl fmt::Display for CaseReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let verdict = if self.passed() { "PASS" } else { "FAIL" };
+        writeln!(
+            f,
+            "{} {}: {} layers, {} anomalies, {} hot layers",
+            verdict,
+            self.name,
+            self.check.layers,
+            self.check.analysis.anomalies.len(),
+            self.check.hot_layers.len()
+        )?;
+        for violation in &self.check.violations {
+            writeln!(f, "  {}", violation)?;
+        }
+        Ok(())
+    }
+}
+
+/// Runs acceptance cases in a scratch directory.
+pub struct AcceptanceHarness {
+    work_dir: PathBuf,
+    keep_outputs: bool,
+}
+
+impl AcceptanceHarness {
+    pub fn new(work_dir: impl Into<PathBuf>) -> Self {
+        Self { work_dir: work_dir.into(), keep_outputs: false }
+    }
+
+    /// Keeps the sliced files for inspection instead of deleting them.
+    pub fn keep_outputs(mut self, keep: bool) -> Self {
+        self.keep_outputs = keep;
+        self
+    }
+
+    /// Slices a case and checks the output.
+    pub async fn run(&self, case: &AcceptanceCase) -> Result<CaseReport> {
+        let configs = ExampleConfigs::for_model(case.printer)?;
+        std::fs::create_dir_all(&self.work_dir)
+            .with_context(|| format!("Failed to create {}", self.work_dir.display()))?;
+        let output = self.work_dir.join(format!("{}.hg4d", case.name));
+
+        let mut plate = Plate::new(DEFAULT_OBJECT_SPACING);
+        plate.add(case.name.clone(), case.model.mesh(), None)?;
+        plate.arrange(&configs.printer.build_volume)?;
+        // Channel profiles decide the pressure, dead volume and transient
+        // checks the slicer runs, as they do for `--materials`
+        let materials: Vec<MaterialProfile> = configs.materials.iter().map(|(_, p)| p.clone()).collect();
+        let mut slicer = Slicer::new(configs.printer.clone(), configs.settings.clone());
+        slicer.set_material_profiles(materials.clone());
+        let sliced = slicer
+            .slice_plate(&plate, &output)
+            .with_context(|| format!("Slicing {} failed", case.name))?;
+        info!("{}: sliced {} layers", case.name, sliced.layer_count);
+
+        let expectation = Expectation { layers: Some(sliced.layer_count), model_height: Some(case.model.height()) };
+        let check = check_file(&output, &configs.printer, &configs.settings, &materials, expectation).await?;
+
+        if !self.keep_outputs {
+            std::fs::remove_file(&output).ok();
+        }
+        Ok(CaseReport { name: case.name.clone(), output, check })
+    }
+
+    /// Runs every case; a case that fails to slice is an error, one that
+    /// breaks an invariant is a failed report.
+    pub async fn run_all(&self, cases: &[AcceptanceCase]) -> Result<Vec<CaseReport>> {
+        let mut reports = Vec::with_capacity(cases.len());
+        for case in cases {
+            let report = self.run(case).await?;
+            if !report.passed() {
+                warn!("{} failed {} invariant(s)", case.name, report.check.violations.len());
+            }
+            reports.push(report);
+        }
+        Ok(reports)
+    }
+}
+
+/// Streams a file through the firmware parser and checks every invariant.
+pub async fn check_file<P: AsRef<Path>>(
+    path: P,
+    printer: &PrinterConfig,
+    settings: &PrintSettings,
+    materials: &[MaterialProfile],
+    expectation: Expectation,
+) -> Result<FileCheck> {
+    let mut stream = LayerStream::open(path, GCodeParser::new(), DEFAULT_LOOKAHEAD_LAYERS)?;
+    let mut frames = Vec::new();
+    while let Some(frame) = stream.next_layer().await {
+        frames.push(frame?);
+    }
+
+    let mut violations = Vec::new();
+    for (n, frame) in frames.iter().enumerate() {
+        if frame.layer_number != n as u32 {
+            violations.push(Violation::LayerNumber { expected: n as u32, found: frame.layer_number });
+        }
+        check_bounds(frame, printer, &mut violations);
+    }
+
+    let analysis = PerformanceAnalyzer::for_printer(printer).analyze_frames(&frames);
+    for anomaly in &analysis.anomalies {
+        let (layer, message) = (anomaly.layer_number, anomaly.message.clone());
+        match anomaly.kind {
+            AnomalyKind::PressureDemand => violations.push(Violation::Pressure { layer, message }),
+            AnomalyKind::ZNotIncreasing => violations.push(Violation::ZNotIncreasing { layer, message }),
+            AnomalyKind::SwitchingRate | AnomalyKind::EmptyLayer => {}
+        }
+    }
+
+    let found = frames.len() as u32;
+    if let Some(expected) = expectation.layers.filter(|&n| n != found) {
+        violations.push(Violation::LayerCount { expected, found, source: "the slicer" });
+    }
+    if let Some(height) = expectation.model_height {
+        let above_first = (height - settings.first_layer_height).max(0.0);
+        let expected = 1 + (above_first / settings.layer_height).ceil() as u32;
+        if found.abs_diff(expected) > 1 {
+            violations.push(Violation::LayerCount { expected, found, source: "the model height" });
+        }
+    }
+
+    let hot_layers = match materials.first() {
+        Some(material) => simulate_cooling(&frames, &analysis, printer, settings, material)?,
+        None => Vec::new(),
+    };
+
+    Ok(FileCheck { layers: found, analysis, hot_layers, violations })
+}
+
+fn check_bounds(frame: &LayerFrame, printer: &PrinterConfig, violations: &mut Vec<Violation>) {
+    let layer = frame.layer_number;
+    if frame.valves_per_node > printer.valve_array.valves_per_node {
+        violations.push(Violation::ValveIndex { layer, valves_per_node: frame.valves_per_node });
+    }
+    for channel in frame.planes.iter().filter_map(|p| p.channel) {
+        if channel >= printer.materials.channel_count {
+            violations.push(Violation::Channel { layer, channel });
+        }
+    }
+    let (grid_x, grid_y) = (printer.grid_x_count(), printer.grid_y_count());
+    // One violation per layer is enough to locate the problem
+    if let Some(node) = frame.nodes().find(|n| n.position.x >= grid_x || n.position.y >= grid_y) {
+        violations.push(Violation::OffGrid { layer, x: node.position.x, y: node.position.y });
+    }
+}
+
+/// Cools each layer for its predicted time; returns the layers left above
+/// the glass transition.
+fn simulate_cooling(
+    frames: &[LayerFrame],
+    analysis: &AnalysisReport,
+    printer: &PrinterConfig,
+    settings: &PrintSettings,
+    material: &MaterialProfile,
+) -> Result<Vec<u32>> {
+    let (width, height) = (printer.grid_x_count() as usize, printer.grid_y_count() as usize);
+    let model = ThermalModel::new(
+        ThermalProperties::from_material(material),
+        width,
+        height,
+        printer.valve_array.grid_spacing,
+        settings.layer_height,
+    );
+    let mut physics = PhysicsEngine::new(THERMAL_STEP_S).with_thermal(model);
+
+    let mut hot = Vec::new();
+    for (frame, stats) in frames.iter().zip(&analysis.layers) {
+        let mut occupied = vec![false; width * height];
+        for node in frame.nodes().filter(|n| n.open_count() > 0) {
+            let (x, y) = (node.position.x as usize, node.position.y as usize);
+            if x < width && y < height {
+                occupied[y * width + x] = true;
+            }
+        }
+        let fan = if frame.layer_number == 0 {
+            material.cooling.initial_fan_speed
+        } else {
+            material.cooling.regular_fan_speed
+        };
+        let report = physics.simulate_layer_cooling(&occupied, stats.predicted_time, fan)?;
+        if report.is_too_hot() {
+            hot.push(frame.layer_number);
+        }
+    }
+    Ok(hot)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use config_types::ResolvedSettings;
+    use gcode_types::{GridCoordinate, Layer, NodeValveState, ValveState};
+    use hypergcode_slicer::gcode::HG4DWriter;
+    use hypergcode_slicer::{hash_printer_config, SliceMetadata};
+
+    #[tokio::test]
+    async fn test_check_file_flags_violations() {
+        let configs = ExampleConfigs::for_model(PrinterModel::HyperCubeMini).unwrap();
+        let materials: Vec<MaterialProfile> = configs.materials.iter().map(|(_, p)| p.clone()).collect();
+        let path = std::env::temp_dir().join(format!("hg4d-acceptance-{}.hg4d", std::process::id()));
+        let metadata = SliceMetadata {
+            printer_config_hash: hash_printer_config(&configs.printer),
+            material_profiles: materials.clone(),
+            print_settings: ResolvedSettings { settings: configs.settings.clone(), applied: vec![] },
+            model_name: "cube".to_string(),
+            slicer_version: "test".to_string(),
+            objects: vec![],
+            material_usage: vec![],
+        };
+        let grid_x = configs.printer.grid_x_count();
+        let mut writer = HG4DWriter::create(&path, metadata).unwrap();
+        writer.write_header().unwrap();
+        for n in 0..3u32 {
+            let z = configs.settings.first_layer_height + configs.settings.layer_height * n as f32;
+            let mut layer = Layer::new(z, n);
+            for x in 0..4 {
+                layer.add_node(NodeValveState::new(GridCoordinate::new(x, 2), vec![ValveState::open(0)]));
+            }
+            if n == 2 {
+                layer.add_node(NodeValveState::new(GridCoordinate::new(grid_x, 2), vec![ValveState::open(0)]));
+            }
+            layer.estimated_time = Some(10.0);
+            writer.write_layer(&layer).unwrap();
+        }
+        writer.finalize().unwrap();
+
+        let clean = Expectation { layers: Some(3), model_height: Some(0.7) };
+        let check = check_file(&path, &configs.printer, &configs.settings, &materials, clean).await.unwrap();
+        assert_eq!(check.layers, 3);
+        assert_eq!(check.analysis.layers.len(), 3);
+        assert_eq!(check.violations, vec![Violation::OffGrid { layer: 2, x: grid_x, y: 2 }]);
+
+        let wrong = Expectation { layers: Some(4), model_height: Some(5.0) };
+        let check = check_file(&path, &configs.printer, &configs.settings, &materials, wrong).await.unwrap();
+        assert!(!check.passed());
+        let counts: Vec<&'static str> = check
+            .violations
+            .iter()
+            .filter_map(|v| match v {
+                Violation::LayerCount { source, .. } => Some(*source),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(counts, vec!["the slicer", "the model height"]);
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[tokio::test]
+    async fn test_standard_case_passes() {
+        let case = standard_cases().into_iter().find(|case| case.name == "cube-20").unwrap();
+        let dir = std::env::temp_dir().join(format!("hg4d-acceptance-run-{}", std::process::id()));
+        let report = AcceptanceHarness::new(&dir).run(&case).await.unwrap();
+        assert!(report.passed(), "{}", report);
+        assert!(report.check.layers > 0);
+        assert!(!report.output.exists());
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
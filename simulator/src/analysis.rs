@@ -0,0 +1,395 @@
+//! Per-layer analysis of .hg4d print files.
+//!
+//! Each layer frame is a static valve pattern held while the layer
+//! deposits, so the analysis works layer to layer:
+//!
+//! - **Switches**: valves whose state differs from the previous layer
+//! - **Simultaneous opens**: valves open in the frame, overall and per
+//!   material channel
+//! - **Layer time**: the slicer's estimate, and the minimum the valves
+//!   allow (one switching period plus the valve response when anything
+//!   switches). The prediction is the larger of the two.
+//! - **Pressure demand**: the pressure each channel needs to deliver its
+//!   open nodes' volume (grid cell × layer height) within the predicted
+//!   layer time, using the channel's flow coefficient (flow = k·√ΔP)
+//!
+//! Layers estimated faster than the valves can switch, channels needing
+//! more than the maximum pressure, Z going backwards and empty layers are
+//! reported as anomalies.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+
+use config_types::PrinterConfig;
+use gcode_types::{GridCoordinate, LayerFrame};
+use hypergcode_firmware::gcode::stream::DEFAULT_LOOKAHEAD_LAYERS;
+use hypergcode_firmware::gcode::{GCodeParser, LayerStream};
+
+/// Printer limits the analysis checks against.
+#[derive(Debug, Clone)]
+pub struct AnalysisLimits {
+    pub grid_spacing: f32,
+    pub max_switching_freq: f32,
+    /// Valve response time (s)
+    pub response_time: f32,
+    pub max_pressure: f32,
+    /// Flow coefficient per channel (mm³/s per √PSI)
+    pub flow_coefficients: BTreeMap<u8, f32>,
+}
+
+impl AnalysisLimits {
+    pub fn from_printer(printer: &PrinterConfig) -> Self {
+        Self {
+            grid_spacing: printer.valve_array.grid_spacing,
+            max_switching_freq: printer.valve_array.max_switching_freq,
+            response_time: printer.valve_array.response_time_ms / 1000.0,
+            max_pressure: printer.materials.pressure.max_pressure.min(printer.safety.max_pressure.get()),
+            flow_coefficients: printer
+                .materials
+                .pressure
+                .channels
+                .iter()
+                .map(|c| (c.channel, c.flow_coefficient))
+                .collect(),
+        }
+    }
+}
+
+impl Default for AnalysisLimits {
+    fn default() -> Self {
+        Self {
+            grid_spacing: 0.5,
+            max_switching_freq: 10.0,
+            response_time: 0.005,
+            max_pressure: 100.0,
+            flow_coefficients: BTreeMap::new(),
+        }
+    }
+}
+
+/// Statistics for one layer.
+#[derive(Debug, Clone, Serialize)]
+pub struct LayerStats {
+    pub layer_number: u32,
+    pub z_height: f32,
+    /// Valves open in the layer's frame
+    pub open_valves: usize,
+    /// Open valves per material channel
+    pub channel_open_valves: BTreeMap<u8, usize>,
+    /// Valves that change state from the previous layer
+    pub valve_switches: usize,
+    /// Slicer's estimate (s), if recorded
+    pub estimated_time: Option<f32>,
+    /// Shortest time the valves allow (s)
+    pub min_time: f32,
+    /// Time used for rates and pressure demand (s)
+    pub predicted_time: f32,
+    /// Array-wide switching throughput (switches/s)
+    pub switch_rate: f32,
+    /// Pressure each channel needs (PSI), where its flow coefficient is known
+    pub pressure_demand: BTreeMap<u8, f32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AnomalyKind {
+    /// Estimated layer time is shorter than one valve switching period
+    SwitchingRate,
+    /// A channel needs more than the maximum pressure
+    PressureDemand,
+    ZNotIncreasing,
+    EmptyLayer,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Anomaly {
+    pub layer_number: u32,
+    pub kind: AnomalyKind,
+    pub message: String,
+}
+
+/// Result of analyzing a print file.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct AnalysisReport {
+    pub layers: Vec<LayerStats>,
+    pub anomalies: Vec<Anomaly>,
+}
+
+impl AnalysisReport {
+    pub fn total_switches(&self) -> usize {
+        self.layers.iter().map(|l| l.valve_switches).sum()
+    }
+
+    pub fn predicted_time(&self) -> f32 {
+        self.layers.iter().map(|l| l.predicted_time).sum()
+    }
+
+    /// Layer with the most valves open at once.
+    pub fn peak_open(&self) -> Option<&LayerStats> {
+        self.layers.iter().max_by_key(|l| l.open_valves)
+    }
+
+    /// Layer with the highest switching throughput.
+    pub fn peak_switch_rate(&self) -> Option<&LayerStats> {
+        self.layers.iter().max_by(|a, b| a.switch_rate.total_cmp(&b.switch_rate))
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        serde_json::to_string_pretty(self).context("Failed to serialize analysis report")
+    }
+}
+
+impl fmt::Display for AnalysisReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} layers, {} valve switches, predicted {:.1} min",
+            self.layers.len(),
+            self.total_switches(),
+            self.predicted_time() / 60.0
+        )?;
+        if let Some(peak) = self.peak_open() {
+            writeln!(f, "Peak simultaneous open: {} valves (layer {})", peak.open_valves, peak.layer_number)?;
+        }
+        if let Some(peak) = self.peak_switch_rate() {
+            writeln!(f, "Peak switching: {:.0} switches/s (layer {})", peak.switch_rate, peak.layer_number)?;
+        }
+        writeln!(f)?;
+        writeln!(
+            f,
+            "{:>6} {:>8} {:>8} {:>9} {:>9} {:>10} {:>12}",
+            "layer", "z", "open", "switches", "time s", "switch/s", "max PSI"
+        )?;
+        for layer in &self.layers {
+            let demand = layer.pressure_demand.values().copied().reduce(f32::max);
+            writeln!(
+                f,
+                "{:>6} {:>8.2} {:>8} {:>9} {:>9.2} {:>10.0} {:>12}",
+                layer.layer_number,
+                layer.z_height,
+                layer.open_valves,
+                layer.valve_switches,
+                layer.predicted_time,
+                layer.switch_rate,
+                demand.map(|p| format!("{:.1}", p)).unwrap_or_else(|| "-".to_string())
+            )?;
+        }
+
+        writeln!(f)?;
+        if self.anomalies.is_empty() {
+            writeln!(f, "No anomalies")?;
+        } else {
+            writeln!(f, "{} anomalies:", self.anomalies.len())?;
+            for anomaly in &self.anomalies {
+                writeln!(f, "  layer {:>5}: {}", anomaly.layer_number, anomaly.message)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Analyzes print files layer by layer.
+pub struct PerformanceAnalyzer {
+    limits: AnalysisLimits,
+}
+
+impl PerformanceAnalyzer {
+    pub fn new() -> Self {
+        Self {
+            limits: AnalysisLimits::default(),
+        }
+    }
+
+    pub fn for_printer(printer: &PrinterConfig) -> Self {
+        Self {
+            limits: AnalysisLimits::from_printer(printer),
+        }
+    }
+
+    /// Streams and analyzes every layer of an .hg4d file.
+    pub async fn analyze_file<P: AsRef<Path>>(&self, path: P) -> Result<AnalysisReport> {
+        let mut stream = LayerStream::open(path, GCodeParser::new(), DEFAULT_LOOKAHEAD_LAYERS)?;
+        let mut analysis = Analysis::default();
+        while let Some(frame) = stream.next_layer().await {
+            self.add_layer(&mut analysis, &frame?);
+        }
+        Ok(analysis.report)
+    }
+
+    /// Analyzes layers already in memory.
+    pub fn analyze_frames<'a>(&self, frames: impl IntoIterator<Item = &'a LayerFrame>) -> AnalysisReport {
+        let mut analysis = Analysis::default();
+        for frame in frames {
+            self.add_layer(&mut analysis, frame);
+        }
+        analysis.report
+    }
+
+    fn add_layer(&self, analysis: &mut Analysis, frame: &LayerFrame) {
+        let limits = &self.limits;
+        let layer_number = frame.layer_number;
+
+        // Open valve mask per node, and open nodes and valves per channel
+        let mut masks: HashMap<GridCoordinate, u16> = HashMap::new();
+        let mut channel_nodes: BTreeMap<u8, usize> = BTreeMap::new();
+        let mut channel_valves: BTreeMap<u8, usize> = BTreeMap::new();
+        for plane in &frame.planes {
+            for row in &plane.rows {
+                for run in &row.runs {
+                    for i in 0..run.len {
+                        let position = GridCoordinate::new(frame.origin.x + run.x + i, frame.origin.y + row.y);
+                        *masks.entry(position).or_default() |= run.mask;
+                    }
+                    if let (Some(channel), true) = (plane.channel, run.mask != 0) {
+                        *channel_nodes.entry(channel).or_default() += run.len as usize;
+                        *channel_valves.entry(channel).or_default() +=
+                            run.len as usize * run.mask.count_ones() as usize;
+                    }
+                }
+            }
+        }
+        let open_valves: usize = masks.values().map(|m| m.count_ones() as usize).sum();
+
+        let valve_switches: usize = masks
+            .iter()
+            .map(|(p, m)| (m ^ analysis.previous.get(p).copied().unwrap_or(0)).count_ones() as usize)
+            .chain(
+                analysis
+                    .previous
+                    .iter()
+                    .filter(|(p, _)| !masks.contains_key(p))
+                    .map(|(_, m)| m.count_ones() as usize),
+            )
+            .sum();
+
+        let period = 1.0 / limits.max_switching_freq.max(f32::EPSILON);
+        let min_time = if valve_switches > 0 { period + limits.response_time } else { period };
+        let predicted_time = frame.estimated_time.unwrap_or(min_time).max(min_time);
+        let switch_rate = valve_switches as f32 / predicted_time;
+
+        let layer_height = match analysis.previous_z {
+            Some(z) => frame.z_height - z,
+            None => frame.z_height,
+        };
+        let cell_volume = limits.grid_spacing * limits.grid_spacing * layer_height.max(0.0);
+        let pressure_demand: BTreeMap<u8, f32> = channel_nodes
+            .iter()
+            .filter_map(|(&channel, &nodes)| {
+                let k = *limits.flow_coefficients.get(&channel)?;
+                let flow = nodes as f32 * cell_volume / predicted_time;
+                (k > 0.0).then(|| (channel, (flow / k).powi(2)))
+            })
+            .collect();
+
+        let mut anomalies = Vec::new();
+        let mut flag = |kind, message: String| anomalies.push(Anomaly { layer_number, kind, message });
+        if let Some(z) = analysis.previous_z.filter(|&z| frame.z_height <= z) {
+            flag(
+                AnomalyKind::ZNotIncreasing,
+                format!("Z {:.3} does not rise above the previous layer's {:.3}", frame.z_height, z),
+            );
+        }
+        if open_valves == 0 {
+            flag(AnomalyKind::EmptyLayer, "No valves open".to_string());
+        }
+        if let Some(estimate) = frame.estimated_time.filter(|&t| t < min_time) {
+            flag(
+                AnomalyKind::SwitchingRate,
+                format!(
+                    "Estimated {:.3}s implies {:.1} Hz switching, above the {:.1} Hz limit",
+                    estimate,
+                    1.0 / estimate.max(f32::EPSILON),
+                    limits.max_switching_freq
+                ),
+            );
+        }
+        for (channel, psi) in &pressure_demand {
+            if *psi > limits.max_pressure.get() {
+                flag(
+                    AnomalyKind::PressureDemand,
+                    format!(
+                        "Channel {} needs {:.1} PSI for {} open nodes, above the {:.1} PSI limit",
+                        channel, psi, channel_nodes[channel], limits.max_pressure.get()
+                    ),
+                );
+            }
+        }
+
+        analysis.report.anomalies.extend(anomalies);
+        analysis.report.layers.push(LayerStats {
+            layer_number,
+            z_height: frame.z_height,
+            open_valves,
+            channel_open_valves: channel_valves,
+            valve_switches,
+            estimated_time: frame.estimated_time,
+            min_time,
+            predicted_time,
+            switch_rate,
+            pressure_demand,
+        });
+        analysis.previous = masks;
+        analysis.previous_z = Some(frame.z_height);
+    }
+}
+
+impl Default for PerformanceAnalyzer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Running state while layers are added.
+#[derive(Default)]
+struct Analysis {
+    report: AnalysisReport,
+    previous: HashMap<GridCoordinate, u16>,
+    previous_z: Option<f32>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gcode_types::{Layer, NodeValveState, ValveState};
+
+    fn frame(n: u32, z: f32, xs: std::ops::Range<u32>, estimate: f32) -> LayerFrame {
+        let mut layer = Layer::new(z, n);
+        for x in xs {
+            layer.add_node(
+                NodeValveState::new(GridCoordinate::new(x, 0), vec![ValveState::open(0)]).with_material(0),
+            );
+        }
+        layer.estimated_time = Some(estimate);
+        LayerFrame::from_layer(&layer).unwrap()
+    }
+
+    #[test]
+    fn test_switches_and_anomalies() {
+        let mut limits = AnalysisLimits::default();
+        limits.flow_coefficients.insert(0, 0.5);
+        let analyzer = PerformanceAnalyzer { limits };
+
+        let frames = vec![
+            frame(0, 0.2, 0..10, 1.0),
+            // Shifted by two nodes: 2 close, 2 open
+            frame(1, 0.4, 2..12, 1.0),
+            // Faster than one 10 Hz period, and Z going backwards
+            frame(2, 0.3, 2..12, 0.05),
+        ];
+        let report = analyzer.analyze_frames(&frames);
+
+        assert_eq!(report.layers[0].valve_switches, 10);
+        assert_eq!(report.layers[1].valve_switches, 4);
+        assert_eq!(report.layers[1].channel_open_valves[&0], 10);
+        // 10 nodes × 0.25 mm² × 0.2 mm over 1 s at k = 0.5
+        assert!((report.layers[1].pressure_demand[&0] - 1.0).abs() < 1e-4);
+
+        let kinds: Vec<AnomalyKind> = report.anomalies.iter().map(|a| a.kind).collect();
+        assert_eq!(kinds, vec![AnomalyKind::ZNotIncreasing, AnomalyKind::SwitchingRate]);
+        assert!(report.to_json().unwrap().contains("\"switching_rate\""));
+    }
+}
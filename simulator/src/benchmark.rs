@@ -0,0 +1,412 @@
+//! Valve switching benchmark.
+//!
+//! Generates synthetic stress patterns for the configured valve grid and
+//! pushes them through the firmware's scheduling and valve controller
+//! abstractions:
+//!
+//! - **Schedule rate**: the pattern is compiled by the firmware
+//!   [`CommandScheduler`], which enforces the per-valve minimum switching
+//!   interval on its fixed tick. The resulting cycle rate shows what tick
+//!   quantization leaves of the configured `max_switching_freq`.
+//! - **Controller rate**: the generated frames are latched into a
+//!   [`MemoryValveController`] as fast as possible, measuring the software
+//!   overhead of one full-array update.
+//!
+//! The achievable rate for a pattern is the lower of the two.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+
+use config_types::PrinterConfig;
+use gcode_types::{Command, Coordinate, G4DCommand, GridCoordinate, ValveState};
+use hypergcode_firmware::core::scheduler::{CommandScheduler, SchedulerConfig};
+use hypergcode_firmware::{ValveController, ValveHealth};
+
+/// Synthetic valve pattern.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StressPattern {
+    /// Alternating nodes, inverted every cycle
+    Checkerboard,
+    /// Every node opened and closed together
+    FullPlate,
+    /// A random fraction of nodes flipped each cycle
+    SparseRandom { density: f32, seed: u64 },
+}
+
+impl StressPattern {
+    pub fn name(&self) -> String {
+        match self {
+            StressPattern::Checkerboard => "checkerboard".to_string(),
+            StressPattern::FullPlate => "full-plate".to_string(),
+            StressPattern::SparseRandom { density, .. } => {
+                format!("sparse-random ({:.0}%)", density * 100.0)
+            }
+        }
+    }
+}
+
+/// Benchmark parameters.
+#[derive(Debug, Clone)]
+pub struct BenchmarkConfig {
+    pub grid_width: u32,
+    pub grid_height: u32,
+    pub valves_per_node: u8,
+    /// Pattern cycles generated per run
+    pub cycles: u32,
+    pub patterns: Vec<StressPattern>,
+}
+
+impl BenchmarkConfig {
+    /// Benchmarks the full grid of a printer with the default pattern set.
+    pub fn from_printer(config: &PrinterConfig, cycles: u32, sparse_density: f32) -> Self {
+        Self {
+            grid_width: config.grid_x_count(),
+            grid_height: config.grid_y_count(),
+            valves_per_node: config.valve_array.valves_per_node,
+            cycles,
+            patterns: vec![
+                StressPattern::Checkerboard,
+                StressPattern::FullPlate,
+                StressPattern::SparseRandom {
+                    density: sparse_density,
+                    seed: 0x4847_3444,
+                },
+            ],
+        }
+    }
+
+    pub fn node_count(&self) -> usize {
+        self.grid_width as usize * self.grid_height as usize
+    }
+}
+
+/// Measurements for one pattern.
+#[derive(Debug, Clone)]
+pub struct PatternResult {
+    pub pattern: String,
+    /// Mean nodes updated per cycle
+    pub nodes_per_cycle: f64,
+    /// Time to compile all cycles through the scheduler
+    pub compile_time: Duration,
+    /// Cycles per second permitted by the compiled schedule
+    pub schedule_rate: f64,
+    /// Cycles per second the valve controller accepted
+    pub controller_rate: f64,
+}
+
+impl PatternResult {
+    /// Cycle rate limited by both schedule and controller.
+    pub fn achievable_rate(&self) -> f64 {
+        self.schedule_rate.min(self.controller_rate)
+    }
+}
+
+/// Complete benchmark report.
+#[derive(Debug, Clone)]
+pub struct BenchmarkReport {
+    pub grid_width: u32,
+    pub grid_height: u32,
+    pub cycles: u32,
+    pub tick_interval: Duration,
+    /// Configured valve switching limit (Hz)
+    pub max_switching_freq: f32,
+    pub results: Vec<PatternResult>,
+}
+
+impl BenchmarkReport {
+    /// True if every pattern reaches the configured switching frequency.
+    pub fn meets_target(&self) -> bool {
+        self.results
+            .iter()
+            .all(|r| r.achievable_rate() >= self.max_switching_freq as f64 * TARGET_TOLERANCE)
+    }
+}
+
+/// Fraction of the configured frequency counted as meeting it.
+const TARGET_TOLERANCE: f64 = 0.95;
+
+impl fmt::Display for BenchmarkReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Valve switching benchmark: {}x{} grid, {} cycles, {:?} tick",
+            self.grid_width, self.grid_height, self.cycles, self.tick_interval
+        )?;
+        writeln!(f, "Configured max switching frequency: {:.1} Hz", self.max_switching_freq)?;
+        writeln!(f)?;
+        writeln!(
+            f,
+            "{:<22} {:>10} {:>12} {:>12} {:>14} {:>10}",
+            "pattern", "nodes/cyc", "compile", "schedule Hz", "controller Hz", "result"
+        )?;
+
+        for r in &self.results {
+            let achievable = r.achievable_rate();
+            let verdict = if achievable >= self.max_switching_freq as f64 * TARGET_TOLERANCE {
+                "OK".to_string()
+            } else {
+                format!("{:.0}%", achievable / self.max_switching_freq as f64 * 100.0)
+            };
+            writeln!(
+                f,
+                "{:<22} {:>10.0} {:>10.1}ms {:>12.1} {:>14.1} {:>10}",
+                r.pattern,
+                r.nodes_per_cycle,
+                r.compile_time.as_secs_f64() * 1000.0,
+                r.schedule_rate,
+                r.controller_rate,
+                verdict
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Runs all configured patterns.
+pub async fn run_benchmark(printer: &PrinterConfig, config: &BenchmarkConfig) -> Result<BenchmarkReport> {
+    let scheduler_config = SchedulerConfig::from_valve_array(&printer.valve_array);
+    let scheduler = CommandScheduler::new(scheduler_config);
+    let mut controller = MemoryValveController::new();
+
+    let mut results = Vec::with_capacity(config.patterns.len());
+    for pattern in &config.patterns {
+        let cycles = generate_cycles(*pattern, config);
+        results.push(measure(&scheduler, &mut controller, *pattern, &cycles).await?);
+    }
+
+    Ok(BenchmarkReport {
+        grid_width: config.grid_width,
+        grid_height: config.grid_height,
+        cycles: config.cycles,
+        tick_interval: scheduler_config.tick_interval,
+        max_switching_freq: printer.valve_array.max_switching_freq,
+        results,
+    })
+}
+
+type NodeUpdates = Vec<(GridCoordinate, Vec<ValveState>)>;
+
+async fn measure(
+    scheduler: &CommandScheduler,
+    controller: &mut MemoryValveController,
+    pattern: StressPattern,
+    cycles: &[NodeUpdates],
+) -> Result<PatternResult> {
+    let spacing = scheduler.config().grid_spacing;
+    let commands: Vec<Command> = cycles
+        .iter()
+        .flatten()
+        .map(|(node, valves)| {
+            Command::G4D(G4DCommand {
+                position: Coordinate::new(node.x as f32 * spacing, node.y as f32 * spacing, 0.0),
+                valves: valves.clone(),
+                extrusion: None,
+                close_early_ms: None,
+            })
+        })
+        .collect();
+
+    let started = Instant::now();
+    let compiled = scheduler.compile_layer(&commands)?;
+    let compile_time = started.elapsed();
+
+    // A schedule of N cycles is complete one switching interval after its
+    // last frame, when the pattern could start over
+    let tick = scheduler.config().tick_interval.as_secs_f64();
+    let switch_ticks = scheduler.config().ticks_for(scheduler.config().min_switch_interval).max(1);
+    let last_tick = compiled
+        .segments
+        .iter()
+        .flat_map(|s| s.frames.last())
+        .map(|f| f.tick)
+        .max()
+        .unwrap_or(0);
+    let schedule_rate = cycles.len() as f64 / ((last_tick + switch_ticks) as f64 * tick);
+
+    let started = Instant::now();
+    for updates in cycles {
+        controller.set_valve_states(updates).await?;
+    }
+    let elapsed = started.elapsed().as_secs_f64().max(f64::EPSILON);
+
+    let total_nodes: usize = cycles.iter().map(|c| c.len()).sum();
+    Ok(PatternResult {
+        pattern: pattern.name(),
+        nodes_per_cycle: total_nodes as f64 / cycles.len().max(1) as f64,
+        compile_time,
+        schedule_rate,
+        controller_rate: cycles.len() as f64 / elapsed,
+    })
+}
+
+/// Generates the node updates of every cycle of a pattern.
+pub fn generate_cycles(pattern: StressPattern, config: &BenchmarkConfig) -> Vec<NodeUpdates> {
+    let valves = |open: bool| -> Vec<ValveState> {
+        (0..config.valves_per_node).map(|i| ValveState::new(i, open)).collect()
+    };
+    let nodes = (0..config.grid_height)
+        .flat_map(|y| (0..config.grid_width).map(move |x| GridCoordinate::new(x, y)));
+
+    match pattern {
+        StressPattern::Checkerboard => (0..config.cycles)
+            .map(|cycle| {
+                nodes
+                    .clone()
+                    .map(|n| (n, valves((n.x + n.y + cycle) % 2 == 0)))
+                    .collect()
+            })
+            .collect(),
+        StressPattern::FullPlate => (0..config.cycles)
+            .map(|cycle| nodes.clone().map(|n| (n, valves(cycle % 2 == 0))).collect())
+            .collect(),
+        StressPattern::SparseRandom { density, seed } => {
+            let mut rng = XorShift::new(seed);
+            let mut state = vec![false; config.node_count()];
+            let threshold = (density.clamp(0.0, 1.0) as f64 * u32::MAX as f64) as u32;
+            (0..config.cycles)
+                .map(|_| {
+                    nodes
+                        .clone()
+                        .enumerate()
+                        .filter(|_| rng.next_u32() < threshold)
+                        .map(|(i, n)| {
+                            state[i] = !state[i];
+                            (n, valves(state[i]))
+                        })
+                        .collect()
+                })
+                .collect()
+        }
+    }
+}
+
+/// Deterministic xorshift64* generator so runs are reproducible.
+struct XorShift(u64);
+
+impl XorShift {
+    fn new(seed: u64) -> Self {
+        Self(seed.max(1))
+    }
+
+    fn next_u32(&mut self) -> u32 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        (self.0.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 32) as u32
+    }
+}
+
+/// Valve controller holding states in memory.
+#[derive(Debug, Default)]
+pub struct MemoryValveController {
+    states: HashMap<GridCoordinate, Vec<ValveState>>,
+    switch_count: u64,
+}
+
+impl MemoryValveController {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of valve state changes applied.
+    pub fn switch_count(&self) -> u64 {
+        self.switch_count
+    }
+}
+
+#[async_trait::async_trait]
+impl ValveController for MemoryValveController {
+    async fn set_valve_states(&mut self, states: &[(GridCoordinate, Vec<ValveState>)]) -> Result<()> {
+        for (node, valves) in states {
+            let current = self.states.entry(*node).or_default();
+            for valve in valves {
+                match current.iter_mut().find(|v| v.index == valve.index) {
+                    Some(existing) if existing.open != valve.open => {
+                        existing.open = valve.open;
+                        self.switch_count += 1;
+                    }
+                    Some(_) => {}
+                    None => {
+                        current.push(*valve);
+                        self.switch_count += 1;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn get_valve_states(&self, position: GridCoordinate) -> Result<Vec<ValveState>> {
+        Ok(self.states.get(&position).cloned().unwrap_or_default())
+    }
+
+    async fn health_check(&mut self) -> Result<Vec<ValveHealth>> {
+        Ok(Vec::new())
+    }
+
+    async fn emergency_close_all(&mut self) -> Result<()> {
+        for valves in self.states.values_mut() {
+            for valve in valves.iter_mut() {
+                valve.open = false;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(pattern: StressPattern) -> BenchmarkConfig {
+        BenchmarkConfig {
+            grid_width: 8,
+            grid_height: 4,
+            valves_per_node: 2,
+            cycles: 6,
+            patterns: vec![pattern],
+        }
+    }
+
+    #[test]
+    fn test_pattern_generation() {
+        let checker = generate_cycles(StressPattern::Checkerboard, &config(StressPattern::Checkerboard));
+        assert_eq!(checker.len(), 6);
+        assert_eq!(checker[0].len(), 32);
+        assert!(checker[0][0].1[0].open);
+        assert!(!checker[1][0].1[0].open);
+        assert!(!checker[0][1].1[0].open);
+
+        let pattern = StressPattern::SparseRandom { density: 0.25, seed: 7 };
+        let sparse = generate_cycles(pattern, &config(pattern));
+        let mean = sparse.iter().map(|c| c.len()).sum::<usize>() as f32 / 6.0;
+        assert!(mean > 2.0 && mean < 20.0, "mean {}", mean);
+        assert_eq!(sparse[0], generate_cycles(pattern, &config(pattern))[0]);
+    }
+
+    #[tokio::test]
+    async fn test_schedule_rate_is_tick_quantized() {
+        // 30 Hz -> 33.3ms interval, rounded up to 34 ticks of 1ms
+        let scheduler = CommandScheduler::new(SchedulerConfig {
+            grid_spacing: 1.0,
+            tick_interval: Duration::from_millis(1),
+            response_time: Duration::from_millis(5),
+            min_switch_interval: Duration::from_secs_f32(1.0 / 30.0),
+            merge_window: Duration::ZERO,
+        });
+        let cfg = config(StressPattern::FullPlate);
+        let cycles = generate_cycles(StressPattern::FullPlate, &cfg);
+        let mut controller = MemoryValveController::new();
+
+        let result = measure(&scheduler, &mut controller, StressPattern::FullPlate, &cycles)
+            .await
+            .unwrap();
+        assert!((result.schedule_rate - 1000.0 / 34.0).abs() < 0.01);
+        assert_eq!(controller.switch_count(), 6 * 32 * 2);
+        assert!(result.controller_rate > 0.0);
+    }
+}
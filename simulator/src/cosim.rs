@@ -0,0 +1,178 @@
+//! Co-simulation: the physics engine as the slicer's pressure simulator.
+//!
+//! [`CoSimulator`] implements the slicer's
+//! [`PressureSimulator`](hypergcode_slicer::PressureSimulator) with the
+//! [`FlowModel`](crate::physics::FlowModel), so layers and the candidate
+//! activation groups the pressure gate tries are evaluated on the full
+//! valve network rather than the slicer's internal model. Attach it with
+//! [`CoSimulator::attach`] and set the slicer's `pressure_fidelity` to
+//! `CoSimulation`; the slower solve buys fewer false passes on large
+//! layers.
+//!
+//! Each routing path's start is a source at the supply pressure, its
+//! intermediate nodes are conduits, and every active node is a sink
+//! weighted by its extrusion fraction.
+
+use std::collections::{HashMap, HashSet};
+
+use anyhow::Result;
+
+use config_types::PrinterConfig;
+use gcode_types::GridCoordinate;
+use hypergcode_slicer::pressure::find_pressure_issues;
+use hypergcode_slicer::{
+    OptimizedRouting, PressureConfig, PressureSimulation, PressureSimulator, PressureValidation, Slicer,
+};
+
+use crate::physics::{FlowModel, FlowProperties, PhysicsEngine};
+
+/// Outlet conductance when the printer has no pressure channel to derive
+/// it from (mm³/s per PSI).
+pub const DEFAULT_OUTLET_CONDUCTANCE: f32 = 0.05;
+
+/// Physics engine exposed through the slicer's simulator interface.
+pub struct CoSimulator {
+    engine: PhysicsEngine,
+    node_spacing: f32,
+    outlet_conductance: f32,
+    min_pressure: f32,
+    max_pressure: f32,
+}
+
+impl CoSimulator {
+    /// Takes grid spacing and pressure limits from the printer. Outlet
+    /// conductance is the first channel's flow coefficient linearized at
+    /// the middle of the operating range.
+    pub fn new(engine: PhysicsEngine, printer: &PrinterConfig) -> Self {
+        let pressure = &printer.materials.pressure;
+        let midpoint = (pressure.min_pressure + pressure.max_pressure) / 2.0;
+        let outlet_conductance = match pressure.channels.first() {
+            Some(channel) if midpoint > 0.0 => channel.flow_coefficient / midpoint.sqrt(),
+            _ => DEFAULT_OUTLET_CONDUCTANCE,
+        };
+        Self {
+            engine,
+            node_spacing: printer.valve_array.grid_spacing,
+            outlet_conductance,
+            min_pressure: pressure.min_pressure,
+            max_pressure: pressure.max_pressure,
+        }
+    }
+
+    /// Registers this simulator with `slicer`. Its configuration must ask
+    /// for `PressureFidelity::CoSimulation` for it to be consulted.
+    pub fn attach(self, slicer: &mut Slicer) {
+        slicer.set_co_simulator(Box::new(self));
+    }
+
+    fn model(&self, config: &PressureConfig) -> FlowModel {
+        // The engine's own model, if set, keeps its tolerances
+        let properties = FlowProperties {
+            viscosity: config.material_viscosity,
+            channel_diameter: config.channel_diameter,
+            node_spacing: self.node_spacing,
+            outlet_conductance: self.outlet_conductance,
+        };
+        match self.engine.flow() {
+            Some(model) => model.clone().with_properties(properties),
+            None => FlowModel::new(properties),
+        }
+    }
+}
+
+impl PressureSimulator for CoSimulator {
+    fn simulate(&self, routing: &OptimizedRouting, config: &PressureConfig) -> Result<PressureSimulation> {
+        let sources: Vec<(GridCoordinate, f32)> = routing
+            .routing_paths
+            .iter()
+            .map(|path| path.from)
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .map(|node| (node, config.supply_pressure))
+            .collect();
+        let sinks: Vec<(GridCoordinate, f32)> = routing
+            .activation_map
+            .active_nodes
+            .iter()
+            .map(|node| (node.position, node.extrusion.unwrap_or(1.0)))
+            .collect();
+        let conduits: HashSet<GridCoordinate> = routing
+            .routing_paths
+            .iter()
+            .flat_map(|path| path.intermediate_nodes.iter().copied())
+            .collect();
+
+        let solution = self.model(config).solve(&sources, &sinks, &conduits);
+        let node_pressures: HashMap<GridCoordinate, f32> = sinks
+            .iter()
+            .map(|(node, _)| (*node, solution.pressures.get(node).copied().unwrap_or(0.0)))
+            .collect();
+        let max_pressure = node_pressures.values().copied().fold(0.0, f32::max);
+        let min_pressure = node_pressures.values().copied().fold(f32::INFINITY, f32::min);
+        Ok(PressureSimulation {
+            flow_rates: solution.deliveries,
+            max_pressure,
+            min_pressure: if min_pressure.is_finite() { min_pressure } else { 0.0 },
+            pressure_stable: solution.converged,
+            node_pressures,
+        })
+    }
+
+    fn validate_pressures(
+        &self,
+        routing: &OptimizedRouting,
+        simulation: &PressureSimulation,
+    ) -> Result<PressureValidation> {
+        Ok(find_pressure_issues(routing, simulation, self.min_pressure, self.max_pressure))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hypergcode_slicer::{ActiveNode, RoutingPath, ValveActivationMap};
+
+    #[test]
+    fn test_far_nodes_starve() {
+        let simulator = CoSimulator {
+            engine: PhysicsEngine::new(0.001),
+            node_spacing: 0.5,
+            outlet_conductance: 0.2,
+            min_pressure: 40.0,
+            max_pressure: 120.0,
+        };
+        let node = |x| GridCoordinate::new(x, 0);
+        let active: Vec<ActiveNode> = (1..30)
+            .map(|x| ActiveNode {
+                position: node(x),
+                material_channel: 0,
+                required_valves: vec![0],
+                object_id: None,
+                extrusion: None,
+            })
+            .collect();
+        let routing = OptimizedRouting {
+            activation_map: ValveActivationMap { layer_number: 0, z_height: 0.2, active_nodes: active },
+            routing_paths: vec![RoutingPath {
+                from: node(0),
+                to: node(29),
+                intermediate_nodes: Vec::new(),
+                valve_sequence: Vec::new(),
+            }],
+            estimated_pressure: HashMap::new(),
+            efficiency: 1.0,
+        };
+        let config = PressureConfig { supply_pressure: 100.0, material_viscosity: 1000.0, channel_diameter: 1.0 };
+
+        let simulation = simulator.simulate(&routing, &config).unwrap();
+        assert!(simulation.pressure_stable);
+        assert!(simulation.node_pressures[&node(1)] > simulation.node_pressures[&node(29)]);
+        assert!(simulation.max_pressure < 100.0);
+
+        let validation = simulator.validate_pressures(&routing, &simulation).unwrap();
+        assert!(!validation.is_ok());
+        assert_eq!(validation.issues.last().unwrap().position, node(29));
+        // The path ends at the last node
+        assert_eq!(validation.issues.last().unwrap().paths, vec![0]);
+    }
+}
@@ -6,8 +6,22 @@
 //!
 //! The simulator consists of three main subsystems:
 //! - **Physics**: Simulates material flow, pressure, and thermal dynamics
-//! - **Visualization**: Renders valve patterns and material deposition
+//! - **Visualization**: Renders material deposition as voxels over simulated
+//!   time, with valve and pressure overlays (interactive window with the
+//!   `window` feature)
 //! - **Analysis**: Analyzes performance and validates G-code
+//!
+//! The **benchmark** module stress-tests valve switching through the firmware
+//! scheduler and valve controller abstractions.
+//!
+//! The **replay** module plays back protocol traces recorded by the firmware
+//! and compares their thermal and pressure readings with model predictions.
+//!
+//! The **acceptance** module runs sample models end to end, slicer to
+//! firmware parser to physics, and checks the output's invariants.
+//!
+//! The **cosim** module lends the physics engine's flow model to the
+//! slicer as a higher-fidelity pressure simulator.
 
 use std::path::Path;
 use anyhow::Result;
@@ -15,10 +29,18 @@ use anyhow::Result;
 pub mod physics;
 pub mod visualization;
 pub mod analysis;
+pub mod benchmark;
+pub mod replay;
+pub mod acceptance;
+pub mod cosim;
 
 pub use physics::PhysicsEngine;
 pub use visualization::Visualizer;
-pub use analysis::PerformanceAnalyzer;
+pub use analysis::{AnalysisReport, Anomaly, AnomalyKind, LayerStats, PerformanceAnalyzer};
+pub use benchmark::{run_benchmark, BenchmarkConfig, BenchmarkReport, StressPattern};
+pub use replay::{diff_channels, FirstOrderPredictor, TracePlayer};
+pub use acceptance::{standard_cases, AcceptanceCase, AcceptanceHarness, CaseReport, SampleModel};
+pub use cosim::CoSimulator;
 
 // Shared Type Definitions
 
@@ -89,6 +111,12 @@ impl Simulation {
         todo!("Implementation needed: Advance physics, update visualization")
     }
 
+    /// Hands over the visualizer, e.g. to open its window once the
+    /// simulation has run.
+    pub fn take_visualizer(&mut self) -> Option<Visualizer> {
+        self.visualizer.take()
+    }
+
     /// Runs simulation until completion.
     pub async fn run(&mut self) -> Result<SimulationResults> {
         todo!("Implementation needed: Run simulation loop")
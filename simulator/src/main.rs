@@ -7,7 +7,12 @@ use clap::{Parser, Subcommand};
 use hypergcode_simulator::{
     Simulation, SimulationConfig,
     PhysicsEngine, Visualizer, PerformanceAnalyzer,
+    BenchmarkConfig, run_benchmark,
+    TracePlayer, FirstOrderPredictor, diff_channels,
+    AcceptanceHarness, standard_cases,
 };
+use protocol::Trace;
+use config_types::PrinterConfig;
 
 #[derive(Parser)]
 #[command(name = "hg4d-simulator")]
@@ -17,7 +22,8 @@ struct Cli {
     #[arg(short, long)]
     file: Option<PathBuf>,
 
-    /// Enable 3D visualization
+    /// Show the simulated print as voxels in a window (requires the
+    /// `window` feature)
     #[arg(short, long)]
     visualize: bool,
 
@@ -43,9 +49,65 @@ enum SimCommands {
     Analyze {
         #[arg(value_name = "FILE")]
         file: PathBuf,
+
+        /// Printer configuration providing switching and pressure limits
+        #[arg(short, long, default_value = "printer.toml")]
+        config: PathBuf,
+
+        /// Also write the report as JSON to this path
+        #[arg(long, value_name = "PATH")]
+        json: Option<PathBuf>,
     },
     /// Benchmark valve switching performance
-    Benchmark,
+    Benchmark {
+        /// Printer configuration providing grid size and switching limits
+        #[arg(short, long, default_value = "printer.toml")]
+        config: PathBuf,
+
+        /// Pattern cycles per run
+        #[arg(long, default_value = "20")]
+        cycles: u32,
+
+        /// Fraction of nodes flipped per cycle in the sparse pattern
+        #[arg(long, default_value = "0.05")]
+        density: f32,
+    },
+    /// Replay a protocol trace recorded by the firmware
+    Replay {
+        #[arg(value_name = "TRACE")]
+        file: PathBuf,
+
+        /// Playback speed multiplier
+        #[arg(long, default_value = "1.0")]
+        speed: f32,
+
+        /// Start at this offset (seconds)
+        #[arg(long)]
+        seek: Option<f64>,
+
+        /// Wait for Enter before each message instead of using recorded timing
+        #[arg(long)]
+        step: bool,
+
+        /// Compare thermal and pressure readings with model predictions
+        /// instead of replaying
+        #[arg(long)]
+        diff: bool,
+    },
+    /// Slice, parse and simulate the sample models, checking the output
+    Acceptance {
+        /// Directory for the sliced files
+        #[arg(long, default_value = "acceptance-out")]
+        work_dir: PathBuf,
+
+        /// Keep the sliced files
+        #[arg(long)]
+        keep: bool,
+
+        /// Only run cases whose name contains this
+        #[arg(long)]
+        filter: Option<String>,
+    },
     /// Validate G-code file
     Validate {
         #[arg(value_name = "FILE")]
@@ -87,7 +149,10 @@ async fn main() -> anyhow::Result<()> {
         println!("  Total time: {:.2}s", results.total_time);
         println!("  Material deposited: {:.2}mm³", results.material_deposited);
         println!("  Valve operations: {}", results.valve_operations);
-        
+
+        if let Some(visualizer) = simulation.take_visualizer() {
+            show(visualizer)?;
+        }
     } else {
         anyhow::bail!("Must specify --file or --virtual-printer");
     }
@@ -97,16 +162,86 @@ async fn main() -> anyhow::Result<()> {
 
 async fn handle_subcommand(command: SimCommands) -> anyhow::Result<()> {
     match command {
-        SimCommands::Analyze { file } => {
+        SimCommands::Analyze { file, config, json } => {
             println!("Analyzing {}...", file.display());
-            // Create analyzer and analyze file
-            let analyzer = PerformanceAnalyzer::new();
-            // TODO: Load file and analyze
-            println!("Analysis complete");
+            let printer = PrinterConfig::from_file(&config)?;
+            let analyzer = PerformanceAnalyzer::for_printer(&printer);
+            let report = analyzer.analyze_file(&file).await?;
+            println!("\n{}", report);
+            if let Some(path) = json {
+                std::fs::write(&path, report.to_json()?)?;
+                println!("Report written to {}", path.display());
+            }
+        }
+        SimCommands::Benchmark { config, cycles, density } => {
+            let printer = PrinterConfig::from_file(&config)?;
+            let bench = BenchmarkConfig::from_printer(&printer, cycles, density);
+            println!("Running benchmark on {} nodes...", bench.node_count());
+
+            let report = run_benchmark(&printer, &bench).await?;
+            println!("\n{}", report);
+            if !report.meets_target() {
+                println!(
+                    "Warning: not all patterns reach the configured {:.1} Hz",
+                    report.max_switching_freq
+                );
+            }
+        }
+        SimCommands::Replay { file, speed, seek, step, diff } => {
+            let trace = Trace::from_file(&file)?;
+            println!(
+                "Trace from {}: {} messages over {:.1}s",
+                trace.header.source,
+                trace.entries.len(),
+                trace.duration_ms() as f64 / 1000.0
+            );
+
+            if diff {
+                for channel in diff_channels(&trace, &FirstOrderPredictor::default()) {
+                    println!("  {}", channel);
+                }
+                return Ok(());
+            }
+
+            let mut player = TracePlayer::new(trace);
+            if let Some(seconds) = seek {
+                player.seek((seconds.max(0.0) * 1000.0) as u64);
+            }
+            if step {
+                let stdin = std::io::stdin();
+                let mut line = String::new();
+                while let Some(entry) = player.step() {
+                    print_entry(entry);
+                    line.clear();
+                    if stdin.read_line(&mut line)? == 0 {
+                        break;
+                    }
+                }
+            } else {
+                player.play(speed, |entry| {
+                    print_entry(entry);
+                    true
+                }).await;
+            }
         }
-        SimCommands::Benchmark => {
-            println!("Running benchmark...");
-            // TODO: Run benchmark suite
+        SimCommands::Acceptance { work_dir, keep, filter } => {
+            let cases: Vec<_> = standard_cases()
+                .into_iter()
+                .filter(|c| match &filter {
+                    Some(f) => c.name.contains(f.as_str()),
+                    None => true,
+                })
+                .collect();
+            println!("Running {} acceptance cases in {}...", cases.len(), work_dir.display());
+            let harness = AcceptanceHarness::new(work_dir).keep_outputs(keep);
+            let reports = harness.run_all(&cases).await?;
+            for report in &reports {
+                print!("{}", report);
+            }
+            let failed = reports.iter().filter(|r| !r.passed()).count();
+            if failed > 0 {
+                anyhow::bail!("{} of {} acceptance cases failed", failed, reports.len());
+            }
         }
         SimCommands::Validate { file } => {
             println!("Validating {}...", file.display());
@@ -117,6 +252,25 @@ async fn handle_subcommand(command: SimCommands) -> anyhow::Result<()> {
     Ok(())
 }
 
+fn print_entry(entry: &protocol::TraceEntry) {
+    println!(
+        "[{:>9.3}s] {:?} {:?}",
+        entry.offset_ms as f64 / 1000.0,
+        entry.direction,
+        entry.message
+    );
+}
+
+#[cfg(feature = "window")]
+fn show(visualizer: Visualizer) -> anyhow::Result<()> {
+    visualizer.run_window()
+}
+
+#[cfg(not(feature = "window"))]
+fn show(_visualizer: Visualizer) -> anyhow::Result<()> {
+    anyhow::bail!("Visualization window not compiled in. Rebuild with --features window")
+}
+
 async fn run_virtual_printer(port: u16, config: SimulationConfig) -> anyhow::Result<()> {
     todo!("Implementation needed: Virtual printer server")
 }
@@ -0,0 +1,234 @@
+//! Steady-state flow through the valve network.
+//!
+//! Open nodes and the nodes material passes through form a resistor
+//! network on the grid: each pair of adjacent nodes is joined by a channel
+//! segment with Hagen-Poiseuille resistance, sources (injection points)
+//! hold the supply pressure, and each depositing node (sink) vents to the
+//! build plate through an outlet whose conductance scales with its
+//! extrusion fraction. Pressures are solved by successive over-relaxation;
+//! nodes with no path to a source read 0 PSI.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use gcode_types::GridCoordinate;
+
+/// Pascals per PSI.
+const PA_PER_PSI: f64 = 6894.757;
+
+/// Successive over-relaxation factor.
+const RELAXATION: f64 = 1.6;
+
+/// Material and channel properties of the network.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FlowProperties {
+    /// Material viscosity (Pa·s)
+    pub viscosity: f32,
+    /// Bore of the channel between adjacent nodes (mm)
+    pub channel_diameter: f32,
+    /// Distance between adjacent nodes (mm)
+    pub node_spacing: f32,
+    /// Flow out of a fully open node per PSI at the node (mm³/s per PSI)
+    pub outlet_conductance: f32,
+}
+
+impl FlowProperties {
+    /// Pressure drop along one node-to-node segment per unit flow
+    /// (PSI per mm³/s).
+    pub fn segment_resistance(&self) -> f32 {
+        let viscosity = self.viscosity as f64;
+        let length = self.node_spacing as f64 * 1e-3;
+        let diameter = self.channel_diameter as f64 * 1e-3;
+        // Pa·s/m³, then per mm³/s and in PSI
+        let resistance = 128.0 * viscosity * length / (std::f64::consts::PI * diameter.powi(4));
+        (resistance * 1e-9 / PA_PER_PSI) as f32
+    }
+}
+
+/// Solved network state.
+#[derive(Debug, Clone, Default)]
+pub struct FlowSolution {
+    /// Pressure at every node (PSI)
+    pub pressures: HashMap<GridCoordinate, f32>,
+    /// Flow leaving each sink (mm³/s)
+    pub deliveries: HashMap<GridCoordinate, f32>,
+    /// Whether the solve met its tolerance within the iteration limit
+    pub converged: bool,
+    pub iterations: u32,
+}
+
+/// Solves pressures in the valve network.
+#[derive(Debug, Clone)]
+pub struct FlowModel {
+    properties: FlowProperties,
+    /// Largest pressure change at convergence (PSI)
+    tolerance: f32,
+    max_iterations: u32,
+}
+
+impl FlowModel {
+    pub fn new(properties: FlowProperties) -> Self {
+        Self {
+            properties,
+            tolerance: 1e-3,
+            max_iterations: 20_000,
+        }
+    }
+
+    pub fn with_tolerance(mut self, tolerance: f32, max_iterations: u32) -> Self {
+        self.tolerance = tolerance;
+        self.max_iterations = max_iterations;
+        self
+    }
+
+    /// Same solver settings on a different network.
+    pub fn with_properties(mut self, properties: FlowProperties) -> Self {
+        self.properties = properties;
+        self
+    }
+
+    pub fn properties(&self) -> &FlowProperties {
+        &self.properties
+    }
+
+    /// Solves the network formed by `sources` (node, supply PSI), `sinks`
+    /// (node, extrusion fraction) and the `conduits` material passes
+    /// through without depositing.
+    pub fn solve(
+        &self,
+        sources: &[(GridCoordinate, f32)],
+        sinks: &[(GridCoordinate, f32)],
+        conduits: &HashSet<GridCoordinate>,
+    ) -> FlowSolution {
+        let fixed: HashMap<GridCoordinate, f64> = sources.iter().map(|&(n, p)| (n, p as f64)).collect();
+        let outlets: HashMap<GridCoordinate, f64> = sinks
+            .iter()
+            .filter(|(n, _)| !fixed.contains_key(n))
+            .map(|&(n, fraction)| (n, (self.properties.outlet_conductance * fraction.max(0.0)) as f64))
+            .collect();
+
+        let mut nodes: Vec<GridCoordinate> = fixed
+            .keys()
+            .chain(outlets.keys())
+            .chain(conduits.iter())
+            .copied()
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect();
+        nodes.sort_by_key(|n| (n.y, n.x));
+        let index: HashMap<GridCoordinate, usize> = nodes.iter().enumerate().map(|(i, n)| (*n, i)).collect();
+        let neighbours: Vec<Vec<usize>> = nodes
+            .iter()
+            .map(|n| adjacent(*n).filter_map(|a| index.get(&a).copied()).collect())
+            .collect();
+
+        // Nodes cut off from every source stay at 0
+        let mut reachable = vec![false; nodes.len()];
+        let mut queue: VecDeque<usize> = nodes
+            .iter()
+            .enumerate()
+            .filter(|(_, n)| fixed.contains_key(n))
+            .map(|(i, _)| i)
+            .collect();
+        for &i in &queue {
+            reachable[i] = true;
+        }
+        while let Some(i) = queue.pop_front() {
+            for &j in &neighbours[i] {
+                if !reachable[j] {
+                    reachable[j] = true;
+                    queue.push_back(j);
+                }
+            }
+        }
+
+        let conductance = 1.0 / self.properties.segment_resistance().max(f32::MIN_POSITIVE) as f64;
+        let supply = fixed.values().copied().fold(0.0, f64::max);
+        let mut pressure: Vec<f64> = nodes
+            .iter()
+            .zip(&reachable)
+            .map(|(n, &r)| match fixed.get(n) {
+                Some(&p) => p,
+                None if r => supply,
+                None => 0.0,
+            })
+            .collect();
+
+        let mut solution = FlowSolution::default();
+        let free: Vec<usize> = (0..nodes.len())
+            .filter(|&i| reachable[i] && !fixed.contains_key(&nodes[i]))
+            .collect();
+        while solution.iterations < self.max_iterations {
+            solution.iterations += 1;
+            let mut largest_change = 0.0f64;
+            for &i in &free {
+                let inflow: f64 = neighbours[i].iter().map(|&j| pressure[j]).sum::<f64>() * conductance;
+                let total = neighbours[i].len() as f64 * conductance + outlets.get(&nodes[i]).copied().unwrap_or(0.0);
+                if total <= 0.0 {
+                    continue;
+                }
+                let target = inflow / total;
+                let updated = pressure[i] + RELAXATION * (target - pressure[i]);
+                largest_change = largest_change.max((updated - pressure[i]).abs());
+                pressure[i] = updated;
+            }
+            if largest_change < self.tolerance as f64 {
+                solution.converged = true;
+                break;
+            }
+        }
+
+        for (i, node) in nodes.iter().enumerate() {
+            let p = pressure[i].max(0.0);
+            solution.pressures.insert(*node, p as f32);
+            if let Some(g) = outlets.get(node) {
+                solution.deliveries.insert(*node, (g * p) as f32);
+            }
+        }
+        solution
+    }
+}
+
+fn adjacent(node: GridCoordinate) -> impl Iterator<Item = GridCoordinate> {
+    let (x, y) = (node.x, node.y);
+    [
+        x.checked_sub(1).map(|x| GridCoordinate::new(x, y)),
+        x.checked_add(1).map(|x| GridCoordinate::new(x, y)),
+        y.checked_sub(1).map(|y| GridCoordinate::new(x, y)),
+        y.checked_add(1).map(|y| GridCoordinate::new(x, y)),
+    ]
+    .into_iter()
+    .flatten()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pressure_falls_along_a_channel() {
+        let model = FlowModel::new(FlowProperties {
+            viscosity: 1000.0,
+            channel_diameter: 1.0,
+            node_spacing: 0.5,
+            outlet_conductance: 0.2,
+        });
+        let resistance = model.properties().segment_resistance();
+        assert!(resistance > 2.0 && resistance < 4.0, "resistance {}", resistance);
+
+        let node = |x| GridCoordinate::new(x, 0);
+        let conduits: HashSet<GridCoordinate> = (1..4).map(node).collect();
+        let sinks = [(node(4), 1.0), (node(5), 1.0), (GridCoordinate::new(20, 20), 1.0)];
+        let solution = model.solve(&[(node(0), 100.0)], &sinks, &conduits);
+
+        assert!(solution.converged);
+        let p = |x| solution.pressures[&node(x)];
+        assert_eq!(p(0), 100.0);
+        assert!((1..6).all(|x| p(x) < p(x - 1)));
+        assert_eq!(solution.pressures[&GridCoordinate::new(20, 20)], 0.0);
+
+        // Everything leaving the sinks passes the first segment
+        let delivered: f32 = solution.deliveries.values().sum();
+        let fed = (p(0) - p(1)) / resistance;
+        assert!((delivered - fed).abs() < 0.01 * fed, "{} vs {}", delivered, fed);
+    }
+}
@@ -0,0 +1,93 @@
+//! # Physics Simulation
+//!
+//! Models the physical processes behind a print so analyses can be checked
+//! without hardware.
+//!
+//! ## Module Organization
+//!
+//! - **thermal**: Cooling of each deposited layer on a coarse grid
+//! - **flow**: Steady-state pressures in the valve network
+
+pub mod thermal;
+pub mod flow;
+
+use std::collections::HashSet;
+
+use anyhow::{anyhow, Result};
+
+pub use thermal::{HotRegion, LayerThermalReport, ThermalModel, ThermalProperties};
+pub use flow::{FlowModel, FlowProperties, FlowSolution};
+
+use gcode_types::GridCoordinate;
+
+/// Steps the physical models of a simulation.
+pub struct PhysicsEngine {
+    /// Largest integration step (seconds)
+    time_step: f32,
+    thermal: Option<ThermalModel>,
+    flow: Option<FlowModel>,
+}
+
+impl PhysicsEngine {
+    pub fn new(time_step: f32) -> Self {
+        Self {
+            time_step,
+            thermal: None,
+            flow: None,
+        }
+    }
+
+    pub fn time_step(&self) -> f32 {
+        self.time_step
+    }
+
+    /// Enables layer cooling simulation.
+    pub fn with_thermal(mut self, model: ThermalModel) -> Self {
+        self.thermal = Some(model);
+        self
+    }
+
+    pub fn thermal(&self) -> Option<&ThermalModel> {
+        self.thermal.as_ref()
+    }
+
+    /// Enables valve network flow simulation.
+    pub fn with_flow(mut self, model: FlowModel) -> Self {
+        self.flow = Some(model);
+        self
+    }
+
+    pub fn flow(&self) -> Option<&FlowModel> {
+        self.flow.as_ref()
+    }
+
+    /// Solves network pressures for one set of open valves; see
+    /// [`FlowModel::solve`].
+    pub fn simulate_flow(
+        &self,
+        sources: &[(GridCoordinate, f32)],
+        sinks: &[(GridCoordinate, f32)],
+        conduits: &HashSet<GridCoordinate>,
+    ) -> Result<FlowSolution> {
+        let flow = self.flow.as_ref().ok_or_else(|| anyhow!("No flow model configured"))?;
+        Ok(flow.solve(sources, sinks, conduits))
+    }
+
+    /// Deposits one layer and lets it cool until the next layer starts.
+    ///
+    /// `occupied` marks the grid cells holding material (row-major), and
+    /// `layer_time` is the time (seconds) until the next layer deposits.
+    pub fn simulate_layer_cooling(
+        &mut self,
+        occupied: &[bool],
+        layer_time: f32,
+        fan_speed: f32,
+    ) -> Result<LayerThermalReport> {
+        let time_step = self.time_step;
+        let thermal = self
+            .thermal
+            .as_mut()
+            .ok_or_else(|| anyhow!("No thermal model configured"))?;
+        thermal.deposit_layer(occupied, layer_time, fan_speed, time_step)
+    }
+}
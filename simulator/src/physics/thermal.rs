@@ -0,0 +1,392 @@
+//! Thermal diffusion across deposited layers.
+//!
+//! Each layer is modelled as a single sheet of cells on a coarse grid. A
+//! freshly deposited cell starts at the deposition temperature and exchanges
+//! heat through three paths, per unit area:
+//!
+//! ```text
+//! ρ·c·h · dT/dt = k·h·∇²T                 lateral conduction (occupied neighbours)
+//!               + G_down·(T_below − T)    conduction into the layer below or bed
+//!               + h_conv·(T_ambient − T)  convection from the top surface
+//! ```
+//!
+//! `G_down` is the bed contact conductance for the first layer and `k / h`
+//! between layers; cells over empty space (overhangs) convect from both
+//! faces instead. `h_conv` grows linearly with fan speed.
+//!
+//! The layer below is held at the temperatures it reached when it was
+//! covered, a deliberate simplification that keeps each layer a 2D problem.
+//! Integration is explicit Euler with the step limited for stability.
+//!
+//! After `layer_time` the report lists the connected regions still above the
+//! glass transition temperature: the next layer lands on soft material
+//! there, which is what minimum layer time is meant to prevent.
+
+use anyhow::{bail, Result};
+use tracing::{debug, warn};
+
+use config_types::{MaterialProfile, MaterialType};
+
+/// Fraction of the stability limit used as the integration step.
+const STABILITY_FACTOR: f32 = 0.2;
+
+/// Thermal parameters of the material and its surroundings.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ThermalProperties {
+    /// Material temperature as deposited (°C)
+    pub deposition_temp: f32,
+    /// Glass transition temperature (°C)
+    pub glass_transition: f32,
+    /// Density (kg/m³)
+    pub density: f32,
+    /// Specific heat capacity (J/kg·K)
+    pub specific_heat: f32,
+    /// Thermal conductivity (W/m·K)
+    pub conductivity: f32,
+    /// Build plate temperature (°C)
+    pub bed_temp: f32,
+    /// Chamber/ambient air temperature (°C)
+    pub ambient_temp: f32,
+    /// Heat transfer from the first layer into the bed (W/m²·K)
+    pub bed_conductance: f32,
+    /// Convection coefficient in still air (W/m²·K)
+    pub convection_still: f32,
+    /// Additional convection with the part fan at 100% (W/m²·K)
+    pub convection_fan: f32,
+}
+
+impl ThermalProperties {
+    /// Derives properties from a material profile, printing in still air at
+    /// 25 °C.
+    pub fn from_material(profile: &MaterialProfile) -> Self {
+        Self {
+            deposition_temp: profile.optimal_temp,
+            glass_transition: profile.properties.glass_transition_temp,
+            density: profile.properties.density * 1000.0,
+            specific_heat: specific_heat(profile.material_type),
+            conductivity: profile.properties.thermal_conductivity,
+            bed_temp: profile.bed_temp,
+            ambient_temp: 25.0,
+            bed_conductance: 500.0,
+            convection_still: 10.0,
+            convection_fan: 90.0,
+        }
+    }
+
+    fn convection(&self, fan_speed: f32) -> f32 {
+        self.convection_still + self.convection_fan * (fan_speed / 100.0).clamp(0.0, 1.0)
+    }
+}
+
+/// Typical specific heat of solid polymer (J/kg·K).
+fn specific_heat(material: MaterialType) -> f32 {
+    match material {
+        MaterialType::PLA | MaterialType::CompositePLA => 1800.0,
+        MaterialType::PETG => 1200.0,
+        MaterialType::ABS | MaterialType::ASA | MaterialType::HIPS => 1400.0,
+        MaterialType::TPU => 1800.0,
+        MaterialType::Nylon => 1700.0,
+        MaterialType::PC => 1200.0,
+        MaterialType::PVA => 1600.0,
+        _ => 1500.0,
+    }
+}
+
+/// Connected area still above the glass transition when the next layer
+/// deposits.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HotRegion {
+    /// Bounds in mm: (min_x, min_y, max_x, max_y)
+    pub bounds: (f32, f32, f32, f32),
+    /// Number of grid cells in the region
+    pub cells: usize,
+    /// Hottest cell (°C)
+    pub peak_temp: f32,
+}
+
+/// Cooling result for one layer.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LayerThermalReport {
+    pub layer: u32,
+    /// Time the layer had to cool (seconds)
+    pub layer_time: f32,
+    /// Hottest deposited cell when the next layer starts (°C)
+    pub peak_temp: f32,
+    /// Mean temperature of deposited cells when the next layer starts (°C)
+    pub mean_temp: f32,
+    pub hot_regions: Vec<HotRegion>,
+}
+
+impl LayerThermalReport {
+    /// Whether the next layer would land on material above glass transition.
+    pub fn is_too_hot(&self) -> bool {
+        !self.hot_regions.is_empty()
+    }
+}
+
+/// Layer-by-layer cooling model on a fixed grid.
+pub struct ThermalModel {
+    properties: ThermalProperties,
+    width: usize,
+    height: usize,
+    /// Cell edge length (mm)
+    cell_size: f32,
+    /// Layer thickness (mm)
+    layer_height: f32,
+    /// Temperature of the layer beneath each cell, `None` where empty
+    below: Option<Vec<Option<f32>>>,
+    layer: u32,
+}
+
+impl ThermalModel {
+    pub fn new(properties: ThermalProperties, width: usize, height: usize, cell_size: f32, layer_height: f32) -> Self {
+        Self {
+            properties,
+            width,
+            height,
+            cell_size,
+            layer_height,
+            below: None,
+            layer: 0,
+        }
+    }
+
+    pub fn properties(&self) -> &ThermalProperties {
+        &self.properties
+    }
+
+    /// Grid size in cells (width, height).
+    pub fn grid_size(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+
+    /// Deposits a layer over the `occupied` cells and integrates its cooling
+    /// for `layer_time` seconds, with steps no longer than `max_step`.
+    pub fn deposit_layer(
+        &mut self,
+        occupied: &[bool],
+        layer_time: f32,
+        fan_speed: f32,
+        max_step: f32,
+    ) -> Result<LayerThermalReport> {
+        if occupied.len() != self.width * self.height {
+            bail!(
+                "Occupancy has {} cells, grid has {}x{}",
+                occupied.len(),
+                self.width,
+                self.height
+            );
+        }
+
+        let p = self.properties;
+        let thickness = self.layer_height / 1000.0;
+        let dx = self.cell_size / 1000.0;
+        let capacity = p.density * p.specific_heat * thickness;
+        let h_conv = p.convection(fan_speed);
+        let between_layers = p.conductivity / thickness.max(f32::EPSILON);
+
+        // Downward path per cell: (conductance, boundary temperature)
+        let down: Vec<(f32, f32)> = (0..occupied.len())
+            .map(|i| match &self.below {
+                None => (p.bed_conductance, p.bed_temp),
+                Some(below) => match below[i] {
+                    Some(t) => (between_layers, t),
+                    // Overhang: underside convects too
+                    None => (h_conv, p.ambient_temp),
+                },
+            })
+            .collect();
+
+        let alpha = p.conductivity / (p.density * p.specific_heat);
+        let max_sink = down.iter().map(|(g, _)| *g).fold(0.0f32, f32::max) + h_conv;
+        let mut step = max_step;
+        if max_sink > 0.0 {
+            step = step.min(STABILITY_FACTOR * capacity / max_sink);
+        }
+        if alpha > 0.0 {
+            step = step.min(STABILITY_FACTOR * dx * dx / alpha);
+        }
+        let steps = (layer_time / step.max(f32::EPSILON)).ceil().max(1.0) as usize;
+        let dt = layer_time / steps as f32;
+
+        let mut temps: Vec<f32> = occupied
+            .iter()
+            .map(|&o| if o { p.deposition_temp } else { p.ambient_temp })
+            .collect();
+        let mut next = temps.clone();
+
+        for _ in 0..steps {
+            for y in 0..self.height {
+                for x in 0..self.width {
+                    let i = y * self.width + x;
+                    if !occupied[i] {
+                        continue;
+                    }
+                    let t = temps[i];
+
+                    // Edges to empty cells are treated as insulated
+                    let mut laplacian = 0.0;
+                    for n in self.neighbours(x, y) {
+                        if occupied[n] {
+                            laplacian += temps[n] - t;
+                        }
+                    }
+                    laplacian /= dx * dx;
+
+                    let (g_down, t_down) = down[i];
+                    let flux = p.conductivity * thickness * laplacian
+                        + g_down * (t_down - t)
+                        + h_conv * (p.ambient_temp - t);
+                    next[i] = t + dt * flux / capacity;
+                }
+            }
+            std::mem::swap(&mut temps, &mut next);
+        }
+
+        let report = self.report(occupied, &temps, layer_time);
+        if report.is_too_hot() {
+            warn!(
+                "Layer {}: {} region(s) above glass transition after {:.1}s (peak {:.0}°C)",
+                self.layer,
+                report.hot_regions.len(),
+                layer_time,
+                report.peak_temp
+            );
+        }
+        debug!("Layer {} cooled in {} steps of {:.4}s", self.layer, steps, dt);
+
+        self.below = Some(
+            occupied
+                .iter()
+                .zip(&temps)
+                .map(|(&o, &t)| o.then_some(t))
+                .collect(),
+        );
+        self.layer += 1;
+        Ok(report)
+    }
+
+    fn neighbours(&self, x: usize, y: usize) -> impl Iterator<Item = usize> + '_ {
+        let w = self.width;
+        [
+            (x > 0).then(|| y * w + x - 1),
+            (x + 1 < w).then(|| y * w + x + 1),
+            (y > 0).then(|| (y - 1) * w + x),
+            (y + 1 < self.height).then(|| (y + 1) * w + x),
+        ]
+        .into_iter()
+        .flatten()
+    }
+
+    fn report(&self, occupied: &[bool], temps: &[f32], layer_time: f32) -> LayerThermalReport {
+        let tg = self.properties.glass_transition;
+        let deposited: Vec<f32> = temps
+            .iter()
+            .zip(occupied)
+            .filter(|(_, o)| **o)
+            .map(|(&t, _)| t)
+            .collect();
+        let peak_temp = deposited.iter().copied().fold(f32::NAN, f32::max);
+        let mean_temp = if deposited.is_empty() {
+            f32::NAN
+        } else {
+            deposited.iter().sum::<f32>() / deposited.len() as f32
+        };
+
+        // Flood-fill connected hot cells
+        let hot = |i: usize| occupied[i] && temps[i] > tg;
+        let mut visited = vec![false; temps.len()];
+        let mut hot_regions = Vec::new();
+        for start in 0..temps.len() {
+            if visited[start] || !hot(start) {
+                continue;
+            }
+            visited[start] = true;
+            let mut stack = vec![start];
+            let (mut min_x, mut min_y, mut max_x, mut max_y) = (usize::MAX, usize::MAX, 0, 0);
+            let mut cells = 0;
+            let mut region_peak = f32::MIN;
+            while let Some(i) = stack.pop() {
+                let (x, y) = (i % self.width, i / self.width);
+                min_x = min_x.min(x);
+                min_y = min_y.min(y);
+                max_x = max_x.max(x);
+                max_y = max_y.max(y);
+                cells += 1;
+                region_peak = region_peak.max(temps[i]);
+                for n in self.neighbours(x, y) {
+                    if !visited[n] && hot(n) {
+                        visited[n] = true;
+                        stack.push(n);
+                    }
+                }
+            }
+            let mm = |c: usize| c as f32 * self.cell_size;
+            hot_regions.push(HotRegion {
+                bounds: (mm(min_x), mm(min_y), mm(max_x + 1), mm(max_y + 1)),
+                cells,
+                peak_temp: region_peak,
+            });
+        }
+
+        LayerThermalReport {
+            layer: self.layer,
+            layer_time,
+            peak_temp,
+            mean_temp,
+            hot_regions,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pla() -> ThermalProperties {
+        ThermalProperties {
+            deposition_temp: 210.0,
+            glass_transition: 60.0,
+            density: 1240.0,
+            specific_heat: 1800.0,
+            conductivity: 0.13,
+            bed_temp: 50.0,
+            ambient_temp: 25.0,
+            bed_conductance: 500.0,
+            convection_still: 10.0,
+            convection_fan: 90.0,
+        }
+    }
+
+    /// Two 3x3 islands on an 8x4 grid.
+    fn islands() -> Vec<bool> {
+        (0..32).map(|i| (i % 8 < 3 || i % 8 >= 5) && i / 8 < 3).collect()
+    }
+
+    #[test]
+    fn test_short_layer_time_leaves_hot_regions() {
+        let mut model = ThermalModel::new(pla(), 8, 4, 1.0, 0.2);
+        let report = model.deposit_layer(&islands(), 0.1, 0.0, 0.01).unwrap();
+
+        assert!(report.is_too_hot());
+        assert_eq!(report.hot_regions.len(), 2);
+        assert_eq!(report.hot_regions[0].cells, 9);
+        assert_eq!(report.hot_regions[0].bounds, (0.0, 0.0, 3.0, 3.0));
+        assert!(report.peak_temp < 210.0 && report.peak_temp > 150.0);
+    }
+
+    #[test]
+    fn test_fan_and_time_cool_below_glass_transition() {
+        let mut model = ThermalModel::new(pla(), 8, 4, 1.0, 0.2);
+        let first = model.deposit_layer(&islands(), 10.0, 100.0, 0.01).unwrap();
+        assert!(!first.is_too_hot(), "peak {}", first.peak_temp);
+        // Bed-dominated equilibrium: (500·50 + 100·25) / 600
+        assert!((first.mean_temp - 45.8).abs() < 0.5);
+
+        // The second layer sits on cooled plastic rather than the heated bed
+        // and needs longer without the fan
+        let still = model.deposit_layer(&islands(), 1.0, 0.0, 0.01).unwrap();
+        assert!(still.is_too_hot());
+        assert_eq!(still.layer, 1);
+    }
+}
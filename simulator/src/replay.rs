@@ -0,0 +1,315 @@
+//! Replay of recorded protocol traces.
+//!
+//! A trace recorded by the firmware (`--record-trace`) is replayed message by
+//! message for post-mortem debugging. [`TracePlayer`] steps through the
+//! messages, seeks to an offset and plays back with the original spacing
+//! (scaled by a speed multiplier). Replay is deterministic: the same trace
+//! always yields the same messages in the same order.
+//!
+//! [`diff_channels`] compares the recorded thermal zone and pressure channel
+//! readings with a [`ChannelPredictor`]'s predictions, driven by the same
+//! recorded targets, to find where the hardware left the model.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::Duration;
+
+use protocol::{ProtocolMessage, Trace, TraceEntry};
+
+/// Steps through a trace.
+pub struct TracePlayer {
+    trace: Trace,
+    /// Index of the next entry
+    position: usize,
+}
+
+impl TracePlayer {
+    pub fn new(trace: Trace) -> Self {
+        Self { trace, position: 0 }
+    }
+
+    pub fn trace(&self) -> &Trace {
+        &self.trace
+    }
+
+    /// Offset of the next entry (ms), or the trace duration at the end.
+    pub fn position_ms(&self) -> u64 {
+        self.trace
+            .entries
+            .get(self.position)
+            .map(|e| e.offset_ms)
+            .unwrap_or_else(|| self.trace.duration_ms())
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.position >= self.trace.entries.len()
+    }
+
+    /// Returns the next entry and advances past it.
+    pub fn step(&mut self) -> Option<&TraceEntry> {
+        let entry = self.trace.entries.get(self.position)?;
+        self.position += 1;
+        Some(entry)
+    }
+
+    /// Moves to the first entry at or after `offset_ms`.
+    pub fn seek(&mut self, offset_ms: u64) {
+        self.position = self.trace.index_at(offset_ms);
+    }
+
+    /// Returns every entry up to and including `offset_ms` not yet played.
+    pub fn advance_to(&mut self, offset_ms: u64) -> &[TraceEntry] {
+        let start = self.position;
+        self.position = self.trace.index_at(offset_ms.saturating_add(1)).max(start);
+        &self.trace.entries[start..self.position]
+    }
+
+    /// Plays the remaining entries with their recorded spacing divided by
+    /// `speed`, passing each to `sink`. Returning false from `sink` stops
+    /// playback.
+    pub async fn play<F>(&mut self, speed: f32, mut sink: F)
+    where
+        F: FnMut(&TraceEntry) -> bool,
+    {
+        let speed = if speed.is_finite() && speed > 0.0 { speed } else { 1.0 };
+        let origin_ms = self.position_ms();
+        let start = tokio::time::Instant::now();
+
+        while let Some(entry) = self.trace.entries.get(self.position) {
+            let wait = Duration::from_secs_f64((entry.offset_ms - origin_ms) as f64 / 1000.0 / speed as f64);
+            tokio::time::sleep_until(start + wait).await;
+            self.position += 1;
+            if !sink(entry) {
+                return;
+            }
+        }
+    }
+}
+
+/// A recorded analog channel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Channel {
+    /// Thermal zone temperature (°C)
+    Zone(u8),
+    Manifold,
+    Bed,
+    Chamber,
+    /// Channel pressure (PSI)
+    Pressure(u8),
+}
+
+impl fmt::Display for Channel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Channel::Zone(id) => write!(f, "zone {}", id),
+            Channel::Manifold => write!(f, "manifold"),
+            Channel::Bed => write!(f, "bed"),
+            Channel::Chamber => write!(f, "chamber"),
+            Channel::Pressure(id) => write!(f, "pressure {}", id),
+        }
+    }
+}
+
+/// One recorded reading.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sample {
+    pub offset_ms: u64,
+    pub value: f32,
+    pub target: f32,
+}
+
+/// Extracts every thermal and pressure reading from a trace, per channel.
+pub fn channel_series(trace: &Trace) -> BTreeMap<Channel, Vec<Sample>> {
+    let mut series: BTreeMap<Channel, Vec<Sample>> = BTreeMap::new();
+    let mut push = |channel, offset_ms, value, target| {
+        series.entry(channel).or_default().push(Sample { offset_ms, value, target });
+    };
+
+    for entry in &trace.entries {
+        let t = entry.offset_ms;
+        match &entry.message {
+            ProtocolMessage::ThermalUpdate(update) => {
+                for zone in &update.zones {
+                    push(Channel::Zone(zone.id), t, zone.current, zone.target);
+                }
+                let others = [
+                    (Channel::Manifold, &update.manifold),
+                    (Channel::Bed, &update.bed),
+                    (Channel::Chamber, &update.chamber),
+                ];
+                for (channel, reading) in others {
+                    if let Some(reading) = reading {
+                        push(channel, t, reading.current, reading.target);
+                    }
+                }
+            }
+            ProtocolMessage::PressureUpdate(update) => {
+                for ch in &update.channels {
+                    push(Channel::Pressure(ch.id), t, ch.pressure, ch.target);
+                }
+            }
+            _ => {}
+        }
+    }
+    series
+}
+
+/// Predicts a channel's reading from its previous prediction and target.
+pub trait ChannelPredictor {
+    /// Prediction `dt` seconds after `previous` while driven toward `target`.
+    fn predict(&self, channel: Channel, previous: f32, target: f32, dt: f32) -> f32;
+}
+
+/// First-order lag toward the target, the response of a well-tuned PID
+/// loop seen from outside.
+#[derive(Debug, Clone, Copy)]
+pub struct FirstOrderPredictor {
+    /// Time constant of heated zones (s)
+    pub thermal_tau: f32,
+    /// Time constant of pressure channels (s)
+    pub pressure_tau: f32,
+}
+
+impl Default for FirstOrderPredictor {
+    fn default() -> Self {
+        Self {
+            thermal_tau: 30.0,
+            pressure_tau: 0.5,
+        }
+    }
+}
+
+impl ChannelPredictor for FirstOrderPredictor {
+    fn predict(&self, channel: Channel, previous: f32, target: f32, dt: f32) -> f32 {
+        let tau = match channel {
+            Channel::Pressure(_) => self.pressure_tau,
+            _ => self.thermal_tau,
+        };
+        if tau <= 0.0 {
+            return target;
+        }
+        previous + (target - previous) * (1.0 - (-dt / tau).exp())
+    }
+}
+
+/// Recorded readings of one channel compared with the prediction.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChannelDiff {
+    pub channel: Channel,
+    pub samples: usize,
+    pub mean_abs_error: f32,
+    pub max_abs_error: f32,
+    /// Offset of the largest error (ms)
+    pub max_error_at_ms: u64,
+}
+
+impl fmt::Display for ChannelDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:<12} {:>6} samples  mean |err| {:>7.2}  max |err| {:>7.2} at {:.1}s",
+            self.channel.to_string(),
+            self.samples,
+            self.mean_abs_error,
+            self.max_abs_error,
+            self.max_error_at_ms as f64 / 1000.0
+        )
+    }
+}
+
+/// Compares every recorded channel with the predictor.
+///
+/// The prediction starts at the first recorded value and is then advanced
+/// between samples using the target recorded at the earlier sample, so it
+/// sees the same setpoint changes the hardware did.
+pub fn diff_channels(trace: &Trace, predictor: &dyn ChannelPredictor) -> Vec<ChannelDiff> {
+    channel_series(trace)
+        .into_iter()
+        .filter_map(|(channel, samples)| {
+            let first = samples.first()?;
+            let mut predicted = first.value;
+            let mut previous = *first;
+            let mut total_error = 0.0;
+            let mut max_error = 0.0;
+            let mut max_error_at_ms = first.offset_ms;
+
+            for sample in &samples[1..] {
+                let dt = (sample.offset_ms - previous.offset_ms) as f32 / 1000.0;
+                predicted = predictor.predict(channel, predicted, previous.target, dt);
+                let error = (sample.value - predicted).abs();
+                total_error += error;
+                if error > max_error {
+                    max_error = error;
+                    max_error_at_ms = sample.offset_ms;
+                }
+                previous = *sample;
+            }
+
+            Some(ChannelDiff {
+                channel,
+                samples: samples.len(),
+                mean_abs_error: total_error / samples.len() as f32,
+                max_abs_error: max_error,
+                max_error_at_ms,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use protocol::{create_thermal_update, TraceDirection, TraceHeader};
+
+    fn trace(readings: &[(u64, f32)]) -> Trace {
+        Trace {
+            header: TraceHeader {
+                version: protocol::trace::TRACE_VERSION,
+                started_at_ms: 0,
+                source: "test".to_string(),
+            },
+            entries: readings
+                .iter()
+                .map(|&(offset_ms, current)| TraceEntry {
+                    offset_ms,
+                    direction: TraceDirection::Outbound,
+                    message: create_thermal_update(vec![(0, current, 200.0)]),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_step_seek_and_advance() {
+        let mut player = TracePlayer::new(trace(&[(0, 20.0), (100, 21.0), (200, 22.0), (300, 23.0)]));
+        assert_eq!(player.step().unwrap().offset_ms, 0);
+        assert_eq!(player.position_ms(), 100);
+
+        assert_eq!(player.advance_to(200).len(), 2);
+        assert!(player.advance_to(250).is_empty());
+
+        player.seek(150);
+        assert_eq!(player.step().unwrap().offset_ms, 200);
+        player.seek(1000);
+        assert!(player.is_finished());
+    }
+
+    #[test]
+    fn test_diff_against_first_order_model() {
+        let model = FirstOrderPredictor { thermal_tau: 10.0, pressure_tau: 1.0 };
+        // Heating exactly as the model predicts, then a 15°C drop at 30s
+        let mut readings = Vec::new();
+        let mut t = 20.0;
+        for s in 0..=30u64 {
+            readings.push((s * 1000, t));
+            t = model.predict(Channel::Zone(0), t, 200.0, 1.0);
+        }
+        readings.last_mut().unwrap().1 -= 15.0;
+
+        let diffs = diff_channels(&trace(&readings), &model);
+        assert_eq!(diffs.len(), 1);
+        assert_eq!(diffs[0].channel, Channel::Zone(0));
+        assert!((diffs[0].max_abs_error - 15.0).abs() < 0.01);
+        assert_eq!(diffs[0].max_error_at_ms, 30_000);
+    }
+}
@@ -0,0 +1,199 @@
+//! Orbit camera around the build plate.
+//!
+//! The camera circles a target point with Z up, as a printer is usually
+//! viewed: yaw turns around the plate, pitch looks down onto it, zoom
+//! changes the distance and panning moves the target across the view.
+//! Matrices are column-major, right-handed, with the 0..1 depth range
+//! wgpu expects.
+
+use std::f32::consts::FRAC_PI_2;
+
+/// Closest and farthest the camera may be from its target (mm).
+const DISTANCE_RANGE: (f32, f32) = (1.0, 10_000.0);
+
+/// Pitch stays short of straight up or down, where the view turns over.
+const MAX_PITCH: f32 = FRAC_PI_2 - 0.01;
+
+/// Column-major 4×4 matrix.
+pub type Matrix4 = [[f32; 4]; 4];
+
+/// See the module documentation.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OrbitCamera {
+    /// Point the camera looks at (mm)
+    pub target: [f32; 3],
+    /// Distance from the target (mm)
+    pub distance: f32,
+    /// Angle around Z from the +X axis (radians)
+    pub yaw: f32,
+    /// Angle above the XY plane (radians)
+    pub pitch: f32,
+    /// Vertical field of view (radians)
+    pub fov_y: f32,
+}
+
+impl Default for OrbitCamera {
+    fn default() -> Self {
+        Self {
+            target: [0.0; 3],
+            distance: 300.0,
+            yaw: -45f32.to_radians(),
+            pitch: 30f32.to_radians(),
+            fov_y: 45f32.to_radians(),
+        }
+    }
+}
+
+impl OrbitCamera {
+    /// Turns the camera around its target (radians).
+    pub fn orbit(&mut self, yaw: f32, pitch: f32) {
+        self.yaw = (self.yaw + yaw).rem_euclid(std::f32::consts::TAU);
+        self.pitch = (self.pitch + pitch).clamp(-MAX_PITCH, MAX_PITCH);
+    }
+
+    /// Moves closer (factor below 1) or farther away.
+    pub fn zoom(&mut self, factor: f32) {
+        if factor.is_finite() && factor > 0.0 {
+            self.distance = (self.distance * factor).clamp(DISTANCE_RANGE.0, DISTANCE_RANGE.1);
+        }
+    }
+
+    /// Moves the target across the view, in fractions of the view height.
+    pub fn pan(&mut self, right: f32, up: f32) {
+        let (_, side, view_up) = self.basis();
+        let scale = 2.0 * self.distance * (self.fov_y / 2.0).tan();
+        for (axis, target) in self.target.iter_mut().enumerate() {
+            *target += (side[axis] * right + view_up[axis] * up) * scale;
+        }
+    }
+
+    /// Centers a box and backs off until it fits the view.
+    pub fn fit(&mut self, min: [f32; 3], max: [f32; 3]) {
+        self.target = [0, 1, 2].map(|axis| (min[axis] + max[axis]) / 2.0);
+        let radius = length(sub(max, min)) / 2.0;
+        self.distance = (radius / (self.fov_y / 2.0).sin()).clamp(DISTANCE_RANGE.0, DISTANCE_RANGE.1);
+    }
+
+    /// Camera position (mm).
+    pub fn eye(&self) -> [f32; 3] {
+        let (sin_yaw, cos_yaw) = self.yaw.sin_cos();
+        let (sin_pitch, cos_pitch) = self.pitch.sin_cos();
+        let offset = [cos_pitch * cos_yaw, cos_pitch * sin_yaw, sin_pitch];
+        [0, 1, 2].map(|axis| self.target[axis] + offset[axis] * self.distance)
+    }
+
+    /// World to view transform.
+    pub fn view_matrix(&self) -> Matrix4 {
+        let eye = self.eye();
+        let (forward, side, up) = self.basis();
+        [
+            [side[0], up[0], -forward[0], 0.0],
+            [side[1], up[1], -forward[1], 0.0],
+            [side[2], up[2], -forward[2], 0.0],
+            [-dot(side, eye), -dot(up, eye), dot(forward, eye), 1.0],
+        ]
+    }
+
+    /// View to clip transform for a viewport's width / height, with the
+    /// near and far planes scaled to the distance.
+    pub fn projection_matrix(&self, aspect: f32) -> Matrix4 {
+        let near = (self.distance * 0.01).max(0.1);
+        let far = self.distance * 10.0;
+        let h = 1.0 / (self.fov_y / 2.0).tan();
+        let r = far / (near - far);
+        [
+            [h / aspect, 0.0, 0.0, 0.0],
+            [0.0, h, 0.0, 0.0],
+            [0.0, 0.0, r, -1.0],
+            [0.0, 0.0, r * near, 0.0],
+        ]
+    }
+
+    /// World to clip transform.
+    pub fn view_projection(&self, aspect: f32) -> Matrix4 {
+        multiply(&self.projection_matrix(aspect), &self.view_matrix())
+    }
+
+    /// Forward, right and up unit vectors of the view.
+    fn basis(&self) -> ([f32; 3], [f32; 3], [f32; 3]) {
+        let forward = normalize(sub(self.target, self.eye()));
+        let side = normalize(cross(forward, [0.0, 0.0, 1.0]));
+        let up = cross(side, forward);
+        (forward, side, up)
+    }
+}
+
+fn sub(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[1] * b[2] - a[2] * b[1], a[2] * b[0] - a[0] * b[2], a[0] * b[1] - a[1] * b[0]]
+}
+
+fn length(a: [f32; 3]) -> f32 {
+    dot(a, a).sqrt()
+}
+
+fn normalize(a: [f32; 3]) -> [f32; 3] {
+    let length = length(a);
+    a.map(|v| v / length)
+}
+
+fn multiply(a: &Matrix4, b: &Matrix4) -> Matrix4 {
+    let mut out = [[0.0; 4]; 4];
+    for (column, b_column) in out.iter_mut().zip(b) {
+        for (row, value) in column.iter_mut().enumerate() {
+            *value = (0..4).map(|k| a[k][row] * b_column[k]).sum();
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transform(m: &Matrix4, p: [f32; 3]) -> [f32; 4] {
+        [0, 1, 2, 3].map(|row| m[0][row] * p[0] + m[1][row] * p[1] + m[2][row] * p[2] + m[3][row])
+    }
+
+    fn close(a: &[f32], b: &[f32]) -> bool {
+        a.iter().zip(b).all(|(a, b)| (a - b).abs() < 1e-3)
+    }
+
+    #[test]
+    fn test_orbit_camera_looks_at_target() {
+        let mut camera = OrbitCamera { yaw: 0.0, pitch: 0.0, ..OrbitCamera::default() };
+        camera.target = [100.0, 100.0, 0.0];
+        assert!(close(&camera.eye(), &[400.0, 100.0, 0.0]));
+
+        // The target sits straight ahead, the eye at the view origin
+        let view = camera.view_matrix();
+        assert!(close(&transform(&view, camera.target), &[0.0, 0.0, -300.0, 1.0]));
+        assert!(close(&transform(&view, camera.eye()), &[0.0, 0.0, 0.0, 1.0]));
+
+        // ... and projects to the middle of the viewport, inside the depth range
+        let clip = transform(&camera.view_projection(16.0 / 9.0), camera.target);
+        assert!(close(&[clip[0] / clip[3], clip[1] / clip[3]], &[0.0, 0.0]));
+        assert!((0.0..1.0).contains(&(clip[2] / clip[3])));
+
+        camera.orbit(0.0, 10.0);
+        assert_eq!(camera.pitch, MAX_PITCH);
+        camera.zoom(1e-6);
+        assert_eq!(camera.distance, DISTANCE_RANGE.0);
+
+        // Panning right at yaw 0 looks from +X, so right is +Y
+        let mut camera = OrbitCamera { yaw: 0.0, pitch: 0.0, ..OrbitCamera::default() };
+        camera.pan(0.5, 0.0);
+        assert!(camera.target[1] > 0.0 && camera.target[0].abs() < 1e-3);
+
+        camera.fit([0.0, 0.0, 0.0], [200.0, 200.0, 100.0]);
+        assert_eq!(camera.target, [100.0, 100.0, 50.0]);
+        assert!(camera.distance > 150.0);
+    }
+}
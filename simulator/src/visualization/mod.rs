@@ -0,0 +1,298 @@
+//! # Deposition Visualization
+//!
+//! Shows a simulated print as it builds up: deposited material as voxels
+//! colored by material, played back over simulated time, with optional
+//! overlays of the valves open in the layer being deposited and of that
+//! layer's solved pressures.
+//!
+//! ## Module Organization
+//!
+//! - **scene**: Voxels and overlays at any simulated time
+//! - **camera**: Orbit camera and its view and projection matrices
+//! - **window**: Interactive wgpu window (built with the `window` feature)
+//!
+//! [`Visualizer`] holds the state behind the window: the scene, playback
+//! and camera. The window only turns key and mouse events into [`Input`]s
+//! and draws the [`SceneFrame`] for the current time, so everything but the
+//! drawing runs, and is tested, without a GPU.
+
+pub mod scene;
+pub mod camera;
+#[cfg(feature = "window")]
+pub mod window;
+
+use anyhow::Result;
+
+use config_types::PrinterConfig;
+use gcode_types::LayerFrame;
+
+pub use camera::OrbitCamera;
+pub use scene::{OverlayQuad, Overlays, SceneFrame, VoxelInstance, VoxelScene};
+
+/// Most voxels along a side of the grid before the scene is coarsened.
+pub const MAX_VOXELS_PER_SIDE: u32 = 256;
+
+/// Play, pause and scrub through simulated time.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Playback {
+    /// Current simulated time (seconds)
+    time: f32,
+    /// End of the print (seconds)
+    duration: f32,
+    /// Simulated seconds per real second
+    speed: f32,
+    playing: bool,
+}
+
+impl Default for Playback {
+    fn default() -> Self {
+        Self {
+            time: 0.0,
+            duration: 0.0,
+            speed: 1.0,
+            playing: false,
+        }
+    }
+}
+
+impl Playback {
+    pub fn time(&self) -> f32 {
+        self.time
+    }
+
+    pub fn duration(&self) -> f32 {
+        self.duration
+    }
+
+    pub fn speed(&self) -> f32 {
+        self.speed
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.playing
+    }
+
+    /// Plays from the current time, or from the start once at the end.
+    pub fn play(&mut self) {
+        if self.time >= self.duration {
+            self.time = 0.0;
+        }
+        self.playing = true;
+    }
+
+    pub fn pause(&mut self) {
+        self.playing = false;
+    }
+
+    pub fn toggle(&mut self) {
+        if self.playing {
+            self.pause();
+        } else {
+            self.play();
+        }
+    }
+
+    /// Jumps to a time, clamped to the print.
+    pub fn seek(&mut self, time: f32) {
+        self.time = time.clamp(0.0, self.duration);
+    }
+
+    pub fn set_speed(&mut self, speed: f32) {
+        self.speed = speed.max(0.0);
+    }
+
+    /// Extends or shortens the print as layers are added.
+    pub fn set_duration(&mut self, duration: f32) {
+        self.duration = duration.max(0.0);
+        self.time = self.time.min(self.duration);
+    }
+
+    /// Advances by `elapsed` real seconds while playing, stopping at the
+    /// end. Returns whether the time changed.
+    pub fn advance(&mut self, elapsed: f32) -> bool {
+        if !self.playing || elapsed <= 0.0 {
+            return false;
+        }
+        let before = self.time;
+        self.time = (self.time + elapsed * self.speed).min(self.duration);
+        if self.time >= self.duration {
+            self.playing = false;
+        }
+        self.time != before
+    }
+}
+
+/// What the operator asked for, independent of the key or mouse gesture.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Input {
+    TogglePlay,
+    /// Jump to a simulated time (seconds)
+    Seek(f32),
+    /// Move through simulated time (seconds, negative to rewind)
+    Scrub(f32),
+    /// Jump to the end of the layer this many layers ahead (or back)
+    StepLayer(i32),
+    SetSpeed(f32),
+    /// Turn the camera (radians)
+    Orbit { yaw: f32, pitch: f32 },
+    /// Move the camera target (fractions of the view height)
+    Pan { right: f32, up: f32 },
+    /// Scale the camera distance
+    Zoom(f32),
+    /// Frame everything deposited so far
+    FitView,
+    ToggleValves,
+    TogglePressure,
+}
+
+/// Scene, playback and camera of a visualized print; see the module
+/// documentation.
+#[derive(Debug, Clone, Default)]
+pub struct Visualizer {
+    scene: VoxelScene,
+    playback: Playback,
+    camera: OrbitCamera,
+    overlays: Overlays,
+}
+
+impl Visualizer {
+    /// Visualizer with a unit grid spacing, for callers without a printer
+    /// configuration.
+    pub fn new() -> Result<Self> {
+        Ok(Self::default())
+    }
+
+    /// Visualizer scaled to a printer's grid, coarsened to at most
+    /// [`MAX_VOXELS_PER_SIDE`] voxels along each side, with the heatmap
+    /// topping out at the highest operating pressure.
+    pub fn for_printer(config: &PrinterConfig) -> Self {
+        let nodes = config.grid_x_count().max(config.grid_y_count());
+        let stride = nodes.div_ceil(MAX_VOXELS_PER_SIDE).max(1);
+        let mut scene = VoxelScene::new(config.valve_array.grid_spacing).with_stride(stride);
+        scene.set_max_pressure(config.materials.pressure.max_pressure);
+
+        let mut camera = OrbitCamera::default();
+        camera.fit([0.0; 3], [config.build_volume.x, config.build_volume.y, config.build_volume.z]);
+        Self { scene, camera, ..Self::default() }
+    }
+
+    /// Adds the next layer, deposited from `start` for `duration`
+    /// simulated seconds.
+    pub fn add_layer(&mut self, frame: &LayerFrame, start: f32, duration: f32) {
+        self.scene.add_layer(frame, start, duration);
+        self.playback.set_duration(self.scene.duration());
+    }
+
+    pub fn scene(&self) -> &VoxelScene {
+        &self.scene
+    }
+
+    /// For channel colors and layer pressures.
+    pub fn scene_mut(&mut self) -> &mut VoxelScene {
+        &mut self.scene
+    }
+
+    pub fn playback(&self) -> &Playback {
+        &self.playback
+    }
+
+    pub fn camera(&self) -> &OrbitCamera {
+        &self.camera
+    }
+
+    pub fn overlays(&self) -> Overlays {
+        self.overlays
+    }
+
+    pub fn set_overlays(&mut self, overlays: Overlays) {
+        self.overlays = overlays;
+    }
+
+    /// Applies an operator input.
+    pub fn handle(&mut self, input: Input) {
+        match input {
+            Input::TogglePlay => self.playback.toggle(),
+            Input::Seek(time) => self.playback.seek(time),
+            Input::Scrub(seconds) => self.playback.seek(self.playback.time() + seconds),
+            Input::StepLayer(layers) => {
+                let completed = self.scene.completed_layers(self.playback.time()) as i64;
+                let target = (completed + layers as i64).clamp(0, self.scene.layer_count() as i64) as usize;
+                let time = target.checked_sub(1).and_then(|i| self.scene.layer_end(i)).unwrap_or(0.0);
+                self.playback.pause();
+                self.playback.seek(time);
+            }
+            Input::SetSpeed(speed) => self.playback.set_speed(speed),
+            Input::Orbit { yaw, pitch } => self.camera.orbit(yaw, pitch),
+            Input::Pan { right, up } => self.camera.pan(right, up),
+            Input::Zoom(factor) => self.camera.zoom(factor),
+            Input::FitView => {
+                if let Some((min, max)) = self.scene.bounds() {
+                    self.camera.fit(min, max);
+                }
+            }
+            Input::ToggleValves => self.overlays.valves = !self.overlays.valves,
+            Input::TogglePressure => self.overlays.pressure = !self.overlays.pressure,
+        }
+    }
+
+    /// Advances playback by `elapsed` real seconds; see
+    /// [`Playback::advance`].
+    pub fn advance(&mut self, elapsed: f32) -> bool {
+        self.playback.advance(elapsed)
+    }
+
+    /// What to draw at the current time.
+    pub fn current_frame(&self) -> SceneFrame {
+        self.scene.frame(self.playback.time(), self.overlays)
+    }
+
+    /// Opens a window and runs it until closed.
+    #[cfg(feature = "window")]
+    pub fn run_window(self) -> Result<()> {
+        window::run(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gcode_types::{GridCoordinate, Layer, NodeValveState, ValveState};
+
+    #[test]
+    fn test_playback_and_layer_stepping() {
+        let mut visualizer = Visualizer::new().unwrap();
+        for n in 0..3 {
+            let mut layer = Layer::new(0.2 * (n + 1) as f32, n);
+            layer.add_node(NodeValveState::new(GridCoordinate::new(n, 0), vec![ValveState::open(0)]));
+            visualizer.add_layer(&LayerFrame::from_layer(&layer).unwrap(), n as f32 * 10.0, 10.0);
+        }
+        assert_eq!(visualizer.playback().duration(), 30.0);
+
+        visualizer.handle(Input::SetSpeed(4.0));
+        visualizer.handle(Input::TogglePlay);
+        assert!(visualizer.advance(2.0));
+        assert_eq!(visualizer.playback().time(), 8.0);
+        assert_eq!(visualizer.current_frame().active_layer, Some(0));
+
+        // Playback stops at the end and restarts from the beginning
+        visualizer.advance(100.0);
+        assert!(!visualizer.playback().is_playing());
+        assert_eq!(visualizer.current_frame().voxels.len(), 3);
+        visualizer.handle(Input::TogglePlay);
+        assert_eq!(visualizer.playback().time(), 0.0);
+
+        // Layer steps pause and land on layer ends
+        visualizer.handle(Input::Seek(15.0));
+        visualizer.handle(Input::StepLayer(1));
+        assert!(!visualizer.playback().is_playing());
+        assert_eq!(visualizer.playback().time(), 20.0);
+        visualizer.handle(Input::StepLayer(-5));
+        assert_eq!(visualizer.playback().time(), 0.0);
+        visualizer.handle(Input::Scrub(-1.0));
+        assert_eq!(visualizer.playback().time(), 0.0);
+
+        visualizer.handle(Input::ToggleValves);
+        visualizer.handle(Input::Seek(25.0));
+        assert_eq!(visualizer.current_frame().overlays.len(), 1);
+    }
+}
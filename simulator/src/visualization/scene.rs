@@ -0,0 +1,367 @@
+//! Deposited material over simulated time.
+//!
+//! A [`VoxelScene`] collects the layers of a print as they are simulated:
+//! the open nodes of each layer become voxels one node wide and one layer
+//! tall, colored by material channel and deposited over the layer's time
+//! span. The scene is asked for the [`SceneFrame`] at any simulated time;
+//! the layer being deposited then grows from its bottom, and the overlays
+//! show that layer's open valves and its solved pressures.
+//!
+//! Large grids are coarsened with a stride: each voxel then covers
+//! `stride × stride` nodes and takes the channel of the last of them.
+
+use std::collections::{BTreeMap, HashMap};
+
+use gcode_types::{Color, GridCoordinate, LayerFrame};
+
+/// Channel colors used when the material's color is not known; the same
+/// palette as the control interface's layer previews.
+const CHANNEL_COLORS: [Color; 8] = [
+    Color { r: 0x3b, g: 0x82, b: 0xf6 },
+    Color { r: 0xef, g: 0x44, b: 0x44 },
+    Color { r: 0x22, g: 0xc5, b: 0x5e },
+    Color { r: 0xea, g: 0xb3, b: 0x08 },
+    Color { r: 0xa8, g: 0x55, b: 0xf7 },
+    Color { r: 0x06, g: 0xb6, b: 0xd4 },
+    Color { r: 0xf9, g: 0x73, b: 0x16 },
+    Color { r: 0xec, g: 0x48, b: 0x99 },
+];
+
+/// Color of nodes without a material channel.
+const UNASSIGNED_COLOR: Color = Color { r: 0x9c, g: 0xa3, b: 0xaf };
+
+/// Open valves in the valve overlay.
+const VALVE_OVERLAY_COLOR: [u8; 4] = [0xff, 0xff, 0xff, 0xa0];
+
+/// Opacity of the pressure heatmap.
+const PRESSURE_OVERLAY_ALPHA: u8 = 0xc0;
+
+/// One voxel to draw, in millimeters.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VoxelInstance {
+    /// Lowest corner
+    pub min: [f32; 3],
+    pub size: [f32; 3],
+    pub color: [u8; 4],
+}
+
+/// A flat square drawn on top of the layer being deposited.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OverlayQuad {
+    /// Lowest corner
+    pub min: [f32; 3],
+    pub size: [f32; 2],
+    pub color: [u8; 4],
+}
+
+/// Which overlays a frame includes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Overlays {
+    /// Nodes with open valves in the layer being deposited
+    pub valves: bool,
+    /// Solved node pressures of the layer being deposited
+    pub pressure: bool,
+}
+
+/// Everything visible at one simulated time.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SceneFrame {
+    /// Simulated time (seconds)
+    pub time: f32,
+    /// Layer being deposited, if any
+    pub active_layer: Option<u32>,
+    pub voxels: Vec<VoxelInstance>,
+    /// Pressure quads first, then valves
+    pub overlays: Vec<OverlayQuad>,
+}
+
+/// One deposited layer.
+#[derive(Debug, Clone)]
+struct SceneLayer {
+    layer_number: u32,
+    z_bottom: f32,
+    z_top: f32,
+    /// Deposition start and end (seconds)
+    start: f32,
+    end: f32,
+    /// Channel of each open block, keyed by block (x, y)
+    blocks: BTreeMap<(u32, u32), Option<u8>>,
+    /// Highest solved pressure in each block (PSI)
+    pressures: HashMap<(u32, u32), f32>,
+}
+
+impl SceneLayer {
+    /// Deposited fraction at a time.
+    fn fill(&self, time: f32) -> f32 {
+        if time >= self.end {
+            1.0
+        } else {
+            ((time - self.start) / (self.end - self.start)).clamp(0.0, 1.0)
+        }
+    }
+}
+
+/// Voxels of a print; see the module documentation.
+#[derive(Debug, Clone)]
+pub struct VoxelScene {
+    /// Distance between nodes (mm)
+    spacing: f32,
+    /// Nodes per voxel edge
+    stride: u32,
+    /// Layers in print order
+    layers: Vec<SceneLayer>,
+    colors: HashMap<u8, Color>,
+    /// Pressure drawn fully red in the heatmap (PSI)
+    max_pressure: f32,
+}
+
+impl Default for VoxelScene {
+    fn default() -> Self {
+        Self::new(1.0)
+    }
+}
+
+impl VoxelScene {
+    pub fn new(spacing: f32) -> Self {
+        Self {
+            spacing,
+            stride: 1,
+            layers: Vec::new(),
+            colors: HashMap::new(),
+            max_pressure: 100.0,
+        }
+    }
+
+    /// Coarsens the scene so each voxel covers `stride × stride` nodes.
+    /// Only affects layers added afterwards.
+    pub fn with_stride(mut self, stride: u32) -> Self {
+        self.stride = stride.max(1);
+        self
+    }
+
+    pub fn stride(&self) -> u32 {
+        self.stride
+    }
+
+    /// Colors a channel's voxels, usually with its material's color.
+    pub fn set_channel_color(&mut self, channel: u8, color: Color) {
+        self.colors.insert(channel, color);
+    }
+
+    pub fn channel_color(&self, channel: Option<u8>) -> Color {
+        match channel {
+            Some(channel) => self
+                .colors
+                .get(&channel)
+                .copied()
+                .unwrap_or(CHANNEL_COLORS[channel as usize % CHANNEL_COLORS.len()]),
+            None => UNASSIGNED_COLOR,
+        }
+    }
+
+    /// Sets the pressure at the top of the heatmap scale (PSI).
+    pub fn set_max_pressure(&mut self, max_pressure: f32) {
+        self.max_pressure = max_pressure.max(f32::EPSILON);
+    }
+
+    /// Adds the next layer, deposited from `start` for `duration` seconds.
+    /// The layer sits on the previous one; nodes without an open valve
+    /// deposit nothing.
+    pub fn add_layer(&mut self, frame: &LayerFrame, start: f32, duration: f32) {
+        let z_bottom = self
+            .layers
+            .last()
+            .map_or(0.0, |previous| previous.z_top.min(frame.z_height));
+        let blocks = frame
+            .nodes()
+            .filter(|node| node.has_open_valve())
+            .map(|node| (self.block(node.position), node.material_channel))
+            .collect();
+        self.layers.push(SceneLayer {
+            layer_number: frame.layer_number,
+            z_bottom,
+            z_top: frame.z_height,
+            start,
+            end: start + duration.max(0.0),
+            blocks,
+            pressures: HashMap::new(),
+        });
+    }
+
+    /// Records the solved pressures of a layer for the heatmap. Returns
+    /// false for a layer not in the scene.
+    pub fn set_pressures(&mut self, layer_number: u32, pressures: &HashMap<GridCoordinate, f32>) -> bool {
+        let stride = self.stride;
+        let Some(layer) = self.layers.iter_mut().find(|layer| layer.layer_number == layer_number) else {
+            return false;
+        };
+        layer.pressures.clear();
+        for (position, &pressure) in pressures {
+            let block = (position.x / stride, position.y / stride);
+            let entry = layer.pressures.entry(block).or_insert(pressure);
+            *entry = entry.max(pressure);
+        }
+        true
+    }
+
+    pub fn layer_count(&self) -> usize {
+        self.layers.len()
+    }
+
+    /// Time the last layer finishes (seconds).
+    pub fn duration(&self) -> f32 {
+        self.layers.iter().map(|layer| layer.end).fold(0.0, f32::max)
+    }
+
+    /// End time of the n-th layer in print order.
+    pub fn layer_end(&self, index: usize) -> Option<f32> {
+        self.layers.get(index).map(|layer| layer.end)
+    }
+
+    /// Layers fully deposited at a time.
+    pub fn completed_layers(&self, time: f32) -> usize {
+        self.layers.iter().take_while(|layer| layer.end <= time).count()
+    }
+
+    /// Layer being deposited at a time.
+    pub fn layer_at(&self, time: f32) -> Option<u32> {
+        self.layers
+            .iter()
+            .find(|layer| layer.start <= time && time < layer.end)
+            .map(|layer| layer.layer_number)
+    }
+
+    /// Lowest and highest corner of everything deposited, in millimeters.
+    pub fn bounds(&self) -> Option<([f32; 3], [f32; 3])> {
+        let size = self.block_size();
+        let mut bounds: Option<([f32; 3], [f32; 3])> = None;
+        for layer in &self.layers {
+            for &(x, y) in layer.blocks.keys() {
+                let min = [x as f32 * size, y as f32 * size, layer.z_bottom];
+                let max = [min[0] + size, min[1] + size, layer.z_top];
+                bounds = Some(match bounds {
+                    None => (min, max),
+                    Some((lo, hi)) => (
+                        [lo[0].min(min[0]), lo[1].min(min[1]), lo[2].min(min[2])],
+                        [hi[0].max(max[0]), hi[1].max(max[1]), hi[2].max(max[2])],
+                    ),
+                });
+            }
+        }
+        bounds
+    }
+
+    /// What is visible at a simulated time.
+    pub fn frame(&self, time: f32, overlays: Overlays) -> SceneFrame {
+        let size = self.block_size();
+        let mut frame = SceneFrame { time, ..SceneFrame::default() };
+
+        for layer in self.layers.iter().take_while(|layer| layer.start <= time) {
+            let fill = layer.fill(time);
+            if fill <= 0.0 {
+                continue;
+            }
+            let height = (layer.z_top - layer.z_bottom) * fill;
+            for (&(x, y), &channel) in &layer.blocks {
+                let color = self.channel_color(channel);
+                frame.voxels.push(VoxelInstance {
+                    min: [x as f32 * size, y as f32 * size, layer.z_bottom],
+                    size: [size, size, height],
+                    color: [color.r, color.g, color.b, 0xff],
+                });
+            }
+
+            if time >= layer.end {
+                continue;
+            }
+            frame.active_layer = Some(layer.layer_number);
+            let z = layer.z_bottom + height;
+            if overlays.pressure {
+                for (&(x, y), &pressure) in &layer.pressures {
+                    let heat = Color::BLUE.blend(&Color::RED, pressure / self.max_pressure);
+                    frame.overlays.push(OverlayQuad {
+                        min: [x as f32 * size, y as f32 * size, z],
+                        size: [size, size],
+                        color: [heat.r, heat.g, heat.b, PRESSURE_OVERLAY_ALPHA],
+                    });
+                }
+            }
+            if overlays.valves {
+                frame.overlays.extend(layer.blocks.keys().map(|&(x, y)| OverlayQuad {
+                    min: [x as f32 * size, y as f32 * size, z],
+                    size: [size, size],
+                    color: VALVE_OVERLAY_COLOR,
+                }));
+            }
+        }
+        frame
+    }
+
+    fn block(&self, position: GridCoordinate) -> (u32, u32) {
+        (position.x / self.stride, position.y / self.stride)
+    }
+
+    fn block_size(&self) -> f32 {
+        self.spacing * self.stride as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gcode_types::{Layer, NodeValveState, ValveState};
+
+    fn frame(z: f32, n: u32, nodes: &[(u32, u32, u8)]) -> LayerFrame {
+        let mut layer = Layer::new(z, n);
+        for &(x, y, channel) in nodes {
+            let node = NodeValveState::new(GridCoordinate::new(x, y), vec![ValveState::open(0)]);
+            layer.add_node(node.with_material(channel));
+        }
+        layer.add_node(NodeValveState::new(GridCoordinate::new(9, 9), vec![ValveState::closed(0)]));
+        LayerFrame::from_layer(&layer).unwrap()
+    }
+
+    #[test]
+    fn test_layers_grow_over_their_time_span() {
+        let mut scene = VoxelScene::new(0.5);
+        scene.set_channel_color(1, Color::GREEN);
+        scene.add_layer(&frame(0.2, 0, &[(0, 0, 0), (1, 0, 1)]), 0.0, 2.0);
+        scene.add_layer(&frame(0.4, 1, &[(0, 0, 0)]), 2.0, 2.0);
+        scene.set_pressures(1, &HashMap::from([(GridCoordinate::new(0, 0), 50.0)]));
+        assert_eq!(scene.duration(), 4.0);
+        assert_eq!(scene.completed_layers(2.0), 1);
+
+        // Nothing deposited yet at the start
+        assert!(scene.frame(0.0, Overlays::default()).voxels.is_empty());
+
+        // Halfway through the second layer: the first is complete, the
+        // second half height, and closed nodes deposit nothing
+        let overlays = Overlays { valves: true, pressure: true };
+        let at = scene.frame(3.0, overlays);
+        assert_eq!(at.active_layer, Some(1));
+        assert_eq!(at.voxels.len(), 3);
+        assert_eq!(at.voxels[1].color, [0, 255, 0, 255]);
+        let top = at.voxels[2];
+        assert_eq!(top.min, [0.0, 0.0, 0.2]);
+        assert!((top.size[2] - 0.1).abs() < 1e-6);
+
+        // Heatmap halfway from blue to red, then the valve highlight
+        assert_eq!(at.overlays.len(), 2);
+        assert_eq!(at.overlays[0].color, [127, 0, 127, PRESSURE_OVERLAY_ALPHA]);
+        assert_eq!(at.overlays[1].color, VALVE_OVERLAY_COLOR);
+        assert!((at.overlays[1].min[2] - 0.3).abs() < 1e-6);
+
+        // Overlays only show while a layer deposits
+        let done = scene.frame(10.0, overlays);
+        assert_eq!((done.active_layer, done.overlays.len()), (None, 0));
+        let (min, max) = scene.bounds().unwrap();
+        assert_eq!((min, max), ([0.0, 0.0, 0.0], [1.0, 0.5, 0.4]));
+
+        // Coarsened: both first-layer nodes share one voxel
+        let mut coarse = VoxelScene::new(0.5).with_stride(2);
+        coarse.add_layer(&frame(0.2, 0, &[(0, 0, 0), (1, 0, 1)]), 0.0, 1.0);
+        let voxels = coarse.frame(1.0, Overlays::default()).voxels;
+        assert_eq!(voxels.len(), 1);
+        assert_eq!(voxels[0].size[0], 1.0);
+    }
+}
@@ -0,0 +1,596 @@
+//! Interactive window drawing a [`Visualizer`] with wgpu.
+//!
+//! Each redraw asks the visualizer for the current [`SceneFrame`] and
+//! draws its voxels as instanced unit cubes scaled and placed per
+//! instance, then its overlays as alpha-blended instanced quads on top.
+//! Instance buffers are only rebuilt when the frame changes: while playing,
+//! after an input, or when the scene grows.
+//!
+//! | Input                 | Action                              |
+//! |-----------------------|-------------------------------------|
+//! | Space                 | Play / pause                        |
+//! | Left / Right          | Scrub one second (Shift: ten)       |
+//! | Page Up / Page Down   | Previous / next layer               |
+//! | `[` / `]`             | Halve / double playback speed       |
+//! | Left drag             | Orbit                               |
+//! | Right or middle drag  | Pan                                 |
+//! | Wheel                 | Zoom                                |
+//! | F                     | Fit the print in view               |
+//! | V                     | Toggle the open valve overlay       |
+//! | P                     | Toggle the pressure heatmap         |
+
+use std::sync::Arc;
+use std::time::Instant;
+
+use anyhow::{anyhow, Result};
+use wgpu::util::DeviceExt;
+use winit::event::{ElementState, Event, KeyEvent, MouseButton, MouseScrollDelta, WindowEvent};
+use winit::event_loop::{ControlFlow, EventLoop};
+use winit::keyboard::{KeyCode, ModifiersState, PhysicalKey};
+use winit::window::{Window, WindowBuilder};
+
+use super::{Input, SceneFrame, Visualizer};
+
+/// Radians of orbit per pixel dragged.
+const ORBIT_PER_PIXEL: f32 = 0.005;
+
+/// Camera distance scale per wheel line.
+const ZOOM_PER_LINE: f32 = 0.9;
+
+/// Pixels of touchpad scrolling counted as one wheel line.
+const PIXELS_PER_LINE: f32 = 40.0;
+
+/// Window title.
+const TITLE: &str = "HyperGCode-4D Simulator";
+
+const BACKGROUND: wgpu::Color = wgpu::Color { r: 0.08, g: 0.09, b: 0.11, a: 1.0 };
+
+const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+
+/// Smallest instance buffer allocated (bytes).
+const MIN_INSTANCE_BYTES: u64 = 64 * 1024;
+
+/// Vertices of the unit cube: two triangles per face.
+const CUBE_VERTICES: u32 = 36;
+
+/// Corner and normal of a cube vertex.
+const CUBE_ATTRIBUTES: [wgpu::VertexAttribute; 2] = wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x3];
+
+/// Lowest corner, size and color of a voxel.
+const VOXEL_ATTRIBUTES: [wgpu::VertexAttribute; 3] =
+    wgpu::vertex_attr_array![2 => Float32x3, 3 => Float32x3, 4 => Unorm8x4];
+
+/// Lowest corner, size and color of an overlay quad.
+const OVERLAY_ATTRIBUTES: [wgpu::VertexAttribute; 3] =
+    wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x2, 2 => Unorm8x4];
+
+const SHADER: &str = r#"
+struct Camera {
+    view_projection: mat4x4<f32>,
+};
+
+@group(0) @binding(0) var<uniform> camera: Camera;
+
+struct VertexOut {
+    @builtin(position) position: vec4<f32>,
+    @location(0) color: vec4<f32>,
+};
+
+@vertex
+fn voxel_vs(
+    @location(0) corner: vec3<f32>,
+    @location(1) normal: vec3<f32>,
+    @location(2) origin: vec3<f32>,
+    @location(3) extent: vec3<f32>,
+    @location(4) color: vec4<f32>,
+) -> VertexOut {
+    var out: VertexOut;
+    out.position = camera.view_projection * vec4<f32>(origin + corner * extent, 1.0);
+    // Fixed light from above, so walls and tops read apart
+    let light = max(dot(normal, normalize(vec3<f32>(0.3, -0.5, 0.8))), 0.0);
+    out.color = vec4<f32>(color.rgb * (0.35 + 0.65 * light), color.a);
+    return out;
+}
+
+@vertex
+fn overlay_vs(
+    @builtin(vertex_index) index: u32,
+    @location(0) origin: vec3<f32>,
+    @location(1) extent: vec2<f32>,
+    @location(2) color: vec4<f32>,
+) -> VertexOut {
+    var corners = array<vec2<f32>, 6>(
+        vec2<f32>(0.0, 0.0), vec2<f32>(1.0, 0.0), vec2<f32>(1.0, 1.0),
+        vec2<f32>(0.0, 0.0), vec2<f32>(1.0, 1.0), vec2<f32>(0.0, 1.0),
+    );
+    var out: VertexOut;
+    out.position = camera.view_projection * vec4<f32>(origin + vec3<f32>(corners[index] * extent, 0.0), 1.0);
+    out.color = color;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOut) -> @location(0) vec4<f32> {
+    return in.color;
+}
+"#;
+
+/// Opens the window and runs its event loop until the window is closed.
+pub fn run(mut visualizer: Visualizer) -> Result<()> {
+    let event_loop = EventLoop::new()?;
+    event_loop.set_control_flow(ControlFlow::Wait);
+    let window = Arc::new(WindowBuilder::new().with_title(TITLE).build(&event_loop)?);
+    let mut renderer = pollster::block_on(Renderer::new(window.clone()))?;
+    renderer.upload(&visualizer.current_frame());
+
+    let mut modifiers = ModifiersState::empty();
+    let mut drag: Option<MouseButton> = None;
+    let mut cursor: Option<(f64, f64)> = None;
+    let mut last_redraw = Instant::now();
+    let mut changed = false;
+    let mut failure = None;
+
+    event_loop.run(|event, target| {
+        let Event::WindowEvent { event, .. } = event else { return };
+        match event {
+            WindowEvent::CloseRequested => target.exit(),
+            WindowEvent::Resized(size) => {
+                renderer.resize(size.width, size.height);
+                window.request_redraw();
+            }
+            WindowEvent::ModifiersChanged(state) => modifiers = state.state(),
+            WindowEvent::KeyboardInput {
+                event: KeyEvent { physical_key: PhysicalKey::Code(key), state: ElementState::Pressed, .. },
+                ..
+            } => {
+                let Some(input) = key_input(key, modifiers.shift_key(), &visualizer) else { return };
+                let was_playing = visualizer.playback().is_playing();
+                visualizer.handle(input);
+                if !was_playing {
+                    // Play from now, not from the last redraw
+                    last_redraw = Instant::now();
+                }
+                changed = true;
+                window.request_redraw();
+            }
+            WindowEvent::MouseInput { state, button, .. } => {
+                drag = (state == ElementState::Pressed).then_some(button);
+            }
+            WindowEvent::CursorMoved { position, .. } => {
+                if let (Some(button), Some((x, y))) = (drag, cursor) {
+                    let (dx, dy) = ((position.x - x) as f32, (position.y - y) as f32);
+                    let input = match button {
+                        MouseButton::Left => Input::Orbit { yaw: -dx * ORBIT_PER_PIXEL, pitch: dy * ORBIT_PER_PIXEL },
+                        _ => {
+                            let height = window.inner_size().height.max(1) as f32;
+                            Input::Pan { right: -dx / height, up: dy / height }
+                        }
+                    };
+                    visualizer.handle(input);
+                    window.request_redraw();
+                }
+                cursor = Some((position.x, position.y));
+            }
+            WindowEvent::MouseWheel { delta, .. } => {
+                let lines = match delta {
+                    MouseScrollDelta::LineDelta(_, y) => y,
+                    MouseScrollDelta::PixelDelta(pixels) => pixels.y as f32 / PIXELS_PER_LINE,
+                };
+                visualizer.handle(Input::Zoom(ZOOM_PER_LINE.powf(lines)));
+                window.request_redraw();
+            }
+            WindowEvent::RedrawRequested => {
+                let now = Instant::now();
+                changed |= visualizer.advance(now.duration_since(last_redraw).as_secs_f32());
+                last_redraw = now;
+                if changed {
+                    renderer.upload(&visualizer.current_frame());
+                    changed = false;
+                }
+                if let Err(e) = renderer.draw(&visualizer) {
+                    failure = Some(e);
+                    target.exit();
+                }
+                if visualizer.playback().is_playing() {
+                    window.request_redraw();
+                }
+            }
+            _ => {}
+        }
+    })?;
+    failure.map_or(Ok(()), Err)
+}
+
+/// Input for a key press, per the table in the module documentation.
+fn key_input(key: KeyCode, shift: bool, visualizer: &Visualizer) -> Option<Input> {
+    let scrub = if shift { 10.0 } else { 1.0 };
+    let speed = visualizer.playback().speed();
+    Some(match key {
+        KeyCode::Space => Input::TogglePlay,
+        KeyCode::ArrowLeft => Input::Scrub(-scrub),
+        KeyCode::ArrowRight => Input::Scrub(scrub),
+        KeyCode::PageUp => Input::StepLayer(-1),
+        KeyCode::PageDown => Input::StepLayer(1),
+        KeyCode::BracketLeft => Input::SetSpeed(speed / 2.0),
+        KeyCode::BracketRight => Input::SetSpeed(speed * 2.0),
+        KeyCode::KeyF => Input::FitView,
+        KeyCode::KeyV => Input::ToggleValves,
+        KeyCode::KeyP => Input::TogglePressure,
+        _ => return None,
+    })
+}
+
+/// GPU pipelines and buffers for one window.
+struct Renderer {
+    surface: wgpu::Surface<'static>,
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    config: wgpu::SurfaceConfiguration,
+    depth: wgpu::TextureView,
+    voxel_pipeline: wgpu::RenderPipeline,
+    overlay_pipeline: wgpu::RenderPipeline,
+    /// View-projection matrix
+    camera_buffer: wgpu::Buffer,
+    camera_bind_group: wgpu::BindGroup,
+    /// Unit cube vertices with normals
+    cube: wgpu::Buffer,
+    voxel_instances: wgpu::Buffer,
+    voxel_count: u32,
+    overlay_instances: wgpu::Buffer,
+    overlay_count: u32,
+}
+
+impl Renderer {
+    async fn new(window: Arc<Window>) -> Result<Self> {
+        let size = window.inner_size();
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor::default());
+        let surface = instance.create_surface(window)?;
+        let adapter = instance
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                compatible_surface: Some(&surface),
+                ..Default::default()
+            })
+            .await
+            .ok_or_else(|| anyhow!("No graphics adapter can draw to the window"))?;
+        let (device, queue) = adapter.request_device(&wgpu::DeviceDescriptor::default(), None).await?;
+
+        let capabilities = surface.get_capabilities(&adapter);
+        // Scene colors are sRGB bytes already; a linear target shows them as given
+        let format = capabilities
+            .formats
+            .iter()
+            .copied()
+            .find(|f| !f.is_srgb())
+            .or_else(|| capabilities.formats.first().copied())
+            .ok_or_else(|| anyhow!("The window surface has no usable format"))?;
+        let config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            format,
+            width: size.width.max(1),
+            height: size.height.max(1),
+            present_mode: wgpu::PresentMode::AutoVsync,
+            desired_maximum_frame_latency: 2,
+            alpha_mode: capabilities.alpha_modes[0],
+            view_formats: Vec::new(),
+        };
+        surface.configure(&device, &config);
+        let depth = depth_view(&device, &config);
+
+        let camera_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("camera"),
+            size: std::mem::size_of::<[[f32; 4]; 4]>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let camera_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("camera"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+        let camera_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("camera"),
+            layout: &camera_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: camera_buffer.as_entire_binding(),
+            }],
+        });
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("scene"),
+            source: wgpu::ShaderSource::Wgsl(SHADER.into()),
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("scene"),
+            bind_group_layouts: &[&camera_layout],
+            push_constant_ranges: &[],
+        });
+        let voxel_pipeline = pipeline(
+            &device,
+            &layout,
+            &shader,
+            format,
+            PipelineKind::Voxels,
+            &[
+                wgpu::VertexBufferLayout {
+                    array_stride: 6 * 4,
+                    step_mode: wgpu::VertexStepMode::Vertex,
+                    attributes: &CUBE_ATTRIBUTES,
+                },
+                wgpu::VertexBufferLayout {
+                    array_stride: 6 * 4 + 4,
+                    step_mode: wgpu::VertexStepMode::Instance,
+                    attributes: &VOXEL_ATTRIBUTES,
+                },
+            ],
+        );
+        let overlay_pipeline = pipeline(
+            &device,
+            &layout,
+            &shader,
+            format,
+            PipelineKind::Overlays,
+            &[wgpu::VertexBufferLayout {
+                array_stride: 5 * 4 + 4,
+                step_mode: wgpu::VertexStepMode::Instance,
+                attributes: &OVERLAY_ATTRIBUTES,
+            }],
+        );
+
+        let cube = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("cube"),
+            contents: &f32_bytes(&cube_vertices()),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+        let voxel_instances = instance_buffer(&device, "voxels", 0);
+        let overlay_instances = instance_buffer(&device, "overlays", 0);
+
+        Ok(Self {
+            surface,
+            device,
+            queue,
+            config,
+            depth,
+            voxel_pipeline,
+            overlay_pipeline,
+            camera_buffer,
+            camera_bind_group,
+            cube,
+            voxel_instances,
+            voxel_count: 0,
+            overlay_instances,
+            overlay_count: 0,
+        })
+    }
+
+    /// Recreates the surface and depth buffer for a new window size.
+    fn resize(&mut self, width: u32, height: u32) {
+        // Minimized windows report zero; keep the last size until restored
+        if width == 0 || height == 0 {
+            return;
+        }
+        self.config.width = width;
+        self.config.height = height;
+        self.surface.configure(&self.device, &self.config);
+        self.depth = depth_view(&self.device, &self.config);
+    }
+
+    /// Replaces the instance buffers with a frame's voxels and overlays,
+    /// growing them when they are too small.
+    fn upload(&mut self, frame: &SceneFrame) {
+        let mut voxels = Vec::with_capacity(frame.voxels.len() * 28);
+        for voxel in &frame.voxels {
+            voxels.extend(f32_bytes(voxel.min.iter().chain(&voxel.size)));
+            voxels.extend_from_slice(&voxel.color);
+        }
+        let mut overlays = Vec::with_capacity(frame.overlays.len() * 24);
+        for quad in &frame.overlays {
+            overlays.extend(f32_bytes(quad.min.iter().chain(&quad.size)));
+            overlays.extend_from_slice(&quad.color);
+        }
+        write_instances(&self.device, &self.queue, &mut self.voxel_instances, "voxels", &voxels);
+        write_instances(&self.device, &self.queue, &mut self.overlay_instances, "overlays", &overlays);
+        self.voxel_count = frame.voxels.len() as u32;
+        self.overlay_count = frame.overlays.len() as u32;
+    }
+
+    /// Draws the uploaded frame from the visualizer's camera.
+    fn draw(&mut self, visualizer: &Visualizer) -> Result<()> {
+        let aspect = self.config.width as f32 / self.config.height as f32;
+        let view_projection = visualizer.camera().view_projection(aspect);
+        self.queue
+            .write_buffer(&self.camera_buffer, 0, &f32_bytes(view_projection.iter().flatten()));
+
+        let output = match self.surface.get_current_texture() {
+            Ok(output) => output,
+            Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {
+                self.surface.configure(&self.device, &self.config);
+                self.surface.get_current_texture()?
+            }
+            // The compositor is busy; the next redraw tries again
+            Err(wgpu::SurfaceError::Timeout) => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
+        let view = output.texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("frame") });
+        {
+            let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("scene"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(BACKGROUND),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &self.depth,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: wgpu::StoreOp::Discard,
+                    }),
+                    stencil_ops: None,
+                }),
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+            pass.set_bind_group(0, &self.camera_bind_group, &[]);
+            // Opaque voxels with depth writes, then overlays blended without
+            if self.voxel_count > 0 {
+                pass.set_pipeline(&self.voxel_pipeline);
+                pass.set_vertex_buffer(0, self.cube.slice(..));
+                pass.set_vertex_buffer(1, self.voxel_instances.slice(..));
+                pass.draw(0..CUBE_VERTICES, 0..self.voxel_count);
+            }
+            if self.overlay_count > 0 {
+                pass.set_pipeline(&self.overlay_pipeline);
+                pass.set_vertex_buffer(0, self.overlay_instances.slice(..));
+                pass.draw(0..6, 0..self.overlay_count);
+            }
+        }
+        self.queue.submit([encoder.finish()]);
+        output.present();
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PipelineKind {
+    /// Culled, depth-tested and depth-written cubes
+    Voxels,
+    /// Quads blended over everything, since they mark the layer on top
+    Overlays,
+}
+
+fn pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    format: wgpu::TextureFormat,
+    kind: PipelineKind,
+    buffers: &[wgpu::VertexBufferLayout],
+) -> wgpu::RenderPipeline {
+    let voxels = kind == PipelineKind::Voxels;
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some(if voxels { "voxels" } else { "overlays" }),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: if voxels { "voxel_vs" } else { "overlay_vs" },
+            buffers,
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point: "fs_main",
+            targets: &[Some(wgpu::ColorTargetState {
+                format,
+                blend: Some(if voxels { wgpu::BlendState::REPLACE } else { wgpu::BlendState::ALPHA_BLENDING }),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+        }),
+        primitive: wgpu::PrimitiveState {
+            cull_mode: voxels.then_some(wgpu::Face::Back),
+            ..Default::default()
+        },
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: DEPTH_FORMAT,
+            depth_write_enabled: voxels,
+            depth_compare: if voxels { wgpu::CompareFunction::Less } else { wgpu::CompareFunction::Always },
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        }),
+        multisample: wgpu::MultisampleState::default(),
+        multiview: None,
+    })
+}
+
+fn depth_view(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) -> wgpu::TextureView {
+    device
+        .create_texture(&wgpu::TextureDescriptor {
+            label: Some("depth"),
+            size: wgpu::Extent3d {
+                width: config.width,
+                height: config.height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: DEPTH_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        })
+        .create_view(&wgpu::TextureViewDescriptor::default())
+}
+
+fn instance_buffer(device: &wgpu::Device, label: &str, bytes: u64) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some(label),
+        size: bytes.max(MIN_INSTANCE_BYTES),
+        usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+/// Writes instance data, first replacing the buffer with one twice the
+/// size needed when it does not fit.
+fn write_instances(device: &wgpu::Device, queue: &wgpu::Queue, buffer: &mut wgpu::Buffer, label: &str, data: &[u8]) {
+    if data.len() as u64 > buffer.size() {
+        *buffer = instance_buffer(device, label, data.len() as u64 * 2);
+    }
+    if !data.is_empty() {
+        queue.write_buffer(buffer, 0, data);
+    }
+}
+
+/// Unit cube as corner and normal per vertex, counter-clockwise seen from
+/// outside.
+fn cube_vertices() -> Vec<f32> {
+    let mut out = Vec::with_capacity(CUBE_VERTICES as usize * 6);
+    for axis in 0..3 {
+        // The face spans the next two axes, whose cross product is +axis
+        let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+        for side in [0.0f32, 1.0] {
+            let mut normal = [0.0; 3];
+            normal[axis] = side * 2.0 - 1.0;
+            let corner = |a: f32, b: f32| {
+                let mut point = [0.0; 3];
+                point[axis] = side;
+                point[u] = a;
+                point[v] = b;
+                point
+            };
+            let mut face = [
+                corner(0.0, 0.0),
+                corner(1.0, 0.0),
+                corner(1.0, 1.0),
+                corner(0.0, 0.0),
+                corner(1.0, 1.0),
+                corner(0.0, 1.0),
+            ];
+            if side == 0.0 {
+                face.reverse();
+            }
+            for point in face {
+                out.extend_from_slice(&point);
+                out.extend_from_slice(&normal);
+            }
+        }
+    }
+    out
+}
+
+fn f32_bytes<'a>(values: impl IntoIterator<Item = &'a f32>) -> Vec<u8> {
+    values.into_iter().flat_map(|v| v.to_le_bytes()).collect()
+}
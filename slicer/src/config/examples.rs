@@ -0,0 +1,575 @@
+//! Example configurations for the stock printer models.
+//!
+//! `hg4d-slicer init <model>` writes these as a starting point. Every file
+//! is generated from the same model specification, so valve node counts
+//! always match the build volume and grid spacing and the printer file
+//! passes `PrinterConfig::validate()` as written.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+
+use config_types::{
+    AdhesionSettings, BarrierTimeoutAction, BedHeating, BuildVolume, Celsius, ChamberHeating,
+    ChannelZoneMapping, CoolingParameters, CubicMm, DeadVolumeSettings, DisconnectAction, DryingParameters,
+    ExtruderConfig, ExtruderType, ExtrusionParameters, HeartbeatConfig, HomingConfig, HostTelemetryConfig,
+    CompatibilityMatrix, InfillPattern, InfillSettings,
+    InspectionSettings, InjectionPoint, ManifoldHeating, MaterialProfile, MaterialProperties, MaterialSystemConfig,
+    MaterialType, MotionConfig, MultiMaterialSettings, PidParameters, PressureChannelConfig,
+    PressureConfig, PressureRegulationType, PressureSensor, PrintSettings, PrinterConfig, Psi,
+    PrinterMetadata, PrinterModel, PurgeParameters, PurgeStrategy, PurgeTowerSettings,
+    RegulatorOutput, SafetyLimits, SensorBus, SensorCalibration, SensorDefinition, SensorType,
+    ShellSettings, SkirtSettings, SpeedSettings, StepperDriverConfig, SubFrameConfig, SupportSettings,
+    ThermalConfig, ThermalZone, ValveArrayConfig, ValveDriverConfig, ValveType, ValveVerificationConfig, ZAxisConfig,
+};
+
+/// Vent solenoid GPIOs (BCM) for channels 0-3.
+const VENT_PINS: [u8; 4] = [5, 6, 13, 19];
+
+/// Zone heater SSR GPIOs (BCM) for channels 0-3.
+const HEATER_PINS: [u8; 4] = [12, 16, 20, 21];
+
+/// Bed and chamber heater SSR GPIOs (BCM).
+const BED_HEATER_PIN: u8 = 26;
+const CHAMBER_HEATER_PIN: u8 = 25;
+
+/// Sensor zones, and ADC channels, of the bed and chamber thermistors;
+/// zones 0-3 are the channel zones.
+const BED_SENSOR_ZONE: u8 = 4;
+const CHAMBER_SENSOR_ZONE: u8 = 5;
+
+/// Hardware that differs between the stock models.
+struct ModelSpec {
+    build_volume: (f32, f32, f32),
+    grid_spacing: f32,
+    channels: u8,
+    valves_per_node: u8,
+    valve_type: ValveType,
+    response_time_ms: f32,
+    max_switching_freq: f32,
+    dead_volume: f32,
+    zone_max_temp: f32,
+    zone_power_watts: f32,
+    manifold: bool,
+    chamber: bool,
+    screw_count: u8,
+    max_flow_rate: f32,
+    filament_diameter: f32,
+}
+
+impl ModelSpec {
+    fn of(model: PrinterModel) -> Self {
+        match model {
+            PrinterModel::HyperCubeMini | PrinterModel::Custom => Self {
+                build_volume: (150.0, 150.0, 150.0),
+                grid_spacing: 0.5,
+                channels: 1,
+                valves_per_node: 4,
+                valve_type: ValveType::Electromagnetic,
+                response_time_ms: 5.0,
+                max_switching_freq: 100.0,
+                dead_volume: 0.05,
+                zone_max_temp: 260.0,
+                zone_power_watts: 40.0,
+                manifold: false,
+                chamber: false,
+                screw_count: 1,
+                max_flow_rate: 15.0,
+                filament_diameter: 1.75,
+            },
+            PrinterModel::HyperCubeStandard => Self {
+                build_volume: (250.0, 250.0, 250.0),
+                grid_spacing: 0.5,
+                channels: 2,
+                valves_per_node: 4,
+                valve_type: ValveType::PneumaticSolenoid,
+                response_time_ms: 3.0,
+                max_switching_freq: 200.0,
+                dead_volume: 0.05,
+                zone_max_temp: 280.0,
+                zone_power_watts: 60.0,
+                manifold: true,
+                chamber: false,
+                screw_count: 2,
+                max_flow_rate: 20.0,
+                filament_diameter: 1.75,
+            },
+            PrinterModel::HyperCubePro => Self {
+                build_volume: (300.0, 300.0, 400.0),
+                grid_spacing: 0.25,
+                channels: 4,
+                valves_per_node: 6,
+                valve_type: ValveType::Piezoelectric,
+                response_time_ms: 1.0,
+                max_switching_freq: 500.0,
+                dead_volume: 0.02,
+                zone_max_temp: 300.0,
+                zone_power_watts: 80.0,
+                manifold: true,
+                chamber: true,
+                screw_count: 4,
+                max_flow_rate: 30.0,
+                filament_diameter: 1.75,
+            },
+            PrinterModel::HyperCubeIndustrial => Self {
+                build_volume: (500.0, 500.0, 600.0),
+                grid_spacing: 0.5,
+                channels: 4,
+                valves_per_node: 6,
+                valve_type: ValveType::Piezoelectric,
+                response_time_ms: 1.0,
+                max_switching_freq: 500.0,
+                dead_volume: 0.03,
+                zone_max_temp: 350.0,
+                zone_power_watts: 150.0,
+                manifold: true,
+                chamber: true,
+                screw_count: 4,
+                max_flow_rate: 50.0,
+                filament_diameter: 2.85,
+            },
+        }
+    }
+}
+
+/// A complete, consistent set of configuration files for one model.
+#[derive(Debug, Clone)]
+pub struct ExampleConfigs {
+    pub printer: PrinterConfig,
+    pub settings: PrintSettings,
+    /// Material profiles with their file stem
+    pub materials: Vec<(String, MaterialProfile)>,
+}
+
+impl ExampleConfigs {
+    /// Generates and validates the configuration set for a model.
+    pub fn for_model(model: PrinterModel) -> Result<Self> {
+        let spec = ModelSpec::of(model);
+        let printer = printer_config(model, &spec);
+        printer
+            .validate()
+            .with_context(|| format!("Generated {} configuration is invalid", model.name()))?;
+
+        Ok(Self {
+            settings: print_settings(&spec),
+            materials: vec![("pla".to_string(), pla()), ("petg".to_string(), petg())],
+            printer,
+        })
+    }
+
+    /// Writes printer.toml, settings.toml and materials/*.toml into `dir`.
+    ///
+    /// Existing files are only replaced with `overwrite`. Returns the paths
+    /// written.
+    pub fn write_to(&self, dir: &Path, overwrite: bool) -> Result<Vec<PathBuf>> {
+        let printer_path = dir.join("printer.toml");
+        let settings_path = dir.join("settings.toml");
+        let material_dir = dir.join("materials");
+        let material_paths: Vec<PathBuf> = self
+            .materials
+            .iter()
+            .map(|(stem, _)| material_dir.join(format!("{}.toml", stem)))
+            .collect();
+
+        let mut paths = vec![printer_path.clone(), settings_path.clone()];
+        paths.extend(material_paths.iter().cloned());
+        if !overwrite {
+            if let Some(existing) = paths.iter().find(|p| p.exists()) {
+                anyhow::bail!("{} already exists (use --force to replace it)", existing.display());
+            }
+        }
+
+        std::fs::create_dir_all(&material_dir)
+            .with_context(|| format!("Failed to create {}", material_dir.display()))?;
+
+        self.printer.to_file(&printer_path)?;
+        let settings = toml::to_string_pretty(&self.settings)
+            .context("Failed to serialize print settings")?;
+        std::fs::write(&settings_path, settings)
+            .with_context(|| format!("Failed to write {}", settings_path.display()))?;
+        for ((_, profile), path) in self.materials.iter().zip(&material_paths) {
+            profile.to_file(path)?;
+        }
+
+        Ok(paths)
+    }
+}
+
+fn printer_config(model: PrinterModel, spec: &ModelSpec) -> PrinterConfig {
+    let (x, y, z) = spec.build_volume;
+    let channels = 0..spec.channels;
+
+    let zones: Vec<ThermalZone> = channels
+        .clone()
+        .map(|c| ThermalZone {
+            id: c,
+            name: format!("Channel {} feed", c),
+            min_temp: 20.0,
+            max_temp: spec.zone_max_temp,
+            power_watts: spec.zone_power_watts,
+            pid: PidParameters::default(),
+            heater_pin: HEATER_PINS.get(c as usize).copied(),
+            regions: vec![],
+            thermal_mass: None,
+        })
+        .collect();
+
+    let thermistor = |id: String, zone: u8| SensorDefinition {
+        id,
+        sensor_type: SensorType::Thermistor {
+            zone,
+            beta: 3950.0,
+            nominal_resistance: 100_000.0,
+            nominal_temp: 25.0,
+            pullup_resistance: 4_700.0,
+        },
+        bus: SensorBus::Spi { bus: 0, chip_select: 0 },
+        channel: zone,
+        calibration: SensorCalibration::None,
+    };
+    let mut sensors: Vec<SensorDefinition> = channels
+        .clone()
+        .map(|c| thermistor(format!("zone{}_temp", c), c))
+        .collect();
+    sensors.push(thermistor("bed_temp".to_string(), BED_SENSOR_ZONE));
+    if spec.chamber {
+        sensors.push(thermistor("chamber_temp".to_string(), CHAMBER_SENSOR_ZONE));
+    }
+    sensors.extend(channels.clone().map(|c| SensorDefinition {
+        id: format!("channel{}_pressure", c),
+        sensor_type: SensorType::Pressure { channel: c, range_psi: (0.0, 150.0) },
+        bus: SensorBus::I2c { bus: 1, address: 0x28 + c },
+        channel: 0,
+        calibration: SensorCalibration::None,
+    }));
+
+    let valve_array = ValveArrayConfig {
+        grid_spacing: spec.grid_spacing,
+        // Same rounding as PrinterConfig::grid_x_count/grid_y_count
+        total_nodes: (x / spec.grid_spacing).ceil() as u32 * (y / spec.grid_spacing).ceil() as u32,
+        valves_per_node: spec.valves_per_node,
+        valve_type: spec.valve_type,
+        response_time_ms: spec.response_time_ms,
+        dead_volume: CubicMm::new(spec.dead_volume),
+        max_switching_freq: spec.max_switching_freq,
+        // Spread along the front edge, one per channel
+        injection_points: channels
+            .clone()
+            .map(|c| InjectionPoint {
+                id: c,
+                x: x * (c as f32 + 1.0) / (spec.channels as f32 + 1.0),
+                y: 0.0,
+                material_channel: c,
+            })
+            .collect(),
+        verification: ValveVerificationConfig::default(),
+        drivers: ValveDriverConfig::default(),
+        sub_frames: SubFrameConfig::default(),
+        frame_merge_window_ms: 0.0,
+    };
+
+    let thermal = ThermalConfig {
+        zones,
+        manifold: spec.manifold.then(|| ManifoldHeating {
+            power_watts: spec.zone_power_watts * 2.0,
+            min_temp: 20.0,
+            max_temp: spec.zone_max_temp,
+            pid: PidParameters::default(),
+        }),
+        chamber: spec.chamber.then(|| ChamberHeating {
+            power_watts: 400.0,
+            max_temp: 70.0,
+            required: false,
+            heater_pin: Some(CHAMBER_HEATER_PIN),
+            sensor_zone: Some(CHAMBER_SENSOR_ZONE),
+            pid: PidParameters::default(),
+            thermal_mass: None,
+        }),
+        bed: Some(BedHeating {
+            power_watts: 300.0,
+            min_temp: 20.0,
+            max_temp: 110.0,
+            pid: PidParameters::default(),
+            heater_pin: Some(BED_HEATER_PIN),
+            sensor_zone: BED_SENSOR_ZONE,
+            thermal_mass: None,
+        }),
+        channel_zones: channels
+            .clone()
+            .map(|c| ChannelZoneMapping { channel: c, zones: vec![c] })
+            .collect(),
+        supply_watts: None,
+    };
+
+    let materials = MaterialSystemConfig {
+        channel_count: spec.channels,
+        isolated_channels: true,
+        extruders: channels
+            .clone()
+            .map(|c| ExtruderConfig {
+                id: c,
+                material_channel: c,
+                extruder_type: ExtruderType::DirectDrive,
+                steps_per_mm: 415.0,
+                max_flow_rate: spec.max_flow_rate,
+                filament_diameter: spec.filament_diameter,
+            })
+            .collect(),
+        pressure: PressureConfig {
+            min_pressure: 5.0,
+            max_pressure: 100.0,
+            regulation_type: PressureRegulationType::Pneumatic,
+            sensors: channels
+                .clone()
+                .map(|c| PressureSensor {
+                    id: c,
+                    location: format!("Channel {} regulator outlet", c),
+                    range_psi: (0.0, 150.0),
+                    accuracy_percent: 0.25,
+                })
+                .collect(),
+            channels: channels
+                .clone()
+                .map(|c| PressureChannelConfig {
+                    channel: c,
+                    sensor_id: format!("channel{}_pressure", c),
+                    downstream_sensor_id: None,
+                    output: RegulatorOutput::I2cDac { bus: 1, address: 0x60, dac_channel: c },
+                    vent_pin: VENT_PINS.get(c as usize).copied(),
+                    pid: PidParameters { kp: 0.01, ki: 0.02, kd: 0.0 },
+                    flow_coefficient: 2.0,
+                })
+                .collect(),
+        },
+        runout_sensors: vec![],
+        compatibility: CompatibilityMatrix::default(),
+    };
+
+    let z_axis = ZAxisConfig {
+        lead_screw_pitch: 8.0,
+        screw_count: spec.screw_count,
+        steps_per_mm: 400.0,
+        max_speed: 10.0,
+        max_acceleration: 50.0,
+        driver: StepperDriverConfig::default(),
+    };
+
+    PrinterConfig {
+        model,
+        build_volume: BuildVolume::new(x, y, z),
+        valve_array,
+        thermal,
+        materials,
+        safety: SafetyLimits {
+            max_temperature: Celsius::new(spec.zone_max_temp + 20.0),
+            max_pressure: Psi::new(120.0),
+            max_valve_rate: spec.max_switching_freq,
+            max_z_speed: z_axis.max_speed,
+            thermal_runaway_rate: 5.0,
+            pressure_fault_threshold: 10.0,
+            barrier_timeout_ms: 60_000,
+            barrier_timeout_action: BarrierTimeoutAction::Abort,
+            interlocks: Vec::new(),
+            heartbeat: HeartbeatConfig::default(),
+            disconnect_action: DisconnectAction::Continue,
+        },
+        motion: MotionConfig {
+            z_axis,
+            homing: HomingConfig {
+                homing_speed: 5.0,
+                home_to_max: false,
+                home_at_startup: true,
+            },
+        },
+        metadata: PrinterMetadata {
+            serial_number: None,
+            firmware_version: None,
+            last_calibration: None,
+            notes: Some(format!("Example configuration for the {}", model.name())),
+        },
+        sensors,
+        power_loss: None,
+        bed_level: None,
+        camera: None,
+        host_telemetry: HostTelemetryConfig::default(),
+        macros: Vec::new(),
+    }
+}
+
+fn print_settings(spec: &ModelSpec) -> PrintSettings {
+    let (x, y, _) = spec.build_volume;
+    let layer_height = if spec.grid_spacing < 0.5 { 0.15 } else { 0.2 };
+
+    let multi_material = (spec.channels > 1).then(|| MultiMaterialSettings {
+        material_map: HashMap::from([("model".to_string(), 0), ("support".to_string(), 1)]),
+        purge_strategy: PurgeStrategy::Tower,
+        purge_tower: Some(PurgeTowerSettings {
+            x: x - 30.0,
+            y: y - 30.0,
+            width: 20.0,
+            depth: 20.0,
+        }),
+        allow_incompatible: false,
+    });
+
+    PrintSettings {
+        layer_height,
+        first_layer_height: 0.3,
+        speeds: SpeedSettings {
+            normal_speed: 50.0,
+            first_layer_factor: 0.5,
+            small_perimeter_factor: 0.5,
+        },
+        shells: ShellSettings::default(),
+        infill: InfillSettings {
+            density: 20.0,
+            pattern: InfillPattern::Gyroid,
+            gradient: None,
+        },
+        supports: SupportSettings {
+            enabled: false,
+            material_channel: (spec.channels > 1).then_some(1),
+            density: 15.0,
+        },
+        multi_material,
+        vase: None,
+        dead_volume: DeadVolumeSettings::default(),
+        inspection: InspectionSettings::default(),
+        adhesion: AdhesionSettings {
+            skirt: Some(SkirtSettings::default()),
+            ..AdhesionSettings::default()
+        },
+        modifiers: Vec::new(),
+    }
+}
+
+fn pla() -> MaterialProfile {
+    MaterialProfile {
+        name: "Generic PLA".to_string(),
+        material_type: MaterialType::PLA,
+        temp_range: (190.0, 220.0),
+        optimal_temp: 205.0,
+        bed_temp: 60.0,
+        properties: MaterialProperties {
+            density: 1.24,
+            viscosity: 300.0,
+            glass_transition_temp: 60.0,
+            thermal_conductivity: 0.13,
+            shrinkage: 0.3,
+        },
+        extrusion: ExtrusionParameters {
+            pressure_psi: 40.0,
+            flow_multiplier: 1.0,
+            retraction_distance: 0.5,
+            retraction_speed: 25.0,
+        },
+        purge: PurgeParameters {
+            purge_volume_incoming: 15.0,
+            purge_volume_outgoing: 15.0,
+            purge_temp: None,
+        },
+        cooling: CoolingParameters {
+            min_layer_time: 8.0,
+            requires_cooling: true,
+            initial_fan_speed: 0.0,
+            regular_fan_speed: 100.0,
+        },
+        color: None,
+        drying: None,
+        chamber_temp: None,
+    }
+}
+
+fn petg() -> MaterialProfile {
+    MaterialProfile {
+        name: "Generic PETG".to_string(),
+        material_type: MaterialType::PETG,
+        temp_range: (220.0, 250.0),
+        optimal_temp: 235.0,
+        bed_temp: 80.0,
+        properties: MaterialProperties {
+            density: 1.27,
+            viscosity: 500.0,
+            glass_transition_temp: 80.0,
+            thermal_conductivity: 0.2,
+            shrinkage: 0.4,
+        },
+        extrusion: ExtrusionParameters {
+            pressure_psi: 50.0,
+            flow_multiplier: 0.97,
+            retraction_distance: 0.8,
+            retraction_speed: 20.0,
+        },
+        purge: PurgeParameters {
+            purge_volume_incoming: 20.0,
+            purge_volume_outgoing: 20.0,
+            purge_temp: None,
+        },
+        cooling: CoolingParameters {
+            min_layer_time: 10.0,
+            requires_cooling: true,
+            initial_fan_speed: 0.0,
+            regular_fan_speed: 50.0,
+        },
+        color: None,
+        drying: Some(DryingParameters {
+            max_exposure_hours: 168.0,
+            temperature: 65.0,
+            hours: 4.0,
+        }),
+        chamber_temp: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MODELS: [PrinterModel; 4] = [
+        PrinterModel::HyperCubeMini,
+        PrinterModel::HyperCubeStandard,
+        PrinterModel::HyperCubePro,
+        PrinterModel::HyperCubeIndustrial,
+    ];
+
+    #[test]
+    fn test_every_model_is_valid_and_heats_its_materials() {
+        for model in MODELS {
+            let configs = ExampleConfigs::for_model(model).unwrap();
+            let printer = &configs.printer;
+            assert_eq!(
+                printer.valve_array.total_nodes,
+                printer.grid_x_count() * printer.grid_y_count()
+            );
+            for (_, profile) in &configs.materials {
+                for channel in 0..printer.materials.channel_count {
+                    printer
+                        .thermal
+                        .plan_zone_temperatures(&[(channel, profile)])
+                        .unwrap();
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_written_files_load_back() {
+        let dir = std::env::temp_dir().join(format!("hg4d-init-{}", std::process::id()));
+        let configs = ExampleConfigs::for_model(PrinterModel::HyperCubePro).unwrap();
+        let paths = configs.write_to(&dir, true).unwrap();
+        assert_eq!(paths.len(), 4);
+        assert!(configs.write_to(&dir, false).is_err());
+
+        let printer = PrinterConfig::from_file(dir.join("printer.toml")).unwrap();
+        printer.validate().unwrap();
+        assert_eq!(printer.valve_array.total_nodes, 1200 * 1200);
+        let settings: PrintSettings =
+            toml::from_str(&std::fs::read_to_string(dir.join("settings.toml")).unwrap()).unwrap();
+        assert_eq!(settings.layer_height, 0.15);
+        let petg = MaterialProfile::from_file(dir.join("materials/petg.toml")).unwrap();
+        assert_eq!(petg.material_type, MaterialType::PETG);
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -1,6 +1,6 @@
-use config_types::{PrinterConfig, PrintSettings, MaterialProfile};
+use config_types::{PrinterConfig, PrintSettings, MaterialProfile, PresetLibrary};
 use std::path::Path;
-use anyhow::Result;
+use anyhow::{Context, Result};
 
 pub struct ConfigLoader;
 
@@ -10,11 +10,19 @@ impl ConfigLoader {
     }
 
     pub fn load_print_settings<P: AsRef<Path>>(path: P) -> Result<PrintSettings> {
-        todo!("Implementation needed: Load print settings from TOML")
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read print settings {}", path.display()))?;
+        toml::from_str(&text).with_context(|| format!("Failed to parse print settings {}", path.display()))
     }
 
     pub fn load_material_profile<P: AsRef<Path>>(path: P) -> Result<MaterialProfile> {
         MaterialProfile::from_file(path)
     }
+
+    /// Loads all settings presets in a directory.
+    pub fn load_presets<P: AsRef<Path>>(dir: P) -> Result<PresetLibrary> {
+        Ok(PresetLibrary::load_dir(dir)?)
+    }
 }
 
@@ -6,13 +6,16 @@
 //! ## Module Organization
 //!
 //! - **printer**: Printer configuration validation
-//! - **settings**: Print settings management
+//! - **settings**: Print settings constraints against the printer and materials
 //! - **loader**: Configuration file loading
+//! - **examples**: Example configurations for the stock printer models
 
 pub mod printer;
 pub mod settings;
 pub mod loader;
+pub mod examples;
 
 pub use printer::PrinterConfigValidator;
-pub use settings::PrintSettingsValidator;
+pub use settings::{PrintSettingsValidator, SettingsIssue, SettingsReport, Severity};
 pub use loader::ConfigLoader;
+pub use examples::ExampleConfigs;
@@ -1,15 +1,541 @@
-use config_types::PrintSettings;
+//! Print settings validation.
+//!
+//! Settings are checked by a list of constraints, each looking at one
+//! relationship between fields, the printer and the loaded materials.
+//! Every constraint runs, so a report lists all problems at once rather
+//! than the first. Findings carry the settings path they concern and, where
+//! there is an obvious fix, a suggestion:
+//!
+//! - **Layer height** against the valve grid spacing: deposits from
+//!   neighbouring nodes only fuse into a continuous layer within a range of
+//!   height-to-spacing ratios
+//! - **First layer height** relative to the layer height
+//! - **Infill and support density** as percentages, and a graded infill's
+//!   range and transition distance
+//! - **Material channels** named by supports, shells and the material map
+//!   must exist on the printer and have a profile loaded
+//! - **Material compatibility**: materials a multi-material job prints
+//!   together, rated by the printer's compatibility matrix; forbidden pairs
+//!   are errors unless `multi_material.allow_incompatible` is set
+//! - **Cooling**: each material's minimum layer time and fan speeds must
+//!   give it a way to cool
+//! - **Modifier meshes**: a positive flow multiplier, and a temperature
+//!   within the range of the material on the modifier's channel
+//!
+//! Constraints that need the printer are skipped by [`PrintSettingsValidator::validate`].
+
+use std::fmt;
+
 use anyhow::Result;
 
+use config_types::{Compatibility, CompatibilityMatrix, MaterialProfile, PrintSettings, PrinterConfig};
+
+use crate::SlicerError;
+
+/// Layer height to grid spacing ratios that still fuse into a layer.
+pub const LAYER_SPACING_RATIO: (f32, f32) = (0.1, 0.8);
+
+/// First layer height as a multiple of the layer height.
+pub const FIRST_LAYER_FACTOR: (f32, f32) = (1.0, 2.0);
+
+/// Slack for comparisons of values read from text files.
+const TOLERANCE: f32 = 1e-4;
+
+/// How serious a finding is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// Slices, but probably not as intended
+    Warning,
+    /// Cannot be sliced
+    Error,
+}
+
+/// One broken constraint.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SettingsIssue {
+    pub severity: Severity,
+    /// Settings path, e.g. `supports.material_channel`
+    pub field: String,
+    pub message: String,
+    /// How to fix it, when there is an obvious fix
+    pub suggestion: Option<String>,
+}
+
+impl SettingsIssue {
+    fn error(field: impl Into<String>, message: String) -> Self {
+        Self { severity: Severity::Error, field: field.into(), message, suggestion: None }
+    }
+
+    fn warning(field: impl Into<String>, message: String) -> Self {
+        Self { severity: Severity::Warning, field: field.into(), message, suggestion: None }
+    }
+
+    fn suggest(mut self, suggestion: String) -> Self {
+        self.suggestion = Some(suggestion);
+        self
+    }
+}
+
+impl fmt::Display for SettingsIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.field, self.message)?;
+        if let Some(suggestion) = &self.suggestion {
+            write!(f, " ({})", suggestion)?;
+        }
+        Ok(())
+    }
+}
+
+/// Everything the constraints found.
+#[derive(Debug, Clone, Default)]
+pub struct SettingsReport {
+    pub issues: Vec<SettingsIssue>,
+}
+
+impl SettingsReport {
+    pub fn errors(&self) -> impl Iterator<Item = &SettingsIssue> {
+        self.issues.iter().filter(|i| i.severity == Severity::Error)
+    }
+
+    pub fn warnings(&self) -> impl Iterator<Item = &SettingsIssue> {
+        self.issues.iter().filter(|i| i.severity == Severity::Warning)
+    }
+
+    /// True if nothing prevents slicing.
+    pub fn is_valid(&self) -> bool {
+        self.errors().next().is_none()
+    }
+
+    /// Fails with every error if there are any; warnings are left to the
+    /// caller.
+    pub fn into_result(self) -> Result<()> {
+        if self.is_valid() {
+            return Ok(());
+        }
+        let errors: Vec<String> = self.errors().map(|i| i.to_string()).collect();
+        Err(SlicerError::Configuration(format!("Invalid print settings:\n  {}", errors.join("\n  "))).into())
+    }
+}
+
+/// What a constraint gets to look at.
+struct Context<'a> {
+    settings: &'a PrintSettings,
+    printer: Option<&'a PrinterConfig>,
+    materials: &'a [MaterialProfile],
+}
+
+type Constraint = fn(&Context, &mut Vec<SettingsIssue>);
+
+const CONSTRAINTS: &[Constraint] = &[
+    layer_height,
+    first_layer_height,
+    densities,
+    material_channels,
+    material_compatibility,
+    cooling,
+    modifiers,
+];
+
 pub struct PrintSettingsValidator;
 
 impl PrintSettingsValidator {
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// Runs every constraint. Without a printer, constraints on the printer
+    /// are skipped; without materials, those on the materials are.
+    pub fn check(
+        &self,
+        settings: &PrintSettings,
+        printer: Option<&PrinterConfig>,
+        materials: &[MaterialProfile],
+    ) -> SettingsReport {
+        let context = Context { settings, printer, materials };
+        let mut issues = Vec::new();
+        for constraint in CONSTRAINTS {
+            constraint(&context, &mut issues);
+        }
+        issues.sort_by(|a, b| b.severity.cmp(&a.severity));
+        SettingsReport { issues }
+    }
+
     pub fn validate(&self, settings: &PrintSettings) -> Result<()> {
-        todo!("Implementation needed: Validate print settings")
+        self.check(settings, None, &[]).into_result()
     }
 
-    pub fn validate_for_printer(&self, settings: &PrintSettings, printer: &config_types::PrinterConfig) -> Result<()> {
-        todo!("Implementation needed: Validate settings compatible with printer")
+    pub fn validate_for_printer(&self, settings: &PrintSettings, printer: &PrinterConfig) -> Result<()> {
+        self.check(settings, Some(printer), &[]).into_result()
     }
 }
 
+impl Default for PrintSettingsValidator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn layer_height(cx: &Context, issues: &mut Vec<SettingsIssue>) {
+    let height = cx.settings.layer_height;
+    if !(height.is_finite() && height > 0.0) {
+        issues.push(SettingsIssue::error("layer_height", format!("{} is not a positive height", height)));
+        return;
+    }
+    let Some(printer) = cx.printer else {
+        return;
+    };
+    let spacing = printer.valve_array.grid_spacing;
+    let (low, high) = (LAYER_SPACING_RATIO.0 * spacing, LAYER_SPACING_RATIO.1 * spacing);
+    if height > high + TOLERANCE {
+        issues.push(
+            SettingsIssue::error(
+                "layer_height",
+                format!(
+                    "{:.3}mm is too tall for the {:.3}mm valve grid; deposits from neighbouring nodes will not fuse",
+                    height, spacing
+                ),
+            )
+            .suggest(format!("use at most {:.3}mm", high)),
+        );
+    } else if height < low - TOLERANCE {
+        issues.push(
+            SettingsIssue::warning(
+                "layer_height",
+                format!("{:.3}mm is under a tenth of the {:.3}mm valve grid", height, spacing),
+            )
+            .suggest(format!("use at least {:.3}mm", low)),
+        );
+    }
+}
+
+fn first_layer_height(cx: &Context, issues: &mut Vec<SettingsIssue>) {
+    let (first, layer) = (cx.settings.first_layer_height, cx.settings.layer_height);
+    if !(first.is_finite() && first > 0.0) {
+        issues.push(SettingsIssue::error("first_layer_height", format!("{} is not a positive height", first)));
+        return;
+    }
+    if !(layer.is_finite() && layer > 0.0) {
+        return;
+    }
+    let (low, high) = (FIRST_LAYER_FACTOR.0 * layer, FIRST_LAYER_FACTOR.1 * layer);
+    if first > high + TOLERANCE {
+        issues.push(
+            SettingsIssue::error(
+                "first_layer_height",
+                format!("{:.3}mm is more than twice the {:.3}mm layer height", first, layer),
+            )
+            .suggest(format!("use between {:.3}mm and {:.3}mm", low, high)),
+        );
+    } else if first < low - TOLERANCE {
+        issues.push(
+            SettingsIssue::warning(
+                "first_layer_height",
+                format!("{:.3}mm is thinner than the {:.3}mm layers above it; bed adhesion suffers", first, layer),
+            )
+            .suggest(format!("use at least {:.3}mm", low)),
+        );
+    }
+}
+
+fn densities(cx: &Context, issues: &mut Vec<SettingsIssue>) {
+    let mut check = |field: &str, density: f32| {
+        if !(0.0..=100.0).contains(&density) {
+            issues.push(
+                SettingsIssue::error(field, format!("{} is not a percentage", density))
+                    .suggest(format!("use {:.0}", density.clamp(0.0, 100.0))),
+            );
+        }
+    };
+    check("infill.density", cx.settings.infill.density);
+    if let Some(gradient) = &cx.settings.infill.gradient {
+        check("infill.gradient.min_density", gradient.min_density);
+        check("infill.gradient.max_density", gradient.max_density);
+        if gradient.min_density > gradient.max_density {
+            issues.push(
+                SettingsIssue::error(
+                    "infill.gradient.min_density",
+                    format!("{} is above max_density {}", gradient.min_density, gradient.max_density),
+                )
+                .suggest("swap min_density and max_density".to_string()),
+            );
+        }
+        if gradient.transition_distance.is_nan() || gradient.transition_distance <= 0.0 {
+            issues.push(
+                SettingsIssue::error(
+                    "infill.gradient.transition_distance",
+                    format!("{}mm is not a distance", gradient.transition_distance),
+                )
+                .suggest("use a few grid spacings, e.g. 5mm".to_string()),
+            );
+        }
+    }
+    if cx.settings.supports.enabled {
+        check("supports.density", cx.settings.supports.density);
+        if cx.settings.supports.density == 0.0 {
+            issues.push(
+                SettingsIssue::warning("supports.density", "supports are enabled with 0% density".to_string())
+                    .suggest("raise the density or disable supports".to_string()),
+            );
+        }
+    }
+}
+
+/// Channels the settings assign material to, by the field naming them.
+fn used_channels(settings: &PrintSettings) -> Vec<(String, u8)> {
+    let mut used: Vec<(String, u8)> = Vec::new();
+    if settings.supports.enabled {
+        if let Some(channel) = settings.supports.material_channel {
+            used.push(("supports.material_channel".to_string(), channel));
+        }
+    }
+    if let Some(channel) = settings.shells.material_channel {
+        used.push(("shells.material_channel".to_string(), channel));
+    }
+    if !settings.adhesion.is_empty() {
+        if let Some(channel) = settings.adhesion.material_channel {
+            used.push(("adhesion.material_channel".to_string(), channel));
+        }
+    }
+    for (i, modifier) in settings.modifiers.iter().enumerate() {
+        if let Some(channel) = modifier.material_channel {
+            used.push((format!("modifiers[{}].material_channel", i), channel));
+        }
+    }
+    if let Some(multi) = &settings.multi_material {
+        let mut entries: Vec<_> = multi.material_map.iter().collect();
+        entries.sort();
+        for (name, &channel) in entries {
+            used.push((format!("multi_material.material_map.{}", name), channel));
+        }
+    }
+
+    used
+}
+
+fn material_channels(cx: &Context, issues: &mut Vec<SettingsIssue>) {
+    for (field, channel) in used_channels(cx.settings) {
+        if let Some(printer) = cx.printer {
+            let count = printer.materials.channel_count;
+            if channel >= count {
+                let suggestion = match count {
+                    0 => "the printer has no material channels".to_string(),
+                    1 => "use channel 0".to_string(),
+                    n => format!("use a channel from 0 to {}", n - 1),
+                };
+                issues.push(
+                    SettingsIssue::error(field, format!("the printer has no material channel {}", channel))
+                        .suggest(suggestion),
+                );
+                continue;
+            }
+        }
+        if !cx.materials.is_empty() && cx.materials.get(channel as usize).is_none() {
+            issues.push(
+                SettingsIssue::error(field, format!("no material is loaded for channel {}", channel))
+                    .suggest(format!("load a material profile for channel {}", channel)),
+            );
+        }
+    }
+}
+
+fn material_compatibility(cx: &Context, issues: &mut Vec<SettingsIssue>) {
+    // Without a printer the built-in ratings still apply
+    let builtin = CompatibilityMatrix::default();
+    let matrix = cx.printer.map_or(&builtin, |printer| &printer.materials.compatibility);
+    // Regions without an assignment print on channel 0
+    let mut channels: Vec<u8> = used_channels(cx.settings).into_iter().map(|(_, c)| c).collect();
+    channels.push(0);
+    channels.sort_unstable();
+    channels.dedup();
+    let loaded: Vec<(u8, &MaterialProfile)> = channels
+        .iter()
+        .filter_map(|&c| cx.materials.get(c as usize).map(|m| (c, m)))
+        .collect();
+    if loaded.len() < 2 {
+        return;
+    }
+
+    let allow = cx.settings.multi_material.as_ref().is_some_and(|m| m.allow_incompatible);
+    for conflict in matrix.check(&loaded) {
+        let field = format!("materials[{}]", conflict.channels.1);
+        match conflict.level {
+            Compatibility::Forbidden if !allow => issues.push(
+                SettingsIssue::error(field, conflict.to_string())
+                    .suggest("load compatible materials or set multi_material.allow_incompatible".to_string()),
+            ),
+            Compatibility::Forbidden => issues.push(SettingsIssue::warning(
+                field,
+                format!("{} (allowed by multi_material.allow_incompatible)", conflict),
+            )),
+            _ => issues.push(SettingsIssue::warning(field, conflict.to_string())),
+        }
+    }
+}
+
+fn cooling(cx: &Context, issues: &mut Vec<SettingsIssue>) {
+    for (channel, material) in cx.materials.iter().enumerate() {
+        let cooling = &material.cooling;
+        let field = |name: &str| format!("materials[{}].cooling.{}", channel, name);
+        if !(cooling.min_layer_time.is_finite() && cooling.min_layer_time >= 0.0) {
+            issues.push(
+                SettingsIssue::error(
+                    field("min_layer_time"),
+                    format!("{} is not a duration", cooling.min_layer_time),
+                )
+                .suggest("use 0 to disable the minimum".to_string()),
+            );
+            continue;
+        }
+        for (name, speed) in [
+            ("initial_fan_speed", cooling.initial_fan_speed),
+            ("regular_fan_speed", cooling.regular_fan_speed),
+        ] {
+            if !(0.0..=100.0).contains(&speed) {
+                issues.push(
+                    SettingsIssue::error(field(name), format!("{} is not a percentage", speed))
+                        .suggest(format!("use {:.0}", speed.clamp(0.0, 100.0))),
+                );
+            }
+        }
+
+        if cooling.requires_cooling && cooling.min_layer_time == 0.0 {
+            let fan = cooling.regular_fan_speed;
+            if fan == 0.0 {
+                issues.push(
+                    SettingsIssue::error(
+                        field("requires_cooling"),
+                        format!("{} needs cooling but has neither a fan speed nor a minimum layer time", material.name),
+                    )
+                    .suggest("set regular_fan_speed or min_layer_time".to_string()),
+                );
+            } else {
+                issues.push(
+                    SettingsIssue::warning(
+                        field("min_layer_time"),
+                        format!("{} needs cooling but small layers are not slowed down", material.name),
+                    )
+                    .suggest("set a minimum layer time of a few seconds".to_string()),
+                );
+            }
+        } else if !cooling.requires_cooling && cooling.regular_fan_speed > 0.0 {
+            issues.push(SettingsIssue::warning(
+                field("regular_fan_speed"),
+                format!("{} does not need cooling but runs the fan at {:.0}%", material.name, cooling.regular_fan_speed),
+            ));
+        }
+    }
+}
+
+fn modifiers(cx: &Context, issues: &mut Vec<SettingsIssue>) {
+    for (i, modifier) in cx.settings.modifiers.iter().enumerate() {
+        let field = |name: &str| format!("modifiers[{}].{}", i, name);
+        let overrides_nothing = modifier.flow_multiplier.is_none()
+            && modifier.temperature.is_none()
+            && modifier.material_channel.is_none();
+        if overrides_nothing {
+            issues.push(
+                SettingsIssue::warning(field("path"), format!("{} overrides nothing", modifier.path.display()))
+                        .suggest("set a flow multiplier, temperature or material channel".to_string()),
+            );
+        }
+        if let Some(flow) = modifier.flow_multiplier.filter(|f| !f.is_finite() || *f <= 0.0) {
+            issues.push(
+                SettingsIssue::error(field("flow_multiplier"), format!("{} is not a flow multiplier", flow))
+                    .suggest("use 1.0 for the material's own flow".to_string()),
+            );
+        }
+        let Some(temp) = modifier.temperature else { continue };
+        let material = modifier.material_channel.and_then(|c| cx.materials.get(c as usize));
+        match material {
+            _ if !temp.is_finite() || temp <= 0.0 => {
+                let message = format!("{} is not a temperature", temp);
+                issues.push(SettingsIssue::error(field("temperature"), message));
+            }
+            Some(material) if temp < material.temp_range.0 || temp > material.temp_range.1 => {
+                let (min, max) = material.temp_range;
+                let message = format!("{:.0}°C is outside {}'s range of {:.0}-{:.0}°C", temp, material.name, min, max);
+                issues.push(
+                    SettingsIssue::error(field("temperature"), message)
+                        .suggest(format!("use {:.0}", temp.clamp(min, max))),
+                );
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ExampleConfigs;
+    use config_types::{MaterialType, ModifierMesh, PrinterModel};
+
+    #[test]
+    fn test_constraints() {
+        let validator = PrintSettingsValidator::new();
+        for model in [
+            PrinterModel::HyperCubeMini,
+            PrinterModel::HyperCubeStandard,
+            PrinterModel::HyperCubePro,
+            PrinterModel::HyperCubeIndustrial,
+        ] {
+            let configs = ExampleConfigs::for_model(model).unwrap();
+            let materials: Vec<MaterialProfile> = configs.materials.iter().map(|(_, p)| p.clone()).collect();
+            let report = validator.check(&configs.settings, Some(&configs.printer), &materials);
+            assert!(report.issues.is_empty(), "{:?}: {:?}", model, report.issues);
+        }
+
+        let configs = ExampleConfigs::for_model(PrinterModel::HyperCubeMini).unwrap();
+        let mut materials: Vec<MaterialProfile> = configs.materials.iter().map(|(_, p)| p.clone()).collect();
+        materials[0].cooling.min_layer_time = 0.0;
+        materials[0].cooling.regular_fan_speed = 0.0;
+        let mut settings = configs.settings.clone();
+        settings.layer_height = 0.45;
+        settings.first_layer_height = 0.3;
+        settings.infill.density = 120.0;
+        settings.supports.enabled = true;
+        settings.supports.material_channel = Some(configs.printer.materials.channel_count);
+        settings.modifiers.push(ModifierMesh {
+            path: "dense.stl".into(),
+            flow_multiplier: Some(0.0),
+            temperature: Some(400.0),
+            material_channel: Some(0),
+        });
+
+        let report = validator.check(&settings, Some(&configs.printer), &materials);
+        let fields: Vec<(&str, Severity)> = report.issues.iter().map(|i| (i.field.as_str(), i.severity)).collect();
+        assert_eq!(
+            fields,
+            vec![
+                ("layer_height", Severity::Error),
+                ("infill.density", Severity::Error),
+                ("supports.material_channel", Severity::Error),
+                ("materials[0].cooling.requires_cooling", Severity::Error),
+                ("modifiers[0].flow_multiplier", Severity::Error),
+                ("modifiers[0].temperature", Severity::Error),
+                ("first_layer_height", Severity::Warning),
+            ]
+        );
+        assert_eq!(report.issues[0].suggestion.as_deref(), Some("use at most 0.400mm"));
+        assert!(validator.validate(&settings).is_err());
+        assert!(report.into_result().unwrap_err().to_string().contains("supports.material_channel"));
+
+        // Supports in polycarbonate under a PLA model
+        let configs = ExampleConfigs::for_model(PrinterModel::HyperCubeStandard).unwrap();
+        let mut materials: Vec<MaterialProfile> = configs.materials.iter().map(|(_, p)| p.clone()).collect();
+        materials[1].material_type = MaterialType::PC;
+        let report = validator.check(&configs.settings, Some(&configs.printer), &materials);
+        assert_eq!(report.issues.len(), 1);
+        assert_eq!((report.issues[0].field.as_str(), report.issues[0].severity), ("materials[1]", Severity::Error));
+
+        let mut settings = configs.settings.clone();
+        settings.multi_material.as_mut().unwrap().allow_incompatible = true;
+        let report = validator.check(&settings, Some(&configs.printer), &materials);
+        assert!(report.is_valid());
+        assert!(report.issues[0].message.contains("allowed by multi_material.allow_incompatible"));
+
+        // No printer: the built-in ratings
+        let report = validator.check(&configs.settings, None, &materials);
+        assert!(report.issues.iter().any(|i| i.field == "materials[1]" && i.severity == Severity::Error));
+    }
+}
@@ -0,0 +1,241 @@
+//! Skirt, brim and raft.
+//!
+//! All three are built from the footprint of the first printed layer, its
+//! outlines with holes filled, and added to the layer slices before valve
+//! mapping so they deposit like any other region:
+//!
+//! - **Raft**: the footprint grown by `margin_mm`, deposited solid on
+//!   `layers` sacrificial layers. The part is raised by the raft's
+//!   thickness plus `air_gap_mm` and its layers renumbered.
+//! - **Brim**: `loops` rings of valve nodes attached to the outline of the
+//!   first layer, the raft's if there is one.
+//! - **Skirt**: `loops` detached rings `distance_mm` outside the first
+//!   layer and brim, on the first `layers` layers, so channel pressure and
+//!   flow settle before the part's own nodes open.
+//!
+//! Adhesion regions carry no object id and use
+//! [`AdhesionSettings::material_channel`], falling back to the channel of
+//! the part's first region.
+
+use config_types::{AdhesionSettings, PrintSettings};
+
+use crate::core::RegionOverrides;
+use crate::utils::clipping::{self, ClipOp};
+use crate::utils::Polygon;
+use crate::{LayerSlice, Region};
+
+/// Adds skirt, brim and raft to a print's layer slices.
+#[derive(Debug, Clone)]
+pub struct AdhesionGenerator {
+    settings: AdhesionSettings,
+    first_layer_height: f32,
+    layer_height: f32,
+    grid_spacing: f32,
+}
+
+impl AdhesionGenerator {
+    pub fn new(settings: &PrintSettings, grid_spacing: f32) -> Self {
+        Self {
+            settings: settings.adhesion.clone(),
+            first_layer_height: settings.first_layer_height,
+            layer_height: settings.layer_height,
+            grid_spacing,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.settings.is_empty()
+    }
+
+    /// Raft layers added below the part.
+    pub fn raft_layers(&self) -> u32 {
+        self.settings.raft.as_ref().map_or(0, |raft| raft.layers)
+    }
+
+    /// Height the part is raised by: raft thickness plus air gap (mm).
+    pub fn z_offset(&self) -> f32 {
+        match &self.settings.raft {
+            Some(raft) if raft.layers > 0 => {
+                self.first_layer_height + (raft.layers - 1) as f32 * self.layer_height + raft.air_gap_mm
+            }
+            _ => 0.0,
+        }
+    }
+
+    /// Adds the adhesion regions to `slices`, inserting raft layers and
+    /// raising the part above them. Slices without any region to take a
+    /// footprint from are returned unchanged.
+    pub fn apply(&self, slices: Vec<LayerSlice>) -> Vec<LayerSlice> {
+        if self.is_empty() {
+            return slices;
+        }
+        let Some(first) = slices.iter().find(|s| !s.regions.is_empty()) else {
+            return slices;
+        };
+        let channel = self.channel(&first.regions);
+        let plan = self.plan(&first.regions);
+
+        let raft_layers = self.raft_layers();
+        let offset = self.z_offset();
+        let mut layers: Vec<LayerSlice> = (0..raft_layers)
+            .map(|n| LayerSlice {
+                z_height: self.first_layer_height + n as f32 * self.layer_height,
+                layer_number: n,
+                regions: to_regions(&plan.raft, channel),
+            })
+            .collect();
+        layers.extend(slices.into_iter().map(|slice| LayerSlice {
+            z_height: slice.z_height + offset,
+            layer_number: slice.layer_number + raft_layers,
+            regions: slice.regions,
+        }));
+
+        if let Some(layer) = layers.first_mut() {
+            layer.regions.extend(to_regions(&plan.brim, channel));
+        }
+        let skirt_layers = self.settings.skirt.as_ref().map_or(0, |skirt| skirt.layers) as usize;
+        for layer in layers.iter_mut().take(skirt_layers) {
+            layer.regions.extend(to_regions(&plan.skirt, channel));
+        }
+        layers
+    }
+
+    /// Material the adhesion aids deposit around a part whose first layer
+    /// is `first_layer` (mm³), for estimates made without slicing.
+    pub fn volume_mm3(&self, first_layer: &[Region]) -> f32 {
+        if self.is_empty() || first_layer.is_empty() {
+            return 0.0;
+        }
+        let plan = self.plan(first_layer);
+        let area = |rings: &[Polygon]| to_regions(rings, 0).iter().map(Region::area).sum::<f32>();
+
+        let raft = area(&plan.raft) * (self.z_offset() - self.settings.raft.as_ref().map_or(0.0, |r| r.air_gap_mm));
+        let brim = area(&plan.brim) * self.first_layer_height;
+        let skirt = self.settings.skirt.as_ref().map_or(0.0, |skirt| {
+            let height = match skirt.layers {
+                0 => 0.0,
+                n => self.first_layer_height + (n - 1) as f32 * self.layer_height,
+            };
+            area(&plan.skirt) * height
+        });
+        raft + brim + skirt
+    }
+
+    /// Channel the adhesion aids print in: the configured one, else that of
+    /// the first region.
+    pub fn channel(&self, regions: &[Region]) -> u8 {
+        self.settings
+            .material_channel
+            .or_else(|| regions.first().map(|r| r.material_channel))
+            .unwrap_or(0)
+    }
+
+    fn plan(&self, first_layer: &[Region]) -> AdhesionPlan {
+        let outlines: Vec<Polygon> = first_layer.iter().map(Region::outer_polygon).collect();
+        let footprint = clipping::boolean(&outlines, &[], ClipOp::Union);
+
+        let raft = match &self.settings.raft {
+            Some(raft) if raft.layers > 0 => clipping::offset(&footprint, raft.margin_mm),
+            _ => Vec::new(),
+        };
+        let base = if raft.is_empty() { footprint } else { raft.clone() };
+
+        let (brim, skirt_base) = match &self.settings.brim {
+            Some(brim) if brim.loops > 0 => {
+                let outer = clipping::offset(&base, brim.loops as f32 * self.grid_spacing);
+                (clipping::boolean(&outer, &base, ClipOp::Difference), outer)
+            }
+            _ => (Vec::new(), base),
+        };
+
+        let skirt = match &self.settings.skirt {
+            Some(skirt) if skirt.loops > 0 && skirt.layers > 0 => {
+                let inner = clipping::offset(&skirt_base, skirt.distance_mm);
+                let outer = clipping::offset(&inner, skirt.loops as f32 * self.grid_spacing);
+                clipping::boolean(&outer, &inner, ClipOp::Difference)
+            }
+            _ => Vec::new(),
+        };
+
+        AdhesionPlan { raft, brim, skirt }
+    }
+}
+
+/// Adhesion outlines for one print, as oriented rings.
+struct AdhesionPlan {
+    raft: Vec<Polygon>,
+    brim: Vec<Polygon>,
+    skirt: Vec<Polygon>,
+}
+
+fn to_regions(rings: &[Polygon], material_channel: u8) -> Vec<Region> {
+    clipping::group_rings(rings.to_vec())
+        .into_iter()
+        .map(|(outer, holes)| Region {
+            outer: outer.to_tuples(),
+            holes: holes.iter().map(|h| h.to_tuples()).collect(),
+            material_channel,
+            object_id: None,
+            overrides: RegionOverrides::default(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use config_types::{BrimSettings, RaftSettings, SkirtSettings};
+
+    #[test]
+    fn test_raft_brim_and_skirt() {
+        let square = Region {
+            outer: vec![(10.0, 10.0), (20.0, 10.0), (20.0, 20.0), (10.0, 20.0)],
+            holes: vec![vec![(12.0, 12.0), (18.0, 12.0), (18.0, 18.0), (12.0, 18.0)]],
+            material_channel: 1,
+            object_id: Some(0),
+            overrides: RegionOverrides::default(),
+        };
+        let slices: Vec<LayerSlice> = (0..5)
+            .map(|n| LayerSlice {
+                z_height: 0.3 + 0.2 * n as f32,
+                layer_number: n,
+                regions: vec![square.clone()],
+            })
+            .collect();
+        let generator = AdhesionGenerator {
+            settings: AdhesionSettings {
+                skirt: Some(SkirtSettings { distance_mm: 3.0, loops: 2, layers: 2 }),
+                brim: Some(BrimSettings { loops: 4 }),
+                raft: Some(RaftSettings { layers: 2, margin_mm: 2.0, air_gap_mm: 0.1 }),
+                material_channel: None,
+            },
+            first_layer_height: 0.3,
+            layer_height: 0.2,
+            grid_spacing: 0.5,
+        };
+
+        let layers = generator.apply(slices);
+        assert_eq!(layers.len(), 7);
+        assert!((generator.z_offset() - 0.6).abs() < 1e-5);
+        assert!((layers[1].z_height - 0.5).abs() < 1e-5);
+        assert!((layers[2].z_height - 0.9).abs() < 1e-5);
+        assert_eq!(layers[6].layer_number, 6);
+
+        // Raft fills the hole and spreads 2mm: 100 + 4 * 10 * 2 + 4π
+        let raft = &layers[1].regions[0];
+        assert!(raft.holes.is_empty() && raft.object_id.is_none() && raft.material_channel == 1);
+        assert!((raft.area() - (180.0 + 4.0 * std::f32::consts::PI)).abs() < 0.5, "raft {}", raft.area());
+
+        // Layer 0: raft, brim and skirt; layer 1: raft and skirt; part above
+        assert_eq!(layers[0].regions.len(), 3);
+        assert_eq!(layers[1].regions.len(), 2);
+        assert_eq!(layers[2].regions.len(), 1);
+        assert_eq!(layers[2].regions[0].outer, square.outer);
+        let brim = &layers[0].regions[1];
+        assert!(brim.contains_point(7.0, 15.0) && !brim.contains_point(9.0, 15.0));
+        let skirt = &layers[0].regions[2];
+        assert!(skirt.contains_point(2.5, 15.0) && !skirt.contains_point(4.0, 15.0));
+
+        assert!(generator.volume_mm3(&layers[2].regions) > raft.area() * 0.5);
+    }
+}
@@ -0,0 +1,332 @@
+//! Multi-object build plates.
+//!
+//! Several models can be sliced as one job. Each becomes a [`PlateObject`]
+//! with its own id and, optionally, a material channel overriding the
+//! channels the model file assigns. [`Plate::arrange`] packs the objects'
+//! footprints onto the build plate in shelves, largest first, keeping at
+//! least the plate spacing between neighbours, and centres the result.
+//!
+//! Footprints are the objects' XY bounding rectangles. This is conservative
+//! for round or L-shaped parts but guarantees the nozzle-free valve grid
+//! never deposits one object's material into another.
+//!
+//! [`Plate::merge`] combines the placed objects into one mesh with
+//! `face_objects` set, so every sliced region carries its object id into
+//! the `.hg4d` for cancel-object support.
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use config_types::BuildVolume;
+
+use crate::core::transform::Transform;
+use crate::{Mesh, MeshUnits, SlicerError};
+
+/// Default clearance between object footprints (mm).
+pub const DEFAULT_OBJECT_SPACING: f32 = 5.0;
+
+/// One model on the plate.
+#[derive(Debug, Clone)]
+pub struct PlateObject {
+    pub info: ObjectInfo,
+    pub mesh: Mesh,
+}
+
+impl PlateObject {
+    /// XY bounding rectangle (min_x, min_y, max_x, max_y).
+    pub fn footprint(&self) -> (f32, f32, f32, f32) {
+        let (min_x, min_y, _, max_x, max_y, _) = self.mesh.bounding_box();
+        (min_x, min_y, max_x, max_y)
+    }
+}
+
+/// Object table entry written to the `.hg4d` header.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ObjectInfo {
+    /// Id tagged into layers for cancel-object
+    pub id: u32,
+    /// Source file name or label
+    pub name: String,
+    /// Channel the whole object prints with; None keeps the model's own
+    /// per-face channels
+    pub material_channel: Option<u8>,
+}
+
+/// Two objects closer than the plate spacing.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Collision {
+    pub first: u32,
+    pub second: u32,
+    /// Clearance between footprints (mm); negative when they overlap
+    pub gap: f32,
+}
+
+/// Objects sliced together as one job.
+#[derive(Debug, Clone)]
+pub struct Plate {
+    objects: Vec<PlateObject>,
+    spacing: f32,
+}
+
+impl Plate {
+    pub fn new(spacing: f32) -> Self {
+        Self {
+            objects: Vec::new(),
+            spacing: spacing.max(0.0),
+        }
+    }
+
+    /// Adds a placed model and returns its object id.
+    pub fn add(&mut self, name: impl Into<String>, mut mesh: Mesh, material_channel: Option<u8>) -> Result<u32> {
+        mesh.validate()?;
+        mesh.convert_units(MeshUnits::Millimeters);
+        let id = self.objects.len() as u32;
+        self.objects.push(PlateObject {
+            info: ObjectInfo {
+                id,
+                name: name.into(),
+                material_channel,
+            },
+            mesh,
+        });
+        Ok(id)
+    }
+
+    pub fn objects(&self) -> &[PlateObject] {
+        &self.objects
+    }
+
+    pub fn infos(&self) -> Vec<ObjectInfo> {
+        self.objects.iter().map(|o| o.info.clone()).collect()
+    }
+
+    pub fn spacing(&self) -> f32 {
+        self.spacing
+    }
+
+    /// Moves every object so the footprints pack into the usable plate
+    /// area with at least the plate spacing between them.
+    ///
+    /// Objects are placed in shelves, deepest first; each shelf is filled
+    /// left to right and the next starts above the deepest object of the
+    /// previous one. The packed block is then centred on the plate.
+    pub fn arrange(&mut self, volume: &BuildVolume) -> Result<()> {
+        let (usable_x, usable_y, usable_z) = volume.usable_volume();
+        let mut order: Vec<usize> = (0..self.objects.len()).collect();
+        order.sort_by(|&a, &b| {
+            let depth = |i: usize| {
+                let (_, min_y, _, max_y) = self.objects[i].footprint();
+                max_y - min_y
+            };
+            depth(b).total_cmp(&depth(a))
+        });
+
+        // Bottom-left corner of each object's footprint, relative to the
+        // usable area's origin
+        let mut corners = vec![(0.0, 0.0); self.objects.len()];
+        let (mut x, mut y, mut shelf_depth) = (0.0f32, 0.0f32, 0.0f32);
+        let (mut extent_x, mut extent_y) = (0.0f32, 0.0f32);
+        for &i in &order {
+            let object = &self.objects[i];
+            let (min_x, min_y, max_x, max_y) = object.footprint();
+            let (width, depth) = (max_x - min_x, max_y - min_y);
+            let (_, _, min_z, _, _, max_z) = object.mesh.bounding_box();
+            if width > usable_x || depth > usable_y || max_z - min_z > usable_z {
+                return Err(SlicerError::BuildVolumeExceeded(format!(
+                    "'{}' ({:.1} x {:.1} x {:.1} mm) does not fit the {:.1} x {:.1} x {:.1} mm build volume",
+                    object.info.name, width, depth, max_z - min_z, usable_x, usable_y, usable_z
+                ))
+                .into());
+            }
+
+            if x > 0.0 && x + width > usable_x {
+                x = 0.0;
+                y += shelf_depth + self.spacing;
+                shelf_depth = 0.0;
+            }
+            if y + depth > usable_y {
+                return Err(SlicerError::BuildVolumeExceeded(format!(
+                    "{} objects with {:.1} mm spacing do not fit on the plate; '{}' is left over",
+                    self.objects.len(),
+                    self.spacing,
+                    object.info.name
+                ))
+                .into());
+            }
+
+            corners[i] = (x, y);
+            extent_x = extent_x.max(x + width);
+            extent_y = extent_y.max(y + depth);
+            x += width + self.spacing;
+            shelf_depth = shelf_depth.max(depth);
+        }
+
+        let offset_x = volume.margin + (usable_x - extent_x) / 2.0;
+        let offset_y = volume.margin + (usable_y - extent_y) / 2.0;
+        for (object, (cx, cy)) in self.objects.iter_mut().zip(corners) {
+            let (min_x, min_y, _, _) = object.footprint();
+            let dx = offset_x + cx - min_x;
+            let dy = offset_y + cy - min_y;
+            object.mesh.transform(&Transform::translation(dx, dy, 0.0))?;
+            object.mesh.drop_to_plate();
+            debug!("Placed object {} '{}' at +({:.1}, {:.1})", object.info.id, object.info.name, dx, dy);
+        }
+        Ok(())
+    }
+
+    /// Pairs of objects whose footprints are closer than the plate spacing.
+    pub fn collisions(&self) -> Vec<Collision> {
+        let mut collisions = Vec::new();
+        for (i, a) in self.objects.iter().enumerate() {
+            let (a_min_x, a_min_y, a_max_x, a_max_y) = a.footprint();
+            for b in &self.objects[i + 1..] {
+                let (b_min_x, b_min_y, b_max_x, b_max_y) = b.footprint();
+                let gap_x = (b_min_x - a_max_x).max(a_min_x - b_max_x);
+                let gap_y = (b_min_y - a_max_y).max(a_min_y - b_max_y);
+                let gap = gap_x.max(gap_y);
+                if gap < self.spacing {
+                    collisions.push(Collision {
+                        first: a.info.id,
+                        second: b.info.id,
+                        gap,
+                    });
+                }
+            }
+        }
+        collisions
+    }
+
+    /// Fails if an object leaves the usable plate area or two objects are
+    /// closer than the spacing. Used when objects keep their own positions.
+    pub fn check(&self, volume: &BuildVolume) -> Result<()> {
+        for object in &self.objects {
+            let (min_x, min_y, min_z, max_x, max_y, max_z) = object.mesh.bounding_box();
+            let inside = |x: f32, y: f32, z: f32| volume.contains_point(x, y, z);
+            if !inside(min_x, min_y, min_z) || !inside(max_x, max_y, max_z) {
+                return Err(SlicerError::BuildVolumeExceeded(format!(
+                    "'{}' extends outside the usable build volume",
+                    object.info.name
+                ))
+                .into());
+            }
+        }
+        if let Some(c) = self.collisions().first() {
+            let name = |id: u32| self.objects[id as usize].info.name.as_str();
+            if c.gap < 0.0 {
+                bail!("Objects '{}' and '{}' overlap", name(c.first), name(c.second));
+            }
+            bail!(
+                "Objects '{}' and '{}' are {:.1} mm apart; at least {:.1} mm is required",
+                name(c.first),
+                name(c.second),
+                c.gap,
+                self.spacing
+            );
+        }
+        Ok(())
+    }
+
+    /// Combines the objects into one mesh tagged per face with object ids
+    /// and material channels.
+    pub fn merge(&self) -> Result<Mesh> {
+        if self.objects.is_empty() {
+            bail!("Plate has no objects");
+        }
+
+        let channelled = self
+            .objects
+            .iter()
+            .any(|o| o.info.material_channel.is_some() || o.mesh.face_channels.is_some());
+        let with_normals = self.objects.iter().all(|o| o.mesh.normals.is_some());
+
+        let mut mesh = Mesh {
+            vertices: Vec::new(),
+            indices: Vec::new(),
+            normals: with_normals.then(Vec::new),
+            face_channels: channelled.then(Vec::new),
+            face_objects: Some(Vec::new()),
+            units: MeshUnits::Millimeters,
+        };
+        for object in &self.objects {
+            let base = (mesh.vertices.len() / 3) as u32;
+            let faces = object.mesh.indices.len() / 3;
+            mesh.vertices.extend_from_slice(&object.mesh.vertices);
+            mesh.indices.extend(object.mesh.indices.iter().map(|i| i + base));
+            if let (Some(normals), Some(source)) = (mesh.normals.as_mut(), &object.mesh.normals) {
+                normals.extend_from_slice(source);
+            }
+            if let Some(channels) = mesh.face_channels.as_mut() {
+                match (object.info.material_channel, &object.mesh.face_channels) {
+                    (Some(channel), _) => channels.extend(std::iter::repeat(channel).take(faces)),
+                    (None, Some(source)) => channels.extend_from_slice(source),
+                    (None, None) => channels.extend(std::iter::repeat(0).take(faces)),
+                }
+            }
+            if let Some(objects) = mesh.face_objects.as_mut() {
+                objects.extend(std::iter::repeat(object.info.id).take(faces));
+            }
+        }
+        mesh.validate()?;
+        Ok(mesh)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Axis-aligned box with its min corner at the origin.
+    fn cuboid(x: f32, y: f32, z: f32) -> Mesh {
+        let mut vertices = Vec::new();
+        for i in 0..8 {
+            vertices.extend([
+                if i & 1 != 0 { x } else { 0.0 },
+                if i & 2 != 0 { y } else { 0.0 },
+                if i & 4 != 0 { z } else { 0.0 },
+            ]);
+        }
+        Mesh {
+            vertices,
+            indices: vec![
+                0, 2, 1, 1, 2, 3, 4, 5, 6, 5, 7, 6, 0, 1, 4, 1, 5, 4,
+                2, 6, 3, 3, 6, 7, 0, 4, 2, 2, 4, 6, 1, 3, 5, 3, 7, 5,
+            ],
+            normals: None,
+            face_channels: None,
+            face_objects: None,
+            units: MeshUnits::Millimeters,
+        }
+    }
+
+    #[test]
+    fn test_arrange_separates_and_tags_objects() {
+        let volume = BuildVolume { x: 100.0, y: 100.0, z: 50.0, margin: 5.0 };
+        let mut plate = Plate::new(DEFAULT_OBJECT_SPACING);
+        plate.add("left.stl", cuboid(40.0, 30.0, 10.0), None).unwrap();
+        plate.add("right.stl", cuboid(40.0, 20.0, 10.0), Some(2)).unwrap();
+        plate.add("tall.stl", cuboid(50.0, 40.0, 20.0), None).unwrap();
+
+        // All three start stacked at the origin
+        assert_eq!(plate.collisions().len(), 3);
+        assert!(plate.check(&volume).is_err());
+
+        plate.arrange(&volume).unwrap();
+        assert!(plate.collisions().is_empty());
+        plate.check(&volume).unwrap();
+
+        let mesh = plate.merge().unwrap();
+        let faces = mesh.face_objects.as_ref().unwrap();
+        assert_eq!(faces.len(), 36);
+        assert_eq!(&faces[12..24], &[1; 12]);
+        let channels = mesh.face_channels.as_ref().unwrap();
+        assert_eq!(&channels[12..24], &[2; 12]);
+        assert_eq!(&channels[..12], &[0; 12]);
+
+        let mut crowded = Plate::new(DEFAULT_OBJECT_SPACING);
+        for i in 0..5 {
+            crowded.add(format!("block{}.stl", i), cuboid(40.0, 40.0, 5.0), None).unwrap();
+        }
+        assert!(crowded.arrange(&volume).is_err());
+    }
+}
@@ -0,0 +1,302 @@
+//! Fast estimates from a voxelized mesh.
+//!
+//! `estimate` has to answer in seconds, so instead of slicing polygons,
+//! mapping valves and routing, [`MeshVoxelizer`] counts valve nodes
+//! directly. At the middle of every layer each grid row is crossed with the
+//! triangles cut by that plane; the nodes between alternate crossings
+//! (even-odd) lie inside the model, on the channel of the face the span
+//! starts at. The first and last `perimeter_count` nodes of a span are
+//! shell and deposit solid; the rest deposit at the infill density, or for
+//! a graded infill at the density for their distance along the row to the
+//! shell (surfaces and painted regions are not accounted for).
+//!
+//! Triangles are swept in Z order, so each layer only visits the triangles
+//! spanning it.
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use config_types::{InfillGradient, PrintSettings};
+
+use crate::core::gradient::graded_density;
+use crate::core::{LayerWorkload, TimeEstimator};
+use crate::Mesh;
+
+/// Valve nodes one layer deposits.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LayerVoxels {
+    /// Top of the layer (mm)
+    pub z_height: f32,
+    pub thickness: f32,
+    /// Nodes deposited per material channel
+    pub nodes: BTreeMap<u8, u32>,
+}
+
+impl LayerVoxels {
+    pub fn node_count(&self) -> u32 {
+        self.nodes.values().sum()
+    }
+}
+
+/// Result of a voxel estimate.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PrintEstimate {
+    pub layer_count: u32,
+    pub estimated_time: Duration,
+    /// Material deposited per channel (mm³)
+    pub material_mm3: BTreeMap<u8, f32>,
+    /// Nodes deposited over the whole print
+    pub node_count: u64,
+}
+
+/// Counts the valve nodes a mesh fills, layer by layer.
+#[derive(Debug, Clone)]
+pub struct MeshVoxelizer {
+    grid_spacing: f32,
+    layer_height: f32,
+    first_layer_height: f32,
+    perimeter_count: u32,
+    /// Fraction of interior nodes deposited (0-1)
+    infill_fraction: f32,
+    gradient: Option<InfillGradient>,
+}
+
+impl MeshVoxelizer {
+    pub fn new(settings: &PrintSettings, grid_spacing: f32) -> Self {
+        Self {
+            grid_spacing,
+            layer_height: settings.layer_height,
+            first_layer_height: settings.first_layer_height,
+            perimeter_count: settings.shells.perimeter_count,
+            infill_fraction: (settings.infill.density / 100.0).clamp(0.0, 1.0),
+            gradient: settings.infill.gradient.clone(),
+        }
+    }
+
+    /// Layer slabs from the bottom of the mesh up, as (bottom, top); the
+    /// last is cut off at the top of the mesh.
+    pub fn layer_bounds(&self, min_z: f32, max_z: f32) -> Vec<(f32, f32)> {
+        let mut bounds = Vec::new();
+        if self.layer_height <= 0.0 || max_z <= min_z {
+            return bounds;
+        }
+        let mut bottom = min_z;
+        let mut top = min_z + self.first_layer_height.max(self.layer_height * 0.1);
+        loop {
+            bounds.push((bottom, top.min(max_z)));
+            if top >= max_z - 1e-4 {
+                return bounds;
+            }
+            bottom = top;
+            top += self.layer_height;
+        }
+    }
+
+    /// Nodes deposited on every layer of `mesh`.
+    pub fn voxelize(&self, mesh: &Mesh) -> Vec<LayerVoxels> {
+        let spacing = self.grid_spacing;
+        let (_, min_y, min_z, _, max_y, max_z) = mesh.bounding_box();
+        if spacing <= 0.0 || mesh.indices.is_empty() {
+            return Vec::new();
+        }
+
+        let vertex = |i: u32| {
+            let i = i as usize * 3;
+            [mesh.vertices[i], mesh.vertices[i + 1], mesh.vertices[i + 2]]
+        };
+        let mut triangles: Vec<(f32, f32, usize)> = mesh
+            .indices
+            .chunks_exact(3)
+            .enumerate()
+            .map(|(t, tri)| {
+                let z = tri.iter().map(|&i| vertex(i)[2]);
+                let low = z.clone().fold(f32::MAX, f32::min);
+                let high = z.fold(f32::MIN, f32::max);
+                (low, high, t)
+            })
+            .collect();
+        triangles.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let first_row = (min_y / spacing).ceil().max(0.0) as i64;
+        let last_row = (max_y / spacing).floor().max(0.0) as i64;
+        let rows = (last_row - first_row + 1).max(0) as usize;
+
+        let mut next = 0;
+        let mut active: Vec<(f32, usize)> = Vec::new();
+        let mut crossings: Vec<Vec<(f32, u8)>> = vec![Vec::new(); rows];
+        let mut layers = Vec::new();
+
+        for (bottom, top) in self.layer_bounds(min_z, max_z) {
+            let z = (bottom + top) / 2.0;
+            while next < triangles.len() && triangles[next].0 <= z {
+                active.push((triangles[next].1, triangles[next].2));
+                next += 1;
+            }
+            active.retain(|&(high, _)| high > z);
+
+            crossings.iter_mut().for_each(Vec::clear);
+            for &(_, t) in &active {
+                let tri = &mesh.indices[t * 3..t * 3 + 3];
+                let Some((p, q)) = cut(vertex(tri[0]), vertex(tri[1]), vertex(tri[2]), z) else {
+                    continue;
+                };
+                let channel = mesh.face_channels.as_ref().map_or(0, |c| c[t]);
+                let (low, high) = if p.1 <= q.1 { (p, q) } else { (q, p) };
+                // Half-open in Y so a vertex shared by two segments counts once
+                let mut row = ((low.1 / spacing).ceil() as i64).max(first_row);
+                while row <= last_row && (row as f32 * spacing) < high.1 {
+                    let y = row as f32 * spacing;
+                    let x = low.0 + (y - low.1) * (high.0 - low.0) / (high.1 - low.1);
+                    crossings[(row - first_row) as usize].push((x, channel));
+                    row += 1;
+                }
+            }
+
+            let mut nodes: BTreeMap<u8, f32> = BTreeMap::new();
+            for row in &mut crossings {
+                row.sort_by(|a, b| a.0.total_cmp(&b.0));
+                for pair in row.chunks_exact(2) {
+                    let start = (pair[0].0 / spacing).ceil().max(0.0);
+                    let end = (pair[1].0 / spacing).ceil().max(0.0);
+                    let span = (end - start).max(0.0);
+                    let shell = span.min(2.0 * self.perimeter_count as f32);
+                    *nodes.entry(pair[0].1).or_default() += shell + self.infill_nodes(span - shell);
+                }
+            }
+
+            layers.push(LayerVoxels {
+                z_height: top,
+                thickness: top - bottom,
+                nodes: nodes.into_iter().map(|(c, n)| (c, n.round() as u32)).collect(),
+            });
+        }
+        layers
+    }
+
+    /// Nodes deposited across `interior` infill nodes of a row.
+    fn infill_nodes(&self, interior: f32) -> f32 {
+        match &self.gradient {
+            Some(gradient) if gradient.near_walls => {
+                let count = interior as u32;
+                (0..count)
+                    .map(|i| {
+                        let distance = i.min(count - 1 - i) as f32 * self.grid_spacing;
+                        (graded_density(gradient, distance) / 100.0).clamp(0.0, 1.0)
+                    })
+                    .sum()
+            }
+            Some(gradient) => interior * (gradient.min_density / 100.0).clamp(0.0, 1.0),
+            None => interior * self.infill_fraction,
+        }
+    }
+
+    /// Layer count, time and material for `mesh`.
+    pub fn estimate(&self, mesh: &Mesh, time_estimator: &TimeEstimator) -> PrintEstimate {
+        let area = self.grid_spacing * self.grid_spacing;
+        let mut estimate = PrintEstimate::default();
+        for layer in self.voxelize(mesh) {
+            let count = layer.node_count();
+            estimate.layer_count += 1;
+            estimate.node_count += count as u64;
+            for (&channel, &nodes) in &layer.nodes {
+                *estimate.material_mm3.entry(channel).or_default() += nodes as f32 * area * layer.thickness;
+            }
+            estimate.estimated_time += time_estimator.estimate_layer(&LayerWorkload {
+                valve_switches: count,
+                pressure_waits: 1,
+                z_travel_mm: layer.thickness,
+                deposited_volume_mm3: count as f32 * area * layer.thickness,
+            });
+        }
+        estimate
+    }
+}
+
+/// Segment where the plane at `z` cuts a triangle, as (x, y) end points.
+pub(crate) fn cut(a: [f32; 3], b: [f32; 3], c: [f32; 3], z: f32) -> Option<((f32, f32), (f32, f32))> {
+    let mut points = [(0.0, 0.0); 2];
+    let mut found = 0;
+    for (p, q) in [(a, b), (b, c), (c, a)] {
+        if (p[2] <= z) != (q[2] <= z) && found < 2 {
+            let t = (z - p[2]) / (q[2] - p[2]);
+            points[found] = (p[0] + t * (q[0] - p[0]), p[1] + t * (q[1] - p[1]));
+            found += 1;
+        }
+    }
+    (found == 2).then_some((points[0], points[1]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::EstimatorCoefficients;
+    use crate::MeshUnits;
+
+    /// Closed box from (x0, y0, 0) to (x1, y1, h).
+    fn cuboid(x0: f32, y0: f32, x1: f32, y1: f32, h: f32) -> Mesh {
+        let vertices = vec![
+            x0, y0, 0.0, x1, y0, 0.0, x1, y1, 0.0, x0, y1, 0.0, //
+            x0, y0, h, x1, y0, h, x1, y1, h, x0, y1, h,
+        ];
+        let indices = vec![
+            0, 2, 1, 0, 3, 2, 4, 5, 6, 4, 6, 7, 0, 1, 5, 0, 5, 4, //
+            1, 2, 6, 1, 6, 5, 2, 3, 7, 2, 7, 6, 3, 0, 4, 3, 4, 7,
+        ];
+        Mesh {
+            vertices,
+            indices,
+            normals: None,
+            face_channels: None,
+            face_objects: None,
+            units: MeshUnits::Millimeters,
+        }
+    }
+
+    #[test]
+    fn test_box_voxel_counts() {
+        let voxelizer = MeshVoxelizer {
+            grid_spacing: 0.5,
+            layer_height: 0.2,
+            first_layer_height: 0.3,
+            perimeter_count: 2,
+            infill_fraction: 1.0,
+            gradient: None,
+        };
+        let mesh = cuboid(10.1, 10.1, 20.1, 15.1, 2.0);
+        let layers = voxelizer.voxelize(&mesh);
+
+        // 0.3 then 0.2 steps up to 2.0 (the last clipped to the mesh top)
+        assert_eq!(layers.len(), 10);
+        // 20 x 10 nodes at 0.5mm spacing
+        assert!(layers.iter().all(|l| l.nodes[&0] == 200), "{:?}", layers[0].nodes);
+
+        let coefficients = EstimatorCoefficients {
+            layer_overhead: 1.0,
+            per_valve_switch: 0.0,
+            per_pressure_wait: 0.0,
+            z_move_scale: 0.0,
+            per_mm3: 0.0,
+        };
+        let solid = voxelizer.estimate(&mesh, &TimeEstimator::from_parts(coefficients, 10.0, 100.0));
+        assert_eq!(solid.layer_count, 10);
+        assert_eq!(solid.estimated_time, Duration::from_secs(10));
+        let volume = solid.material_mm3[&0];
+        assert!((volume - 100.0).abs() < 1.0, "{}", volume);
+
+        // At 20% only the 4 perimeter nodes of each row are solid
+        let sparse = MeshVoxelizer { infill_fraction: 0.2, ..voxelizer.clone() };
+        assert_eq!(sparse.voxelize(&mesh)[0].nodes[&0], 72);
+
+        // Graded to nothing within one node of the shell: 2 of 16 per row
+        let gradient = InfillGradient {
+            min_density: 0.0,
+            max_density: 100.0,
+            transition_distance: 0.5,
+            near_walls: true,
+            near_surfaces: true,
+            regions_file: None,
+        };
+        let graded = MeshVoxelizer { gradient: Some(gradient), ..voxelizer };
+        assert_eq!(graded.voxelize(&mesh)[0].nodes[&0], 60);
+    }
+}
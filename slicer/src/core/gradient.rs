@@ -0,0 +1,254 @@
+//! Graded infill density.
+//!
+//! With `InfillSettings::gradient` set, infill is densest next to the shell
+//! and the top and bottom surfaces, where it carries the load and holds up
+//! the skin, and thins linearly to `min_density` over the transition
+//! distance. Painted regions from the gradient's region spec file are
+//! filled at their own density regardless of distance.
+//!
+//! The region spec is TOML with one box per `[[region]]`, in print
+//! coordinates (mm); where boxes overlap the later one wins:
+//!
+//! ```toml
+//! [[region]]
+//! min = [10.0, 10.0, 0.0]
+//! max = [30.0, 25.0, 12.0]
+//! density = 80.0 # optional, defaults to max_density
+//! ```
+//!
+//! Distance to the shell is measured by `ShellGenerator` from its insets.
+//! Distance to a surface comes from the [`SliceStack`]: the height to the
+//! nearest layer above or below in which the node lies outside the model.
+
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+
+use config_types::InfillGradient;
+
+use crate::LayerSlice;
+
+/// Density (percentage) `distance` mm from a wall or surface.
+pub fn graded_density(gradient: &InfillGradient, distance: f32) -> f32 {
+    let t = if gradient.transition_distance > 0.0 {
+        (distance / gradient.transition_distance).clamp(0.0, 1.0)
+    } else {
+        1.0
+    };
+    gradient.max_density + (gradient.min_density - gradient.max_density) * t
+}
+
+/// A painted box.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct DensityRegion {
+    pub min: [f32; 3],
+    pub max: [f32; 3],
+    /// Density inside (percentage); None uses the gradient's max_density
+    #[serde(default)]
+    pub density: Option<f32>,
+}
+
+impl DensityRegion {
+    pub fn contains(&self, x: f32, y: f32, z: f32) -> bool {
+        [x, y, z]
+            .iter()
+            .zip(self.min.iter().zip(&self.max))
+            .all(|(v, (lo, hi))| (*lo..=*hi).contains(v))
+    }
+}
+
+/// Painted regions read from a region spec file.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct RegionSpec {
+    #[serde(default, rename = "region")]
+    pub regions: Vec<DensityRegion>,
+}
+
+impl RegionSpec {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read region spec {}", path.display()))?;
+        Self::parse(&text).with_context(|| format!("Invalid region spec {}", path.display()))
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        let spec: RegionSpec = toml::from_str(text)?;
+        for (i, region) in spec.regions.iter().enumerate() {
+            if region.min.iter().zip(&region.max).any(|(lo, hi)| lo > hi) {
+                bail!("region {}: min {:?} is not below max {:?}", i, region.min, region.max);
+            }
+            if let Some(density) = region.density.filter(|d| !(0.0..=100.0).contains(d)) {
+                bail!("region {}: density {} is not a percentage", i, density);
+            }
+        }
+        Ok(spec)
+    }
+
+    /// Painted density at a point: the last region containing it, with
+    /// `default` for regions without their own.
+    pub fn density_at(&self, x: f32, y: f32, z: f32, default: f32) -> Option<f32> {
+        self.regions
+            .iter()
+            .rev()
+            .find(|r| r.contains(x, y, z))
+            .map(|r| r.density.unwrap_or(default))
+    }
+}
+
+/// Infill density for each node, from its distances and painted regions.
+#[derive(Debug, Clone)]
+pub struct DensityField {
+    gradient: InfillGradient,
+    regions: RegionSpec,
+}
+
+impl DensityField {
+    pub fn new(gradient: InfillGradient, regions: RegionSpec) -> Self {
+        Self { gradient, regions }
+    }
+
+    /// Field for `gradient` with its region spec file, if any.
+    pub fn load(gradient: &InfillGradient) -> Result<Self> {
+        let regions = match &gradient.regions_file {
+            Some(path) => RegionSpec::load(path)?,
+            None => RegionSpec::default(),
+        };
+        Ok(Self::new(gradient.clone(), regions))
+    }
+
+    pub fn gradient(&self) -> &InfillGradient {
+        &self.gradient
+    }
+
+    /// Density (percentage) at a point `wall` mm inside the shell and
+    /// `surface` mm from the nearest top or bottom surface; a distance of
+    /// None is beyond the transition. Painted regions apply only with a Z.
+    pub fn density(&self, x: f32, y: f32, z: Option<f32>, wall: Option<f32>, surface: Option<f32>) -> f32 {
+        let g = &self.gradient;
+        if let Some(density) = z.and_then(|z| self.regions.density_at(x, y, z, g.max_density)) {
+            return density;
+        }
+        let nearest = [wall.filter(|_| g.near_walls), surface.filter(|_| g.near_surfaces)]
+            .into_iter()
+            .flatten()
+            .fold(f32::INFINITY, f32::min);
+        graded_density(g, nearest)
+    }
+}
+
+/// A print's layer slices, for the Z of a layer and the distance from a
+/// node to the nearest top or bottom surface.
+#[derive(Debug, Clone, Copy)]
+pub struct SliceStack<'a> {
+    slices: &'a [LayerSlice],
+}
+
+impl<'a> SliceStack<'a> {
+    /// `slices` in layer order.
+    pub fn new(slices: &'a [LayerSlice]) -> Self {
+        Self { slices }
+    }
+
+    pub fn z_of(&self, layer_number: u32) -> Option<f32> {
+        self.index_of(layer_number).map(|i| self.slices[i].z_height)
+    }
+
+    /// Height from `(x, y)` on a layer to the nearest layer above or below
+    /// where that point is outside the model, searching up to `limit` mm.
+    /// Below the first layer and above the last is outside.
+    pub fn surface_distance(&self, layer_number: u32, x: f32, y: f32, limit: f32) -> Option<f32> {
+        let index = self.index_of(layer_number)?;
+        let z = self.slices[index].z_height;
+        let step = match self.slices {
+            [first, second, ..] => (second.z_height - first.z_height).abs(),
+            _ => z.abs(),
+        };
+        let outside = |i: usize| !self.slices[i].regions.iter().any(|r| r.contains_point(x, y));
+
+        let below = (0..index)
+            .rev()
+            .map(|i| (i, z - self.slices[i].z_height))
+            .take_while(|&(_, d)| d <= limit)
+            .find(|&(i, _)| outside(i))
+            .map(|(_, d)| d)
+            .or_else(|| {
+                let d = z - (self.slices[0].z_height - step);
+                (d <= limit).then_some(d)
+            });
+        let above = (index + 1..self.slices.len())
+            .map(|i| (i, self.slices[i].z_height - z))
+            .take_while(|&(_, d)| d <= limit)
+            .find(|&(i, _)| outside(i))
+            .map(|(_, d)| d)
+            .or_else(|| {
+                let d = self.slices[self.slices.len() - 1].z_height + step - z;
+                (d <= limit).then_some(d)
+            });
+        match (below, above) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        }
+    }
+
+    fn index_of(&self, layer_number: u32) -> Option<usize> {
+        self.slices.binary_search_by_key(&layer_number, |s| s.layer_number).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::RegionOverrides;
+    use crate::Region;
+
+    #[test]
+    fn test_density_field_and_surfaces() {
+        let gradient = InfillGradient {
+            min_density: 10.0,
+            max_density: 50.0,
+            transition_distance: 4.0,
+            near_walls: true,
+            near_surfaces: true,
+            regions_file: None,
+        };
+        assert_eq!(graded_density(&gradient, 0.0), 50.0);
+        assert_eq!(graded_density(&gradient, 2.0), 30.0);
+        assert_eq!(graded_density(&gradient, 9.0), 10.0);
+
+        let spec = RegionSpec::parse(
+            "[[region]]\nmin = [0.0, 0.0, 0.0]\nmax = [5.0, 5.0, 5.0]\n\n\
+             [[region]]\nmin = [2.0, 2.0, 0.0]\nmax = [3.0, 3.0, 1.0]\ndensity = 90.0\n",
+        )
+        .unwrap();
+        assert!(RegionSpec::parse("[[region]]\nmin = [1.0, 0.0, 0.0]\nmax = [0.0, 1.0, 1.0]").is_err());
+        let field = DensityField::new(gradient, spec);
+        assert_eq!(field.density(1.0, 1.0, Some(1.0), None, None), 50.0);
+        assert_eq!(field.density(2.5, 2.5, Some(0.5), Some(0.0), None), 90.0);
+        // The nearer of wall and surface sets the density
+        assert_eq!(field.density(10.0, 10.0, Some(10.0), Some(3.0), Some(1.0)), 40.0);
+        assert_eq!(field.density(2.5, 2.5, None, None, None), 10.0);
+
+        // A 10mm square, 20 layers of 1mm, narrowed to 4mm from layer 15 up
+        let square = |size: f32| Region {
+            outer: vec![(0.0, 0.0), (size, 0.0), (size, size), (0.0, size)],
+            holes: vec![],
+            material_channel: 0,
+            object_id: None,
+            overrides: RegionOverrides::default(),
+        };
+        let slices: Vec<LayerSlice> = (0..20)
+            .map(|n| LayerSlice {
+                z_height: (n + 1) as f32,
+                layer_number: n,
+                regions: vec![square(if n < 15 { 10.0 } else { 4.0 })],
+            })
+            .collect();
+        let stack = SliceStack::new(&slices);
+        assert_eq!(stack.z_of(3), Some(4.0));
+        assert_eq!(stack.surface_distance(1, 5.0, 5.0, 4.0), Some(2.0));
+        assert_eq!(stack.surface_distance(12, 8.0, 8.0, 4.0), Some(3.0));
+        assert_eq!(stack.surface_distance(12, 2.0, 2.0, 4.0), None);
+        assert_eq!(stack.surface_distance(19, 2.0, 2.0, 4.0), Some(1.0));
+    }
+}
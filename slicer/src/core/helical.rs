@@ -0,0 +1,199 @@
+//! Spiral (vase) mode slicing.
+//!
+//! Above the solid bottom layers, each layer slice contributes only its
+//! outline. The outline is rasterized into a closed walk of grid nodes and
+//! deposited as one revolution of a helix: a G4L ramp raises Z by one layer
+//! height over the revolution while the nodes open one after another, each
+//! G4D carrying the Z at which it must fire. Every revolution starts at the
+//! node nearest to where the previous one ended, so the wall has no seam.
+
+use std::time::Duration;
+
+use anyhow::Result;
+
+use config_types::{PrintSettings, PrinterConfig};
+use gcode_types::{Command, Coordinate, G4DCommand, G4CCommand, GridCoordinate, ValveState};
+
+use crate::gcode::CommandBuilder;
+use crate::{LayerSlice, SlicerError};
+
+/// Generates helical revolutions from layer outlines.
+#[derive(Debug, Clone)]
+pub struct HelicalSlicer {
+    grid_spacing: f32,
+    /// Time each node along the wall stays open
+    node_time: Duration,
+    valves: Vec<u8>,
+}
+
+impl HelicalSlicer {
+    pub fn new(printer: &PrinterConfig, settings: &PrintSettings) -> Self {
+        let grid_spacing = printer.valve_array.grid_spacing;
+        let speed = settings.speeds.normal_speed.max(f32::EPSILON);
+        Self {
+            grid_spacing,
+            node_time: Duration::from_secs_f32(grid_spacing / speed),
+            valves: (0..printer.valve_array.valves_per_node).collect(),
+        }
+    }
+
+    /// Closed walk of grid nodes along an outline, counter-clockwise,
+    /// starting at the node nearest `start` (or the first vertex).
+    pub fn perimeter_nodes(&self, outline: &[(f32, f32)], start: Option<GridCoordinate>) -> Vec<GridCoordinate> {
+        let mut points: Vec<(f32, f32)> = outline.to_vec();
+        let signed_area: f32 = points
+            .iter()
+            .zip(points.iter().cycle().skip(1))
+            .map(|(a, b)| a.0 * b.1 - b.0 * a.1)
+            .sum();
+        if signed_area < 0.0 {
+            points.reverse();
+        }
+
+        // Sample each edge at half the grid spacing so no node is skipped
+        let step = self.grid_spacing / 2.0;
+        let mut nodes: Vec<GridCoordinate> = Vec::new();
+        for (a, b) in points.iter().zip(points.iter().cycle().skip(1)) {
+            let length = ((b.0 - a.0).powi(2) + (b.1 - a.1).powi(2)).sqrt();
+            let samples = (length / step).ceil().max(1.0) as u32;
+            for i in 0..samples {
+                let t = i as f32 / samples as f32;
+                let node = self.node_at(a.0 + (b.0 - a.0) * t, a.1 + (b.1 - a.1) * t);
+                if nodes.last() != Some(&node) {
+                    nodes.push(node);
+                }
+            }
+        }
+        while nodes.len() > 1 && nodes.first() == nodes.last() {
+            nodes.pop();
+        }
+
+        if let Some(start) = start {
+            if let Some(first) = (0..nodes.len()).min_by_key(|&i| nodes[i].manhattan_distance(&start)) {
+                nodes.rotate_left(first);
+            }
+        }
+        nodes
+    }
+
+    /// Commands for one revolution rising from `z_start` to the slice's Z.
+    ///
+    /// Returns the commands and the last node, which the next revolution
+    /// starts next to.
+    pub fn revolution(
+        &self,
+        slice: &LayerSlice,
+        z_start: f32,
+        start: Option<GridCoordinate>,
+    ) -> Result<(Vec<Command>, GridCoordinate)> {
+        let region = match slice.regions.as_slice() {
+            [region] => region,
+            regions => {
+                return Err(SlicerError::LayerGeneration(format!(
+                    "Vase mode needs a single outline per layer; layer {} has {}",
+                    slice.layer_number,
+                    regions.len()
+                ))
+                .into())
+            }
+        };
+        let nodes = self.perimeter_nodes(&region.outer, start);
+        let Some(&last) = nodes.last() else {
+            return Err(SlicerError::LayerGeneration(format!(
+                "Layer {} outline is empty",
+                slice.layer_number
+            ))
+            .into());
+        };
+
+        let rise = slice.z_height - z_start;
+        let count = nodes.len() as f32;
+        let mut commands = vec![
+            Command::G4C(G4CCommand {
+                color: None,
+                material_channel: Some(region.material_channel),
+                mixing_ratios: None,
+            }),
+            CommandBuilder::layer_ramp(slice.z_height, self.node_time * nodes.len() as u32),
+        ];
+        let mut previous: Option<GridCoordinate> = None;
+        for (k, node) in nodes.iter().enumerate() {
+            let z = z_start + rise * k as f32 / count;
+            commands.push(self.deposit(*node, z, true));
+            if let Some(previous) = previous {
+                commands.push(self.deposit(previous, z, false));
+            }
+            previous = Some(*node);
+        }
+        commands.push(self.deposit(last, slice.z_height, false));
+
+        Ok((commands, last))
+    }
+
+    fn node_at(&self, x: f32, y: f32) -> GridCoordinate {
+        GridCoordinate::new(
+            (x / self.grid_spacing).round().max(0.0) as u32,
+            (y / self.grid_spacing).round().max(0.0) as u32,
+        )
+    }
+
+    fn deposit(&self, node: GridCoordinate, z: f32, open: bool) -> Command {
+        let position = node.to_physical(self.grid_spacing);
+        Command::G4D(G4DCommand {
+            position: Coordinate::new(position.x, position.y, z),
+            valves: self.valves.iter().map(|&index| ValveState { index, open }).collect(),
+            extrusion: None,
+            close_early_ms: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::examples::ExampleConfigs;
+    use crate::core::RegionOverrides;
+    use crate::Region;
+    use config_types::PrinterModel;
+
+    #[test]
+    fn test_revolution_rises_one_layer() {
+        let configs = ExampleConfigs::for_model(PrinterModel::HyperCubeMini).unwrap();
+        let helix = HelicalSlicer::new(&configs.printer, &configs.settings);
+        // Clockwise square, 2mm side on a 0.5mm grid
+        let square = vec![(10.0, 10.0), (10.0, 12.0), (12.0, 12.0), (12.0, 10.0)];
+        let slice = LayerSlice {
+            z_height: 1.2,
+            layer_number: 5,
+            regions: vec![Region {
+                outer: square.clone(),
+                holes: vec![],
+                material_channel: 0,
+                object_id: None,
+                overrides: RegionOverrides::default(),
+            }],
+        };
+
+        let (commands, last) = helix.revolution(&slice, 1.0, None).unwrap();
+        let nodes = helix.perimeter_nodes(&square, None);
+        assert_eq!(nodes.len(), 16);
+        assert_eq!(last, nodes[15]);
+
+        let Command::G4L(ramp) = commands[1] else { panic!("expected ramp") };
+        assert_eq!(ramp.z_height, 1.2);
+        assert_eq!(ramp.ramp_ms, Some(16 * 10));
+        let zs: Vec<f32> = commands
+            .iter()
+            .filter_map(|c| match c {
+                Command::G4D(d) => Some(d.position.z),
+                _ => None,
+            })
+            .collect();
+        assert!(zs.windows(2).all(|w| w[0] <= w[1]));
+        assert!((zs[0] - 1.0).abs() < 1e-6 && (zs[zs.len() - 1] - 1.2).abs() < 1e-6);
+
+        // The next revolution starts beside the previous end
+        let next = helix.perimeter_nodes(&square, Some(last));
+        assert_eq!(next[0], last);
+    }
+}
@@ -2,18 +2,25 @@
 //!
 //! This module implements algorithms for determining optimal layer heights and
 //! computing the intersection of meshes with horizontal planes at each Z height.
+//!
+//! Layer heights are the tops of the layers. Each layer is cut halfway
+//! through its thickness, one section per material channel and object so
+//! regions keep the channel and object of the faces they are cut from.
+
+use std::collections::BTreeSet;
 
-use crate::{Mesh, LayerSlice, Region, SlicerError};
+use crate::core::modifiers::section_faces;
+use crate::{LayerGenerator, LayerSlice, Mesh, Region, SlicerError};
 use config_types::PrintSettings;
 use anyhow::Result;
 
-/// Trait for generating layers from meshes.
-pub trait LayerGenerator: Send + Sync {
-    fn generate_layers(&self, mesh: &Mesh, layer_heights: &[f32]) -> Result<Vec<LayerSlice>>;
-    fn calculate_layer_heights(&self, mesh: &Mesh, settings: &PrintSettings) -> Result<Vec<f32>>;
-}
+/// Tops closer than this to the top of the mesh end the stack (mm).
+const TOP_TOLERANCE: f32 = 1e-4;
 
 /// Adaptive layer generator that adjusts layer height based on geometry.
+///
+/// Nearly flat surfaces, where stair-stepping shows most, get the minimum
+/// height and vertical walls the maximum; equal limits give uniform layers.
 pub struct AdaptiveLayerGenerator {
     min_layer_height: f32,
     max_layer_height: f32,
@@ -28,22 +35,180 @@ impl AdaptiveLayerGenerator {
     }
 
     /// Analyzes mesh geometry to determine optimal layer heights.
-    fn analyze_curvature(&self, mesh: &Mesh) -> Vec<(f32, f32)> {
-        todo!("Implementation needed: Analyze mesh curvature to determine layer height needs")
+    ///
+    /// Returns the wanted layer height in each band of `min_layer_height`
+    /// from the bottom of the mesh: the thinnest asked for by the sloped
+    /// faces crossing the band. Horizontal faces are skipped, they are
+    /// reached exactly by a layer top or not at all.
+    fn analyze_curvature(&self, mesh: &Mesh) -> Vec<f32> {
+        let (_, _, min_z, _, _, max_z) = mesh.bounding_box();
+        let band = |z: f32| ((z - min_z) / self.min_layer_height) as usize;
+        let mut wanted = vec![self.max_layer_height; band(max_z) + 1];
+        let vertex = |i: u32| {
+            let i = i as usize * 3;
+            [mesh.vertices[i], mesh.vertices[i + 1], mesh.vertices[i + 2]]
+        };
+        for tri in mesh.indices.chunks_exact(3) {
+            let (a, b, c) = (vertex(tri[0]), vertex(tri[1]), vertex(tri[2]));
+            let (u, v) = ([b[0] - a[0], b[1] - a[1], b[2] - a[2]], [c[0] - a[0], c[1] - a[1], c[2] - a[2]]);
+            let normal = [u[1] * v[2] - u[2] * v[1], u[2] * v[0] - u[0] * v[2], u[0] * v[1] - u[1] * v[0]];
+            let length = (normal[0].powi(2) + normal[1].powi(2) + normal[2].powi(2)).sqrt();
+            let low = a[2].min(b[2]).min(c[2]);
+            let high = a[2].max(b[2]).max(c[2]);
+            if length <= f32::EPSILON || high - low <= f32::EPSILON {
+                continue;
+            }
+            let flatness = (normal[2] / length).abs();
+            let height = self.max_layer_height - (self.max_layer_height - self.min_layer_height) * flatness;
+            for entry in &mut wanted[band(low)..=band(high)] {
+                *entry = entry.min(height);
+            }
+        }
+        wanted
     }
 
     /// Slices mesh at specific Z height to get cross-section.
     fn slice_at_height(&self, mesh: &Mesh, z: f32) -> Result<Vec<Region>> {
-        todo!("Implementation needed: Compute mesh intersection with horizontal plane at Z")
+        let face_count = mesh.indices.len() / 3;
+        let key = |face: usize| {
+            (
+                mesh.face_channels.as_ref().and_then(|c| c.get(face).copied()).unwrap_or(0),
+                mesh.face_objects.as_ref().and_then(|o| o.get(face).copied()),
+            )
+        };
+        let groups: BTreeSet<(u8, Option<u32>)> = (0..face_count).map(key).collect();
+
+        let mut regions = Vec::new();
+        for (channel, object) in groups {
+            for mut region in section_faces(mesh, z, |face| key(face) == (channel, object)) {
+                region.material_channel = channel;
+                region.object_id = object;
+                regions.push(region);
+            }
+        }
+        Ok(regions)
     }
 }
 
 impl LayerGenerator for AdaptiveLayerGenerator {
     fn generate_layers(&self, mesh: &Mesh, layer_heights: &[f32]) -> Result<Vec<LayerSlice>> {
-        todo!("Implementation needed: Generate layer slices at specified heights")
+        let (_, _, min_z, _, _, _) = mesh.bounding_box();
+        let mut bottom = min_z;
+        let mut slices = Vec::with_capacity(layer_heights.len());
+        for (layer_number, &top) in layer_heights.iter().enumerate() {
+            if top <= bottom {
+                return Err(SlicerError::LayerGeneration(format!(
+                    "Layer {} top at {:.3}mm is not above the layer below ({:.3}mm)",
+                    layer_number, top, bottom
+                ))
+                .into());
+            }
+            slices.push(LayerSlice {
+                z_height: top,
+                layer_number: layer_number as u32,
+                regions: self.slice_at_height(mesh, (bottom + top) / 2.0)?,
+            });
+            bottom = top;
+        }
+        Ok(slices)
     }
 
     fn calculate_layer_heights(&self, mesh: &Mesh, settings: &PrintSettings) -> Result<Vec<f32>> {
-        todo!("Implementation needed: Calculate adaptive layer heights based on geometry")
+        if !(self.min_layer_height > 0.0 && self.min_layer_height <= self.max_layer_height) {
+            return Err(SlicerError::Configuration(format!(
+                "Invalid layer height range {}..{}mm",
+                self.min_layer_height, self.max_layer_height
+            ))
+            .into());
+        }
+        if settings.first_layer_height <= 0.0 {
+            return Err(SlicerError::Configuration(format!(
+                "First layer height must be positive, got {}mm",
+                settings.first_layer_height
+            ))
+            .into());
+        }
+
+        let (_, _, min_z, _, _, max_z) = mesh.bounding_box();
+        if max_z <= min_z {
+            return Ok(Vec::new());
+        }
+        let adaptive = self.min_layer_height < self.max_layer_height;
+        let wanted = if adaptive { self.analyze_curvature(mesh) } else { Vec::new() };
+
+        let mut top = min_z + settings.first_layer_height;
+        let mut heights = vec![top.min(max_z)];
+        while top < max_z - TOP_TOLERANCE {
+            // The thinnest layer any face within reach of the next layer asks for
+            let band = |z: f32| (((z - min_z) / self.min_layer_height) as usize).min(wanted.len());
+            let reach = &wanted[band(top)..(band(top + self.max_layer_height) + 1).min(wanted.len())];
+            let height = reach.iter().copied().fold(self.max_layer_height, f32::min);
+            top += height;
+            heights.push(top.min(max_z));
+        }
+        Ok(heights)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MeshUnits;
+
+    /// Square pyramid on a 10mm base, 10mm tall, over a 10mm cube of
+    /// another object.
+    fn tower() -> Mesh {
+        let vertices = vec![
+            0.0, 0.0, 0.0, 10.0, 0.0, 0.0, 10.0, 10.0, 0.0, 0.0, 10.0, 0.0, //
+            0.0, 0.0, 10.0, 10.0, 0.0, 10.0, 10.0, 10.0, 10.0, 0.0, 10.0, 10.0, //
+            5.0, 5.0, 20.0,
+        ];
+        let indices = vec![
+            // Cube
+            0, 2, 1, 0, 3, 2, 4, 5, 6, 4, 6, 7, 0, 1, 5, 0, 5, 4, //
+            1, 2, 6, 1, 6, 5, 2, 3, 7, 2, 7, 6, 3, 0, 4, 3, 4, 7, //
+            // Pyramid standing on the cube's top
+            4, 6, 5, 4, 7, 6, 4, 5, 8, 5, 6, 8, 6, 7, 8, 7, 4, 8,
+        ];
+        Mesh {
+            vertices,
+            indices,
+            normals: None,
+            face_channels: Some([vec![0; 12], vec![1; 6]].concat()),
+            face_objects: Some([vec![1; 12], vec![2; 6]].concat()),
+            units: MeshUnits::Millimeters,
+        }
+    }
+
+    #[test]
+    fn test_layers_carry_channel_and_object() {
+        let mesh = tower();
+        let mut settings = crate::config::ExampleConfigs::for_model(config_types::PrinterModel::HyperCubeMini)
+            .unwrap()
+            .settings;
+        settings.first_layer_height = 0.3;
+
+        let uniform = AdaptiveLayerGenerator::new(0.5, 0.5);
+        let heights = uniform.calculate_layer_heights(&mesh, &settings).unwrap();
+        assert!((heights[0] - 0.3).abs() < 1e-5 && (heights[1] - 0.8).abs() < 1e-5);
+        assert_eq!(*heights.last().unwrap(), 20.0);
+        assert_eq!(heights.len(), 41);
+
+        let slices = uniform.generate_layers(&mesh, &heights).unwrap();
+        let low = &slices[5].regions;
+        assert_eq!(low.len(), 1);
+        assert_eq!((low[0].material_channel, low[0].object_id), (0, Some(1)));
+        assert!((low[0].area() - 100.0).abs() < 1e-2);
+        let high = &slices[30].regions;
+        assert_eq!(high.len(), 1);
+        assert_eq!((high[0].material_channel, high[0].object_id), (1, Some(2)));
+        assert!(high[0].area() < 100.0);
+
+        // The sloped pyramid gets thinner layers than the cube's walls
+        let adaptive = AdaptiveLayerGenerator::new(0.1, 0.5);
+        let heights = adaptive.calculate_layer_heights(&mesh, &settings).unwrap();
+        let in_range = |from: f32, to: f32| heights.iter().filter(|&&z| z > from && z <= to).count();
+        assert!(in_range(11.0, 19.0) > 2 * in_range(1.0, 9.0));
+        assert!(uniform.generate_layers(&mesh, &[1.0, 1.0]).is_err());
     }
 }
@@ -43,8 +43,9 @@
 //! - Mesh validation can be skipped if file is known-good to save time
 
 // External crate imports - Standard library
+use std::collections::{BTreeMap, HashMap};
 use std::fs::File;
-use std::io::{BufReader, Read, Seek};
+use std::io::{BufRead, BufReader, Read, Seek};
 use std::path::{Path, PathBuf};
 
 // External crate imports - Third party
@@ -144,6 +145,9 @@ impl MeshStats {
     }
 }
 
+/// Display color (sRGB) per material channel, as far as the file gives one.
+pub type ChannelColors = BTreeMap<u8, [u8; 3]>;
+
 /// Format detection result.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum MeshFormat {
@@ -186,31 +190,85 @@ pub struct StlLoader {
 
 impl StlLoader {
     pub fn new() -> Self {
-        todo!("Implementation needed: Create STL loader with default options")
+        Self::with_options(LoadOptions::default())
     }
 
     pub fn with_options(options: LoadOptions) -> Self {
-        todo!("Implementation needed: Create STL loader with custom options")
+        Self { options }
     }
 
     /// Detects whether file is ASCII or binary STL.
+    ///
+    /// A file whose size matches the triangle count in its header is binary
+    /// even if the header starts with `solid`, as some exporters write.
     pub fn detect_stl_format<P: AsRef<Path>>(path: P) -> Result<MeshFormat> {
-        todo!("Implementation needed: Read file header to determine ASCII vs binary")
+        let file = File::open(path.as_ref())
+            .with_context(|| format!("Failed to open {}", path.as_ref().display()))?;
+        let size = file.metadata()?.len();
+        let mut header = Vec::with_capacity(STL_BINARY_HEADER_SIZE);
+        file.take(STL_BINARY_HEADER_SIZE as u64).read_to_end(&mut header)?;
+
+        if header.len() == STL_BINARY_HEADER_SIZE {
+            let count = (&header[80..84]).read_u32::<LittleEndian>()? as u64;
+            if size == STL_BINARY_HEADER_SIZE as u64 + count * STL_BINARY_TRIANGLE_SIZE as u64 {
+                return Ok(MeshFormat::StlBinary);
+            }
+        }
+        let text = String::from_utf8_lossy(&header);
+        if text.trim_start().starts_with("solid") {
+            return Ok(MeshFormat::StlAscii);
+        }
+        if header.len() < STL_BINARY_HEADER_SIZE {
+            bail!(MeshLoadError::InvalidStl("file is shorter than a binary header".to_string()));
+        }
+        bail!(MeshLoadError::InvalidStl("triangle count does not match the file size".to_string()))
     }
 
     /// Loads binary STL format.
     fn load_binary_stl<P: AsRef<Path>>(&self, path: P) -> Result<Mesh> {
-        todo!("Implementation needed: Parse binary STL format")
+        let file = File::open(path.as_ref())?;
+        // Safety: the file is only read, and not expected to change while loading
+        let data = unsafe { Mmap::map(&file)? };
+        let count = (&data[80..84]).read_u32::<LittleEndian>()? as usize;
+
+        let mut vertices = Vec::with_capacity(count * 9);
+        for triangle in data[STL_BINARY_HEADER_SIZE..].chunks_exact(STL_BINARY_TRIANGLE_SIZE).take(count) {
+            // Skip the facet normal; the attribute bytes after the vertices are unused
+            let mut coords = &triangle[12..48];
+            for _ in 0..9 {
+                vertices.push(coords.read_f32::<LittleEndian>()?);
+            }
+        }
+        Ok(unindexed_mesh(vertices))
     }
 
     /// Loads ASCII STL format.
     fn load_ascii_stl<P: AsRef<Path>>(&self, path: P) -> Result<Mesh> {
-        todo!("Implementation needed: Parse ASCII STL format")
+        let reader = BufReader::new(File::open(path.as_ref())?);
+        let mut vertices = Vec::new();
+        for (number, line) in reader.lines().enumerate() {
+            let line = line?;
+            let mut tokens = line.split_whitespace();
+            if tokens.next() != Some("vertex") {
+                continue;
+            }
+            let coords = tokens
+                .map(str::parse::<f32>)
+                .collect::<std::result::Result<Vec<_>, _>>()
+                .ok()
+                .filter(|c| c.len() == 3)
+                .ok_or_else(|| MeshLoadError::InvalidStl(format!("line {}: bad vertex", number + 1)))?;
+            vertices.extend(coords);
+        }
+        if vertices.len() % 9 != 0 {
+            bail!(MeshLoadError::InvalidStl("facet without three vertices".to_string()));
+        }
+        Ok(unindexed_mesh(vertices))
     }
 
     /// Post-processes loaded mesh according to options.
     fn post_process(&self, mesh: &mut Mesh) -> Result<()> {
-        todo!("Implementation needed: Apply scaling, centering, validation, etc.")
+        apply_load_options(mesh, &self.options)
     }
 }
 
@@ -222,7 +280,22 @@ impl Default for StlLoader {
 
 impl ModelLoader for StlLoader {
     fn load<P: AsRef<Path>>(&self, path: P) -> Result<Mesh> {
-        todo!("Implementation needed: Detect STL variant and delegate to appropriate loader")
+        let path = path.as_ref();
+        let format = Self::detect_stl_format(path)?;
+        let mut mesh = match format {
+            MeshFormat::StlBinary => self.load_binary_stl(path)?,
+            _ => self.load_ascii_stl(path)?,
+        };
+        self.post_process(&mut mesh)?;
+
+        info!(
+            "Loaded {} ({}): {} vertices, {} triangles",
+            path.display(),
+            format.name(),
+            mesh.vertices.len() / 3,
+            mesh.indices.len() / 3
+        );
+        Ok(mesh)
     }
 
     fn supported_extensions(&self) -> &[&str] {
@@ -230,43 +303,196 @@ impl ModelLoader for StlLoader {
     }
 
     fn validate<P: AsRef<Path>>(&self, path: P) -> Result<()> {
-        todo!("Implementation needed: Quick validation without full load")
+        Self::detect_stl_format(path).map(|_| ())
     }
 }
 
 /// OBJ file loader with material and color support.
+///
+/// Multi-material OBJ exports carry their material split either as `usemtl`
+/// groups (with colors in the referenced `.mtl` libraries) or as per-vertex
+/// colors (`v x y z r g b`). Each triangle is assigned a material channel,
+/// stored in `Mesh::face_channels`, in this order of preference:
+///
+/// 1. The face's material name, if listed in [`ChannelMapping::by_name`]
+/// 2. The face's color matched to the nearest [`ChannelMapping::palette`]
+///    entry; the average vertex color is used when every vertex has one,
+///    otherwise the material's diffuse color
+/// 3. Order of first appearance of the face's material (or color, for
+///    vertex-colored meshes without materials)
+///
+/// Meshes with a single material and no vertex colors get no channel data.
 pub struct ObjLoader {
     options: LoadOptions,
     /// Whether to load material definitions from .mtl files
     load_materials: bool,
+    mapping: ChannelMapping,
+}
+
+/// Rules for turning OBJ materials and colors into material channels.
+#[derive(Debug, Clone, Default)]
+pub struct ChannelMapping {
+    /// Material name → channel
+    pub by_name: HashMap<String, u8>,
+
+    /// Channel base colors (sRGB); colored faces take the nearest channel
+    pub palette: Vec<(u8, [u8; 3])>,
+}
+
+/// Raw OBJ contents before channel assignment.
+#[derive(Debug, Default)]
+struct ParsedObj {
+    vertices: Vec<f32>,
+    /// Per-vertex color (0-1), when the file provides one
+    vertex_colors: Vec<Option<[f32; 3]>>,
+    indices: Vec<u32>,
+    /// Index into `material_names` per triangle
+    face_materials: Vec<Option<usize>>,
+    material_names: Vec<String>,
+    /// `mtllib` references, relative to the OBJ file
+    material_libraries: Vec<String>,
 }
 
 impl ObjLoader {
     pub fn new() -> Self {
-        todo!("Implementation needed: Create OBJ loader with default options")
+        Self::with_options(LoadOptions::default())
     }
 
     pub fn with_options(options: LoadOptions) -> Self {
-        todo!("Implementation needed: Create OBJ loader with custom options")
+        Self {
+            options,
+            load_materials: true,
+            mapping: ChannelMapping::default(),
+        }
     }
 
     pub fn set_load_materials(&mut self, load: bool) {
-        todo!("Implementation needed: Configure material loading")
+        self.load_materials = load;
+    }
+
+    /// Sets how materials and colors map to channels.
+    pub fn with_channel_mapping(mut self, mapping: ChannelMapping) -> Self {
+        self.mapping = mapping;
+        self
     }
 
     /// Parses OBJ file format.
-    fn parse_obj<P: AsRef<Path>>(&self, path: P) -> Result<Mesh> {
-        todo!("Implementation needed: Parse OBJ vertex and face data")
+    fn parse_obj<P: AsRef<Path>>(&self, path: P) -> Result<ParsedObj> {
+        let file = File::open(path.as_ref())
+            .with_context(|| format!("Failed to open {}", path.as_ref().display()))?;
+        parse_obj_source(BufReader::new(file))
     }
 
     /// Loads associated .mtl material library if present.
     fn load_mtl<P: AsRef<Path>>(&self, path: P) -> Result<Vec<ObjMaterial>> {
-        todo!("Implementation needed: Parse .mtl material definitions")
+        let file = File::open(path.as_ref())
+            .with_context(|| format!("Failed to open material library {}", path.as_ref().display()))?;
+        parse_mtl_source(BufReader::new(file))
     }
 
-    /// Applies materials to mesh regions.
-    fn apply_materials(&self, mesh: &mut Mesh, materials: &[ObjMaterial]) -> Result<()> {
-        todo!("Implementation needed: Map materials to mesh faces")
+    /// Assigns a material channel to every triangle. Returns the color of
+    /// the first colored face of each channel.
+    fn apply_materials(&self, mesh: &mut Mesh, parsed: &ParsedObj, materials: &[ObjMaterial]) -> Result<ChannelColors> {
+        let has_vertex_colors = parsed.vertex_colors.iter().any(Option::is_some);
+        if parsed.material_names.len() <= 1 && !has_vertex_colors {
+            return Ok(ChannelColors::new());
+        }
+
+        let diffuse: HashMap<&str, [f32; 3]> = materials
+            .iter()
+            .filter_map(|m| m.diffuse_color.map(|(r, g, b)| (m.name.as_str(), [r, g, b])))
+            .collect();
+
+        // Fallback keys in order of first appearance
+        let mut first_seen: Vec<String> = Vec::new();
+        let mut channels = Vec::with_capacity(parsed.face_materials.len());
+        let mut colors = ChannelColors::new();
+
+        for (face, material) in parsed.face_materials.iter().enumerate() {
+            let name = material.map(|m| parsed.material_names[m].as_str());
+            let tri = &parsed.indices[face * 3..face * 3 + 3];
+            let vertex_color = average_color(tri.iter().map(|&i| parsed.vertex_colors[i as usize]));
+            let color = vertex_color.or_else(|| name.and_then(|n| diffuse.get(n).copied()));
+            let mut assign = |channel: u8| {
+                if let Some(color) = color {
+                    colors.entry(channel).or_insert_with(|| to_rgb8(color));
+                }
+                channels.push(channel);
+            };
+
+            if let Some(&channel) = name.and_then(|n| self.mapping.by_name.get(n)) {
+                assign(channel);
+                continue;
+            }
+            if let (Some(color), false) = (color, self.mapping.palette.is_empty()) {
+                assign(nearest_channel(&self.mapping.palette, to_rgb8(color)));
+                continue;
+            }
+
+            let key = match (name, vertex_color) {
+                (Some(name), _) => name.to_string(),
+                (None, Some(c)) => format!("{:?}", to_rgb8(c)),
+                (None, None) => String::new(),
+            };
+            let index = match first_seen.iter().position(|k| *k == key) {
+                Some(i) => i,
+                None => {
+                    first_seen.push(key);
+                    first_seen.len() - 1
+                }
+            };
+            let channel = u8::try_from(index).map_err(|_| {
+                MeshLoadError::InvalidObj("more than 256 distinct materials".to_string())
+            })?;
+            assign(channel);
+        }
+
+        debug!(
+            "Assigned {} OBJ faces to {} material channel(s)",
+            channels.len(),
+            channels.iter().collect::<std::collections::BTreeSet<_>>().len()
+        );
+        mesh.face_channels = Some(channels);
+        Ok(colors)
+    }
+
+    /// Loads the mesh together with the channels' display colors.
+    pub fn load_with_colors<P: AsRef<Path>>(&self, path: P) -> Result<(Mesh, ChannelColors)> {
+        let path = path.as_ref();
+        let parsed = self.parse_obj(path)?;
+
+        let mut materials = Vec::new();
+        if self.load_materials {
+            let dir = path.parent().unwrap_or_else(|| Path::new(""));
+            for library in &parsed.material_libraries {
+                let library_path = dir.join(library);
+                if !library_path.exists() {
+                    warn!("Material library {} not found; ignoring", library_path.display());
+                    continue;
+                }
+                materials.extend(self.load_mtl(&library_path)?);
+            }
+        }
+
+        let mut mesh = Mesh {
+            vertices: parsed.vertices.clone(),
+            indices: parsed.indices.clone(),
+            normals: None,
+            face_channels: None,
+            face_objects: None,
+            units: MeshUnits::Millimeters,
+        };
+        let colors = self.apply_materials(&mut mesh, &parsed, &materials)?;
+        apply_load_options(&mut mesh, &self.options)?;
+
+        info!(
+            "Loaded {}: {} vertices, {} triangles, {} material(s)",
+            path.display(),
+            mesh.vertices.len() / 3,
+            mesh.indices.len() / 3,
+            parsed.material_names.len()
+        );
+        Ok((mesh, colors))
     }
 }
 
@@ -278,7 +504,7 @@ impl Default for ObjLoader {
 
 impl ModelLoader for ObjLoader {
     fn load<P: AsRef<Path>>(&self, path: P) -> Result<Mesh> {
-        todo!("Implementation needed: Load OBJ file with optional materials")
+        self.load_with_colors(path).map(|(mesh, _)| mesh)
     }
 
     fn supported_extensions(&self) -> &[&str] {
@@ -286,37 +512,122 @@ impl ModelLoader for ObjLoader {
     }
 
     fn validate<P: AsRef<Path>>(&self, path: P) -> Result<()> {
-        todo!("Implementation needed: Validate OBJ file structure")
+        let parsed = self.parse_obj(path)?;
+        if parsed.indices.is_empty() {
+            bail!(MeshLoadError::InvalidObj("no faces".to_string()));
+        }
+        Ok(())
     }
 }
 
 /// 3MF file loader with full metadata support.
+///
+/// Every object placed by the build section is merged into one mesh, with
+/// the item's transform applied. Base materials become material channels
+/// in document order, so a file with one `basematerials` group maps each
+/// `pindex` to the channel of the same number. The model's `unit` is kept
+/// where [`MeshUnits`] has it; microns and feet are converted to
+/// millimeters.
 pub struct ThreeMfLoader {
     options: LoadOptions,
 }
 
+/// Root model part of a 3MF package, by convention.
+const THREEMF_DEFAULT_MODEL: &str = "3D/3dmodel.model";
+
 impl ThreeMfLoader {
     pub fn new() -> Self {
-        todo!("Implementation needed: Create 3MF loader")
+        Self::with_options(LoadOptions::default())
     }
 
     pub fn with_options(options: LoadOptions) -> Self {
-        todo!("Implementation needed: Create 3MF loader with custom options")
+        Self { options }
+    }
+
+    /// Reads the root model part, as named by the package relationships.
+    fn read_model<P: AsRef<Path>>(&self, path: P) -> Result<String> {
+        let file = File::open(path.as_ref())
+            .with_context(|| format!("Failed to open {}", path.as_ref().display()))?;
+        let mut archive =
+            zip::ZipArchive::new(file).map_err(|e| MeshLoadError::Invalid3mf(e.to_string()))?;
+
+        let mut part = THREEMF_DEFAULT_MODEL.to_string();
+        if let Ok(mut rels) = archive.by_name("_rels/.rels") {
+            let mut xml = String::new();
+            rels.read_to_string(&mut xml)?;
+            if let Some(target) = xml_tags(&xml)
+                .filter(|tag| tag.name == "Relationship" && tag.attr("Type").is_some_and(|t| t.ends_with("/3dmodel")))
+                .find_map(|tag| tag.attr("Target").map(str::to_string))
+            {
+                part = target.trim_start_matches('/').to_string();
+            }
+        }
+
+        let mut model = archive
+            .by_name(&part)
+            .map_err(|_| MeshLoadError::Invalid3mf(format!("missing model part {}", part)))?;
+        let mut xml = String::new();
+        model.read_to_string(&mut xml)?;
+        Ok(xml)
     }
 
     /// Extracts mesh from 3MF package.
     fn extract_mesh<P: AsRef<Path>>(&self, path: P) -> Result<Mesh> {
-        todo!("Implementation needed: Unzip 3MF and parse 3D model XML")
+        parse_3mf_model(&self.read_model(path)?).map(|(mesh, _)| mesh)
     }
 
     /// Extracts material definitions from 3MF.
     fn extract_materials<P: AsRef<Path>>(&self, path: P) -> Result<Vec<ThreeMfMaterial>> {
-        todo!("Implementation needed: Parse material definitions from 3MF")
+        Ok(parse_3mf_materials(&self.read_model(path)?))
     }
 
     /// Extracts metadata from 3MF.
     fn extract_metadata<P: AsRef<Path>>(&self, path: P) -> Result<ThreeMfMetadata> {
-        todo!("Implementation needed: Parse 3MF metadata")
+        let xml = self.read_model(path)?;
+        let mut metadata = ThreeMfMetadata {
+            title: None,
+            designer: None,
+            description: None,
+            creation_date: None,
+            modification_date: None,
+        };
+        for tag in xml_tags(&xml).filter(|tag| tag.name == "metadata" && !tag.closing) {
+            let value = Some(unescape_xml(tag.text.trim()));
+            match tag.attr("name") {
+                Some("Title") => metadata.title = value,
+                Some("Designer") => metadata.designer = value,
+                Some("Description") => metadata.description = value,
+                Some("CreationDate") => metadata.creation_date = value,
+                Some("ModificationDate") => metadata.modification_date = value,
+                _ => {}
+            }
+        }
+        Ok(metadata)
+    }
+
+    /// Loads the mesh together with the base materials' display colors.
+    pub fn load_with_colors<P: AsRef<Path>>(&self, path: P) -> Result<(Mesh, ChannelColors)> {
+        let path = path.as_ref();
+        let xml = self.read_model(path)?;
+        let (mut mesh, materials) = parse_3mf_model(&xml)?;
+        apply_load_options(&mut mesh, &self.options)?;
+
+        let colors = materials
+            .iter()
+            .enumerate()
+            .filter_map(|(channel, m)| {
+                let channel = u8::try_from(channel).ok()?;
+                Some((channel, [m.color.0, m.color.1, m.color.2]))
+            })
+            .collect();
+        info!(
+            "Loaded {}: {} vertices, {} triangles, {} material(s)",
+            path.display(),
+            mesh.vertices.len() / 3,
+            mesh.indices.len() / 3,
+            materials.len()
+        );
+        Ok((mesh, colors))
     }
 }
 
@@ -328,7 +639,7 @@ impl Default for ThreeMfLoader {
 
 impl ModelLoader for ThreeMfLoader {
     fn load<P: AsRef<Path>>(&self, path: P) -> Result<Mesh> {
-        todo!("Implementation needed: Load 3MF package and extract mesh")
+        self.load_with_colors(path).map(|(mesh, _)| mesh)
     }
 
     fn supported_extensions(&self) -> &[&str] {
@@ -336,7 +647,11 @@ impl ModelLoader for ThreeMfLoader {
     }
 
     fn validate<P: AsRef<Path>>(&self, path: P) -> Result<()> {
-        todo!("Implementation needed: Validate 3MF package structure")
+        let mesh = self.extract_mesh(path)?;
+        if mesh.indices.is_empty() {
+            bail!(MeshLoadError::Invalid3mf("no triangles".to_string()));
+        }
+        Ok(())
     }
 }
 
@@ -349,12 +664,52 @@ pub struct AutoLoader {
 
 impl AutoLoader {
     pub fn new() -> Self {
-        todo!("Implementation needed: Create auto-detecting loader with all format handlers")
+        Self::with_options(LoadOptions::default())
+    }
+
+    pub fn with_options(options: LoadOptions) -> Self {
+        Self {
+            stl_loader: StlLoader::with_options(options.clone()),
+            obj_loader: ObjLoader::with_options(options.clone()),
+            threemf_loader: ThreeMfLoader::with_options(options),
+        }
     }
 
     /// Detects file format from extension and/or content.
     pub fn detect_format<P: AsRef<Path>>(path: P) -> Result<MeshFormat> {
-        todo!("Implementation needed: Detect format from extension or file header")
+        let path = path.as_ref();
+        let extension = path.extension().and_then(|e| e.to_str()).map(str::to_ascii_lowercase);
+        match extension.as_deref() {
+            Some("stl") => return StlLoader::detect_stl_format(path),
+            Some("obj") => return Ok(MeshFormat::Obj),
+            Some("3mf") => return Ok(MeshFormat::ThreeMf),
+            _ => {}
+        }
+
+        // No known extension: 3MF packages are zip archives
+        let mut magic = Vec::with_capacity(4);
+        File::open(path)
+            .with_context(|| format!("Failed to open {}", path.display()))?
+            .take(4)
+            .read_to_end(&mut magic)?;
+        if magic == b"PK\x03\x04" {
+            return Ok(MeshFormat::ThreeMf);
+        }
+        StlLoader::detect_stl_format(path).map_err(|_| {
+            MeshLoadError::UnsupportedFormat(path.display().to_string()).into()
+        })
+    }
+
+    /// Loads any supported format together with the channels' display
+    /// colors; STL files have none.
+    pub fn load_with_colors<P: AsRef<Path>>(&self, path: P) -> Result<(Mesh, ChannelColors)> {
+        let path = path.as_ref();
+        match Self::detect_format(path)? {
+            MeshFormat::StlAscii | MeshFormat::StlBinary => Ok((self.stl_loader.load(path)?, ChannelColors::new())),
+            MeshFormat::Obj => self.obj_loader.load_with_colors(path),
+            MeshFormat::ThreeMf => self.threemf_loader.load_with_colors(path),
+            MeshFormat::Unknown => bail!(MeshLoadError::UnsupportedFormat(path.display().to_string())),
+        }
     }
 }
 
@@ -366,7 +721,7 @@ impl Default for AutoLoader {
 
 impl ModelLoader for AutoLoader {
     fn load<P: AsRef<Path>>(&self, path: P) -> Result<Mesh> {
-        todo!("Implementation needed: Detect format and delegate to appropriate loader")
+        self.load_with_colors(path).map(|(mesh, _)| mesh)
     }
 
     fn supported_extensions(&self) -> &[&str] {
@@ -374,7 +729,13 @@ impl ModelLoader for AutoLoader {
     }
 
     fn validate<P: AsRef<Path>>(&self, path: P) -> Result<()> {
-        todo!("Implementation needed: Detect format and validate")
+        let path = path.as_ref();
+        match Self::detect_format(path)? {
+            MeshFormat::StlAscii | MeshFormat::StlBinary => self.stl_loader.validate(path),
+            MeshFormat::Obj => self.obj_loader.validate(path),
+            MeshFormat::ThreeMf => self.threemf_loader.validate(path),
+            MeshFormat::Unknown => bail!(MeshLoadError::UnsupportedFormat(path.display().to_string())),
+        }
     }
 }
 
@@ -411,6 +772,417 @@ pub struct ThreeMfMetadata {
 
 // Shared Utility Functions - Fully Implemented
 
+/// Parses OBJ geometry, vertex colors and material groups.
+///
+/// Polygons are fan-triangulated. Texture coordinates, normals, groups and
+/// smoothing directives are ignored.
+fn parse_obj_source<R: BufRead>(reader: R) -> Result<ParsedObj> {
+    let mut obj = ParsedObj::default();
+    let mut current_material = None;
+
+    for (number, line) in reader.lines().enumerate() {
+        let line = line?;
+        let invalid = |what: &str| MeshLoadError::InvalidObj(format!("line {}: {}", number + 1, what));
+        let mut tokens = line.split_whitespace();
+
+        match tokens.next() {
+            Some("v") => {
+                let values = tokens
+                    .map(str::parse::<f32>)
+                    .collect::<std::result::Result<Vec<_>, _>>()
+                    .map_err(|_| invalid("bad vertex coordinate"))?;
+                if values.len() < 3 {
+                    return Err(invalid("vertex needs x y z").into());
+                }
+                obj.vertices.extend_from_slice(&values[..3]);
+                obj.vertex_colors.push(match values.get(3..6) {
+                    // Some exporters write 0-255 instead of 0-1
+                    Some(c) if c.iter().any(|v| *v > 1.0) => Some([c[0] / 255.0, c[1] / 255.0, c[2] / 255.0]),
+                    Some(c) => Some([c[0], c[1], c[2]]),
+                    None => None,
+                });
+            }
+            Some("f") => {
+                let vertex_count = obj.vertex_colors.len() as i64;
+                let refs = tokens
+                    .map(|t| {
+                        let index: i64 = t.split('/').next().unwrap_or("").parse().ok()?;
+                        // 1-based, or negative relative to the vertices so far
+                        let resolved = if index < 0 { vertex_count + index } else { index - 1 };
+                        (0..vertex_count).contains(&resolved).then_some(resolved as u32)
+                    })
+                    .collect::<Option<Vec<u32>>>()
+                    .ok_or_else(|| invalid("face references a missing vertex"))?;
+                if refs.len() < 3 {
+                    return Err(invalid("face needs at least 3 vertices").into());
+                }
+                for k in 1..refs.len() - 1 {
+                    obj.indices.extend_from_slice(&[refs[0], refs[k], refs[k + 1]]);
+                    obj.face_materials.push(current_material);
+                }
+            }
+            Some("usemtl") => {
+                let name = tokens.collect::<Vec<_>>().join(" ");
+                let index = match obj.material_names.iter().position(|n| *n == name) {
+                    Some(i) => i,
+                    None => {
+                        obj.material_names.push(name);
+                        obj.material_names.len() - 1
+                    }
+                };
+                current_material = Some(index);
+            }
+            Some("mtllib") => obj.material_libraries.extend(tokens.map(str::to_string)),
+            _ => {}
+        }
+    }
+
+    Ok(obj)
+}
+
+/// Parses `.mtl` material definitions.
+fn parse_mtl_source<R: BufRead>(reader: R) -> Result<Vec<ObjMaterial>> {
+    let mut materials: Vec<ObjMaterial> = Vec::new();
+
+    for (number, line) in reader.lines().enumerate() {
+        let line = line?;
+        let mut tokens = line.split_whitespace();
+        let keyword = tokens.next();
+
+        if keyword == Some("newmtl") {
+            materials.push(ObjMaterial {
+                name: tokens.collect::<Vec<_>>().join(" "),
+                diffuse_color: None,
+                specular_color: None,
+                ambient_color: None,
+                opacity: 1.0,
+            });
+            continue;
+        }
+
+        let Some(material) = materials.last_mut() else {
+            continue;
+        };
+        let values: Vec<f32> = tokens.filter_map(|t| t.parse().ok()).collect();
+        let rgb = || match values.as_slice() {
+            [r, g, b, ..] => Ok(Some((*r, *g, *b))),
+            _ => Err(MeshLoadError::InvalidObj(format!("mtl line {}: color needs r g b", number + 1))),
+        };
+        match keyword {
+            Some("Kd") => material.diffuse_color = rgb()?,
+            Some("Ks") => material.specular_color = rgb()?,
+            Some("Ka") => material.ambient_color = rgb()?,
+            Some("d") => material.opacity = values.first().copied().unwrap_or(1.0),
+            Some("Tr") => material.opacity = 1.0 - values.first().copied().unwrap_or(0.0),
+            _ => {}
+        }
+    }
+
+    Ok(materials)
+}
+
+/// Mesh of separate triangles, as STL stores them; `merge_threshold`
+/// joins the shared corners.
+fn unindexed_mesh(vertices: Vec<f32>) -> Mesh {
+    Mesh {
+        indices: (0..(vertices.len() / 3) as u32).collect(),
+        vertices,
+        normals: None,
+        face_channels: None,
+        face_objects: None,
+        units: MeshUnits::Millimeters,
+    }
+}
+
+/// One XML start, end or empty-element tag and the text following it.
+///
+/// 3MF parts are flat enough that a tag scanner suffices: namespace
+/// prefixes are dropped, comments and declarations skipped.
+struct XmlTag<'a> {
+    name: &'a str,
+    attributes: Vec<(&'a str, &'a str)>,
+    /// `</name>`
+    closing: bool,
+    /// Raw text up to the next tag
+    text: &'a str,
+}
+
+impl<'a> XmlTag<'a> {
+    /// Raw (still escaped) attribute value.
+    fn attr(&self, name: &str) -> Option<&'a str> {
+        self.attributes.iter().find(|(key, _)| *key == name).map(|(_, value)| *value)
+    }
+
+    fn number<T: std::str::FromStr>(&self, name: &str) -> Option<T> {
+        self.attr(name)?.trim().parse().ok()
+    }
+}
+
+fn xml_tags(xml: &str) -> impl Iterator<Item = XmlTag<'_>> {
+    xml.split('<').skip(1).filter_map(|piece| {
+        let (tag, text) = piece.split_once('>')?;
+        if tag.starts_with('?') || tag.starts_with('!') {
+            return None;
+        }
+        let closing = tag.starts_with('/');
+        let tag = tag.trim_start_matches('/').trim_end_matches('/');
+        let name_end = tag.find(char::is_whitespace).unwrap_or(tag.len());
+        let name = tag[..name_end].rsplit(':').next().unwrap_or_default();
+
+        let mut attributes = Vec::new();
+        let mut rest = &tag[name_end..];
+        while let Some(eq) = rest.find('=') {
+            let key = rest[..eq].trim();
+            let after = rest[eq + 1..].trim_start();
+            let quote = after.chars().next()?;
+            let value_end = after[1..].find(quote)? + 1;
+            attributes.push((key.rsplit(':').next().unwrap_or(key), &after[1..value_end]));
+            rest = &after[value_end + 1..];
+        }
+        Some(XmlTag { name, attributes, closing, text })
+    })
+}
+
+fn unescape_xml(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// Base materials in document order; the position is the channel.
+fn parse_3mf_materials(xml: &str) -> Vec<ThreeMfMaterial> {
+    let mut group = "";
+    let mut index = 0;
+    let mut materials = Vec::new();
+    for tag in xml_tags(xml).filter(|tag| !tag.closing) {
+        match tag.name {
+            "basematerials" => {
+                group = tag.attr("id").unwrap_or_default();
+                index = 0;
+            }
+            "base" => {
+                materials.push(ThreeMfMaterial {
+                    // Group id and pindex
+                    id: format!("{}:{}", group, index),
+                    name: unescape_xml(tag.attr("name").unwrap_or_default()),
+                    color: tag.attr("displaycolor").and_then(parse_3mf_color).unwrap_or((128, 128, 128, 255)),
+                    material_type: "base".to_string(),
+                });
+                index += 1;
+            }
+            _ => {}
+        }
+    }
+    materials
+}
+
+/// `#RRGGBB` or `#RRGGBBAA`.
+fn parse_3mf_color(value: &str) -> Option<(u8, u8, u8, u8)> {
+    let hex = value.trim().strip_prefix('#')?;
+    let byte = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+    match hex.len() {
+        6 => Some((byte(0)?, byte(2)?, byte(4)?, 255)),
+        8 => Some((byte(0)?, byte(2)?, byte(4)?, byte(6)?)),
+        _ => None,
+    }
+}
+
+/// One `<object>` mesh with each triangle's material, as (group id, index).
+#[derive(Default)]
+struct ThreeMfObject {
+    vertices: Vec<f32>,
+    indices: Vec<u32>,
+    materials: Vec<Option<(String, usize)>>,
+}
+
+/// Parses the model part into one mesh of the build items.
+fn parse_3mf_model(xml: &str) -> Result<(Mesh, Vec<ThreeMfMaterial>)> {
+    let invalid = |what: String| MeshLoadError::Invalid3mf(what);
+    let materials = parse_3mf_materials(xml);
+
+    let mut units = MeshUnits::Millimeters;
+    let mut scale = 1.0;
+    let mut objects: BTreeMap<String, ThreeMfObject> = BTreeMap::new();
+    let mut build: Vec<(String, Option<[f32; 12]>)> = Vec::new();
+    // Current object id and its default material
+    let mut current: Option<(String, Option<(String, usize)>)> = None;
+
+    for tag in xml_tags(xml) {
+        if tag.closing {
+            if tag.name == "object" {
+                current = None;
+            }
+            continue;
+        }
+        match tag.name {
+            "model" => {
+                (units, scale) = match tag.attr("unit").unwrap_or("millimeter") {
+                    "micron" => (MeshUnits::Millimeters, 0.001),
+                    "millimeter" => (MeshUnits::Millimeters, 1.0),
+                    "centimeter" => (MeshUnits::Centimeters, 1.0),
+                    "meter" => (MeshUnits::Meters, 1.0),
+                    "inch" => (MeshUnits::Inches, 1.0),
+                    "foot" => (MeshUnits::Millimeters, 304.8),
+                    other => return Err(invalid(format!("unknown unit {}", other)).into()),
+                };
+            }
+            "object" => {
+                let id = tag.attr("id").ok_or_else(|| invalid("object without id".to_string()))?;
+                let default = tag.attr("pid").zip(tag.number::<usize>("pindex"));
+                current = Some((id.to_string(), default.map(|(pid, index)| (pid.to_string(), index))));
+                objects.entry(id.to_string()).or_default();
+            }
+            "vertex" => {
+                let Some((id, _)) = &current else { continue };
+                let coords = [tag.number::<f32>("x"), tag.number("y"), tag.number("z")];
+                let [Some(x), Some(y), Some(z)] = coords else {
+                    return Err(invalid(format!("object {}: bad vertex", id)).into());
+                };
+                objects.get_mut(id).unwrap().vertices.extend([x * scale, y * scale, z * scale]);
+            }
+            "triangle" => {
+                let Some((id, default)) = &current else { continue };
+                let object = objects.get_mut(id).unwrap();
+                let count = (object.vertices.len() / 3) as u32;
+                let refs = [tag.number::<u32>("v1"), tag.number("v2"), tag.number("v3")];
+                let [Some(a), Some(b), Some(c)] = refs else {
+                    return Err(invalid(format!("object {}: bad triangle", id)).into());
+                };
+                if a >= count || b >= count || c >= count {
+                    return Err(invalid(format!("object {}: triangle references a missing vertex", id)).into());
+                }
+                object.indices.extend([a, b, c]);
+                let material = match (tag.attr("pid"), tag.number::<usize>("p1")) {
+                    (Some(pid), Some(index)) => Some((pid.to_string(), index)),
+                    (None, Some(index)) => default.as_ref().map(|(pid, _)| (pid.clone(), index)),
+                    _ => default.clone(),
+                };
+                object.materials.push(material);
+            }
+            "component" => warn!("3MF component references are not supported; skipping"),
+            "item" => {
+                let id = tag.attr("objectid").ok_or_else(|| invalid("build item without objectid".to_string()))?;
+                let transform = tag.attr("transform").and_then(|t| {
+                    let values: Vec<f32> = t.split_whitespace().filter_map(|v| v.parse().ok()).collect();
+                    values.try_into().ok()
+                });
+                build.push((id.to_string(), transform));
+            }
+            _ => {}
+        }
+    }
+
+    // Without a build section every object is placed as modelled
+    if build.is_empty() {
+        build = objects.keys().map(|id| (id.clone(), None)).collect();
+    }
+
+    // Channel = position among all base materials
+    let mut group_offsets: HashMap<&str, usize> = HashMap::new();
+    for (position, material) in materials.iter().enumerate() {
+        if let Some((group, _)) = material.id.split_once(':') {
+            group_offsets.entry(group).or_insert(position);
+        }
+    }
+
+    let mut mesh = Mesh {
+        vertices: Vec::new(),
+        indices: Vec::new(),
+        normals: None,
+        face_channels: None,
+        face_objects: None,
+        units,
+    };
+    let mut channels: Vec<u8> = Vec::new();
+    for (id, transform) in &build {
+        let object = objects
+            .get(id)
+            .ok_or_else(|| invalid(format!("build references missing object {}", id)))?;
+        let base = (mesh.vertices.len() / 3) as u32;
+        for v in object.vertices.chunks_exact(3) {
+            mesh.vertices.extend(match transform {
+                Some(m) => [
+                    v[0] * m[0] + v[1] * m[3] + v[2] * m[6] + m[9],
+                    v[0] * m[1] + v[1] * m[4] + v[2] * m[7] + m[10],
+                    v[0] * m[2] + v[1] * m[5] + v[2] * m[8] + m[11],
+                ],
+                None => [v[0], v[1], v[2]],
+            });
+        }
+        mesh.indices.extend(object.indices.iter().map(|i| base + i));
+        for material in &object.materials {
+            let channel = material
+                .as_ref()
+                .and_then(|(group, index)| group_offsets.get(group.as_str()).map(|offset| offset + index))
+                .unwrap_or(0);
+            channels.push(u8::try_from(channel).map_err(|_| invalid("more than 256 base materials".to_string()))?);
+        }
+    }
+
+    // Like OBJ, a model all on channel 0 carries no channel data
+    if channels.iter().any(|&c| c != 0) {
+        mesh.face_channels = Some(channels);
+    }
+    Ok((mesh, materials))
+}
+
+/// Average color of a triangle, if every vertex has one.
+fn average_color(colors: impl Iterator<Item = Option<[f32; 3]>>) -> Option<[f32; 3]> {
+    let mut sum = [0.0f32; 3];
+    let mut count = 0;
+    for color in colors {
+        let color = color?;
+        for k in 0..3 {
+            sum[k] += color[k];
+        }
+        count += 1;
+    }
+    (count > 0).then(|| sum.map(|v| v / count as f32))
+}
+
+fn to_rgb8(color: [f32; 3]) -> [u8; 3] {
+    color.map(|v| (v.clamp(0.0, 1.0) * 255.0).round() as u8)
+}
+
+/// Channel whose base color is closest to `color`.
+fn nearest_channel(palette: &[(u8, [u8; 3])], color: [u8; 3]) -> u8 {
+    let distance = |c: &[u8; 3]| -> u32 {
+        (0..3).map(|k| (c[k] as i32 - color[k] as i32).pow(2) as u32).sum()
+    };
+    palette
+        .iter()
+        .min_by_key(|(_, c)| distance(c))
+        .map(|(channel, _)| *channel)
+        .unwrap_or(0)
+}
+
+/// Applies unit conversion, scaling, vertex merging, centering and
+/// validation as requested by `options`.
+pub fn apply_load_options(mesh: &mut Mesh, options: &LoadOptions) -> Result<()> {
+    if let Some(units) = options.target_units {
+        mesh.convert_units(units);
+    }
+    if options.scale_factor != 1.0 {
+        scale_mesh(mesh, options.scale_factor);
+    }
+    if let Some(threshold) = options.merge_threshold {
+        let merged = merge_vertices(mesh, threshold);
+        if merged > 0 {
+            debug!("Merged {} duplicate vertices", merged);
+        }
+    }
+    if options.center_on_origin {
+        center_mesh(mesh);
+    }
+    if options.validate_topology {
+        mesh.validate()?;
+        validate_mesh_topology(mesh)?;
+    }
+    Ok(())
+}
+
 /// Computes mesh statistics for validation and reporting.
 pub fn compute_mesh_stats(mesh: &Mesh) -> MeshStats {
     let vertex_count = mesh.vertices.len() / 3;
@@ -668,6 +1440,68 @@ mod tests {
         assert_eq!(MeshFormat::ThreeMf.extensions(), &["3mf"]);
     }
 
+    #[test]
+    fn test_parse_obj_colors_and_materials() {
+        let source = "mtllib parts.mtl\n\
+            v 0 0 0 1 0 0\n\
+            v 1 0 0 1 0 0\n\
+            v 1 1 0 255 0 0\n\
+            v 0 1 0\n\
+            usemtl body\n\
+            f 1/1/1 2/2/2 3/3/3 4/4/4\n\
+            usemtl trim\n\
+            f -4 -2 -1\n";
+        let obj = parse_obj_source(source.as_bytes()).unwrap();
+
+        assert_eq!(obj.vertices.len(), 12);
+        assert_eq!(obj.indices, vec![0, 1, 2, 0, 2, 3, 0, 2, 3]);
+        assert_eq!(obj.face_materials, vec![Some(0), Some(0), Some(1)]);
+        assert_eq!(obj.material_libraries, vec!["parts.mtl".to_string()]);
+        assert_eq!(obj.vertex_colors[2], Some([1.0, 0.0, 0.0]));
+        assert_eq!(obj.vertex_colors[3], None);
+
+        assert!(parse_obj_source("f 1 2 3\n".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_obj_faces_mapped_to_channels() {
+        let dir = std::env::temp_dir().join(format!("hg4d-obj-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("parts.mtl"),
+            "newmtl body\nKd 0.9 0.9 0.9\nd 1.0\nnewmtl trim\nKd 0.1 0.1 0.8\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("model.obj"),
+            "mtllib parts.mtl\nv 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\n\
+             usemtl body\nf 1 2 3\nusemtl trim\nf 1 3 4\n",
+        )
+        .unwrap();
+
+        let options = LoadOptions {
+            validate_topology: false,
+            ..LoadOptions::default()
+        };
+        let mesh = ObjLoader::with_options(options.clone()).load(dir.join("model.obj")).unwrap();
+        assert_eq!(mesh.face_channels, Some(vec![0, 1]));
+
+        // Palette matching by diffuse color, then explicit names take priority
+        let mapping = ChannelMapping {
+            by_name: HashMap::new(),
+            palette: vec![(2, [0, 0, 255]), (5, [255, 255, 255])],
+        };
+        let loader = ObjLoader::with_options(options.clone()).with_channel_mapping(mapping.clone());
+        assert_eq!(loader.load(dir.join("model.obj")).unwrap().face_channels, Some(vec![5, 2]));
+
+        let mut named = mapping;
+        named.by_name.insert("trim".to_string(), 7);
+        let loader = ObjLoader::with_options(options).with_channel_mapping(named);
+        assert_eq!(loader.load(dir.join("model.obj")).unwrap().face_channels, Some(vec![5, 7]));
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
     #[test]
     fn test_center_mesh() {
         let mut mesh = Mesh {
@@ -678,6 +1512,8 @@ mod tests {
             ],
             indices: vec![0, 1, 2],
             normals: None,
+            face_channels: None,
+            face_objects: None,
             units: MeshUnits::Millimeters,
         };
 
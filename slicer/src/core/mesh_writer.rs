@@ -0,0 +1,405 @@
+//! # Mesh Writing Module
+//!
+//! The counterpart of [`super::mesh_loader`], used by `hg4d-slicer convert`
+//! to normalize model files. What a format can carry decides what survives:
+//!
+//! - **STL**: geometry only, in millimeters; material channels are dropped
+//! - **OBJ**: millimeters; channels become `usemtl channel_N` groups, written
+//!   in channel order, with a `.mtl` library of the channel colors next to
+//!   the file
+//! - **3MF**: the mesh's own units in the model's `unit` attribute; channels
+//!   become one `basematerials` group whose `pindex` equals the channel
+//!
+//! Channel colors come from the source file where it had them
+//! ([`ChannelColors`]) and from [`DEFAULT_CHANNEL_COLORS`] otherwise.
+
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use byteorder::{LittleEndian, WriteBytesExt};
+use tracing::{info, warn};
+
+use super::mesh_loader::{ChannelColors, MeshFormat, MeshLoadError};
+use crate::{Mesh, MeshUnits};
+
+/// Colors for channels the source gave none, by channel number.
+pub const DEFAULT_CHANNEL_COLORS: [[u8; 3]; 8] = [
+    [230, 230, 230],
+    [200, 40, 40],
+    [40, 110, 210],
+    [240, 190, 30],
+    [40, 160, 80],
+    [140, 60, 170],
+    [240, 120, 30],
+    [40, 40, 40],
+];
+
+/// Writes a mesh in one file format.
+pub trait MeshWriter {
+    fn write(&self, mesh: &Mesh, path: &Path) -> Result<()>;
+
+    fn format(&self) -> MeshFormat;
+}
+
+/// Writer for `format`, using `colors` for the material channels.
+pub fn writer_for(format: MeshFormat, colors: ChannelColors) -> Result<Box<dyn MeshWriter>> {
+    Ok(match format {
+        MeshFormat::StlBinary => Box::new(StlWriter::binary()),
+        MeshFormat::StlAscii => Box::new(StlWriter::ascii()),
+        MeshFormat::Obj => Box::new(ObjWriter::new(colors)),
+        MeshFormat::ThreeMf => Box::new(ThreeMfWriter::new(colors)),
+        MeshFormat::Unknown => bail!(MeshLoadError::UnsupportedFormat("unknown".to_string())),
+    })
+}
+
+/// STL writer, binary or ASCII.
+pub struct StlWriter {
+    binary: bool,
+}
+
+impl StlWriter {
+    pub fn binary() -> Self {
+        Self { binary: true }
+    }
+
+    pub fn ascii() -> Self {
+        Self { binary: false }
+    }
+}
+
+impl MeshWriter for StlWriter {
+    fn write(&self, mesh: &Mesh, path: &Path) -> Result<()> {
+        if mesh.face_channels.is_some() {
+            warn!("STL has no materials; material channels are not written to {}", path.display());
+        }
+        let mesh = in_millimeters(mesh);
+        let mut out = create(path)?;
+
+        if self.binary {
+            let mut header = [0u8; 80];
+            let label = b"hg4d-slicer export";
+            header[..label.len()].copy_from_slice(label);
+            out.write_all(&header)?;
+            out.write_u32::<LittleEndian>(triangle_count(&mesh))?;
+            for tri in triangles(&mesh) {
+                for value in normal(&tri).iter().chain(tri.iter().flatten()) {
+                    out.write_f32::<LittleEndian>(*value)?;
+                }
+                out.write_u16::<LittleEndian>(0)?;
+            }
+        } else {
+            writeln!(out, "solid hg4d")?;
+            for tri in triangles(&mesh) {
+                let [nx, ny, nz] = normal(&tri);
+                writeln!(out, "  facet normal {} {} {}", nx, ny, nz)?;
+                writeln!(out, "    outer loop")?;
+                for [x, y, z] in tri {
+                    writeln!(out, "      vertex {} {} {}", x, y, z)?;
+                }
+                writeln!(out, "    endloop")?;
+                writeln!(out, "  endfacet")?;
+            }
+            writeln!(out, "endsolid hg4d")?;
+        }
+        out.flush()?;
+        Ok(())
+    }
+
+    fn format(&self) -> MeshFormat {
+        if self.binary {
+            MeshFormat::StlBinary
+        } else {
+            MeshFormat::StlAscii
+        }
+    }
+}
+
+/// Wavefront OBJ writer with a material library for the channels.
+pub struct ObjWriter {
+    colors: ChannelColors,
+}
+
+impl ObjWriter {
+    pub fn new(colors: ChannelColors) -> Self {
+        Self { colors }
+    }
+}
+
+impl MeshWriter for ObjWriter {
+    fn write(&self, mesh: &Mesh, path: &Path) -> Result<()> {
+        let mesh = in_millimeters(mesh);
+        let mut out = create(path)?;
+        writeln!(out, "# hg4d-slicer export")?;
+
+        let library = path.with_extension("mtl");
+        let channels = used_channels(&mesh);
+        if let Some(channels) = &channels {
+            let mut mtl = create(&library)?;
+            for &channel in channels {
+                let [r, g, b] = channel_color(&self.colors, channel).map(|c| c as f32 / 255.0);
+                writeln!(mtl, "newmtl channel_{}", channel)?;
+                writeln!(mtl, "Kd {:.4} {:.4} {:.4}", r, g, b)?;
+                writeln!(mtl, "d 1.0")?;
+            }
+            mtl.flush()?;
+            let name = library.file_name().and_then(|n| n.to_str()).unwrap_or_default();
+            writeln!(out, "mtllib {}", name)?;
+        }
+
+        for v in mesh.vertices.chunks_exact(3) {
+            writeln!(out, "v {} {} {}", v[0], v[1], v[2])?;
+        }
+        let face = |out: &mut BufWriter<File>, tri: &[u32]| writeln!(out, "f {} {} {}", tri[0] + 1, tri[1] + 1, tri[2] + 1);
+        match (&channels, &mesh.face_channels) {
+            // OBJ readers number materials by first use, so groups go in channel order
+            (Some(channels), Some(face_channels)) => {
+                for &channel in channels {
+                    writeln!(out, "usemtl channel_{}", channel)?;
+                    for (tri, _) in mesh
+                        .indices
+                        .chunks_exact(3)
+                        .zip(face_channels)
+                        .filter(|(_, &c)| c == channel)
+                    {
+                        face(&mut out, tri)?;
+                    }
+                }
+            }
+            _ => {
+                for tri in mesh.indices.chunks_exact(3) {
+                    face(&mut out, tri)?;
+                }
+            }
+        }
+        out.flush()?;
+        Ok(())
+    }
+
+    fn format(&self) -> MeshFormat {
+        MeshFormat::Obj
+    }
+}
+
+/// 3MF package writer.
+pub struct ThreeMfWriter {
+    colors: ChannelColors,
+}
+
+impl ThreeMfWriter {
+    pub fn new(colors: ChannelColors) -> Self {
+        Self { colors }
+    }
+
+    /// The `3D/3dmodel.model` part.
+    fn model_xml(&self, mesh: &Mesh) -> String {
+        let unit = match mesh.units {
+            MeshUnits::Millimeters => "millimeter",
+            MeshUnits::Centimeters => "centimeter",
+            MeshUnits::Meters => "meter",
+            MeshUnits::Inches => "inch",
+        };
+        let mut xml = String::new();
+        xml.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        xml.push_str(&format!(
+            "<model unit=\"{}\" xml:lang=\"en-US\" xmlns=\"http://schemas.microsoft.com/3dmanufacturing/core/2015/02\">\n",
+            unit
+        ));
+        xml.push_str("  <metadata name=\"Application\">hg4d-slicer</metadata>\n");
+        xml.push_str("  <resources>\n");
+
+        // One base per channel up to the highest used, so pindex == channel
+        let max_channel = mesh.face_channels.as_ref().and_then(|c| c.iter().max().copied());
+        let object_material = match max_channel {
+            Some(max) => {
+                xml.push_str("    <basematerials id=\"1\">\n");
+                for channel in 0..=max {
+                    let [r, g, b] = channel_color(&self.colors, channel);
+                    xml.push_str(&format!(
+                        "      <base name=\"channel_{}\" displaycolor=\"#{:02X}{:02X}{:02X}\" />\n",
+                        channel, r, g, b
+                    ));
+                }
+                xml.push_str("    </basematerials>\n");
+                " pid=\"1\" pindex=\"0\""
+            }
+            None => "",
+        };
+
+        xml.push_str(&format!("    <object id=\"2\" type=\"model\"{}>\n", object_material));
+        xml.push_str("      <mesh>\n        <vertices>\n");
+        for v in mesh.vertices.chunks_exact(3) {
+            xml.push_str(&format!("          <vertex x=\"{}\" y=\"{}\" z=\"{}\" />\n", v[0], v[1], v[2]));
+        }
+        xml.push_str("        </vertices>\n        <triangles>\n");
+        for (n, tri) in mesh.indices.chunks_exact(3).enumerate() {
+            let material = match &mesh.face_channels {
+                Some(channels) => format!(" p1=\"{}\"", channels.get(n).copied().unwrap_or(0)),
+                None => String::new(),
+            };
+            xml.push_str(&format!(
+                "          <triangle v1=\"{}\" v2=\"{}\" v3=\"{}\"{} />\n",
+                tri[0], tri[1], tri[2], material
+            ));
+        }
+        xml.push_str("        </triangles>\n      </mesh>\n    </object>\n");
+        xml.push_str("  </resources>\n");
+        xml.push_str("  <build>\n    <item objectid=\"2\" />\n  </build>\n");
+        xml.push_str("</model>\n");
+        xml
+    }
+}
+
+const THREEMF_CONTENT_TYPES: &str = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+<Types xmlns=\"http://schemas.openxmlformats.org/package/2006/content-types\">\n\
+  <Default Extension=\"rels\" ContentType=\"application/vnd.openxmlformats-package.relationships+xml\" />\n\
+  <Default Extension=\"model\" ContentType=\"application/vnd.ms-package.3dmanufacturing-3dmodel+xml\" />\n\
+</Types>\n";
+
+const THREEMF_RELATIONSHIPS: &str = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+<Relationships xmlns=\"http://schemas.openxmlformats.org/package/2006/relationships\">\n\
+  <Relationship Target=\"/3D/3dmodel.model\" Id=\"rel0\" Type=\"http://schemas.microsoft.com/3dmanufacturing/2013/01/3dmodel\" />\n\
+</Relationships>\n";
+
+impl MeshWriter for ThreeMfWriter {
+    fn write(&self, mesh: &Mesh, path: &Path) -> Result<()> {
+        let file = File::create(path).with_context(|| format!("Failed to create {}", path.display()))?;
+        let mut zip = zip::ZipWriter::new(file);
+        let options = zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+        for (name, content) in [
+            ("[Content_Types].xml", Cow::Borrowed(THREEMF_CONTENT_TYPES)),
+            ("_rels/.rels", Cow::Borrowed(THREEMF_RELATIONSHIPS)),
+            ("3D/3dmodel.model", Cow::Owned(self.model_xml(mesh))),
+        ] {
+            zip.start_file(name, options)?;
+            zip.write_all(content.as_bytes())?;
+        }
+        zip.finish()?;
+        info!("Wrote {} ({} triangles)", path.display(), mesh.indices.len() / 3);
+        Ok(())
+    }
+
+    fn format(&self) -> MeshFormat {
+        MeshFormat::ThreeMf
+    }
+}
+
+fn create(path: &Path) -> Result<BufWriter<File>> {
+    let file = File::create(path).with_context(|| format!("Failed to create {}", path.display()))?;
+    Ok(BufWriter::new(file))
+}
+
+/// Unitless formats are read as millimeters.
+fn in_millimeters(mesh: &Mesh) -> Cow<'_, Mesh> {
+    if mesh.units == MeshUnits::Millimeters {
+        return Cow::Borrowed(mesh);
+    }
+    let mut converted = mesh.clone();
+    converted.convert_units(MeshUnits::Millimeters);
+    Cow::Owned(converted)
+}
+
+fn channel_color(colors: &ChannelColors, channel: u8) -> [u8; 3] {
+    colors
+        .get(&channel)
+        .copied()
+        .unwrap_or(DEFAULT_CHANNEL_COLORS[channel as usize % DEFAULT_CHANNEL_COLORS.len()])
+}
+
+/// Channels in use, ascending; None for single-material meshes.
+fn used_channels(mesh: &Mesh) -> Option<Vec<u8>> {
+    let channels: std::collections::BTreeSet<u8> = mesh.face_channels.as_ref()?.iter().copied().collect();
+    Some(channels.into_iter().collect())
+}
+
+fn triangle_count(mesh: &Mesh) -> u32 {
+    (mesh.indices.len() / 3) as u32
+}
+
+fn triangles(mesh: &Mesh) -> impl Iterator<Item = [[f32; 3]; 3]> + '_ {
+    mesh.indices.chunks_exact(3).map(move |tri| {
+        tri.map(|i| {
+            let v = &mesh.vertices[i as usize * 3..i as usize * 3 + 3];
+            [v[0], v[1], v[2]]
+        })
+    })
+}
+
+/// Unit facet normal from the winding; zero for degenerate triangles.
+fn normal(tri: &[[f32; 3]; 3]) -> [f32; 3] {
+    let [a, b, c] = tri;
+    let u = [b[0] - a[0], b[1] - a[1], b[2] - a[2]];
+    let v = [c[0] - a[0], c[1] - a[1], c[2] - a[2]];
+    let n = [u[1] * v[2] - u[2] * v[1], u[2] * v[0] - u[0] * v[2], u[0] * v[1] - u[1] * v[0]];
+    let length = (n[0] * n[0] + n[1] * n[1] + n[2] * n[2]).sqrt();
+    if length > 0.0 {
+        n.map(|x| x / length)
+    } else {
+        [0.0; 3]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::mesh_loader::{AutoLoader, LoadOptions};
+
+    fn tetrahedron() -> Mesh {
+        Mesh {
+            vertices: vec![0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0],
+            indices: vec![0, 2, 1, 0, 1, 3, 0, 3, 2, 1, 2, 3],
+            normals: None,
+            face_channels: Some(vec![0, 2, 0, 2]),
+            face_objects: None,
+            units: MeshUnits::Centimeters,
+        }
+    }
+
+    #[test]
+    fn test_round_trips_keep_units_and_channels() {
+        let dir = std::env::temp_dir().join(format!("hg4d-convert-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let loader = AutoLoader::with_options(LoadOptions {
+            validate_topology: false,
+            auto_fix: false,
+            target_units: None,
+            center_on_origin: false,
+            scale_factor: 1.0,
+            merge_threshold: None,
+        });
+        let mesh = tetrahedron();
+        let colors = ChannelColors::from([(2, [10, 20, 30])]);
+
+        // 3MF keeps centimeters, channel numbers and colors
+        let path = dir.join("part.3mf");
+        writer_for(MeshFormat::ThreeMf, colors.clone()).unwrap().write(&mesh, &path).unwrap();
+        let (loaded, loaded_colors) = loader.load_with_colors(&path).unwrap();
+        assert_eq!(loaded.units, MeshUnits::Centimeters);
+        assert_eq!(loaded.vertices, mesh.vertices);
+        assert_eq!(loaded.face_channels, mesh.face_channels);
+        assert_eq!(loaded_colors[&2], [10, 20, 30]);
+
+        // OBJ and STL are written in millimeters
+        let path = dir.join("part.obj");
+        writer_for(MeshFormat::Obj, colors).unwrap().write(&mesh, &path).unwrap();
+        let (loaded, loaded_colors) = loader.load_with_colors(&path).unwrap();
+        assert_eq!(loaded.bounding_box().3, 10.0);
+        // Channels 0 and 2 in first-use order
+        assert_eq!(loaded.face_channels, Some(vec![0, 0, 1, 1]));
+        assert_eq!(loaded_colors[&1], [10, 20, 30]);
+
+        for format in [MeshFormat::StlBinary, MeshFormat::StlAscii] {
+            let path = dir.join("part.stl");
+            writer_for(format, ChannelColors::new()).unwrap().write(&mesh, &path).unwrap();
+            assert_eq!(AutoLoader::detect_format(&path).unwrap(), format);
+            let loaded = loader.load(&path).unwrap();
+            assert_eq!(loaded.indices.len(), 12);
+            assert_eq!(loaded.face_channels, None);
+            assert!((loaded.bounding_box().5 - 10.0).abs() < 1e-5);
+        }
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -6,17 +6,56 @@
 //! ## Module Organization
 //!
 //! - **mesh_loader**: Loads 3D models from various file formats
+//! - **mesh_writer**: Writes meshes as STL, OBJ or 3MF
 //! - **layer_generator**: Slices meshes into horizontal layers
 //! - **valve_mapper**: Maps layer geometry to valve grid coordinates
 //! - **path_optimizer**: Optimizes material routing through valve network
+//! - **time_estimator**: Print time model calibrated from firmware feedback
+//! - **transform**: Model placement (translate/rotate/scale, lay flat, auto-orient)
+//! - **slice_cache**: On-disk cache of stage artifacts for incremental re-slicing
+//! - **helical**: Spiral (vase) mode revolutions along a continuous Z ramp
+//! - **shells**: Perimeter rings and patterned infill on the valve grid
+//! - **gradient**: Infill density graded toward walls, surfaces and painted regions
+//! - **modifiers**: Modifier meshes overriding flow, temperature or channel inside a volume
+//! - **pipeline**: Parallel per-layer processing with ordered, bounded output
+//! - **arrange**: Multi-object plates: packing, collision checks, object tagging
+//! - **sparse**: Runs of empty layers, fast-forwarded in one Z move
+//! - **adhesion**: Skirt, brim and raft around the first layer
+//! - **estimate**: Time and material from voxel counts, without slicing
 
 pub mod mesh_loader;
+pub mod mesh_writer;
 pub mod layer_generator;
 pub mod valve_mapper;
 pub mod path_optimizer;
+pub mod time_estimator;
+pub mod transform;
+pub mod slice_cache;
+pub mod helical;
+pub mod shells;
+pub mod gradient;
+pub mod modifiers;
+pub mod pipeline;
+pub mod arrange;
+pub mod sparse;
+pub mod adhesion;
+pub mod estimate;
 
 // Re-exports for convenient access
-pub use mesh_loader::{StlLoader, ObjLoader, ThreeMfLoader, AutoLoader};
+pub use mesh_loader::{StlLoader, ObjLoader, ThreeMfLoader, AutoLoader, ChannelColors, ChannelMapping};
+pub use mesh_writer::{writer_for, MeshWriter, ObjWriter, StlWriter, ThreeMfWriter};
 pub use layer_generator::AdaptiveLayerGenerator;
-pub use valve_mapper::GridAlignedMapper;
+pub use valve_mapper::{BoundaryMode, GridAlignedMapper, RasterNode};
 pub use path_optimizer::AStarOptimizer;
+pub use time_estimator::{TimeEstimator, EstimatorCoefficients, LayerWorkload};
+pub use transform::{apply_transforms, AutoOrienter, Axis, MeshTransform, Transform};
+pub use slice_cache::{CacheKey, CacheStage, SliceCache};
+pub use helical::HelicalSlicer;
+pub use shells::{NodeRole, ShellGenerator, ShellNode};
+pub use gradient::{DensityField, DensityRegion, RegionSpec, SliceStack};
+pub use modifiers::{Modifier, ModifierSet, RegionOverrides};
+pub use pipeline::LayerPipeline;
+pub use arrange::{Collision, ObjectInfo, Plate, PlateObject};
+pub use sparse::{find_empty_runs, mark_empty_layers, EmptyRun};
+pub use adhesion::AdhesionGenerator;
+pub use estimate::{LayerVoxels, MeshVoxelizer, PrintEstimate};
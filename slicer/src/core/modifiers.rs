@@ -0,0 +1,332 @@
+//! Modifier meshes.
+//!
+//! A modifier is a closed mesh listed in `PrintSettings::modifiers` that
+//! deposits nothing itself; the model regions inside it take its flow
+//! multiplier, temperature or material channel instead of their own.
+//!
+//! [`ModifierSet::apply`] sections every modifier at the middle of a layer
+//! and splits each region of the layer into the pieces inside and outside
+//! that section. Pieces inside keep their object and take the modifier's
+//! channel, if it sets one; the flow multiplier and temperature are carried
+//! on the piece as [`RegionOverrides`]:
+//!
+//! - `ShellGenerator` scales the extrusion of every node the piece deposits
+//!   by the flow multiplier, on top of the perimeter flow factor
+//! - `StandardGCodeGenerator::modifier_temperature_commands` sets the zones
+//!   feeding the piece's channel to the temperature on the layers that
+//!   deposit it, and back to their planned targets after
+//!
+//! Modifiers apply in list order, so where two overlap the later one's
+//! overrides win and the earlier one's remain for what the later leaves
+//! unset.
+
+use std::collections::HashMap;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use config_types::ModifierMesh;
+
+use crate::core::estimate::cut;
+use crate::core::slice_cache::CacheKey;
+use crate::utils::{self, ClipOp, Polygon};
+use crate::{LayerSlice, Mesh, MeshUnits, ModelLoader, Region};
+
+/// Lattice section end points are matched on when linking rings (mm).
+const LINK_RESOLUTION: f32 = 1e-4;
+
+/// Overrides a region carries from the modifiers it lies in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct RegionOverrides {
+    /// Extrusion multiplier for the region's nodes
+    #[serde(default)]
+    pub flow_multiplier: Option<f32>,
+    /// Temperature of the zones feeding the region's channel (°C)
+    #[serde(default)]
+    pub temperature: Option<f32>,
+}
+
+impl RegionOverrides {
+    pub fn is_empty(&self) -> bool {
+        self.flow_multiplier.is_none() && self.temperature.is_none()
+    }
+
+    /// Extrusion multiplier, 1.0 without an override.
+    pub fn flow(&self) -> f32 {
+        self.flow_multiplier.unwrap_or(1.0)
+    }
+}
+
+/// A loaded modifier mesh.
+#[derive(Debug, Clone)]
+pub struct Modifier {
+    pub name: String,
+    pub mesh: Mesh,
+    pub settings: ModifierMesh,
+}
+
+impl Modifier {
+    /// Applies the modifier's overrides to a region inside it.
+    fn override_region(&self, region: &mut Region) {
+        if let Some(channel) = self.settings.material_channel {
+            region.material_channel = channel;
+        }
+        if let Some(flow) = self.settings.flow_multiplier {
+            region.overrides.flow_multiplier = Some(flow);
+        }
+        if let Some(temperature) = self.settings.temperature {
+            region.overrides.temperature = Some(temperature);
+        }
+    }
+}
+
+/// The modifiers of a print, in priority order (last wins).
+#[derive(Debug, Clone, Default)]
+pub struct ModifierSet {
+    modifiers: Vec<Modifier>,
+}
+
+impl ModifierSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Loads every modifier mesh in `modifiers`.
+    pub fn load(modifiers: &[ModifierMesh], loader: &impl ModelLoader) -> Result<Self> {
+        let mut set = Self::new();
+        for settings in modifiers {
+            let mesh = loader
+                .load(&settings.path)
+                .with_context(|| format!("Failed to load modifier mesh {}", settings.path.display()))?;
+            let name = settings
+                .path
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+                .unwrap_or_else(|| settings.path.display().to_string());
+            set.add(name, mesh, settings.clone())?;
+        }
+        Ok(set)
+    }
+
+    /// Adds a modifier after those already in the set.
+    pub fn add(&mut self, name: impl Into<String>, mut mesh: Mesh, settings: ModifierMesh) -> Result<()> {
+        let name = name.into();
+        mesh.validate().with_context(|| format!("Modifier mesh '{}' is invalid", name))?;
+        mesh.convert_units(MeshUnits::Millimeters);
+        self.modifiers.push(Modifier { name, mesh, settings });
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.modifiers.is_empty()
+    }
+
+    pub fn modifiers(&self) -> &[Modifier] {
+        &self.modifiers
+    }
+
+    /// Inputs the layer slices depend on, for `CacheKey::slices`.
+    pub fn cache_inputs(&self) -> Vec<(String, &ModifierMesh)> {
+        self.modifiers
+            .iter()
+            .map(|m| (CacheKey::of_mesh(&m.mesh).to_string(), &m.settings))
+            .collect()
+    }
+
+    /// Splits the regions of a layer `thickness` mm thick along the
+    /// modifiers crossing its middle.
+    pub fn apply(&self, slice: &mut LayerSlice, thickness: f32) {
+        let z = slice.z_height - thickness / 2.0;
+        let sections: Vec<(&Modifier, Vec<Region>)> = self
+            .modifiers
+            .iter()
+            .filter(|m| {
+                let (_, _, min_z, _, _, max_z) = m.mesh.bounding_box();
+                (min_z..=max_z).contains(&z)
+            })
+            .map(|m| (m, section(&m.mesh, z)))
+            .filter(|(_, section)| !section.is_empty())
+            .collect();
+        if sections.is_empty() {
+            return;
+        }
+
+        let regions = std::mem::take(&mut slice.regions);
+        for region in regions {
+            let mut pieces = vec![region];
+            for (modifier, section) in &sections {
+                pieces = pieces
+                    .into_iter()
+                    .flat_map(|piece| {
+                        let mut inside = piece.clip(section, ClipOp::Intersection);
+                        if inside.is_empty() {
+                            return vec![piece];
+                        }
+                        inside.iter_mut().for_each(|p| modifier.override_region(p));
+                        let mut split = piece.clip(section, ClipOp::Difference);
+                        split.extend(inside);
+                        split
+                    })
+                    .collect();
+            }
+            slice.regions.extend(pieces);
+        }
+        debug!(
+            "Layer {}: {} modifiers split the layer into {} regions",
+            slice.layer_number,
+            sections.len(),
+            slice.regions.len()
+        );
+    }
+}
+
+/// Cross-section of a closed mesh at `z`.
+///
+/// Each cut segment is directed so the solid lies on its left, as told by
+/// the outward normal of its face, and segments are linked end to start,
+/// so outer boundaries come out counter-clockwise and holes clockwise.
+/// Open chains left by a mesh that is not closed are dropped.
+pub fn section(mesh: &Mesh, z: f32) -> Vec<Region> {
+    section_faces(mesh, z, |_| true)
+}
+
+/// [`section`] of the faces for which `keep` returns true, by face index.
+pub(crate) fn section_faces(mesh: &Mesh, z: f32, keep: impl Fn(usize) -> bool) -> Vec<Region> {
+    let key = |p: (f32, f32)| {
+        (
+            (p.0 / LINK_RESOLUTION).round() as i64,
+            (p.1 / LINK_RESOLUTION).round() as i64,
+        )
+    };
+    let vertex = |i: u32| {
+        let i = i as usize * 3;
+        [mesh.vertices[i], mesh.vertices[i + 1], mesh.vertices[i + 2]]
+    };
+
+    // Segment start -> (end, start point)
+    let mut links: HashMap<(i64, i64), ((i64, i64), (f32, f32))> = HashMap::new();
+    for (face, tri) in mesh.indices.chunks_exact(3).enumerate() {
+        if !keep(face) {
+            continue;
+        }
+        let (a, b, c) = (vertex(tri[0]), vertex(tri[1]), vertex(tri[2]));
+        let Some((p, q)) = cut(a, b, c, z) else {
+            continue;
+        };
+        let (u, v) = ([b[0] - a[0], b[1] - a[1], b[2] - a[2]], [c[0] - a[0], c[1] - a[1], c[2] - a[2]]);
+        let normal = (u[1] * v[2] - u[2] * v[1], u[2] * v[0] - u[0] * v[2]);
+        // The left of p -> q, (-dy, dx), must point away from the normal
+        let (dx, dy) = (q.0 - p.0, q.1 - p.1);
+        let (p, q) = if -dy * normal.0 + dx * normal.1 > 0.0 { (q, p) } else { (p, q) };
+        if key(p) != key(q) {
+            links.insert(key(p), (key(q), p));
+        }
+    }
+
+    let mut rings = Vec::new();
+    while let Some(&start) = links.keys().next() {
+        let mut points = Vec::new();
+        let mut at = start;
+        while let Some((next, point)) = links.remove(&at) {
+            points.push(point);
+            at = next;
+        }
+        if at == start && points.len() >= 3 {
+            rings.push(Polygon::from_tuples(&points));
+        }
+    }
+
+    let rings = utils::clipping::boolean(&rings, &[], ClipOp::Union);
+    utils::clipping::group_rings(rings)
+        .into_iter()
+        .map(|(outer, holes)| Region {
+            outer: outer.to_tuples(),
+            holes: holes.iter().map(|h| h.to_tuples()).collect(),
+            material_channel: 0,
+            object_id: None,
+            overrides: RegionOverrides::default(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// Closed box from (x0, y0, z0) to (x1, y1, z1).
+    fn cuboid(x0: f32, y0: f32, z0: f32, x1: f32, y1: f32, z1: f32) -> Mesh {
+        let vertices = vec![
+            x0, y0, z0, x1, y0, z0, x1, y1, z0, x0, y1, z0, //
+            x0, y0, z1, x1, y0, z1, x1, y1, z1, x0, y1, z1,
+        ];
+        let indices = vec![
+            0, 2, 1, 0, 3, 2, 4, 5, 6, 4, 6, 7, 0, 1, 5, 0, 5, 4, //
+            1, 2, 6, 1, 6, 5, 2, 3, 7, 2, 7, 6, 3, 0, 4, 3, 4, 7,
+        ];
+        Mesh {
+            vertices,
+            indices,
+            normals: None,
+            face_channels: None,
+            face_objects: None,
+            units: MeshUnits::Millimeters,
+        }
+    }
+
+    #[test]
+    fn test_modifiers_split_regions() {
+        let cut = section(&cuboid(0.0, 0.0, 0.0, 10.0, 20.0, 5.0), 2.5);
+        assert_eq!(cut.len(), 1);
+        assert!((cut[0].area() - 200.0).abs() < 1e-3);
+        assert!(section(&cuboid(0.0, 0.0, 0.0, 10.0, 20.0, 5.0), 6.0).is_empty());
+
+        let modifier = |flow, temperature, material_channel| ModifierMesh {
+            path: PathBuf::from("modifier.stl"),
+            flow_multiplier: flow,
+            temperature,
+            material_channel,
+        };
+        let mut modifiers = ModifierSet::new();
+        // Left half of the part, lower 5mm; then a corner that overlaps it
+        let left = cuboid(-5.0, -5.0, 0.0, 10.0, 25.0, 5.0);
+        modifiers.add("left", left, modifier(Some(1.5), None, Some(2))).unwrap();
+        let corner = cuboid(5.0, 15.0, 0.0, 15.0, 25.0, 5.0);
+        modifiers.add("corner", corner, modifier(None, Some(215.0), None)).unwrap();
+
+        let part = Region {
+            outer: vec![(0.0, 0.0), (20.0, 0.0), (20.0, 20.0), (0.0, 20.0)],
+            holes: vec![],
+            material_channel: 0,
+            object_id: Some(3),
+            overrides: RegionOverrides::default(),
+        };
+        let layer = |z_height| LayerSlice { z_height, layer_number: 0, regions: vec![part.clone()] };
+        let mut slice = layer(1.0);
+        modifiers.apply(&mut slice, 0.2);
+
+        let area = |channel: u8, overrides: RegionOverrides| -> f32 {
+            slice
+                .regions
+                .iter()
+                .filter(|r| r.material_channel == channel && r.overrides == overrides)
+                .map(Region::area)
+                .sum()
+        };
+        let left = RegionOverrides { flow_multiplier: Some(1.5), temperature: None };
+        let both = RegionOverrides { flow_multiplier: Some(1.5), temperature: Some(215.0) };
+        let corner = RegionOverrides { flow_multiplier: None, temperature: Some(215.0) };
+        assert!((area(2, left) - 175.0).abs() < 1e-3);
+        assert!((area(2, both) - 25.0).abs() < 1e-3);
+        assert!((area(0, corner) - 25.0).abs() < 1e-3);
+        assert!((area(0, RegionOverrides::default()) - 175.0).abs() < 1e-3);
+        assert!(slice.regions.iter().all(|r| r.object_id == Some(3)));
+
+        // Above the modifiers the layer is left whole
+        let mut slice = layer(6.0);
+        modifiers.apply(&mut slice, 0.2);
+        assert_eq!(slice.regions.len(), 1);
+        assert!(slice.regions[0].overrides.is_empty());
+    }
+}
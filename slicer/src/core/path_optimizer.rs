@@ -1,41 +1,105 @@
 //! Path optimization algorithms for efficient material routing through valve network.
 
-use crate::{ValveActivationMap, RoutingConfig, OptimizedRouting, RoutingPath, SlicerError};
+use crate::{ValveActivationMap, RoutingConfig, OptimizedRouting, RoutingOptimizer, RoutingPath, SlicerError};
 use gcode_types::GridCoordinate;
 use anyhow::Result;
-use std::collections::HashMap;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
 
-/// Trait for routing optimization.
-pub trait RoutingOptimizer: Send + Sync {
-    fn optimize_routing(
-        &self,
-        activation_map: &ValveActivationMap,
-        config: &RoutingConfig,
-    ) -> Result<OptimizedRouting>;
-    
-    fn evaluate_routing(&self, routing: &OptimizedRouting) -> f32;
-}
+/// Fixed-point scale for path costs so the open set can be ordered exactly.
+const COST_SCALE: f32 = 1000.0;
+
+/// Fraction of supply pressure lost per grid segment traversed.
+pub(crate) const PRESSURE_LOSS_PER_SEGMENT: f32 = 0.02;
+
+/// Number of paths already using each grid edge.
+type EdgeUsage = HashMap<(GridCoordinate, GridCoordinate), u32>;
 
 /// A* pathfinding-based routing optimizer.
+///
+/// Nodes are routed one at a time in grid order. Each edge already carrying
+/// material for an earlier path costs `1 + congestion_weight * uses`, so later
+/// paths spread across the network instead of piling onto the same channels.
 pub struct AStarOptimizer {
     heuristic_weight: f32,
+    congestion_weight: f32,
 }
 
 impl AStarOptimizer {
     pub fn new() -> Self {
         Self {
             heuristic_weight: 1.0,
+            congestion_weight: 0.5,
         }
     }
 
-    /// Finds shortest path from source to destination through valve network.
+    /// Sets the extra cost per existing use of an edge.
+    pub fn with_congestion_weight(mut self, weight: f32) -> Self {
+        self.congestion_weight = weight.max(0.0);
+        self
+    }
+
+    /// Finds the cheapest path from source to destination through the valve
+    /// network, taking existing edge usage into account.
+    ///
+    /// Paths longer than `config.max_path_length` segments or leaving the
+    /// grid are never returned. Search states are (node, steps taken): a
+    /// dearer but shorter way to a node keeps the slack to detour later, so
+    /// a cheaper, longer arrival must not replace it.
     fn find_path(
         &self,
         from: GridCoordinate,
         to: GridCoordinate,
         config: &RoutingConfig,
+        usage: &EdgeUsage,
     ) -> Option<RoutingPath> {
-        todo!("Implementation needed: A* pathfinding through valve network")
+        let max_len = config.max_path_length;
+        let grid = (config.grid_width, config.grid_height);
+        if from.manhattan_distance(&to) > max_len || to.x >= grid.0 || to.y >= grid.1 {
+            return None;
+        }
+
+        // Open set ordered by (f, h, y, x, steps) so ties resolve identically
+        // every run
+        let mut open = BinaryHeap::new();
+        let mut best: HashMap<(GridCoordinate, u32), u64> = HashMap::new();
+        let mut came_from: HashMap<(GridCoordinate, u32), GridCoordinate> = HashMap::new();
+
+        let h0 = self.scaled_heuristic(from, to);
+        open.push(Reverse((h0, h0, from.y, from.x, 0)));
+        best.insert((from, 0), 0);
+
+        while let Some(Reverse((f, h, y, x, steps))) = open.pop() {
+            let current = GridCoordinate::new(x, y);
+            if current == to {
+                return Some(build_path(from, (to, steps), &came_from));
+            }
+
+            let g = best[&(current, steps)];
+            if f != g + h {
+                continue; // Stale entry superseded by a cheaper one
+            }
+
+            for (next, _) in neighbors(current, grid) {
+                let next_steps = steps + 1;
+                if next_steps + next.manhattan_distance(&to) > max_len {
+                    continue;
+                }
+
+                let next_g = g + self.edge_cost(current, next, usage);
+                let improved = best
+                    .get(&(next, next_steps))
+                    .is_none_or(|&known| next_g < known);
+                if improved {
+                    best.insert((next, next_steps), next_g);
+                    came_from.insert((next, next_steps), current);
+                    let next_h = self.scaled_heuristic(next, to);
+                    open.push(Reverse((next_g + next_h, next_h, next.y, next.x, next_steps)));
+                }
+            }
+        }
+
+        None
     }
 
     /// Calculates heuristic distance between two grid points.
@@ -44,18 +108,44 @@ impl AStarOptimizer {
         (from.x.abs_diff(to.x) + from.y.abs_diff(to.y)) as f32
     }
 
-    /// Estimates pressure drop along a path.
+    fn scaled_heuristic(&self, from: GridCoordinate, to: GridCoordinate) -> u64 {
+        (self.heuristic(from, to) * self.heuristic_weight * COST_SCALE) as u64
+    }
+
+    /// Cost of traversing one edge given how many paths already use it.
+    fn edge_cost(&self, a: GridCoordinate, b: GridCoordinate, usage: &EdgeUsage) -> u64 {
+        let uses = usage.get(&edge_key(a, b)).copied().unwrap_or(0);
+        ((1.0 + self.congestion_weight * uses as f32) * COST_SCALE) as u64
+    }
+
+    /// Total congestion-aware cost of a path.
+    fn path_cost(&self, path: &RoutingPath, usage: &EdgeUsage) -> u64 {
+        path_nodes(path)
+            .windows(2)
+            .map(|w| self.edge_cost(w[0], w[1], usage))
+            .sum()
+    }
+
+    /// Estimates pressure drop along a path as a fraction of supply pressure.
     fn estimate_pressure_drop(&self, path: &RoutingPath) -> f32 {
-        todo!("Implementation needed: Estimate pressure loss along routing path")
+        let segments = path_length(path) as i32;
+        1.0 - (1.0 - PRESSURE_LOSS_PER_SEGMENT).powi(segments)
     }
 
     /// Finds optimal injection point for a set of target nodes.
+    ///
+    /// Picks the point with the smallest total Manhattan distance; ties go to
+    /// the point listed first.
     fn select_injection_point(
         &self,
         targets: &[GridCoordinate],
         injection_points: &[GridCoordinate],
     ) -> GridCoordinate {
-        todo!("Implementation needed: Select best injection point for targets")
+        injection_points
+            .iter()
+            .copied()
+            .min_by_key(|p| targets.iter().map(|t| p.manhattan_distance(t) as u64).sum::<u64>())
+            .unwrap_or_else(|| GridCoordinate::new(0, 0))
     }
 }
 
@@ -71,11 +161,149 @@ impl RoutingOptimizer for AStarOptimizer {
         activation_map: &ValveActivationMap,
         config: &RoutingConfig,
     ) -> Result<OptimizedRouting> {
-        todo!("Implementation needed: Optimize routing for all active nodes")
+        if config.injection_points.is_empty() {
+            return Err(SlicerError::RoutingOptimization(
+                "No injection points configured".to_string(),
+            )
+            .into());
+        }
+
+        // Route in a fixed order so identical inputs give identical paths
+        let mut targets: Vec<GridCoordinate> =
+            activation_map.active_nodes.iter().map(|n| n.position).collect();
+        targets.sort_by_key(|p| (p.y, p.x));
+        targets.dedup();
+
+        let mut usage = EdgeUsage::new();
+        let mut routing_paths = Vec::with_capacity(targets.len());
+        let mut estimated_pressure = HashMap::new();
+
+        for target in targets {
+            let preferred = self.select_injection_point(&[target], &config.injection_points);
+            let mut candidates = config.injection_points.clone();
+            candidates.sort_by_key(|p| (*p != preferred, p.manhattan_distance(&target), p.y, p.x));
+            candidates.dedup();
+
+            let mut chosen: Option<(u64, RoutingPath)> = None;
+            for source in candidates {
+                let Some(path) = self.find_path(source, target, config, &usage) else {
+                    continue;
+                };
+                let cost = self.path_cost(&path, &usage);
+                if chosen.as_ref().is_none_or(|(best, _)| cost < *best) {
+                    chosen = Some((cost, path));
+                }
+            }
+
+            let (_, path) = chosen.ok_or_else(|| {
+                SlicerError::RoutingOptimization(format!(
+                    "No path to node ({}, {}) within {} segments",
+                    target.x, target.y, config.max_path_length
+                ))
+            })?;
+
+            for w in path_nodes(&path).windows(2) {
+                *usage.entry(edge_key(w[0], w[1])).or_insert(0) += 1;
+            }
+
+            let pressure = config.pressure_limit * (1.0 - self.estimate_pressure_drop(&path));
+            estimated_pressure.insert(target, pressure);
+            routing_paths.push(path);
+        }
+
+        let mut routing = OptimizedRouting {
+            activation_map: activation_map.clone(),
+            routing_paths,
+            estimated_pressure,
+            efficiency: 0.0,
+        };
+        routing.efficiency = self.evaluate_routing(&routing);
+        Ok(routing)
     }
 
+    /// Ratio of the ideal (Manhattan) path length to the routed length,
+    /// summed over all paths.
     fn evaluate_routing(&self, routing: &OptimizedRouting) -> f32 {
-        todo!("Implementation needed: Evaluate routing quality (0.0 = poor, 1.0 = optimal)")
+        let (ideal, actual) = routing.routing_paths.iter().fold((0u64, 0u64), |(i, a), p| {
+            (i + p.from.manhattan_distance(&p.to) as u64, a + path_length(p) as u64)
+        });
+        if actual == 0 {
+            return 1.0;
+        }
+        ideal as f32 / actual as f32
+    }
+}
+
+/// Four-connected neighbours inside a `(width, height)` grid with the valve
+/// that opens toward each one (0=X+, 1=X-, 2=Y+, 3=Y-).
+fn neighbors(p: GridCoordinate, (width, height): (u32, u32)) -> impl Iterator<Item = (GridCoordinate, u8)> {
+    [
+        p.x.checked_add(1).filter(|&x| x < width).map(|x| (GridCoordinate::new(x, p.y), 0)),
+        p.x.checked_sub(1).map(|x| (GridCoordinate::new(x, p.y), 1)),
+        p.y.checked_add(1).filter(|&y| y < height).map(|y| (GridCoordinate::new(p.x, y), 2)),
+        p.y.checked_sub(1).map(|y| (GridCoordinate::new(p.x, y), 3)),
+    ]
+    .into_iter()
+    .flatten()
+}
+
+/// Valve at `from` that must open to flow toward the adjacent node `to`.
+fn valve_toward(from: GridCoordinate, to: GridCoordinate) -> u8 {
+    neighbors(from, (u32::MAX, u32::MAX))
+        .find(|(n, _)| *n == to)
+        .map(|(_, valve)| valve)
+        .unwrap_or(0)
+}
+
+/// Direction-independent key for the edge between two adjacent nodes.
+fn edge_key(a: GridCoordinate, b: GridCoordinate) -> (GridCoordinate, GridCoordinate) {
+    if (a.y, a.x) <= (b.y, b.x) { (a, b) } else { (b, a) }
+}
+
+fn path_nodes(path: &RoutingPath) -> Vec<GridCoordinate> {
+    let mut nodes = Vec::with_capacity(path.intermediate_nodes.len() + 2);
+    nodes.push(path.from);
+    nodes.extend(&path.intermediate_nodes);
+    if path.to != path.from {
+        nodes.push(path.to);
+    }
+    nodes
+}
+
+/// Number of grid segments in a path.
+fn path_length(path: &RoutingPath) -> u32 {
+    path_nodes(path).len() as u32 - 1
+}
+
+/// Walks `came_from` back from `to`, reached after `steps` segments.
+fn build_path(
+    from: GridCoordinate,
+    (to, steps): (GridCoordinate, u32),
+    came_from: &HashMap<(GridCoordinate, u32), GridCoordinate>,
+) -> RoutingPath {
+    let mut nodes = vec![to];
+    let mut current = to;
+    for step in (1..=steps).rev() {
+        current = came_from[&(current, step)];
+        nodes.push(current);
+    }
+    nodes.reverse();
+
+    let valve_sequence = nodes
+        .windows(2)
+        .map(|w| (w[0], valve_toward(w[0], w[1])))
+        .collect();
+    let intermediate_nodes = if nodes.len() > 2 {
+        nodes[1..nodes.len() - 1].to_vec()
+    } else {
+        Vec::new()
+    };
+
+    RoutingPath {
+        from,
+        to,
+        intermediate_nodes,
+        valve_sequence,
     }
 }
 
@@ -90,4 +318,137 @@ mod tests {
         let to = GridCoordinate::new(3, 4);
         assert_eq!(optimizer.heuristic(from, to), 7.0);
     }
+
+    fn config(injection_points: Vec<GridCoordinate>, max_path_length: u32) -> RoutingConfig {
+        RoutingConfig {
+            injection_points,
+            max_path_length,
+            pressure_limit: 100.0,
+            grid_width: 64,
+            grid_height: 64,
+        }
+    }
+
+    fn activation_map(positions: &[(u32, u32)]) -> ValveActivationMap {
+        ValveActivationMap {
+            layer_number: 0,
+            z_height: 0.2,
+            active_nodes: positions
+                .iter()
+                .map(|&(x, y)| crate::ActiveNode {
+                    position: GridCoordinate::new(x, y),
+                    material_channel: 0,
+                    required_valves: Vec::new(),
+                    object_id: None,
+                    extrusion: None,
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_find_path_is_shortest() {
+        let optimizer = AStarOptimizer::new();
+        let cfg = config(vec![GridCoordinate::new(0, 0)], 20);
+        let path = optimizer
+            .find_path(GridCoordinate::new(0, 0), GridCoordinate::new(3, 2), &cfg, &EdgeUsage::new())
+            .unwrap();
+        assert_eq!(path_length(&path), 5);
+        assert_eq!(path.valve_sequence.len(), 5);
+        assert_eq!(path.intermediate_nodes.len(), 4);
+    }
+
+    #[test]
+    fn test_max_path_length_respected() {
+        let optimizer = AStarOptimizer::new();
+        let cfg = config(vec![GridCoordinate::new(0, 0)], 4);
+        let result = optimizer.optimize_routing(&activation_map(&[(3, 2)]), &cfg);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_routes_from_nearest_injection_point() {
+        let optimizer = AStarOptimizer::new();
+        let cfg = config(vec![GridCoordinate::new(0, 0), GridCoordinate::new(10, 0)], 30);
+        let routing = optimizer
+            .optimize_routing(&activation_map(&[(9, 1), (1, 1)]), &cfg)
+            .unwrap();
+
+        assert_eq!(routing.routing_paths.len(), 2);
+        assert_eq!(routing.routing_paths[0].from, GridCoordinate::new(0, 0));
+        assert_eq!(routing.routing_paths[1].from, GridCoordinate::new(10, 0));
+        assert!((routing.efficiency - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_congestion_spreads_paths() {
+        let optimizer = AStarOptimizer::new().with_congestion_weight(2.0);
+        let cfg = config(vec![GridCoordinate::new(0, 0)], 20);
+        let routing = optimizer
+            .optimize_routing(&activation_map(&[(2, 2), (3, 3)]), &cfg)
+            .unwrap();
+
+        let first: Vec<_> = path_nodes(&routing.routing_paths[0]);
+        let second: Vec<_> = path_nodes(&routing.routing_paths[1]);
+        let shared = first
+            .windows(2)
+            .filter(|w| {
+                second
+                    .windows(2)
+                    .any(|v| edge_key(v[0], v[1]) == edge_key(w[0], w[1]))
+            })
+            .count();
+        assert!(shared < path_length(&routing.routing_paths[0]) as usize);
+    }
+
+    #[test]
+    fn test_paths_stay_on_grid() {
+        let optimizer = AStarOptimizer::new().with_congestion_weight(10.0);
+        // A single column: however busy its edges, there is no way around
+        let cfg = RoutingConfig { grid_width: 1, grid_height: 4, ..config(vec![GridCoordinate::new(0, 0)], 10) };
+        let usage = EdgeUsage::from([(edge_key(GridCoordinate::new(0, 1), GridCoordinate::new(0, 2)), 5)]);
+        let path = optimizer
+            .find_path(GridCoordinate::new(0, 0), GridCoordinate::new(0, 3), &cfg, &usage)
+            .unwrap();
+        assert!(path_nodes(&path).iter().all(|n| n.x == 0 && n.y < 4));
+        assert_eq!(path_length(&path), 3);
+        assert!(optimizer.find_path(GridCoordinate::new(0, 0), GridCoordinate::new(1, 0), &cfg, &usage).is_none());
+    }
+
+    #[test]
+    fn test_cheaper_longer_arrival_keeps_shorter_one() {
+        let optimizer = AStarOptimizer::new();
+        let cfg = RoutingConfig { grid_width: 4, grid_height: 2, ..config(vec![GridCoordinate::new(0, 0)], 5) };
+        let edge = |a: (u32, u32), b: (u32, u32)| {
+            edge_key(GridCoordinate::new(a.0, a.1), GridCoordinate::new(b.0, b.1))
+        };
+        // Detouring to (1, 0) is cheaper than the busy direct edge, but
+        // uses up the slack needed to get around the busy (2, 0)-(3, 0)
+        let usage = EdgeUsage::from([
+            (edge((0, 0), (1, 0)), 10),
+            (edge((2, 0), (3, 0)), 10),
+            (edge((1, 1), (2, 1)), 10),
+            (edge((0, 1), (1, 1)), 2),
+        ]);
+        let path = optimizer
+            .find_path(GridCoordinate::new(0, 0), GridCoordinate::new(3, 0), &cfg, &usage)
+            .unwrap();
+        let nodes: Vec<_> = path_nodes(&path).iter().map(|n| (n.x, n.y)).collect();
+        assert_eq!(nodes, vec![(0, 0), (1, 0), (2, 0), (2, 1), (3, 1), (3, 0)]);
+        assert_eq!(optimizer.path_cost(&path, &usage), 10 * COST_SCALE as u64);
+    }
+
+    #[test]
+    fn test_routing_is_deterministic() {
+        let optimizer = AStarOptimizer::new();
+        let cfg = config(vec![GridCoordinate::new(0, 0), GridCoordinate::new(8, 8)], 30);
+        let map = activation_map(&[(4, 4), (2, 6), (6, 2), (4, 5)]);
+
+        let a = optimizer.optimize_routing(&map, &cfg).unwrap();
+        let b = optimizer.optimize_routing(&map, &cfg).unwrap();
+        let nodes = |r: &OptimizedRouting| {
+            r.routing_paths.iter().map(path_nodes).collect::<Vec<_>>()
+        };
+        assert_eq!(nodes(&a), nodes(&b));
+    }
 }
@@ -0,0 +1,167 @@
+//! Parallel per-layer processing.
+//!
+//! Layers are independent once the mesh is sliced, so slicing, valve
+//! mapping and routing optimization run per layer on a rayon thread pool
+//! sized by `SlicerConfig::worker_threads`. Idle workers steal queued
+//! layers from busy ones.
+//!
+//! Results are handed to the sink (normally the `.hg4d` writer) strictly in
+//! input order. A layer counts as in flight from the moment it is queued
+//! until the sink has taken it, including while it waits in the reorder
+//! buffer behind a slower predecessor; at most
+//! `SlicerConfig::max_in_flight_layers` are in flight at once, which bounds
+//! memory regardless of model height or thread count.
+
+use std::collections::BTreeMap;
+use std::panic::AssertUnwindSafe;
+use std::sync::mpsc;
+
+use anyhow::{anyhow, Context, Result};
+use tracing::debug;
+
+use crate::SlicerConfig;
+
+/// Thread pool running a per-layer stage with ordered, bounded output.
+pub struct LayerPipeline {
+    pool: rayon::ThreadPool,
+    max_in_flight: usize,
+}
+
+impl LayerPipeline {
+    /// `worker_threads` of 0 uses every core.
+    pub fn new(worker_threads: usize, max_in_flight_layers: usize) -> Result<Self> {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(worker_threads)
+            .thread_name(|i| format!("slicer-worker-{}", i))
+            .build()
+            .context("Failed to build slicer thread pool")?;
+        debug!(
+            "Layer pipeline: {} threads, {} layers in flight",
+            pool.current_num_threads(),
+            max_in_flight_layers.max(1)
+        );
+        Ok(Self {
+            pool,
+            max_in_flight: max_in_flight_layers.max(1),
+        })
+    }
+
+    pub fn from_config(config: &SlicerConfig) -> Result<Self> {
+        Self::new(config.worker_threads, config.max_in_flight_layers)
+    }
+
+    pub fn threads(&self) -> usize {
+        self.pool.current_num_threads()
+    }
+
+    /// Runs `process` over `inputs` in parallel and passes each result to
+    /// `sink` in input order. Returns the number of layers sunk.
+    ///
+    /// The first failure, in `process` or `sink`, stops new layers being
+    /// queued; layers already running finish and are dropped, and that
+    /// failure is returned.
+    pub fn run<I, T, U, F, S>(&self, inputs: I, process: F, mut sink: S) -> Result<usize>
+    where
+        I: IntoIterator<Item = T>,
+        T: Send,
+        U: Send,
+        F: Fn(T) -> Result<U> + Sync,
+        S: FnMut(U) -> Result<()>,
+    {
+        let mut inputs = inputs.into_iter();
+        let (tx, rx) = mpsc::channel::<(usize, Result<U>)>();
+        let mut reorder = BTreeMap::new();
+        let mut queued = 0;
+        let mut sunk = 0;
+        let mut failure: Option<anyhow::Error> = None;
+        let process = &process;
+
+        // The calling thread waits on results while the pool works
+        self.pool.in_place_scope(|scope| {
+            loop {
+                while failure.is_none() && queued - sunk < self.max_in_flight {
+                    let Some(input) = inputs.next() else {
+                        break;
+                    };
+                    let tx = tx.clone();
+                    let index = queued;
+                    scope.spawn(move |_| {
+                        // A panicking layer must still report, or the
+                        // receiver below would wait for it forever
+                        let result = std::panic::catch_unwind(AssertUnwindSafe(|| process(input)))
+                            .unwrap_or_else(|_| Err(anyhow!("Worker panicked")));
+                        tx.send((index, result)).ok();
+                    });
+                    queued += 1;
+                }
+                if sunk == queued {
+                    break;
+                }
+
+                let Ok((index, result)) = rx.recv() else {
+                    break;
+                };
+                reorder.insert(index, result);
+                while let Some(result) = reorder.remove(&sunk) {
+                    let layer = sunk;
+                    sunk += 1;
+                    if failure.is_some() {
+                        continue;
+                    }
+                    if let Err(e) = result.and_then(&mut sink) {
+                        failure = Some(e.context(format!("Layer {} failed", layer)));
+                    }
+                }
+            }
+        });
+
+        match failure {
+            Some(e) => Err(e),
+            None => Ok(sunk),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    #[test]
+    fn test_ordered_output_with_bounded_in_flight() {
+        let pipeline = LayerPipeline::new(4, 3).unwrap();
+        let running = AtomicUsize::new(0);
+        let peak = AtomicUsize::new(0);
+        let mut written = Vec::new();
+
+        let count = pipeline
+            .run(
+                0..40u32,
+                |layer| {
+                    let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                    peak.fetch_max(now, Ordering::SeqCst);
+                    // Early layers are slowest, so later ones finish first
+                    std::thread::sleep(Duration::from_millis(((40 - layer) % 7) as u64));
+                    running.fetch_sub(1, Ordering::SeqCst);
+                    Ok(layer * 2)
+                },
+                |value| {
+                    written.push(value);
+                    Ok(())
+                },
+            )
+            .unwrap();
+
+        assert_eq!(count, 40);
+        assert_eq!(written, (0..40).map(|l| l * 2).collect::<Vec<_>>());
+        assert!(peak.load(Ordering::SeqCst) <= 3);
+
+        let failed = pipeline.run(
+            0..10u32,
+            |layer| if layer == 5 { Err(anyhow!("no regions")) } else { Ok(layer) },
+            |_| Ok(()),
+        );
+        assert!(format!("{:#}", failed.unwrap_err()).contains("Layer 5 failed: no regions"));
+    }
+}
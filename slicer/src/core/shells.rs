@@ -0,0 +1,265 @@
+//! Perimeter shells and infill on the valve grid.
+//!
+//! A region's nodes are split by depth below its boundary: a node is in
+//! ring `k` when it lies inside the region inset by `k` grid spacings but
+//! not inside the inset by `k + 1`. The first `perimeter_count` rings form
+//! the shell, deposited solid with the perimeter flow factor and optionally
+//! on a separate channel. Deeper nodes are infill, thinned to the configured
+//! density by a node-level pattern that varies with the layer so successive
+//! layers bond. With a graded infill the density is set per node by a
+//! [`DensityField`]; further insets, up to the transition distance, give
+//! each infill node its distance to the shell. A region's flow override
+//! from a modifier mesh scales the extrusion of all its nodes.
+
+use anyhow::Result;
+
+use config_types::{InfillPattern, InfillSettings, PrintSettings, ShellSettings};
+use gcode_types::GridCoordinate;
+
+use crate::core::gradient::{DensityField, RegionSpec, SliceStack};
+use crate::core::valve_mapper::{GridAlignedMapper, RasterNode};
+use crate::{Region, ValveGridConfig};
+
+/// What a node deposits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeRole {
+    /// Perimeter ring, 0 being outermost
+    Perimeter(u32),
+    Infill,
+}
+
+/// A node with its role in the layer.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ShellNode {
+    pub node: RasterNode,
+    pub role: NodeRole,
+    pub material_channel: u8,
+}
+
+/// Splits regions into perimeter rings and patterned infill.
+#[derive(Debug, Clone)]
+pub struct ShellGenerator {
+    shells: ShellSettings,
+    infill: InfillSettings,
+    /// Per-node density of a graded infill
+    density: Option<DensityField>,
+}
+
+impl ShellGenerator {
+    /// Generator for `settings`, without the painted regions of a graded
+    /// infill (see [`load`](Self::load)).
+    pub fn new(settings: &PrintSettings) -> Self {
+        Self {
+            shells: settings.shells.clone(),
+            infill: settings.infill.clone(),
+            density: settings
+                .infill
+                .gradient
+                .clone()
+                .map(|g| DensityField::new(g, RegionSpec::default())),
+        }
+    }
+
+    /// Generator for `settings`, reading a graded infill's region spec file.
+    pub fn load(settings: &PrintSettings) -> Result<Self> {
+        let density = settings.infill.gradient.as_ref().map(DensityField::load).transpose()?;
+        Ok(Self { density, ..Self::new(settings) })
+    }
+
+    /// Nodes a region deposits on a layer.
+    pub fn generate(
+        &self,
+        region: &Region,
+        grid: &ValveGridConfig,
+        mapper: &GridAlignedMapper,
+        layer_number: u32,
+    ) -> Vec<ShellNode> {
+        self.generate_in_stack(region, grid, mapper, layer_number, None)
+    }
+
+    /// [`generate`](Self::generate) with the print's slices, which a graded
+    /// infill needs to grade toward top and bottom surfaces and to place
+    /// painted regions in Z. Without them it grades toward walls only.
+    pub fn generate_in_stack(
+        &self,
+        region: &Region,
+        grid: &ValveGridConfig,
+        mapper: &GridAlignedMapper,
+        layer_number: u32,
+        stack: Option<&SliceStack>,
+    ) -> Vec<ShellNode> {
+        // Rings past the shell that measure a graded infill's wall distance
+        let wall_rings = match &self.density {
+            Some(field) if field.gradient().near_walls && grid.spacing > 0.0 => {
+                (field.gradient().transition_distance / grid.spacing).ceil() as u32
+            }
+            _ => 0,
+        };
+        let insets: Vec<Vec<Region>> = (1..=self.shells.perimeter_count + wall_rings)
+            .map(|k| region.offset(-(k as f32) * grid.spacing))
+            .collect();
+        let perimeter_channel = self.shells.material_channel.unwrap_or(region.material_channel);
+        let z = stack.and_then(|s| s.z_of(layer_number));
+        let flow = region.overrides.flow();
+
+        mapper
+            .rasterize_region(region, grid)
+            .into_iter()
+            .filter_map(|node| {
+                let x = grid.origin_x + node.position.x as f32 * grid.spacing;
+                let y = grid.origin_y + node.position.y as f32 * grid.spacing;
+                let depth = insets
+                    .iter()
+                    .take_while(|inset| inset.iter().any(|r| r.contains_point(x, y)))
+                    .count() as u32;
+
+                if depth < self.shells.perimeter_count {
+                    Some(ShellNode {
+                        node: RasterNode {
+                            extrusion: node.extrusion * self.shells.flow_factor * flow,
+                            ..node
+                        },
+                        role: NodeRole::Perimeter(depth),
+                        material_channel: perimeter_channel,
+                    })
+                } else {
+                    let density = match &self.density {
+                        Some(field) => {
+                            let transition = field.gradient().transition_distance;
+                            let wall = (depth < insets.len() as u32)
+                                .then(|| (depth - self.shells.perimeter_count) as f32 * grid.spacing);
+                            let surface =
+                                stack.and_then(|s| s.surface_distance(layer_number, x, y, transition));
+                            field.density(x, y, z, wall, surface)
+                        }
+                        None => self.infill.density,
+                    };
+                    self.pattern_includes(node.position, layer_number, density).then_some(ShellNode {
+                        node: RasterNode { extrusion: node.extrusion * flow, ..node },
+                        role: NodeRole::Infill,
+                        material_channel: region.material_channel,
+                    })
+                }
+            })
+            .collect()
+    }
+
+    /// Whether the infill pattern deposits at a node on a layer.
+    pub fn infill_includes(&self, position: GridCoordinate, layer_number: u32) -> bool {
+        self.pattern_includes(position, layer_number, self.infill.density)
+    }
+
+    /// Whether the infill pattern at `density` (percentage) deposits at a
+    /// node on a layer.
+    pub fn pattern_includes(&self, position: GridCoordinate, layer_number: u32, density: f32) -> bool {
+        let fraction = (density / 100.0).clamp(0.0, 1.0);
+        if fraction >= 1.0 {
+            return true;
+        }
+        if fraction <= 0.0 {
+            return false;
+        }
+
+        let (x, y, z) = (position.x as i64, position.y as i64, layer_number as i64);
+        // Line period in nodes for a pattern made of `families` line families
+        let period = |families: f32| ((families / fraction).round() as i64).max(1);
+        match self.infill.pattern {
+            InfillPattern::Rectilinear => {
+                let p = period(1.0);
+                if layer_number % 2 == 0 { x % p == 0 } else { y % p == 0 }
+            }
+            InfillPattern::Grid => {
+                let p = period(2.0);
+                x % p == 0 || y % p == 0
+            }
+            InfillPattern::Triangular => {
+                let p = period(3.0);
+                x % p == 0 || y % p == 0 || (x + y) % p == 0
+            }
+            InfillPattern::Cubic => {
+                // Triangular lines that drift with height form stacked cubes
+                let p = period(3.0);
+                (x + z) % p == 0 || (y + z) % p == 0 || (x + y + z) % p == 0
+            }
+            InfillPattern::Honeycomb => {
+                // Offset bricks, the grid approximation of hexagonal cells
+                let p = period(1.5).max(2);
+                let band = y / p;
+                y % p == 0 || (x + (band % 2) * (p / 2)) % p == 0
+            }
+            InfillPattern::Gyroid => {
+                // The gyroid surface is |g| < t; g is spread over about
+                // [-1.5, 1.5], so the kept fraction is roughly t / 1.5
+                let p = period(2.0) as f32;
+                let f = std::f32::consts::TAU / (p * 2.0);
+                let (fx, fy, fz) = (x as f32 * f, y as f32 * f, z as f32 * f);
+                let g = fx.sin() * fy.cos() + fy.sin() * fz.cos() + fz.sin() * fx.cos();
+                g.abs() < 1.5 * fraction
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::valve_mapper::RoundingMode;
+    use crate::core::RegionOverrides;
+    use config_types::InfillGradient;
+
+    #[test]
+    fn test_rings_and_sparse_infill() {
+        let grid = ValveGridConfig {
+            spacing: 1.0,
+            origin_x: 0.0,
+            origin_y: 0.0,
+            grid_width: 40,
+            grid_height: 40,
+            valves_per_node: 4,
+        };
+        let region = Region {
+            outer: vec![(4.5, 4.5), (20.5, 4.5), (20.5, 20.5), (4.5, 20.5)],
+            holes: vec![],
+            material_channel: 0,
+            object_id: None,
+            overrides: RegionOverrides::default(),
+        };
+        let generator = ShellGenerator {
+            shells: ShellSettings { perimeter_count: 2, flow_factor: 1.1, material_channel: Some(1) },
+            infill: InfillSettings { density: 25.0, pattern: InfillPattern::Grid, gradient: None },
+            density: None,
+        };
+        let mapper = GridAlignedMapper::new(RoundingMode::Nearest);
+        let nodes = generator.generate(&region, &grid, &mapper, 0);
+        let role = |x, y| nodes.iter().find(|n| n.node.position == GridCoordinate::new(x, y)).map(|n| n.role);
+
+        // 16 x 16 nodes: outer ring 60, second ring 52
+        let perimeters: Vec<&ShellNode> = nodes.iter().filter(|n| n.role != NodeRole::Infill).collect();
+        assert_eq!(perimeters.len(), 60 + 52);
+        assert!(perimeters.iter().all(|n| n.material_channel == 1 && (n.node.extrusion - 1.1).abs() < 1e-6));
+        assert_eq!(role(5, 12), Some(NodeRole::Perimeter(0)));
+        assert_eq!(role(6, 12), Some(NodeRole::Perimeter(1)));
+
+        // The 12 x 12 interior is thinned to about a quarter
+        let infill = nodes.iter().filter(|n| n.role == NodeRole::Infill).count();
+        assert!(infill > 144 / 8 && infill < 144 / 2, "infill {}", infill);
+
+        // Graded over 3mm: solid against the shell, empty in the middle
+        let gradient = InfillGradient {
+            min_density: 0.0,
+            max_density: 100.0,
+            transition_distance: 3.0,
+            near_walls: true,
+            near_surfaces: true,
+            regions_file: None,
+        };
+        let graded = ShellGenerator {
+            density: Some(DensityField::new(gradient, RegionSpec::default())),
+            ..generator
+        };
+        let nodes = graded.generate(&region, &grid, &mapper, 0);
+        let role = |x, y| nodes.iter().find(|n| n.node.position == GridCoordinate::new(x, y)).map(|n| n.role);
+        assert!((7..=17).all(|y| role(7, y) == Some(NodeRole::Infill)));
+        assert_eq!(role(12, 12), None);
+    }
+}
@@ -0,0 +1,345 @@
+//! On-disk cache of intermediate slicing artifacts.
+//!
+//! Slicing runs as a chain of stages, each a pure function of the previous
+//! stage's output and its own inputs. Every stage's artifact is stored
+//! under a key that hashes its upstream key together with its inputs:
+//!
+//! ```text
+//! mesh key    = H(placed mesh geometry)
+//! slices key  = H("slices",     mesh key,   layer heights, modifier meshes)
+//! maps key    = H("valve_maps", slices key, grid, boundary mode, shells, infill,
+//!                 graded infill regions, supports, vase, adhesion, multi-material)
+//! ```
+//!
+//! Changing infill density changes only the valve-map key, so re-slicing
+//! reuses the stored layer slices and recomputes the valve maps and
+//! everything after them. Changing the model or layer height changes every
+//! key downstream of it. Stale entries are never read; `clear` removes them.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use anyhow::{Context, Result};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use tracing::{debug, warn};
+
+use config_types::{PrintSettings, PrinterConfig};
+
+use super::{BoundaryMode, ModifierSet};
+use crate::Mesh;
+
+/// Bumped when an artifact type changes shape, invalidating every entry.
+pub const CACHE_VERSION: u32 = 1;
+
+/// Cached slicing stages, in pipeline order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheStage {
+    /// `Vec<LayerSlice>`
+    Slices,
+    /// `Vec<ValveActivationMap>`
+    ValveMaps,
+}
+
+impl CacheStage {
+    pub fn name(&self) -> &'static str {
+        match self {
+            CacheStage::Slices => "slices",
+            CacheStage::ValveMaps => "valve_maps",
+        }
+    }
+}
+
+/// Content hash identifying one artifact.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CacheKey([u8; 32]);
+
+impl fmt::Display for CacheKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for byte in &self.0 {
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+impl CacheKey {
+    /// Key of a placed mesh's geometry.
+    pub fn of_mesh(mesh: &Mesh) -> Self {
+        let mut hasher = Sha256::new();
+        hasher.update(CACHE_VERSION.to_le_bytes());
+        hasher.update(format!("{:?}", mesh.units).as_bytes());
+        hasher.update((mesh.vertices.len() as u64).to_le_bytes());
+        for v in &mesh.vertices {
+            hasher.update(v.to_le_bytes());
+        }
+        hasher.update((mesh.indices.len() as u64).to_le_bytes());
+        for i in &mesh.indices {
+            hasher.update(i.to_le_bytes());
+        }
+        if let Some(channels) = &mesh.face_channels {
+            hasher.update(channels);
+        }
+        if let Some(objects) = &mesh.face_objects {
+            for id in objects {
+                hasher.update(id.to_le_bytes());
+            }
+        }
+        Self(hasher.finalize().into())
+    }
+
+    /// Key of a stage's artifact given its upstream key and inputs.
+    pub fn derive<T: Serialize>(stage: CacheStage, upstream: &CacheKey, inputs: &T) -> Result<Self> {
+        let inputs = serde_json::to_vec(inputs).context("Failed to serialize cache inputs")?;
+        let mut hasher = Sha256::new();
+        hasher.update(stage.name().as_bytes());
+        hasher.update(upstream.0);
+        hasher.update(&inputs);
+        Ok(Self(hasher.finalize().into()))
+    }
+
+    /// Key of the layer slices of a placed mesh, split by the modifier
+    /// meshes.
+    pub fn slices(mesh: &CacheKey, settings: &PrintSettings, modifiers: &ModifierSet) -> Result<Self> {
+        Self::derive(
+            CacheStage::Slices,
+            mesh,
+            &(settings.layer_height, settings.first_layer_height, modifiers.cache_inputs()),
+        )
+    }
+
+    /// Key of the valve maps built from a set of slices.
+    ///
+    /// Covers every setting the maps depend on past the slices: adhesion
+    /// aids are added to the slices before mapping, vase mode leaves its
+    /// spiral layers unmapped, and a graded infill's painted regions file
+    /// is hashed by content (an unreadable file is mapped without it).
+    pub fn valve_maps(
+        slices: &CacheKey,
+        printer: &PrinterConfig,
+        settings: &PrintSettings,
+        boundary: BoundaryMode,
+    ) -> Result<Self> {
+        let grid = (
+            printer.valve_array.grid_spacing,
+            printer.valve_array.valves_per_node,
+            printer.grid_x_count(),
+            printer.grid_y_count(),
+        );
+        let boundary = format!("{:?}", boundary);
+        let regions_file = settings
+            .infill
+            .gradient
+            .as_ref()
+            .and_then(|gradient| gradient.regions_file.as_ref())
+            .and_then(|path| fs::read(path).ok())
+            .map(|bytes| Self(Sha256::digest(bytes).into()).to_string());
+        // Sorted, so the key does not depend on hash map order
+        let multi_material = settings.multi_material.as_ref().map(|mm| {
+            let material_map: BTreeMap<_, _> = mm.material_map.iter().collect();
+            (material_map, mm.purge_strategy, &mm.purge_tower, mm.allow_incompatible)
+        });
+        Self::derive(
+            CacheStage::ValveMaps,
+            slices,
+            &(
+                grid,
+                boundary,
+                &settings.shells,
+                &settings.infill,
+                regions_file,
+                &settings.supports,
+                &settings.vase,
+                &settings.adhesion,
+                multi_material,
+            ),
+        )
+    }
+}
+
+/// Hit and miss counts since the cache was opened.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+/// Stage artifacts stored under a directory, one file per key.
+pub struct SliceCache {
+    dir: PathBuf,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl SliceCache {
+    pub fn open<P: AsRef<Path>>(dir: P) -> Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create cache directory {}", dir.display()))?;
+        Ok(Self {
+            dir,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        })
+    }
+
+    /// `$XDG_CACHE_HOME/hypergcode`, falling back to `~/.cache/hypergcode`.
+    pub fn default_dir() -> PathBuf {
+        std::env::var_os("XDG_CACHE_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+            .unwrap_or_else(std::env::temp_dir)
+            .join("hypergcode")
+    }
+
+    fn path(&self, stage: CacheStage, key: &CacheKey) -> PathBuf {
+        self.dir.join(stage.name()).join(format!("{}.bin", key))
+    }
+
+    /// Returns the stored artifact, or computes and stores it.
+    ///
+    /// An unreadable entry is treated as a miss and overwritten; a failed
+    /// write is logged and the computed artifact still returned.
+    pub fn get_or_compute<T, F>(&self, stage: CacheStage, key: &CacheKey, compute: F) -> Result<T>
+    where
+        T: Serialize + DeserializeOwned,
+        F: FnOnce() -> Result<T>,
+    {
+        let path = self.path(stage, key);
+        if let Ok(bytes) = fs::read(&path) {
+            match bincode::deserialize(&bytes) {
+                Ok(artifact) => {
+                    debug!("Cache hit for {} {}", stage.name(), key);
+                    self.hits.fetch_add(1, Ordering::Relaxed);
+                    return Ok(artifact);
+                }
+                Err(e) => warn!("Discarding unreadable cache entry {}: {}", path.display(), e),
+            }
+        }
+
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        let artifact = compute()?;
+        if let Err(e) = self.store(&path, &artifact) {
+            warn!("Failed to cache {} {}: {:#}", stage.name(), key, e);
+        }
+        Ok(artifact)
+    }
+
+    fn store<T: Serialize>(&self, path: &Path, artifact: &T) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let bytes = bincode::serialize(artifact)?;
+        // Concurrent slicers may race on the same key; rename is atomic
+        let tmp = path.with_extension(format!("tmp{}", std::process::id()));
+        fs::write(&tmp, bytes)?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Removes every stored artifact.
+    pub fn clear(&self) -> Result<()> {
+        for stage in [CacheStage::Slices, CacheStage::ValveMaps] {
+            let dir = self.dir.join(stage.name());
+            match fs::remove_dir_all(&dir) {
+                Ok(()) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e).with_context(|| format!("Failed to clear {}", dir.display())),
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MeshUnits;
+
+    fn mesh(height: f32) -> Mesh {
+        Mesh {
+            vertices: vec![0.0, 0.0, 0.0, 10.0, 0.0, 0.0, 0.0, 10.0, 0.0, 0.0, 0.0, height],
+            indices: vec![0, 2, 1, 0, 1, 3, 1, 2, 3, 0, 3, 2],
+            normals: None,
+            face_channels: None,
+            face_objects: None,
+            units: MeshUnits::Millimeters,
+        }
+    }
+
+    #[test]
+    fn test_only_changed_stage_recomputes() {
+        let dir = std::env::temp_dir().join(format!("hg4d-cache-{}", std::process::id()));
+        let cache = SliceCache::open(&dir).unwrap();
+        let mesh_key = CacheKey::of_mesh(&mesh(10.0));
+        assert_ne!(mesh_key, CacheKey::of_mesh(&mesh(12.0)));
+
+        let slices = CacheKey::derive(CacheStage::Slices, &mesh_key, &0.2f32).unwrap();
+        let maps_a = CacheKey::derive(CacheStage::ValveMaps, &slices, &20.0f32).unwrap();
+        let maps_b = CacheKey::derive(CacheStage::ValveMaps, &slices, &40.0f32).unwrap();
+
+        let run = |maps: &CacheKey, computed: &mut Vec<&'static str>| {
+            let layers: Vec<u32> = cache
+                .get_or_compute(CacheStage::Slices, &slices, || {
+                    computed.push("slices");
+                    Ok(vec![1, 2, 3])
+                })
+                .unwrap();
+            cache
+                .get_or_compute(CacheStage::ValveMaps, maps, || {
+                    computed.push("maps");
+                    Ok(layers.iter().map(|l| l * 10).collect::<Vec<u32>>())
+                })
+                .unwrap()
+        };
+
+        let mut computed = Vec::new();
+        assert_eq!(run(&maps_a, &mut computed), vec![10, 20, 30]);
+        assert_eq!(computed, vec!["slices", "maps"]);
+
+        // Infill change: slices come from the cache
+        computed.clear();
+        run(&maps_b, &mut computed);
+        assert_eq!(computed, vec!["maps"]);
+
+        computed.clear();
+        run(&maps_a, &mut computed);
+        assert!(computed.is_empty());
+        assert_eq!(cache.stats(), CacheStats { hits: 3, misses: 3 });
+
+        cache.clear().unwrap();
+        std::fs::remove_dir_all(&dir).ok();
+
+        // Every setting the maps depend on changes their key
+        let configs =
+            crate::config::ExampleConfigs::for_model(config_types::PrinterModel::HyperCubeStandard).unwrap();
+        let key = |settings: &PrintSettings| {
+            CacheKey::valve_maps(&slices, &configs.printer, settings, BoundaryMode::Snap).unwrap()
+        };
+        let base = key(&configs.settings);
+        assert_eq!(base, key(&configs.settings.clone()));
+        let mut changed = configs.settings.clone();
+        changed.shells.perimeter_count += 1;
+        assert_ne!(key(&changed), base);
+        let mut changed = configs.settings.clone();
+        changed.vase = Some(config_types::VaseSettings { bottom_layers: 2 });
+        assert_ne!(key(&changed), base);
+        let mut changed = configs.settings.clone();
+        changed.adhesion.skirt = None;
+        assert_ne!(key(&changed), base);
+        let mut changed = configs.settings.clone();
+        changed.multi_material.as_mut().unwrap().material_map.insert("infill".to_string(), 1);
+        assert_ne!(key(&changed), base);
+    }
+}
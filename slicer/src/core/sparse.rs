@@ -0,0 +1,144 @@
+//! Empty layer detection.
+//!
+//! Models with large empty Z ranges (pillars under a raised part, objects of
+//! different heights on one plate) produce layers in which no valve opens.
+//! Printing them one by one costs a full layer cycle each for nothing but a
+//! Z step. [`mark_empty_layers`] finds runs of such layers and prepares them
+//! for the firmware's fast-forward, which advances Z through a whole run in
+//! one move at the axis' maximum speed:
+//!
+//! - closed-only nodes and object tags are dropped, so each empty layer
+//!   encodes as a frame without runs
+//! - the run's estimated time becomes the time of that single move, shared
+//!   between its layers, instead of a per-layer minimum
+//!
+//! Layers after the last deposit are never moved through and are estimated
+//! at zero.
+
+use gcode_types::Layer;
+
+use super::TimeEstimator;
+
+/// Consecutive layers without an open valve.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EmptyRun {
+    pub first_layer: u32,
+    pub last_layer: u32,
+    /// Z before the run: the layer below it, or the plate
+    pub from_z: f32,
+    /// Z of the run's last layer
+    pub to_z: f32,
+    /// True if no deposit follows the run
+    pub trailing: bool,
+}
+
+impl EmptyRun {
+    pub fn layer_count(&self) -> u32 {
+        self.last_layer - self.first_layer + 1
+    }
+
+    pub fn distance(&self) -> f32 {
+        self.to_z - self.from_z
+    }
+}
+
+/// Finds the runs of empty layers, in order.
+pub fn find_empty_runs(layers: &[Layer]) -> Vec<EmptyRun> {
+    let mut runs: Vec<EmptyRun> = Vec::new();
+    let mut previous_z = 0.0;
+    let mut open: Option<EmptyRun> = None;
+    for layer in layers {
+        if layer.is_empty() {
+            let run = open.get_or_insert(EmptyRun {
+                first_layer: layer.layer_number,
+                last_layer: layer.layer_number,
+                from_z: previous_z,
+                to_z: layer.z_height,
+                trailing: false,
+            });
+            run.last_layer = layer.layer_number;
+            run.to_z = layer.z_height;
+        } else if let Some(run) = open.take() {
+            runs.push(run);
+        }
+        previous_z = layer.z_height;
+    }
+    if let Some(mut run) = open {
+        run.trailing = true;
+        runs.push(run);
+    }
+    runs
+}
+
+/// Strips the empty layers down to their Z and re-estimates them as one
+/// fast-forward move per run. Returns the runs found.
+pub fn mark_empty_layers(layers: &mut [Layer], estimator: &TimeEstimator) -> Vec<EmptyRun> {
+    let runs = find_empty_runs(layers);
+    for run in &runs {
+        let share = if run.trailing {
+            0.0
+        } else {
+            estimator.z_move_time(run.distance()) / run.layer_count() as f32
+        };
+        for layer in layers
+            .iter_mut()
+            .filter(|l| (run.first_layer..=run.last_layer).contains(&l.layer_number))
+        {
+            layer.nodes.clear();
+            layer.objects.clear();
+            layer.primary_material = None;
+            layer.estimated_time = Some(share);
+            layer.min_layer_time = None;
+        }
+    }
+    runs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::EstimatorCoefficients;
+    use gcode_types::{GridCoordinate, NodeValveState, ValveState};
+
+    fn layer(n: u32, open: bool) -> Layer {
+        let mut layer = Layer::new(0.2 * (n + 1) as f32, n);
+        layer.add_node(NodeValveState::new(GridCoordinate::new(1, 1), vec![ValveState::new(0, open)]));
+        layer.estimated_time = Some(5.0);
+        layer
+    }
+
+    #[test]
+    fn test_mark_empty_runs() {
+        let coefficients = EstimatorCoefficients {
+            layer_overhead: 0.2,
+            per_valve_switch: 0.01,
+            per_pressure_wait: 0.5,
+            z_move_scale: 1.0,
+            per_mm3: 0.0,
+        };
+        // No acceleration limit: moves run at max speed throughout
+        let estimator = TimeEstimator::from_parts(coefficients, 10.0, 0.0);
+        let mut layers = vec![
+            layer(0, true),
+            layer(1, false),
+            layer(2, false),
+            layer(3, false),
+            layer(4, true),
+            layer(5, false),
+        ];
+        let runs = mark_empty_layers(&mut layers, &estimator);
+
+        assert_eq!(runs.len(), 2);
+        assert_eq!((runs[0].first_layer, runs[0].last_layer, runs[0].trailing), (1, 3, false));
+        assert!((runs[0].from_z - 0.2).abs() < 1e-6 && (runs[0].to_z - 0.8).abs() < 1e-6);
+        assert_eq!((runs[1].first_layer, runs[1].layer_count(), runs[1].trailing), (5, 1, true));
+
+        // 0.6mm at 10mm/s, shared by three layers
+        for layer in &layers[1..4] {
+            assert!(layer.nodes.is_empty());
+            assert!((layer.estimated_time.unwrap() - 0.02).abs() < 1e-5);
+        }
+        assert_eq!(layers[4].estimated_time, Some(5.0));
+        assert_eq!(layers[5].estimated_time, Some(0.0));
+    }
+}
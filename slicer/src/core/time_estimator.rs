@@ -0,0 +1,471 @@
+//! Print time estimation with feedback calibration.
+//!
+//! Layer time is modelled as a linear combination of the work a layer
+//! performs: a fixed per-layer overhead, valve switching, pressure
+//! stabilization waits, Z motion and material deposition. The result is
+//! floored by the material's minimum layer time so small layers are given
+//! time to cool.
+//!
+//! The coefficients start from values derived from the printer configuration
+//! and can be refitted from [`LayerTimingReport`]s sent back by the firmware.
+
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use config_types::PrinterConfig;
+use gcode_types::Command;
+use protocol::LayerTimingReport;
+
+/// Default wait for pressure to settle after a G4W pressure barrier (s).
+const DEFAULT_PRESSURE_SETTLE_S: f32 = 0.5;
+
+/// Default fixed cost per layer for command dispatch and bookkeeping (s).
+const DEFAULT_LAYER_OVERHEAD_S: f32 = 0.2;
+
+/// Strength of the pull toward the prior coefficients during calibration.
+/// Keeps fits stable when only a handful of layers have been reported.
+const CALIBRATION_REGULARIZATION: f32 = 0.1;
+
+/// Layers whose measured time is within this factor of the minimum layer
+/// time are treated as cooling-bound and excluded from calibration.
+const COOLING_BOUND_MARGIN: f32 = 1.05;
+
+/// Number of terms in the linear model.
+const TERM_COUNT: usize = 5;
+
+/// Coefficients of the layer time model.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct EstimatorCoefficients {
+    /// Fixed cost per layer (s)
+    pub layer_overhead: f32,
+
+    /// Time per valve state change (s)
+    pub per_valve_switch: f32,
+
+    /// Time per pressure stabilization wait (s)
+    pub per_pressure_wait: f32,
+
+    /// Multiplier on the kinematic Z move time
+    pub z_move_scale: f32,
+
+    /// Deposition time per mm³ of material (s/mm³)
+    pub per_mm3: f32,
+}
+
+impl EstimatorCoefficients {
+    /// Initial coefficients derived from printer hardware parameters.
+    pub fn from_printer(config: &PrinterConfig) -> Self {
+        let total_flow: f32 = config
+            .materials
+            .extruders
+            .iter()
+            .map(|e| e.max_flow_rate)
+            .sum();
+
+        Self {
+            layer_overhead: DEFAULT_LAYER_OVERHEAD_S,
+            per_valve_switch: config.valve_array.response_time_ms / 1000.0,
+            per_pressure_wait: DEFAULT_PRESSURE_SETTLE_S,
+            z_move_scale: 1.0,
+            per_mm3: if total_flow > 0.0 { 1.0 / total_flow } else { 0.0 },
+        }
+    }
+
+    /// Loads previously calibrated coefficients from a JSON file.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        serde_json::from_str(&content)
+            .with_context(|| format!("Failed to parse {}", path.display()))
+    }
+
+    /// Saves coefficients as JSON.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let content = serde_json::to_string_pretty(self)?;
+        std::fs::write(path, content)
+            .with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    fn to_array(self) -> [f32; TERM_COUNT] {
+        [
+            self.layer_overhead,
+            self.per_valve_switch,
+            self.per_pressure_wait,
+            self.z_move_scale,
+            self.per_mm3,
+        ]
+    }
+
+    fn from_array(values: [f32; TERM_COUNT]) -> Self {
+        Self {
+            layer_overhead: values[0],
+            per_valve_switch: values[1],
+            per_pressure_wait: values[2],
+            z_move_scale: values[3],
+            per_mm3: values[4],
+        }
+    }
+}
+
+/// Work performed by a single layer.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct LayerWorkload {
+    pub valve_switches: u32,
+    pub pressure_waits: u32,
+    pub z_travel_mm: f32,
+    pub deposited_volume_mm3: f32,
+}
+
+impl From<&LayerTimingReport> for LayerWorkload {
+    fn from(report: &LayerTimingReport) -> Self {
+        Self {
+            valve_switches: report.valve_switches,
+            pressure_waits: report.pressure_waits,
+            z_travel_mm: report.z_travel_mm,
+            deposited_volume_mm3: report.deposited_volume_mm3,
+        }
+    }
+}
+
+/// Outcome of fitting the model to reported layer timings.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CalibrationSummary {
+    /// Reports used in the fit
+    pub samples_used: usize,
+
+    /// Reports skipped because the layer was cooling-bound
+    pub samples_skipped: usize,
+
+    /// Mean absolute error before calibration (s)
+    pub error_before: f32,
+
+    /// Mean absolute error after calibration (s)
+    pub error_after: f32,
+}
+
+/// Layer and print time estimator.
+#[derive(Debug, Clone)]
+pub struct TimeEstimator {
+    coefficients: EstimatorCoefficients,
+    prior: EstimatorCoefficients,
+    z_max_speed: f32,
+    z_max_acceleration: f32,
+    min_layer_time: f32,
+}
+
+impl TimeEstimator {
+    pub fn new(config: &PrinterConfig) -> Self {
+        Self::from_parts(
+            EstimatorCoefficients::from_printer(config),
+            config.motion.z_axis.max_speed,
+            config.motion.z_axis.max_acceleration,
+        )
+    }
+
+    /// Creates an estimator from explicit coefficients and Z kinematics.
+    pub fn from_parts(
+        coefficients: EstimatorCoefficients,
+        z_max_speed: f32,
+        z_max_acceleration: f32,
+    ) -> Self {
+        Self {
+            coefficients,
+            prior: coefficients,
+            z_max_speed,
+            z_max_acceleration,
+            min_layer_time: 0.0,
+        }
+    }
+
+    /// Sets the minimum time per layer used for cooling (s).
+    pub fn with_min_layer_time(mut self, seconds: f32) -> Self {
+        self.min_layer_time = seconds.max(0.0);
+        self
+    }
+
+    /// Replaces the model coefficients, e.g. with a saved calibration.
+    ///
+    /// The supplied values also become the prior for later calibration.
+    pub fn with_coefficients(mut self, coefficients: EstimatorCoefficients) -> Self {
+        self.coefficients = coefficients;
+        self.prior = coefficients;
+        self
+    }
+
+    pub fn coefficients(&self) -> &EstimatorCoefficients {
+        &self.coefficients
+    }
+
+    /// Estimates the time of a layer from its workload.
+    pub fn estimate_layer(&self, workload: &LayerWorkload) -> Duration {
+        let raw = self.raw_layer_seconds(workload);
+        Duration::from_secs_f32(raw.max(self.min_layer_time).max(0.0))
+    }
+
+    /// Time the layer's work takes without the cooling floor: what a
+    /// written layer records as its estimated time, alongside its
+    /// `min_layer_time`, so the firmware can tell how long to dwell.
+    pub fn estimate_deposition(&self, workload: &LayerWorkload) -> Duration {
+        Duration::from_secs_f32(self.raw_layer_seconds(workload).max(0.0))
+    }
+
+    /// Minimum time per layer used for cooling (s).
+    pub fn min_layer_time(&self) -> f32 {
+        self.min_layer_time
+    }
+
+    /// Estimates the time of a layer from its commands.
+    pub fn estimate_commands(&self, previous_z: f32, commands: &[Command]) -> Duration {
+        let report = LayerTimingReport::from_commands(0, previous_z, commands, Duration::ZERO);
+        self.estimate_layer(&LayerWorkload::from(&report))
+    }
+
+    /// Estimates the total time of a sequence of layers.
+    pub fn estimate_total<'a, I>(&self, workloads: I) -> Duration
+    where
+        I: IntoIterator<Item = &'a LayerWorkload>,
+    {
+        workloads.into_iter().map(|w| self.estimate_layer(w)).sum()
+    }
+
+    /// Kinematic time for a Z move with a trapezoidal velocity profile (s).
+    pub fn z_move_time(&self, distance: f32) -> f32 {
+        let distance = distance.abs();
+        if distance <= 0.0 || self.z_max_speed <= 0.0 {
+            return 0.0;
+        }
+        if self.z_max_acceleration <= 0.0 {
+            return distance / self.z_max_speed;
+        }
+
+        let accel_distance = self.z_max_speed * self.z_max_speed / self.z_max_acceleration;
+        if distance < accel_distance {
+            // Triangular profile: never reaches max speed
+            2.0 * (distance / self.z_max_acceleration).sqrt()
+        } else {
+            distance / self.z_max_speed + self.z_max_speed / self.z_max_acceleration
+        }
+    }
+
+    /// Refits the coefficients from measured layer durations.
+    ///
+    /// Uses least squares regularized toward the prior coefficients, so a
+    /// small history nudges the model rather than replacing it. Negative
+    /// coefficients are clamped to zero.
+    pub fn calibrate(&mut self, reports: &[LayerTimingReport]) -> CalibrationSummary {
+        let cooling_floor = self.min_layer_time * COOLING_BOUND_MARGIN;
+        let samples: Vec<([f32; TERM_COUNT], f32)> = reports
+            .iter()
+            .map(|r| (self.features(&LayerWorkload::from(r)), r.duration_ms as f32 / 1000.0))
+            .filter(|(_, actual)| *actual > cooling_floor)
+            .collect();
+
+        let error_before = self.mean_abs_error(&samples);
+        let mut summary = CalibrationSummary {
+            samples_used: samples.len(),
+            samples_skipped: reports.len() - samples.len(),
+            error_before,
+            error_after: error_before,
+        };
+        if samples.is_empty() {
+            return summary;
+        }
+
+        // Normal equations: (XᵀX + λD) c = Xᵀy + λD c₀, with D scaling the
+        // penalty to each feature's magnitude.
+        let prior = self.prior.to_array();
+        let mut ata = [[0.0f64; TERM_COUNT]; TERM_COUNT];
+        let mut atb = [0.0f64; TERM_COUNT];
+        for (x, y) in &samples {
+            for i in 0..TERM_COUNT {
+                for j in 0..TERM_COUNT {
+                    ata[i][j] += x[i] as f64 * x[j] as f64;
+                }
+                atb[i] += x[i] as f64 * *y as f64;
+            }
+        }
+        for i in 0..TERM_COUNT {
+            let scale = (ata[i][i] / samples.len() as f64).max(1e-9);
+            let lambda = CALIBRATION_REGULARIZATION as f64 * scale * samples.len() as f64;
+            ata[i][i] += lambda;
+            atb[i] += lambda * prior[i] as f64;
+        }
+
+        if let Some(solution) = solve_linear(ata, atb) {
+            let mut fitted = [0.0f32; TERM_COUNT];
+            for i in 0..TERM_COUNT {
+                fitted[i] = (solution[i] as f32).max(0.0);
+            }
+            self.coefficients = EstimatorCoefficients::from_array(fitted);
+            summary.error_after = self.mean_abs_error(&samples);
+        }
+
+        summary
+    }
+
+    fn features(&self, workload: &LayerWorkload) -> [f32; TERM_COUNT] {
+        [
+            1.0,
+            workload.valve_switches as f32,
+            workload.pressure_waits as f32,
+            self.z_move_time(workload.z_travel_mm),
+            workload.deposited_volume_mm3,
+        ]
+    }
+
+    fn raw_layer_seconds(&self, workload: &LayerWorkload) -> f32 {
+        let x = self.features(workload);
+        let c = self.coefficients.to_array();
+        x.iter().zip(c.iter()).map(|(x, c)| x * c).sum()
+    }
+
+    fn mean_abs_error(&self, samples: &[([f32; TERM_COUNT], f32)]) -> f32 {
+        if samples.is_empty() {
+            return 0.0;
+        }
+        let c = self.coefficients.to_array();
+        let total: f32 = samples
+            .iter()
+            .map(|(x, y)| {
+                let predicted: f32 = x.iter().zip(c.iter()).map(|(x, c)| x * c).sum();
+                (predicted - y).abs()
+            })
+            .sum();
+        total / samples.len() as f32
+    }
+}
+
+/// Solves a small dense linear system with Gaussian elimination and partial
+/// pivoting. Returns `None` if the matrix is singular.
+fn solve_linear(
+    mut a: [[f64; TERM_COUNT]; TERM_COUNT],
+    mut b: [f64; TERM_COUNT],
+) -> Option<[f64; TERM_COUNT]> {
+    for col in 0..TERM_COUNT {
+        let pivot = (col..TERM_COUNT).max_by(|&i, &j| a[i][col].abs().total_cmp(&a[j][col].abs()))?;
+        if a[pivot][col].abs() < 1e-12 {
+            return None;
+        }
+        a.swap(col, pivot);
+        b.swap(col, pivot);
+
+        for row in col + 1..TERM_COUNT {
+            let factor = a[row][col] / a[col][col];
+            for k in col..TERM_COUNT {
+                a[row][k] -= factor * a[col][k];
+            }
+            b[row] -= factor * b[col];
+        }
+    }
+
+    let mut x = [0.0; TERM_COUNT];
+    for row in (0..TERM_COUNT).rev() {
+        let sum: f64 = (row + 1..TERM_COUNT).map(|k| a[row][k] * x[k]).sum();
+        x[row] = (b[row] - sum) / a[row][row];
+    }
+    Some(x)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn estimator() -> TimeEstimator {
+        let coefficients = EstimatorCoefficients {
+            layer_overhead: DEFAULT_LAYER_OVERHEAD_S,
+            per_valve_switch: 0.01,
+            per_pressure_wait: DEFAULT_PRESSURE_SETTLE_S,
+            z_move_scale: 1.0,
+            per_mm3: 0.0,
+        };
+        TimeEstimator::from_parts(coefficients, 10.0, 100.0)
+    }
+
+    fn workload(switches: u32, waits: u32, volume: f32) -> LayerWorkload {
+        LayerWorkload {
+            valve_switches: switches,
+            pressure_waits: waits,
+            z_travel_mm: 0.2,
+            deposited_volume_mm3: volume,
+        }
+    }
+
+    fn report(layer: u32, w: &LayerWorkload, seconds: f32) -> LayerTimingReport {
+        LayerTimingReport {
+            layer_number: layer,
+            z_height: 0.2 * (layer + 1) as f32,
+            duration_ms: (seconds * 1000.0) as u64,
+            valve_switches: w.valve_switches,
+            pressure_waits: w.pressure_waits,
+            z_travel_mm: w.z_travel_mm,
+            deposited_volume_mm3: w.deposited_volume_mm3,
+            barrier_waits: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_min_layer_time_floor() {
+        let est = estimator().with_min_layer_time(8.0);
+        let t = est.estimate_layer(&workload(1, 0, 0.0));
+        assert!((t.as_secs_f32() - 8.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_z_move_profiles() {
+        let est = estimator();
+        // Accel distance = 1mm: short moves are triangular
+        assert!((est.z_move_time(0.25) - 0.1).abs() < 1e-4);
+        assert!((est.z_move_time(10.0) - 1.1).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_calibration_reduces_error() {
+        let mut est = estimator();
+        // Real hardware switches three times slower than configured
+        let reports: Vec<_> = (0..40)
+            .map(|i| {
+                let w = workload(100 + i * 10, 1 + i % 3, 0.0);
+                let actual = 0.2 + w.valve_switches as f32 * 0.03 + w.pressure_waits as f32 * 0.5
+                    + est.z_move_time(0.2);
+                report(i, &w, actual)
+            })
+            .collect();
+
+        let summary = est.calibrate(&reports);
+        assert_eq!(summary.samples_used, 40);
+        assert!(summary.error_after < summary.error_before * 0.5);
+        assert!(est.coefficients().per_valve_switch > 0.02);
+    }
+
+    #[test]
+    fn test_valve_switches_count_state_changes() {
+        use crate::gcode::commands::{CommandBuilder, G4DBuilder};
+        use gcode_types::Coordinate;
+
+        let deposit = |x, open| G4DBuilder::new(Coordinate::new(x, 1.0, 0.2)).valve(0, open).build();
+        let commands = vec![
+            CommandBuilder::layer_advance(0.2),
+            deposit(1.0, true),
+            // Holding a valve open is not a switch
+            deposit(1.0, true),
+            deposit(1.0, false),
+            deposit(2.0, false),
+        ];
+        let report = LayerTimingReport::from_commands(0, 0.0, &commands, Duration::ZERO);
+        assert_eq!(report.valve_switches, 2);
+    }
+
+    #[test]
+    fn test_cooling_bound_layers_skipped() {
+        let mut est = estimator().with_min_layer_time(10.0);
+        let w = workload(10, 0, 0.0);
+        let summary = est.calibrate(&[report(0, &w, 10.0), report(1, &w, 10.2)]);
+        assert_eq!(summary.samples_used, 0);
+        assert_eq!(summary.samples_skipped, 2);
+    }
+}
@@ -0,0 +1,615 @@
+//! Mesh transformation and orientation.
+//!
+//! Models are placed on the build plate before slicing by a list of
+//! [`MeshTransform`] steps applied in order. Besides the affine operations
+//! (translate, rotate, scale) two placement helpers are provided:
+//!
+//! - **Lay flat** rotates a chosen triangle's face onto the build plate.
+//! - **Auto-orient** tries a set of candidate orientations (the six axis
+//!   directions plus the largest flat faces of the model) and keeps the one
+//!   that needs the least support, measured on the valve grid.
+//!
+//! After the steps are applied the mesh is dropped so its lowest point rests
+//! at Z = 0.
+
+use std::collections::HashMap;
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use crate::Mesh;
+
+/// Default steepest overhang (degrees from vertical) printable without support.
+pub const DEFAULT_OVERHANG_ANGLE: f32 = 45.0;
+
+/// Hits closer than this (mm) are treated as the same surface.
+const SURFACE_EPSILON: f32 = 1e-4;
+
+type Vec3 = [f32; 3];
+type Mat3 = [[f32; 3]; 3];
+
+const IDENTITY: Mat3 = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+
+/// Coordinate axis.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Axis {
+    X,
+    Y,
+    Z,
+}
+
+/// A single step of the placement pipeline.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum MeshTransform {
+    /// Move across the plate (mm); Z always ends at the plate
+    Translate { x: f32, y: f32 },
+    /// Rotate about an axis through the origin (right-handed, degrees)
+    Rotate { axis: Axis, degrees: f32 },
+    /// Scale per axis about the origin
+    Scale { x: f32, y: f32, z: f32 },
+    /// Rotate so the given triangle faces the build plate
+    LayFlat { face: usize },
+    /// Choose the orientation that minimizes support volume
+    AutoOrient,
+}
+
+/// Affine transform `p' = linear · p + translation`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transform {
+    pub linear: Mat3,
+    pub translation: Vec3,
+}
+
+impl Transform {
+    pub fn identity() -> Self {
+        Self {
+            linear: IDENTITY,
+            translation: [0.0; 3],
+        }
+    }
+
+    pub fn translation(x: f32, y: f32, z: f32) -> Self {
+        Self {
+            linear: IDENTITY,
+            translation: [x, y, z],
+        }
+    }
+
+    pub fn scale(x: f32, y: f32, z: f32) -> Self {
+        Self {
+            linear: [[x, 0.0, 0.0], [0.0, y, 0.0], [0.0, 0.0, z]],
+            translation: [0.0; 3],
+        }
+    }
+
+    pub fn rotation(axis: Axis, degrees: f32) -> Self {
+        let (s, c) = degrees.to_radians().sin_cos();
+        let linear = match axis {
+            Axis::X => [[1.0, 0.0, 0.0], [0.0, c, -s], [0.0, s, c]],
+            Axis::Y => [[c, 0.0, s], [0.0, 1.0, 0.0], [-s, 0.0, c]],
+            Axis::Z => [[c, -s, 0.0], [s, c, 0.0], [0.0, 0.0, 1.0]],
+        };
+        Self {
+            linear,
+            translation: [0.0; 3],
+        }
+    }
+
+    /// Rotation taking unit vector `from` onto unit vector `to`.
+    pub fn rotation_between(from: Vec3, to: Vec3) -> Self {
+        let v = cross(from, to);
+        let c = dot(from, to);
+
+        let linear = if c < -1.0 + 1e-6 {
+            // Opposite vectors: half turn about any axis perpendicular to `from`
+            let helper = if from[0].abs() < 0.9 { [1.0, 0.0, 0.0] } else { [0.0, 1.0, 0.0] };
+            let axis = normalize(cross(from, helper));
+            let mut m = [[0.0; 3]; 3];
+            for (i, row) in m.iter_mut().enumerate() {
+                for (j, value) in row.iter_mut().enumerate() {
+                    *value = 2.0 * axis[i] * axis[j] - IDENTITY[i][j];
+                }
+            }
+            m
+        } else {
+            // Rodrigues: I + [v]x + [v]x² / (1 + c)
+            let k = [[0.0, -v[2], v[1]], [v[2], 0.0, -v[0]], [-v[1], v[0], 0.0]];
+            let k2 = mat_mul(&k, &k);
+            let mut m = IDENTITY;
+            for i in 0..3 {
+                for j in 0..3 {
+                    m[i][j] += k[i][j] + k2[i][j] / (1.0 + c);
+                }
+            }
+            m
+        };
+
+        Self {
+            linear,
+            translation: [0.0; 3],
+        }
+    }
+
+    /// Returns the transform that applies `self` first, then `next`.
+    pub fn then(&self, next: &Transform) -> Self {
+        Self {
+            linear: mat_mul(&next.linear, &self.linear),
+            translation: add(mat_vec(&next.linear, self.translation), next.translation),
+        }
+    }
+
+    pub fn apply_point(&self, p: Vec3) -> Vec3 {
+        add(mat_vec(&self.linear, p), self.translation)
+    }
+
+    fn determinant(&self) -> f32 {
+        let m = &self.linear;
+        m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+            - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+            + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+    }
+
+    /// Inverse transpose of the linear part, used for normals.
+    fn normal_matrix(&self) -> Mat3 {
+        let m = &self.linear;
+        let det = self.determinant();
+        // Cofactor matrix equals det · inverse transpose
+        [
+            [
+                (m[1][1] * m[2][2] - m[1][2] * m[2][1]) / det,
+                (m[1][2] * m[2][0] - m[1][0] * m[2][2]) / det,
+                (m[1][0] * m[2][1] - m[1][1] * m[2][0]) / det,
+            ],
+            [
+                (m[0][2] * m[2][1] - m[0][1] * m[2][2]) / det,
+                (m[0][0] * m[2][2] - m[0][2] * m[2][0]) / det,
+                (m[0][1] * m[2][0] - m[0][0] * m[2][1]) / det,
+            ],
+            [
+                (m[0][1] * m[1][2] - m[0][2] * m[1][1]) / det,
+                (m[0][2] * m[1][0] - m[0][0] * m[1][2]) / det,
+                (m[0][0] * m[1][1] - m[0][1] * m[1][0]) / det,
+            ],
+        ]
+    }
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self::identity()
+    }
+}
+
+impl Mesh {
+    /// Applies an affine transform to vertices and normals.
+    ///
+    /// Mirroring transforms (negative determinant) also reverse triangle
+    /// winding so faces keep pointing outward.
+    pub fn transform(&mut self, transform: &Transform) -> Result<()> {
+        let det = transform.determinant();
+        if det.abs() < f32::EPSILON {
+            bail!("Transform is degenerate (zero scale on an axis)");
+        }
+
+        for v in self.vertices.chunks_exact_mut(3) {
+            let p = transform.apply_point([v[0], v[1], v[2]]);
+            v.copy_from_slice(&p);
+        }
+
+        if let Some(normals) = self.normals.as_mut() {
+            let nm = transform.normal_matrix();
+            for n in normals.chunks_exact_mut(3) {
+                let t = normalize(mat_vec(&nm, [n[0], n[1], n[2]]));
+                n.copy_from_slice(&t);
+            }
+        }
+
+        if det < 0.0 {
+            for tri in self.indices.chunks_exact_mut(3) {
+                tri.swap(1, 2);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Translates the mesh so its lowest point sits at Z = 0.
+    pub fn drop_to_plate(&mut self) {
+        if self.vertices.is_empty() {
+            return;
+        }
+        let (_, _, min_z, _, _, _) = self.bounding_box();
+        for z in self.vertices.iter_mut().skip(2).step_by(3) {
+            *z -= min_z;
+        }
+    }
+
+    /// Unit outward normal of a triangle, from its winding.
+    pub fn face_normal(&self, face: usize) -> Option<Vec3> {
+        let tri = self.indices.get(face * 3..face * 3 + 3)?;
+        let [a, b, c] = [tri[0], tri[1], tri[2]].map(|i| self.vertex(i));
+        let n = cross(sub(b, a), sub(c, a));
+        if length(n) <= f32::EPSILON {
+            return None;
+        }
+        Some(normalize(n))
+    }
+
+    /// Rotates the mesh so `face` rests on the build plate.
+    pub fn lay_flat(&mut self, face: usize) -> Result<()> {
+        let Some(normal) = self.face_normal(face) else {
+            bail!("Face {} does not exist or is degenerate", face);
+        };
+        self.transform(&Transform::rotation_between(normal, [0.0, 0.0, -1.0]))?;
+        self.drop_to_plate();
+        Ok(())
+    }
+
+    fn vertex(&self, index: u32) -> Vec3 {
+        let i = index as usize * 3;
+        [self.vertices[i], self.vertices[i + 1], self.vertices[i + 2]]
+    }
+}
+
+/// Applies placement steps in order and drops the result onto the plate.
+pub fn apply_transforms(mesh: &mut Mesh, steps: &[MeshTransform], grid_spacing: f32) -> Result<()> {
+    for step in steps {
+        debug!("Applying {:?}", step);
+        match *step {
+            MeshTransform::Translate { x, y } => mesh.transform(&Transform::translation(x, y, 0.0))?,
+            MeshTransform::Rotate { axis, degrees } => {
+                mesh.transform(&Transform::rotation(axis, degrees))?
+            }
+            MeshTransform::Scale { x, y, z } => mesh.transform(&Transform::scale(x, y, z))?,
+            MeshTransform::LayFlat { face } => mesh.lay_flat(face)?,
+            MeshTransform::AutoOrient => {
+                let best = AutoOrienter::new(grid_spacing).find_best(mesh)?;
+                debug!("Auto-orient: support volume {:.1} mm³", best.support_volume);
+                mesh.transform(&best.transform)?;
+            }
+        }
+    }
+    mesh.drop_to_plate();
+    Ok(())
+}
+
+/// Result of orientation search.
+#[derive(Debug, Clone, Copy)]
+pub struct Orientation {
+    /// Rotation to apply to the mesh
+    pub transform: Transform,
+    /// Estimated support volume in this orientation (mm³)
+    pub support_volume: f32,
+    /// Model height in this orientation (mm)
+    pub height: f32,
+}
+
+/// Searches candidate orientations for the least support material.
+///
+/// Support is measured per valve grid column: a ray is cast up through each
+/// grid cell and every gap between the plate (or a top surface) and a
+/// downward-facing overhang steeper than `overhang_angle` counts as support.
+#[derive(Debug, Clone)]
+pub struct AutoOrienter {
+    grid_spacing: f32,
+    overhang_angle: f32,
+    max_face_candidates: usize,
+}
+
+impl AutoOrienter {
+    pub fn new(grid_spacing: f32) -> Self {
+        Self {
+            grid_spacing,
+            overhang_angle: DEFAULT_OVERHANG_ANGLE,
+            max_face_candidates: 12,
+        }
+    }
+
+    /// Sets the steepest self-supporting overhang (degrees from vertical).
+    pub fn with_overhang_angle(mut self, degrees: f32) -> Self {
+        self.overhang_angle = degrees;
+        self
+    }
+
+    /// Sets how many of the largest flat faces are tried as the base.
+    pub fn with_face_candidates(mut self, count: usize) -> Self {
+        self.max_face_candidates = count;
+        self
+    }
+
+    /// Evaluates all candidates and returns the best one.
+    ///
+    /// Ties in support volume go to the lower orientation, which is faster
+    /// to print and more stable on the plate.
+    pub fn find_best(&self, mesh: &Mesh) -> Result<Orientation> {
+        mesh.validate()?;
+        if self.grid_spacing <= 0.0 {
+            bail!("Grid spacing must be positive");
+        }
+
+        let mut best: Option<Orientation> = None;
+        for down in self.candidate_directions(mesh) {
+            let transform = Transform::rotation_between(down, [0.0, 0.0, -1.0]);
+            let mut candidate = mesh.clone();
+            candidate.transform(&transform)?;
+            candidate.drop_to_plate();
+
+            let (_, _, min_z, _, _, max_z) = candidate.bounding_box();
+            let orientation = Orientation {
+                transform,
+                support_volume: self.support_volume(&candidate),
+                height: max_z - min_z,
+            };
+
+            let better = match &best {
+                None => true,
+                Some(b) => {
+                    let tolerance = self.grid_spacing.powi(3);
+                    orientation.support_volume < b.support_volume - tolerance
+                        || (orientation.support_volume <= b.support_volume + tolerance
+                            && orientation.height < b.height - SURFACE_EPSILON)
+                }
+            };
+            if better {
+                best = Some(orientation);
+            }
+        }
+
+        best.ok_or_else(|| anyhow::anyhow!("No orientation candidates"))
+    }
+
+    /// Estimates support volume (mm³) for the mesh as currently placed.
+    pub fn support_volume(&self, mesh: &Mesh) -> f32 {
+        let (min_x, min_y, plate_z, max_x, max_y, _) = mesh.bounding_box();
+        let spacing = self.grid_spacing;
+        let cols = ((max_x - min_x) / spacing).ceil().max(1.0) as usize;
+        let rows = ((max_y - min_y) / spacing).ceil().max(1.0) as usize;
+        let support_threshold = self.overhang_angle.to_radians().sin();
+
+        // Per column: (z, is_down_facing, needs_support)
+        let mut columns: Vec<Vec<(f32, bool, bool)>> = vec![Vec::new(); cols * rows];
+
+        for tri in mesh.indices.chunks_exact(3) {
+            let [a, b, c] = [tri[0], tri[1], tri[2]].map(|i| mesh.vertex(i));
+            let n = cross(sub(b, a), sub(c, a));
+            let len = length(n);
+            if len <= f32::EPSILON || (n[2] / len).abs() < 1e-3 {
+                continue; // vertical or degenerate: no ray crosses it
+            }
+            let nz = n[2] / len;
+            let down = nz < 0.0;
+            let needs_support = down && -nz > support_threshold;
+
+            let tri_min_x = a[0].min(b[0]).min(c[0]);
+            let tri_max_x = a[0].max(b[0]).max(c[0]);
+            let tri_min_y = a[1].min(b[1]).min(c[1]);
+            let tri_max_y = a[1].max(b[1]).max(c[1]);
+            let col_start = (((tri_min_x - min_x) / spacing - 0.5).ceil().max(0.0)) as usize;
+            let col_end = (((tri_max_x - min_x) / spacing - 0.5).floor() as isize).min(cols as isize - 1);
+            let row_start = (((tri_min_y - min_y) / spacing - 0.5).ceil().max(0.0)) as usize;
+            let row_end = (((tri_max_y - min_y) / spacing - 0.5).floor() as isize).min(rows as isize - 1);
+
+            for row in row_start as isize..=row_end {
+                for col in col_start as isize..=col_end {
+                    let px = min_x + (col as f32 + 0.5) * spacing;
+                    let py = min_y + (row as f32 + 0.5) * spacing;
+                    if let Some(z) = ray_hit(a, b, c, px, py) {
+                        columns[row as usize * cols + col as usize].push((z, down, needs_support));
+                    }
+                }
+            }
+        }
+
+        let mut total_height = 0.0;
+        for hits in columns.iter_mut() {
+            // Top surfaces sort before bottoms at the same Z so stacked
+            // bodies touching face-to-face need no support between them
+            hits.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)));
+            // Shared triangle edges can report the same surface twice
+            hits.dedup_by(|b, a| a.1 == b.1 && (a.0 - b.0).abs() < SURFACE_EPSILON);
+
+            let mut last_top = plate_z;
+            for &(z, down, needs_support) in hits.iter() {
+                if !down {
+                    last_top = z;
+                } else if needs_support && z - last_top > SURFACE_EPSILON {
+                    total_height += z - last_top;
+                }
+            }
+        }
+
+        total_height * spacing * spacing
+    }
+
+    /// Axis directions plus the normals of the largest coplanar face groups.
+    fn candidate_directions(&self, mesh: &Mesh) -> Vec<Vec3> {
+        let mut candidates: Vec<Vec3> = vec![
+            [0.0, 0.0, -1.0],
+            [0.0, 0.0, 1.0],
+            [1.0, 0.0, 0.0],
+            [-1.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0],
+            [0.0, -1.0, 0.0],
+        ];
+
+        let mut groups: HashMap<[i32; 3], (Vec3, f32)> = HashMap::new();
+        for tri in mesh.indices.chunks_exact(3) {
+            let [a, b, c] = [tri[0], tri[1], tri[2]].map(|i| mesh.vertex(i));
+            let n = cross(sub(b, a), sub(c, a));
+            let area = length(n) / 2.0;
+            if area <= f32::EPSILON {
+                continue;
+            }
+            let unit = normalize(n);
+            let key = unit.map(|v| (v * 100.0).round() as i32);
+            let entry = groups.entry(key).or_insert((unit, 0.0));
+            entry.1 += area;
+        }
+
+        let mut faces: Vec<(Vec3, f32)> = groups.into_values().collect();
+        faces.sort_by(|a, b| b.1.total_cmp(&a.1));
+        for (normal, _) in faces.into_iter().take(self.max_face_candidates) {
+            if !candidates.iter().any(|c| dot(*c, normal) > 0.9999) {
+                candidates.push(normal);
+            }
+        }
+
+        candidates
+    }
+}
+
+/// Z where a vertical ray at (px, py) crosses triangle abc, if it does.
+fn ray_hit(a: Vec3, b: Vec3, c: Vec3, px: f32, py: f32) -> Option<f32> {
+    let det = (b[1] - c[1]) * (a[0] - c[0]) + (c[0] - b[0]) * (a[1] - c[1]);
+    if det.abs() <= f32::EPSILON {
+        return None;
+    }
+    let w0 = ((b[1] - c[1]) * (px - c[0]) + (c[0] - b[0]) * (py - c[1])) / det;
+    let w1 = ((c[1] - a[1]) * (px - c[0]) + (a[0] - c[0]) * (py - c[1])) / det;
+    let w2 = 1.0 - w0 - w1;
+    let eps = -1e-5;
+    if w0 < eps || w1 < eps || w2 < eps {
+        return None;
+    }
+    Some(w0 * a[2] + w1 * b[2] + w2 * c[2])
+}
+
+fn add(a: Vec3, b: Vec3) -> Vec3 {
+    [a[0] + b[0], a[1] + b[1], a[2] + b[2]]
+}
+
+fn sub(a: Vec3, b: Vec3) -> Vec3 {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn dot(a: Vec3, b: Vec3) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: Vec3, b: Vec3) -> Vec3 {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+fn length(a: Vec3) -> f32 {
+    dot(a, a).sqrt()
+}
+
+fn normalize(a: Vec3) -> Vec3 {
+    let len = length(a);
+    if len <= f32::EPSILON {
+        a
+    } else {
+        [a[0] / len, a[1] / len, a[2] / len]
+    }
+}
+
+fn mat_vec(m: &Mat3, v: Vec3) -> Vec3 {
+    [dot(m[0], v), dot(m[1], v), dot(m[2], v)]
+}
+
+fn mat_mul(a: &Mat3, b: &Mat3) -> Mat3 {
+    let mut m = [[0.0; 3]; 3];
+    for (i, row) in m.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            *value = (0..3).map(|k| a[i][k] * b[k][j]).sum();
+        }
+    }
+    m
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MeshUnits;
+
+    /// Closed box with outward winding.
+    fn add_box(mesh: &mut Mesh, min: Vec3, max: Vec3) {
+        let base = (mesh.vertices.len() / 3) as u32;
+        for i in 0..8 {
+            mesh.vertices.push(if i & 1 == 0 { min[0] } else { max[0] });
+            mesh.vertices.push(if i & 2 == 0 { min[1] } else { max[1] });
+            mesh.vertices.push(if i & 4 == 0 { min[2] } else { max[2] });
+        }
+        let faces = [
+            [0, 2, 3], [0, 3, 1], // -Z
+            [4, 5, 7], [4, 7, 6], // +Z
+            [0, 1, 5], [0, 5, 4], // -Y
+            [2, 6, 7], [2, 7, 3], // +Y
+            [0, 4, 6], [0, 6, 2], // -X
+            [1, 3, 7], [1, 7, 5], // +X
+        ];
+        for f in faces {
+            mesh.indices.extend(f.iter().map(|i| base + i));
+        }
+    }
+
+    fn empty_mesh() -> Mesh {
+        Mesh {
+            vertices: Vec::new(),
+            indices: Vec::new(),
+            normals: None,
+            face_channels: None,
+            face_objects: None,
+            units: MeshUnits::Millimeters,
+        }
+    }
+
+    #[test]
+    fn test_rotation_and_mirror() {
+        let p = Transform::rotation(Axis::Z, 90.0).apply_point([1.0, 0.0, 0.0]);
+        assert!((p[0]).abs() < 1e-6 && (p[1] - 1.0).abs() < 1e-6);
+
+        let mut mesh = empty_mesh();
+        add_box(&mut mesh, [0.0; 3], [1.0; 3]);
+        let before = mesh.volume();
+        mesh.transform(&Transform::scale(-2.0, 1.0, 1.0)).unwrap();
+        assert!((mesh.volume() - before * 2.0).abs() < 1e-4);
+        // Winding was reversed, so the bottom face still points down
+        assert!(mesh.face_normal(0).unwrap()[2] < -0.99);
+    }
+
+    #[test]
+    fn test_lay_flat() {
+        let mut mesh = empty_mesh();
+        add_box(&mut mesh, [0.0, 0.0, 0.0], [10.0, 4.0, 2.0]);
+        // Face 6 is on the -Y side
+        mesh.lay_flat(6).unwrap();
+
+        let normal = mesh.face_normal(6).unwrap();
+        assert!((normal[2] + 1.0).abs() < 1e-5);
+        let (_, _, min_z, _, _, max_z) = mesh.bounding_box();
+        assert!(min_z.abs() < 1e-5);
+        assert!((max_z - 4.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_support_volume_of_floating_box() {
+        let mut mesh = empty_mesh();
+        add_box(&mut mesh, [0.0, 0.0, 0.0], [1.0, 1.0, 1.0]);
+        add_box(&mut mesh, [0.0, 0.0, 5.0], [10.0, 10.0, 6.0]);
+
+        // 99 cells need 5mm under the slab; above the pillar the gap is 4mm
+        let volume = AutoOrienter::new(1.0).support_volume(&mesh);
+        assert!((volume - (99.0 * 5.0 + 4.0)).abs() < 1.0, "got {}", volume);
+    }
+
+    #[test]
+    fn test_auto_orient_flips_mushroom() {
+        let mut mesh = empty_mesh();
+        add_box(&mut mesh, [4.0, 4.0, 0.0], [6.0, 6.0, 8.0]);
+        add_box(&mut mesh, [0.0, 0.0, 8.0], [10.0, 10.0, 10.0]);
+
+        let orienter = AutoOrienter::new(1.0);
+        assert!(orienter.support_volume(&mesh) > 100.0);
+
+        apply_transforms(&mut mesh, &[MeshTransform::AutoOrient], 1.0).unwrap();
+        assert!(orienter.support_volume(&mesh) < 1.0);
+        let (_, _, min_z, _, _, max_z) = mesh.bounding_box();
+        assert!(min_z.abs() < 1e-4 && (max_z - 10.0).abs() < 1e-3);
+    }
+}
@@ -1,50 +1,152 @@
 //! Valve mapping algorithms that translate layer geometry to valve grid coordinates.
+//!
+//! Each valve node deposits material over a square cell one grid spacing
+//! wide, centred on the node. Snapping a region to the nodes whose centres
+//! fall inside it leaves ragged, stair-stepped perimeters at coarse
+//! spacings. [`BoundaryMode`] selects how cells the boundary crosses are
+//! treated:
+//!
+//! - **Snap**: on if the node centre is inside the region
+//! - **Coverage**: on with a partial extrusion equal to the fraction of the
+//!   cell the region covers (the G4D `extrusion` field)
+//! - **Dither**: fully on or off, with error diffusion along the boundary so
+//!   the deposited area matches the covered area on average
+//!
+//! Coverage is found by supersampling only the cells within half a cell
+//! diagonal of an edge; every other cell is fully inside or outside.
 
-use crate::{LayerSlice, ValveActivationMap, ActiveNode, ValveGridConfig, SlicerError};
-use gcode_types::{GridCoordinate, ValveState};
+use std::collections::HashSet;
+
+use crate::core::gradient::SliceStack;
+use crate::core::shells::ShellGenerator;
+use crate::{LayerSlice, Region, ValveActivationMap, ActiveNode, ValveGridConfig, ValveMapper, SlicerError};
+use gcode_types::GridCoordinate;
 use anyhow::Result;
 
-/// Trait for mapping geometry to valve grid.
-pub trait ValveMapper: Send + Sync {
-    fn map_to_grid(
-        &self,
-        layer_slice: &LayerSlice,
-        grid_config: &ValveGridConfig,
-    ) -> Result<ValveActivationMap>;
-    
-    fn validate_mapping(&self, activation_map: &ValveActivationMap) -> Result<()>;
-}
+/// Samples per cell edge when measuring boundary coverage (16 per cell).
+const COVERAGE_SAMPLES: u32 = 4;
 
 /// Grid-aligned mapper that snaps geometry to nearest grid points.
+///
+/// Every valve of an active node opens; the node's channel decides which
+/// material reaches it. With a [`ShellGenerator`] regions are split into
+/// perimeter shells and patterned infill, otherwise they are filled solid.
 pub struct GridAlignedMapper {
     rounding_mode: RoundingMode,
+    boundary: BoundaryMode,
+    shells: Option<ShellGenerator>,
 }
 
+/// Nodes a snapped boundary keeps.
 #[derive(Debug, Clone, Copy)]
 pub enum RoundingMode {
+    /// Node centre inside the region
     Nearest,
+    /// Cell entirely inside the region
     Inside,
+    /// Cell touching the region
     Outside,
 }
 
+/// Treatment of cells crossed by a region boundary.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum BoundaryMode {
+    /// Fully on or off by the mapper's [`RoundingMode`]
+    #[default]
+    Snap,
+    /// Partial extrusion proportional to coverage; cells covered less than
+    /// `min_coverage` are left off
+    Coverage { min_coverage: f32 },
+    /// Binary activation with error diffusion along the boundary
+    Dither,
+}
+
+/// A node a region activates.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RasterNode {
+    pub position: GridCoordinate,
+    /// Fraction of the cell the region covers
+    pub coverage: f32,
+    /// Fraction of a full node's extrusion to deposit (1.0 except for
+    /// partial boundary nodes in coverage mode)
+    pub extrusion: f32,
+}
+
 impl GridAlignedMapper {
     pub fn new(mode: RoundingMode) -> Self {
-        Self { rounding_mode: mode }
+        Self {
+            rounding_mode: mode,
+            boundary: BoundaryMode::default(),
+            shells: None,
+        }
     }
 
-    /// Converts physical coordinates to grid coordinates.
-    fn to_grid_coord(&self, x: f32, y: f32, spacing: f32) -> GridCoordinate {
-        todo!("Implementation needed: Convert physical coords to grid coords with rounding")
+    pub fn with_boundary(mut self, boundary: BoundaryMode) -> Self {
+        self.boundary = boundary;
+        self
     }
 
-    /// Determines which grid points fall inside a polygonal region.
-    fn points_in_polygon(&self, polygon: &[(f32, f32)], grid_config: &ValveGridConfig) -> Vec<GridCoordinate> {
-        todo!("Implementation needed: Find all grid points inside polygon")
+    /// Splits regions into shells and infill with `shells`.
+    pub fn with_shells(mut self, shells: ShellGenerator) -> Self {
+        self.shells = Some(shells);
+        self
+    }
+
+    /// Nodes a region deposits and their channels: its shells and infill,
+    /// or the whole region, with the region's flow override applied.
+    fn region_nodes(
+        &self,
+        region: &Region,
+        grid_config: &ValveGridConfig,
+        layer_number: u32,
+        stack: Option<&SliceStack>,
+    ) -> Vec<(RasterNode, u8)> {
+        match &self.shells {
+            Some(shells) => shells
+                .generate_in_stack(region, grid_config, self, layer_number, stack)
+                .into_iter()
+                .map(|node| (node.node, node.material_channel))
+                .collect(),
+            None => {
+                let flow = region.overrides.flow();
+                self.rasterize_region(region, grid_config)
+                    .into_iter()
+                    .map(|node| {
+                        (RasterNode { extrusion: node.extrusion * flow, ..node }, region.material_channel)
+                    })
+                    .collect()
+            }
+        }
+    }
+
+    /// Rasterizes a region onto the grid using the boundary mode.
+    pub fn rasterize_region(&self, region: &Region, grid_config: &ValveGridConfig) -> Vec<RasterNode> {
+        let Some(raster) = CoverageRaster::measure(region, grid_config) else {
+            return Vec::new();
+        };
+
+        match self.boundary {
+            BoundaryMode::Snap => raster
+                .nodes()
+                .filter(|&(_, coverage, inside)| match self.rounding_mode {
+                    RoundingMode::Nearest => inside,
+                    RoundingMode::Inside => coverage >= 1.0,
+                    RoundingMode::Outside => coverage > 0.0,
+                })
+                .map(|(position, coverage, _)| RasterNode { position, coverage, extrusion: 1.0 })
+                .collect(),
+            BoundaryMode::Coverage { min_coverage } => raster
+                .nodes()
+                .filter(|(_, coverage, _)| *coverage > 0.0 && *coverage >= min_coverage)
+                .map(|(position, coverage, _)| RasterNode { position, coverage, extrusion: coverage })
+                .collect(),
+            BoundaryMode::Dither => raster.dither(),
+        }
     }
 
     /// Determines required valves for each active node.
-    fn determine_valve_states(&self, position: GridCoordinate, material_channel: u8) -> Vec<u8> {
-        todo!("Implementation needed: Determine which valves must be open for this node")
+    fn determine_valve_states(&self, grid_config: &ValveGridConfig) -> Vec<u8> {
+        (0..grid_config.valves_per_node).collect()
     }
 }
 
@@ -54,10 +156,336 @@ impl ValveMapper for GridAlignedMapper {
         layer_slice: &LayerSlice,
         grid_config: &ValveGridConfig,
     ) -> Result<ValveActivationMap> {
-        todo!("Implementation needed: Map layer geometry to valve activation map")
+        self.map_in_stack(layer_slice, grid_config, None)
+    }
+
+    fn map_in_stack(
+        &self,
+        layer_slice: &LayerSlice,
+        grid_config: &ValveGridConfig,
+        stack: Option<&SliceStack>,
+    ) -> Result<ValveActivationMap> {
+        let required_valves = self.determine_valve_states(grid_config);
+        let mut claimed = HashSet::new();
+        let mut active_nodes = Vec::new();
+        for region in &layer_slice.regions {
+            let nodes = self.region_nodes(region, grid_config, layer_slice.layer_number, stack);
+            for (node, material_channel) in nodes {
+                // Boundary cells shared by touching regions go to the first
+                if !claimed.insert(node.position) {
+                    continue;
+                }
+                active_nodes.push(ActiveNode {
+                    position: node.position,
+                    material_channel,
+                    required_valves: required_valves.clone(),
+                    object_id: region.object_id,
+                    extrusion: (node.extrusion != 1.0).then_some(node.extrusion),
+                });
+            }
+        }
+
+        let activation_map = ValveActivationMap {
+            layer_number: layer_slice.layer_number,
+            z_height: layer_slice.z_height,
+            active_nodes,
+        };
+        self.validate_mapping(&activation_map)?;
+        Ok(activation_map)
     }
 
     fn validate_mapping(&self, activation_map: &ValveActivationMap) -> Result<()> {
-        todo!("Implementation needed: Validate activation map is achievable")
+        let mut seen = HashSet::new();
+        for node in &activation_map.active_nodes {
+            if node.required_valves.is_empty() {
+                return Err(SlicerError::ValveMapping(format!(
+                    "Layer {}: node ({}, {}) opens no valves",
+                    activation_map.layer_number, node.position.x, node.position.y
+                ))
+                .into());
+            }
+            if node.extrusion.is_some_and(|e| !(e.is_finite() && e > 0.0)) {
+                return Err(SlicerError::ValveMapping(format!(
+                    "Layer {}: node ({}, {}) has invalid extrusion {:?}",
+                    activation_map.layer_number, node.position.x, node.position.y, node.extrusion
+                ))
+                .into());
+            }
+            // A node deposits one material per layer
+            if !seen.insert(node.position) {
+                return Err(SlicerError::ValveMapping(format!(
+                    "Layer {}: node ({}, {}) is activated twice",
+                    activation_map.layer_number, node.position.x, node.position.y
+                ))
+                .into());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Per-node coverage over a region's bounding box on the grid.
+struct CoverageRaster {
+    origin: GridCoordinate,
+    width: u32,
+    height: u32,
+    /// Row-major (coverage, centre inside)
+    cells: Vec<(f32, bool)>,
+}
+
+impl CoverageRaster {
+    fn measure(region: &Region, grid: &ValveGridConfig) -> Option<Self> {
+        if region.outer.len() < 3 || grid.spacing <= 0.0 || grid.grid_width == 0 || grid.grid_height == 0 {
+            return None;
+        }
+
+        let (min_x, min_y, max_x, max_y) = region.outer.iter().fold(
+            (f32::MAX, f32::MAX, f32::MIN, f32::MIN),
+            |(ax, ay, bx, by), &(x, y)| (ax.min(x), ay.min(y), bx.max(x), by.max(y)),
+        );
+        // Nodes whose cells can touch the bounding box
+        let to_index = |v: f32, origin: f32, limit: u32| {
+            ((v - origin) / grid.spacing).round().clamp(0.0, (limit - 1) as f32) as u32
+        };
+        let x0 = to_index(min_x, grid.origin_x, grid.grid_width);
+        let x1 = to_index(max_x, grid.origin_x, grid.grid_width);
+        let y0 = to_index(min_y, grid.origin_y, grid.grid_height);
+        let y1 = to_index(max_y, grid.origin_y, grid.grid_height);
+
+        let half = grid.spacing / 2.0;
+        let half_diagonal = half * std::f32::consts::SQRT_2;
+        let mut cells = Vec::with_capacity(((x1 - x0 + 1) * (y1 - y0 + 1)) as usize);
+
+        for y in y0..=y1 {
+            for x in x0..=x1 {
+                let cx = grid.origin_x + x as f32 * grid.spacing;
+                let cy = grid.origin_y + y as f32 * grid.spacing;
+                let inside = region_contains(region, cx, cy);
+                let coverage = if edge_distance(region, cx, cy) >= half_diagonal {
+                    if inside { 1.0 } else { 0.0 }
+                } else {
+                    sample_coverage(region, cx - half, cy - half, grid.spacing)
+                };
+                cells.push((coverage, inside));
+            }
+        }
+
+        Some(Self {
+            origin: GridCoordinate::new(x0, y0),
+            width: x1 - x0 + 1,
+            height: y1 - y0 + 1,
+            cells,
+        })
+    }
+
+    /// (position, coverage, centre inside) for every cell.
+    fn nodes(&self) -> impl Iterator<Item = (GridCoordinate, f32, bool)> + '_ {
+        self.cells.iter().enumerate().map(move |(i, &(coverage, inside))| {
+            (self.position(i), coverage, inside)
+        })
+    }
+
+    fn position(&self, index: usize) -> GridCoordinate {
+        let i = index as u32;
+        GridCoordinate::new(self.origin.x + i % self.width, self.origin.y + i / self.width)
+    }
+
+    /// Floyd-Steinberg error diffusion over the partially covered cells.
+    ///
+    /// Rows alternate direction to avoid directional artefacts. Fully
+    /// covered and empty cells neither receive nor pass on error, so the
+    /// interior is unaffected.
+    fn dither(&self) -> Vec<RasterNode> {
+        let (w, h) = (self.width as i64, self.height as i64);
+        let partial = |c: f32| c > 0.0 && c < 1.0;
+        let mut value: Vec<f32> = self.cells.iter().map(|&(c, _)| c).collect();
+        let mut nodes = Vec::new();
+
+        for y in 0..h {
+            let forward = y % 2 == 0;
+            for step in 0..w {
+                let x = if forward { step } else { w - 1 - step };
+                let i = (y * w + x) as usize;
+                let coverage = self.cells[i].0;
+
+                let on = if partial(coverage) { value[i] >= 0.5 } else { coverage >= 1.0 };
+                if on {
+                    nodes.push(RasterNode { position: self.position(i), coverage, extrusion: 1.0 });
+                }
+                if !partial(coverage) {
+                    continue;
+                }
+
+                let error = value[i] - if on { 1.0 } else { 0.0 };
+                let dir = if forward { 1 } else { -1 };
+                let targets = [(dir, 0, 7.0), (-dir, 1, 3.0), (0, 1, 5.0), (dir, 1, 1.0)];
+                let eligible: Vec<(usize, f32)> = targets
+                    .iter()
+                    .filter_map(|&(dx, dy, weight)| {
+                        let (nx, ny) = (x + dx, y + dy);
+                        if nx < 0 || nx >= w || ny >= h {
+                            return None;
+                        }
+                        let j = (ny * w + nx) as usize;
+                        partial(self.cells[j].0).then_some((j, weight))
+                    })
+                    .collect();
+                let total: f32 = eligible.iter().map(|(_, w)| w).sum();
+                for (j, weight) in eligible {
+                    value[j] += error * weight / total;
+                }
+            }
+        }
+
+        nodes
+    }
+}
+
+/// Even-odd test against the outer boundary and holes.
+fn region_contains(region: &Region, x: f32, y: f32) -> bool {
+    polygon_contains(&region.outer, x, y) && !region.holes.iter().any(|h| polygon_contains(h, x, y))
+}
+
+fn polygon_contains(polygon: &[(f32, f32)], x: f32, y: f32) -> bool {
+    let mut inside = false;
+    let mut j = polygon.len().wrapping_sub(1);
+    for i in 0..polygon.len() {
+        let (xi, yi) = polygon[i];
+        let (xj, yj) = polygon[j];
+        if (yi > y) != (yj > y) && x < (xj - xi) * (y - yi) / (yj - yi) + xi {
+            inside = !inside;
+        }
+        j = i;
+    }
+    inside
+}
+
+/// Distance from a point to the nearest edge of the region.
+fn edge_distance(region: &Region, x: f32, y: f32) -> f32 {
+    std::iter::once(&region.outer)
+        .chain(&region.holes)
+        .flat_map(|ring| {
+            (0..ring.len()).map(move |i| segment_distance(ring[i], ring[(i + 1) % ring.len()], x, y))
+        })
+        .fold(f32::MAX, f32::min)
+}
+
+fn segment_distance((ax, ay): (f32, f32), (bx, by): (f32, f32), x: f32, y: f32) -> f32 {
+    let (dx, dy) = (bx - ax, by - ay);
+    let len_sq = dx * dx + dy * dy;
+    let t = if len_sq > 0.0 {
+        (((x - ax) * dx + (y - ay) * dy) / len_sq).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    let (px, py) = (ax + t * dx - x, ay + t * dy - y);
+    (px * px + py * py).sqrt()
+}
+
+/// Fraction of sample points inside the region over a square cell.
+fn sample_coverage(region: &Region, left: f32, bottom: f32, size: f32) -> f32 {
+    let step = size / COVERAGE_SAMPLES as f32;
+    let mut hits = 0;
+    for sy in 0..COVERAGE_SAMPLES {
+        for sx in 0..COVERAGE_SAMPLES {
+            let x = left + (sx as f32 + 0.5) * step;
+            let y = bottom + (sy as f32 + 0.5) * step;
+            if region_contains(region, x, y) {
+                hits += 1;
+            }
+        }
+    }
+    hits as f32 / (COVERAGE_SAMPLES * COVERAGE_SAMPLES) as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::RegionOverrides;
+
+    fn grid() -> ValveGridConfig {
+        ValveGridConfig {
+            spacing: 1.0,
+            origin_x: 0.0,
+            origin_y: 0.0,
+            grid_width: 40,
+            grid_height: 40,
+            valves_per_node: 4,
+        }
+    }
+
+    /// Square whose left and right edges run through node centres, so those
+    /// columns are half covered.
+    fn square() -> Region {
+        Region {
+            outer: vec![(5.0, 4.6), (15.0, 4.6), (15.0, 15.4), (5.0, 15.4)],
+            holes: vec![],
+            material_channel: 0,
+            object_id: None,
+            overrides: RegionOverrides::default(),
+        }
+    }
+
+    fn at(nodes: &[RasterNode], x: u32, y: u32) -> Option<RasterNode> {
+        nodes.iter().copied().find(|n| n.position == GridCoordinate::new(x, y))
+    }
+
+    #[test]
+    fn test_coverage_mode_emits_partial_extrusion() {
+        let mapper = GridAlignedMapper::new(RoundingMode::Nearest)
+            .with_boundary(BoundaryMode::Coverage { min_coverage: 0.1 });
+        let nodes = mapper.rasterize_region(&square(), &grid());
+
+        assert_eq!(at(&nodes, 10, 10).unwrap().extrusion, 1.0);
+        assert_eq!(at(&nodes, 5, 10).unwrap().extrusion, 0.5);
+        assert_eq!(at(&nodes, 15, 10).unwrap().extrusion, 0.5);
+        // Bottom edge at 4.6 covers 90% of row 5 and none of row 4
+        assert!(at(&nodes, 10, 5).unwrap().extrusion > 0.7);
+        assert!(at(&nodes, 10, 4).is_none());
+
+        // Deposited area approximates the 10 x 10.8 mm square
+        let area: f32 = nodes.iter().map(|n| n.extrusion).sum();
+        assert!((area - 108.0).abs() < 4.0, "area {}", area);
+    }
+
+    #[test]
+    fn test_dither_matches_boundary_coverage() {
+        let mapper = GridAlignedMapper::new(RoundingMode::Nearest).with_boundary(BoundaryMode::Dither);
+        let nodes = mapper.rasterize_region(&square(), &grid());
+
+        assert!(nodes.iter().all(|n| n.extrusion == 1.0));
+        // Half-covered columns end up with roughly half their nodes on
+        let left_column = nodes.iter().filter(|n| n.position.x == 5).count();
+        assert!((4..=7).contains(&left_column), "left column {}", left_column);
+        // Interior is untouched
+        assert!((6..15).all(|x| at(&nodes, x, 10).is_some()));
+
+        let snapped = GridAlignedMapper::new(RoundingMode::Nearest).rasterize_region(&square(), &grid());
+        assert!(nodes.len() < snapped.len() + 11);
+    }
+
+    #[test]
+    fn test_shells_leave_perimeter_on_its_channel() {
+        let mut settings = crate::config::ExampleConfigs::for_model(config_types::PrinterModel::HyperCubeMini)
+            .unwrap()
+            .settings;
+        settings.shells.perimeter_count = 1;
+        settings.shells.flow_factor = 1.0;
+        settings.shells.material_channel = Some(1);
+        settings.infill.density = 0.0;
+        let slice = LayerSlice { z_height: 0.2, layer_number: 0, regions: vec![square()] };
+
+        let solid = GridAlignedMapper::new(RoundingMode::Nearest).map_to_grid(&slice, &grid()).unwrap();
+        let shelled = GridAlignedMapper::new(RoundingMode::Nearest)
+            .with_shells(ShellGenerator::new(&settings))
+            .map_to_grid(&slice, &grid())
+            .unwrap();
+        assert!(solid.active_nodes.iter().all(|n| n.material_channel == 0));
+        // Only the outer ring is left, on the perimeter channel
+        assert!(!shelled.active_nodes.is_empty() && shelled.active_nodes.len() < solid.active_nodes.len() / 2);
+        assert!(shelled.active_nodes.iter().all(|n| {
+            let p = n.position;
+            n.material_channel == 1 && (p.x <= 6 || p.x >= 14 || p.y <= 5 || p.y >= 15)
+        }));
     }
 }
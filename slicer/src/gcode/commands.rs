@@ -7,6 +7,7 @@ pub struct G4DBuilder {
     position: Coordinate,
     valves: Vec<ValveState>,
     extrusion: Option<f32>,
+    close_early_ms: Option<f32>,
 }
 
 impl G4DBuilder {
@@ -15,6 +16,7 @@ impl G4DBuilder {
             position,
             valves: Vec::new(),
             extrusion: None,
+            close_early_ms: None,
         }
     }
 
@@ -28,11 +30,18 @@ impl G4DBuilder {
         self
     }
 
+    /// Closes this command's closing valves `ms` ahead of schedule.
+    pub fn close_early(mut self, ms: f32) -> Self {
+        self.close_early_ms = Some(ms);
+        self
+    }
+
     pub fn build(self) -> Command {
         Command::G4D(G4DCommand {
             position: self.position,
             valves: self.valves,
             extrusion: self.extrusion,
+            close_early_ms: self.close_early_ms,
         })
     }
 }
@@ -75,6 +84,16 @@ impl CommandBuilder {
         Command::G4L(G4LCommand {
             z_height: z,
             feed_rate: None,
+            ramp_ms: None,
+        })
+    }
+
+    /// Creates a continuous Z ramp to `z` for vase mode.
+    pub fn layer_ramp(z: f32, duration: std::time::Duration) -> Command {
+        Command::G4L(G4LCommand {
+            z_height: z,
+            feed_rate: None,
+            ramp_ms: Some((duration.as_secs_f64() * 1000.0).round().max(1.0) as u32),
         })
     }
 
@@ -97,16 +116,37 @@ impl CommandBuilder {
     /// Creates temperature set command.
     pub fn set_temperature(zone: u8, temp: f32, wait: bool) -> Command {
         Command::G4H(G4HCommand {
-            temperature: temp,
+            temperature: Celsius::new(temp),
             zone: Some(zone),
             wait,
+            heater: Heater::Zone,
+        })
+    }
+
+    /// Creates build plate temperature command.
+    pub fn set_bed_temperature(temp: f32, wait: bool) -> Command {
+        Command::G4H(G4HCommand {
+            temperature: Celsius::new(temp),
+            zone: None,
+            wait,
+            heater: Heater::Bed,
+        })
+    }
+
+    /// Creates build chamber temperature command.
+    pub fn set_chamber_temperature(temp: f32, wait: bool) -> Command {
+        Command::G4H(G4HCommand {
+            temperature: Celsius::new(temp),
+            zone: None,
+            wait,
+            heater: Heater::Chamber,
         })
     }
 
     /// Creates pressure set command.
     pub fn set_pressure(channel: u8, pressure: f32) -> Command {
         Command::G4P(G4PCommand {
-            pressure,
+            pressure: Psi::new(pressure),
             material_channel: Some(channel),
         })
     }
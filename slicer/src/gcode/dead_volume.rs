@@ -0,0 +1,247 @@
+//! Dead volume compensation.
+//!
+//! Each valve holds a little melt between its seat and the outlet
+//! (`valve_array.dead_volume`). After the valve closes, that volume keeps
+//! draining and the deposit oozes past its end. [`DeadVolumeCompensator`]
+//! rewrites a layer's commands before they are written:
+//!
+//! - **Early close**: a G4D that closes open valves gets `close_early_ms`,
+//!   the valve response time plus the time the dead volume takes to drain
+//!   at the valve's share of its channel's flow, capped at
+//!   `max_lead_ms`. The firmware latches the closing valves that much
+//!   sooner, but never before they may switch again.
+//! - **Suck-back**: once the last open valve of a channel closes, a short
+//!   pressure pulse draws the melt back out of the seats: G4P to the pulse
+//!   pressure, a G4W for its length, then G4P back to the channel's
+//!   setpoint.
+//!
+//! The compensator follows G4C channel selection and G4P setpoints across
+//! calls, so one instance should see every layer of a print in order.
+
+use std::collections::{BTreeMap, HashMap};
+
+use anyhow::{bail, Result};
+
+use config_types::{DeadVolumeSettings, MaterialProfile, PrinterConfig};
+use gcode_types::{Command, G4DCommand, G4PCommand, G4WCommand, Psi, WaitType};
+
+/// What a pass over a layer changed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DeadVolumeReport {
+    /// G4D commands given a close-early lead
+    pub early_closes: usize,
+    /// Suck-back pulses inserted
+    pub pulses: usize,
+}
+
+/// Adds early closes and suck-back pulses to the command stream.
+#[derive(Debug, Clone)]
+pub struct DeadVolumeCompensator {
+    settings: DeadVolumeSettings,
+    grid_spacing: f32,
+    response_ms: f32,
+    /// Dead volume per valve (mm³)
+    dead_volume: f32,
+    /// Maximum flow per channel (mm³/s)
+    channel_flow: BTreeMap<u8, f32>,
+    /// Current pressure setpoint per channel
+    pressures: BTreeMap<u8, Psi>,
+    /// Channel G4D commands deposit from
+    channel: u8,
+    /// Open valves: (node x, node y, valve index) -> channel
+    open: HashMap<(i64, i64, u8), u8>,
+}
+
+impl DeadVolumeCompensator {
+    /// Creates a compensator. `materials` are indexed by channel and give
+    /// the pressures suck-back pulses return to until a G4P sets another.
+    pub fn new(printer: &PrinterConfig, settings: &DeadVolumeSettings, materials: &[MaterialProfile]) -> Result<Self> {
+        if settings.max_lead_ms.is_nan() || settings.max_lead_ms < 0.0 {
+            bail!("Dead volume max_lead_ms must not be negative, got {}", settings.max_lead_ms);
+        }
+        if let Some(pulse) = settings.suck_back {
+            let min = printer.materials.pressure.min_pressure;
+            if !pulse.pressure.is_finite() || pulse.pressure < min {
+                bail!(
+                    "Suck-back pressure {:.1} PSI is below the regulator minimum of {:.1} PSI",
+                    pulse.pressure,
+                    min
+                );
+            }
+            if pulse.duration_ms == 0 {
+                bail!("Suck-back pulse duration must be at least 1ms");
+            }
+        }
+
+        let mut channel_flow = BTreeMap::new();
+        for extruder in &printer.materials.extruders {
+            *channel_flow.entry(extruder.material_channel).or_insert(0.0) += extruder.max_flow_rate;
+        }
+        let pressures = materials
+            .iter()
+            .enumerate()
+            .map(|(channel, profile)| (channel as u8, Psi::new(profile.extrusion.pressure_psi)))
+            .collect();
+
+        Ok(Self {
+            settings: settings.clone(),
+            grid_spacing: printer.valve_array.grid_spacing,
+            response_ms: printer.valve_array.response_time_ms.max(0.0),
+            dead_volume: printer.valve_array.dead_volume.get().max(0.0),
+            channel_flow,
+            pressures,
+            channel: 0,
+            open: HashMap::new(),
+        })
+    }
+
+    /// Compensates one layer's commands in place.
+    pub fn apply(&mut self, commands: &mut Vec<Command>) -> DeadVolumeReport {
+        let mut report = DeadVolumeReport::default();
+        let mut output = Vec::with_capacity(commands.len());
+
+        for mut cmd in commands.drain(..) {
+            let mut idle = None;
+            match &mut cmd {
+                Command::G4C(g4c) => {
+                    if let Some(channel) = g4c.material_channel {
+                        self.channel = channel;
+                    }
+                }
+                Command::G4P(g4p) => match g4p.material_channel {
+                    Some(channel) => {
+                        self.pressures.insert(channel, g4p.pressure);
+                    }
+                    None => {
+                        for pressure in self.pressures.values_mut() {
+                            *pressure = g4p.pressure;
+                        }
+                    }
+                },
+                Command::G4D(g4d) => idle = self.deposit(g4d, &mut report),
+                _ => {}
+            }
+            output.push(cmd);
+
+            if let (Some(channel), Some(pulse)) = (idle, self.settings.suck_back) {
+                let Some(&restore) = self.pressures.get(&channel) else {
+                    continue;
+                };
+                output.push(Command::G4P(G4PCommand { pressure: Psi::new(pulse.pressure), material_channel: Some(channel) }));
+                output.push(Command::G4W(G4WCommand {
+                    wait_type: WaitType::Duration(pulse.duration_ms),
+                    timeout_ms: None,
+                }));
+                output.push(Command::G4P(G4PCommand { pressure: restore, material_channel: Some(channel) }));
+                report.pulses += 1;
+            }
+        }
+
+        *commands = output;
+        report
+    }
+
+    /// Updates the open valves for a deposit and sets its lead. Returns the
+    /// channel if the deposit closed that channel's last open valve.
+    fn deposit(&mut self, g4d: &mut G4DCommand, report: &mut DeadVolumeReport) -> Option<u8> {
+        let node = (
+            (g4d.position.x / self.grid_spacing).round() as i64,
+            (g4d.position.y / self.grid_spacing).round() as i64,
+        );
+        let closing: Vec<u8> = g4d
+            .valves
+            .iter()
+            .filter(|v| !v.open)
+            .filter_map(|v| self.open.get(&(node.0, node.1, v.index)).copied())
+            .collect();
+
+        if let Some(&channel) = closing.first() {
+            if self.settings.early_close && g4d.close_early_ms.is_none() {
+                let lead = self.lead_ms(channel);
+                if lead > 0.0 {
+                    g4d.close_early_ms = Some(lead);
+                    report.early_closes += 1;
+                }
+            }
+        }
+
+        for valve in &g4d.valves {
+            let key = (node.0, node.1, valve.index);
+            if valve.open {
+                self.open.insert(key, self.channel);
+            } else {
+                self.open.remove(&key);
+            }
+        }
+
+        closing
+            .into_iter()
+            .find(|channel| !self.open.values().any(|c| c == channel))
+    }
+
+    /// Response time plus the time the dead volume drains at the valve's
+    /// share of its channel's flow (ms).
+    fn lead_ms(&self, channel: u8) -> f32 {
+        let open = self.open.values().filter(|&&c| c == channel).count().max(1);
+        let drain = match self.channel_flow.get(&channel) {
+            Some(&flow) if flow > 0.0 => 1000.0 * self.dead_volume / (flow / open as f32),
+            _ => 0.0,
+        };
+        (self.response_ms + drain).min(self.settings.max_lead_ms)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ExampleConfigs;
+    use crate::gcode::commands::{G4DBuilder, MaterialCommandBuilder};
+    use config_types::{PrinterModel, SuckBackPulse};
+    use gcode_types::Coordinate;
+
+    #[test]
+    fn test_early_close_and_suck_back() {
+        let configs = ExampleConfigs::for_model(PrinterModel::HyperCubeMini).unwrap();
+        let materials: Vec<MaterialProfile> = configs.materials.iter().map(|(_, p)| p.clone()).collect();
+        let mut settings = DeadVolumeSettings {
+            suck_back: Some(SuckBackPulse { pressure: 1.0, duration_ms: 30 }),
+            ..DeadVolumeSettings::default()
+        };
+        assert!(DeadVolumeCompensator::new(&configs.printer, &settings, &materials).is_err());
+        settings.suck_back = Some(SuckBackPulse { pressure: 5.0, duration_ms: 30 });
+        let mut compensator = DeadVolumeCompensator::new(&configs.printer, &settings, &materials).unwrap();
+
+        let at = |x: f32, open: bool| G4DBuilder::new(Coordinate::new(x, 0.0, 0.2)).valve(0, open).build();
+        let mut commands = vec![
+            MaterialCommandBuilder::set_material_channel(0),
+            at(0.0, true),
+            at(0.5, true),
+            at(0.0, false),
+            at(0.5, false),
+        ];
+        let report = compensator.apply(&mut commands);
+        assert_eq!(report, DeadVolumeReport { early_closes: 2, pulses: 1 });
+        assert_eq!(commands.len(), 8);
+
+        let lead = |cmd: &Command| match cmd {
+            Command::G4D(g4d) => g4d.close_early_ms,
+            _ => panic!("expected G4D"),
+        };
+        let valves = &configs.printer.valve_array;
+        let flow = configs.printer.materials.extruders[0].max_flow_rate;
+        // Two valves share the flow, then one has it to itself
+        let first = valves.response_time_ms + 1000.0 * valves.dead_volume.get() / (flow / 2.0);
+        let second = valves.response_time_ms + 1000.0 * valves.dead_volume.get() / flow;
+        assert!(lead(&commands[1]).is_none());
+        assert!((lead(&commands[3]).unwrap() - first.min(settings.max_lead_ms)).abs() < 1e-4);
+        assert!((lead(&commands[4]).unwrap() - second.min(settings.max_lead_ms)).abs() < 1e-4);
+
+        assert_eq!(commands[5], Command::G4P(G4PCommand { pressure: Psi::new(5.0), material_channel: Some(0) }));
+        assert_eq!(
+            commands[6],
+            Command::G4W(G4WCommand { wait_type: WaitType::Duration(30), timeout_ms: None })
+        );
+        let restore = Psi::new(materials[0].extrusion.pressure_psi);
+        assert_eq!(commands[7], Command::G4P(G4PCommand { pressure: restore, material_channel: Some(0) }));
+    }
+}
@@ -1,58 +1,185 @@
 //! G-code generation from processed layer data.
 
-use crate::{ProcessedLayer, SliceMetadata, SlicerError};
-use gcode_types::{Command, Layer, NodeValveState};
-use config_types::MaterialProfile;
-use anyhow::Result;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 
-/// Trait for generating HyperGCode-4D commands.
-pub trait GCodeGenerator: Send + Sync {
-    fn generate_layer_gcode(
-        &self,
-        layer: &ProcessedLayer,
-        material_profiles: &[MaterialProfile],
-    ) -> Result<Vec<Command>>;
-    
-    fn generate_header(&self, metadata: &SliceMetadata) -> Result<Vec<Command>>;
-    fn generate_footer(&self) -> Result<Vec<Command>>;
-}
+use crate::{GCodeGenerator, ProcessedLayer, Region, SliceMetadata, SlicerError, ValveActivationMap};
+use super::commands::{CommandBuilder, G4DBuilder, MaterialCommandBuilder};
+use gcode_types::{
+    Command, Coordinate, G4LCommand, G4WCommand, GridCoordinate, Layer, NodeValveState, ValveState, WaitType,
+};
+use config_types::{MaterialProfile, ThermalConfig};
+use anyhow::Result;
 
 /// Standard G-code generator implementation.
+///
+/// A layer is a G4L to its height, a G4P per deposited channel, then per
+/// channel a G4C followed by a G4D for each of its nodes, and a valve wait.
+/// G4D positions are in mm (grid coordinate times the grid spacing) and
+/// their extrusion is the node's fraction of a full cell one layer tall.
 pub struct StandardGCodeGenerator {
     include_comments: bool,
+    thermal: ThermalConfig,
+    /// Distance between valve nodes (mm)
+    grid_spacing: f32,
+    /// Nominal layer height (mm)
+    layer_height: f32,
 }
 
 impl StandardGCodeGenerator {
-    pub fn new() -> Self {
+    /// Generator with unit grid spacing and layer height, so positions are
+    /// grid coordinates and extrusion is in node volumes until
+    /// [`with_grid`](Self::with_grid) is called.
+    pub fn new(thermal: ThermalConfig) -> Self {
         Self {
             include_comments: true,
+            thermal,
+            grid_spacing: 1.0,
+            layer_height: 1.0,
         }
     }
 
-    /// Generates heating commands for all zones.
-    fn generate_heating_commands(&self, material_profiles: &[MaterialProfile]) -> Vec<Command> {
-        todo!("Implementation needed: Generate G4H commands for zone temperatures")
+    /// Sets the valve grid spacing and nominal layer height (mm).
+    pub fn with_grid(mut self, grid_spacing: f32, layer_height: f32) -> Self {
+        self.grid_spacing = grid_spacing;
+        self.layer_height = layer_height;
+        self
     }
 
-    /// Generates pressure setup commands.
-    fn generate_pressure_commands(&self, layer: &ProcessedLayer) -> Vec<Command> {
-        todo!("Implementation needed: Generate G4P commands for pressure setup")
+    pub fn with_comments(mut self, include_comments: bool) -> Self {
+        self.include_comments = include_comments;
+        self
+    }
+
+    /// Generates heating commands for the zones backing the given channels,
+    /// and for the bed and chamber if the printer has them and the
+    /// materials want them.
+    ///
+    /// Zones that no used channel flows through are left cold. All targets
+    /// are set first so heaters warm in parallel, then a single temperature
+    /// wait holds the print until every one has settled.
+    pub fn generate_heating_commands(
+        &self,
+        channels: &[(u8, &MaterialProfile)],
+    ) -> Result<Vec<Command>, SlicerError> {
+        let plan = self
+            .thermal
+            .plan_zone_temperatures(channels)
+            .map_err(|e| SlicerError::MaterialIncompatibility(e.to_string()))?;
+
+        let mut commands: Vec<Command> = plan
+            .iter()
+            .map(|(&zone, &temp)| CommandBuilder::set_temperature(zone, temp, false))
+            .collect();
+        if let Some(temp) = self.thermal.plan_bed_temperature(channels) {
+            commands.push(CommandBuilder::set_bed_temperature(temp, false));
+        }
+        if let Some(temp) = self.thermal.plan_chamber_temperature(channels) {
+            commands.push(CommandBuilder::set_chamber_temperature(temp, false));
+        }
+        if !commands.is_empty() {
+            commands.push(Command::G4W(G4WCommand {
+                wait_type: WaitType::Temperature,
+                timeout_ms: None,
+            }));
+        }
+        Ok(commands)
     }
 
-    /// Generates valve activation commands for a layer.
+    /// Zone temperature changes between two layers for the temperature
+    /// overrides of modifier meshes.
+    ///
+    /// `planned` holds the print's zone targets, as from
+    /// `ThermalConfig::plan_zone_temperatures`. On a layer depositing a
+    /// region with a temperature override, the zones feeding that region's
+    /// channel are set to it (the highest, if several regions share a
+    /// zone); the first layer without one returns them to their planned
+    /// targets. Changes are followed by a temperature wait, so the layer
+    /// deposits at its temperature.
+    pub fn modifier_temperature_commands(
+        &self,
+        planned: &BTreeMap<u8, f32>,
+        previous: &[Region],
+        next: &[Region],
+    ) -> Vec<Command> {
+        let targets = |regions: &[Region]| {
+            let mut targets = planned.clone();
+            let mut overridden: BTreeMap<u8, f32> = BTreeMap::new();
+            for region in regions {
+                let Some(temp) = region.overrides.temperature else { continue };
+                for &zone in self.thermal.zones_for_channel(region.material_channel) {
+                    let target = overridden.entry(zone).or_insert(temp);
+                    *target = target.max(temp);
+                }
+            }
+            targets.extend(overridden);
+            targets
+        };
+        let (before, after) = (targets(previous), targets(next));
+
+        let mut commands: Vec<Command> = after
+            .iter()
+            .filter(|(zone, temp)| before.get(zone) != Some(temp))
+            .map(|(&zone, &temp)| CommandBuilder::set_temperature(zone, temp, false))
+            .collect();
+        if !commands.is_empty() {
+            commands.push(Command::G4W(G4WCommand {
+                wait_type: WaitType::Temperature,
+                timeout_ms: None,
+            }));
+        }
+        commands
+    }
+
+    /// Generates pressure setup commands: each deposited channel at its
+    /// material's recommended pressure.
+    fn generate_pressure_commands(
+        &self,
+        layer: &ProcessedLayer,
+        material_profiles: &[MaterialProfile],
+    ) -> Result<Vec<Command>, SlicerError> {
+        let channels: BTreeSet<u8> =
+            layer.routing.activation_map.active_nodes.iter().map(|n| n.material_channel).collect();
+        Ok(channel_materials(&channels, material_profiles)?
+            .into_iter()
+            .map(|(channel, profile)| CommandBuilder::set_pressure(channel, profile.extrusion.pressure_psi))
+            .collect())
+    }
+
+    /// Generates valve activation commands for a layer, grouped by channel
+    /// with each group behind a G4C selecting it.
     fn generate_valve_commands(&self, layer: &ProcessedLayer) -> Vec<Command> {
-        todo!("Implementation needed: Generate G4D commands for valve patterns")
+        let mut by_channel: BTreeMap<u8, Vec<Command>> = BTreeMap::new();
+        let full = self.grid_spacing * self.grid_spacing * self.layer_height;
+        for node in &layer.routing.activation_map.active_nodes {
+            let position = Coordinate::new(
+                node.position.x as f32 * self.grid_spacing,
+                node.position.y as f32 * self.grid_spacing,
+                layer.z_height,
+            );
+            let command = node
+                .required_valves
+                .iter()
+                .fold(G4DBuilder::new(position), |builder, &valve| builder.valve(valve, true))
+                .extrusion(node.extrusion.unwrap_or(1.0) * full)
+                .build();
+            by_channel.entry(node.material_channel).or_default().push(command);
+        }
+
+        by_channel
+            .into_iter()
+            .flat_map(|(channel, commands)| {
+                std::iter::once(MaterialCommandBuilder::set_material_channel(channel)).chain(commands)
+            })
+            .collect()
     }
 
     /// Generates layer advance command.
     fn generate_layer_advance(&self, z_height: f32, feed_rate: Option<f32>) -> Command {
-        todo!("Implementation needed: Generate G4L command for Z movement")
+        Command::G4L(G4LCommand { z_height, feed_rate, ramp_ms: None })
     }
-}
 
-impl Default for StandardGCodeGenerator {
-    fn default() -> Self {
-        Self::new()
+    fn comment(&self, text: String) -> Option<Command> {
+        self.include_comments.then_some(Command::Comment(text))
     }
 }
 
@@ -62,14 +189,217 @@ impl GCodeGenerator for StandardGCodeGenerator {
         layer: &ProcessedLayer,
         material_profiles: &[MaterialProfile],
     ) -> Result<Vec<Command>> {
-        todo!("Implementation needed: Generate complete G-code for layer")
+        let mut commands: Vec<Command> = self
+            .comment(format!(
+                "Layer {} at Z{:.3}: {} nodes",
+                layer.layer_number,
+                layer.z_height,
+                layer.routing.activation_map.active_nodes.len()
+            ))
+            .into_iter()
+            .collect();
+        commands.push(self.generate_layer_advance(layer.z_height, None));
+        commands.extend(self.generate_pressure_commands(layer, material_profiles)?);
+        commands.extend(self.generate_valve_commands(layer));
+        commands.push(CommandBuilder::wait_valves());
+        Ok(commands)
     }
 
     fn generate_header(&self, metadata: &SliceMetadata) -> Result<Vec<Command>> {
-        todo!("Implementation needed: Generate file header with metadata comments")
+        let mut commands: Vec<Command> = [
+            format!("{} sliced by HyperGCode-4D slicer {}", metadata.model_name, metadata.slicer_version),
+            format!(
+                "Layer height {:.3}mm, {} setting overrides",
+                metadata.print_settings.settings.layer_height,
+                metadata.print_settings.applied.len()
+            ),
+        ]
+        .into_iter()
+        .filter_map(|text| self.comment(text))
+        .collect();
+
+        // Channels expected to deposit material, or every loaded one
+        let channels: BTreeSet<u8> = if metadata.material_usage.iter().any(|&grams| grams > 0.0) {
+            (0..metadata.material_usage.len() as u8)
+                .filter(|&channel| metadata.material_usage[channel as usize] > 0.0)
+                .collect()
+        } else {
+            (0..metadata.material_profiles.len() as u8).collect()
+        };
+        let materials = channel_materials(&channels, &metadata.material_profiles)?;
+        commands.extend(self.generate_heating_commands(&materials)?);
+        for &(channel, profile) in &materials {
+            commands.push(CommandBuilder::set_pressure(channel, profile.extrusion.pressure_psi));
+        }
+        if !materials.is_empty() {
+            commands.push(CommandBuilder::wait_pressure());
+        }
+        Ok(commands)
     }
 
     fn generate_footer(&self) -> Result<Vec<Command>> {
-        todo!("Implementation needed: Generate footer with cooldown commands")
+        let mut commands: Vec<Command> = self.comment("Cooldown".to_string()).into_iter().collect();
+        for zone in &self.thermal.zones {
+            commands.push(CommandBuilder::set_temperature(zone.id, 0.0, false));
+        }
+        if self.thermal.bed.is_some() {
+            commands.push(CommandBuilder::set_bed_temperature(0.0, false));
+        }
+        if self.thermal.chamber.is_some() {
+            commands.push(CommandBuilder::set_chamber_temperature(0.0, false));
+        }
+        Ok(commands)
+    }
+}
+
+/// G4Ds closing every valve a layer's commands leave open, one per node in
+/// the order the nodes were first opened.
+///
+/// The firmware closes them at the end of the layer anyway; written out,
+/// the closes can carry the close-early lead dead volume compensation
+/// gives them.
+pub fn closing_commands(commands: &[Command], grid_spacing: f32) -> Vec<Command> {
+    let mut nodes: Vec<(Coordinate, BTreeSet<u8>)> = Vec::new();
+    let mut index: HashMap<GridCoordinate, usize> = HashMap::new();
+    for command in commands {
+        let Command::G4D(deposit) = command else {
+            continue;
+        };
+        let to_grid = |mm: f32| (mm / grid_spacing).round().max(0.0) as u32;
+        let position = GridCoordinate::new(to_grid(deposit.position.x), to_grid(deposit.position.y));
+        let i = *index.entry(position).or_insert_with(|| {
+            nodes.push((deposit.position, BTreeSet::new()));
+            nodes.len() - 1
+        });
+        for valve in &deposit.valves {
+            if valve.open {
+                nodes[i].1.insert(valve.index);
+            } else {
+                nodes[i].1.remove(&valve.index);
+            }
+        }
     }
+
+    nodes
+        .into_iter()
+        .filter(|(_, open)| !open.is_empty())
+        .map(|(position, open)| {
+            open.into_iter().fold(G4DBuilder::new(position), |g4d, valve| g4d.valve(valve, false)).build()
+        })
+        .collect()
+}
+
+/// The valve layer a layer's commands deposit.
+///
+/// Every node a G4D opens a valve at is open, with the valves ever opened
+/// there, in the order first opened; each takes the channel of the last
+/// G4C before it. A G4D closing valves at a node gives it its close-early
+/// lead, the longest if several do. Positions (mm) are converted to grid
+/// coordinates by `grid_spacing`. A layer whose nodes share one channel has
+/// it as its primary material, and one with a `G4W INSPECT` is held for
+/// inspection once deposited. A G4L ramp makes it a ramp layer, its nodes
+/// kept in the order they open.
+pub fn layer_from_commands(
+    commands: &[Command],
+    layer_number: u32,
+    z_height: f32,
+    grid_spacing: f32,
+) -> Layer {
+    let mut layer = Layer::new(z_height, layer_number);
+    let mut index: HashMap<GridCoordinate, usize> = HashMap::new();
+    let mut channel = None;
+    for command in commands {
+        match command {
+            Command::G4C(select) if select.material_channel.is_some() => channel = select.material_channel,
+            Command::G4D(deposit) => {
+                let to_grid = |mm: f32| (mm / grid_spacing).round().max(0.0) as u32;
+                let position = GridCoordinate::new(to_grid(deposit.position.x), to_grid(deposit.position.y));
+                let open: Vec<u8> = deposit.valves.iter().filter(|v| v.open).map(|v| v.index).collect();
+                if open.is_empty() {
+                    if let (Some(ms), Some(&i)) = (deposit.close_early_ms, index.get(&position)) {
+                        let lead = &mut layer.nodes[i].close_early_ms;
+                        *lead = Some(lead.map_or(ms, |l| l.max(ms)));
+                    }
+                    continue;
+                }
+                let i = *index.entry(position).or_insert_with(|| {
+                    layer.nodes.push(NodeValveState {
+                        position,
+                        valves: Vec::new(),
+                        material_channel: channel,
+                        close_early_ms: None,
+                    });
+                    layer.nodes.len() - 1
+                });
+                let valves = &mut layer.nodes[i].valves;
+                for valve in open {
+                    if !valves.iter().any(|v| v.index == valve) {
+                        valves.push(ValveState::open(valve));
+                    }
+                }
+            }
+            Command::G4W(wait) if wait.wait_type == WaitType::Inspection => layer.inspect = true,
+            Command::G4L(advance) if advance.is_ramp() => layer.ramp_ms = advance.ramp_ms,
+            _ => {}
+        }
+    }
+
+    let mut channels = layer.nodes.iter().map(|n| n.material_channel);
+    if let Some(Some(first)) = channels.next() {
+        if channels.all(|c| c == Some(first)) {
+            layer.primary_material = Some(first);
+        }
+    }
+    layer
+}
+
+/// Material channels deposited anywhere in the print.
+pub fn used_channels(layers: &[Layer]) -> BTreeSet<u8> {
+    layers
+        .iter()
+        .flat_map(|layer| {
+            layer
+                .nodes
+                .iter()
+                .filter_map(|n| n.material_channel)
+                .chain(layer.primary_material)
+        })
+        .collect()
+}
+
+/// Tags a layer's nodes with the objects their regions were sliced from.
+///
+/// Nodes without an object are left untagged and cannot be cancelled.
+pub fn tag_objects(layer: &mut Layer, activation_map: &ValveActivationMap) {
+    let mut objects: BTreeMap<u32, Vec<_>> = BTreeMap::new();
+    for node in &activation_map.active_nodes {
+        if let Some(id) = node.object_id {
+            objects.entry(id).or_default().push(node.position);
+        }
+    }
+    for (id, positions) in objects {
+        layer.tag_object(id, positions);
+    }
+}
+
+/// Pairs each used channel with its material profile.
+///
+/// Profiles are indexed by channel: `material_profiles[n]` is loaded into
+/// channel `n`.
+pub fn channel_materials<'a>(
+    channels: &BTreeSet<u8>,
+    material_profiles: &'a [MaterialProfile],
+) -> Result<Vec<(u8, &'a MaterialProfile)>, SlicerError> {
+    channels
+        .iter()
+        .map(|&channel| {
+            material_profiles
+                .get(channel as usize)
+                .map(|profile| (channel, profile))
+                .ok_or_else(|| SlicerError::MaterialIncompatibility(format!(
+                    "Channel {} is used but no material profile is loaded for it",
+                    channel
+                )))
+        })
+        .collect()
 }
@@ -0,0 +1,335 @@
+//! Experimental import of conventional toolpath G-code.
+//!
+//! Converts G-code from a Marlin-flavour FFF slicer into valve layers so the
+//! same geometry can be printed, and benchmarked, both ways. The result only
+//! approximates the original:
+//!
+//! - every extruding G0/G1 move is rasterized as a bead of `bead_width`:
+//!   each grid node whose center lies within half a bead of the move's path
+//!   opens valve 0. Beads narrower than the grid still mark the nodes they
+//!   pass over
+//! - one layer per Z at which material was extruded, in ascending Z
+//! - tool `T<n>` selects material channel `n`; where tools overlap within a
+//!   layer the later move wins
+//! - extrusion amounts, feed rates, retraction and fan control are ignored;
+//!   arcs (G2/G3) are imported as straight chords
+//!
+//! Positioning follows G90/G91 and M82/M83, G92 resets axes and G28 homes
+//! to 0. G-code coordinates are taken as valve plane coordinates, shifted by
+//! `ImportOptions::offset`.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::BufRead;
+
+use anyhow::{bail, Context, Result};
+use config_types::PrinterConfig;
+use gcode_types::{GridCoordinate, Layer, NodeValveState, ValveState};
+
+/// Bead width of typical 0.4mm nozzle prints (mm).
+pub const DEFAULT_BEAD_WIDTH: f32 = 0.45;
+
+/// Layers are told apart by Z rounded to this many steps per mm (1µm).
+const Z_STEPS_PER_MM: f32 = 1000.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ImportOptions {
+    /// Width of the deposited bead (mm)
+    pub bead_width: f32,
+    /// Added to every G-code X/Y before rasterizing (mm)
+    pub offset: (f32, f32),
+}
+
+impl Default for ImportOptions {
+    fn default() -> Self {
+        Self {
+            bead_width: DEFAULT_BEAD_WIDTH,
+            offset: (0.0, 0.0),
+        }
+    }
+}
+
+/// Counts describing what the import kept and dropped.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ImportStats {
+    pub lines: usize,
+    pub extrusion_moves: usize,
+    pub travel_moves: usize,
+    /// Arcs imported as straight chords
+    pub arcs: usize,
+    /// Nodes covered by a bead but outside the valve grid
+    pub clipped_nodes: usize,
+    /// Extruded filament per channel (mm of E)
+    pub filament_mm: BTreeMap<u8, f32>,
+    /// Commands other than motion and mode changes, by word
+    pub ignored: BTreeMap<String, usize>,
+}
+
+/// Layers and statistics of an import.
+#[derive(Debug, Clone)]
+pub struct ImportedGCode {
+    pub layers: Vec<Layer>,
+    pub stats: ImportStats,
+}
+
+impl ImportedGCode {
+    /// Most common Z step between layers, as the print's layer height.
+    pub fn layer_height(&self) -> Option<f32> {
+        let mut steps: BTreeMap<i64, usize> = BTreeMap::new();
+        for pair in self.layers.windows(2) {
+            let step = ((pair[1].z_height - pair[0].z_height) * Z_STEPS_PER_MM).round() as i64;
+            *steps.entry(step).or_default() += 1;
+        }
+        steps
+            .into_iter()
+            .max_by_key(|&(step, count)| (count, std::cmp::Reverse(step)))
+            .map(|(step, _)| step as f32 / Z_STEPS_PER_MM)
+    }
+}
+
+/// Machine state while reading G-code.
+#[derive(Debug, Clone)]
+struct Machine {
+    position: [f32; 4],
+    absolute: bool,
+    absolute_e: bool,
+    channel: u8,
+}
+
+impl Default for Machine {
+    fn default() -> Self {
+        Self {
+            position: [0.0; 4],
+            absolute: true,
+            absolute_e: true,
+            channel: 0,
+        }
+    }
+}
+
+/// Rasterizes toolpath G-code onto a printer's valve grid.
+pub struct MarlinImporter {
+    grid_spacing: f32,
+    grid_x: u32,
+    grid_y: u32,
+    channel_count: u8,
+    options: ImportOptions,
+}
+
+impl MarlinImporter {
+    pub fn new(printer: &PrinterConfig, options: ImportOptions) -> Self {
+        Self {
+            grid_spacing: printer.valve_array.grid_spacing,
+            grid_x: printer.grid_x_count(),
+            grid_y: printer.grid_y_count(),
+            channel_count: printer.materials.channel_count,
+            options,
+        }
+    }
+
+    /// Reads G-code and rasterizes its extrusion moves.
+    pub fn import(&self, reader: impl BufRead) -> Result<ImportedGCode> {
+        let mut machine = Machine::default();
+        let mut stats = ImportStats::default();
+        // Z in steps -> (y, x) -> channel
+        let mut layers: BTreeMap<i64, BTreeMap<(u32, u32), u8>> = BTreeMap::new();
+
+        for (number, line) in reader.lines().enumerate() {
+            let line = line.with_context(|| format!("Failed to read line {}", number + 1))?;
+            stats.lines += 1;
+            let code = line.split(';').next().unwrap_or_default().trim();
+            let mut words = code.split_whitespace();
+            let Some(command) = words.next().map(str::to_ascii_uppercase) else {
+                continue;
+            };
+            let words: Vec<&str> = words.collect();
+            let params: Vec<(char, f32)> = words
+                .iter()
+                .filter_map(|word| {
+                    let mut chars = word.chars();
+                    let letter = chars.next()?.to_ascii_uppercase();
+                    Some((letter, chars.as_str().parse().ok()?))
+                })
+                .collect();
+            let param = |letter: char| params.iter().find(|(l, _)| *l == letter).map(|(_, v)| *v);
+
+            match command.as_str() {
+                "G0" | "G1" | "G2" | "G3" => {
+                    if command == "G2" || command == "G3" {
+                        stats.arcs += 1;
+                    }
+                    let start = machine.position;
+                    for (axis, letter) in ['X', 'Y', 'Z'].into_iter().enumerate() {
+                        if let Some(value) = param(letter) {
+                            machine.position[axis] = if machine.absolute { value } else { start[axis] + value };
+                        }
+                    }
+                    if let Some(e) = param('E') {
+                        machine.position[3] = if machine.absolute_e { e } else { start[3] + e };
+                    }
+                    let extruded = machine.position[3] - start[3];
+                    let end = machine.position;
+                    if extruded > 0.0 && (end[0] != start[0] || end[1] != start[1]) {
+                        stats.extrusion_moves += 1;
+                        *stats.filament_mm.entry(machine.channel).or_default() += extruded;
+                        let z = (end[2] * Z_STEPS_PER_MM).round() as i64;
+                        let nodes = layers.entry(z).or_default();
+                        stats.clipped_nodes += self.rasterize(nodes, (start[0], start[1]), (end[0], end[1]), machine.channel);
+                    } else {
+                        stats.travel_moves += 1;
+                    }
+                }
+                "G28" => {
+                    // "G28 X" homes X alone, so the axis letter counts without a value
+                    let named = |letter: char| words.iter().any(|w| w.to_ascii_uppercase().starts_with(letter));
+                    let all = !['X', 'Y', 'Z'].into_iter().any(named);
+                    for (axis, letter) in ['X', 'Y', 'Z'].into_iter().enumerate() {
+                        if all || named(letter) {
+                            machine.position[axis] = 0.0;
+                        }
+                    }
+                }
+                "G90" => {
+                    machine.absolute = true;
+                    machine.absolute_e = true;
+                }
+                "G91" => {
+                    machine.absolute = false;
+                    machine.absolute_e = false;
+                }
+                "M82" => machine.absolute_e = true,
+                "M83" => machine.absolute_e = false,
+                "G92" => {
+                    for (axis, letter) in ['X', 'Y', 'Z', 'E'].into_iter().enumerate() {
+                        if let Some(value) = param(letter) {
+                            machine.position[axis] = value;
+                        }
+                    }
+                }
+                tool if tool.starts_with('T') && tool.len() > 1 => {
+                    let channel: u8 = tool[1..]
+                        .parse()
+                        .with_context(|| format!("Line {}: invalid tool '{}'", number + 1, tool))?;
+                    if channel >= self.channel_count {
+                        bail!(
+                            "Line {}: tool T{} needs channel {}, printer has {} channels",
+                            number + 1,
+                            channel,
+                            channel,
+                            self.channel_count
+                        );
+                    }
+                    machine.channel = channel;
+                }
+                other => *stats.ignored.entry(other.to_string()).or_default() += 1,
+            }
+        }
+
+        let layers = layers
+            .into_iter()
+            .filter(|(_, nodes)| !nodes.is_empty())
+            .enumerate()
+            .map(|(n, (z, nodes))| {
+                let mut layer = Layer::new(z as f32 / Z_STEPS_PER_MM, n as u32);
+                let channels: BTreeSet<u8> = nodes.values().copied().collect();
+                if channels.len() == 1 {
+                    layer.primary_material = channels.first().copied();
+                }
+                for ((y, x), channel) in nodes {
+                    layer.add_node(
+                        NodeValveState::new(GridCoordinate::new(x, y), vec![ValveState::open(0)]).with_material(channel),
+                    );
+                }
+                layer
+            })
+            .collect();
+        Ok(ImportedGCode { layers, stats })
+    }
+
+    /// Marks the nodes under a bead from `a` to `b`; returns how many fell
+    /// outside the grid.
+    fn rasterize(&self, nodes: &mut BTreeMap<(u32, u32), u8>, a: (f32, f32), b: (f32, f32), channel: u8) -> usize {
+        let spacing = self.grid_spacing;
+        let (ox, oy) = self.options.offset;
+        let (ax, ay, bx, by) = (a.0 + ox, a.1 + oy, b.0 + ox, b.1 + oy);
+        let reach = (self.options.bead_width / 2.0).max(spacing / 2.0);
+
+        let (dx, dy) = (bx - ax, by - ay);
+        let length_sq = dx * dx + dy * dy;
+        let node_range = |from: f32, to: f32| {
+            let low = ((from.min(to) - reach) / spacing).ceil() as i64;
+            let high = ((from.max(to) + reach) / spacing).floor() as i64;
+            low..=high
+        };
+
+        let mut clipped = 0;
+        for j in node_range(ay, by) {
+            for i in node_range(ax, bx) {
+                let (px, py) = (i as f32 * spacing, j as f32 * spacing);
+                let t = if length_sq > 0.0 {
+                    (((px - ax) * dx + (py - ay) * dy) / length_sq).clamp(0.0, 1.0)
+                } else {
+                    0.0
+                };
+                let (cx, cy) = (ax + t * dx - px, ay + t * dy - py);
+                if cx * cx + cy * cy > reach * reach {
+                    continue;
+                }
+                if i < 0 || j < 0 || i >= self.grid_x as i64 || j >= self.grid_y as i64 {
+                    clipped += 1;
+                    continue;
+                }
+                nodes.insert((j as u32, i as u32), channel);
+            }
+        }
+        clipped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ExampleConfigs;
+    use config_types::PrinterModel;
+
+    #[test]
+    fn test_import_rasterizes_extrusion_moves() {
+        let printer = ExampleConfigs::for_model(PrinterModel::HyperCubeStandard).unwrap().printer;
+        let importer = MarlinImporter::new(&printer, ImportOptions { bead_width: 0.4, offset: (0.0, 0.0) });
+        let gcode = "\
+            ; generated by some slicer\n\
+            G28\n\
+            M83\n\
+            G1 Z0.2 F600\n\
+            G0 X10 Y10\n\
+            G1 X12 Y10 E0.1 ; 2mm bead along X\n\
+            G1 E-0.8 ; retract\n\
+            T1\n\
+            G1 Z0.4\n\
+            G91\n\
+            G1 X0 Y2 E0.1\n\
+            M106 S255\n\
+            G90\n\
+            G1 X-5 Y-5 E1\n";
+        let imported = importer.import(gcode.as_bytes()).unwrap();
+        let stats = &imported.stats;
+
+        assert_eq!(stats.extrusion_moves, 3);
+        assert_eq!(stats.ignored.get("M106"), Some(&1));
+        assert!((stats.filament_mm[&0] - 0.1).abs() < 1e-6);
+        assert_eq!(imported.layers.len(), 2);
+        assert_eq!(imported.layer_height(), Some(0.2));
+
+        // 0.5mm grid: X 20..=24 on row 20
+        let first = &imported.layers[0];
+        assert_eq!(first.primary_material, Some(0));
+        let xs: Vec<u32> = first.nodes.iter().map(|n| n.position.x).collect();
+        assert_eq!(xs, vec![20, 21, 22, 23, 24]);
+        assert!(first.nodes.iter().all(|n| n.position.y == 20));
+
+        // Relative move up Y on channel 1, then a bead leaving the grid
+        let second = &imported.layers[1];
+        assert!(second.nodes.iter().all(|n| n.material_channel == Some(1)));
+        assert!(second.nodes.iter().any(|n| n.position == GridCoordinate::new(24, 24)));
+        assert!(stats.clipped_nodes > 0);
+    }
+}
@@ -0,0 +1,252 @@
+//! Inspection of .hg4d files before dispatch.
+//!
+//! [`FileReport::inspect`] reads the header and layer index and decodes
+//! every layer block. A block that fails its checksum or does not decode is
+//! recorded against its layer rather than aborting, so one report lists
+//! every damaged layer. Totals cover the layers that decoded:
+//!
+//! - **Estimated time**: the sum of the slicer's per-layer estimates
+//! - **Material**: open nodes per channel; with the printer configuration,
+//!   also volume (grid cell × layer height) and mass from the embedded
+//!   material densities
+//!
+//! The index itself is checked for layers out of order, Z going backwards
+//! and overlapping blocks.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::Result;
+
+use config_types::PrinterConfig;
+
+use super::writer::{FileHeader, HG4DReader};
+use crate::hash_printer_config;
+
+/// One entry of the layer index with what decoding it found.
+#[derive(Debug, Clone)]
+pub struct LayerSummary {
+    pub layer_number: u32,
+    pub z_height: f32,
+    /// Stored (compressed) block size
+    pub stored_bytes: u32,
+    /// Nodes with at least one open valve; None if the block is damaged
+    pub active_nodes: Option<usize>,
+    /// Expected duration including any cooling dwell (s)
+    pub estimated_time: Option<f32>,
+    /// Checksum or decoding failure
+    pub error: Option<String>,
+}
+
+/// Everything `hg4d-slicer info` prints about a file.
+#[derive(Debug, Clone)]
+pub struct FileReport {
+    pub path: PathBuf,
+    pub file_bytes: u64,
+    pub format_version: u32,
+    pub header: FileHeader,
+    pub layers: Vec<LayerSummary>,
+    /// Sum of per-layer estimates; None if the file carries none
+    pub estimated_time: Option<Duration>,
+    /// Open node-layers per material channel
+    pub node_deposits: BTreeMap<u8, u64>,
+    /// Deposited volume per channel (mm³), when a printer config was given
+    pub material_mm3: Option<BTreeMap<u8, f64>>,
+    /// Whether the file was sliced for the given printer configuration
+    pub config_matches: Option<bool>,
+    /// Inconsistencies in the layer index
+    pub index_problems: Vec<String>,
+}
+
+impl FileReport {
+    /// Reads and checks a file. `printer` enables volume totals and the
+    /// configuration hash comparison.
+    pub fn inspect<P: AsRef<Path>>(path: P, printer: Option<&PrinterConfig>) -> Result<Self> {
+        let path = path.as_ref();
+        let file_bytes = std::fs::metadata(path)?.len();
+        let mut reader = HG4DReader::open(path)?;
+        let index = reader.index().to_vec();
+        let cell_area = printer.map(|p| {
+            let spacing = p.valve_array.grid_spacing as f64;
+            spacing * spacing
+        });
+
+        let mut layers = Vec::with_capacity(index.len());
+        let mut estimated: Option<f64> = None;
+        let mut node_deposits: BTreeMap<u8, u64> = BTreeMap::new();
+        let mut material_mm3: BTreeMap<u8, f64> = BTreeMap::new();
+        let mut previous_z = 0.0f32;
+        for (n, entry) in index.iter().enumerate() {
+            let mut summary = LayerSummary {
+                layer_number: entry.layer_number,
+                z_height: entry.z_height,
+                stored_bytes: entry.data_size,
+                active_nodes: None,
+                estimated_time: None,
+                error: None,
+            };
+            let height = (entry.z_height - previous_z).max(0.0) as f64;
+            previous_z = entry.z_height;
+
+            match reader.read_layer(n) {
+                Ok(layer) => {
+                    let mut active = 0;
+                    for node in layer.nodes.iter().filter(|node| node.open_count() > 0) {
+                        active += 1;
+                        let channel = node.material_channel.or(layer.primary_material).unwrap_or(0);
+                        *node_deposits.entry(channel).or_default() += 1;
+                        if let Some(area) = cell_area {
+                            *material_mm3.entry(channel).or_default() += area * height;
+                        }
+                    }
+                    summary.active_nodes = Some(active);
+                    summary.estimated_time = layer.expected_duration();
+                    if let Some(time) = summary.estimated_time {
+                        *estimated.get_or_insert(0.0) += time as f64;
+                    }
+                }
+                Err(e) => summary.error = Some(format!("{:#}", e)),
+            }
+            layers.push(summary);
+        }
+
+        let config_matches = printer.map(|p| {
+            let hash: String = hash_printer_config(p).iter().map(|b| format!("{:02x}", b)).collect();
+            hash == reader.header().printer_config_hash
+        });
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            file_bytes,
+            format_version: reader.format_version(),
+            header: reader.header().clone(),
+            index_problems: index_problems(&layers, &index),
+            layers,
+            estimated_time: estimated.map(Duration::from_secs_f64),
+            node_deposits,
+            material_mm3: cell_area.map(|_| material_mm3),
+            config_matches,
+        })
+    }
+
+    /// Layers whose blocks failed their checksum or did not decode.
+    pub fn damaged_layers(&self) -> impl Iterator<Item = &LayerSummary> {
+        self.layers.iter().filter(|l| l.error.is_some())
+    }
+
+    /// True if every block is intact and the index is consistent.
+    pub fn is_intact(&self) -> bool {
+        self.damaged_layers().next().is_none() && self.index_problems.is_empty()
+    }
+
+    /// Mass per channel (g) from the volumes and the densities of the
+    /// materials embedded in the file.
+    pub fn material_grams(&self) -> BTreeMap<u8, f64> {
+        let Some(volumes) = &self.material_mm3 else {
+            return BTreeMap::new();
+        };
+        volumes
+            .iter()
+            .filter_map(|(&channel, &mm3)| {
+                let density = self.header.materials.get(channel as usize)?.properties.density as f64;
+                // g/cm³ × mm³ / 1000
+                Some((channel, mm3 * density / 1000.0))
+            })
+            .collect()
+    }
+}
+
+fn index_problems(layers: &[LayerSummary], index: &[gcode_types::LayerIndexEntry]) -> Vec<String> {
+    let mut problems = Vec::new();
+    for (previous, layer) in layers.iter().zip(layers.iter().skip(1)) {
+        if layer.layer_number != previous.layer_number + 1 {
+            problems.push(format!(
+                "Layer {} follows layer {}",
+                layer.layer_number, previous.layer_number
+            ));
+        }
+        if layer.z_height < previous.z_height {
+            problems.push(format!(
+                "Layer {} Z {:.3}mm is below layer {} at {:.3}mm",
+                layer.layer_number, layer.z_height, previous.layer_number, previous.z_height
+            ));
+        }
+    }
+    let mut blocks: Vec<_> = index.iter().collect();
+    blocks.sort_by_key(|e| e.file_offset);
+    for (a, b) in blocks.iter().zip(blocks.iter().skip(1)) {
+        if a.file_offset + a.data_size as u64 > b.file_offset {
+            problems.push(format!(
+                "Blocks of layers {} and {} overlap",
+                a.layer_number, b.layer_number
+            ));
+        }
+    }
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ExampleConfigs;
+    use crate::gcode::HG4DWriter;
+    use crate::SliceMetadata;
+    use config_types::{PrinterModel, ResolvedSettings};
+    use gcode_types::{GridCoordinate, Layer, NodeValveState, ValveState};
+    use std::io::{Seek, SeekFrom, Write};
+
+    #[test]
+    fn test_report_totals_and_damage() {
+        let configs = ExampleConfigs::for_model(PrinterModel::HyperCubeMini).unwrap();
+        let path = std::env::temp_dir().join(format!("hg4d-inspect-{}.hg4d", std::process::id()));
+        let metadata = SliceMetadata {
+            printer_config_hash: hash_printer_config(&configs.printer),
+            material_profiles: configs.materials.iter().map(|(_, p)| p.clone()).collect(),
+            print_settings: ResolvedSettings { settings: configs.settings.clone(), applied: vec![] },
+            model_name: "cube".to_string(),
+            slicer_version: "test".to_string(),
+            objects: vec![],
+            material_usage: vec![],
+        };
+        let mut writer = HG4DWriter::create(&path, metadata).unwrap();
+        writer.write_header().unwrap();
+        for n in 0..3 {
+            let mut layer = Layer::new(0.2 * (n + 1) as f32, n);
+            for x in 0..4 {
+                layer.add_node(NodeValveState::new(GridCoordinate::new(x, 0), vec![ValveState::open(0)]));
+            }
+            layer.estimated_time = Some(1.5);
+            writer.write_layer(&layer).unwrap();
+        }
+        writer.finalize().unwrap();
+
+        let report = FileReport::inspect(&path, Some(&configs.printer)).unwrap();
+        assert!(report.is_intact());
+        assert_eq!(report.config_matches, Some(true));
+        assert_eq!(report.header.model_name, "cube");
+        assert_eq!(report.estimated_time, Some(Duration::from_secs_f64(4.5)));
+        assert_eq!(report.node_deposits[&0], 12);
+        let spacing = configs.printer.valve_array.grid_spacing as f64;
+        let expected = 12.0 * spacing * spacing * 0.2;
+        assert!((report.material_mm3.as_ref().unwrap()[&0] - expected).abs() < 1e-4);
+
+        // Corrupt the middle layer's block
+        let offset = report.layers[1].stored_bytes as u64 / 2 + {
+            let reader = HG4DReader::open(&path).unwrap();
+            reader.index()[1].file_offset
+        };
+        let mut file = std::fs::OpenOptions::new().write(true).open(&path).unwrap();
+        file.seek(SeekFrom::Start(offset)).unwrap();
+        file.write_all(&[0xff, 0x00, 0xff]).unwrap();
+        drop(file);
+
+        let report = FileReport::inspect(&path, None).unwrap();
+        assert!(!report.is_intact());
+        let damaged: Vec<u32> = report.damaged_layers().map(|l| l.layer_number).collect();
+        assert_eq!(damaged, vec![1]);
+        assert_eq!(report.node_deposits[&0], 8);
+        assert!(report.material_mm3.is_none());
+        std::fs::remove_file(&path).ok();
+    }
+}
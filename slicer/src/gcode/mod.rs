@@ -8,14 +8,32 @@
 //! - **generator**: Converts layer data to HyperGCode-4D commands
 //! - **commands**: Command builder utilities
 //! - **validator**: Validates generated G-code
-//! - **writer**: Writes .hg4d binary format
+//! - **writer**: Writes and reads .hg4d binary format
+//! - **postprocess**: User hooks transforming commands before writing
+//! - **dead_volume**: Early valve closes and suck-back pulses against oozing
+//! - **pauses**: Operator inspection holds after selected layers
+//! - **inspect**: Summaries and integrity checks of written .hg4d files
+//! - **import**: Experimental conversion of toolpath G-code to valve layers
+//! - **report**: JSON sidecar report for CI and print farms
 
 pub mod generator;
 pub mod commands;
 pub mod validator;
 pub mod writer;
+pub mod postprocess;
+pub mod dead_volume;
+pub mod pauses;
+pub mod inspect;
+pub mod import;
+pub mod report;
 
 pub use generator::StandardGCodeGenerator;
 pub use commands::CommandBuilder;
 pub use validator::GCodeValidator;
-pub use writer::HG4DWriter;
+pub use writer::{FileHeader, HG4DReader, HG4DWriter};
+pub use inspect::FileReport;
+pub use import::{ImportOptions, MarlinImporter};
+pub use report::{LayerStats, SliceReport};
+pub use dead_volume::{DeadVolumeCompensator, DeadVolumeReport};
+pub use pauses::InspectionPauses;
+pub use postprocess::{CommandPostProcessor, ExternalPostProcessor, FnPostProcessor, LayerContext};
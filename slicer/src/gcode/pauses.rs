@@ -0,0 +1,124 @@
+//! Inspection pauses.
+//!
+//! [`InspectionPauses`] is a post-processor that ends selected layers with
+//! `G4W INSPECT`, on which the firmware holds at temperature, notifies the
+//! control interface (and a camera, if configured) and waits for the
+//! operator to resume. Pauses are given as layer numbers or Z heights in
+//! [`InspectionSettings`]; a height pauses after the first layer whose top
+//! reaches it. Heights assume the print's nominal layer height above the
+//! first layer.
+
+use std::collections::BTreeSet;
+
+use anyhow::Result;
+use tracing::debug;
+
+use config_types::{InspectionSettings, PrintSettings};
+use gcode_types::{Command, G4WCommand, WaitType};
+
+use super::postprocess::{CommandPostProcessor, LayerContext};
+
+/// Slack when comparing a pause height with a layer top (mm).
+const HEIGHT_EPSILON: f32 = 1e-4;
+
+/// Appends inspection holds to the selected layers.
+#[derive(Debug, Clone)]
+pub struct InspectionPauses {
+    layers: BTreeSet<u32>,
+    heights: Vec<f32>,
+    first_layer_height: f32,
+    layer_height: f32,
+}
+
+impl InspectionPauses {
+    pub fn new(settings: &InspectionSettings, first_layer_height: f32, layer_height: f32) -> Self {
+        Self {
+            layers: settings.layers.iter().copied().collect(),
+            heights: settings.heights.iter().copied().filter(|h| *h > 0.0).collect(),
+            first_layer_height,
+            layer_height,
+        }
+    }
+
+    /// Pauses from the print settings.
+    pub fn from_settings(settings: &PrintSettings) -> Self {
+        Self::new(&settings.inspection, settings.first_layer_height, settings.layer_height)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.layers.is_empty() && self.heights.is_empty()
+    }
+
+    /// Whether the print holds once this layer is deposited.
+    pub fn pauses_after(&self, context: &LayerContext) -> bool {
+        if self.layers.contains(&context.layer_number) {
+            return true;
+        }
+        let top = context.z_height;
+        let bottom = if context.layer_number == 0 {
+            0.0
+        } else {
+            (top - self.layer_height).max(self.first_layer_height.min(top))
+        };
+        self.heights
+            .iter()
+            .any(|h| *h > bottom + HEIGHT_EPSILON && *h <= top + HEIGHT_EPSILON)
+    }
+}
+
+impl CommandPostProcessor for InspectionPauses {
+    fn name(&self) -> &str {
+        "inspection-pauses"
+    }
+
+    fn process_layer(&self, context: &LayerContext, commands: &mut Vec<Command>) -> Result<()> {
+        if !self.pauses_after(context) {
+            return Ok(());
+        }
+        debug!(
+            "Inspection pause after layer {} (Z {:.2}mm)",
+            context.layer_number, context.z_height
+        );
+        commands.push(Command::Comment(format!(
+            "Inspection pause after layer {}",
+            context.layer_number
+        )));
+        commands.push(Command::G4W(G4WCommand {
+            wait_type: WaitType::Inspection,
+            timeout_ms: None,
+        }));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pauses_by_layer_and_height() {
+        let settings = InspectionSettings {
+            layers: vec![3],
+            heights: vec![1.0, 0.1],
+        };
+        let pauses = InspectionPauses::new(&settings, 0.3, 0.2);
+        let context = |layer_number: u32| LayerContext {
+            layer_number,
+            z_height: 0.3 + 0.2 * layer_number as f32,
+            total_layers: 20,
+        };
+
+        let paused: Vec<u32> = (0..20).filter(|n| pauses.pauses_after(&context(*n))).collect();
+        // 0.1mm falls in the first layer, 1.0mm in layer 4 (0.9 to 1.1mm)
+        assert_eq!(paused, vec![0, 3, 4]);
+
+        let mut commands = vec![Command::Comment("layer".to_string())];
+        pauses.process_layer(&context(3), &mut commands).unwrap();
+        assert_eq!(commands.len(), 3);
+        assert_eq!(commands[2].to_gcode_text(), "G4W INSPECT");
+
+        let mut commands = Vec::new();
+        pauses.process_layer(&context(5), &mut commands).unwrap();
+        assert!(commands.is_empty());
+    }
+}
@@ -0,0 +1,275 @@
+//! Command stream post-processing hooks.
+//!
+//! After G-code generation and before writing, each layer's commands pass
+//! through the slicer's post-processors in registration order. A processor
+//! may insert, remove or rewrite commands; the next processor sees the
+//! result.
+//!
+//! Besides in-process implementations of [`CommandPostProcessor`], external
+//! processors can be loaded from the command line:
+//!
+//! - **Scripts**: any executable (Python, shell, ...)
+//! - **WASM**: a WASI module, run under a WASI runtime (`wasmtime` by default)
+//!
+//! External processors are started once per slice and exchange one JSON
+//! line per layer over stdin/stdout. The slicer writes a [`LayerRequest`]
+//! and reads back the layer's commands as a JSON array (same serialization
+//! as [`Command`]). Anything the processor prints to stderr is passed
+//! through to the user.
+
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, ChildStdout, Command as Process, Stdio};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+
+use gcode_types::Command;
+
+/// Default runtime used to execute WASM processors.
+pub const DEFAULT_WASM_RUNTIME: &str = "wasmtime";
+
+/// Time an external processor gets to exit after its input closes.
+const EXIT_GRACE_PERIOD: Duration = Duration::from_secs(2);
+
+/// Position of the layer being processed.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct LayerContext {
+    pub layer_number: u32,
+    /// Layer Z height (mm)
+    pub z_height: f32,
+    pub total_layers: u32,
+}
+
+/// Transforms the command list of each layer before it is written.
+pub trait CommandPostProcessor: Send + Sync {
+    /// Name used in logs and error messages.
+    fn name(&self) -> &str;
+
+    /// Mutates one layer's commands in place.
+    fn process_layer(&self, context: &LayerContext, commands: &mut Vec<Command>) -> Result<()>;
+}
+
+/// Adapts a closure into a post-processor.
+pub struct FnPostProcessor<F> {
+    name: String,
+    func: F,
+}
+
+impl<F> FnPostProcessor<F>
+where
+    F: Fn(&LayerContext, &mut Vec<Command>) -> Result<()> + Send + Sync,
+{
+    pub fn new(name: impl Into<String>, func: F) -> Self {
+        Self {
+            name: name.into(),
+            func,
+        }
+    }
+}
+
+impl<F> CommandPostProcessor for FnPostProcessor<F>
+where
+    F: Fn(&LayerContext, &mut Vec<Command>) -> Result<()> + Send + Sync,
+{
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn process_layer(&self, context: &LayerContext, commands: &mut Vec<Command>) -> Result<()> {
+        (self.func)(context, commands)
+    }
+}
+
+/// One line sent to an external processor.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LayerRequest {
+    #[serde(flatten)]
+    pub context: LayerContext,
+    pub commands: Vec<Command>,
+}
+
+struct ProcessPipes {
+    child: Child,
+    /// Taken on drop to signal end of input
+    stdin: Option<ChildStdin>,
+    stdout: BufReader<ChildStdout>,
+}
+
+/// Post-processor running as a child process (script or WASI module).
+pub struct ExternalPostProcessor {
+    name: String,
+    pipes: Mutex<ProcessPipes>,
+}
+
+impl ExternalPostProcessor {
+    /// Starts `program` with `args`.
+    pub fn spawn(name: impl Into<String>, program: &Path, args: &[String]) -> Result<Self> {
+        let name = name.into();
+        let mut child = Process::new(program)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .spawn()
+            .with_context(|| format!("Failed to start post-processor '{}'", name))?;
+
+        let stdin = child.stdin.take().ok_or_else(|| anyhow!("No stdin for '{}'", name))?;
+        let stdout = child.stdout.take().ok_or_else(|| anyhow!("No stdout for '{}'", name))?;
+        debug!("Started post-processor '{}' (pid {})", name, child.id());
+
+        Ok(Self {
+            name,
+            pipes: Mutex::new(ProcessPipes {
+                child,
+                stdin: Some(stdin),
+                stdout: BufReader::new(stdout),
+            }),
+        })
+    }
+
+    /// Loads a processor from a path: `.wasm` files run under
+    /// `wasm_runtime`, anything else is executed directly.
+    pub fn load(path: &Path, wasm_runtime: &str) -> Result<Self> {
+        if !path.exists() {
+            bail!("Post-processor {} not found", path.display());
+        }
+        let name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_else(|| path.display().to_string());
+
+        if path.extension().is_some_and(|e| e.eq_ignore_ascii_case("wasm")) {
+            let args = vec!["run".to_string(), path.display().to_string()];
+            Self::spawn(name, &PathBuf::from(wasm_runtime), &args)
+        } else {
+            Self::spawn(name, path, &[])
+        }
+    }
+}
+
+impl CommandPostProcessor for ExternalPostProcessor {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn process_layer(&self, context: &LayerContext, commands: &mut Vec<Command>) -> Result<()> {
+        let mut pipes = self
+            .pipes
+            .lock()
+            .map_err(|_| anyhow!("Post-processor '{}' poisoned", self.name))?;
+
+        let request = LayerRequest {
+            context: *context,
+            commands: std::mem::take(commands),
+        };
+        let mut line = serde_json::to_string(&request)?;
+        line.push('\n');
+        let stdin = pipes
+            .stdin
+            .as_mut()
+            .ok_or_else(|| anyhow!("Post-processor '{}' already closed", self.name))?;
+        stdin
+            .write_all(line.as_bytes())
+            .and_then(|_| stdin.flush())
+            .with_context(|| format!("Post-processor '{}' stopped accepting input", self.name))?;
+
+        let mut reply = String::new();
+        if pipes.stdout.read_line(&mut reply)? == 0 {
+            bail!("Post-processor '{}' exited before answering layer {}", self.name, context.layer_number);
+        }
+        *commands = serde_json::from_str(&reply).with_context(|| {
+            format!("Post-processor '{}' returned invalid commands for layer {}", self.name, context.layer_number)
+        })?;
+        Ok(())
+    }
+}
+
+impl Drop for ExternalPostProcessor {
+    fn drop(&mut self) {
+        if let Ok(pipes) = self.pipes.get_mut() {
+            // Closing stdin is the end-of-stream signal; kill if it lingers
+            pipes.stdin.take();
+            let deadline = Instant::now() + EXIT_GRACE_PERIOD;
+            while pipes.child.try_wait().ok().flatten().is_none() {
+                if Instant::now() >= deadline {
+                    if let Err(e) = pipes.child.kill() {
+                        warn!("Failed to stop post-processor '{}': {}", self.name, e);
+                    }
+                    break;
+                }
+                std::thread::sleep(Duration::from_millis(10));
+            }
+            pipes.child.wait().ok();
+        }
+    }
+}
+
+/// Runs `processors` over one layer in order.
+pub fn run_post_processors(
+    processors: &[Box<dyn CommandPostProcessor>],
+    context: &LayerContext,
+    commands: &mut Vec<Command>,
+) -> Result<()> {
+    for processor in processors {
+        processor
+            .process_layer(context, commands)
+            .with_context(|| format!("Post-processor '{}' failed on layer {}", processor.name(), context.layer_number))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gcode_types::{G4WCommand, WaitType};
+
+    #[test]
+    fn test_processors_run_in_order() {
+        let every_second: Box<dyn CommandPostProcessor> =
+            Box::new(FnPostProcessor::new("pause", |ctx: &LayerContext, cmds: &mut Vec<Command>| {
+                if ctx.layer_number % 2 == 1 {
+                    cmds.push(Command::G4W(G4WCommand {
+                        wait_type: WaitType::Duration(5000),
+                        timeout_ms: None,
+                    }));
+                }
+                Ok(())
+            }));
+        let annotate: Box<dyn CommandPostProcessor> =
+            Box::new(FnPostProcessor::new("annotate", |ctx: &LayerContext, cmds: &mut Vec<Command>| {
+                cmds.insert(0, Command::Comment(format!("layer {} ({} cmds)", ctx.layer_number, cmds.len())));
+                Ok(())
+            }));
+        let processors = vec![every_second, annotate];
+
+        let context = LayerContext { layer_number: 3, z_height: 0.8, total_layers: 10 };
+        let mut commands = vec![Command::Comment("body".to_string())];
+        run_post_processors(&processors, &context, &mut commands).unwrap();
+
+        assert_eq!(commands.len(), 3);
+        assert_eq!(commands[0], Command::Comment("layer 3 (2 cmds)".to_string()));
+        assert!(matches!(commands[2], Command::G4W(_)));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_external_processor_round_trip() {
+        // Replies with the request's command array unchanged
+        let script = "while read -r line; do echo \"$line\" | sed -e 's/.*\"commands\"://' -e 's/}$//'; done";
+        let processor = ExternalPostProcessor::spawn(
+            "echo",
+            Path::new("/bin/sh"),
+            &["-c".to_string(), script.to_string()],
+        )
+        .unwrap();
+
+        let context = LayerContext { layer_number: 0, z_height: 0.2, total_layers: 1 };
+        let mut commands = vec![Command::Comment("keep".to_string())];
+        processor.process_layer(&context, &mut commands).unwrap();
+        assert_eq!(commands, vec![Command::Comment("keep".to_string())]);
+    }
+}
@@ -0,0 +1,280 @@
+//! JSON slice report written next to the .hg4d file.
+//!
+//! CI pipelines and print farm software gate jobs on what the slicer found
+//! (estimated time, material, warnings) without parsing the binary file.
+//! With `SlicerConfig::write_report` (`--report`), slicing writes a
+//! [`SliceReport`] to [`SliceReport::path_for`] the output, e.g.
+//! `part.hg4d` → `part.report.json`. It holds:
+//!
+//! - the [`SliceResult`](crate::SliceResult)
+//! - [`LayerStats`] for every layer: active nodes, valve switches, pressure
+//!   waits, deposited volume and estimated time
+//! - every warning, with the layer it concerns where there is one, layers
+//!   in order after the warnings about the whole job
+//! - SHA-256 hashes of the printer configuration (as in the .hg4d header),
+//!   the resolved print settings and each material profile, so a farm can
+//!   check a file was sliced for the printer and profiles it has loaded
+//!
+//! The report carries [`REPORT_FORMAT_VERSION`]; fields are only added
+//! within a version.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use gcode_types::Command;
+use protocol::LayerTimingReport;
+
+use crate::{ProcessedLayer, SliceMetadata, SliceResult};
+
+/// Version of the report layout.
+pub const REPORT_FORMAT_VERSION: u32 = 1;
+
+/// Statistics of one written layer.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LayerStats {
+    pub layer_number: u32,
+    pub z_height: f32,
+    /// Nodes with at least one open valve
+    pub active_nodes: usize,
+    /// G4D valve switching commands
+    pub valve_switches: u32,
+    /// G4W pressure stabilization waits
+    pub pressure_waits: u32,
+    /// Material deposited (mm³)
+    pub deposited_volume_mm3: f32,
+    /// Expected duration (s)
+    pub estimated_time_s: f32,
+    /// Staggered opening groups; None opens every valve at once
+    pub activation_groups: Option<u8>,
+}
+
+impl LayerStats {
+    /// Statistics of a layer from its final command list.
+    pub fn new(
+        layer_number: u32,
+        z_height: f32,
+        active_nodes: usize,
+        commands: &[Command],
+        estimated_time: Duration,
+    ) -> Self {
+        // Counted the way the firmware reports a layer's workload
+        let workload = LayerTimingReport::from_commands(layer_number, z_height, commands, Duration::ZERO);
+        Self {
+            layer_number,
+            z_height,
+            active_nodes,
+            valve_switches: workload.valve_switches,
+            pressure_waits: workload.pressure_waits,
+            deposited_volume_mm3: workload.deposited_volume_mm3,
+            estimated_time_s: estimated_time.as_secs_f32(),
+            activation_groups: None,
+        }
+    }
+
+    /// Statistics of a processed layer and the commands written for it.
+    pub fn from_layer(layer: &ProcessedLayer, commands: &[Command]) -> Self {
+        Self {
+            activation_groups: layer.activation_groups.map(|g| g.count),
+            ..Self::new(
+                layer.layer_number,
+                layer.z_height,
+                layer.routing.activation_map.active_nodes.len(),
+                commands,
+                layer.timing.total_time,
+            )
+        }
+    }
+}
+
+/// A warning, against the layer it concerns if any.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReportWarning {
+    pub layer: Option<u32>,
+    pub message: String,
+}
+
+/// Hex SHA-256 hashes of the inputs a file was sliced with.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConfigHashes {
+    /// Same as the .hg4d header's `printer_config_hash`
+    pub printer_config: String,
+    pub print_settings: String,
+    /// (profile name, hash) per material profile, in channel order
+    pub materials: Vec<(String, String)>,
+}
+
+/// Sidecar report of one slicing run; see the module documentation.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SliceReport {
+    pub format_version: u32,
+    pub slicer_version: String,
+    pub model_name: String,
+    pub result: SliceResult,
+    pub hashes: ConfigHashes,
+    pub layers: Vec<LayerStats>,
+    pub warnings: Vec<ReportWarning>,
+}
+
+impl SliceReport {
+    /// Report of a finished run. `layers` are the written layers' stats and
+    /// `layer_warnings` each layer's warnings; `result.warnings` are taken
+    /// as concerning the whole job.
+    pub fn new(
+        result: &SliceResult,
+        metadata: &SliceMetadata,
+        mut layers: Vec<LayerStats>,
+        layer_warnings: impl IntoIterator<Item = (u32, String)>,
+    ) -> Self {
+        layers.sort_by_key(|l| l.layer_number);
+        let mut warnings: Vec<ReportWarning> = result
+            .warnings
+            .iter()
+            .map(|message| ReportWarning { layer: None, message: message.clone() })
+            .collect();
+        let mut per_layer: Vec<ReportWarning> = layer_warnings
+            .into_iter()
+            .map(|(layer, message)| ReportWarning { layer: Some(layer), message })
+            .collect();
+        // Stable, so a layer's warnings keep the order they were raised in
+        per_layer.sort_by_key(|w| w.layer);
+        warnings.extend(per_layer);
+
+        Self {
+            format_version: REPORT_FORMAT_VERSION,
+            slicer_version: metadata.slicer_version.clone(),
+            model_name: metadata.model_name.clone(),
+            result: result.clone(),
+            hashes: ConfigHashes {
+                printer_config: hex(&metadata.printer_config_hash),
+                print_settings: hash_json(&metadata.print_settings.settings),
+                materials: metadata
+                    .material_profiles
+                    .iter()
+                    .map(|profile| (profile.name.clone(), hash_json(profile)))
+                    .collect(),
+            },
+            layers,
+            warnings,
+        }
+    }
+
+    /// Where the report for an output file goes: its name with
+    /// `.report.json` in place of the extension.
+    pub fn path_for(output: &Path) -> PathBuf {
+        output.with_extension("report.json")
+    }
+
+    /// Warnings about one layer.
+    pub fn layer_warnings(&self, layer: u32) -> impl Iterator<Item = &ReportWarning> {
+        self.warnings.iter().filter(move |w| w.layer == Some(layer))
+    }
+
+    /// The longest layer, by estimated time.
+    pub fn slowest_layer(&self) -> Option<&LayerStats> {
+        self.layers
+            .iter()
+            .max_by(|a, b| a.estimated_time_s.total_cmp(&b.estimated_time_s))
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let json = serde_json::to_vec_pretty(self).context("Failed to serialize slice report")?;
+        fs::write(path, json).with_context(|| format!("Failed to write slice report {}", path.display()))
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let json = fs::read(path).with_context(|| format!("Failed to read slice report {}", path.display()))?;
+        serde_json::from_slice(&json).with_context(|| format!("Invalid slice report {}", path.display()))
+    }
+}
+
+fn hash_json<T: Serialize>(value: &T) -> String {
+    let serialized = serde_json::to_vec(value).expect("Config serialization should not fail");
+    hex(&Sha256::digest(&serialized))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ExampleConfigs;
+    use crate::gcode::commands::{CommandBuilder, G4DBuilder};
+    use crate::hash_printer_config;
+    use config_types::{PrinterModel, ResolvedSettings};
+    use gcode_types::Coordinate;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_report_round_trip() {
+        let configs = ExampleConfigs::for_model(PrinterModel::HyperCubeMini).unwrap();
+        let metadata = SliceMetadata {
+            printer_config_hash: hash_printer_config(&configs.printer),
+            material_profiles: configs.materials.iter().map(|(_, m)| m.clone()).collect(),
+            print_settings: ResolvedSettings { settings: configs.settings.clone(), applied: Vec::new() },
+            model_name: "bracket.stl".to_string(),
+            slicer_version: crate::SLICER_VERSION.to_string(),
+            objects: Vec::new(),
+            material_usage: vec![12.5],
+        };
+        let output = std::env::temp_dir().join(format!("hg4d-report-{}.hg4d", std::process::id()));
+        let result = SliceResult {
+            layer_count: 2,
+            estimated_time: Duration::from_secs(30),
+            material_usage: HashMap::from([(0, 12.5)]),
+            elapsed_time: Duration::from_millis(800),
+            warnings: vec!["Model rests on 2 islands".to_string()],
+            output_path: output.clone(),
+            bounding_box: (0.0, 0.0, 0.0, 20.0, 20.0, 0.4),
+        };
+
+        let deposit = |x| {
+            G4DBuilder::new(Coordinate::new(x, 1.0, 0.2)).valve(0, true).extrusion(0.05).build()
+        };
+        let commands = vec![
+            CommandBuilder::layer_advance(0.2),
+            deposit(1.0),
+            deposit(2.0),
+            CommandBuilder::wait_pressure(),
+        ];
+        let layers = vec![
+            LayerStats::new(1, 0.4, 2, &commands, Duration::from_secs(20)),
+            LayerStats::new(0, 0.2, 2, &commands, Duration::from_secs(10)),
+        ];
+        let warnings = [
+            (1, "Pressure spike of 14 PSI".to_string()),
+            (0, "Starved node at (3, 4)".to_string()),
+            (1, "Overpressure at (9, 9)".to_string()),
+        ];
+        let report = SliceReport::new(&result, &metadata, layers, warnings);
+
+        let path = SliceReport::path_for(&output);
+        let name = format!("hg4d-report-{}.report.json", std::process::id());
+        assert_eq!(path.file_name().unwrap().to_string_lossy(), name);
+        report.write(&path).unwrap();
+        let loaded = SliceReport::load(&path).unwrap();
+        std::fs::remove_file(&path).ok();
+
+        assert_eq!(loaded.format_version, REPORT_FORMAT_VERSION);
+        assert_eq!(loaded.result.layer_count, 2);
+        assert_eq!(loaded.layers.iter().map(|l| l.layer_number).collect::<Vec<_>>(), vec![0, 1]);
+        let layer = &loaded.layers[0];
+        assert_eq!((layer.valve_switches, layer.pressure_waits), (2, 1));
+        assert!((layer.deposited_volume_mm3 - 0.1).abs() < 1e-6);
+        assert_eq!(loaded.slowest_layer().unwrap().layer_number, 1);
+
+        let order: Vec<Option<u32>> = loaded.warnings.iter().map(|w| w.layer).collect();
+        assert_eq!(order, vec![None, Some(0), Some(1), Some(1)]);
+        assert_eq!(loaded.layer_warnings(1).next().unwrap().message, "Pressure spike of 14 PSI");
+
+        assert_eq!(loaded.hashes.printer_config, hex(&metadata.printer_config_hash));
+        assert_eq!(loaded.hashes.print_settings.len(), 64);
+        assert_eq!(loaded.hashes.materials.len(), configs.materials.len());
+    }
+}
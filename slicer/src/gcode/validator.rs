@@ -1,79 +1,582 @@
 //! G-code validation to ensure generated commands are safe and correct.
+//!
+//! Single commands are checked against the printer's limits. Sequence
+//! validation additionally tracks state across commands:
+//!
+//! - Z must increase from one G4L to the next
+//! - a material zone temperature (G4H) and a pressure for the active channel (G4P) must
+//!   be set before the first deposition
+//! - every G4D must land inside the build volume and address valves that
+//!   exist on a node
+//! - per layer, no valve may switch faster than `max_valve_rate`, using the
+//!   layer time predicted by [`TimeEstimator`]
+//!
+//! On printers whose thermal zones cover areas of the valve grid,
+//! [`GCodeValidator::validate_zone_coverage`] checks the sliced layers: every
+//! node that opens a valve must lie in a zone backing its channel that can
+//! hold the channel's material temperature.
 
-use gcode_types::Command;
-use config_types::{PrinterConfig, SafetyLimits};
-use anyhow::Result;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use anyhow::{bail, Result};
+use gcode_types::{Command, Heater, Layer, ValveToggles};
+use config_types::{MaterialProfile, PrinterConfig, SafetyLimits};
+use serde::Serialize;
+
+use crate::core::TimeEstimator;
 
 /// Validates generated G-code against printer capabilities and safety limits.
 pub struct GCodeValidator {
     printer_config: PrinterConfig,
+    time_estimator: TimeEstimator,
 }
 
 impl GCodeValidator {
     pub fn new(printer_config: PrinterConfig) -> Self {
-        Self { printer_config }
+        Self {
+            time_estimator: TimeEstimator::new(&printer_config),
+            printer_config,
+        }
+    }
+
+    /// Uses a calibrated estimator for the per-layer switching rate check.
+    pub fn with_time_estimator(mut self, time_estimator: TimeEstimator) -> Self {
+        self.time_estimator = time_estimator;
+        self
     }
 
     /// Validates a complete sequence of commands.
     pub fn validate_sequence(&self, commands: &[Command]) -> Result<ValidationReport> {
-        todo!("Implementation needed: Validate entire command sequence")
+        let mut report = ValidationReport::new();
+        let mut state = SequenceState::new(self.printer_config.valve_array.grid_spacing);
+        let mut layer_start = 0;
+
+        for (index, cmd) in commands.iter().enumerate() {
+            let checks = match cmd {
+                Command::G4D(g4d) => vec![
+                    (IssueKind::Position, self.validate_coordinates(&g4d.position)),
+                    (IssueKind::Valve, self.validate_valve_pattern(&g4d.valves)),
+                ],
+                _ => vec![(IssueKind::of_command(cmd), self.validate_command(cmd))],
+            };
+            for (kind, result) in checks {
+                if let Err(e) = result {
+                    report.push(ValidationIssue::error(kind, state.layer, Some(index), format!("{:#}", e)));
+                }
+            }
+
+            match cmd {
+                Command::G4L(g4l) => {
+                    self.check_layer_rate(&mut report, &state, &commands[layer_start..index]);
+                    if let Some(z) = state.z {
+                        if g4l.z_height < z {
+                            report.push(ValidationIssue::error(
+                                IssueKind::ZNotIncreasing,
+                                state.layer,
+                                Some(index),
+                                format!("Z moves down from {:.3}mm to {:.3}mm", z, g4l.z_height),
+                            ));
+                        } else if g4l.z_height == z {
+                            report.push(ValidationIssue::warning(
+                                IssueKind::ZNotIncreasing,
+                                state.layer,
+                                Some(index),
+                                format!("Layer repeats Z {:.3}mm", z),
+                            ));
+                        }
+                    }
+                    state.z = Some(g4l.z_height);
+                    state.layer += 1;
+                    state.toggles.reset();
+                    layer_start = index + 1;
+                    report.layer_count += 1;
+                }
+                Command::G4H(g4h) => state.heated |= g4h.heater == Heater::Zone,
+                Command::G4P(g4p) => match g4p.material_channel {
+                    Some(channel) => {
+                        state.pressurized.insert(channel);
+                    }
+                    None => state.all_pressurized = true,
+                },
+                Command::G4C(g4c) => {
+                    if let Some(channel) = g4c.material_channel {
+                        state.channel = Some(channel);
+                    }
+                }
+                Command::G4D(g4d) => {
+                    if !state.deposited {
+                        state.deposited = true;
+                        if !state.heated {
+                            report.push(ValidationIssue::error(
+                                IssueKind::TargetMissing,
+                                state.layer,
+                                Some(index),
+                                "Deposition before any temperature target (G4H)",
+                            ));
+                        }
+                        let pressurized = state.all_pressurized
+                            || match state.channel {
+                                Some(channel) => state.pressurized.contains(&channel),
+                                None => !state.pressurized.is_empty(),
+                            };
+                        if !pressurized {
+                            report.push(ValidationIssue::error(
+                                IssueKind::TargetMissing,
+                                state.layer,
+                                Some(index),
+                                "Deposition before the active channel's pressure target (G4P)",
+                            ));
+                        }
+                    }
+
+                    report.valve_switches += state.toggles.record(g4d) as u64;
+                }
+                _ => {}
+            }
+        }
+        self.check_layer_rate(&mut report, &state, &commands[layer_start..]);
+
+        report.add_info(format!("{} layers, {} valve switches", report.layer_count, report.valve_switches));
+        Ok(report)
+    }
+
+    /// Checks that every depositing node of every layer is heated by a zone
+    /// backing its channel that can hold that channel's material. Nothing
+    /// is checked unless some zone has regions; channels missing from
+    /// `channels` are skipped.
+    pub fn validate_zone_coverage(&self, layers: &[Layer], channels: &[(u8, &MaterialProfile)]) -> ValidationReport {
+        let mut report = ValidationReport::new();
+        let thermal = &self.printer_config.thermal;
+        if !thermal.is_spatially_mapped() {
+            return report;
+        }
+        let spacing = self.printer_config.valve_array.grid_spacing;
+        let materials: HashMap<u8, &MaterialProfile> = channels.iter().copied().collect();
+
+        for layer in layers {
+            // (channel, capable zone found) -> (nodes, first node)
+            let mut gaps: BTreeMap<(u8, bool), (usize, (u32, u32))> = BTreeMap::new();
+            for node in layer.nodes.iter().filter(|n| n.valves.iter().any(|v| v.open)) {
+                let channel = node.material_channel.or(layer.primary_material).unwrap_or(0);
+                let Some(material) = materials.get(&channel) else {
+                    continue;
+                };
+                let (x, y) = (node.position.x, node.position.y);
+                let mut zones = thermal.zones_at(channel, x, y, spacing).peekable();
+                let covered = zones.peek().is_some();
+                if covered && zones.any(|zone| zone.can_hold(material)) {
+                    continue;
+                }
+                gaps.entry((channel, covered)).or_insert((0, (x, y))).0 += 1;
+            }
+
+            for ((channel, covered), (count, (x, y))) in gaps {
+                let material = &materials[&channel].name;
+                let message = if covered {
+                    format!(
+                        "{} channel {} nodes are heated only by zones that cannot hold {}, first at ({}, {})",
+                        count, channel, material, x, y
+                    )
+                } else {
+                    format!(
+                        "{} channel {} nodes lie outside every zone backing the channel, first at ({}, {})",
+                        count, channel, x, y
+                    )
+                };
+                report.push(ValidationIssue::error(IssueKind::ZoneCoverage, layer.layer_number, None, message));
+            }
+            report.layer_count += 1;
+        }
+        report
     }
 
     /// Validates a single command.
     pub fn validate_command(&self, cmd: &Command) -> Result<()> {
-        todo!("Implementation needed: Validate individual command")
+        match cmd {
+            Command::G4D(g4d) => {
+                self.validate_coordinates(&g4d.position)?;
+                self.validate_valve_pattern(&g4d.valves)
+            }
+            Command::G4L(g4l) => {
+                if !(0.0..=self.printer_config.build_volume.z).contains(&g4l.z_height) {
+                    bail!(
+                        "Layer Z {:.3}mm outside build height {:.1}mm",
+                        g4l.z_height, self.printer_config.build_volume.z
+                    );
+                }
+                Ok(())
+            }
+            Command::G4H(g4h) => match g4h.heater {
+                Heater::Zone => self.validate_temperature(g4h.temperature.get(), g4h.zone),
+                Heater::Bed | Heater::Chamber => self.validate_enclosure_temperature(g4h.temperature.get(), g4h.heater),
+            },
+            Command::G4P(g4p) => self.validate_pressure(g4p.pressure.get(), g4p.material_channel),
+            Command::G4C(g4c) => match g4c.material_channel {
+                Some(channel) if channel >= self.printer_config.materials.channel_count => {
+                    bail!("Material channel {} does not exist", channel)
+                }
+                _ => Ok(()),
+            },
+            Command::G4S(_) | Command::G4W(_) | Command::Comment(_) => Ok(()),
+        }
     }
 
     /// Checks if temperature is within safe range.
+    ///
+    /// A target of 0 turns the heater off and is always allowed.
     fn validate_temperature(&self, temp: f32, zone: Option<u8>) -> Result<()> {
-        todo!("Implementation needed: Validate temperature against zone limits")
+        if temp == 0.0 {
+            return Ok(());
+        }
+        let limits: &SafetyLimits = &self.printer_config.safety;
+        let max = limits.max_temperature.get();
+        if !temp.is_finite() || temp < 0.0 || temp > max {
+            bail!("Temperature {:.1}°C outside 0-{:.1}°C", temp, max);
+        }
+        if let Some(zone_id) = zone {
+            let Some(zone) = self.printer_config.thermal.zone(zone_id) else {
+                bail!("Thermal zone {} does not exist", zone_id);
+            };
+            if temp < zone.min_temp || temp > zone.max_temp {
+                bail!(
+                    "Temperature {:.1}°C outside zone {} range {:.1}-{:.1}°C",
+                    temp, zone_id, zone.min_temp, zone.max_temp
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks a bed or chamber target against that heater's limits.
+    fn validate_enclosure_temperature(&self, temp: f32, heater: Heater) -> Result<()> {
+        if temp == 0.0 {
+            return Ok(());
+        }
+        let thermal = &self.printer_config.thermal;
+        let (name, min, max) = match heater {
+            Heater::Bed => match &thermal.bed {
+                Some(bed) => ("bed", bed.min_temp, bed.max_temp),
+                None => bail!("Printer has no heated bed"),
+            },
+            Heater::Chamber => match &thermal.chamber {
+                Some(chamber) => ("chamber", 0.0, chamber.max_temp),
+                None => bail!("Printer has no heated chamber"),
+            },
+            Heater::Zone => return self.validate_temperature(temp, None),
+        };
+        if !temp.is_finite() || temp < min || temp > max {
+            bail!("Temperature {:.1}°C outside {} range {:.1}-{:.1}°C", temp, name, min, max);
+        }
+        Ok(())
     }
 
     /// Checks if pressure is within safe range.
+    ///
+    /// A target of 0 vents the channel and is always allowed.
     fn validate_pressure(&self, pressure: f32, channel: Option<u8>) -> Result<()> {
-        todo!("Implementation needed: Validate pressure against system limits")
+        if pressure == 0.0 {
+            return Ok(());
+        }
+        let system = &self.printer_config.materials.pressure;
+        let max = system.max_pressure.min(self.printer_config.safety.max_pressure.get());
+        if !pressure.is_finite() || pressure < system.min_pressure || pressure > max {
+            bail!("Pressure {:.1} PSI outside {:.1}-{:.1} PSI", pressure, system.min_pressure, max);
+        }
+        if let Some(channel) = channel {
+            if !system.channels.is_empty() && !system.channels.iter().any(|c| c.channel == channel) {
+                bail!("Pressure channel {} does not exist", channel);
+            }
+        }
+        Ok(())
     }
 
     /// Checks if coordinates are within build volume.
     fn validate_coordinates(&self, coord: &gcode_types::Coordinate) -> Result<()> {
-        todo!("Implementation needed: Validate coordinates within build volume")
+        let volume = &self.printer_config.build_volume;
+        if !coord.is_valid()
+            || !(0.0..=volume.x).contains(&coord.x)
+            || !(0.0..=volume.y).contains(&coord.y)
+            || !(0.0..=volume.z).contains(&coord.z)
+        {
+            bail!(
+                "Position ({:.2}, {:.2}, {:.2}) outside build volume {}x{}x{}mm",
+                coord.x, coord.y, coord.z, volume.x, volume.y, volume.z
+            );
+        }
+        Ok(())
     }
 
     /// Checks if valve pattern is achievable with hardware.
     fn validate_valve_pattern(&self, valves: &[gcode_types::ValveState]) -> Result<()> {
-        todo!("Implementation needed: Validate valve pattern is physically possible")
+        let per_node = self.printer_config.valve_array.valves_per_node;
+        let mut seen = HashSet::new();
+        for valve in valves {
+            if valve.index >= per_node {
+                bail!("Valve {} does not exist ({} valves per node)", valve.index, per_node);
+            }
+            if !seen.insert(valve.index) {
+                bail!("Valve {} set twice in one command", valve.index);
+            }
+        }
+        Ok(())
+    }
+
+    /// Reports the busiest valve of a layer if it switches faster than the
+    /// safety limit over the estimated layer time.
+    fn check_layer_rate(&self, report: &mut ValidationReport, state: &SequenceState, commands: &[Command]) {
+        let Some((((x, y), index), switches)) = state.toggles.busiest() else {
+            return;
+        };
+        let seconds = self
+            .time_estimator
+            .estimate_commands(state.z.unwrap_or(0.0), commands)
+            .as_secs_f32();
+        if seconds <= 0.0 {
+            return;
+        }
+
+        let rate = switches as f32 / seconds;
+        report.peak_switch_rate = report.peak_switch_rate.max(rate);
+        let limit = self.printer_config.safety.max_valve_rate;
+        if rate > limit {
+            report.push(ValidationIssue::error(
+                IssueKind::SwitchingRate,
+                state.layer,
+                None,
+                format!(
+                    "Valve {} at node ({}, {}) switches {} times in {:.2}s ({:.1} Hz, limit {:.1} Hz)",
+                    index, x, y, switches, seconds, rate, limit
+                ),
+            ));
+        }
+    }
+}
+
+/// State carried from command to command during sequence validation.
+#[derive(Debug)]
+struct SequenceState {
+    layer: u32,
+    z: Option<f32>,
+    heated: bool,
+    pressurized: HashSet<u8>,
+    all_pressurized: bool,
+    channel: Option<u8>,
+    deposited: bool,
+    /// State changes per (node, valve) in the current layer
+    toggles: ValveToggles,
+}
+
+impl SequenceState {
+    fn new(grid_spacing: f32) -> Self {
+        Self {
+            layer: 0,
+            z: None,
+            heated: false,
+            pressurized: HashSet::new(),
+            all_pressurized: false,
+            channel: None,
+            deposited: false,
+            toggles: ValveToggles::new(grid_spacing),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+/// What a validation issue is about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IssueKind {
+    ZNotIncreasing,
+    /// Deposition before temperature or pressure targets
+    TargetMissing,
+    Position,
+    Valve,
+    Temperature,
+    Pressure,
+    Material,
+    SwitchingRate,
+    /// Deposition outside a zone able to heat the material
+    ZoneCoverage,
+    Summary,
+}
+
+impl IssueKind {
+    fn of_command(cmd: &Command) -> Self {
+        match cmd {
+            Command::G4D(_) | Command::G4L(_) => IssueKind::Position,
+            Command::G4H(_) => IssueKind::Temperature,
+            Command::G4P(_) => IssueKind::Pressure,
+            _ => IssueKind::Material,
+        }
+    }
+}
+
+/// One finding of a validation run.
+#[derive(Debug, Clone, Serialize)]
+pub struct ValidationIssue {
+    pub severity: Severity,
+    pub kind: IssueKind,
+    /// Layer the issue occurs in (0 before the first G4L)
+    pub layer: u32,
+    /// Index of the offending command, if the issue concerns one
+    pub command_index: Option<usize>,
+    pub message: String,
+}
+
+impl ValidationIssue {
+    pub fn error(kind: IssueKind, layer: u32, command_index: Option<usize>, message: impl Into<String>) -> Self {
+        Self { severity: Severity::Error, kind, layer, command_index, message: message.into() }
+    }
+
+    pub fn warning(kind: IssueKind, layer: u32, command_index: Option<usize>, message: impl Into<String>) -> Self {
+        Self { severity: Severity::Warning, kind, layer, command_index, message: message.into() }
     }
 }
 
 /// Report of validation results.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct ValidationReport {
     pub valid: bool,
-    pub errors: Vec<String>,
-    pub warnings: Vec<String>,
-    pub info: Vec<String>,
+    pub issues: Vec<ValidationIssue>,
+    pub layer_count: u32,
+    pub valve_switches: u64,
+    /// Highest single-valve switching rate over any layer (Hz)
+    pub peak_switch_rate: f32,
 }
 
 impl ValidationReport {
     pub fn new() -> Self {
         Self {
             valid: true,
-            errors: Vec::new(),
-            warnings: Vec::new(),
-            info: Vec::new(),
+            issues: Vec::new(),
+            layer_count: 0,
+            valve_switches: 0,
+            peak_switch_rate: 0.0,
         }
     }
 
-    pub fn add_error(&mut self, msg: impl Into<String>) {
-        self.errors.push(msg.into());
-        self.valid = false;
+    pub fn push(&mut self, issue: ValidationIssue) {
+        if issue.severity == Severity::Error {
+            self.valid = false;
+        }
+        self.issues.push(issue);
     }
 
-    pub fn add_warning(&mut self, msg: impl Into<String>) {
-        self.warnings.push(msg.into());
+    pub fn add_info(&mut self, msg: impl Into<String>) {
+        self.issues.push(ValidationIssue {
+            severity: Severity::Info,
+            kind: IssueKind::Summary,
+            layer: self.layer_count,
+            command_index: None,
+            message: msg.into(),
+        });
     }
 
-    pub fn add_info(&mut self, msg: impl Into<String>) {
-        self.info.push(msg.into());
+    pub fn errors(&self) -> impl Iterator<Item = &ValidationIssue> {
+        self.issues.iter().filter(|i| i.severity == Severity::Error)
+    }
+
+    pub fn warnings(&self) -> impl Iterator<Item = &ValidationIssue> {
+        self.issues.iter().filter(|i| i.severity == Severity::Warning)
+    }
+}
+
+impl Default for ValidationReport {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::examples::ExampleConfigs;
+    use config_types::{PrinterModel, ZoneRegion};
+    use gcode_types::{
+        Celsius, Coordinate, G4DCommand, G4HCommand, G4LCommand, G4PCommand, GridCoordinate, NodeValveState, Psi,
+        ValveState,
+    };
+
+    fn deposit(x: f32, index: u8, open: bool) -> Command {
+        Command::G4D(G4DCommand {
+            position: Coordinate::new(x, 10.0, 0.2),
+            valves: vec![ValveState { index, open }],
+            extrusion: None,
+            close_early_ms: None,
+        })
+    }
+
+    #[test]
+    fn test_sequence_state_tracking() {
+        let printer = ExampleConfigs::for_model(PrinterModel::HyperCubeMini).unwrap().printer;
+        let validator = GCodeValidator::new(printer);
+        let layer = |z: f32| Command::G4L(G4LCommand { z_height: z, feed_rate: None, ramp_ms: None });
+
+        let good = vec![
+            Command::G4H(G4HCommand { temperature: Celsius::new(60.0), zone: None, wait: false, heater: Heater::Bed }),
+            Command::G4H(G4HCommand { temperature: Celsius::new(200.0), zone: None, wait: true, heater: Heater::Zone }),
+            Command::G4P(G4PCommand { pressure: Psi::new(40.0), material_channel: Some(0) }),
+            layer(0.2),
+            deposit(10.0, 0, true),
+            deposit(10.0, 0, false),
+            layer(0.4),
+        ];
+        let report = validator.validate_sequence(&good).unwrap();
+        assert!(report.valid, "{:?}", report.issues);
+        assert_eq!(report.layer_count, 2);
+        assert_eq!(report.valve_switches, 2);
+
+        let bad = vec![
+            layer(0.4),
+            deposit(10.0, 0, true),
+            deposit(500.0, 0, true),
+            deposit(10.0, 7, true),
+            layer(0.2),
+        ];
+        let report = validator.validate_sequence(&bad).unwrap();
+        let kinds: Vec<IssueKind> = report.errors().map(|i| i.kind).collect();
+        assert!(!report.valid);
+        assert_eq!(kinds.iter().filter(|&&k| k == IssueKind::TargetMissing).count(), 2);
+        assert!(kinds.contains(&IssueKind::Position));
+        assert!(kinds.contains(&IssueKind::Valve));
+        assert!(kinds.contains(&IssueKind::ZNotIncreasing));
+    }
+
+    #[test]
+    fn test_zone_coverage() {
+        let mut printer = ExampleConfigs::for_model(PrinterModel::HyperCubeMini).unwrap().printer;
+        let profile = ExampleConfigs::for_model(PrinterModel::HyperCubeMini).unwrap().materials.remove(0).1;
+        let validator = GCodeValidator::new(printer.clone());
+        let mut layer = Layer::new(0.2, 0);
+        layer.primary_material = Some(0);
+        for x in [10, 150] {
+            layer.add_node(NodeValveState::new(GridCoordinate::new(x, 10), vec![ValveState::new(0, true)]));
+        }
+        // Zones without regions cover everything
+        assert!(validator.validate_zone_coverage(&[layer.clone()], &[(0, &profile)]).valid);
+
+        // Channel 0's zone now heats only the first 100 node columns
+        let zone = printer.thermal.zones_for_channel(0)[0];
+        let zone = printer.thermal.zones.iter_mut().find(|z| z.id == zone).unwrap();
+        zone.regions = vec![ZoneRegion::Nodes { x: (0, 99), y: (0, 199) }];
+        let validator = GCodeValidator::new(printer.clone());
+        let report = validator.validate_zone_coverage(&[layer.clone()], &[(0, &profile)]);
+        let errors: Vec<_> = report.errors().collect();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].kind, IssueKind::ZoneCoverage);
+        assert!(errors[0].message.contains("(150, 10)"), "{}", errors[0].message);
+
+        // A covering zone too cold for the material
+        let mut hot = profile.clone();
+        hot.temp_range = (400.0, 450.0);
+        let report = validator.validate_zone_coverage(&[layer], &[(0, &hot)]);
+        assert_eq!(report.errors().count(), 2);
     }
 }
@@ -1,27 +1,70 @@
-//! Binary .hg4d file writer.
+//! Binary .hg4d file writer and reader.
 
-use gcode_types::{Command, Layer};
+use gcode_types::codec::{self, BlockCodec};
+use gcode_types::{Command, IndexTrailer, Layer, LayerBlock, LayerIndexEntry};
+use config_types::{MaterialProfile, ResolvedSettings};
+use crate::core::ObjectInfo;
 use crate::{SliceMetadata, HG4D_MAGIC, HG4D_FORMAT_VERSION};
-use std::io::{Write, BufWriter};
+use std::io::{Read, Write, BufReader, BufWriter, Seek, SeekFrom};
 use std::fs::File;
 use std::path::Path;
-use anyhow::Result;
-use byteorder::{LittleEndian, WriteBytesExt};
+use anyhow::{bail, Context, Result};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use serde::{Deserialize, Serialize};
+
+/// Largest decoded layer block the reader accepts.
+const MAX_LAYER_BLOCK_SIZE: usize = 256 * 1024 * 1024;
+
+/// Largest metadata section the reader accepts.
+const MAX_METADATA_SIZE: u32 = 16 * 1024 * 1024;
+
+/// Header metadata section, stored after the format version as a
+/// little-endian `u32` byte length followed by a UTF-8 TOML document.
+///
+/// Scalars come first so the document serializes before any tables.
+#[derive(Serialize)]
+struct HeaderMetadata<'a> {
+    model_name: &'a str,
+    slicer_version: &'a str,
+    /// Hex-encoded SHA-256 of the printer configuration
+    printer_config_hash: String,
+    /// Layer block codec; absent when blocks are stored bare
+    codec: Option<BlockCodec>,
+    /// Expected grams per channel, index = channel
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    material_usage: &'a [f32],
+    settings: &'a ResolvedSettings,
+    materials: &'a [MaterialProfile],
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    objects: &'a [ObjectInfo],
+}
+
+impl<'a> HeaderMetadata<'a> {
+    fn new(metadata: &'a SliceMetadata, codec: BlockCodec) -> Self {
+        Self {
+            model_name: &metadata.model_name,
+            slicer_version: &metadata.slicer_version,
+            printer_config_hash: metadata
+                .printer_config_hash
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect(),
+            codec: (codec != BlockCodec::None).then_some(codec),
+            material_usage: &metadata.material_usage,
+            settings: &metadata.print_settings,
+            materials: &metadata.material_profiles,
+            objects: &metadata.objects,
+        }
+    }
+}
 
 /// Writes .hg4d binary format files.
 pub struct HG4DWriter {
     writer: BufWriter<File>,
     metadata: SliceMetadata,
     layer_index: Vec<LayerIndexEntry>,
-}
-
-#[derive(Debug, Clone)]
-struct LayerIndexEntry {
-    layer_number: u32,
-    z_height: f32,
-    file_offset: u64,
-    data_size: u32,
-    checksum: u32,
+    codec: BlockCodec,
+    compression_level: u32,
 }
 
 impl HG4DWriter {
@@ -34,9 +77,19 @@ impl HG4DWriter {
             writer,
             metadata,
             layer_index: Vec::new(),
+            codec: BlockCodec::None,
+            compression_level: 0,
         })
     }
 
+    /// Compresses layer blocks with `codec`. Must be set before the header
+    /// is written.
+    pub fn with_codec(mut self, codec: BlockCodec, level: u32) -> Self {
+        self.codec = codec;
+        self.compression_level = level;
+        self
+    }
+
     /// Writes file header.
     pub fn write_header(&mut self) -> Result<()> {
         // Magic number
@@ -45,27 +98,64 @@ impl HG4DWriter {
         // Format version
         self.writer.write_u32::<LittleEndian>(HG4D_FORMAT_VERSION)?;
         
-        // TODO: Write metadata section
-        todo!("Implementation needed: Write metadata section")
+        // Metadata section
+        let document = toml::to_string(&HeaderMetadata::new(&self.metadata, self.codec))
+            .context("Failed to serialize .hg4d metadata")?;
+        self.writer.write_u32::<LittleEndian>(document.len() as u32)?;
+        self.writer.write_all(document.as_bytes())?;
+
+        Ok(())
     }
 
     /// Writes a single layer.
+    ///
+    /// The layer is stored as a [`LayerBlock`], using the run-length encoded
+    /// frame representation whenever it is smaller than the node list, then
+    /// compressed with the writer's codec (see [`gcode_types::codec`]).
     pub fn write_layer(&mut self, layer: &Layer) -> Result<()> {
-        todo!("Implementation needed: Serialize and write layer data")
+        let mut data = LayerBlock::encode_compact(layer)?;
+        if self.codec != BlockCodec::None {
+            data = codec::pack_block(self.codec, self.compression_level, &data)?;
+        }
+        let file_offset = self.writer.stream_position()?;
+        let checksum = self.calculate_checksum(&data);
+
+        self.writer.write_all(&data)?;
+
+        self.layer_index.push(LayerIndexEntry {
+            layer_number: layer.layer_number,
+            z_height: layer.z_height,
+            file_offset,
+            data_size: data.len() as u32,
+            checksum,
+        });
+
+        Ok(())
     }
 
-    /// Writes layer index.
+    /// Writes the layer index and its trailer (see [`gcode_types::index`]).
     fn write_layer_index(&mut self) -> Result<()> {
-        todo!("Implementation needed: Write layer index for random access")
+        let index_offset = self.writer.stream_position()?;
+        for entry in &self.layer_index {
+            self.writer.write_all(&entry.to_bytes())?;
+        }
+
+        let trailer = IndexTrailer {
+            index_offset,
+            layer_count: self.layer_index.len() as u32,
+        };
+        self.writer.write_all(&trailer.to_bytes())?;
+        Ok(())
     }
 
-    /// Writes file footer and finalizes.
+    /// Writes the layer index and flushes the file.
+    ///
+    /// The index trailer must be the last bytes of the file; block integrity
+    /// is covered by the per-layer CRC32s in the index.
     pub fn finalize(mut self) -> Result<()> {
-        // Write layer index
         self.write_layer_index()?;
-        
-        // Write footer with checksums
-        todo!("Implementation needed: Write footer with integrity checksums")
+        self.writer.flush()?;
+        Ok(())
     }
 
     /// Calculates checksum for data block.
@@ -75,7 +165,140 @@ impl HG4DWriter {
     }
 }
 
-/// Reads .hg4d binary format files.
+/// Header metadata as read back. Every field defaults so files from older
+/// slicers still open.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct FileHeader {
+    #[serde(default)]
+    pub model_name: String,
+    #[serde(default)]
+    pub slicer_version: String,
+    /// Hex-encoded SHA-256 of the printer configuration
+    #[serde(default)]
+    pub printer_config_hash: String,
+    #[serde(default)]
+    pub codec: Option<BlockCodec>,
+    #[serde(default)]
+    pub settings: Option<ResolvedSettings>,
+    /// Material per channel, index = channel
+    #[serde(default)]
+    pub materials: Vec<MaterialProfile>,
+    #[serde(default)]
+    pub objects: Vec<ObjectInfo>,
+    /// Expected grams per channel, index = channel
+    #[serde(default)]
+    pub material_usage: Vec<f32>,
+}
+
+/// Reads .hg4d binary format files (for validation and debugging).
 pub struct HG4DReader {
-    // TODO: Implement reader (for validation/debugging)
+    reader: BufReader<File>,
+    format_version: u32,
+    header: FileHeader,
+    index: Vec<LayerIndexEntry>,
+}
+
+impl HG4DReader {
+    /// Opens a file and loads its header and layer index.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+        let mut reader = BufReader::new(file);
+
+        if reader.read_u32::<LittleEndian>()? != HG4D_MAGIC {
+            bail!("{} is not an .hg4d file", path.display());
+        }
+        let version = reader.read_u32::<LittleEndian>()?;
+        if version != HG4D_FORMAT_VERSION {
+            bail!(
+                "Unsupported .hg4d format version {} (this slicer reads {}); re-slice {}",
+                version,
+                HG4D_FORMAT_VERSION,
+                path.display()
+            );
+        }
+        let length = reader.read_u32::<LittleEndian>()?;
+        if length > MAX_METADATA_SIZE {
+            bail!("Metadata section of {} bytes is too large", length);
+        }
+        let mut document = vec![0u8; length as usize];
+        reader.read_exact(&mut document).context("Metadata section is truncated")?;
+        let header: FileHeader = toml::from_str(
+            std::str::from_utf8(&document).context("Metadata is not UTF-8")?,
+        )
+        .context("Invalid .hg4d metadata")?;
+
+        let end = reader.seek(SeekFrom::End(-(IndexTrailer::ENCODED_SIZE as i64)))?;
+        let mut bytes = [0u8; IndexTrailer::ENCODED_SIZE];
+        reader.read_exact(&mut bytes)?;
+        let trailer = IndexTrailer::from_bytes(&bytes)?;
+        if trailer.index_offset + trailer.index_size() != end {
+            bail!("Layer index does not end at the trailer");
+        }
+
+        reader.seek(SeekFrom::Start(trailer.index_offset))?;
+        let mut raw = vec![0u8; trailer.index_size() as usize];
+        reader.read_exact(&mut raw)?;
+        let index = raw
+            .chunks_exact(LayerIndexEntry::ENCODED_SIZE)
+            .map(LayerIndexEntry::from_bytes)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self {
+            reader,
+            format_version: version,
+            header,
+            index,
+        })
+    }
+
+    /// Block codec recorded in the header, if any.
+    pub fn codec(&self) -> Option<BlockCodec> {
+        self.header.codec
+    }
+
+    pub fn format_version(&self) -> u32 {
+        self.format_version
+    }
+
+    pub fn header(&self) -> &FileHeader {
+        &self.header
+    }
+
+    pub fn index(&self) -> &[LayerIndexEntry] {
+        &self.index
+    }
+
+    /// Reads, verifies and decompresses the `n`th layer block, returning the
+    /// encoded [`LayerBlock`] bytes.
+    pub fn read_block(&mut self, n: usize) -> Result<Vec<u8>> {
+        let entry = *self
+            .index
+            .get(n)
+            .with_context(|| format!("File has {} layers, no layer {}", self.index.len(), n))?;
+        let mut stored = vec![0u8; entry.data_size as usize];
+        self.reader.seek(SeekFrom::Start(entry.file_offset))?;
+        self.reader.read_exact(&mut stored)?;
+
+        let actual = crc32fast::hash(&stored);
+        if actual != entry.checksum {
+            bail!(
+                "Layer {} checksum mismatch: expected {:08x}, got {:08x}",
+                entry.layer_number,
+                entry.checksum,
+                actual
+            );
+        }
+
+        Ok(match self.header.codec {
+            Some(_) => codec::unpack_block(&stored, MAX_LAYER_BLOCK_SIZE)?,
+            None => stored,
+        })
+    }
+
+    /// Reads the `n`th layer.
+    pub fn read_layer(&mut self, n: usize) -> Result<Layer> {
+        let block = self.read_block(n)?;
+        Ok(LayerBlock::from_bytes(&block)?.into_layer())
+    }
 }
@@ -1,7 +1,8 @@
 //! # Graphical User Interface
 //!
 //! This module provides the GUI for interactive slicing when compiled with
-//! the 'gui' feature flag.
+//! the 'gui' feature flag. The settings editor model does not draw anything
+//! and is always built.
 //!
 //! ## Module Organization
 //!
@@ -14,7 +15,6 @@
 pub mod main_window;
 #[cfg(feature = "gui")]
 pub mod preview;
-#[cfg(feature = "gui")]
 pub mod settings;
 #[cfg(feature = "gui")]
 pub mod dialogs;
@@ -23,7 +23,6 @@ pub mod dialogs;
 pub use main_window::MainWindow;
 #[cfg(feature = "gui")]
 pub use preview::PreviewWidget;
-#[cfg(feature = "gui")]
 pub use settings::SettingsPanel;
 
 #[cfg(not(feature = "gui"))]
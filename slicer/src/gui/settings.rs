@@ -0,0 +1,469 @@
+//! Settings editor panels.
+//!
+//! [`SettingsPanel`] holds the state behind the settings editor; the
+//! window only draws it. Print settings and the material profile of each
+//! channel are shown as trees of [`SettingNode`]s built from their
+//! serialized form, so a new setting appears without editor code: tables
+//! become groups and every other value an editable leaf.
+//!
+//! ## Editing
+//!
+//! Leaves are edited by path with the text the operator typed, on every
+//! keystroke. The text is parsed as the type of the value it replaces
+//! (arrays and settings not yet set as TOML literals, empty text clearing
+//! an optional setting) and the result deserialized back. Text that fails
+//! either step stays on the leaf as a draft with its error while the last
+//! valid value stays in effect. Every accepted edit runs the constraints of
+//! [`PrintSettingsValidator`] again; each issue is attached to the setting
+//! it names and counted on the groups above it.
+//!
+//! Material settings use the validator's paths, `materials[<channel>].…`.
+//!
+//! ## Presets
+//!
+//! [`SettingsPanel::to_preset`] saves only what differs from the defaults,
+//! with `unset` for optional settings the defaults have and the edit
+//! cleared; [`SettingsPanel::apply_preset`] resolves a preset on the
+//! defaults. [`SettingsPanel::diff`] and [`SettingsPanel::preset_diff`]
+//! list the overridden settings for the diff view.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use config_types::{MaterialProfile, PrintSettings, PrinterConfig, SettingsLayer, SettingsPreset, SettingsStack};
+
+use crate::config::{PrintSettingsValidator, SettingsIssue, SettingsReport, Severity};
+
+const MATERIALS_PREFIX: &str = "materials[";
+
+/// Text that was typed but not accepted.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Draft {
+    pub text: String,
+    pub error: String,
+}
+
+/// One setting or group of settings in an editor tree.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SettingNode {
+    /// Path as used by the validator and presets, e.g. `infill.density`
+    pub path: String,
+    /// Last path segment
+    pub label: String,
+    /// Value of a leaf; None for a group
+    pub value: Option<toml::Value>,
+    pub children: Vec<SettingNode>,
+    pub draft: Option<Draft>,
+    /// Issues on this setting or, for a group, on any setting under it
+    pub issues: Vec<SettingsIssue>,
+    /// Differs from the defaults (print settings) or the profile as loaded
+    pub modified: bool,
+}
+
+impl SettingNode {
+    pub fn is_group(&self) -> bool {
+        self.value.is_none()
+    }
+
+    pub fn error_count(&self) -> usize {
+        self.issues.iter().filter(|i| i.severity == Severity::Error).count()
+    }
+
+    /// Node at `path` in this subtree.
+    pub fn find(&self, path: &str) -> Option<&SettingNode> {
+        if self.path == path {
+            return Some(self);
+        }
+        self.children.iter().find_map(|c| c.find(path))
+    }
+}
+
+/// A setting a preset or edit changes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SettingChange {
+    pub path: String,
+    /// Default value; None if the defaults leave it unset
+    pub default: Option<toml::Value>,
+    /// New value; None if cleared
+    pub value: Option<toml::Value>,
+}
+
+/// State of the settings editor.
+pub struct SettingsPanel {
+    defaults: PrintSettings,
+    settings: PrintSettings,
+    /// Profiles as loaded, by channel, for change marks
+    loaded_materials: Vec<MaterialProfile>,
+    materials: Vec<MaterialProfile>,
+    printer: Option<PrinterConfig>,
+    validator: PrintSettingsValidator,
+    drafts: BTreeMap<String, Draft>,
+    report: SettingsReport,
+}
+
+impl SettingsPanel {
+    /// Editor starting from `defaults`, with the profiles loaded on each
+    /// channel.
+    pub fn new(defaults: PrintSettings, materials: Vec<MaterialProfile>) -> Self {
+        let mut panel = Self {
+            settings: defaults.clone(),
+            defaults,
+            loaded_materials: materials.clone(),
+            materials,
+            printer: None,
+            validator: PrintSettingsValidator::new(),
+            drafts: BTreeMap::new(),
+            report: SettingsReport::default(),
+        };
+        panel.revalidate();
+        panel
+    }
+
+    /// Also checks the settings against `printer`.
+    pub fn with_printer(mut self, printer: PrinterConfig) -> Self {
+        self.printer = Some(printer);
+        self.revalidate();
+        self
+    }
+
+    pub fn settings(&self) -> &PrintSettings {
+        &self.settings
+    }
+
+    pub fn material(&self, channel: u8) -> Option<&MaterialProfile> {
+        self.materials.get(channel as usize)
+    }
+
+    pub fn report(&self) -> &SettingsReport {
+        &self.report
+    }
+
+    /// Nothing prevents slicing and no typed text is pending.
+    pub fn is_valid(&self) -> bool {
+        self.report.is_valid() && self.drafts.is_empty()
+    }
+
+    /// Print settings tree.
+    pub fn print_tree(&self) -> SettingNode {
+        let current = to_table(&self.settings).unwrap_or_default();
+        let baseline = to_table(&self.defaults).unwrap_or_default();
+        self.build_group(String::new(), "settings".to_string(), &current, Some(&baseline))
+    }
+
+    /// Material profile tree of a channel.
+    pub fn material_tree(&self, channel: u8) -> Option<SettingNode> {
+        let profile = self.materials.get(channel as usize)?;
+        let current = to_table(profile).ok()?;
+        let baseline = self.loaded_materials.get(channel as usize).and_then(|p| to_table(p).ok());
+        let path = format!("{}{}]", MATERIALS_PREFIX, channel);
+        Some(self.build_group(path, profile.name.clone(), &current, baseline.as_ref()))
+    }
+
+    /// Sets the setting at `path` from typed text. Rejected text is kept
+    /// as the setting's draft and the error returned; the previous value
+    /// stays in effect.
+    pub fn edit(&mut self, path: &str, text: &str) -> Result<()> {
+        match self.apply_edit(path, text) {
+            Ok(()) => {
+                self.drafts.remove(path);
+                self.revalidate();
+                Ok(())
+            }
+            Err(e) => {
+                let draft = Draft { text: text.to_string(), error: format!("{:#}", e) };
+                self.drafts.insert(path.to_string(), draft);
+                Err(e)
+            }
+        }
+    }
+
+    /// Drops typed text that was not accepted.
+    pub fn discard_draft(&mut self, path: &str) {
+        self.drafts.remove(path);
+    }
+
+    /// Back to the defaults and the profiles as loaded.
+    pub fn reset(&mut self) {
+        self.settings = self.defaults.clone();
+        self.materials = self.loaded_materials.clone();
+        self.drafts.clear();
+        self.revalidate();
+    }
+
+    /// Settings that differ from the defaults.
+    pub fn diff(&self) -> Vec<SettingChange> {
+        diff_settings(&self.defaults, &self.settings)
+    }
+
+    /// Settings `preset` overrides when applied on the defaults.
+    pub fn preset_diff(&self, preset: &SettingsPreset) -> Result<Vec<SettingChange>> {
+        let resolved = self.resolve(preset)?;
+        Ok(diff_settings(&self.defaults, &resolved))
+    }
+
+    /// The edited settings as a preset holding only what differs from the
+    /// defaults.
+    pub fn to_preset(&self, name: &str, layer: SettingsLayer) -> SettingsPreset {
+        self.diff().into_iter().fold(SettingsPreset::new(name, layer), |preset, change| match change.value {
+            Some(value) => preset.set(&change.path, value),
+            None => preset.unset(&change.path),
+        })
+    }
+
+    /// Replaces the edited settings with `preset` applied on the defaults.
+    pub fn apply_preset(&mut self, preset: &SettingsPreset) -> Result<()> {
+        self.settings = self.resolve(preset)?;
+        self.drafts.retain(|path, _| path.starts_with(MATERIALS_PREFIX));
+        self.revalidate();
+        Ok(())
+    }
+
+    pub fn save_preset(&self, path: &Path, name: &str, layer: SettingsLayer) -> Result<SettingsPreset> {
+        let preset = self.to_preset(name, layer);
+        preset
+            .to_file(path)
+            .with_context(|| format!("Failed to save preset {}", path.display()))?;
+        Ok(preset)
+    }
+
+    pub fn load_preset(&mut self, path: &Path) -> Result<SettingsPreset> {
+        let preset = SettingsPreset::from_file(path)
+            .with_context(|| format!("Failed to load preset {}", path.display()))?;
+        self.apply_preset(&preset)?;
+        Ok(preset)
+    }
+
+    fn resolve(&self, preset: &SettingsPreset) -> Result<PrintSettings> {
+        let mut stack = SettingsStack::new(self.defaults.clone());
+        stack.push(preset.clone());
+        let resolved = stack
+            .resolve()
+            .with_context(|| format!("Preset '{}' does not apply", preset.name))?;
+        Ok(resolved.settings)
+    }
+
+    fn apply_edit(&mut self, path: &str, text: &str) -> Result<()> {
+        match path.strip_prefix(MATERIALS_PREFIX) {
+            Some(rest) => {
+                let (channel, field) = rest
+                    .split_once("].")
+                    .ok_or_else(|| anyhow!("'{}' names no material setting", path))?;
+                let channel: usize = channel.parse().with_context(|| format!("'{}' is not a channel", channel))?;
+                let profile = self
+                    .materials
+                    .get_mut(channel)
+                    .ok_or_else(|| anyhow!("No material on channel {}", channel))?;
+                *profile = edited(profile, field, text)?;
+            }
+            None => self.settings = edited(&self.settings, path, text)?,
+        }
+        Ok(())
+    }
+
+    fn revalidate(&mut self) {
+        self.report = self.validator.check(&self.settings, self.printer.as_ref(), &self.materials);
+    }
+
+    fn build_group(
+        &self,
+        path: String,
+        label: String,
+        table: &toml::Table,
+        baseline: Option<&toml::Table>,
+    ) -> SettingNode {
+        let children = table
+            .iter()
+            .map(|(key, value)| {
+                let child_path = if path.is_empty() { key.clone() } else { format!("{}.{}", path, key) };
+                let child_baseline = baseline.and_then(|b| b.get(key));
+                match value {
+                    toml::Value::Table(child) => self.build_group(
+                        child_path,
+                        key.clone(),
+                        child,
+                        child_baseline.and_then(toml::Value::as_table),
+                    ),
+                    _ => SettingNode {
+                        draft: self.drafts.get(&child_path).cloned(),
+                        issues: self.issues_under(&child_path),
+                        modified: baseline.is_some() && child_baseline != Some(value),
+                        label: key.clone(),
+                        value: Some(value.clone()),
+                        children: Vec::new(),
+                        path: child_path,
+                    },
+                }
+            })
+            .collect::<Vec<_>>();
+        SettingNode {
+            issues: self.issues_under(&path),
+            modified: children.iter().any(|c| c.modified) || baseline.is_some_and(|b| b.len() != table.len()),
+            draft: None,
+            label,
+            value: None,
+            children,
+            path,
+        }
+    }
+
+    /// Issues on `path` or below it; the root (empty path) takes every
+    /// print setting issue.
+    fn issues_under(&self, path: &str) -> Vec<SettingsIssue> {
+        self.report
+            .issues
+            .iter()
+            .filter(|i| match path {
+                "" => !i.field.starts_with(MATERIALS_PREFIX),
+                _ => i.field == path || i.field.strip_prefix(path).is_some_and(|rest| rest.starts_with('.')),
+            })
+            .cloned()
+            .collect()
+    }
+}
+
+/// `value` with the setting at `path` parsed from `text`.
+fn edited<T: Serialize + DeserializeOwned>(value: &T, path: &str, text: &str) -> Result<T> {
+    let mut table = to_table(value)?;
+    let current = get_path(&table, path);
+    if matches!(current, Some(toml::Value::Table(_))) {
+        bail!("'{}' is a group of settings", path);
+    }
+    let parsed = parse_text(text, current)?;
+    set_path(&mut table, path, parsed);
+    toml::Value::Table(table)
+        .try_into()
+        .map_err(|e: toml::de::Error| anyhow!("{}", e.message()))
+}
+
+/// Typed text as the type of `current`. Empty text clears the setting.
+fn parse_text(text: &str, current: Option<&toml::Value>) -> Result<Option<toml::Value>> {
+    let text = text.trim();
+    if text.is_empty() {
+        return Ok(None);
+    }
+    let value = match current {
+        Some(toml::Value::String(_)) => toml::Value::String(text.to_string()),
+        Some(toml::Value::Boolean(_)) => {
+            toml::Value::Boolean(text.parse().map_err(|_| anyhow!("'{}' is not true or false", text))?)
+        }
+        Some(toml::Value::Integer(_)) => {
+            toml::Value::Integer(text.parse().map_err(|_| anyhow!("'{}' is not a whole number", text))?)
+        }
+        Some(toml::Value::Float(_)) => {
+            toml::Value::Float(text.parse().map_err(|_| anyhow!("'{}' is not a number", text))?)
+        }
+        // Arrays and settings not set yet; a bare word is taken as a string
+        _ => match toml::from_str::<toml::Table>(&format!("value = {}", text)) {
+            Ok(mut table) => table.remove("value").expect("parsed the value key"),
+            Err(_) if current.is_none() && !text.contains(['[', '{', '"']) => toml::Value::String(text.to_string()),
+            Err(e) => bail!("'{}' is not a TOML value: {}", text, e.message()),
+        },
+    };
+    Ok(Some(value))
+}
+
+fn to_table<T: Serialize>(value: &T) -> Result<toml::Table> {
+    match toml::Value::try_from(value)? {
+        toml::Value::Table(table) => Ok(table),
+        _ => bail!("settings do not serialize to a table"),
+    }
+}
+
+fn get_path<'a>(table: &'a toml::Table, path: &str) -> Option<&'a toml::Value> {
+    match path.split_once('.') {
+        None => table.get(path),
+        Some((head, rest)) => get_path(table.get(head)?.as_table()?, rest),
+    }
+}
+
+/// Sets or, with None, removes a dotted path, creating tables on the way.
+fn set_path(table: &mut toml::Table, path: &str, value: Option<toml::Value>) {
+    match path.split_once('.') {
+        None => {
+            match value {
+                Some(value) => table.insert(path.to_string(), value),
+                None => table.remove(path),
+            };
+        }
+        Some((head, rest)) => {
+            let entry = table
+                .entry(head.to_string())
+                .or_insert_with(|| toml::Value::Table(toml::Table::new()));
+            if !entry.is_table() {
+                *entry = toml::Value::Table(toml::Table::new());
+            }
+            set_path(entry.as_table_mut().expect("entry is a table"), rest, value);
+        }
+    }
+}
+
+/// Leaf settings that differ between `base` and `other`, by path.
+pub fn diff_settings(base: &PrintSettings, other: &PrintSettings) -> Vec<SettingChange> {
+    let mut changes = Vec::new();
+    if let (Ok(base), Ok(other)) = (to_table(base), to_table(other)) {
+        diff_tables("", &base, &other, &mut changes);
+    }
+    changes
+}
+
+fn diff_tables(prefix: &str, base: &toml::Table, other: &toml::Table, changes: &mut Vec<SettingChange>) {
+    let keys: BTreeSet<&String> = base.keys().chain(other.keys()).collect();
+    for key in keys {
+        let path = if prefix.is_empty() { key.clone() } else { format!("{}.{}", prefix, key) };
+        match (base.get(key), other.get(key)) {
+            (Some(toml::Value::Table(a)), Some(toml::Value::Table(b))) => diff_tables(&path, a, b, changes),
+            (a, b) if a != b => changes.push(SettingChange { path, default: a.cloned(), value: b.cloned() }),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ExampleConfigs;
+    use config_types::PrinterModel;
+
+    #[test]
+    fn test_edit_validate_and_presets() {
+        let configs = ExampleConfigs::for_model(PrinterModel::HyperCubeMini).unwrap();
+        let materials: Vec<MaterialProfile> = configs.materials.iter().map(|(_, p)| p.clone()).collect();
+        let mut panel = SettingsPanel::new(configs.settings.clone(), materials);
+        assert!(panel.is_valid() && panel.diff().is_empty());
+
+        // Typed text of the wrong type stays a draft; the value is kept
+        assert!(panel.edit("infill.density", "dense").is_err());
+        let tree = panel.print_tree();
+        let density = tree.find("infill.density").unwrap();
+        assert_eq!(density.draft.as_ref().unwrap().text, "dense");
+        assert_eq!(density.value, Some(toml::Value::Float(configs.settings.infill.density as f64)));
+        assert!(!panel.is_valid());
+
+        // A number out of range is accepted and flagged by the validator
+        panel.edit("infill.density", "120").unwrap();
+        let tree = panel.print_tree();
+        assert_eq!(tree.find("infill.density").unwrap().error_count(), 1);
+        assert_eq!(tree.find("infill").unwrap().error_count(), 1);
+        assert!(tree.find("infill").unwrap().modified && !tree.find("speeds").unwrap().modified);
+        assert!(panel.edit("infill.pattern", "Spiral").is_err());
+
+        panel.edit("infill.density", "35").unwrap();
+        panel.edit("materials[0].cooling.regular_fan_speed", "150").unwrap();
+        let material = panel.material_tree(0).unwrap();
+        assert_eq!(material.find("materials[0].cooling.regular_fan_speed").unwrap().error_count(), 1);
+        assert_eq!(panel.print_tree().error_count(), 0);
+
+        // Only the change goes into the preset, which restores it
+        let preset = panel.to_preset("dense", SettingsLayer::Job);
+        assert_eq!(panel.diff().len(), 1);
+        assert_eq!(panel.preset_diff(&preset).unwrap(), panel.diff());
+        panel.reset();
+        assert!(panel.diff().is_empty());
+        panel.apply_preset(&preset).unwrap();
+        assert_eq!(panel.settings().infill.density, 35.0);
+    }
+}
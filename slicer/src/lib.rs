@@ -16,6 +16,7 @@
 //! - **pressure**: Pressure simulation and flow optimization
 //! - **config**: Configuration management
 //! - **utils**: Shared utilities for geometry and math operations
+//! - **gui**: Interactive slicing and the settings editor (`gui` feature)
 //!
 //! ## Slicing Workflow
 //!
@@ -76,7 +77,7 @@
 //! capabilities.
 
 // External crate imports - Standard library
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::fs::File;
 use std::io::{BufReader, BufWriter};
 use std::path::{Path, PathBuf};
@@ -90,7 +91,7 @@ use tracing::{debug, error, info, warn};
 
 // Internal ecosystem imports
 use gcode_types::{Command, Coordinate, GridCoordinate, Layer, ValveState};
-use config_types::{PrinterConfig, MaterialProfile, PrintSettings};
+use config_types::{PrinterConfig, MaterialProfile, PrintSettings, ResolvedSettings};
 
 // Public module declarations
 pub mod core;
@@ -99,6 +100,7 @@ pub mod materials;
 pub mod pressure;
 pub mod config;
 pub mod utils;
+pub mod gui;
 
 // Shared Type Definitions - Fully Implemented
 
@@ -183,6 +185,11 @@ pub struct SlicerConfig {
     /// Number of worker threads for parallel processing
     pub worker_threads: usize,
 
+    /// Layers being processed or awaiting the writer at once; bounds
+    /// memory when layers finish out of order
+    #[serde(default = "default_max_in_flight_layers")]
+    pub max_in_flight_layers: usize,
+
     /// Enable pressure simulation validation
     pub enable_pressure_simulation: bool,
 
@@ -194,16 +201,59 @@ pub struct SlicerConfig {
 
     /// Compression level for .hg4d output (0-9)
     pub compression_level: u32,
+
+    /// Codec compressing .hg4d layer blocks
+    #[serde(default = "default_block_codec")]
+    pub block_codec: gcode_types::BlockCodec,
+
+    /// Simulator checking layers and candidate activation groups
+    #[serde(default)]
+    pub pressure_fidelity: PressureFidelity,
+
+    /// Estimate the pressure spike of each layer's valves closing and flag
+    /// layers over the safety fault threshold
+    #[serde(default)]
+    pub transient_pressure: bool,
+
+    /// Write a [`gcode::SliceReport`] next to the output
+    #[serde(default)]
+    pub write_report: bool,
+}
+
+/// Which pressure simulator the slicer consults; a trade of speed for accuracy.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PressureFidelity {
+    /// The slicer's built-in network model
+    #[default]
+    Internal,
+    /// An external engine attached with [`Slicer::set_co_simulator`], such
+    /// as the simulator crate's physics engine; slower and more accurate.
+    /// Falls back to the built-in model if none is attached
+    CoSimulation,
+}
+
+fn default_block_codec() -> gcode_types::BlockCodec {
+    gcode_types::BlockCodec::Zstd
+}
+
+fn default_max_in_flight_layers() -> usize {
+    32
 }
 
 impl Default for SlicerConfig {
     fn default() -> Self {
         Self {
             worker_threads: num_cpus::get(),
+            max_in_flight_layers: default_max_in_flight_layers(),
             enable_pressure_simulation: true,
             enable_routing_optimization: true,
             optimization_iterations: 100,
             compression_level: 6,
+            block_codec: default_block_codec(),
+            pressure_fidelity: PressureFidelity::default(),
+            transient_pressure: false,
+            write_report: false,
         }
     }
 }
@@ -240,6 +290,17 @@ pub trait ValveMapper: Send + Sync {
         grid_config: &ValveGridConfig,
     ) -> Result<ValveActivationMap>;
 
+    /// [`map_to_grid`](Self::map_to_grid) with the print's slices, for
+    /// mappers whose patterns depend on the layers above and below.
+    fn map_in_stack(
+        &self,
+        layer_slice: &LayerSlice,
+        grid_config: &ValveGridConfig,
+        _stack: Option<&core::SliceStack>,
+    ) -> Result<ValveActivationMap> {
+        self.map_to_grid(layer_slice, grid_config)
+    }
+
     /// Validates that mapping is achievable with given hardware.
     fn validate_mapping(&self, activation_map: &ValveActivationMap) -> Result<()>;
 }
@@ -266,8 +327,14 @@ pub trait PressureSimulator: Send + Sync {
         pressure_config: &PressureConfig,
     ) -> Result<PressureSimulation>;
 
-    /// Validates that pressures remain within safe limits.
-    fn validate_pressures(&self, simulation: &PressureSimulation) -> Result<()>;
+    /// Checks pressures against safe limits, listing the nodes outside them
+    /// and the routing paths feeding those nodes. See
+    /// [`pressure::find_pressure_issues`].
+    fn validate_pressures(
+        &self,
+        routing: &OptimizedRouting,
+        simulation: &PressureSimulation,
+    ) -> Result<PressureValidation>;
 }
 
 /// Trait for G-code generation.
@@ -300,6 +367,14 @@ pub struct Mesh {
     /// Optional vertex normals
     pub normals: Option<Vec<f32>>,
 
+    /// Material channel per triangle, for multi-material models; slicing
+    /// assigns each region the channel of the faces it is cut from
+    pub face_channels: Option<Vec<u8>>,
+
+    /// Object id per triangle when several models share the plate; slicing
+    /// tags each region with the object of the faces it is cut from
+    pub face_objects: Option<Vec<u32>>,
+
     /// Model units (mm assumed if not specified)
     pub units: MeshUnits,
 }
@@ -357,6 +432,30 @@ impl Mesh {
         self.units = target;
     }
 
+    /// Calculates enclosed volume using signed tetrahedra (mm³ for mm meshes).
+    ///
+    /// Only meaningful for closed meshes; the absolute value is returned so
+    /// inverted winding does not produce a negative volume.
+    pub fn volume(&self) -> f32 {
+        let vertex = |i: u32| {
+            let i = i as usize * 3;
+            (self.vertices[i], self.vertices[i + 1], self.vertices[i + 2])
+        };
+
+        let signed: f32 = self
+            .indices
+            .chunks_exact(3)
+            .map(|tri| {
+                let (ax, ay, az) = vertex(tri[0]);
+                let (bx, by, bz) = vertex(tri[1]);
+                let (cx, cy, cz) = vertex(tri[2]);
+                ax * (by * cz - bz * cy) - ay * (bx * cz - bz * cx) + az * (bx * cy - by * cx)
+            })
+            .sum();
+
+        (signed / 6.0).abs()
+    }
+
     /// Validates mesh integrity.
     pub fn validate(&self) -> Result<()> {
         if self.vertices.is_empty() {
@@ -382,12 +481,32 @@ impl Mesh {
             }
         }
 
+        if let Some(channels) = &self.face_channels {
+            if channels.len() != self.indices.len() / 3 {
+                anyhow::bail!(
+                    "{} face channels for {} triangles",
+                    channels.len(),
+                    self.indices.len() / 3
+                );
+            }
+        }
+
+        if let Some(objects) = &self.face_objects {
+            if objects.len() != self.indices.len() / 3 {
+                anyhow::bail!(
+                    "{} face objects for {} triangles",
+                    objects.len(),
+                    self.indices.len() / 3
+                );
+            }
+        }
+
         Ok(())
     }
 }
 
 /// A 2D slice of the mesh at a specific Z height.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LayerSlice {
     /// Z height of this slice
     pub z_height: f32,
@@ -400,7 +519,7 @@ pub struct LayerSlice {
 }
 
 /// A polygonal region in a layer.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Region {
     /// Outer boundary polygon
     pub outer: Vec<(f32, f32)>,
@@ -410,6 +529,85 @@ pub struct Region {
 
     /// Material channel for this region
     pub material_channel: u8,
+
+    /// Object (mesh) the region was sliced from, tagged into the layer so
+    /// the object can be cancelled mid-print
+    pub object_id: Option<u32>,
+
+    /// Flow and temperature from the modifier meshes the region lies in
+    #[serde(default)]
+    pub overrides: core::RegionOverrides,
+}
+
+impl Region {
+    /// Outer boundary, counter-clockwise.
+    pub fn outer_polygon(&self) -> utils::Polygon {
+        utils::Polygon::from_tuples(&self.outer).oriented(true)
+    }
+
+    /// Holes, clockwise.
+    pub fn hole_polygons(&self) -> Vec<utils::Polygon> {
+        self.holes
+            .iter()
+            .map(|hole| utils::Polygon::from_tuples(hole).oriented(false))
+            .collect()
+    }
+
+    /// Material area, excluding holes.
+    pub fn area(&self) -> f32 {
+        self.outer_polygon().area() - self.hole_polygons().iter().map(|h| h.area()).sum::<f32>()
+    }
+
+    pub fn contains_point(&self, x: f32, y: f32) -> bool {
+        let point = utils::Point2D::new(x, y);
+        self.outer_polygon().contains_point(point)
+            && !self.hole_polygons().iter().any(|h| h.contains_point(point))
+    }
+
+    pub fn union(&self, other: &Region) -> Vec<Region> {
+        self.clip(std::slice::from_ref(other), utils::ClipOp::Union)
+    }
+
+    pub fn intersection(&self, other: &Region) -> Vec<Region> {
+        self.clip(std::slice::from_ref(other), utils::ClipOp::Intersection)
+    }
+
+    pub fn difference(&self, other: &Region) -> Vec<Region> {
+        self.clip(std::slice::from_ref(other), utils::ClipOp::Difference)
+    }
+
+    /// Applies `op` between this region and the union of `others`. The
+    /// pieces keep this region's material channel and object.
+    pub fn clip(&self, others: &[Region], op: utils::ClipOp) -> Vec<Region> {
+        let clip: Vec<utils::Polygon> = others.iter().flat_map(|r| r.rings()).collect();
+        self.with_rings(utils::clipping::boolean(&self.rings(), &clip, op))
+    }
+
+    /// Grows (positive `delta`) or shrinks the region with rounded corners;
+    /// holes shrink as the region grows. Shrinking may split the region or
+    /// remove it.
+    pub fn offset(&self, delta: f32) -> Vec<Region> {
+        self.with_rings(utils::clipping::offset(&self.rings(), delta))
+    }
+
+    fn rings(&self) -> Vec<utils::Polygon> {
+        let mut rings = vec![self.outer_polygon()];
+        rings.extend(self.hole_polygons());
+        rings
+    }
+
+    fn with_rings(&self, rings: Vec<utils::Polygon>) -> Vec<Region> {
+        utils::clipping::group_rings(rings)
+            .into_iter()
+            .map(|(outer, holes)| Region {
+                outer: outer.to_tuples(),
+                holes: holes.iter().map(|h| h.to_tuples()).collect(),
+                material_channel: self.material_channel,
+                object_id: self.object_id,
+                overrides: self.overrides,
+            })
+            .collect()
+    }
 }
 
 /// Valve grid configuration.
@@ -424,7 +622,7 @@ pub struct ValveGridConfig {
 }
 
 /// Map of which valve nodes should be active for a layer.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ValveActivationMap {
     pub layer_number: u32,
     pub z_height: f32,
@@ -432,11 +630,15 @@ pub struct ValveActivationMap {
 }
 
 /// A single active valve node.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ActiveNode {
     pub position: GridCoordinate,
     pub material_channel: u8,
     pub required_valves: Vec<u8>, // Which valves must be open
+    pub object_id: Option<u32>,
+    /// Fraction of a full node's extrusion for partially covered boundary
+    /// nodes (G4D `extrusion` field); None deposits the full amount
+    pub extrusion: Option<f32>,
 }
 
 /// Routing configuration parameters.
@@ -445,6 +647,9 @@ pub struct RoutingConfig {
     pub injection_points: Vec<GridCoordinate>,
     pub max_path_length: u32,
     pub pressure_limit: f32,
+    /// Grid size in nodes; paths never leave it
+    pub grid_width: u32,
+    pub grid_height: u32,
 }
 
 /// Optimized routing result.
@@ -453,6 +658,7 @@ pub struct OptimizedRouting {
     pub activation_map: ValveActivationMap,
     pub routing_paths: Vec<RoutingPath>,
     pub estimated_pressure: HashMap<GridCoordinate, f32>,
+    pub efficiency: f32, // 0.0 = poor, 1.0 = every path is a shortest path
 }
 
 /// A path material takes through the network.
@@ -482,6 +688,68 @@ pub struct PressureSimulation {
     pub pressure_stable: bool,
 }
 
+/// Why a node's simulated pressure is unsafe.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PressureIssueKind {
+    /// Above the maximum operating pressure
+    Overpressure,
+    /// Below the minimum operating pressure; the node under-extrudes
+    Starved,
+}
+
+/// A node whose simulated pressure is outside the safe range.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PressureIssue {
+    pub position: GridCoordinate,
+    pub kind: PressureIssueKind,
+    /// Simulated pressure (PSI)
+    pub pressure: f32,
+    /// Indices into `OptimizedRouting::routing_paths` ending at or passing
+    /// through the node
+    pub paths: Vec<usize>,
+}
+
+/// Result of [`PressureSimulator::validate_pressures`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PressureValidation {
+    /// Issues ordered by row, then column
+    pub issues: Vec<PressureIssue>,
+}
+
+impl PressureValidation {
+    /// True if every node is within limits.
+    pub fn is_ok(&self) -> bool {
+        self.issues.is_empty()
+    }
+
+    /// Issue counts and the worst node of each kind, for error messages.
+    pub fn summary(&self) -> String {
+        let mut parts = Vec::new();
+        for (kind, label) in [
+            (PressureIssueKind::Overpressure, "over pressure"),
+            (PressureIssueKind::Starved, "starved"),
+        ] {
+            let of_kind = self.issues.iter().filter(|i| i.kind == kind);
+            let worst = match kind {
+                PressureIssueKind::Overpressure => of_kind.clone().max_by(|a, b| a.pressure.total_cmp(&b.pressure)),
+                PressureIssueKind::Starved => of_kind.clone().min_by(|a, b| a.pressure.total_cmp(&b.pressure)),
+            };
+            if let Some(worst) = worst {
+                parts.push(format!(
+                    "{} nodes {} (worst {:.1} PSI at node {},{} fed by {} paths)",
+                    of_kind.count(),
+                    label,
+                    worst.pressure,
+                    worst.position.x,
+                    worst.position.y,
+                    worst.paths.len()
+                ));
+            }
+        }
+        parts.join(", ")
+    }
+}
+
 /// Fully processed layer ready for G-code generation.
 #[derive(Debug, Clone)]
 pub struct ProcessedLayer {
@@ -490,6 +758,12 @@ pub struct ProcessedLayer {
     pub routing: OptimizedRouting,
     pub pressure_sim: PressureSimulation,
     pub timing: LayerTiming,
+    /// Staggered valve opening chosen by the pressure gate, copied into
+    /// the written layer; None opens every valve at once
+    pub activation_groups: Option<gcode_types::ActivationGroups>,
+    /// Problems found processing the layer (pressure issues, closure
+    /// spikes), reported against it
+    pub warnings: Vec<String>,
 }
 
 /// Timing information for a layer.
@@ -505,9 +779,15 @@ pub struct LayerTiming {
 pub struct SliceMetadata {
     pub printer_config_hash: [u8; 32],
     pub material_profiles: Vec<MaterialProfile>,
-    pub print_settings: PrintSettings,
+    /// Resolved settings and the presets/overrides that produced them
+    pub print_settings: ResolvedSettings,
     pub model_name: String,
     pub slicer_version: String,
+    /// Objects on the plate, for cancel-object; empty for a single model
+    pub objects: Vec<core::ObjectInfo>,
+    /// Expected material per channel (g), index = channel: the
+    /// `SliceResult::material_usage` the firmware checks consumption against
+    pub material_usage: Vec<f32>,
 }
 
 // Implementation Skeletons
@@ -517,33 +797,108 @@ pub struct Slicer {
     printer_config: PrinterConfig,
     print_settings: PrintSettings,
     slicer_config: SlicerConfig,
-    model_loader: Box<dyn ModelLoader>,
+    /// Picks the loader by file extension
+    model_loader: core::AutoLoader,
     layer_generator: Box<dyn LayerGenerator>,
     valve_mapper: Box<dyn ValveMapper>,
+    /// Boundary treatment of `valve_mapper`, part of the valve map cache key
+    boundary: core::BoundaryMode,
     routing_optimizer: Box<dyn RoutingOptimizer>,
     pressure_simulator: Box<dyn PressureSimulator>,
+    /// Higher-fidelity simulator used with `PressureFidelity::CoSimulation`
+    co_simulator: Option<Box<dyn PressureSimulator>>,
     gcode_generator: Box<dyn GCodeGenerator>,
+    /// Profiles of the loaded materials, index = channel
+    material_profiles: Vec<MaterialProfile>,
+    time_estimator: core::TimeEstimator,
+    transforms: Vec<core::MeshTransform>,
+    post_processors: Vec<Box<dyn gcode::CommandPostProcessor>>,
     progress_callback: Option<ProgressCallback>,
+    /// Stage artifacts reused across re-slices, if enabled
+    cache: Option<core::SliceCache>,
+    /// Thread pool for the per-layer stages
+    pipeline: core::LayerPipeline,
+    /// Modifier meshes from `PrintSettings::modifiers`
+    modifiers: core::ModifierSet,
+    /// Configuration warnings carried into every `SliceResult`
+    settings_warnings: Vec<String>,
 }
 
 impl Slicer {
     /// Creates a new slicer with given configurations.
     pub fn new(printer_config: PrinterConfig, print_settings: PrintSettings) -> Self {
-        todo!("Implementation needed: Initialize slicer with default implementations of all traits")
+        Self::with_config(printer_config, print_settings, SlicerConfig::default())
     }
 
     /// Creates slicer with custom configuration.
+    ///
+    /// # Panics
+    ///
+    /// If the worker thread pool cannot be created.
     pub fn with_config(
         printer_config: PrinterConfig,
         print_settings: PrintSettings,
         slicer_config: SlicerConfig,
     ) -> Self {
-        todo!("Implementation needed: Initialize slicer with custom configuration")
+        let layer_height = print_settings.layer_height;
+        let spacing = printer_config.valve_array.grid_spacing;
+        let boundary = core::BoundaryMode::default();
+        let shells = core::ShellGenerator::load(&print_settings).unwrap_or_else(|e| {
+            warn!("Grading infill without its painted regions: {:#}", e);
+            core::ShellGenerator::new(&print_settings)
+        });
+        let (min_pressure, max_pressure) = operating_pressure(&printer_config);
+        let pipeline =
+            core::LayerPipeline::from_config(&slicer_config).expect("Failed to build slicer thread pool");
+
+        let mut slicer = Self {
+            model_loader: core::AutoLoader::new(),
+            layer_generator: Box::new(AdaptiveLayerGenerator::new(layer_height, layer_height)),
+            valve_mapper: Box::new(
+                GridAlignedMapper::new(core::valve_mapper::RoundingMode::Nearest)
+                    .with_boundary(boundary)
+                    .with_shells(shells),
+            ),
+            boundary,
+            routing_optimizer: Box::new(AStarOptimizer::new()),
+            pressure_simulator: Box::new(FluidFlowSimulator::new(0.5).with_limits(min_pressure, max_pressure)),
+            co_simulator: None,
+            gcode_generator: Box::new(
+                StandardGCodeGenerator::new(printer_config.thermal.clone()).with_grid(spacing, layer_height),
+            ),
+            material_profiles: Vec::new(),
+            time_estimator: core::TimeEstimator::new(&printer_config),
+            transforms: Vec::new(),
+            post_processors: Vec::new(),
+            progress_callback: None,
+            cache: None,
+            pipeline,
+            modifiers: core::ModifierSet::new(),
+            settings_warnings: Vec::new(),
+            printer_config,
+            print_settings,
+            slicer_config,
+        };
+        if let Err(e) = slicer.load_modifiers() {
+            warn!("Slicing without modifier meshes: {:#}", e);
+        }
+        slicer
     }
 
     /// Sets a progress callback for monitoring.
     pub fn set_progress_callback(&mut self, callback: ProgressCallback) {
-        todo!("Implementation needed: Store progress callback")
+        self.progress_callback = Some(callback);
+    }
+
+    /// Sets the profiles of the loaded materials, index = channel. Every
+    /// channel the model deposits needs one.
+    pub fn set_material_profiles(&mut self, profiles: Vec<MaterialProfile>) {
+        self.material_profiles = profiles;
+    }
+
+    /// Profiles of the loaded materials, index = channel.
+    pub fn material_profiles(&self) -> &[MaterialProfile] {
+        &self.material_profiles
     }
 
     /// Slices a 3D model file and writes output.
@@ -552,55 +907,836 @@ impl Slicer {
         input_path: P,
         output_path: Q,
     ) -> Result<SliceResult> {
-        todo!("Implementation needed: Complete slicing workflow from file input to file output")
+        let started = Instant::now();
+        let input_path = input_path.as_ref();
+        let mesh = self.load_model(input_path)?;
+        let name = input_path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_else(|| input_path.display().to_string());
+        self.slice_to_file(&mesh, name, Vec::new(), output_path.as_ref(), started)
     }
 
     /// Slices a mesh directly (for programmatic use).
+    ///
+    /// The mesh is sliced as is, without the placement steps.
     pub fn slice_mesh(&self, mesh: &Mesh) -> Result<Vec<Layer>> {
-        todo!("Implementation needed: Slice mesh and return layer structures")
+        self.validate_model(mesh)?;
+        let sliced = self.slice_stages(mesh)?;
+        Ok(self.generate_output(sliced)?.layers)
     }
 
     /// Validates that model can be sliced with current configuration.
     pub fn validate_model(&self, mesh: &Mesh) -> Result<()> {
-        todo!("Implementation needed: Check mesh fits build volume, validate geometry")
+        self.report_phase(SlicePhase::ValidatingGeometry, 0.0, None);
+        mesh.validate().map_err(|e| SlicerError::InvalidGeometry(e.to_string()))?;
+
+        let (min_x, min_y, min_z, max_x, max_y, max_z) = mesh.bounding_box();
+        let volume = &self.printer_config.build_volume;
+        let corners = [(min_x, min_y, min_z), (max_x, max_y, max_z)];
+        if let Some(&(x, y, z)) = corners.iter().find(|&&(x, y, z)| !point_in_build_volume(x, y, z, volume)) {
+            return Err(SlicerError::BuildVolumeExceeded(format!(
+                "Model reaches ({:.1}, {:.1}, {:.1})mm, outside the {}x{}x{}mm build volume",
+                x, y, z, volume.x, volume.y, volume.z
+            ))
+            .into());
+        }
+        self.report_phase(SlicePhase::ValidatingGeometry, 1.0, None);
+        Ok(())
+    }
+
+    /// Replaces the placement steps applied to models before slicing.
+    pub fn set_transforms(&mut self, transforms: Vec<core::MeshTransform>) {
+        self.transforms = transforms;
+    }
+
+    /// Appends a placement step.
+    pub fn add_transform(&mut self, transform: core::MeshTransform) {
+        self.transforms.push(transform);
+    }
+
+    /// Placement steps applied to models before slicing, in order.
+    pub fn transforms(&self) -> &[core::MeshTransform] {
+        &self.transforms
+    }
+
+    /// Applies the placement steps to a mesh and drops it onto the plate.
+    ///
+    /// Auto-orientation measures support on this printer's valve grid.
+    pub fn place_mesh(&self, mesh: &mut Mesh) -> Result<()> {
+        core::apply_transforms(
+            mesh,
+            &self.transforms,
+            self.printer_config.valve_array.grid_spacing,
+        )
+        .context("Failed to place model on build plate")
+    }
+
+    /// Loads the modifier meshes listed in the print settings. They are in
+    /// the coordinates of the placed model and are not placed themselves.
+    pub fn load_modifiers(&mut self) -> Result<()> {
+        self.modifiers = core::ModifierSet::load(&self.print_settings.modifiers, &core::AutoLoader::new())?;
+        if !self.modifiers.is_empty() {
+            info!("Loaded {} modifier meshes", self.modifiers.modifiers().len());
+        }
+        Ok(())
+    }
+
+    /// Modifier meshes applied to the layer slices.
+    pub fn modifiers(&self) -> &core::ModifierSet {
+        &self.modifiers
+    }
+
+    /// Reuses layer slices and valve maps from earlier runs whose inputs
+    /// match (see [`core::slice_cache`]).
+    pub fn set_cache(&mut self, cache: core::SliceCache) {
+        self.cache = Some(cache);
+    }
+
+    /// Cache hit and miss counts, if caching is enabled.
+    pub fn cache_stats(&self) -> Option<core::slice_cache::CacheStats> {
+        self.cache.as_ref().map(core::SliceCache::stats)
+    }
+
+    /// Attaches an external pressure simulator, consulted instead of the
+    /// built-in one when `SlicerConfig::pressure_fidelity` is
+    /// `CoSimulation`.
+    pub fn set_co_simulator(&mut self, simulator: Box<dyn PressureSimulator>) {
+        if self.slicer_config.pressure_fidelity != PressureFidelity::CoSimulation {
+            warn!("Co-simulator attached but pressure fidelity is internal; it will not be used");
+        }
+        self.co_simulator = Some(simulator);
+    }
+
+    /// The simulator pressure checks run against under the configured
+    /// fidelity.
+    pub fn pressure_simulator(&self) -> &dyn PressureSimulator {
+        match (&self.co_simulator, self.slicer_config.pressure_fidelity) {
+            (Some(simulator), PressureFidelity::CoSimulation) => simulator.as_ref(),
+            _ => self.pressure_simulator.as_ref(),
+        }
+    }
+
+    /// Records the warnings of a settings check, e.g. material pairs the
+    /// compatibility matrix warns about, so each `SliceResult` lists them.
+    pub fn record_settings_warnings(&mut self, report: &config::SettingsReport) {
+        self.settings_warnings = report.warnings().map(|issue| issue.to_string()).collect();
+    }
+
+    /// Appends a post-processor; processors run in the order added.
+    pub fn add_post_processor(&mut self, processor: Box<dyn gcode::CommandPostProcessor>) {
+        info!("Registered post-processor '{}'", processor.name());
+        self.post_processors.push(processor);
+    }
+
+    /// Names of registered post-processors, in run order.
+    pub fn post_processor_names(&self) -> Vec<&str> {
+        self.post_processors.iter().map(|p| p.name()).collect()
+    }
+
+    /// Runs the post-processors over one generated layer.
+    pub fn post_process_layer(
+        &self,
+        context: &gcode::LayerContext,
+        commands: &mut Vec<Command>,
+    ) -> Result<()> {
+        gcode::postprocess::run_post_processors(&self.post_processors, context, commands)
     }
 
-    /// Estimates print time without full slicing.
+    /// Loads several models as one plate, each optionally forced onto a
+    /// material channel, and either packs them with `spacing` between
+    /// footprints or, with `arrange` false, checks their own placement.
+    pub fn load_plate(&self, inputs: &[(PathBuf, Option<u8>)], spacing: f32, arrange: bool) -> Result<core::Plate> {
+        let channels = self.printer_config.materials.channel_count;
+        let mut plate = core::Plate::new(spacing);
+        for (path, channel) in inputs {
+            if let Some(channel) = channel.filter(|&c| c >= channels) {
+                return Err(SlicerError::MaterialIncompatibility(format!(
+                    "{} assigned to channel {}, printer has {}",
+                    path.display(),
+                    channel,
+                    channels
+                ))
+                .into());
+            }
+            let mesh = self.load_model(path)?;
+            let name = path
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+                .unwrap_or_else(|| path.display().to_string());
+            plate.add(name, mesh, *channel)?;
+        }
+
+        if arrange {
+            plate.arrange(&self.printer_config.build_volume)?;
+        }
+        plate.check(&self.printer_config.build_volume)?;
+        info!("Plate of {} objects ready", plate.objects().len());
+        Ok(plate)
+    }
+
+    /// Slices a plate of objects into one output file.
+    ///
+    /// Regions carry their object's id, so the written layers tag nodes for
+    /// cancel-object, and the metadata lists the objects.
+    pub fn slice_plate<Q: AsRef<Path>>(&self, plate: &core::Plate, output_path: Q) -> Result<SliceResult> {
+        let started = Instant::now();
+        let mesh = plate.merge()?;
+        let name = plate.objects().iter().map(|o| o.info.name.as_str()).collect::<Vec<_>>().join(", ");
+        self.slice_to_file(&mesh, name, plate.infos(), output_path.as_ref(), started)
+    }
+
+    /// Estimates layer count, print time and material without full slicing.
+    ///
+    /// Counts the valve nodes each layer of the mesh fills (see
+    /// [`core::estimate`]) instead of slicing, mapping and routing, so it
+    /// finishes in seconds even for large models. Skirt, brim and raft are
+    /// sized from the model's bounding rectangle.
+    pub fn estimate(&self, mesh: &Mesh) -> Result<core::PrintEstimate> {
+        mesh.validate()?;
+
+        let spacing = self.printer_config.valve_array.grid_spacing;
+        let voxelizer = core::MeshVoxelizer::new(&self.print_settings, spacing);
+        let mut estimate = voxelizer.estimate(mesh, &self.time_estimator);
+
+        let adhesion = core::AdhesionGenerator::new(&self.print_settings, spacing);
+        if !adhesion.is_empty() && estimate.layer_count > 0 {
+            let (min_x, min_y, _, max_x, max_y, _) = mesh.bounding_box();
+            let footprint = Region {
+                outer: vec![(min_x, min_y), (max_x, min_y), (max_x, max_y), (min_x, max_y)],
+                holes: Vec::new(),
+                material_channel: estimate.material_mm3.keys().next().copied().unwrap_or(0),
+                object_id: None,
+                overrides: core::RegionOverrides::default(),
+            };
+            let footprint = [footprint];
+            let volume = adhesion.volume_mm3(&footprint);
+            let node_volume = spacing * spacing * self.print_settings.first_layer_height;
+            let nodes = if node_volume > 0.0 { (volume / node_volume).ceil() as u32 } else { 0 };
+            let extra = core::LayerWorkload {
+                valve_switches: nodes,
+                pressure_waits: adhesion.raft_layers(),
+                z_travel_mm: adhesion.z_offset(),
+                deposited_volume_mm3: volume,
+            };
+            estimate.estimated_time += self.time_estimator.estimate_layer(&extra);
+            estimate.layer_count += adhesion.raft_layers();
+            estimate.node_count += nodes as u64;
+            *estimate.material_mm3.entry(adhesion.channel(&footprint)).or_default() += volume;
+        }
+        Ok(estimate)
+    }
+
+    /// Estimates print time without full slicing (see [`estimate`](Self::estimate)).
     pub fn estimate_time(&self, mesh: &Mesh) -> Result<Duration> {
-        todo!("Implementation needed: Quick estimation of print time")
+        Ok(self.estimate(mesh)?.estimated_time)
+    }
+
+    /// Returns the time estimator used for print time predictions.
+    pub fn time_estimator(&self) -> &core::TimeEstimator {
+        &self.time_estimator
+    }
+
+    /// Replaces the time model coefficients, e.g. with a calibration saved
+    /// from an earlier [`calibrate_time_model`](Self::calibrate_time_model).
+    pub fn set_time_coefficients(&mut self, coefficients: core::EstimatorCoefficients) {
+        self.time_estimator = self.time_estimator.clone().with_coefficients(coefficients);
     }
 
-    /// Estimates material usage without full slicing.
+    /// Refits the time model from layer durations reported by the firmware.
+    pub fn calibrate_time_model(
+        &mut self,
+        reports: &[protocol::LayerTimingReport],
+    ) -> core::time_estimator::CalibrationSummary {
+        let summary = self.time_estimator.calibrate(reports);
+        info!(
+            "Time model calibrated from {} layers: mean error {:.2}s -> {:.2}s",
+            summary.samples_used, summary.error_before, summary.error_after
+        );
+        summary
+    }
+
+    /// Estimates material per channel (mm³) without full slicing (see
+    /// [`estimate`](Self::estimate)), including skirt, brim and raft.
     pub fn estimate_material(&self, mesh: &Mesh) -> Result<HashMap<u8, f32>> {
-        todo!("Implementation needed: Estimate material usage per channel")
+        Ok(self.estimate(mesh)?.material_mm3.into_iter().collect())
     }
 
     // Private helper methods
 
     fn report_progress(&self, progress: SliceProgress) {
-        todo!("Implementation needed: Call progress callback if set")
+        if let Some(callback) = &self.progress_callback {
+            callback(progress);
+        }
+    }
+
+    /// Reports progress through a phase, with the layer reached and the
+    /// layer count for phases that go layer by layer.
+    fn report_phase(&self, phase: SlicePhase, progress: f32, layer: Option<(u32, u32)>) {
+        self.report_progress(SliceProgress {
+            phase,
+            progress,
+            current_layer: layer.map(|(current, _)| current),
+            total_layers: layer.map(|(_, total)| total),
+            message: phase.description().to_string(),
+        });
     }
 
     fn load_model<P: AsRef<Path>>(&self, path: P) -> Result<Mesh> {
-        todo!("Implementation needed: Use model_loader to load file")
+        let path = path.as_ref();
+        self.report_phase(SlicePhase::LoadingModel, 0.0, None);
+        let mut mesh = self
+            .model_loader
+            .load(path)
+            .map_err(|e| SlicerError::ModelLoad(format!("{}: {:#}", path.display(), e)))?;
+        mesh.convert_units(MeshUnits::Millimeters);
+        self.place_mesh(&mut mesh)?;
+        self.report_phase(SlicePhase::LoadingModel, 1.0, None);
+        Ok(mesh)
+    }
+
+    /// Slices a placed mesh and writes it to `output_path`.
+    fn slice_to_file(
+        &self,
+        mesh: &Mesh,
+        model_name: String,
+        objects: Vec<core::ObjectInfo>,
+        output_path: &Path,
+        started: Instant,
+    ) -> Result<SliceResult> {
+        self.validate_model(mesh)?;
+        let sliced = self.slice_stages(mesh)?;
+        let output = self.generate_output(sliced)?;
+
+        let metadata = SliceMetadata {
+            printer_config_hash: hash_printer_config(&self.printer_config),
+            material_profiles: self.material_profiles.clone(),
+            print_settings: ResolvedSettings { settings: self.print_settings.clone(), applied: Vec::new() },
+            model_name,
+            slicer_version: SLICER_VERSION.to_string(),
+            objects,
+            material_usage: Vec::new(),
+        };
+        let report_metadata = self.slicer_config.write_report.then(|| metadata.clone());
+        let grams = self.write_output(&output, output_path, metadata)?;
+
+        let result = SliceResult {
+            layer_count: output.layers.len() as u32,
+            estimated_time: output
+                .layers
+                .iter()
+                .filter_map(Layer::expected_duration)
+                .map(Duration::from_secs_f32)
+                .sum(),
+            material_usage: grams
+                .iter()
+                .enumerate()
+                .filter(|&(_, &g)| g > 0.0)
+                .map(|(channel, &g)| (channel as u8, g))
+                .collect(),
+            elapsed_time: started.elapsed(),
+            warnings: self.settings_warnings.clone(),
+            output_path: output_path.to_path_buf(),
+            bounding_box: mesh.bounding_box(),
+        };
+        info!(
+            "Sliced {} layers to {} in {:.1}s",
+            result.layer_count,
+            output_path.display(),
+            result.elapsed_time.as_secs_f32()
+        );
+        if let Some(metadata) = report_metadata {
+            let report = gcode::SliceReport::new(&result, &metadata, output.stats, output.warnings);
+            report.write(&gcode::SliceReport::path_for(output_path))?;
+        }
+        Ok(result)
+    }
+
+    /// Slices a mesh and maps, routes and checks every layer, in order.
+    fn slice_stages(&self, mesh: &Mesh) -> Result<Vec<SlicedLayer>> {
+        let slices_key = match self.cache {
+            Some(_) => Some(core::CacheKey::slices(
+                &core::CacheKey::of_mesh(mesh),
+                &self.print_settings,
+                &self.modifiers,
+            )?),
+            None => None,
+        };
+        let slices = self.generate_all_layers(mesh, slices_key.as_ref())?;
+        let maps = self.map_all_layers(&slices, slices_key.as_ref())?;
+
+        let total = slices.len() as u32;
+        let mut sliced = Vec::with_capacity(slices.len());
+        self.pipeline.run(
+            slices.into_iter().zip(maps),
+            |(slice, map)| Ok(SlicedLayer { processed: self.process_layer(map)?, slice }),
+            |layer| {
+                sliced.push(layer);
+                self.report_phase(
+                    SlicePhase::CalculatingPressure,
+                    sliced.len() as f32 / total as f32,
+                    Some((sliced.len() as u32, total)),
+                );
+                Ok(())
+            },
+        )?;
+        Ok(sliced)
+    }
+
+    /// Layer slices of a placed mesh with the adhesion aids added. With a
+    /// cache and `key`, the slices are reused from earlier runs.
+    fn generate_all_layers(&self, mesh: &Mesh, key: Option<&core::CacheKey>) -> Result<Vec<LayerSlice>> {
+        self.report_phase(SlicePhase::GeneratingLayers, 0.0, None);
+        let generate = || {
+            let heights = self.layer_generator.calculate_layer_heights(mesh, &self.print_settings)?;
+            let mut slices = self.layer_generator.generate_layers(mesh, &heights)?;
+            if !self.modifiers.is_empty() {
+                let (_, _, mut bottom, _, _, _) = mesh.bounding_box();
+                for slice in &mut slices {
+                    let top = slice.z_height;
+                    self.modifiers.apply(slice, top - bottom);
+                    bottom = top;
+                }
+            }
+            Ok(slices)
+        };
+        let slices = match (&self.cache, key) {
+            (Some(cache), Some(key)) => cache.get_or_compute(core::CacheStage::Slices, key, generate)?,
+            _ => generate()?,
+        };
+        if slices.is_empty() {
+            return Err(SlicerError::LayerGeneration("Model is too thin for a single layer".to_string()).into());
+        }
+
+        let spacing = self.printer_config.valve_array.grid_spacing;
+        let slices = core::AdhesionGenerator::new(&self.print_settings, spacing).apply(slices);
+        debug!("Generated {} layers", slices.len());
+        self.report_phase(SlicePhase::GeneratingLayers, 1.0, None);
+        Ok(slices)
+    }
+
+    /// Valve maps of every slice, in order. Vase mode layers printed as a
+    /// helix get empty maps. With a cache and the slices' `key`, the maps
+    /// are reused from earlier runs.
+    fn map_all_layers(
+        &self,
+        slices: &[LayerSlice],
+        key: Option<&core::CacheKey>,
+    ) -> Result<Vec<ValveActivationMap>> {
+        let grid = self.grid_config();
+        let stack = core::SliceStack::new(slices);
+        let total = slices.len() as u32;
+        let map = || {
+            let mut maps = Vec::with_capacity(slices.len());
+            self.pipeline.run(
+                slices,
+                |slice| {
+                    if self.spirals(slice.layer_number) {
+                        return Ok(ValveActivationMap {
+                            layer_number: slice.layer_number,
+                            z_height: slice.z_height,
+                            active_nodes: Vec::new(),
+                        });
+                    }
+                    self.valve_mapper.map_in_stack(slice, &grid, Some(&stack))
+                },
+                |map| {
+                    maps.push(map);
+                    self.report_phase(
+                        SlicePhase::MappingValves,
+                        maps.len() as f32 / total as f32,
+                        Some((maps.len() as u32, total)),
+                    );
+                    Ok(())
+                },
+            )?;
+            Ok(maps)
+        };
+
+        match (&self.cache, key) {
+            (Some(cache), Some(key)) => {
+                let maps_key =
+                    core::CacheKey::valve_maps(key, &self.printer_config, &self.print_settings, self.boundary)?;
+                cache.get_or_compute(core::CacheStage::ValveMaps, &maps_key, map)
+            }
+            _ => map(),
+        }
+    }
+
+    /// Routes a layer's valve map and checks its pressures.
+    fn process_layer(&self, activation_map: ValveActivationMap) -> Result<ProcessedLayer> {
+        let layer_number = activation_map.layer_number;
+        let z_height = activation_map.z_height;
+        let node_count = activation_map.active_nodes.len();
+
+        let routing = if self.slicer_config.enable_routing_optimization && node_count > 0 {
+            let config = self.routing_config(&activation_map);
+            self.routing_optimizer.optimize_routing(&activation_map, &config)?
+        } else {
+            OptimizedRouting {
+                activation_map,
+                routing_paths: Vec::new(),
+                estimated_pressure: HashMap::new(),
+                efficiency: 1.0,
+            }
+        };
+
+        let mut warnings = Vec::new();
+        let (pressure_sim, activation_groups) = if self.slicer_config.enable_pressure_simulation && node_count > 0 {
+            let config = self.pressure_config(&routing.activation_map)?;
+            let report =
+                pressure::PressureGate::new(self.pressure_simulator(), config.clone()).check_layer(&routing)?;
+            if let Some(groups) = report.groups {
+                let pressures = report.issues.iter().map(|issue| issue.pressure);
+                let (low, high) = pressures
+                    .fold((f32::INFINITY, f32::NEG_INFINITY), |(low, high), p| (low.min(p), high.max(p)));
+                warnings.push(format!(
+                    "{} nodes at {:.1} to {:.1} PSI opening at once, staggered into {} groups",
+                    report.issues.len(),
+                    low,
+                    high,
+                    groups.count
+                ));
+            }
+            if self.slicer_config.transient_pressure {
+                let transients = self
+                    .transient_simulator(&routing.activation_map, &config)?
+                    .check_layer(&routing.activation_map, report.groups);
+                if transients.exceeds_threshold() {
+                    warnings.push(format!("Closure transient: {}", transients.summary()));
+                }
+            }
+            (report.simulation, report.groups)
+        } else {
+            let idle = PressureSimulation {
+                node_pressures: HashMap::new(),
+                flow_rates: HashMap::new(),
+                max_pressure: 0.0,
+                min_pressure: 0.0,
+                pressure_stable: true,
+            };
+            (idle, None)
+        };
+
+        let spacing = self.printer_config.valve_array.grid_spacing;
+        let nodes: f32 = routing.activation_map.active_nodes.iter().map(|n| n.extrusion.unwrap_or(1.0)).sum();
+        let workload = core::LayerWorkload {
+            valve_switches: node_count as u32,
+            pressure_waits: 0,
+            z_travel_mm: 0.0,
+            deposited_volume_mm3: nodes * spacing * spacing * self.print_settings.layer_height,
+        };
+        let per_switch = self.time_estimator.coefficients().per_valve_switch;
+        let timing = LayerTiming {
+            valve_switching_time: Duration::from_secs_f32((per_switch * node_count as f32).max(0.0)),
+            deposition_time: self.time_estimator.estimate_deposition(&workload),
+            total_time: self.time_estimator.estimate_layer(&workload),
+        };
+
+        Ok(ProcessedLayer {
+            layer_number,
+            z_height,
+            routing,
+            pressure_sim,
+            timing,
+            activation_groups,
+            warnings,
+        })
+    }
+
+    /// Generates and post-processes every layer's commands, in order, and
+    /// converts them to the layers written to the file.
+    ///
+    /// Each layer records its activation groups, its estimated deposition
+    /// time and, as its minimum layer time, the longest cooling time of
+    /// the materials it deposits. Runs of empty layers are then marked for
+    /// fast-forward.
+    fn generate_output(&self, sliced: Vec<SlicedLayer>) -> Result<GeneratedOutput> {
+        let total = sliced.len() as u32;
+        let spacing = self.printer_config.valve_array.grid_spacing;
+        let helix = self
+            .print_settings
+            .vase
+            .is_some()
+            .then(|| core::HelicalSlicer::new(&self.printer_config, &self.print_settings));
+        let mut helix_end = None;
+        let mut previous_z = 0.0;
+        let mut layers = Vec::with_capacity(sliced.len());
+        let mut material_mm3: BTreeMap<u8, f32> = BTreeMap::new();
+        let mut stats = Vec::with_capacity(sliced.len());
+        let mut warnings = Vec::new();
+        let modifier_temperatures = self.modifier_temperatures(&sliced)?;
+        let mut previous_regions = Vec::new();
+        let mut compensator = gcode::DeadVolumeCompensator::new(
+            &self.printer_config,
+            &self.print_settings.dead_volume,
+            &self.material_profiles,
+        )?;
+
+        for SlicedLayer { slice, processed } in sliced {
+            let mut commands = match &helix {
+                Some(helix) if self.spirals(slice.layer_number) && !slice.regions.is_empty() => {
+                    let (commands, end) = helix.revolution(&slice, previous_z, helix_end)?;
+                    helix_end = Some(end);
+                    commands
+                }
+                _ => self.gcode_generator.generate_layer_gcode(&processed, &self.material_profiles)?,
+            };
+            if let Some((generator, planned)) = &modifier_temperatures {
+                let changes = generator.modifier_temperature_commands(planned, &previous_regions, &slice.regions);
+                let at = commands.iter().position(|c| matches!(c, Command::G4L(_))).map_or(0, |i| i + 1);
+                commands.splice(at..at, changes);
+            }
+            previous_regions = slice.regions;
+            // Close what the layer leaves open where the firmware would, so
+            // the closes carry their leads into the written layer
+            commands.extend(gcode::generator::closing_commands(&commands, spacing));
+            let compensated = compensator.apply(&mut commands);
+            if compensated.pulses > 0 {
+                debug!("Layer {}: {} suck-back pulses", processed.layer_number, compensated.pulses);
+            }
+            let context = gcode::LayerContext {
+                layer_number: processed.layer_number,
+                z_height: processed.z_height,
+                total_layers: total,
+            };
+            self.post_process_layer(&context, &mut commands)?;
+            stats.push(gcode::LayerStats::from_layer(&processed, &commands));
+            warnings.extend(processed.warnings.iter().map(|w| (processed.layer_number, w.clone())));
+
+            let mut layer =
+                gcode::generator::layer_from_commands(&commands, processed.layer_number, processed.z_height, spacing);
+            gcode::generator::tag_objects(&mut layer, &processed.routing.activation_map);
+            layer.activation_groups = processed.activation_groups;
+            let report =
+                protocol::LayerTimingReport::from_commands(layer.layer_number, previous_z, &commands, Duration::ZERO);
+            let deposition = self.time_estimator.estimate_deposition(&core::LayerWorkload::from(&report));
+            layer.estimated_time = Some(deposition.as_secs_f32());
+            layer.min_layer_time = self.min_layer_time(&layer);
+            for (channel, mm3) in deposited_by_channel(&commands) {
+                *material_mm3.entry(channel).or_default() += mm3;
+            }
+
+            previous_z = layer.z_height;
+            self.report_phase(
+                SlicePhase::GeneratingGCode,
+                (layers.len() + 1) as f32 / total as f32,
+                Some((layer.layer_number, total)),
+            );
+            layers.push(layer);
+        }
+
+        let runs = core::mark_empty_layers(&mut layers, &self.time_estimator);
+        if !runs.is_empty() {
+            debug!("{} runs of empty layers marked for fast-forward", runs.len());
+        }
+        Ok(GeneratedOutput { layers, material_mm3, stats, warnings })
     }
 
-    fn generate_all_layers(&self, mesh: &Mesh) -> Result<Vec<LayerSlice>> {
-        todo!("Implementation needed: Generate all layer slices")
+    /// Generator and planned zone targets for the temperature changes of
+    /// modifier meshes, if any modifier sets a temperature.
+    fn modifier_temperatures(
+        &self,
+        sliced: &[SlicedLayer],
+    ) -> Result<Option<(StandardGCodeGenerator, BTreeMap<u8, f32>)>> {
+        if !self.modifiers.modifiers().iter().any(|m| m.settings.temperature.is_some()) {
+            return Ok(None);
+        }
+        let used: BTreeSet<u8> =
+            sliced.iter().flat_map(|layer| layer.slice.regions.iter().map(|r| r.material_channel)).collect();
+        let channels = gcode::generator::channel_materials(&used, &self.material_profiles)?;
+        let planned = self
+            .printer_config
+            .thermal
+            .plan_zone_temperatures(&channels)
+            .map_err(|e| SlicerError::MaterialIncompatibility(e.to_string()))?;
+        Ok(Some((StandardGCodeGenerator::new(self.printer_config.thermal.clone()), planned)))
     }
 
-    fn process_layer(&self, slice: LayerSlice) -> Result<ProcessedLayer> {
-        todo!("Implementation needed: Map, optimize, simulate single layer")
+    /// Refuses layers that deposit a used channel where no zone backing it
+    /// can hold its material, before anything is written.
+    fn check_zone_coverage(&self, layers: &[Layer]) -> Result<()> {
+        let channels: Vec<(u8, &MaterialProfile)> = gcode::generator::used_channels(layers)
+            .into_iter()
+            .filter_map(|channel| self.material_profiles.get(channel as usize).map(|profile| (channel, profile)))
+            .collect();
+        let report = GCodeValidator::new(self.printer_config.clone()).validate_zone_coverage(layers, &channels);
+        if report.valid {
+            return Ok(());
+        }
+        let errors: Vec<String> =
+            report.errors().map(|issue| format!("layer {}: {}", issue.layer, issue.message)).collect();
+        Err(SlicerError::GCodeGeneration(errors.join("; ")).into())
     }
 
+    /// Writes the layers to a .hg4d file. Returns the material used per
+    /// channel (g), which the metadata records.
     fn write_output<P: AsRef<Path>>(
         &self,
-        layers: Vec<ProcessedLayer>,
+        output: &GeneratedOutput,
         path: P,
-        metadata: SliceMetadata,
-    ) -> Result<()> {
-        todo!("Implementation needed: Write .hg4d binary file")
+        mut metadata: SliceMetadata,
+    ) -> Result<Vec<f32>> {
+        let path = path.as_ref();
+        self.check_zone_coverage(&output.layers)?;
+        let channels = output
+            .material_mm3
+            .keys()
+            .next_back()
+            .map_or(0, |&channel| channel as usize + 1)
+            .max(metadata.material_profiles.len());
+        metadata.material_usage = (0..channels)
+            .map(|channel| {
+                let mm3 = output.material_mm3.get(&(channel as u8)).copied().unwrap_or(0.0);
+                let density = metadata.material_profiles.get(channel).map_or(0.0, |p| p.properties.density);
+                mm3 * density / 1000.0
+            })
+            .collect();
+        let usage = metadata.material_usage.clone();
+
+        let mut writer = gcode::HG4DWriter::create(path, metadata)
+            .map_err(|e| SlicerError::OutputWrite(format!("{}: {:#}", path.display(), e)))?
+            .with_codec(self.slicer_config.block_codec, self.slicer_config.compression_level);
+        writer.write_header()?;
+        let total = output.layers.len() as u32;
+        for (written, layer) in output.layers.iter().enumerate() {
+            writer.write_layer(layer)?;
+            self.report_phase(
+                SlicePhase::WritingOutput,
+                (written + 1) as f32 / total as f32,
+                Some((layer.layer_number, total)),
+            );
+        }
+        writer.finalize()?;
+        Ok(usage)
+    }
+
+    /// The printer's valve grid, with node (0, 0) at the plate origin.
+    fn grid_config(&self) -> ValveGridConfig {
+        let valves = &self.printer_config.valve_array;
+        ValveGridConfig {
+            spacing: valves.grid_spacing,
+            origin_x: 0.0,
+            origin_y: 0.0,
+            grid_width: self.printer_config.grid_x_count(),
+            grid_height: self.printer_config.grid_y_count(),
+            valves_per_node: valves.valves_per_node,
+        }
+    }
+
+    /// Routing from the injection points feeding a layer's channels, or
+    /// from all of them if none is assigned to those channels.
+    fn routing_config(&self, activation_map: &ValveActivationMap) -> RoutingConfig {
+        let valves = &self.printer_config.valve_array;
+        let channels: BTreeSet<u8> = activation_map.active_nodes.iter().map(|n| n.material_channel).collect();
+        let feeding: Vec<_> =
+            valves.injection_points.iter().filter(|p| channels.contains(&p.material_channel)).collect();
+        let points = if feeding.is_empty() { valves.injection_points.iter().collect() } else { feeding };
+        let to_grid = |mm: f32| (mm / valves.grid_spacing).round().max(0.0) as u32;
+        RoutingConfig {
+            injection_points: points.into_iter().map(|p| GridCoordinate::new(to_grid(p.x), to_grid(p.y))).collect(),
+            max_path_length: self.printer_config.grid_x_count() + self.printer_config.grid_y_count(),
+            pressure_limit: operating_pressure(&self.printer_config).1,
+            grid_width: self.printer_config.grid_x_count(),
+            grid_height: self.printer_config.grid_y_count(),
+        }
+    }
+
+    /// Supply conditions of a layer: the lowest recommended pressure of the
+    /// materials it deposits, within the operating range, and the most
+    /// viscous of them.
+    fn pressure_config(&self, activation_map: &ValveActivationMap) -> Result<PressureConfig> {
+        let channels: BTreeSet<u8> = activation_map.active_nodes.iter().map(|n| n.material_channel).collect();
+        let materials = gcode::generator::channel_materials(&channels, &self.material_profiles)?;
+        let (min_pressure, max_pressure) = operating_pressure(&self.printer_config);
+        let supply = materials.iter().map(|(_, p)| p.extrusion.pressure_psi).fold(max_pressure, f32::min);
+        Ok(PressureConfig {
+            supply_pressure: supply.clamp(min_pressure, max_pressure),
+            material_viscosity: materials.iter().map(|(_, p)| p.properties.viscosity).fold(0.0, f32::max),
+            channel_diameter: pressure::simulator::SUPPLY_LINE_DIAMETER,
+        })
+    }
+
+    /// Closure transient model for a layer, with the densest of the
+    /// materials it deposits.
+    fn transient_simulator(
+        &self,
+        activation_map: &ValveActivationMap,
+        config: &PressureConfig,
+    ) -> Result<pressure::TransientSimulator> {
+        let channels: BTreeSet<u8> = activation_map.active_nodes.iter().map(|n| n.material_channel).collect();
+        let materials = gcode::generator::channel_materials(&channels, &self.material_profiles)?;
+        let Some((_, material)) =
+            materials.into_iter().max_by(|(_, a), (_, b)| a.properties.density.total_cmp(&b.properties.density))
+        else {
+            return Err(SlicerError::PressureSimulation("Layer deposits no material".to_string()).into());
+        };
+        let transient = pressure::TransientConfig::for_printer(
+            &self.printer_config,
+            config,
+            material,
+            self.print_settings.layer_height,
+        );
+        Ok(pressure::TransientSimulator::new(&self.printer_config, transient))
+    }
+
+    /// Longest cooling time of the materials a layer deposits (s).
+    fn min_layer_time(&self, layer: &Layer) -> Option<f32> {
+        gcode::generator::used_channels(std::slice::from_ref(layer))
+            .into_iter()
+            .filter_map(|channel| self.material_profiles.get(channel as usize))
+            .map(|profile| profile.cooling.min_layer_time)
+            .filter(|&seconds| seconds > 0.0)
+            .reduce(f32::max)
+    }
+
+    /// Whether a layer is printed as a helical revolution in vase mode.
+    fn spirals(&self, layer_number: u32) -> bool {
+        self.print_settings.vase.as_ref().is_some_and(|vase| layer_number >= vase.bottom_layers)
+    }
+}
+
+/// A layer's slice and its routed, checked valve map.
+struct SlicedLayer {
+    slice: LayerSlice,
+    processed: ProcessedLayer,
+}
+
+/// Layers ready to write and the material they deposit.
+struct GeneratedOutput {
+    layers: Vec<Layer>,
+    /// Material per channel (mm³)
+    material_mm3: BTreeMap<u8, f32>,
+    /// Statistics of each layer's final commands
+    stats: Vec<gcode::LayerStats>,
+    /// (layer number, warning) raised processing the layers
+    warnings: Vec<(u32, String)>,
+}
+
+/// Operating pressure range (PSI), the maximum capped by the safety limit.
+fn operating_pressure(config: &PrinterConfig) -> (f32, f32) {
+    let pressure = &config.materials.pressure;
+    let max_pressure = pressure.max_pressure.min(config.safety.max_pressure.get());
+    (pressure.min_pressure.min(max_pressure), max_pressure)
+}
+
+/// Material a layer's commands deposit per channel (mm³): each G4D's
+/// extrusion, in the channel of the last G4C before it.
+fn deposited_by_channel(commands: &[Command]) -> BTreeMap<u8, f32> {
+    let mut deposited = BTreeMap::new();
+    let mut channel = None;
+    for command in commands {
+        match command {
+            Command::G4C(select) if select.material_channel.is_some() => channel = select.material_channel,
+            Command::G4D(deposit) => {
+                if let (Some(channel), Some(mm3)) = (channel, deposit.extrusion) {
+                    *deposited.entry(channel).or_default() += mm3;
+                }
+            }
+            _ => {}
+        }
     }
+    deposited
 }
 
 // Module-level utility functions - Fully Implemented
@@ -642,7 +1778,14 @@ pub fn point_in_build_volume(
 pub const SLICER_VERSION: &str = env!("CARGO_PKG_VERSION");
 
 /// Supported .hg4d format version.
-pub const HG4D_FORMAT_VERSION: u32 = 1;
+///
+/// Layer blocks are bincode, which has no field names: a field added to
+/// `Layer`, `LayerFrame` or `NodeValveState` shifts everything after it,
+/// `#[serde(default)]` or not, so every such change needs a new version.
+/// Version 2 added object tags, activation groups, cooling floors,
+/// close-early leads, inspection holds and Z ramps; version 1 files are
+/// rejected and must be re-sliced.
+pub const HG4D_FORMAT_VERSION: u32 = 2;
 
 /// Magic number for .hg4d files (ASCII "HG4D").
 pub const HG4D_MAGIC: u32 = 0x48473444;
@@ -738,6 +1881,8 @@ mod tests {
             ],
             indices: vec![0, 1, 2, 0, 2, 3],
             normals: None,
+            face_channels: None,
+            face_objects: None,
             units: MeshUnits::Millimeters,
         };
 
@@ -750,6 +1895,150 @@ mod tests {
         assert_eq!(max_z, 5.0);
     }
 
+    #[test]
+    fn test_mesh_volume() {
+        // Tetrahedron with legs of 6mm: volume = 6³ / 6 = 36mm³
+        let mesh = Mesh {
+            vertices: vec![
+                0.0, 0.0, 0.0,
+                6.0, 0.0, 0.0,
+                0.0, 6.0, 0.0,
+                0.0, 0.0, 6.0,
+            ],
+            indices: vec![0, 2, 1, 0, 1, 3, 0, 3, 2, 1, 2, 3],
+            normals: None,
+            face_channels: None,
+            face_objects: None,
+            units: MeshUnits::Millimeters,
+        };
+
+        assert!((mesh.volume() - 36.0).abs() < 1e-3);
+    }
+
+    /// 5mm cube standing at (10, 10) on the plate.
+    fn cube() -> Mesh {
+        let vertices = [
+            (0.0, 0.0, 0.0), (5.0, 0.0, 0.0), (5.0, 5.0, 0.0), (0.0, 5.0, 0.0),
+            (0.0, 0.0, 5.0), (5.0, 0.0, 5.0), (5.0, 5.0, 5.0), (0.0, 5.0, 5.0),
+        ];
+        Mesh {
+            vertices: vertices.iter().flat_map(|&(x, y, z)| [x + 10.0, y + 10.0, z]).collect(),
+            indices: vec![
+                0, 2, 1, 0, 3, 2, 4, 5, 6, 4, 6, 7, 0, 1, 5, 0, 5, 4,
+                1, 2, 6, 1, 6, 5, 2, 3, 7, 2, 7, 6, 3, 0, 4, 3, 4, 7,
+            ],
+            normals: None,
+            face_channels: None,
+            face_objects: None,
+            units: MeshUnits::Millimeters,
+        }
+    }
+
+    #[test]
+    fn test_slice_plate_round_trip() {
+        let mut configs = config::ExampleConfigs::for_model(config_types::PrinterModel::HyperCubeMini).unwrap();
+        configs.settings.adhesion.skirt = None;
+        configs.settings.infill.density = 100.0;
+        let slicer_config = SlicerConfig {
+            worker_threads: 2,
+            enable_routing_optimization: false,
+            write_report: true,
+            ..SlicerConfig::default()
+        };
+        let mut slicer = Slicer::with_config(configs.printer.clone(), configs.settings.clone(), slicer_config);
+        slicer.set_material_profiles(configs.materials.iter().map(|(_, m)| m.clone()).collect());
+
+        let mut plate = core::Plate::new(5.0);
+        plate.add("cube.stl", cube(), None).unwrap();
+        let output = std::env::temp_dir().join(format!("hg4d-slice-{}.hg4d", std::process::id()));
+        slicer.settings_warnings = vec!["materials[0]: settings warning".to_string()];
+        let result = slicer.slice_plate(&plate, &output).unwrap();
+
+        // 0.3mm first layer, then 0.2mm up to 5mm
+        assert_eq!(result.layer_count, 25);
+        assert_eq!(result.warnings, slicer.settings_warnings);
+        assert!(result.material_usage[&0] > 0.0);
+        assert!(result.estimated_time > Duration::ZERO);
+
+        let mut reader = gcode::HG4DReader::open(&output).unwrap();
+        assert_eq!(reader.index().len(), 25);
+        let layer = reader.read_layer(10).unwrap();
+        assert!((100..=121).contains(&layer.nodes.len()), "{} nodes", layer.nodes.len());
+        assert!(layer.nodes.iter().all(|n| n.material_channel == Some(0) && n.position.x >= 20));
+        assert_eq!(layer.objects.len(), 1);
+        assert!(layer.estimated_time.is_some_and(|t| t > 0.0));
+        // Dead volume compensation leads every node's close
+        let max_lead = configs.settings.dead_volume.max_lead_ms;
+        assert!(layer.nodes.iter().all(|n| n.close_early_ms.is_some_and(|ms| ms > 0.0 && ms <= max_lead)));
+        std::fs::remove_file(&output).ok();
+
+        // The report sits beside the file, one entry per layer
+        let report_path = gcode::SliceReport::path_for(&output);
+        let report = gcode::SliceReport::load(&report_path).unwrap();
+        assert_eq!(report.layers.len(), 25);
+        assert_eq!(report.result.layer_count, 25);
+        assert!(report.layers[10].deposited_volume_mm3 > 0.0);
+        std::fs::remove_file(&report_path).ok();
+
+        // The same layers without a file
+        assert_eq!(slicer.slice_mesh(&plate.merge().unwrap()).unwrap().len(), 25);
+
+        // Channel 0's zone no longer reaches the cube: nothing is written
+        let mut printer = configs.printer.clone();
+        let zone = printer.thermal.zones_for_channel(0)[0];
+        let zone = printer.thermal.zones.iter_mut().find(|z| z.id == zone).unwrap();
+        zone.regions = vec![config_types::ZoneRegion::Nodes { x: (0, 9), y: (0, 299) }];
+        let mut slicer = Slicer::with_config(printer, configs.settings.clone(), slicer.slicer_config.clone());
+        slicer.set_material_profiles(configs.materials.iter().map(|(_, m)| m.clone()).collect());
+        let error = slicer.slice_plate(&plate, &output).unwrap_err();
+        assert!(error.to_string().contains("outside every zone"), "{:#}", error);
+        assert!(!output.exists());
+    }
+
+    #[test]
+    fn test_modifier_changes_channel_and_temperature() {
+        let mut configs = config::ExampleConfigs::for_model(config_types::PrinterModel::HyperCubeStandard).unwrap();
+        configs.settings.adhesion.skirt = None;
+        let slicer_config = SlicerConfig {
+            worker_threads: 2,
+            enable_routing_optimization: false,
+            ..SlicerConfig::default()
+        };
+        let mut slicer = Slicer::with_config(configs.printer.clone(), configs.settings.clone(), slicer_config);
+        slicer.set_material_profiles(configs.materials.iter().map(|(_, m)| m.clone()).collect());
+
+        // The top half of the cube prints with channel 1 at 215°C
+        let mut upper = cube();
+        upper.vertices.chunks_exact_mut(3).for_each(|v| v[2] = 2.5 + v[2] / 2.0);
+        let settings = config_types::ModifierMesh {
+            path: PathBuf::from("upper.stl"),
+            flow_multiplier: None,
+            temperature: Some(215.0),
+            material_channel: Some(1),
+        };
+        slicer.modifiers.add("upper", upper, settings).unwrap();
+        let heats = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let seen = heats.clone();
+        slicer.add_post_processor(Box::new(gcode::FnPostProcessor::new("heats", move |context, commands| {
+            let count = commands.iter().filter(|c| matches!(c, Command::G4H(_))).count();
+            seen.lock().unwrap().push((context.layer_number, count));
+            Ok(())
+        })));
+
+        let layers = slicer.slice_mesh(&cube()).unwrap();
+        let channels = |layer: &Layer| layer.nodes.iter().map(|n| n.material_channel).collect::<BTreeSet<_>>();
+        let below = layers.iter().find(|l| l.z_height > 1.0 && l.z_height < 2.0).unwrap();
+        let above = layers.iter().find(|l| l.z_height > 3.0 && l.z_height < 4.5).unwrap();
+        assert_eq!(channels(below), BTreeSet::from([Some(0)]));
+        assert_eq!(channels(above), BTreeSet::from([Some(1)]));
+
+        // Set on the first layer inside, nothing while it stays inside
+        let heats = heats.lock().unwrap();
+        let first_inside = layers.iter().find(|l| l.z_height > 2.5 + 0.1).unwrap().layer_number;
+        assert!(heats.iter().any(|&(n, count)| n == first_inside && count > 0), "{:?}", heats);
+        assert!(heats.iter().all(|&(n, count)| count == 0 || n == first_inside), "{:?}", heats);
+    }
+
     #[test]
     fn test_calculate_layer_count() {
         assert_eq!(calculate_layer_count(100.0, 0.2), 500);
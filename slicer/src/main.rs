@@ -20,6 +20,54 @@
 //!     --settings fast-draft.toml
 //! ```
 //!
+//! **CI / print farm** (JSON report written to model.report.json):
+//! ```bash
+//! hg4d-slicer --input model.stl --output model.hg4d --report
+//! ```
+//!
+//! **Multi-object plate** (one job, objects packed on the plate):
+//! ```bash
+//! hg4d-slicer --input bracket.stl --add gear.stl@1 --add gear.stl@1
+//! ```
+//!
+//! **Inspecting a sliced file** (metadata, totals, checksum verification):
+//! ```bash
+//! hg4d-slicer info model.hg4d --config printer.toml --verify
+//! ```
+//!
+//! **Layer patch** (only the layers that changed since the printer's copy):
+//! ```bash
+//! hg4d-slicer diff model-v1.hg4d model-v2.hg4d --output model.hg4dp
+//! ```
+//!
+//! **Injection point placement** (rank layouts against sliced prints):
+//! ```bash
+//! hg4d-slicer injection-layout parts/*.hg4d --config printer.toml --points 2
+//! ```
+//!
+//! **Time model calibration** (refit from the LayerTiming messages a
+//! printer broadcast, then slice with the result):
+//! ```bash
+//! hg4d-slicer calibrate bay3-timings.jsonl --config printer.toml --model time-model.json
+//! hg4d-slicer --input model.stl --time-model time-model.json
+//! ```
+//!
+//! **Model conversion** (units and material channels kept where the format allows):
+//! ```bash
+//! hg4d-slicer convert part.3mf part.obj --format obj
+//! ```
+//!
+//! **Toolpath G-code import** (experimental, for side-by-side benchmarks):
+//! ```bash
+//! hg4d-slicer import-gcode part.gcode --config printer.toml --bead-width 0.45
+//! ```
+//!
+//! **Slicing for a live printer** (printer.toml checked against the printer's
+//! capability descriptor before slicing):
+//! ```bash
+//! hg4d-slicer --input model.stl --config printer.toml --target http://printer.local:8080
+//! ```
+//!
 //! **Server Mode** (for integration):
 //! ```bash
 //! hg4d-slicer --server --port 8081
@@ -38,10 +86,12 @@
 //! Memory usage scales with model complexity and valve array density.
 
 // External crate imports - Runtime
-use std::path::PathBuf;
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
 use std::process::ExitCode;
 use std::sync::Arc;
 
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::runtime::Runtime;
 use tokio::signal;
 use tracing::{info, error, warn, debug};
@@ -53,9 +103,25 @@ use anyhow::{Result, Context};
 
 // Internal ecosystem imports
 use hypergcode_slicer::{
-    Slicer, SlicerConfig, SliceResult, SliceProgress, SlicePhase,
+    hash_printer_config, ModelLoader, Slicer, SlicerConfig, SliceMetadata, SliceResult, SliceProgress, SlicePhase,
+    HG4D_FORMAT_VERSION,
+};
+use hypergcode_slicer::config::{ConfigLoader, ExampleConfigs, PrintSettingsValidator, SettingsReport};
+use hypergcode_slicer::core::{arrange, writer_for, AutoLoader, Axis, EstimatorCoefficients, MeshTransform, SliceCache};
+use hypergcode_slicer::core::mesh_loader::{LoadOptions, MeshFormat};
+use hypergcode_slicer::gcode::postprocess::{self, ExternalPostProcessor};
+use hypergcode_slicer::gcode::import::{self, ImportOptions, MarlinImporter};
+use hypergcode_slicer::gcode::{FileReport, HG4DReader, HG4DWriter, InspectionPauses};
+use hypergcode_slicer::pressure::{DemandProfile, PlacementAnalyzer};
+use hypergcode_slicer::utils::progress::{
+    format_bytes, format_short_duration, paint, status_line, ProgressRenderer, Tone,
 };
-use config_types::{PrinterConfig, PrintSettings, MaterialProfile};
+use gcode_types::{BlockCodec, LayerPatch};
+use config_types::{PrinterConfig, PrintSettings, MaterialProfile, ResolvedSettings};
+use protocol::{CapabilityDescriptor, LayerTimingReport, ProtocolMessage};
+
+/// How long `--target` may take to return its capability descriptor.
+const DESCRIPTOR_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
 
 // Command-Line Interface Definition
 
@@ -74,10 +140,33 @@ struct Cli {
     #[arg(short, long, value_name = "FILE")]
     output: Option<PathBuf>,
 
+    /// Material channel for every face of the --input model
+    #[arg(long, value_name = "CHANNEL")]
+    channel: Option<u8>,
+
+    /// Another model for the same plate, optionally on a channel
+    /// ("gear.stl@1"); repeatable
+    #[arg(long = "add", value_name = "FILE[@CHANNEL]", value_parser = parse_plate_input)]
+    add: Vec<(PathBuf, Option<u8>)>,
+
+    /// Clearance between objects on a multi-object plate (mm)
+    #[arg(long, value_name = "MM", default_value_t = arrange::DEFAULT_OBJECT_SPACING)]
+    object_spacing: f32,
+
+    /// Keep each object where its file and the placement flags put it,
+    /// only checking for collisions
+    #[arg(long)]
+    no_arrange: bool,
+
     /// Printer configuration file
     #[arg(short = 'c', long, value_name = "FILE", default_value = "printer.toml")]
     config: PathBuf,
 
+    /// Control interface of the printer to slice for (e.g. http://printer.local:8080);
+    /// the printer configuration must match its capability descriptor
+    #[arg(long, value_name = "URL")]
+    target: Option<String>,
+
     /// Print settings file
     #[arg(short = 's', long, value_name = "FILE", default_value = "settings.toml")]
     settings: PathBuf,
@@ -110,6 +199,60 @@ struct Cli {
     #[arg(long)]
     dry_run: bool,
 
+    /// Scale the model, uniformly ("2") or per axis ("1,1,2")
+    #[arg(long, value_name = "S|X,Y,Z", value_parser = parse_scale)]
+    scale: Option<[f32; 3]>,
+
+    /// Rotate about an axis, e.g. "z:90" (repeatable, applied in order)
+    #[arg(long, value_name = "AXIS:DEG", value_parser = parse_rotation)]
+    rotate: Vec<(Axis, f32)>,
+
+    /// Rest the given triangle index on the build plate
+    #[arg(long, value_name = "FACE", conflicts_with = "auto_orient")]
+    lay_flat: Option<usize>,
+
+    /// Orient the model to minimize support volume
+    #[arg(long)]
+    auto_orient: bool,
+
+    /// Move the model across the plate after orienting ("X,Y" mm)
+    #[arg(long, value_name = "X,Y", value_parser = parse_offset, allow_hyphen_values = true)]
+    translate: Option<(f32, f32)>,
+
+    /// Post-process each layer's commands with a script or WASI module
+    /// (.wasm); repeatable, run in the order given
+    #[arg(long = "post-process", value_name = "FILE")]
+    post_process: Vec<PathBuf>,
+
+    /// Hold for operator inspection after this layer (0-based); repeatable
+    #[arg(long, value_name = "LAYER")]
+    pause_at_layer: Vec<u32>,
+
+    /// Hold for operator inspection after the layer reaching this height
+    /// (mm); repeatable
+    #[arg(long, value_name = "MM")]
+    pause_at_z: Vec<f32>,
+
+    /// Runtime used to execute .wasm post-processors
+    #[arg(long, value_name = "PROGRAM", default_value = postprocess::DEFAULT_WASM_RUNTIME)]
+    wasm_runtime: String,
+
+    /// Directory for cached slices and valve maps
+    #[arg(long, value_name = "DIR")]
+    cache_dir: Option<PathBuf>,
+
+    /// Re-slice from scratch without reading or writing the cache
+    #[arg(long)]
+    no_cache: bool,
+
+    /// Write a JSON report next to the output (<output>.report.json)
+    #[arg(long)]
+    report: bool,
+
+    /// Calibrated print time model (written by `calibrate`)
+    #[arg(long, value_name = "FILE")]
+    time_model: Option<PathBuf>,
+
     /// Subcommands for specific operations
     #[command(subcommand)]
     command: Option<Commands>,
@@ -166,6 +309,134 @@ enum Commands {
         /// Output directory
         #[arg(short, long, default_value = ".")]
         output_dir: PathBuf,
+        
+        /// Replace existing files
+        #[arg(long)]
+        force: bool,
+    },
+
+    /// Show an .hg4d file's metadata, layer index and totals
+    Info {
+        /// Sliced .hg4d file
+        #[arg(value_name = "FILE")]
+        input: PathBuf,
+
+        /// Printer configuration to compare against; also enables
+        /// material volume and mass totals
+        #[arg(short, long, value_name = "FILE")]
+        config: Option<PathBuf>,
+
+        /// List every layer instead of an index summary
+        #[arg(long)]
+        layers: bool,
+
+        /// Exit with an error if any layer is damaged, the index is
+        /// inconsistent or the file was sliced for another printer
+        #[arg(long)]
+        verify: bool,
+    },
+
+    /// Convert toolpath G-code from a conventional slicer into an .hg4d
+    /// file (experimental, for comparisons on identical geometry)
+    ImportGcode {
+        /// Marlin-flavour G-code file
+        #[arg(value_name = "FILE")]
+        input: PathBuf,
+
+        /// Output .hg4d file (default: input with .hg4d extension)
+        #[arg(value_name = "OUTPUT")]
+        output: Option<PathBuf>,
+
+        /// Printer configuration whose valve grid the paths are rasterized onto
+        #[arg(short, long, value_name = "FILE", default_value = "printer.toml")]
+        config: PathBuf,
+
+        /// Print settings recorded in the file header
+        #[arg(short, long, value_name = "FILE", default_value = "settings.toml")]
+        settings: PathBuf,
+
+        /// Material profile per channel, in channel order
+        #[arg(short, long, value_name = "FILE")]
+        materials: Vec<PathBuf>,
+
+        /// Width of the original extrusion bead (mm)
+        #[arg(long, value_name = "MM", default_value_t = import::DEFAULT_BEAD_WIDTH)]
+        bead_width: f32,
+
+        /// Shift applied to G-code X/Y ("X,Y" mm)
+        #[arg(long, value_name = "X,Y", value_parser = parse_offset, allow_hyphen_values = true)]
+        offset: Option<(f32, f32)>,
+    },
+
+    /// Write a patch turning one .hg4d file into another, carrying only
+    /// the layers that changed
+    Diff {
+        /// File the printer already has
+        #[arg(value_name = "BASE")]
+        base: PathBuf,
+
+        /// Newly sliced file
+        #[arg(value_name = "TARGET")]
+        target: PathBuf,
+
+        /// Output patch (default: target with .hg4dp extension)
+        #[arg(short, long, value_name = "FILE")]
+        output: Option<PathBuf>,
+    },
+
+    /// Compare layer block codecs on the layers of an .hg4d file
+    BenchCodecs {
+        /// Sliced .hg4d file supplying representative layers
+        #[arg(value_name = "FILE")]
+        input: PathBuf,
+
+        /// zstd levels to try
+        #[arg(long, value_delimiter = ',', default_value = "1,3,6,9")]
+        levels: Vec<u32>,
+    },
+
+    /// Recommend injection point placement from representative prints
+    InjectionLayout {
+        /// Sliced .hg4d files or saved demand profiles (.json)
+        #[arg(value_name = "FILE", required = true)]
+        inputs: Vec<PathBuf>,
+
+        /// Printer configuration whose grid and layout are evaluated
+        #[arg(short, long, value_name = "FILE")]
+        config: PathBuf,
+
+        /// Injection points per channel (default: as configured)
+        #[arg(long)]
+        points: Option<usize>,
+
+        /// Only place points on the grid edge
+        #[arg(long)]
+        perimeter: bool,
+
+        /// Routing path limit in grid segments
+        #[arg(long)]
+        max_path_length: Option<u32>,
+
+        /// Write the combined demand profile for later runs
+        #[arg(long, value_name = "FILE")]
+        save_profile: Option<PathBuf>,
+    },
+
+    /// Refit the print time model from layer timings measured by a printer
+    Calibrate {
+        /// Recorded LayerTiming reports or protocol messages, one JSON
+        /// object per line; other message types are skipped
+        #[arg(value_name = "FILE", required = true)]
+        reports: Vec<PathBuf>,
+
+        /// Configuration of the printer that measured the layers
+        #[arg(short, long, value_name = "FILE", default_value = "printer.toml")]
+        config: PathBuf,
+
+        /// Time model to refine and overwrite; started from the printer
+        /// configuration if it does not exist yet
+        #[arg(long, value_name = "FILE", default_value = "time-model.json")]
+        model: PathBuf,
     },
 }
 
@@ -177,6 +448,16 @@ enum ModelFormat {
     ThreeMf,
 }
 
+impl From<ModelFormat> for MeshFormat {
+    fn from(format: ModelFormat) -> Self {
+        match format {
+            ModelFormat::Stl => MeshFormat::StlBinary,
+            ModelFormat::Obj => MeshFormat::Obj,
+            ModelFormat::ThreeMf => MeshFormat::ThreeMf,
+        }
+    }
+}
+
 #[derive(ValueEnum, Clone, Debug)]
 enum PrinterModel {
     Mini,
@@ -185,6 +466,17 @@ enum PrinterModel {
     Industrial,
 }
 
+impl From<PrinterModel> for config_types::PrinterModel {
+    fn from(model: PrinterModel) -> Self {
+        match model {
+            PrinterModel::Mini => config_types::PrinterModel::HyperCubeMini,
+            PrinterModel::Standard => config_types::PrinterModel::HyperCubeStandard,
+            PrinterModel::Pro => config_types::PrinterModel::HyperCubePro,
+            PrinterModel::Industrial => config_types::PrinterModel::HyperCubeIndustrial,
+        }
+    }
+}
+
 // Configuration Management Types
 
 /// Runtime configuration combining all settings.
@@ -193,17 +485,61 @@ struct RuntimeConfig {
     print_settings: PrintSettings,
     material_profiles: Vec<MaterialProfile>,
     slicer_config: SlicerConfig,
+    time_model: Option<EstimatorCoefficients>,
 }
 
 impl RuntimeConfig {
     /// Loads configuration from files specified in CLI args.
     fn from_cli(cli: &Cli) -> Result<Self> {
-        todo!("Implementation needed: Load all configuration files")
+        Self::load(&cli.config, cli)
+    }
+
+    /// Loads the printer configuration at `config` with the print settings,
+    /// material profiles and slicer options given in the CLI args. Material
+    /// profiles are in channel order.
+    fn load(config: &Path, cli: &Cli) -> Result<Self> {
+        let printer_config = ConfigLoader::load_printer_config(config)
+            .with_context(|| format!("Failed to load printer configuration {}", config.display()))?;
+        let print_settings = ConfigLoader::load_print_settings(&cli.settings)?;
+        let material_profiles = cli
+            .materials
+            .iter()
+            .map(|path| {
+                ConfigLoader::load_material_profile(path)
+                    .with_context(|| format!("Failed to load material profile {}", path.display()))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        // --threads also sizes the layer pipeline
+        let mut slicer_config = SlicerConfig { write_report: cli.report, ..SlicerConfig::default() };
+        if let Some(threads) = cli.threads {
+            slicer_config.worker_threads = threads;
+        }
+
+        let time_model = cli
+            .time_model
+            .as_ref()
+            .map(EstimatorCoefficients::load)
+            .transpose()
+            .context("Failed to load the time model")?;
+
+        Ok(Self { printer_config, print_settings, material_profiles, slicer_config, time_model })
     }
 
-    /// Validates that all configurations are compatible.
-    fn validate(&self) -> Result<()> {
-        todo!("Implementation needed: Cross-validate all configurations")
+    /// Validates that all configurations are compatible, returning the
+    /// warnings for the slice result.
+    fn validate(&self) -> Result<SettingsReport> {
+        self.printer_config.validate()?;
+        let report = PrintSettingsValidator::new().check(
+            &self.print_settings,
+            Some(&self.printer_config),
+            &self.material_profiles,
+        );
+        for issue in report.warnings() {
+            warn!("{}", issue);
+        }
+        report.clone().into_result()?;
+        Ok(report)
     }
 }
 
@@ -236,26 +572,125 @@ enum JobStatus {
 
 /// Initializes logging based on verbosity level.
 fn init_logging(verbose: u8) -> Result<()> {
-    todo!("Implementation needed: Setup tracing subscriber with appropriate log level")
+    let level = match verbose {
+        0 => "info",
+        1 => "debug",
+        _ => "trace",
+    };
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(level));
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(fmt::layer().with_writer(std::io::stderr))
+        .try_init()
+        .context("Failed to install log subscriber")
 }
 
 /// Loads and validates all configurations.
 fn load_configuration(cli: &Cli) -> Result<RuntimeConfig> {
-    todo!("Implementation needed: Load configurations from files")
+    let config = RuntimeConfig::from_cli(cli)?;
+    info!(
+        "Loaded {} with {} material profiles",
+        cli.config.display(),
+        config.material_profiles.len()
+    );
+    Ok(config)
+}
+
+/// Fetches the capability descriptor of the printer behind a control
+/// interface.
+///
+/// `target` is an `http://host[:port]` URL, with or without a trailing
+/// slash. The request is plain HTTP/1.0 so the body arrives unchunked.
+async fn fetch_descriptor(target: &str) -> Result<CapabilityDescriptor> {
+    let url = format!("{}/api/capabilities/descriptor", target.trim_end_matches('/'));
+    let rest = url
+        .strip_prefix("http://")
+        .with_context(|| format!("{} is not an http:// URL", target))?;
+    let (authority, path) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+    let address = if authority.contains(':') { authority.to_string() } else { format!("{}:80", authority) };
+
+    let exchange = async {
+        let mut stream = tokio::net::TcpStream::connect(&address).await?;
+        let request = format!(
+            "GET {} HTTP/1.0\r\nHost: {}\r\nAccept: application/json\r\nConnection: close\r\n\r\n",
+            path, authority
+        );
+        stream.write_all(request.as_bytes()).await?;
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).await?;
+        Ok::<_, std::io::Error>(response)
+    };
+    let response = tokio::time::timeout(DESCRIPTOR_TIMEOUT, exchange)
+        .await
+        .with_context(|| format!("{} did not answer within {:?}", url, DESCRIPTOR_TIMEOUT))?
+        .with_context(|| format!("Failed to request {}", url))?;
+
+    let split = response
+        .windows(4)
+        .position(|window| window == b"\r\n\r\n")
+        .with_context(|| format!("Malformed response from {}", url))?;
+    let head = String::from_utf8_lossy(&response[..split]);
+    let status_line = head.lines().next().unwrap_or_default();
+    let status = status_line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|code| code.parse::<u16>().ok())
+        .with_context(|| format!("Malformed status line from {}: {:?}", url, status_line))?;
+    if !(200..300).contains(&status) {
+        anyhow::bail!("{} answered {}", url, status_line);
+    }
+    serde_json::from_slice(&response[split + 4..])
+        .with_context(|| format!("Failed to decode the capability descriptor from {}", url))
+}
+
+/// Checks that a printer configuration describes the printer it is sliced
+/// for, and that the printer reads the files this slicer writes.
+fn check_target(printer_config: &PrinterConfig, printer: &CapabilityDescriptor) -> Result<()> {
+    if !printer.reads_format(HG4D_FORMAT_VERSION) {
+        anyhow::bail!(
+            "Firmware {} reads .hg4d versions {:?}; this slicer writes version {}",
+            printer.firmware_version,
+            printer.hg4d_versions,
+            HG4D_FORMAT_VERSION
+        );
+    }
+    let local = CapabilityDescriptor::from_printer_config(
+        printer_config,
+        &printer.firmware_version,
+        &printer.hg4d_versions,
+    );
+    let mismatches = local.diff(printer);
+    if !mismatches.is_empty() {
+        let list: Vec<_> = mismatches.iter().map(|m| format!("  - {}", m)).collect();
+        anyhow::bail!("Printer configuration is stale:\n{}", list.join("\n"));
+    }
+    Ok(())
 }
 
 /// Creates slicer instance with loaded configuration.
 fn create_slicer(config: &RuntimeConfig) -> Result<Slicer> {
-    todo!("Implementation needed: Initialize slicer with configuration")
+    let mut slicer = Slicer::with_config(
+        config.printer_config.clone(),
+        config.print_settings.clone(),
+        config.slicer_config.clone(),
+    );
+    slicer.set_material_profiles(config.material_profiles.clone());
+    if let Some(coefficients) = config.time_model {
+        slicer.set_time_coefficients(coefficients);
+    }
+    Ok(slicer)
 }
 
 /// Runs batch slicing operation.
 async fn run_batch_slice(
     input: PathBuf,
     output: PathBuf,
-    slicer: Slicer,
+    mut slicer: Slicer,
 ) -> Result<SliceResult> {
-    todo!("Implementation needed: Execute single slice operation with progress reporting")
+    slicer.set_progress_callback(Arc::new(create_progress_reporter()));
+    slicer
+        .slice_file(&input, &output)
+        .with_context(|| format!("Failed to slice {}", input.display()))
 }
 
 /// Runs GUI mode.
@@ -276,7 +711,49 @@ async fn run_server(port: u16, config: RuntimeConfig) -> Result<()> {
 
 /// Runs estimate subcommand.
 async fn run_estimate(input: PathBuf, config: RuntimeConfig) -> Result<()> {
-    todo!("Implementation needed: Quick estimation without full slicing")
+    let started = std::time::Instant::now();
+    let slicer = create_slicer(&config)?;
+    let mut mesh = AutoLoader::new()
+        .load(&input)
+        .with_context(|| format!("Failed to load {}", input.display()))?;
+    slicer.place_mesh(&mut mesh)?;
+    let estimate = slicer.estimate(&mesh)?;
+
+    let color = std::io::stdout().is_terminal();
+    let (min_x, min_y, min_z, max_x, max_y, max_z) = mesh.bounding_box();
+    println!();
+    println!(
+        "{} {} in {} (voxel estimate, not sliced)",
+        paint("Estimated", Tone::Success, color),
+        input.display(),
+        format_short_duration(started.elapsed())
+    );
+    println!("  Layers:          {}", estimate.layer_count);
+    println!("  Estimated time:  {}", format_duration(estimate.estimated_time));
+    println!(
+        "  Model size:      {:.1} x {:.1} x {:.1} mm",
+        max_x - min_x,
+        max_y - min_y,
+        max_z - min_z
+    );
+
+    if !estimate.material_mm3.is_empty() {
+        println!("  {}", paint("Material usage:", Tone::Bold, color));
+        println!("    {:<9} {:>10} {:>10}", "channel", "cm³", "grams");
+        let mut total = (0.0, 0.0);
+        for (&channel, &mm3) in &estimate.material_mm3 {
+            let density = config.material_profiles.get(channel as usize).map(|p| p.properties.density);
+            let grams = density.map(|d| mm3 * d / 1000.0);
+            let shown = grams.map(|g| format!("{:.1}", g)).unwrap_or_else(|| "-".to_string());
+            println!("    {:<9} {:>10.1} {:>10}", channel, mm3 / 1000.0, shown);
+            total.0 += mm3;
+            total.1 += grams.unwrap_or(0.0);
+        }
+        if estimate.material_mm3.len() > 1 {
+            println!("    {:<9} {:>10.1} {:>10.1}", "total", total.0 / 1000.0, total.1);
+        }
+    }
+    Ok(())
 }
 
 /// Runs validate subcommand.
@@ -295,12 +772,440 @@ async fn run_convert(
     output: PathBuf,
     format: ModelFormat,
 ) -> Result<()> {
-    todo!("Implementation needed: Convert between model formats")
+    // Keep the model as authored: no repair, recentering or unit conversion
+    let options = LoadOptions {
+        validate_topology: false,
+        auto_fix: false,
+        target_units: None,
+        center_on_origin: false,
+        scale_factor: 1.0,
+        merge_threshold: None,
+    };
+    let (mesh, colors) = AutoLoader::with_options(options)
+        .load_with_colors(&input)
+        .with_context(|| format!("Failed to load {}", input.display()))?;
+
+    let format = MeshFormat::from(format);
+    let extension = output.extension().and_then(|e| e.to_str()).map(str::to_ascii_lowercase);
+    if !format.extensions().iter().any(|e| Some(*e) == extension.as_deref()) {
+        warn!(
+            "{} does not have a .{} extension; other tools may not recognize it",
+            output.display(),
+            format.extensions()[0]
+        );
+    }
+
+    let channels = mesh
+        .face_channels
+        .as_ref()
+        .map(|c| c.iter().collect::<std::collections::BTreeSet<_>>().len())
+        .unwrap_or(1);
+    writer_for(format, colors)?
+        .write(&mesh, &output)
+        .with_context(|| format!("Failed to write {}", output.display()))?;
+
+    println!(
+        "Converted {} -> {} ({} triangles, {} vertices)",
+        input.display(),
+        output.display(),
+        mesh.indices.len() / 3,
+        mesh.vertices.len() / 3
+    );
+    if channels > 1 {
+        match format {
+            MeshFormat::StlBinary | MeshFormat::StlAscii => {
+                println!("  {} material channels dropped: STL carries geometry only", channels)
+            }
+            _ => println!("  {} material channels kept", channels),
+        }
+    }
+    Ok(())
 }
 
 /// Runs init subcommand to generate example configs.
-async fn run_init(model: PrinterModel, output_dir: PathBuf) -> Result<()> {
-    todo!("Implementation needed: Generate example configuration files")
+async fn run_init(model: PrinterModel, output_dir: PathBuf, force: bool) -> Result<()> {
+    let model = config_types::PrinterModel::from(model);
+    let configs = ExampleConfigs::for_model(model)?;
+    let written = configs.write_to(&output_dir, force)?;
+
+    println!("Generated {} configuration:", model.name());
+    for path in &written {
+        println!("  {}", path.display());
+    }
+    println!(
+        "Valve array: {} x {} nodes at {} mm spacing, {} material channel(s)",
+        configs.printer.grid_x_count(),
+        configs.printer.grid_y_count(),
+        configs.printer.valve_array.grid_spacing,
+        configs.printer.materials.channel_count
+    );
+    Ok(())
+}
+
+/// Runs info subcommand.
+async fn run_info(input: PathBuf, config: Option<PathBuf>, list_layers: bool, verify: bool) -> Result<()> {
+    let printer = config
+        .as_ref()
+        .map(|path| {
+            PrinterConfig::from_file(path)
+                .with_context(|| format!("Failed to load printer configuration {}", path.display()))
+        })
+        .transpose()?;
+    let report = FileReport::inspect(&input, printer.as_ref())?;
+    let header = &report.header;
+
+    println!("{} ({:.2} MB)", report.path.display(), report.file_bytes as f64 / 1e6);
+    println!("  Format version:  {}", report.format_version);
+    println!("  Model:           {}", header.model_name);
+    println!("  Slicer version:  {}", header.slicer_version);
+    println!("  Codec:           {}", header.codec.unwrap_or(BlockCodec::None));
+    let hash_note = match report.config_matches {
+        Some(true) => " (matches printer config)",
+        Some(false) => " (DOES NOT match printer config)",
+        None => "",
+    };
+    println!("  Printer config:  {}{}", header.printer_config_hash, hash_note);
+    if let Some(settings) = &header.settings {
+        println!("  Layer height:    {} mm", settings.settings.layer_height);
+    }
+    match report.estimated_time {
+        Some(time) => println!("  Estimated time:  {}", format_duration(time)),
+        None => println!("  Estimated time:  not recorded"),
+    }
+
+    println!("  Materials:");
+    let grams = report.material_grams();
+    for (&channel, &nodes) in &report.node_deposits {
+        let name = header
+            .materials
+            .get(channel as usize)
+            .map(|p| p.name.as_str())
+            .unwrap_or("(no profile)");
+        let volume = report
+            .material_mm3
+            .as_ref()
+            .and_then(|v| v.get(&channel))
+            .map(|mm3| format!(", {:.1} cm³", mm3 / 1000.0))
+            .unwrap_or_default();
+        let mass = grams.get(&channel).map(|g| format!(", {:.1} g", g)).unwrap_or_default();
+        println!("    channel {}: {} - {} node deposits{}{}", channel, name, nodes, volume, mass);
+    }
+    for object in &header.objects {
+        println!("  Object {}: {}", object.id, object.name);
+    }
+
+    println!("  Layers:          {}", report.layers.len());
+    if list_layers {
+        println!("    {:>6} {:>9} {:>10} {:>7} {:>8}", "layer", "z (mm)", "bytes", "nodes", "time (s)");
+        for layer in &report.layers {
+            let nodes = layer.active_nodes.map(|n| n.to_string()).unwrap_or_else(|| "-".to_string());
+            let time = layer.estimated_time.map(|t| format!("{:.2}", t)).unwrap_or_else(|| "-".to_string());
+            println!(
+                "    {:>6} {:>9.3} {:>10} {:>7} {:>8}",
+                layer.layer_number, layer.z_height, layer.stored_bytes, nodes, time
+            );
+        }
+    } else if let (Some(first), Some(last)) = (report.layers.first(), report.layers.last()) {
+        let sizes = report.layers.iter().map(|l| l.stored_bytes as u64);
+        let total: u64 = sizes.clone().sum();
+        println!(
+            "    Z {:.3} - {:.3} mm, blocks {} - {} bytes (mean {})",
+            first.z_height,
+            last.z_height,
+            sizes.clone().min().unwrap_or(0),
+            sizes.max().unwrap_or(0),
+            total / report.layers.len() as u64
+        );
+    }
+
+    for layer in report.damaged_layers() {
+        println!("  DAMAGED layer {}: {}", layer.layer_number, layer.error.as_deref().unwrap_or(""));
+    }
+    for problem in &report.index_problems {
+        println!("  INDEX: {}", problem);
+    }
+
+    if verify {
+        if !report.is_intact() {
+            anyhow::bail!(
+                "{} failed verification: {} damaged layer(s), {} index problem(s)",
+                input.display(),
+                report.damaged_layers().count(),
+                report.index_problems.len()
+            );
+        }
+        if report.config_matches == Some(false) {
+            anyhow::bail!("{} was sliced for a different printer configuration", input.display());
+        }
+        println!("Verified: all {} layer checksums OK", report.layers.len());
+    }
+    Ok(())
+}
+
+fn format_duration(duration: std::time::Duration) -> String {
+    let secs = duration.as_secs();
+    format!("{}h {:02}m {:02}s", secs / 3600, secs / 60 % 60, secs % 60)
+}
+
+/// Runs the G-code import subcommand.
+async fn run_import_gcode(
+    input: PathBuf,
+    output: Option<PathBuf>,
+    config: PathBuf,
+    settings: PathBuf,
+    materials: Vec<PathBuf>,
+    options: ImportOptions,
+) -> Result<()> {
+    let printer = PrinterConfig::from_file(&config)
+        .with_context(|| format!("Failed to load printer configuration {}", config.display()))?;
+    let settings_text = std::fs::read_to_string(&settings)
+        .with_context(|| format!("Failed to read print settings {}", settings.display()))?;
+    let mut settings: PrintSettings = toml::from_str(&settings_text)
+        .with_context(|| format!("Failed to parse print settings {}", settings.display()))?;
+    let material_profiles = materials
+        .iter()
+        .map(|path| {
+            MaterialProfile::from_file(path)
+                .with_context(|| format!("Failed to load material profile {}", path.display()))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let file = std::fs::File::open(&input).with_context(|| format!("Failed to open {}", input.display()))?;
+    let imported = MarlinImporter::new(&printer, options).import(std::io::BufReader::new(file))?;
+    if imported.layers.is_empty() {
+        anyhow::bail!("{} contains no extrusion moves", input.display());
+    }
+    if let Some(height) = imported.layer_height() {
+        settings.layer_height = height;
+    }
+    let stats = &imported.stats;
+    if stats.clipped_nodes > 0 {
+        warn!("{} bead nodes fall outside the valve grid; use --offset to move the print", stats.clipped_nodes);
+    }
+    if stats.arcs > 0 {
+        warn!("{} arc moves were imported as straight lines", stats.arcs);
+    }
+
+    let output = output.unwrap_or_else(|| input.with_extension("hg4d"));
+    let metadata = SliceMetadata {
+        printer_config_hash: hash_printer_config(&printer),
+        material_profiles,
+        print_settings: ResolvedSettings { settings, applied: vec![] },
+        model_name: input
+            .file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+        slicer_version: format!("{} (G-code import)", env!("CARGO_PKG_VERSION")),
+        objects: vec![],
+        material_usage: vec![],
+    };
+    let mut writer = HG4DWriter::create(&output, metadata)
+        .with_context(|| format!("Failed to create {}", output.display()))?;
+    writer.write_header()?;
+    for layer in &imported.layers {
+        writer.write_layer(layer)?;
+    }
+    writer.finalize()?;
+
+    let nodes: usize = imported.layers.iter().map(|l| l.node_count()).sum();
+    println!(
+        "Imported {} -> {}: {} layers, {} node deposits from {} extrusion moves",
+        input.display(),
+        output.display(),
+        imported.layers.len(),
+        nodes,
+        stats.extrusion_moves
+    );
+    for (channel, filament) in &stats.filament_mm {
+        println!("  channel {}: {:.1} mm of filament in the original", channel, filament);
+    }
+    if !stats.ignored.is_empty() {
+        let ignored: Vec<String> = stats.ignored.iter().map(|(code, n)| format!("{} x{}", code, n)).collect();
+        println!("  Ignored: {}", ignored.join(", "));
+    }
+    Ok(())
+}
+
+/// Runs injection layout subcommand.
+async fn run_injection_layout(
+    inputs: Vec<PathBuf>,
+    config: PathBuf,
+    points: Option<usize>,
+    perimeter: bool,
+    max_path_length: Option<u32>,
+    save_profile: Option<PathBuf>,
+) -> Result<()> {
+    let printer = PrinterConfig::from_file(&config)
+        .with_context(|| format!("Failed to load printer configuration {}", config.display()))?;
+    let mut profile = DemandProfile::for_printer(&printer);
+    for input in &inputs {
+        if input.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("json")) {
+            profile.merge(&DemandProfile::load(input)?)?;
+            continue;
+        }
+        let mut reader = HG4DReader::open(input)?;
+        for n in 0..reader.index().len() {
+            profile.add_layer(&reader.read_layer(n)?);
+        }
+    }
+    if let Some(path) = &save_profile {
+        profile.save(path)?;
+        info!("Demand profile written to {}", path.display());
+    }
+
+    let mut analyzer = PlacementAnalyzer::new(&printer).perimeter_only(perimeter);
+    if let Some(limit) = max_path_length {
+        analyzer = analyzer.with_max_path_length(limit);
+    }
+    let report = analyzer.analyze(&profile, points)?;
+    println!("Demand from {} layers over {} input(s)", report.layers, inputs.len());
+    for placement in &report.channels {
+        println!("Channel {}:", placement.channel);
+        println!(
+            "  {:<16} {:>10} {:>8} {:>10} {:>10} {:>9}",
+            "layout", "mean path", "max", "mean drop", "out reach", "imbalance"
+        );
+        for score in &placement.candidates {
+            println!(
+                "  {:<16} {:>10.1} {:>8} {:>9.1}% {:>9.1}% {:>9.2}",
+                score.name,
+                score.mean_path_length,
+                score.max_path_length,
+                score.mean_pressure_drop * 100.0,
+                score.out_of_reach * 100.0,
+                score.load_imbalance
+            );
+        }
+    }
+
+    println!("Recommended layout:");
+    for point in report.recommended() {
+        println!("  [[valve_array.injection_points]]");
+        println!("  id = {}", point.id);
+        println!("  x = {:.2}", point.x);
+        println!("  y = {:.2}", point.y);
+        println!("  material_channel = {}", point.material_channel);
+    }
+    Ok(())
+}
+
+/// Runs diff subcommand.
+async fn run_diff(base: PathBuf, target: PathBuf, output: Option<PathBuf>) -> Result<()> {
+    let output = output.unwrap_or_else(|| target.with_extension("hg4dp"));
+    let old = std::fs::read(&base).with_context(|| format!("Failed to read {}", base.display()))?;
+    let new = std::fs::read(&target).with_context(|| format!("Failed to read {}", target.display()))?;
+
+    let patch = LayerPatch::diff(&old, &new)
+        .with_context(|| format!("Failed to diff {} against {}", target.display(), base.display()))?;
+    // Checked here so a bad patch never reaches the printer
+    patch.apply(&old).context("Patch does not reproduce the target")?;
+    let bytes = patch.to_bytes();
+    std::fs::write(&output, &bytes).with_context(|| format!("Failed to write {}", output.display()))?;
+
+    println!(
+        "{}: {} of {} layers changed, {:.1} KB ({:.1}% of {})",
+        output.display(),
+        patch.changed_layers(),
+        patch.layers.len(),
+        bytes.len() as f64 / 1e3,
+        100.0 * bytes.len() as f64 / new.len().max(1) as f64,
+        target.display()
+    );
+    Ok(())
+}
+
+/// Runs calibrate subcommand.
+async fn run_calibrate(inputs: Vec<PathBuf>, config: RuntimeConfig, model: PathBuf) -> Result<()> {
+    let mut slicer = create_slicer(&config)?;
+    if model.exists() {
+        let coefficients = EstimatorCoefficients::load(&model)?;
+        slicer.set_time_coefficients(coefficients);
+    }
+    let mut reports = Vec::new();
+    for path in &inputs {
+        let content = std::fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+        for (number, line) in content.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()) {
+            let report = parse_timing_report(line)
+                .with_context(|| format!("{}:{}: not a layer timing report", path.display(), number + 1))?;
+            reports.extend(report);
+        }
+    }
+    if reports.is_empty() {
+        let names: Vec<_> = inputs.iter().map(|p| p.display().to_string()).collect();
+        anyhow::bail!("No layer timing reports in {}", names.join(", "));
+    }
+
+    let summary = slicer.calibrate_time_model(&reports);
+    if summary.samples_used == 0 {
+        anyhow::bail!(
+            "All {} layers were cooling-bound; they say nothing about the time model",
+            summary.samples_skipped
+        );
+    }
+    let coefficients = slicer.time_estimator().coefficients();
+    coefficients.save(&model)?;
+
+    println!(
+        "{}: fitted to {} layers ({} cooling-bound skipped), mean error {:.2}s -> {:.2}s",
+        model.display(),
+        summary.samples_used,
+        summary.samples_skipped,
+        summary.error_before,
+        summary.error_after
+    );
+    println!("  Layer overhead:   {:.3} s", coefficients.layer_overhead);
+    println!("  Valve switch:     {:.4} s", coefficients.per_valve_switch);
+    println!("  Pressure wait:    {:.3} s", coefficients.per_pressure_wait);
+    println!("  Z move scale:     {:.3}", coefficients.z_move_scale);
+    println!("  Deposition:       {:.5} s/mm³", coefficients.per_mm3);
+    Ok(())
+}
+
+/// Reads one recorded line: a bare report, or a protocol message of which
+/// only LayerTiming carries one.
+fn parse_timing_report(line: &str) -> Result<Option<LayerTimingReport>> {
+    if let Ok(report) = serde_json::from_str::<LayerTimingReport>(line) {
+        return Ok(Some(report));
+    }
+    match protocol::decode_message(line.as_bytes())? {
+        Some(ProtocolMessage::LayerTiming(report)) => Ok(Some(report)),
+        _ => Ok(None),
+    }
+}
+
+/// Runs codec benchmark subcommand.
+async fn run_bench_codecs(input: PathBuf, levels: Vec<u32>) -> Result<()> {
+    let mut reader = HG4DReader::open(&input)?;
+    let blocks = (0..reader.index().len())
+        .map(|n| reader.read_block(n))
+        .collect::<Result<Vec<_>>>()?;
+    let raw: u64 = blocks.iter().map(|b| b.len() as u64).sum();
+    println!(
+        "{}: {} layers, {:.2} MB encoded (file codec: {})",
+        input.display(),
+        blocks.len(),
+        raw as f64 / 1e6,
+        reader.codec().unwrap_or(BlockCodec::None)
+    );
+
+    let mut runs = vec![(BlockCodec::None, 0), (BlockCodec::Lz4, 0)];
+    runs.extend(levels.iter().map(|&level| (BlockCodec::Zstd, level)));
+
+    println!("{:<8} {:>5} {:>12} {:>7} {:>12} {:>12}", "codec", "level", "size (MB)", "ratio", "encode (ms)", "decode MB/s");
+    for (codec, level) in runs {
+        let result = gcode_types::codec::benchmark(codec, level, &blocks)?;
+        println!(
+            "{:<8} {:>5} {:>12.2} {:>7.3} {:>12.1} {:>12.0}",
+            codec.name(),
+            if codec == BlockCodec::Zstd { level.to_string() } else { "-".to_string() },
+            result.stored_bytes as f64 / 1e6,
+            result.ratio(),
+            result.encode_time.as_secs_f64() * 1000.0,
+            result.decode_mb_per_s()
+        );
+    }
+    println!("Decode speed is measured on this machine; the printer controller is usually slower.");
+    Ok(())
 }
 
 // Main Function Architecture
@@ -368,20 +1273,52 @@ fn build_runtime(threads: Option<usize>) -> Result<Runtime> {
 
 /// Main application logic coordinating all operations.
 async fn run_application(
-    cli: Cli,
+    mut cli: Cli,
     shutdown: tokio::sync::broadcast::Receiver<()>,
 ) -> Result<()> {
     // Handle subcommands first
-    if let Some(command) = cli.command {
-        return handle_subcommand(command).await;
+    if let Some(command) = cli.command.take() {
+        return handle_subcommand(command, &cli).await;
     }
 
     // Load configuration
     let config = load_configuration(&cli)?;
-    config.validate()?;
+    let report = config.validate()?;
+    if let Some(target) = &cli.target {
+        let descriptor = fetch_descriptor(target).await?;
+        check_target(&config.printer_config, &descriptor)
+            .with_context(|| format!("{} does not match the printer at {}", cli.config.display(), target))?;
+        info!("Printer configuration matches {} (firmware {})", target, descriptor.firmware_version);
+    }
 
     // Create slicer
-    let slicer = create_slicer(&config)?;
+    let mut slicer = create_slicer(&config)?;
+    slicer.record_settings_warnings(&report);
+    slicer.set_transforms(placement_transforms(&cli));
+    if !cli.no_cache {
+        let dir = cli.cache_dir.clone().unwrap_or_else(SliceCache::default_dir);
+        match SliceCache::open(&dir) {
+            Ok(cache) => slicer.set_cache(cache),
+            Err(e) => warn!("Slicing without cache: {:#}", e),
+        }
+    }
+    for path in &cli.post_process {
+        let processor = ExternalPostProcessor::load(path, &cli.wasm_runtime)
+            .with_context(|| format!("Failed to load post-processor {}", path.display()))?;
+        slicer.add_post_processor(Box::new(processor));
+    }
+    // Inspection pauses run last so they close each layer
+    let mut inspection = config.print_settings.inspection.clone();
+    inspection.layers.extend(&cli.pause_at_layer);
+    inspection.heights.extend(&cli.pause_at_z);
+    let pauses = InspectionPauses::new(
+        &inspection,
+        config.print_settings.first_layer_height,
+        config.print_settings.layer_height,
+    );
+    if !pauses.is_empty() {
+        slicer.add_post_processor(Box::new(pauses));
+    }
 
     // Determine operation mode
     if cli.server {
@@ -402,6 +1339,14 @@ async fn run_application(
             validate_slice_params(&input, &output, &config)?;
             info!("Validation successful");
             Ok(())
+        } else if !cli.add.is_empty() || cli.channel.is_some() {
+            let mut inputs = vec![(input, cli.channel)];
+            inputs.extend(cli.add);
+            info!("Slicing {} objects -> {}", inputs.len(), output.display());
+            let plate = slicer.load_plate(&inputs, cli.object_spacing, !cli.no_arrange)?;
+            let result = slicer.slice_plate(&plate, &output)?;
+            print_slice_results(&result);
+            Ok(())
         } else {
             info!("Slicing {} -> {}", input.display(), output.display());
             let result = run_batch_slice(input, output, slicer).await?;
@@ -411,11 +1356,11 @@ async fn run_application(
     }
 }
 
-/// Handles all subcommands.
-async fn handle_subcommand(command: Commands) -> Result<()> {
+/// Handles all subcommands; `cli` carries the top-level options.
+async fn handle_subcommand(command: Commands, cli: &Cli) -> Result<()> {
     match command {
         Commands::Estimate { input, config } => {
-            let cfg = RuntimeConfig::from_cli(&Cli::parse())?;
+            let cfg = RuntimeConfig::load(&config, cli)?;
             run_estimate(input, cfg).await
         }
         Commands::Validate { input } => {
@@ -427,9 +1372,104 @@ async fn handle_subcommand(command: Commands) -> Result<()> {
         Commands::Convert { input, output, format } => {
             run_convert(input, output, format).await
         }
-        Commands::Init { model, output_dir } => {
-            run_init(model, output_dir).await
+        Commands::Init { model, output_dir, force } => {
+            run_init(model, output_dir, force).await
         }
+        Commands::Info { input, config, layers, verify } => {
+            run_info(input, config, layers, verify).await
+        }
+        Commands::ImportGcode { input, output, config, settings, materials, bead_width, offset } => {
+            let options = ImportOptions { bead_width, offset: offset.unwrap_or((0.0, 0.0)) };
+            run_import_gcode(input, output, config, settings, materials, options).await
+        }
+        Commands::Diff { base, target, output } => {
+            run_diff(base, target, output).await
+        }
+        Commands::BenchCodecs { input, levels } => {
+            run_bench_codecs(input, levels).await
+        }
+        Commands::InjectionLayout { inputs, config, points, perimeter, max_path_length, save_profile } => {
+            run_injection_layout(inputs, config, points, perimeter, max_path_length, save_profile).await
+        }
+        Commands::Calibrate { reports, config, model } => {
+            let cfg = RuntimeConfig::load(&config, cli)?;
+            run_calibrate(reports, cfg, model).await
+        }
+    }
+}
+
+// Model Placement
+
+/// Builds placement steps from CLI flags.
+///
+/// Steps run in a fixed order regardless of flag order: scale, rotations
+/// (in the order given), lay-flat or auto-orient, then translate.
+fn placement_transforms(cli: &Cli) -> Vec<MeshTransform> {
+    let mut steps = Vec::new();
+
+    if let Some([x, y, z]) = cli.scale {
+        steps.push(MeshTransform::Scale { x, y, z });
+    }
+    for &(axis, degrees) in &cli.rotate {
+        steps.push(MeshTransform::Rotate { axis, degrees });
+    }
+    if let Some(face) = cli.lay_flat {
+        steps.push(MeshTransform::LayFlat { face });
+    } else if cli.auto_orient {
+        steps.push(MeshTransform::AutoOrient);
+    }
+    if let Some((x, y)) = cli.translate {
+        steps.push(MeshTransform::Translate { x, y });
+    }
+
+    steps
+}
+
+fn parse_floats(value: &str) -> std::result::Result<Vec<f32>, String> {
+    value
+        .split(',')
+        .map(|v| v.trim().parse::<f32>().map_err(|e| format!("'{}': {}", v, e)))
+        .collect()
+}
+
+fn parse_scale(value: &str) -> std::result::Result<[f32; 3], String> {
+    let scale = match parse_floats(value)?.as_slice() {
+        &[s] => [s, s, s],
+        &[x, y, z] => [x, y, z],
+        _ => return Err("expected S or X,Y,Z".to_string()),
+    };
+    if scale.iter().any(|s| *s == 0.0) {
+        return Err("scale factors must be non-zero".to_string());
+    }
+    Ok(scale)
+}
+
+fn parse_rotation(value: &str) -> std::result::Result<(Axis, f32), String> {
+    let (axis, degrees) = value.split_once(':').ok_or("expected AXIS:DEG, e.g. z:90")?;
+    let axis = match axis.trim().to_ascii_lowercase().as_str() {
+        "x" => Axis::X,
+        "y" => Axis::Y,
+        "z" => Axis::Z,
+        other => return Err(format!("unknown axis '{}'", other)),
+    };
+    let degrees = degrees.trim().parse::<f32>().map_err(|e| e.to_string())?;
+    Ok((axis, degrees))
+}
+
+fn parse_offset(value: &str) -> std::result::Result<(f32, f32), String> {
+    match parse_floats(value)?.as_slice() {
+        &[x, y] => Ok((x, y)),
+        _ => Err("expected X,Y".to_string()),
+    }
+}
+
+fn parse_plate_input(value: &str) -> std::result::Result<(PathBuf, Option<u8>), String> {
+    match value.rsplit_once('@') {
+        Some((path, channel)) if !path.is_empty() => {
+            let channel = channel.trim().parse::<u8>().map_err(|e| format!("channel '{}': {}", channel, e))?;
+            Ok((PathBuf::from(path), Some(channel)))
+        }
+        _ => Ok((PathBuf::from(value), None)),
     }
 }
 
@@ -441,24 +1481,115 @@ fn validate_slice_params(
     output: &PathBuf,
     config: &RuntimeConfig,
 ) -> Result<()> {
-    todo!("Implementation needed: Validate input file exists, output writable, etc.")
+    if !input.is_file() {
+        anyhow::bail!("Input file {} does not exist", input.display());
+    }
+    let dir = match output.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    if !dir.is_dir() {
+        anyhow::bail!("Output directory {} does not exist", dir.display());
+    }
+    if std::fs::metadata(dir)?.permissions().readonly() {
+        anyhow::bail!("Output directory {} is not writable", dir.display());
+    }
+    let channels = config.printer_config.materials.channel_count as usize;
+    if config.material_profiles.len() > channels {
+        anyhow::bail!(
+            "{} material profiles given for {} channels",
+            config.material_profiles.len(),
+            channels
+        );
+    }
+    Ok(())
 }
 
 /// Prints slice results in human-readable format.
 fn print_slice_results(result: &SliceResult) {
-    todo!("Implementation needed: Pretty-print results with colors and formatting")
+    let color = std::io::stdout().is_terminal();
+    let (min_x, min_y, min_z, max_x, max_y, max_z) = result.bounding_box;
+    let size = std::fs::metadata(&result.output_path)
+        .map(|m| format_bytes(m.len()))
+        .unwrap_or_else(|_| "unknown".to_string());
+
+    println!();
+    println!(
+        "{} {} in {}",
+        paint("Sliced", Tone::Success, color),
+        result.output_path.display(),
+        format_short_duration(result.elapsed_time)
+    );
+    println!("  Layers:          {}", result.layer_count);
+    println!("  Estimated time:  {}", format_duration(result.estimated_time));
+    println!("  Output size:     {}", size);
+    println!(
+        "  Model size:      {:.1} x {:.1} x {:.1} mm",
+        max_x - min_x,
+        max_y - min_y,
+        max_z - min_z
+    );
+
+    if !result.material_usage.is_empty() {
+        let mut usage: Vec<(u8, f32)> = result.material_usage.iter().map(|(&c, &g)| (c, g)).collect();
+        usage.sort_by_key(|(channel, _)| *channel);
+        println!("  {}", paint("Material usage:", Tone::Bold, color));
+        println!("    {:<9} {:>10}", "channel", "grams");
+        for (channel, grams) in &usage {
+            println!("    {:<9} {:>10.1}", channel, grams);
+        }
+        if usage.len() > 1 {
+            println!("    {:<9} {:>10.1}", "total", usage.iter().map(|(_, g)| g).sum::<f32>());
+        }
+    }
+
+    if !result.warnings.is_empty() {
+        let heading = format!("Warnings ({}):", result.warnings.len());
+        println!("  {}", paint(&heading, Tone::Warning, color));
+        for warning in &result.warnings {
+            println!("    - {}", warning);
+        }
+    }
 }
 
 /// Converts slice progress to human-readable status message.
 fn format_progress(progress: &SliceProgress) -> String {
-    todo!("Implementation needed: Format progress for terminal output")
+    let mut line = status_line(progress, None);
+    if !progress.message.is_empty() {
+        line.push_str(" - ");
+        line.push_str(&progress.message);
+    }
+    line
 }
 
 // Signal Handling and Shutdown
 
 /// Sets up handlers for SIGINT and SIGTERM.
 fn setup_signal_handlers() -> tokio::sync::broadcast::Receiver<()> {
-    todo!("Implementation needed: Setup graceful shutdown on signals")
+    let (sender, receiver) = tokio::sync::broadcast::channel(1);
+    tokio::spawn(async move {
+        #[cfg(unix)]
+        {
+            let mut terminate = match signal::unix::signal(signal::unix::SignalKind::terminate()) {
+                Ok(terminate) => terminate,
+                Err(e) => {
+                    warn!("SIGTERM handler unavailable: {}", e);
+                    let _ = signal::ctrl_c().await;
+                    let _ = sender.send(());
+                    return;
+                }
+            };
+            tokio::select! {
+                _ = signal::ctrl_c() => {}
+                _ = terminate.recv() => {}
+            }
+        }
+        #[cfg(not(unix))]
+        let _ = signal::ctrl_c().await;
+        info!("Shutdown requested");
+        let _ = sender.send(());
+    });
+    receiver
 }
 
 /// Runs server with graceful shutdown support.
@@ -473,9 +1604,16 @@ async fn run_server_with_shutdown(
 // Monitoring and Observability Setup
 
 /// Creates progress reporter for terminal output.
-fn create_progress_reporter() -> impl Fn(SliceProgress) {
+///
+/// Bars go to stderr so piped stdout only carries the summary; they are
+/// redrawn in place only when stderr is a terminal.
+fn create_progress_reporter() -> impl Fn(SliceProgress) + Send + Sync + 'static {
+    let renderer = std::sync::Mutex::new(ProgressRenderer::new(std::io::stderr().is_terminal()));
     move |progress: SliceProgress| {
-        todo!("Implementation needed: Display progress bar or status updates")
+        let mut renderer = renderer.lock().unwrap_or_else(|e| e.into_inner());
+        if let Err(e) = renderer.update(&mut std::io::stderr().lock(), &progress) {
+            debug!("Progress display failed: {}", e);
+        }
     }
 }
 
@@ -495,11 +1633,126 @@ mod tests {
             "--input", "model.stl",
             "--output", "model.hg4d",
             "--config", "printer.toml",
+            "--report",
+            "--target", "http://printer.local:8080",
         ];
         
         let cli = Cli::parse_from(args);
         assert_eq!(cli.input, Some(PathBuf::from("model.stl")));
         assert_eq!(cli.output, Some(PathBuf::from("model.hg4d")));
+        assert!(cli.report);
+        assert_eq!(cli.target.as_deref(), Some("http://printer.local:8080"));
+    }
+
+    #[test]
+    fn test_check_target() {
+        let config = ExampleConfigs::for_model(config_types::PrinterModel::HyperCubeStandard)
+            .unwrap()
+            .printer;
+        let mut printer = CapabilityDescriptor::from_printer_config(&config, "0.4.0", &[HG4D_FORMAT_VERSION]);
+        assert!(check_target(&config, &printer).is_ok());
+
+        printer.grid.valves_per_node += 1;
+        let error = format!("{:#}", check_target(&config, &printer).unwrap_err());
+        assert!(error.contains("grid.valves_per_node"), "{}", error);
+
+        printer.hg4d_versions = vec![HG4D_FORMAT_VERSION + 1];
+        let error = format!("{:#}", check_target(&config, &printer).unwrap_err());
+        assert!(error.contains("this slicer writes version"), "{}", error);
+    }
+
+    /// Answers one request on a local port with `response`, returning the
+    /// target URL and the request it received.
+    async fn serve_once(response: String) -> (String, tokio::task::JoinHandle<String>) {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let target = format!("http://{}/", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            let mut buffer = [0u8; 1024];
+            while !request.ends_with(b"\r\n\r\n") {
+                let n = socket.read(&mut buffer).await.unwrap();
+                request.extend_from_slice(&buffer[..n]);
+            }
+            socket.write_all(response.as_bytes()).await.unwrap();
+            String::from_utf8(request).unwrap()
+        });
+        (target, server)
+    }
+
+    #[test]
+    fn test_fetch_descriptor() {
+        let config = ExampleConfigs::for_model(config_types::PrinterModel::HyperCubeMini)
+            .unwrap()
+            .printer;
+        let descriptor = CapabilityDescriptor::from_printer_config(&config, "0.4.0", &[HG4D_FORMAT_VERSION]);
+        let body = serde_json::to_string(&descriptor).unwrap();
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        runtime.block_on(async {
+            let (target, server) = serve_once(format!(
+                "HTTP/1.0 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+                body.len(),
+                body
+            ))
+            .await;
+            assert_eq!(fetch_descriptor(&target).await.unwrap(), descriptor);
+            let request = server.await.unwrap();
+            assert!(request.starts_with("GET /api/capabilities/descriptor HTTP/1.0\r\n"), "{}", request);
+
+            let (target, _) = serve_once("HTTP/1.0 404 Not Found\r\n\r\n".to_string()).await;
+            let error = format!("{:#}", fetch_descriptor(target.trim_end_matches('/')).await.unwrap_err());
+            assert!(error.contains("404 Not Found"), "{}", error);
+
+            let (target, _) = serve_once("HTTP/1.0 200 OK\r\n\r\n{\"grid\": 1}".to_string()).await;
+            let error = format!("{:#}", fetch_descriptor(&target).await.unwrap_err());
+            assert!(error.contains("Failed to decode"), "{}", error);
+        });
+        assert!(runtime.block_on(fetch_descriptor("https://printer.local")).is_err());
+    }
+
+    #[test]
+    fn test_estimate_uses_its_config() {
+        let dir = std::env::temp_dir().join(format!("hg4d-estimate-{}", std::process::id()));
+        let configs = ExampleConfigs::for_model(config_types::PrinterModel::HyperCubeMini).unwrap();
+        let paths = configs.write_to(&dir, true).unwrap();
+        let model = dir.join("cube.stl");
+        let cube = hypergcode_slicer::Mesh {
+            vertices: vec![
+                0.0, 0.0, 0.0, 5.0, 0.0, 0.0, 5.0, 5.0, 0.0, 0.0, 5.0, 0.0, //
+                0.0, 0.0, 5.0, 5.0, 0.0, 5.0, 5.0, 5.0, 5.0, 0.0, 5.0, 5.0,
+            ],
+            indices: vec![
+                0, 2, 1, 0, 3, 2, 4, 5, 6, 4, 6, 7, 0, 1, 5, 0, 5, 4, //
+                1, 2, 6, 1, 6, 5, 2, 3, 7, 2, 7, 6, 3, 0, 4, 3, 4, 7,
+            ],
+            normals: None,
+            face_channels: None,
+            face_objects: None,
+            units: hypergcode_slicer::MeshUnits::Millimeters,
+        };
+        writer_for(MeshFormat::StlBinary, Default::default()).unwrap().write(&cube, &model).unwrap();
+
+        // The subcommand's --config is not the top-level default
+        let arg = |path: &PathBuf| path.to_string_lossy().into_owned();
+        let mut cli = Cli::parse_from(vec![
+            "hg4d-slicer".to_string(),
+            "-s".to_string(), arg(&paths[1]),
+            "-m".to_string(), arg(&paths[2]),
+            "-j".to_string(), "2".to_string(),
+            "--report".to_string(),
+            "estimate".to_string(), arg(&model),
+            "-c".to_string(), arg(&paths[0]),
+        ]);
+        let config = RuntimeConfig::load(&paths[0], &cli).unwrap();
+        assert_eq!(config.slicer_config.worker_threads, 2);
+        assert!(config.slicer_config.write_report);
+        assert_eq!(config.material_profiles.len(), 1);
+
+        let command = cli.command.take().unwrap();
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        runtime.block_on(handle_subcommand(command, &cli)).unwrap();
+
+        std::fs::remove_dir_all(&dir).ok();
     }
 
     #[test]
@@ -513,4 +1766,130 @@ mod tests {
         let cli = Cli::parse_from(args);
         assert!(matches!(cli.command, Some(Commands::Estimate { .. })));
     }
+
+    #[test]
+    fn test_info_parsing() {
+        let cli = Cli::parse_from(vec!["hg4d-slicer", "info", "part.hg4d", "--verify", "-c", "printer.toml"]);
+        match cli.command {
+            Some(Commands::Info { input, config, layers, verify }) => {
+                assert_eq!(input, PathBuf::from("part.hg4d"));
+                assert_eq!(config, Some(PathBuf::from("printer.toml")));
+                assert!(verify && !layers);
+            }
+            other => panic!("unexpected command {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_injection_layout_parsing() {
+        let cli = Cli::parse_from(vec![
+            "hg4d-slicer", "injection-layout", "a.hg4d", "demand.json",
+            "-c", "printer.toml", "--points", "2", "--perimeter",
+        ]);
+        match cli.command {
+            Some(Commands::InjectionLayout { inputs, points, perimeter, max_path_length, .. }) => {
+                assert_eq!(inputs, vec![PathBuf::from("a.hg4d"), PathBuf::from("demand.json")]);
+                assert_eq!(points, Some(2));
+                assert!(perimeter && max_path_length.is_none());
+            }
+            other => panic!("unexpected command {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_calibrate_reads_recorded_timings() {
+        let cli = Cli::parse_from(vec!["hg4d-slicer", "calibrate", "bay3.jsonl", "--model", "bay3.json"]);
+        match cli.command {
+            Some(Commands::Calibrate { reports, config, model }) => {
+                assert_eq!(reports, vec![PathBuf::from("bay3.jsonl")]);
+                assert_eq!((config, model), (PathBuf::from("printer.toml"), PathBuf::from("bay3.json")));
+            }
+            other => panic!("unexpected command {:?}", other),
+        }
+
+        let report = LayerTimingReport {
+            layer_number: 4,
+            z_height: 1.0,
+            duration_ms: 2400,
+            valve_switches: 120,
+            pressure_waits: 1,
+            z_travel_mm: 0.2,
+            deposited_volume_mm3: 15.0,
+            barrier_waits: Vec::new(),
+        };
+        let bare = serde_json::to_string(&report).unwrap();
+        assert_eq!(parse_timing_report(&bare).unwrap(), Some(report.clone()));
+        let wire = |message| String::from_utf8(protocol::serialize_message(&message).unwrap()).unwrap();
+        let message = wire(ProtocolMessage::LayerTiming(report.clone()));
+        assert_eq!(parse_timing_report(&message).unwrap(), Some(report));
+        let other = wire(protocol::create_error_event(protocol::ErrorSeverity::Info, "TEST", "not a timing"));
+        assert_eq!(parse_timing_report(&other).unwrap(), None);
+        assert!(parse_timing_report("{").is_err());
+    }
+
+    #[test]
+    fn test_placement_flags() {
+        let cli = Cli::parse_from(vec![
+            "hg4d-slicer",
+            "--input", "model.stl",
+            "--translate", "-5,10",
+            "--rotate", "x:90",
+            "--rotate", "Z:-45",
+            "--scale", "2",
+            "--auto-orient",
+        ]);
+
+        assert_eq!(
+            placement_transforms(&cli),
+            vec![
+                MeshTransform::Scale { x: 2.0, y: 2.0, z: 2.0 },
+                MeshTransform::Rotate { axis: Axis::X, degrees: 90.0 },
+                MeshTransform::Rotate { axis: Axis::Z, degrees: -45.0 },
+                MeshTransform::AutoOrient,
+                MeshTransform::Translate { x: -5.0, y: 10.0 },
+            ]
+        );
+        assert!(parse_scale("1,0,1").is_err());
+    }
+
+    #[test]
+    fn test_post_process_flags() {
+        let cli = Cli::parse_from(vec![
+            "hg4d-slicer",
+            "--input", "model.stl",
+            "--post-process", "pauses.py",
+            "--post-process", "annotate.wasm",
+            "--pause-at-layer", "12",
+            "--pause-at-z", "25.5",
+        ]);
+
+        assert_eq!(
+            cli.post_process,
+            vec![PathBuf::from("pauses.py"), PathBuf::from("annotate.wasm")]
+        );
+        assert_eq!(cli.wasm_runtime, "wasmtime");
+        assert_eq!(cli.pause_at_layer, vec![12]);
+        assert_eq!(cli.pause_at_z, vec![25.5]);
+    }
+
+    #[test]
+    fn test_plate_flags() {
+        let cli = Cli::parse_from(vec![
+            "hg4d-slicer",
+            "--input", "bracket.stl",
+            "--channel", "0",
+            "--add", "gear.stl@1",
+            "--add", "lid.obj",
+            "--object-spacing", "8",
+        ]);
+
+        assert_eq!(cli.channel, Some(0));
+        assert_eq!(
+            cli.add,
+            vec![(PathBuf::from("gear.stl"), Some(1)), (PathBuf::from("lid.obj"), None)]
+        );
+        assert_eq!(cli.object_spacing, 8.0);
+        assert!(!cli.no_arrange);
+        assert!(parse_plate_input("gear.stl@x").is_err());
+    }
 }
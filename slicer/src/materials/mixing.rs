@@ -1,15 +1,136 @@
-use gcode_types::Color;
+//! Color mixing.
+//!
+//! Converts target colors into per-channel mixing ratios for G4C commands.
+//! Each loaded material contributes its base color (`MaterialProfile::color`)
+//! and the mix is modelled as a weighted average of those colors in linear
+//! light. Ratios are non-negative and sum to 1, so the reachable colors form
+//! the convex hull of the base colors; targets outside it are clamped to the
+//! nearest reachable color and reported with a warning.
+
+use anyhow::{bail, Result};
+use tracing::warn;
+
 use config_types::MaterialProfile;
+use gcode_types::{Color, G4CCommand};
+
+/// Default distance (sRGB units, 0-255 per component) within which a mix
+/// counts as reaching its target.
+pub const DEFAULT_GAMUT_TOLERANCE: f32 = 3.0;
+
+/// Ratios below this are dropped from the plan.
+const MIN_RATIO: f32 = 1e-3;
+
+const SOLVER_ITERATIONS: usize = 500;
+
+/// Mixing ratios for one target color.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MixPlan {
+    pub target: Color,
+    /// Channel ratios (summing to 1), channels with no contribution omitted
+    pub ratios: Vec<(u8, f32)>,
+    /// Color the ratios actually produce
+    pub achieved: Color,
+    /// Distance between target and achieved color (sRGB units)
+    pub error: f32,
+    /// Whether the target lies within the materials' gamut
+    pub in_gamut: bool,
+}
+
+impl MixPlan {
+    /// G4C command selecting this mix.
+    pub fn to_command(&self) -> G4CCommand {
+        G4CCommand {
+            color: Some(self.target),
+            material_channel: None,
+            mixing_ratios: Some(self.ratios.clone()),
+        }
+    }
+}
 
-pub struct MaterialMixer;
+pub struct MaterialMixer {
+    tolerance: f32,
+}
 
 impl MaterialMixer {
     pub fn new() -> Self {
-        Self
+        Self {
+            tolerance: DEFAULT_GAMUT_TOLERANCE,
+        }
+    }
+
+    pub fn with_tolerance(mut self, tolerance: f32) -> Self {
+        self.tolerance = tolerance;
+        self
+    }
+
+    /// Base colors of loaded materials, indexed by channel (profile index).
+    /// Materials without a color cannot take part in mixing and are skipped.
+    pub fn channel_colors(profiles: &[MaterialProfile]) -> Vec<(u8, Color)> {
+        profiles
+            .iter()
+            .enumerate()
+            .filter_map(|(channel, profile)| {
+                profile
+                    .color
+                    .map(|[r, g, b]| (channel as u8, Color::new(r, g, b)))
+            })
+            .collect()
     }
 
     pub fn calculate_mix_ratios(&self, target_color: Color, available_colors: &[Color]) -> Vec<(usize, f32)> {
-        todo!("Implementation needed: Calculate mixing ratios to achieve target color")
+        let bases: Vec<[f32; 3]> = available_colors.iter().map(to_linear).collect();
+        solve_simplex(&bases, to_linear(&target_color))
+            .into_iter()
+            .enumerate()
+            .filter(|(_, w)| *w > 0.0)
+            .collect()
+    }
+
+    /// Plans the mix for one target color.
+    pub fn plan(&self, target: Color, channels: &[(u8, Color)]) -> Result<MixPlan> {
+        if channels.is_empty() {
+            bail!("No materials with a base color are loaded; cannot mix {}", target);
+        }
+
+        let bases: Vec<[f32; 3]> = channels.iter().map(|(_, c)| to_linear(c)).collect();
+        let weights = solve_simplex(&bases, to_linear(&target));
+
+        let mut mixed = [0.0f32; 3];
+        for (w, base) in weights.iter().zip(&bases) {
+            for i in 0..3 {
+                mixed[i] += w * base[i];
+            }
+        }
+        let achieved = from_linear(mixed);
+        let error = distance(&target, &achieved);
+        let in_gamut = error <= self.tolerance;
+        if !in_gamut {
+            warn!(
+                "Color {} is outside the loaded materials' gamut; using {} (off by {:.1})",
+                target, achieved, error
+            );
+        }
+
+        let ratios = channels
+            .iter()
+            .zip(&weights)
+            .filter(|(_, w)| **w > 0.0)
+            .map(|((channel, _), w)| (*channel, *w))
+            .collect();
+
+        Ok(MixPlan {
+            target,
+            ratios,
+            achieved,
+            error,
+            in_gamut,
+        })
+    }
+
+    /// Plans mixes for per-region targets, in the order given.
+    pub fn plan_regions(&self, targets: &[Color], profiles: &[MaterialProfile]) -> Result<Vec<MixPlan>> {
+        let channels = Self::channel_colors(profiles);
+        targets.iter().map(|t| self.plan(*t, &channels)).collect()
     }
 
     pub fn blend_properties(&self, materials: &[(MaterialProfile, f32)]) -> BlendedProperties {
@@ -17,6 +138,12 @@ impl MaterialMixer {
     }
 }
 
+impl Default for MaterialMixer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct BlendedProperties {
     pub viscosity: f32,
@@ -24,3 +151,133 @@ pub struct BlendedProperties {
     pub temp_range: (f32, f32),
 }
 
+// Color space helpers
+
+fn srgb_to_linear(v: u8) -> f32 {
+    let c = v as f32 / 255.0;
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(v: f32) -> u8 {
+    let v = v.clamp(0.0, 1.0);
+    let c = if v <= 0.0031308 {
+        v * 12.92
+    } else {
+        1.055 * v.powf(1.0 / 2.4) - 0.055
+    };
+    (c * 255.0).round() as u8
+}
+
+fn to_linear(c: &Color) -> [f32; 3] {
+    [srgb_to_linear(c.r), srgb_to_linear(c.g), srgb_to_linear(c.b)]
+}
+
+fn from_linear(c: [f32; 3]) -> Color {
+    Color::new(linear_to_srgb(c[0]), linear_to_srgb(c[1]), linear_to_srgb(c[2]))
+}
+
+fn distance(a: &Color, b: &Color) -> f32 {
+    let d = |x: u8, y: u8| (x as f32 - y as f32).powi(2);
+    (d(a.r, b.r) + d(a.g, b.g) + d(a.b, b.b)).sqrt()
+}
+
+/// Minimizes `|Σ wᵢ·baseᵢ − target|²` over weights on the unit simplex by
+/// projected gradient descent. Tiny weights are zeroed and the rest
+/// renormalized.
+fn solve_simplex(bases: &[[f32; 3]], target: [f32; 3]) -> Vec<f32> {
+    let n = bases.len();
+    if n == 0 {
+        return Vec::new();
+    }
+
+    // Step 1/L with L bounded by twice the squared Frobenius norm
+    let lipschitz: f32 = 2.0 * bases.iter().flatten().map(|v| v * v).sum::<f32>();
+    let step = if lipschitz > 0.0 { 1.0 / lipschitz } else { 0.0 };
+
+    let mut w = vec![1.0 / n as f32; n];
+    for _ in 0..SOLVER_ITERATIONS {
+        let mut residual = [0.0f32; 3];
+        for (wi, base) in w.iter().zip(bases) {
+            for k in 0..3 {
+                residual[k] += wi * base[k];
+            }
+        }
+        for k in 0..3 {
+            residual[k] -= target[k];
+        }
+
+        for (wi, base) in w.iter_mut().zip(bases) {
+            let grad: f32 = 2.0 * (0..3).map(|k| base[k] * residual[k]).sum::<f32>();
+            *wi -= step * grad;
+        }
+        project_to_simplex(&mut w);
+    }
+
+    for wi in w.iter_mut() {
+        if *wi < MIN_RATIO {
+            *wi = 0.0;
+        }
+    }
+    let sum: f32 = w.iter().sum();
+    if sum > 0.0 {
+        w.iter_mut().for_each(|wi| *wi /= sum);
+    }
+    w
+}
+
+/// Euclidean projection onto `{w : wᵢ ≥ 0, Σ wᵢ = 1}`.
+fn project_to_simplex(w: &mut [f32]) {
+    let mut sorted = w.to_vec();
+    sorted.sort_by(|a, b| b.total_cmp(a));
+
+    let mut cumulative = 0.0;
+    let mut theta = 0.0;
+    for (i, v) in sorted.iter().enumerate() {
+        cumulative += v;
+        let t = (cumulative - 1.0) / (i + 1) as f32;
+        if v - t > 0.0 {
+            theta = t;
+        }
+    }
+    w.iter_mut().for_each(|wi| *wi = (*wi - theta).max(0.0));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mix_within_gamut() {
+        let mixer = MaterialMixer::new();
+        let channels = [(0, Color::RED), (1, Color::BLUE), (2, Color::WHITE)];
+
+        // Half red, half blue in linear light
+        let plan = mixer.plan(Color::new(188, 0, 188), &channels).unwrap();
+        assert!(plan.in_gamut, "error {}", plan.error);
+        assert_eq!(plan.ratios.len(), 2);
+        for (_, ratio) in &plan.ratios {
+            assert!((ratio - 0.5).abs() < 0.02);
+        }
+
+        let total: f32 = plan.ratios.iter().map(|(_, r)| r).sum();
+        assert!((total - 1.0).abs() < 1e-4);
+        assert_eq!(plan.to_command().mixing_ratios.unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_unreachable_color_is_clamped() {
+        let mixer = MaterialMixer::new();
+        let channels = [(0, Color::RED), (1, Color::BLUE)];
+
+        let plan = mixer.plan(Color::GREEN, &channels).unwrap();
+        assert!(!plan.in_gamut);
+        assert_eq!(plan.achieved.g, 0);
+        assert!(plan.error > 200.0);
+
+        assert!(mixer.plan(Color::GREEN, &[]).is_err());
+    }
+}
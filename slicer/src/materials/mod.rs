@@ -18,4 +18,4 @@ pub mod mixing;
 pub use profiles::MaterialProfileManager;
 pub use multi_material::MultiMaterialCoordinator;
 pub use purge::PurgeCalculator;
-pub use mixing::MaterialMixer;
+pub use mixing::{MaterialMixer, MixPlan};
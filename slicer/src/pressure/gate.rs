@@ -0,0 +1,266 @@
+//! Pressure validation gate with staggered activation.
+//!
+//! Every layer's routing is simulated before G-code generation. When the
+//! simulation leaves the safe range, usually because a dense layer opens so
+//! many valves at once that the supply sags, [`PressureGate`] retries with
+//! the layer split into [`ActivationGroups`]: each group is simulated on its
+//! own, and the smallest group count whose groups all pass is kept. The
+//! choice is written into the layer ([`LayerPressureReport::record`]) so the
+//! firmware opens the valves in the same groups the simulation checked.
+//!
+//! A layer that still fails at the gate's maximum group count is rejected
+//! with the problem nodes and paths of its all-at-once simulation.
+
+use anyhow::Result;
+use tracing::{debug, warn};
+
+use gcode_types::{ActivationGroups, GridCoordinate, Layer};
+
+use crate::{
+    OptimizedRouting, PressureConfig, PressureIssue, PressureIssueKind, PressureSimulation,
+    PressureSimulator, PressureValidation, SlicerError,
+};
+
+/// Largest number of groups a layer is split into.
+pub const MAX_ACTIVATION_GROUPS: u8 = 8;
+
+/// Lists the nodes of `simulation` outside `min_pressure..=max_pressure`
+/// and the routing paths feeding each, for
+/// [`PressureSimulator::validate_pressures`] implementations.
+pub fn find_pressure_issues(
+    routing: &OptimizedRouting,
+    simulation: &PressureSimulation,
+    min_pressure: f32,
+    max_pressure: f32,
+) -> PressureValidation {
+    let mut issues: Vec<PressureIssue> = simulation
+        .node_pressures
+        .iter()
+        .filter_map(|(&position, &pressure)| {
+            let kind = if pressure > max_pressure {
+                PressureIssueKind::Overpressure
+            } else if pressure < min_pressure {
+                PressureIssueKind::Starved
+            } else {
+                return None;
+            };
+            Some(PressureIssue {
+                position,
+                kind,
+                pressure,
+                paths: paths_through(routing, position),
+            })
+        })
+        .collect();
+    issues.sort_by_key(|issue| (issue.position.y, issue.position.x));
+    PressureValidation { issues }
+}
+
+fn paths_through(routing: &OptimizedRouting, position: GridCoordinate) -> Vec<usize> {
+    routing
+        .routing_paths
+        .iter()
+        .enumerate()
+        .filter(|(_, path)| path.to == position || path.intermediate_nodes.contains(&position))
+        .map(|(i, _)| i)
+        .collect()
+}
+
+/// The nodes of `routing` opening in `group`, with the paths feeding them.
+pub fn group_routing(routing: &OptimizedRouting, groups: ActivationGroups, group: u8) -> OptimizedRouting {
+    let mut sub = routing.clone();
+    sub.activation_map
+        .active_nodes
+        .retain(|node| groups.group_of(node.position) == group);
+    sub.routing_paths.retain(|path| groups.group_of(path.to) == group);
+    let kept = &sub.routing_paths;
+    sub.estimated_pressure.retain(|position, _| {
+        groups.group_of(*position) == group
+            || kept.iter().any(|path| path.intermediate_nodes.contains(position))
+    });
+    sub
+}
+
+/// Outcome of checking one layer.
+#[derive(Debug, Clone)]
+pub struct LayerPressureReport {
+    pub layer_number: u32,
+    /// Simulation of the layer with every valve opening at once
+    pub simulation: PressureSimulation,
+    /// Problem nodes of that simulation; empty if the layer passed as is
+    pub issues: Vec<PressureIssue>,
+    /// Groups the layer passed with, if it needed staggering
+    pub groups: Option<ActivationGroups>,
+}
+
+impl LayerPressureReport {
+    /// Writes the chosen opening strategy into the generated layer.
+    pub fn record(&self, layer: &mut Layer) {
+        layer.activation_groups = self.groups;
+    }
+}
+
+/// Simulates layers and picks their activation groups; see the module
+/// documentation.
+pub struct PressureGate<'a> {
+    simulator: &'a dyn PressureSimulator,
+    config: PressureConfig,
+    max_groups: u8,
+}
+
+impl<'a> PressureGate<'a> {
+    pub fn new(simulator: &'a dyn PressureSimulator, config: PressureConfig) -> Self {
+        Self {
+            simulator,
+            config,
+            max_groups: MAX_ACTIVATION_GROUPS,
+        }
+    }
+
+    /// Limits splitting to `max_groups`; below 2 disables staggering.
+    pub fn with_max_groups(mut self, max_groups: u8) -> Self {
+        self.max_groups = max_groups;
+        self
+    }
+
+    /// Simulates the layer, staggering it if needed. Fails if no group
+    /// count up to the maximum keeps every node within limits.
+    pub fn check_layer(&self, routing: &OptimizedRouting) -> Result<LayerPressureReport> {
+        let layer_number = routing.activation_map.layer_number;
+        let simulation = self.simulator.simulate(routing, &self.config)?;
+        let validation = self.simulator.validate_pressures(routing, &simulation)?;
+        let mut report = LayerPressureReport {
+            layer_number,
+            simulation,
+            issues: Vec::new(),
+            groups: None,
+        };
+        if validation.is_ok() {
+            return Ok(report);
+        }
+
+        debug!("Layer {} fails pressure limits: {}", layer_number, validation.summary());
+        for count in 2..=self.max_groups {
+            let groups = ActivationGroups::new(count);
+            if self.groups_pass(routing, groups)? {
+                warn!(
+                    "Layer {}: opening valves in {} groups ({})",
+                    layer_number,
+                    count,
+                    validation.summary()
+                );
+                report.issues = validation.issues;
+                report.groups = Some(groups);
+                return Ok(report);
+            }
+        }
+
+        Err(SlicerError::PressureSimulation(format!(
+            "Layer {} exceeds pressure limits even in {} groups: {}",
+            layer_number,
+            self.max_groups.max(1),
+            validation.summary()
+        ))
+        .into())
+    }
+
+    fn groups_pass(&self, routing: &OptimizedRouting, groups: ActivationGroups) -> Result<bool> {
+        for group in 0..groups.count {
+            let sub = group_routing(routing, groups, group);
+            if sub.activation_map.active_nodes.is_empty() {
+                continue;
+            }
+            let simulation = self.simulator.simulate(&sub, &self.config)?;
+            if !self.simulator.validate_pressures(&sub, &simulation)?.is_ok() {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ActiveNode, RoutingPath, ValveActivationMap};
+    use std::collections::HashMap;
+
+    /// Supply sags by 5 PSI per open node.
+    struct SaggingSupply {
+        min_pressure: f32,
+    }
+
+    impl PressureSimulator for SaggingSupply {
+        fn simulate(&self, routing: &OptimizedRouting, config: &PressureConfig) -> Result<PressureSimulation> {
+            let nodes = &routing.activation_map.active_nodes;
+            let pressure = config.supply_pressure - 5.0 * nodes.len() as f32;
+            Ok(PressureSimulation {
+                node_pressures: nodes.iter().map(|n| (n.position, pressure)).collect(),
+                flow_rates: HashMap::new(),
+                max_pressure: pressure,
+                min_pressure: pressure,
+                pressure_stable: true,
+            })
+        }
+
+        fn validate_pressures(
+            &self,
+            routing: &OptimizedRouting,
+            simulation: &PressureSimulation,
+        ) -> Result<PressureValidation> {
+            Ok(find_pressure_issues(routing, simulation, self.min_pressure, 120.0))
+        }
+    }
+
+    fn routing(count: u32) -> OptimizedRouting {
+        let active_nodes = (0..count)
+            .map(|x| ActiveNode {
+                position: GridCoordinate::new(x, 0),
+                material_channel: 0,
+                required_valves: vec![0],
+                object_id: None,
+                extrusion: None,
+            })
+            .collect();
+        OptimizedRouting {
+            activation_map: ValveActivationMap { layer_number: 7, z_height: 1.6, active_nodes },
+            routing_paths: vec![RoutingPath {
+                from: GridCoordinate::new(0, 0),
+                to: GridCoordinate::new(3, 0),
+                intermediate_nodes: vec![GridCoordinate::new(1, 0), GridCoordinate::new(2, 0)],
+                valve_sequence: vec![],
+            }],
+            estimated_pressure: HashMap::new(),
+            efficiency: 1.0,
+        }
+    }
+
+    #[test]
+    fn test_layers_are_staggered_until_within_limits() {
+        let config = PressureConfig {
+            supply_pressure: 100.0,
+            material_viscosity: 1.0,
+            channel_diameter: 1.0,
+        };
+        let simulator = SaggingSupply { min_pressure: 60.0 };
+        let gate = PressureGate::new(&simulator, config.clone());
+
+        // 4 nodes sag to 80 PSI: fine as is
+        let report = gate.check_layer(&routing(4)).unwrap();
+        assert!(report.groups.is_none() && report.issues.is_empty());
+
+        // 10 nodes sag to 50 PSI; two groups of 5 stay at 75
+        let report = gate.check_layer(&routing(10)).unwrap();
+        assert_eq!(report.groups, Some(ActivationGroups::new(2)));
+        assert_eq!(report.issues.len(), 10);
+        assert!(report.issues.iter().all(|i| i.kind == PressureIssueKind::Starved));
+        assert_eq!(report.issues[2].paths, vec![0], "node 2 lies on the path");
+        let mut layer = Layer::new(1.6, 7);
+        report.record(&mut layer);
+        assert_eq!(layer.activation_groups, Some(ActivationGroups::new(2)));
+
+        let strict = SaggingSupply { min_pressure: 99.0 };
+        let err = PressureGate::new(&strict, config).check_layer(&routing(10)).unwrap_err();
+        assert!(err.to_string().contains("Layer 7"), "{}", err);
+    }
+}
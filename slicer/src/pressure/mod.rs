@@ -8,11 +8,20 @@
 //! - **simulator**: Fluid flow physics simulation
 //! - **optimizer**: Pressure-aware routing optimization
 //! - **analysis**: Flow pattern analysis
+//! - **placement**: Injection point layout evaluation
+//! - **gate**: Per-layer pressure validation and staggered activation groups
+//! - **transient**: Pressure spikes on valve closure
 
 pub mod simulator;
 pub mod optimizer;
 pub mod analysis;
+pub mod placement;
+pub mod gate;
+pub mod transient;
 
 pub use simulator::FluidFlowSimulator;
 pub use optimizer::PressureOptimizer;
 pub use analysis::FlowAnalyzer;
+pub use placement::{DemandProfile, PlacementAnalyzer, PlacementReport};
+pub use gate::{find_pressure_issues, LayerPressureReport, PressureGate};
+pub use transient::{ClosureTransient, LayerTransientReport, TransientConfig, TransientSimulator};
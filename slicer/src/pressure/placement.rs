@@ -0,0 +1,557 @@
+//! Injection point placement analysis.
+//!
+//! Injection points are fixed in the printer configuration, but where they
+//! sit decides how far material travels through the valve network on every
+//! layer. When designing a printer, [`PlacementAnalyzer`] scores candidate
+//! layouts against a [`DemandProfile`]: how many layers each grid node was
+//! active in, per material channel, over representative prints.
+//!
+//! An active node is fed from the nearest injection point on its channel.
+//! Its route length is the Manhattan distance (the uncongested A* path) and
+//! its pressure drop follows the routing optimizer's per-segment loss.
+//! Candidates per channel are:
+//!
+//! - the layout in the printer configuration
+//! - symmetric patterns: front edge, edge midpoints, corners, perimeter
+//!   ring and an interior lattice
+//! - a demand-weighted k-medians fit, which minimizes mean route length
+//!
+//! Layouts are ranked by the share of demand out of reach, then by mean
+//! pressure drop.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use config_types::{InjectionPoint, PrinterConfig};
+use gcode_types::{GridCoordinate, Layer};
+
+use crate::core::path_optimizer::PRESSURE_LOSS_PER_SEGMENT;
+use crate::ValveActivationMap;
+
+/// Reassignment rounds of the k-medians fit.
+const FIT_ITERATIONS: usize = 50;
+
+/// Node activity over a set of representative layers.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DemandProfile {
+    pub grid_x: u32,
+    pub grid_y: u32,
+    /// Layers recorded
+    pub layers: u32,
+    /// Layers each node was active in, per channel, row-major
+    pub activations: BTreeMap<u8, Vec<u32>>,
+}
+
+impl DemandProfile {
+    pub fn new(grid_x: u32, grid_y: u32) -> Self {
+        Self {
+            grid_x,
+            grid_y,
+            layers: 0,
+            activations: BTreeMap::new(),
+        }
+    }
+
+    pub fn for_printer(config: &PrinterConfig) -> Self {
+        Self::new(config.grid_x_count(), config.grid_y_count())
+    }
+
+    /// Records a layer read from a sliced file. Nodes without a channel of
+    /// their own use the layer's primary channel.
+    pub fn add_layer(&mut self, layer: &Layer) {
+        self.layers += 1;
+        for node in layer.nodes.iter().filter(|n| n.open_count() > 0) {
+            let channel = node.material_channel.or(layer.primary_material).unwrap_or(0);
+            self.record(channel, node.position);
+        }
+    }
+
+    /// Records a layer straight from valve mapping.
+    pub fn add_activation_map(&mut self, map: &ValveActivationMap) {
+        self.layers += 1;
+        for node in &map.active_nodes {
+            self.record(node.material_channel, node.position);
+        }
+    }
+
+    /// Adds another profile of the same grid.
+    pub fn merge(&mut self, other: &DemandProfile) -> Result<()> {
+        if (other.grid_x, other.grid_y) != (self.grid_x, self.grid_y) {
+            anyhow::bail!(
+                "Profile grid {}x{} does not match {}x{}",
+                other.grid_x,
+                other.grid_y,
+                self.grid_x,
+                self.grid_y
+            );
+        }
+        self.layers += other.layers;
+        for (&channel, counts) in &other.activations {
+            let own = self.channel_mut(channel);
+            for (own, count) in own.iter_mut().zip(counts) {
+                *own += count;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read demand profile {}", path.display()))?;
+        let profile: Self = serde_json::from_str(&text)
+            .with_context(|| format!("Invalid demand profile {}", path.display()))?;
+        let cells = (profile.grid_x * profile.grid_y) as usize;
+        if let Some((channel, _)) = profile.activations.iter().find(|(_, c)| c.len() != cells) {
+            anyhow::bail!("Demand profile channel {} does not cover the {} node grid", channel, cells);
+        }
+        Ok(profile)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        std::fs::write(path, serde_json::to_string(self)?)
+            .with_context(|| format!("Failed to write demand profile {}", path.display()))
+    }
+
+    pub fn channels(&self) -> impl Iterator<Item = u8> + '_ {
+        self.activations.keys().copied()
+    }
+
+    /// Active nodes of a channel with their activation counts.
+    fn demand(&self, channel: u8) -> Vec<(GridCoordinate, f32)> {
+        let Some(counts) = self.activations.get(&channel) else {
+            return Vec::new();
+        };
+        counts
+            .iter()
+            .enumerate()
+            .filter(|(_, &count)| count > 0)
+            .map(|(i, &count)| {
+                let i = i as u32;
+                (GridCoordinate::new(i % self.grid_x, i / self.grid_x), count as f32)
+            })
+            .collect()
+    }
+
+    fn record(&mut self, channel: u8, position: GridCoordinate) {
+        // Nodes outside the grid belong to a different printer; skip them
+        if position.x >= self.grid_x || position.y >= self.grid_y {
+            return;
+        }
+        let index = (position.y * self.grid_x + position.x) as usize;
+        self.channel_mut(channel)[index] += 1;
+    }
+
+    fn channel_mut(&mut self, channel: u8) -> &mut Vec<u32> {
+        let cells = (self.grid_x * self.grid_y) as usize;
+        self.activations.entry(channel).or_insert_with(|| vec![0; cells])
+    }
+}
+
+/// How one layout serves a channel's demand.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LayoutScore {
+    pub name: String,
+    pub points: Vec<GridCoordinate>,
+    /// Demand-weighted mean route length in grid segments
+    pub mean_path_length: f32,
+    pub max_path_length: u32,
+    /// Demand-weighted mean fraction of supply pressure lost
+    pub mean_pressure_drop: f32,
+    pub max_pressure_drop: f32,
+    /// Share of demand beyond the routing path limit
+    pub out_of_reach: f32,
+    /// Busiest point's share of demand over an even split; 1.0 is balanced
+    pub load_imbalance: f32,
+}
+
+/// Ranked layouts for one material channel, best first.
+#[derive(Debug, Clone)]
+pub struct ChannelPlacement {
+    pub channel: u8,
+    pub candidates: Vec<LayoutScore>,
+}
+
+impl ChannelPlacement {
+    pub fn best(&self) -> Option<&LayoutScore> {
+        self.candidates.first()
+    }
+
+    /// The configured layout's score, if the channel has one.
+    pub fn configured(&self) -> Option<&LayoutScore> {
+        self.candidates.iter().find(|c| c.name == "configured")
+    }
+}
+
+/// Result of a placement analysis.
+#[derive(Debug, Clone)]
+pub struct PlacementReport {
+    pub grid_spacing: f32,
+    pub layers: u32,
+    pub channels: Vec<ChannelPlacement>,
+}
+
+impl PlacementReport {
+    /// The best layout of every channel as configuration entries, numbered
+    /// in channel order.
+    pub fn recommended(&self) -> Vec<InjectionPoint> {
+        let mut points = Vec::new();
+        for placement in &self.channels {
+            let Some(best) = placement.best() else {
+                continue;
+            };
+            for point in &best.points {
+                points.push(InjectionPoint {
+                    id: points.len() as u8,
+                    x: point.x as f32 * self.grid_spacing,
+                    y: point.y as f32 * self.grid_spacing,
+                    material_channel: placement.channel,
+                });
+            }
+        }
+        points
+    }
+}
+
+/// Evaluates injection point layouts against a demand profile.
+#[derive(Debug, Clone)]
+pub struct PlacementAnalyzer {
+    grid_x: u32,
+    grid_y: u32,
+    grid_spacing: f32,
+    configured: Vec<InjectionPoint>,
+    max_path_length: Option<u32>,
+    perimeter_only: bool,
+}
+
+impl PlacementAnalyzer {
+    pub fn new(config: &PrinterConfig) -> Self {
+        Self {
+            grid_x: config.grid_x_count().max(1),
+            grid_y: config.grid_y_count().max(1),
+            grid_spacing: config.valve_array.grid_spacing,
+            configured: config.valve_array.injection_points.clone(),
+            max_path_length: None,
+            perimeter_only: false,
+        }
+    }
+
+    /// Counts demand further than `segments` from every point as out of
+    /// reach.
+    pub fn with_max_path_length(mut self, segments: u32) -> Self {
+        self.max_path_length = Some(segments);
+        self
+    }
+
+    /// Only considers points on the grid edge, where feed lines can enter
+    /// without crossing the build area.
+    pub fn perimeter_only(mut self, perimeter_only: bool) -> Self {
+        self.perimeter_only = perimeter_only;
+        self
+    }
+
+    /// Scores every candidate for each channel in the profile.
+    /// `points_per_channel` of None uses the configured count (at least one).
+    pub fn analyze(&self, profile: &DemandProfile, points_per_channel: Option<usize>) -> Result<PlacementReport> {
+        if (profile.grid_x, profile.grid_y) != (self.grid_x, self.grid_y) {
+            anyhow::bail!(
+                "Demand profile grid {}x{} does not match the printer's {}x{}",
+                profile.grid_x,
+                profile.grid_y,
+                self.grid_x,
+                self.grid_y
+            );
+        }
+
+        let mut channels = Vec::new();
+        for channel in profile.channels() {
+            let demand = profile.demand(channel);
+            if demand.is_empty() {
+                continue;
+            }
+            let configured: Vec<GridCoordinate> = self
+                .configured
+                .iter()
+                .filter(|p| p.material_channel == channel)
+                .map(|p| self.to_grid(p.x, p.y))
+                .collect();
+            let count = points_per_channel.unwrap_or(configured.len()).max(1);
+
+            let mut layouts = Vec::new();
+            if !configured.is_empty() {
+                layouts.push(("configured".to_string(), configured));
+            }
+            layouts.extend(self.patterns(count));
+            let fitted = self.fit(&demand, self.lattice(count));
+            layouts.push(("fitted".to_string(), fitted));
+
+            let mut candidates: Vec<LayoutScore> = Vec::new();
+            for (name, mut points) in layouts {
+                points.sort_by_key(|p| (p.y, p.x));
+                points.dedup();
+                if candidates.iter().any(|c| c.points == points) {
+                    continue;
+                }
+                candidates.push(self.score(name, points, &demand));
+            }
+            candidates.sort_by(|a, b| {
+                a.out_of_reach
+                    .total_cmp(&b.out_of_reach)
+                    .then(a.mean_pressure_drop.total_cmp(&b.mean_pressure_drop))
+            });
+            channels.push(ChannelPlacement { channel, candidates });
+        }
+
+        Ok(PlacementReport {
+            grid_spacing: self.grid_spacing,
+            layers: profile.layers,
+            channels,
+        })
+    }
+
+    fn score(&self, name: String, points: Vec<GridCoordinate>, demand: &[(GridCoordinate, f32)]) -> LayoutScore {
+        let mut total = 0.0f64;
+        let mut length_sum = 0.0f64;
+        let mut drop_sum = 0.0f64;
+        let mut max_length = 0;
+        let mut unreachable = 0.0f64;
+        let mut load = vec![0.0f64; points.len()];
+        for &(node, weight) in demand {
+            let (nearest, length) = nearest(&points, node);
+            let weight = weight as f64;
+            total += weight;
+            length_sum += weight * length as f64;
+            drop_sum += weight * pressure_drop(length) as f64;
+            max_length = max_length.max(length);
+            load[nearest] += weight;
+            if self.max_path_length.is_some_and(|limit| length > limit) {
+                unreachable += weight;
+            }
+        }
+
+        let total = total.max(f64::EPSILON);
+        let busiest = load.iter().copied().fold(0.0, f64::max);
+        LayoutScore {
+            name,
+            mean_path_length: (length_sum / total) as f32,
+            max_path_length: max_length,
+            mean_pressure_drop: (drop_sum / total) as f32,
+            max_pressure_drop: pressure_drop(max_length),
+            out_of_reach: (unreachable / total) as f32,
+            load_imbalance: (busiest / total * points.len() as f64) as f32,
+            points,
+        }
+    }
+
+    /// Symmetric layouts of `count` points.
+    fn patterns(&self, count: usize) -> Vec<(String, Vec<GridCoordinate>)> {
+        let (max_x, max_y) = (self.grid_x - 1, self.grid_y - 1);
+        let (mid_x, mid_y) = (max_x / 2, max_y / 2);
+        let mut patterns = vec![(
+            "front edge".to_string(),
+            (0..count)
+                .map(|i| GridCoordinate::new(self.grid_x * (i as u32 + 1) / (count as u32 + 1), 0))
+                .collect(),
+        )];
+        if count <= 4 {
+            let midpoints = [(mid_x, 0), (mid_x, max_y), (0, mid_y), (max_x, mid_y)];
+            let corners = [(0, 0), (max_x, max_y), (max_x, 0), (0, max_y)];
+            for (name, positions) in [("edge midpoints", midpoints), ("corners", corners)] {
+                let points = positions[..count].iter().map(|&(x, y)| GridCoordinate::new(x, y)).collect();
+                patterns.push((name.to_string(), points));
+            }
+        }
+        patterns.push(("perimeter ring".to_string(), self.ring(count)));
+        if !self.perimeter_only {
+            let name = if count == 1 { "centre" } else { "lattice" };
+            patterns.push((name.to_string(), self.lattice(count)));
+        }
+        patterns
+    }
+
+    /// `count` points evenly spaced around the grid edge.
+    fn ring(&self, count: usize) -> Vec<GridCoordinate> {
+        let (w, h) = (self.grid_x - 1, self.grid_y - 1);
+        let perimeter = (2 * (w + h)).max(1);
+        (0..count as u32)
+            .map(|i| {
+                let mut d = (perimeter * (2 * i + 1) / (2 * count as u32)) % perimeter;
+                if d < w {
+                    return GridCoordinate::new(d, 0);
+                }
+                d -= w;
+                if d < h {
+                    return GridCoordinate::new(w, d);
+                }
+                d -= h;
+                if d < w {
+                    return GridCoordinate::new(w - d, h);
+                }
+                GridCoordinate::new(0, h - (d - w))
+            })
+            .collect()
+    }
+
+    /// Centres of the cells of the smallest near-square lattice holding
+    /// `count` points.
+    fn lattice(&self, count: usize) -> Vec<GridCoordinate> {
+        let cols = (count as f32).sqrt().ceil() as u32;
+        let rows = (count as u32).div_ceil(cols);
+        (0..count as u32)
+            .map(|i| {
+                let (col, row) = (i % cols, i / cols);
+                GridCoordinate::new(
+                    self.grid_x * (2 * col + 1) / (2 * cols),
+                    self.grid_y * (2 * row + 1) / (2 * rows),
+                )
+            })
+            .collect()
+    }
+
+    /// Weighted k-medians: assign nodes to their nearest point, move each
+    /// point to the weighted median of its nodes, repeat until stable. For
+    /// the Manhattan metric each move can only shorten the mean route.
+    fn fit(&self, demand: &[(GridCoordinate, f32)], start: Vec<GridCoordinate>) -> Vec<GridCoordinate> {
+        let mut points: Vec<GridCoordinate> = start.into_iter().map(|p| self.constrain(p)).collect();
+        for _ in 0..FIT_ITERATIONS {
+            let mut clusters: Vec<Vec<(GridCoordinate, f32)>> = vec![Vec::new(); points.len()];
+            for &(node, weight) in demand {
+                clusters[nearest(&points, node).0].push((node, weight));
+            }
+            let mut moved: Vec<GridCoordinate> = points
+                .iter()
+                .zip(&clusters)
+                .map(|(&point, cluster)| {
+                    if cluster.is_empty() {
+                        return point;
+                    }
+                    let x = weighted_median(cluster.iter().map(|&(n, w)| (n.x, w)));
+                    let y = weighted_median(cluster.iter().map(|&(n, w)| (n.y, w)));
+                    self.constrain(GridCoordinate::new(x, y))
+                })
+                .collect();
+            // A point serving nothing is wasted; restart it at the node
+            // worst served by the others
+            for (i, cluster) in clusters.iter().enumerate() {
+                if cluster.is_empty() {
+                    let others: Vec<GridCoordinate> =
+                        moved.iter().enumerate().filter(|&(j, _)| j != i).map(|(_, &p)| p).collect();
+                    if let Some(&(node, _)) = demand.iter().max_by_key(|&&(node, _)| nearest(&others, node).1) {
+                        moved[i] = self.constrain(node);
+                    }
+                }
+            }
+            if moved == points {
+                break;
+            }
+            points = moved;
+        }
+        points
+    }
+
+    /// Moves a point onto the nearest edge when only the perimeter is
+    /// allowed.
+    fn constrain(&self, point: GridCoordinate) -> GridCoordinate {
+        if !self.perimeter_only {
+            return point;
+        }
+        let (max_x, max_y) = (self.grid_x - 1, self.grid_y - 1);
+        let edges = [
+            (point.y, GridCoordinate::new(point.x, 0)),
+            (max_y - point.y, GridCoordinate::new(point.x, max_y)),
+            (point.x, GridCoordinate::new(0, point.y)),
+            (max_x - point.x, GridCoordinate::new(max_x, point.y)),
+        ];
+        edges.iter().min_by_key(|(distance, _)| *distance).map(|&(_, p)| p).unwrap_or(point)
+    }
+
+    fn to_grid(&self, x: f32, y: f32) -> GridCoordinate {
+        let index = |mm: f32, count: u32| ((mm / self.grid_spacing).round().max(0.0) as u32).min(count - 1);
+        GridCoordinate::new(index(x, self.grid_x), index(y, self.grid_y))
+    }
+}
+
+/// Index of the closest point and its Manhattan distance.
+fn nearest(points: &[GridCoordinate], node: GridCoordinate) -> (usize, u32) {
+    points
+        .iter()
+        .enumerate()
+        .map(|(i, p)| (i, p.x.abs_diff(node.x) + p.y.abs_diff(node.y)))
+        .min_by_key(|&(_, d)| d)
+        .unwrap_or((0, 0))
+}
+
+fn pressure_drop(segments: u32) -> f32 {
+    1.0 - (1.0 - PRESSURE_LOSS_PER_SEGMENT).powi(segments as i32)
+}
+
+fn weighted_median(values: impl Iterator<Item = (u32, f32)>) -> u32 {
+    let mut values: Vec<(u32, f32)> = values.collect();
+    values.sort_by_key(|&(v, _)| v);
+    let half = values.iter().map(|&(_, w)| w).sum::<f32>() / 2.0;
+    let mut cumulative = 0.0;
+    for &(value, weight) in &values {
+        cumulative += weight;
+        if cumulative >= half {
+            return value;
+        }
+    }
+    values.last().map(|&(v, _)| v).unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ExampleConfigs;
+    use config_types::PrinterModel;
+    use gcode_types::{NodeValveState, ValveState};
+
+    #[test]
+    fn test_fitted_layout_beats_front_edge() {
+        let configs = ExampleConfigs::for_model(PrinterModel::HyperCubeMini).unwrap();
+        let printer = &configs.printer;
+        let (gx, gy) = (printer.grid_x_count(), printer.grid_y_count());
+
+        // Parts always printed in the back-right quarter of the plate
+        let mut profile = DemandProfile::for_printer(printer);
+        for n in 0..5 {
+            let mut layer = Layer::new(0.2 * (n + 1) as f32, n);
+            for x in gx * 3 / 4..gx {
+                for y in gy * 3 / 4..gy {
+                    layer.add_node(NodeValveState::new(GridCoordinate::new(x, y), vec![ValveState::open(0)]));
+                }
+            }
+            profile.add_layer(&layer);
+        }
+        let path = std::env::temp_dir().join(format!("hg4d-demand-{}.json", std::process::id()));
+        profile.save(&path).unwrap();
+        assert_eq!(DemandProfile::load(&path).unwrap(), profile);
+        std::fs::remove_file(&path).ok();
+
+        let report = PlacementAnalyzer::new(printer).analyze(&profile, Some(1)).unwrap();
+        let channel = &report.channels[0];
+        let best = channel.best().unwrap();
+        assert_eq!(best.name, "fitted");
+        assert!(best.mean_pressure_drop < channel.configured().unwrap().mean_pressure_drop);
+        let point = best.points[0];
+        assert!(point.x >= gx * 3 / 4 && point.y >= gy * 3 / 4);
+
+        let recommended = report.recommended();
+        assert_eq!(recommended.len(), 1);
+        assert_eq!(recommended[0].x, point.x as f32 * printer.valve_array.grid_spacing);
+
+        // On the perimeter the fit lands on the nearest edge instead
+        let report = PlacementAnalyzer::new(printer)
+            .perimeter_only(true)
+            .with_max_path_length(gx + gy)
+            .analyze(&profile, Some(1))
+            .unwrap();
+        let best = report.channels[0].best().unwrap();
+        assert!(best.points[0].x == gx - 1 || best.points[0].y == gy - 1);
+        assert_eq!(best.out_of_reach, 0.0);
+    }
+}
@@ -1,11 +1,46 @@
-use crate::{OptimizedRouting, PressureConfig, PressureSimulation};
+//! Built-in pressure model.
+//!
+//! Each injection point is fed through a supply line from the regulator.
+//! Every open node draws material in proportion to its pressure and its
+//! extrusion fraction, and the line loses pressure with the total flow
+//! through it (Hagen-Poiseuille, or a power-law fluid). The plane's own
+//! channels are short next to the supply line and are not modelled, so all
+//! nodes fed from one injection point see the same pressure: dense layers
+//! starve, which the pressure gate answers by staggering them.
+//!
+//! Nodes without a routing path (routing optimization disabled) are fed
+//! from a single shared supply.
+
+use crate::pressure::find_pressure_issues;
+use crate::{OptimizedRouting, PressureConfig, PressureSimulation, PressureSimulator, PressureValidation};
 use gcode_types::GridCoordinate;
 use std::collections::HashMap;
 use anyhow::Result;
 
+/// Flow drawn by a fully open node at the supply pressure (mm³/s).
+pub const NODE_FLOW: f32 = 0.05;
+
+/// Length of the supply line from the regulator to an injection point (mm).
+pub const SUPPLY_LINE_LENGTH: f32 = 100.0;
+
+/// Bore of the supply line (mm).
+pub const SUPPLY_LINE_DIAMETER: f32 = 6.0;
+
+/// Pascals per PSI.
+const PA_PER_PSI: f32 = 6894.76;
+
+/// Largest number of relaxation steps before the solve gives up.
+const MAX_ITERATIONS: u32 = 1000;
+
+/// Change in source pressure (PSI) below which the solve has converged.
+const TOLERANCE: f32 = 1e-3;
+
 pub struct FluidFlowSimulator {
+    /// Relaxation step of the iterative solve, 0 to 1
     time_step: f32,
     viscosity_model: ViscosityModel,
+    min_pressure: f32,
+    max_pressure: f32,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -19,18 +54,202 @@ impl FluidFlowSimulator {
         Self {
             time_step,
             viscosity_model: ViscosityModel::Newtonian,
+            min_pressure: 0.0,
+            max_pressure: f32::MAX,
         }
     }
 
+    pub fn with_viscosity_model(mut self, model: ViscosityModel) -> Self {
+        self.viscosity_model = model;
+        self
+    }
+
+    /// Operating range nodes are validated against (PSI).
+    pub fn with_limits(mut self, min_pressure: f32, max_pressure: f32) -> Self {
+        self.min_pressure = min_pressure;
+        self.max_pressure = max_pressure;
+        self
+    }
+
     pub fn simulate(&self, routing: &OptimizedRouting, config: &PressureConfig) -> Result<PressureSimulation> {
-        todo!("Implementation needed: Simulate pressure distribution through valve network")
+        let (sources, converged) = self.solve_network(routing, config);
+        let feeds = feeding_sources(routing);
+
+        let mut node_pressures = HashMap::new();
+        let mut flow_rates = HashMap::new();
+        for node in &routing.activation_map.active_nodes {
+            let pressure = sources.get(&feeds.get(&node.position).copied()).copied().unwrap_or(0.0);
+            node_pressures.insert(node.position, pressure);
+            flow_rates.insert(node.position, node_flow(node.extrusion, pressure, config.supply_pressure));
+        }
+
+        let max_pressure = node_pressures.values().copied().fold(0.0, f32::max);
+        let min_pressure = node_pressures.values().copied().fold(f32::INFINITY, f32::min);
+        Ok(PressureSimulation {
+            node_pressures,
+            flow_rates,
+            max_pressure,
+            min_pressure: if min_pressure.is_finite() { min_pressure } else { 0.0 },
+            pressure_stable: converged,
+        })
+    }
+
+    /// Pressure lost over `path_length` mm of a channel `diameter` mm wide
+    /// carrying `flow_rate` mm³/s of material of the given viscosity (Pa·s)
+    /// (PSI).
+    fn calculate_pressure_drop(&self, flow_rate: f32, path_length: f32, diameter: f32, viscosity: f32) -> f32 {
+        if flow_rate <= 0.0 || diameter <= 0.0 {
+            return 0.0;
+        }
+        let (q, l, d) = (flow_rate * 1e-9, path_length * 1e-3, diameter * 1e-3);
+        let viscosity = match self.viscosity_model {
+            ViscosityModel::Newtonian => viscosity,
+            ViscosityModel::PowerLaw { n, k } => {
+                // Apparent viscosity at the wall shear rate
+                let shear_rate = 32.0 * q / (std::f32::consts::PI * d.powi(3));
+                k * shear_rate.max(f32::EPSILON).powf(n - 1.0)
+            }
+        };
+        128.0 * viscosity * l * q / (std::f32::consts::PI * d.powi(4)) / PA_PER_PSI
+    }
+
+    /// Pressure at each supply (keyed by injection point, None for nodes
+    /// without a path) and whether the solve converged.
+    fn solve_network(
+        &self,
+        routing: &OptimizedRouting,
+        config: &PressureConfig,
+    ) -> (HashMap<Option<GridCoordinate>, f32>, bool) {
+        let feeds = feeding_sources(routing);
+        let mut demand: HashMap<Option<GridCoordinate>, f32> = HashMap::new();
+        for node in &routing.activation_map.active_nodes {
+            *demand.entry(feeds.get(&node.position).copied()).or_default() += node.extrusion.unwrap_or(1.0);
+        }
+
+        // Flow is proportional to pressure, so the line drop is too: relax
+        // toward P = supply - drop(flow(P)), halving the step whenever it
+        // overshoots
+        let mut converged = true;
+        let pressures = demand
+            .into_iter()
+            .map(|(source, fraction)| {
+                let mut pressure = config.supply_pressure;
+                let mut step = self.time_step.clamp(0.05, 1.0);
+                let mut last_change = f32::INFINITY;
+                let mut settled = false;
+                for _ in 0..MAX_ITERATIONS {
+                    let flow = fraction * NODE_FLOW * pressure / config.supply_pressure.max(f32::EPSILON);
+                    let drop = self.calculate_pressure_drop(
+                        flow,
+                        SUPPLY_LINE_LENGTH,
+                        config.channel_diameter,
+                        config.material_viscosity,
+                    );
+                    let target = (config.supply_pressure - drop).max(0.0);
+                    let next = pressure + step * (target - pressure);
+                    let change = (next - pressure).abs();
+                    pressure = next;
+                    if change < TOLERANCE {
+                        settled = true;
+                        break;
+                    }
+                    if change >= last_change {
+                        step /= 2.0;
+                    }
+                    last_change = change;
+                }
+                converged &= settled;
+                (source, pressure)
+            })
+            .collect();
+        (pressures, converged)
     }
+}
+
+impl PressureSimulator for FluidFlowSimulator {
+    fn simulate(&self, routing: &OptimizedRouting, config: &PressureConfig) -> Result<PressureSimulation> {
+        FluidFlowSimulator::simulate(self, routing, config)
+    }
+
+    fn validate_pressures(
+        &self,
+        routing: &OptimizedRouting,
+        simulation: &PressureSimulation,
+    ) -> Result<PressureValidation> {
+        Ok(find_pressure_issues(routing, simulation, self.min_pressure, self.max_pressure))
+    }
+}
 
-    fn calculate_pressure_drop(&self, flow_rate: f32, path_length: f32, diameter: f32) -> f32 {
-        todo!("Implementation needed: Calculate pressure drop using Hagen-Poiseuille equation")
+/// Injection point each routed node is fed from.
+fn feeding_sources(routing: &OptimizedRouting) -> HashMap<GridCoordinate, GridCoordinate> {
+    routing.routing_paths.iter().map(|path| (path.to, path.from)).collect()
+}
+
+fn node_flow(extrusion: Option<f32>, pressure: f32, supply: f32) -> f32 {
+    extrusion.unwrap_or(1.0) * NODE_FLOW * pressure / supply.max(f32::EPSILON)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ActiveNode, RoutingPath, ValveActivationMap};
+
+    fn routing(count: u32, routed: bool) -> OptimizedRouting {
+        let source = GridCoordinate::new(0, 0);
+        let active_nodes: Vec<ActiveNode> = (1..=count)
+            .map(|x| ActiveNode {
+                position: GridCoordinate::new(x, 0),
+                material_channel: 0,
+                required_valves: vec![0],
+                object_id: None,
+                extrusion: None,
+            })
+            .collect();
+        let routing_paths = if routed {
+            active_nodes
+                .iter()
+                .map(|node| RoutingPath {
+                    from: source,
+                    to: node.position,
+                    intermediate_nodes: Vec::new(),
+                    valve_sequence: Vec::new(),
+                })
+                .collect()
+        } else {
+            Vec::new()
+        };
+        OptimizedRouting {
+            activation_map: ValveActivationMap { layer_number: 0, z_height: 0.2, active_nodes },
+            routing_paths,
+            estimated_pressure: HashMap::new(),
+            efficiency: 1.0,
+        }
     }
 
-    fn solve_network(&self, routing: &OptimizedRouting) -> HashMap<GridCoordinate, f32> {
-        todo!("Implementation needed: Solve network flow equations")
+    #[test]
+    fn test_supply_sags_with_open_nodes() {
+        let simulator = FluidFlowSimulator::new(0.5).with_limits(20.0, 100.0);
+        let config = PressureConfig {
+            supply_pressure: 40.0,
+            material_viscosity: 300.0,
+            channel_diameter: SUPPLY_LINE_DIAMETER,
+        };
+
+        let small = simulator.simulate(&routing(100, true), &config).unwrap();
+        let large = simulator.simulate(&routing(10_000, true), &config).unwrap();
+        assert!(small.pressure_stable && large.pressure_stable);
+        assert!(small.min_pressure > 39.0, "{}", small.min_pressure);
+        assert!(large.min_pressure < small.min_pressure);
+        // Flow falls with pressure
+        let node = GridCoordinate::new(1, 0);
+        assert!(large.flow_rates[&node] < small.flow_rates[&node]);
+
+        assert!(simulator.validate_pressures(&routing(100, true), &small).unwrap().is_ok());
+        let starved = simulator.validate_pressures(&routing(10_000, true), &large).unwrap();
+        assert!(!starved.is_ok());
+
+        // Unrouted nodes share one supply
+        let shared = simulator.simulate(&routing(10_000, false), &config).unwrap();
+        assert!((shared.min_pressure - large.min_pressure).abs() < 0.01);
     }
 }
@@ -0,0 +1,365 @@
+//! Transient pressure on valve closure.
+//!
+//! The network simulation solves for steady flow and so misses what
+//! happens in the milliseconds after many valves close together: the melt
+//! moving through a feed line has to stop, and its momentum briefly pushes
+//! the line above the supply pressure, a water hammer. If the spike exceeds
+//! `SafetyLimits::pressure_fault_threshold`, the firmware's pressure
+//! monitor faults the print.
+//!
+//! [`TransientSimulator`] models each injection point's feed line as a
+//! lumped RLC circuit:
+//!
+//! - inertance `ρℓ/A` of the melt column
+//! - resistance `128μℓ/(πd⁴)` (Hagen-Poiseuille)
+//! - compliance `V/K` of the melt in the line and the closing valves'
+//!   dead volumes, with `K` the melt's bulk modulus
+//!
+//! The supply holds its pressure at the line inlet while the flow out
+//! through the closing valves ramps to zero over the valve response time.
+//! The line is integrated until it settles, and the peak above the supply
+//! is the spike. An instant closure of a frictionless line gives the
+//! Joukowsky rise `ρav`; closing slower than the line's period, or a
+//! viscous melt, damps it.
+//!
+//! The valves of a layer close together when its deposit ends, or group by
+//! group when the pressure gate staggered it. Each closing node is fed from
+//! the nearest injection point on its channel, at the Manhattan distance
+//! used by the routing optimizer; the nodes sharing a point close as one
+//! event on that line.
+
+use std::collections::BTreeMap;
+
+use tracing::warn;
+
+use config_types::{InjectionPoint, MaterialProfile, PrinterConfig, Psi};
+use gcode_types::{ActivationGroups, GridCoordinate};
+
+use crate::{ActiveNode, PressureConfig, ValveActivationMap};
+
+/// Bulk modulus of a polymer melt (Pa).
+pub const MELT_BULK_MODULUS: f64 = 1.5e9;
+
+/// Integration steps per line period and per closure.
+const STEPS_PER_PERIOD: f64 = 40.0;
+
+/// Upper bound on integration steps for one event.
+const MAX_STEPS: usize = 200_000;
+
+/// Feed line and melt properties for transient estimates.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TransientConfig {
+    /// Pressure the supply holds at the line inlet (PSI)
+    pub supply_pressure: f32,
+    /// Melt density (g/cm³)
+    pub density: f32,
+    /// Melt viscosity (Pa·s)
+    pub viscosity: f32,
+    /// Feed channel diameter (mm)
+    pub channel_diameter: f32,
+    /// Channel length per grid segment (mm)
+    pub segment_length: f32,
+    /// Dead volume of one valve (mm³)
+    pub valve_dead_volume: f32,
+    /// Time a valve takes to close (ms)
+    pub closure_time_ms: f32,
+    /// Flow through one open node (mm³/s)
+    pub node_flow: f32,
+    /// Most a line carries, per channel (mm³/s); unlisted channels are
+    /// uncapped
+    pub max_line_flow: BTreeMap<u8, f32>,
+    /// Spike above the supply the firmware faults at (PSI)
+    pub fault_threshold: f32,
+}
+
+impl TransientConfig {
+    /// Configuration for a print on `printer` with `material`, at the
+    /// supply pressure and channel size of `pressure`.
+    ///
+    /// A node deposits its cell, one grid spacing square and a layer high,
+    /// every valve frame at the maximum switching rate; a line carries no
+    /// more than the extruder feeding its channel.
+    pub fn for_printer(
+        printer: &PrinterConfig,
+        pressure: &PressureConfig,
+        material: &MaterialProfile,
+        layer_height: f32,
+    ) -> Self {
+        let valves = &printer.valve_array;
+        Self {
+            supply_pressure: pressure.supply_pressure,
+            density: material.properties.density,
+            viscosity: pressure.material_viscosity,
+            channel_diameter: pressure.channel_diameter,
+            segment_length: valves.grid_spacing,
+            valve_dead_volume: valves.dead_volume.get(),
+            closure_time_ms: valves.response_time_ms,
+            node_flow: valves.grid_spacing * valves.grid_spacing * layer_height * valves.max_switching_freq,
+            max_line_flow: printer
+                .materials
+                .extruders
+                .iter()
+                .map(|e| (e.material_channel, e.max_flow_rate))
+                .collect(),
+            fault_threshold: printer.safety.pressure_fault_threshold,
+        }
+    }
+}
+
+/// Spike on one feed line when its nodes close.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClosureTransient {
+    pub injection_point: u8,
+    pub channel: u8,
+    /// Nodes closing on the line
+    pub nodes: u32,
+    /// Flow stopped (mm³/s)
+    pub flow: f32,
+    /// Flow-weighted mean line length (mm)
+    pub line_length: f32,
+    /// Highest pressure at the line end (PSI)
+    pub peak_pressure: f32,
+    /// Peak above the supply pressure (PSI)
+    pub spike: f32,
+}
+
+/// Closure transients of one layer.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LayerTransientReport {
+    pub layer_number: u32,
+    /// Every line's transient, per activation group (group 0 if the layer
+    /// opens at once)
+    pub transients: Vec<(u8, ClosureTransient)>,
+    /// Spike the firmware faults at (PSI)
+    pub fault_threshold: f32,
+}
+
+impl LayerTransientReport {
+    /// The highest spike of the layer.
+    pub fn worst(&self) -> Option<&ClosureTransient> {
+        self.transients.iter().map(|(_, t)| t).max_by(|a, b| a.spike.total_cmp(&b.spike))
+    }
+
+    /// True if some line's spike reaches the fault threshold.
+    pub fn exceeds_threshold(&self) -> bool {
+        self.worst().is_some_and(|t| t.spike >= self.fault_threshold)
+    }
+
+    pub fn summary(&self) -> String {
+        match self.worst() {
+            Some(t) => format!(
+                "{:.1} PSI spike on injection point {} (channel {}) closing {} nodes, threshold {:.1} PSI",
+                t.spike, t.injection_point, t.channel, t.nodes, self.fault_threshold
+            ),
+            None => "no valves close".to_string(),
+        }
+    }
+}
+
+/// Estimates closure spikes; see the module documentation.
+pub struct TransientSimulator {
+    config: TransientConfig,
+    /// (id, channel, grid position) of each injection point
+    points: Vec<(u8, u8, GridCoordinate)>,
+}
+
+impl TransientSimulator {
+    pub fn new(printer: &PrinterConfig, config: TransientConfig) -> Self {
+        let spacing = printer.valve_array.grid_spacing;
+        let (grid_x, grid_y) = (printer.grid_x_count().max(1), printer.grid_y_count().max(1));
+        let index = |mm: f32, count: u32| ((mm / spacing).round().max(0.0) as u32).min(count - 1);
+        let points = printer
+            .valve_array
+            .injection_points
+            .iter()
+            .map(|p: &InjectionPoint| {
+                (p.id, p.material_channel, GridCoordinate::new(index(p.x, grid_x), index(p.y, grid_y)))
+            })
+            .collect();
+        Self { config, points }
+    }
+
+    pub fn config(&self) -> &TransientConfig {
+        &self.config
+    }
+
+    /// Transients of a layer's valves closing, group by group if the layer
+    /// is staggered.
+    pub fn check_layer(
+        &self,
+        map: &ValveActivationMap,
+        groups: Option<ActivationGroups>,
+    ) -> LayerTransientReport {
+        let mut by_group: BTreeMap<u8, Vec<&ActiveNode>> = BTreeMap::new();
+        for node in &map.active_nodes {
+            let group = groups.map_or(0, |g| g.group_of(node.position));
+            by_group.entry(group).or_default().push(node);
+        }
+        let transients = by_group
+            .into_iter()
+            .flat_map(|(group, nodes)| self.closure(&nodes).into_iter().map(move |t| (group, t)))
+            .collect();
+        let report = LayerTransientReport {
+            layer_number: map.layer_number,
+            transients,
+            fault_threshold: self.config.fault_threshold,
+        };
+        if report.exceeds_threshold() {
+            warn!("Layer {}: {}", report.layer_number, report.summary());
+        }
+        report
+    }
+
+    /// Transients of `nodes` closing together, one per feed line.
+    pub fn closure(&self, nodes: &[&ActiveNode]) -> Vec<ClosureTransient> {
+        // Injection point index -> (nodes, flow, flow-weighted length)
+        let mut lines: BTreeMap<usize, (u32, f32, f32)> = BTreeMap::new();
+        for node in nodes {
+            let nearest = self
+                .points
+                .iter()
+                .enumerate()
+                .filter(|(_, (_, channel, _))| *channel == node.material_channel)
+                .map(|(i, (_, _, p))| (i, p.x.abs_diff(node.position.x) + p.y.abs_diff(node.position.y)))
+                .min_by_key(|&(_, d)| d);
+            let Some((point, segments)) = nearest else {
+                continue;
+            };
+            let flow = self.config.node_flow * node.extrusion.unwrap_or(1.0);
+            let length = segments.max(1) as f32 * self.config.segment_length;
+            let line = lines.entry(point).or_default();
+            line.0 += 1;
+            line.1 += flow;
+            line.2 += flow * length;
+        }
+
+        lines
+            .into_iter()
+            .map(|(point, (count, flow, weighted))| {
+                let (id, channel, _) = self.points[point];
+                let line_length = if flow > 0.0 { weighted / flow } else { self.config.segment_length };
+                let flow = match self.config.max_line_flow.get(&channel) {
+                    Some(&cap) => flow.min(cap),
+                    None => flow,
+                };
+                let dead_volume = count as f32 * self.config.valve_dead_volume;
+                let peak_pressure = self.line_peak(flow, line_length, dead_volume);
+                ClosureTransient {
+                    injection_point: id,
+                    channel,
+                    nodes: count,
+                    flow,
+                    line_length,
+                    peak_pressure,
+                    spike: (peak_pressure - self.config.supply_pressure).max(0.0),
+                }
+            })
+            .collect()
+    }
+
+    /// Highest pressure (PSI) at the end of a line `length` mm long when
+    /// `flow` mm³/s through it stops.
+    pub fn line_peak(&self, flow: f32, length: f32, dead_volume: f32) -> f32 {
+        let c = &self.config;
+        let supply = pascals(c.supply_pressure);
+        let diameter = c.channel_diameter as f64 * 1e-3;
+        let length = length.max(c.segment_length) as f64 * 1e-3;
+        let area = std::f64::consts::PI * diameter * diameter / 4.0;
+        if area <= 0.0 || length <= 0.0 || flow <= 0.0 {
+            return c.supply_pressure;
+        }
+
+        let inertance = c.density as f64 * 1e3 * length / area;
+        let resistance = 128.0 * c.viscosity as f64 * length / (std::f64::consts::PI * diameter.powi(4));
+        let compliance = (area * length + dead_volume as f64 * 1e-9) / MELT_BULK_MODULUS;
+        let q0 = flow as f64 * 1e-9;
+        let closure = (c.closure_time_ms as f64 * 1e-3).max(0.0);
+
+        let period = std::f64::consts::TAU * (inertance * compliance).sqrt();
+        let mut dt = period / STEPS_PER_PERIOD;
+        if closure > 0.0 {
+            dt = dt.min(closure / STEPS_PER_PERIOD);
+        }
+        // Long enough for the ramp and the slower of ringing and relaxation
+        let settle = closure + 10.0 * period.max(resistance * compliance);
+        let steps = ((settle / dt).ceil() as usize).min(MAX_STEPS);
+        let dt = settle / steps as f64;
+
+        // Steady flow before the valves start closing
+        let (mut q, mut p) = (q0, supply - resistance * q0);
+        let mut peak = p;
+        for step in 1..=steps {
+            let t = step as f64 * dt;
+            let open = if closure > 0.0 { (1.0 - t / closure).max(0.0) } else { 0.0 };
+            // Friction implicit, so a viscous line stays stable at any step
+            q = (q + dt * (supply - p) / inertance) / (1.0 + dt * resistance / inertance);
+            p += dt * (q - q0 * open) / compliance;
+            peak = peak.max(p);
+        }
+        Psi::from_kpa((peak / 1e3) as f32).get()
+    }
+}
+
+fn pascals(psi: f32) -> f64 {
+    Psi::new(psi).to_kpa() as f64 * 1e3
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ExampleConfigs;
+    use config_types::PrinterModel;
+
+    #[test]
+    fn test_closure_spikes() {
+        let configs = ExampleConfigs::for_model(PrinterModel::HyperCubeMini).unwrap();
+        let printer = &configs.printer;
+        let water = TransientConfig {
+            supply_pressure: 60.0,
+            density: 1.0,
+            viscosity: 1e-4,
+            channel_diameter: 2.0,
+            segment_length: printer.valve_array.grid_spacing,
+            valve_dead_volume: 0.0,
+            closure_time_ms: 0.0,
+            node_flow: 50.0,
+            max_line_flow: BTreeMap::new(),
+            fault_threshold: printer.safety.pressure_fault_threshold,
+        };
+        let simulator = TransientSimulator::new(printer, water.clone());
+
+        // An instant stop of a frictionless line rises by ρav (Joukowsky)
+        let flow = 2000.0;
+        let area = std::f64::consts::PI * 1e-6;
+        let joukowsky = (1e3 * (MELT_BULK_MODULUS / 1e3).sqrt() * (flow as f64 * 1e-9 / area)) as f32;
+        let spike = simulator.line_peak(flow, 50.0, 0.0) - 60.0;
+        let expected = Psi::from_kpa(joukowsky / 1e3).get();
+        assert!((spike - expected).abs() < 0.05 * expected, "{} vs {}", spike, expected);
+
+        // Closing slower than the line rings, or a viscous melt, damps it
+        let slow = TransientConfig { closure_time_ms: 5.0, ..water.clone() };
+        let slow = TransientSimulator::new(printer, slow);
+        assert!(slow.line_peak(flow, 50.0, 0.0) - 60.0 < spike / 4.0);
+        let melt = TransientSimulator::new(printer, TransientConfig { viscosity: 300.0, ..water.clone() });
+        assert!(melt.line_peak(flow, 50.0, 0.0) <= 60.0 + 1e-3);
+
+        // 40 nodes close at once; in 4 groups each line stops a quarter
+        let active_nodes: Vec<ActiveNode> = (0..40)
+            .map(|x| ActiveNode {
+                position: GridCoordinate::new(x, 2),
+                material_channel: 0,
+                required_valves: vec![0],
+                object_id: None,
+                extrusion: None,
+            })
+            .collect();
+        let map = ValveActivationMap { layer_number: 12, z_height: 2.6, active_nodes };
+        let report = simulator.check_layer(&map, None);
+        let worst = report.worst().unwrap().clone();
+        assert_eq!((worst.channel, worst.nodes), (0, 40));
+        assert!(report.exceeds_threshold(), "{}", report.summary());
+        let staggered = simulator.check_layer(&map, Some(ActivationGroups::new(4)));
+        assert_eq!(staggered.transients.len(), 4);
+        assert!(staggered.worst().unwrap().spike < worst.spike / 2.0);
+    }
+}
@@ -0,0 +1,436 @@
+//! Polygon boolean operations and offsetting.
+//!
+//! Shapes are sets of oriented rings: counter-clockwise rings bound
+//! material, clockwise rings bound holes, and a point is inside where the
+//! winding number is positive. Every operation works the same way:
+//!
+//! 1. All edges of both operands are split wherever they cross or touch
+//!    another edge, including collinear overlaps.
+//! 2. Each piece is kept if the result is filled on exactly one side of
+//!    it, oriented with the filled side on its left. Pieces shared by both
+//!    operands collapse into one.
+//! 3. The kept pieces are linked into closed rings.
+//!
+//! Because pieces are classified by sampling either side rather than by
+//! tracking crossings, coincident edges, touching vertices and holes need no
+//! special cases. Offsetting builds a raw ring per input ring and resolves
+//! its self-intersections with the same positive-winding union.
+//!
+//! Work is done in `f64` with vertices snapped to a 10 nm lattice so
+//! that pieces meeting at a point link exactly.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::utils::geometry::{Point2D, Polygon};
+
+/// Vertex snapping lattice, in mm.
+const RESOLUTION: f64 = 1e-5;
+
+/// Maximum distance between an offset arc and its chords, in mm.
+const ARC_TOLERANCE: f64 = 0.005;
+
+/// Boolean operation between a subject and a clip shape.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClipOp {
+    Union,
+    Intersection,
+    /// Subject minus clip
+    Difference,
+    Xor,
+}
+
+impl ClipOp {
+    fn apply(self, subject: bool, clip: bool) -> bool {
+        match self {
+            ClipOp::Union => subject || clip,
+            ClipOp::Intersection => subject && clip,
+            ClipOp::Difference => subject && !clip,
+            ClipOp::Xor => subject != clip,
+        }
+    }
+}
+
+type P = (f64, f64);
+type Key = (i64, i64);
+
+#[derive(Debug, Clone, Copy)]
+struct Edge {
+    a: P,
+    b: P,
+}
+
+/// Applies `op` to two shapes given as oriented rings.
+///
+/// The result is a set of oriented rings; use [`group_rings`] to pair
+/// holes with their outer boundaries.
+pub fn boolean(subject: &[Polygon], clip: &[Polygon], op: ClipOp) -> Vec<Polygon> {
+    let subject_edges = edges(subject);
+    let clip_edges = edges(clip);
+    let mut all = subject_edges.clone();
+    all.extend_from_slice(&clip_edges);
+
+    let mut kept: Vec<(P, P)> = Vec::new();
+    let mut seen: HashSet<(Key, Key)> = HashSet::new();
+    for (a, b) in split(&all) {
+        let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+        let length = (dx * dx + dy * dy).sqrt();
+        let eps = (length * 0.25).min(RESOLUTION * 10.0);
+        let (nx, ny) = (-dy / length * eps, dx / length * eps);
+        let mid = ((a.0 + b.0) / 2.0, (a.1 + b.1) / 2.0);
+        let left = (mid.0 + nx, mid.1 + ny);
+        let right = (mid.0 - nx, mid.1 - ny);
+
+        let filled = |p: P| op.apply(winding(&subject_edges, p) > 0, winding(&clip_edges, p) > 0);
+        let piece = match (filled(left), filled(right)) {
+            (true, false) => (a, b),
+            (false, true) => (b, a),
+            _ => continue,
+        };
+        if seen.insert((key(piece.0), key(piece.1))) {
+            kept.push(piece);
+        }
+    }
+
+    link(&kept)
+}
+
+/// Grows (positive `delta`) or shrinks a shape given as oriented rings,
+/// rounding corners. Holes shrink as the material around them grows.
+pub fn offset(rings: &[Polygon], delta: f32) -> Vec<Polygon> {
+    let delta = delta as f64;
+    if delta == 0.0 {
+        return boolean(rings, &[], ClipOp::Union);
+    }
+
+    let raw: Vec<Polygon> = rings
+        .iter()
+        .filter_map(|ring| {
+            let points = dedup(ring);
+            (points.len() >= 3).then(|| to_polygon(&offset_ring(&points, delta)))
+        })
+        .collect();
+    boolean(&raw, &[], ClipOp::Union)
+}
+
+/// Pairs each clockwise hole with the smallest counter-clockwise ring
+/// around it. Holes without an enclosing ring are dropped.
+pub fn group_rings(rings: Vec<Polygon>) -> Vec<(Polygon, Vec<Polygon>)> {
+    let (outers, holes): (Vec<Polygon>, Vec<Polygon>) = rings.into_iter().partition(|r| r.is_ccw());
+    let mut groups: Vec<(Polygon, Vec<Polygon>)> = outers.into_iter().map(|o| (o, Vec::new())).collect();
+
+    for hole in holes {
+        // A point just to the left of a hole edge lies in the enclosing material
+        let points = dedup(&hole);
+        let (Some(&a), Some(&b)) = (points.first(), points.get(1)) else { continue };
+        let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+        let length = (dx * dx + dy * dy).sqrt();
+        let eps = (length * 0.25).min(RESOLUTION * 10.0);
+        let probe = (
+            (a.0 + b.0) / 2.0 - dy / length * eps,
+            (a.1 + b.1) / 2.0 + dx / length * eps,
+        );
+
+        let owner = groups
+            .iter()
+            .enumerate()
+            .filter(|(_, (outer, _))| winding(&edges(std::slice::from_ref(outer)), probe) > 0)
+            .min_by(|(_, (x, _)), (_, (y, _))| x.area().total_cmp(&y.area()))
+            .map(|(i, _)| i);
+        if let Some(i) = owner {
+            groups[i].1.push(hole);
+        }
+    }
+    groups
+}
+
+fn edges(rings: &[Polygon]) -> Vec<Edge> {
+    let mut edges = Vec::new();
+    for ring in rings {
+        let points = dedup(ring);
+        if points.len() < 3 {
+            continue;
+        }
+        for i in 0..points.len() {
+            edges.push(Edge {
+                a: points[i],
+                b: points[(i + 1) % points.len()],
+            });
+        }
+    }
+    edges
+}
+
+/// Ring vertices snapped to the lattice, without repeats.
+fn dedup(ring: &Polygon) -> Vec<P> {
+    let mut points: Vec<P> = Vec::with_capacity(ring.points.len());
+    for p in &ring.points {
+        let p = snap((p.x as f64, p.y as f64));
+        if points.last() != Some(&p) {
+            points.push(p);
+        }
+    }
+    while points.len() > 1 && points.first() == points.last() {
+        points.pop();
+    }
+    points
+}
+
+fn key(p: P) -> Key {
+    ((p.0 / RESOLUTION).round() as i64, (p.1 / RESOLUTION).round() as i64)
+}
+
+fn snap(p: P) -> P {
+    let (x, y) = key(p);
+    (x as f64 * RESOLUTION, y as f64 * RESOLUTION)
+}
+
+fn cross(a: P, b: P) -> f64 {
+    a.0 * b.1 - a.1 * b.0
+}
+
+fn sub(a: P, b: P) -> P {
+    (a.0 - b.0, a.1 - b.1)
+}
+
+/// Splits every edge at its crossings and touches with every other edge.
+fn split(edges: &[Edge]) -> Vec<(P, P)> {
+    let mut params: Vec<Vec<f64>> = vec![vec![0.0, 1.0]; edges.len()];
+    for i in 0..edges.len() {
+        for j in (i + 1)..edges.len() {
+            let (e, f) = (edges[i], edges[j]);
+            let (d1, d2) = (sub(e.b, e.a), sub(f.b, f.a));
+            let len1 = (d1.0 * d1.0 + d1.1 * d1.1).sqrt();
+            let len2 = (d2.0 * d2.0 + d2.1 * d2.1).sqrt();
+            let denom = cross(d1, d2);
+            let w = sub(f.a, e.a);
+
+            if denom.abs() > 1e-12 * len1 * len2 {
+                let t = cross(w, d2) / denom;
+                let u = cross(w, d1) / denom;
+                let (et, eu) = (RESOLUTION / len1, RESOLUTION / len2);
+                if (-et..=1.0 + et).contains(&t) && (-eu..=1.0 + eu).contains(&u) {
+                    params[i].push(t.clamp(0.0, 1.0));
+                    params[j].push(u.clamp(0.0, 1.0));
+                }
+            } else if (cross(w, d1) / len1).abs() <= RESOLUTION {
+                // Collinear: each edge splits at the other's endpoints
+                for p in [f.a, f.b] {
+                    let t = ((p.0 - e.a.0) * d1.0 + (p.1 - e.a.1) * d1.1) / (len1 * len1);
+                    if t > 0.0 && t < 1.0 {
+                        params[i].push(t);
+                    }
+                }
+                for p in [e.a, e.b] {
+                    let u = ((p.0 - f.a.0) * d2.0 + (p.1 - f.a.1) * d2.1) / (len2 * len2);
+                    if u > 0.0 && u < 1.0 {
+                        params[j].push(u);
+                    }
+                }
+            }
+        }
+    }
+
+    let mut pieces = Vec::new();
+    for (edge, mut ts) in edges.iter().zip(params) {
+        ts.sort_by(f64::total_cmp);
+        let points: Vec<P> = ts
+            .iter()
+            .map(|&t| snap((edge.a.0 + (edge.b.0 - edge.a.0) * t, edge.a.1 + (edge.b.1 - edge.a.1) * t)))
+            .collect();
+        for pair in points.windows(2) {
+            if key(pair[0]) != key(pair[1]) {
+                pieces.push((pair[0], pair[1]));
+            }
+        }
+    }
+    pieces
+}
+
+/// Winding number of `p` with respect to closed edge loops.
+fn winding(edges: &[Edge], p: P) -> i32 {
+    let mut winding = 0;
+    for edge in edges {
+        let side = cross(sub(edge.b, edge.a), sub(p, edge.a));
+        if edge.a.1 <= p.1 {
+            if edge.b.1 > p.1 && side > 0.0 {
+                winding += 1;
+            }
+        } else if edge.b.1 <= p.1 && side < 0.0 {
+            winding -= 1;
+        }
+    }
+    winding
+}
+
+/// Links directed pieces into closed rings, taking the sharpest right turn
+/// where several pieces leave one vertex so touching shapes stay separate.
+fn link(pieces: &[(P, P)]) -> Vec<Polygon> {
+    let mut outgoing: HashMap<Key, Vec<usize>> = HashMap::new();
+    for (i, piece) in pieces.iter().enumerate() {
+        outgoing.entry(key(piece.0)).or_default().push(i);
+    }
+
+    let mut used = vec![false; pieces.len()];
+    let mut rings = Vec::new();
+    for first in 0..pieces.len() {
+        if used[first] {
+            continue;
+        }
+        let start = key(pieces[first].0);
+        let mut ring = vec![pieces[first].0];
+        let mut current = first;
+        let closed = loop {
+            used[current] = true;
+            let (from, to) = pieces[current];
+            if key(to) == start {
+                break true;
+            }
+            ring.push(to);
+
+            let incoming = sub(to, from);
+            let next = outgoing
+                .get(&key(to))
+                .into_iter()
+                .flatten()
+                .copied()
+                .filter(|&i| !used[i])
+                .min_by(|&x, &y| turn(incoming, pieces[x]).total_cmp(&turn(incoming, pieces[y])));
+            match next {
+                Some(next) => current = next,
+                None => break false,
+            }
+        };
+
+        if closed {
+            let ring = simplify(ring);
+            if ring.len() >= 3 {
+                let polygon = to_polygon(&ring);
+                if polygon.area() as f64 > RESOLUTION * RESOLUTION {
+                    rings.push(polygon);
+                }
+            }
+        }
+    }
+    rings
+}
+
+/// Signed turn angle from `incoming` onto `piece`; negative turns right.
+fn turn(incoming: P, piece: (P, P)) -> f64 {
+    let d = sub(piece.1, piece.0);
+    cross(incoming, d).atan2(incoming.0 * d.0 + incoming.1 * d.1)
+}
+
+/// Drops vertices that lie on the line through their neighbours.
+fn simplify(mut ring: Vec<P>) -> Vec<P> {
+    let mut changed = true;
+    while changed && ring.len() >= 3 {
+        changed = false;
+        let n = ring.len();
+        for i in 0..n {
+            let (prev, p, next) = (ring[(i + n - 1) % n], ring[i], ring[(i + 1) % n]);
+            let d = sub(next, prev);
+            let length = (d.0 * d.0 + d.1 * d.1).sqrt();
+            if length == 0.0 || (cross(d, sub(p, prev)) / length).abs() <= RESOLUTION / 2.0 {
+                let forward = (p.0 - prev.0) * d.0 + (p.1 - prev.1) * d.1;
+                if length == 0.0 || (forward >= 0.0 && forward <= length * length) {
+                    ring.remove(i);
+                    changed = true;
+                    break;
+                }
+            }
+        }
+    }
+    ring
+}
+
+/// Raw offset of one ring along its right-hand normals, which point away
+/// from the material for both outers and holes. The result may cross
+/// itself; [`offset`] resolves that with a union.
+fn offset_ring(points: &[P], delta: f64) -> Vec<P> {
+    let n = points.len();
+    let normal = |a: P, b: P| {
+        let (dx, dy) = sub(b, a);
+        let length = (dx * dx + dy * dy).sqrt();
+        (dy / length, -dx / length)
+    };
+    let step = {
+        let ratio = (1.0 - ARC_TOLERANCE / delta.abs()).clamp(-1.0, 1.0);
+        (2.0 * ratio.acos()).clamp(0.01, std::f64::consts::FRAC_PI_4)
+    };
+
+    let mut out = Vec::new();
+    for i in 0..n {
+        let (prev, v, next) = (points[(i + n - 1) % n], points[i], points[(i + 1) % n]);
+        let n1 = normal(prev, v);
+        let n2 = normal(v, next);
+        let bend = cross(sub(v, prev), sub(next, v));
+
+        if bend * delta > 0.0 {
+            // The offset edges separate here; round the gap
+            let sweep = cross(n1, n2).atan2(n1.0 * n2.0 + n1.1 * n2.1);
+            let steps = (sweep.abs() / step).ceil().max(1.0) as usize;
+            for k in 0..=steps {
+                let angle = sweep * k as f64 / steps as f64;
+                let (sin, cos) = angle.sin_cos();
+                let dir = (n1.0 * cos - n1.1 * sin, n1.0 * sin + n1.1 * cos);
+                out.push((v.0 + dir.0 * delta, v.1 + dir.1 * delta));
+            }
+        } else if bend == 0.0 {
+            out.push((v.0 + n1.0 * delta, v.1 + n1.1 * delta));
+        } else {
+            // The offset edges overlap here; passing through the vertex
+            // leaves a reversed loop that the union discards
+            out.push((v.0 + n1.0 * delta, v.1 + n1.1 * delta));
+            out.push(v);
+            out.push((v.0 + n2.0 * delta, v.1 + n2.1 * delta));
+        }
+    }
+    out
+}
+
+fn to_polygon(points: &[P]) -> Polygon {
+    Polygon::new(points.iter().map(|&(x, y)| Point2D::new(x as f32, y as f32)).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn square(x: f32, y: f32, side: f32) -> Polygon {
+        Polygon::from_tuples(&[(x, y), (x + side, y), (x + side, y + side), (x, y + side)])
+    }
+
+    fn total_area(rings: &[Polygon]) -> f32 {
+        rings.iter().map(|r| r.signed_area()).sum()
+    }
+
+    #[test]
+    fn test_boolean_and_offset_with_holes() {
+        // 10mm square with a 4mm hole, against an overlapping 10mm square
+        let frame = vec![square(0.0, 0.0, 10.0), square(3.0, 3.0, 4.0).oriented(false)];
+        let other = vec![square(5.0, 0.0, 10.0)];
+
+        let union = boolean(&frame, &other, ClipOp::Union);
+        assert!((total_area(&union) - (150.0 - 8.0)).abs() < 1e-3);
+        let intersection = boolean(&frame, &other, ClipOp::Intersection);
+        assert!((total_area(&intersection) - (50.0 - 8.0)).abs() < 1e-3);
+        let difference = boolean(&frame, &other, ClipOp::Difference);
+        assert!((total_area(&difference) - (50.0 - 8.0)).abs() < 1e-3);
+
+        // Coincident edges merge into one shape
+        let halves = boolean(&[square(0.0, 0.0, 5.0)], &[square(5.0, 0.0, 5.0)], ClipOp::Union);
+        assert_eq!(halves.len(), 1);
+        assert!((halves[0].area() - 50.0).abs() < 1e-3);
+
+        // Shrinking the frame widens the hole; the hole stays a hole
+        let shrunk = group_rings(offset(&frame, -1.0));
+        assert_eq!(shrunk.len(), 1);
+        assert_eq!(shrunk[0].1.len(), 1);
+        assert!((shrunk[0].0.area() - 64.0).abs() < 1e-3);
+        let hole = shrunk[0].1[0].area();
+        assert!(hole > 35.0 && hole < 36.0 - std::f32::consts::PI + 4.0 + 1e-3);
+
+        // Shrinking past the wall width leaves nothing
+        assert!(offset(&frame, -2.0).is_empty());
+    }
+}
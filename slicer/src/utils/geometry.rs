@@ -46,18 +46,81 @@ impl Triangle {
     }
 }
 
-#[derive(Debug, Clone)]
+/// A closed ring of points; the last point connects back to the first.
+///
+/// Counter-clockwise rings bound material and clockwise rings bound holes,
+/// which is the orientation [`crate::utils::clipping`] produces and expects.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Polygon {
     pub points: Vec<Point2D>,
 }
 
 impl Polygon {
+    pub fn new(points: Vec<Point2D>) -> Self {
+        Self { points }
+    }
+
+    pub fn from_tuples(points: &[(f32, f32)]) -> Self {
+        Self {
+            points: points.iter().map(|&(x, y)| Point2D::new(x, y)).collect(),
+        }
+    }
+
+    pub fn to_tuples(&self) -> Vec<(f32, f32)> {
+        self.points.iter().map(|p| (p.x, p.y)).collect()
+    }
+
+    /// Even-odd point-in-polygon test; points on an edge may go either way.
     pub fn contains_point(&self, point: Point2D) -> bool {
-        todo!("Implementation needed: Point-in-polygon test")
+        let mut inside = false;
+        let n = self.points.len();
+        for i in 0..n {
+            let a = self.points[i];
+            let b = self.points[(i + 1) % n];
+            if (a.y > point.y) != (b.y > point.y) {
+                let x = a.x + (point.y - a.y) / (b.y - a.y) * (b.x - a.x);
+                if point.x < x {
+                    inside = !inside;
+                }
+            }
+        }
+        inside
     }
 
     pub fn area(&self) -> f32 {
-        todo!("Implementation needed: Calculate polygon area")
+        self.signed_area().abs()
     }
-}
 
+    /// Shoelace area; positive for counter-clockwise rings.
+    pub fn signed_area(&self) -> f32 {
+        let n = self.points.len();
+        let twice: f64 = (0..n)
+            .map(|i| {
+                let a = self.points[i];
+                let b = self.points[(i + 1) % n];
+                a.x as f64 * b.y as f64 - b.x as f64 * a.y as f64
+            })
+            .sum();
+        (twice / 2.0) as f32
+    }
+
+    pub fn is_ccw(&self) -> bool {
+        self.signed_area() > 0.0
+    }
+
+    /// The same ring wound counter-clockwise (`ccw`) or clockwise.
+    pub fn oriented(mut self, ccw: bool) -> Self {
+        if self.is_ccw() != ccw {
+            self.points.reverse();
+        }
+        self
+    }
+
+    /// Grows (positive `delta`) or shrinks the area bounded by this ring,
+    /// with rounded corners. Shrinking may split it into several rings or
+    /// remove it entirely.
+    pub fn offset(&self, delta: f32) -> Vec<Polygon> {
+        let ring = self.clone().oriented(true);
+        crate::utils::clipping::offset(&[ring], delta)
+    }
+}
@@ -6,13 +6,19 @@
 //! ## Module Organization
 //!
 //! - **geometry**: 2D/3D geometry operations
+//! - **clipping**: Polygon boolean operations and offsetting
 //! - **math**: Mathematical utilities
 //! - **spatial**: Spatial indexing and queries
+//! - **progress**: Terminal progress bars for batch slicing
 
+pub mod clipping;
 pub mod geometry;
 pub mod math;
 pub mod spatial;
+pub mod progress;
 
+pub use clipping::ClipOp;
 pub use geometry::{Point2D, Point3D, Triangle, Polygon};
 pub use math::{interpolate, clamp, map_range};
 pub use spatial::SpatialIndex;
+pub use progress::ProgressRenderer;
@@ -0,0 +1,220 @@
+//! Terminal progress display for batch slicing.
+//!
+//! [`ProgressRenderer`] turns the [`SliceProgress`] callbacks of a slicing run
+//! into one bar per phase with the current layer and an ETA. On a terminal
+//! the bar is redrawn in place (at most every [`REDRAW_INTERVAL`]) and left
+//! on screen when its phase completes; otherwise, e.g. in CI logs, each
+//! phase prints one line when it starts and one when it ends.
+
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+use crate::{SlicePhase, SliceProgress};
+
+/// Bar width in characters.
+pub const BAR_WIDTH: usize = 30;
+
+/// Minimum time between redraws of an unfinished bar.
+pub const REDRAW_INTERVAL: Duration = Duration::from_millis(80);
+
+/// Width of the phase name column, fitting the longest description.
+const PHASE_COLUMN: usize = 34;
+
+/// Text emphasis, rendered as ANSI escapes when enabled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tone {
+    Bold,
+    Success,
+    Warning,
+}
+
+/// Wraps `text` in the escape codes for `tone` if `enabled`.
+pub fn paint(text: &str, tone: Tone, enabled: bool) -> String {
+    if !enabled {
+        return text.to_string();
+    }
+    let code = match tone {
+        Tone::Bold => "1",
+        Tone::Success => "32",
+        Tone::Warning => "33",
+    };
+    format!("\x1b[{}m{}\x1b[0m", code, text)
+}
+
+/// `[=====>    ]` for `fraction` of `width` cells.
+pub fn bar(fraction: f32, width: usize) -> String {
+    let filled = ((fraction.clamp(0.0, 1.0) * width as f32).round() as usize).min(width);
+    let head = if filled < width && filled > 0 { ">" } else { "" };
+    let body = filled.saturating_sub(head.len());
+    format!("[{}{}{}]", "=".repeat(body), head, " ".repeat(width - body - head.len()))
+}
+
+/// Time left if the rest of the phase runs at the rate seen so far. None
+/// until there is enough progress to extrapolate.
+pub fn estimate_remaining(elapsed: Duration, fraction: f32) -> Option<Duration> {
+    if !(0.01..1.0).contains(&fraction) {
+        return None;
+    }
+    Some(elapsed.mul_f32((1.0 - fraction) / fraction))
+}
+
+/// Compact duration: `850ms`, `42s`, `3m 05s`, `1h 02m`.
+pub fn format_short_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    match secs {
+        0 => format!("{}ms", duration.as_millis()),
+        1..=59 => format!("{}s", secs),
+        60..=3599 => format!("{}m {:02}s", secs / 60, secs % 60),
+        _ => format!("{}h {:02}m", secs / 3600, secs / 60 % 60),
+    }
+}
+
+/// Byte count in binary units: `512 B`, `1.4 MiB`.
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
+
+/// One line of progress: phase, bar, percentage, layer counter and ETA.
+pub fn status_line(progress: &SliceProgress, eta: Option<Duration>) -> String {
+    let mut line = format!(
+        "{:<width$} {} {:>3}%",
+        progress.phase.description(),
+        bar(progress.progress, BAR_WIDTH),
+        (progress.progress.clamp(0.0, 1.0) * 100.0).round() as u32,
+        width = PHASE_COLUMN
+    );
+    match (progress.current_layer, progress.total_layers) {
+        (Some(layer), Some(total)) => line.push_str(&format!("  layer {}/{}", layer, total)),
+        (Some(layer), None) => line.push_str(&format!("  layer {}", layer)),
+        _ => {}
+    }
+    if let Some(eta) = eta {
+        line.push_str(&format!("  ETA {}", format_short_duration(eta)));
+    }
+    line
+}
+
+/// Draws slicing progress; see the module documentation.
+#[derive(Debug)]
+pub struct ProgressRenderer {
+    interactive: bool,
+    phase: Option<SlicePhase>,
+    phase_started: Instant,
+    /// Current phase has reached 100% and its line is closed
+    phase_done: bool,
+    last_draw: Option<Instant>,
+}
+
+impl ProgressRenderer {
+    /// `interactive` redraws in place and should only be set for terminals.
+    pub fn new(interactive: bool) -> Self {
+        Self {
+            interactive,
+            phase: None,
+            phase_started: Instant::now(),
+            phase_done: false,
+            last_draw: None,
+        }
+    }
+
+    pub fn update(&mut self, out: &mut dyn Write, progress: &SliceProgress) -> io::Result<()> {
+        let now = Instant::now();
+        if self.phase != Some(progress.phase) {
+            if self.phase.is_some() && !self.phase_done && self.interactive {
+                // The previous phase ended without a final 100% report
+                writeln!(out)?;
+            }
+            self.phase = Some(progress.phase);
+            self.phase_started = now;
+            self.phase_done = false;
+            self.last_draw = None;
+            if !self.interactive {
+                writeln!(out, "{}...", progress.phase.description())?;
+            }
+        }
+        if self.phase_done {
+            return Ok(());
+        }
+
+        let complete = progress.progress >= 1.0;
+        let elapsed = now.duration_since(self.phase_started);
+        if complete {
+            self.phase_done = true;
+            let line = format!("{}  {}", status_line(progress, None), format_short_duration(elapsed));
+            if self.interactive {
+                writeln!(out, "\r\x1b[2K{}", line)?;
+            } else {
+                writeln!(out, "{}", line)?;
+            }
+        } else if self.interactive {
+            let due = match self.last_draw {
+                Some(last) => now.duration_since(last) >= REDRAW_INTERVAL,
+                None => true,
+            };
+            if due {
+                self.last_draw = Some(now);
+                let eta = estimate_remaining(elapsed, progress.progress);
+                write!(out, "\r\x1b[2K{}", status_line(progress, eta))?;
+            }
+        }
+        out.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn progress(phase: SlicePhase, fraction: f32, layer: Option<u32>) -> SliceProgress {
+        SliceProgress {
+            phase,
+            progress: fraction,
+            current_layer: layer,
+            total_layers: layer.map(|_| 500),
+            message: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_progress_rendering() {
+        assert_eq!(bar(0.0, 10), "[          ]");
+        assert_eq!(bar(0.5, 10), "[====>     ]");
+        assert_eq!(bar(1.0, 10), "[==========]");
+        assert_eq!(estimate_remaining(Duration::from_secs(10), 0.25), Some(Duration::from_secs(30)));
+        assert_eq!(estimate_remaining(Duration::from_secs(10), 0.0), None);
+        assert_eq!(format_bytes(1536 * 1024), "1.5 MiB");
+        assert_eq!(format_short_duration(Duration::from_secs(185)), "3m 05s");
+
+        let line = status_line(&progress(SlicePhase::GeneratingLayers, 0.72, Some(360)), Some(Duration::from_secs(4)));
+        assert!(line.ends_with("72%  layer 360/500  ETA 4s"), "{}", line);
+
+        // Without a terminal, one line per phase start and end
+        let mut renderer = ProgressRenderer::new(false);
+        let mut out = Vec::new();
+        for update in [
+            progress(SlicePhase::LoadingModel, 0.0, None),
+            progress(SlicePhase::LoadingModel, 1.0, None),
+            progress(SlicePhase::GeneratingLayers, 0.5, Some(250)),
+            progress(SlicePhase::GeneratingLayers, 1.0, Some(500)),
+            progress(SlicePhase::GeneratingLayers, 1.0, Some(500)),
+        ] {
+            renderer.update(&mut out, &update).unwrap();
+        }
+        let text = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 4, "{}", text);
+        assert_eq!(lines[0], "Loading 3D model...");
+        assert!(lines[3].contains("100%  layer 500/500"));
+        assert!(!text.contains('\x1b'));
+    }
+}